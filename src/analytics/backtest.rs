@@ -0,0 +1,694 @@
+// Replays historical prices and lending rates against a yield strategy or rebalancing policy
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, TimeZone, Utc};
+use ethers::providers::Middleware;
+use ethers::types::Address;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::analytics::price_feeds::{eth_usd_aggregator, PriceFeedService};
+use crate::defi::aave::{YieldStep, YieldStrategy};
+use crate::defi::DefiManager;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `backtest_history_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/market_history.json";
+/// Blocks read per archive replay
+const MAX_ARCHIVE_POINTS: u64 = 500;
+const SECONDS_PER_YEAR: f64 = 365.25 * 86_400.0;
+/// Share of a borrow repaid by one liquidation
+const CLOSE_FACTOR: f64 = 0.5;
+
+fn default_swap_fee_bps() -> f64 {
+    30.0
+}
+
+fn default_liquidation_threshold() -> f64 {
+    0.8
+}
+
+fn default_liquidation_bonus() -> f64 {
+    0.05
+}
+
+fn default_drift_threshold_percentage() -> f64 {
+    5.0
+}
+
+/// Market state at one point of a replayed history, APYs in percent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPoint {
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    pub prices_usd: HashMap<Address, f64>,
+    #[serde(default)]
+    pub supply_apy: HashMap<Address, f64>,
+    #[serde(default)]
+    pub borrow_apy: HashMap<Address, f64>,
+    /// Share of each collateral's value counted towards the health factor
+    #[serde(default)]
+    pub liquidation_thresholds: HashMap<Address, f64>,
+}
+
+/// Where the replayed history comes from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistorySource {
+    /// Aave reserve rates and oracle prices read from an archive node every `step_blocks`; the
+    /// points are added to the market history store
+    Archive { from_block: u64, to_block: u64, step_blocks: u64 },
+    /// Points of the market history store, between two optional dates
+    Stored {
+        #[serde(default)]
+        from: Option<DateTime<Utc>>,
+        #[serde(default)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Points supplied with the request
+    Inline { points: Vec<MarketPoint> },
+}
+
+/// Target weights restored whenever one drifts too far from its target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebalancePolicy {
+    pub name: Option<String>,
+    /// Share of the portfolio in each asset, normalized to sum to one
+    pub weights: HashMap<Address, f64>,
+    #[serde(default = "default_drift_threshold_percentage")]
+    pub drift_threshold_percentage: f64,
+    /// Keep the assets supplied to earn the supply APY instead of holding them
+    #[serde(default)]
+    pub supply_idle: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BacktestStrategy {
+    /// Steps of a yield strategy applied once to the capital, deposited in its first asset
+    Yield { strategy: YieldStrategy },
+    Rebalance { policy: RebalancePolicy },
+}
+
+impl BacktestStrategy {
+    fn name(&self) -> String {
+        match self {
+            Self::Yield { strategy } => strategy.name.clone(),
+            Self::Rebalance { policy } => policy.name.clone().unwrap_or_else(|| "Rebalancing policy".to_string()),
+        }
+    }
+
+    /// Assets the replay needs prices of
+    fn assets(&self) -> Vec<Address> {
+        let mut assets = Vec::new();
+        let mut add = |asset: Address| {
+            if !assets.contains(&asset) {
+                assets.push(asset);
+            }
+        };
+        match self {
+            Self::Yield { strategy } => {
+                strategy.assets_involved.iter().copied().for_each(&mut add);
+                for step in &strategy.steps {
+                    match step {
+                        YieldStep::Supply { asset, .. } | YieldStep::Borrow { asset, .. } => add(*asset),
+                        YieldStep::Swap { token_in, token_out, .. } => {
+                            add(*token_in);
+                            add(*token_out);
+                        }
+                        YieldStep::Farm { .. } => {}
+                    }
+                }
+            }
+            Self::Rebalance { policy } => policy.weights.keys().copied().for_each(add),
+        }
+        assets
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestRequest {
+    pub chain_id: u64,
+    #[serde(with = "crate::api::models::usd")]
+    pub initial_capital_usd: f64,
+    pub strategy: BacktestStrategy,
+    pub history: HistorySource,
+    /// Fee of every swap and rebalancing trade, in basis points
+    #[serde(default = "default_swap_fee_bps")]
+    pub swap_fee_bps: f64,
+    /// Trading fee APY earned by liquidity positions, in percent
+    #[serde(default)]
+    pub lp_fee_apy: f64,
+    /// Liquidation threshold of collateral the history has none for
+    #[serde(default = "default_liquidation_threshold")]
+    pub liquidation_threshold: f64,
+    /// Collateral seized above the repaid debt by a liquidation
+    #[serde(default = "default_liquidation_bonus")]
+    pub liquidation_bonus: f64,
+}
+
+/// A liquidation of the replayed position after its health factor fell below 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationEvent {
+    pub timestamp: DateTime<Utc>,
+    pub health_factor: f64,
+    pub debt_asset: Address,
+    #[serde(with = "crate::api::models::usd")]
+    pub debt_repaid_usd: f64,
+    pub collateral_asset: Address,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_seized_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub penalty_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub timestamp: DateTime<Utc>,
+    #[serde(with = "crate::api::models::usd")]
+    pub value_usd: f64,
+    pub health_factor: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestReport {
+    pub chain_id: u64,
+    pub strategy: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub points: usize,
+    #[serde(with = "crate::api::models::usd")]
+    pub initial_value_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub final_value_usd: f64,
+    pub total_return_percentage: f64,
+    /// Return annualized over the replayed span
+    pub apy: f64,
+    /// Largest fall from a previous peak of the value
+    pub max_drawdown_percentage: f64,
+    /// Value of the liquidity positions against holding the deposited tokens, before trading fees
+    #[serde(with = "crate::api::models::usd")]
+    pub impermanent_loss_usd: f64,
+    pub impermanent_loss_percentage: f64,
+    pub rebalances: u32,
+    #[serde(with = "crate::api::models::usd")]
+    pub fees_paid_usd: f64,
+    pub liquidations: Vec<LiquidationEvent>,
+    pub equity_curve: Vec<EquityPoint>,
+    pub warnings: Vec<String>,
+}
+
+/// Constant-product liquidity position
+struct LiquidityPosition {
+    token_a: Address,
+    token_b: Address,
+    /// sqrt(x * y), grown by trading fees
+    liquidity: f64,
+    deposited_liquidity: f64,
+    deposited_a: f64,
+    deposited_b: f64,
+}
+
+/// Token balances of the replayed position and the prices they are valued at
+struct Simulation<'a> {
+    request: &'a BacktestRequest,
+    prices: HashMap<Address, f64>,
+    thresholds: HashMap<Address, f64>,
+    held: HashMap<Address, f64>,
+    supplied: HashMap<Address, f64>,
+    borrowed: HashMap<Address, f64>,
+    liquidity: Vec<LiquidityPosition>,
+    fees_paid_usd: f64,
+    warnings: Vec<String>,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(request: &'a BacktestRequest, start: &MarketPoint, assets: &[Address]) -> Result<Self> {
+        if let Some(asset) = assets.iter().find(|asset| !start.prices_usd.get(asset).is_some_and(|price| *price > 0.0)) {
+            return Err(anyhow!("No price for {:?} at the start of the history", asset));
+        }
+        Ok(Self {
+            request,
+            prices: start.prices_usd.clone(),
+            thresholds: start.liquidation_thresholds.clone(),
+            held: HashMap::new(),
+            supplied: HashMap::new(),
+            borrowed: HashMap::new(),
+            liquidity: Vec::new(),
+            fees_paid_usd: 0.0,
+            warnings: Vec::new(),
+        })
+    }
+
+    fn price(&self, asset: Address) -> f64 {
+        self.prices.get(&asset).copied().unwrap_or_default()
+    }
+
+    fn value(&self, balances: &HashMap<Address, f64>) -> f64 {
+        balances.iter().map(|(asset, units)| units * self.price(*asset)).sum()
+    }
+
+    fn liquidity_value(&self, position: &LiquidityPosition, liquidity: f64) -> f64 {
+        2.0 * liquidity * (self.price(position.token_a) * self.price(position.token_b)).sqrt()
+    }
+
+    fn equity(&self) -> f64 {
+        let liquidity: f64 = self.liquidity.iter().map(|position| self.liquidity_value(position, position.liquidity)).sum();
+        self.value(&self.held) + self.value(&self.supplied) + liquidity - self.value(&self.borrowed)
+    }
+
+    fn health_factor(&self) -> Option<f64> {
+        let debt = self.value(&self.borrowed);
+        if debt <= 0.0 {
+            return None;
+        }
+        let collateral: f64 = self.supplied.iter()
+            .map(|(asset, units)| {
+                let threshold = self.thresholds.get(asset).copied().unwrap_or(self.request.liquidation_threshold);
+                units * self.price(*asset) * threshold
+            })
+            .sum();
+        Some(collateral / debt)
+    }
+
+    fn fee_rate(&self) -> f64 {
+        self.request.swap_fee_bps / 10_000.0
+    }
+
+    /// Swap `units` of held `from` into `to`, returning the units received
+    fn swap(&mut self, from: Address, to: Address, units: f64) -> f64 {
+        let value = units * self.price(from);
+        let fee = value * self.fee_rate();
+        let received = (value - fee) / self.price(to);
+        *self.held.entry(from).or_default() -= units;
+        *self.held.entry(to).or_default() += received;
+        self.fees_paid_usd += fee;
+        received
+    }
+
+    fn apply_yield_strategy(&mut self, strategy: &YieldStrategy) -> Result<()> {
+        let deposit = strategy.assets_involved.first().copied()
+            .or_else(|| strategy.steps.iter().find_map(|step| match step {
+                YieldStep::Supply { asset, .. } => Some(*asset),
+                YieldStep::Swap { token_in, .. } => Some(*token_in),
+                _ => None,
+            }))
+            .ok_or_else(|| anyhow!("Strategy {} has no asset to deposit", strategy.name))?;
+        self.held.insert(deposit, self.request.initial_capital_usd / self.price(deposit));
+
+        let mut last = deposit;
+        for step in &strategy.steps {
+            match step {
+                YieldStep::Supply { asset, amount_ratio, .. } => {
+                    let units = if self.held.get(asset).copied().unwrap_or_default() > 0.0 {
+                        self.held[asset] * amount_ratio
+                    } else {
+                        let units = self.held.get(&deposit).copied().unwrap_or_default() * amount_ratio;
+                        self.swap(deposit, *asset, units)
+                    };
+                    *self.held.entry(*asset).or_default() -= units;
+                    *self.supplied.entry(*asset).or_default() += units;
+                }
+                YieldStep::Borrow { asset, amount_ratio, .. } => {
+                    let units = self.value(&self.supplied) * amount_ratio / self.price(*asset);
+                    *self.borrowed.entry(*asset).or_default() += units;
+                    *self.held.entry(*asset).or_default() += units;
+                    last = *asset;
+                }
+                YieldStep::Swap { token_in, token_out, .. } => {
+                    let units = self.held.get(token_in).copied().unwrap_or_default();
+                    if units > 0.0 {
+                        self.swap(*token_in, *token_out, units);
+                    }
+                    last = *token_out;
+                }
+                YieldStep::Farm { pool_address, .. } => {
+                    // The pool's tokens are not known, pair the last asset obtained with the deposit
+                    let partner = if last != deposit {
+                        Some(deposit)
+                    } else {
+                        strategy.assets_involved.iter().copied().find(|asset| *asset != deposit)
+                    };
+                    let units = self.held.get(&last).copied().unwrap_or_default();
+                    match partner {
+                        Some(partner) if units > 0.0 => self.provide_liquidity(last, partner, units),
+                        _ => self.warnings.push(format!("Farm step in {:?} has nothing to pair and is skipped", pool_address)),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap half of `units` of `token_a` into `token_b` and deposit both at equal value
+    fn provide_liquidity(&mut self, token_a: Address, token_b: Address, units: f64) {
+        let half = units / 2.0;
+        let received = self.swap(token_a, token_b, half);
+        let value = (half * self.price(token_a)).min(received * self.price(token_b));
+        let (amount_a, amount_b) = (value / self.price(token_a), value / self.price(token_b));
+        *self.held.entry(token_a).or_default() -= amount_a;
+        *self.held.entry(token_b).or_default() -= amount_b;
+        let liquidity = (amount_a * amount_b).sqrt();
+        self.liquidity.push(LiquidityPosition {
+            token_a,
+            token_b,
+            liquidity,
+            deposited_liquidity: liquidity,
+            deposited_a: amount_a,
+            deposited_b: amount_b,
+        });
+    }
+
+    /// Allocate `value` at the policy's weights, paying the swap fee on all of it
+    fn allocate(&mut self, policy: &RebalancePolicy, weights: &HashMap<Address, f64>, value: f64) {
+        let value = value * (1.0 - self.fee_rate());
+        self.held.clear();
+        self.supplied.clear();
+        let balances = if policy.supply_idle { &mut self.supplied } else { &mut self.held };
+        for (asset, weight) in weights {
+            let price = self.prices.get(asset).copied().unwrap_or_default();
+            balances.insert(*asset, value * weight / price);
+        }
+    }
+
+    /// Trade back to the target weights once one drifted past the threshold, returning whether it did
+    fn rebalance(&mut self, policy: &RebalancePolicy, weights: &HashMap<Address, f64>) -> bool {
+        let equity = self.equity();
+        if equity <= 0.0 {
+            return false;
+        }
+        let balances = if policy.supply_idle { &self.supplied } else { &self.held };
+        let drifts: Vec<f64> = weights.iter()
+            .map(|(asset, weight)| balances.get(asset).copied().unwrap_or_default() * self.price(*asset) / equity - weight)
+            .collect();
+        if !drifts.iter().any(|drift| drift.abs() * 100.0 > policy.drift_threshold_percentage) {
+            return false;
+        }
+
+        // Only the traded half of the drift pays the fee
+        let traded = drifts.iter().map(|drift| drift.abs()).sum::<f64>() / 2.0 * equity;
+        let fee = traded * self.fee_rate();
+        self.fees_paid_usd += fee;
+        let value = equity - fee;
+        let balances = if policy.supply_idle { &mut self.supplied } else { &mut self.held };
+        for (asset, weight) in weights {
+            let price = self.prices.get(asset).copied().unwrap_or_default();
+            balances.insert(*asset, value * weight / price);
+        }
+        true
+    }
+
+    /// Interest and trading fees over `years` at the rates of `point`
+    fn accrue(&mut self, point: &MarketPoint, years: f64) {
+        let grow = |apy: Option<&f64>| (1.0 + apy.copied().unwrap_or_default() / 100.0).powf(years);
+        for (asset, units) in self.supplied.iter_mut() {
+            *units *= grow(point.supply_apy.get(asset));
+        }
+        for (asset, units) in self.borrowed.iter_mut() {
+            *units *= grow(point.borrow_apy.get(asset));
+        }
+        let fees = grow(Some(&self.request.lp_fee_apy));
+        for position in &mut self.liquidity {
+            position.liquidity *= fees;
+        }
+    }
+
+    /// Move to the prices of `point`, keeping the last known price of assets it has none for
+    fn update(&mut self, point: &MarketPoint) {
+        for (asset, price) in &point.prices_usd {
+            if *price > 0.0 {
+                self.prices.insert(*asset, *price);
+            }
+        }
+        self.thresholds.extend(point.liquidation_thresholds.iter().map(|(asset, threshold)| (*asset, *threshold)));
+    }
+
+    /// Repay part of the largest debt out of the largest collateral plus the bonus while unhealthy
+    fn liquidate(&mut self, timestamp: DateTime<Utc>) -> Option<LiquidationEvent> {
+        let health_factor = self.health_factor().filter(|health_factor| *health_factor < 1.0)?;
+        let largest = |balances: &HashMap<Address, f64>| balances.iter()
+            .map(|(asset, units)| (*asset, units * self.price(*asset)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let (debt_asset, debt_value) = largest(&self.borrowed)?;
+        let (collateral_asset, collateral_value) = largest(&self.supplied)?;
+
+        let seized = (debt_value * CLOSE_FACTOR * (1.0 + self.request.liquidation_bonus)).min(collateral_value);
+        let repaid = seized / (1.0 + self.request.liquidation_bonus);
+        *self.borrowed.entry(debt_asset).or_default() -= repaid / self.price(debt_asset);
+        *self.supplied.entry(collateral_asset).or_default() -= seized / self.price(collateral_asset);
+
+        Some(LiquidationEvent {
+            timestamp,
+            health_factor,
+            debt_asset,
+            debt_repaid_usd: repaid,
+            collateral_asset,
+            collateral_seized_usd: seized,
+            penalty_usd: seized - repaid,
+        })
+    }
+
+    /// Liquidity positions' loss against holding the deposits, and that holding's value
+    fn impermanent_loss(&self) -> (f64, f64) {
+        self.liquidity.iter().fold((0.0, 0.0), |(loss, held), position| {
+            let hold_value = position.deposited_a * self.price(position.token_a) + position.deposited_b * self.price(position.token_b);
+            (loss + self.liquidity_value(position, position.deposited_liquidity) - hold_value, held + hold_value)
+        })
+    }
+}
+
+/// Replay a strategy over a market history; points must be in time order
+pub fn run_backtest(request: &BacktestRequest, points: &[MarketPoint]) -> Result<BacktestReport> {
+    if !request.initial_capital_usd.is_finite() || request.initial_capital_usd <= 0.0 {
+        return Err(anyhow!("Initial capital must be positive"));
+    }
+    let (Some(start), Some(end)) = (points.first(), points.last()) else {
+        return Err(anyhow!("The history has no points"));
+    };
+    if points.len() < 2 || end.timestamp <= start.timestamp {
+        return Err(anyhow!("The history needs at least two points in time order"));
+    }
+
+    let mut simulation = Simulation::new(request, start, &request.strategy.assets())?;
+    let weights = match &request.strategy {
+        BacktestStrategy::Yield { strategy } => {
+            simulation.apply_yield_strategy(strategy)?;
+            None
+        }
+        BacktestStrategy::Rebalance { policy } => {
+            let total: f64 = policy.weights.values().filter(|weight| **weight > 0.0).sum();
+            if total <= 0.0 {
+                return Err(anyhow!("Rebalancing weights must include a positive weight"));
+            }
+            let weights: HashMap<Address, f64> = policy.weights.iter()
+                .filter(|(_, weight)| **weight > 0.0)
+                .map(|(asset, weight)| (*asset, weight / total))
+                .collect();
+            simulation.allocate(policy, &weights, request.initial_capital_usd);
+            Some((policy, weights))
+        }
+    };
+
+    let mut equity_curve = vec![EquityPoint {
+        timestamp: start.timestamp,
+        value_usd: simulation.equity(),
+        health_factor: simulation.health_factor(),
+    }];
+    let mut liquidations = Vec::new();
+    let mut rebalances = 0;
+    let mut peak = request.initial_capital_usd;
+    let mut max_drawdown: f64 = 0.0;
+    for window in points.windows(2) {
+        let (previous, point) = (&window[0], &window[1]);
+        let years = (point.timestamp - previous.timestamp).num_seconds().max(0) as f64 / SECONDS_PER_YEAR;
+        simulation.accrue(previous, years);
+        simulation.update(point);
+        if let Some((policy, weights)) = &weights {
+            rebalances += u32::from(simulation.rebalance(policy, weights));
+        }
+        if let Some(event) = simulation.liquidate(point.timestamp) {
+            liquidations.push(event);
+        }
+
+        let value_usd = simulation.equity();
+        peak = peak.max(value_usd);
+        if peak > 0.0 {
+            max_drawdown = max_drawdown.max((peak - value_usd) / peak);
+        }
+        equity_curve.push(EquityPoint { timestamp: point.timestamp, value_usd, health_factor: simulation.health_factor() });
+    }
+
+    let final_value_usd = simulation.equity();
+    let growth = final_value_usd / request.initial_capital_usd;
+    let years = (end.timestamp - start.timestamp).num_seconds() as f64 / SECONDS_PER_YEAR;
+    let apy = if growth > 0.0 { (growth.powf(1.0 / years) - 1.0) * 100.0 } else { -100.0 };
+    let (impermanent_loss_usd, liquidity_hold_value) = simulation.impermanent_loss();
+    if liquidations.is_empty() && simulation.health_factor().is_some_and(|health_factor| health_factor < 1.1) {
+        simulation.warnings.push("The position ends within 10% of liquidation".to_string());
+    }
+
+    Ok(BacktestReport {
+        chain_id: request.chain_id,
+        strategy: request.strategy.name(),
+        start: start.timestamp,
+        end: end.timestamp,
+        points: points.len(),
+        initial_value_usd: request.initial_capital_usd,
+        final_value_usd,
+        total_return_percentage: (growth - 1.0) * 100.0,
+        apy,
+        max_drawdown_percentage: max_drawdown * 100.0,
+        impermanent_loss_usd,
+        impermanent_loss_percentage: if liquidity_hold_value > 0.0 { impermanent_loss_usd / liquidity_hold_value * 100.0 } else { 0.0 },
+        rebalances,
+        fees_paid_usd: simulation.fees_paid_usd,
+        liquidations,
+        equity_curve,
+        warnings: simulation.warnings,
+    })
+}
+
+/// Loads market histories and replays strategies against them
+pub struct BacktestService {
+    defi_manager: Arc<DefiManager>,
+    price_feeds: Arc<PriceFeedService>,
+    store_path: Option<PathBuf>,
+    /// Points read from archive nodes per chain, by timestamp
+    history: RwLock<HashMap<u64, BTreeMap<DateTime<Utc>, MarketPoint>>>,
+}
+
+impl BacktestService {
+    pub async fn new(defi_manager: Arc<DefiManager>, price_feeds: Arc<PriceFeedService>, store_path: Option<PathBuf>) -> Result<Self> {
+        let stored: HashMap<u64, Vec<MarketPoint>> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        let history = stored.into_iter()
+            .map(|(chain_id, points)| (chain_id, points.into_iter().map(|point| (point.timestamp, point)).collect()))
+            .collect();
+
+        Ok(Self {
+            defi_manager,
+            price_feeds,
+            store_path,
+            history: RwLock::new(history),
+        })
+    }
+
+    /// Service persisting market history to `backtest_history_store_path`, an empty path keeps it in memory
+    pub async fn from_config(config: &config::Config, defi_manager: Arc<DefiManager>, price_feeds: Arc<PriceFeedService>) -> Result<Self> {
+        let path = config
+            .get_string("backtest_history_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(defi_manager, price_feeds, store_path).await
+    }
+
+    pub async fn run(&self, request: &BacktestRequest) -> Result<BacktestReport> {
+        let points = match &request.history {
+            HistorySource::Archive { from_block, to_block, step_blocks } => {
+                let points = self.archive_points(request.chain_id, &request.strategy.assets(), *from_block, *to_block, *step_blocks).await?;
+                self.record(request.chain_id, &points).await;
+                points
+            }
+            HistorySource::Stored { from, to } => self.stored_points(request.chain_id, *from, *to).await,
+            HistorySource::Inline { points } => {
+                let mut points = points.clone();
+                points.sort_by_key(|point| point.timestamp);
+                points
+            }
+        };
+
+        let report = run_backtest(request, &points)?;
+        info!(
+            "Backtested {} on chain {} over {} points: {:.2}% APY, {:.2}% max drawdown, {} liquidations",
+            report.strategy, report.chain_id, report.points, report.apy, report.max_drawdown_percentage, report.liquidations.len(),
+        );
+        Ok(report)
+    }
+
+    /// Points of the market history store on a chain between two optional dates, oldest first
+    pub async fn stored_points(&self, chain_id: u64, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Vec<MarketPoint> {
+        self.history.read().await
+            .get(&chain_id)
+            .map(|points| points.values()
+                .filter(|point| from.is_none_or(|from| point.timestamp >= from) && to.is_none_or(|to| point.timestamp <= to))
+                .cloned()
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Aave rates, thresholds and prices of `assets` every `step_blocks` blocks
+    async fn archive_points(&self, chain_id: u64, assets: &[Address], from_block: u64, to_block: u64, step_blocks: u64) -> Result<Vec<MarketPoint>> {
+        if step_blocks == 0 || to_block <= from_block {
+            return Err(anyhow!("Archive replays need from_block before to_block and a positive step_blocks"));
+        }
+        if (to_block - from_block) / step_blocks + 1 > MAX_ARCHIVE_POINTS {
+            return Err(anyhow!("Archive replays read at most {} blocks, raise step_blocks", MAX_ARCHIVE_POINTS));
+        }
+        let aggregator = eth_usd_aggregator(chain_id)
+            .ok_or_else(|| anyhow!("No ETH/USD feed to price archive data on chain {}", chain_id))?;
+        let provider = self.defi_manager.dex_manager().chain_manager().get_provider(chain_id).await?;
+        let aave = self.defi_manager.aave();
+
+        let mut points = Vec::new();
+        for block in (from_block..=to_block).step_by(step_blocks as usize) {
+            let header = provider.provider.get_block(block).await?
+                .ok_or_else(|| anyhow!("Block {} not found", block))?;
+            let timestamp = Utc.timestamp_opt(header.timestamp.as_u64() as i64, 0).single()
+                .ok_or_else(|| anyhow!("Block {} has an invalid timestamp", block))?;
+            let eth_usd = self.price_feeds.chainlink_answer_at(chain_id, aggregator, block).await?;
+            let reserves = try_join_all(assets.iter().map(|asset| aave.reserve_at_block(chain_id, *asset, block))).await
+                .map_err(|e| anyhow!("Reading Aave reserves at block {} failed, an archive node is required: {}", block, e))?;
+
+            points.push(MarketPoint {
+                timestamp,
+                block_number: Some(block),
+                prices_usd: reserves.iter()
+                    .map(|reserve| (reserve.asset, reserve.price_eth.as_u128() as f64 / 1e18 * eth_usd))
+                    .collect(),
+                supply_apy: reserves.iter().map(|reserve| (reserve.asset, reserve.supply_apy)).collect(),
+                borrow_apy: reserves.iter().map(|reserve| (reserve.asset, reserve.variable_borrow_apy)).collect(),
+                liquidation_thresholds: reserves.iter()
+                    .filter(|reserve| reserve.liquidation_threshold > 0.0)
+                    .map(|reserve| (reserve.asset, reserve.liquidation_threshold))
+                    .collect(),
+            });
+        }
+        Ok(points)
+    }
+
+    /// Merge archive points into the stored history, points at the same time merge their assets
+    async fn record(&self, chain_id: u64, points: &[MarketPoint]) {
+        let mut history = self.history.write().await;
+        let chain = history.entry(chain_id).or_default();
+        for point in points {
+            match chain.get_mut(&point.timestamp) {
+                Some(existing) => {
+                    existing.prices_usd.extend(point.prices_usd.clone());
+                    existing.supply_apy.extend(point.supply_apy.clone());
+                    existing.borrow_apy.extend(point.borrow_apy.clone());
+                    existing.liquidation_thresholds.extend(point.liquidation_thresholds.clone());
+                }
+                None => {
+                    chain.insert(point.timestamp, point.clone());
+                }
+            }
+        }
+
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let stored: HashMap<u64, Vec<&MarketPoint>> = history.iter()
+            .map(|(chain_id, points)| (*chain_id, points.values().collect()))
+            .collect();
+        if let Err(e) = write_store(path, &stored).await {
+            warn!("Failed to persist market history to {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// Carrying cost projections for lending and leveraged positions
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::defi::PositionValuation;
+
+/// Rate observations kept per market
+const MAX_OBSERVATIONS: usize = 1000;
+/// Minimum history span before a trend is extrapolated
+const MIN_TREND_SPAN_HOURS: i64 = 1;
+/// Perpetual funding is settled three times a day
+const FUNDING_PERIODS_PER_DAY: f64 = 3.0;
+
+/// Rates of a lending market at a point in time, APYs in percent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateObservation {
+    pub observed_at: DateTime<Utc>,
+    pub supply_apy: f64,
+    pub borrow_apy: f64,
+}
+
+/// Current rates of a market and their recent drift
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateTrend {
+    pub protocol: String,
+    pub asset: Address,
+    pub supply_apy: f64,
+    pub borrow_apy: f64,
+    /// Change of the APY in percentage points per day, from a least-squares fit of the history
+    pub supply_apy_slope_per_day: f64,
+    pub borrow_apy_slope_per_day: f64,
+    pub observations: usize,
+}
+
+/// Direction of a perpetual futures hedge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HedgeSide {
+    Long,
+    Short,
+}
+
+/// Perpetual futures position hedging the lending exposure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerpHedge {
+    #[serde(with = "crate::api::models::usd")]
+    pub notional_usd: f64,
+    /// Funding rate per 8 hour period as a fraction; positive means longs pay shorts
+    pub funding_rate_8h: f64,
+    pub side: HedgeSide,
+}
+
+/// Projected cash flows of a single day, positive values are income
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CarryDay {
+    pub day: u32,
+    pub date: NaiveDate,
+    #[serde(with = "crate::api::models::usd")]
+    pub supply_interest_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub borrow_interest_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub rewards_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub funding_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub cumulative_net_usd: f64,
+}
+
+/// Carrying costs summed over a horizon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CarryProjection {
+    pub horizon_days: u32,
+    #[serde(with = "crate::api::models::usd")]
+    pub supply_interest_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub borrow_interest_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub rewards_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub funding_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_usd: f64,
+    pub net_positive: bool,
+    pub daily: Vec<CarryDay>,
+}
+
+/// Carrying cost calendar of a user's positions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CarryCalendar {
+    pub chain_id: u64,
+    pub user: Address,
+    pub hedge: Option<PerpHedge>,
+    pub rate_trends: Vec<RateTrend>,
+    pub projections: Vec<CarryProjection>,
+    pub generated_at: DateTime<Utc>,
+}
+
+type MarketKey = (u64, String, Address);
+
+/// Projects interest, rewards and funding of positions from current rates and their trends
+pub struct CarryCalendarService {
+    history: Arc<RwLock<HashMap<MarketKey, VecDeque<RateObservation>>>>,
+}
+
+impl CarryCalendarService {
+    pub async fn new() -> Result<Self> {
+        Ok(Self {
+            history: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Record the current rates of the positions' markets
+    pub async fn record_rates(&self, chain_id: u64, positions: &[PositionValuation]) {
+        let now = Utc::now();
+        let mut history = self.history.write().await;
+
+        for position in positions {
+            let observations = history
+                .entry((chain_id, position.protocol.clone(), position.asset))
+                .or_default();
+            observations.push_back(RateObservation {
+                observed_at: now,
+                supply_apy: position.supply_apy,
+                borrow_apy: position.borrow_apy,
+            });
+            while observations.len() > MAX_OBSERVATIONS {
+                observations.pop_front();
+            }
+        }
+    }
+
+    /// Current rates of a market and their per-day drift
+    pub async fn rate_trend(&self, chain_id: u64, position: &PositionValuation) -> RateTrend {
+        let history = self.history.read().await;
+        let observations = history.get(&(chain_id, position.protocol.clone(), position.asset));
+
+        let (supply_slope, borrow_slope, count) = match observations {
+            Some(observations) => (
+                Self::slope_per_day(observations, |o| o.supply_apy),
+                Self::slope_per_day(observations, |o| o.borrow_apy),
+                observations.len(),
+            ),
+            None => (0.0, 0.0, 0),
+        };
+
+        RateTrend {
+            protocol: position.protocol.clone(),
+            asset: position.asset,
+            supply_apy: position.supply_apy,
+            borrow_apy: position.borrow_apy,
+            supply_apy_slope_per_day: supply_slope,
+            borrow_apy_slope_per_day: borrow_slope,
+            observations: count,
+        }
+    }
+
+    /// Build projections of the positions' carrying costs for each horizon, dated from the
+    /// user's local `today`
+    pub async fn build_calendar(
+        &self,
+        chain_id: u64,
+        user: Address,
+        positions: &[PositionValuation],
+        hedge: Option<PerpHedge>,
+        horizons_days: &[u32],
+        today: NaiveDate,
+    ) -> CarryCalendar {
+        self.record_rates(chain_id, positions).await;
+
+        let mut rate_trends = Vec::with_capacity(positions.len());
+        for position in positions {
+            rate_trends.push(self.rate_trend(chain_id, position).await);
+        }
+
+        let projections = horizons_days.iter()
+            .map(|horizon| Self::project(positions, &rate_trends, hedge.as_ref(), *horizon, today))
+            .collect();
+
+        CarryCalendar {
+            chain_id,
+            user,
+            hedge,
+            rate_trends,
+            projections,
+            generated_at: Utc::now(),
+        }
+    }
+
+    /// Day-by-day projection; interest follows the rate trend, rewards and funding are held flat
+    fn project(
+        positions: &[PositionValuation],
+        trends: &[RateTrend],
+        hedge: Option<&PerpHedge>,
+        horizon_days: u32,
+        start: NaiveDate,
+    ) -> CarryProjection {
+        let mut daily = Vec::with_capacity(horizon_days as usize);
+        let mut cumulative = 0.0;
+
+        for day in 1..=horizon_days {
+            // Rates are sampled mid-day to approximate the day's average
+            let elapsed = day as f64 - 0.5;
+            let mut supply_interest = 0.0;
+            let mut borrow_interest = 0.0;
+            let mut rewards = 0.0;
+
+            for (position, trend) in positions.iter().zip(trends) {
+                let supply_apy = (trend.supply_apy + trend.supply_apy_slope_per_day * elapsed).max(0.0);
+                let borrow_apy = (trend.borrow_apy + trend.borrow_apy_slope_per_day * elapsed).max(0.0);
+
+                supply_interest += Self::daily_amount(position.supplied_usd, supply_apy);
+                borrow_interest -= Self::daily_amount(position.borrowed_usd, borrow_apy);
+                rewards += Self::daily_amount(position.supplied_usd, position.supply_reward_apy)
+                    + Self::daily_amount(position.borrowed_usd, position.borrow_reward_apy);
+            }
+
+            let funding = hedge.map(Self::daily_funding).unwrap_or(0.0);
+            let net = supply_interest + borrow_interest + rewards + funding;
+            cumulative += net;
+
+            daily.push(CarryDay {
+                day,
+                date: start + Duration::days(day as i64),
+                supply_interest_usd: supply_interest,
+                borrow_interest_usd: borrow_interest,
+                rewards_usd: rewards,
+                funding_usd: funding,
+                net_usd: net,
+                cumulative_net_usd: cumulative,
+            });
+        }
+
+        let sum = |field: fn(&CarryDay) -> f64| daily.iter().map(field).sum::<f64>();
+        CarryProjection {
+            horizon_days,
+            supply_interest_usd: sum(|d| d.supply_interest_usd),
+            borrow_interest_usd: sum(|d| d.borrow_interest_usd),
+            rewards_usd: sum(|d| d.rewards_usd),
+            funding_usd: sum(|d| d.funding_usd),
+            net_usd: cumulative,
+            net_positive: cumulative >= 0.0,
+            daily,
+        }
+    }
+
+    fn daily_amount(value_usd: f64, apy_percent: f64) -> f64 {
+        value_usd * apy_percent / 100.0 / 365.0
+    }
+
+    /// Funding received (positive) or paid (negative) per day
+    fn daily_funding(hedge: &PerpHedge) -> f64 {
+        let paid_by_longs = hedge.notional_usd * hedge.funding_rate_8h * FUNDING_PERIODS_PER_DAY;
+        match hedge.side {
+            HedgeSide::Long => -paid_by_longs,
+            HedgeSide::Short => paid_by_longs,
+        }
+    }
+
+    /// Least-squares slope of a rate over time, zero until the history spans long enough
+    fn slope_per_day(observations: &VecDeque<RateObservation>, rate: fn(&RateObservation) -> f64) -> f64 {
+        let (first, last) = match (observations.front(), observations.back()) {
+            (Some(first), Some(last)) => (first.observed_at, last.observed_at),
+            _ => return 0.0,
+        };
+        if last - first < Duration::hours(MIN_TREND_SPAN_HOURS) {
+            return 0.0;
+        }
+
+        let points: Vec<(f64, f64)> = observations.iter()
+            .map(|o| ((o.observed_at - first).num_seconds() as f64 / 86_400.0, rate(o)))
+            .collect();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let variance: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+
+        if variance > 0.0 { covariance / variance } else { 0.0 }
+    }
+}
@@ -0,0 +1,151 @@
+// USD cost of the gas built transactions are expected to burn
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, BlockNumber, TransactionRequest, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService};
+use crate::chains::gas_optimizer::uses_legacy_gas_price;
+use crate::chains::ChainManager;
+
+/// Fee data is reused for about a mainnet block
+const FEE_DATA_TTL_SECS: i64 = 12;
+
+/// Fee data and native token price of a chain, what a unit of gas costs right now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasFeeQuote {
+    pub chain_id: u64,
+    /// `None` on chains without EIP-1559 or priced by gas price, like BSC
+    pub base_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    /// Price a transaction is expected to pay per gas, base fee plus tip or the legacy gas price
+    pub gas_price: U256,
+    /// Most a transaction priced at the suggested fees can pay per gas
+    pub max_fee_per_gas: U256,
+    pub native_price_usd: f64,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl GasFeeQuote {
+    /// Expected USD cost of `gas_units`
+    pub fn cost_usd(&self, gas_units: U256) -> f64 {
+        wei_to_native(gas_units.saturating_mul(self.gas_price)) * self.native_price_usd
+    }
+}
+
+/// Gas cost of one transaction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GasCost {
+    pub gas_units: U256,
+    pub gas_price: U256,
+    #[serde(with = "crate::api::models::usd")]
+    pub estimated_cost_usd: f64,
+}
+
+/// Prices gas estimates with the chain's current fees and native token price
+pub struct GasCostEstimator {
+    chain_manager: Arc<ChainManager>,
+    price_feeds: Arc<PriceFeedService>,
+    quotes: RwLock<HashMap<u64, GasFeeQuote>>,
+}
+
+impl GasCostEstimator {
+    pub fn new(chain_manager: Arc<ChainManager>, price_feeds: Arc<PriceFeedService>) -> Self {
+        Self {
+            chain_manager,
+            price_feeds,
+            quotes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Current fee data of a chain, cached for about a block
+    pub async fn fee_quote(&self, chain_id: u64) -> Result<GasFeeQuote> {
+        if let Some(quote) = self.quotes.read().await.get(&chain_id)
+            .filter(|quote| (Utc::now() - quote.fetched_at).num_seconds() < FEE_DATA_TTL_SECS)
+        {
+            return Ok(quote.clone());
+        }
+
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let base_fee_per_gas = if uses_legacy_gas_price(chain_id) {
+            None
+        } else {
+            chain.provider.get_block(BlockNumber::Latest).await?
+                .and_then(|block| block.base_fee_per_gas)
+        };
+        let (gas_price, max_fee_per_gas, max_priority_fee_per_gas) = match base_fee_per_gas {
+            Some(base_fee) => {
+                let (max_fee, priority_fee) = chain.provider.estimate_eip1559_fees(None).await?;
+                (base_fee.saturating_add(priority_fee).min(max_fee), max_fee, Some(priority_fee))
+            }
+            None => {
+                let gas_price = chain.provider.get_gas_price().await?;
+                (gas_price, gas_price, None)
+            }
+        };
+        let native_price_usd = self.price_feeds
+            .get_price(chain_id, pricing_address(chain_id, Address::zero())).await?
+            .price_usd;
+
+        let quote = GasFeeQuote {
+            chain_id,
+            base_fee_per_gas,
+            max_priority_fee_per_gas,
+            gas_price,
+            max_fee_per_gas,
+            native_price_usd,
+            fetched_at: Utc::now(),
+        };
+        self.quotes.write().await.insert(chain_id, quote.clone());
+        Ok(quote)
+    }
+
+    /// Cost of `gas_units` at the chain's current fees
+    pub async fn cost(&self, chain_id: u64, gas_units: U256) -> Result<GasCost> {
+        let quote = self.fee_quote(chain_id).await?;
+        Ok(GasCost {
+            gas_units,
+            gas_price: quote.gas_price,
+            estimated_cost_usd: quote.cost_usd(gas_units),
+        })
+    }
+
+    /// Cost of a built transaction, from its gas limit, the `gas_estimate` given or a node estimate in that order
+    ///
+    /// `None` when no estimate is available, e.g. for a transaction that reverts until an earlier one of its
+    /// bundle is mined, or when the chain's fees or native price cannot be read.
+    pub async fn transaction_cost(&self, chain_id: u64, tx: &TransactionRequest, gas_estimate: Option<U256>) -> Option<GasCost> {
+        let gas_units = match tx.gas.or(gas_estimate) {
+            Some(gas_units) => gas_units,
+            None => {
+                let chain = self.chain_manager.get_provider(chain_id).await.ok()?;
+                let typed = TypedTransaction::Legacy(tx.clone());
+                match chain.provider.estimate_gas(&typed, None).await {
+                    Ok(gas_units) => gas_units,
+                    Err(e) => {
+                        debug!("No gas estimate for a transaction to {:?} on chain {}: {}", tx.to, chain_id, e);
+                        return None;
+                    }
+                }
+            }
+        };
+        match self.cost(chain_id, gas_units).await {
+            Ok(cost) => Some(cost),
+            Err(e) => {
+                debug!("Gas on chain {} is unpriced: {}", chain_id, e);
+                None
+            }
+        }
+    }
+}
+
+fn wei_to_native(wei: U256) -> f64 {
+    wei.to_string().parse::<f64>().unwrap_or(0.0) / 1e18
+}
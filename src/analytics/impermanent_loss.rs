@@ -0,0 +1,151 @@
+// Impermanent loss of constant-product and concentrated liquidity positions
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// Price moves from the entry price the projections are computed for, in percent
+const PROJECTED_PRICE_CHANGES: [f64; 12] = [-90.0, -75.0, -50.0, -25.0, -10.0, -5.0, 5.0, 10.0, 25.0, 50.0, 100.0, 300.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolModel {
+    UniswapV2,
+    SushiSwap,
+    UniswapV3,
+}
+
+impl PoolModel {
+    pub fn is_concentrated(self) -> bool {
+        self == PoolModel::UniswapV3
+    }
+}
+
+/// Impermanent loss at one price
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IlProjection {
+    /// Move of the price from the entry price, in percent
+    pub price_change_percentage: f64,
+    pub price: f64,
+    pub impermanent_loss_percentage: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpermanentLoss {
+    pub protocol: PoolModel,
+    pub entry_price: f64,
+    pub current_price: f64,
+    pub price_change_percentage: f64,
+    pub price_lower: Option<f64>,
+    pub price_upper: Option<f64>,
+    pub in_range: bool,
+    /// Value of the position against holding the deposited tokens, negative for a loss; trading fees are not included
+    pub impermanent_loss_percentage: f64,
+    /// Loss on the deposit's value at entry, measured in the quote token
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub impermanent_loss_usd: Option<f64>,
+    pub projections: Vec<IlProjection>,
+}
+
+/// Token amounts held by `liquidity` in a price range at `price`
+pub fn liquidity_amounts(liquidity: f64, price: f64, lower: f64, upper: f64) -> (f64, f64) {
+    let sqrt_price = price.clamp(lower, upper).sqrt();
+    (liquidity * (1.0 / sqrt_price - 1.0 / upper.sqrt()), liquidity * (sqrt_price - lower.sqrt()))
+}
+
+/// Value in the quote token of a position holding one unit of liquidity at `amounts_price`, priced at `price`
+fn position_value(price: f64, amounts_price: f64, range: Option<(f64, f64)>) -> f64 {
+    let (amount0, amount1) = match range {
+        // x = L / sqrt(P), y = L * sqrt(P)
+        None => (1.0 / amounts_price.sqrt(), amounts_price.sqrt()),
+        Some((lower, upper)) => liquidity_amounts(1.0, amounts_price, lower, upper),
+    };
+    amount0 * price + amount1
+}
+
+/// Impermanent loss of liquidity deposited at `entry_price` once the price is `current_price`, in percent
+///
+/// Prices are in the quote token per base token (token1 per token0); `range` bounds a concentrated position.
+pub fn impermanent_loss_percentage(entry_price: f64, current_price: f64, range: Option<(f64, f64)>) -> f64 {
+    let held = position_value(current_price, entry_price, range);
+    if held <= 0.0 {
+        return 0.0;
+    }
+    (position_value(current_price, current_price, range) / held - 1.0) * 100.0
+}
+
+/// Impermanent loss of a position with projections over price moves from its entry price
+pub fn impermanent_loss(
+    protocol: PoolModel,
+    entry_price: f64,
+    current_price: f64,
+    range: Option<(f64, f64)>,
+    deposit_usd: Option<f64>,
+) -> Result<ImpermanentLoss> {
+    if !(entry_price.is_finite() && entry_price > 0.0 && current_price.is_finite() && current_price > 0.0) {
+        return Err(anyhow!("Entry and current prices must be positive"));
+    }
+    let range = match (protocol.is_concentrated(), range) {
+        (true, Some((lower, upper))) if lower > 0.0 && lower < upper && upper.is_finite() => Some((lower, upper)),
+        (true, Some(_)) => return Err(anyhow!("The price range needs 0 < price_lower < price_upper")),
+        (true, None) => return Err(anyhow!("Uniswap V3 positions need price_lower and price_upper")),
+        (false, _) => None,
+    };
+
+    let loss = impermanent_loss_percentage(entry_price, current_price, range);
+    // The deposit held to now, in the quote token, against its value at entry
+    let held_growth = position_value(current_price, entry_price, range) / position_value(entry_price, entry_price, range);
+    let projections = PROJECTED_PRICE_CHANGES.iter()
+        .map(|change| {
+            let price = entry_price * (1.0 + change / 100.0);
+            IlProjection {
+                price_change_percentage: *change,
+                price,
+                impermanent_loss_percentage: impermanent_loss_percentage(entry_price, price, range),
+            }
+        })
+        .collect();
+
+    Ok(ImpermanentLoss {
+        protocol,
+        entry_price,
+        current_price,
+        price_change_percentage: (current_price / entry_price - 1.0) * 100.0,
+        price_lower: range.map(|(lower, _)| lower),
+        price_upper: range.map(|(_, upper)| upper),
+        in_range: range.is_none_or(|(lower, upper)| (lower..=upper).contains(&current_price)),
+        impermanent_loss_percentage: loss,
+        impermanent_loss_usd: deposit_usd.map(|deposit| deposit * held_growth * loss / 100.0),
+        projections,
+    })
+}
+
+/// Impermanent loss of a constant-product pool over the projected price moves
+pub fn constant_product_projections() -> Vec<IlProjection> {
+    PROJECTED_PRICE_CHANGES.iter()
+        .map(|change| IlProjection {
+            price_change_percentage: *change,
+            price: 1.0 + change / 100.0,
+            impermanent_loss_percentage: impermanent_loss_percentage(1.0, 1.0 + change / 100.0, None),
+        })
+        .collect()
+}
+
+/// Price of token0 in token1 at a tick, in raw token units
+pub fn tick_to_price(tick: i32) -> f64 {
+    1.0001f64.powi(tick)
+}
+
+/// Price a concentrated position was opened at, from the amounts and liquidity of its first deposit
+///
+/// A deposit of token0 only was made at or below the range, one of token1 only at or above it; either
+/// bound gives the same amounts as the actual price.
+pub fn entry_price_from_deposit(liquidity: f64, amount0: f64, amount1: f64, tick_lower: i32, tick_upper: i32) -> Option<f64> {
+    let (lower, upper) = (tick_to_price(tick_lower), tick_to_price(tick_upper));
+    match (amount0 > 0.0, amount1 > 0.0) {
+        (_, _) if liquidity <= 0.0 => None,
+        (true, false) => Some(lower),
+        (false, true) => Some(upper),
+        // y = L * (sqrt(P) - sqrt(Pa))
+        (true, true) => Some((amount1 / liquidity + lower.sqrt()).powi(2).clamp(lower, upper)),
+        (false, false) => None,
+    }
+}
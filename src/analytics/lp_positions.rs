@@ -0,0 +1,158 @@
+// Value, accrued fees and fee APR of Uniswap V3 liquidity positions
+use anyhow::Result;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::analytics::impermanent_loss::{liquidity_amounts, tick_to_price};
+use crate::analytics::price_feeds::PriceFeedService;
+use crate::contracts::erc20::ERC20Contract;
+use crate::dex::uniswap::LiquidityPosition;
+use crate::dex::DexManager;
+
+/// About a day of mainnet blocks
+const DEFAULT_FEE_APR_WINDOW_BLOCKS: u64 = 7_200;
+const SECONDS_PER_YEAR: f64 = 365.25 * 86_400.0;
+
+/// A liquidity position with its value and earnings in USD
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionEarnings {
+    pub chain_id: u64,
+    #[serde(flatten)]
+    pub position: LiquidityPosition,
+    /// Tokens the position's liquidity holds at the current price
+    pub amount0: f64,
+    pub amount1: f64,
+    pub token0_price_usd: Option<f64>,
+    pub token1_price_usd: Option<f64>,
+    /// Value of the liquidity, uncollected fees excluded
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub value_usd: Option<f64>,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub uncollected_fees_usd: Option<f64>,
+    /// Pool volume over the fee APR window
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub pool_volume_usd: Option<f64>,
+    pub volume_window_secs: Option<u64>,
+    /// Fees the position's share of the active liquidity earned over the window, annualized against its value, in
+    /// percent; 0 while out of range
+    pub fee_apr: Option<f64>,
+}
+
+/// Reads Uniswap V3 positions and prices their liquidity, fees and fee APR
+pub struct LpPositionTracker {
+    dex_manager: Arc<DexManager>,
+    price_feeds: Arc<PriceFeedService>,
+    window_blocks: u64,
+}
+
+impl LpPositionTracker {
+    pub fn new(dex_manager: Arc<DexManager>, price_feeds: Arc<PriceFeedService>, window_blocks: u64) -> Self {
+        Self { dex_manager, price_feeds, window_blocks: window_blocks.max(1) }
+    }
+
+    /// Tracker estimating fee APRs over the last `lp_fee_apr_window_blocks` blocks
+    pub fn from_config(config: &config::Config, dex_manager: Arc<DexManager>, price_feeds: Arc<PriceFeedService>) -> Self {
+        let window_blocks = config
+            .get_int("lp_fee_apr_window_blocks")
+            .map(|blocks| blocks.max(1) as u64)
+            .unwrap_or(DEFAULT_FEE_APR_WINDOW_BLOCKS);
+        Self::new(dex_manager, price_feeds, window_blocks)
+    }
+
+    pub async fn positions(&self, chain_id: u64, owner: Address) -> Result<Vec<PositionEarnings>> {
+        let positions = self.dex_manager.uniswap().get_positions(chain_id, owner).await?;
+        let mut earnings = Vec::with_capacity(positions.len());
+        for position in positions {
+            earnings.push(self.earnings(chain_id, position).await);
+        }
+        Ok(earnings)
+    }
+
+    pub async fn position(&self, chain_id: u64, token_id: U256) -> Result<PositionEarnings> {
+        let position = self.dex_manager.uniswap().get_position(chain_id, token_id).await?;
+        Ok(self.earnings(chain_id, position).await)
+    }
+
+    async fn earnings(&self, chain_id: u64, position: LiquidityPosition) -> PositionEarnings {
+        let (decimals0, decimals1) = futures::join!(
+            self.token_decimals(chain_id, position.token0),
+            self.token_decimals(chain_id, position.token1),
+        );
+        let units = |raw: f64, decimals: u8| raw / 10f64.powi(decimals as i32);
+        let raw = |amount: U256| amount.to_string().parse::<f64>().unwrap_or_default();
+
+        let liquidity = raw(position.liquidity);
+        let (lower, upper) = (tick_to_price(position.tick_lower), tick_to_price(position.tick_upper));
+        let (amount0, amount1) = position.current_price
+            .map(|price| liquidity_amounts(liquidity, price, lower, upper))
+            .map(|(amount0, amount1)| (units(amount0, decimals0), units(amount1, decimals1)))
+            .unwrap_or_default();
+
+        let prices = self.price_feeds.get_prices(chain_id, &[position.token0, position.token1]).await
+            .unwrap_or_else(|e| {
+                warn!("Could not price the tokens of position {}: {}", position.token_id, e);
+                Default::default()
+            });
+        let token0_price_usd = prices.get(&position.token0).map(|price| price.price_usd);
+        let token1_price_usd = prices.get(&position.token1).map(|price| price.price_usd);
+        let prices = token0_price_usd.zip(token1_price_usd);
+        let usd = |amount0: f64, amount1: f64| prices.map(|(price0, price1)| amount0 * price0 + amount1 * price1);
+
+        let value_usd = usd(amount0, amount1);
+        let uncollected_fees_usd = usd(
+            units(raw(position.uncollected_fees0), decimals0),
+            units(raw(position.uncollected_fees1), decimals1),
+        );
+
+        let volume = match self.dex_manager.uniswap().get_pool_volume(chain_id, position.pool, self.window_blocks).await {
+            Ok(volume) => Some(volume),
+            Err(e) => {
+                warn!("Could not read the volume of pool {:?}: {}", position.pool, e);
+                None
+            }
+        };
+        let pool_volume_usd = volume.as_ref()
+            .and_then(|volume| usd(units(raw(volume.volume0), decimals0), units(raw(volume.volume1), decimals1)));
+        let pool_liquidity = self.dex_manager.uniswap()
+            .get_pool_info(chain_id, position.token0, position.token1, position.fee).await
+            .map(|pool| raw(pool.liquidity))
+            .ok();
+
+        let fee_apr = match (position.in_range, pool_volume_usd, volume.as_ref(), pool_liquidity, value_usd) {
+            (Some(false), ..) => Some(0.0),
+            (Some(true), Some(volume_usd), Some(volume), Some(pool_liquidity), Some(value_usd))
+                if volume.elapsed_secs > 0 && pool_liquidity > 0.0 && value_usd > 0.0 =>
+            {
+                let fees_usd = volume_usd * position.fee as f64 / 1_000_000.0 * (liquidity / pool_liquidity).min(1.0);
+                Some(fees_usd * SECONDS_PER_YEAR / volume.elapsed_secs as f64 / value_usd * 100.0)
+            }
+            _ => None,
+        };
+
+        PositionEarnings {
+            chain_id,
+            amount0,
+            amount1,
+            token0_price_usd,
+            token1_price_usd,
+            value_usd,
+            uncollected_fees_usd,
+            pool_volume_usd,
+            volume_window_secs: volume.map(|volume| volume.elapsed_secs),
+            fee_apr,
+            position,
+        }
+    }
+
+    async fn token_decimals(&self, chain_id: u64, token: Address) -> u8 {
+        let Ok(chain) = self.dex_manager.chain_manager().get_provider(chain_id).await else {
+            return 18;
+        };
+        match ERC20Contract::new(token, Arc::new(chain.provider.clone()), chain_id).await {
+            Ok(contract) => contract.get_token_info().map(|info| info.decimals).unwrap_or(18),
+            Err(_) => 18,
+        }
+    }
+}
@@ -1,20 +1,75 @@
 use anyhow::Result;
+use std::sync::Arc;
 
+pub mod backtest;
+pub mod carry_calendar;
+pub mod gas_costs;
+pub mod impermanent_loss;
+pub mod lp_positions;
+pub mod nft_portfolio;
 pub mod price_feeds;
+pub mod portfolio_import;
 pub mod portfolio_tracker;
 pub mod yield_analyzer;
 pub mod risk_assessor;
+pub mod snapshotter;
+pub mod tax_export;
+pub mod time_zones;
+pub mod token_balances;
+
+use crate::chains::assets::AssetRegistry;
+use crate::chains::ChainManager;
+use carry_calendar::CarryCalendarService;
+use gas_costs::GasCostEstimator;
+use portfolio_tracker::{CostBasisMethod, PortfolioTracker};
+use price_feeds::{PriceFeedConfig, PriceFeedService};
+use time_zones::{TenantTimeSettings, TimeZoneSettings};
 
 pub struct AnalyticsService {
-    // Analytics functionality
+    pub price_feeds: Arc<PriceFeedService>,
+    pub gas_costs: Arc<GasCostEstimator>,
+    pub carry: Arc<CarryCalendarService>,
+    pub portfolio: Arc<PortfolioTracker>,
+    pub time_zones: Arc<TimeZoneSettings>,
 }
 
 impl AnalyticsService {
-    pub async fn new(_config: &config::Config) -> Result<Self> {
-        Ok(Self {})
+    pub async fn new(config: &config::Config) -> Result<Self> {
+        let chain_manager = Arc::new(ChainManager::new_demo().await?);
+        Self::with_chain_manager(config, chain_manager).await
+    }
+
+    /// Create analytics reading on-chain prices through a shared chain manager
+    pub async fn with_chain_manager(config: &config::Config, chain_manager: Arc<ChainManager>) -> Result<Self> {
+        let price_feeds = Arc::new(
+            PriceFeedService::with_reference_feeds(
+                chain_manager.clone(),
+                PriceFeedConfig::from_config(config),
+                &AssetRegistry::from_config(config).await?,
+            ).await?,
+        );
+        let gas_costs = Arc::new(GasCostEstimator::new(chain_manager, price_feeds.clone()));
+        let carry = Arc::new(CarryCalendarService::new().await?);
+        let portfolio = Arc::new(PortfolioTracker::from_config(config).await?);
+        let time_zones = Arc::new(TimeZoneSettings::from_config(config)?);
+
+        Ok(Self { price_feeds, gas_costs, carry, portfolio, time_zones })
     }
 
     pub async fn new_demo() -> Result<Self> {
-        Ok(Self {})
+        let chain_manager = Arc::new(ChainManager::new_demo().await?);
+        let price_feeds = Arc::new(
+            PriceFeedService::with_reference_feeds(
+                chain_manager.clone(),
+                PriceFeedConfig::default(),
+                &AssetRegistry::builtin(),
+            ).await?,
+        );
+        let gas_costs = Arc::new(GasCostEstimator::new(chain_manager, price_feeds.clone()));
+        let carry = Arc::new(CarryCalendarService::new().await?);
+        let portfolio = Arc::new(PortfolioTracker::new(CostBasisMethod::default(), None).await?);
+        let time_zones = Arc::new(TimeZoneSettings::new(TenantTimeSettings::default())?);
+
+        Ok(Self { price_feeds, gas_costs, carry, portfolio, time_zones })
     }
 }
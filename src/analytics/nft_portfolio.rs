@@ -0,0 +1,500 @@
+// NFT holdings of wallets with token metadata and floor price valuation
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ethers::{
+    abi::Token,
+    contract::Contract,
+    providers::Middleware,
+    types::{Address, U256},
+};
+use futures::{future::join_all, stream, FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService};
+use crate::analytics::token_balances::{incoming_transfers, ChainScanError};
+use crate::cache::{CacheManager, NamespacedCache};
+use crate::chains::ChainManager;
+use crate::contracts::erc721::{ERC721Contract, NFTAttribute};
+use crate::contracts::multicall::multicall;
+
+/// Blocks searched for received NFTs the first time a wallet is scanned on a chain
+const DEFAULT_DISCOVERY_BLOCKS: u64 = 10_000;
+const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+/// ERC-165 id of ERC721Enumerable
+const ENUMERABLE_INTERFACE_ID: [u8; 4] = [0x78, 0x0e, 0x9d, 0x63];
+/// Tokens listed per collection; valuation still counts the whole balance
+const MAX_TOKENS_PER_COLLECTION: usize = 100;
+/// Metadata documents fetched at once
+const METADATA_CONCURRENCY: usize = 8;
+/// Token metadata rarely changes once minted
+const METADATA_CACHE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// NFT scanner configuration
+#[derive(Debug, Clone)]
+pub struct NftConfig {
+    /// Collections checked for every wallet besides the ones it received tokens of
+    pub collections: Vec<(u64, Address)>,
+    pub discovery_blocks: u64,
+    /// Gateway `ipfs://` URIs are fetched through
+    pub ipfs_gateway: String,
+}
+
+impl Default for NftConfig {
+    fn default() -> Self {
+        Self {
+            collections: Vec::new(),
+            discovery_blocks: DEFAULT_DISCOVERY_BLOCKS,
+            ipfs_gateway: DEFAULT_IPFS_GATEWAY.to_string(),
+        }
+    }
+}
+
+impl NftConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut nft_config = Self::default();
+
+        if let Ok(collections) = config.get_string("nft_collections") {
+            nft_config.collections = collections.split(',')
+                .filter_map(|collection| parse_collection(collection.trim()))
+                .collect();
+        }
+        if let Ok(blocks) = config.get_int("nft_scan_discovery_blocks") {
+            nft_config.discovery_blocks = blocks.max(0) as u64;
+        }
+        if let Ok(gateway) = config.get_string("nft_ipfs_gateway") {
+            if !gateway.is_empty() {
+                nft_config.ipfs_gateway = format!("{}/", gateway.trim_end_matches('/'));
+            }
+        }
+
+        nft_config
+    }
+}
+
+/// `chain_id:address` of a collection
+fn parse_collection(collection: &str) -> Option<(u64, Address)> {
+    let (chain_id, address) = collection.split_once(':')?;
+    Some((chain_id.trim().parse().ok()?, address.trim().parse().ok()?))
+}
+
+/// Source of collection floor prices used to value holdings
+#[async_trait]
+pub trait FloorPriceSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Floor price of a collection in the chain's native asset, `None` when the source does not know it
+    async fn floor_price(&self, chain_id: u64, collection: Address) -> Result<Option<f64>>;
+}
+
+/// Floor prices set in `nft_floor_prices` as `chain_id:collection=price` entries
+pub struct ConfiguredFloorPrices {
+    prices: HashMap<(u64, Address), f64>,
+}
+
+impl ConfiguredFloorPrices {
+    pub fn from_config(config: &config::Config) -> Self {
+        let prices = config.get_string("nft_floor_prices").unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let (collection, price) = entry.split_once('=')?;
+                let price: f64 = price.trim().parse().ok().filter(|price: &f64| price.is_finite() && *price >= 0.0)?;
+                Some((parse_collection(collection.trim())?, price))
+            })
+            .collect();
+        Self { prices }
+    }
+}
+
+#[async_trait]
+impl FloorPriceSource for ConfiguredFloorPrices {
+    fn name(&self) -> &'static str {
+        "configured"
+    }
+
+    async fn floor_price(&self, chain_id: u64, collection: Address) -> Result<Option<f64>> {
+        Ok(self.prices.get(&(chain_id, collection)).copied())
+    }
+}
+
+/// Token metadata read from its `tokenURI`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NftMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Image URL with IPFS and Arweave URIs resolved to HTTP gateways
+    pub image: Option<String>,
+    pub animation_url: Option<String>,
+    pub attributes: Vec<NFTAttribute>,
+}
+
+/// One token a wallet owns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftHolding {
+    pub chain_id: u64,
+    pub collection: Address,
+    pub token_id: U256,
+    pub token_uri: Option<String>,
+    /// `None` when metadata was not requested or could not be fetched
+    pub metadata: Option<NftMetadata>,
+}
+
+/// Tokens a wallet owns in one collection, valued at the collection floor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionHoldings {
+    pub chain_id: u64,
+    pub collection: Address,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    /// Tokens owned per `balanceOf`, which may exceed the tokens listed
+    pub balance: u64,
+    /// Whether the collection lists its tokens per owner (ERC721Enumerable); otherwise only tokens received
+    /// within the discovery window are listed
+    pub enumerable: bool,
+    pub floor_price_native: Option<f64>,
+    pub floor_price_usd: Option<f64>,
+    pub floor_price_source: Option<String>,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub value_usd: Option<f64>,
+    pub tokens: Vec<NftHolding>,
+}
+
+/// NFT holdings of a wallet, most valuable collections first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftPortfolio {
+    pub address: Address,
+    /// Primary ENS name of the address, filled in by the API
+    pub ens_name: Option<String>,
+    pub chain_ids: Vec<u64>,
+    /// Floor value of the collections with a known floor price
+    #[serde(with = "crate::api::models::usd")]
+    pub total_value_usd: f64,
+    pub collections: Vec<CollectionHoldings>,
+    pub errors: Vec<ChainScanError>,
+}
+
+/// Tokens a wallet received on one chain and the last block searched
+#[derive(Debug, Clone, Default)]
+struct ReceivedNfts {
+    tokens: BTreeSet<(Address, U256)>,
+    scanned_to: Option<u64>,
+}
+
+/// Finds the NFTs wallets own through ERC721Enumerable or Transfer logs and reads their metadata
+pub struct NftPortfolioService {
+    chain_manager: Arc<ChainManager>,
+    price_feeds: Arc<PriceFeedService>,
+    floor_sources: Vec<Arc<dyn FloorPriceSource>>,
+    config: NftConfig,
+    http: reqwest::Client,
+    metadata_cache: NamespacedCache<NftMetadata>,
+    received: RwLock<HashMap<(u64, Address), ReceivedNfts>>,
+}
+
+impl NftPortfolioService {
+    pub fn new(
+        chain_manager: Arc<ChainManager>,
+        price_feeds: Arc<PriceFeedService>,
+        floor_sources: Vec<Arc<dyn FloorPriceSource>>,
+        config: NftConfig,
+        caches: &CacheManager,
+    ) -> Self {
+        Self {
+            chain_manager,
+            price_feeds,
+            floor_sources,
+            config,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            metadata_cache: caches.namespace("nft_metadata", METADATA_CACHE_TTL),
+            received: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Service valuing collections at the floor prices of `nft_floor_prices`
+    pub fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        price_feeds: Arc<PriceFeedService>,
+        caches: &CacheManager,
+    ) -> Self {
+        let floor_sources: Vec<Arc<dyn FloorPriceSource>> = vec![Arc::new(ConfiguredFloorPrices::from_config(config))];
+        Self::new(chain_manager, price_feeds, floor_sources, NftConfig::from_config(config), caches)
+    }
+
+    /// NFTs of a wallet on `chain_ids`, every supported chain when `None`
+    pub async fn portfolio(&self, address: Address, chain_ids: Option<Vec<u64>>, with_metadata: bool) -> NftPortfolio {
+        let chain_ids = match chain_ids {
+            Some(chain_ids) => chain_ids,
+            None => self.chain_manager.chain_ids().await,
+        };
+        let scans = join_all(chain_ids.iter().map(|chain_id| self.scan_chain(*chain_id, address, with_metadata))).await;
+
+        let mut collections = Vec::new();
+        let mut errors = Vec::new();
+        for (chain_id, scan) in chain_ids.iter().zip(scans) {
+            match scan {
+                Ok(chain_collections) => collections.extend(chain_collections),
+                Err(e) => {
+                    warn!("NFT scan of {:?} on chain {} failed: {}", address, chain_id, e);
+                    errors.push(ChainScanError { chain_id: *chain_id, error: e.to_string() });
+                }
+            }
+        }
+        collections.sort_by(|a, b| b.value_usd.unwrap_or(-1.0).total_cmp(&a.value_usd.unwrap_or(-1.0)));
+
+        NftPortfolio {
+            address,
+            ens_name: None,
+            chain_ids,
+            total_value_usd: collections.iter().filter_map(|collection| collection.value_usd).fold(0.0, |total, value| total + value),
+            collections,
+            errors,
+        }
+    }
+
+    async fn scan_chain(&self, chain_id: u64, address: Address, with_metadata: bool) -> Result<Vec<CollectionHoldings>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain.provider.clone());
+
+        let received = match self.discover(chain_id, address).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("NFT discovery for {:?} on chain {} failed: {}", address, chain_id, e);
+                BTreeSet::new()
+            }
+        };
+        let mut candidates: BTreeMap<Address, Vec<U256>> = self.config.collections.iter()
+            .filter(|(collection_chain, _)| *collection_chain == chain_id)
+            .map(|(_, collection)| (*collection, Vec::new()))
+            .collect();
+        for (collection, token_id) in &received {
+            candidates.entry(*collection).or_default().push(*token_id);
+        }
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let addresses: Vec<Address> = candidates.keys().copied().collect();
+
+        let erc721 = Contract::new(Address::zero(), ERC721Contract::get_erc721_abi()?, provider.clone());
+        let calls = addresses.iter()
+            .map(|collection| erc721.at(*collection).method::<_, U256>("balanceOf", address))
+            .collect::<Result<Vec<_>, _>>()?;
+        let held: Vec<(Address, u64)> = addresses.iter()
+            .zip(multicall(&provider, chain_id, calls).await?)
+            .filter_map(|(collection, balance)| Some((*collection, balance?.into_uint()?)))
+            .filter(|(_, balance)| !balance.is_zero())
+            .map(|(collection, balance)| (collection, balance.min(U256::from(u64::MAX)).as_u64()))
+            .collect();
+        if held.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let reads = |method: &'static str| {
+            held.iter()
+                .map(|(collection, _)| erc721.at(*collection).method::<_, String>(method, ()))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let names = multicall(&provider, chain_id, reads("name")?).await?;
+        let symbols = multicall(&provider, chain_id, reads("symbol")?).await?;
+        let calls = held.iter()
+            .map(|(collection, _)| erc721.at(*collection).method::<_, bool>("supportsInterface", ENUMERABLE_INTERFACE_ID))
+            .collect::<Result<Vec<_>, _>>()?;
+        let enumerable = multicall(&provider, chain_id, calls).await?;
+
+        let native_price_usd = match self.price_feeds.get_price(chain_id, pricing_address(chain_id, Address::zero())).await {
+            Ok(price) => Some(price.price_usd),
+            Err(e) => {
+                debug!("NFT floors on chain {} stay unpriced: {}", chain_id, e);
+                None
+            }
+        };
+
+        let mut collections = Vec::with_capacity(held.len());
+        for (((collection, balance), (name, symbol)), enumerable) in held.iter().zip(names.into_iter().zip(symbols)).zip(enumerable) {
+            let enumerable = enumerable.and_then(Token::into_bool).unwrap_or(false);
+            let token_ids = if enumerable {
+                let listed = (*balance as usize).min(MAX_TOKENS_PER_COLLECTION);
+                let calls = (0..listed)
+                    .map(|index| erc721.at(*collection).method::<_, U256>("tokenOfOwnerByIndex", (address, U256::from(index))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                multicall(&provider, chain_id, calls).await?.into_iter()
+                    .filter_map(|token_id| token_id?.into_uint())
+                    .collect()
+            } else {
+                // Tokens received in the discovery window that the wallet still owns
+                let received = candidates.get(collection).cloned().unwrap_or_default();
+                let calls = received.iter()
+                    .map(|token_id| erc721.at(*collection).method::<_, Address>("ownerOf", *token_id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                received.iter()
+                    .zip(multicall(&provider, chain_id, calls).await?)
+                    .filter(|(_, owner)| owner.clone().and_then(Token::into_address) == Some(address))
+                    .map(|(token_id, _)| *token_id)
+                    .take(MAX_TOKENS_PER_COLLECTION)
+                    .collect::<Vec<U256>>()
+            };
+
+            let calls = token_ids.iter()
+                .map(|token_id| erc721.at(*collection).method::<_, String>("tokenURI", *token_id))
+                .collect::<Result<Vec<_>, _>>()?;
+            let token_uris = multicall(&provider, chain_id, calls).await?;
+            let mut tokens: Vec<NftHolding> = token_ids.into_iter()
+                .zip(token_uris)
+                .map(|(token_id, token_uri)| NftHolding {
+                    chain_id,
+                    collection: *collection,
+                    token_id,
+                    token_uri: token_uri.and_then(Token::into_string).filter(|uri| !uri.is_empty()),
+                    metadata: None,
+                })
+                .collect();
+            if with_metadata {
+                self.load_metadata(&mut tokens).await;
+            }
+
+            let floor = self.floor_price(chain_id, *collection).await;
+            let floor_price_usd = floor.as_ref().zip(native_price_usd).map(|((price, _), native_price)| price * native_price);
+            collections.push(CollectionHoldings {
+                chain_id,
+                collection: *collection,
+                name: name.and_then(Token::into_string),
+                symbol: symbol.and_then(Token::into_string),
+                balance: *balance,
+                enumerable,
+                floor_price_native: floor.as_ref().map(|(price, _)| *price),
+                floor_price_usd,
+                floor_price_source: floor.map(|(_, source)| source.to_string()),
+                value_usd: floor_price_usd.map(|price| price * *balance as f64),
+                tokens,
+            });
+        }
+        Ok(collections)
+    }
+
+    /// First floor price any source knows
+    async fn floor_price(&self, chain_id: u64, collection: Address) -> Option<(f64, &'static str)> {
+        for source in &self.floor_sources {
+            match source.floor_price(chain_id, collection).await {
+                Ok(Some(price)) => return Some((price, source.name())),
+                Ok(None) => {}
+                Err(e) => debug!("{} floor price of {:?} unavailable: {}", source.name(), collection, e),
+            }
+        }
+        None
+    }
+
+    /// Fetch the metadata of tokens with a URI
+    async fn load_metadata(&self, tokens: &mut [NftHolding]) {
+        let loads: Vec<_> = tokens.iter().map(|token| self.token_metadata(token)).collect();
+        let metadata: Vec<Option<NftMetadata>> = stream::iter(loads).buffered(METADATA_CONCURRENCY).collect().await;
+        for (token, metadata) in tokens.iter_mut().zip(metadata) {
+            token.metadata = metadata;
+        }
+    }
+
+    /// Metadata of a token through the shared cache
+    async fn token_metadata(&self, token: &NftHolding) -> Option<NftMetadata> {
+        let uri = token.token_uri.clone()?;
+        let key = format!("{}:{:?}:{}", token.chain_id, token.collection, token.token_id);
+        let (token_id, http, gateway) = (token.token_id, self.http.clone(), self.config.ipfs_gateway.clone());
+        let load = move || fetch_metadata(http, gateway, uri, token_id).boxed();
+        match self.metadata_cache.get_or_load(&key, load).await {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                debug!("No metadata for {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// NFTs transferred to the wallet, searching only the blocks added since its last scan
+    async fn discover(&self, chain_id: u64, address: Address) -> Result<BTreeSet<(Address, U256)>> {
+        if self.config.discovery_blocks == 0 {
+            return Ok(BTreeSet::new());
+        }
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let known = self.received.read().await.get(&(chain_id, address)).cloned().unwrap_or_default();
+        let latest = chain.provider.get_block_number().await?.as_u64();
+        let from = match known.scanned_to {
+            Some(scanned_to) => scanned_to + 1,
+            None => latest.saturating_sub(self.config.discovery_blocks.saturating_sub(1)),
+        };
+        if from > latest {
+            return Ok(known.tokens);
+        }
+
+        let mut tokens = known.tokens;
+        // ERC-721 transfers index the token id, ERC-20 ones have a topic less
+        tokens.extend(
+            incoming_transfers(&chain.provider, address, from, latest).await?.iter()
+                .filter(|log| log.topics.len() == 4)
+                .map(|log| (log.address, U256::from_big_endian(log.topics[3].as_bytes()))),
+        );
+        self.received.write().await.insert(
+            (chain_id, address),
+            ReceivedNfts { tokens: tokens.clone(), scanned_to: Some(latest) },
+        );
+        Ok(tokens)
+    }
+}
+
+/// HTTP URL of an `ipfs://` or `ar://` URI, other URIs unchanged
+fn gateway_url(uri: &str, ipfs_gateway: &str) -> String {
+    if let Some(path) = uri.strip_prefix("ipfs://") {
+        format!("{}{}", ipfs_gateway, path.trim_start_matches("ipfs/"))
+    } else if let Some(path) = uri.strip_prefix("ar://") {
+        format!("https://arweave.net/{}", path)
+    } else {
+        uri.to_string()
+    }
+}
+
+/// Metadata JSON of a token URI, inline `data:` URIs included
+async fn fetch_metadata(http: reqwest::Client, ipfs_gateway: String, uri: String, token_id: U256) -> Result<NftMetadata> {
+    // ERC-1155 style templates substitute the hex token id
+    let uri = uri.replace("{id}", &format!("{:064x}", token_id));
+    let document: Value = if let Some(data) = uri.strip_prefix("data:application/json;base64,") {
+        serde_json::from_slice(&STANDARD.decode(data)?)?
+    } else if let Some((_, data)) = uri.strip_prefix("data:application/json").and_then(|rest| rest.split_once(',')) {
+        serde_json::from_str(data)?
+    } else {
+        let url = gateway_url(&uri, &ipfs_gateway);
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(anyhow!("Unsupported token URI {}", uri));
+        }
+        http.get(&url).send().await?.error_for_status()?.json().await?
+    };
+
+    let text = |field: &str| document.get(field).and_then(Value::as_str).map(str::to_string);
+    let attributes = document.get("attributes").and_then(Value::as_array).map(|attributes| {
+        attributes.iter()
+            .map(|attribute| NFTAttribute {
+                trait_type: attribute.get("trait_type").and_then(Value::as_str).unwrap_or_default().to_string(),
+                value: match attribute.get("value") {
+                    Some(Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                },
+                display_type: attribute.get("display_type").and_then(Value::as_str).map(str::to_string),
+            })
+            .collect()
+    });
+
+    Ok(NftMetadata {
+        name: text("name"),
+        description: text("description"),
+        image: text("image").or_else(|| text("image_url")).map(|image| gateway_url(&image, &ipfs_gateway)),
+        animation_url: text("animation_url").map(|animation| gateway_url(&animation, &ipfs_gateway)),
+        attributes: attributes.unwrap_or_default(),
+    })
+}
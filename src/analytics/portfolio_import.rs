@@ -0,0 +1,618 @@
+// Import of portfolio exports from third-party trackers
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, Utc};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tracing::info;
+
+use super::portfolio_tracker::{PortfolioSnapshot, PortfolioTracker, TrackedHolding};
+use crate::defi::strategy_registry::StrategyRegistry;
+use crate::defi::{ActiveStrategy, StrategyStatus};
+
+/// Tokens resolvable by symbol: (chain id, symbol, address, decimals)
+const KNOWN_TOKENS: [(u64, &str, &str, u8); 23] = [
+    (1, "WETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 18),
+    (1, "USDC", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 6),
+    (1, "USDT", "0xdAC17F958D2ee523a2206206994597C13D831ec7", 6),
+    (1, "DAI", "0x6B175474E89094C44Da98b954EedeAC495271d0F", 18),
+    (1, "WBTC", "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", 8),
+    (137, "WMATIC", "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270", 18),
+    (137, "USDC", "0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174", 6),
+    (137, "USDT", "0xc2132D05D31c914a87C6611C10748AEb04B58e8F", 6),
+    (137, "DAI", "0x8f3Cf7ad23Cd3CaDbD9735AFf958023239c6A063", 18),
+    (137, "WETH", "0x7ceB23fD6bC0adD59E62ac25578270cFf1b9f619", 18),
+    (42161, "WETH", "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1", 18),
+    (42161, "USDC", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", 6),
+    (42161, "USDT", "0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9", 6),
+    (42161, "DAI", "0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1", 18),
+    (42161, "WBTC", "0x2f2a2543B76A4166549F7aaB2e75Bef0aefC5B0f", 8),
+    (56, "WBNB", "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c", 18),
+    (56, "USDC", "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d", 18),
+    (56, "USDT", "0x55d398326f99059fF775485246999027B3197955", 18),
+    (56, "BTCB", "0x7130d2A12B9BCbFAe4f2634d864A1Ee1Ce3Ead9c", 18),
+    (43114, "WAVAX", "0xB31f66AA3C1e785363F0875A1B74E27b85FD66c7", 18),
+    (43114, "USDC", "0xB97EF9Ef8734C71904D8002F8b6Bc66Dd9c48a6E", 6),
+    (43114, "USDT", "0x9702230A8Ea53601f5cD2dc00fDBc13d4dF4A8c7", 6),
+    (43114, "WBTC", "0x50b7545627a5162F82A992c33b87aDc75187B218", 8),
+];
+
+/// Export formats the importer understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// DeBank `all_token_list` / `all_complex_protocol_list` style JSON
+    Debank,
+    /// Zapper style JSON list of app balances
+    Zapper,
+    /// One position per row, columns matched by common header names
+    Csv,
+}
+
+impl ImportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportFormat::Debank => "debank",
+            ImportFormat::Zapper => "zapper",
+            ImportFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A position read from an export, mapped to internal identifiers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedPosition {
+    pub observed_at: DateTime<Utc>,
+    pub holding: TrackedHolding,
+    pub decimals: Option<u8>,
+    pub apy: Option<f64>,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub address: Address,
+    pub format: ImportFormat,
+    pub positions_imported: usize,
+    pub snapshots_seeded: usize,
+    pub strategies_seeded: Vec<String>,
+    /// Chains, protocols and assets that could not be mapped
+    pub unmapped: Vec<String>,
+    /// Rows dropped because their chain is unsupported or they carry no amount
+    pub skipped_rows: usize,
+}
+
+#[derive(Deserialize)]
+struct DebankExport {
+    #[serde(default)]
+    tokens: Vec<DebankToken>,
+    #[serde(default)]
+    protocols: Vec<DebankProtocol>,
+    /// Unix seconds of the export
+    time_at: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct DebankToken {
+    /// Token address or the chain's native id, e.g. "eth"
+    id: String,
+    chain: String,
+    symbol: String,
+    decimals: Option<u8>,
+    #[serde(default)]
+    amount: f64,
+    #[serde(default)]
+    price: f64,
+}
+
+#[derive(Deserialize)]
+struct DebankProtocol {
+    id: String,
+    chain: String,
+    #[serde(default)]
+    portfolio_item_list: Vec<DebankPortfolioItem>,
+}
+
+#[derive(Deserialize)]
+struct DebankPortfolioItem {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    detail: DebankItemDetail,
+}
+
+#[derive(Deserialize, Default)]
+struct DebankItemDetail {
+    #[serde(default)]
+    supply_token_list: Vec<DebankToken>,
+    #[serde(default)]
+    borrow_token_list: Vec<DebankToken>,
+    #[serde(default)]
+    reward_token_list: Vec<DebankToken>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ZapperExport {
+    List(Vec<ZapperBalance>),
+    Wrapped { balances: Vec<ZapperBalance> },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZapperBalance {
+    /// "tokens" for plain wallet balances
+    app_id: Option<String>,
+    network: String,
+    address: Option<String>,
+    symbol: String,
+    decimals: Option<u8>,
+    #[serde(default)]
+    balance: f64,
+    #[serde(default, rename = "balanceUSD")]
+    balance_usd: f64,
+    /// "supplied", "borrowed", "claimable", ...
+    meta_type: Option<String>,
+    apy: Option<f64>,
+    timestamp: Option<String>,
+}
+
+/// Parses tracker exports and seeds the portfolio history and strategy registry
+pub struct PortfolioImporter {
+    tracker: Arc<PortfolioTracker>,
+    strategies: Arc<StrategyRegistry>,
+}
+
+impl PortfolioImporter {
+    pub fn new(tracker: Arc<PortfolioTracker>, strategies: Arc<StrategyRegistry>) -> Self {
+        Self { tracker, strategies }
+    }
+
+    /// Import an export for a wallet; JSON formats accept the document or its text
+    pub async fn import(&self, address: Address, format: ImportFormat, payload: &Value) -> Result<ImportReport> {
+        let mut mapper = ImportMapper::default();
+        let positions = match format {
+            ImportFormat::Debank => mapper.parse_debank(Self::json_document(payload)?)?,
+            ImportFormat::Zapper => mapper.parse_zapper(Self::json_document(payload)?)?,
+            ImportFormat::Csv => {
+                let text = payload.as_str().ok_or_else(|| anyhow!("CSV payload must be a string"))?;
+                mapper.parse_csv(text)?
+            }
+        };
+        if positions.is_empty() {
+            return Err(anyhow!("Export contains no importable positions"));
+        }
+
+        let snapshots = Self::build_snapshots(address, format, &positions);
+        let snapshots_seeded = snapshots.len();
+        self.tracker.record_snapshots(snapshots).await;
+
+        let strategies = Self::build_strategies(format, &positions);
+        let strategies_seeded = strategies.iter().map(|s| s.strategy_id.clone()).collect();
+        for strategy in strategies {
+            self.strategies.register(address, strategy).await;
+        }
+
+        info!(
+            "Imported {} positions for {:?} from {} export ({} snapshots)",
+            positions.len(), address, format.name(), snapshots_seeded
+        );
+
+        let mut unmapped: Vec<String> = mapper.unmapped.into_iter().collect();
+        unmapped.sort();
+
+        Ok(ImportReport {
+            address,
+            format,
+            positions_imported: positions.len(),
+            snapshots_seeded,
+            strategies_seeded,
+            unmapped,
+            skipped_rows: mapper.skipped_rows,
+        })
+    }
+
+    fn json_document(payload: &Value) -> Result<Value> {
+        match payload {
+            Value::String(text) => Ok(serde_json::from_str(text)?),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// One snapshot per distinct observation time
+    fn build_snapshots(address: Address, format: ImportFormat, positions: &[ImportedPosition]) -> Vec<PortfolioSnapshot> {
+        let mut by_time: BTreeMap<DateTime<Utc>, Vec<TrackedHolding>> = BTreeMap::new();
+        for position in positions {
+            by_time.entry(position.observed_at).or_default().push(position.holding.clone());
+        }
+
+        let source = format!("import:{}", format.name());
+        by_time.into_iter()
+            .map(|(taken_at, holdings)| PortfolioSnapshot::new(address, taken_at, holdings, source.clone()))
+            .collect()
+    }
+
+    /// One strategy per protocol market held at the latest observation
+    fn build_strategies(format: ImportFormat, positions: &[ImportedPosition]) -> Vec<ActiveStrategy> {
+        let latest = match positions.iter().map(|p| p.observed_at).max() {
+            Some(latest) => latest,
+            None => return Vec::new(),
+        };
+        let leveraged: HashSet<(u64, &str)> = positions.iter()
+            .filter(|p| p.observed_at == latest && p.holding.position_type == "borrow")
+            .filter_map(|p| p.holding.protocol.as_deref().map(|protocol| (p.holding.chain_id, protocol)))
+            .collect();
+
+        let mut strategies = Vec::new();
+        for current in positions.iter().filter(|p| p.observed_at == latest) {
+            let holding = &current.holding;
+            let (protocol, token, decimals) = match (&holding.protocol, holding.token, current.decimals) {
+                (Some(protocol), Some(token), Some(decimals)) => (protocol, token, decimals),
+                _ => continue,
+            };
+            let is_leveraged = leveraged.contains(&(holding.chain_id, protocol.as_str()));
+            let strategy_type = match holding.position_type.as_str() {
+                "supply" if is_leveraged => "leveraged_lending",
+                "supply" => "lending",
+                "liquidity" => "liquidity_provision",
+                "staked" => "staking",
+                _ => continue,
+            };
+
+            // The earliest observation of the same market is the entry point
+            let entry = positions.iter()
+                .filter(|p| {
+                    p.holding.chain_id == holding.chain_id
+                        && p.holding.protocol == holding.protocol
+                        && p.holding.token == holding.token
+                        && p.holding.position_type == holding.position_type
+                })
+                .min_by_key(|p| p.observed_at)
+                .unwrap_or(current);
+
+            strategies.push(ActiveStrategy {
+                strategy_id: format!(
+                    "import-{}-{}-{}-{:?}-{}",
+                    format.name(), holding.chain_id, protocol, token, holding.position_type
+                ),
+                protocol: protocol.clone(),
+                strategy_type: strategy_type.to_string(),
+                invested_amount: to_raw_amount(entry.holding.amount, decimals),
+                current_value: to_raw_amount(holding.amount, decimals),
+                apy: current.apy.unwrap_or(0.0),
+                risk_level: if is_leveraged { "Medium" } else { "Low" }.to_string(),
+                start_date: entry.observed_at,
+                profit_loss: holding.value_usd - entry.holding.value_usd,
+                template_id: None,
+                chain_id: Some(holding.chain_id),
+                parameters: HashMap::new(),
+                status: StrategyStatus::Active,
+                closed_at: None,
+                transaction_ids: Vec::new(),
+            });
+        }
+
+        strategies
+    }
+}
+
+/// Maps tracker identifiers to internal ones, collecting what it cannot map
+#[derive(Default)]
+struct ImportMapper {
+    unmapped: HashSet<String>,
+    skipped_rows: usize,
+}
+
+impl ImportMapper {
+    fn parse_debank(&mut self, document: Value) -> Result<Vec<ImportedPosition>> {
+        let export: DebankExport = serde_json::from_value(document)
+            .map_err(|e| anyhow!("Invalid DeBank export: {}", e))?;
+        let observed_at = export.time_at
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .unwrap_or_else(Utc::now);
+
+        let mut positions = Vec::new();
+        for token in &export.tokens {
+            positions.extend(self.debank_position(token, None, "wallet", observed_at));
+        }
+        for protocol in &export.protocols {
+            for item in &protocol.portfolio_item_list {
+                let supply_type = match item.name.to_lowercase().as_str() {
+                    "liquidity pool" => "liquidity",
+                    "staked" | "locked" | "farming" => "staked",
+                    _ => "supply",
+                };
+                let lists = [
+                    (&item.detail.supply_token_list, supply_type),
+                    (&item.detail.borrow_token_list, "borrow"),
+                    (&item.detail.reward_token_list, "rewards"),
+                ];
+                for (tokens, position_type) in lists {
+                    for token in tokens {
+                        // Protocol tokens carry the protocol's chain when their own is missing
+                        let token = DebankToken {
+                            chain: if token.chain.is_empty() { protocol.chain.clone() } else { token.chain.clone() },
+                            id: token.id.clone(),
+                            symbol: token.symbol.clone(),
+                            decimals: token.decimals,
+                            amount: token.amount,
+                            price: token.price,
+                        };
+                        positions.extend(self.debank_position(&token, Some(&protocol.id), position_type, observed_at));
+                    }
+                }
+            }
+        }
+
+        Ok(positions)
+    }
+
+    fn debank_position(
+        &mut self,
+        token: &DebankToken,
+        protocol: Option<&str>,
+        position_type: &str,
+        observed_at: DateTime<Utc>,
+    ) -> Option<ImportedPosition> {
+        let row = RawRow {
+            observed_at,
+            chain: token.chain.clone(),
+            protocol: protocol.map(str::to_string),
+            position_type: position_type.to_string(),
+            symbol: token.symbol.clone(),
+            token: Some(token.id.clone()),
+            decimals: token.decimals,
+            amount: Some(token.amount),
+            value_usd: Some(token.amount * token.price),
+            apy: None,
+        };
+        self.map_row(row)
+    }
+
+    fn parse_zapper(&mut self, document: Value) -> Result<Vec<ImportedPosition>> {
+        let export: ZapperExport = serde_json::from_value(document)
+            .map_err(|e| anyhow!("Invalid Zapper export: {}", e))?;
+        let balances = match export {
+            ZapperExport::List(balances) | ZapperExport::Wrapped { balances } => balances,
+        };
+
+        let now = Utc::now();
+        let mut positions = Vec::new();
+        for balance in balances {
+            let observed_at = balance.timestamp.as_deref().and_then(parse_timestamp).unwrap_or(now);
+            let row = RawRow {
+                observed_at,
+                chain: balance.network,
+                protocol: balance.app_id,
+                position_type: balance.meta_type.unwrap_or_default(),
+                symbol: balance.symbol,
+                token: balance.address,
+                decimals: balance.decimals,
+                amount: Some(balance.balance),
+                value_usd: Some(balance.balance_usd),
+                apy: balance.apy,
+            };
+            positions.extend(self.map_row(row));
+        }
+
+        Ok(positions)
+    }
+
+    fn parse_csv(&mut self, text: &str) -> Result<Vec<ImportedPosition>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(text.as_bytes());
+        let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_lowercase()).collect();
+        let column = |aliases: &[&str]| headers.iter().position(|h| aliases.contains(&h.as_str()));
+
+        let timestamp = column(&["timestamp", "date", "time"]);
+        let chain = column(&["chain", "network"]);
+        let protocol = column(&["protocol", "app", "platform"]);
+        let position_type = column(&["type", "position_type", "meta_type", "category"]);
+        let symbol = column(&["symbol", "asset", "token"])
+            .ok_or_else(|| anyhow!("CSV export needs a symbol column"))?;
+        let token = column(&["token_address", "address", "contract"]);
+        let decimals = column(&["decimals"]);
+        let amount = column(&["amount", "balance", "quantity"])
+            .ok_or_else(|| anyhow!("CSV export needs an amount column"))?;
+        let value_usd = column(&["value_usd", "balance_usd", "usd_value", "value"]);
+        let apy = column(&["apy"]);
+
+        let now = Utc::now();
+        let mut positions = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let field = |index: Option<usize>| {
+                index.and_then(|i| record.get(i)).filter(|value| !value.is_empty()).map(str::to_string)
+            };
+            let number = |index: Option<usize>| field(index).and_then(|value| value.replace(',', "").parse::<f64>().ok());
+
+            let row = RawRow {
+                observed_at: field(timestamp).as_deref().and_then(parse_timestamp).unwrap_or(now),
+                // Single-chain exports usually omit the column
+                chain: field(chain).unwrap_or_else(|| "ethereum".to_string()),
+                protocol: field(protocol),
+                position_type: field(position_type).unwrap_or_default(),
+                symbol: field(Some(symbol)).unwrap_or_default(),
+                token: field(token),
+                decimals: field(decimals).and_then(|value| value.parse().ok()),
+                amount: number(Some(amount)),
+                value_usd: number(value_usd),
+                apy: number(apy),
+            };
+            positions.extend(self.map_row(row));
+        }
+
+        Ok(positions)
+    }
+
+    fn map_row(&mut self, row: RawRow) -> Option<ImportedPosition> {
+        let chain_id = match map_chain(&row.chain) {
+            Some(chain_id) => chain_id,
+            None => {
+                self.unmapped.insert(format!("chain:{}", row.chain));
+                self.skipped_rows += 1;
+                return None;
+            }
+        };
+        let amount = match row.amount {
+            Some(amount) if amount.is_finite() && amount != 0.0 => amount.abs(),
+            _ => {
+                self.skipped_rows += 1;
+                return None;
+            }
+        };
+
+        let protocol = row.protocol.as_deref().and_then(|name| self.map_protocol(name));
+        let position_type = map_position_type(&row.position_type, protocol.is_some());
+        let (token, known_decimals) = self.map_token(chain_id, row.token.as_deref(), &row.symbol, protocol.is_some());
+
+        Some(ImportedPosition {
+            observed_at: row.observed_at,
+            holding: TrackedHolding {
+                chain_id,
+                token,
+                symbol: row.symbol,
+                protocol,
+                position_type,
+                amount,
+                value_usd: row.value_usd.unwrap_or(0.0).abs(),
+            },
+            decimals: row.decimals.or(known_decimals),
+            apy: row.apy,
+        })
+    }
+
+    /// Internal protocol id, `None` for plain wallet balances
+    fn map_protocol(&mut self, name: &str) -> Option<String> {
+        let normalized: String = name.to_lowercase().chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        let known = [
+            ("aave", "aave"),
+            ("compound", "compound"),
+            ("uniswap", "uniswap"),
+            ("sushi", "sushiswap"),
+            ("curve", "curve"),
+            ("lido", "lido"),
+        ];
+
+        if normalized.is_empty() || normalized == "tokens" || normalized == "wallet" {
+            return None;
+        }
+        // Trackers suffix versions and chains, e.g. "aave3", "aave-v3", "arb_aave3"
+        if let Some((_, id)) = known.iter().find(|(prefix, _)| normalized.contains(prefix)) {
+            return Some(id.to_string());
+        }
+
+        self.unmapped.insert(format!("protocol:{}", name));
+        Some(normalized)
+    }
+
+    fn map_token(&mut self, chain_id: u64, token: Option<&str>, symbol: &str, in_protocol: bool) -> (Option<Address>, Option<u8>) {
+        let symbol_upper = symbol.to_uppercase();
+        let by_symbol = |symbol: &str| {
+            KNOWN_TOKENS.iter()
+                .find(|(chain, known, _, _)| *chain == chain_id && *known == symbol)
+                .and_then(|(_, _, address, decimals)| address.parse::<Address>().ok().map(|a| (a, *decimals)))
+        };
+
+        if let Some(address) = token.and_then(|token| token.parse::<Address>().ok()) {
+            let decimals = KNOWN_TOKENS.iter()
+                .find(|(chain, _, known, _)| *chain == chain_id && known.parse::<Address>().ok() == Some(address))
+                .map(|(_, _, _, decimals)| *decimals);
+            return (Some(address), decimals);
+        }
+        // DeBank uses the chain name as the id of its native token
+        if token.is_some_and(|id| map_chain(id) == Some(chain_id)) || native_symbol(chain_id) == symbol_upper {
+            return (Some(Address::zero()), Some(18));
+        }
+        if let Some((address, decimals)) = by_symbol(&symbol_upper) {
+            return (Some(address), Some(decimals));
+        }
+        // Receipt tokens such as aUSDC or cDAI map to their underlying inside a protocol
+        if in_protocol {
+            for prefix in ["AETH", "APOL", "AARB", "A", "C"] {
+                if let Some(found) = symbol_upper.strip_prefix(prefix).and_then(by_symbol) {
+                    return (Some(found.0), Some(found.1));
+                }
+            }
+        }
+
+        self.unmapped.insert(format!("asset:{} on chain {}", symbol, chain_id));
+        (None, None)
+    }
+}
+
+/// Export row before mapping
+struct RawRow {
+    observed_at: DateTime<Utc>,
+    chain: String,
+    protocol: Option<String>,
+    position_type: String,
+    symbol: String,
+    token: Option<String>,
+    decimals: Option<u8>,
+    amount: Option<f64>,
+    value_usd: Option<f64>,
+    apy: Option<f64>,
+}
+
+fn map_chain(name: &str) -> Option<u64> {
+    match name.trim().to_lowercase().as_str() {
+        "eth" | "ethereum" | "mainnet" | "1" => Some(1),
+        "matic" | "polygon" | "pol" | "137" => Some(137),
+        "arb" | "arbitrum" | "arbitrum-one" | "42161" => Some(42161),
+        "bsc" | "bnb" | "binance-smart-chain" | "56" => Some(56),
+        "avax" | "avalanche" | "avalanche-c" | "43114" => Some(43114),
+        _ => None,
+    }
+}
+
+fn native_symbol(chain_id: u64) -> &'static str {
+    match chain_id {
+        137 => "MATIC",
+        56 => "BNB",
+        43114 => "AVAX",
+        _ => "ETH",
+    }
+}
+
+fn map_position_type(raw: &str, in_protocol: bool) -> String {
+    let position_type = match raw.trim().to_lowercase().as_str() {
+        "supply" | "supplied" | "deposit" | "deposited" | "lending" | "collateral" => "supply",
+        "borrow" | "borrowed" | "debt" => "borrow",
+        "claimable" | "reward" | "rewards" => "rewards",
+        "liquidity" | "liquidity pool" | "pool" | "lp" => "liquidity",
+        "staked" | "staking" | "locked" | "farming" => "staked",
+        _ if in_protocol => "supply",
+        _ => "wallet",
+    };
+    position_type.to_string()
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(seconds) = value.parse::<i64>() {
+        // Millisecond timestamps are 13 digits
+        let seconds = if seconds > 100_000_000_000 { seconds / 1000 } else { seconds };
+        return DateTime::from_timestamp(seconds, 0);
+    }
+    DateTime::parse_from_rfc3339(value).ok().map(|time| time.with_timezone(&Utc))
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|time| time.and_utc())
+        })
+}
+
+/// Whole-token amount to the smallest unit
+fn to_raw_amount(amount: f64, decimals: u8) -> U256 {
+    // Scale in two steps so 18-decimal amounts keep precision within f64 range
+    let split = decimals.min(9);
+    let scaled = (amount * 10f64.powi(split as i32)).round();
+    if !scaled.is_finite() || scaled <= 0.0 {
+        return U256::zero();
+    }
+    U256::from(scaled as u128) * U256::exp10((decimals - split) as usize)
+}
@@ -1,12 +1,646 @@
 // Portfolio tracking implementations
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
 
+use super::time_zones::TenantTimeSettings;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `portfolio_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/portfolio.json";
+
+/// Snapshots kept per wallet
+const MAX_SNAPSHOTS: usize = 5000;
+/// Trades kept per wallet
+const MAX_TRADES: usize = 10_000;
+/// Changes of a holding below this many tokens between snapshots are rounding, not trades
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+/// A wallet balance or protocol position at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedHolding {
+    pub chain_id: u64,
+    /// `None` when the asset could not be mapped to a token address
+    pub token: Option<Address>,
+    pub symbol: String,
+    /// `None` for plain wallet balances
+    pub protocol: Option<String>,
+    /// "wallet", "supply", "borrow", "liquidity", "staked" or "rewards"
+    pub position_type: String,
+    pub amount: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub value_usd: f64,
+}
+
+/// Net value held in one protocol, or in the wallet itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolAllocation {
+    /// "wallet" for plain balances
+    pub protocol: String,
+    #[serde(with = "crate::api::models::usd")]
+    pub value_usd: f64,
+    /// Share of the gross value of every allocation, in percent
+    pub share_percentage: f64,
+}
+
+/// Health factor of a lending protocol the wallet borrows from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotHealth {
+    pub chain_id: u64,
+    pub protocol: String,
+    pub health_factor: f64,
+}
+
+/// Value of a wallet at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioSnapshot {
+    pub address: Address,
+    pub taken_at: DateTime<Utc>,
+    /// Net value, borrows count negative
+    #[serde(with = "crate::api::models::usd")]
+    pub total_value_usd: f64,
+    pub holdings: Vec<TrackedHolding>,
+    /// Net value per protocol, largest first
+    #[serde(default)]
+    pub allocation: Vec<ProtocolAllocation>,
+    /// Empty for imported snapshots and wallets without debt
+    #[serde(default)]
+    pub health_factors: Vec<SnapshotHealth>,
+    /// Where the snapshot came from, e.g. "live" or "import:debank"
+    pub source: String,
+}
+
+impl PortfolioSnapshot {
+    pub fn new(address: Address, taken_at: DateTime<Utc>, holdings: Vec<TrackedHolding>, source: String) -> Self {
+        let signed = |holding: &TrackedHolding| if holding.position_type == "borrow" { -holding.value_usd } else { holding.value_usd };
+        let total_value_usd = holdings.iter().map(signed).sum();
+
+        let mut by_protocol: BTreeMap<&str, f64> = BTreeMap::new();
+        for holding in &holdings {
+            *by_protocol.entry(holding.protocol.as_deref().unwrap_or("wallet")).or_default() += signed(holding);
+        }
+        let gross: f64 = by_protocol.values().map(|value| value.abs()).sum();
+        let mut allocation: Vec<ProtocolAllocation> = by_protocol.into_iter()
+            .map(|(protocol, value_usd)| ProtocolAllocation {
+                protocol: protocol.to_string(),
+                value_usd,
+                share_percentage: if gross > 0.0 { value_usd.abs() / gross * 100.0 } else { 0.0 },
+            })
+            .collect();
+        allocation.sort_by(|a, b| b.value_usd.abs().total_cmp(&a.value_usd.abs()));
+
+        Self {
+            address,
+            taken_at,
+            total_value_usd,
+            holdings,
+            allocation,
+            health_factors: Vec::new(),
+            source,
+        }
+    }
+
+    pub fn with_health_factors(self, health_factors: Vec<SnapshotHealth>) -> Self {
+        Self { health_factors, ..self }
+    }
+}
+
+/// Which lots a sale is matched against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostBasisMethod {
+    /// Oldest lots are sold first
+    #[default]
+    Fifo,
+    /// Every unit costs the average price paid for the holding
+    Average,
+}
+
+impl CostBasisMethod {
+    /// Method from `pnl_cost_basis_method`, `fifo` unless set to `average`
+    pub fn from_config(config: &config::Config) -> Self {
+        match config.get_string("pnl_cost_basis_method").as_deref() {
+            Ok("average") => Self::Average,
+            _ => Self::Fifo,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+    /// Yield, rewards or airdrops received, realized at their price and held at that cost
+    Income,
+}
+
+/// A purchase or sale of a token at a known price
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    pub chain_id: u64,
+    /// `None` when the asset is only known by its symbol
+    pub token: Option<Address>,
+    pub symbol: String,
+    pub side: TradeSide,
+    pub amount: f64,
+    pub price_usd: f64,
+    #[serde(default, with = "crate::api::models::usd")]
+    pub fee_usd: f64,
+    #[serde(default = "Utc::now")]
+    pub executed_at: DateTime<Utc>,
+    #[serde(default = "default_trade_source")]
+    pub source: String,
+}
+
+fn default_trade_source() -> String {
+    "manual".to_string()
+}
+
+impl Trade {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.amount.is_finite() && self.amount > 0.0) {
+            return Err(anyhow!("Trade amount must be positive"));
+        }
+        if !(self.price_usd.is_finite() && self.price_usd >= 0.0 && self.fee_usd.is_finite() && self.fee_usd >= 0.0) {
+            return Err(anyhow!("Trade price and fee cannot be negative"));
+        }
+        Ok(())
+    }
+}
+
+/// Cost basis and PnL of one asset of a wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionPnl {
+    pub chain_id: u64,
+    pub token: Option<Address>,
+    pub symbol: String,
+    /// Net amount held, negative for a net borrow
+    pub quantity: f64,
+    /// What the held lots cost, borrowed amounts carry none
+    #[serde(with = "crate::api::models::usd")]
+    pub cost_basis_usd: f64,
+    pub average_entry_price: Option<f64>,
+    /// Last price seen in a snapshot or trade
+    pub current_price: Option<f64>,
+    #[serde(with = "crate::api::models::usd")]
+    pub realized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub unrealized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub fees_usd: f64,
+}
+
+/// PnL of a wallet at the close of a local day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyPnl {
+    pub date: NaiveDate,
+    /// Realized during the day, net of fees
+    #[serde(with = "crate::api::models::usd")]
+    pub realized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub cumulative_realized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub unrealized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub total_pnl_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioPnl {
+    pub address: Address,
+    pub method: CostBasisMethod,
+    #[serde(with = "crate::api::models::usd")]
+    pub realized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub unrealized_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub total_pnl_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub fees_usd: f64,
+    pub positions: Vec<PositionPnl>,
+    /// One point per local day from the first trade or snapshot to today
+    pub daily: Vec<DailyPnl>,
+}
+
+/// Assets with a token address are matched by it, the others by symbol
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct AssetKey {
+    chain_id: u64,
+    token: Option<Address>,
+    symbol: String,
+}
+
+impl AssetKey {
+    fn new(chain_id: u64, token: Option<Address>, symbol: &str) -> Self {
+        let symbol = if token.is_some() { String::new() } else { symbol.to_uppercase() };
+        Self { chain_id, token, symbol }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Lot {
+    amount: f64,
+    price: f64,
+}
+
+/// Open lots and PnL of one asset while trades are replayed
+#[derive(Debug, Default)]
+struct AssetBook {
+    symbol: String,
+    quantity: f64,
+    lots: VecDeque<Lot>,
+    realized: f64,
+    fees: f64,
+    last_price: Option<f64>,
+}
+
+impl AssetBook {
+    fn buy(&mut self, amount: f64, price: f64, method: CostBasisMethod) {
+        // A buy repays a net borrow before it opens lots
+        let covered = amount.min((-self.quantity).max(0.0));
+        self.quantity += amount;
+        let amount = amount - covered;
+        if amount <= QUANTITY_EPSILON {
+            return;
+        }
+        match (method, self.lots.front_mut()) {
+            (CostBasisMethod::Average, Some(lot)) => {
+                lot.price = (lot.amount * lot.price + amount * price) / (lot.amount + amount);
+                lot.amount += amount;
+            }
+            _ => self.lots.push_back(Lot { amount, price }),
+        }
+    }
+
+    /// Sales beyond the open lots are borrowed or of unknown cost and realize nothing
+    fn sell(&mut self, amount: f64, price: f64) {
+        self.quantity -= amount;
+        let mut remaining = amount;
+        while remaining > QUANTITY_EPSILON {
+            let Some(lot) = self.lots.front_mut() else {
+                break;
+            };
+            let taken = remaining.min(lot.amount);
+            self.realized += taken * (price - lot.price);
+            lot.amount -= taken;
+            remaining -= taken;
+            if lot.amount <= QUANTITY_EPSILON {
+                self.lots.pop_front();
+            }
+        }
+    }
+
+    fn cost_basis(&self) -> f64 {
+        self.lots.iter().map(|lot| lot.amount * lot.price).sum()
+    }
+
+    fn unrealized(&self) -> f64 {
+        self.last_price
+            .map(|price| self.lots.iter().map(|lot| lot.amount * (price - lot.price)).sum())
+            .unwrap_or_default()
+    }
+}
+
+/// Net amount and price of an asset across a snapshot's positions
+#[derive(Debug, Default)]
+struct SnapshotHolding {
+    symbol: String,
+    quantity: f64,
+    gross_amount: f64,
+    gross_value_usd: f64,
+}
+
+/// Trades and snapshots of a wallet, in the order they are replayed
+enum PnlEvent<'a> {
+    Trade(&'a Trade),
+    Snapshot(&'a PortfolioSnapshot),
+}
+
+impl PnlEvent<'_> {
+    fn at(&self) -> DateTime<Utc> {
+        match self {
+            PnlEvent::Trade(trade) => trade.executed_at,
+            PnlEvent::Snapshot(snapshot) => snapshot.taken_at,
+        }
+    }
+}
+
+/// Replays a wallet's trades and snapshots into per-asset books
+///
+/// A snapshot holding more or less of an asset than the trades so far account for is taken as a buy or
+/// sale of the difference at the snapshot's price, so imported history gets entry prices too.
+struct PnlLedger {
+    method: CostBasisMethod,
+    books: BTreeMap<AssetKey, AssetBook>,
+}
+
+impl PnlLedger {
+    fn new(method: CostBasisMethod) -> Self {
+        Self { method, books: BTreeMap::new() }
+    }
+
+    fn apply(&mut self, event: &PnlEvent) {
+        match event {
+            PnlEvent::Trade(trade) => {
+                let method = self.method;
+                let book = self.book(AssetKey::new(trade.chain_id, trade.token, &trade.symbol), &trade.symbol);
+                match trade.side {
+                    TradeSide::Buy => book.buy(trade.amount, trade.price_usd, method),
+                    TradeSide::Sell => book.sell(trade.amount, trade.price_usd),
+                    TradeSide::Income => {
+                        book.buy(trade.amount, trade.price_usd, method);
+                        book.realized += trade.amount * trade.price_usd;
+                    }
+                }
+                book.realized -= trade.fee_usd;
+                book.fees += trade.fee_usd;
+                book.last_price = Some(trade.price_usd);
+            }
+            PnlEvent::Snapshot(snapshot) => self.reconcile(snapshot),
+        }
+    }
+
+    fn reconcile(&mut self, snapshot: &PortfolioSnapshot) {
+        // Supplied, staked and borrowed amounts all belong to the wallet's net holding of the asset
+        let mut holdings: BTreeMap<AssetKey, SnapshotHolding> = BTreeMap::new();
+        for holding in &snapshot.holdings {
+            let sign = if holding.position_type == "borrow" { -1.0 } else { 1.0 };
+            let entry = holdings.entry(AssetKey::new(holding.chain_id, holding.token, &holding.symbol))
+                .or_insert_with(|| SnapshotHolding { symbol: holding.symbol.clone(), ..SnapshotHolding::default() });
+            entry.quantity += sign * holding.amount;
+            entry.gross_amount += holding.amount.abs();
+            entry.gross_value_usd += holding.value_usd.abs();
+        }
+        // Assets gone from the snapshot were sold off
+        for (key, book) in &self.books {
+            if !holdings.contains_key(key) && book.quantity.abs() > QUANTITY_EPSILON {
+                holdings.insert(key.clone(), SnapshotHolding { symbol: book.symbol.clone(), ..SnapshotHolding::default() });
+            }
+        }
+
+        let method = self.method;
+        for (key, holding) in holdings {
+            let book = self.book(key, &holding.symbol);
+            let price = (holding.gross_amount > QUANTITY_EPSILON)
+                .then(|| holding.gross_value_usd / holding.gross_amount)
+                .or(book.last_price);
+            let change = holding.quantity - book.quantity;
+            if let Some(price) = price {
+                if change > QUANTITY_EPSILON {
+                    book.buy(change, price, method);
+                } else if change < -QUANTITY_EPSILON {
+                    book.sell(-change, price);
+                }
+                book.last_price = Some(price);
+            }
+        }
+    }
+
+    fn book(&mut self, key: AssetKey, symbol: &str) -> &mut AssetBook {
+        self.books.entry(key).or_insert_with(|| AssetBook { symbol: symbol.to_string(), ..AssetBook::default() })
+    }
+
+    fn realized(&self) -> f64 {
+        self.books.values().map(|book| book.realized).sum()
+    }
+
+    fn unrealized(&self) -> f64 {
+        self.books.values().map(AssetBook::unrealized).sum()
+    }
+}
+
+/// Snapshots and trades of one wallet as persisted
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WalletHistory {
+    address: Address,
+    snapshots: Vec<PortfolioSnapshot>,
+    trades: Vec<Trade>,
+}
+
+/// Keeps the value history and trades of tracked wallets
 pub struct PortfolioTracker {
-    // Portfolio tracking functionality
+    snapshots: Arc<RwLock<HashMap<Address, Vec<PortfolioSnapshot>>>>,
+    trades: RwLock<HashMap<Address, Vec<Trade>>>,
+    cost_basis: CostBasisMethod,
+    /// JSON file holding the history, `None` keeps it in memory only
+    store_path: Option<PathBuf>,
+    /// Orders writes of the store, snapshots and trades are recorded under separate locks
+    persisting: Mutex<()>,
 }
 
 impl PortfolioTracker {
-    pub async fn new() -> Result<Self> {
-        Ok(Self {})
+    pub async fn new(cost_basis: CostBasisMethod, store_path: Option<PathBuf>) -> Result<Self> {
+        let stored: Vec<WalletHistory> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !stored.is_empty()) {
+            info!("Loaded the portfolio history of {} wallets from {}", stored.len(), path.display());
+        }
+        let mut snapshots = HashMap::new();
+        let mut trades = HashMap::new();
+        for wallet in stored {
+            snapshots.insert(wallet.address, wallet.snapshots);
+            trades.insert(wallet.address, wallet.trades);
+        }
+
+        Ok(Self {
+            snapshots: Arc::new(RwLock::new(snapshots)),
+            trades: RwLock::new(trades),
+            cost_basis,
+            store_path,
+            persisting: Mutex::new(()),
+        })
+    }
+
+    /// Tracker persisting to `portfolio_store_path` and matching sales to lots by `pnl_cost_basis_method`; an
+    /// empty path keeps history in memory
+    pub async fn from_config(config: &config::Config) -> Result<Self> {
+        let path = config
+            .get_string("portfolio_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(CostBasisMethod::from_config(config), store_path).await
+    }
+
+    /// Store snapshots, keeping each wallet's history ordered by time
+    pub async fn record_snapshots(&self, new_snapshots: Vec<PortfolioSnapshot>) {
+        let mut snapshots = self.snapshots.write().await;
+        for snapshot in new_snapshots {
+            let history = snapshots.entry(snapshot.address).or_default();
+            // Re-importing the same export replaces the earlier snapshot at that time
+            history.retain(|existing| existing.taken_at != snapshot.taken_at || existing.source != snapshot.source);
+            history.push(snapshot);
+            history.sort_by_key(|existing| existing.taken_at);
+            if history.len() > MAX_SNAPSHOTS {
+                let excess = history.len() - MAX_SNAPSHOTS;
+                history.drain(..excess);
+            }
+        }
+        drop(snapshots);
+        self.persist().await;
+    }
+
+    /// Snapshots of a wallet, oldest first
+    pub async fn history(&self, address: Address, since: Option<DateTime<Utc>>) -> Vec<PortfolioSnapshot> {
+        self.snapshots.read().await
+            .get(&address)
+            .map(|history| {
+                history.iter()
+                    .filter(|snapshot| since.is_none_or(|since| snapshot.taken_at >= since))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Last snapshot of each interval, oldest first; intervals start on multiples of their length in the
+    /// tenant's local time, so daily intervals close at local midnight
+    pub async fn closes(
+        &self,
+        address: Address,
+        interval: Duration,
+        since: Option<DateTime<Utc>>,
+        settings: &TenantTimeSettings,
+    ) -> Vec<PortfolioSnapshot> {
+        let length = interval.num_seconds().max(1);
+        let bucket = |at: DateTime<Utc>| {
+            at.with_timezone(&settings.time_zone).naive_local().and_utc().timestamp().div_euclid(length)
+        };
+        let mut closes: Vec<PortfolioSnapshot> = Vec::new();
+        for snapshot in self.history(address, since).await {
+            match closes.last_mut() {
+                Some(close) if bucket(close.taken_at) == bucket(snapshot.taken_at) => *close = snapshot,
+                _ => closes.push(snapshot),
+            }
+        }
+        closes
+    }
+
+    pub async fn latest(&self, address: Address) -> Option<PortfolioSnapshot> {
+        self.snapshots.read().await.get(&address).and_then(|history| history.last().cloned())
+    }
+
+    /// Trades recorded for a wallet, oldest first
+    pub async fn trades(&self, address: Address) -> Vec<Trade> {
+        self.trades.read().await.get(&address).cloned().unwrap_or_default()
+    }
+
+    /// Store trades of a wallet with the prices they were made at, keeping them ordered by time
+    pub async fn record_trades(&self, address: Address, new_trades: Vec<Trade>) -> Result<()> {
+        for trade in &new_trades {
+            trade.validate()?;
+        }
+        let mut trades = self.trades.write().await;
+        let history = trades.entry(address).or_default();
+        history.extend(new_trades);
+        history.sort_by_key(|trade| trade.executed_at);
+        if history.len() > MAX_TRADES {
+            let excess = history.len() - MAX_TRADES;
+            history.drain(..excess);
+        }
+        drop(trades);
+        self.persist().await;
+        Ok(())
+    }
+
+    async fn persist(&self) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let _writing = self.persisting.lock().await;
+        let stored: Vec<WalletHistory> = {
+            let snapshots = self.snapshots.read().await;
+            let trades = self.trades.read().await;
+            let mut addresses: Vec<Address> = snapshots.keys().chain(trades.keys()).copied().collect();
+            addresses.sort();
+            addresses.dedup();
+            addresses.into_iter()
+                .map(|address| WalletHistory {
+                    address,
+                    snapshots: snapshots.get(&address).cloned().unwrap_or_default(),
+                    trades: trades.get(&address).cloned().unwrap_or_default(),
+                })
+                .collect()
+        };
+        if let Err(e) = write_store(path, &stored).await {
+            warn!("Failed to persist portfolio history to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Realized and unrealized PnL of a wallet with a daily series in the tenant's time zone
+    pub async fn pnl(&self, address: Address, method: Option<CostBasisMethod>, settings: &TenantTimeSettings) -> PortfolioPnl {
+        let method = method.unwrap_or(self.cost_basis);
+        let snapshots = self.history(address, None).await;
+        let trades = self.trades(address).await;
+        // Trades come before a snapshot taken at the same time, which already shows them
+        let mut events: Vec<PnlEvent> = trades.iter().map(PnlEvent::Trade)
+            .chain(snapshots.iter().map(PnlEvent::Snapshot))
+            .collect();
+        events.sort_by_key(|event| (event.at(), matches!(event, PnlEvent::Snapshot(_))));
+
+        let mut ledger = PnlLedger::new(method);
+        let mut daily = Vec::new();
+        let mut events = events.iter().peekable();
+        if let Some(first) = events.peek() {
+            let today = settings.local_date(Utc::now());
+            let mut date = settings.local_date(first.at());
+            let mut realized_before = 0.0;
+            while date <= today {
+                let day_end = settings.day_start(date + Duration::days(1));
+                while let Some(event) = events.next_if(|event| event.at() < day_end) {
+                    ledger.apply(event);
+                }
+                let (realized, unrealized) = (ledger.realized(), ledger.unrealized());
+                daily.push(DailyPnl {
+                    date,
+                    realized_pnl_usd: realized - realized_before,
+                    cumulative_realized_pnl_usd: realized,
+                    unrealized_pnl_usd: unrealized,
+                    total_pnl_usd: realized + unrealized,
+                });
+                realized_before = realized;
+                date += Duration::days(1);
+            }
+            // Events stamped after the current local day
+            events.for_each(|event| ledger.apply(event));
+        }
+
+        let positions: Vec<PositionPnl> = ledger.books.iter()
+            .map(|(key, book)| {
+                let held: f64 = book.lots.iter().map(|lot| lot.amount).sum();
+                PositionPnl {
+                    chain_id: key.chain_id,
+                    token: key.token,
+                    symbol: book.symbol.clone(),
+                    quantity: book.quantity,
+                    cost_basis_usd: book.cost_basis(),
+                    average_entry_price: (held > QUANTITY_EPSILON).then(|| book.cost_basis() / held),
+                    current_price: book.last_price,
+                    realized_pnl_usd: book.realized,
+                    unrealized_pnl_usd: book.unrealized(),
+                    fees_usd: book.fees,
+                }
+            })
+            .collect();
+        let (realized, unrealized) = (ledger.realized(), ledger.unrealized());
+
+        PortfolioPnl {
+            address,
+            method,
+            realized_pnl_usd: realized,
+            unrealized_pnl_usd: unrealized,
+            total_pnl_usd: realized + unrealized,
+            fees_usd: positions.iter().map(|position| position.fees_usd).sum(),
+            positions,
+            daily,
+        }
     }
 }
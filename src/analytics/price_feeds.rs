@@ -1,12 +1,681 @@
 // Price feed implementations
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::Abi,
+    contract::Contract,
+    types::{Address, I256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::chains::assets::AssetRegistry;
+use crate::chains::ChainManager;
+use crate::transactions::read_store;
+
+/// Chainlink USD aggregators by canonical asset and chain, registered for every token of the asset on the chain
+const BUILTIN_CHAINLINK_FEEDS: [(&str, u64, &str); 23] = [
+    ("eth", 1, "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419"),
+    ("eth", 137, "0xF9680D99D6C9589e2a93a78A04A279e509205945"),
+    ("eth", 42161, "0x639Fe6ab55C921f74e7fac1ee960C0B6293ba612"),
+    ("eth", 56, "0x9ef1B8c0E4F7dc8bF5719Ea496883DC6401d5b2e"),
+    ("eth", 43114, "0x976B3D034E162d8bD72D6b9C989d545b839003b0"),
+    ("btc", 1, "0xF4030086522a5bEEa4988F8cA5B36dbC97BeE88c"),
+    ("btc", 137, "0xc907E116054Ad103354f2D350FD2514433D57F6f"),
+    ("btc", 42161, "0x6ce185860a4963106506C203335A2910413708e9"),
+    ("btc", 56, "0x264990fbd0A4796A3E3d8E37C4d5F87a3aCa5Ebf"),
+    ("btc", 43114, "0x2779D32d5166BAaa2B2b658333bA7e6Ec0C65743"),
+    ("usdc", 1, "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6"),
+    ("usdc", 137, "0xfE4A8cc5b5B2366C1B58Bea3858e81843581b2F7"),
+    ("usdc", 42161, "0x50834F3163758fcC1Df9973b6e91f0F0F0434aD3"),
+    ("usdt", 1, "0x3E7d1eAB13ad0104d2750B8863b489D65364e32D"),
+    ("usdt", 137, "0x0A6513e40db6EB1b165753AD52E80663aeA50545"),
+    ("usdt", 42161, "0x3f3f5dF88dC9F13eac63DF89EC16ef6e7E25DdE7"),
+    ("dai", 1, "0xAed0c38402a5d19df6E4c03F4E2DceD6e29c1ee9"),
+    ("dai", 137, "0x4746DeC9e833A82EC7C2C1356372CcF2cfcD2F3D"),
+    ("dai", 42161, "0xc5C8E77B397E531B8EC06BFb0048328B30E9eCfB"),
+    ("matic", 1, "0x7bAC85A8a13A4BcD8abb3eB7d6b4d632c5a57676"),
+    ("matic", 137, "0xAB594600376Ec9fD91F8e885dADF0CE036862dE0"),
+    ("bnb", 56, "0x0567F2323251f0Aab15c8dFb1967E4e8A7D42aeE"),
+    ("avax", 43114, "0x0A77230d17318075983913bC2145DB16C7366156"),
+];
+
+/// (asset id, chain id, Uniswap V3 pool against USDC, asset is token0, asset decimals, USDC decimals)
+const BUILTIN_TWAP_POOLS: [(&str, u64, &str, bool, u8, u8); 4] = [
+    ("eth", 1, "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640", false, 18, 6),
+    ("btc", 1, "0x99ac8cA7087fA4A2A1FB6357269965A2014ABc35", true, 8, 6),
+    ("eth", 137, "0x45dDa9cb7c25131DF268515131f647d726f50608", false, 18, 6),
+    ("eth", 42161, "0xC6962004f452bE9203591991D15f6b388e09E8D0", true, 18, 6),
+];
+
+/// Where a token price was sourced from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PriceSource {
+    Chainlink,
+    DexTwap,
+    CoinGecko,
+}
+
+/// USD price of a token on a given chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPrice {
+    pub chain_id: u64,
+    pub token: Address,
+    pub price_usd: f64,
+    pub source: PriceSource,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Price of a token from one on-chain source, uncached, with when the source last updated it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourcePrice {
+    pub source: PriceSource,
+    pub price_usd: f64,
+    /// Chainlink's last round update; the end of the averaging window, now, for TWAPs
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Uniswap V3 pool used to derive a TWAP against a USD stablecoin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwapPool {
+    pub pool: Address,
+    /// True when the priced token is token0 of the pool
+    pub token_is_token0: bool,
+    pub token_decimals: u8,
+    pub quote_decimals: u8,
+}
+
+/// Chainlink USD aggregator of a token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainlinkFeed {
+    pub chain_id: u64,
+    pub token: Address,
+    pub aggregator: Address,
+}
+
+/// TWAP pool of a token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwapPoolFeed {
+    pub chain_id: u64,
+    pub token: Address,
+    #[serde(flatten)]
+    pub pool: TwapPool,
+}
+
+/// Reference feeds registered on top of the built-in ones, read from `price_feeds.feeds_path`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReferenceFeeds {
+    #[serde(default)]
+    pub chainlink: Vec<ChainlinkFeed>,
+    #[serde(default)]
+    pub twap_pools: Vec<TwapPoolFeed>,
+}
+
+/// Map the native-asset placeholder (zero address) to the chain's wrapped native token
+pub fn pricing_address(chain_id: u64, token: Address) -> Address {
+    if !token.is_zero() {
+        return token;
+    }
+
+    let wrapped = match chain_id {
+        1 => "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",     // WETH
+        137 => "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270",   // WMATIC
+        42161 => "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1", // WETH
+        56 => "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c",    // WBNB
+        43114 => "0xB31f66AA3C1e785363F0875A1B74E27b85FD66c7", // WAVAX
+        _ => return token,
+    };
+    wrapped.parse().unwrap_or(token)
+}
+
+/// Chainlink ETH/USD aggregators, e.g. converting Aave's ETH-denominated oracle prices
+pub fn eth_usd_aggregator(chain_id: u64) -> Option<Address> {
+    let aggregator = match chain_id {
+        1 => "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419",
+        137 => "0xF9680D99D6C9589e2a93a78A04A279e509205945",
+        _ => return None,
+    };
+    aggregator.parse().ok()
+}
+
+/// Price feed configuration
+#[derive(Debug, Clone)]
+pub struct PriceFeedConfig {
+    pub cache_ttl: Duration,
+    pub source_order: Vec<PriceSource>,
+    pub twap_window_secs: u32,
+    pub coingecko_base_url: String,
+    pub coingecko_api_key: Option<String>,
+    /// Maximum contract addresses per CoinGecko request
+    pub coingecko_batch_size: usize,
+    /// Minimum spacing between CoinGecko requests (free tier is ~30 req/min)
+    pub coingecko_min_interval: Duration,
+    /// JSON file of Chainlink feeds and TWAP pools added to the built-in ones
+    pub feeds_path: Option<PathBuf>,
+}
+
+impl Default for PriceFeedConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl: Duration::from_secs(60),
+            source_order: vec![PriceSource::Chainlink, PriceSource::DexTwap, PriceSource::CoinGecko],
+            twap_window_secs: 1800,
+            coingecko_base_url: "https://api.coingecko.com/api/v3".to_string(),
+            coingecko_api_key: None,
+            coingecko_batch_size: 50,
+            coingecko_min_interval: Duration::from_millis(2100),
+            feeds_path: None,
+        }
+    }
+}
+
+impl PriceFeedConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut feed_config = Self::default();
+
+        if let Ok(ttl) = config.get_int("price_feeds.cache_ttl_secs") {
+            feed_config.cache_ttl = Duration::from_secs(ttl.max(0) as u64);
+        }
+        if let Ok(window) = config.get_int("price_feeds.twap_window_secs") {
+            feed_config.twap_window_secs = window.max(1) as u32;
+        }
+        if let Ok(url) = config.get_string("price_feeds.coingecko_base_url") {
+            feed_config.coingecko_base_url = url;
+        }
+        if let Ok(key) = config.get_string("coingecko_api_key") {
+            if !key.is_empty() {
+                feed_config.coingecko_api_key = Some(key);
+            }
+        }
+        if let Ok(batch) = config.get_int("price_feeds.coingecko_batch_size") {
+            feed_config.coingecko_batch_size = batch.max(1) as usize;
+        }
+        if let Ok(interval) = config.get_int("price_feeds.coingecko_min_interval_ms") {
+            feed_config.coingecko_min_interval = Duration::from_millis(interval.max(0) as u64);
+        }
+        if let Ok(path) = config.get_string("price_feeds.feeds_path") {
+            feed_config.feeds_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        }
+
+        feed_config
+    }
+}
+
+/// CoinGecko HTTP client with request batching and client-side rate limiting
+pub struct CoinGeckoClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    batch_size: usize,
+    min_interval: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl CoinGeckoClient {
+    pub fn new(config: &PriceFeedConfig) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            base_url: config.coingecko_base_url.trim_end_matches('/').to_string(),
+            api_key: config.coingecko_api_key.clone(),
+            batch_size: config.coingecko_batch_size.max(1),
+            min_interval: config.coingecko_min_interval,
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// CoinGecko asset platform id for a chain
+    fn platform_id(chain_id: u64) -> Option<&'static str> {
+        match chain_id {
+            1 => Some("ethereum"),
+            137 => Some("polygon-pos"),
+            42161 => Some("arbitrum-one"),
+            56 => Some("binance-smart-chain"),
+            43114 => Some("avalanche"),
+            _ => None,
+        }
+    }
+
+    /// Fetch USD prices for a set of token contracts, batching requests
+    pub async fn get_token_prices(&self, chain_id: u64, tokens: &[Address]) -> Result<HashMap<Address, f64>> {
+        let platform = Self::platform_id(chain_id)
+            .ok_or_else(|| anyhow!("Chain {} not supported by CoinGecko", chain_id))?;
+
+        let mut prices = HashMap::new();
+        for batch in tokens.chunks(self.batch_size) {
+            let batch_prices = self.fetch_batch(platform, batch).await?;
+            prices.extend(batch_prices);
+        }
+
+        Ok(prices)
+    }
+
+    async fn fetch_batch(&self, platform: &str, tokens: &[Address]) -> Result<HashMap<Address, f64>> {
+        let addresses = tokens
+            .iter()
+            .map(|token| format!("{:?}", token))
+            .collect::<Vec<_>>()
+            .join(",");
+        let url = format!("{}/simple/token_price/{}", self.base_url, platform);
+
+        // One retry after honouring Retry-After on HTTP 429
+        for attempt in 0..2 {
+            self.wait_for_slot().await;
+
+            let mut request = self.http
+                .get(&url)
+                .query(&[("contract_addresses", addresses.as_str()), ("vs_currencies", "usd")]);
+            if let Some(key) = &self.api_key {
+                request = request.header("x-cg-demo-api-key", key);
+            }
+
+            let response = request.send().await?;
+
+            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS && attempt == 0 {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .unwrap_or(60);
+                warn!("CoinGecko rate limited, retrying in {}s", retry_after);
+                tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                continue;
+            }
+
+            let body: HashMap<String, HashMap<String, f64>> = response.error_for_status()?.json().await?;
+
+            let prices = body
+                .into_iter()
+                .filter_map(|(address, quote)| {
+                    let token = address.parse::<Address>().ok()?;
+                    let price = *quote.get("usd")?;
+                    Some((token, price))
+                })
+                .collect();
+
+            return Ok(prices);
+        }
+
+        Err(anyhow!("CoinGecko rate limit exceeded"))
+    }
+
+    /// Sleep until the minimum request interval has elapsed
+    async fn wait_for_slot(&self) {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last) = *last_request {
+            let elapsed = last.elapsed();
+            if elapsed < self.min_interval {
+                tokio::time::sleep(self.min_interval - elapsed).await;
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+}
 
 pub struct PriceFeedService {
-    // Price feed functionality
+    chain_manager: Arc<ChainManager>,
+    config: PriceFeedConfig,
+    coingecko: CoinGeckoClient,
+    chainlink_feeds: Arc<RwLock<HashMap<(u64, Address), Address>>>,
+    twap_pools: Arc<RwLock<HashMap<(u64, Address), TwapPool>>>,
+    price_cache: Arc<RwLock<HashMap<(u64, Address), TokenPrice>>>,
 }
 
 impl PriceFeedService {
-    pub async fn new() -> Result<Self> {
-        Ok(Self {})
+    pub async fn new(chain_manager: Arc<ChainManager>, config: PriceFeedConfig) -> Result<Self> {
+        info!("Initializing PriceFeedService with source order {:?}", config.source_order);
+
+        Ok(Self {
+            chain_manager,
+            coingecko: CoinGeckoClient::new(&config),
+            config,
+            chainlink_feeds: Arc::new(RwLock::new(HashMap::new())),
+            twap_pools: Arc::new(RwLock::new(HashMap::new())),
+            price_cache: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Service with the built-in reference feeds of the tokens in `assets` and those of `feeds_path`
+    pub async fn with_reference_feeds(
+        chain_manager: Arc<ChainManager>,
+        config: PriceFeedConfig,
+        assets: &AssetRegistry,
+    ) -> Result<Self> {
+        let feeds_path = config.feeds_path.clone();
+        let service = Self::new(chain_manager, config).await?;
+        service.register_builtin_feeds(assets).await;
+        if let Some(path) = feeds_path {
+            let feeds: ReferenceFeeds = read_store(&path).await?
+                .ok_or_else(|| anyhow!("Price feed list {} does not exist", path.display()))?;
+            info!(
+                "Loaded {} Chainlink feeds and {} TWAP pools from {}",
+                feeds.chainlink.len(), feeds.twap_pools.len(), path.display(),
+            );
+            for feed in feeds.chainlink {
+                service.register_chainlink_feed(feed.chain_id, feed.token, feed.aggregator).await;
+            }
+            for feed in feeds.twap_pools {
+                service.register_twap_pool(feed.chain_id, feed.token, feed.pool).await;
+            }
+        }
+        Ok(service)
+    }
+
+    /// Register the built-in Chainlink aggregators and TWAP pools for each token of their asset on their chain
+    async fn register_builtin_feeds(&self, assets: &AssetRegistry) {
+        let tokens = |id: &str, chain_id: u64| -> Vec<Address> {
+            assets.assets().iter()
+                .filter(|asset| asset.id == id)
+                .flat_map(|asset| asset.representations.iter())
+                .filter(|representation| representation.chain_id == chain_id)
+                .map(|representation| representation.address)
+                .collect()
+        };
+        for (id, chain_id, aggregator) in BUILTIN_CHAINLINK_FEEDS {
+            let aggregator: Address = aggregator.parse().expect("valid built-in aggregator address");
+            for token in tokens(id, chain_id) {
+                self.register_chainlink_feed(chain_id, token, aggregator).await;
+            }
+        }
+        for (id, chain_id, pool, token_is_token0, token_decimals, quote_decimals) in BUILTIN_TWAP_POOLS {
+            let pool = TwapPool {
+                pool: pool.parse().expect("valid built-in pool address"),
+                token_is_token0,
+                token_decimals,
+                quote_decimals,
+            };
+            for token in tokens(id, chain_id) {
+                self.register_twap_pool(chain_id, token, pool.clone()).await;
+            }
+        }
+        debug!(
+            "Registered {} Chainlink feeds and {} TWAP pools",
+            self.chainlink_feeds.read().await.len(), self.twap_pools.read().await.len(),
+        );
+    }
+
+    /// Register a Chainlink USD aggregator for a token
+    pub async fn register_chainlink_feed(&self, chain_id: u64, token: Address, aggregator: Address) {
+        self.chainlink_feeds.write().await.insert((chain_id, token), aggregator);
+    }
+
+    /// Register a Uniswap V3 stablecoin pool used for TWAP pricing
+    pub async fn register_twap_pool(&self, chain_id: u64, token: Address, pool: TwapPool) {
+        self.twap_pools.write().await.insert((chain_id, token), pool);
+    }
+
+    /// Get the USD price of a single token
+    pub async fn get_price(&self, chain_id: u64, token: Address) -> Result<TokenPrice> {
+        let mut prices = self.get_prices(chain_id, &[token]).await?;
+        prices
+            .remove(&token)
+            .ok_or_else(|| anyhow!("No price source available for {:?} on chain {}", token, chain_id))
+    }
+
+    /// Get USD prices for many tokens; tokens without any price are omitted
+    pub async fn get_prices(&self, chain_id: u64, tokens: &[Address]) -> Result<HashMap<Address, TokenPrice>> {
+        let mut prices = HashMap::new();
+        let mut pending = Vec::new();
+
+        {
+            let cache = self.price_cache.read().await;
+            let now = Utc::now();
+            for token in tokens {
+                match cache.get(&(chain_id, *token)) {
+                    Some(cached) if Self::is_fresh(cached, now, self.config.cache_ttl) => {
+                        prices.insert(*token, cached.clone());
+                    }
+                    _ => pending.push(*token),
+                }
+            }
+        }
+
+        for source in &self.config.source_order {
+            if pending.is_empty() {
+                break;
+            }
+
+            let resolved = match source {
+                PriceSource::Chainlink => self.resolve_each(chain_id, &pending, *source).await,
+                PriceSource::DexTwap => self.resolve_each(chain_id, &pending, *source).await,
+                PriceSource::CoinGecko => match self.coingecko.get_token_prices(chain_id, &pending).await {
+                    Ok(found) => found,
+                    Err(e) => {
+                        warn!("CoinGecko lookup failed on chain {}: {}", chain_id, e);
+                        HashMap::new()
+                    }
+                },
+            };
+
+            let fetched_at = Utc::now();
+            let mut cache = self.price_cache.write().await;
+            for (token, price_usd) in resolved {
+                let price = TokenPrice {
+                    chain_id,
+                    token,
+                    price_usd,
+                    source: *source,
+                    fetched_at,
+                };
+                cache.insert((chain_id, token), price.clone());
+                prices.insert(token, price);
+            }
+            pending.retain(|token| !prices.contains_key(token));
+        }
+
+        if !pending.is_empty() {
+            debug!("No price found for {} tokens on chain {}", pending.len(), chain_id);
+        }
+
+        Ok(prices)
+    }
+
+    /// Drop all cached prices
+    pub async fn clear_cache(&self) {
+        self.price_cache.write().await.clear();
+    }
+
+    fn is_fresh(price: &TokenPrice, now: DateTime<Utc>, ttl: Duration) -> bool {
+        (now - price.fetched_at)
+            .to_std()
+            .map(|age| age < ttl)
+            .unwrap_or(true)
+    }
+
+    async fn resolve_each(&self, chain_id: u64, tokens: &[Address], source: PriceSource) -> HashMap<Address, f64> {
+        let mut resolved = HashMap::new();
+
+        for token in tokens {
+            let result = match source {
+                PriceSource::Chainlink => self.chainlink_price(chain_id, *token).await,
+                PriceSource::DexTwap => self.twap_price(chain_id, *token).await,
+                PriceSource::CoinGecko => continue,
+            };
+
+            match result {
+                Ok(Some(price)) => {
+                    resolved.insert(*token, price);
+                }
+                Ok(None) => {}
+                Err(e) => debug!("{:?} price lookup failed for {:?}: {}", source, token, e),
+            }
+        }
+
+        resolved
+    }
+
+    /// Chainlink and TWAP prices of a token read now, for cross-checking sources against each other;
+    /// sources without a registered feed or failing to answer are left out
+    pub async fn source_prices(&self, chain_id: u64, token: Address) -> Vec<SourcePrice> {
+        let (chainlink, twap) = tokio::join!(self.chainlink_round(chain_id, token), self.twap_price(chain_id, token));
+        let mut prices = Vec::new();
+        match chainlink {
+            Ok(Some((price_usd, updated_at))) => prices.push(SourcePrice { source: PriceSource::Chainlink, price_usd, updated_at }),
+            Ok(None) => {}
+            Err(e) => debug!("Chainlink price lookup failed for {:?}: {}", token, e),
+        }
+        match twap {
+            Ok(Some(price_usd)) => prices.push(SourcePrice { source: PriceSource::DexTwap, price_usd, updated_at: Utc::now() }),
+            Ok(None) => {}
+            Err(e) => debug!("DexTwap price lookup failed for {:?}: {}", token, e),
+        }
+        prices
+    }
+
+    /// Read the latest answer from a registered Chainlink aggregator
+    async fn chainlink_price(&self, chain_id: u64, token: Address) -> Result<Option<f64>> {
+        let Some((price, updated_at)) = self.chainlink_round(chain_id, token).await? else {
+            return Ok(None);
+        };
+
+        let age = (Utc::now() - updated_at).num_seconds();
+        if age > 3600 * 24 {
+            return Err(anyhow!("Chainlink answer is stale ({}s old)", age));
+        }
+
+        Ok(Some(price))
+    }
+
+    /// Latest answer of a registered Chainlink aggregator with the time its round was updated
+    async fn chainlink_round(&self, chain_id: u64, token: Address) -> Result<Option<(f64, DateTime<Utc>)>> {
+        let aggregator = match self.chainlink_feeds.read().await.get(&(chain_id, token)) {
+            Some(aggregator) => *aggregator,
+            None => return Ok(None),
+        };
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+        let contract = Contract::new(aggregator, Self::get_aggregator_abi()?, provider);
+
+        let decimals: u8 = contract.method::<_, u8>("decimals", ())?.call().await?;
+        let (_, answer, _, updated_at, _): (U256, I256, U256, U256, U256) = contract
+            .method::<_, (U256, I256, U256, U256, U256)>("latestRoundData", ())?
+            .call()
+            .await?;
+
+        if answer <= I256::zero() {
+            return Err(anyhow!("Chainlink returned non-positive answer"));
+        }
+
+        let updated_at = DateTime::from_timestamp(updated_at.low_u64() as i64, 0)
+            .ok_or_else(|| anyhow!("Chainlink returned an invalid update time {}", updated_at))?;
+        let price = answer.into_raw().as_u128() as f64 / 10f64.powi(decimals as i32);
+        Ok(Some((price, updated_at)))
+    }
+
+    /// USD price of a token as of a past block from its registered Chainlink feed, `None` without one
+    pub async fn price_at(&self, chain_id: u64, token: Address, block: u64) -> Result<Option<f64>> {
+        let aggregator = match self.chainlink_feeds.read().await.get(&(chain_id, token)) {
+            Some(aggregator) => *aggregator,
+            None => return Ok(None),
+        };
+        self.chainlink_answer_at(chain_id, aggregator, block).await.map(Some)
+    }
+
+    /// Answer of a Chainlink aggregator as of a past block, without the staleness check of live prices
+    pub async fn chainlink_answer_at(&self, chain_id: u64, aggregator: Address, block: u64) -> Result<f64> {
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+        let contract = Contract::new(aggregator, Self::get_aggregator_abi()?, provider);
+
+        let decimals: u8 = contract.method::<_, u8>("decimals", ())?.block(block).call().await?;
+        let (_, answer, _, _, _): (U256, I256, U256, U256, U256) = contract
+            .method::<_, (U256, I256, U256, U256, U256)>("latestRoundData", ())?
+            .block(block)
+            .call()
+            .await?;
+        if answer <= I256::zero() {
+            return Err(anyhow!("Chainlink returned non-positive answer at block {}", block));
+        }
+
+        Ok(answer.into_raw().as_u128() as f64 / 10f64.powi(decimals as i32))
+    }
+
+    /// Compute a time-weighted average price from a Uniswap V3 pool oracle
+    async fn twap_price(&self, chain_id: u64, token: Address) -> Result<Option<f64>> {
+        let pool = match self.twap_pools.read().await.get(&(chain_id, token)) {
+            Some(pool) => pool.clone(),
+            None => return Ok(None),
+        };
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+        let contract = Contract::new(pool.pool, Self::get_pool_observe_abi()?, provider);
+
+        let window = self.config.twap_window_secs;
+        let (tick_cumulatives, _): (Vec<I256>, Vec<U256>) = contract
+            .method::<_, (Vec<I256>, Vec<U256>)>("observe", vec![window, 0u32])?
+            .call()
+            .await?;
+
+        if tick_cumulatives.len() != 2 {
+            return Err(anyhow!("Unexpected observe() response length"));
+        }
+
+        let delta = tick_cumulatives[1] - tick_cumulatives[0];
+        let average_tick = delta.as_i64() as f64 / window as f64;
+
+        // price of token0 in token1, adjusted for decimals
+        let raw_price = 1.0001f64.powf(average_tick);
+        let price = if pool.token_is_token0 {
+            raw_price * 10f64.powi(pool.token_decimals as i32 - pool.quote_decimals as i32)
+        } else {
+            (1.0 / raw_price) * 10f64.powi(pool.token_decimals as i32 - pool.quote_decimals as i32)
+        };
+
+        Ok(Some(price))
+    }
+
+    fn get_aggregator_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "decimals",
+                "outputs": [{"internalType": "uint8", "name": "", "type": "uint8"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "latestRoundData",
+                "outputs": [
+                    {"internalType": "uint80", "name": "roundId", "type": "uint80"},
+                    {"internalType": "int256", "name": "answer", "type": "int256"},
+                    {"internalType": "uint256", "name": "startedAt", "type": "uint256"},
+                    {"internalType": "uint256", "name": "updatedAt", "type": "uint256"},
+                    {"internalType": "uint80", "name": "answeredInRound", "type": "uint80"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+
+    fn get_pool_observe_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "uint32[]", "name": "secondsAgos", "type": "uint32[]"}],
+                "name": "observe",
+                "outputs": [
+                    {"internalType": "int56[]", "name": "tickCumulatives", "type": "int56[]"},
+                    {"internalType": "uint160[]", "name": "secondsPerLiquidityCumulativeX128s", "type": "uint160[]"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
     }
 }
@@ -1,12 +1,395 @@
-// Risk assessment implementations
-use anyhow::Result;
+// Value-at-Risk and expected shortfall of lending positions, estimated from the stored market history
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::Address;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
 
+use crate::analytics::backtest::{BacktestService, MarketPoint};
+use crate::analytics::price_feeds::pricing_address;
+use crate::defi::PositionValuation;
+
+const DEFAULT_CONFIDENCE: f64 = 0.95;
+const DEFAULT_HORIZON_DAYS: f64 = 1.0;
+const DEFAULT_LOOKBACK_DAYS: i64 = 365;
+/// Fewest return observations VaR is estimated from
+const MIN_OBSERVATIONS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct RiskAssessorConfig {
+    /// Probability the loss stays within VaR, e.g. 0.95
+    pub confidence: f64,
+    pub horizon_days: f64,
+    /// Span of market history returns are drawn from
+    pub lookback: Duration,
+}
+
+impl Default for RiskAssessorConfig {
+    fn default() -> Self {
+        Self {
+            confidence: DEFAULT_CONFIDENCE,
+            horizon_days: DEFAULT_HORIZON_DAYS,
+            lookback: Duration::days(DEFAULT_LOOKBACK_DAYS),
+        }
+    }
+}
+
+impl RiskAssessorConfig {
+    /// VaR at `var_confidence` (0.5 to 0.999) over `var_horizon_days`, from the last
+    /// `var_lookback_days` of market history
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut risk_config = Self::default();
+
+        if let Ok(confidence) = config.get_float("var_confidence") {
+            risk_config.confidence = confidence.clamp(0.5, 0.999);
+        }
+        if let Ok(days) = config.get_float("var_horizon_days") {
+            if days > 0.0 {
+                risk_config.horizon_days = days;
+            }
+        }
+        if let Ok(days) = config.get_int("var_lookback_days") {
+            risk_config.lookback = Duration::days(days.max(1));
+        }
+
+        risk_config
+    }
+}
+
+/// Loss of a portfolio not exceeded at the confidence level, and the average loss beyond it
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskMeasure {
+    #[serde(with = "crate::api::models::usd")]
+    pub var_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub expected_shortfall_usd: f64,
+    /// Share of each position, adding up to the totals
+    pub contributions: Vec<PositionRisk>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionRisk {
+    pub protocol: String,
+    pub asset: Address,
+    /// Supplied less borrowed value, borrows are short exposure
+    #[serde(with = "crate::api::models::usd")]
+    pub exposure_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub var_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub expected_shortfall_usd: f64,
+}
+
+/// Why a position is left out of the estimates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnmodeledReason {
+    /// The position has no current price to value its exposure
+    Unpriced,
+    /// No stored price of the asset within the lookback
+    NoHistory,
+    /// Too few stored prices of the asset to draw the minimum number of returns from
+    ShortHistory,
+}
+
+/// Position left out of the estimates
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmodeledAsset {
+    pub protocol: String,
+    pub asset: Address,
+    /// `None` for an unpriced position
+    #[serde(with = "crate::api::models::option_usd")]
+    pub exposure_usd: Option<f64>,
+    pub reason: UnmodeledReason,
+    /// History points pricing the asset
+    pub history_points: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueAtRisk {
+    pub confidence: f64,
+    pub horizon_days: f64,
+    /// Returns between consecutive history points pricing every modeled asset
+    pub observations: usize,
+    pub history_from: Option<DateTime<Utc>>,
+    pub history_to: Option<DateTime<Utc>>,
+    /// Delta-normal estimate from the zero-mean covariance of the returns, `None` with too few
+    /// observations
+    pub parametric: Option<RiskMeasure>,
+    /// Positions revalued under each observed return, `None` with too few observations
+    pub historical: Option<RiskMeasure>,
+    /// Positions left out of both estimates and why
+    pub unmodeled_assets: Vec<UnmodeledAsset>,
+}
+
+/// Estimates the market risk of lending positions from the market history backtests store
 pub struct RiskAssessor {
-    // Risk assessment functionality
+    backtests: Arc<BacktestService>,
+    config: RiskAssessorConfig,
 }
 
 impl RiskAssessor {
-    pub async fn new() -> Result<Self> {
-        Ok(Self {})
+    pub fn new(backtests: Arc<BacktestService>, config: RiskAssessorConfig) -> Self {
+        Self { backtests, config }
+    }
+
+    pub async fn value_at_risk(&self, chain_id: u64, positions: &[PositionValuation]) -> ValueAtRisk {
+        let points = self.backtests.stored_points(chain_id, Some(Utc::now() - self.config.lookback), None).await;
+        value_at_risk(chain_id, positions, &points, &self.config)
+    }
+}
+
+/// VaR and expected shortfall of `positions` from `points` ordered oldest first. Returns between
+/// points are scaled to the horizon by the square root of time
+pub fn value_at_risk(chain_id: u64, positions: &[PositionValuation], points: &[MarketPoint], config: &RiskAssessorConfig) -> ValueAtRisk {
+    // History is keyed by Aave reserve, so the native asset of a Compound market is priced as its wrapped token
+    let price_key = |asset: Address| {
+        if points.iter().any(|point| point.prices_usd.contains_key(&asset)) { asset } else { pricing_address(chain_id, asset) }
+    };
+    let history_points = |key: &Address| points.iter()
+        .filter(|point| point.prices_usd.get(key).is_some_and(|price| *price > 0.0))
+        .count();
+    let mut modeled: Vec<(&PositionValuation, Address)> = Vec::new();
+    let mut unmodeled_assets = Vec::new();
+    for position in positions {
+        let held = !position.supplied_amount.is_zero() || !position.borrowed_amount.is_zero();
+        if !held || (position.price_usd.is_some() && position.supplied_usd == position.borrowed_usd) {
+            continue;
+        }
+        let key = price_key(position.asset);
+        let count = history_points(&key);
+        // Every return needs two points, and each modeled asset has to be priced at all of them
+        let reason = match (position.price_usd, count) {
+            (None, _) => UnmodeledReason::Unpriced,
+            (Some(_), 0) => UnmodeledReason::NoHistory,
+            (Some(_), count) if count <= MIN_OBSERVATIONS => UnmodeledReason::ShortHistory,
+            (Some(_), _) => {
+                modeled.push((position, key));
+                continue;
+            }
+        };
+        unmodeled_assets.push(UnmodeledAsset {
+            protocol: position.protocol.clone(),
+            asset: position.asset,
+            exposure_usd: position.price_usd.map(|_| position.supplied_usd - position.borrowed_usd),
+            reason,
+            history_points: count,
+        });
+    }
+    unmodeled_assets.sort_by(|a, b| (a.asset, &a.protocol).cmp(&(b.asset, &b.protocol)));
+
+    let mut keys: Vec<Address> = modeled.iter().map(|(_, key)| *key).collect();
+    keys.sort();
+    keys.dedup();
+    let priced: Vec<&MarketPoint> = points.iter()
+        .filter(|point| keys.iter().all(|key| point.prices_usd.get(key).is_some_and(|price| *price > 0.0)))
+        .collect();
+    let horizon_secs = config.horizon_days * 86_400.0;
+    // Log returns of each key per observation
+    let returns: Vec<Vec<f64>> = if keys.is_empty() {
+        Vec::new()
+    } else {
+        priced.windows(2)
+            .filter_map(|pair| {
+                let elapsed = (pair[1].timestamp - pair[0].timestamp).num_seconds() as f64;
+                (elapsed > 0.0).then(|| {
+                    let scale = (horizon_secs / elapsed).sqrt();
+                    keys.iter().map(|key| (pair[1].prices_usd[key] / pair[0].prices_usd[key]).ln() * scale).collect()
+                })
+            })
+            .collect()
+    };
+
+    let mut report = ValueAtRisk {
+        confidence: config.confidence,
+        horizon_days: config.horizon_days,
+        observations: returns.len(),
+        history_from: None,
+        history_to: None,
+        parametric: None,
+        historical: None,
+        unmodeled_assets,
+    };
+    if returns.len() < MIN_OBSERVATIONS {
+        return report;
+    }
+    report.history_from = priced.first().map(|point| point.timestamp);
+    report.history_to = priced.last().map(|point| point.timestamp);
+
+    let index: HashMap<Address, usize> = keys.iter().enumerate().map(|(i, key)| (*key, i)).collect();
+    let exposures: Vec<(&PositionValuation, usize, f64)> = modeled.iter()
+        .map(|(position, key)| (*position, index[key], position.supplied_usd - position.borrowed_usd))
+        .collect();
+    report.parametric = Some(parametric(&exposures, &returns, keys.len(), config.confidence));
+    report.historical = Some(historical(&exposures, &returns, config.confidence));
+    report
+}
+
+/// Delta-normal VaR `z * sigma` and ES `sigma * phi(z) / (1 - c)`, split by each position's marginal
+/// contribution to `sigma`
+fn parametric(exposures: &[(&PositionValuation, usize, f64)], returns: &[Vec<f64>], assets: usize, confidence: f64) -> RiskMeasure {
+    let n = returns.len() as f64;
+    let mut covariance = vec![vec![0.0; assets]; assets];
+    for observation in returns {
+        for (row, a) in covariance.iter_mut().zip(observation) {
+            for (cell, b) in row.iter_mut().zip(observation) {
+                *cell += a * b / n;
+            }
+        }
+    }
+    let mut asset_exposure = vec![0.0; assets];
+    for (_, asset, exposure) in exposures {
+        asset_exposure[*asset] += exposure;
+    }
+    // Covariance of each asset's return with the portfolio's
+    let marginal: Vec<f64> = covariance.iter()
+        .map(|row| row.iter().zip(&asset_exposure).map(|(cov, exposure)| cov * exposure).sum())
+        .collect();
+    let sigma = asset_exposure.iter().zip(&marginal).map(|(exposure, m)| exposure * m).sum::<f64>().max(0.0).sqrt();
+
+    let z = normal_quantile(confidence);
+    let tail = (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt() / (1.0 - confidence);
+    let contributions = exposures.iter()
+        .map(|(position, asset, exposure)| {
+            let share = if sigma > 0.0 { exposure * marginal[*asset] / sigma } else { 0.0 };
+            PositionRisk {
+                protocol: position.protocol.clone(),
+                asset: position.asset,
+                exposure_usd: *exposure,
+                var_usd: share * z,
+                expected_shortfall_usd: share * tail,
+            }
+        })
+        .collect();
+    RiskMeasure { var_usd: sigma * z, expected_shortfall_usd: sigma * tail, contributions }
+}
+
+/// Empirical VaR, the loss exceeded in `1 - c` of the observations, and ES, the average of those
+/// losses; positions contribute their loss in the VaR observation and their average loss in the tail
+fn historical(exposures: &[(&PositionValuation, usize, f64)], returns: &[Vec<f64>], confidence: f64) -> RiskMeasure {
+    let losses: Vec<Vec<f64>> = returns.iter()
+        .map(|observation| exposures.iter().map(|(_, asset, exposure)| -exposure * (observation[*asset].exp() - 1.0)).collect())
+        .collect();
+    let totals: Vec<f64> = losses.iter().map(|position_losses| position_losses.iter().sum()).collect();
+    let mut order: Vec<usize> = (0..losses.len()).collect();
+    order.sort_by(|a, b| totals[*b].total_cmp(&totals[*a]));
+    let tail = &order[..(((1.0 - confidence) * losses.len() as f64).ceil() as usize).clamp(1, losses.len())];
+    let var_observation = tail[tail.len() - 1];
+
+    let contributions = exposures.iter().enumerate()
+        .map(|(i, (position, _, exposure))| PositionRisk {
+            protocol: position.protocol.clone(),
+            asset: position.asset,
+            exposure_usd: *exposure,
+            var_usd: losses[var_observation][i],
+            expected_shortfall_usd: tail.iter().map(|observation| losses[*observation][i]).sum::<f64>() / tail.len() as f64,
+        })
+        .collect();
+    RiskMeasure {
+        var_usd: totals[var_observation],
+        expected_shortfall_usd: tail.iter().map(|observation| totals[*observation]).sum::<f64>() / tail.len() as f64,
+        contributions,
+    }
+}
+
+/// Inverse of the standard normal distribution, Acklam's rational approximation (relative error
+/// below 1.2e-9)
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2, 1.383577518672690e2, -3.066479806614716e1, 2.506628277459239];
+    const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2, 6.680131188771972e1, -1.328068155288572e1];
+    const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838, -2.549732539343734, 4.374664141464968, 2.938163982698783];
+    const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996, 3.754408661907416];
+    const P_LOW: f64 = 0.02425;
+
+    let lower_tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < P_LOW {
+        lower_tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -lower_tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+
+    fn position(asset: Address, supplied_usd: f64, price_usd: Option<f64>) -> PositionValuation {
+        PositionValuation {
+            protocol: "aave".to_string(),
+            asset,
+            decimals: Some(18),
+            supplied_amount: U256::exp10(18),
+            borrowed_amount: U256::zero(),
+            supplied: Some(1.0),
+            borrowed: Some(0.0),
+            supply_apy: 0.0,
+            borrow_apy: 0.0,
+            supply_reward_apy: 0.0,
+            borrow_reward_apy: 0.0,
+            price_usd,
+            price_source: None,
+            supplied_usd,
+            borrowed_usd: 0.0,
+            liquidation_threshold: 0.8,
+        }
+    }
+
+    /// Daily points pricing `full` throughout and `sparse` at the first three
+    fn history(full: Address, sparse: Address) -> Vec<MarketPoint> {
+        let start = Utc::now() - Duration::days(30);
+        (0..30)
+            .map(|day| {
+                let mut prices_usd = HashMap::from([(full, 100.0 + (day % 5) as f64)]);
+                if day < 3 {
+                    prices_usd.insert(sparse, 1.0);
+                }
+                MarketPoint {
+                    timestamp: start + Duration::days(day),
+                    block_number: None,
+                    prices_usd,
+                    supply_apy: HashMap::new(),
+                    borrow_apy: HashMap::new(),
+                    liquidation_thresholds: HashMap::new(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn assets_left_out_of_var_are_reported_with_the_reason() {
+        let (full, sparse, missing, unpriced) =
+            (Address::repeat_byte(1), Address::repeat_byte(2), Address::repeat_byte(3), Address::repeat_byte(4));
+        let positions = vec![
+            position(full, 1_000.0, Some(100.0)),
+            position(sparse, 500.0, Some(1.0)),
+            position(missing, 250.0, Some(5.0)),
+            position(unpriced, 0.0, None),
+        ];
+
+        let report = value_at_risk(1, &positions, &history(full, sparse), &RiskAssessorConfig::default());
+
+        // The sparse asset does not cut the observations of the modeled one
+        assert_eq!(report.observations, 29);
+        let parametric = report.parametric.expect("enough observations");
+        assert_eq!(parametric.contributions.len(), 1);
+        assert_eq!(parametric.contributions[0].asset, full);
+        let unmodeled: Vec<(Address, UnmodeledReason, usize)> = report.unmodeled_assets.iter()
+            .map(|unmodeled| (unmodeled.asset, unmodeled.reason, unmodeled.history_points))
+            .collect();
+        assert_eq!(unmodeled, vec![
+            (sparse, UnmodeledReason::ShortHistory, 3),
+            (missing, UnmodeledReason::NoHistory, 0),
+            (unpriced, UnmodeledReason::Unpriced, 0),
+        ]);
+        assert_eq!(report.unmodeled_assets[2].exposure_usd, None);
     }
 }
@@ -0,0 +1,219 @@
+// Scheduled portfolio snapshots of tracked wallets
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use ethers::{providers::Middleware, types::Address};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::analytics::portfolio_tracker::{PortfolioSnapshot, PortfolioTracker, SnapshotHealth, TrackedHolding};
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService};
+use crate::defi::DefiManager;
+use crate::dex::DexManager;
+use crate::monitor::PositionMonitor;
+use crate::shutdown::ShutdownSignal;
+
+/// Snapshotter configuration
+#[derive(Debug, Clone)]
+pub struct SnapshotterConfig {
+    pub interval: Duration,
+    pub chain_ids: Vec<u64>,
+    /// Wallets snapshotted besides the ones the position monitor watches
+    pub wallets: Vec<Address>,
+}
+
+impl Default for SnapshotterConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3600),
+            chain_ids: vec![1],
+            wallets: Vec::new(),
+        }
+    }
+}
+
+impl SnapshotterConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut snapshotter_config = Self::default();
+
+        if let Ok(secs) = config.get_int("portfolio_snapshot_interval_secs") {
+            snapshotter_config.interval = Duration::from_secs(secs.max(60) as u64);
+        }
+        if let Ok(chain_ids) = config.get_string("portfolio_snapshot_chain_ids") {
+            snapshotter_config.chain_ids = chain_ids.split(',')
+                .filter_map(|chain_id| chain_id.trim().parse().ok())
+                .collect();
+        }
+        if let Ok(wallets) = config.get_string("portfolio_snapshot_wallets") {
+            snapshotter_config.wallets = wallets.split(',')
+                .filter_map(|wallet| wallet.trim().parse().ok())
+                .collect();
+        }
+
+        snapshotter_config
+    }
+}
+
+/// Records the value, protocol allocation and health factors of tracked wallets on an interval
+pub struct PortfolioSnapshotter {
+    defi_manager: Arc<DefiManager>,
+    dex_manager: Arc<DexManager>,
+    price_feeds: Arc<PriceFeedService>,
+    portfolio: Arc<PortfolioTracker>,
+    monitor: Arc<PositionMonitor>,
+    config: SnapshotterConfig,
+}
+
+impl PortfolioSnapshotter {
+    pub fn new(
+        defi_manager: Arc<DefiManager>,
+        dex_manager: Arc<DexManager>,
+        price_feeds: Arc<PriceFeedService>,
+        portfolio: Arc<PortfolioTracker>,
+        monitor: Arc<PositionMonitor>,
+        config: SnapshotterConfig,
+    ) -> Self {
+        info!(
+            "Initializing PortfolioSnapshotter ({} configured wallets on chains {:?}, every {:?})",
+            config.wallets.len(), config.chain_ids, config.interval,
+        );
+        Self { defi_manager, dex_manager, price_feeds, portfolio, monitor, config }
+    }
+
+    /// Snapshot every tracked wallet in the background until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.snapshot_all().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Portfolio snapshotter stopped");
+        })
+    }
+
+    async fn snapshot_all(&self) {
+        let wallets = self.tracked_wallets().await;
+        let mut recorded = Vec::with_capacity(wallets.len());
+        for (address, chain_ids) in wallets {
+            match self.build_snapshot(address, &chain_ids).await {
+                Ok(snapshot) => recorded.push(snapshot),
+                Err(e) => warn!("Portfolio snapshot of {:?} failed: {}", address, e),
+            }
+        }
+        if !recorded.is_empty() {
+            info!("Recorded {} portfolio snapshots", recorded.len());
+            self.portfolio.record_snapshots(recorded).await;
+        }
+    }
+
+    /// Snapshot a wallet now on the configured chains and the ones it is watched on
+    pub async fn snapshot(&self, address: Address) -> Result<PortfolioSnapshot> {
+        let chain_ids = self.tracked_wallets().await
+            .remove(&address)
+            .unwrap_or_else(|| self.config.chain_ids.iter().copied().collect());
+        let snapshot = self.build_snapshot(address, &chain_ids).await?;
+        self.portfolio.record_snapshots(vec![snapshot.clone()]).await;
+        Ok(snapshot)
+    }
+
+    /// Configured wallets on the configured chains, plus watched positions on their chain
+    async fn tracked_wallets(&self) -> BTreeMap<Address, BTreeSet<u64>> {
+        let mut wallets: BTreeMap<Address, BTreeSet<u64>> = self.config.wallets.iter()
+            .map(|wallet| (*wallet, self.config.chain_ids.iter().copied().collect()))
+            .collect();
+        for position in self.monitor.list_watched().await {
+            wallets.entry(position.user).or_default().insert(position.chain_id);
+        }
+        wallets
+    }
+
+    async fn build_snapshot(&self, address: Address, chain_ids: &BTreeSet<u64>) -> Result<PortfolioSnapshot> {
+        let mut holdings = Vec::new();
+        let mut health_factors = Vec::new();
+        let mut errors = Vec::new();
+        for &chain_id in chain_ids {
+            match self.native_holding(chain_id, address).await {
+                Ok(Some(holding)) => holdings.push(holding),
+                Ok(None) => {}
+                Err(e) => errors.push(format!("native balance on chain {}: {}", chain_id, e)),
+            }
+
+            let portfolio = match self.defi_manager.get_portfolio_overview(chain_id, address).await {
+                Ok(portfolio) => portfolio,
+                Err(e) => {
+                    errors.push(format!("positions on chain {}: {}", chain_id, e));
+                    continue;
+                }
+            };
+            for position in &portfolio.positions_usd {
+                let symbol = self.dex_manager.assets().resolve(chain_id, position.asset)
+                    .map(|(_, representation)| representation.symbol.clone())
+                    .unwrap_or_else(|| format!("{:?}", position.asset));
+                let holding = |position_type: &str, amount: f64, value_usd: f64| TrackedHolding {
+                    chain_id,
+                    token: Some(position.asset),
+                    symbol: symbol.clone(),
+                    protocol: Some(position.protocol.clone()),
+                    position_type: position_type.to_string(),
+                    amount,
+                    value_usd,
+                };
+                // Without decimals the amounts are unknown, like a position that failed to load
+                let (Some(supplied), Some(borrowed)) = (position.supplied, position.borrowed) else {
+                    errors.push(format!("{} position in {:?} on chain {}: decimals unknown", position.protocol, position.asset, chain_id));
+                    continue;
+                };
+                if !position.supplied_amount.is_zero() {
+                    holdings.push(holding("supply", supplied, position.supplied_usd));
+                }
+                if !position.borrowed_amount.is_zero() {
+                    holdings.push(holding("borrow", borrowed, position.borrowed_usd));
+                }
+            }
+            health_factors.extend(portfolio.risk.protocols.iter().filter_map(|protocol| {
+                protocol.health_factor.map(|health_factor| SnapshotHealth {
+                    chain_id,
+                    protocol: protocol.protocol.clone(),
+                    health_factor,
+                })
+            }));
+        }
+
+        // A wallet with nothing readable on any chain gets no snapshot rather than a zero one
+        if holdings.is_empty() && !errors.is_empty() {
+            return Err(anyhow!("Nothing could be read: {}", errors.join("; ")));
+        }
+        for error in &errors {
+            warn!("Portfolio snapshot of {:?} is missing its {}", address, error);
+        }
+        Ok(PortfolioSnapshot::new(address, Utc::now(), holdings, "live".to_string()).with_health_factors(health_factors))
+    }
+
+    async fn native_holding(&self, chain_id: u64, address: Address) -> Result<Option<TrackedHolding>> {
+        let chain = self.dex_manager.chain_manager().get_provider(chain_id).await?;
+        let balance = chain.provider.get_balance(address, None).await?;
+        if balance.is_zero() {
+            return Ok(None);
+        }
+        let amount = balance.to_string().parse::<f64>().unwrap_or_default() / 1e18;
+        let price_usd = self.price_feeds
+            .get_price(chain_id, pricing_address(chain_id, Address::zero())).await?
+            .price_usd;
+        Ok(Some(TrackedHolding {
+            chain_id,
+            token: None,
+            symbol: chain.config.native_token.clone(),
+            protocol: None,
+            position_type: "wallet".to_string(),
+            amount,
+            value_usd: amount * price_usd,
+        }))
+    }
+}
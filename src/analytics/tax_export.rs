@@ -0,0 +1,562 @@
+// Tax reports of a wallet's swaps, liquidity events, income and liquidations
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256, I256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::analytics::portfolio_tracker::{PortfolioTracker, Trade, TradeSide};
+use crate::analytics::price_feeds::{eth_usd_aggregator, pricing_address, PriceFeedService};
+use crate::contracts::erc20::ERC20Contract;
+use crate::dex::DexManager;
+use crate::transactions::settlement::{SettlementReport, SettlementReporter};
+use crate::transactions::{TransactionStatus, TransactionTracker};
+
+/// Canonical assets valued at a dollar at any point in time
+const USD_STABLECOINS: [&str; 3] = ["usdc", "usdt", "dai"];
+
+/// Column layout of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxExportFormat {
+    /// Every field of the events, for tools with a custom CSV mapping
+    #[default]
+    Csv,
+    /// Koinly universal import format
+    Koinly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxEventKind {
+    /// Recorded purchase or sale against USD
+    Trade,
+    Swap,
+    LiquidityIn,
+    LiquidityOut,
+    /// Yield, rewards and arbitrage profit
+    Income,
+    /// Proceeds of liquidating another account
+    Liquidation,
+}
+
+impl TaxEventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Trade => "trade",
+            Self::Swap => "swap",
+            Self::LiquidityIn => "liquidity_in",
+            Self::LiquidityOut => "liquidity_out",
+            Self::Income => "income",
+            Self::Liquidation => "liquidation",
+        }
+    }
+
+    /// Koinly label; unlabeled rows with both sides are trades, deposits without one are transfers
+    fn koinly_label(self, sent: bool) -> &'static str {
+        match self {
+            Self::LiquidityIn => "liquidity in",
+            Self::LiquidityOut => "liquidity out",
+            Self::Income | Self::Liquidation if !sent => "income",
+            _ => "",
+        }
+    }
+}
+
+/// Amount of one currency sent or received
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxAmount {
+    pub amount: f64,
+    pub currency: String,
+}
+
+/// One taxable movement of a wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxEvent {
+    pub timestamp: DateTime<Utc>,
+    pub chain_id: u64,
+    pub kind: TaxEventKind,
+    pub sent: Option<TaxAmount>,
+    pub received: Option<TaxAmount>,
+    /// Gas of the execution in the native asset, or the fee of a recorded trade in USD
+    pub fee: Option<TaxAmount>,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub fee_usd: Option<f64>,
+    /// Value when it happened, `None` when neither side could be priced at the time
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub value_usd: Option<f64>,
+    pub tx_hash: Option<H256>,
+    /// Operation or trade source the event came from, e.g. "dex:swap"
+    pub description: String,
+}
+
+/// Rendered export with the number of events that could not be valued
+#[derive(Debug, Clone)]
+pub struct TaxReport {
+    pub csv: String,
+    /// Events without a USD value at the time, to be valued in the tax tool
+    pub unpriced_events: usize,
+}
+
+/// Token metadata and historical prices looked up while an export is built
+#[derive(Default)]
+struct ExportLookups {
+    tokens: HashMap<(u64, Option<Address>), (String, u8)>,
+    prices: HashMap<(u64, Option<Address>, u64), Option<f64>>,
+    /// Tokens already reported as having no feed
+    unpriced: HashSet<(u64, Address)>,
+}
+
+/// Builds tax reports from recorded trades and the settlement reports of executed transactions
+pub struct TaxExporter {
+    dex_manager: Arc<DexManager>,
+    price_feeds: Arc<PriceFeedService>,
+    transactions: Arc<TransactionTracker>,
+    settlements: Arc<SettlementReporter>,
+    portfolio: Arc<PortfolioTracker>,
+}
+
+impl TaxExporter {
+    pub fn new(
+        dex_manager: Arc<DexManager>,
+        price_feeds: Arc<PriceFeedService>,
+        transactions: Arc<TransactionTracker>,
+        settlements: Arc<SettlementReporter>,
+        portfolio: Arc<PortfolioTracker>,
+    ) -> Self {
+        Self { dex_manager, price_feeds, transactions, settlements, portfolio }
+    }
+
+    /// Taxable events of a wallet within `range`, oldest first
+    pub async fn events(&self, address: Address, range: Option<(DateTime<Utc>, DateTime<Utc>)>) -> Vec<TaxEvent> {
+        let in_range = |at: DateTime<Utc>| range.is_none_or(|(from, to)| at >= from && at < to);
+        let mut events: Vec<TaxEvent> = self.portfolio.trades(address).await.iter()
+            .filter(|trade| in_range(trade.executed_at))
+            .map(trade_event)
+            .collect();
+
+        let mut lookups = ExportLookups::default();
+        let mut executions = BTreeSet::new();
+        for record in self.transactions.history(address, range, usize::MAX).await {
+            let Some(kind) = execution_kind(&record.source) else {
+                continue;
+            };
+            if record.status != TransactionStatus::Confirmed {
+                continue;
+            }
+            let execution_id = record.execution_id.clone().unwrap_or_else(|| record.id.clone());
+            if !executions.insert(execution_id.clone()) {
+                continue;
+            }
+            match self.settlements.report(&execution_id).await {
+                Ok(Some(report)) if report.succeeded => {
+                    events.extend(self.report_events(address, kind, &report, &mut lookups).await);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Execution {} is left out of the tax report of {:?}: {}", execution_id, address, e),
+            }
+        }
+
+        // Executions are selected by when they were built and dated by their block
+        events.retain(|event| in_range(event.timestamp));
+        events.sort_by_key(|event| event.timestamp);
+        events
+    }
+
+    /// Taxable events of a wallet within `range` as CSV
+    pub async fn export(
+        &self,
+        address: Address,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+        format: TaxExportFormat,
+    ) -> Result<TaxReport> {
+        let events = self.events(address, range).await;
+        let unpriced_events = events.iter().filter(|event| event.value_usd.is_none()).count();
+        if unpriced_events > 0 {
+            warn!("{} of {} tax events of {:?} have no USD value", unpriced_events, events.len(), address);
+        }
+        Ok(TaxReport { csv: render_csv(&events, format)?, unpriced_events })
+    }
+
+    /// Swap legs of swapping operations, the wallet's balance changes for the others
+    async fn report_events(
+        &self,
+        address: Address,
+        kind: TaxEventKind,
+        report: &SettlementReport,
+        lookups: &mut ExportLookups,
+    ) -> Vec<TaxEvent> {
+        let chain_id = report.chain_id;
+        let block = report.transactions.iter().find_map(|transaction| transaction.block_number);
+        let timestamp = match block {
+            Some(block) => self.block_time(chain_id, block).await,
+            None => None,
+        };
+        let gas_fee = report.transactions.iter()
+            .fold(U256::zero(), |total, transaction| total.saturating_add(transaction.gas_fee));
+        let tx_hash = report.transactions.first().map(|transaction| transaction.hash);
+
+        let mut movements = Vec::new();
+        if kind == TaxEventKind::Swap && !report.legs.is_empty() {
+            for leg in &report.legs {
+                movements.push((
+                    Some((Some(leg.token_in), leg.amount_in)),
+                    Some((Some(leg.token_out), leg.amount_out)),
+                    Some(leg.transaction),
+                ));
+            }
+        } else {
+            let (mut sent, mut received) = (Vec::new(), Vec::new());
+            for delta in report.deltas.iter().filter(|delta| delta.wallet == address) {
+                // Gas is reported as a fee rather than as native tokens sent
+                let change = match delta.token {
+                    None => delta.delta.saturating_add(I256::from_raw(gas_fee)),
+                    Some(_) => delta.delta,
+                };
+                match change.cmp(&I256::zero()) {
+                    std::cmp::Ordering::Less => sent.push((delta.token, change.unsigned_abs())),
+                    std::cmp::Ordering::Greater => received.push((delta.token, change.unsigned_abs())),
+                    std::cmp::Ordering::Equal => {}
+                }
+            }
+            if sent.len() == 1 && received.len() == 1 {
+                movements.push((sent.pop(), received.pop(), tx_hash));
+            } else {
+                movements.extend(sent.into_iter().map(|sent| (Some(sent), None, tx_hash)));
+                movements.extend(received.into_iter().map(|received| (None, Some(received), tx_hash)));
+            }
+        }
+
+        let mut events = Vec::with_capacity(movements.len());
+        for (sent, received, hash) in movements {
+            let sent = match sent {
+                Some((token, amount)) => Some(self.amount(chain_id, token, amount, block, lookups).await),
+                None => None,
+            };
+            let received = match received {
+                Some((token, amount)) => Some(self.amount(chain_id, token, amount, block, lookups).await),
+                None => None,
+            };
+            let value_usd = sent.as_ref().and_then(|(_, value)| *value)
+                .or_else(|| received.as_ref().and_then(|(_, value)| *value));
+            events.push(TaxEvent {
+                timestamp: timestamp.unwrap_or(report.settled_at),
+                chain_id,
+                kind,
+                sent: sent.map(|(amount, _)| amount),
+                received: received.map(|(amount, _)| amount),
+                fee: None,
+                fee_usd: None,
+                value_usd,
+                tx_hash: hash,
+                description: report.source.clone(),
+            });
+        }
+
+        // The execution's gas goes with its first event
+        if let Some(first) = events.first_mut().filter(|_| !gas_fee.is_zero()) {
+            let (fee, fee_usd) = self.amount(chain_id, None, gas_fee, block, lookups).await;
+            first.fee = Some(fee);
+            first.fee_usd = fee_usd;
+        }
+        events
+    }
+
+    /// Amount of a token in whole units with its USD value at `block`
+    async fn amount(
+        &self,
+        chain_id: u64,
+        token: Option<Address>,
+        raw: U256,
+        block: Option<u64>,
+        lookups: &mut ExportLookups,
+    ) -> (TaxAmount, Option<f64>) {
+        let (currency, decimals) = self.token(chain_id, token, lookups).await;
+        let amount = raw.to_string().parse::<f64>().unwrap_or_default() / 10f64.powi(decimals as i32);
+        let price = self.price_at(chain_id, token, block, lookups).await;
+        (TaxAmount { amount, currency }, price.map(|price| amount * price))
+    }
+
+    async fn token(&self, chain_id: u64, token: Option<Address>, lookups: &mut ExportLookups) -> (String, u8) {
+        if let Some(known) = lookups.tokens.get(&(chain_id, token)) {
+            return known.clone();
+        }
+        let known = match token {
+            None => match self.dex_manager.chain_manager().get_provider(chain_id).await {
+                Ok(chain) => (chain.config.native_token.clone(), 18),
+                Err(_) => ("ETH".to_string(), 18),
+            },
+            Some(token) => match self.dex_manager.assets().resolve(chain_id, token) {
+                Some((_, representation)) => (representation.symbol.clone(), representation.decimals),
+                None => self.erc20_info(chain_id, token).await.unwrap_or_else(|| (format!("{:?}", token), 18)),
+            },
+        };
+        lookups.tokens.insert((chain_id, token), known.clone());
+        known
+    }
+
+    async fn erc20_info(&self, chain_id: u64, token: Address) -> Option<(String, u8)> {
+        let chain = self.dex_manager.chain_manager().get_provider(chain_id).await.ok()?;
+        let contract = ERC20Contract::new(token, Arc::new(chain.provider.clone()), chain_id).await.ok()?;
+        contract.get_token_info().map(|info| (info.symbol.clone(), info.decimals))
+    }
+
+    /// USD price at a past block: a dollar for stablecoins, otherwise the token's registered Chainlink
+    /// feed or, for ETH, the chain's ETH/USD aggregator
+    async fn price_at(
+        &self,
+        chain_id: u64,
+        token: Option<Address>,
+        block: Option<u64>,
+        lookups: &mut ExportLookups,
+    ) -> Option<f64> {
+        let priced = pricing_address(chain_id, token.unwrap_or_else(Address::zero));
+        let asset_id = self.dex_manager.assets().resolve(chain_id, priced).map(|(asset, _)| asset.id.clone());
+        if asset_id.as_deref().is_some_and(|id| USD_STABLECOINS.contains(&id)) {
+            return Some(1.0);
+        }
+        let block = block?;
+        if let Some(price) = lookups.prices.get(&(chain_id, token, block)) {
+            return *price;
+        }
+
+        let price = match self.price_feeds.price_at(chain_id, priced, block).await {
+            Ok(Some(price)) => Some(price),
+            Ok(None) => match eth_usd_aggregator(chain_id).filter(|_| asset_id.as_deref() == Some("eth")) {
+                Some(aggregator) => match self.price_feeds.chainlink_answer_at(chain_id, aggregator, block).await {
+                    Ok(price) => Some(price),
+                    Err(e) => {
+                        warn!("No ETH/USD price on chain {} at block {}: {}", chain_id, block, e);
+                        None
+                    }
+                },
+                None => {
+                    if lookups.unpriced.insert((chain_id, priced)) {
+                        warn!("No Chainlink feed registered for {:?} on chain {}, its tax events are unpriced", priced, chain_id);
+                    }
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("No price of {:?} on chain {} at block {}: {}", priced, chain_id, block, e);
+                None
+            }
+        };
+        lookups.prices.insert((chain_id, token, block), price);
+        price
+    }
+
+    async fn block_time(&self, chain_id: u64, block: u64) -> Option<DateTime<Utc>> {
+        let chain = self.dex_manager.chain_manager().get_provider(chain_id).await.ok()?;
+        let block = chain.provider.get_block(block).await.ok()??;
+        Utc.timestamp_opt(block.timestamp.low_u64() as i64, 0).single()
+    }
+}
+
+/// How the settlement of an operation is reported, `None` for operations that only move funds between
+/// the wallet and its own positions, such as supplying or borrowing
+fn execution_kind(source: &str) -> Option<TaxEventKind> {
+    match source {
+        "dex:add_liquidity" => Some(TaxEventKind::LiquidityIn),
+        "dex:remove_liquidity" => Some(TaxEventKind::LiquidityOut),
+        "defi:flash_liquidation" => Some(TaxEventKind::Liquidation),
+        "defi:flash_loan_arbitrage" => Some(TaxEventKind::Income),
+        "dex:auto_range" | "defi:closeout" | "defi:rebalance" => Some(TaxEventKind::Swap),
+        source if source.contains("swap") => Some(TaxEventKind::Swap),
+        _ => None,
+    }
+}
+
+fn trade_event(trade: &Trade) -> TaxEvent {
+    let token = TaxAmount { amount: trade.amount, currency: trade.symbol.clone() };
+    let value_usd = trade.amount * trade.price_usd;
+    let usd = TaxAmount { amount: value_usd, currency: "USD".to_string() };
+    let (kind, sent, received) = match trade.side {
+        TradeSide::Buy => (TaxEventKind::Trade, Some(usd), Some(token)),
+        TradeSide::Sell => (TaxEventKind::Trade, Some(token), Some(usd)),
+        TradeSide::Income => (TaxEventKind::Income, None, Some(token)),
+    };
+    let fee_usd = (trade.fee_usd > 0.0).then_some(trade.fee_usd);
+
+    TaxEvent {
+        timestamp: trade.executed_at,
+        chain_id: trade.chain_id,
+        kind,
+        sent,
+        received,
+        fee: fee_usd.map(|fee| TaxAmount { amount: fee, currency: "USD".to_string() }),
+        fee_usd,
+        value_usd: Some(value_usd),
+        tx_hash: None,
+        description: trade.source.clone(),
+    }
+}
+
+/// Render events as CSV in the given column layout
+fn render_csv(events: &[TaxEvent], format: TaxExportFormat) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let amount = |side: &Option<TaxAmount>| match side {
+        Some(side) => (side.amount.to_string(), side.currency.clone()),
+        None => (String::new(), String::new()),
+    };
+    let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let hash = |event: &TaxEvent| event.tx_hash.map(|hash| format!("{:?}", hash)).unwrap_or_default();
+
+    match format {
+        TaxExportFormat::Csv => {
+            writer.write_record([
+                "timestamp", "chain_id", "type", "sent_amount", "sent_currency", "received_amount",
+                "received_currency", "fee_amount", "fee_currency", "fee_usd", "value_usd", "tx_hash", "description",
+            ])?;
+            for event in events {
+                let ((sent, sent_currency), (received, received_currency)) = (amount(&event.sent), amount(&event.received));
+                let (fee, fee_currency) = amount(&event.fee);
+                writer.write_record([
+                    event.timestamp.to_rfc3339(),
+                    event.chain_id.to_string(),
+                    event.kind.as_str().to_string(),
+                    sent,
+                    sent_currency,
+                    received,
+                    received_currency,
+                    fee,
+                    fee_currency,
+                    optional(event.fee_usd),
+                    optional(event.value_usd),
+                    hash(event),
+                    event.description.clone(),
+                ])?;
+            }
+        }
+        TaxExportFormat::Koinly => {
+            writer.write_record([
+                "Date", "Sent Amount", "Sent Currency", "Received Amount", "Received Currency", "Fee Amount",
+                "Fee Currency", "Net Worth Amount", "Net Worth Currency", "Label", "Description", "TxHash",
+            ])?;
+            for event in events {
+                let ((sent, sent_currency), (received, received_currency)) = (amount(&event.sent), amount(&event.received));
+                let (fee, fee_currency) = amount(&event.fee);
+                let net_worth_currency = if event.value_usd.is_some() { "USD" } else { "" };
+                writer.write_record([
+                    event.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                    sent,
+                    sent_currency,
+                    received,
+                    received_currency,
+                    fee,
+                    fee_currency,
+                    optional(event.value_usd),
+                    net_worth_currency.to_string(),
+                    event.kind.koinly_label(event.sent.is_some()).to_string(),
+                    event.description.clone(),
+                    hash(event),
+                ])?;
+            }
+        }
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::portfolio_tracker::CostBasisMethod;
+    use crate::analytics::price_feeds::PriceFeedConfig;
+    use crate::chains::assets::AssetRegistry;
+    use crate::chains::ChainManager;
+    use axum::{routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    const WBTC: &str = "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599";
+    const BTC_USD_AGGREGATOR: &str = "0xF4030086522a5bEEa4988F8cA5B36dbC97BeE88c";
+
+    fn word(value: u64) -> String {
+        format!("{:064x}", value)
+    }
+
+    /// JSON-RPC node answering the chain id and the BTC/USD aggregator's `decimals` and `latestRoundData`
+    async fn rpc(Json(request): Json<Value>) -> Json<Value> {
+        let aggregator: Address = BTC_USD_AGGREGATOR.parse().unwrap();
+        let result = match request["method"].as_str() {
+            Some("eth_chainId") => Some(json!("0x1")),
+            Some("eth_call") => {
+                let call = &request["params"][0];
+                let to = call["to"].as_str().and_then(|to| to.parse::<Address>().ok());
+                let data = call["data"].as_str().or_else(|| call["input"].as_str()).unwrap_or_default();
+                match (to == Some(aggregator), data.get(..10)) {
+                    (true, Some("0x313ce567")) => Some(json!(format!("0x{}", word(8)))),
+                    (true, Some("0xfeaf968c")) => Some(json!(format!(
+                        "0x{}{}{}{}{}",
+                        word(1), word(65_000 * 100_000_000), word(1_700_000_000), word(1_700_000_000), word(1),
+                    ))),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        Json(match result {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            None => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32601, "message": "unsupported" } }),
+        })
+    }
+
+    async fn exporter() -> TaxExporter {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, Router::new().route("/", post(rpc))).await });
+
+        let config = config::Config::builder()
+            .set_override("ethereum_rpc_url", url).unwrap()
+            .set_override("ethereum_ws_url", "").unwrap()
+            .build()
+            .unwrap();
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let price_feeds = PriceFeedService::with_reference_feeds(
+            chain_manager.clone(),
+            PriceFeedConfig::default(),
+            &AssetRegistry::builtin(),
+        ).await.unwrap();
+        let transactions = Arc::new(TransactionTracker::new(chain_manager.clone(), None, None).await.unwrap());
+        let settlements = Arc::new(SettlementReporter::new(chain_manager, transactions.clone(), None).await.unwrap());
+        TaxExporter::new(
+            Arc::new(DexManager::new_demo(transactions.clone()).await.unwrap()),
+            Arc::new(price_feeds),
+            transactions,
+            settlements,
+            Arc::new(PortfolioTracker::new(CostBasisMethod::Fifo, None).await.unwrap()),
+        )
+    }
+
+    #[tokio::test]
+    async fn non_eth_token_is_valued_at_its_chainlink_price_at_the_block() {
+        let exporter = exporter().await;
+        let mut lookups = ExportLookups::default();
+        let wbtc: Address = WBTC.parse().unwrap();
+
+        let (amount, value_usd) = exporter
+            .amount(1, Some(wbtc), U256::from(50_000_000u64), Some(19_000_000), &mut lookups)
+            .await;
+
+        assert_eq!(amount.currency, "WBTC");
+        assert_eq!(amount.amount, 0.5);
+        assert_eq!(value_usd, Some(32_500.0));
+    }
+
+    #[tokio::test]
+    async fn token_without_a_feed_is_unpriced() {
+        let exporter = exporter().await;
+        let mut lookups = ExportLookups::default();
+        let unknown = Address::repeat_byte(0x11);
+
+        let price = exporter.price_at(1, Some(unknown), Some(19_000_000), &mut lookups).await;
+
+        assert_eq!(price, None);
+        assert!(lookups.unpriced.contains(&(1, unknown)));
+    }
+}
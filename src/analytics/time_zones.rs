@@ -0,0 +1,149 @@
+// Per-tenant time zones for day boundaries, report cutoffs and delivery times
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// Local hour digests go out at when `default_digest_hour` is not configured
+const DEFAULT_DIGEST_HOUR: u32 = 8;
+
+/// Local time settings of a tenant, keyed by its wallet address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantTimeSettings {
+    /// IANA zone name, e.g. "Europe/Berlin"
+    pub time_zone: Tz,
+    /// Local hour (0-23) digests are delivered at
+    pub digest_hour: u32,
+    /// First month and day of the tax year, e.g. 4 and 6 for the UK
+    pub tax_year_start_month: u32,
+    pub tax_year_start_day: u32,
+}
+
+impl Default for TenantTimeSettings {
+    fn default() -> Self {
+        Self {
+            time_zone: Tz::UTC,
+            digest_hour: DEFAULT_DIGEST_HOUR,
+            tax_year_start_month: 1,
+            tax_year_start_day: 1,
+        }
+    }
+}
+
+impl TenantTimeSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.digest_hour > 23 {
+            return Err(anyhow!("Digest hour {} is not between 0 and 23", self.digest_hour));
+        }
+        // 2001 is not a leap year, so February 29 is rejected as a tax year start
+        if NaiveDate::from_ymd_opt(2001, self.tax_year_start_month, self.tax_year_start_day).is_none() {
+            return Err(anyhow!(
+                "Tax year cannot start on month {} day {}",
+                self.tax_year_start_month, self.tax_year_start_day
+            ));
+        }
+        Ok(())
+    }
+
+    /// Calendar date in the tenant's zone
+    pub fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&self.time_zone).date_naive()
+    }
+
+    /// UTC instant the tenant's local day starts at
+    pub fn day_start(&self, date: NaiveDate) -> DateTime<Utc> {
+        self.resolve_local(date.and_time(NaiveTime::MIN))
+    }
+
+    /// Half-open UTC range of the tax year starting in calendar year `year`
+    pub fn tax_year_bounds(&self, year: i32) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+        let start_of = |year| {
+            NaiveDate::from_ymd_opt(year, self.tax_year_start_month, self.tax_year_start_day)
+                .ok_or_else(|| anyhow!("Invalid tax year {}", year))
+        };
+        Ok((self.day_start(start_of(year)?), self.day_start(start_of(year + 1)?)))
+    }
+
+    /// Next digest delivery after `now`, at the digest hour in the tenant's zone
+    pub fn next_digest_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = self.local_date(now);
+        [today, today + Duration::days(1)]
+            .into_iter()
+            .map(|date| self.resolve_local(date.and_hms_opt(self.digest_hour, 0, 0).unwrap_or_default()))
+            .find(|delivery| *delivery > now)
+            .unwrap_or_else(|| now + Duration::days(1))
+    }
+
+    /// Local hour of day (0-23) of a UTC instant
+    pub fn local_hour(&self, at: DateTime<Utc>) -> u32 {
+        at.with_timezone(&self.time_zone).hour()
+    }
+
+    /// Map a local wall-clock time to UTC; times skipped by a DST jump move to the end of the gap
+    fn resolve_local(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        (0..=4)
+            .map(|step| local + Duration::minutes(30 * step))
+            .find_map(|candidate| self.time_zone.from_local_datetime(&candidate).earliest())
+            .map(|resolved| resolved.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+    }
+}
+
+/// Time settings per tenant, falling back to the configured default
+pub struct TimeZoneSettings {
+    default: TenantTimeSettings,
+    tenants: RwLock<HashMap<Address, TenantTimeSettings>>,
+}
+
+impl TimeZoneSettings {
+    pub fn new(default: TenantTimeSettings) -> Result<Self> {
+        default.validate()?;
+        Ok(Self {
+            default,
+            tenants: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Default from `default_time_zone` and `default_digest_hour`, UTC and 08:00 when unset
+    pub fn from_config(config: &config::Config) -> Result<Self> {
+        let time_zone = match config.get_string("default_time_zone") {
+            Ok(name) if !name.is_empty() => name
+                .parse::<Tz>()
+                .map_err(|e| anyhow!("Invalid default_time_zone {}: {}", name, e))?,
+            _ => Tz::UTC,
+        };
+        let digest_hour = config
+            .get_int("default_digest_hour")
+            .map(|hour| hour as u32)
+            .unwrap_or(DEFAULT_DIGEST_HOUR);
+
+        Self::new(TenantTimeSettings {
+            time_zone,
+            digest_hour,
+            ..TenantTimeSettings::default()
+        })
+    }
+
+    pub fn default_settings(&self) -> TenantTimeSettings {
+        self.default
+    }
+
+    pub async fn get(&self, tenant: Address) -> TenantTimeSettings {
+        self.tenants.read().await.get(&tenant).copied().unwrap_or(self.default)
+    }
+
+    pub async fn set(&self, tenant: Address, settings: TenantTimeSettings) -> Result<()> {
+        settings.validate()?;
+        self.tenants.write().await.insert(tenant, settings);
+        Ok(())
+    }
+
+    /// Drop a tenant's settings so it follows the default again
+    pub async fn reset(&self, tenant: Address) -> bool {
+        self.tenants.write().await.remove(&tenant).is_some()
+    }
+}
+
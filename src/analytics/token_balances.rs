@@ -0,0 +1,301 @@
+// ERC-20 and native balances of arbitrary wallets across chains
+use anyhow::Result;
+use ethers::{
+    abi::{parse_abi, Token},
+    contract::Contract,
+    providers::{Middleware, Provider},
+    types::{Address, BlockNumber, Filter, Log, H256, U256},
+    utils::keccak256,
+};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService};
+use crate::api::models::TokenAmount;
+use crate::chains::assets::AssetRegistry;
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+use crate::contracts::multicall::multicall;
+
+/// Blocks searched for incoming transfers the first time a wallet is scanned on a chain
+const DEFAULT_DISCOVERY_BLOCKS: u64 = 10_000;
+/// Largest block range of one `eth_getLogs` request
+const LOG_CHUNK_BLOCKS: u64 = 2_000;
+
+/// Balance of one token, or of the native asset, held by a wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub chain_id: u64,
+    /// `None` for the native asset
+    pub token: Option<Address>,
+    pub symbol: String,
+    pub amount: TokenAmount,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub price_usd: Option<f64>,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub value_usd: Option<f64>,
+    /// Found through a transfer to the wallet rather than the curated token list
+    pub discovered: bool,
+}
+
+/// Chain a scan could not read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainScanError {
+    pub chain_id: u64,
+    pub error: String,
+}
+
+/// Non-zero balances of a wallet on every scanned chain, most valuable first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletTokenBalances {
+    pub address: Address,
+    /// Primary ENS name of the address, filled in by the API
+    pub ens_name: Option<String>,
+    pub chain_ids: Vec<u64>,
+    /// Value of the priced balances; unpriced tokens, often spam airdrops, count for nothing
+    #[serde(with = "crate::api::models::usd")]
+    pub total_value_usd: f64,
+    pub balances: Vec<TokenBalance>,
+    pub errors: Vec<ChainScanError>,
+}
+
+/// Tokens a wallet received on one chain and the last block searched
+#[derive(Debug, Clone, Default)]
+struct DiscoveredTokens {
+    tokens: BTreeSet<Address>,
+    scanned_to: Option<u64>,
+}
+
+/// Reads wallet balances of the curated asset list and of tokens discovered in Transfer logs
+pub struct TokenBalanceScanner {
+    chain_manager: Arc<ChainManager>,
+    assets: Arc<AssetRegistry>,
+    price_feeds: Arc<PriceFeedService>,
+    discovery_blocks: u64,
+    discovered: RwLock<HashMap<(u64, Address), DiscoveredTokens>>,
+}
+
+impl TokenBalanceScanner {
+    pub fn new(
+        chain_manager: Arc<ChainManager>,
+        assets: Arc<AssetRegistry>,
+        price_feeds: Arc<PriceFeedService>,
+        discovery_blocks: u64,
+    ) -> Self {
+        Self {
+            chain_manager,
+            assets,
+            price_feeds,
+            discovery_blocks,
+            discovered: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Scanner searching the last `token_scan_discovery_blocks` blocks for received tokens
+    pub fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        assets: Arc<AssetRegistry>,
+        price_feeds: Arc<PriceFeedService>,
+    ) -> Self {
+        let discovery_blocks = config
+            .get_int("token_scan_discovery_blocks")
+            .map(|blocks| blocks.max(0) as u64)
+            .unwrap_or(DEFAULT_DISCOVERY_BLOCKS);
+        Self::new(chain_manager, assets, price_feeds, discovery_blocks)
+    }
+
+    /// Balances of a wallet on `chain_ids`, every supported chain when `None`
+    pub async fn scan(&self, address: Address, chain_ids: Option<Vec<u64>>) -> WalletTokenBalances {
+        let chain_ids = match chain_ids {
+            Some(chain_ids) => chain_ids,
+            None => self.chain_manager.chain_ids().await,
+        };
+        let scans = join_all(chain_ids.iter().map(|chain_id| self.scan_chain(*chain_id, address))).await;
+
+        let mut balances = Vec::new();
+        let mut errors = Vec::new();
+        for (chain_id, scan) in chain_ids.iter().zip(scans) {
+            match scan {
+                Ok(chain_balances) => balances.extend(chain_balances),
+                Err(e) => {
+                    warn!("Token scan of {:?} on chain {} failed: {}", address, chain_id, e);
+                    errors.push(ChainScanError { chain_id: *chain_id, error: e.to_string() });
+                }
+            }
+        }
+        balances.sort_by(|a, b| b.value_usd.unwrap_or(-1.0).total_cmp(&a.value_usd.unwrap_or(-1.0)));
+
+        WalletTokenBalances {
+            address,
+            ens_name: None,
+            chain_ids,
+            total_value_usd: balances.iter().filter_map(|balance| balance.value_usd).fold(0.0, |total, value| total + value),
+            balances,
+            errors,
+        }
+    }
+
+    async fn scan_chain(&self, chain_id: u64, address: Address) -> Result<Vec<TokenBalance>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain.provider.clone());
+
+        let curated: BTreeSet<Address> = self.assets.assets().iter()
+            .flat_map(|asset| &asset.representations)
+            .filter(|representation| representation.chain_id == chain_id && !representation.address.is_zero())
+            .map(|representation| representation.address)
+            .collect();
+        let discovered = match self.discover(chain_id, &provider, address).await {
+            Ok(discovered) => discovered,
+            Err(e) => {
+                warn!("Token discovery for {:?} on chain {} failed: {}", address, chain_id, e);
+                BTreeSet::new()
+            }
+        };
+        let tokens: Vec<Address> = curated.union(&discovered).copied().collect();
+
+        let erc20 = Contract::new(Address::zero(), erc20_abi()?, provider.clone());
+        let calls = tokens.iter()
+            .map(|token| erc20.at(*token).method::<_, U256>("balanceOf", address))
+            .collect::<Result<Vec<_>, _>>()?;
+        let held: Vec<(Address, U256)> = tokens.iter()
+            .zip(multicall(&provider, chain_id, calls).await?)
+            .filter_map(|(token, balance)| Some((*token, balance?.into_uint()?)))
+            .filter(|(_, balance)| !balance.is_zero())
+            .collect();
+
+        // Symbols and decimals of tokens outside the curated list
+        let unknown: Vec<Address> = held.iter()
+            .map(|(token, _)| *token)
+            .filter(|token| self.assets.resolve(chain_id, *token).is_none())
+            .collect();
+        let calls = unknown.iter()
+            .map(|token| erc20.at(*token).method::<_, String>("symbol", ()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let symbols = multicall(&provider, chain_id, calls).await?;
+        let calls = unknown.iter()
+            .map(|token| erc20.at(*token).method::<_, u8>("decimals", ()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let decimals = multicall(&provider, chain_id, calls).await?;
+        let metadata: HashMap<Address, (String, u8)> = unknown.iter()
+            .zip(symbols.into_iter().zip(decimals))
+            .map(|(token, (symbol, decimals))| {
+                let symbol = symbol.and_then(Token::into_string).unwrap_or_else(|| format!("{:?}", token));
+                let decimals = decimals.and_then(Token::into_uint).map(|decimals| decimals.low_u32().min(36) as u8);
+                (*token, (symbol, decimals.unwrap_or(18)))
+            })
+            .collect();
+
+        let mut balances: Vec<TokenBalance> = held.into_iter()
+            .map(|(token, balance)| {
+                let (symbol, decimals) = match self.assets.resolve(chain_id, token) {
+                    Some((_, representation)) => (representation.symbol.clone(), representation.decimals),
+                    None => metadata.get(&token).cloned().unwrap_or_else(|| (format!("{:?}", token), 18)),
+                };
+                TokenBalance {
+                    chain_id,
+                    token: Some(token),
+                    symbol,
+                    amount: TokenAmount::new(balance, decimals),
+                    price_usd: None,
+                    value_usd: None,
+                    discovered: !curated.contains(&token),
+                }
+            })
+            .collect();
+        let native = chain.provider.get_balance(address, None).await?;
+        if !native.is_zero() {
+            balances.push(TokenBalance {
+                chain_id,
+                token: None,
+                symbol: chain.config.native_token.clone(),
+                amount: TokenAmount::new(native, 18),
+                price_usd: None,
+                value_usd: None,
+                discovered: false,
+            });
+        }
+
+        let priced: Vec<Address> = balances.iter()
+            .map(|balance| pricing_address(chain_id, balance.token.unwrap_or_else(Address::zero)))
+            .collect();
+        let prices = self.price_feeds.get_prices(chain_id, &priced).await.unwrap_or_else(|e| {
+            warn!("Could not price the tokens of {:?} on chain {}: {}", address, chain_id, e);
+            Default::default()
+        });
+        for (balance, token) in balances.iter_mut().zip(priced) {
+            balance.price_usd = prices.get(&token).map(|price| price.price_usd);
+            balance.value_usd = balance.price_usd.map(|price| price * units(balance.amount.raw, balance.amount.decimals));
+        }
+        Ok(balances)
+    }
+
+    /// Tokens transferred to the wallet, searching only the blocks added since its last scan
+    async fn discover(&self, chain_id: u64, provider: &Provider<PooledHttp>, address: Address) -> Result<BTreeSet<Address>> {
+        if self.discovery_blocks == 0 {
+            return Ok(BTreeSet::new());
+        }
+        let known = self.discovered.read().await.get(&(chain_id, address)).cloned().unwrap_or_default();
+        let latest = provider.get_block_number().await?.as_u64();
+        let from = match known.scanned_to {
+            Some(scanned_to) => scanned_to + 1,
+            None => latest.saturating_sub(self.discovery_blocks.saturating_sub(1)),
+        };
+        if from > latest {
+            return Ok(known.tokens);
+        }
+
+        let mut tokens = known.tokens;
+        // ERC-721 transfers index the token id as well, ERC-20 ones carry the amount as data
+        tokens.extend(
+            incoming_transfers(provider, address, from, latest).await?.iter()
+                .filter(|log| log.topics.len() == 3)
+                .map(|log| log.address),
+        );
+        debug!("{} tokens discovered for {:?} on chain {} up to block {}", tokens.len(), address, chain_id, latest);
+
+        self.discovered.write().await.insert(
+            (chain_id, address),
+            DiscoveredTokens { tokens: tokens.clone(), scanned_to: Some(latest) },
+        );
+        Ok(tokens)
+    }
+}
+
+/// ERC-20 and ERC-721 Transfer logs to `address` from `from` to `to`, read in ranges providers accept
+pub(crate) async fn incoming_transfers(provider: &Provider<PooledHttp>, address: Address, from: u64, to: u64) -> Result<Vec<Log>> {
+    let filter = Filter::new()
+        .topic0(H256::from(keccak256("Transfer(address,address,uint256)")))
+        .topic2(H256::from(address));
+    logs_in_ranges(provider, filter, from, to).await
+}
+
+/// Logs matching `filter` from `from` to `to`, read in ranges providers accept
+pub(crate) async fn logs_in_ranges(provider: &Provider<PooledHttp>, filter: Filter, mut from: u64, to: u64) -> Result<Vec<Log>> {
+    let mut logs = Vec::new();
+    while from <= to {
+        let chunk_to = (from + LOG_CHUNK_BLOCKS - 1).min(to);
+        let filter = filter.clone()
+            .from_block(BlockNumber::Number(from.into()))
+            .to_block(BlockNumber::Number(chunk_to.into()));
+        logs.extend(provider.get_logs(&filter).await?);
+        from = chunk_to + 1;
+    }
+    Ok(logs)
+}
+
+fn erc20_abi() -> Result<ethers::abi::Abi> {
+    Ok(parse_abi(&[
+        "function balanceOf(address account) view returns (uint256)",
+        "function symbol() view returns (string)",
+        "function decimals() view returns (uint8)",
+    ])?)
+}
+
+fn units(amount: U256, decimals: u8) -> f64 {
+    amount.to_string().parse::<f64>().unwrap_or_default() / 10f64.powi(decimals as i32)
+}
@@ -0,0 +1,641 @@
+use axum::{
+    extract::{FromRequestParts, Path, State},
+    http::request::Parts,
+    response::Json,
+    routing::{delete, get, post},
+    Router,
+};
+use ethers::types::{Address, U256};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::api::{error::ApiError, rate_limit::RateLimitStats, ApiState};
+use crate::chains::fork::{AnvilFork, ForkInfo};
+use crate::chains::indexer::IndexedContract;
+use crate::chains::tokens::CustomTokenRequest;
+use crate::contracts::probes::DeploymentCheck;
+use crate::jobs::backfill::{BackfillCheckpoint, BackfillRequest};
+use crate::jobs::{JobRecord, JobTask};
+use crate::security::{ChannelStatus, CircuitBreakerStatus};
+
+/// Caches that can be flushed through the admin API
+const FLUSHABLE_CACHES: [&str; 6] = ["prices", "dex_pools", "lending", "venue_mev", "abis", "token_logos"];
+
+/// Operator identity extracted from a valid admin token
+pub struct AdminGuard {
+    pub actor: String,
+}
+
+impl FromRequestParts<Arc<ApiState>> for AdminGuard {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<ApiState>) -> Result<Self, Self::Rejection> {
+        let expected = state.admin_token.as_deref()
+            .ok_or_else(|| ApiError::Forbidden("Admin API is disabled, admin_api_token is not set".to_string()))?;
+        let provided = parts
+            .headers
+            .get("x-admin-token")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| ApiError::Unauthorized("Missing x-admin-token".to_string()))?;
+
+        if !constant_time_eq(expected.as_bytes(), provided.as_bytes()) {
+            warn!("Rejected admin request with invalid token");
+            return Err(ApiError::Unauthorized("Invalid admin token".to_string()));
+        }
+
+        let actor = parts
+            .headers
+            .get("x-admin-actor")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("admin")
+            .to_string();
+
+        Ok(Self { actor })
+    }
+}
+
+/// Rotate RPC endpoint request
+#[derive(Deserialize)]
+pub struct RotateRpcRequest {
+    pub rpc_url: String,
+}
+
+/// Flush caches request
+#[derive(Deserialize)]
+pub struct FlushCachesRequest {
+    pub caches: Vec<String>,
+}
+
+/// Invalidate one cached entry request, e.g. once a known trade moved a market
+#[derive(Deserialize)]
+#[serde(tag = "cache", rename_all = "snake_case")]
+pub enum InvalidateCacheRequest {
+    /// An Aave reserve or Compound cToken
+    Lending { chain_id: u64, address: Address },
+    /// A Uniswap V3 pool
+    DexPools { chain_id: u64, token0: Address, token1: Address, fee: u32 },
+}
+
+/// Fund a fork account request
+#[derive(Deserialize)]
+pub struct FundForkAccountRequest {
+    pub address: Address,
+    /// Native balance to set, in wei
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount: U256,
+}
+
+/// Impersonate a fork account request
+#[derive(Deserialize)]
+pub struct ImpersonateForkAccountRequest {
+    pub address: Address,
+}
+
+/// Mine fork blocks request
+#[derive(Deserialize)]
+pub struct MineForkBlocksRequest {
+    /// Blocks to mine, 1 when omitted
+    #[serde(default = "default_mined_blocks")]
+    pub blocks: u64,
+}
+
+fn default_mined_blocks() -> u64 {
+    1
+}
+
+/// Most blocks one request mines
+const MAX_MINED_BLOCKS: u64 = 100_000;
+
+/// Fork snapshot response
+#[derive(Serialize)]
+pub struct ForkSnapshotResponse {
+    pub snapshot_id: U256,
+}
+
+/// Admin action response
+#[derive(Serialize)]
+pub struct AdminActionResponse {
+    pub action: String,
+    pub success: bool,
+    pub details: String,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/chains/{chain_id}/rpc", post(rotate_rpc_endpoint))
+        .route("/chains/{chain_id}/pause", post(pause_chain))
+        .route("/chains/{chain_id}/resume", post(resume_chain))
+        .route("/caches/flush", post(flush_caches))
+        .route("/caches/invalidate", post(invalidate_cache))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/{id}", get(get_job))
+        .route("/jobs/{id}/rerun", post(rerun_job))
+        .route("/backfills", get(list_backfills).post(start_backfill))
+        .route("/indexer/contracts", post(register_indexed_contract))
+        .route("/indexer/contracts/{chain_id}/{address}", delete(unregister_indexed_contract))
+        .route("/tokens", post(add_custom_token))
+        .route("/tokens/{chain_id}/{address}", delete(remove_custom_token))
+        .route("/reconcile", post(trigger_reconciliation))
+        .route("/deployments/probe", post(probe_deployments))
+        .route("/rate-limits", get(get_rate_limits))
+        .route("/circuit-breakers", get(get_circuit_breakers))
+        .route("/circuit-breakers/reset", post(reset_circuit_breakers))
+        .route("/notifications", get(get_notification_channels))
+        .route("/fork", get(get_fork_info))
+        .route("/fork/fund", post(fund_fork_account))
+        .route("/fork/impersonate", post(impersonate_fork_account))
+        .route("/fork/mine", post(mine_fork_blocks))
+        .route("/fork/snapshot", post(snapshot_fork))
+        .route("/fork/revert/{snapshot_id}", post(revert_fork))
+}
+
+/// Rotate the RPC endpoint of a chain
+async fn rotate_rpc_endpoint(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+    Json(request): Json<RotateRpcRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    // Only the host is audit-logged, RPC URLs often embed API keys
+    let host = reqwest::Url::parse(&request.rpc_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid RPC URL for chain {}", chain_id)))?;
+
+    // Connection errors repeat the URL they failed on, so neither the audit log nor the response
+    // carries them
+    let result = state.chain_manager.rotate_rpc_endpoint(chain_id, request.rpc_url).await;
+    let details = match &result {
+        Ok(()) => format!("chain {} now using {}", chain_id, host),
+        Err(_) => format!("chain {} rotation to {} failed", chain_id, host),
+    };
+
+    audit(&state, &admin, "rotate_rpc", details.clone()).await?;
+    if result.is_err() {
+        return Err(ApiError::Upstream(format!("Chain {} could not be rotated to {}", chain_id, host)));
+    }
+
+    Ok(Json(AdminActionResponse {
+        action: "rotate_rpc".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Pause a chain
+async fn pause_chain(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    state.chain_manager.pause_chain(chain_id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    let details = format!("chain {} paused", chain_id);
+    audit(&state, &admin, "pause_chain", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "pause_chain".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Resume a paused chain
+async fn resume_chain(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    let resumed = state.chain_manager.resume_chain(chain_id).await;
+
+    let details = if resumed {
+        format!("chain {} resumed", chain_id)
+    } else {
+        format!("chain {} was not paused", chain_id)
+    };
+    audit(&state, &admin, "resume_chain", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "resume_chain".to_string(),
+        success: resumed,
+        details,
+    }))
+}
+
+/// Flush the named caches
+async fn flush_caches(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<FlushCachesRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    if request.caches.is_empty()
+        || request.caches.iter().any(|cache| !FLUSHABLE_CACHES.contains(&cache.as_str()))
+    {
+        return Err(ApiError::BadRequest(format!("Caches to flush must be among {}", FLUSHABLE_CACHES.join(", "))));
+    }
+
+    for cache in &request.caches {
+        flush_cache(&state, cache).await;
+    }
+
+    let details = format!("flushed {}", request.caches.join(", "));
+    audit(&state, &admin, "flush_caches", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "flush_caches".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Drop one cached entry, on every replica when the cache is shared
+async fn invalidate_cache(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<InvalidateCacheRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    let details = match request {
+        InvalidateCacheRequest::Lending { chain_id, address } => {
+            state.defi_manager.aave().invalidate_reserve(chain_id, address).await;
+            state.defi_manager.compound().invalidate_ctoken(chain_id, address).await;
+            format!("invalidated lending market {:?} on chain {}", address, chain_id)
+        }
+        InvalidateCacheRequest::DexPools { chain_id, token0, token1, fee } => {
+            state.dex_manager.uniswap().invalidate_pool(chain_id, token0, token1, fee).await;
+            format!("invalidated pool {:?}/{:?} ({}) on chain {}", token0, token1, fee, chain_id)
+        }
+    };
+    audit(&state, &admin, "invalidate_cache", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "invalidate_cache".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Probe the protocol addresses again, e.g. after an upgrade or an RPC outage
+async fn probe_deployments(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<Vec<DeploymentCheck>>, ApiError> {
+    let checks = state.deployments.probe_all().await;
+    let misconfigured = checks.iter().filter(|check| check.status.is_misconfigured()).count();
+    audit(&state, &admin, "probe_deployments", format!("{} of {} contracts misconfigured", misconfigured, checks.len())).await?;
+    Ok(Json(checks))
+}
+
+/// List background jobs
+async fn list_jobs(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<Vec<JobRecord>>, ApiError> {
+    Ok(Json(state.jobs.list_jobs().await))
+}
+
+/// Get a background job with its progress
+async fn get_job(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobRecord>, ApiError> {
+    state.jobs.get_job(&id).await.map(Json).ok_or_else(|| ApiError::NotFound(format!("Job {} not found", id)))
+}
+
+/// Re-run a failed background job
+async fn rerun_job(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobRecord>, ApiError> {
+    let job = state.jobs.rerun(&id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Conflict))?;
+
+    audit(&state, &admin, "rerun_job", format!("job {} ({}) attempt {}", job.name, job.id, job.attempts)).await?;
+
+    Ok(Json(job))
+}
+
+/// Start a full reconciliation in the background
+async fn trigger_reconciliation(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<JobRecord>, ApiError> {
+    let task_state = state.clone();
+    let task: JobTask = Arc::new(move |_| run_reconciliation(task_state.clone()).boxed());
+
+    let job = state.jobs.submit("full_reconciliation", task).await;
+    audit(&state, &admin, "reconcile", format!("started job {}", job.id)).await?;
+
+    Ok(Json(job))
+}
+
+/// Checkpoints of every backfill
+async fn list_backfills(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<Vec<BackfillCheckpoint>>, ApiError> {
+    Ok(Json(state.backfills.list().await))
+}
+
+/// Start or resume a historical block range backfill as a background job
+async fn start_backfill(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<BackfillRequest>,
+) -> Result<Json<JobRecord>, ApiError> {
+    let job = state.backfills.submit(request).await
+        .map_err(|e| {
+            warn!("Backfill rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    audit(&state, &admin, "backfill", format!("started job {} ({})", job.id, job.name)).await?;
+
+    Ok(Json(job))
+}
+
+/// Index the events of a contract from the next poll on
+async fn register_indexed_contract(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(contract): Json<IndexedContract>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    let changed = state.indexer.register(contract.clone()).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+    let details = format!("{} {:?} on chain {}", contract.kind.as_str(), contract.address, contract.chain_id);
+    if changed {
+        audit(&state, &admin, "index_contract", details.clone()).await?;
+    }
+
+    Ok(Json(AdminActionResponse {
+        action: "index_contract".to_string(),
+        success: true,
+        details: if changed { details } else { format!("{} is already indexed", details) },
+    }))
+}
+
+/// Stop indexing a contract, keeping the events stored so far
+async fn unregister_indexed_contract(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, address)): Path<(u64, Address)>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    if !state.indexer.unregister(chain_id, address).await {
+        return Err(ApiError::NotFound(format!("{:?} is not indexed on chain {}", address, chain_id)));
+    }
+    let details = format!("{:?} on chain {}", address, chain_id);
+    audit(&state, &admin, "unindex_contract", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "unindex_contract".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Add a token to the registry or override a listed token's metadata
+async fn add_custom_token(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<CustomTokenRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    let token = state.tokens.add_custom(request).await.map_err(|e| {
+        warn!("Custom token rejected: {}", e);
+        ApiError::from_error(e, ApiError::BadRequest)
+    })?;
+    let details = format!("{} {:?} on chain {} ({} decimals)", token.symbol, token.address, token.chain_id, token.decimals);
+    audit(&state, &admin, "add_token", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "add_token".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Remove a custom token, restoring its listed metadata if any
+async fn remove_custom_token(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, address)): Path<(u64, Address)>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    if !state.tokens.remove_custom(chain_id, address).await {
+        return Err(ApiError::NotFound(format!("{:?} is not a custom token on chain {}", address, chain_id)));
+    }
+    let details = format!("{:?} on chain {}", address, chain_id);
+    audit(&state, &admin, "remove_token", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "remove_token".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Rate limits in force and the requests they throttled per route group
+async fn get_rate_limits(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Json<RateLimitStats> {
+    Json(state.rate_limiter.stats().await)
+}
+
+/// Get whether trading is halted and each circuit breaker's reading
+async fn get_circuit_breakers(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Json<CircuitBreakerStatus> {
+    Json(state.circuit_breakers.status().await)
+}
+
+/// Lift a trading halt once the cause was looked into
+async fn reset_circuit_breakers(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    let details = match state.circuit_breakers.reset().await {
+        Some(halt) => format!("trading resumed after the {} circuit breaker halt: {}", halt.breaker, halt.reason),
+        None => "trading was not halted".to_string(),
+    };
+    audit(&state, &admin, "reset_circuit_breakers", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "reset_circuit_breakers".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Security notification channels with their severity filter and delivery counts
+async fn get_notification_channels(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Json<Vec<ChannelStatus>> {
+    Json(state.security.advanced.notifications().statuses().await)
+}
+
+/// Get the fork node the API runs against
+async fn get_fork_info(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<ForkInfo>, ApiError> {
+    Ok(Json(fork_node(&state)?.info().await))
+}
+
+/// Set the native balance of an account on the fork
+async fn fund_fork_account(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<FundForkAccountRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    fork_node(&state)?.set_balance(request.address, request.amount).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    let details = format!("set balance of {:?} to {} wei", request.address, request.amount);
+    audit(&state, &admin, "fork_fund", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "fork_fund".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Let the API send transactions from a fork account without its key
+async fn impersonate_fork_account(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<ImpersonateForkAccountRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    fork_node(&state)?.impersonate(request.address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    let details = format!("impersonating {:?}", request.address);
+    audit(&state, &admin, "fork_impersonate", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "fork_impersonate".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Mine blocks on the fork, moving time-dependent state such as interest forward
+async fn mine_fork_blocks(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<MineForkBlocksRequest>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    if request.blocks == 0 || request.blocks > MAX_MINED_BLOCKS {
+        return Err(ApiError::BadRequest(format!("blocks must be between 1 and {}", MAX_MINED_BLOCKS)));
+    }
+    fork_node(&state)?.mine(request.blocks).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    let details = format!("mined {} blocks", request.blocks);
+    audit(&state, &admin, "fork_mine", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "fork_mine".to_string(),
+        success: true,
+        details,
+    }))
+}
+
+/// Snapshot the fork state
+async fn snapshot_fork(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<ForkSnapshotResponse>, ApiError> {
+    let snapshot_id = fork_node(&state)?.snapshot().await
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    audit(&state, &admin, "fork_snapshot", format!("snapshot {}", snapshot_id)).await?;
+
+    Ok(Json(ForkSnapshotResponse { snapshot_id }))
+}
+
+/// Revert the fork to a snapshot
+async fn revert_fork(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(snapshot_id): Path<u64>,
+) -> Result<Json<AdminActionResponse>, ApiError> {
+    let reverted = fork_node(&state)?.revert(U256::from(snapshot_id)).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    let details = if reverted {
+        format!("reverted to snapshot {}", snapshot_id)
+    } else {
+        format!("snapshot {} not found", snapshot_id)
+    };
+    audit(&state, &admin, "fork_revert", details.clone()).await?;
+
+    Ok(Json(AdminActionResponse {
+        action: "fork_revert".to_string(),
+        success: reverted,
+        details,
+    }))
+}
+
+fn fork_node(state: &ApiState) -> Result<&Arc<AnvilFork>, ApiError> {
+    // Fork controls only exist when the API runs against a fork
+    state.chain_manager.fork().ok_or_else(|| ApiError::Conflict("The API is not running against a fork".to_string()))
+}
+
+/// Drop every cache and re-verify chain connectivity
+async fn run_reconciliation(state: Arc<ApiState>) -> anyhow::Result<()> {
+    for cache in FLUSHABLE_CACHES {
+        flush_cache(&state, cache).await;
+    }
+
+    let paused = state.chain_manager.get_paused_chains().await;
+    let unhealthy: Vec<String> = state.chain_manager
+        .health_check()
+        .await
+        .into_iter()
+        .filter(|health| !health.rpc_healthy && !paused.contains(&health.chain_id))
+        .map(|health| health.name)
+        .collect();
+
+    if !unhealthy.is_empty() {
+        return Err(anyhow::anyhow!("Unhealthy chains after reconciliation: {}", unhealthy.join(", ")));
+    }
+
+    Ok(())
+}
+
+async fn flush_cache(state: &ApiState, cache: &str) {
+    match cache {
+        "prices" => state.analytics.price_feeds.clear_cache().await,
+        "dex_pools" => {
+            state.dex_manager.uniswap().clear_cache().await;
+            state.dex_manager.sushiswap().clear_cache().await;
+            state.dex_manager.uniswap_v2().clear_cache().await;
+        }
+        "lending" => {
+            state.defi_manager.aave().clear_cache().await;
+            state.defi_manager.compound().clear_cache().await;
+        }
+        "venue_mev" => state.dex_manager.aggregator().reset_venue_mev_stats().await,
+        "abis" => state.contracts.clear_abi_cache().await,
+        "token_logos" => state.tokens.clear_logo_cache().await,
+        _ => {}
+    }
+}
+
+async fn audit(state: &ApiState, admin: &AdminGuard, action: &str, details: String) -> Result<(), ApiError> {
+    state.security.log_admin_action(&admin.actor, action, details).await
+        .map_err(ApiError::internal)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,43 @@
+use axum::{
+    extract::Query,
+    response::Json,
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::analytics::impermanent_loss::{impermanent_loss, ImpermanentLoss, PoolModel};
+
+/// Impermanent loss query parameters, prices in the quote token per base token
+#[derive(Deserialize)]
+pub struct ImpermanentLossQuery {
+    pub protocol: PoolModel,
+    pub entry_price: f64,
+    pub current_price: f64,
+    /// Range bounds of Uniswap V3 positions
+    pub price_lower: Option<f64>,
+    pub price_upper: Option<f64>,
+    /// Value of the deposit at entry, to report the loss in the quote token
+    pub deposit_usd: Option<f64>,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/impermanent-loss", get(get_impermanent_loss))
+}
+
+/// Impermanent loss of an LP position and its projection over price moves from the entry price
+async fn get_impermanent_loss(
+    Query(query): Query<ImpermanentLossQuery>,
+) -> Result<Json<ImpermanentLoss>, ApiError> {
+    let range = query.price_lower.zip(query.price_upper);
+    if range.is_none() && (query.price_lower.is_some() || query.price_upper.is_some()) {
+        return Err(ApiError::BadRequest("price_lower and price_upper must be given together".to_string()));
+    }
+
+    impermanent_loss(query.protocol, query.entry_price, query.current_price, range, query.deposit_usd)
+        .map(Json)
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))
+}
@@ -0,0 +1,324 @@
+// API keys with role-based scopes, enforced on every route group but health, docs, auth and admin, and
+// the circuit breaker halt of trading routes
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{MatchedPath, Path, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{Json, Response},
+    routing::{delete, get},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use ethers::utils::hex;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::api::{admin::AdminGuard, error::ApiError, ApiState};
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `api_keys_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/api_keys.json";
+/// Prefix making keys recognizable in logs and secret scanners
+const KEY_PREFIX: &str = "bd_";
+const KEY_BYTES: usize = 32;
+/// Characters of a key kept to identify it in listings
+const DISPLAY_PREFIX_LENGTH: usize = 11;
+
+/// POST routes that only read or analyze, available to read-only keys
+const READ_ONLY_POSTS: [&str; 5] = [
+    "/simulate",
+    "/security/analyze",
+    "/dex/executions/analyze",
+    "/defi/collateral/optimize",
+    "/user-operations/estimate",
+];
+
+/// What a request is about to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Read,
+    /// Building, signing, submitting or cancelling transactions and orders, or changing settings
+    Trade,
+}
+
+impl Scope {
+    /// Scope of a route, reads unless it changes state
+    fn of(method: &Method, path: &str) -> Self {
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            return Scope::Read;
+        }
+        if *method == Method::POST && READ_ONLY_POSTS.iter().any(|read_only| path.ends_with(read_only)) {
+            return Scope::Read;
+        }
+        Scope::Trade
+    }
+}
+
+/// Role an API key is issued with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiRole {
+    /// Portfolio, market and risk data only
+    ReadOnly,
+    /// Everything a read-only key can do, plus trading and position changes
+    Trading,
+}
+
+impl ApiRole {
+    pub fn allows(self, scope: Scope) -> bool {
+        match scope {
+            Scope::Read => true,
+            Scope::Trade => self == ApiRole::Trading,
+        }
+    }
+}
+
+/// An issued key, only its hash is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredApiKey {
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+    key_hash: String,
+}
+
+/// API key as listed, without the secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub role: ApiRole,
+    /// First characters of the key, to tell keys apart
+    pub prefix: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Issued API keys, persisted so revocations survive a restart
+pub struct ApiKeyStore {
+    /// Whether routes require a key, they are open when disabled
+    enabled: bool,
+    path: Option<PathBuf>,
+    /// Keys by the hash of their secret
+    keys: RwLock<HashMap<String, StoredApiKey>>,
+}
+
+impl ApiKeyStore {
+    pub async fn new(enabled: bool, path: Option<PathBuf>) -> Result<Self> {
+        if !enabled {
+            warn!("api_auth_enabled is not set, API routes are open to anyone without a key");
+        }
+        let keys: Vec<StoredApiKey> = match &path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if !keys.is_empty() {
+            info!("Loaded {} API keys", keys.len());
+        }
+        Ok(Self {
+            enabled,
+            path,
+            keys: RwLock::new(keys.into_iter().map(|key| (key.key_hash.clone(), key)).collect()),
+        })
+    }
+
+    /// Keys stored at `api_keys_store_path` (empty keeps them in memory), enforced when `api_auth_enabled`
+    pub async fn from_config(config: &config::Config) -> Result<Self> {
+        let enabled = config.get_bool("api_auth_enabled").unwrap_or(false);
+        let path = config
+            .get_string("api_keys_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        Self::new(enabled, (!path.is_empty()).then(|| PathBuf::from(path))).await
+    }
+
+    /// Issue a key, returning the secret that is never shown again
+    pub async fn create(&self, name: String, role: ApiRole, created_by: &str) -> Result<(String, ApiKeyInfo)> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("API key name is empty"));
+        }
+        let mut secret = [0u8; KEY_BYTES];
+        SystemRandom::new().fill(&mut secret).map_err(|_| anyhow!("System randomness unavailable"))?;
+        let key = format!("{}{}", KEY_PREFIX, hex::encode(secret));
+
+        let info = ApiKeyInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            role,
+            prefix: key[..DISPLAY_PREFIX_LENGTH].to_string(),
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+        };
+        let mut keys = self.keys.write().await;
+        keys.insert(hash_key(&key), StoredApiKey { info: info.clone(), key_hash: hash_key(&key) });
+        self.persist(&keys).await?;
+        Ok((key, info))
+    }
+
+    /// Revoke a key by id, false when it does not exist
+    pub async fn revoke(&self, id: &str) -> Result<bool> {
+        let mut keys = self.keys.write().await;
+        let before = keys.len();
+        keys.retain(|_, key| key.info.id != id);
+        if keys.len() == before {
+            return Ok(false);
+        }
+        self.persist(&keys).await?;
+        Ok(true)
+    }
+
+    /// Issued keys, oldest first
+    pub async fn list(&self) -> Vec<ApiKeyInfo> {
+        let mut keys: Vec<ApiKeyInfo> = self.keys.read().await.values().map(|key| key.info.clone()).collect();
+        keys.sort_by_key(|key| key.created_at);
+        keys
+    }
+
+    /// Key presented as `Authorization: Bearer <key>` or `x-api-key`
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Option<ApiKeyInfo> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let key = header("x-api-key")
+            .or_else(|| header("authorization").and_then(|value| value.strip_prefix("Bearer ")))?;
+        self.keys.read().await.get(&hash_key(key.trim())).map(|key| key.info.clone())
+    }
+
+    async fn persist(&self, keys: &HashMap<String, StoredApiKey>) -> Result<()> {
+        match &self.path {
+            Some(path) => write_store(path, &keys.values().collect::<Vec<_>>()).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Middleware rejecting requests without a key (401) or whose key's role lacks the route's scope (403)
+pub async fn require_scope(
+    State(state): State<Arc<ApiState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if !state.api_keys.enabled {
+        return Ok(next.run(request).await);
+    }
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let scope = Scope::of(request.method(), &path);
+
+    let key = state.api_keys.authenticate(request.headers()).await.ok_or_else(|| {
+        debug!("Rejected {} {} without a valid API key", request.method(), path);
+        ApiError::Unauthorized("A valid API key is required".to_string())
+    })?;
+    if !key.role.allows(scope) {
+        warn!("API key {} ({:?}) is not allowed to {} {}", key.name, key.role, request.method(), path);
+        return Err(ApiError::Forbidden(format!("API key {} is read-only", key.name)));
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Refuse trading requests while a circuit breaker halts trading; reads and cancellations stay open
+pub async fn halt_trading(
+    State(state): State<Arc<ApiState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if *request.method() == Method::DELETE {
+        return Ok(next.run(request).await);
+    }
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    if Scope::of(request.method(), &path) == Scope::Trade {
+        state.circuit_breakers.ensure_trading().await
+            .map_err(|e| ApiError::from_error(e, ApiError::Locked))?;
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Issue API key request
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub role: ApiRole,
+}
+
+/// Issued API key, the only response carrying its secret
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/key", get(get_current_key))
+        .route("/keys", get(list_keys).post(create_key))
+        .route("/keys/{id}", delete(revoke_key))
+}
+
+/// The key a request is made with, to check its role
+async fn get_current_key(
+    State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
+) -> Result<Json<ApiKeyInfo>, ApiError> {
+    state.api_keys.authenticate(&headers).await
+        .map(Json)
+        .ok_or_else(|| ApiError::Unauthorized("No valid API key presented".to_string()))
+}
+
+async fn list_keys(
+    State(state): State<Arc<ApiState>>,
+    _admin: AdminGuard,
+) -> Json<Vec<ApiKeyInfo>> {
+    Json(state.api_keys.list().await)
+}
+
+async fn create_key(
+    State(state): State<Arc<ApiState>>,
+    admin: AdminGuard,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreatedApiKey>, ApiError> {
+    let (key, info) = state.api_keys.create(request.name, request.role, &admin.actor).await
+        .map_err(|e| {
+            warn!("Issuing an API key failed: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+    state.security.log_admin_action(&admin.actor, "create_api_key", format!("{} ({}, {:?})", info.name, info.id, info.role)).await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(CreatedApiKey { key, info }))
+}
+
+async fn revoke_key(
+    State(state): State<Arc<ApiState>>,
+    admin: AdminGuard,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let revoked = state.api_keys.revoke(&id).await
+        .map_err(|e| {
+            warn!("Revoking API key {} failed: {}", id, e);
+            ApiError::from_error(e, ApiError::Internal)
+        })?;
+    if !revoked {
+        return Err(ApiError::NotFound(format!("API key {} not found", id)));
+    }
+    state.security.log_admin_action(&admin.actor, "revoke_api_key", id).await
+        .map_err(ApiError::internal)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,18 +1,27 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    http::header,
+    response::{IntoResponse, Json},
     routing::{get, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
 use ethers::{
     providers::Middleware,
-    types::{Address, Block, Transaction, H256, U256},
+    types::{Address, Block, Bytes, Transaction, H256, U256},
 };
 
-use crate::api::ApiState;
+use crate::api::{ens, error::ApiError, replay::SignedJson, tokens, ApiState};
+use crate::chains::assets::{AssetEquivalent, CanonicalAsset};
+use crate::chains::ens::AddressOrName;
+use crate::chains::gas_optimizer::GasHourProfile;
+use crate::chains::indexer::{IndexedContract, IndexedEvent, IndexedEventQuery, IndexerChainStatus};
+use crate::chains::reorg::{ChainReorg, ReorgChainStatus};
+use crate::chains::tokens::{TokenListStatus, TokenMetadata};
+use crate::chains::tx_broadcaster::TrackedTransaction;
 
 /// Chain switch request
 #[derive(Deserialize)]
@@ -20,6 +29,28 @@ pub struct ChainSwitchRequest {
     pub chain_id: u64,
 }
 
+/// Reorg tracking per chain and the reorganizations detected recently
+#[derive(Serialize)]
+pub struct ReorgReport {
+    pub chains: Vec<ReorgChainStatus>,
+    /// Newest first
+    pub recent: Vec<ChainReorg>,
+}
+
+/// An ENS name with its address, or an address with its primary name
+#[derive(Serialize)]
+pub struct EnsRecord {
+    pub address: Address,
+    pub name: Option<String>,
+}
+
+/// Token search; `search` matches symbols and names
+#[derive(Deserialize)]
+pub struct TokenQuery {
+    pub chain_id: Option<u64>,
+    pub search: Option<String>,
+}
+
 /// Block query parameters
 #[derive(Deserialize)]
 pub struct BlockQuery {
@@ -27,6 +58,31 @@ pub struct BlockQuery {
     pub block_hash: Option<H256>,
 }
 
+/// Signed transaction submission
+#[derive(Deserialize)]
+pub struct SendRawTransactionRequest {
+    pub raw_transaction: Bytes,
+}
+
+/// Transaction status query; waits for confirmations when `wait_secs` is set
+#[derive(Deserialize)]
+pub struct TransactionStatusQuery {
+    pub confirmations: Option<u64>,
+    pub wait_secs: Option<u64>,
+}
+
+/// Gas hour profile query; `user` selects whose time zone the hours are in
+#[derive(Deserialize)]
+pub struct GasHoursQuery {
+    pub user: Option<Address>,
+    pub days: Option<u64>,
+}
+
+/// Longest a status request may wait for confirmations
+const MAX_STATUS_WAIT_SECS: u64 = 60;
+const DEFAULT_GAS_PROFILE_DAYS: u64 = 7;
+const MAX_GAS_PROFILE_DAYS: u64 = 30;
+
 /// Chain info response
 #[derive(Serialize)]
 pub struct ChainInfoResponse {
@@ -73,19 +129,33 @@ pub struct NetworkStatsResponse {
 pub fn routes() -> Router<Arc<ApiState>> {
     Router::new()
         .route("/", get(list_supported_chains))
+        .route("/assets", get(list_assets))
+        .route("/indexer", get(get_indexer_status))
+        .route("/indexer/contracts", get(list_indexed_contracts))
+        .route("/indexer/events", get(list_indexed_events))
+        .route("/reorgs", get(get_reorgs))
+        .route("/ens/{name}", get(resolve_ens))
+        .route("/tokens", get(list_tokens))
+        .route("/tokens/lists", get(list_token_lists))
+        .route("/{chain_id}/tokens/{token}", get(get_token))
+        .route("/{chain_id}/tokens/{token}/logo", get(get_token_logo))
+        .route("/{chain_id}/assets/{token}", get(get_asset_equivalents))
         .route("/switch", post(switch_chain))
         .route("/{chain_id}", get(get_chain_info))
         .route("/{chain_id}/gas", get(get_gas_price))
+        .route("/{chain_id}/gas/hours", get(get_gas_hours))
         .route("/{chain_id}/stats", get(get_network_stats))
         .route("/{chain_id}/block", get(get_block))
         .route("/{chain_id}/transaction/{tx_hash}", get(get_transaction))
+        .route("/{chain_id}/transactions", post(send_raw_transaction))
+        .route("/{chain_id}/transactions/{tx_hash}/status", get(get_transaction_status))
         .route("/{chain_id}/balance/{address}", get(get_balance))
 }
 
 /// List all supported chains
 async fn list_supported_chains(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Json<Vec<ChainInfoResponse>>, StatusCode> {
+) -> Result<Json<Vec<ChainInfoResponse>>, ApiError> {
     // Return hardcoded supported chains info
     let chains = vec![
         ChainInfoResponse {
@@ -130,6 +200,34 @@ async fn list_supported_chains(
             gas_price: U256::from(100_000_000u64), // 0.1 Gwei
             is_connected: true,
         },
+        ChainInfoResponse {
+            chain_id: 56,
+            name: "BNB Smart Chain".to_string(),
+            rpc_url: "https://bsc-dataseed.bnbchain.org".to_string(),
+            block_explorer: "https://bscscan.com".to_string(),
+            native_currency: CurrencyInfo {
+                name: "BNB".to_string(),
+                symbol: "BNB".to_string(),
+                decimals: 18,
+            },
+            current_block: 40000000, // Would be fetched dynamically
+            gas_price: U256::from(1_000_000_000u64), // 1 Gwei
+            is_connected: true,
+        },
+        ChainInfoResponse {
+            chain_id: 43114,
+            name: "Avalanche C-Chain".to_string(),
+            rpc_url: "https://api.avax.network/ext/bc/C/rpc".to_string(),
+            block_explorer: "https://snowtrace.io".to_string(),
+            native_currency: CurrencyInfo {
+                name: "Avalanche".to_string(),
+                symbol: "AVAX".to_string(),
+                decimals: 18,
+            },
+            current_block: 45000000, // Would be fetched dynamically
+            gas_price: U256::from(25_000_000_000u64), // 25 nAVAX
+            is_connected: true,
+        },
     ];
     
     Ok(Json(chains))
@@ -139,11 +237,11 @@ async fn list_supported_chains(
 async fn switch_chain(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<ChainSwitchRequest>,
-) -> Result<Json<String>, StatusCode> {
+) -> Result<Json<String>, ApiError> {
     // In real implementation, would switch the chain
     // For now, just verify the chain exists
     let _provider_info = state.chain_manager.get_provider(request.chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     Ok(Json(format!("Switched to chain {}", request.chain_id)))
 }
@@ -152,22 +250,22 @@ async fn switch_chain(
 async fn get_chain_info(
     State(state): State<Arc<ApiState>>,
     Path(chain_id): Path<u64>,
-) -> Result<Json<ChainInfoResponse>, StatusCode> {
+) -> Result<Json<ChainInfoResponse>, ApiError> {
     // Get provider for the chain
     let provider_info = state.chain_manager.get_provider(chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     // Get current block number
     let block_number = provider_info.provider
         .get_block_number()
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?;
     
     // Get gas price
     let gas_price = provider_info.provider
         .get_gas_price()
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?;
     
     let chain_info = match chain_id {
         1 => ChainInfoResponse {
@@ -212,7 +310,35 @@ async fn get_chain_info(
             gas_price,
             is_connected: true,
         },
-        _ => return Err(StatusCode::NOT_FOUND),
+        56 => ChainInfoResponse {
+            chain_id: 56,
+            name: "BNB Smart Chain".to_string(),
+            rpc_url: "https://bsc-dataseed.bnbchain.org".to_string(),
+            block_explorer: "https://bscscan.com".to_string(),
+            native_currency: CurrencyInfo {
+                name: "BNB".to_string(),
+                symbol: "BNB".to_string(),
+                decimals: 18,
+            },
+            current_block: block_number.as_u64(),
+            gas_price,
+            is_connected: true,
+        },
+        43114 => ChainInfoResponse {
+            chain_id: 43114,
+            name: "Avalanche C-Chain".to_string(),
+            rpc_url: "https://api.avax.network/ext/bc/C/rpc".to_string(),
+            block_explorer: "https://snowtrace.io".to_string(),
+            native_currency: CurrencyInfo {
+                name: "Avalanche".to_string(),
+                symbol: "AVAX".to_string(),
+                decimals: 18,
+            },
+            current_block: block_number.as_u64(),
+            gas_price,
+            is_connected: true,
+        },
+        _ => return Err(ApiError::NotFound(format!("Chain {} is not supported", chain_id))),
     };
     
     Ok(Json(chain_info))
@@ -222,14 +348,14 @@ async fn get_chain_info(
 async fn get_gas_price(
     State(state): State<Arc<ApiState>>,
     Path(chain_id): Path<u64>,
-) -> Result<Json<GasPriceResponse>, StatusCode> {
+) -> Result<Json<GasPriceResponse>, ApiError> {
     let provider_info = state.chain_manager.get_provider(chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     let gas_price = provider_info.provider
         .get_gas_price()
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?;
     
     // Simulate fast and slow gas prices (would use gas station APIs in real implementation)
     let fast_gas_price = gas_price * 120 / 100; // 20% higher
@@ -244,25 +370,49 @@ async fn get_gas_price(
     }))
 }
 
+/// Average base fee by local hour, with the cheapest hour to send transactions that can wait
+async fn get_gas_hours(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+    Query(query): Query<GasHoursQuery>,
+) -> Result<Json<GasHourProfile>, ApiError> {
+    let days = query.days.unwrap_or(DEFAULT_GAS_PROFILE_DAYS);
+    if days == 0 || days > MAX_GAS_PROFILE_DAYS {
+        return Err(ApiError::BadRequest(format!("days must be between 1 and {}", MAX_GAS_PROFILE_DAYS)));
+    }
+    let settings = match query.user {
+        Some(user) => state.analytics.time_zones.get(user).await,
+        None => state.analytics.time_zones.default_settings(),
+    };
+
+    let profile = state.chain_manager.gas_hour_profile(chain_id, &settings, days).await
+        .map_err(|e| {
+            warn!("Failed to profile gas by hour on chain {}: {}", chain_id, e);
+            ApiError::from_error(e, ApiError::Upstream)
+        })?;
+
+    Ok(Json(profile))
+}
+
 /// Get network statistics
 async fn get_network_stats(
     State(state): State<Arc<ApiState>>,
     Path(chain_id): Path<u64>,
-) -> Result<Json<NetworkStatsResponse>, StatusCode> {
+) -> Result<Json<NetworkStatsResponse>, ApiError> {
     let provider_info = state.chain_manager.get_provider(chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     let block_number = provider_info.provider
         .get_block_number()
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?;
     
     // Get latest block to count transactions
     let latest_block = provider_info.provider
         .get_block(ethers::types::BlockId::Number(ethers::types::BlockNumber::Latest))
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("No latest block on chain {}", chain_id)))?;
     
     Ok(Json(NetworkStatsResponse {
         chain_id,
@@ -280,9 +430,9 @@ async fn get_block(
     State(state): State<Arc<ApiState>>,
     Path(chain_id): Path<u64>,
     Query(query): Query<BlockQuery>,
-) -> Result<Json<Block<H256>>, StatusCode> {
+) -> Result<Json<Block<H256>>, ApiError> {
     let provider_info = state.chain_manager.get_provider(chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     let block_id = if let Some(block_number) = query.block_number {
         ethers::types::BlockId::Number(ethers::types::BlockNumber::Number(block_number.into()))
@@ -295,8 +445,8 @@ async fn get_block(
     let block = provider_info.provider
         .get_block(block_id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("Block not found on chain {}", chain_id)))?;
     
     Ok(Json(block))
 }
@@ -305,31 +455,161 @@ async fn get_block(
 async fn get_transaction(
     State(state): State<Arc<ApiState>>,
     Path((chain_id, tx_hash)): Path<(u64, H256)>,
-) -> Result<Json<Transaction>, StatusCode> {
+) -> Result<Json<Transaction>, ApiError> {
     let provider_info = state.chain_manager.get_provider(chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     let transaction = provider_info.provider
         .get_transaction(tx_hash)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {:?} not found on chain {}", tx_hash, chain_id)))?;
     
     Ok(Json(transaction))
 }
 
+/// Broadcast a signed transaction
+async fn send_raw_transaction(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+    SignedJson(request): SignedJson<SendRawTransactionRequest>,
+) -> Result<Json<TrackedTransaction>, ApiError> {
+    let tracked = state.broadcaster.send_raw(chain_id, request.raw_transaction).await
+        .map_err(|e| {
+            warn!("Raw transaction broadcast failed: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(tracked))
+}
+
+/// Confirmation status of a transaction sent through the broadcaster
+async fn get_transaction_status(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, tx_hash)): Path<(u64, H256)>,
+    Query(query): Query<TransactionStatusQuery>,
+) -> Result<Json<TrackedTransaction>, ApiError> {
+    match state.broadcaster.get(tx_hash).await {
+        Some(tracked) if tracked.chain_id == chain_id => {}
+        _ => return Err(ApiError::NotFound(format!("Transaction {:?} was not broadcast on chain {}", tx_hash, chain_id))),
+    }
+
+    let tracked = match query.wait_secs {
+        Some(wait_secs) => {
+            let timeout = Duration::from_secs(wait_secs.min(MAX_STATUS_WAIT_SECS));
+            state.broadcaster.wait_for_confirmation(tx_hash, query.confirmations.unwrap_or(1), timeout).await
+        }
+        None => state.broadcaster.refresh(tx_hash).await,
+    }
+    .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    Ok(Json(tracked))
+}
+
 /// Get address balance
 async fn get_balance(
     State(state): State<Arc<ApiState>>,
     Path((chain_id, address)): Path<(u64, Address)>,
-) -> Result<Json<U256>, StatusCode> {
+) -> Result<Json<U256>, ApiError> {
     let provider_info = state.chain_manager.get_provider(chain_id).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     let balance = provider_info.provider
         .get_balance(address, None)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::Upstream(e.to_string()))?;
     
     Ok(Json(balance))
 }
+
+/// Canonical assets with their native, wrapped and bridged tokens
+async fn list_assets(State(state): State<Arc<ApiState>>) -> Json<Vec<CanonicalAsset>> {
+    Json(state.dex_manager.assets().assets().to_vec())
+}
+
+/// Tokens standing for the same asset as `token`, on any chain
+async fn get_asset_equivalents(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+) -> Result<Json<Vec<AssetEquivalent>>, ApiError> {
+    state.dex_manager.assets().equivalents(chain_id, token)
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Token {:?} on chain {} is not a known asset", token, chain_id)))
+}
+
+/// Known tokens with their symbol, decimals and logo
+async fn list_tokens(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<TokenQuery>,
+) -> Json<Vec<TokenMetadata>> {
+    Json(state.tokens.list(query.chain_id, query.search.as_deref()).await)
+}
+
+/// Configured token lists and the outcome of their last load
+async fn list_token_lists(State(state): State<Arc<ApiState>>) -> Json<Vec<TokenListStatus>> {
+    Json(state.tokens.list_statuses().await)
+}
+
+/// Metadata of a token, read from its contract when no token list has it
+async fn get_token(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+) -> Result<Json<TokenMetadata>, ApiError> {
+    tokens::validate(&state, chain_id, token).await.map(Json)
+}
+
+/// Logo of a token, served from the logo cache
+async fn get_token_logo(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let logo = state.tokens.logo(chain_id, token).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?
+        .ok_or_else(|| ApiError::NotFound(format!("Token {:?} on chain {} has no logo", token, chain_id)))?;
+
+    Ok((
+        [(header::CONTENT_TYPE, logo.content_type.clone()), (header::CACHE_CONTROL, "public, max-age=86400".to_string())],
+        logo.bytes.clone(),
+    ))
+}
+
+/// Live indexing progress of every chain with indexed contracts
+async fn get_indexer_status(State(state): State<Arc<ApiState>>) -> Json<Vec<IndexerChainStatus>> {
+    Json(state.indexer.status().await)
+}
+
+/// Contracts whose events are indexed
+async fn list_indexed_contracts(State(state): State<Arc<ApiState>>) -> Json<Vec<IndexedContract>> {
+    Json(state.indexer.contracts().await)
+}
+
+/// Indexed events filtered by chain, contract, account, event name and block range, newest first
+async fn list_indexed_events(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<IndexedEventQuery>,
+) -> Json<Vec<IndexedEvent>> {
+    Json(state.indexer.events(&query).await)
+}
+
+/// Tracked blocks and reorganizations of every polled chain
+async fn get_reorgs(State(state): State<Arc<ApiState>>) -> Json<ReorgReport> {
+    Json(ReorgReport {
+        chains: state.reorgs.status().await,
+        recent: state.reorgs.history().await,
+    })
+}
+
+/// Forward resolution of an ENS name, or reverse lookup when given an address
+async fn resolve_ens(
+    State(state): State<Arc<ApiState>>,
+    Path(target): Path<AddressOrName>,
+) -> Result<Json<EnsRecord>, ApiError> {
+    let record = match target {
+        AddressOrName::Address(address) => EnsRecord { address, name: state.ens.lookup(address).await },
+        AddressOrName::Name(ref name) => EnsRecord {
+            address: ens::resolve(&state, &target).await?,
+            name: Some(name.clone()),
+        },
+    };
+    Ok(Json(record))
+}
@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use ethers::{
+    abi::Abi,
+    providers::Middleware,
+    types::{Address, TransactionRequest, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::contracts::{ContractCallResult, DecodedEvent};
+use crate::contracts::approvals::{ApprovalPolicy, PermitRequest, SignedPermit, TokenApproval, TokenSpend};
+use crate::contracts::probes::DeploymentCheck;
+
+/// Contract method call request
+#[derive(Deserialize)]
+pub struct ContractCallRequest {
+    /// Method name, or full signature such as `transfer(address,uint256)` for overloads
+    pub method: String,
+    #[serde(default)]
+    pub args: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct AllowanceQuery {
+    pub owner: Address,
+    pub spender: Address,
+    /// Amount the spender is about to pull; an approval is built when the allowance falls short
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    pub amount: Option<U256>,
+    /// Overrides the configured approval policy
+    pub policy: Option<ApprovalPolicy>,
+}
+
+#[derive(Serialize)]
+pub struct AllowanceCheck {
+    pub token: Address,
+    pub owner: Address,
+    pub spender: Address,
+    pub allowance: U256,
+    pub policy: ApprovalPolicy,
+    pub approval: Option<TokenApproval>,
+}
+
+#[derive(Deserialize)]
+pub struct PermitQuery {
+    pub owner: Address,
+    pub spender: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub value: U256,
+    /// Unix timestamp after which the permit is rejected
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub deadline: U256,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/deployments", get(list_deployments))
+        .route("/{chain_id}/{address}/abi", get(get_contract_abi))
+        .route("/{chain_id}/{address}/call", post(call_contract_method))
+        .route("/{chain_id}/tx/{tx_hash}/events", get(decode_transaction_events))
+        .route("/{chain_id}/{address}/allowance", get(check_allowance))
+        .route("/{chain_id}/{address}/permit", post(create_permit))
+        .route("/{chain_id}/{address}/permit/transaction", post(build_permit_transaction))
+}
+
+/// Interface checks of the protocol addresses in use, from the last probe
+async fn list_deployments(State(state): State<Arc<ApiState>>) -> Json<Vec<DeploymentCheck>> {
+    Json(state.deployments.checks().await)
+}
+
+/// Get the verified ABI of a contract
+async fn get_contract_abi(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, address)): Path<(u64, Address)>,
+) -> Result<Json<Abi>, ApiError> {
+    let abi = state.contracts.get_abi(chain_id, address).await.map_err(|e| {
+        warn!("ABI lookup for {:?} on chain {} failed: {}", address, chain_id, e);
+        ApiError::from_error(e, ApiError::NotFound)
+    })?;
+
+    Ok(Json(abi))
+}
+
+/// Call a method of a verified contract
+async fn call_contract_method(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, address)): Path<(u64, Address)>,
+    Json(request): Json<ContractCallRequest>,
+) -> Result<Json<ContractCallResult>, ApiError> {
+    state.contracts.get_abi(chain_id, address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    let result = state.contracts
+        .call_contract_method(chain_id, address, &request.method, &request.args)
+        .await
+        .map_err(|e| {
+            warn!("Call of {} on {:?} failed: {}", request.method, address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(result))
+}
+
+/// Decode the events of a mined transaction
+async fn decode_transaction_events(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, tx_hash)): Path<(u64, H256)>,
+) -> Result<Json<Vec<DecodedEvent>>, ApiError> {
+    let chain = state.chain_manager.get_provider(chain_id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    let receipt = chain.provider.get_transaction_receipt(tx_hash).await
+        .map_err(|e| ApiError::Upstream(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("No receipt for {:?} on chain {}", tx_hash, chain_id)))?;
+
+    Ok(Json(state.contracts.decode_logs(chain_id, &receipt.logs).await))
+}
+
+/// Current allowance of a spender, with the approval an amount needs under the policy
+async fn check_allowance(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+    Query(query): Query<AllowanceQuery>,
+) -> Result<Json<AllowanceCheck>, ApiError> {
+    let approvals = state.dex_manager.approvals();
+    let allowance = approvals.allowance(chain_id, token, query.owner, query.spender).await.map_err(|e| {
+        warn!("Allowance of {:?} on chain {} failed: {}", token, chain_id, e);
+        ApiError::from_error(e, ApiError::Upstream)
+    })?;
+
+    let approval = match query.amount {
+        Some(amount) => {
+            let spend = TokenSpend { token, spender: query.spender, amount };
+            approvals.required_approval(chain_id, query.owner, spend, query.policy).await.map_err(|e| {
+                warn!("Approval of {:?} on chain {} failed: {}", token, chain_id, e);
+                ApiError::from_error(e, ApiError::Upstream)
+            })?
+        }
+        None => None,
+    };
+
+    Ok(Json(AllowanceCheck {
+        token,
+        owner: query.owner,
+        spender: query.spender,
+        allowance,
+        policy: query.policy.unwrap_or(approvals.policy()),
+        approval,
+    }))
+}
+
+/// EIP-2612 typed data for the owner to sign instead of sending an approval
+async fn create_permit(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+    Json(request): Json<PermitQuery>,
+) -> Result<Json<PermitRequest>, ApiError> {
+    let permit = state.dex_manager.approvals()
+        .permit_request(chain_id, token, request.owner, request.spender, request.value, request.deadline)
+        .await
+        .map_err(|e| {
+            warn!("Permit for {:?} on chain {} failed: {}", token, chain_id, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(permit))
+}
+
+/// `permit` transaction submitting a signed permit
+async fn build_permit_transaction(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+    Json(permit): Json<SignedPermit>,
+) -> Result<Json<TransactionRequest>, ApiError> {
+    let transaction = state.dex_manager.approvals()
+        .permit_transaction(chain_id, token, &permit)
+        .await
+        .map_err(|e| {
+            warn!("Permit transaction for {:?} on chain {} failed: {}", token, chain_id, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(transaction))
+}
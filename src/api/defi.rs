@@ -1,15 +1,42 @@
 use axum::{
-    extract::{Path, State},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
     http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    response::{Json, Response},
+    routing::{delete, get, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use ethers::types::{Address, U256};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use ethers::types::{Address, H256, U256};
 
-use crate::api::ApiState;
+use crate::api::{error::ApiError, models::{ArchiveQuery, TokenAmount}, replay::SignedJson, tokens, ApiState};
+use crate::analytics::carry_calendar::{CarryCalendar, HedgeSide, PerpHedge};
+use crate::analytics::backtest::{BacktestReport, BacktestRequest};
+use crate::analytics::risk_assessor::ValueAtRisk;
+use crate::defi::arbitrage::ArbitrageScan;
+use crate::defi::bridge::{BridgeQuote, BridgeQuoteRequest, BridgeTransfer, BridgeTransferPlan, BridgeTransferRequest};
+use crate::defi::closeout::{CloseoutAction, CloseoutPlan, CloseoutRequest};
+use crate::defi::collateral_optimizer::{CollateralOptimizationRequest, CollateralPlan};
+use crate::defi::compound::LiquidationOpportunity;
+use crate::defi::compound_borrowers::CompoundBorrower;
+use crate::defi::convex::{ConvexAction, ConvexPoolYield, ConvexPosition, ConvexTransactions};
+use crate::defi::flash_loans::FlashLiquidation;
+use crate::defi::maker::{IlkInfo, MakerVault, VaultAction, VaultTransactions};
+use crate::defi::rate_history::{RateHistoryFilter, RateHistoryReport};
+use crate::defi::reward_compounder::{RewardCompounding, RewardCompoundingRequest};
+use crate::defi::strategy_bundle::StrategyBundle;
+use crate::defi::strategy_executor::{StrategyExecution, StrategyExecutionRequest};
+use crate::defi::strategy_gas::StrategyGasReport;
+use crate::defi::strategy_templates::{RiskClass, StrategyTemplate, TemplateFilter};
+use crate::defi::{ActiveStrategy, CrossChainYieldComparison, OptimalYieldOpportunity, PortfolioRisk, RewardBalance, RewardClaim};
+use crate::wallets::labels::WalletUse;
+use crate::wallets::smart_account::UserOperation;
 
 pub fn routes() -> Router<Arc<ApiState>> {
     Router::new()
@@ -20,7 +47,44 @@ pub fn routes() -> Router<Arc<ApiState>> {
         .route("/protocols/{protocol}/borrow", post(borrow_asset))
         .route("/protocols/{protocol}/repay", post(repay_asset))
         .route("/opportunities", get(get_yield_opportunities))
+        .route("/yields/{chain_id}/{asset}/compare", get(compare_yields_across_chains))
+        .route("/rates/history", get(get_rate_history))
+        .route("/bridge/quote", get(quote_bridge_transfer))
+        .route("/bridge/transfers", get(list_bridge_transfers).post(create_bridge_transfer))
+        .route("/bridge/transfers/{id}", get(get_bridge_transfer))
+        .route("/bridge/transfers/{id}/deposit", post(record_bridge_deposit))
         .route("/portfolio/{user}", get(get_user_portfolio))
+        .route("/portfolio/{user}/risk", get(get_user_portfolio_risk))
+        .route("/portfolio/{user}/carry", get(get_user_carry_calendar))
+        .route("/portfolio/{user}/closeout", post(plan_portfolio_closeout))
+        .route("/rewards/{user}", get(get_claimable_rewards))
+        .route("/rewards/{user}/claim", post(build_reward_claim))
+        .route("/compounding", get(list_reward_compounding).post(register_reward_compounding))
+        .route("/compounding/{id}", get(get_reward_compounding).delete(remove_reward_compounding))
+        .route("/compounding/{id}/run", post(run_reward_compounding))
+        .route("/collateral/optimize", post(optimize_collateral))
+        .route("/arbitrage", get(get_dex_arbitrage))
+        .route("/arbitrage/ws", get(dex_arbitrage_websocket))
+        .route("/backtest", post(run_backtest))
+        .route("/compound/{chain_id}/borrowers", get(list_compound_borrowers))
+        .route("/compound/{chain_id}/liquidations", get(scan_compound_liquidations))
+        .route("/compound/{chain_id}/liquidations/flash", post(build_flash_liquidation))
+        .route("/maker/{chain_id}/collateral", get(list_maker_collateral_types))
+        .route("/maker/{chain_id}/vaults/{owner}", get(list_maker_vaults).post(build_maker_vault_action))
+        .route("/convex/{chain_id}/pools", get(list_convex_pools))
+        .route("/convex/{chain_id}/positions/{owner}", get(list_convex_positions).post(build_convex_action))
+        .route("/strategies/templates", get(list_strategy_templates))
+        .route("/strategies/templates/{id}", get(get_strategy_template))
+        .route("/strategies/templates/{id}/instantiate", post(instantiate_strategy_template))
+        .route("/strategies/bundle", post(bundle_yield_strategy))
+        .route("/strategies/{user}", get(list_user_strategies))
+        .route("/strategies/{user}/{id}", delete(close_user_strategy))
+        .route("/strategies/{user}/{id}/transactions", post(link_strategy_transactions))
+        .route("/strategies/{user}/{id}/gas", get(get_strategy_gas))
+        .route("/executions", get(list_strategy_executions).post(submit_strategy_execution))
+        .route("/executions/{id}", get(get_strategy_execution))
+        .route("/executions/{id}/resume", post(resume_strategy_execution))
+        .route("/executions/{id}/cancel", post(cancel_strategy_execution))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,60 +93,98 @@ pub struct ProtocolStatsResponse {
     pub tvl: U256,
     pub total_borrowed: U256,
     pub total_supplied: U256,
+    #[serde(with = "crate::api::models::ratio")]
     pub utilization_rate: f64,
+    #[serde(with = "crate::api::models::ratio")]
     pub average_supply_apy: f64,
+    #[serde(with = "crate::api::models::ratio")]
     pub average_borrow_apy: f64,
     pub active_users: u64,
+    #[serde(with = "crate::api::models::ratio")]
     pub health_factor: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LendingRequest {
-    pub asset: Address,
-    pub amount: U256,
+/// Instantiate a strategy template for a user
+#[derive(Debug, Deserialize)]
+pub struct InstantiateTemplateRequest {
     pub user: Address,
+    pub chain_id: u64,
+    /// Amount of the template's first required asset to deploy
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount: U256,
+    #[serde(default)]
+    pub parameters: HashMap<String, f64>,
+}
+
+/// Tracked transactions to charge to a strategy
+#[derive(Debug, Deserialize)]
+pub struct LinkStrategyTransactionsRequest {
+    pub transaction_ids: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct YieldOpportunity {
-    pub protocol: String,
+pub struct LendingRequest {
     pub asset: Address,
-    pub apy: f64,
-    pub risk_level: String,
-    pub minimum_deposit: U256,
-    pub available_liquidity: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount: U256,
+    pub user: Address,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserPortfolioResponse {
     pub user: Address,
+    #[serde(with = "crate::api::models::usd")]
     pub total_supplied_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
     pub total_borrowed_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
     pub net_worth_usd: f64,
+    #[serde(with = "crate::api::models::ratio")]
     pub overall_health_factor: f64,
     pub positions: Vec<PositionInfo>,
+    pub unpriced_assets: Vec<Address>,
+    pub risk: PortfolioRisk,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PositionInfo {
     pub protocol: String,
     pub asset: Address,
-    pub supplied_amount: U256,
-    pub borrowed_amount: U256,
+    /// Symbol from the token registry, the asset address when it is unknown
+    pub symbol: String,
+    /// `None` when the asset's decimals could not be read
+    pub supplied_amount: Option<TokenAmount>,
+    pub borrowed_amount: Option<TokenAmount>,
+    #[serde(with = "crate::api::models::ratio")]
     pub supply_apy: f64,
+    #[serde(with = "crate::api::models::ratio")]
     pub borrow_apy: f64,
+    pub price_usd: Option<f64>,
+    #[serde(with = "crate::api::models::usd")]
+    pub supplied_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub borrowed_usd: f64,
+}
+
+/// Liquidation risk of a portfolio with the market risk of its positions
+#[derive(Debug, Serialize)]
+pub struct PortfolioRiskResponse {
+    #[serde(flatten)]
+    pub risk: PortfolioRisk,
+    pub value_at_risk: ValueAtRisk,
 }
 
 /// List supported DeFi protocols
 async fn list_defi_protocols(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Json<Vec<String>>, StatusCode> {
+) -> Result<Json<Vec<String>>, ApiError> {
     let protocols = vec![
         "aave".to_string(),
         "compound".to_string(),
         "maker".to_string(),
         "yearn".to_string(),
         "curve".to_string(),
+        "convex".to_string(),
         "lido".to_string(),
     ];
     
@@ -93,10 +195,10 @@ async fn list_defi_protocols(
 async fn get_protocol_stats(
     State(state): State<Arc<ApiState>>,
     Path(protocol): Path<String>,
-) -> Result<Json<ProtocolStatsResponse>, StatusCode> {
+) -> Result<Json<ProtocolStatsResponse>, ApiError> {
     let chain_id = 1u64; // Default to Ethereum mainnet
     let _stats = state.defi_manager.get_protocol_stats(chain_id).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     let response = ProtocolStatsResponse {
         name: protocol.clone(),
@@ -117,9 +219,10 @@ async fn get_protocol_stats(
 async fn supply_asset(
     State(state): State<Arc<ApiState>>,
     Path(protocol): Path<String>,
-    Json(request): Json<LendingRequest>,
-) -> Result<Json<String>, StatusCode> {
+    SignedJson(request): SignedJson<LendingRequest>,
+) -> Result<Json<String>, ApiError> {
     let chain_id = 1u64; // Default to Ethereum mainnet
+    tokens::validate(&state, chain_id, request.asset).await?;
     let tx_hash = state.defi_manager.supply_asset(
         chain_id,
         protocol.clone(),
@@ -127,7 +230,7 @@ async fn supply_asset(
         request.amount,
         request.user,
     ).await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     Ok(Json(tx_hash))
 }
@@ -136,9 +239,10 @@ async fn supply_asset(
 async fn withdraw_asset(
     State(state): State<Arc<ApiState>>,
     Path(protocol): Path<String>,
-    Json(request): Json<LendingRequest>,
-) -> Result<Json<String>, StatusCode> {
+    SignedJson(request): SignedJson<LendingRequest>,
+) -> Result<Json<String>, ApiError> {
     let chain_id = 1u64; // Default to Ethereum mainnet
+    tokens::validate(&state, chain_id, request.asset).await?;
     let tx_hash = state.defi_manager.withdraw_asset(
         chain_id,
         protocol.clone(),
@@ -146,7 +250,7 @@ async fn withdraw_asset(
         request.amount,
         request.user,
     ).await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     Ok(Json(tx_hash))
 }
@@ -155,9 +259,10 @@ async fn withdraw_asset(
 async fn borrow_asset(
     State(state): State<Arc<ApiState>>,
     Path(protocol): Path<String>,
-    Json(request): Json<LendingRequest>,
-) -> Result<Json<String>, StatusCode> {
+    SignedJson(request): SignedJson<LendingRequest>,
+) -> Result<Json<String>, ApiError> {
     let chain_id = 1u64; // Default to Ethereum mainnet
+    tokens::validate(&state, chain_id, request.asset).await?;
     let tx_hash = state.defi_manager.borrow_asset(
         chain_id,
         protocol.clone(),
@@ -165,7 +270,7 @@ async fn borrow_asset(
         request.amount,
         request.user,
     ).await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     Ok(Json(tx_hash))
 }
@@ -174,9 +279,10 @@ async fn borrow_asset(
 async fn repay_asset(
     State(state): State<Arc<ApiState>>,
     Path(protocol): Path<String>,
-    Json(request): Json<LendingRequest>,
-) -> Result<Json<String>, StatusCode> {
+    SignedJson(request): SignedJson<LendingRequest>,
+) -> Result<Json<String>, ApiError> {
     let chain_id = 1u64; // Default to Ethereum mainnet
+    tokens::validate(&state, chain_id, request.asset).await?;
     let tx_hash = state.defi_manager.repay_asset(
         chain_id,
         protocol.clone(),
@@ -184,55 +290,825 @@ async fn repay_asset(
         request.amount,
         request.user,
     ).await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     Ok(Json(tx_hash))
 }
 
-/// Get yield opportunities across protocols
+/// Asset and amount to find yield strategies for
+#[derive(Debug, Deserialize)]
+pub struct YieldOpportunitiesQuery {
+    /// Ethereum mainnet by default
+    pub chain_id: Option<u64>,
+    pub asset: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount: U256,
+}
+
+/// Yield strategies for depositing an asset across protocols, with their risk scores and, for
+/// leveraged ones, the projected interest, rewards, break-even and liquidation path
 async fn get_yield_opportunities(
-    State(_state): State<Arc<ApiState>>,
-) -> Result<Json<Vec<YieldOpportunity>>, StatusCode> {
-    // Mock implementation - would fetch from DeFi manager
-    let opportunities = vec![
-        YieldOpportunity {
-            protocol: "Aave".to_string(),
-            asset: "0xA0b86a33E6F2C8F9B69C6CfF9b7D83c63c5b90e2".parse().unwrap(), // USDC
-            apy: 0.025, // 2.5%
-            risk_level: "Low".to_string(),
-            minimum_deposit: U256::from(1000u64) * U256::exp10(6), // 1000 USDC
-            available_liquidity: U256::from(50000000u64) * U256::exp10(6), // 50M USDC
-        },
-        YieldOpportunity {
-            protocol: "Compound".to_string(),
-            asset: "0x6B175474E89094C44Da98b954EedeAC495271d0F".parse().unwrap(), // DAI
-            apy: 0.032, // 3.2%
-            risk_level: "Low".to_string(),
-            minimum_deposit: U256::from(100u64) * U256::exp10(18), // 100 DAI
-            available_liquidity: U256::from(25000000u64) * U256::exp10(18), // 25M DAI
-        },
-    ];
-    
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<YieldOpportunitiesQuery>,
+) -> Result<Json<Vec<OptimalYieldOpportunity>>, ApiError> {
+    let chain_id = query.chain_id.unwrap_or(1);
+    tokens::validate(&state, chain_id, query.asset).await?;
+    let opportunities = state.defi_manager.find_optimal_yield_opportunities(chain_id, query.asset, query.amount).await
+        .map_err(|e| {
+            warn!("Yield opportunities for {:?} on chain {} failed: {}", query.asset, chain_id, e);
+            ApiError::from_error(e, ApiError::Internal)
+        })?;
+
     Ok(Json(opportunities))
 }
 
+/// Amount to move, pricing the bridge to yields on other chains
+#[derive(Debug, Deserialize)]
+pub struct YieldComparisonQuery {
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    pub amount: Option<U256>,
+}
+
+/// Supply rates of the asset's tokens on every chain, including bridged and wrapped ones
+async fn compare_yields_across_chains(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, asset)): Path<(u64, Address)>,
+    Query(query): Query<YieldComparisonQuery>,
+) -> Result<Json<CrossChainYieldComparison>, ApiError> {
+    tokens::validate(&state, chain_id, asset).await?;
+    let comparison = state.defi_manager.compare_yields_across_chains(chain_id, asset, query.amount).await.map_err(|e| {
+        warn!("Yield comparison for {:?} on chain {} failed: {}", asset, chain_id, e);
+        ApiError::from_error(e, ApiError::NotFound)
+    })?;
+
+    Ok(Json(comparison))
+}
+
+/// Sampled Aave and Compound rates with each market's 7 and 30 day trailing averages
+async fn get_rate_history(
+    State(state): State<Arc<ApiState>>,
+    Query(filter): Query<RateHistoryFilter>,
+) -> Result<Json<RateHistoryReport>, ApiError> {
+    if let (Some(from), Some(to)) = (filter.from, filter.to) {
+        if from >= to {
+            return Err(ApiError::BadRequest("from must be before to".to_string()));
+        }
+    }
+
+    Ok(Json(state.rate_collector.history().query(&filter).await))
+}
+
+/// Across fee and deadlines of moving tokens to another chain
+async fn quote_bridge_transfer(
+    State(state): State<Arc<ApiState>>,
+    Query(request): Query<BridgeQuoteRequest>,
+) -> Result<Json<BridgeQuote>, ApiError> {
+    let quote = state.bridge.quote(&request).await.map_err(|e| {
+        warn!("Bridge quote from chain {} to {} failed: {}", request.origin_chain_id, request.destination_chain_id, e);
+        ApiError::from_error(e, ApiError::BadRequest)
+    })?;
+
+    Ok(Json(quote))
+}
+
+/// Build the approval and deposit of a bridge transfer and start tracking it
+async fn create_bridge_transfer(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<BridgeTransferRequest>,
+) -> Result<Json<BridgeTransferPlan>, ApiError> {
+    let plan = state.bridge.build_transfer(&request).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(plan))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BridgeTransfersQuery {
+    pub depositor: Option<Address>,
+}
+
+async fn list_bridge_transfers(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<BridgeTransfersQuery>,
+) -> Result<Json<Vec<BridgeTransfer>>, ApiError> {
+    Ok(Json(state.bridge.list(query.depositor).await))
+}
+
+/// Transfer with its status read again from the origin chain and Across
+async fn get_bridge_transfer(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<BridgeTransfer>, ApiError> {
+    let transfer = state.bridge.refresh(&id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    Ok(Json(transfer))
+}
+
+/// Hash of a broadcast deposit
+#[derive(Debug, Deserialize)]
+pub struct BridgeDepositRequest {
+    pub tx_hash: H256,
+}
+
+async fn record_bridge_deposit(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+    Json(request): Json<BridgeDepositRequest>,
+) -> Result<Json<BridgeTransfer>, ApiError> {
+    if state.bridge.get(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("Bridge transfer {} not found", id)));
+    }
+    let transfer = state.bridge.record_deposit(&id, request.tx_hash).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Conflict))?;
+
+    Ok(Json(transfer))
+}
+
 /// Get user's DeFi portfolio
 async fn get_user_portfolio(
     State(state): State<Arc<ApiState>>,
     Path(user): Path<Address>,
-) -> Result<Json<UserPortfolioResponse>, StatusCode> {
+) -> Result<Json<UserPortfolioResponse>, ApiError> {
     let chain_id = 1u64; // Default to Ethereum mainnet
     let portfolio = state.defi_manager.get_portfolio_overview(chain_id, user).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
+    let mut positions = Vec::with_capacity(portfolio.positions_usd.len());
+    for position in portfolio.positions_usd {
+        positions.push(PositionInfo {
+            protocol: position.protocol,
+            asset: position.asset,
+            symbol: state.tokens.symbol(chain_id, position.asset).await,
+            supplied_amount: position.decimals.map(|decimals| TokenAmount::new(position.supplied_amount, decimals)),
+            borrowed_amount: position.decimals.map(|decimals| TokenAmount::new(position.borrowed_amount, decimals)),
+            supply_apy: position.supply_apy,
+            borrow_apy: position.borrow_apy,
+            price_usd: position.price_usd,
+            supplied_usd: position.supplied_usd,
+            borrowed_usd: position.borrowed_usd,
+        });
+    }
+
     let response = UserPortfolioResponse {
         user: portfolio.user,
         total_supplied_usd: portfolio.total_supplied_usd,
         total_borrowed_usd: portfolio.total_borrowed_usd,
         net_worth_usd: portfolio.net_worth_usd,
         overall_health_factor: portfolio.overall_health_factor,
-        positions: vec![], // Would map from portfolio positions
+        positions,
+        unpriced_assets: portfolio.unpriced_assets,
+        risk: portfolio.risk,
     };
     
     Ok(Json(response))
 }
+
+/// Get per-protocol health factors, collateral liquidation distances and Value-at-Risk
+async fn get_user_portfolio_risk(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<Address>,
+) -> Result<Json<PortfolioRiskResponse>, ApiError> {
+    let chain_id = 1u64; // Default to Ethereum mainnet
+    let portfolio = state.defi_manager.get_portfolio_overview(chain_id, user).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+    let value_at_risk = state.risk_assessor.value_at_risk(chain_id, &portfolio.positions_usd).await;
+
+    Ok(Json(PortfolioRiskResponse { risk: portfolio.risk, value_at_risk }))
+}
+
+/// Carry calendar query parameters, the hedge is optional
+#[derive(Debug, Deserialize)]
+pub struct CarryQuery {
+    pub chain_id: Option<u64>,
+    pub hedge_notional_usd: Option<f64>,
+    pub funding_rate_8h: Option<f64>,
+    pub hedge_side: Option<HedgeSide>,
+}
+
+/// Project carrying costs of a user's positions over the next 7 and 30 days
+async fn get_user_carry_calendar(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<Address>,
+    Query(query): Query<CarryQuery>,
+) -> Result<Json<CarryCalendar>, ApiError> {
+    let hedge = match (query.hedge_notional_usd, query.funding_rate_8h) {
+        (Some(notional_usd), Some(funding_rate_8h)) => Some(PerpHedge {
+            notional_usd,
+            funding_rate_8h,
+            side: query.hedge_side.unwrap_or(HedgeSide::Short),
+        }),
+        (None, None) => None,
+        _ => return Err(ApiError::BadRequest("hedge_notional_usd and funding_rate_8h must be given together".to_string())),
+    };
+
+    let chain_id = query.chain_id.unwrap_or(1);
+    let portfolio = state.defi_manager.get_portfolio_overview(chain_id, user).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    // Days start at midnight in the user's time zone
+    let today = state.analytics.time_zones.get(user).await.local_date(chrono::Utc::now());
+    let calendar = state.analytics.carry
+        .build_calendar(chain_id, user, &portfolio.positions_usd, hedge, &[7, 30], today)
+        .await;
+
+    Ok(Json(calendar))
+}
+
+/// Accounts with an outstanding Compound borrow, as indexed by the `compound_borrowers` backfill
+async fn list_compound_borrowers(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+) -> Result<Json<Vec<CompoundBorrower>>, ApiError> {
+    Ok(Json(state.compound_borrowers.open_borrowers(chain_id).await))
+}
+
+/// Maker collateral types with their liquidation ratio, stability fee and remaining debt ceiling
+async fn list_maker_collateral_types(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+) -> Result<Json<Vec<IlkInfo>>, ApiError> {
+    let ilks = state.defi_manager.maker().ilks(chain_id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(ilks))
+}
+
+/// An owner's Maker vaults with their collateralization
+async fn list_maker_vaults(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, owner)): Path<(u64, Address)>,
+) -> Result<Json<Vec<MakerVault>>, ApiError> {
+    let vaults = state.defi_manager.maker().vaults(chain_id, owner).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(vaults))
+}
+
+/// Transactions opening a vault or depositing collateral, drawing or repaying DAI, for the owner to send
+async fn build_maker_vault_action(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, owner)): Path<(u64, Address)>,
+    SignedJson(action): SignedJson<VaultAction>,
+) -> Result<Json<VaultTransactions>, ApiError> {
+    let transactions = state.defi_manager.build_vault_action(chain_id, owner, action).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(transactions))
+}
+
+/// Curve pools staked on Convex with their boost and APY split into trading fees, CRV and CVX
+async fn list_convex_pools(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+) -> Result<Json<Vec<ConvexPoolYield>>, ApiError> {
+    let pools = state.defi_manager.convex_pools(chain_id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(pools))
+}
+
+/// An owner's staked Curve LP on Convex with the CRV and CVX accrued
+async fn list_convex_positions(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, owner)): Path<(u64, Address)>,
+) -> Result<Json<Vec<ConvexPosition>>, ApiError> {
+    let positions = state.defi_manager.convex().positions(chain_id, owner).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(positions))
+}
+
+/// Transactions adding liquidity to a Curve pool, staking or unstaking its LP on Convex or claiming
+/// rewards, for the owner to send
+async fn build_convex_action(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, owner)): Path<(u64, Address)>,
+    SignedJson(action): SignedJson<ConvexAction>,
+) -> Result<Json<ConvexTransactions>, ApiError> {
+    let transactions = state.defi_manager.build_convex_action(chain_id, owner, action).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(transactions))
+}
+
+/// Liquidation scan query parameters
+#[derive(Debug, Deserialize)]
+pub struct LiquidationScanQuery {
+    /// Leave out opportunities netting less, in USD
+    pub min_net_profit_usd: Option<f64>,
+}
+
+/// Liquidatable accounts among the indexed borrowers, most profitable first
+#[derive(Debug, Serialize)]
+pub struct LiquidationScan {
+    pub chain_id: u64,
+    pub borrowers_scanned: usize,
+    pub opportunities: Vec<LiquidationOpportunity>,
+}
+
+/// Check the indexed Compound borrowers' liquidity and rank the underwater ones by net liquidation profit
+async fn scan_compound_liquidations(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+    Query(query): Query<LiquidationScanQuery>,
+) -> Result<Json<LiquidationScan>, ApiError> {
+    if !state.defi_manager.compound().markets().contains_key(&chain_id) {
+        return Err(ApiError::NotFound(format!("Compound is not deployed on chain {}", chain_id)));
+    }
+    let borrowers = state.compound_borrowers.open_borrowers(chain_id).await;
+    let mut opportunities = state.defi_manager.compound()
+        .find_liquidation_opportunities(chain_id, &borrowers).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+    if let Some(min_net_profit_usd) = query.min_net_profit_usd {
+        opportunities.retain(|opportunity| opportunity.net_profit_usd >= min_net_profit_usd);
+    }
+
+    Ok(Json(LiquidationScan {
+        chain_id,
+        borrowers_scanned: borrowers.len(),
+        opportunities,
+    }))
+}
+
+/// Liquidation of one borrower to run through a flash loan receiver contract
+#[derive(Debug, Deserialize)]
+pub struct FlashLiquidationRequest {
+    pub borrower: Address,
+    pub ctoken_borrowed: Address,
+    pub ctoken_collateral: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub repay_amount: U256,
+    /// Contract receiving the loan and making the calls
+    pub receiver: Address,
+    /// Wallet the profit goes to
+    pub beneficiary: Address,
+    /// Profit in the repaid asset below which the receiver reverts
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    pub min_profit: Option<U256>,
+}
+
+/// Build the flash loan transaction liquidating a Compound borrower and selling the seized collateral
+async fn build_flash_liquidation(
+    State(state): State<Arc<ApiState>>,
+    Path(chain_id): Path<u64>,
+    Json(request): Json<FlashLiquidationRequest>,
+) -> Result<Json<FlashLiquidation>, ApiError> {
+    state.wallet_manager.labels().require(request.beneficiary, WalletUse::FlashLoan).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Forbidden))?;
+    let liquidation = state.defi_manager.build_flash_liquidation(
+        chain_id,
+        request.borrower,
+        request.ctoken_borrowed,
+        request.ctoken_collateral,
+        request.repay_amount,
+        request.receiver,
+        request.beneficiary,
+        request.min_profit.unwrap_or_default(),
+    ).await
+        .map_err(|e| {
+            warn!("Flash liquidation of {:?} failed: {}", request.borrower, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+    // The seized collateral is sold for the loaned asset, refuse the sale at a manipulated price
+    if liquidation.dex.is_some() {
+        let check = state.price_guard.validate_trade(
+            chain_id,
+            liquidation.collateral_asset,
+            liquidation.collateral_amount,
+            liquidation.loan.asset,
+            liquidation.expected_output,
+        ).await;
+        state.circuit_breakers.observe_trade(check).await
+            .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+    }
+
+    Ok(Json(liquidation))
+}
+
+/// Arbitrage feed query parameters
+#[derive(Debug, Deserialize)]
+pub struct ArbitrageQuery {
+    /// One of the scanned chains, all of them by default
+    pub chain_id: Option<u64>,
+    /// Leave out round trips netting less, in USD; the configured minimum by default
+    pub min_net_profit_usd: Option<f64>,
+    /// Re-quote now instead of returning the last background scan
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+impl ArbitrageQuery {
+    fn filter(&self, mut scan: ArbitrageScan, default_min_net_profit_usd: f64) -> ArbitrageScan {
+        let min_net_profit_usd = self.min_net_profit_usd.unwrap_or(default_min_net_profit_usd);
+        scan.opportunities.retain(|opportunity| opportunity.net_profit_usd >= min_net_profit_usd);
+        scan
+    }
+}
+
+/// Cross-DEX round trips over the configured tokens, net of gas and flash loan fees, most profitable first
+async fn get_dex_arbitrage(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ArbitrageQuery>,
+) -> Result<Json<Vec<ArbitrageScan>>, ApiError> {
+    let config = state.arbitrage.config();
+    let chain_ids: Vec<u64> = match query.chain_id {
+        Some(chain_id) if !config.chain_ids.contains(&chain_id) => {
+            return Err(ApiError::NotFound(format!("Chain {} is not scanned for arbitrage", chain_id)));
+        }
+        Some(chain_id) => vec![chain_id],
+        None => config.chain_ids.clone(),
+    };
+
+    let mut scans = state.arbitrage.latest(query.chain_id).await;
+    for chain_id in chain_ids {
+        if !query.refresh && scans.iter().any(|scan| scan.chain_id == chain_id) {
+            continue;
+        }
+        let scan = state.arbitrage.refresh(chain_id).await
+            .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+        scans.retain(|existing| existing.chain_id != chain_id);
+        scans.push(scan);
+    }
+    scans.sort_by_key(|scan| scan.chain_id);
+
+    Ok(Json(scans.into_iter().map(|scan| query.filter(scan, config.min_net_profit_usd)).collect()))
+}
+
+/// Stream each arbitrage scan to a WebSocket client as it is refreshed
+async fn dex_arbitrage_websocket(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ArbitrageQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let scans = state.arbitrage.subscribe();
+    let min_net_profit_usd = state.arbitrage.config().min_net_profit_usd;
+    ws.on_upgrade(move |socket| stream_arbitrage(socket, scans, query, min_net_profit_usd))
+}
+
+async fn stream_arbitrage(
+    mut socket: WebSocket,
+    mut scans: tokio::sync::broadcast::Receiver<ArbitrageScan>,
+    query: ArbitrageQuery,
+    min_net_profit_usd: f64,
+) {
+    loop {
+        let scan = match scans.recv().await {
+            Ok(scan) => scan,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Arbitrage WebSocket client lagged, skipped {} scans", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if query.chain_id.is_some_and(|chain_id| chain_id != scan.chain_id) {
+            continue;
+        }
+
+        let payload = match serde_json::to_string(&query.filter(scan, min_net_profit_usd)) {
+            Ok(payload) => payload,
+            Err(_) => continue,
+        };
+        if socket.send(Message::Text(payload.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Replay a yield strategy or rebalancing policy over historical prices and rates
+async fn run_backtest(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<BacktestRequest>,
+) -> Result<Json<BacktestReport>, ApiError> {
+    let report = state.backtests.run(&request).await
+        .map_err(|e| {
+            warn!("Backtest failed: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(report))
+}
+
+/// Browse strategy templates, optionally by chain, risk class and asset
+async fn list_strategy_templates(
+    State(state): State<Arc<ApiState>>,
+    Query(filter): Query<TemplateFilter>,
+) -> Result<Json<Vec<StrategyTemplate>>, ApiError> {
+    Ok(Json(state.defi_manager.templates().list(&filter)))
+}
+
+async fn get_strategy_template(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<StrategyTemplate>, ApiError> {
+    state.defi_manager.templates().get(&id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Strategy template {} not found", id)))
+}
+
+/// Add a strategy built from a template to the user's strategy registry
+async fn instantiate_strategy_template(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+    Json(request): Json<InstantiateTemplateRequest>,
+) -> Result<Json<ActiveStrategy>, ApiError> {
+    let templates = state.defi_manager.templates();
+    let template = templates.get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("Strategy template {} not found", id)))?;
+    if template.risk_class == RiskClass::High {
+        state.wallet_manager.labels().require(request.user, WalletUse::HighRiskStrategy).await
+            .map_err(|e| ApiError::from_error(e, ApiError::Forbidden))?;
+    }
+
+    let strategy = templates
+        .instantiate(&id, request.chain_id, request.amount, &request.parameters)
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+    state.defi_manager.strategies().register(request.user, strategy.clone()).await;
+
+    Ok(Json(strategy))
+}
+
+/// List a user's strategies, open ones unless `status` asks for closed or all
+async fn list_user_strategies(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<Address>,
+    Query(query): Query<ArchiveQuery>,
+) -> Result<Json<Vec<ActiveStrategy>>, ApiError> {
+    Ok(Json(state.defi_manager.strategies().list(user, query.status).await))
+}
+
+/// Close a strategy, keeping it in the user's history
+async fn close_user_strategy(
+    State(state): State<Arc<ApiState>>,
+    Path((user, id)): Path<(Address, String)>,
+) -> Result<Json<ActiveStrategy>, ApiError> {
+    let strategy = state.defi_manager.strategies().close(user, &id).await
+        .ok_or_else(|| ApiError::NotFound(format!("Strategy {} not found", id)))?;
+
+    Ok(Json(strategy))
+}
+
+/// Charge tracked transactions of the user to a strategy
+async fn link_strategy_transactions(
+    State(state): State<Arc<ApiState>>,
+    Path((user, id)): Path<(Address, String)>,
+    Json(request): Json<LinkStrategyTransactionsRequest>,
+) -> Result<Json<ActiveStrategy>, ApiError> {
+    let strategy = state.defi_manager.link_strategy_transactions(user, &id, &request.transaction_ids).await
+        .map_err(|e| {
+            warn!("Linking transactions to strategy {} failed: {}", id, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?
+        .ok_or_else(|| ApiError::NotFound(format!("Strategy {} not found", id)))?;
+
+    Ok(Json(strategy))
+}
+
+/// Gas spent by a strategy, amortized against its returns
+async fn get_strategy_gas(
+    State(state): State<Arc<ApiState>>,
+    Path((user, id)): Path<(Address, String)>,
+) -> Result<Json<StrategyGasReport>, ApiError> {
+    state.defi_manager.strategy_gas_report(user, &id).await
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Strategy {} not found", id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StrategyBundleRequest {
+    /// Smart account executing the batch and holding the positions
+    pub account: Address,
+    pub strategy: OptimalYieldOpportunity,
+    /// Have the configured paymaster pay for gas
+    #[serde(default)]
+    pub sponsor: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StrategyBundleResponse {
+    #[serde(flatten)]
+    pub bundle: StrategyBundle,
+    /// Gas-estimated batch for the owner to sign, the estimate simulates every step
+    pub user_operation: UserOperation,
+}
+
+/// Encode a yield strategy as one smart account batch that either runs every step or none
+async fn bundle_yield_strategy(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<StrategyBundleRequest>,
+) -> Result<Json<StrategyBundleResponse>, ApiError> {
+    let smart_accounts = state.wallet_manager.smart_accounts();
+    let account = smart_accounts.get_account(request.account).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    let bundle = state.defi_manager.bundle_optimal_yield_strategy(account.chain_id, &request.strategy, account.address).await
+        .map_err(|e| {
+            warn!("Bundling strategy {} for {:?} failed: {}", request.strategy.strategy_type, account.address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+    let user_operation = smart_accounts.build_user_operation(account.address, &bundle.smart_account_calls(), request.sponsor).await
+        .map_err(|e| {
+            warn!("Strategy bundle for {:?} would not execute: {}", account.address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(StrategyBundleResponse { bundle, user_operation }))
+}
+
+/// Execute a yield strategy step by step with the owner's server-side wallet
+async fn submit_strategy_execution(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<StrategyExecutionRequest>,
+) -> Result<Json<StrategyExecution>, ApiError> {
+    let execution = state.strategy_executions.submit(request).await
+        .map_err(|e| {
+            warn!("Strategy execution rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(execution))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StrategyExecutionsQuery {
+    pub owner: Address,
+}
+
+/// Strategy executions of an owner, newest first
+async fn list_strategy_executions(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<StrategyExecutionsQuery>,
+) -> Result<Json<Vec<StrategyExecution>>, ApiError> {
+    Ok(Json(state.strategy_executions.list(query.owner).await))
+}
+
+/// A strategy execution with the progress of each step
+async fn get_strategy_execution(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<StrategyExecution>, ApiError> {
+    state.strategy_executions.get(&id).await.map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Strategy execution {} not found", id)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResumeExecutionRequest {
+    /// Continue after the failed step instead of retrying it
+    #[serde(default)]
+    pub skip_failed_step: bool,
+}
+
+/// Continue a failed strategy execution
+async fn resume_strategy_execution(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+    SignedJson(request): SignedJson<ResumeExecutionRequest>,
+) -> Result<Json<StrategyExecution>, ApiError> {
+    if state.strategy_executions.get(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("Strategy execution {} not found", id)));
+    }
+    let execution = state.strategy_executions.resume(&id, request.skip_failed_step).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Conflict))?;
+
+    Ok(Json(execution))
+}
+
+/// Stop a strategy execution before its next step
+async fn cancel_strategy_execution(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<StrategyExecution>, ApiError> {
+    if state.strategy_executions.get(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("Strategy execution {} not found", id)));
+    }
+    let execution = state.strategy_executions.cancel(&id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Conflict))?;
+
+    Ok(Json(execution))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RewardsQuery {
+    pub chain_id: u64,
+}
+
+/// COMP and Aave rewards a user can claim, with their USD value
+async fn get_claimable_rewards(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<Address>,
+    Query(query): Query<RewardsQuery>,
+) -> Result<Json<Vec<RewardBalance>>, ApiError> {
+    Ok(Json(state.defi_manager.claimable_rewards(query.chain_id, user).await))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RewardClaimRequest {
+    pub chain_id: u64,
+    /// `aave` or `compound`
+    pub protocol: String,
+}
+
+/// Transaction claiming a user's rewards from one protocol, for the user to send
+async fn build_reward_claim(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<Address>,
+    SignedJson(request): SignedJson<RewardClaimRequest>,
+) -> Result<Json<RewardClaim>, ApiError> {
+    let claim = state.defi_manager.build_reward_claim(request.chain_id, &request.protocol, user).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(claim))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RewardCompoundingQuery {
+    pub owner: Option<Address>,
+}
+
+/// Claim, swap and supply back an owner's rewards on a schedule through the strategy executor
+async fn register_reward_compounding(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<RewardCompoundingRequest>,
+) -> Result<Json<RewardCompounding>, ApiError> {
+    let schedule = state.reward_compounder.register(request).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(schedule))
+}
+
+/// Reward compounding schedules, optionally of one owner
+async fn list_reward_compounding(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<RewardCompoundingQuery>,
+) -> Result<Json<Vec<RewardCompounding>>, ApiError> {
+    Ok(Json(state.reward_compounder.list(query.owner).await))
+}
+
+/// A reward compounding schedule with its latest round
+async fn get_reward_compounding(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<RewardCompounding>, ApiError> {
+    state.reward_compounder.get(&id).await.map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Reward compounding schedule {} not found", id)))
+}
+
+/// Stop compounding; a round already submitted keeps executing
+async fn remove_reward_compounding(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if state.reward_compounder.remove(&id).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!("Reward compounding schedule {} not found", id)))
+    }
+}
+
+/// Run a compounding round now instead of waiting for the schedule
+async fn run_reward_compounding(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<RewardCompounding>, ApiError> {
+    if state.reward_compounder.get(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("Reward compounding schedule {} not found", id)));
+    }
+    let schedule = state.reward_compounder.run(&id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(schedule))
+}
+
+/// Plan an exit of every position of a user into a stablecoin, without submitting anything
+async fn plan_portfolio_closeout(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<Address>,
+    Json(request): Json<CloseoutRequest>,
+) -> Result<Json<CloseoutPlan>, ApiError> {
+    let plan = state.defi_manager.plan_closeout(user, request).await
+        .map_err(|e| {
+            warn!("Close-out planning for {:?} failed: {}", user, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+    // Only wallets labeled for strategies may repay through a flash loan
+    if plan.steps.iter().any(|step| matches!(step.action, CloseoutAction::FlashLoanRepay { .. })) {
+        state.wallet_manager.labels().require(user, WalletUse::FlashLoan).await
+            .map_err(|e| ApiError::from_error(e, ApiError::Forbidden))?;
+    }
+
+    Ok(Json(plan))
+}
+
+/// Plan collateral placement across Aave and Compound for a target or maximum borrow
+async fn optimize_collateral(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<CollateralOptimizationRequest>,
+) -> Result<Json<CollateralPlan>, ApiError> {
+    let plan = state.defi_manager.optimize_collateral(request).await
+        .map_err(|e| {
+            warn!("Collateral optimization failed: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(plan))
+}
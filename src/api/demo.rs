@@ -0,0 +1,53 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::demo::{ScenarioOptions, ScenarioRunner, ScenarioTrace};
+
+/// Scenario run request, all fields optional
+#[derive(Deserialize, Default)]
+pub struct RunScenarioRequest {
+    pub chain_id: Option<u64>,
+    pub stop_on_failure: Option<bool>,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/scenarios", get(list_scenarios))
+        .route("/scenarios/{name}", post(run_scenario))
+}
+
+/// List built-in demo scenarios
+async fn list_scenarios() -> Json<Vec<&'static str>> {
+    Json(ScenarioRunner::list_scenarios())
+}
+
+/// Run a demo scenario and return its step-by-step trace
+async fn run_scenario(
+    State(state): State<Arc<ApiState>>,
+    Path(name): Path<String>,
+    request: Option<Json<RunScenarioRequest>>,
+) -> Result<Json<ScenarioTrace>, ApiError> {
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let defaults = ScenarioOptions::default();
+    let options = ScenarioOptions {
+        chain_id: request.chain_id.unwrap_or(defaults.chain_id),
+        stop_on_failure: request.stop_on_failure.unwrap_or(defaults.stop_on_failure),
+    };
+
+    let runner = ScenarioRunner::new(
+        state.wallet_manager.clone(),
+        state.dex_manager.clone(),
+        state.defi_manager.clone(),
+    );
+    let trace = runner.run(&name, options).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    Ok(Json(trace))
+}
@@ -7,9 +7,23 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use ethers::types::{Address, U256};
+use ethers::types::{Address, Signature, H256, U256};
+use chrono::{DateTime, Utc};
+use tracing::warn;
 
-use crate::api::{models::SwapQuote, ApiState};
+use crate::api::{ens, error::ApiError, models::SwapQuote, replay::SignedJson, tokens, ApiState};
+use crate::analytics::lp_positions::PositionEarnings;
+use crate::chains::ens::AddressOrName;
+use crate::chains::tokens::TokenMetadata;
+use crate::dex::auto_range::{AutoRangeRequest, AutoRangeStrategy};
+use crate::dex::Permit2SwapPlan;
+use crate::dex::aggregator::{DexType, PriceImpactAnalysis, QuoteMode, ServedQuote, SlippageSettings, VenueMevStats};
+use crate::dex::orders::{DcaPlan, Order, OrderRequest, OrderStatus};
+use crate::dex::pool_discovery::{TradingPairsPage, DEFAULT_PAGE_SIZE, SUPPORTED_CHAINS};
+use crate::dex::quote_accuracy::VenueQuoteAccuracy;
+use crate::dex::swap_stats::{DexStats, SwapStatsFilter};
+use crate::dex::FarmingOpportunity;
+use crate::security::{MevThreat, TokenSafetyReport, TradePriceCheck};
 
 /// Pool query parameters
 #[derive(Deserialize)]
@@ -18,15 +32,27 @@ pub struct PoolQuery {
     pub token_b: Address,
 }
 
+/// Trading pairs query parameters
+#[derive(Deserialize)]
+pub struct PairsQuery {
+    pub chain_id: u64,
+    pub offset: Option<usize>,
+    /// Pairs per page, 50 by default and at most 500
+    pub limit: Option<usize>,
+}
+
 /// Swap request
 #[derive(Deserialize)]
 pub struct SwapRequest {
     pub pool_address: Address,
     pub token_in: Address,
     pub token_out: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
     pub amount_in: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
     pub min_amount_out: U256,
-    pub recipient: Address,
+    /// Address or ENS name
+    pub recipient: AddressOrName,
 }
 
 /// Add liquidity request
@@ -35,11 +61,157 @@ pub struct AddLiquidityRequest {
     pub pool_address: Address,
     pub token_a: Address,
     pub token_b: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
     pub amount_a: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
     pub amount_b: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
     pub min_amount_a: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
     pub min_amount_b: U256,
-    pub recipient: Address,
+    /// Address or ENS name
+    pub recipient: AddressOrName,
+}
+
+/// Price impact analysis query parameters
+#[derive(Deserialize)]
+pub struct ImpactQuery {
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_in: U256,
+}
+
+/// Route comparison query parameters
+#[derive(Deserialize)]
+pub struct CompareQuotesQuery {
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_in: U256,
+    /// Address or ENS name
+    pub recipient: AddressOrName,
+    /// `fast` serves cached and approximate quotes, `exact` reads every venue
+    #[serde(default)]
+    pub mode: QuoteMode,
+}
+
+/// Route comparison with the metadata of the traded tokens
+#[derive(Serialize)]
+pub struct QuoteComparisonResponse {
+    pub token_in: TokenMetadata,
+    pub token_out: TokenMetadata,
+    /// Safety scans of the tokens missing from the token lists, to review before trading them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub token_safety: Vec<TokenSafetyReport>,
+    /// Best route's price against Chainlink and TWAP prices, `None` when the check could not run
+    pub price_check: Option<TradePriceCheck>,
+    #[serde(flatten)]
+    pub quote: ServedQuote,
+}
+
+/// Post-execution MEV analysis request
+#[derive(Deserialize)]
+pub struct AnalyzeExecutionRequest {
+    pub chain_id: u64,
+    pub dex: DexType,
+    pub pool_address: Address,
+    pub tx_hash: H256,
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_in: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_out: U256,
+    #[serde(with = "crate::api::models::ratio")]
+    pub expected_price_impact: f64,
+}
+
+/// Post-execution MEV analysis response
+#[derive(Serialize)]
+pub struct AnalyzeExecutionResponse {
+    pub sandwiched: bool,
+    pub threat: Option<MevThreat>,
+}
+
+/// Uniswap swaps to pay through Permit2
+#[derive(Deserialize)]
+pub struct Permit2SwapRequest {
+    pub chain_id: u64,
+    pub owner: Address,
+    pub swaps: Vec<Permit2SwapLeg>,
+    pub max_slippage_percentage: Option<f64>,
+    /// Sign the permit with the owner's connected wallet and return the transaction at once
+    #[serde(default)]
+    pub sign: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Permit2SwapLeg {
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_in: U256,
+}
+
+/// Permit2 plan with the owner's signature of its permit
+#[derive(Deserialize)]
+pub struct Permit2SwapCompletion {
+    pub plan: Permit2SwapPlan,
+    /// 65-byte hex signature of the plan's permit typed data
+    pub signature: Option<String>,
+}
+
+/// TWAP order submission
+#[derive(Deserialize)]
+pub struct TwapOrderRequest {
+    #[serde(flatten)]
+    pub order: OrderRequest,
+    pub slices: u32,
+    pub interval_seconds: u64,
+}
+
+/// Limit order submission
+#[derive(Deserialize)]
+pub struct LimitOrderRequest {
+    #[serde(flatten)]
+    pub order: OrderRequest,
+    /// Whole `token_out` per whole `token_in`
+    pub limit_price: f64,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// DCA plan submission, `amount_in` is the total budget
+#[derive(Deserialize)]
+pub struct DcaPlanRequest {
+    #[serde(flatten)]
+    pub order: OrderRequest,
+    /// Cron schedule in the owner's time zone, e.g. `0 9 * * MON`
+    pub schedule: String,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_per_buy: U256,
+}
+
+/// Order listing query parameters
+#[derive(Deserialize)]
+pub struct OrdersQuery {
+    pub owner: Address,
+    pub status: Option<OrderStatus>,
+}
+
+/// Liquidity position and farm query parameters
+#[derive(Deserialize)]
+pub struct LiquidityQuery {
+    pub chain_id: u64,
+    pub owner: Option<Address>,
+}
+
+/// Auto-range listing query parameters
+#[derive(Deserialize)]
+pub struct AutoRangeQuery {
+    pub owner: Option<Address>,
 }
 
 /// Pool info response
@@ -54,6 +226,7 @@ pub struct PoolInfoResponse {
     pub fee_rate: U256,
     pub volume_24h: U256,
     pub tvl: U256,
+    #[serde(with = "crate::api::models::ratio")]
     pub apr: f64,
 }
 
@@ -67,6 +240,18 @@ pub struct TokenInfo {
     pub price_usd: f64,
 }
 
+impl From<TokenMetadata> for TokenInfo {
+    fn from(token: TokenMetadata) -> Self {
+        Self {
+            address: token.address,
+            symbol: token.symbol,
+            name: token.name,
+            decimals: token.decimals,
+            price_usd: 1.0,
+        }
+    }
+}
+
 /// DEX statistics response
 #[derive(Serialize)]
 pub struct DexStatsResponse {
@@ -85,10 +270,31 @@ pub fn routes() -> Router<Arc<ApiState>> {
         .route("/{dex}/pools", get(list_pools))
         .route("/{dex}/pool", get(get_pool_info))
         .route("/quote", get(get_swap_quote))
+        .route("/quotes/compare", get(compare_quotes))
+        .route("/quotes/accuracy", get(get_quote_accuracy))
+        .route("/statistics", get(get_dex_statistics))
+        .route("/pairs", get(list_trading_pairs))
+        .route("/impact", get(analyze_trade_impact))
         .route("/swap", post(execute_swap))
+        .route("/swap/permit2", post(plan_permit2_swap))
+        .route("/swap/permit2/transaction", post(complete_permit2_swap))
         .route("/{dex}/liquidity/add", post(add_liquidity))
         .route("/{dex}/liquidity/remove", post(remove_liquidity))
         .route("/{dex}/tokens", get(list_supported_tokens))
+        .route("/farms", get(list_farming_opportunities))
+        .route("/uniswap/positions", get(list_liquidity_positions))
+        .route("/uniswap/positions/{token_id}", get(get_liquidity_position))
+        .route("/uniswap/auto-range", get(list_auto_ranges).post(register_auto_range))
+        .route("/uniswap/auto-range/{id}", get(get_auto_range).delete(remove_auto_range))
+        .route("/uniswap/auto-range/{id}/check", post(check_auto_range))
+        .route("/executions/analyze", post(analyze_execution))
+        .route("/mev/venues", get(get_venue_mev_stats))
+        .route("/orders", get(list_orders))
+        .route("/orders/twap", post(submit_twap_order))
+        .route("/orders/limit", post(submit_limit_order))
+        .route("/orders/{id}", get(get_order).delete(cancel_order))
+        .route("/dca", get(list_dca_plans).post(create_dca_plan))
+        .route("/dca/{id}", get(get_dca_plan).delete(cancel_dca_plan))
 }
 
 #[utoipa::path(
@@ -102,7 +308,7 @@ pub fn routes() -> Router<Arc<ApiState>> {
 /// List supported DEX protocols
 async fn list_dex_protocols(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Json<Vec<String>>, StatusCode> {
+) -> Result<Json<Vec<String>>, ApiError> {
     let protocols = vec![
         "uniswap-v2".to_string(),
         "uniswap-v3".to_string(),
@@ -119,9 +325,9 @@ async fn list_dex_protocols(
 async fn get_dex_stats(
     State(state): State<Arc<ApiState>>,
     Path(dex): Path<String>,
-) -> Result<Json<DexStatsResponse>, StatusCode> {
+) -> Result<Json<DexStatsResponse>, ApiError> {
     let _stats = state.dex_manager.get_protocol_stats(&dex).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     let response = DexStatsResponse {
         name: dex.clone(),
@@ -139,27 +345,17 @@ async fn get_dex_stats(
 async fn list_pools(
     State(state): State<Arc<ApiState>>,
     Path(dex): Path<String>,
-) -> Result<Json<Vec<PoolInfoResponse>>, StatusCode> {
+) -> Result<Json<Vec<PoolInfoResponse>>, ApiError> {
     let pools = state.dex_manager.get_top_pools(&dex, 50).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
-    let pool_responses: Vec<PoolInfoResponse> = pools.into_iter()
-        .map(|pool| PoolInfoResponse {
+    let chain_id = 1u64; // Default to Ethereum mainnet
+    let mut pool_responses = Vec::with_capacity(pools.len());
+    for pool in pools {
+        pool_responses.push(PoolInfoResponse {
             address: pool.address,
-            token_a: TokenInfo {
-                address: pool.token_a,
-                symbol: "TOKEN".to_string(),
-                name: "Token".to_string(),
-                decimals: 18,
-                price_usd: 1.0,
-            },
-            token_b: TokenInfo {
-                address: pool.token_b,
-                symbol: "TOKEN".to_string(),
-                name: "Token".to_string(),
-                decimals: 18,
-                price_usd: 1.0,
-            },
+            token_a: token_info(&state, chain_id, pool.token_a).await,
+            token_b: token_info(&state, chain_id, pool.token_b).await,
             reserve_a: pool.reserve_a,
             reserve_b: pool.reserve_b,
             total_supply: U256::zero(),
@@ -167,8 +363,8 @@ async fn list_pools(
             volume_24h: U256::zero(),
             tvl: U256::zero(),
             apr: 0.0,
-        })
-        .collect();
+        });
+    }
     
     Ok(Json(pool_responses))
 }
@@ -178,26 +374,16 @@ async fn get_pool_info(
     State(state): State<Arc<ApiState>>,
     Path(dex): Path<String>,
     axum::extract::Query(query): axum::extract::Query<PoolQuery>,
-) -> Result<Json<PoolInfoResponse>, StatusCode> {
+) -> Result<Json<PoolInfoResponse>, ApiError> {
+    let chain_id = 1u64; // Default to Ethereum mainnet
+    let (token_a, token_b) = tokens::validate_pair(&state, chain_id, query.token_a, query.token_b).await?;
     let pool = state.dex_manager.get_pool_info(&dex, query.token_a, query.token_b).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     let response = PoolInfoResponse {
         address: pool.address,
-        token_a: TokenInfo {
-            address: query.token_a,
-            symbol: "TOKEN_A".to_string(),
-            name: "Token A".to_string(),
-            decimals: 18,
-            price_usd: 1.0,
-        },
-        token_b: TokenInfo {
-            address: query.token_b,
-            symbol: "TOKEN_B".to_string(),
-            name: "Token B".to_string(),
-            decimals: 18,
-            price_usd: 1.0,
-        },
+        token_a: token_a.into(),
+        token_b: token_b.into(),
         reserve_a: pool.reserve_a,
         reserve_b: pool.reserve_b,
         total_supply: U256::zero(),
@@ -215,7 +401,8 @@ async fn add_liquidity(
     State(state): State<Arc<ApiState>>,
     Path(dex): Path<String>,
     Json(request): Json<AddLiquidityRequest>,
-) -> Result<Json<String>, StatusCode> {
+) -> Result<Json<String>, ApiError> {
+    let recipient = ens::resolve(&state, &request.recipient).await?;
     let tx_hash = state.dex_manager.add_liquidity(
         &dex,
         request.token_a,
@@ -224,9 +411,9 @@ async fn add_liquidity(
         request.amount_b,
         request.min_amount_a,
         request.min_amount_b,
-        request.recipient,
+        recipient,
     ).await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     Ok(Json(format!("{:#x}", tx_hash)))
 }
@@ -236,7 +423,8 @@ async fn remove_liquidity(
     State(state): State<Arc<ApiState>>,
     Path(dex): Path<String>,
     Json(request): Json<AddLiquidityRequest>,
-) -> Result<Json<String>, StatusCode> {
+) -> Result<Json<String>, ApiError> {
+    let recipient = ens::resolve(&state, &request.recipient).await?;
     let tx_hash = state.dex_manager.remove_liquidity(
         &dex,
         request.token_a,
@@ -244,9 +432,9 @@ async fn remove_liquidity(
         request.amount_a,
         request.min_amount_a,
         request.min_amount_b,
-        request.recipient,
+        recipient,
     ).await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
     
     Ok(Json(format!("{:#x}", tx_hash)))
 }
@@ -254,22 +442,394 @@ async fn remove_liquidity(
 /// List supported tokens
 async fn list_supported_tokens(
     State(state): State<Arc<ApiState>>,
-    Path(dex): Path<String>,
-) -> Result<Json<Vec<TokenInfo>>, StatusCode> {
-    let tokens = state.dex_manager.get_supported_tokens(&dex).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let token_infos: Vec<TokenInfo> = tokens.into_iter()
-        .map(|token| TokenInfo {
-            address: token.address,
-            symbol: token.symbol,
-            name: token.name,
-            decimals: token.decimals,
-            price_usd: 1.0,
-        })
+    Path(_dex): Path<String>,
+) -> Json<Vec<TokenInfo>> {
+    let chain_id = 1u64; // Default to Ethereum mainnet
+    let token_infos = state.tokens.list(Some(chain_id), None).await
+        .into_iter()
+        .map(TokenInfo::from)
         .collect();
     
-    Ok(Json(token_infos))
+    Json(token_infos)
+}
+
+/// Analyze an executed swap for sandwich attacks
+async fn analyze_execution(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<AnalyzeExecutionRequest>,
+) -> Result<Json<AnalyzeExecutionResponse>, ApiError> {
+    let threat = state.dex_manager.analyze_execution(
+        request.chain_id,
+        request.dex,
+        request.pool_address,
+        request.tx_hash,
+        request.token_in,
+        request.token_out,
+        request.amount_in,
+        request.amount_out,
+        request.expected_price_impact,
+    ).await
+    .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    if let Some(threat) = &threat {
+        state.security.record_mev_threat(threat.clone()).await
+            .map_err(ApiError::internal)?;
+    }
+
+    Ok(Json(AnalyzeExecutionResponse {
+        sandwiched: threat.is_some(),
+        threat,
+    }))
+}
+
+/// Quotes from every local venue and the configured external aggregators
+async fn compare_quotes(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<CompareQuotesQuery>,
+) -> Result<Json<QuoteComparisonResponse>, ApiError> {
+    let (token_in, token_out) = tokens::validate_pair(&state, query.chain_id, query.token_in, query.token_out).await?;
+    let recipient = ens::resolve(&state, &query.recipient).await?;
+    let quote = state.dex_manager.get_quotes(
+        query.chain_id,
+        query.token_in,
+        query.token_out,
+        query.amount_in,
+        recipient,
+        query.mode,
+    ).await
+    .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+    let token_safety = tokens::unlisted_safety(&state, &[token_in.clone(), token_out.clone()]).await?;
+    let best_route = &quote.comparison.best_route;
+    let price_check = state.price_guard.check_trade(
+        query.chain_id,
+        query.token_in,
+        best_route.input_amount,
+        query.token_out,
+        best_route.output_amount,
+    ).await
+        .map_err(|e| warn!("Oracle price check of quote failed: {}", e))
+        .ok();
+
+    Ok(Json(QuoteComparisonResponse { token_in, token_out, token_safety, price_check, quote }))
+}
+
+/// Price impact of a trade with the slippage recommended for its pool
+async fn analyze_trade_impact(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<ImpactQuery>,
+) -> Result<Json<PriceImpactAnalysis>, ApiError> {
+    tokens::validate_pair(&state, query.chain_id, query.token_in, query.token_out).await?;
+    let analysis = state.dex_manager.analyze_trade_impact(
+        query.chain_id,
+        query.token_in,
+        query.token_out,
+        query.amount_in,
+    ).await
+    .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+
+    Ok(Json(analysis))
+}
+
+/// Plan Uniswap swaps paid with a Permit2 signature instead of router approvals
+async fn plan_permit2_swap(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<Permit2SwapRequest>,
+) -> Result<Json<Permit2SwapPlan>, ApiError> {
+    for leg in &request.swaps {
+        tokens::validate_tradeable_pair(&state, request.chain_id, leg.token_in, leg.token_out).await?;
+    }
+    let swaps = request.swaps.iter().map(|leg| (leg.token_in, leg.token_out, leg.amount_in)).collect();
+    let slippage = request.max_slippage_percentage.map(|max_slippage_percentage| SlippageSettings {
+        max_slippage_percentage,
+        ..SlippageSettings::default()
+    });
+    let plan = state.dex_manager.plan_permit2_swaps(request.chain_id, request.owner, swaps, slippage).await
+        .map_err(|e| {
+            warn!("Permit2 swap planning failed: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+    for swap in &plan.swaps {
+        let (Some(token_in), Some(token_out)) = (swap.quote.path.first(), swap.quote.path.last()) else {
+            continue;
+        };
+        state.circuit_breakers.observe_trade(state.price_guard.validate_trade(
+            request.chain_id, *token_in, swap.quote.input_amount, *token_out, swap.quote.output_amount,
+        ).await).await
+            .map_err(|e| ApiError::from_error(e, ApiError::Upstream))?;
+    }
+
+    let Some(permit) = plan.permit.as_ref().filter(|_| request.sign) else {
+        return Ok(Json(plan));
+    };
+    let signature = state.wallet_manager.sign_typed_data(request.owner, &permit.typed_data).await
+        .map_err(|e| {
+            warn!("Permit2 permit signing failed: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+    let plan = state.dex_manager.complete_permit2_swaps(plan, Some(signature)).await
+        .map_err(|e| {
+            warn!("Permit2 swap rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(plan))
+}
+
+/// Universal Router transaction of a Permit2 plan once its permit is signed
+async fn complete_permit2_swap(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<Permit2SwapCompletion>,
+) -> Result<Json<Permit2SwapPlan>, ApiError> {
+    let signature = request.signature
+        .map(|signature| signature.trim_start_matches("0x").parse::<Signature>())
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid permit signature: {}", e)))?;
+    let plan = state.dex_manager.complete_permit2_swaps(request.plan, signature).await
+        .map_err(|e| {
+            warn!("Permit2 swap rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(plan))
+}
+
+/// Split a swap into equal slices executed at a fixed interval
+async fn submit_twap_order(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<TwapOrderRequest>,
+) -> Result<Json<Order>, ApiError> {
+    tokens::validate_tradeable_pair(&state, request.order.chain_id, request.order.token_in, request.order.token_out).await?;
+    let order = state.orders.submit_twap(request.order, request.slices, request.interval_seconds).await
+        .map_err(|e| {
+            warn!("TWAP order rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(order))
+}
+
+/// Swap once the best quote reaches a target price
+async fn submit_limit_order(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<LimitOrderRequest>,
+) -> Result<Json<Order>, ApiError> {
+    tokens::validate_tradeable_pair(&state, request.order.chain_id, request.order.token_in, request.order.token_out).await?;
+    let order = state.orders.submit_limit(request.order, request.limit_price, request.expires_at).await
+        .map_err(|e| {
+            warn!("Limit order rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(order))
+}
+
+/// Buy on a recurring schedule until the budget is spent
+async fn create_dca_plan(
+    State(state): State<Arc<ApiState>>,
+    SignedJson(request): SignedJson<DcaPlanRequest>,
+) -> Result<Json<DcaPlan>, ApiError> {
+    tokens::validate_tradeable_pair(&state, request.order.chain_id, request.order.token_in, request.order.token_out).await?;
+    let order = state.orders.submit_dca(request.order, request.schedule, request.amount_per_buy).await
+        .map_err(|e| {
+            warn!("DCA plan rejected: {}", e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    state.orders.dca_plan(&order.id).await.map(Json)
+        .ok_or_else(|| ApiError::internal(format!("DCA plan {} missing after submission", order.id)))
+}
+
+/// DCA plans of an owner with budget spent and average entry price
+async fn list_dca_plans(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<OrdersQuery>,
+) -> Result<Json<Vec<DcaPlan>>, ApiError> {
+    Ok(Json(state.orders.dca_plans(query.owner, query.status).await))
+}
+
+async fn get_dca_plan(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<DcaPlan>, ApiError> {
+    state.orders.dca_plan(&id).await.map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("DCA plan {} not found", id)))
+}
+
+/// Stop a DCA plan, buys already made stay in its summary
+async fn cancel_dca_plan(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<DcaPlan>, ApiError> {
+    if state.orders.dca_plan(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("DCA plan {} not found", id)));
+    }
+    state.orders.cancel(&id).await.map_err(|e| ApiError::from_error(e, ApiError::Conflict))?;
+
+    state.orders.dca_plan(&id).await.map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("DCA plan {} not found", id)))
+}
+
+/// Farms with the impermanent loss a deposit would take over a range of price moves
+async fn list_farming_opportunities(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<LiquidityQuery>,
+) -> Result<Json<Vec<FarmingOpportunity>>, ApiError> {
+    let farms = state.dex_manager
+        .get_farming_opportunities(query.chain_id, query.owner.unwrap_or_default(), &state.analytics.price_feeds)
+        .await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(farms))
+}
+
+/// Uniswap V3 positions of an owner with their value, fees, fee APR and live impermanent loss
+async fn list_liquidity_positions(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<LiquidityQuery>,
+) -> Result<Json<Vec<PositionEarnings>>, ApiError> {
+    let owner = query.owner.ok_or_else(|| ApiError::BadRequest("owner is required".to_string()))?;
+    let positions = state.lp_positions.positions(query.chain_id, owner).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(positions))
+}
+
+async fn get_liquidity_position(
+    State(state): State<Arc<ApiState>>,
+    Path(token_id): Path<U256>,
+    axum::extract::Query(query): axum::extract::Query<LiquidityQuery>,
+) -> Result<Json<PositionEarnings>, ApiError> {
+    let position = state.lp_positions.position(query.chain_id, token_id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(position))
+}
+
+/// Recenter a Uniswap V3 position whenever the price drifts to the edge of its range
+async fn register_auto_range(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<AutoRangeRequest>,
+) -> Result<Json<AutoRangeStrategy>, ApiError> {
+    let strategy = state.auto_range.register(request).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(strategy))
+}
+
+/// Auto-range strategies, optionally of one owner
+async fn list_auto_ranges(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<AutoRangeQuery>,
+) -> Result<Json<Vec<AutoRangeStrategy>>, ApiError> {
+    Ok(Json(state.auto_range.list(query.owner).await))
+}
+
+/// An auto-range strategy with its pending rebalance and history
+async fn get_auto_range(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<AutoRangeStrategy>, ApiError> {
+    state.auto_range.get(&id).await
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Auto-range strategy {} not found", id)))
+}
+
+/// Stop managing a position; a pending rebalance stays in the transaction history
+async fn remove_auto_range(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if state.auto_range.remove(&id).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!("Auto-range strategy {} not found", id)))
+    }
+}
+
+/// Check a strategy now instead of waiting for the next interval
+async fn check_auto_range(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<AutoRangeStrategy>, ApiError> {
+    if state.auto_range.get(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("Auto-range strategy {} not found", id)));
+    }
+    let strategy = state.auto_range.check(&id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(strategy))
+}
+
+/// Orders of an owner, newest first
+async fn list_orders(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<OrdersQuery>,
+) -> Result<Json<Vec<Order>>, ApiError> {
+    Ok(Json(state.orders.list(query.owner, query.status).await))
+}
+
+/// An order with its fills so far
+async fn get_order(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Order>, ApiError> {
+    state.orders.get(&id).await.map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", id)))
+}
+
+/// Cancel an open order
+async fn cancel_order(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Order>, ApiError> {
+    if state.orders.get(&id).await.is_none() {
+        return Err(ApiError::NotFound(format!("Order {} not found", id)));
+    }
+    let order = state.orders.cancel(&id).await.map_err(|e| ApiError::from_error(e, ApiError::Conflict))?;
+
+    Ok(Json(order))
+}
+
+/// Get observed MEV losses per venue
+async fn get_venue_mev_stats(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<std::collections::HashMap<DexType, VenueMevStats>>, ApiError> {
+    Ok(Json(state.dex_manager.aggregator().get_venue_mev_stats().await))
+}
+
+/// Statistics of the quotes served and swaps built, optionally for one chain, venue and time window
+async fn get_dex_statistics(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(filter): axum::extract::Query<SwapStatsFilter>,
+) -> Result<Json<DexStats>, ApiError> {
+    if let (Some(from), Some(to)) = (filter.from, filter.to) {
+        if from >= to {
+            return Err(ApiError::BadRequest("from must be before to".to_string()));
+        }
+    }
+    Ok(Json(state.dex_manager.get_dex_statistics(&filter).await))
+}
+
+/// List the chain's pools across venues, highest value locked first
+async fn list_trading_pairs(
+    State(state): State<Arc<ApiState>>,
+    axum::extract::Query(query): axum::extract::Query<PairsQuery>,
+) -> Result<Json<TradingPairsPage>, ApiError> {
+    if !SUPPORTED_CHAINS.contains(&query.chain_id) {
+        return Err(ApiError::BadRequest(format!("Pool discovery does not support chain {}", query.chain_id)));
+    }
+    state.pool_discovery
+        .pairs(query.chain_id, query.offset.unwrap_or(0), query.limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .await
+        .map(Json)
+        .map_err(|e| ApiError::from_error(e, ApiError::Upstream))
+}
+
+/// Get how far each venue's quotes missed the simulated and settled outputs
+async fn get_quote_accuracy(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<std::collections::HashMap<DexType, VenueQuoteAccuracy>>, ApiError> {
+    Ok(Json(state.dex_manager.aggregator().get_quote_accuracy().await))
 }
 
 #[utoipa::path(
@@ -308,3 +868,17 @@ pub async fn execute_swap(
         "tx_hash": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
     }))
 }
+
+/// Registry metadata of a pool token, its address standing in for the symbol of unknown tokens
+async fn token_info(state: &ApiState, chain_id: u64, token: Address) -> TokenInfo {
+    match state.tokens.get(chain_id, token).await {
+        Some(metadata) => TokenInfo::from(metadata),
+        None => TokenInfo {
+            address: token,
+            symbol: format!("{:?}", token),
+            name: "Unknown token".to_string(),
+            decimals: 18,
+            price_usd: 1.0,
+        },
+    }
+}
@@ -1,6 +1,5 @@
 use axum::{
     extract::State,
-    http::StatusCode,
     response::{Html, Json},
     routing::get,
     Router,
@@ -8,7 +7,7 @@ use axum::{
 use serde_json::json;
 use std::sync::Arc;
 
-use crate::api::ApiState;
+use crate::api::{error::ApiError, ApiState};
 
 pub fn routes() -> Router<Arc<ApiState>> {
     Router::new()
@@ -19,7 +18,7 @@ pub fn routes() -> Router<Arc<ApiState>> {
 /// Get API documentation homepage
 async fn get_api_docs(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Html<String>, StatusCode> {
+) -> Result<Html<String>, ApiError> {
     let html = r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -165,7 +164,7 @@ async fn get_api_docs(
 /// Get OpenAPI specification
 async fn get_openapi_spec(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     let spec = json!({
         "info": {
             "title": "Blockchain Demo API",
@@ -287,7 +286,18 @@ async fn get_openapi_spec(
                     "properties": {
                         "token_in": {"type": "string"},
                         "token_out": {"type": "string"},
-                        "amount_in": {"type": "string"}
+                        "amount_in": {
+                            "type": "string",
+                            "description": "Base units as 0x-prefixed hex or decimal string"
+                        }
+                    }
+                },
+                "TokenAmount": {
+                    "type": "object",
+                    "properties": {
+                        "raw": {"type": "string", "description": "Base units, 0x-prefixed hex"},
+                        "decimals": {"type": "integer"},
+                        "formatted": {"type": "string", "description": "Exact amount in whole tokens"}
                     }
                 },
                 "SecurityAnalysisRequest": {
@@ -307,7 +317,7 @@ async fn get_openapi_spec(
 /// Get Swagger UI
 async fn get_swagger_ui(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Html<String>, StatusCode> {
+) -> Result<Html<String>, ApiError> {
     let html = r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -0,0 +1,30 @@
+// ENS names accepted in place of addresses in paths and request fields
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use ethers::types::Address;
+use std::sync::Arc;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::chains::ens::{AddressOrName, UnresolvedName};
+
+/// `{address}` path parameter given as hex or an ENS name, resolved to the address
+pub struct AddressPath(pub Address);
+
+impl FromRequestParts<Arc<ApiState>> for AddressPath {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<ApiState>) -> Result<Self, Self::Rejection> {
+        let Path(target) = Path::<AddressOrName>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+        Ok(Self(resolve(state, &target).await?))
+    }
+}
+
+/// Address of a name, `404` when the name has none
+pub async fn resolve(state: &ApiState, target: &AddressOrName) -> Result<Address, ApiError> {
+    state.ens.resolve(target).await.map_err(|e| match e.downcast_ref::<UnresolvedName>() {
+        Some(unresolved) => ApiError::NotFound(unresolved.to_string()),
+        None => ApiError::from_error(e, ApiError::Upstream),
+    })
+}
@@ -0,0 +1,202 @@
+// API errors rendered as RFC 7807 problem details
+use axum::{
+    http::{header::{CONTENT_TYPE, RETRY_AFTER}, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::fmt;
+use tracing::{error, warn};
+use utoipa::ToSchema;
+
+use crate::chains::ChainUnavailable;
+use crate::security::{PriceDeviation, SanctionedCounterparty, SpendingPolicyRefused, TradingHalted};
+use crate::wallets::labels::WalletUseDenied;
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Error returned by an API handler, with the status and code clients branch on
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    /// Well-formed request whose content cannot be processed, e.g. invalid typed data
+    Unprocessable(String),
+    /// Resource that has to be unlocked first, e.g. a locked Ledger
+    Locked(String),
+    RateLimited { retry_after: u64 },
+    /// A chain's RPC cannot be reached, retried after `retry_after` seconds
+    ChainUnavailable { chain_id: u64, message: String, retry_after: u64 },
+    /// A dependency other than a chain is unavailable, e.g. no Ledger connected
+    Unavailable(String),
+    /// An RPC node, bundler, explorer or other upstream service failed
+    Upstream(String),
+    /// Unexpected failure, logged but not detailed to the client
+    Internal(String),
+}
+
+/// RFC 7807 problem details body
+#[derive(Serialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Stable machine-readable error code
+    pub code: &'static str,
+    /// Whether the same request may succeed later
+    pub retryable: bool,
+    /// Chain the error is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// Seconds to wait before retrying
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+impl ApiError {
+    /// Classify a failed call: unreachable chains, wallet labels forbidding the use, sanctioned
+    /// counterparties, spending policy refusals, off-oracle prices and trading halts get their own errors, anything else becomes `fallback` with the error's message
+    pub fn from_error(error: anyhow::Error, fallback: fn(String) -> ApiError) -> Self {
+        if let Some(unavailable) = error.downcast_ref::<ChainUnavailable>() {
+            warn!("{}", unavailable);
+            return ApiError::ChainUnavailable {
+                chain_id: unavailable.chain_id,
+                message: unavailable.to_string(),
+                retry_after: unavailable.retry_after.as_secs().max(1),
+            };
+        }
+        if let Some(denied) = error.downcast_ref::<WalletUseDenied>() {
+            return ApiError::Forbidden(denied.to_string());
+        }
+        if let Some(sanctioned) = error.downcast_ref::<SanctionedCounterparty>() {
+            return ApiError::Forbidden(sanctioned.to_string());
+        }
+        if let Some(refused) = error.downcast_ref::<SpendingPolicyRefused>() {
+            return ApiError::Forbidden(refused.to_string());
+        }
+        if let Some(deviation) = error.downcast_ref::<PriceDeviation>() {
+            return ApiError::Unprocessable(deviation.to_string());
+        }
+        if let Some(halted) = error.downcast_ref::<TradingHalted>() {
+            return ApiError::Locked(halted.to_string());
+        }
+        fallback(format!("{:#}", error))
+    }
+
+    pub fn internal(error: impl fmt::Display) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Locked(_) => StatusCode::LOCKED,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ChainUnavailable { .. } | ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Unprocessable(_) => "unprocessable",
+            ApiError::Locked(_) => "locked",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::ChainUnavailable { .. } => "chain_unavailable",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiError::RateLimited { .. } | ApiError::ChainUnavailable { .. } | ApiError::Unavailable(_) | ApiError::Upstream(_)
+        )
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            ApiError::RateLimited { retry_after } | ApiError::ChainUnavailable { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Unprocessable(message)
+            | ApiError::Locked(message)
+            | ApiError::ChainUnavailable { message, .. }
+            | ApiError::Unavailable(message)
+            | ApiError::Upstream(message) => message.clone(),
+            ApiError::RateLimited { retry_after } => format!("Too many requests, retry in {}s", retry_after),
+            ApiError::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+
+    pub fn problem(&self) -> ProblemDetails {
+        let status = self.status();
+        ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code: self.code(),
+            retryable: self.retryable(),
+            chain_id: match self {
+                ApiError::ChainUnavailable { chain_id, .. } => Some(*chain_id),
+                _ => None,
+            },
+            retry_after: self.retry_after(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Internal(message) => write!(f, "{}", message),
+            _ => write!(f, "{}", self.detail()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let ApiError::Internal(message) = &self {
+            error!("Request failed: {}", message);
+        }
+        let retry_after = self.retry_after();
+        let mut response = (self.status(), Json(self.problem())).into_response();
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+        if let Some(seconds) = retry_after {
+            headers.insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
@@ -0,0 +1,35 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::transactions::settlement::SettlementReport;
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/{id}/settlement", get(get_settlement))
+}
+
+/// Settlement report of an executed bundle, `409` until every transaction of it settled
+async fn get_settlement(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<SettlementReport>, ApiError> {
+    if state.transactions.execution(&id).await.is_empty() {
+        return Err(ApiError::NotFound(format!("Execution {} not found", id)));
+    }
+
+    match state.settlements.report(&id).await {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err(ApiError::Conflict(format!("Execution {} has not settled yet", id))),
+        Err(e) => {
+            warn!("Settlement of execution {} failed: {}", id, e);
+            Err(ApiError::from_error(e, ApiError::Upstream))
+        }
+    }
+}
@@ -4,6 +4,8 @@ use std::sync::Arc;
 use utoipa::ToSchema;
 
 use crate::api::ApiState;
+use crate::chains::pool::EndpointStats;
+use crate::contracts::probes::DeploymentCheck;
 
 #[derive(Serialize, ToSchema)]
 pub struct HealthResponse {
@@ -19,15 +21,26 @@ pub struct ServiceStatus {
     pub database: bool,
     pub dex_services: bool,
     pub defi_services: bool,
+    /// Protocol addresses whose deployed contract does not match the expected interface
+    #[schema(value_type = Vec<Object>)]
+    pub misconfigured_contracts: Vec<DeploymentCheck>,
 }
 
 #[derive(Serialize, ToSchema)]
 pub struct ChainHealth {
     pub chain_id: u64,
     pub name: String,
+    /// "active", or "unavailable" while the chain's RPC cannot be reached
+    pub status: String,
     pub rpc_healthy: bool,
     pub block_height: Option<u64>,
     pub gas_price: Option<String>,
+    pub error: Option<String>,
+    /// Load of each RPC endpoint of the chain, empty while it is unavailable
+    #[schema(value_type = Vec<Object>)]
+    pub rpc_endpoints: Vec<EndpointStats>,
+    /// Every request slot of every endpoint is taken and requests are queueing
+    pub rpc_saturated: bool,
 }
 
 pub fn routes() -> Router<Arc<ApiState>> {
@@ -44,9 +57,16 @@ pub fn routes() -> Router<Arc<ApiState>> {
 )]
 pub async fn health_check(State(state): State<Arc<ApiState>>) -> Json<HealthResponse> {
     let chains = state.chain_manager.health_check().await;
-    
+    let misconfigured_contracts: Vec<DeploymentCheck> = state.deployments.checks().await
+        .into_iter()
+        .filter(|check| check.status.is_misconfigured())
+        .collect();
+    // Unreachable or saturated chains and misconfigured addresses degrade the service instead of taking it down
+    let healthy = chains.iter().all(|chain| chain.rpc_healthy && !chain.rpc_saturated) && misconfigured_contracts.is_empty();
+    let status = if healthy { "healthy" } else { "degraded" };
+
     let response = HealthResponse {
-        status: "healthy".to_string(),
+        status: status.to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         timestamp: chrono::Utc::now().to_rfc3339(),
         services: ServiceStatus {
@@ -54,6 +74,7 @@ pub async fn health_check(State(state): State<Arc<ApiState>>) -> Json<HealthResp
             database: true, // TODO: Implement actual DB health check
             dex_services: true, // TODO: Implement actual DEX health check
             defi_services: true, // TODO: Implement actual DeFi health check
+            misconfigured_contracts,
         },
     };
 
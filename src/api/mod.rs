@@ -1,35 +1,169 @@
 use anyhow::Result;
+use axum::middleware;
 use std::sync::Arc;
 use ethers::providers::{Provider, Http};
 use tracing::info;
 
+pub mod admin;
+pub mod analytics;
+pub mod auth;
 pub mod chains;
+pub mod contracts;
 pub mod defi;
+pub mod demo;
 pub mod dex;
 pub mod docs;
+pub mod ens;
+pub mod error;
+pub mod executions;
 pub mod health;
 pub mod models;
+pub mod monitor;
 pub mod portfolio;
+pub mod rate_limit;
+pub mod replay;
 pub mod security;
+pub mod simulate;
+pub mod tenants;
+pub mod tokens;
+pub mod transactions;
 pub mod wallets;
 
+use crate::cache::CacheManager;
 use crate::chains::ChainManager;
+use crate::chains::assets::AssetRegistry;
+use crate::chains::ens::EnsResolver;
+use crate::chains::fork::ForkConfig;
+use crate::chains::indexer::EventLogIndexer;
+use crate::chains::reorg::ReorgMonitor;
+use crate::chains::tokens::TokenRegistry;
+use crate::chains::tx_broadcaster::TxBroadcaster;
+use crate::contracts::{ContractManager, ExplorerConfig};
+use crate::contracts::approvals::ApprovalPolicy;
+use crate::contracts::probes::DeploymentProber;
 use crate::dex::DexManager;
-use crate::wallets::WalletManager;
+use crate::dex::auto_range::AutoRangeManager;
+use crate::dex::aggregator::external::ExternalAggregatorConfig;
+use crate::dex::orders::OrderEngine;
+use crate::dex::pool_discovery::{PoolDiscovery, PoolDiscoveryConfig};
+use crate::dex::quote_accuracy::QuoteAccuracyMonitor;
+use crate::dex::swap_stats::SwapRecorder;
+use crate::wallets::{
+    labels::WalletLabels,
+    multisig::{MultiSigManager, SafeConfig},
+    smart_account::{SmartAccountConfig, SmartAccountManager},
+    walletconnect::WalletConnectConfig,
+    WalletManager,
+};
+use crate::defi::rate_history::{RateHistory, RateHistoryCollector};
+use crate::defi::reward_compounder::RewardCompounder;
 use crate::defi::DefiManager;
 use crate::analytics::AnalyticsService;
-use crate::security::SecurityManager;
+use crate::analytics::backtest::BacktestService;
+use crate::analytics::risk_assessor::{RiskAssessor, RiskAssessorConfig};
+use crate::analytics::lp_positions::LpPositionTracker;
+use crate::analytics::nft_portfolio::NftPortfolioService;
+use crate::analytics::snapshotter::{PortfolioSnapshotter, SnapshotterConfig};
+use crate::analytics::tax_export::TaxExporter;
+use crate::analytics::token_balances::TokenBalanceScanner;
+use crate::security::{
+    ApprovalScanner, AuditTrailConfig, CircuitBreakerConfig, CircuitBreakers, ContractAnalyzer, EmergencyPlaybooks,
+    MarketDataConfig, MarketDataSources, MempoolWatcher, NotificationConfig, PlaybookConfig, PriceGuardConfig,
+    SanctionsScreener, SecurityManager, SpendingPolicyConfig, TokenSafetyScanner, TradePriceGuard,
+};
+use crate::defi::arbitrage::{ArbitrageConfig, ArbitrageEngine};
+use crate::defi::bridge::BridgeManager;
+use crate::defi::compound_borrowers::CompoundBorrowerIndex;
+use crate::defi::strategy_executor::{StrategyExecutor, StrategyExecutorConfig};
+use crate::jobs::backfill::BackfillOrchestrator;
+use crate::jobs::JobManager;
+use crate::transactions::{settlement::SettlementReporter, TransactionTracker};
+use crate::monitor::{MonitorConfig, PositionMonitor};
+use self::auth::ApiKeyStore;
+use self::rate_limit::RateLimiter;
+use self::replay::ReplayGuard;
 // use crate::websocket::WebSocketState; // Temporarily disabled
 
 /// Central application state containing all managers and services
 #[derive(Clone)]
 pub struct ApiState {
     pub chain_manager: Arc<ChainManager>,
+    /// ENS names accepted for addresses and shown for them in responses
+    pub ens: Arc<EnsResolver>,
+    /// Symbols, decimals and logos of tokens from token lists and custom additions
+    pub tokens: Arc<TokenRegistry>,
+    /// Uniswap V3 pools and SushiSwap pairs of each chain, ranked by value locked
+    pub pool_discovery: Arc<PoolDiscovery>,
     pub dex_manager: Arc<DexManager>,
     pub wallet_manager: Arc<WalletManager>,
     pub defi_manager: Arc<DefiManager>,
     pub analytics: Arc<AnalyticsService>,
     pub security: Arc<SecurityManager>,
+    /// Sanctions lists every transaction counterparty is screened against
+    pub sanctions: Arc<SanctionsScreener>,
+    /// Honeypot and scam checks run before unknown tokens are traded
+    pub token_safety: Arc<TokenSafetyScanner>,
+    /// Chainlink and TWAP cross-check of the prices swaps and liquidations trade at
+    pub price_guard: Arc<TradePriceGuard>,
+    /// Breakers halting trading routes and the order engine on threat, simulation, gas or oracle spikes
+    pub circuit_breakers: Arc<CircuitBreakers>,
+    /// Outstanding token approvals of any wallet, rated by spender
+    pub approvals: Arc<ApprovalScanner>,
+    /// Protective transactions prepared for the wallets of critical alerts
+    pub playbooks: Arc<EmergencyPlaybooks>,
+    pub contracts: Arc<ContractManager>,
+    pub broadcaster: Arc<TxBroadcaster>,
+    pub orders: Arc<OrderEngine>,
+    /// Yield strategies executed one confirmed step at a time
+    pub strategy_executions: Arc<StrategyExecutor>,
+    /// Lending rewards claimed, swapped and supplied back on a schedule
+    pub reward_compounder: Arc<RewardCompounder>,
+    pub transactions: Arc<TransactionTracker>,
+    /// Settlement reports of executed bundles
+    pub settlements: Arc<SettlementReporter>,
+    /// Settled swap outputs fed back into the aggregator's quote error distribution
+    pub quote_accuracy: Arc<QuoteAccuracyMonitor>,
+    /// Aave and Compound rates sampled for the trailing averages yields are ranked by
+    pub rate_collector: Arc<RateHistoryCollector>,
+    pub jobs: Arc<JobManager>,
+    pub backfills: Arc<BackfillOrchestrator>,
+    pub compound_borrowers: Arc<CompoundBorrowerIndex>,
+    /// Across transfers between chains, followed until filled
+    pub bridge: Arc<BridgeManager>,
+    /// Decoded events of registered contracts, reorg-aware
+    pub indexer: Arc<EventLogIndexer>,
+    /// Recent block hashes per chain, reorganizations handed to the indexer, transactions and caches
+    pub reorgs: Arc<ReorgMonitor>,
+    pub monitor: Arc<PositionMonitor>,
+    /// Cross-DEX round trips refreshed in the background
+    pub arbitrage: Arc<ArbitrageEngine>,
+    /// Strategy replays over archive and stored market history
+    pub backtests: Arc<BacktestService>,
+    /// Value-at-Risk of lending positions from the stored market history
+    pub risk_assessor: Arc<RiskAssessor>,
+    /// Value, fees and fee APR of Uniswap V3 positions
+    pub lp_positions: Arc<LpPositionTracker>,
+    /// Scheduled portfolio snapshots of tracked wallets
+    pub snapshotter: Arc<PortfolioSnapshotter>,
+    /// Tax reports of wallets' trades, liquidity events, income and liquidations
+    pub tax_exports: Arc<TaxExporter>,
+    /// ERC-20 and native balances of any wallet across chains
+    pub token_balances: Arc<TokenBalanceScanner>,
+    /// NFTs of any wallet with their metadata and floor value
+    pub nfts: Arc<NftPortfolioService>,
+    /// Uniswap V3 positions recentered when they drift out of range
+    pub auto_range: Arc<AutoRangeManager>,
+    pub mempool: Arc<MempoolWatcher>,
+    /// Interface checks of the protocol addresses in use
+    pub deployments: Arc<DeploymentProber>,
+    /// Token required by admin endpoints; admin API is disabled when unset
+    pub admin_token: Option<String>,
+    /// Signed nonces required by broadcast and execution endpoints
+    pub replay_guard: Arc<ReplayGuard>,
+    /// API keys required by the portfolio, DEX, DeFi and security routes when authentication is enabled
+    pub api_keys: Arc<ApiKeyStore>,
+    /// Request budgets per API key or client IP
+    pub rate_limiter: Arc<RateLimiter>,
     // pub websocket: Arc<WebSocketState>, // Temporarily disabled
 }
 
@@ -37,37 +171,340 @@ impl ApiState {
     pub async fn new(config: config::Config) -> Result<Self> {
         info!("Initializing API state with configuration");
         
-        // Initialize all managers with error tolerance for demo mode
-        let wallet_manager = Arc::new(WalletManager::new(None).await?);
-        let analytics = Arc::new(AnalyticsService::new(&config).await?);
+        // Fork and live modes run every manager against real chains sharing one chain manager;
+        // live chains connect on first use so an RPC outage only degrades that chain
+        let shared_chain_manager = match ForkConfig::from_config(&config) {
+            Some(fork_config) => Some(ChainManager::new_fork(&fork_config).await?),
+            None if config.get_bool("live_chains").unwrap_or(false) => Some(ChainManager::new(&config).await?),
+            None => None,
+        };
+
+        // Quotes, reserve and pool data, shared across replicas when Redis is configured
+        let caches = CacheManager::from_config(&config)?;
+        let rate_history = Arc::new(RateHistory::from_config(&config).await?);
+
+        let (chain_manager, transactions, analytics, dex_manager, bridge, defi_manager) = match shared_chain_manager {
+            Some(chain_manager) => {
+                let chain_manager = Arc::new(chain_manager);
+                let analytics = Arc::new(AnalyticsService::with_chain_manager(&config, chain_manager.clone()).await?);
+                let transactions = Arc::new(
+                    TransactionTracker::from_config(&config, chain_manager.clone(), analytics.gas_costs.clone()).await?,
+                );
+                let dex_manager = Arc::new(DexManager::new(
+                    chain_manager.clone(),
+                    transactions.clone(),
+                    ExternalAggregatorConfig::from_config(&config),
+                    ApprovalPolicy::from_config(&config),
+                    Arc::new(AssetRegistry::from_config(&config).await?),
+                    &caches,
+                    SwapRecorder::from_config(&config).await?,
+                ).await?);
+                let bridge = Arc::new(BridgeManager::from_config(
+                    &config,
+                    chain_manager.clone(),
+                    dex_manager.assets().clone(),
+                    dex_manager.approvals().clone(),
+                    transactions.clone(),
+                ).await?);
+                let defi_manager = Arc::new(DefiManager::new(
+                    chain_manager.clone(),
+                    dex_manager.clone(),
+                    analytics.price_feeds.clone(),
+                    transactions.clone(),
+                    &caches,
+                ).await?.with_bridge(bridge.clone()).with_rate_history(rate_history.clone()));
+                (chain_manager, transactions, analytics, dex_manager, bridge, defi_manager)
+            }
+            None => {
+                // Create demo/empty managers to avoid RPC connection issues
+                let analytics = Arc::new(AnalyticsService::new(&config).await?);
+                let chain_manager = Arc::new(ChainManager::new_demo().await?);
+                let transactions = Arc::new(
+                    TransactionTracker::from_config(&config, chain_manager.clone(), analytics.gas_costs.clone()).await?,
+                );
+                let dex_manager = Arc::new(DexManager::new_demo(transactions.clone()).await?);
+                let bridge = Arc::new(BridgeManager::from_config(
+                    &config,
+                    chain_manager.clone(),
+                    dex_manager.assets().clone(),
+                    dex_manager.approvals().clone(),
+                    transactions.clone(),
+                ).await?);
+                let defi_manager = Arc::new(
+                    DefiManager::new_demo(analytics.price_feeds.clone(), transactions.clone()).await?
+                        .with_bridge(bridge.clone())
+                        .with_rate_history(rate_history.clone()),
+                );
+                (chain_manager, transactions, analytics, dex_manager, bridge, defi_manager)
+            }
+        };
+
+        let ens = Arc::new(EnsResolver::from_config(&config, chain_manager.clone(), &caches));
+        let tokens = Arc::new(
+            TokenRegistry::from_config(&config, chain_manager.clone(), dex_manager.assets(), &caches).await?,
+        );
+        let pool_discovery = Arc::new(PoolDiscovery::new(
+            chain_manager.clone(),
+            analytics.price_feeds.clone(),
+            tokens.clone(),
+            &caches,
+            PoolDiscoveryConfig::from_config(&config),
+        )?);
+        let sanctions = Arc::new(SanctionsScreener::from_config(&config));
+        let price_guard = Arc::new(TradePriceGuard::new(
+            analytics.price_feeds.clone(),
+            tokens.clone(),
+            PriceGuardConfig::from_config(&config),
+        ));
+        let contract_analyzer = Arc::new(ContractAnalyzer::new(chain_manager.clone(), &caches));
+        let security = Arc::new(
+            SecurityManager::new_demo(
+                analytics.price_feeds.clone(),
+                sanctions.clone(),
+                AuditTrailConfig::from_config(&config),
+                price_guard.clone(),
+                CircuitBreakerConfig::from_config(&config),
+                NotificationConfig::from_config(&config),
+                SpendingPolicyConfig::from_config(&config),
+                contract_analyzer,
+                MarketDataSources {
+                    price_feeds: analytics.price_feeds.clone(),
+                    dex: dex_manager.clone(),
+                    config: MarketDataConfig::from_config(&config),
+                },
+            )
+            .await?,
+        );
+        let circuit_breakers = security.advanced.circuit_breakers();
+        let token_safety = Arc::new(TokenSafetyScanner::new(
+            chain_manager.clone(),
+            dex_manager.assets().clone(),
+            &caches,
+        ));
+        let broadcaster = Arc::new(TxBroadcaster::new(
+            chain_manager.clone(),
+            transactions.clone(),
+            Some(security.advanced.spending_policies()),
+        ));
+        let multisig = MultiSigManager::new(
+            chain_manager.clone(),
+            broadcaster.clone(),
+            SafeConfig::from_config(&config),
+        ).await?;
+        let wallet_manager = Arc::new(WalletManager::with_security(
+            security.clone(),
+            WalletConnectConfig::from_config(&config),
+            multisig,
+            SmartAccountManager::new(chain_manager.clone(), SmartAccountConfig::from_config(&config)).await?,
+            WalletLabels::from_config(&config).await?,
+        ).await?);
         // let websocket = Arc::new(WebSocketState::new()); // Temporarily disabled
-        
-        // Create demo/empty managers to avoid RPC connection issues
-        let chain_manager = Arc::new(ChainManager::new_demo().await?);
-        let dex_manager = Arc::new(DexManager::new_demo().await?);
-        let defi_manager = Arc::new(DefiManager::new_demo().await?);
-        let security = Arc::new(SecurityManager::new_demo().await?);
+        let contracts = Arc::new(
+            ContractManager::with_explorer(chain_manager.clone(), ExplorerConfig::from_config(&config)).await?,
+        );
+        let orders = Arc::new(OrderEngine::from_config(
+            &config,
+            dex_manager.clone(),
+            wallet_manager.clone(),
+            broadcaster.clone(),
+            analytics.time_zones.clone(),
+            price_guard.clone(),
+            circuit_breakers.clone(),
+        ).await?);
+        let strategy_executions = Arc::new(StrategyExecutor::new(
+            defi_manager.clone(),
+            wallet_manager.clone(),
+            broadcaster.clone(),
+            circuit_breakers.clone(),
+            StrategyExecutorConfig::from_config(&config),
+        ).await?);
+        let reward_compounder = Arc::new(
+            RewardCompounder::from_config(&config, defi_manager.clone(), strategy_executions.clone()).await?,
+        );
+        let settlements = Arc::new(
+            SettlementReporter::from_config(&config, chain_manager.clone(), transactions.clone()).await?,
+        );
+        let quote_accuracy = Arc::new(QuoteAccuracyMonitor::from_config(
+            &config,
+            dex_manager.clone(),
+            transactions.clone(),
+            settlements.clone(),
+        ).await?);
+        let rate_collector = Arc::new(RateHistoryCollector::from_config(&config, defi_manager.clone(), rate_history));
+        let jobs = Arc::new(JobManager::new().await?);
+        let compound_borrowers = Arc::new(
+            CompoundBorrowerIndex::from_config(&config, defi_manager.compound().markets()).await?,
+        );
+        let indexer = Arc::new(EventLogIndexer::from_config(&config, chain_manager.clone()).await?);
+        let backfills = Arc::new(BackfillOrchestrator::from_config(
+            &config,
+            chain_manager.clone(),
+            jobs.clone(),
+            vec![compound_borrowers.clone(), indexer.clone()],
+        ).await?);
+        let reorgs = Arc::new(ReorgMonitor::from_config(
+            &config,
+            chain_manager.clone(),
+            vec![
+                indexer.clone(),
+                broadcaster.clone(),
+                transactions.clone(),
+                bridge.clone(),
+                Arc::new(caches.reorg_invalidator(&["uniswap_v3_pools", "aave_reserves", "compound_ctokens"])),
+            ],
+        ));
+        let monitor = Arc::new(PositionMonitor::new(
+            defi_manager.clone(),
+            MonitorConfig::from_config(&config),
+            security.advanced.notifications(),
+        )?);
+        let arbitrage = Arc::new(ArbitrageEngine::new(
+            dex_manager.clone(),
+            analytics.price_feeds.clone(),
+            analytics.gas_costs.clone(),
+            ArbitrageConfig::from_config(&config),
+        ));
+        let snapshotter = Arc::new(PortfolioSnapshotter::new(
+            defi_manager.clone(),
+            dex_manager.clone(),
+            analytics.price_feeds.clone(),
+            analytics.portfolio.clone(),
+            monitor.clone(),
+            SnapshotterConfig::from_config(&config),
+        ));
+        let tax_exports = Arc::new(TaxExporter::new(
+            dex_manager.clone(),
+            analytics.price_feeds.clone(),
+            transactions.clone(),
+            settlements.clone(),
+            analytics.portfolio.clone(),
+        ));
+        let token_balances = Arc::new(TokenBalanceScanner::from_config(
+            &config,
+            chain_manager.clone(),
+            dex_manager.assets().clone(),
+            analytics.price_feeds.clone(),
+        ));
+        let approvals = Arc::new(ApprovalScanner::from_config(&config, chain_manager.clone(), contracts.clone()));
+        let playbooks = Arc::new(EmergencyPlaybooks::new(
+            PlaybookConfig::from_config(&config),
+            approvals.clone(),
+            defi_manager.clone(),
+            transactions.clone(),
+            security.advanced.audit_trail(),
+        ));
+        let nfts = Arc::new(NftPortfolioService::from_config(
+            &config,
+            chain_manager.clone(),
+            analytics.price_feeds.clone(),
+            &caches,
+        ));
+        let lp_positions = Arc::new(LpPositionTracker::from_config(
+            &config,
+            dex_manager.clone(),
+            analytics.price_feeds.clone(),
+        ));
+        let auto_range = Arc::new(
+            AutoRangeManager::from_config(&config, dex_manager.clone(), transactions.clone()).await?,
+        );
+        let backtests = Arc::new(
+            BacktestService::from_config(&config, defi_manager.clone(), analytics.price_feeds.clone()).await?,
+        );
+        let risk_assessor = Arc::new(RiskAssessor::new(backtests.clone(), RiskAssessorConfig::from_config(&config)));
+        let mempool = Arc::new(MempoolWatcher::from_config(
+            &config,
+            chain_manager.clone(),
+            security.clone(),
+            transactions.clone(),
+        ));
+        let mut deployments = dex_manager.deployments();
+        deployments.extend(defi_manager.deployments());
+        let deployments = Arc::new(DeploymentProber::new(chain_manager.clone(), deployments));
+        let admin_token = config
+            .get_string("admin_api_token")
+            .ok()
+            .filter(|token| !token.is_empty());
+        let replay_guard = Arc::new(ReplayGuard::from_config(&config));
+        let api_keys = Arc::new(ApiKeyStore::from_config(&config).await?);
+        let rate_limiter = Arc::new(RateLimiter::from_config(&config));
 
         Ok(Self {
             chain_manager,
+            ens,
+            tokens,
+            pool_discovery,
             dex_manager,
             wallet_manager,
             defi_manager,
             analytics,
             security,
+            sanctions,
+            token_safety,
+            price_guard,
+            circuit_breakers,
+            approvals,
+            playbooks,
+            contracts,
+            broadcaster,
+            orders,
+            strategy_executions,
+            reward_compounder,
+            transactions,
+            settlements,
+            quote_accuracy,
+            rate_collector,
+            jobs,
+            backfills,
+            compound_borrowers,
+            bridge,
+            indexer,
+            reorgs,
+            monitor,
+            arbitrage,
+            backtests,
+            risk_assessor,
+            lp_positions,
+            snapshotter,
+            tax_exports,
+            token_balances,
+            nfts,
+            auto_range,
+            mempool,
+            deployments,
+            admin_token,
+            replay_guard,
+            api_keys,
+            rate_limiter,
             // websocket, // Temporarily disabled
         })
     }
 }
 
-pub fn routes() -> axum::Router<Arc<ApiState>> {
+pub fn routes(state: Arc<ApiState>) -> axum::Router<Arc<ApiState>> {
+    let scoped = |router: axum::Router<Arc<ApiState>>| {
+        router.route_layer(middleware::from_fn_with_state(state.clone(), auth::require_scope))
+    };
+    let halted = |router: axum::Router<Arc<ApiState>>| {
+        router.route_layer(middleware::from_fn_with_state(state.clone(), auth::halt_trading))
+    };
     axum::Router::new()
         .nest("/docs", docs::routes())
         .nest("/health", health::routes())
-        .nest("/portfolio", portfolio::routes())
-        .nest("/dex", dex::routes())
-        .nest("/defi", defi::routes())
-        .nest("/security", security::routes())
-        .nest("/wallets", wallets::routes())
-        .nest("/chains", chains::routes())
+        .nest("/auth", auth::routes())
+        .nest("/portfolio", scoped(portfolio::routes()))
+        .nest("/analytics", scoped(analytics::routes()))
+        .nest("/dex", scoped(halted(dex::routes())))
+        .nest("/defi", scoped(halted(defi::routes())))
+        .nest("/security", scoped(security::routes()))
+        .nest("/wallets", scoped(halted(wallets::routes())))
+        .nest("/chains", scoped(chains::routes()))
+        .nest("/contracts", scoped(contracts::routes()))
+        .nest("/transactions", scoped(transactions::routes()))
+        .nest("/executions", scoped(executions::routes()))
+        .nest("/tenants", scoped(tenants::routes()))
+        .nest("/monitor", scoped(monitor::routes()))
+        .nest("/demo", scoped(demo::routes()))
+        .nest("/admin", admin::routes())
+        .merge(scoped(simulate::routes()))
+        .route_layer(middleware::from_fn_with_state(state, rate_limit::throttle))
 }
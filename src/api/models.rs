@@ -1,7 +1,220 @@
+use ethers::types::U256;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+// JSON serialization policy shared by every API response:
+// - Raw on-chain quantities (wei, token base units, gas) are `U256` and serialize as
+//   0x-prefixed hex, the JSON-RPC encoding of the ethers types embedded in responses.
+//   Request fields accept hex, decimal strings or JSON integers via `u256_lenient::deserialize`.
+// - Decimal-adjusted amounts are exact decimal strings next to the raw value
+//   (`TokenAmount`), never floats.
+// - USD values are rounded to cents (`usd`); rates, APYs and health factors to four
+//   decimals (`ratio`). Non-finite values serialize as null. Prices keep full precision.
+// - Maps serialize with sorted keys (`sorted_map`) so identical state yields identical JSON.
+
+/// Token amount with its exact decimal-adjusted form
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TokenAmount {
+    /// Amount in the token's smallest unit, 0x-prefixed hex
+    #[schema(value_type = String, example = "0xde0b6b3a7640000")]
+    pub raw: U256,
+    pub decimals: u8,
+    /// Exact amount in whole tokens
+    #[schema(example = "1.0")]
+    pub formatted: String,
+}
+
+impl TokenAmount {
+    pub fn new(raw: U256, decimals: u8) -> Self {
+        Self {
+            raw,
+            decimals,
+            formatted: format_token_amount(raw, decimals),
+        }
+    }
+}
+
+/// Exact decimal string of a base-unit amount, without trailing zeros
+pub fn format_token_amount(raw: U256, decimals: u8) -> String {
+    let digits = raw.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}.0", whole)
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// `status` query filter for records that are archived instead of deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFilter {
+    #[default]
+    Active,
+    /// Closed strategies, disconnected wallets and resolved alerts
+    Archived,
+    All,
+}
+
+impl ArchiveFilter {
+    pub fn includes(self, archived: bool) -> bool {
+        match self {
+            ArchiveFilter::Active => !archived,
+            ArchiveFilter::Archived => archived,
+            ArchiveFilter::All => true,
+        }
+    }
+}
+
+/// Query string of list endpoints over archivable records
+#[derive(Debug, Default, Deserialize)]
+pub struct ArchiveQuery {
+    #[serde(default)]
+    pub status: ArchiveFilter,
+}
+
+/// Parse a quantity given as hex string, decimal string or JSON integer
+fn parse_u256<E: serde::de::Error>(value: LenientU256) -> Result<U256, E> {
+    match value {
+        LenientU256::Number(number) => Ok(U256::from(number)),
+        LenientU256::Text(text) => {
+            let text = text.trim();
+            let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => U256::from_str_radix(hex, 16).map_err(|e| e.to_string()),
+                None => U256::from_dec_str(text).map_err(|e| e.to_string()),
+            };
+            parsed.map_err(|e| E::custom(format!("invalid quantity {:?}: {}", text, e)))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LenientU256 {
+    Number(u64),
+    Text(String),
+}
+
+/// `U256` read from hex, decimal string or integer, for `deserialize_with`; it is written as hex like any `U256`
+pub mod u256_lenient {
+    use super::{parse_u256, LenientU256};
+    use ethers::types::U256;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        parse_u256(LenientU256::deserialize(deserializer)?)
+    }
+}
+
+pub mod option_u256_lenient {
+    use super::{parse_u256, LenientU256};
+    use ethers::types::U256;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U256>, D::Error> {
+        Option::<LenientU256>::deserialize(deserializer)?.map(parse_u256).transpose()
+    }
+}
+
+fn serialize_rounded<S: serde::Serializer>(value: f64, places: i32, serializer: S) -> Result<S::Ok, S::Error> {
+    if !value.is_finite() {
+        return serializer.serialize_none();
+    }
+    let scale = 10f64.powi(places);
+    let rounded = (value * scale).round() / scale;
+    // Avoid emitting -0.0 for tiny negative values
+    serializer.serialize_f64(if rounded == 0.0 { 0.0 } else { rounded })
+}
+
+/// USD value rounded to cents
+pub mod usd {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        super::serialize_rounded(*value, 2, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        f64::deserialize(deserializer)
+    }
+}
+
+/// Rate, APY or health factor rounded to four decimals
+pub mod ratio {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        super::serialize_rounded(*value, 4, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        f64::deserialize(deserializer)
+    }
+}
+
+pub mod option_ratio {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::serialize_rounded(*value, 4, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+        Option::<f64>::deserialize(deserializer)
+    }
+}
+
+pub mod option_usd {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::serialize_rounded(*value, 2, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+        Option::<f64>::deserialize(deserializer)
+    }
+}
+
+/// Map serialized in key order
+pub mod sorted_map {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+    use std::hash::Hash;
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Eq + Hash + Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        HashMap::deserialize(deserializer)
+    }
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
@@ -31,6 +244,7 @@ pub struct ChainHealth {
 pub struct Portfolio {
     pub id: String,
     pub address: String,
+    #[serde(with = "usd")]
     pub total_value_usd: f64,
     pub assets: Vec<Asset>,
     pub defi_positions: Vec<DefiPosition>,
@@ -44,6 +258,7 @@ pub struct Asset {
     pub name: String,
     pub balance: f64,
     pub price_usd: f64,
+    #[serde(with = "usd")]
     pub value_usd: f64,
     pub chain_id: u64,
 }
@@ -54,7 +269,9 @@ pub struct DefiPosition {
     pub position_type: String, // lending, staking, liquidity_pool
     pub token_address: String,
     pub amount: f64,
+    #[serde(with = "usd")]
     pub value_usd: f64,
+    #[serde(with = "option_ratio")]
     pub apy: Option<f64>,
     pub rewards: Vec<Reward>,
 }
@@ -63,6 +280,7 @@ pub struct DefiPosition {
 pub struct Reward {
     pub token_address: String,
     pub amount: f64,
+    #[serde(with = "usd")]
     pub value_usd: f64,
 }
 
@@ -72,10 +290,12 @@ pub struct SwapQuote {
     pub to_token: String,
     pub from_amount: f64,
     pub to_amount: f64,
+    #[serde(with = "ratio")]
     pub price_impact: f64,
     pub gas_estimate: u64,
     pub dex: String,
     pub route: Vec<String>,
+    #[serde(with = "ratio")]
     pub slippage_tolerance: f64,
 }
 
@@ -93,7 +313,9 @@ pub struct YieldOpportunity {
     pub protocol: String,
     pub pool_address: String,
     pub tokens: Vec<String>,
+    #[serde(with = "ratio")]
     pub apy: f64,
+    #[serde(with = "usd")]
     pub tvl: f64,
     pub risk_score: u8,
     pub strategy_type: String,
@@ -104,8 +326,11 @@ pub struct ArbitrageOpportunity {
     pub token_pair: TokenPair,
     pub dex_a: DexInfo,
     pub dex_b: DexInfo,
+    #[serde(with = "usd")]
     pub profit_potential: f64,
+    #[serde(with = "usd")]
     pub gas_cost: f64,
+    #[serde(with = "usd")]
     pub net_profit: f64,
 }
 
@@ -119,6 +344,7 @@ pub struct TokenPair {
 pub struct DexInfo {
     pub name: String,
     pub price: f64,
+    #[serde(with = "usd")]
     pub liquidity: f64,
 }
 
@@ -127,6 +353,7 @@ pub struct GasOptimization {
     pub current_gas_price: u64,
     pub recommended_gas_price: u64,
     pub estimated_confirmation_time: u32, // in seconds
+    #[serde(with = "usd")]
     pub potential_savings: f64,
 }
 
@@ -153,8 +380,11 @@ pub struct LendingPosition {
     pub token: String,
     pub supplied: f64,
     pub borrowed: f64,
+    #[serde(with = "ratio")]
     pub supply_apy: f64,
+    #[serde(with = "ratio")]
     pub borrow_apy: f64,
+    #[serde(with = "ratio")]
     pub health_factor: f64,
 }
 
@@ -166,10 +396,3 @@ pub struct FlashLoanOpportunity {
     pub fee: f64,
     pub arbitrage_profit: f64,
 }
-
-#[derive(Serialize, Deserialize, ToSchema, Clone)]
-pub struct ErrorResponse {
-    pub code: u16,
-    pub message: String,
-    pub details: Option<String>,
-}
@@ -0,0 +1,121 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::StatusCode,
+    response::{Json, Response},
+    routing::{delete, get},
+    Router,
+};
+use ethers::types::Address;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::api::{error::ApiError, models::ArchiveFilter, ApiState};
+use crate::monitor::{PositionAlert, WatchedPosition};
+
+/// Register position request
+#[derive(Deserialize)]
+pub struct WatchPositionRequest {
+    pub chain_id: u64,
+    pub user: Address,
+    pub health_factor_threshold: Option<f64>,
+    pub borrow_ratio_threshold: Option<f64>,
+}
+
+/// Alert history query parameters
+#[derive(Deserialize)]
+pub struct AlertsQuery {
+    pub limit: Option<usize>,
+    /// Active alerts by default, `archived` for resolved ones
+    #[serde(default)]
+    pub status: ArchiveFilter,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/positions", get(list_watched_positions).post(watch_position))
+        .route("/positions/{chain_id}/{user}", delete(unwatch_position))
+        .route("/alerts", get(get_recent_alerts))
+        .route("/alerts/ws", get(alerts_websocket))
+}
+
+/// List monitored positions
+async fn list_watched_positions(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<Vec<WatchedPosition>>, ApiError> {
+    Ok(Json(state.monitor.list_watched().await))
+}
+
+/// Register a user's positions for background monitoring
+async fn watch_position(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<WatchPositionRequest>,
+) -> Result<Json<WatchedPosition>, ApiError> {
+    let invalid = |threshold: Option<f64>| threshold.is_some_and(|t| !t.is_finite() || t <= 0.0);
+    if invalid(request.health_factor_threshold) || invalid(request.borrow_ratio_threshold) {
+        return Err(ApiError::BadRequest("Thresholds must be positive numbers".to_string()));
+    }
+
+    let position = state.monitor.watch(
+        request.chain_id,
+        request.user,
+        request.health_factor_threshold,
+        request.borrow_ratio_threshold,
+    ).await;
+
+    Ok(Json(position))
+}
+
+/// Stop monitoring a user's positions
+async fn unwatch_position(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, user)): Path<(u64, Address)>,
+) -> Result<StatusCode, ApiError> {
+    if state.monitor.unwatch(chain_id, user).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!("{:?} is not monitored on chain {}", user, chain_id)))
+    }
+}
+
+/// Get recently dispatched alerts
+async fn get_recent_alerts(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<AlertsQuery>,
+) -> Result<Json<Vec<PositionAlert>>, ApiError> {
+    Ok(Json(state.monitor.recent_alerts(query.limit.unwrap_or(50), query.status).await))
+}
+
+/// Stream alerts to a WebSocket client as they are dispatched
+async fn alerts_websocket(
+    State(state): State<Arc<ApiState>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let alerts = state.monitor.subscribe();
+    ws.on_upgrade(move |socket| stream_alerts(socket, alerts))
+}
+
+async fn stream_alerts(mut socket: WebSocket, mut alerts: tokio::sync::broadcast::Receiver<PositionAlert>) {
+    loop {
+        let alert = match alerts.recv().await {
+            Ok(alert) => alert,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Alert WebSocket client lagged, skipped {} alerts", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        let payload = match serde_json::to_string(&alert) {
+            Ok(payload) => payload,
+            Err(_) => continue,
+        };
+        if socket.send(Message::Text(payload.into())).await.is_err() {
+            break;
+        }
+    }
+}
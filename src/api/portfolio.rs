@@ -1,13 +1,88 @@
-use axum::{extract::State, response::Json, routing::get, Router};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderName},
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::Value;
 use std::sync::Arc;
+use tracing::warn;
 use utoipa::ToSchema;
 
-use crate::api::{models::Portfolio, ApiState};
+use crate::analytics::portfolio_import::{ImportFormat, ImportReport, PortfolioImporter};
+use crate::analytics::portfolio_tracker::{CostBasisMethod, PortfolioPnl, PortfolioSnapshot, Trade};
+use crate::analytics::nft_portfolio::NftPortfolio;
+use crate::analytics::tax_export::TaxExportFormat;
+use crate::analytics::token_balances::WalletTokenBalances;
+use crate::api::{ens::AddressPath, error::ApiError, models::Portfolio, ApiState};
+
+/// Portfolio import request
+#[derive(Deserialize)]
+pub struct ImportPortfolioRequest {
+    pub format: ImportFormat,
+    /// Export document; CSV and JSON text are passed as a string
+    pub payload: Value,
+}
+
+/// Portfolio history query parameters
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Keep the closing snapshot of each interval, e.g. "15m", "1h" or "1d" (days in the wallet's time zone);
+    /// every snapshot when omitted
+    pub interval: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// PnL query parameters
+#[derive(Deserialize)]
+pub struct PnlQuery {
+    /// Overrides the configured cost basis method
+    pub method: Option<CostBasisMethod>,
+}
+
+/// Tax report query parameters
+#[derive(Deserialize)]
+pub struct TaxReportQuery {
+    #[serde(default)]
+    pub format: TaxExportFormat,
+    /// Only events of the tax year starting in this year, cut off in the wallet's time zone
+    pub tax_year: Option<i32>,
+    /// Fail instead of leaving the values of unpriced events blank
+    #[serde(default)]
+    pub require_prices: bool,
+}
+
+/// Token balance query parameters
+#[derive(Deserialize)]
+pub struct TokenBalancesQuery {
+    /// Comma-separated chain ids, every supported chain when omitted
+    pub chain_ids: Option<String>,
+}
+
+/// NFT holdings query parameters
+#[derive(Deserialize)]
+pub struct NftQuery {
+    /// Comma-separated chain ids, every supported chain when omitted
+    pub chain_ids: Option<String>,
+    /// Fetch each token's metadata, on by default
+    pub metadata: Option<bool>,
+}
 
 pub fn routes() -> Router<Arc<ApiState>> {
     Router::new()
         .route("/", get(get_portfolio))
         .route("/{address}", get(get_portfolio_by_address))
+        .route("/{address}/import", post(import_portfolio))
+        .route("/{address}/tokens", get(get_token_balances))
+        .route("/{address}/nfts", get(get_nft_holdings))
+        .route("/{address}/history", get(get_portfolio_history))
+        .route("/{address}/snapshots", post(take_portfolio_snapshot))
+        .route("/{address}/trades", get(list_trades).post(record_trades))
+        .route("/{address}/pnl", get(get_portfolio_pnl))
+        .route("/{address}/tax-report", get(get_tax_report))
 }
 
 #[utoipa::path(
@@ -38,3 +113,167 @@ pub async fn get_portfolio_by_address(
 ) -> Json<Portfolio> {
     get_portfolio(State(_state)).await
 }
+
+/// Import a DeBank, Zapper or CSV portfolio export for a wallet
+pub async fn import_portfolio(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Json(request): Json<ImportPortfolioRequest>,
+) -> Result<Json<ImportReport>, ApiError> {
+    let importer = PortfolioImporter::new(
+        state.analytics.portfolio.clone(),
+        state.defi_manager.strategies().clone(),
+    );
+    let report = importer.import(address, request.format, &request.payload).await.map_err(|e| {
+        warn!("Portfolio import for {:?} failed: {}", address, e);
+        ApiError::from_error(e, ApiError::BadRequest)
+    })?;
+
+    Ok(Json(report))
+}
+
+/// ERC-20 and native balances of a wallet with USD values, across chains
+pub async fn get_token_balances(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Query(query): Query<TokenBalancesQuery>,
+) -> Result<Json<WalletTokenBalances>, ApiError> {
+    let chain_ids = query.chain_ids.as_deref().map(parse_chain_ids).transpose()?;
+    let (mut balances, ens_name) = tokio::join!(state.token_balances.scan(address, chain_ids), state.ens.lookup(address));
+    balances.ens_name = ens_name;
+    Ok(Json(balances))
+}
+
+/// NFTs of a wallet with their metadata, valued at their collections' floor prices
+pub async fn get_nft_holdings(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Query(query): Query<NftQuery>,
+) -> Result<Json<NftPortfolio>, ApiError> {
+    let chain_ids = query.chain_ids.as_deref().map(parse_chain_ids).transpose()?;
+    let (mut portfolio, ens_name) = tokio::join!(
+        state.nfts.portfolio(address, chain_ids, query.metadata.unwrap_or(true)),
+        state.ens.lookup(address),
+    );
+    portfolio.ens_name = ens_name;
+    Ok(Json(portfolio))
+}
+
+pub(crate) fn parse_chain_ids(chain_ids: &str) -> Result<Vec<u64>, ApiError> {
+    chain_ids.split(',')
+        .map(|chain_id| chain_id.trim().parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ApiError::BadRequest(format!("Invalid chain ids {}", chain_ids)))
+}
+
+/// Get the recorded value history of a wallet
+pub async fn get_portfolio_history(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<PortfolioSnapshot>>, ApiError> {
+    let history = match query.interval.as_deref() {
+        None => state.analytics.portfolio.history(address, query.since).await,
+        Some(interval) => {
+            let interval = parse_interval(interval)?;
+            let settings = state.analytics.time_zones.get(address).await;
+            state.analytics.portfolio.closes(address, interval, query.since, &settings).await
+        }
+    };
+
+    Ok(Json(history))
+}
+
+/// Interval of a number and a unit, `m`, `h`, `d` or `w`
+fn parse_interval(interval: &str) -> Result<Duration, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("Unsupported interval {}, expected e.g. 15m, 1h, 1d or 1w", interval));
+    let unit_at = interval.len().checked_sub(1).filter(|at| interval.is_char_boundary(*at)).ok_or_else(invalid)?;
+    let (count, unit) = interval.split_at(unit_at);
+    let count: i64 = count.parse().ok().filter(|count| *count > 0).ok_or_else(invalid)?;
+    let interval = match unit {
+        "m" => Duration::try_minutes(count),
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        "w" => Duration::try_weeks(count),
+        _ => None,
+    };
+    interval.ok_or_else(invalid)
+}
+
+/// Snapshot a wallet's value, protocol allocation and health factors now
+pub async fn take_portfolio_snapshot(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<PortfolioSnapshot>, ApiError> {
+    state.snapshotter.snapshot(address).await
+        .map(Json)
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))
+}
+
+/// Trades recorded for a wallet, oldest first
+pub async fn list_trades(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Json<Vec<Trade>> {
+    Json(state.analytics.portfolio.trades(address).await)
+}
+
+/// Record trades of a wallet with their execution prices, for its cost basis
+pub async fn record_trades(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Json(trades): Json<Vec<Trade>>,
+) -> Result<Json<Vec<Trade>>, ApiError> {
+    state.analytics.portfolio.record_trades(address, trades).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(state.analytics.portfolio.trades(address).await))
+}
+
+/// Realized and unrealized PnL of a wallet with its daily series in the wallet's time zone
+pub async fn get_portfolio_pnl(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Query(query): Query<PnlQuery>,
+) -> Json<PortfolioPnl> {
+    let settings = state.analytics.time_zones.get(address).await;
+    Json(state.analytics.portfolio.pnl(address, query.method, &settings).await)
+}
+
+/// Download a wallet's swaps, liquidity events, income and liquidations as CSV
+pub async fn get_tax_report(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Query(query): Query<TaxReportQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let range = match query.tax_year {
+        Some(year) => {
+            let settings = state.analytics.time_zones.get(address).await;
+            Some(settings.tax_year_bounds(year).map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?)
+        }
+        None => None,
+    };
+    let report = state.tax_exports.export(address, range, query.format).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+    if query.require_prices && report.unpriced_events > 0 {
+        return Err(ApiError::Unprocessable(format!(
+            "{} events have no historical USD price; register a Chainlink feed for their tokens",
+            report.unpriced_events,
+        )));
+    }
+
+    let period = query.tax_year.map(|year| format!("-{}", year)).unwrap_or_default();
+    let format = match query.format {
+        TaxExportFormat::Csv => "",
+        TaxExportFormat::Koinly => "-koinly",
+    };
+    let disposition = format!("attachment; filename=\"tax-report-{:?}{}{}.csv\"", address, period, format);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (HeaderName::from_static("x-unpriced-events"), report.unpriced_events.to_string()),
+        ],
+        report.csv,
+    ))
+}
@@ -0,0 +1,220 @@
+// Token bucket rate limiting per API key or client IP, keeping abusive clients off the RPC backends
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::api::{error::ApiError, ApiState};
+
+/// Requests per minute of a group without its own `rate_limit_<group>_per_minute`
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 600;
+/// Route groups under `/api/v1`, each limited separately
+const ROUTE_GROUPS: [&str; 17] = [
+    "docs", "health", "auth", "portfolio", "dex", "defi", "security", "wallets", "chains", "contracts",
+    "transactions", "executions", "tenants", "monitor", "demo", "admin", "simulate",
+];
+/// Buckets tracked before full ones are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    /// Take a token, or the seconds until one is available
+    fn take(&mut self, requests_per_minute: u32, now: Instant) -> Result<(), u64> {
+        let capacity = requests_per_minute as f64;
+        let per_second = capacity / 60.0;
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(capacity);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(((1.0 - self.tokens) / per_second).ceil().max(1.0) as u64)
+    }
+
+    fn is_full(&self, requests_per_minute: u32, now: Instant) -> bool {
+        let refilled = self.tokens + now.duration_since(self.updated_at).as_secs_f64() * requests_per_minute as f64 / 60.0;
+        refilled >= requests_per_minute as f64
+    }
+}
+
+/// Limits in force and the requests they throttled
+#[derive(Serialize)]
+pub struct RateLimitStats {
+    pub enabled: bool,
+    pub default_per_minute: u32,
+    /// Groups with their own limit, 0 leaves a group unlimited
+    pub group_limits: BTreeMap<String, u32>,
+    /// Requests answered with 429, by route group
+    pub throttled: BTreeMap<String, u64>,
+    /// Clients currently holding a bucket
+    pub tracked_clients: usize,
+}
+
+/// Requests per minute by route group, reloaded with the config file
+struct Limits {
+    default_per_minute: u32,
+    group_limits: HashMap<String, u32>,
+}
+
+impl Limits {
+    fn from_config(config: &config::Config) -> Self {
+        let limit = |key: &str| config.get_int(key).ok().and_then(|value| u32::try_from(value).ok());
+        let group_limits = ROUTE_GROUPS
+            .iter()
+            .filter_map(|group| limit(&format!("rate_limit_{}_per_minute", group)).map(|value| (group.to_string(), value)))
+            .collect();
+        Self {
+            default_per_minute: limit("rate_limit_per_minute").unwrap_or(DEFAULT_REQUESTS_PER_MINUTE),
+            group_limits,
+        }
+    }
+
+    fn limit(&self, group: &str) -> u32 {
+        self.group_limits.get(group).copied().unwrap_or(self.default_per_minute)
+    }
+}
+
+/// Buckets of `requests_per_minute` tokens per client and route group, refilled continuously
+pub struct RateLimiter {
+    enabled: bool,
+    limits: RwLock<Limits>,
+    /// Identify clients by the first `x-forwarded-for` address, only behind a trusted proxy
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+    throttled: Mutex<BTreeMap<String, u64>>,
+}
+
+impl RateLimiter {
+    pub fn new(enabled: bool, default_per_minute: u32, group_limits: HashMap<String, u32>, trust_forwarded_for: bool) -> Self {
+        if enabled {
+            info!("Rate limiting to {} requests per minute per client, {} groups overridden", default_per_minute, group_limits.len());
+        }
+        Self {
+            enabled,
+            limits: RwLock::new(Limits { default_per_minute, group_limits }),
+            trust_forwarded_for,
+            buckets: Mutex::new(HashMap::new()),
+            throttled: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Limits from `rate_limit_enabled` (default true), `rate_limit_per_minute`, `rate_limit_<group>_per_minute`
+    /// and `rate_limit_trust_forwarded_for`
+    pub fn from_config(config: &config::Config) -> Self {
+        let Limits { default_per_minute, group_limits } = Limits::from_config(config);
+        Self::new(
+            config.get_bool("rate_limit_enabled").unwrap_or(true),
+            default_per_minute,
+            group_limits,
+            config.get_bool("rate_limit_trust_forwarded_for").unwrap_or(false),
+        )
+    }
+
+    /// Apply reloaded per-minute limits, buckets keep their tokens and refill at the new rate
+    pub async fn reload(&self, config: &config::Config) {
+        let limits = Limits::from_config(config);
+        info!(
+            "Rate limits reloaded: {} requests per minute, {} groups overridden",
+            limits.default_per_minute,
+            limits.group_limits.len()
+        );
+        *self.limits.write().await = limits;
+    }
+
+    /// Let a request of `client` to `group` through, or the seconds to wait before retrying
+    async fn check(&self, client: &str, group: &str) -> Result<(), u64> {
+        let limits = self.limits.read().await;
+        let requests_per_minute = limits.limit(group);
+        if !self.enabled || requests_per_minute == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().await;
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|(_, group), bucket| !bucket.is_full(limits.limit(group), now));
+        }
+        drop(limits);
+        let result = buckets
+            .entry((client.to_string(), group.to_string()))
+            .or_insert_with(|| Bucket { tokens: requests_per_minute as f64, updated_at: now })
+            .take(requests_per_minute, now);
+        drop(buckets);
+
+        if result.is_err() {
+            *self.throttled.lock().await.entry(group.to_string()).or_default() += 1;
+        }
+        result
+    }
+
+    pub async fn stats(&self) -> RateLimitStats {
+        let limits = self.limits.read().await;
+        RateLimitStats {
+            enabled: self.enabled,
+            default_per_minute: limits.default_per_minute,
+            group_limits: limits.group_limits.iter().map(|(group, limit)| (group.clone(), *limit)).collect(),
+            throttled: self.throttled.lock().await.clone(),
+            tracked_clients: self.buckets.lock().await.len(),
+        }
+    }
+
+    fn client_ip(&self, request: &Request) -> Option<String> {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| request.headers().get("x-forwarded-for").and_then(|value| value.to_str().ok()))
+            .flatten()
+            .and_then(|value| value.split(',').next())
+            .map(|address| address.trim().to_string());
+        forwarded.or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(address)| address.ip().to_string())
+        })
+    }
+}
+
+/// Route group of a matched path, its first segment under `/api/v1`
+fn route_group(path: &str) -> &str {
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    path.trim_start_matches('/').split('/').next().unwrap_or_default()
+}
+
+/// Middleware answering `429 Too Many Requests` with `Retry-After` once a client's bucket for the route
+/// group is empty; clients are their API key when one is presented, their IP otherwise
+pub async fn throttle(State(state): State<Arc<ApiState>>, request: Request, next: Next) -> Response {
+    let group = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| route_group(matched.as_str()).to_string())
+        .unwrap_or_default();
+    let client = match state.api_keys.authenticate(request.headers()).await {
+        Some(key) => format!("key:{}", key.id),
+        None => match state.rate_limiter.client_ip(&request) {
+            Some(ip) => format!("ip:{}", ip),
+            None => {
+                debug!("No client address for a {} request, not rate limited", group);
+                return next.run(request).await;
+            }
+        },
+    };
+
+    if let Err(retry_after) = state.rate_limiter.check(&client, &group).await {
+        warn!("Throttled {} on {} routes, retry in {}s", client, group, retry_after);
+        return ApiError::RateLimited { retry_after }.into_response();
+    }
+
+    next.run(request).await
+}
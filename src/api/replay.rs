@@ -0,0 +1,153 @@
+// Signed request nonces rejecting captured broadcast and execution requests replayed later
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, OriginalUri, Request},
+    http::{request::Parts, StatusCode},
+    response::Json,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::api::{error::ApiError, ApiState};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Age after which a signed request is rejected when `request_signature_max_age_secs` is not configured
+const DEFAULT_MAX_AGE_SECONDS: i64 = 300;
+/// Nonces tracked before expired ones are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+const MIN_NONCE_LENGTH: usize = 16;
+const MAX_NONCE_LENGTH: usize = 128;
+
+/// Verifies `x-request-signature` over the timestamp, nonce, method, path and body of a request,
+/// remembering nonces until their timestamp expires so a request is accepted at most once
+pub struct ReplayGuard {
+    /// HMAC key shared with clients, signing is not required when unset
+    secret: Option<Vec<u8>>,
+    max_age_seconds: i64,
+    /// Nonce to the first unix time its timestamp is rejected at, so it is kept as long as it could be replayed
+    seen: Mutex<HashMap<String, i64>>,
+}
+
+impl ReplayGuard {
+    pub fn new(secret: Option<Vec<u8>>, max_age_seconds: i64) -> Self {
+        if secret.is_none() {
+            warn!("request_signing_secret is not set, broadcast and execution requests can be replayed");
+        }
+        Self {
+            secret,
+            max_age_seconds,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Guard keyed by `request_signing_secret`, accepting signatures up to `request_signature_max_age_secs` old
+    pub fn from_config(config: &config::Config) -> Self {
+        let secret = config
+            .get_string("request_signing_secret")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(String::into_bytes);
+        let max_age_seconds = config
+            .get_int("request_signature_max_age_secs")
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(DEFAULT_MAX_AGE_SECONDS);
+        Self::new(secret, max_age_seconds)
+    }
+
+    /// Accept a request once: 401 for a missing, stale or invalid signature, 409 for a reused nonce
+    async fn verify(&self, parts: &Parts, body: &[u8]) -> Result<(), ApiError> {
+        let Some(secret) = &self.secret else {
+            return Ok(());
+        };
+        let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok());
+        let (Some(timestamp), Some(nonce), Some(signature)) = (
+            header("x-request-timestamp"),
+            header("x-request-nonce"),
+            header("x-request-signature"),
+        ) else {
+            return Err(ApiError::Unauthorized("Missing request signature headers".to_string()));
+        };
+
+        let now = Utc::now().timestamp();
+        let signed_at: i64 = timestamp.parse()
+            .map_err(|_| ApiError::Unauthorized("Invalid x-request-timestamp".to_string()))?;
+        if (now - signed_at).abs() > self.max_age_seconds {
+            warn!("Rejected request signed at {}, outside the {}s window", signed_at, self.max_age_seconds);
+            return Err(ApiError::Unauthorized(format!("Request signature is older than {}s", self.max_age_seconds)));
+        }
+        if !(MIN_NONCE_LENGTH..=MAX_NONCE_LENGTH).contains(&nonce.len()) {
+            return Err(ApiError::Unauthorized(format!(
+                "x-request-nonce must be {} to {} characters", MIN_NONCE_LENGTH, MAX_NONCE_LENGTH,
+            )));
+        }
+
+        // Nested routers see a stripped URI, the signature covers the path the client called
+        let path = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map(|original| &original.0)
+            .unwrap_or(&parts.uri)
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        let payload = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            timestamp,
+            nonce,
+            parts.method,
+            path,
+            ethers::utils::hex::encode(Sha256::digest(body)),
+        );
+        let signature = ethers::utils::hex::decode(signature.trim_start_matches("0x"))
+            .map_err(|_| ApiError::Unauthorized("x-request-signature is not hex".to_string()))?;
+        let mut mac = HmacSha256::new_from_slice(secret).map_err(ApiError::internal)?;
+        mac.update(payload.as_bytes());
+        if mac.verify_slice(&signature).is_err() {
+            warn!("Rejected request to {} with an invalid signature", path);
+            return Err(ApiError::Unauthorized("Invalid request signature".to_string()));
+        }
+
+        // Only record nonces of valid signatures so nobody can burn a client's nonces
+        let mut seen = self.seen.lock().await;
+        if seen.len() >= PRUNE_THRESHOLD {
+            seen.retain(|_, expires_at| *expires_at > now);
+        }
+        if seen.get(nonce).is_some_and(|expires_at| *expires_at > now) {
+            warn!("Rejected replayed request to {} with nonce {}", path, nonce);
+            return Err(ApiError::Conflict(format!("Nonce {} was already used", nonce)));
+        }
+        // Timestamps exactly max_age old are still accepted, the nonce must outlive that second
+        seen.insert(nonce.to_string(), signed_at + self.max_age_seconds + 1);
+        Ok(())
+    }
+}
+
+/// JSON body of a request that must carry a fresh signed nonce when request signing is enabled
+pub struct SignedJson<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest<Arc<ApiState>> for SignedJson<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &Arc<ApiState>) -> Result<Self, Self::Rejection> {
+        let (parts, body) = request.into_parts();
+        let body = Bytes::from_request(Request::from_parts(parts.clone(), body), state)
+            .await
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+
+        state.replay_guard.verify(&parts, &body).await?;
+
+        let Json(value) = Json::<T>::from_bytes(&body).map_err(|rejection| match rejection.status() {
+            StatusCode::UNPROCESSABLE_ENTITY => ApiError::Unprocessable(rejection.body_text()),
+            _ => ApiError::BadRequest(rejection.body_text()),
+        })?;
+        Ok(Self(value))
+    }
+}
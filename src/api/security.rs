@@ -1,7 +1,7 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    http::header,
+    response::{IntoResponse, Json},
     routing::{get, post},
     Router,
 };
@@ -10,14 +10,32 @@ use std::sync::Arc;
 use ethers::types::{Address, TransactionRequest};
 use chrono::{DateTime, Utc};
 
-use crate::api::ApiState;
-use crate::security::{SecurityAnalysisResult, SecurityStatus, EmergencyAlert};
+use crate::api::{error::ApiError, ApiState};
+use crate::api::admin::AdminGuard;
+use crate::api::{ens::{self, AddressPath}, portfolio::parse_chain_ids, tokens};
+use crate::security::{
+    ApprovalReport, AuditEntry, AuditExportFormat, AuditQuery, ChainVerification, RevokeScope, ScreeningResult, SecurityAnalysisResult, SecurityStatus, EmergencyAlert, TokenSafetyReport,
+    MarketRiskData, StressScenario, StressTestResult, OverrideRequest, PlaybookRun, PolicyContext, PolicyOverride, SpendingPolicies, TransactionLimits,
+};
+use crate::security::audit_trail::AuditEntryType;
+use crate::security::sanctions::{self, SanctionsListStatus};
 use crate::security::emergency_response::EmergencyLevel;
+use crate::chains::ens::AddressOrName;
 
 /// Security analysis request
 #[derive(Deserialize)]
 pub struct SecurityAnalysisRequest {
     pub transaction: TransactionRequest,
+    /// Strategy the transaction is made for, checked against its spending policy
+    pub strategy_id: Option<String>,
+    /// Approved spending policy override to apply if the transaction breaks a policy
+    pub override_id: Option<String>,
+}
+
+/// Spending policy override query parameters
+#[derive(Deserialize)]
+pub struct PolicyOverridesQuery {
+    pub wallet: Option<Address>,
 }
 
 /// Security report query parameters
@@ -34,8 +52,109 @@ pub struct EmergencyAlertRequest {
     pub description: String,
     pub level: EmergencyLevel,
     pub affected_addresses: Option<Vec<Address>>,
+    /// Lending markets ("aave", "compound") to exit and compromised spender addresses
+    pub affected_protocols: Option<Vec<String>>,
+    /// Prepare the playbook transactions without queueing them, defaults to `emergency_playbooks_dry_run`
+    pub dry_run: Option<bool>,
+}
+
+/// Triggered alert and the playbooks run for its wallets
+#[derive(Serialize)]
+pub struct EmergencyAlertResponse {
+    pub alert_id: String,
+    pub playbooks: Vec<PlaybookRun>,
+}
+
+/// Playbook run query parameters
+#[derive(Deserialize)]
+pub struct PlaybookRunsQuery {
+    pub alert_id: Option<String>,
+}
+
+/// Approval scan query parameters
+#[derive(Deserialize)]
+pub struct ApprovalsQuery {
+    /// Comma-separated chain ids, every supported chain when omitted
+    pub chain_ids: Option<String>,
+    /// Approvals to build revoke transactions for: `risky` (default), `all` or `none`
+    #[serde(default)]
+    pub revoke: RevokeScope,
+}
+
+/// Audit log query parameters
+#[derive(Deserialize)]
+pub struct AuditLogQuery {
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// Address the entries are about, or operator named in their `actor:` flag
+    pub actor: Option<String>,
+    /// Comma-separated entry types such as `AdminAction,SanctionsScreening`
+    pub entry_type: Option<String>,
+    pub flag: Option<String>,
+    /// Entries per page, 100 by default and at most 1000; ignored by exports
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Export layout: `jsonl` (default) or `csv`
+    #[serde(default)]
+    pub format: AuditExportFormat,
+}
+
+impl AuditLogQuery {
+    fn to_query(&self, paged: bool) -> Result<AuditQuery, ApiError> {
+        let entry_types = match self.entry_type.as_deref() {
+            Some(entry_types) => entry_types.split(',')
+                .map(|entry_type| {
+                    serde_json::from_value::<AuditEntryType>(serde_json::Value::String(entry_type.trim().to_string()))
+                        .map_err(|_| ApiError::BadRequest(format!("Unknown audit entry type {}", entry_type)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        Ok(AuditQuery {
+            start_time: self.start_time,
+            end_time: self.end_time,
+            entry_types,
+            user_address: None,
+            actor: self.actor.clone(),
+            contract_address: None,
+            transaction_hash: None,
+            risk_score_min: None,
+            risk_score_max: None,
+            security_flags: self.flag.iter().cloned().collect(),
+            limit: paged.then(|| self.limit.unwrap_or(DEFAULT_AUDIT_PAGE).min(MAX_AUDIT_PAGE)),
+            offset: paged.then_some(self.offset.unwrap_or(0)),
+        })
+    }
 }
 
+/// Preset scenarios by name and custom scenarios to run against a wallet's lending positions, every
+/// preset when neither is given
+#[derive(Deserialize)]
+pub struct StressTestRequest {
+    pub wallet: AddressOrName,
+    pub chain_id: Option<u64>,
+    #[serde(default)]
+    pub presets: Vec<String>,
+    #[serde(default)]
+    pub scenarios: Vec<StressScenario>,
+}
+
+#[derive(Serialize)]
+pub struct StressTestResponse {
+    pub wallet: Address,
+    pub chain_id: u64,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_worth_usd: f64,
+    /// Assets without a price, left out of every scenario
+    pub unpriced_assets: Vec<Address>,
+    pub results: Vec<StressTestResult>,
+}
+
+const MAX_STRESS_SCENARIOS: usize = 20;
+
+const DEFAULT_AUDIT_PAGE: usize = 100;
+const MAX_AUDIT_PAGE: usize = 1000;
+
 /// Security status response
 #[derive(Serialize)]
 pub struct SecurityStatusResponse {
@@ -61,17 +180,33 @@ pub fn routes() -> Router<Arc<ApiState>> {
         .route("/analyze", post(analyze_transaction))
         .route("/report", get(generate_security_report))
         .route("/metrics", get(get_security_metrics))
+        .route("/risk/market", get(get_market_risk_data))
+        .route("/stress-test", post(run_stress_test))
+        .route("/stress-test/presets", get(get_stress_presets))
         .route("/emergency/alert", post(trigger_emergency_alert))
         .route("/emergency/alerts", get(get_active_alerts))
+        .route("/emergency/playbooks", get(get_playbook_runs))
         .route("/threats/{address}", get(get_address_threats))
+        .route("/tokens/{chain_id}/{token}", get(get_token_safety))
+        .route("/approvals/{address}", get(get_approvals))
+        .route("/sanctions", get(get_sanctions_lists))
+        .route("/sanctions/{address}", get(screen_address))
+        .route("/audit", get(get_audit_log))
+        .route("/audit/export", get(export_audit_log))
+        .route("/audit/verify", get(verify_audit_log))
+        .route("/config/limits", get(get_transaction_limits).put(update_transaction_limits))
+        .route("/config/policies", get(get_spending_policies).put(update_spending_policies))
+        .route("/policies/overrides", get(get_policy_overrides).post(request_policy_override))
+        .route("/policies/overrides/{id}/approve", post(approve_policy_override))
+        .route("/policies/overrides/{id}/reject", post(reject_policy_override))
 }
 
 /// Get current security status
 async fn get_security_status(
     State(state): State<Arc<ApiState>>,
-) -> Result<Json<SecurityStatusResponse>, StatusCode> {
+) -> Result<Json<SecurityStatusResponse>, ApiError> {
     let status = state.security.get_security_status().await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(ApiError::internal)?;
     
     Ok(Json(SecurityStatusResponse {
         status,
@@ -85,23 +220,24 @@ async fn get_security_status(
 async fn analyze_transaction(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<SecurityAnalysisRequest>,
-) -> Result<Json<SecurityAnalysisResult>, StatusCode> {
-    match state.security.analyze_transaction(&request.transaction).await {
-        Ok(analysis) => Ok(Json(analysis)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+) -> Result<Json<SecurityAnalysisResult>, ApiError> {
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let analysis = state.security.analyze_transaction(&request.transaction, &context).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+
+    Ok(Json(analysis))
 }
 
 /// Generate comprehensive security report
 async fn generate_security_report(
     State(state): State<Arc<ApiState>>,
     Query(query): Query<SecurityReportQuery>,
-) -> Result<Json<crate::security::SecurityReport>, StatusCode> {
+) -> Result<Json<crate::security::SecurityReport>, ApiError> {
     let start_time = query.start_time.unwrap_or_else(|| Utc::now() - chrono::Duration::days(7));
     let end_time = query.end_time.unwrap_or_else(Utc::now);
     
     let report = state.security.generate_security_report(start_time, end_time).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(ApiError::internal)?;
     
     Ok(Json(report))
 }
@@ -109,7 +245,7 @@ async fn generate_security_report(
 /// Get security metrics
 async fn get_security_metrics(
     State(state): State<Arc<ApiState>>,
-) -> Result<Json<SecurityMetricsResponse>, StatusCode> {
+) -> Result<Json<SecurityMetricsResponse>, ApiError> {
     // In a real implementation, this would get actual metrics from the security manager
     Ok(Json(SecurityMetricsResponse {
         transactions_analyzed: 1250,
@@ -120,18 +256,18 @@ async fn get_security_metrics(
     }))
 }
 
-/// Trigger emergency alert
+/// Trigger emergency alert, running the emergency playbooks of critical alerts
 async fn trigger_emergency_alert(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<EmergencyAlertRequest>,
-) -> Result<Json<String>, StatusCode> {
+) -> Result<Json<EmergencyAlertResponse>, ApiError> {
     let alert = EmergencyAlert {
         id: format!("alert_{}", Utc::now().timestamp()),
         level: request.level,
         title: request.title,
         description: request.description,
         affected_addresses: request.affected_addresses.unwrap_or(vec![]),
-        affected_protocols: vec![],
+        affected_protocols: request.affected_protocols.unwrap_or_default(),
         detected_at: Utc::now(),
         resolved_at: None,
         auto_actions_taken: vec![],
@@ -139,16 +275,25 @@ async fn trigger_emergency_alert(
         estimated_impact: None,
     };
     
-    state.security.handle_emergency(alert).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    Ok(Json("Emergency alert triggered successfully".to_string()))
+    state.security.handle_emergency(alert.clone()).await
+        .map_err(ApiError::internal)?;
+    let playbooks = state.playbooks.respond(&alert, request.dry_run).await;
+
+    Ok(Json(EmergencyAlertResponse { alert_id: alert.id, playbooks }))
+}
+
+/// Playbook runs, newest first, optionally of one alert
+async fn get_playbook_runs(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<PlaybookRunsQuery>,
+) -> Json<Vec<PlaybookRun>> {
+    Json(state.playbooks.runs(query.alert_id.as_deref()).await)
 }
 
 /// Get active emergency alerts
 async fn get_active_alerts(
     State(_state): State<Arc<ApiState>>,
-) -> Result<Json<Vec<EmergencyAlert>>, StatusCode> {
+) -> Result<Json<Vec<EmergencyAlert>>, ApiError> {
     // In a real implementation, this would get active alerts from the emergency response system
     Ok(Json(vec![]))
 }
@@ -157,7 +302,249 @@ async fn get_active_alerts(
 async fn get_address_threats(
     State(_state): State<Arc<ApiState>>,
     Path(_address): Path<Address>,
-) -> Result<Json<Vec<String>>, StatusCode> {
+) -> Result<Json<Vec<String>>, ApiError> {
     // In a real implementation, this would get threats for the specific address
     Ok(Json(vec![]))
 }
+
+/// Honeypot, tax, blacklist, mint and proxy checks of a token
+async fn get_token_safety(
+    State(state): State<Arc<ApiState>>,
+    Path((chain_id, token)): Path<(u64, Address)>,
+) -> Result<Json<TokenSafetyReport>, ApiError> {
+    Ok(Json(tokens::safety(&state, chain_id, token).await?))
+}
+
+/// Outstanding ERC-20 and ERC-721 approvals of a wallet with revoke transactions
+async fn get_approvals(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Query(query): Query<ApprovalsQuery>,
+) -> Result<Json<ApprovalReport>, ApiError> {
+    let chain_ids = query.chain_ids.as_deref().map(parse_chain_ids).transpose()?;
+    let (mut report, ens_name) = tokio::join!(
+        state.approvals.scan(address, chain_ids, query.revoke),
+        state.ens.lookup(address),
+    );
+    report.ens_name = ens_name;
+    for approval in &mut report.approvals {
+        approval.symbol = state.tokens.get(approval.chain_id, approval.token).await.map(|token| token.symbol);
+    }
+    Ok(Json(report))
+}
+
+/// Sampled prices, volatility, pair liquidity and correlations behind the market risk assessment
+async fn get_market_risk_data(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<MarketRiskData>, ApiError> {
+    Ok(Json(state.security.advanced.risk_engine().market_risk_data().await))
+}
+
+/// Project a wallet's lending positions through price and liquidity shocks
+async fn run_stress_test(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<StressTestRequest>,
+) -> Result<Json<StressTestResponse>, ApiError> {
+    let risk_engine = state.security.advanced.risk_engine();
+    let mut scenarios = request.scenarios;
+    for name in &request.presets {
+        let preset = risk_engine.stress_preset(name).await
+            .ok_or_else(|| ApiError::BadRequest(format!("Unknown stress test preset {}", name)))?;
+        scenarios.push(preset);
+    }
+    if scenarios.is_empty() {
+        scenarios = risk_engine.stress_presets().await;
+    }
+    if scenarios.len() > MAX_STRESS_SCENARIOS {
+        return Err(ApiError::BadRequest(format!("At most {} scenarios can be run at once", MAX_STRESS_SCENARIOS)));
+    }
+    for scenario in &scenarios {
+        scenario.validate().map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    }
+
+    let wallet = ens::resolve(&state, &request.wallet).await?;
+    let chain_id = request.chain_id.unwrap_or(1);
+    let portfolio = state.defi_manager.get_portfolio_overview(chain_id, wallet).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Internal))?;
+    let results = risk_engine
+        .run_stress_tests(chain_id, &portfolio.positions_usd, &scenarios, state.dex_manager.assets())
+        .await;
+
+    Ok(Json(StressTestResponse {
+        wallet,
+        chain_id,
+        net_worth_usd: portfolio.net_worth_usd,
+        unpriced_assets: portfolio.unpriced_assets,
+        results,
+    }))
+}
+
+/// Preset stress scenarios `POST /stress-test` accepts by name
+async fn get_stress_presets(
+    State(state): State<Arc<ApiState>>,
+) -> Json<Vec<StressScenario>> {
+    Json(state.security.advanced.risk_engine().stress_presets().await)
+}
+
+/// Configured sanctions lists with their address count, last load and last error
+async fn get_sanctions_lists(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<Vec<SanctionsListStatus>>, ApiError> {
+    Ok(Json(state.sanctions.list_statuses().await))
+}
+
+/// Screen an address against the sanctions lists, recording the result in the audit trail
+async fn screen_address(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<ScreeningResult>, ApiError> {
+    let result = state.sanctions.screen(&[address]).await;
+    sanctions::record(&state.security.advanced.audit_trail(), &result, "Address screening").await
+        .map_err(ApiError::internal)?;
+    Ok(Json(result))
+}
+
+/// Audit entries matching the filters, newest first
+async fn get_audit_log(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    let entries = state.security.advanced.audit_trail().query_entries(query.to_query(true)?).await
+        .map_err(ApiError::internal)?;
+    Ok(Json(entries))
+}
+
+/// Download every audit entry matching the filters, oldest first, with their chain hashes
+async fn export_audit_log(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let audit_trail = state.security.advanced.audit_trail();
+    let export = audit_trail.export(query.to_query(false)?, query.format).await
+        .map_err(ApiError::internal)?;
+    state.security.log_admin_action(&admin.actor, "export_audit_log", format!("{:?} export", query.format)).await
+        .map_err(ApiError::internal)?;
+
+    let (content_type, extension) = match query.format {
+        AuditExportFormat::Jsonl => ("application/x-ndjson", "jsonl"),
+        AuditExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+    };
+    let disposition = format!("attachment; filename=\"audit-log-{}.{}\"", Utc::now().format("%Y%m%dT%H%M%SZ"), extension);
+    Ok((
+        [(header::CONTENT_TYPE, content_type.to_string()), (header::CONTENT_DISPOSITION, disposition)],
+        export,
+    ))
+}
+
+/// Recompute the hash chain of the retained audit entries
+async fn verify_audit_log(
+    _admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<ChainVerification>, ApiError> {
+    Ok(Json(state.security.advanced.audit_trail().verify().await))
+}
+
+/// Get configured transaction value and gas limits
+async fn get_transaction_limits(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<TransactionLimits>, ApiError> {
+    Ok(Json(state.security.get_transaction_limits().await))
+}
+
+/// Replace transaction value and gas limits
+async fn update_transaction_limits(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(limits): Json<TransactionLimits>,
+) -> Result<Json<TransactionLimits>, ApiError> {
+    let chains: Vec<String> = limits.chains.keys().map(|id| id.to_string()).collect();
+    let details = format!(
+        "default ${:.2} / {} gas, chain overrides: [{}]",
+        limits.default.max_value_usd,
+        limits.default.max_gas_limit,
+        chains.join(", ")
+    );
+
+    state.security.set_transaction_limits(limits).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    state.security.log_admin_action(&admin.actor, "update_transaction_limits", details).await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(state.security.get_transaction_limits().await))
+}
+
+/// Get the spending policies of wallets and strategies
+async fn get_spending_policies(
+    State(state): State<Arc<ApiState>>,
+) -> Json<SpendingPolicies> {
+    Json(state.security.advanced.spending_policies().get_policies().await)
+}
+
+/// Replace the spending policies of wallets and strategies
+async fn update_spending_policies(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Json(policies): Json<SpendingPolicies>,
+) -> Result<Json<SpendingPolicies>, ApiError> {
+    let details = format!("{} wallet and {} strategy policies", policies.wallets.len(), policies.strategies.len());
+    let engine = state.security.advanced.spending_policies();
+    engine.set_policies(policies).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    state.security.log_admin_action(&admin.actor, "update_spending_policies", details).await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(engine.get_policies().await))
+}
+
+/// Spending policy overrides, newest first
+async fn get_policy_overrides(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<PolicyOverridesQuery>,
+) -> Json<Vec<PolicyOverride>> {
+    Json(state.security.advanced.spending_policies().overrides(query.wallet).await)
+}
+
+/// Ask an operator to let one transaction through despite the spending policies
+async fn request_policy_override(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<OverrideRequest>,
+) -> Result<Json<PolicyOverride>, ApiError> {
+    let requested = state.security.advanced.spending_policies().request_override(request).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+    Ok(Json(requested))
+}
+
+/// Approve a pending override, usable for one transaction until it expires
+async fn approve_policy_override(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<PolicyOverride>, ApiError> {
+    decide_policy_override(&state, &admin, &id, true).await
+}
+
+/// Reject a pending override
+async fn reject_policy_override(
+    admin: AdminGuard,
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+) -> Result<Json<PolicyOverride>, ApiError> {
+    decide_policy_override(&state, &admin, &id, false).await
+}
+
+async fn decide_policy_override(state: &ApiState, admin: &AdminGuard, id: &str, approve: bool) -> Result<Json<PolicyOverride>, ApiError> {
+    let decided = state.security.advanced.spending_policies().decide_override(id, approve, &admin.actor).await
+        .map_err(|e| ApiError::Conflict(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("Spending policy override {} not found", id)))?;
+
+    let action = if approve { "approve_policy_override" } else { "reject_policy_override" };
+    let details = format!("{} for {:?} up to ${:.2}: {}", decided.id, decided.wallet, decided.max_value_usd, decided.reason);
+    state.security.log_admin_action(&admin.actor, action, details).await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(decided))
+}
@@ -0,0 +1,37 @@
+use axum::{
+    extract::State,
+    response::Json,
+    routing::post,
+    Router,
+};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::chains::simulator::{SimulationRequest, SimulationResult, TransactionSimulator};
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/simulate", post(simulate_transaction))
+}
+
+/// Simulate a transaction against current chain state before signing
+async fn simulate_transaction(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<SimulationRequest>,
+) -> Result<Json<SimulationResult>, ApiError> {
+    if request.transaction.to.is_none() && request.transaction.data.is_none() {
+        return Err(ApiError::BadRequest("A transaction needs a recipient or calldata".to_string()));
+    }
+
+    let simulator = TransactionSimulator::new(state.chain_manager.clone());
+    let result = simulator.simulate(&request).await.map_err(|e| {
+        warn!("Simulation on chain {} failed: {}", request.chain_id, e);
+        ApiError::from_error(e, ApiError::Upstream)
+    })?;
+    if !result.success {
+        state.circuit_breakers.record_failed_simulation().await.map_err(ApiError::internal)?;
+    }
+
+    Ok(Json(result))
+}
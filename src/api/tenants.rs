@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::analytics::time_zones::TenantTimeSettings;
+use crate::api::{error::ApiError, ApiState};
+
+/// Time settings of a tenant with the schedule they produce
+#[derive(Serialize)]
+pub struct TenantTimeResponse {
+    pub tenant: Address,
+    #[serde(flatten)]
+    pub settings: TenantTimeSettings,
+    /// Next digest delivery at the tenant's local digest hour
+    pub next_digest_at: DateTime<Utc>,
+}
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new().route(
+        "/{address}/time",
+        get(get_time_settings).put(set_time_settings).delete(reset_time_settings),
+    )
+}
+
+async fn time_response(state: &ApiState, tenant: Address) -> TenantTimeResponse {
+    let settings = state.analytics.time_zones.get(tenant).await;
+    TenantTimeResponse {
+        tenant,
+        settings,
+        next_digest_at: settings.next_digest_at(Utc::now()),
+    }
+}
+
+/// Time zone, digest hour and tax year start of a tenant, the defaults when never set
+async fn get_time_settings(
+    State(state): State<Arc<ApiState>>,
+    Path(tenant): Path<Address>,
+) -> Result<Json<TenantTimeResponse>, ApiError> {
+    Ok(Json(time_response(&state, tenant).await))
+}
+
+async fn set_time_settings(
+    State(state): State<Arc<ApiState>>,
+    Path(tenant): Path<Address>,
+    Json(settings): Json<TenantTimeSettings>,
+) -> Result<Json<TenantTimeResponse>, ApiError> {
+    state.analytics.time_zones.set(tenant, settings).await.map_err(|e| {
+        warn!("Rejected time settings for {:?}: {}", tenant, e);
+        ApiError::from_error(e, ApiError::BadRequest)
+    })?;
+
+    Ok(Json(time_response(&state, tenant).await))
+}
+
+/// Return a tenant to the configured default time settings
+async fn reset_time_settings(
+    State(state): State<Arc<ApiState>>,
+    Path(tenant): Path<Address>,
+) -> Result<Json<TenantTimeResponse>, ApiError> {
+    if !state.analytics.time_zones.reset(tenant).await {
+        return Err(ApiError::NotFound(format!("Tenant {:?} has no time settings", tenant)));
+    }
+
+    Ok(Json(time_response(&state, tenant).await))
+}
@@ -0,0 +1,65 @@
+// Token addresses of requests checked against the token registry
+use ethers::types::Address;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::chains::tokens::{TokenMetadata, TokenSource, UnknownToken};
+use crate::security::{TokenRisk, TokenSafetyReport};
+
+/// Metadata of a token, `400` when the address is not a token
+pub async fn validate(state: &ApiState, chain_id: u64, token: Address) -> Result<TokenMetadata, ApiError> {
+    state.tokens.validate(chain_id, token).await.map_err(|e| match e.downcast_ref::<UnknownToken>() {
+        Some(unknown) => ApiError::BadRequest(unknown.to_string()),
+        None => ApiError::from_error(e, ApiError::Upstream),
+    })
+}
+
+/// Metadata of both tokens of a swap
+pub async fn validate_pair(
+    state: &ApiState,
+    chain_id: u64,
+    token_in: Address,
+    token_out: Address,
+) -> Result<(TokenMetadata, TokenMetadata), ApiError> {
+    tokio::try_join!(validate(state, chain_id, token_in), validate(state, chain_id, token_out))
+}
+
+/// Safety report of a token, `400` when the address is not a token
+pub async fn safety(state: &ApiState, chain_id: u64, token: Address) -> Result<TokenSafetyReport, ApiError> {
+    validate(state, chain_id, token).await?;
+    state.token_safety.scan(chain_id, token).await.map_err(|e| ApiError::from_error(e, ApiError::Upstream))
+}
+
+/// Safety reports of the tokens found on-chain rather than in a token list or the custom tokens
+pub async fn unlisted_safety(state: &ApiState, tokens: &[TokenMetadata]) -> Result<Vec<TokenSafetyReport>, ApiError> {
+    let unlisted = tokens.iter().filter(|token| token.source == TokenSource::Onchain);
+    futures::future::try_join_all(unlisted.map(|token| safety(state, token.chain_id, token.address))).await
+}
+
+/// Refuse orders in unlisted tokens that cannot be sold or carry critical risk
+pub async fn ensure_tradeable(state: &ApiState, tokens: &[TokenMetadata]) -> Result<(), ApiError> {
+    for report in unlisted_safety(state, tokens).await? {
+        if report.honeypot || report.risk == TokenRisk::Critical {
+            let detail = report.findings.iter()
+                .filter(|finding| finding.risk == TokenRisk::Critical)
+                .map(|finding| finding.detail.as_str())
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(ApiError::BadRequest(format!(
+                "Token {:?} on chain {} failed its safety scan: {}", report.token, report.chain_id, detail,
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Metadata of both tokens of an order, `400` when either fails its safety scan
+pub async fn validate_tradeable_pair(
+    state: &ApiState,
+    chain_id: u64,
+    token_in: Address,
+    token_out: Address,
+) -> Result<(TokenMetadata, TokenMetadata), ApiError> {
+    let (token_in, token_out) = validate_pair(state, chain_id, token_in, token_out).await?;
+    ensure_tradeable(state, &[token_in.clone(), token_out.clone()]).await?;
+    Ok((token_in, token_out))
+}
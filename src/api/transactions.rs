@@ -0,0 +1,93 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use ethers::types::{Address, H256};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::api::{error::ApiError, ApiState};
+use crate::transactions::{TransactionRecord, TransactionStatus};
+
+/// Transaction history query parameters
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<usize>,
+    /// Only transactions of the tax year starting in this year, cut off in the user's time zone
+    pub tax_year: Option<i32>,
+}
+
+/// Hash of a built transaction the user broadcast themselves
+#[derive(Deserialize)]
+pub struct AttachHashRequest {
+    pub hash: H256,
+}
+
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+pub fn routes() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/{hash}", get(get_transaction))
+        .route("/user/{address}", get(get_user_transactions))
+        .route("/records/{id}/hash", post(attach_transaction_hash))
+}
+
+/// Transaction lifecycle, refreshed from the chain while it can still change
+async fn get_transaction(
+    State(state): State<Arc<ApiState>>,
+    Path(hash): Path<H256>,
+) -> Result<Json<TransactionRecord>, ApiError> {
+    let record = state.transactions.get_by_hash(hash).await
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {:?} is not tracked", hash)))?;
+    if !matches!(record.status, TransactionStatus::Pending | TransactionStatus::Confirmed) {
+        return Ok(Json(record));
+    }
+
+    // An unreachable chain still leaves the last known state worth returning
+    match state.transactions.refresh(hash).await {
+        Ok(refreshed) => Ok(Json(refreshed)),
+        Err(e) => {
+            warn!("Failed to refresh transaction {:?}: {}", hash, e);
+            Ok(Json(record))
+        }
+    }
+}
+
+/// Transactions built for or sent by a user, newest first
+async fn get_user_transactions(
+    State(state): State<Arc<ApiState>>,
+    Path(address): Path<Address>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<TransactionRecord>>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    let range = match query.tax_year {
+        Some(year) => {
+            let settings = state.analytics.time_zones.get(address).await;
+            Some(settings.tax_year_bounds(year).map_err(|e| {
+                warn!("Invalid tax year for {:?}: {}", address, e);
+                ApiError::from_error(e, ApiError::BadRequest)
+            })?)
+        }
+        None => None,
+    };
+
+    Ok(Json(state.transactions.history(address, range, limit).await))
+}
+
+/// Link a built transaction to the hash it was broadcast under
+async fn attach_transaction_hash(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+    Json(request): Json<AttachHashRequest>,
+) -> Result<Json<TransactionRecord>, ApiError> {
+    let record = state.transactions.attach_hash(&id, request.hash).await
+        .map_err(|e| {
+            warn!("Failed to attach hash to transaction record {}: {}", id, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(record))
+}
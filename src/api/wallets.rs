@@ -1,18 +1,41 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::Json,
-    routing::{get, post, delete},
+    routing::{get, post, put, delete},
     Router,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tracing::warn;
 use ethers::{
-    types::{Address, Signature, transaction::eip2718::TypedTransaction},
+    types::{Address, Bytes, Signature, H256, U256, transaction::{eip2718::TypedTransaction, eip712::TypedData}},
     utils::hex,
 };
 
-use crate::api::ApiState;
+use crate::api::{
+    admin::AdminGuard,
+    ens::{self, AddressPath},
+    error::ApiError,
+    models::ArchiveQuery,
+    replay::SignedJson,
+    ApiState,
+};
+use crate::chains::ens::AddressOrName;
+use crate::chains::tx_broadcaster::TrackedTransaction;
+use crate::security::PolicyContext;
+use crate::wallets::{
+    eip712,
+    labels::WalletLabel,
+    ledger::{transport::LedgerError, DerivationScheme, LedgerAccount, LedgerDevice, LedgerWallet},
+    multisig::{MultiSigWallet, PendingTransaction, SafeCall},
+    smart_account::{SmartAccountCall, SmartAccountWallet, SubmittedUserOperation, UserOperation},
+    walletconnect::WalletConnectPairing,
+};
+
+/// Ledger addresses derived when the query does not ask for a count
+const DEFAULT_LEDGER_DISCOVERY_COUNT: u32 = 5;
 
 /// Wallet connection request
 #[derive(Deserialize)]
@@ -22,18 +45,91 @@ pub struct WalletConnectionRequest {
     pub metadata: Option<std::collections::HashMap<String, String>>,
 }
 
+/// Range of Ledger accounts to derive
+#[derive(Deserialize)]
+pub struct LedgerDiscoveryQuery {
+    #[serde(default)]
+    pub scheme: DerivationScheme,
+    #[serde(default)]
+    pub start: u32,
+    pub count: Option<u32>,
+}
+
 /// Local wallet creation request
 #[derive(Deserialize)]
 pub struct LocalWalletRequest {
     pub private_key: Option<String>, // If None, generates random
 }
 
-/// Multi-sig wallet creation request
+/// Safe deployment request, paid for by a local wallet
 #[derive(Deserialize)]
 pub struct MultiSigWalletRequest {
     pub owners: Vec<Address>,
     pub threshold: u8,
     pub chain_id: u64,
+    pub deployer: Address,
+    /// CREATE2 salt of the Safe proxy, a fresh one when omitted
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    pub salt_nonce: Option<U256>,
+    /// Strategy the deployment is made for, counted against its spending policy
+    pub strategy_id: Option<String>,
+    /// Approved spending policy override to use up if the deployment breaks a policy
+    pub override_id: Option<String>,
+}
+
+/// Safe deployed elsewhere to manage here
+#[derive(Deserialize)]
+pub struct ImportMultiSigRequest {
+    pub chain_id: u64,
+    pub address: Address,
+}
+
+/// Safe transaction proposal, signed right away by `proposer` when it is a connected owner
+#[derive(Deserialize)]
+pub struct ProposeSafeTransactionRequest {
+    #[serde(flatten)]
+    pub call: SafeCall,
+    pub proposer: Option<Address>,
+}
+
+/// Owner confirmation: a connected owner signing here, or a signature made elsewhere
+#[derive(Deserialize)]
+pub struct ConfirmSafeTransactionRequest {
+    pub owner: Option<Address>,
+    pub signature: Option<Bytes>,
+}
+
+/// Local wallet paying the gas of a Safe execution
+#[derive(Deserialize)]
+pub struct ExecuteSafeTransactionRequest {
+    pub executor: Address,
+    /// Strategy the execution is made for, counted against its spending policy
+    pub strategy_id: Option<String>,
+    /// Approved spending policy override to use up if the execution breaks a policy
+    pub override_id: Option<String>,
+}
+
+/// ERC-4337 smart account of a connected owner wallet
+#[derive(Deserialize)]
+pub struct SmartAccountRequest {
+    pub owner: Address,
+    pub chain_id: u64,
+    /// Factory salt, so one owner can hold several accounts
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    pub salt: Option<U256>,
+}
+
+/// Calls to make from a smart account in one user operation
+#[derive(Deserialize)]
+pub struct UserOperationRequest {
+    pub calls: Vec<SmartAccountCall>,
+    /// Have the configured paymaster pay for gas
+    #[serde(default)]
+    pub sponsor: bool,
+    /// Strategy the calls are made for, counted against its spending policy
+    pub strategy_id: Option<String>,
+    /// Approved spending policy override to use up if a call breaks a policy
+    pub override_id: Option<String>,
 }
 
 /// Message signing request
@@ -46,6 +142,34 @@ pub struct SignMessageRequest {
 #[derive(Deserialize)]
 pub struct SignTransactionRequest {
     pub transaction: TypedTransaction,
+    /// Strategy the transaction is made for, counted against its spending policy
+    pub strategy_id: Option<String>,
+    /// Approved spending policy override to use up if the transaction breaks a policy
+    pub override_id: Option<String>,
+}
+
+/// EIP-712 signing request, `typed_data` as dApps pass it to `eth_signTypedData_v4`
+#[derive(Deserialize)]
+pub struct SignTypedDataRequest {
+    pub typed_data: TypedData,
+}
+
+/// Typed data signature with the digest it was made over
+#[derive(Serialize)]
+pub struct TypedDataSignatureResponse {
+    pub digest: H256,
+    pub signature: Signature,
+}
+
+/// Transaction to sign with a local wallet and broadcast
+#[derive(Deserialize)]
+pub struct SendTransactionRequest {
+    pub chain_id: u64,
+    pub transaction: TypedTransaction,
+    /// Strategy the transaction is made for, counted against its spending policy
+    pub strategy_id: Option<String>,
+    /// Approved spending policy override to use up if the transaction breaks a policy
+    pub override_id: Option<String>,
 }
 
 /// Wallet info response
@@ -56,6 +180,32 @@ pub struct WalletInfoResponse {
     pub chain_id: u64,
     pub is_connected: bool,
     pub balance: Option<String>, // ETH balance
+    pub disconnected_at: Option<DateTime<Utc>>,
+    /// Role restricting what the wallet may be used for, `None` when unlabeled
+    pub label: Option<WalletLabel>,
+    /// Primary ENS name, `None` when the wallet has none
+    pub ens_name: Option<String>,
+}
+
+/// Chain a wallet should sign on
+#[derive(Deserialize)]
+pub struct WalletChainRequest {
+    pub chain_id: u64,
+}
+
+/// Chain a wallet signs on and the chains it can switch to
+#[derive(Serialize)]
+pub struct WalletChainsResponse {
+    pub address: Address,
+    pub chain_id: u64,
+    /// `None` when the wallet signs on any chain
+    pub supported_chain_ids: Option<Vec<u64>>,
+}
+
+/// Wallet label assignment
+#[derive(Deserialize)]
+pub struct WalletLabelRequest {
+    pub label: WalletLabel,
 }
 
 /// Wallet connection response
@@ -71,23 +221,42 @@ pub fn routes() -> Router<Arc<ApiState>> {
     Router::new()
         .route("/connect/metamask", post(connect_metamask))
         .route("/connect/walletconnect", post(connect_walletconnect))
+        .route("/walletconnect/pairings/{topic}", get(get_walletconnect_pairing))
         .route("/connect/ledger", post(connect_ledger))
+        .route("/ledger/devices", get(list_ledger_devices))
+        .route("/ledger/addresses", get(discover_ledger_addresses))
         .route("/create/local", post(create_local_wallet))
         .route("/create/multisig", post(create_multisig_wallet))
+        .route("/multisig/import", post(import_multisig_wallet))
+        .route("/multisig/{address}", get(get_multisig_wallet))
+        .route("/multisig/{address}/transactions", get(list_safe_transactions).post(propose_safe_transaction))
+        .route("/multisig/{address}/transactions/{safe_tx_hash}/confirmations", post(confirm_safe_transaction))
+        .route("/multisig/{address}/transactions/{safe_tx_hash}/execute", post(execute_safe_transaction))
+        .route("/create/smart-account", post(create_smart_account))
+        .route("/smart-account/{address}", get(get_smart_account))
+        .route("/smart-account/{address}/user-operations", get(list_user_operations).post(execute_user_operation))
+        .route("/smart-account/{address}/user-operations/estimate", post(estimate_user_operation))
+        .route("/smart-account/{address}/user-operations/{user_op_hash}", get(get_user_operation))
         .route("/list", get(list_wallets))
         .route("/{address}", get(get_wallet_info))
         .route("/{address}", delete(disconnect_wallet))
+        .route("/{address}/label", put(set_wallet_label).delete(clear_wallet_label))
+        .route("/{address}/chains", get(get_wallet_chains))
+        .route("/{address}/chain", put(switch_wallet_chain))
         .route("/{address}/sign/message", post(sign_message))
         .route("/{address}/sign/transaction", post(sign_transaction))
+        .route("/{address}/sign/typed-data", post(sign_typed_data))
+        .route("/{address}/send", post(send_transaction))
+        .route("/{address}/transactions/{tx_hash}/speed-up", post(speed_up_transaction))
 }
 
 /// Connect MetaMask wallet
 async fn connect_metamask(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<WalletConnectionRequest>,
-) -> Result<Json<WalletConnectionResponse>, StatusCode> {
+) -> Result<Json<WalletConnectionResponse>, ApiError> {
     let address = state.wallet_manager.connect_metamask(request.chain_id).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(ApiError::internal)?;
     
     Ok(Json(WalletConnectionResponse {
         address,
@@ -97,40 +266,47 @@ async fn connect_metamask(
     }))
 }
 
-/// Connect WalletConnect
+/// Start a WalletConnect pairing, returning the URI for the user's wallet to scan
 async fn connect_walletconnect(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<WalletConnectionRequest>,
-) -> Result<Json<WalletConnectionResponse>, StatusCode> {
-    let project_id = request.metadata
-        .as_ref()
-        .and_then(|m| m.get("project_id"))
-        .ok_or(StatusCode::BAD_REQUEST)?;
-    
-    let address = state.wallet_manager.connect_walletconnect(project_id).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    Ok(Json(WalletConnectionResponse {
-        address,
-        wallet_type: "walletconnect".to_string(),
-        chain_id: request.chain_id,
-        message: "WalletConnect connected successfully".to_string(),
-    }))
+) -> Result<Json<WalletConnectPairing>, ApiError> {
+    let pairing = state.wallet_manager.pair_walletconnect(&[request.chain_id]).await
+        .map_err(|e| {
+            warn!("WalletConnect pairing failed: {}", e);
+            ApiError::from_error(e, ApiError::Unavailable)
+        })?;
+
+    Ok(Json(pairing))
+}
+
+/// Status of a WalletConnect pairing, with the wallet's accounts once approved
+async fn get_walletconnect_pairing(
+    State(state): State<Arc<ApiState>>,
+    Path(topic): Path<String>,
+) -> Result<Json<WalletConnectPairing>, ApiError> {
+    let pairing = state.wallet_manager.walletconnect_pairing(&topic).await
+        .map_err(|e| {
+            warn!("WalletConnect pairing lookup failed: {}", e);
+            ApiError::from_error(e, ApiError::Unavailable)
+        })?;
+
+    pairing.map(Json).ok_or_else(|| ApiError::NotFound(format!("No pairing with topic {}", topic)))
 }
 
 /// Connect Ledger wallet
 async fn connect_ledger(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<WalletConnectionRequest>,
-) -> Result<Json<WalletConnectionResponse>, StatusCode> {
+) -> Result<Json<WalletConnectionResponse>, ApiError> {
     let derivation_path = request.metadata
         .as_ref()
         .and_then(|m| m.get("derivation_path"))
         .map(|s| s.as_str())
-        .unwrap_or("m/44'/60'/0'/0");
+        .unwrap_or("m/44'/60'/0'/0/0");
     
     let address = state.wallet_manager.connect_ledger(derivation_path).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ledger_error(e, ApiError::BadRequest))?;
     
     Ok(Json(WalletConnectionResponse {
         address,
@@ -140,13 +316,51 @@ async fn connect_ledger(
     }))
 }
 
+/// Ledger devices attached to the server
+async fn list_ledger_devices() -> Result<Json<Vec<LedgerDevice>>, ApiError> {
+    let devices = LedgerWallet::list_devices().await
+        .map_err(|e| ledger_error(e, ApiError::Internal))?;
+
+    Ok(Json(devices))
+}
+
+/// Addresses of the attached Ledger, e.g. `?scheme=ledger_live&start=0&count=5`
+async fn discover_ledger_addresses(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<LedgerDiscoveryQuery>,
+) -> Result<Json<Vec<LedgerAccount>>, ApiError> {
+    let accounts = state.wallet_manager
+        .discover_ledger_addresses(query.scheme, query.start, query.count.unwrap_or(DEFAULT_LEDGER_DISCOVERY_COUNT))
+        .await
+        .map_err(|e| ledger_error(e, ApiError::BadRequest))?;
+
+    Ok(Json(accounts))
+}
+
+/// Error for a failed Ledger operation, telling apart the states the user has to fix on the device
+fn ledger_error(error: anyhow::Error, fallback: fn(String) -> ApiError) -> ApiError {
+    warn!("Ledger request failed: {}", error);
+    let Some(ledger) = error.downcast_ref::<LedgerError>() else {
+        return ApiError::from_error(error, fallback);
+    };
+    let message = ledger.to_string();
+    match ledger {
+        LedgerError::DeviceNotFound | LedgerError::Unsupported(_) => ApiError::Unavailable(message),
+        LedgerError::DeviceLocked => ApiError::Locked(message),
+        LedgerError::AppNotOpen(_) => ApiError::Conflict(message),
+        LedgerError::Rejected => ApiError::Forbidden(message),
+        LedgerError::InvalidData => ApiError::Unprocessable(message),
+        LedgerError::Status(_) => ApiError::Upstream(message),
+    }
+}
+
 /// Create local wallet
 async fn create_local_wallet(
     State(state): State<Arc<ApiState>>,
     Json(request): Json<LocalWalletRequest>,
-) -> Result<Json<WalletConnectionResponse>, StatusCode> {
+) -> Result<Json<WalletConnectionResponse>, ApiError> {
     let address = state.wallet_manager.create_local_wallet(request.private_key).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(ApiError::internal)?;
     
     Ok(Json(WalletConnectionResponse {
         address,
@@ -156,40 +370,246 @@ async fn create_local_wallet(
     }))
 }
 
-/// Create multi-sig wallet
+/// Deploy a Safe through the proxy factory
 async fn create_multisig_wallet(
     State(state): State<Arc<ApiState>>,
-    Json(request): Json<MultiSigWalletRequest>,
-) -> Result<Json<WalletConnectionResponse>, StatusCode> {
-    let address = state.wallet_manager.create_multisig_wallet(
+    SignedJson(request): SignedJson<MultiSigWalletRequest>,
+) -> Result<Json<MultiSigWallet>, ApiError> {
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let wallet = state.wallet_manager.create_multisig_wallet(
         request.owners,
         request.threshold,
         request.chain_id,
-    ).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    Ok(Json(WalletConnectionResponse {
-        address,
-        wallet_type: "multisig".to_string(),
-        chain_id: request.chain_id,
-        message: "Multi-sig wallet created successfully".to_string(),
-    }))
+        request.deployer,
+        request.salt_nonce,
+        &context,
+    ).await.map_err(|e| {
+        warn!("Safe deployment failed: {}", e);
+        ApiError::from_error(e, ApiError::BadRequest)
+    })?;
+
+    Ok(Json(wallet))
+}
+
+/// Manage a Safe deployed elsewhere, reading its owners and threshold from the chain
+async fn import_multisig_wallet(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<ImportMultiSigRequest>,
+) -> Result<Json<MultiSigWallet>, ApiError> {
+    let wallet = state.wallet_manager.import_multisig_wallet(request.chain_id, request.address).await
+        .map_err(|e| {
+            warn!("Safe import of {:?} failed: {}", request.address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(wallet))
+}
+
+async fn get_multisig_wallet(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<MultiSigWallet>, ApiError> {
+    let wallet = state.wallet_manager.multisig().get_wallet(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    Ok(Json(wallet))
+}
+
+/// Transactions of a Safe waiting for signatures or execution, synced with the Safe Transaction Service
+async fn list_safe_transactions(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<Vec<PendingTransaction>>, ApiError> {
+    state.wallet_manager.multisig().get_wallet(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    let transactions = state.wallet_manager.multisig().pending_transactions(address).await
+        .map_err(|e| {
+            warn!("Listing transactions of Safe {:?} failed: {}", address, e);
+            ApiError::from_error(e, ApiError::Internal)
+        })?;
+
+    Ok(Json(transactions))
+}
+
+/// Propose a Safe transaction at the next free nonce
+async fn propose_safe_transaction(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Json(request): Json<ProposeSafeTransactionRequest>,
+) -> Result<Json<PendingTransaction>, ApiError> {
+    state.wallet_manager.multisig().get_wallet(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    let pending = state.wallet_manager.propose_safe_transaction(address, request.call, request.proposer).await
+        .map_err(|e| {
+            warn!("Safe transaction proposal for {:?} failed: {}", address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(pending))
+}
+
+/// Add an owner's signature to a Safe transaction
+async fn confirm_safe_transaction(
+    State(state): State<Arc<ApiState>>,
+    Path((target, safe_tx_hash)): Path<(AddressOrName, H256)>,
+    Json(request): Json<ConfirmSafeTransactionRequest>,
+) -> Result<Json<PendingTransaction>, ApiError> {
+    let address = ens::resolve(&state, &target).await?;
+    safe_transaction(&state, address, safe_tx_hash).await?;
+    let result = match (request.owner, request.signature) {
+        (_, Some(signature)) => state.wallet_manager.multisig().add_signature(safe_tx_hash, signature).await,
+        (Some(owner), None) => state.wallet_manager.confirm_safe_transaction(safe_tx_hash, owner).await,
+        (None, None) => return Err(ApiError::BadRequest("Either owner or signature is required".to_string())),
+    };
+    let pending = result.map_err(|e| {
+        warn!("Confirmation of Safe transaction {:?} failed: {}", safe_tx_hash, e);
+        ledger_error(e, ApiError::BadRequest)
+    })?;
+
+    Ok(Json(pending))
+}
+
+/// Execute a Safe transaction once enough owners signed it
+async fn execute_safe_transaction(
+    State(state): State<Arc<ApiState>>,
+    Path((target, safe_tx_hash)): Path<(AddressOrName, H256)>,
+    SignedJson(request): SignedJson<ExecuteSafeTransactionRequest>,
+) -> Result<Json<PendingTransaction>, ApiError> {
+    let address = ens::resolve(&state, &target).await?;
+    let pending = safe_transaction(&state, address, safe_tx_hash).await?;
+    if !pending.is_ready() {
+        return Err(ApiError::Conflict(format!("Safe transaction {:?} does not have enough signatures", safe_tx_hash)));
+    }
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let pending = state.wallet_manager.execute_safe_transaction(safe_tx_hash, request.executor, &context).await
+        .map_err(|e| {
+            warn!("Execution of Safe transaction {:?} failed: {}", safe_tx_hash, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(pending))
+}
+
+/// Pending transaction of the Safe in the path, 404 for another Safe's
+async fn safe_transaction(state: &ApiState, safe: Address, safe_tx_hash: H256) -> Result<PendingTransaction, ApiError> {
+    state.wallet_manager.multisig().get_transaction(safe_tx_hash).await
+        .ok()
+        .filter(|pending| pending.safe == safe)
+        .ok_or_else(|| ApiError::NotFound(format!("Safe {:?} has no transaction {:?}", safe, safe_tx_hash)))
+}
+
+/// Register the smart account of a connected owner, deployed by its first user operation
+async fn create_smart_account(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<SmartAccountRequest>,
+) -> Result<Json<SmartAccountWallet>, ApiError> {
+    let account = state.wallet_manager.create_smart_account(request.owner, request.chain_id, request.salt).await
+        .map_err(|e| {
+            warn!("Smart account creation for {:?} failed: {}", request.owner, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(account))
+}
+
+async fn get_smart_account(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<SmartAccountWallet>, ApiError> {
+    let account = state.wallet_manager.smart_accounts().get_account(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    Ok(Json(account))
+}
+
+/// User operations submitted from a smart account, newest first
+async fn list_user_operations(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<Vec<SubmittedUserOperation>>, ApiError> {
+    state.wallet_manager.smart_accounts().get_account(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    Ok(Json(state.wallet_manager.smart_accounts().user_operations(address).await))
+}
+
+/// Build and gas-estimate a user operation without signing or submitting it
+async fn estimate_user_operation(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Json(request): Json<UserOperationRequest>,
+) -> Result<Json<UserOperation>, ApiError> {
+    state.wallet_manager.smart_accounts().get_account(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    let user_operation = state.wallet_manager.smart_accounts()
+        .build_user_operation(address, &request.calls, request.sponsor)
+        .await
+        .map_err(|e| {
+            warn!("User operation estimate for {:?} failed: {}", address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(user_operation))
 }
 
-/// List all connected wallets
+/// Sign a user operation with the account's owner and submit it to the bundler
+async fn execute_user_operation(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    SignedJson(request): SignedJson<UserOperationRequest>,
+) -> Result<Json<SubmittedUserOperation>, ApiError> {
+    state.wallet_manager.smart_accounts().get_account(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let submitted = state.wallet_manager.execute_user_operation(address, request.calls, request.sponsor, &context).await
+        .map_err(|e| {
+            warn!("User operation from {:?} failed: {}", address, e);
+            ledger_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(submitted))
+}
+
+/// A submitted user operation, with its bundle transaction once included
+async fn get_user_operation(
+    State(state): State<Arc<ApiState>>,
+    Path((target, user_op_hash)): Path<(AddressOrName, H256)>,
+) -> Result<Json<SubmittedUserOperation>, ApiError> {
+    let address = ens::resolve(&state, &target).await?;
+    let operation = state.wallet_manager.smart_accounts().get_user_operation(user_op_hash).await
+        .map_err(|e| {
+            warn!("User operation {:?} lookup failed: {}", user_op_hash, e);
+            ApiError::from_error(e, ApiError::NotFound)
+        })?;
+    if operation.sender != address {
+        return Err(ApiError::NotFound(format!("User operation {:?} is not from {:?}", user_op_hash, address)));
+    }
+
+    Ok(Json(operation))
+}
+
+/// List connected wallets, or disconnected ones with `status=archived`/`all`
 async fn list_wallets(
     State(state): State<Arc<ApiState>>,
-) -> Result<Json<Vec<WalletInfoResponse>>, StatusCode> {
-    let wallets = state.wallet_manager.list_wallets().await;
+    Query(query): Query<ArchiveQuery>,
+) -> Result<Json<Vec<WalletInfoResponse>>, ApiError> {
+    let wallets = state.wallet_manager.list_wallets(query.status).await;
+    let addresses: Vec<Address> = wallets.iter().map(|info| info.address).collect();
+    let mut ens_names = state.ens.lookup_all(&addresses).await;
     
-    let wallet_responses = wallets.into_iter().map(|info| {
-        WalletInfoResponse {
+    let mut wallet_responses = Vec::with_capacity(wallets.len());
+    for info in wallets {
+        wallet_responses.push(WalletInfoResponse {
             address: info.address,
             wallet_type: format!("{:?}", info.wallet_type), // Convert enum to string
             chain_id: info.chain_id,
             is_connected: info.is_connected,
             balance: None, // Would fetch balance in real implementation
-        }
-    }).collect();
+            disconnected_at: info.disconnected_at,
+            label: state.wallet_manager.labels().get(info.address).await,
+            ens_name: ens_names.remove(&info.address),
+        });
+    }
     
     Ok(Json(wallet_responses))
 }
@@ -197,10 +617,10 @@ async fn list_wallets(
 /// Get wallet information
 async fn get_wallet_info(
     State(state): State<Arc<ApiState>>,
-    Path(address): Path<Address>,
-) -> Result<Json<WalletInfoResponse>, StatusCode> {
+    AddressPath(address): AddressPath,
+) -> Result<Json<WalletInfoResponse>, ApiError> {
     let info = state.wallet_manager.get_wallet_info(address).await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
     
     Ok(Json(WalletInfoResponse {
         address: info.address,
@@ -208,16 +628,82 @@ async fn get_wallet_info(
         chain_id: info.chain_id,
         is_connected: info.is_connected,
         balance: None, // Would fetch balance in real implementation
+        disconnected_at: info.disconnected_at,
+        label: state.wallet_manager.labels().get(address).await,
+        ens_name: state.ens.lookup(address).await,
     }))
 }
 
+/// Chain a wallet signs on and, for WalletConnect wallets, the chains their session approved
+async fn get_wallet_chains(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+) -> Result<Json<WalletChainsResponse>, ApiError> {
+    let (chain_id, supported_chain_ids) = state.wallet_manager.wallet_chains(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+
+    Ok(Json(WalletChainsResponse { address, chain_id, supported_chain_ids }))
+}
+
+/// Switch the chain a WalletConnect or MetaMask wallet signs on
+async fn switch_wallet_chain(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    Json(request): Json<WalletChainRequest>,
+) -> Result<Json<WalletChainsResponse>, ApiError> {
+    state.wallet_manager.wallet_chains(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    state.wallet_manager.switch_wallet_chain(address, request.chain_id).await
+        .map_err(|e| ApiError::from_error(e, ApiError::BadRequest))?;
+
+    let (chain_id, supported_chain_ids) = state.wallet_manager.wallet_chains(address).await
+        .map_err(|e| ApiError::from_error(e, ApiError::NotFound))?;
+    Ok(Json(WalletChainsResponse { address, chain_id, supported_chain_ids }))
+}
+
+/// Label a wallet `view_only` (alias `cold`), `operational` or `strategy`, restricting the automated
+/// and high-risk flows it may be used for
+async fn set_wallet_label(
+    State(state): State<Arc<ApiState>>,
+    admin: AdminGuard,
+    AddressPath(address): AddressPath,
+    Json(request): Json<WalletLabelRequest>,
+) -> Result<StatusCode, ApiError> {
+    state.wallet_manager.labels().set(address, Some(request.label)).await
+        .map_err(|e| {
+            warn!("Labeling wallet {:?} failed: {}", address, e);
+            ApiError::from_error(e, ApiError::Internal)
+        })?;
+    state.security.log_admin_action(&admin.actor, "label_wallet", format!("{:?}: {}", address, request.label)).await
+        .map_err(ApiError::internal)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove a wallet's label, leaving it usable for on-request signing only
+async fn clear_wallet_label(
+    State(state): State<Arc<ApiState>>,
+    admin: AdminGuard,
+    AddressPath(address): AddressPath,
+) -> Result<StatusCode, ApiError> {
+    state.wallet_manager.labels().set(address, None).await
+        .map_err(|e| {
+            warn!("Removing the label of wallet {:?} failed: {}", address, e);
+            ApiError::from_error(e, ApiError::Internal)
+        })?;
+    state.security.log_admin_action(&admin.actor, "unlabel_wallet", format!("{:?}", address)).await
+        .map_err(ApiError::internal)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Disconnect wallet
 async fn disconnect_wallet(
     State(state): State<Arc<ApiState>>,
-    Path(address): Path<Address>,
-) -> Result<Json<String>, StatusCode> {
+    AddressPath(address): AddressPath,
+) -> Result<Json<String>, ApiError> {
     state.wallet_manager.disconnect_wallet(address).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(ApiError::internal)?;
     
     Ok(Json("Wallet disconnected successfully".to_string()))
 }
@@ -225,15 +711,15 @@ async fn disconnect_wallet(
 /// Sign message with wallet
 async fn sign_message(
     State(state): State<Arc<ApiState>>,
-    Path(address): Path<Address>,
-    Json(request): Json<SignMessageRequest>,
-) -> Result<Json<Signature>, StatusCode> {
+    AddressPath(address): AddressPath,
+    SignedJson(request): SignedJson<SignMessageRequest>,
+) -> Result<Json<Signature>, ApiError> {
     // Decode hex message
     let message = hex::decode(&request.message.trim_start_matches("0x"))
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|e| ApiError::BadRequest(format!("Message is not hex: {}", e)))?;
     
     let signature = state.wallet_manager.sign_message(address, &message).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ledger_error(e, ApiError::Internal))?;
     
     Ok(Json(signature))
 }
@@ -241,11 +727,75 @@ async fn sign_message(
 /// Sign transaction with wallet
 async fn sign_transaction(
     State(state): State<Arc<ApiState>>,
-    Path(address): Path<Address>,
-    Json(request): Json<SignTransactionRequest>,
-) -> Result<Json<Signature>, StatusCode> {
-    let signature = state.wallet_manager.sign_transaction(address, request.transaction).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AddressPath(address): AddressPath,
+    SignedJson(request): SignedJson<SignTransactionRequest>,
+) -> Result<Json<Signature>, ApiError> {
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let signature = state.wallet_manager.sign_transaction(address, request.transaction, &context).await
+        .map_err(|e| ledger_error(e, ApiError::Internal))?;
     
     Ok(Json(signature))
 }
+
+/// Sign EIP-712 typed data with wallet
+async fn sign_typed_data(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    SignedJson(request): SignedJson<SignTypedDataRequest>,
+) -> Result<Json<TypedDataSignatureResponse>, ApiError> {
+    let digest = eip712::digest(&request.typed_data)
+        .map_err(|e| {
+            warn!("Invalid typed data for {:?}: {}", address, e);
+            ApiError::from_error(e, ApiError::Unprocessable)
+        })?;
+
+    let signature = state.wallet_manager.sign_typed_data(address, &request.typed_data).await
+        .map_err(|e| ledger_error(e, ApiError::Internal))?;
+
+    Ok(Json(TypedDataSignatureResponse { digest, signature }))
+}
+
+/// Sign with a local wallet and broadcast, managing the nonce
+async fn send_transaction(
+    State(state): State<Arc<ApiState>>,
+    AddressPath(address): AddressPath,
+    SignedJson(request): SignedJson<SendTransactionRequest>,
+) -> Result<Json<TrackedTransaction>, ApiError> {
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let signer = state.wallet_manager.local_signer(address, &request.transaction, &context).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Forbidden))?;
+
+    let tracked = state.broadcaster.send_with_signer(request.chain_id, request.transaction, &signer).await
+        .map_err(|e| {
+            warn!("Broadcast from {:?} failed: {}", address, e);
+            ApiError::from_error(e, ApiError::BadRequest)
+        })?;
+
+    Ok(Json(tracked))
+}
+
+/// Replace a pending transaction with the same one at higher fees
+async fn speed_up_transaction(
+    State(state): State<Arc<ApiState>>,
+    Path((target, tx_hash)): Path<(AddressOrName, H256)>,
+    SignedJson(request): SignedJson<SignTransactionRequest>,
+) -> Result<Json<TrackedTransaction>, ApiError> {
+    let address = ens::resolve(&state, &target).await?;
+    let mut transaction = request.transaction;
+    // In the original's nonce, the replacement's spending takes the place of the original's
+    if let Some(original) = state.broadcaster.get(tx_hash).await {
+        transaction.set_nonce(original.nonce);
+        transaction.set_chain_id(original.chain_id);
+    }
+    let context = PolicyContext { strategy_id: request.strategy_id, override_id: request.override_id };
+    let signer = state.wallet_manager.local_signer(address, &transaction, &context).await
+        .map_err(|e| ApiError::from_error(e, ApiError::Forbidden))?;
+
+    let tracked = state.broadcaster.speed_up(tx_hash, transaction, &signer).await
+        .map_err(|e| {
+            warn!("Speed-up of {:?} failed: {}", tx_hash, e);
+            ApiError::from_error(e, ApiError::Conflict)
+        })?;
+
+    Ok(Json(tracked))
+}
@@ -0,0 +1,341 @@
+// Shared caching of quotes, reserve and market data, in memory by default or in Redis across replicas
+pub mod redis;
+pub mod timed;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::Utc;
+use config::Source;
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::chains::reorg::{ChainReorg, ReorgHandler};
+
+pub use self::redis::RedisCache;
+pub use self::timed::TimedCache;
+
+/// Seconds expired entries are still served while they are refreshed, unless `cache_stale_secs` is set
+const DEFAULT_STALE_SECS: u64 = 60;
+/// Prefix of every key, so several deployments can share a Redis
+const DEFAULT_KEY_PREFIX: &str = "blockchain-demo";
+/// Entries held in memory before expired ones are pruned
+const MEMORY_PRUNE_THRESHOLD: usize = 10_000;
+
+/// Key-value store behind the caches, values are opaque bytes expiring after their TTL
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Name reported in logs, e.g. `memory` or `redis`
+    fn backend(&self) -> &'static str;
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    async fn set(&self, key: &str, value: &[u8], ttl: Duration) -> Result<()>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Delete every key starting with `prefix`
+    async fn delete_prefix(&self, prefix: &str) -> Result<()>;
+}
+
+/// Per-instance cache, the default
+#[derive(Default)]
+pub struct MemoryCache {
+    entries: RwLock<HashMap<String, (Vec<u8>, Instant)>>,
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let entries = self.entries.read().await;
+        Ok(entries
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(value, _)| value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl: Duration) -> Result<()> {
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        if entries.len() >= MEMORY_PRUNE_THRESHOLD {
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        entries.insert(key.to_string(), (value.to_vec(), now + ttl));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.write().await.remove(key);
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        self.entries.write().await.retain(|key, _| !key.starts_with(prefix));
+        Ok(())
+    }
+}
+
+/// How long entries of a namespace are fresh, and how long after that they are still served while
+/// being refreshed in the background
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    pub ttl: Duration,
+    pub stale: Duration,
+}
+
+/// Cached value with the time it was loaded, shared by every replica reading it
+#[derive(Serialize, Deserialize)]
+struct Envelope<V> {
+    value: V,
+    stored_at_ms: i64,
+}
+
+/// The configured cache backend, handing out typed namespaces to the managers
+pub struct CacheManager {
+    backend: Arc<dyn Cache>,
+    key_prefix: String,
+    config_ttls: HashMap<String, Duration>,
+    default_ttl: Option<Duration>,
+    stale: Duration,
+    /// Size bounds of in-memory caches from `cache_<name>_max_entries`
+    config_max_entries: HashMap<String, usize>,
+    default_max_entries: Option<usize>,
+}
+
+impl CacheManager {
+    pub fn new(backend: Arc<dyn Cache>, key_prefix: String, default_ttl: Option<Duration>, stale: Duration) -> Self {
+        info!("Caching in {} (stale entries served for {:?} while refreshing)", backend.backend(), stale);
+        Self {
+            backend,
+            key_prefix,
+            config_ttls: HashMap::new(),
+            default_ttl,
+            stale,
+            config_max_entries: HashMap::new(),
+            default_max_entries: None,
+        }
+    }
+
+    /// In-memory caching with the namespaces' own TTLs, used by demo managers
+    pub fn memory() -> Self {
+        Self::new(Arc::new(MemoryCache::default()), DEFAULT_KEY_PREFIX.to_string(), None, Duration::from_secs(DEFAULT_STALE_SECS))
+    }
+
+    /// Backend from `cache_backend` (`memory`, the default, or `redis` at `redis_url`), keys prefixed with
+    /// `cache_key_prefix`; `cache_ttl_secs` and `cache_<namespace>_ttl_secs` override TTLs,
+    /// `cache_stale_secs` the stale window and `cache_max_entries` and `cache_<name>_max_entries` the
+    /// size of in-memory caches
+    pub fn from_config(config: &config::Config) -> Result<Self> {
+        let backend: Arc<dyn Cache> = match config.get_string("cache_backend").unwrap_or_default().as_str() {
+            "" | "memory" => Arc::new(MemoryCache::default()),
+            "redis" => {
+                let url = config
+                    .get_string("redis_url")
+                    .map_err(|_| anyhow!("cache_backend is redis but redis_url is not set"))?;
+                Arc::new(RedisCache::new(&url)?)
+            }
+            other => return Err(anyhow!("Unknown cache_backend {}, expected memory or redis", other)),
+        };
+        let seconds = |key: &str| config.get_int(key).ok().and_then(|value| u64::try_from(value).ok()).map(Duration::from_secs);
+
+        let mut manager = Self::new(
+            backend,
+            config.get_string("cache_key_prefix").unwrap_or_else(|_| DEFAULT_KEY_PREFIX.to_string()),
+            seconds("cache_ttl_secs"),
+            seconds("cache_stale_secs").unwrap_or(Duration::from_secs(DEFAULT_STALE_SECS)),
+        );
+        let entries = |key: &str| config.get_int(key).ok().and_then(|value| usize::try_from(value).ok());
+        manager.default_max_entries = entries("cache_max_entries");
+        if let Ok(values) = config.collect() {
+            for key in values.keys() {
+                let Some(name) = key.strip_prefix("cache_") else {
+                    continue;
+                };
+                if let Some(namespace) = name.strip_suffix("_ttl_secs") {
+                    if let Some(ttl) = seconds(key) {
+                        manager.config_ttls.insert(namespace.to_string(), ttl);
+                    }
+                } else if let Some(cache) = name.strip_suffix("_max_entries") {
+                    if let Some(max_entries) = entries(key) {
+                        manager.config_max_entries.insert(cache.to_string(), max_entries);
+                    }
+                }
+            }
+        }
+        Ok(manager)
+    }
+
+    /// Typed cache of `name`, entries fresh for `ttl` unless configured otherwise
+    pub fn namespace<V>(&self, name: &str, ttl: Duration) -> NamespacedCache<V> {
+        let ttl = self.config_ttls.get(name).copied().or(self.default_ttl).unwrap_or(ttl);
+        debug!("Cache namespace {} fresh for {:?}", name, ttl);
+        NamespacedCache {
+            backend: self.backend.clone(),
+            prefix: format!("{}:{}:", self.key_prefix, name),
+            policy: CachePolicy { ttl, stale: self.stale },
+            refreshing: Arc::new(Mutex::new(HashSet::new())),
+            value: PhantomData,
+        }
+    }
+
+    /// In-memory cache of `name` for values that are not shared between replicas, with `ttl` and
+    /// `max_entries` unless configured otherwise
+    pub fn timed<K, V>(&self, name: &'static str, ttl: Duration, max_entries: usize) -> TimedCache<K, V>
+    where
+        K: Eq + std::hash::Hash + Clone + std::fmt::Debug + Send + 'static,
+        V: Clone + Send + 'static,
+    {
+        let ttl = self.config_ttls.get(name).copied().or(self.default_ttl).unwrap_or(ttl);
+        let max_entries = self.config_max_entries.get(name).copied().or(self.default_max_entries).unwrap_or(max_entries);
+        TimedCache::new(name, ttl, max_entries)
+    }
+
+    /// Handler dropping the namespaces of on-chain state when a chain reorganizes
+    pub fn reorg_invalidator(&self, names: &[&str]) -> ReorgInvalidator {
+        ReorgInvalidator {
+            backend: self.backend.clone(),
+            prefixes: names.iter().map(|name| format!("{}:{}:", self.key_prefix, name)).collect(),
+        }
+    }
+}
+
+/// Namespaces whose entries were read from blocks a reorganization may have replaced
+pub struct ReorgInvalidator {
+    backend: Arc<dyn Cache>,
+    prefixes: Vec<String>,
+}
+
+#[async_trait]
+impl ReorgHandler for ReorgInvalidator {
+    fn name(&self) -> &'static str {
+        "caches"
+    }
+
+    /// Entries are not keyed by block, so every entry of the namespaces is dropped
+    async fn handle_reorg(&self, _reorg: &ChainReorg) -> Result<()> {
+        for prefix in &self.prefixes {
+            self.backend.delete_prefix(prefix).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Values of one kind, keyed within their namespace of the shared backend
+pub struct NamespacedCache<V> {
+    backend: Arc<dyn Cache>,
+    prefix: String,
+    policy: CachePolicy,
+    /// Keys being revalidated in the background, refreshed once however many readers see them stale
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V> Clone for NamespacedCache<V> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            prefix: self.prefix.clone(),
+            policy: self.policy,
+            refreshing: self.refreshing.clone(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<V> NamespacedCache<V>
+where
+    V: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Cached value of `key`: fresh entries are returned, stale ones too while `load` refreshes them in
+    /// the background, and anything older or missing is loaded. Cache failures only cost a load.
+    pub async fn get_or_load<F>(&self, key: &str, load: F) -> Result<V>
+    where
+        F: FnOnce() -> BoxFuture<'static, Result<V>> + Send + 'static,
+    {
+        let full_key = format!("{}{}", self.prefix, key);
+        match self.read(&full_key).await {
+            Some((value, age)) if age < self.policy.ttl => return Ok(value),
+            Some((value, _)) => {
+                self.revalidate(full_key, load).await;
+                return Ok(value);
+            }
+            None => {}
+        }
+
+        let value = load().await?;
+        self.write(&full_key, &value).await;
+        Ok(value)
+    }
+
+    /// Drop the entry of `key`, on every replica when the backend is shared
+    pub async fn invalidate(&self, key: &str) {
+        if let Err(e) = self.backend.delete(&format!("{}{}", self.prefix, key)).await {
+            warn!("Failed to invalidate cached {}{}: {}", self.prefix, key, e);
+        }
+    }
+
+    /// Drop every entry of the namespace
+    pub async fn invalidate_all(&self) {
+        if let Err(e) = self.backend.delete_prefix(&self.prefix).await {
+            warn!("Failed to invalidate cached {}*: {}", self.prefix, e);
+        }
+    }
+
+    async fn read(&self, full_key: &str) -> Option<(V, Duration)> {
+        let bytes = match self.backend.get(full_key).await {
+            Ok(bytes) => bytes?,
+            Err(e) => {
+                warn!("Cache read of {} failed: {}", full_key, e);
+                return None;
+            }
+        };
+        match serde_json::from_slice::<Envelope<V>>(&bytes) {
+            Ok(envelope) => {
+                let age_ms = (Utc::now().timestamp_millis() - envelope.stored_at_ms).max(0) as u64;
+                Some((envelope.value, Duration::from_millis(age_ms)))
+            }
+            Err(e) => {
+                debug!("Ignoring unreadable cached {}: {}", full_key, e);
+                None
+            }
+        }
+    }
+
+    async fn write(&self, full_key: &str, value: &V) {
+        let envelope = Envelope { value, stored_at_ms: Utc::now().timestamp_millis() };
+        let result = match serde_json::to_vec(&envelope) {
+            Ok(bytes) => self.backend.set(full_key, &bytes, self.policy.ttl + self.policy.stale).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            warn!("Cache write of {} failed: {}", full_key, e);
+        }
+    }
+
+    /// Reload a stale entry in the background unless a refresh is already running
+    async fn revalidate<F>(&self, full_key: String, load: F)
+    where
+        F: FnOnce() -> BoxFuture<'static, Result<V>> + Send + 'static,
+    {
+        if !self.refreshing.lock().await.insert(full_key.clone()) {
+            return;
+        }
+        let cache = self.clone();
+        tokio::spawn(async move {
+            match load().await {
+                Ok(value) => cache.write(&full_key, &value).await,
+                Err(e) => debug!("Refreshing cached {} failed, serving it stale: {}", full_key, e),
+            }
+            cache.refreshing.lock().await.remove(&full_key);
+        });
+    }
+}
@@ -0,0 +1,240 @@
+// Redis cache backend speaking RESP over a single reconnecting connection
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use super::Cache;
+
+const DEFAULT_PORT: u16 = 6379;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Commands that take longer are abandoned and the connection reopened
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+/// Keys examined per SCAN round while invalidating a prefix
+const SCAN_COUNT: &str = "500";
+
+/// Reply to a command
+#[derive(Debug)]
+enum Reply {
+    Status,
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+impl Reply {
+    fn into_bulk(self) -> Result<Option<Vec<u8>>> {
+        match self {
+            Reply::Bulk(value) => Ok(value),
+            other => Err(anyhow!("Expected a bulk string from Redis, got {:?}", other)),
+        }
+    }
+}
+
+/// Connection settings from a `redis://[user:password@]host[:port][/db]` URL
+struct RedisTarget {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    database: Option<u32>,
+}
+
+impl RedisTarget {
+    fn parse(url: &str) -> Result<Self> {
+        let url = reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid redis_url: {}", e))?;
+        if url.scheme() != "redis" {
+            return Err(anyhow!("redis_url must use the redis:// scheme"));
+        }
+        let host = url.host_str().ok_or_else(|| anyhow!("redis_url has no host"))?;
+        let database = match url.path().trim_start_matches('/') {
+            "" => None,
+            database => Some(database.parse().map_err(|_| anyhow!("Invalid Redis database {}", database))?),
+        };
+        Ok(Self {
+            address: format!("{}:{}", host, url.port().unwrap_or(DEFAULT_PORT)),
+            username: Some(url.username()).filter(|username| !username.is_empty()).map(str::to_string),
+            password: url.password().map(str::to_string),
+            database,
+        })
+    }
+}
+
+/// Cache shared by every replica pointed at the same Redis; commands run one at a time
+pub struct RedisCache {
+    target: RedisTarget,
+    connection: Mutex<Option<BufStream<TcpStream>>>,
+}
+
+impl RedisCache {
+    /// Connects on first use, so an unreachable Redis only costs cache misses
+    pub fn new(url: &str) -> Result<Self> {
+        let target = RedisTarget::parse(url)?;
+        info!("Caching in Redis at {}", target.address);
+        Ok(Self { target, connection: Mutex::new(None) })
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>> {
+        let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&self.target.address))
+            .await
+            .map_err(|_| anyhow!("Connecting to Redis at {} timed out", self.target.address))??;
+        stream.set_nodelay(true)?;
+        let mut connection = BufStream::new(stream);
+
+        if let Some(password) = &self.target.password {
+            match &self.target.username {
+                Some(username) => send(&mut connection, &[b"AUTH", username.as_bytes(), password.as_bytes()]).await?,
+                None => send(&mut connection, &[b"AUTH", password.as_bytes()]).await?,
+            };
+        }
+        if let Some(database) = self.target.database {
+            send(&mut connection, &[b"SELECT", database.to_string().as_bytes()]).await?;
+        }
+        debug!("Connected to Redis at {}", self.target.address);
+        Ok(connection)
+    }
+
+    /// Run a command, reconnecting first when the last one failed
+    async fn command(&self, args: &[&[u8]]) -> Result<Reply> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(self.connect().await?);
+        }
+        let stream = connection.as_mut().expect("connection was just opened");
+        let result = tokio::time::timeout(COMMAND_TIMEOUT, send(stream, args))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("Redis command timed out")));
+        if result.is_err() {
+            // The reply stream may be out of step, start over on the next command
+            *connection = None;
+        }
+        result
+    }
+}
+
+/// Write a command as an array of bulk strings and read its reply, Redis errors become `Err`
+async fn send(stream: &mut BufStream<TcpStream>, args: &[&[u8]]) -> Result<Reply> {
+    let mut request = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        request.extend_from_slice(arg);
+        request.extend_from_slice(b"\r\n");
+    }
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+    read_reply(stream).await
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> Result<String> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(anyhow!("Redis closed the connection"));
+    }
+    Ok(line.trim_end_matches("\r\n").to_string())
+}
+
+fn read_reply(stream: &mut BufStream<TcpStream>) -> futures::future::BoxFuture<'_, Result<Reply>> {
+    Box::pin(async move {
+        let line = read_line(stream).await?;
+        let (kind, rest) = line.split_at(line.len().min(1));
+        let length = || rest.parse::<i64>().map_err(|_| anyhow!("Malformed Redis reply {}", line));
+        match kind {
+            "+" => Ok(Reply::Status),
+            "-" => Err(anyhow!("Redis error: {}", rest)),
+            ":" => Ok(Reply::Integer(length()?)),
+            "$" => {
+                let length = length()?;
+                if length < 0 {
+                    return Ok(Reply::Bulk(None));
+                }
+                let mut value = vec![0u8; length as usize + 2];
+                stream.read_exact(&mut value).await?;
+                value.truncate(length as usize);
+                Ok(Reply::Bulk(Some(value)))
+            }
+            "*" => {
+                let length = length()?;
+                if length < 0 {
+                    return Ok(Reply::Array(None));
+                }
+                let mut items = Vec::with_capacity(length as usize);
+                for _ in 0..length {
+                    items.push(read_reply(stream).await?);
+                }
+                Ok(Reply::Array(Some(items)))
+            }
+            _ => Err(anyhow!("Unexpected Redis reply {}", line)),
+        }
+    })
+}
+
+/// Escape glob characters so a prefix only matches itself in SCAN
+fn escape_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for character in prefix.chars() {
+        if matches!(character, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(character);
+    }
+    pattern.push('*');
+    pattern
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.command(&[b"GET", key.as_bytes()]).await?.into_bulk()
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl: Duration) -> Result<()> {
+        let ttl_ms = ttl.as_millis().max(1).to_string();
+        self.command(&[b"SET", key.as_bytes(), value, b"PX", ttl_ms.as_bytes()]).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.command(&[b"DEL", key.as_bytes()]).await?;
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        let pattern = escape_pattern(prefix);
+        let mut cursor = b"0".to_vec();
+        let mut deleted = 0;
+        loop {
+            let reply = self.command(&[b"SCAN", &cursor, b"MATCH", pattern.as_bytes(), b"COUNT", SCAN_COUNT.as_bytes()]).await?;
+            let Reply::Array(Some(mut parts)) = reply else {
+                return Err(anyhow!("Unexpected SCAN reply {:?}", reply));
+            };
+            if parts.len() != 2 {
+                return Err(anyhow!("Unexpected SCAN reply with {} parts", parts.len()));
+            }
+            let keys = parts.pop().expect("SCAN reply has two parts");
+            cursor = parts.pop().expect("SCAN reply has two parts").into_bulk()?.unwrap_or_default();
+
+            let keys: Vec<Vec<u8>> = match keys {
+                Reply::Array(Some(keys)) => keys.into_iter().filter_map(|key| key.into_bulk().ok().flatten()).collect(),
+                _ => Vec::new(),
+            };
+            if !keys.is_empty() {
+                let mut args: Vec<&[u8]> = vec![b"DEL"];
+                args.extend(keys.iter().map(Vec::as_slice));
+                if let Reply::Integer(count) = self.command(&args).await? {
+                    deleted += count;
+                }
+            }
+            if cursor == b"0" {
+                break;
+            }
+        }
+        debug!("Invalidated {} Redis keys under {}", deleted, prefix);
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+// Per-process cache with a TTL, a size bound evicting the least recently used entries, and refresh-ahead
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+/// Share of the TTL after which a read also reloads the entry in the background, so values read
+/// often never expire in front of a caller
+const REFRESH_AHEAD_FRACTION: f64 = 0.75;
+
+struct Entry<V> {
+    value: V,
+    loaded_at: Instant,
+    /// Position in the recency order, higher was used later
+    last_used: u64,
+    refreshing: bool,
+}
+
+struct State<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Keys by their last use, the first is evicted when the cache is full
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> State<K, V> {
+    fn touch(&mut self, key: &K) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.last_used);
+            entry.last_used = clock;
+            self.recency.insert(clock, key.clone());
+        }
+    }
+
+    fn insert(&mut self, key: K, value: V, max_entries: usize) -> Option<K> {
+        self.clock += 1;
+        let mut evicted = None;
+        match self.entries.get(&key) {
+            Some(entry) => {
+                self.recency.remove(&entry.last_used);
+            }
+            None if self.entries.len() >= max_entries => {
+                if let Some((_, oldest)) = self.recency.pop_first() {
+                    self.entries.remove(&oldest);
+                    evicted = Some(oldest);
+                }
+            }
+            None => {}
+        }
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(key, Entry { value, loaded_at: Instant::now(), last_used: self.clock, refreshing: false });
+        evicted
+    }
+}
+
+/// Values loaded on demand and dropped once older than the TTL, holding at most `max_entries`
+pub struct TimedCache<K, V> {
+    name: &'static str,
+    ttl: Duration,
+    max_entries: usize,
+    state: Arc<Mutex<State<K, V>>>,
+}
+
+impl<K, V> Clone for TimedCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            ttl: self.ttl,
+            max_entries: self.max_entries,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K, V> TimedCache<K, V>
+where
+    K: Eq + Hash + Clone + std::fmt::Debug + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub fn new(name: &'static str, ttl: Duration, max_entries: usize) -> Self {
+        debug!("Cache {} fresh for {:?}, up to {} entries", name, ttl, max_entries);
+        Self {
+            name,
+            ttl,
+            max_entries: max_entries.max(1),
+            state: Arc::new(Mutex::new(State { entries: HashMap::new(), recency: BTreeMap::new(), clock: 0 })),
+        }
+    }
+
+    /// Cached value of `key` while younger than the TTL, otherwise the result of `load`
+    pub async fn get_or_load<F, Fut>(&self, key: K, load: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        if let Some((value, _)) = self.fresh(&key).await {
+            return Ok(value);
+        }
+        let value = load().await?;
+        self.store(key, value.clone()).await;
+        Ok(value)
+    }
+
+    /// Like [`get_or_load`](Self::get_or_load), and entries read late in their TTL are reloaded in
+    /// the background, once however many readers see them
+    pub async fn get_or_refresh<F>(&self, key: K, load: F) -> Result<V>
+    where
+        F: FnOnce() -> BoxFuture<'static, Result<V>> + Send + 'static,
+    {
+        match self.fresh(&key).await {
+            Some((value, true)) => {
+                let cache = self.clone();
+                tokio::spawn(async move {
+                    match load().await {
+                        Ok(value) => cache.store(key, value).await,
+                        Err(e) => {
+                            debug!("Refreshing {} entry {:?} failed, keeping it until it expires: {}", cache.name, key, e);
+                            if let Some(entry) = cache.state.lock().await.entries.get_mut(&key) {
+                                entry.refreshing = false;
+                            }
+                        }
+                    }
+                });
+                Ok(value)
+            }
+            Some((value, false)) => Ok(value),
+            None => {
+                let value = load().await?;
+                self.store(key, value.clone()).await;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Drop the entry of `key`
+    pub async fn invalidate(&self, key: &K) {
+        let mut state = self.state.lock().await;
+        if let Some(entry) = state.entries.remove(key) {
+            state.recency.remove(&entry.last_used);
+        }
+    }
+
+    /// Drop every entry
+    pub async fn clear(&self) {
+        let mut state = self.state.lock().await;
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    /// The value of `key` unless expired, and whether the caller should refresh it in the background
+    async fn fresh(&self, key: &K) -> Option<(V, bool)> {
+        let mut state = self.state.lock().await;
+        let entry = state.entries.get_mut(key)?;
+        let age = entry.loaded_at.elapsed();
+        if age >= self.ttl {
+            return None;
+        }
+        let refresh = !entry.refreshing && age >= self.ttl.mul_f64(REFRESH_AHEAD_FRACTION);
+        entry.refreshing |= refresh;
+        let value = entry.value.clone();
+        state.touch(key);
+        Some((value, refresh))
+    }
+
+    async fn store(&self, key: K, value: V) {
+        let evicted = self.state.lock().await.insert(key, value, self.max_entries);
+        if let Some(evicted) = evicted {
+            debug!("Cache {} full, evicted {:?}", self.name, evicted);
+        }
+    }
+}
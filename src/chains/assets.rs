@@ -0,0 +1,228 @@
+// Canonical assets and their native, wrapped and bridged tokens across chains
+use anyhow::{Result, anyhow};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::transactions::read_store;
+
+/// (asset id, chain id, symbol, address, decimals, kind, bridge)
+type BuiltinRepresentation = (&'static str, u64, &'static str, &'static str, u8, RepresentationKind, Option<&'static str>);
+
+const BUILTIN_REPRESENTATIONS: [BuiltinRepresentation; 37] = [
+    ("eth", 1, "ETH", "0x0000000000000000000000000000000000000000", 18, RepresentationKind::Native, None),
+    ("eth", 1, "WETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 18, RepresentationKind::Wrapped, None),
+    ("eth", 137, "WETH", "0x7ceB23fD6bC0adD59E62ac25578270cFf1b9f619", 18, RepresentationKind::Bridged, Some("polygon-pos")),
+    ("eth", 42161, "ETH", "0x0000000000000000000000000000000000000000", 18, RepresentationKind::Native, None),
+    ("eth", 42161, "WETH", "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1", 18, RepresentationKind::Wrapped, None),
+    ("eth", 56, "ETH", "0x2170Ed0880ac9A755fd29B2688956BD959F933F8", 18, RepresentationKind::Bridged, Some("binance-bridge")),
+    ("eth", 43114, "WETH.e", "0x49D5c2BdFfac6CE2BFdB6640F4F80f226bc10bAB", 18, RepresentationKind::Bridged, Some("avalanche-bridge")),
+    ("usdc", 1, "USDC", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 6, RepresentationKind::Native, None),
+    ("usdc", 137, "USDC", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359", 6, RepresentationKind::Native, None),
+    ("usdc", 137, "USDC.e", "0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174", 6, RepresentationKind::Bridged, Some("polygon-pos")),
+    ("usdc", 42161, "USDC", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", 6, RepresentationKind::Native, None),
+    ("usdc", 42161, "USDC.e", "0xFF970A61A04b1cA14834A43f5dE4533eBDDB5CC8", 6, RepresentationKind::Bridged, Some("arbitrum")),
+    ("usdc", 56, "USDC", "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d", 18, RepresentationKind::Bridged, Some("binance-bridge")),
+    ("usdc", 43114, "USDC", "0xB97EF9Ef8734C71904D8002F8b6Bc66Dd9c48a6E", 6, RepresentationKind::Native, None),
+    ("usdc", 43114, "USDC.e", "0xA7D7079b0FEaD91F3e65f86E8915Cb59c1a4C664", 6, RepresentationKind::Bridged, Some("avalanche-bridge")),
+    ("usdt", 1, "USDT", "0xdAC17F958D2ee523a2206206994597C13D831ec7", 6, RepresentationKind::Native, None),
+    ("usdt", 137, "USDT", "0xc2132D05D31c914a87C6611C10748AEb04B58e8F", 6, RepresentationKind::Bridged, Some("polygon-pos")),
+    ("usdt", 42161, "USDT", "0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9", 6, RepresentationKind::Bridged, Some("arbitrum")),
+    ("usdt", 56, "USDT", "0x55d398326f99059fF775485246999027B3197955", 18, RepresentationKind::Bridged, Some("binance-bridge")),
+    ("usdt", 43114, "USDt", "0x9702230A8Ea53601f5cD2dc00fDBc13d4dF4A8c7", 6, RepresentationKind::Native, None),
+    ("dai", 1, "DAI", "0x6B175474E89094C44Da98b954EedeAC495271d0F", 18, RepresentationKind::Native, None),
+    ("dai", 137, "DAI", "0x8f3Cf7ad23Cd3CaDbD9735AFf958023239c6A063", 18, RepresentationKind::Bridged, Some("polygon-pos")),
+    ("dai", 42161, "DAI", "0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1", 18, RepresentationKind::Bridged, Some("arbitrum")),
+    ("dai", 56, "DAI", "0x1AF3F329e8BE154074D8769D1FFa4eE058B1DBc3", 18, RepresentationKind::Bridged, Some("binance-bridge")),
+    ("dai", 43114, "DAI.e", "0xd586E7F844cEa2F87f50152665BCbc2C279D8d70", 18, RepresentationKind::Bridged, Some("avalanche-bridge")),
+    ("btc", 1, "WBTC", "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", 8, RepresentationKind::Wrapped, None),
+    ("btc", 137, "WBTC", "0x1BFD67037B42Cf73acF2047067bd4F2C47D9BfD6", 8, RepresentationKind::Bridged, Some("polygon-pos")),
+    ("btc", 42161, "WBTC", "0x2f2a2543B76A4166549F7aaB2e75Bef0aefC5B0f", 8, RepresentationKind::Bridged, Some("arbitrum")),
+    ("btc", 56, "BTCB", "0x7130d2A12B9BCbFAe4f2634d864A1Ee1Ce3Ead9c", 18, RepresentationKind::Bridged, Some("binance-bridge")),
+    ("btc", 43114, "WBTC.e", "0x50b7545627a5162F82A992c33b87aDc75187B218", 8, RepresentationKind::Bridged, Some("avalanche-bridge")),
+    ("matic", 1, "MATIC", "0x7D1AfA7B718fb893dB30A3aBc0Cfc608AaCfeBB0", 18, RepresentationKind::Native, None),
+    ("matic", 137, "MATIC", "0x0000000000000000000000000000000000000000", 18, RepresentationKind::Native, None),
+    ("matic", 137, "WMATIC", "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270", 18, RepresentationKind::Wrapped, None),
+    ("bnb", 56, "BNB", "0x0000000000000000000000000000000000000000", 18, RepresentationKind::Native, None),
+    ("bnb", 56, "WBNB", "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c", 18, RepresentationKind::Wrapped, None),
+    ("avax", 43114, "AVAX", "0x0000000000000000000000000000000000000000", 18, RepresentationKind::Native, None),
+    ("avax", 43114, "WAVAX", "0xB31f66AA3C1e785363F0875A1B74E27b85FD66c7", 18, RepresentationKind::Wrapped, None),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepresentationKind {
+    /// Issued by the asset's issuer on this chain, or the chain's gas token (zero address)
+    Native,
+    /// 1:1 wrapper redeemable on the same chain, such as WETH or WBTC
+    Wrapped,
+    /// Minted by a bridge against tokens locked on another chain
+    Bridged,
+}
+
+/// Token standing for a canonical asset on one chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetRepresentation {
+    pub chain_id: u64,
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    pub kind: RepresentationKind,
+    /// Bridge that minted a bridged token
+    #[serde(default)]
+    pub bridge: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanonicalAsset {
+    /// Lowercase id such as `usdc` or `eth`
+    pub id: String,
+    pub representations: Vec<AssetRepresentation>,
+}
+
+/// How interchangeable two tokens are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetCompatibility {
+    /// The same token on the same chain
+    Identical,
+    /// Same asset on the same chain, convertible 1:1 by wrapping or unwrapping
+    Redeemable,
+    /// Same asset, but issued differently or on another chain; converting needs a swap or a
+    /// bridge and the two can trade apart
+    SameAsset,
+    /// Different assets, or a token the registry does not know
+    Incompatible,
+}
+
+/// Representation of an asset with how it relates to the token it was looked up from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetEquivalent {
+    pub asset_id: String,
+    #[serde(flatten)]
+    pub representation: AssetRepresentation,
+    pub compatibility: AssetCompatibility,
+}
+
+/// Canonical mapping between assets and their tokens, so "same asset" comparisons across
+/// chains and venues only compare compatible tokens
+#[derive(Debug, Clone)]
+pub struct AssetRegistry {
+    assets: Vec<CanonicalAsset>,
+}
+
+impl AssetRegistry {
+    /// Registry with the built-in mainnet, Polygon, Arbitrum, BSC and Avalanche tokens
+    pub fn builtin() -> Self {
+        let mut registry = Self { assets: Vec::new() };
+        for (id, chain_id, symbol, address, decimals, kind, bridge) in BUILTIN_REPRESENTATIONS {
+            registry.insert(id, AssetRepresentation {
+                chain_id,
+                address: address.parse().expect("valid built-in token address"),
+                symbol: symbol.to_string(),
+                decimals,
+                kind,
+                bridge: bridge.map(str::to_string),
+            });
+        }
+        registry
+    }
+
+    /// Built-in registry extended with the assets listed in `wrapped_assets_path`, if set
+    pub async fn from_config(config: &config::Config) -> Result<Self> {
+        let mut registry = Self::builtin();
+        let Some(path) = config.get_string("wrapped_assets_path").ok().filter(|path| !path.is_empty()) else {
+            return Ok(registry);
+        };
+
+        let path = PathBuf::from(path);
+        let extra: Vec<CanonicalAsset> = read_store(&path).await?
+            .ok_or_else(|| anyhow!("Wrapped asset mapping {} does not exist", path.display()))?;
+        let count: usize = extra.iter().map(|asset| asset.representations.len()).sum();
+        for asset in extra {
+            for representation in asset.representations {
+                registry.insert(&asset.id, representation);
+            }
+        }
+        info!("Loaded {} asset representations from {}", count, path.display());
+        Ok(registry)
+    }
+
+    /// Add a representation, replacing whatever asset the token was mapped to before
+    fn insert(&mut self, id: &str, representation: AssetRepresentation) {
+        let id = id.to_lowercase();
+        for asset in &mut self.assets {
+            asset.representations.retain(|existing| {
+                (existing.chain_id, existing.address) != (representation.chain_id, representation.address)
+            });
+        }
+        match self.assets.iter_mut().find(|asset| asset.id == id) {
+            Some(asset) => asset.representations.push(representation),
+            None => self.assets.push(CanonicalAsset { id, representations: vec![representation] }),
+        }
+    }
+
+    pub fn assets(&self) -> &[CanonicalAsset] {
+        &self.assets
+    }
+
+    /// Asset a token stands for and its representation
+    pub fn resolve(&self, chain_id: u64, token: Address) -> Option<(&CanonicalAsset, &AssetRepresentation)> {
+        self.assets.iter().find_map(|asset| {
+            asset.representations.iter()
+                .find(|representation| representation.chain_id == chain_id && representation.address == token)
+                .map(|representation| (asset, representation))
+        })
+    }
+
+    /// Wrapper of a chain's gas token, such as WETH on mainnet or WBNB on BSC
+    pub fn wrapped_native(&self, chain_id: u64) -> Option<&AssetRepresentation> {
+        self.assets.iter()
+            .filter(|asset| {
+                asset.representations.iter()
+                    .any(|representation| representation.chain_id == chain_id && representation.address.is_zero())
+            })
+            .flat_map(|asset| &asset.representations)
+            .find(|representation| representation.chain_id == chain_id && representation.kind == RepresentationKind::Wrapped)
+    }
+
+    pub fn compatibility(&self, (chain_a, token_a): (u64, Address), (chain_b, token_b): (u64, Address)) -> AssetCompatibility {
+        if (chain_a, token_a) == (chain_b, token_b) {
+            return AssetCompatibility::Identical;
+        }
+        let (Some((asset_a, representation_a)), Some((asset_b, representation_b))) =
+            (self.resolve(chain_a, token_a), self.resolve(chain_b, token_b))
+        else {
+            return AssetCompatibility::Incompatible;
+        };
+        if asset_a.id != asset_b.id {
+            return AssetCompatibility::Incompatible;
+        }
+
+        // A native token and its wrapper redeem into each other, bridged tokens never do
+        let redeemable = chain_a == chain_b
+            && [representation_a.kind, representation_b.kind].contains(&RepresentationKind::Wrapped)
+            && [representation_a.kind, representation_b.kind].contains(&RepresentationKind::Native);
+        if redeemable {
+            AssetCompatibility::Redeemable
+        } else {
+            AssetCompatibility::SameAsset
+        }
+    }
+
+    /// Every representation of the token's asset on any chain, the token itself first,
+    /// `None` for tokens the registry does not know
+    pub fn equivalents(&self, chain_id: u64, token: Address) -> Option<Vec<AssetEquivalent>> {
+        let (asset, _) = self.resolve(chain_id, token)?;
+        let mut equivalents: Vec<AssetEquivalent> = asset.representations.iter()
+            .map(|representation| AssetEquivalent {
+                asset_id: asset.id.clone(),
+                representation: representation.clone(),
+                compatibility: self.compatibility((chain_id, token), (representation.chain_id, representation.address)),
+            })
+            .collect();
+        equivalents.sort_by_key(|equivalent| (equivalent.compatibility, equivalent.representation.chain_id));
+        Some(equivalents)
+    }
+}
@@ -0,0 +1,73 @@
+// Avalanche C-Chain implementations
+use anyhow::Result;
+use ethers::{
+    providers::{Http, Provider, Middleware},
+    types::{Address, U256},
+};
+use std::sync::Arc;
+use tokio::time::{Duration, timeout};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub struct AvalancheChain {
+    provider: Arc<Provider<Http>>,
+    chain_id: u64,
+    rpc_url: String,
+    is_testnet: bool,
+}
+
+impl AvalancheChain {
+    pub async fn new(rpc_url: String, is_testnet: bool) -> Result<Self> {
+        info!("Initializing Avalanche C-Chain connection to: {}", rpc_url);
+        
+        let provider = Provider::<Http>::try_from(&rpc_url)?;
+        let provider = Arc::new(provider);
+        
+        // Verify connection and get chain ID
+        let chain_id = timeout(
+            Duration::from_secs(10), 
+            provider.get_chainid()
+        ).await??;
+        
+        info!("Connected to Avalanche C-Chain ID: {}", chain_id);
+        
+        // Validate it's actually the Avalanche C-Chain
+        let expected_chain_id = if is_testnet { 43113 } else { 43114 }; // Fuji testnet or Avalanche C-Chain
+        if chain_id.as_u64() != expected_chain_id {
+            warn!("Expected Avalanche C-Chain ID {} but got {}", expected_chain_id, chain_id);
+        }
+        
+        Ok(Self {
+            provider,
+            chain_id: chain_id.as_u64(),
+            rpc_url,
+            is_testnet,
+        })
+    }
+
+    pub async fn get_balance(&self, address: Address) -> Result<U256> {
+        Ok(self.provider.get_balance(address, None).await?)
+    }
+
+    pub async fn get_avax_balance(&self, address: Address) -> Result<U256> {
+        // AVAX is the native token on the C-Chain
+        self.get_balance(address).await
+    }
+
+    pub async fn health_check(&self) -> Result<bool> {
+        match timeout(Duration::from_secs(5), self.provider.get_block_number()).await {
+            Ok(Ok(_)) => {
+                info!("Avalanche C-Chain health check passed");
+                Ok(true)
+            }
+            Ok(Err(e)) => {
+                warn!("Avalanche C-Chain health check failed: {}", e);
+                Ok(false)
+            }
+            Err(_) => {
+                warn!("Avalanche C-Chain health check timed out");
+                Ok(false)
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// JSON-RPC batches collecting several provider calls into one round trip
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::Detokenize,
+    contract::ContractCall,
+    types::{BlockId, BlockNumber, Bytes},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use super::pool::{BatchResult, PooledHttp};
+
+/// Calls to send together, each answered by the index `request` or `call` returned
+#[derive(Debug, Default)]
+pub struct RpcBatch {
+    calls: Vec<(&'static str, Value)>,
+}
+
+impl RpcBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a JSON-RPC request, `()` for one without parameters
+    pub fn request<P: Serialize>(&mut self, method: &'static str, params: P) -> Result<usize> {
+        self.calls.push((method, serde_json::to_value(params)?));
+        Ok(self.calls.len() - 1)
+    }
+
+    /// Queue a contract read as an `eth_call`, at the call's block or the latest one
+    pub fn call<M, D>(&mut self, call: &ContractCall<M, D>) -> Result<usize> {
+        let block = call.block.unwrap_or(BlockId::Number(BlockNumber::Latest));
+        self.request("eth_call", (&call.tx, block))
+    }
+
+    pub async fn send(&self, client: &PooledHttp) -> Result<BatchResults> {
+        Ok(BatchResults(client.batch(&self.calls).await?))
+    }
+}
+
+/// Results of a batch, a call failing on its own without failing the others
+#[derive(Debug)]
+pub struct BatchResults(Vec<BatchResult>);
+
+impl BatchResults {
+    pub fn get<R: DeserializeOwned>(&self, index: usize) -> Result<R> {
+        match self.0.get(index) {
+            Some(Ok(value)) => Ok(serde_json::from_value(value.clone())?),
+            Some(Err(e)) => Err(anyhow!("{}", e)),
+            None => Err(anyhow!("No call {} in the batch", index)),
+        }
+    }
+
+    /// Output of a contract read queued with `RpcBatch::call`
+    pub fn decode<M, D: Detokenize>(&self, index: usize, call: &ContractCall<M, D>) -> Result<D> {
+        let data: Bytes = self.get(index)?;
+        let tokens = call.function.decode_output(&data)?;
+        Ok(D::from_tokens(tokens)?)
+    }
+}
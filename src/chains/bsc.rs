@@ -0,0 +1,73 @@
+// BNB Smart Chain implementations
+use anyhow::Result;
+use ethers::{
+    providers::{Http, Provider, Middleware},
+    types::{Address, U256},
+};
+use std::sync::Arc;
+use tokio::time::{Duration, timeout};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub struct BscChain {
+    provider: Arc<Provider<Http>>,
+    chain_id: u64,
+    rpc_url: String,
+    is_testnet: bool,
+}
+
+impl BscChain {
+    pub async fn new(rpc_url: String, is_testnet: bool) -> Result<Self> {
+        info!("Initializing BNB Smart Chain connection to: {}", rpc_url);
+        
+        let provider = Provider::<Http>::try_from(&rpc_url)?;
+        let provider = Arc::new(provider);
+        
+        // Verify connection and get chain ID
+        let chain_id = timeout(
+            Duration::from_secs(10), 
+            provider.get_chainid()
+        ).await??;
+        
+        info!("Connected to BNB Smart Chain ID: {}", chain_id);
+        
+        // Validate it's actually BNB Smart Chain
+        let expected_chain_id = if is_testnet { 97 } else { 56 }; // BSC testnet or BSC mainnet
+        if chain_id.as_u64() != expected_chain_id {
+            warn!("Expected BNB Smart Chain ID {} but got {}", expected_chain_id, chain_id);
+        }
+        
+        Ok(Self {
+            provider,
+            chain_id: chain_id.as_u64(),
+            rpc_url,
+            is_testnet,
+        })
+    }
+
+    pub async fn get_balance(&self, address: Address) -> Result<U256> {
+        Ok(self.provider.get_balance(address, None).await?)
+    }
+
+    pub async fn get_bnb_balance(&self, address: Address) -> Result<U256> {
+        // BNB is the native token on BNB Smart Chain
+        self.get_balance(address).await
+    }
+
+    pub async fn health_check(&self) -> Result<bool> {
+        match timeout(Duration::from_secs(5), self.provider.get_block_number()).await {
+            Ok(Ok(_)) => {
+                info!("BNB Smart Chain health check passed");
+                Ok(true)
+            }
+            Ok(Err(e)) => {
+                warn!("BNB Smart Chain health check failed: {}", e);
+                Ok(false)
+            }
+            Err(_) => {
+                warn!("BNB Smart Chain health check timed out");
+                Ok(false)
+            }
+        }
+    }
+}
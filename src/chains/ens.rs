@@ -0,0 +1,174 @@
+// ENS names resolved to addresses and addresses reverse-resolved to their primary name, both cached
+use anyhow::{Result, anyhow};
+use ethers::{
+    providers::{Middleware, ProviderError},
+    types::Address,
+};
+use futures::{future::join_all, FutureExt};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use super::ChainManager;
+use crate::cache::{CacheManager, NamespacedCache};
+
+/// Chain holding the ENS registry unless `ens_chain_id` is set
+const DEFAULT_ENS_CHAIN_ID: u64 = 1;
+/// Names and reverse records change rarely; negative answers are cached as long
+const NAME_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Address given as hex or as an ENS name such as `vitalik.eth`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AddressOrName {
+    Address(Address),
+    /// Lowercased name
+    Name(String),
+}
+
+impl FromStr for AddressOrName {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if let Ok(address) = value.parse::<Address>() {
+            return Ok(Self::Address(address));
+        }
+        if value.starts_with("0x") {
+            return Err(anyhow!("Invalid address {}", value));
+        }
+        let name = value.to_lowercase();
+        let valid = name.contains('.')
+            && name.split('.').all(|label| !label.is_empty())
+            && !name.chars().any(|c| c.is_whitespace() || c == '/');
+        if !valid {
+            return Err(anyhow!("{} is neither an address nor an ENS name", value));
+        }
+        Ok(Self::Name(name))
+    }
+}
+
+impl fmt::Display for AddressOrName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(address) => write!(f, "{:?}", address),
+            Self::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+impl From<Address> for AddressOrName {
+    fn from(address: Address) -> Self {
+        Self::Address(address)
+    }
+}
+
+impl Serialize for AddressOrName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AddressOrName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// An ENS name without an address, told apart from a failed lookup
+#[derive(Debug, Clone)]
+pub struct UnresolvedName(pub String);
+
+impl fmt::Display for UnresolvedName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ENS name {} does not resolve to an address", self.0)
+    }
+}
+
+impl std::error::Error for UnresolvedName {}
+
+/// Resolves names against the ENS registry of one chain, sharing answers through the cache
+pub struct EnsResolver {
+    chain_manager: Arc<ChainManager>,
+    chain_id: u64,
+    names: NamespacedCache<Option<Address>>,
+    reverse: NamespacedCache<Option<String>>,
+}
+
+impl EnsResolver {
+    pub fn new(chain_manager: Arc<ChainManager>, chain_id: u64, caches: &CacheManager) -> Self {
+        info!("Resolving ENS names on chain {}", chain_id);
+        Self {
+            chain_manager,
+            chain_id,
+            names: caches.namespace("ens_names", NAME_CACHE_TTL),
+            reverse: caches.namespace("ens_reverse", NAME_CACHE_TTL),
+        }
+    }
+
+    /// Resolver reading the registry of `ens_chain_id`, mainnet by default
+    pub fn from_config(config: &config::Config, chain_manager: Arc<ChainManager>, caches: &CacheManager) -> Self {
+        let chain_id = config
+            .get_int("ens_chain_id")
+            .ok()
+            .and_then(|chain_id| u64::try_from(chain_id).ok())
+            .unwrap_or(DEFAULT_ENS_CHAIN_ID);
+        Self::new(chain_manager, chain_id, caches)
+    }
+
+    /// Address of `target`, failing with `UnresolvedName` for a name without one
+    pub async fn resolve(&self, target: &AddressOrName) -> Result<Address> {
+        match target {
+            AddressOrName::Address(address) => Ok(*address),
+            AddressOrName::Name(name) => self.resolve_name(name).await?
+                .ok_or_else(|| UnresolvedName(name.clone()).into()),
+        }
+    }
+
+    /// Address a name points to, `None` when it has no resolver or address
+    pub async fn resolve_name(&self, name: &str) -> Result<Option<Address>> {
+        let (chain_manager, chain_id, owned) = (self.chain_manager.clone(), self.chain_id, name.to_string());
+        let load = move || async move {
+            let chain = chain_manager.get_provider(chain_id).await?;
+            match chain.provider.resolve_name(&owned).await {
+                Ok(address) => Ok((!address.is_zero()).then_some(address)),
+                Err(ProviderError::EnsError(_)) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }.boxed();
+        self.names.get_or_load(name, load).await
+    }
+
+    /// Primary name of an address whose forward record points back to it; lookup failures count as
+    /// no name so responses never fail on them
+    pub async fn lookup(&self, address: Address) -> Option<String> {
+        let (chain_manager, chain_id) = (self.chain_manager.clone(), self.chain_id);
+        let load = move || async move {
+            let chain = chain_manager.get_provider(chain_id).await?;
+            match chain.provider.lookup_address(address).await {
+                Ok(name) => Ok(Some(name)),
+                Err(ProviderError::EnsError(_) | ProviderError::EnsNotOwned(_)) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }.boxed();
+        match self.reverse.get_or_load(&format!("{:?}", address), load).await {
+            Ok(name) => name,
+            Err(e) => {
+                debug!("Reverse ENS lookup of {:?} failed: {}", address, e);
+                None
+            }
+        }
+    }
+
+    /// Primary names of the addresses that have one
+    pub async fn lookup_all(&self, addresses: &[Address]) -> HashMap<Address, String> {
+        let names = join_all(addresses.iter().map(|address| self.lookup(*address))).await;
+        addresses.iter()
+            .zip(names)
+            .filter_map(|(address, name)| Some((*address, name?)))
+            .collect()
+    }
+}
@@ -0,0 +1,202 @@
+// Anvil mainnet fork for sandboxed demos and integration runs
+use anyhow::{Result, anyhow};
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Address, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Fork mode configuration
+#[derive(Debug, Clone)]
+pub struct ForkConfig {
+    /// Upstream RPC the fork reads state from
+    pub fork_url: Option<String>,
+    /// Already running anvil/hardhat node to connect to instead of spawning one
+    pub rpc_url: Option<String>,
+    pub block_number: Option<u64>,
+    pub chain_id: u64,
+    pub port: u16,
+    pub anvil_path: String,
+    pub startup_timeout: Duration,
+}
+
+impl Default for ForkConfig {
+    fn default() -> Self {
+        Self {
+            fork_url: None,
+            rpc_url: None,
+            block_number: None,
+            chain_id: 1,
+            port: 8545,
+            anvil_path: "anvil".to_string(),
+            startup_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ForkConfig {
+    /// Fork settings when `fork_mode` is enabled
+    pub fn from_config(config: &config::Config) -> Option<Self> {
+        if !config.get_bool("fork_mode").unwrap_or(false) {
+            return None;
+        }
+
+        let mut fork_config = Self {
+            fork_url: config.get_string("fork_url").ok()
+                .or_else(|| config.get_string("ethereum_rpc_url").ok()
+                    .and_then(|urls| urls.split(',').next().map(|url| url.trim().to_string()))),
+            rpc_url: config.get_string("fork_rpc_url").ok(),
+            block_number: config.get_int("fork_block_number").ok().map(|block| block as u64),
+            ..Self::default()
+        };
+
+        if let Ok(chain_id) = config.get_int("fork_chain_id") {
+            fork_config.chain_id = chain_id as u64;
+        }
+        if let Ok(port) = config.get_int("fork_port") {
+            fork_config.port = port as u16;
+        }
+        if let Ok(path) = config.get_string("anvil_path") {
+            fork_config.anvil_path = path;
+        }
+
+        Some(fork_config)
+    }
+}
+
+/// Fork status reported by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForkInfo {
+    pub chain_id: u64,
+    pub rpc_url: String,
+    pub fork_block: Option<u64>,
+    /// True when this process spawned the node and owns its lifetime
+    pub managed: bool,
+}
+
+/// Local fork node, spawned by us or connected to
+pub struct AnvilFork {
+    pub chain_id: u64,
+    pub rpc_url: String,
+    pub fork_block: Option<u64>,
+    provider: Provider<Http>,
+    // Killed when the fork is dropped
+    process: Mutex<Option<Child>>,
+}
+
+impl AnvilFork {
+    /// Connect to the configured fork node, spawning anvil when no RPC URL is given
+    pub async fn start(config: &ForkConfig) -> Result<Self> {
+        let (rpc_url, process) = match &config.rpc_url {
+            Some(rpc_url) => {
+                info!("Connecting to existing fork node at {}", rpc_url);
+                (rpc_url.clone(), None)
+            }
+            None => {
+                let fork_url = config.fork_url.as_ref()
+                    .ok_or_else(|| anyhow!("fork_url is required to spawn anvil"))?;
+                let child = Self::spawn_anvil(config, fork_url)?;
+                (format!("http://127.0.0.1:{}", config.port), Some(child))
+            }
+        };
+
+        let provider = Provider::<Http>::try_from(rpc_url.as_str())?;
+        Self::wait_until_ready(&provider, config.startup_timeout).await?;
+
+        let chain_id = provider.get_chainid().await?.as_u64();
+        if chain_id != config.chain_id {
+            warn!("Fork reports chain id {}, expected {}", chain_id, config.chain_id);
+        }
+        let fork_block = provider.get_block_number().await.ok().map(|block| block.as_u64());
+        info!("Fork ready at {} (chain {}, block {:?})", rpc_url, chain_id, fork_block);
+
+        Ok(Self {
+            chain_id,
+            rpc_url,
+            fork_block,
+            provider,
+            process: Mutex::new(process),
+        })
+    }
+
+    fn spawn_anvil(config: &ForkConfig, fork_url: &str) -> Result<Child> {
+        let mut command = Command::new(&config.anvil_path);
+        command
+            .arg("--fork-url").arg(fork_url)
+            .arg("--port").arg(config.port.to_string())
+            .arg("--chain-id").arg(config.chain_id.to_string())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(block) = config.block_number {
+            command.arg("--fork-block-number").arg(block.to_string());
+        }
+
+        info!("Spawning anvil fork on port {}", config.port);
+        command.spawn().map_err(|e| anyhow!("Failed to spawn {}: {}", config.anvil_path, e))
+    }
+
+    async fn wait_until_ready(provider: &Provider<Http>, timeout: Duration) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if provider.get_chainid().await.is_ok() {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow!("Fork node did not become ready within {:?}", timeout));
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    }
+
+    pub async fn info(&self) -> ForkInfo {
+        ForkInfo {
+            chain_id: self.chain_id,
+            rpc_url: self.rpc_url.clone(),
+            fork_block: self.fork_block,
+            managed: self.process.lock().await.is_some(),
+        }
+    }
+
+    /// Set an account's native balance
+    pub async fn set_balance(&self, account: Address, amount: U256) -> Result<()> {
+        self.provider.request::<_, ()>("anvil_setBalance", (account, amount)).await?;
+        Ok(())
+    }
+
+    /// Allow sending transactions from an account without its key
+    pub async fn impersonate(&self, account: Address) -> Result<()> {
+        self.provider.request::<_, ()>("anvil_impersonateAccount", [account]).await?;
+        Ok(())
+    }
+
+    /// Snapshot the fork state, returning an id to revert to
+    pub async fn snapshot(&self) -> Result<U256> {
+        Ok(self.provider.request::<_, U256>("evm_snapshot", ()).await?)
+    }
+
+    /// Revert to a snapshot; the snapshot is consumed
+    pub async fn revert(&self, snapshot_id: U256) -> Result<bool> {
+        Ok(self.provider.request::<_, bool>("evm_revert", [snapshot_id]).await?)
+    }
+
+    /// Mine `blocks` empty blocks
+    pub async fn mine(&self, blocks: u64) -> Result<()> {
+        self.provider.request::<_, ()>("anvil_mine", [U256::from(blocks)]).await?;
+        Ok(())
+    }
+
+    /// Stop a spawned anvil process
+    pub async fn shutdown(&self) {
+        if let Some(mut child) = self.process.lock().await.take() {
+            if let Err(e) = child.kill().await {
+                warn!("Failed to stop anvil fork: {}", e);
+            }
+        }
+    }
+}
@@ -1,9 +1,50 @@
-use anyhow::Result;
-use ethers::types::U256;
+use anyhow::{Result, anyhow};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use ethers::{
+    providers::{Middleware, Provider},
+    types::{BlockNumber, U256},
+};
+use futures::{stream, StreamExt};
+use serde::Serialize;
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 use tracing::info;
 
+use super::pool::PooledHttp;
+use crate::analytics::time_zones::TenantTimeSettings;
+
+/// Blocks sampled per day of history when profiling base fees by hour
+const GAS_PROFILE_SAMPLES_PER_DAY: u64 = 48;
+/// Blocks spanned when measuring the average block time
+const BLOCK_TIME_WINDOW: u64 = 1000;
+const GAS_PROFILE_CONCURRENCY: usize = 8;
+
+/// Average base fee during one local hour of the day
+#[derive(Debug, Clone, Serialize)]
+pub struct HourlyBaseFee {
+    /// Hour of day (0-23) in the requested time zone
+    pub hour: u32,
+    pub average_base_fee_gwei: f64,
+    pub samples: usize,
+}
+
+/// Base fees by local hour, for picking when to send transactions that can wait
+#[derive(Debug, Clone, Serialize)]
+pub struct GasHourProfile {
+    pub chain_id: u64,
+    pub time_zone: Tz,
+    pub days: u64,
+    pub hours: Vec<HourlyBaseFee>,
+    pub cheapest_hour: Option<u32>,
+}
+
+/// Chains whose validators order transactions by gas price, where blocks carry a zero base fee
+/// and EIP-1559 fee estimates are meaningless
+pub fn uses_legacy_gas_price(chain_id: u64) -> bool {
+    matches!(chain_id, 56 | 97)
+}
+
 pub struct GasOptimizer {
     chain_configs: HashMap<u64, ChainGasConfig>,
     recent_prices: RwLock<HashMap<u64, Vec<GasPricePoint>>>,
@@ -53,6 +94,22 @@ impl GasOptimizer {
             confirmation_target_blocks: 1,
         });
 
+        // BNB Smart Chain configuration, priced by gas price rather than base fee
+        chain_configs.insert(56, ChainGasConfig {
+            base_fee_multiplier: 1.0,
+            priority_fee_multiplier: 1.0,
+            max_fee_multiplier: 1.1,
+            confirmation_target_blocks: 2,
+        });
+
+        // Avalanche C-Chain configuration
+        chain_configs.insert(43114, ChainGasConfig {
+            base_fee_multiplier: 1.1,
+            priority_fee_multiplier: 1.05,
+            max_fee_multiplier: 1.5,
+            confirmation_target_blocks: 1,
+        });
+
         Self {
             chain_configs,
             recent_prices: RwLock::new(HashMap::new()),
@@ -89,6 +146,8 @@ impl GasOptimizer {
             1 => U256::from(20_000_000_000u64), // 20 gwei for Ethereum
             137 => U256::from(30_000_000_000u64), // 30 gwei for Polygon
             42161 => U256::from(100_000_000u64), // 0.1 gwei for Arbitrum
+            56 => U256::from(1_000_000_000u64), // 1 gwei gas price for BSC
+            43114 => U256::from(25_000_000_000u64), // 25 nAVAX for Avalanche
             _ => U256::from(20_000_000_000u64),
         };
 
@@ -101,6 +160,8 @@ impl GasOptimizer {
             1 => U256::from(2_000_000_000u64), // 2 gwei for Ethereum
             137 => U256::from(30_000_000_000u64), // 30 gwei for Polygon (higher due to validator requirements)
             42161 => U256::from(10_000_000u64), // 0.01 gwei for Arbitrum
+            56 => U256::zero(), // BSC validators order by gas price alone
+            43114 => U256::from(1_000_000_000u64), // 1 nAVAX for Avalanche
             _ => U256::from(1_000_000_000u64),
         };
 
@@ -131,6 +192,8 @@ impl GasOptimizer {
             1 => 12, // Ethereum: ~12 seconds
             137 => 2, // Polygon: ~2 seconds
             42161 => 1, // Arbitrum: ~1 second (L2)
+            56 => 3, // BSC: ~3 seconds
+            43114 => 2, // Avalanche: ~2 seconds
             _ => 12,
         };
 
@@ -149,6 +212,8 @@ impl GasOptimizer {
         let eth_price_usd = match chain_id {
             1 | 42161 => 2000.0, // ETH price
             137 => 0.8, // MATIC price
+            56 => 600.0, // BNB price
+            43114 => 30.0, // AVAX price
             _ => 2000.0,
         };
 
@@ -157,4 +222,74 @@ impl GasOptimizer {
 
         Ok(savings_usd)
     }
+
+    /// Average base fee per local hour over the last `days`, sampled from block headers
+    pub async fn hourly_base_fees(
+        &self,
+        chain_id: u64,
+        provider: &Provider<PooledHttp>,
+        settings: &TenantTimeSettings,
+        days: u64,
+    ) -> Result<GasHourProfile> {
+        let latest = provider.get_block(BlockNumber::Latest).await?
+            .ok_or_else(|| anyhow!("Chain {} returned no latest block", chain_id))?;
+        let latest_number = latest.number.ok_or_else(|| anyhow!("Latest block has no number"))?.as_u64();
+
+        // Spread the samples evenly over the period using the chain's measured block time
+        let window_start = latest_number.saturating_sub(BLOCK_TIME_WINDOW);
+        let earlier = provider.get_block(window_start).await?
+            .ok_or_else(|| anyhow!("Chain {} returned no block {}", chain_id, window_start))?;
+        let elapsed = latest.timestamp.saturating_sub(earlier.timestamp).as_u64();
+        let block_time = elapsed as f64 / (latest_number - window_start).max(1) as f64;
+        let span_blocks = (days as f64 * 86_400.0 / block_time.max(0.1)) as u64;
+        let sample_count = days * GAS_PROFILE_SAMPLES_PER_DAY;
+        let step = (span_blocks / sample_count).max(1);
+
+        let blocks: Vec<u64> = (0..sample_count)
+            .map_while(|index| latest_number.checked_sub(index * step))
+            .collect();
+        let mut fetched = stream::iter(blocks)
+            .map(|number| provider.get_block(number))
+            .buffer_unordered(GAS_PROFILE_CONCURRENCY);
+
+        let mut totals = [(0.0f64, 0usize); 24];
+        while let Some(block) = fetched.next().await {
+            let Ok(Some(block)) = block else {
+                continue;
+            };
+            let (Some(base_fee), Some(timestamp)) = (
+                block.base_fee_per_gas,
+                DateTime::from_timestamp(block.timestamp.as_u64() as i64, 0),
+            ) else {
+                continue;
+            };
+            let bucket = &mut totals[settings.local_hour(timestamp) as usize];
+            bucket.0 += base_fee.as_u128() as f64 / 1e9;
+            bucket.1 += 1;
+        }
+
+        let hours: Vec<HourlyBaseFee> = totals.iter()
+            .enumerate()
+            .filter(|(_, (_, samples))| *samples > 0)
+            .map(|(hour, (total, samples))| HourlyBaseFee {
+                hour: hour as u32,
+                average_base_fee_gwei: total / *samples as f64,
+                samples: *samples,
+            })
+            .collect();
+        if hours.is_empty() {
+            return Err(anyhow!("Chain {} blocks carry no base fee", chain_id));
+        }
+        let cheapest_hour = hours.iter()
+            .min_by(|a, b| a.average_base_fee_gwei.total_cmp(&b.average_base_fee_gwei))
+            .map(|hour| hour.hour);
+
+        Ok(GasHourProfile {
+            chain_id,
+            time_zone: settings.time_zone,
+            days,
+            hours,
+            cheapest_hour,
+        })
+    }
 }
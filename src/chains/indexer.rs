@@ -0,0 +1,689 @@
+// Decoded event logs of registered contracts, indexed live and through backfills
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use ethers::{
+    abi::{parse_abi, Event, RawLog, Token},
+    providers::{Middleware, Provider},
+    types::{Address, Bytes, Filter, Log, I256, H256},
+};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+use crate::chains::reorg::{ChainReorg, ReorgHandler};
+use crate::chains::ws::{self, WsSubscription};
+use crate::jobs::backfill::BackfillProcessor;
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `indexer_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/indexer.json";
+/// Poll checkpoints kept per chain to find where a reorganization forked
+const MAX_CHECKPOINTS: usize = 64;
+const DEFAULT_EVENT_LIMIT: usize = 100;
+const MAX_EVENT_LIMIT: usize = 1_000;
+
+/// Contract families the indexer knows the events of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexedContractKind {
+    Erc20,
+    AaveV2Pool,
+    AaveV3Pool,
+    #[serde(rename = "compound_ctoken")]
+    CompoundCToken,
+    UniswapV2Pair,
+    UniswapV3Pool,
+}
+
+impl IndexedContractKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Erc20 => "erc20",
+            Self::AaveV2Pool => "aave_v2_pool",
+            Self::AaveV3Pool => "aave_v3_pool",
+            Self::CompoundCToken => "compound_ctoken",
+            Self::UniswapV2Pair => "uniswap_v2_pair",
+            Self::UniswapV3Pool => "uniswap_v3_pool",
+        }
+    }
+
+    fn signatures(self) -> &'static [&'static str] {
+        match self {
+            Self::Erc20 => &["event Transfer(address indexed from, address indexed to, uint256 value)"],
+            Self::AaveV2Pool => &[
+                "event Deposit(address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint16 indexed referral)",
+                "event Withdraw(address indexed reserve, address indexed user, address indexed to, uint256 amount)",
+                "event Borrow(address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint256 borrowRateMode, uint256 borrowRate, uint16 indexed referral)",
+                "event Repay(address indexed reserve, address indexed user, address indexed repayer, uint256 amount)",
+                "event LiquidationCall(address indexed collateralAsset, address indexed debtAsset, address indexed user, uint256 debtToCover, uint256 liquidatedCollateralAmount, address liquidator, bool receiveAToken)",
+            ],
+            Self::AaveV3Pool => &[
+                "event Supply(address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint16 indexed referralCode)",
+                "event Withdraw(address indexed reserve, address indexed user, address indexed to, uint256 amount)",
+                "event Borrow(address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint8 interestRateMode, uint256 borrowRate, uint16 indexed referralCode)",
+                "event Repay(address indexed reserve, address indexed user, address indexed repayer, uint256 amount, bool useATokens)",
+                "event LiquidationCall(address indexed collateralAsset, address indexed debtAsset, address indexed user, uint256 debtToCover, uint256 liquidatedCollateralAmount, address liquidator, bool receiveAToken)",
+            ],
+            Self::CompoundCToken => &[
+                "event Mint(address minter, uint256 mintAmount, uint256 mintTokens)",
+                "event Redeem(address redeemer, uint256 redeemAmount, uint256 redeemTokens)",
+                "event Borrow(address borrower, uint256 borrowAmount, uint256 accountBorrows, uint256 totalBorrows)",
+                "event RepayBorrow(address payer, address borrower, uint256 repayAmount, uint256 accountBorrows, uint256 totalBorrows)",
+                "event LiquidateBorrow(address liquidator, address borrower, uint256 repayAmount, address cTokenCollateral, uint256 seizeTokens)",
+            ],
+            Self::UniswapV2Pair => &[
+                "event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to)",
+            ],
+            Self::UniswapV3Pool => &[
+                "event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)",
+            ],
+        }
+    }
+
+    /// Events of the family keyed by their topic0
+    fn events(self) -> Result<HashMap<H256, Event>> {
+        let abi = parse_abi(self.signatures())?;
+        Ok(abi.events().map(|event| (event.signature(), event.clone())).collect())
+    }
+}
+
+impl FromStr for IndexedContractKind {
+    type Err = anyhow::Error;
+
+    fn from_str(kind: &str) -> Result<Self> {
+        [
+            Self::Erc20,
+            Self::AaveV2Pool,
+            Self::AaveV3Pool,
+            Self::CompoundCToken,
+            Self::UniswapV2Pair,
+            Self::UniswapV3Pool,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == kind.trim())
+        .ok_or_else(|| anyhow!("Unknown contract kind '{}'", kind))
+    }
+}
+
+/// Contract whose events are indexed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedContract {
+    pub chain_id: u64,
+    pub address: Address,
+    pub kind: IndexedContractKind,
+    pub label: Option<String>,
+}
+
+/// Decoded event with the block and transaction it was emitted in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedEvent {
+    pub chain_id: u64,
+    pub contract: Address,
+    pub kind: IndexedContractKind,
+    pub event: String,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub transaction_hash: H256,
+    pub log_index: u64,
+    /// Decoded parameters by name, integers as decimal strings
+    pub params: BTreeMap<String, serde_json::Value>,
+    /// Every address among the parameters, what account filters match on
+    pub accounts: Vec<Address>,
+}
+
+/// Events selected by a query, newest first
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IndexedEventQuery {
+    pub chain_id: Option<u64>,
+    pub contract: Option<Address>,
+    pub account: Option<Address>,
+    pub event: Option<String>,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// Last block indexed live on a chain and the hashes of recent poll boundaries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ChainCursor {
+    last_block: u64,
+    checkpoints: Vec<(u64, H256)>,
+}
+
+/// Live indexing progress of a chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexerChainStatus {
+    pub chain_id: u64,
+    pub contracts: usize,
+    pub events: usize,
+    /// `None` until the chain is polled for the first time
+    pub last_block: Option<u64>,
+    pub reorgs: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexerStore {
+    /// Contracts registered through the API, configured ones are added on startup
+    contracts: Vec<IndexedContract>,
+    cursors: BTreeMap<u64, ChainCursor>,
+    events: Vec<IndexedEvent>,
+}
+
+/// Indexer configuration
+#[derive(Debug, Clone)]
+pub struct IndexerConfig {
+    pub poll_interval: Duration,
+    /// Most blocks read in one poll, a chain further behind catches up over several polls
+    pub max_blocks_per_poll: u64,
+    /// Events kept in total, the oldest blocks are dropped first
+    pub max_events: usize,
+    pub contracts: Vec<IndexedContract>,
+    /// JSON file holding the index, `None` keeps it in memory only
+    pub store_path: Option<PathBuf>,
+}
+
+impl Default for IndexerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(15),
+            max_blocks_per_poll: 500,
+            max_events: 100_000,
+            contracts: Vec::new(),
+            store_path: Some(PathBuf::from(DEFAULT_STORE_PATH)),
+        }
+    }
+}
+
+impl IndexerConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut indexer_config = Self::default();
+
+        if let Ok(secs) = config.get_int("indexer_poll_interval_secs") {
+            indexer_config.poll_interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(blocks) = config.get_int("indexer_max_blocks_per_poll") {
+            indexer_config.max_blocks_per_poll = blocks.max(1) as u64;
+        }
+        if let Ok(max_events) = config.get_int("indexer_max_events") {
+            indexer_config.max_events = max_events.max(0) as usize;
+        }
+        // Entries are `chain_id:kind:address`
+        if let Ok(contracts) = config.get_string("indexer_contracts") {
+            for entry in contracts.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                match parse_contract(entry) {
+                    Ok(contract) => indexer_config.contracts.push(contract),
+                    Err(e) => warn!("Ignoring indexer contract '{}': {}", entry, e),
+                }
+            }
+        }
+        if let Ok(path) = config.get_string("indexer_store_path") {
+            indexer_config.store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        }
+
+        indexer_config
+    }
+}
+
+#[derive(Default)]
+struct IndexState {
+    contracts: Vec<IndexedContract>,
+    cursors: BTreeMap<u64, ChainCursor>,
+    /// Keyed by chain, block and log index, so re-indexing a range replaces rather than duplicates
+    events: BTreeMap<(u64, u64, u64), IndexedEvent>,
+    reorgs: HashMap<u64, u64>,
+}
+
+/// Polls the logs of registered contracts, stores them decoded and drops the ones of orphaned blocks
+pub struct EventLogIndexer {
+    chain_manager: Arc<ChainManager>,
+    config: IndexerConfig,
+    decoders: HashMap<IndexedContractKind, HashMap<H256, Event>>,
+    state: RwLock<IndexState>,
+    /// Bumped when contracts are registered or removed, so log subscriptions are renewed
+    contracts_changed: watch::Sender<u64>,
+}
+
+impl EventLogIndexer {
+    pub async fn new(chain_manager: Arc<ChainManager>, config: IndexerConfig) -> Result<Self> {
+        let store: IndexerStore = match &config.store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => IndexerStore::default(),
+        };
+        if let Some(path) = config.store_path.as_ref().filter(|_| !store.events.is_empty()) {
+            info!("Loaded {} indexed events from {}", store.events.len(), path.display());
+        }
+
+        let mut contracts = store.contracts;
+        for contract in &config.contracts {
+            if !contracts.iter().any(|known| known.chain_id == contract.chain_id && known.address == contract.address) {
+                contracts.push(contract.clone());
+            }
+        }
+        let decoders = [
+            IndexedContractKind::Erc20,
+            IndexedContractKind::AaveV2Pool,
+            IndexedContractKind::AaveV3Pool,
+            IndexedContractKind::CompoundCToken,
+            IndexedContractKind::UniswapV2Pair,
+            IndexedContractKind::UniswapV3Pool,
+        ]
+        .into_iter()
+        .map(|kind| Ok((kind, kind.events()?)))
+        .collect::<Result<_>>()?;
+
+        Ok(Self {
+            chain_manager,
+            decoders,
+            state: RwLock::new(IndexState {
+                contracts,
+                cursors: store.cursors,
+                events: store.events.into_iter()
+                    .map(|event| ((event.chain_id, event.block_number, event.log_index), event))
+                    .collect(),
+                reorgs: HashMap::new(),
+            }),
+            contracts_changed: watch::Sender::new(0),
+            config,
+        })
+    }
+
+    pub async fn from_config(config: &config::Config, chain_manager: Arc<ChainManager>) -> Result<Self> {
+        Self::new(chain_manager, IndexerConfig::from_config(config)).await
+    }
+
+    /// Poll the registered contracts in the background until shutdown, and a chain with a WebSocket
+    /// as soon as one of its contracts emits a log
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let (wake, mut emitted) = mpsc::unbounded_channel();
+            let indexer = self.clone();
+            let wakers = ws::spawn_wakers(
+                self.chain_manager.clone(),
+                move |chain_id| {
+                    let indexer = indexer.clone();
+                    async move { indexer.log_filter(chain_id).await.map(WsSubscription::Logs) }.boxed()
+                },
+                Some(self.contracts_changed.subscribe()),
+                wake,
+                shutdown.clone(),
+            );
+            let mut ticker = tokio::time::interval(self.config.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.poll_all().await,
+                    Some(chain_id) = emitted.recv() => self.poll(chain_id).await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            let _ = wakers.await;
+            info!("Event indexer stopped");
+        })
+    }
+
+    pub async fn contracts(&self) -> Vec<IndexedContract> {
+        self.state.read().await.contracts.clone()
+    }
+
+    /// Index a contract's events from the next poll on; returns false when it was already registered
+    pub async fn register(&self, contract: IndexedContract) -> Result<bool> {
+        self.chain_manager.get_provider(contract.chain_id).await?;
+        let mut state = self.state.write().await;
+        if let Some(known) = state.contracts.iter_mut()
+            .find(|known| known.chain_id == contract.chain_id && known.address == contract.address)
+        {
+            if *known == contract {
+                return Ok(false);
+            }
+            *known = contract;
+        } else {
+            info!("Indexing {} events of {:?} on chain {}", contract.kind.as_str(), contract.address, contract.chain_id);
+            state.contracts.push(contract);
+        }
+        self.persist(&state).await;
+        self.contracts_changed.send_modify(|version| *version += 1);
+        Ok(true)
+    }
+
+    /// Stop indexing a contract, its events already stored are kept
+    pub async fn unregister(&self, chain_id: u64, address: Address) -> bool {
+        let mut state = self.state.write().await;
+        let before = state.contracts.len();
+        state.contracts.retain(|contract| !(contract.chain_id == chain_id && contract.address == address));
+        let removed = state.contracts.len() < before;
+        if removed {
+            self.persist(&state).await;
+            self.contracts_changed.send_modify(|version| *version += 1);
+        }
+        removed
+    }
+
+    /// Stored events matching the query, newest first
+    pub async fn events(&self, query: &IndexedEventQuery) -> Vec<IndexedEvent> {
+        let limit = query.limit.unwrap_or(DEFAULT_EVENT_LIMIT).min(MAX_EVENT_LIMIT);
+        self.state.read().await.events.values()
+            .rev()
+            .filter(|event| query.chain_id.is_none_or(|chain_id| event.chain_id == chain_id))
+            .filter(|event| query.contract.is_none_or(|contract| event.contract == contract))
+            .filter(|event| query.account.is_none_or(|account| event.accounts.contains(&account)))
+            .filter(|event| query.event.as_deref().is_none_or(|name| event.event.eq_ignore_ascii_case(name)))
+            .filter(|event| query.from_block.is_none_or(|from| event.block_number >= from))
+            .filter(|event| query.to_block.is_none_or(|to| event.block_number <= to))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Live progress of every chain with registered contracts or stored events
+    pub async fn status(&self) -> Vec<IndexerChainStatus> {
+        let state = self.state.read().await;
+        let mut chain_ids: BTreeSet<u64> = state.contracts.iter().map(|contract| contract.chain_id).collect();
+        chain_ids.extend(state.events.keys().map(|(chain_id, _, _)| *chain_id));
+        chain_ids.into_iter()
+            .map(|chain_id| IndexerChainStatus {
+                chain_id,
+                contracts: state.contracts.iter().filter(|contract| contract.chain_id == chain_id).count(),
+                events: state.events.range((chain_id, 0, 0)..=(chain_id, u64::MAX, u64::MAX)).count(),
+                last_block: state.cursors.get(&chain_id).map(|cursor| cursor.last_block),
+                reorgs: state.reorgs.get(&chain_id).copied().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Drop the events of `from_block` and later on a chain and rewind live indexing to re-read them;
+    /// returns the number of events dropped
+    pub async fn invalidate_from(&self, chain_id: u64, from_block: u64) -> usize {
+        let mut state = self.state.write().await;
+        let dropped = invalidate(&mut state, chain_id, from_block);
+        *state.reorgs.entry(chain_id).or_default() += 1;
+        self.persist(&state).await;
+        dropped
+    }
+
+    async fn poll_all(&self) {
+        let chain_ids: BTreeSet<u64> = self.state.read().await.contracts.iter()
+            .map(|contract| contract.chain_id)
+            .collect();
+        for chain_id in chain_ids {
+            self.poll(chain_id).await;
+        }
+    }
+
+    async fn poll(&self, chain_id: u64) {
+        if let Err(e) = self.poll_chain(chain_id).await {
+            warn!("Event indexing on chain {} failed: {}", chain_id, e);
+        }
+    }
+
+    async fn poll_chain(&self, chain_id: u64) -> Result<()> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = &chain.provider;
+        let latest = provider.get_block_number().await?.as_u64();
+
+        let cursor = self.state.read().await.cursors.get(&chain_id).cloned();
+        let from = match cursor {
+            Some(cursor) => match self.fork_point(provider, &cursor).await? {
+                Some(fork_block) => {
+                    let dropped = self.invalidate_from(chain_id, fork_block + 1).await;
+                    warn!(
+                        "Chain {} reorganized above block {}, dropped {} indexed events",
+                        chain_id, fork_block, dropped,
+                    );
+                    fork_block + 1
+                }
+                None => cursor.last_block + 1,
+            },
+            // History before the first poll is indexed by backfills
+            None => latest,
+        };
+        if from > latest {
+            return Ok(());
+        }
+        let to = latest.min(from + self.config.max_blocks_per_poll - 1);
+
+        // The boundary hash is read before the logs, so logs of a block replaced meanwhile are caught below
+        let boundary = provider.get_block(to).await?
+            .and_then(|block| block.hash)
+            .ok_or_else(|| anyhow!("Block {} of chain {} is not available", to, chain_id))?;
+        let logs = self.fetch_logs(chain_id, provider, from, to).await?;
+        if logs.iter().any(|log| log.block_number.map(|block| block.as_u64()) == Some(to) && log.block_hash != Some(boundary)) {
+            bail!("Block {} of chain {} changed while it was indexed", to, chain_id);
+        }
+
+        let mut state = self.state.write().await;
+        let indexed = self.apply_logs(&mut state, chain_id, &logs);
+        let cursor = state.cursors.entry(chain_id).or_default();
+        cursor.last_block = to;
+        cursor.checkpoints.push((to, boundary));
+        if cursor.checkpoints.len() > MAX_CHECKPOINTS {
+            cursor.checkpoints.remove(0);
+        }
+        self.trim(&mut state);
+        self.persist(&state).await;
+        if indexed > 0 {
+            debug!("Indexed {} events of chain {} in blocks {}..={}", indexed, chain_id, from, to);
+        }
+        Ok(())
+    }
+
+    /// Last checkpoint still on the canonical chain when a later one was reorganized away, `None`
+    /// when the newest checkpoint is intact
+    async fn fork_point(&self, provider: &Provider<PooledHttp>, cursor: &ChainCursor) -> Result<Option<u64>> {
+        let mut reorganized = false;
+        for (block, hash) in cursor.checkpoints.iter().rev() {
+            let canonical = provider.get_block(*block).await?.and_then(|block| block.hash);
+            if canonical == Some(*hash) {
+                return Ok(reorganized.then_some(*block));
+            }
+            reorganized = true;
+        }
+        // Deeper than every checkpoint, re-read from before the oldest one
+        Ok(cursor.checkpoints.first().filter(|_| reorganized).map(|(block, _)| block.saturating_sub(1)))
+    }
+
+    async fn fetch_logs(&self, chain_id: u64, provider: &Provider<PooledHttp>, from: u64, to: u64) -> Result<Vec<Log>> {
+        let Some(filter) = self.log_filter(chain_id).await else {
+            return Ok(Vec::new());
+        };
+        Ok(provider.get_logs(&filter.from_block(from).to_block(to)).await?)
+    }
+
+    /// Decodable events of the chain's registered contracts, `None` when none are registered
+    async fn log_filter(&self, chain_id: u64) -> Option<Filter> {
+        let addresses: Vec<Address> = self.state.read().await.contracts.iter()
+            .filter(|contract| contract.chain_id == chain_id)
+            .map(|contract| contract.address)
+            .collect();
+        if addresses.is_empty() {
+            return None;
+        }
+        let topics: Vec<H256> = self.decoders.values()
+            .flat_map(|events| events.keys().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        Some(Filter::new().address(addresses).topic0(topics))
+    }
+
+    /// Store the decodable logs, removing the ones a node reports as removed; returns the number stored
+    fn apply_logs(&self, state: &mut IndexState, chain_id: u64, logs: &[Log]) -> u64 {
+        let kinds: HashMap<Address, IndexedContractKind> = state.contracts.iter()
+            .filter(|contract| contract.chain_id == chain_id)
+            .map(|contract| (contract.address, contract.kind))
+            .collect();
+        let mut indexed = 0;
+        for log in logs {
+            let (Some(block_number), Some(log_index)) = (log.block_number, log.log_index) else {
+                continue;
+            };
+            let key = (chain_id, block_number.as_u64(), log_index.as_u64());
+            if log.removed == Some(true) {
+                state.events.remove(&key);
+                continue;
+            }
+            let Some(kind) = kinds.get(&log.address) else {
+                continue;
+            };
+            match self.decode(chain_id, *kind, log) {
+                Some(event) => {
+                    state.events.insert(key, event);
+                    indexed += 1;
+                }
+                None => debug!("Skipping undecodable log {:?}:{} of {:?}", log.transaction_hash, log_index, log.address),
+            }
+        }
+        indexed
+    }
+
+    fn decode(&self, chain_id: u64, kind: IndexedContractKind, log: &Log) -> Option<IndexedEvent> {
+        let event = self.decoders.get(&kind)?.get(log.topics.first()?)?;
+        // ERC-721 transfers share the ERC-20 signature but index the token id, which fails to parse here
+        let decoded = event.parse_log(RawLog { topics: log.topics.clone(), data: log.data.to_vec() }).ok()?;
+
+        let mut accounts = Vec::new();
+        let params = decoded.params.into_iter()
+            .map(|param| {
+                if let Token::Address(account) = &param.value {
+                    if !accounts.contains(account) {
+                        accounts.push(*account);
+                    }
+                }
+                (param.name, token_json(param.value))
+            })
+            .collect();
+        Some(IndexedEvent {
+            chain_id,
+            contract: log.address,
+            kind,
+            event: event.name.clone(),
+            block_number: log.block_number?.as_u64(),
+            block_hash: log.block_hash?,
+            transaction_hash: log.transaction_hash?,
+            log_index: log.log_index?.as_u64(),
+            params,
+            accounts,
+        })
+    }
+
+    /// Drop the oldest blocks' events beyond `max_events`
+    fn trim(&self, state: &mut IndexState) {
+        let excess = state.events.len().saturating_sub(self.config.max_events);
+        if excess == 0 {
+            return;
+        }
+        let mut by_age: Vec<(u64, (u64, u64, u64))> = state.events.keys().map(|key| (key.1, *key)).collect();
+        by_age.sort_unstable();
+        for (_, key) in by_age.into_iter().take(excess) {
+            state.events.remove(&key);
+        }
+    }
+
+    async fn persist(&self, state: &IndexState) {
+        let Some(path) = &self.config.store_path else {
+            return;
+        };
+        let store = IndexerStore {
+            contracts: state.contracts.clone(),
+            cursors: state.cursors.clone(),
+            events: state.events.values().cloned().collect(),
+        };
+        if let Err(e) = write_store(path, &store).await {
+            warn!("Failed to persist indexed events to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl BackfillProcessor for EventLogIndexer {
+    fn name(&self) -> &'static str {
+        "event_indexer"
+    }
+
+    async fn process(&self, chain_id: u64, provider: &Provider<PooledHttp>, from_block: u64, to_block: u64) -> Result<u64> {
+        let logs = self.fetch_logs(chain_id, provider, from_block, to_block).await?;
+        let mut state = self.state.write().await;
+        if !state.contracts.iter().any(|contract| contract.chain_id == chain_id) {
+            bail!("No contracts are registered for indexing on chain {}", chain_id);
+        }
+        let indexed = self.apply_logs(&mut state, chain_id, &logs);
+        self.trim(&mut state);
+        self.persist(&state).await;
+        Ok(indexed)
+    }
+}
+
+#[async_trait]
+impl ReorgHandler for EventLogIndexer {
+    fn name(&self) -> &'static str {
+        "event_indexer"
+    }
+
+    /// Drop the events above the fork unless a poll already rewound past it
+    async fn handle_reorg(&self, reorg: &ChainReorg) -> Result<()> {
+        let chain_id = reorg.chain_id;
+        let affected = {
+            let state = self.state.read().await;
+            state.cursors.get(&chain_id).is_some_and(|cursor| cursor.last_block > reorg.fork_block)
+                || state.events.range((chain_id, reorg.fork_block + 1, 0)..=(chain_id, u64::MAX, u64::MAX)).next().is_some()
+        };
+        if affected {
+            let dropped = self.invalidate_from(chain_id, reorg.fork_block + 1).await;
+            info!("Dropped {} indexed events of chain {} above block {}", dropped, chain_id, reorg.fork_block);
+        }
+        Ok(())
+    }
+}
+
+/// Remove a chain's events from `from_block` on and move its cursor back before them
+fn invalidate(state: &mut IndexState, chain_id: u64, from_block: u64) -> usize {
+    let orphaned: Vec<(u64, u64, u64)> = state.events
+        .range((chain_id, from_block, 0)..=(chain_id, u64::MAX, u64::MAX))
+        .map(|(key, _)| *key)
+        .collect();
+    for key in &orphaned {
+        state.events.remove(key);
+    }
+    if let Some(cursor) = state.cursors.get_mut(&chain_id) {
+        cursor.checkpoints.retain(|(block, _)| *block < from_block);
+        cursor.last_block = cursor.last_block.min(from_block.saturating_sub(1));
+    }
+    orphaned.len()
+}
+
+fn parse_contract(entry: &str) -> Result<IndexedContract> {
+    let mut parts = entry.splitn(3, ':');
+    let (Some(chain_id), Some(kind), Some(address)) = (parts.next(), parts.next(), parts.next()) else {
+        bail!("expected chain_id:kind:address");
+    };
+    Ok(IndexedContract {
+        chain_id: chain_id.trim().parse()?,
+        address: address.trim().parse()?,
+        kind: kind.parse()?,
+        label: None,
+    })
+}
+
+fn token_json(token: Token) -> serde_json::Value {
+    match token {
+        Token::Address(address) => serde_json::Value::String(format!("{:?}", address)),
+        Token::Uint(value) => serde_json::Value::String(value.to_string()),
+        Token::Int(value) => serde_json::Value::String(I256::from_raw(value).to_string()),
+        Token::Bool(value) => serde_json::Value::Bool(value),
+        Token::String(value) => serde_json::Value::String(value),
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => serde_json::Value::String(Bytes::from(bytes).to_string()),
+        Token::Array(tokens) | Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+            serde_json::Value::Array(tokens.into_iter().map(token_json).collect())
+        }
+    }
+}
@@ -1,23 +1,73 @@
 use anyhow::Result;
 use ethers::{
-    providers::{Http, Middleware, Provider},
-    types::{Address, U256},
+    providers::{Middleware, Provider},
+    types::{Address, U256, U64},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{info, warn, error};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, instrument, warn, error};
 
 pub mod ethereum;
 pub mod polygon;
 pub mod arbitrum;
+pub mod bsc;
+pub mod avalanche;
+pub mod assets;
+pub mod batch;
+pub mod ens;
+pub mod fork;
+pub mod indexer;
+pub mod pool;
+pub mod reorg;
 pub mod gas_optimizer;
+pub mod simulator;
+pub mod tokens;
+pub mod tx_broadcaster;
+pub mod ws;
 
+use crate::analytics::time_zones::TenantTimeSettings;
 use crate::api::health::ChainHealth;
 use ethereum::EthereumChain;
 use polygon::PolygonChain;
 use arbitrum::ArbitrumChain;
-use gas_optimizer::GasOptimizer;
+use bsc::BscChain;
+use avalanche::AvalancheChain;
+use batch::{BatchResults, RpcBatch};
+use fork::{AnvilFork, ForkConfig};
+use gas_optimizer::{GasHourProfile, GasOptimizer};
+use pool::{PoolConfig, PooledHttp};
+use ws::{WsConfig, WsConnection};
+
+/// Longest a chain may take to come up before it is marked unavailable
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Backoff after the first failed activation, doubled on each further failure
+const ACTIVATION_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_ACTIVATION_BACKOFF: Duration = Duration::from_secs(300);
+
+/// A configured chain whose RPC could not be reached
+#[derive(Debug, Clone)]
+pub struct ChainUnavailable {
+    pub chain_id: u64,
+    pub reason: String,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for ChainUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Chain {} temporarily unavailable: {} (retry in {}s)",
+            self.chain_id,
+            self.reason,
+            self.retry_after.as_secs().max(1)
+        )
+    }
+}
+
+impl std::error::Error for ChainUnavailable {}
 
 #[derive(Debug, Clone)]
 pub struct ChainConfig {
@@ -35,30 +85,59 @@ pub enum ChainImplementation {
     Ethereum(EthereumChain),
     Polygon(PolygonChain),
     Arbitrum(ArbitrumChain),
+    Bsc(BscChain),
+    Avalanche(AvalancheChain),
 }
 
 pub struct ChainManager {
-    chains: HashMap<u64, Arc<ChainProvider>>,
+    chains: RwLock<HashMap<u64, Arc<ChainProvider>>>,
+    /// Configured chains, connected on first use
+    lazy_chains: HashMap<u64, LazyChain>,
+    paused_chains: RwLock<HashSet<u64>>,
     gas_optimizer: GasOptimizer,
+    fork: Option<Arc<AnvilFork>>,
+    /// Heartbeats and reconnection of the chains' WebSocket connections
+    ws_config: WsConfig,
+    /// Request slots per RPC endpoint
+    pool_config: PoolConfig,
 }
 
 pub struct ChainProvider {
     pub config: ChainConfig,
-    pub provider: Provider<Http>,
+    /// Requests balanced over the chain's RPC endpoints, each limited to its request slots
+    pub provider: Provider<PooledHttp>,
+    /// Subscriptions (new heads, logs, pending transactions), `None` without a `ws_url`
+    pub ws: Option<Arc<WsConnection>>,
     pub chain_impl: Arc<ChainImplementation>,
-    pub connection_pool: Arc<RwLock<ConnectionPool>>,
 }
 
-#[derive(Debug)]
-struct ConnectionPool {
-    active_connections: u32,
-    max_connections: u32,
-    retry_count: HashMap<String, u32>,
+struct LazyChain {
+    config: ChainConfig,
+    // Held while connecting so concurrent requests wait for one attempt
+    activation: Mutex<ActivationState>,
+}
+
+#[derive(Default)]
+struct ActivationState {
+    failures: u32,
+    retry_at: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl LazyChain {
+    fn new(config: ChainConfig) -> Self {
+        Self {
+            config,
+            activation: Mutex::new(ActivationState::default()),
+        }
+    }
 }
 
 impl ChainManager {
+    /// Register the configured chains; each one connects on first use so an RPC outage
+    /// only degrades that chain instead of failing startup
     pub async fn new(config: &config::Config) -> Result<Self> {
-        let mut chains = HashMap::new();
+        let mut lazy_chains = HashMap::new();
 
         // Initialize Ethereum mainnet
         let eth_config = ChainConfig {
@@ -75,8 +154,7 @@ impl ChainManager {
             is_testnet: false,
         };
 
-        let eth_provider = ChainProvider::new(eth_config).await?;
-        chains.insert(1, Arc::new(eth_provider));
+        lazy_chains.insert(1, LazyChain::new(eth_config));
 
         // Initialize Polygon
         let polygon_config = ChainConfig {
@@ -93,8 +171,7 @@ impl ChainManager {
             is_testnet: false,
         };
 
-        let polygon_provider = ChainProvider::new(polygon_config).await?;
-        chains.insert(137, Arc::new(polygon_provider));
+        lazy_chains.insert(137, LazyChain::new(polygon_config));
 
         // Initialize Arbitrum
         let arbitrum_config = ChainConfig {
@@ -111,16 +188,54 @@ impl ChainManager {
             is_testnet: false,
         };
 
-        let arbitrum_provider = ChainProvider::new(arbitrum_config).await?;
-        chains.insert(42161, Arc::new(arbitrum_provider));
+        lazy_chains.insert(42161, LazyChain::new(arbitrum_config));
+
+        // Initialize BNB Smart Chain
+        let bsc_config = ChainConfig {
+            chain_id: 56,
+            name: "BNB Smart Chain".to_string(),
+            rpc_url: config
+                .get_string("bsc_rpc_url")
+                .unwrap_or_else(|_| "https://bsc-dataseed.bnbchain.org".to_string()),
+            ws_url: Some(config
+                .get_string("bsc_ws_url")
+                .unwrap_or_else(|_| "wss://bsc-rpc.publicnode.com".to_string())),
+            block_explorer: "https://bscscan.com".to_string(),
+            native_token: "BNB".to_string(),
+            is_testnet: false,
+        };
+
+        lazy_chains.insert(56, LazyChain::new(bsc_config));
+
+        // Initialize Avalanche C-Chain
+        let avalanche_config = ChainConfig {
+            chain_id: 43114,
+            name: "Avalanche C-Chain".to_string(),
+            rpc_url: config
+                .get_string("avalanche_rpc_url")
+                .unwrap_or_else(|_| "https://api.avax.network/ext/bc/C/rpc".to_string()),
+            ws_url: Some(config
+                .get_string("avalanche_ws_url")
+                .unwrap_or_else(|_| "wss://api.avax.network/ext/bc/C/ws".to_string())),
+            block_explorer: "https://snowtrace.io".to_string(),
+            native_token: "AVAX".to_string(),
+            is_testnet: false,
+        };
+
+        lazy_chains.insert(43114, LazyChain::new(avalanche_config));
 
         let gas_optimizer = gas_optimizer::GasOptimizer::new();
 
-        info!("Initialized ChainManager with {} chains", chains.len());
+        info!("Registered {} chains, connecting on first use", lazy_chains.len());
 
         Ok(Self {
-            chains,
+            chains: RwLock::new(HashMap::new()),
+            lazy_chains,
+            paused_chains: RwLock::new(HashSet::new()),
             gas_optimizer,
+            fork: None,
+            ws_config: WsConfig::from_config(config),
+            pool_config: PoolConfig::from_config(config),
         })
     }
 
@@ -130,16 +245,162 @@ impl ChainManager {
         let gas_optimizer = gas_optimizer::GasOptimizer::new();
 
         Ok(Self {
-            chains,
+            chains: RwLock::new(chains),
+            lazy_chains: HashMap::new(),
+            paused_chains: RwLock::new(HashSet::new()),
             gas_optimizer,
+            fork: None,
+            ws_config: WsConfig::default(),
+            pool_config: PoolConfig::default(),
+        })
+    }
+
+    /// Serve the forked chain from a local anvil node so reads and executions hit real state
+    pub async fn new_fork(config: &ForkConfig) -> Result<Self> {
+        info!("Creating ChainManager in fork mode");
+        let fork = Arc::new(AnvilFork::start(config).await?);
+
+        let fork_config = ChainConfig {
+            chain_id: fork.chain_id,
+            name: format!("Fork of chain {}", fork.chain_id),
+            rpc_url: fork.rpc_url.clone(),
+            ws_url: None,
+            block_explorer: "https://etherscan.io".to_string(),
+            native_token: "ETH".to_string(),
+            is_testnet: true,
+        };
+        let provider = ChainProvider::new(fork_config, WsConfig::default(), PoolConfig::default()).await?;
+
+        let mut chains = HashMap::new();
+        chains.insert(fork.chain_id, Arc::new(provider));
+
+        Ok(Self {
+            chains: RwLock::new(chains),
+            lazy_chains: HashMap::new(),
+            paused_chains: RwLock::new(HashSet::new()),
+            gas_optimizer: gas_optimizer::GasOptimizer::new(),
+            fork: Some(fork),
+            ws_config: WsConfig::default(),
+            pool_config: PoolConfig::default(),
         })
     }
 
+    /// Local fork node when running in fork mode
+    pub fn fork(&self) -> Option<&Arc<AnvilFork>> {
+        self.fork.as_ref()
+    }
+
     pub async fn get_provider(&self, chain_id: u64) -> Result<Arc<ChainProvider>> {
-        self.chains
+        if self.paused_chains.read().await.contains(&chain_id) {
+            return Err(anyhow::anyhow!("Chain {} is paused", chain_id));
+        }
+
+        self.resolve_provider(chain_id).await
+    }
+
+    /// Provider of an active chain, connecting a registered chain on first use
+    async fn resolve_provider(&self, chain_id: u64) -> Result<Arc<ChainProvider>> {
+        if let Some(provider) = self.chains.read().await.get(&chain_id).cloned() {
+            return Ok(provider);
+        }
+
+        let lazy = self.lazy_chains
             .get(&chain_id)
-            .cloned()
-            .ok_or_else(|| anyhow::anyhow!("Chain {} not supported", chain_id))
+            .ok_or_else(|| anyhow::anyhow!("Chain {} not supported", chain_id))?;
+        self.activate(lazy).await
+    }
+
+    /// Connect a registered chain, backing off after failed attempts
+    #[instrument(skip_all, fields(chain_id = lazy.config.chain_id))]
+    async fn activate(&self, lazy: &LazyChain) -> Result<Arc<ChainProvider>> {
+        let chain_id = lazy.config.chain_id;
+        let mut state = lazy.activation.lock().await;
+
+        // Another request may have connected the chain while we waited
+        if let Some(provider) = self.chains.read().await.get(&chain_id).cloned() {
+            return Ok(provider);
+        }
+
+        let now = Instant::now();
+        if let Some(retry_at) = state.retry_at.filter(|retry_at| *retry_at > now) {
+            return Err(ChainUnavailable {
+                chain_id,
+                reason: state.last_error.clone().unwrap_or_else(|| "RPC unreachable".to_string()),
+                retry_after: retry_at - now,
+            }.into());
+        }
+
+        info!("Activating chain {} ({})", chain_id, lazy.config.name);
+        let reason = match tokio::time::timeout(ACTIVATION_TIMEOUT, ChainProvider::new(lazy.config.clone(), self.ws_config, self.pool_config)).await {
+            Ok(Ok(provider)) => {
+                let provider = Arc::new(provider);
+                self.chains.write().await.insert(chain_id, provider.clone());
+                *state = ActivationState::default();
+                info!("Chain {} active", chain_id);
+                return Ok(provider);
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("no response within {}s", ACTIVATION_TIMEOUT.as_secs()),
+        };
+
+        state.failures += 1;
+        let retry_after = ACTIVATION_BACKOFF
+            .saturating_mul(2u32.saturating_pow(state.failures - 1))
+            .min(MAX_ACTIVATION_BACKOFF);
+        state.retry_at = Some(Instant::now() + retry_after);
+        state.last_error = Some(reason.clone());
+        warn!("Chain {} unavailable after {} attempts: {}", chain_id, state.failures, reason);
+
+        Err(ChainUnavailable { chain_id, reason, retry_after }.into())
+    }
+
+    /// Config of an active or registered chain
+    async fn chain_config(&self, chain_id: u64) -> Option<ChainConfig> {
+        if let Some(provider) = self.chains.read().await.get(&chain_id) {
+            return Some(provider.config.clone());
+        }
+        self.lazy_chains.get(&chain_id).map(|lazy| lazy.config.clone())
+    }
+
+    /// Replace the RPC endpoint of a chain, keeping the old provider if the new one fails
+    #[instrument(skip_all, fields(chain_id = chain_id))]
+    pub async fn rotate_rpc_endpoint(&self, chain_id: u64, rpc_url: String) -> Result<()> {
+        let mut config = self.chain_config(chain_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Chain {} not supported", chain_id))?;
+
+        config.rpc_url = rpc_url;
+        let provider = ChainProvider::new(config, self.ws_config, self.pool_config).await?;
+
+        self.chains.write().await.insert(chain_id, Arc::new(provider));
+        if let Some(lazy) = self.lazy_chains.get(&chain_id) {
+            *lazy.activation.lock().await = ActivationState::default();
+        }
+        info!("Rotated RPC endpoint for chain {}", chain_id);
+        Ok(())
+    }
+
+    /// Stop handing out providers for a chain until it is resumed
+    pub async fn pause_chain(&self, chain_id: u64) -> Result<()> {
+        if self.chain_config(chain_id).await.is_none() {
+            return Err(anyhow::anyhow!("Chain {} not supported", chain_id));
+        }
+
+        self.paused_chains.write().await.insert(chain_id);
+        warn!("Chain {} paused", chain_id);
+        Ok(())
+    }
+
+    pub async fn resume_chain(&self, chain_id: u64) -> bool {
+        let resumed = self.paused_chains.write().await.remove(&chain_id);
+        if resumed {
+            info!("Chain {} resumed", chain_id);
+        }
+        resumed
+    }
+
+    pub async fn get_paused_chains(&self) -> Vec<u64> {
+        self.paused_chains.read().await.iter().copied().collect()
     }
 
     pub async fn get_block_number(&self, chain_id: u64) -> Result<u64> {
@@ -160,43 +421,96 @@ impl ChainManager {
         Ok(balance)
     }
 
+    /// Average base fee per hour of the day in the tenant's time zone over the last `days`
+    #[instrument(skip_all, fields(chain_id = chain_id))]
+    pub async fn gas_hour_profile(&self, chain_id: u64, settings: &TenantTimeSettings, days: u64) -> Result<GasHourProfile> {
+        let provider = self.get_provider(chain_id).await?;
+        self.gas_optimizer.hourly_base_fees(chain_id, &provider.provider, settings, days).await
+    }
+
     pub async fn estimate_gas_optimized(&self, chain_id: u64, tx_data: &[u8]) -> Result<(U256, U256)> {
         self.gas_optimizer.estimate_optimal_gas(chain_id, tx_data).await
     }
 
+    /// Health of every chain, trying to connect registered chains that are not active yet
     pub async fn health_check(&self) -> Vec<ChainHealth> {
         let mut health_results = Vec::new();
 
-        for (chain_id, provider) in &self.chains {
-            let health = self.check_chain_health(*chain_id, provider).await;
+        for chain_id in self.chain_ids().await {
+            let health = match self.resolve_provider(chain_id).await {
+                Ok(provider) => self.check_chain_health(chain_id, &provider).await,
+                Err(e) => ChainHealth {
+                    chain_id,
+                    name: self.lazy_chains.get(&chain_id)
+                        .map(|lazy| lazy.config.name.clone())
+                        .unwrap_or_default(),
+                    status: "unavailable".to_string(),
+                    rpc_healthy: false,
+                    block_height: None,
+                    gas_price: None,
+                    error: Some(e.to_string()),
+                    rpc_endpoints: Vec::new(),
+                    rpc_saturated: false,
+                },
+            };
             health_results.push(health);
         }
 
         health_results
     }
 
+    /// Ids of active and registered chains, sorted
+    pub async fn chain_ids(&self) -> Vec<u64> {
+        let mut chain_ids: Vec<u64> = self.chains.read().await.keys().copied().collect();
+        chain_ids.extend(self.lazy_chains.keys().copied());
+        chain_ids.sort_unstable();
+        chain_ids.dedup();
+        chain_ids
+    }
+
     async fn check_chain_health(&self, chain_id: u64, provider: &Arc<ChainProvider>) -> ChainHealth {
+        let rpc_endpoints = provider.provider.as_ref().stats();
+        let rpc_saturated = rpc_endpoints.iter()
+            .all(|endpoint| endpoint.in_flight >= endpoint.max_concurrent && endpoint.queued > 0);
+        if rpc_saturated {
+            warn!("Chain {} RPC endpoints saturated, requests are queueing", chain_id);
+        }
         let mut health = ChainHealth {
             chain_id,
             name: provider.config.name.clone(),
+            status: "active".to_string(),
             rpc_healthy: false,
             block_height: None,
             gas_price: None,
+            error: None,
+            rpc_endpoints,
+            rpc_saturated,
+        };
+
+        // Block height and gas price in one round trip
+        let (block_number, gas_price) = match Self::read_head(provider).await {
+            Ok(head) => head,
+            Err(e) => {
+                warn!("Chain {} RPC unhealthy: {}", chain_id, e);
+                health.error = Some(e.to_string());
+                return health;
+            }
         };
 
         // Test RPC connectivity and get block height
-        match provider.provider.get_block_number().await {
+        match block_number {
             Ok(block_number) => {
                 health.rpc_healthy = true;
                 health.block_height = Some(block_number.as_u64());
             }
             Err(e) => {
                 warn!("Chain {} RPC unhealthy: {}", chain_id, e);
+                health.error = Some(e.to_string());
             }
         }
 
         // Get current gas price
-        match provider.provider.get_gas_price().await {
+        match gas_price {
             Ok(gas_price) => {
                 health.gas_price = Some(gas_price.to_string());
             }
@@ -208,14 +522,31 @@ impl ChainManager {
         health
     }
 
-    pub fn get_supported_chains(&self) -> Vec<&ChainConfig> {
-        self.chains.values().map(|provider| &provider.config).collect()
+    /// Block height and gas price of a chain, each able to fail on its own
+    async fn read_head(provider: &ChainProvider) -> Result<(Result<U64>, Result<U256>)> {
+        let mut batch = RpcBatch::new();
+        let block_number = batch.request("eth_blockNumber", ())?;
+        let gas_price = batch.request("eth_gasPrice", ())?;
+        let results = provider.batch(&batch).await?;
+        Ok((results.get(block_number), results.get(gas_price)))
+    }
+
+    /// Configs of active chains and registered chains that have not connected yet
+    pub async fn get_supported_chains(&self) -> Vec<ChainConfig> {
+        let mut configs = Vec::new();
+        for chain_id in self.chain_ids().await {
+            configs.extend(self.chain_config(chain_id).await);
+        }
+        configs
     }
 }
 
 impl ChainProvider {
-    pub async fn new(config: ChainConfig) -> Result<Self> {
-        let provider = Provider::<Http>::try_from(&config.rpc_url)?;
+    /// Connect over the comma-separated endpoints of `rpc_url`
+    pub async fn new(config: ChainConfig, ws_config: WsConfig, pool_config: PoolConfig) -> Result<Self> {
+        let provider = Provider::new(PooledHttp::new(&config.rpc_url, pool_config)?);
+        // The chain-specific implementations talk to the first endpoint
+        let rpc_url = config.rpc_url.split(',').next().unwrap_or_default().trim().to_string();
         
         // Test the connection
         match provider.get_chainid().await {
@@ -239,39 +570,51 @@ impl ChainProvider {
         // Create chain-specific implementation
         let chain_impl = match config.chain_id {
             1 | 11155111 => { // Ethereum mainnet or Sepolia
-                let eth_chain = EthereumChain::new(config.rpc_url.clone(), config.is_testnet).await?;
+                let eth_chain = EthereumChain::new(rpc_url.clone(), config.is_testnet).await?;
                 Arc::new(ChainImplementation::Ethereum(eth_chain))
             },
             137 | 80001 => { // Polygon mainnet or Mumbai
-                let polygon_chain = PolygonChain::new(config.rpc_url.clone(), config.is_testnet).await?;
+                let polygon_chain = PolygonChain::new(rpc_url.clone(), config.is_testnet).await?;
                 Arc::new(ChainImplementation::Polygon(polygon_chain))
             },
             42161 | 421614 => { // Arbitrum One or Sepolia
-                let arbitrum_chain = ArbitrumChain::new(config.rpc_url.clone(), config.is_testnet).await?;
+                let arbitrum_chain = ArbitrumChain::new(rpc_url.clone(), config.is_testnet).await?;
                 Arc::new(ChainImplementation::Arbitrum(arbitrum_chain))
             },
+            56 | 97 => { // BNB Smart Chain mainnet or testnet
+                let bsc_chain = BscChain::new(rpc_url.clone(), config.is_testnet).await?;
+                Arc::new(ChainImplementation::Bsc(bsc_chain))
+            },
+            43114 | 43113 => { // Avalanche C-Chain or Fuji
+                let avalanche_chain = AvalancheChain::new(rpc_url.clone(), config.is_testnet).await?;
+                Arc::new(ChainImplementation::Avalanche(avalanche_chain))
+            },
             _ => {
                 // Fallback to generic Ethereum implementation for unknown chains
                 warn!("Unknown chain ID {}, using generic Ethereum implementation", config.chain_id);
-                let eth_chain = EthereumChain::new(config.rpc_url.clone(), config.is_testnet).await?;
+                let eth_chain = EthereumChain::new(rpc_url.clone(), config.is_testnet).await?;
                 Arc::new(ChainImplementation::Ethereum(eth_chain))
             }
         };
 
-        let connection_pool = Arc::new(RwLock::new(ConnectionPool {
-            active_connections: 0,
-            max_connections: 10,
-            retry_count: HashMap::new(),
-        }));
+        // Connected when the first subscriber needs it
+        let ws = config.ws_url.as_ref()
+            .filter(|url| !url.is_empty())
+            .map(|url| Arc::new(WsConnection::new(config.chain_id, url.clone(), ws_config)));
 
         Ok(Self {
             config,
             provider,
+            ws,
             chain_impl,
-            connection_pool,
         })
     }
 
+    /// Send the batch's calls in as few round trips as the endpoints accept
+    pub async fn batch(&self, batch: &RpcBatch) -> Result<BatchResults> {
+        batch.send(self.provider.as_ref()).await
+    }
+
     pub async fn with_retry<T, F, Fut>(&self, operation: F) -> Result<T>
     where
         F: Fn() -> Fut,
@@ -305,6 +648,8 @@ impl ChainProvider {
             ChainImplementation::Ethereum(eth) => eth.get_balance(address).await,
             ChainImplementation::Polygon(poly) => poly.get_matic_balance(address).await,
             ChainImplementation::Arbitrum(arb) => arb.get_eth_balance(address).await,
+            ChainImplementation::Bsc(bsc) => bsc.get_bnb_balance(address).await,
+            ChainImplementation::Avalanche(avax) => avax.get_avax_balance(address).await,
         }
     }
 
@@ -313,6 +658,8 @@ impl ChainProvider {
             ChainImplementation::Ethereum(eth) => eth.health_check().await,
             ChainImplementation::Polygon(poly) => poly.health_check().await,
             ChainImplementation::Arbitrum(arb) => arb.health_check().await,
+            ChainImplementation::Bsc(bsc) => bsc.health_check().await,
+            ChainImplementation::Avalanche(avax) => avax.health_check().await,
         }
     }
 
@@ -327,6 +674,12 @@ impl ChainProvider {
             ChainImplementation::Arbitrum(_) => {
                 if self.config.is_testnet { "Arbitrum Sepolia" } else { "Arbitrum One" }
             },
+            ChainImplementation::Bsc(_) => {
+                if self.config.is_testnet { "BSC Testnet" } else { "BNB Smart Chain" }
+            },
+            ChainImplementation::Avalanche(_) => {
+                if self.config.is_testnet { "Avalanche Fuji" } else { "Avalanche C-Chain" }
+            },
         }
     }
 }
@@ -0,0 +1,360 @@
+// Concurrency-limited HTTP transport balancing a chain's requests over its RPC endpoints
+use async_trait::async_trait;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, warn};
+
+/// Requests in flight per endpoint and how long a request waits for one to finish
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    pub max_concurrent: usize,
+    pub queue_timeout: Duration,
+    /// Calls per JSON-RPC batch, lowered per endpoint when it rejects a batch as too large
+    pub max_batch_size: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 10,
+            queue_timeout: Duration::from_secs(10),
+            max_batch_size: 50,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut pool_config = Self::default();
+
+        if let Ok(max_concurrent) = config.get_int("rpc_max_concurrent_requests") {
+            pool_config.max_concurrent = max_concurrent.max(1) as usize;
+        }
+        if let Ok(secs) = config.get_int("rpc_queue_timeout_secs") {
+            pool_config.queue_timeout = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(size) = config.get_int("rpc_max_batch_size") {
+            pool_config.max_batch_size = size.max(1) as usize;
+        }
+
+        pool_config
+    }
+}
+
+/// A request that found no free slot on its endpoint within the queue timeout, or a failed one
+#[derive(Debug)]
+pub enum PoolError {
+    Http(HttpClientError),
+    Saturated { endpoint: String, waited: Duration },
+    /// A batch failed in transport or came back unreadable
+    Batch { endpoint: String, reason: String },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => e.fmt(f),
+            Self::Saturated { endpoint, waited } => {
+                write!(f, "RPC endpoint {} saturated, no request slot within {:?}", endpoint, waited)
+            }
+            Self::Batch { endpoint, reason } => write!(f, "Batch request to {} failed: {}", endpoint, reason),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+impl RpcError for PoolError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Http(e) => e.as_error_response(),
+            Self::Saturated { .. } | Self::Batch { .. } => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Http(e) => e.as_serde_error(),
+            Self::Saturated { .. } | Self::Batch { .. } => None,
+        }
+    }
+}
+
+impl From<PoolError> for ProviderError {
+    fn from(e: PoolError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+/// Load and outcome of an endpoint since startup
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStats {
+    /// Scheme and host only, paths often carry API keys
+    pub endpoint: String,
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    pub queued: u64,
+    pub requests: u64,
+    /// Requests failing in transport, JSON-RPC error responses such as reverts are not counted
+    pub failures: u64,
+    /// Requests rejected after waiting the whole queue timeout
+    pub rejected: u64,
+    pub avg_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicU64,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    rejected: AtomicU64,
+    latency_ms: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    name: String,
+    url: reqwest::Url,
+    http: Http,
+    slots: Arc<Semaphore>,
+    counters: Counters,
+    /// Largest batch the endpoint is known to accept
+    batch_limit: AtomicUsize,
+}
+
+/// Outcome of a call in a batch
+pub type BatchResult = Result<Value, JsonRpcError>;
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: usize,
+    #[serde(default)]
+    result: Value,
+    error: Option<JsonRpcError>,
+}
+
+/// JSON-RPC over HTTP sending each request to the least busy endpoint, at most `max_concurrent` at
+/// a time per endpoint; the rest queue until the queue timeout
+#[derive(Debug, Clone)]
+pub struct PooledHttp {
+    endpoints: Arc<Vec<Endpoint>>,
+    config: PoolConfig,
+    client: reqwest::Client,
+    /// Rotates the starting endpoint so equally busy ones share the load
+    next: Arc<AtomicUsize>,
+}
+
+impl PooledHttp {
+    /// Pool over the comma-separated endpoints of `rpc_urls`
+    pub fn new(rpc_urls: &str, config: PoolConfig) -> anyhow::Result<Self> {
+        let endpoints = rpc_urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                Ok(Endpoint {
+                    name: endpoint_name(url),
+                    url: reqwest::Url::parse(url)?,
+                    http: url.parse::<Http>()?,
+                    slots: Arc::new(Semaphore::new(config.max_concurrent)),
+                    counters: Counters::default(),
+                    batch_limit: AtomicUsize::new(config.max_batch_size),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            anyhow::bail!("No RPC endpoint configured");
+        }
+
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            config,
+            client: reqwest::Client::new(),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn stats(&self) -> Vec<EndpointStats> {
+        self.endpoints.iter()
+            .map(|endpoint| {
+                let requests = endpoint.counters.requests.load(Ordering::Relaxed);
+                let latency_ms = endpoint.counters.latency_ms.load(Ordering::Relaxed);
+                EndpointStats {
+                    endpoint: endpoint.name.clone(),
+                    max_concurrent: self.config.max_concurrent,
+                    in_flight: self.config.max_concurrent - endpoint.slots.available_permits(),
+                    queued: endpoint.counters.queued.load(Ordering::Relaxed),
+                    requests,
+                    failures: endpoint.counters.failures.load(Ordering::Relaxed),
+                    rejected: endpoint.counters.rejected.load(Ordering::Relaxed),
+                    avg_latency_ms: (requests > 0).then(|| latency_ms as f64 / requests as f64),
+                }
+            })
+            .collect()
+    }
+
+    /// Endpoint with the most free slots, the rotation deciding between equals
+    fn pick(&self) -> &Endpoint {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.endpoints.len())
+            .map(|offset| &self.endpoints[(start + offset) % self.endpoints.len()])
+            .max_by_key(|endpoint| endpoint.slots.available_permits())
+            .expect("a pool has at least one endpoint")
+    }
+
+    /// A slot on the least busy endpoint, waiting at most the queue timeout
+    async fn acquire(&self, method: &str) -> Result<(&Endpoint, SemaphorePermit<'_>), PoolError> {
+        let endpoint = self.pick();
+        endpoint.counters.queued.fetch_add(1, Ordering::Relaxed);
+        let slot = tokio::time::timeout(self.config.queue_timeout, endpoint.slots.acquire()).await;
+        endpoint.counters.queued.fetch_sub(1, Ordering::Relaxed);
+        match slot {
+            Ok(Ok(slot)) => Ok((endpoint, slot)),
+            _ => {
+                endpoint.counters.rejected.fetch_add(1, Ordering::Relaxed);
+                debug!("{} rejected, {} saturated", method, endpoint.name);
+                Err(PoolError::Saturated { endpoint: endpoint.name.clone(), waited: self.config.queue_timeout })
+            }
+        }
+    }
+
+    fn record(&self, endpoint: &Endpoint, started: Instant, failed: bool) {
+        endpoint.counters.requests.fetch_add(1, Ordering::Relaxed);
+        endpoint.counters.latency_ms.fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        if failed {
+            endpoint.counters.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Send `calls` in as few round trips as the endpoints accept, results in the order of the
+    /// calls. A batch an endpoint rejects is split in halves, down to single requests, and the
+    /// endpoint's batches stay that small afterwards.
+    pub async fn batch(&self, calls: &[(&'static str, Value)]) -> Result<Vec<BatchResult>, PoolError> {
+        let mut results: Vec<Option<BatchResult>> = vec![None; calls.len()];
+        let mut pending: VecDeque<Range<usize>> = VecDeque::from([0..calls.len()]);
+        while let Some(range) = pending.pop_front() {
+            if range.is_empty() {
+                continue;
+            }
+            if range.len() == 1 {
+                let (method, params) = &calls[range.start];
+                let params = if params.is_null() { json!([]) } else { params.clone() };
+                results[range.start] = Some(match self.request::<_, Value>(method, params).await {
+                    Ok(result) => Ok(result),
+                    Err(PoolError::Http(HttpClientError::JsonRpcError(e))) => Err(e),
+                    Err(e) => return Err(e),
+                });
+                continue;
+            }
+
+            let (endpoint, _slot) = self.acquire("batch").await?;
+            let limit = endpoint.batch_limit.load(Ordering::Relaxed).min(self.config.max_batch_size);
+            let range = if range.len() > limit {
+                pending.push_front(range.start + limit..range.end);
+                range.start..range.start + limit
+            } else {
+                range
+            };
+
+            match self.send_batch(endpoint, calls, range.clone()).await? {
+                Some(responses) => {
+                    for (index, result) in responses {
+                        results[index] = Some(result);
+                    }
+                }
+                None => {
+                    let half = range.len() / 2;
+                    endpoint.batch_limit.fetch_min(half.max(1), Ordering::Relaxed);
+                    warn!("{} rejected a batch of {} calls, splitting to {}", endpoint.name, range.len(), half);
+                    pending.push_front(range.start + half..range.end);
+                    pending.push_front(range.start..range.start + half);
+                }
+            }
+        }
+
+        Ok(results.into_iter().map(|result| result.unwrap_or(Ok(Value::Null))).collect())
+    }
+
+    /// One round trip with the calls of `range`, ids being their indexes; `None` when the endpoint
+    /// refused the batch or answered only part of it
+    async fn send_batch(
+        &self,
+        endpoint: &Endpoint,
+        calls: &[(&'static str, Value)],
+        range: Range<usize>,
+    ) -> Result<Option<Vec<(usize, BatchResult)>>, PoolError> {
+        let body: Vec<Value> = range.clone()
+            .map(|index| {
+                let (method, params) = &calls[index];
+                let params = if params.is_null() { json!([]) } else { params.clone() };
+                json!({ "jsonrpc": "2.0", "id": index, "method": method, "params": params })
+            })
+            .collect();
+        let failed = |reason: String| PoolError::Batch { endpoint: endpoint.name.clone(), reason };
+
+        let started = Instant::now();
+        let response = self.client.post(endpoint.url.clone()).json(&body).send().await;
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.record(endpoint, started, true);
+                return Err(failed(e.to_string()));
+            }
+        };
+        let status = response.status();
+        let text = response.text().await;
+        self.record(endpoint, started, text.is_err() || status.is_server_error());
+        let text = text.map_err(|e| failed(e.to_string()))?;
+
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            return Err(failed(format!("HTTP {}", status)));
+        }
+        // Too large batches come back as a client error or a single error object
+        let Ok(responses) = serde_json::from_str::<Vec<BatchResponse>>(&text) else {
+            debug!("{} refused a batch of {}: HTTP {} {}", endpoint.name, range.len(), status, text);
+            return Ok(None);
+        };
+        let mut responses: HashMap<usize, BatchResult> = responses.into_iter()
+            .filter(|response| range.contains(&response.id))
+            .map(|response| (response.id, response.error.map_or(Ok(response.result), Err)))
+            .collect();
+        if responses.len() < range.len() {
+            return Ok(None);
+        }
+        Ok(Some(range.map(|index| (index, responses.remove(&index).unwrap_or(Ok(Value::Null)))).collect()))
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for PooledHttp {
+    type Error = PoolError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, PoolError>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let (endpoint, _slot) = self.acquire(method).await?;
+
+        let started = Instant::now();
+        let result = endpoint.http.request(method, params).await;
+        self.record(endpoint, started, result.as_ref().is_err_and(|e| e.as_error_response().is_none()));
+        result.map_err(PoolError::Http)
+    }
+}
+
+fn endpoint_name(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default()),
+        Err(_) => "invalid".to_string(),
+    }
+}
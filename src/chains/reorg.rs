@@ -0,0 +1,320 @@
+// Reorganization detection over the recent block hashes of every chain, fanned out to the
+// subsystems holding data read from orphaned blocks
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::{
+    providers::{Middleware, Provider},
+    types::H256,
+};
+use futures::FutureExt;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::chains::pool::PooledHttp;
+use crate::chains::ws::{self, WsSubscription};
+use crate::chains::ChainManager;
+use crate::shutdown::ShutdownSignal;
+
+/// Reorganizations kept for the API
+const MAX_HISTORY: usize = 100;
+const REORG_EVENTS_CAPACITY: usize = 64;
+
+/// A reorganization replacing the blocks above `fork_block`
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainReorg {
+    pub chain_id: u64,
+    /// Newest block both branches share, data of later blocks must be re-read
+    pub fork_block: u64,
+    /// Blocks of the old branch that were replaced
+    pub depth: u64,
+    pub old_head: H256,
+    pub new_head_number: u64,
+    /// `true` when the fork is older than every tracked block, so `fork_block` is only a lower bound
+    pub beyond_tracked: bool,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// Holder of chain-derived data, e.g. an event index or transaction history
+#[async_trait]
+pub trait ReorgHandler: Send + Sync {
+    /// Name reported in logs
+    fn name(&self) -> &'static str;
+
+    /// Drop or re-validate whatever was read from blocks after `reorg.fork_block`
+    async fn handle_reorg(&self, reorg: &ChainReorg) -> Result<()>;
+}
+
+/// Tracking state of a chain
+#[derive(Debug, Clone, Serialize)]
+pub struct ReorgChainStatus {
+    pub chain_id: u64,
+    pub tracked_blocks: usize,
+    /// `None` until the chain is polled successfully
+    pub head: Option<u64>,
+    pub reorgs: u64,
+    pub deepest_reorg: u64,
+    pub last_reorg: Option<DateTime<Utc>>,
+}
+
+/// Reorg monitor configuration
+#[derive(Debug, Clone)]
+pub struct ReorgConfig {
+    pub poll_interval: Duration,
+    /// Recent block hashes kept per chain, the deepest reorganization located exactly
+    pub tracked_blocks: usize,
+}
+
+impl Default for ReorgConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(12),
+            tracked_blocks: 64,
+        }
+    }
+}
+
+impl ReorgConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut reorg_config = Self::default();
+
+        if let Ok(secs) = config.get_int("reorg_poll_interval_secs") {
+            reorg_config.poll_interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(blocks) = config.get_int("reorg_tracked_blocks") {
+            reorg_config.tracked_blocks = blocks.max(2) as usize;
+        }
+
+        reorg_config
+    }
+}
+
+#[derive(Default)]
+struct ChainBlocks {
+    /// Hashes of the recent canonical blocks by number
+    hashes: BTreeMap<u64, H256>,
+    reorgs: u64,
+    deepest_reorg: u64,
+    last_reorg: Option<DateTime<Utc>>,
+}
+
+/// Polls the heads of the configured chains, compares them with the block hashes seen before and
+/// hands every reorganization to the registered handlers
+pub struct ReorgMonitor {
+    chain_manager: Arc<ChainManager>,
+    config: ReorgConfig,
+    handlers: Vec<Arc<dyn ReorgHandler>>,
+    chains: RwLock<HashMap<u64, ChainBlocks>>,
+    history: RwLock<VecDeque<ChainReorg>>,
+    events: broadcast::Sender<ChainReorg>,
+}
+
+impl ReorgMonitor {
+    pub fn new(chain_manager: Arc<ChainManager>, handlers: Vec<Arc<dyn ReorgHandler>>, config: ReorgConfig) -> Self {
+        let (events, _) = broadcast::channel(REORG_EVENTS_CAPACITY);
+        Self {
+            chain_manager,
+            config,
+            handlers,
+            chains: RwLock::new(HashMap::new()),
+            history: RwLock::new(VecDeque::new()),
+            events,
+        }
+    }
+
+    pub fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        handlers: Vec<Arc<dyn ReorgHandler>>,
+    ) -> Self {
+        Self::new(chain_manager, handlers, ReorgConfig::from_config(config))
+    }
+
+    /// Poll every chain in the background until shutdown, and a chain with a WebSocket as soon as a
+    /// new head arrives
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let (wake, mut heads) = mpsc::unbounded_channel();
+            let wakers = ws::spawn_wakers(
+                self.chain_manager.clone(),
+                |_| async { Some(WsSubscription::NewHeads) }.boxed(),
+                None,
+                wake,
+                shutdown.clone(),
+            );
+            let mut ticker = tokio::time::interval(self.config.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.poll_all().await,
+                    Some(chain_id) = heads.recv() => self.poll(chain_id).await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            let _ = wakers.await;
+            info!("Reorg monitor stopped");
+        })
+    }
+
+    /// Reorganizations as they are detected, after the handlers ran
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainReorg> {
+        self.events.subscribe()
+    }
+
+    /// Recent reorganizations, newest first
+    pub async fn history(&self) -> Vec<ChainReorg> {
+        self.history.read().await.iter().rev().cloned().collect()
+    }
+
+    pub async fn status(&self) -> Vec<ReorgChainStatus> {
+        let chains = self.chains.read().await;
+        let mut statuses: Vec<ReorgChainStatus> = chains.iter()
+            .map(|(chain_id, blocks)| ReorgChainStatus {
+                chain_id: *chain_id,
+                tracked_blocks: blocks.hashes.len(),
+                head: blocks.hashes.keys().next_back().copied(),
+                reorgs: blocks.reorgs,
+                deepest_reorg: blocks.deepest_reorg,
+                last_reorg: blocks.last_reorg,
+            })
+            .collect();
+        statuses.sort_by_key(|status| status.chain_id);
+        statuses
+    }
+
+    async fn poll_all(&self) {
+        for chain_id in self.chain_manager.chain_ids().await {
+            self.poll(chain_id).await;
+        }
+    }
+
+    async fn poll(&self, chain_id: u64) {
+        if self.chain_manager.get_paused_chains().await.contains(&chain_id) {
+            return;
+        }
+        match self.poll_chain(chain_id).await {
+            Ok(Some(reorg)) => self.dispatch(reorg).await,
+            Ok(None) => {}
+            Err(e) => warn!("Reorg check of chain {} failed: {}", chain_id, e),
+        }
+    }
+
+    /// Compare the tracked hashes with the canonical chain and record the new blocks
+    async fn poll_chain(&self, chain_id: u64) -> Result<Option<ChainReorg>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = &chain.provider;
+        let latest = provider.get_block_number().await?.as_u64();
+
+        let tracked = self.chains.read().await
+            .get(&chain_id)
+            .map(|blocks| blocks.hashes.clone())
+            .unwrap_or_default();
+        let reorg = match tracked.iter().next_back() {
+            Some((head, old_head)) => self.find_fork(chain_id, provider, &tracked, latest)
+                .await?
+                .map(|(fork_block, beyond_tracked)| ChainReorg {
+                    chain_id,
+                    fork_block,
+                    depth: head - fork_block,
+                    old_head: *old_head,
+                    new_head_number: latest,
+                    beyond_tracked,
+                    detected_at: Utc::now(),
+                }),
+            None => None,
+        };
+
+        // Blocks after the newest one still canonical, at most a window's worth
+        let resume = match &reorg {
+            Some(reorg) => reorg.fork_block + 1,
+            None => tracked.keys().next_back().map_or(latest, |head| head + 1),
+        };
+        let from = resume.max(latest.saturating_sub(self.config.tracked_blocks as u64 - 1));
+        let mut fetched = Vec::new();
+        for number in from..=latest {
+            let block = provider.get_block(number).await?
+                .ok_or_else(|| anyhow!("Block {} of chain {} is not available", number, chain_id))?;
+            let hash = block.hash.ok_or_else(|| anyhow!("Block {} of chain {} has no hash", number, chain_id))?;
+            // A block replaced while the range was read is caught by the next poll
+            if fetched.last().is_some_and(|(_, parent)| *parent != block.parent_hash) {
+                debug!("Chain {} changed while block {} was read", chain_id, number);
+                break;
+            }
+            fetched.push((number, hash));
+        }
+
+        let mut chains = self.chains.write().await;
+        let blocks = chains.entry(chain_id).or_default();
+        if let Some(reorg) = &reorg {
+            blocks.hashes.retain(|number, _| *number <= reorg.fork_block);
+            blocks.reorgs += 1;
+            blocks.deepest_reorg = blocks.deepest_reorg.max(reorg.depth);
+            blocks.last_reorg = Some(reorg.detected_at);
+        }
+        blocks.hashes.extend(fetched);
+        while blocks.hashes.len() > self.config.tracked_blocks {
+            blocks.hashes.pop_first();
+        }
+        Ok(reorg)
+    }
+
+    /// Newest tracked block still canonical when a later one was replaced, and whether the fork is
+    /// deeper than every tracked block; `None` when the tracked head is intact
+    async fn find_fork(
+        &self,
+        chain_id: u64,
+        provider: &Provider<PooledHttp>,
+        tracked: &BTreeMap<u64, H256>,
+        latest: u64,
+    ) -> Result<Option<(u64, bool)>> {
+        let mut reorganized = false;
+        for (number, hash) in tracked.iter().rev() {
+            // A shorter new branch orphans the tracked blocks above its head
+            if *number > latest {
+                reorganized = true;
+                continue;
+            }
+            let canonical = provider.get_block(*number).await?.and_then(|block| block.hash);
+            if canonical == Some(*hash) {
+                return Ok(reorganized.then_some((*number, false)));
+            }
+            if canonical.is_none() {
+                return Err(anyhow!("Block {} of chain {} is not available", number, chain_id));
+            }
+            reorganized = true;
+        }
+        Ok(tracked.keys().next().map(|oldest| (oldest.saturating_sub(1), true)))
+    }
+
+    /// Run every handler on the reorganization, then publish it
+    async fn dispatch(&self, reorg: ChainReorg) {
+        warn!(
+            "Chain {} reorganized {} blocks deep above block {}{}",
+            reorg.chain_id,
+            reorg.depth,
+            reorg.fork_block,
+            if reorg.beyond_tracked { " (deeper than the tracked blocks)" } else { "" },
+        );
+        for handler in &self.handlers {
+            match handler.handle_reorg(&reorg).await {
+                Ok(()) => debug!("{} handled the reorg of chain {}", handler.name(), reorg.chain_id),
+                Err(e) => warn!("{} failed to handle the reorg of chain {}: {}", handler.name(), reorg.chain_id, e),
+            }
+        }
+
+        let mut history = self.history.write().await;
+        history.push_back(reorg.clone());
+        if history.len() > MAX_HISTORY {
+            history.pop_front();
+        }
+        drop(history);
+        // No subscribers is fine
+        let _ = self.events.send(reorg);
+    }
+}
@@ -0,0 +1,326 @@
+// Pre-signing transaction simulation
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::{Middleware, Provider, ProviderError, RawCall, RpcError},
+    types::{
+        spoof, transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, Bytes,
+        CallFrame, DiffMode, TransactionRequest, H256, I256, U256,
+    },
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use super::pool::PooledHttp;
+use super::ChainManager;
+
+/// `Error(string)` selector
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// `Panic(uint256)` selector
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Transaction to simulate before signing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationRequest {
+    pub chain_id: u64,
+    pub transaction: TransactionRequest,
+    /// Block to simulate against, latest when unset
+    pub block_number: Option<u64>,
+    /// Geth-style state override set applied to the call
+    pub state_overrides: Option<spoof::State>,
+    /// Run debug_traceCall for events and balance diffs; needs a node with the debug namespace
+    #[serde(default = "default_trace")]
+    pub trace: bool,
+}
+
+fn default_trace() -> bool {
+    true
+}
+
+/// Change of an account's native or token balance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceDiff {
+    pub account: Address,
+    /// `None` for the native asset
+    pub token: Option<Address>,
+    pub before: Option<U256>,
+    pub after: Option<U256>,
+    pub delta: I256,
+}
+
+/// Log emitted during simulation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulatedEvent {
+    pub address: Address,
+    /// Event signature when the topic is a well-known event
+    pub name: Option<String>,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+}
+
+/// Outcome of a simulated transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub success: bool,
+    pub return_data: Bytes,
+    pub revert_reason: Option<String>,
+    pub gas_used: Option<U256>,
+    pub balance_diffs: Vec<BalanceDiff>,
+    pub events: Vec<SimulatedEvent>,
+    /// False when the node does not support debug_traceCall; events and diffs are then empty
+    pub traced: bool,
+    pub trace_error: Option<String>,
+}
+
+/// Executes candidate transactions against chain state without broadcasting them
+pub struct TransactionSimulator {
+    chain_manager: Arc<ChainManager>,
+}
+
+impl TransactionSimulator {
+    pub fn new(chain_manager: Arc<ChainManager>) -> Self {
+        Self { chain_manager }
+    }
+
+    pub async fn simulate(&self, request: &SimulationRequest) -> Result<SimulationResult> {
+        let chain = self.chain_manager.get_provider(request.chain_id).await?;
+        let provider = &chain.provider;
+
+        let block_number = match request.block_number {
+            Some(number) => number,
+            None => provider.get_block_number().await?.as_u64(),
+        };
+        let block = BlockId::Number(BlockNumber::Number(block_number.into()));
+        let tx: TypedTransaction = request.transaction.clone().chain_id(request.chain_id).into();
+        info!("Simulating transaction to {:?} on chain {} at block {}", tx.to(), request.chain_id, block_number);
+
+        let overrides = request.state_overrides.clone().unwrap_or_default();
+        let call = provider.call_raw(&tx).block(block).state(&overrides).await;
+
+        let (success, return_data, revert_reason) = match call {
+            Ok(output) => (true, output, None),
+            Err(e) => {
+                let revert_data = e.as_error_response().and_then(|response| response.as_revert_data());
+                match revert_data {
+                    Some(data) => {
+                        let reason = Self::decode_revert_reason(&data);
+                        (false, data, Some(reason))
+                    }
+                    // Not a revert, e.g. the RPC is unreachable
+                    None => return Err(e.into()),
+                }
+            }
+        };
+
+        // Gas estimation cannot take state overrides, only use it when none are set
+        let gas_used = if success && request.state_overrides.is_none() {
+            provider.estimate_gas(&tx, Some(block)).await.ok()
+        } else {
+            None
+        };
+
+        let mut result = SimulationResult {
+            chain_id: request.chain_id,
+            block_number,
+            success,
+            return_data,
+            revert_reason,
+            gas_used,
+            balance_diffs: Vec::new(),
+            events: Vec::new(),
+            traced: false,
+            trace_error: None,
+        };
+
+        if request.trace {
+            match self.trace(provider, &tx, block, request.state_overrides.as_ref()).await {
+                Ok((call_frame, diff)) => {
+                    if result.gas_used.is_none() {
+                        result.gas_used = Some(call_frame.gas_used);
+                    }
+                    result.events = Self::collect_events(&call_frame);
+                    result.balance_diffs = Self::native_diffs(&diff);
+                    result.balance_diffs.extend(Self::token_diffs(&result.events));
+                    result.traced = true;
+                }
+                Err(e) => {
+                    debug!("debug_traceCall unavailable on chain {}: {}", request.chain_id, e);
+                    result.trace_error = Some(e.to_string());
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn trace(
+        &self,
+        provider: &Provider<PooledHttp>,
+        tx: &TypedTransaction,
+        block: BlockId,
+        overrides: Option<&spoof::State>,
+    ) -> Result<(CallFrame, DiffMode)> {
+        let call_options = json!({
+            "tracer": "callTracer",
+            "tracerConfig": { "withLog": true },
+            "stateOverrides": overrides,
+        });
+        let prestate_options = json!({
+            "tracer": "prestateTracer",
+            "tracerConfig": { "diffMode": true },
+            "stateOverrides": overrides,
+        });
+
+        let (call_frame, diff) = tokio::try_join!(
+            provider.request::<_, CallFrame>("debug_traceCall", (tx, block, call_options)),
+            provider.request::<_, DiffMode>("debug_traceCall", (tx, block, prestate_options)),
+        ).map_err(|e: ProviderError| anyhow!("debug_traceCall failed: {}", e))?;
+
+        Ok((call_frame, diff))
+    }
+
+    /// Decode `Error(string)`, `Panic(uint256)` or report the custom error selector
+    pub fn decode_revert_reason(data: &[u8]) -> String {
+        if data.len() < 4 {
+            return "execution reverted".to_string();
+        }
+
+        let (selector, payload) = data.split_at(4);
+        if selector == ERROR_SELECTOR {
+            if let Ok(tokens) = abi::decode(&[ParamType::String], payload) {
+                if let Some(Token::String(reason)) = tokens.into_iter().next() {
+                    return reason;
+                }
+            }
+        } else if selector == PANIC_SELECTOR {
+            if let Ok(tokens) = abi::decode(&[ParamType::Uint(256)], payload) {
+                if let Some(Token::Uint(code)) = tokens.into_iter().next() {
+                    return format!("panic: {}", Self::panic_description(code.low_u64()));
+                }
+            }
+        }
+
+        format!("custom error 0x{}", hex_encode(selector))
+    }
+
+    fn panic_description(code: u64) -> &'static str {
+        match code {
+            0x01 => "assertion failed",
+            0x11 => "arithmetic overflow or underflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "invalid enum value",
+            0x22 => "invalid storage byte array",
+            0x31 => "pop on empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "out of memory",
+            0x51 => "call to uninitialized function",
+            _ => "unknown panic code",
+        }
+    }
+
+    /// Flatten logs of the call tree in execution order
+    fn collect_events(frame: &CallFrame) -> Vec<SimulatedEvent> {
+        let mut events = Vec::new();
+        // A reverted frame's logs are discarded by the EVM
+        if frame.error.is_some() {
+            return events;
+        }
+
+        for log in frame.logs.iter().flatten() {
+            let topics = log.topics.clone().unwrap_or_default();
+            events.push(SimulatedEvent {
+                address: log.address.unwrap_or_default(),
+                name: topics.first().and_then(|topic| known_event(*topic)).map(str::to_string),
+                topics,
+                data: log.data.clone().unwrap_or_default(),
+            });
+        }
+        for call in frame.calls.iter().flatten() {
+            events.extend(Self::collect_events(call));
+        }
+
+        events
+    }
+
+    fn native_diffs(diff: &DiffMode) -> Vec<BalanceDiff> {
+        let mut accounts: Vec<&Address> = diff.pre.keys().chain(diff.post.keys()).collect();
+        accounts.sort();
+        accounts.dedup();
+
+        accounts.into_iter()
+            .filter_map(|account| {
+                let before = diff.pre.get(account).and_then(|state| state.balance);
+                // diffMode omits unchanged fields from `post`
+                let after = diff.post.get(account).and_then(|state| state.balance).or(before);
+                let delta = signed(after.unwrap_or_default()) - signed(before.unwrap_or_default());
+                (!delta.is_zero()).then_some(BalanceDiff {
+                    account: *account,
+                    token: None,
+                    before,
+                    after,
+                    delta,
+                })
+            })
+            .collect()
+    }
+
+    /// Net ERC-20 movements per account from `Transfer` events
+    fn token_diffs(events: &[SimulatedEvent]) -> Vec<BalanceDiff> {
+        let transfer = H256::from(keccak256("Transfer(address,address,uint256)"));
+        let mut deltas: BTreeMap<(Address, Address), I256> = BTreeMap::new();
+
+        for event in events {
+            // ERC-721 transfers index the token id and carry no data
+            if event.topics.len() != 3 || event.topics[0] != transfer || event.data.len() != 32 {
+                continue;
+            }
+            let from = Address::from(event.topics[1]);
+            let to = Address::from(event.topics[2]);
+            let amount = signed(U256::from_big_endian(&event.data));
+
+            *deltas.entry((from, event.address)).or_insert_with(I256::zero) -= amount;
+            *deltas.entry((to, event.address)).or_insert_with(I256::zero) += amount;
+        }
+
+        deltas.into_iter()
+            .filter(|(_, delta)| !delta.is_zero())
+            .map(|((account, token), delta)| BalanceDiff {
+                account,
+                token: Some(token),
+                before: None,
+                after: None,
+                delta,
+            })
+            .collect()
+    }
+}
+
+fn signed(value: U256) -> I256 {
+    I256::from_raw(value)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Signature of common events by topic0
+fn known_event(topic: H256) -> Option<&'static str> {
+    const EVENTS: [&str; 8] = [
+        "Transfer(address,address,uint256)",
+        "Approval(address,address,uint256)",
+        "Deposit(address,uint256)",
+        "Withdrawal(address,uint256)",
+        "Swap(address,uint256,uint256,uint256,uint256,address)",
+        "Swap(address,address,int256,int256,uint160,uint128,int24)",
+        "Sync(uint112,uint112)",
+        "Supply(address,address,address,uint256,uint16)",
+    ];
+
+    EVENTS.into_iter().find(|signature| H256::from(keccak256(signature)) == topic)
+}
@@ -0,0 +1,500 @@
+// Token metadata from standard token lists, custom additions and contract reads, with cached logos
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::parse_abi,
+    contract::Contract,
+    providers::Middleware,
+    types::Address,
+};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::assets::AssetRegistry;
+use super::ChainManager;
+use crate::cache::{CacheManager, TimedCache};
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store};
+
+/// Lists loaded unless `token_list_urls` is set, Uniswap's default list
+const DEFAULT_LIST_URLS: &str = "https://tokens.uniswap.org";
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+/// Store used when `custom_tokens_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/custom_tokens.json";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Logos are immutable in practice, a day keeps them fresh enough
+const LOGO_TTL: Duration = Duration::from_secs(24 * 3600);
+const MAX_CACHED_LOGOS: usize = 500;
+/// Largest logo served, larger images are rejected rather than proxied
+const MAX_LOGO_BYTES: usize = 512 * 1024;
+const IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+
+/// Where a token's metadata came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenSource {
+    /// Read from the token contract because no list has it
+    Onchain,
+    /// Token of the built-in asset registry
+    Builtin,
+    List,
+    /// Added through the admin API
+    Custom,
+}
+
+/// Symbol, decimals and logo of a token on one chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub chain_id: u64,
+    pub address: Address,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    pub logo_uri: Option<String>,
+    pub source: TokenSource,
+    /// Name of the token list it was loaded from
+    #[serde(default)]
+    pub list: Option<String>,
+}
+
+/// Token to add to the registry; fields left out are read from the contract
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomTokenRequest {
+    pub chain_id: u64,
+    pub address: Address,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub decimals: Option<u8>,
+    pub logo_uri: Option<String>,
+}
+
+/// Outcome of the last load of a configured token list
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenListStatus {
+    pub url: String,
+    pub name: Option<String>,
+    pub tokens: usize,
+    pub loaded_at: Option<DateTime<Utc>>,
+    /// Error of the last attempt; the tokens loaded before it are kept
+    pub error: Option<String>,
+}
+
+/// Logo image of a token
+#[derive(Debug, Clone)]
+pub struct TokenLogo {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Token list in the Uniswap token list format
+#[derive(Deserialize)]
+struct TokenList {
+    name: String,
+    /// Entries are parsed one by one so a malformed token does not discard the list
+    tokens: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListedToken {
+    chain_id: u64,
+    address: Address,
+    name: String,
+    symbol: String,
+    decimals: u8,
+    #[serde(rename = "logoURI", default)]
+    logo_uri: Option<String>,
+}
+
+/// Tokens of one configured list as last loaded
+struct LoadedList {
+    status: TokenListStatus,
+    tokens: Vec<TokenMetadata>,
+}
+
+/// An address without token metadata and without an ERC-20 contract behind it
+#[derive(Debug, Clone)]
+pub struct UnknownToken {
+    pub chain_id: u64,
+    pub address: Address,
+}
+
+impl fmt::Display for UnknownToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not a known token on chain {}", self.address, self.chain_id)
+    }
+}
+
+impl std::error::Error for UnknownToken {}
+
+/// Registry of token metadata: built-in tokens overlaid by the configured token lists and custom
+/// additions, with unlisted tokens read from their contracts on first use
+pub struct TokenRegistry {
+    chain_manager: Arc<ChainManager>,
+    list_urls: Vec<String>,
+    refresh_interval: Duration,
+    http: reqwest::Client,
+    /// JSON file holding the custom tokens, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    builtin: HashMap<(u64, Address), TokenMetadata>,
+    lists: RwLock<Vec<LoadedList>>,
+    /// Built-in and listed tokens, earlier lists taking precedence
+    listed: RwLock<HashMap<(u64, Address), TokenMetadata>>,
+    custom: RwLock<HashMap<(u64, Address), TokenMetadata>>,
+    discovered: RwLock<HashMap<(u64, Address), TokenMetadata>>,
+    logos: TimedCache<(u64, Address), Arc<TokenLogo>>,
+}
+
+impl TokenRegistry {
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        assets: &AssetRegistry,
+        list_urls: Vec<String>,
+        refresh_interval: Duration,
+        store_path: Option<PathBuf>,
+        caches: &CacheManager,
+    ) -> Result<Self> {
+        let builtin: HashMap<(u64, Address), TokenMetadata> = assets.assets().iter()
+            .flat_map(|asset| &asset.representations)
+            .map(|representation| {
+                let metadata = TokenMetadata {
+                    chain_id: representation.chain_id,
+                    address: representation.address,
+                    symbol: representation.symbol.clone(),
+                    name: representation.symbol.clone(),
+                    decimals: representation.decimals,
+                    logo_uri: None,
+                    source: TokenSource::Builtin,
+                    list: None,
+                };
+                ((representation.chain_id, representation.address), metadata)
+            })
+            .collect();
+        let custom: Vec<TokenMetadata> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        info!("Initializing TokenRegistry with {} custom tokens and {} token lists", custom.len(), list_urls.len());
+
+        Ok(Self {
+            chain_manager,
+            list_urls,
+            refresh_interval,
+            http: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            store_path,
+            listed: RwLock::new(builtin.clone()),
+            builtin,
+            lists: RwLock::new(Vec::new()),
+            custom: RwLock::new(custom.into_iter().map(|token| ((token.chain_id, token.address), token)).collect()),
+            discovered: RwLock::new(HashMap::new()),
+            logos: caches.timed("token_logos", LOGO_TTL, MAX_CACHED_LOGOS),
+        })
+    }
+
+    /// Registry loading the comma-separated `token_list_urls` (URLs or file paths) every
+    /// `token_list_refresh_interval_secs`, persisting custom tokens to `custom_tokens_store_path`
+    pub async fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        assets: &AssetRegistry,
+        caches: &CacheManager,
+    ) -> Result<Self> {
+        let list_urls = config
+            .get_string("token_list_urls")
+            .unwrap_or_else(|_| DEFAULT_LIST_URLS.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+        let refresh_interval = config
+            .get_int("token_list_refresh_interval_secs")
+            .map(|secs| Duration::from_secs(secs.max(60) as u64))
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL);
+        let path = config
+            .get_string("custom_tokens_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(chain_manager, assets, list_urls, refresh_interval, store_path, caches).await
+    }
+
+    /// Load the token lists now and again every refresh interval until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.refresh_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.refresh_lists().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Token list refresher stopped");
+        })
+    }
+
+    /// Reload every configured list, keeping the previous tokens of a list that fails to load
+    pub async fn refresh_lists(&self) {
+        if self.list_urls.is_empty() {
+            return;
+        }
+        let results = join_all(self.list_urls.iter().map(|url| self.fetch_list(url))).await;
+
+        let mut lists = self.lists.write().await;
+        for (url, result) in self.list_urls.iter().zip(results) {
+            let index = match lists.iter().position(|list| &list.status.url == url) {
+                Some(index) => index,
+                None => {
+                    lists.push(LoadedList {
+                        status: TokenListStatus { url: url.clone(), name: None, tokens: 0, loaded_at: None, error: None },
+                        tokens: Vec::new(),
+                    });
+                    lists.len() - 1
+                }
+            };
+            let list = &mut lists[index];
+            match result {
+                Ok((name, tokens)) => {
+                    info!("Loaded {} tokens from token list {}", tokens.len(), name);
+                    list.status = TokenListStatus {
+                        url: url.clone(),
+                        name: Some(name),
+                        tokens: tokens.len(),
+                        loaded_at: Some(Utc::now()),
+                        error: None,
+                    };
+                    list.tokens = tokens;
+                }
+                Err(e) => {
+                    warn!("Failed to load token list {}: {}", url, e);
+                    list.status.error = Some(e.to_string());
+                }
+            }
+        }
+
+        let mut listed = self.builtin.clone();
+        for list in lists.iter().rev() {
+            listed.extend(list.tokens.iter().map(|token| ((token.chain_id, token.address), token.clone())));
+        }
+        *self.listed.write().await = listed;
+    }
+
+    async fn fetch_list(&self, url: &str) -> Result<(String, Vec<TokenMetadata>)> {
+        let list: TokenList = if url.starts_with("http://") || url.starts_with("https://") {
+            self.http.get(url).send().await?.error_for_status()?.json().await?
+        } else {
+            serde_json::from_slice(&tokio::fs::read(url).await?)?
+        };
+
+        let total = list.tokens.len();
+        let tokens: Vec<TokenMetadata> = list.tokens.into_iter()
+            .filter_map(|token| serde_json::from_value::<ListedToken>(token).ok())
+            .map(|token| TokenMetadata {
+                chain_id: token.chain_id,
+                address: token.address,
+                symbol: token.symbol,
+                name: token.name,
+                decimals: token.decimals,
+                logo_uri: token.logo_uri,
+                source: TokenSource::List,
+                list: Some(list.name.clone()),
+            })
+            .collect();
+        if tokens.len() < total {
+            debug!("Skipped {} malformed tokens of token list {}", total - tokens.len(), list.name);
+        }
+        Ok((list.name, tokens))
+    }
+
+    pub async fn list_statuses(&self) -> Vec<TokenListStatus> {
+        self.lists.read().await.iter().map(|list| list.status.clone()).collect()
+    }
+
+    /// Metadata of a token the registry knows, without reading the chain
+    pub async fn get(&self, chain_id: u64, address: Address) -> Option<TokenMetadata> {
+        let key = (chain_id, address);
+        if let Some(token) = self.custom.read().await.get(&key) {
+            return Some(token.clone());
+        }
+        if let Some(token) = self.listed.read().await.get(&key) {
+            return Some(token.clone());
+        }
+        self.discovered.read().await.get(&key).cloned()
+    }
+
+    /// Symbol of a token, its address when the registry does not know it
+    pub async fn symbol(&self, chain_id: u64, address: Address) -> String {
+        self.get(chain_id, address).await
+            .map(|token| token.symbol)
+            .unwrap_or_else(|| format!("{:?}", address))
+    }
+
+    /// Metadata of a token, read from its contract when no list has it; fails with `UnknownToken`
+    /// for an address without an ERC-20 contract
+    pub async fn validate(&self, chain_id: u64, address: Address) -> Result<TokenMetadata> {
+        if let Some(token) = self.get(chain_id, address).await {
+            return Ok(token);
+        }
+        let token = self.read_contract(chain_id, address, None).await?;
+        self.discovered.write().await.insert((chain_id, address), token.clone());
+        Ok(token)
+    }
+
+    /// Known tokens, on `chain_id` when set and whose symbol or name contains `search` when set,
+    /// sorted by chain and symbol
+    pub async fn list(&self, chain_id: Option<u64>, search: Option<&str>) -> Vec<TokenMetadata> {
+        let mut tokens = self.listed.read().await.clone();
+        tokens.extend(self.custom.read().await.iter().map(|(key, token)| (*key, token.clone())));
+
+        let search = search.map(str::to_lowercase);
+        let mut tokens: Vec<TokenMetadata> = tokens.into_values()
+            .filter(|token| chain_id.is_none_or(|chain_id| token.chain_id == chain_id))
+            .filter(|token| search.as_deref().is_none_or(|search| {
+                token.symbol.to_lowercase().contains(search) || token.name.to_lowercase().contains(search)
+            }))
+            .collect();
+        tokens.sort_by(|a, b| (a.chain_id, a.symbol.to_lowercase()).cmp(&(b.chain_id, b.symbol.to_lowercase())));
+        tokens
+    }
+
+    /// Add or replace a custom token, reading the fields the request leaves out from the contract
+    pub async fn add_custom(&self, request: CustomTokenRequest) -> Result<TokenMetadata> {
+        let mut token = match (&request.symbol, &request.name, request.decimals) {
+            (Some(symbol), Some(name), Some(decimals)) => TokenMetadata {
+                chain_id: request.chain_id,
+                address: request.address,
+                symbol: symbol.clone(),
+                name: name.clone(),
+                decimals,
+                logo_uri: None,
+                source: TokenSource::Custom,
+                list: None,
+            },
+            _ => self.read_contract(request.chain_id, request.address, request.decimals).await?,
+        };
+        token.symbol = request.symbol.unwrap_or(token.symbol);
+        token.name = request.name.unwrap_or(token.name);
+        token.decimals = request.decimals.unwrap_or(token.decimals);
+        token.logo_uri = request.logo_uri;
+        token.source = TokenSource::Custom;
+        if token.symbol.trim().is_empty() {
+            return Err(anyhow!("Token symbol cannot be empty"));
+        }
+
+        let mut custom = self.custom.write().await;
+        custom.insert((token.chain_id, token.address), token.clone());
+        self.persist(&custom).await;
+        self.logos.invalidate(&(token.chain_id, token.address)).await;
+        Ok(token)
+    }
+
+    /// Remove a custom token, `false` when there was none
+    pub async fn remove_custom(&self, chain_id: u64, address: Address) -> bool {
+        let mut custom = self.custom.write().await;
+        if custom.remove(&(chain_id, address)).is_none() {
+            return false;
+        }
+        self.persist(&custom).await;
+        self.logos.invalidate(&(chain_id, address)).await;
+        true
+    }
+
+    /// Logo of a token, fetched from its `logo_uri` once and served from the cache afterwards;
+    /// `None` when the token has no logo
+    pub async fn logo(&self, chain_id: u64, address: Address) -> Result<Option<Arc<TokenLogo>>> {
+        let Some(uri) = self.get(chain_id, address).await.and_then(|token| token.logo_uri) else {
+            return Ok(None);
+        };
+        let url = match uri.strip_prefix("ipfs://") {
+            Some(path) => format!("{}{}", IPFS_GATEWAY, path.trim_start_matches("ipfs/")),
+            None => uri.clone(),
+        };
+        let http = self.http.clone();
+        let logo = self.logos.get_or_load((chain_id, address), || async move {
+            let response = http.get(&url).send().await?.error_for_status()?;
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("image/png")
+                .to_string();
+            if !content_type.starts_with("image/") {
+                return Err(anyhow!("Logo {} is {} rather than an image", url, content_type));
+            }
+            let bytes = response.bytes().await?;
+            if bytes.len() > MAX_LOGO_BYTES {
+                return Err(anyhow!("Logo {} is {} bytes, more than {}", url, bytes.len(), MAX_LOGO_BYTES));
+            }
+            Ok(Arc::new(TokenLogo { content_type, bytes: bytes.to_vec() }))
+        }).await?;
+        Ok(Some(logo))
+    }
+
+    pub async fn clear_logo_cache(&self) {
+        self.logos.clear().await;
+    }
+
+    /// Symbol, name and decimals of an ERC-20 contract; `decimals` stands in for a contract
+    /// without `decimals()`
+    async fn read_contract(&self, chain_id: u64, address: Address, decimals: Option<u8>) -> Result<TokenMetadata> {
+        let unknown = || anyhow::Error::new(UnknownToken { chain_id, address });
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain.provider.clone());
+        if provider.get_code(address, None).await?.is_empty() {
+            return Err(unknown());
+        }
+
+        let erc20 = Contract::new(address, erc20_metadata_abi()?, provider);
+        let decimals = match (erc20.method::<_, u8>("decimals", ())?.call().await, decimals) {
+            (Ok(decimals), _) => decimals,
+            (Err(_), Some(decimals)) => decimals,
+            (Err(_), None) => return Err(unknown()),
+        };
+        let symbol = erc20.method::<_, String>("symbol", ())?.call().await.ok();
+        let name = erc20.method::<_, String>("name", ())?.call().await.ok();
+        let symbol = symbol.unwrap_or_else(|| format!("{:?}", address));
+        debug!("Read token {} {:?} on chain {} from its contract", symbol, address, chain_id);
+
+        Ok(TokenMetadata {
+            chain_id,
+            address,
+            name: name.unwrap_or_else(|| symbol.clone()),
+            symbol,
+            decimals,
+            logo_uri: None,
+            source: TokenSource::Onchain,
+            list: None,
+        })
+    }
+
+    /// Write the custom tokens, keeping the in-memory state on failure
+    async fn persist(&self, custom: &HashMap<(u64, Address), TokenMetadata>) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let mut tokens: Vec<&TokenMetadata> = custom.values().collect();
+        tokens.sort_by_key(|token| (token.chain_id, token.address));
+        if let Err(e) = write_store(path, &tokens).await {
+            warn!("Failed to persist custom tokens to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn erc20_metadata_abi() -> Result<ethers::abi::Abi> {
+    Ok(parse_abi(&[
+        "function symbol() view returns (string)",
+        "function name() view returns (string)",
+        "function decimals() view returns (uint8)",
+    ])?)
+}
@@ -0,0 +1,344 @@
+// Nonce tracking, submission and confirmation of signed transactions
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::{
+    providers::Middleware,
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes, H256, U256},
+    utils::rlp::Rlp,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, instrument, warn};
+
+use super::reorg::{ChainReorg, ReorgHandler};
+use super::ChainManager;
+use crate::security::SpendingPolicyEngine;
+use crate::transactions::TransactionTracker;
+
+/// Fee increase per replacement, nodes require at least 10% to accept a replacement
+const FEE_BUMP_PERCENT: u64 = 125;
+/// Replacements tried after an underpriced rejection before giving up
+const MAX_REPLACEMENTS: u32 = 5;
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BroadcastStatus {
+    Pending,
+    Confirmed,
+    /// Mined but reverted
+    Failed,
+    /// Superseded by a fee-bumped transaction with the same nonce
+    Replaced,
+    /// Another transaction with the same nonce was mined
+    Dropped,
+}
+
+/// A transaction submitted through the broadcaster
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedTransaction {
+    pub hash: H256,
+    pub chain_id: u64,
+    pub from: Address,
+    pub nonce: U256,
+    pub status: BroadcastStatus,
+    pub submitted_at: DateTime<Utc>,
+    pub block_number: Option<u64>,
+    pub confirmations: u64,
+    pub gas_used: Option<U256>,
+    pub replaced_by: Option<H256>,
+}
+
+impl TrackedTransaction {
+    fn new(hash: H256, chain_id: u64, from: Address, nonce: U256) -> Self {
+        Self {
+            hash,
+            chain_id,
+            from,
+            nonce,
+            status: BroadcastStatus::Pending,
+            submitted_at: Utc::now(),
+            block_number: None,
+            confirmations: 0,
+            gas_used: None,
+            replaced_by: None,
+        }
+    }
+}
+
+/// Sends signed transactions, keeping per-sender nonces in order
+pub struct TxBroadcaster {
+    chain_manager: Arc<ChainManager>,
+    /// Next nonce to hand out per (chain, sender)
+    nonces: Mutex<HashMap<(u64, Address), U256>>,
+    transactions: RwLock<HashMap<H256, TrackedTransaction>>,
+    /// Lifecycle history linking broadcasts to the requests they carry out
+    tracker: Arc<TransactionTracker>,
+    /// Counts the spending held for a transaction once it is broadcast, releases it when it is not
+    spending_policies: Option<Arc<SpendingPolicyEngine>>,
+}
+
+impl TxBroadcaster {
+    pub fn new(
+        chain_manager: Arc<ChainManager>,
+        tracker: Arc<TransactionTracker>,
+        spending_policies: Option<Arc<SpendingPolicyEngine>>,
+    ) -> Self {
+        Self {
+            chain_manager,
+            nonces: Mutex::new(HashMap::new()),
+            transactions: RwLock::new(HashMap::new()),
+            tracker,
+            spending_policies,
+        }
+    }
+
+    /// Reserve the next nonce for a sender, never going below the node's pending count
+    pub async fn next_nonce(&self, chain_id: u64, from: Address) -> Result<U256> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let mut nonces = self.nonces.lock().await;
+        let on_chain = chain.provider
+            .get_transaction_count(from, Some(BlockNumber::Pending.into()))
+            .await?;
+
+        let nonce = nonces.get(&(chain_id, from)).copied().unwrap_or_default().max(on_chain);
+        nonces.insert((chain_id, from), nonce + 1);
+        Ok(nonce)
+    }
+
+    /// Forget the local nonce so the next one is read from the node again
+    async fn resync_nonce(&self, chain_id: u64, from: Address) {
+        self.nonces.lock().await.remove(&(chain_id, from));
+    }
+
+    /// Submit an already signed transaction
+    #[instrument(skip_all, fields(chain_id = chain_id))]
+    pub async fn send_raw(&self, chain_id: u64, raw: Bytes) -> Result<TrackedTransaction> {
+        let (tx, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw))
+            .map_err(|e| anyhow!("Invalid signed transaction: {}", e))?;
+        let from = signature.recover(tx.sighash())?;
+        let nonce = tx.nonce().copied().ok_or_else(|| anyhow!("Signed transaction has no nonce"))?;
+
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let hash = match chain.provider.send_raw_transaction(raw).await {
+            Ok(pending) => pending.tx_hash(),
+            Err(e) => {
+                self.resync_nonce(chain_id, from).await;
+                return Err(anyhow!("Broadcast rejected: {}", e));
+            }
+        };
+
+        let tracked = self.track(TrackedTransaction::new(hash, chain_id, from, nonce)).await?;
+        self.tracker.record_broadcast(&tx, &tracked).await;
+        Ok(tracked)
+    }
+
+    /// Assign a nonce, sign and submit, bumping fees while the node rejects it as underpriced
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?signer.address()))]
+    pub async fn send_with_signer<S: Signer>(
+        &self,
+        chain_id: u64,
+        mut tx: TypedTransaction,
+        signer: &S,
+    ) -> Result<TrackedTransaction> {
+        let submitted = self.submit(chain_id, &mut tx, signer).await;
+        self.settle_spending(signer.address(), &tx, submitted.is_ok()).await;
+        let tracked = submitted?;
+        self.tracker.record_broadcast(&tx, &tracked).await;
+        Ok(tracked)
+    }
+
+    /// Count the spending policy hold of a transaction that was broadcast, give it back otherwise
+    async fn settle_spending(&self, from: Address, tx: &TypedTransaction, broadcast: bool) {
+        let Some(spending_policies) = &self.spending_policies else {
+            return;
+        };
+        if broadcast {
+            spending_policies.confirm(from, tx).await;
+        } else {
+            spending_policies.release(from, tx).await;
+        }
+    }
+
+    /// Submission shared by new sends and replacements, which record history differently
+    async fn submit<S: Signer>(&self, chain_id: u64, tx: &mut TypedTransaction, signer: &S) -> Result<TrackedTransaction> {
+        let from = signer.address();
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+
+        tx.set_from(from);
+        tx.set_chain_id(chain_id);
+        if tx.nonce().is_none() {
+            tx.set_nonce(self.next_nonce(chain_id, from).await?);
+        }
+        chain.provider.fill_transaction(tx, None).await?;
+        let nonce = tx.nonce().copied().unwrap_or_default();
+
+        let mut replacements = 0;
+        loop {
+            let signature = signer.sign_transaction(tx).await
+                .map_err(|e| anyhow!("Failed to sign transaction: {}", e))?;
+
+            match chain.provider.send_raw_transaction(tx.rlp_signed(&signature)).await {
+                Ok(pending) => {
+                    let hash = pending.tx_hash();
+                    info!("Broadcast {:?} from {:?} with nonce {} on chain {}", hash, from, nonce, chain_id);
+                    return self.track(TrackedTransaction::new(hash, chain_id, from, nonce)).await;
+                }
+                Err(e) if is_underpriced(&e.to_string()) && replacements < MAX_REPLACEMENTS => {
+                    replacements += 1;
+                    bump_fees(tx);
+                    warn!("Transaction with nonce {} underpriced, retrying with higher fees ({})", nonce, replacements);
+                }
+                Err(e) => {
+                    self.resync_nonce(chain_id, from).await;
+                    return Err(anyhow!("Broadcast rejected: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Re-send a pending transaction with higher fees and the same nonce
+    #[instrument(skip_all, fields(chain_id = tx.chain_id().map(|id| id.as_u64()), wallet = ?signer.address()))]
+    pub async fn speed_up<S: Signer>(&self, hash: H256, mut tx: TypedTransaction, signer: &S) -> Result<TrackedTransaction> {
+        let replaced = self.replace(hash, &mut tx, signer).await;
+        self.settle_spending(signer.address(), &tx, replaced.is_ok()).await;
+        replaced
+    }
+
+    /// Replacement of `hash` with `tx` at higher fees, which `speed_up` settles the spending of
+    async fn replace<S: Signer>(&self, hash: H256, tx: &mut TypedTransaction, signer: &S) -> Result<TrackedTransaction> {
+        let original = self.get(hash).await.ok_or_else(|| anyhow!("Unknown transaction {:?}", hash))?;
+        if original.status != BroadcastStatus::Pending {
+            return Err(anyhow!("Transaction {:?} is no longer pending", hash));
+        }
+        if original.from != signer.address() {
+            return Err(anyhow!("Transaction {:?} was not sent by {:?}", hash, signer.address()));
+        }
+
+        tx.set_nonce(original.nonce);
+        let chain = self.chain_manager.get_provider(original.chain_id).await?;
+        chain.provider.fill_transaction(tx, None).await?;
+        bump_fees(tx);
+
+        let replacement = self.submit(original.chain_id, tx, signer).await?;
+        if let Some(original) = self.transactions.write().await.get_mut(&hash) {
+            original.status = BroadcastStatus::Replaced;
+            original.replaced_by = Some(replacement.hash);
+        }
+        if let Err(e) = self.tracker.record_replacement(hash, &replacement).await {
+            warn!("Replacement {:?} not linked to history: {}", replacement.hash, e);
+        }
+        Ok(replacement)
+    }
+
+    async fn track(&self, tx: TrackedTransaction) -> Result<TrackedTransaction> {
+        self.transactions.write().await.insert(tx.hash, tx.clone());
+        Ok(tx)
+    }
+
+    pub async fn get(&self, hash: H256) -> Option<TrackedTransaction> {
+        self.transactions.read().await.get(&hash).cloned()
+    }
+
+    /// Poll the node for the receipt and update the tracked status
+    pub async fn refresh(&self, hash: H256) -> Result<TrackedTransaction> {
+        let mut tracked = self.get(hash).await.ok_or_else(|| anyhow!("Unknown transaction {:?}", hash))?;
+        if tracked.status == BroadcastStatus::Replaced {
+            return Ok(tracked);
+        }
+
+        let chain = self.chain_manager.get_provider(tracked.chain_id).await?;
+        match chain.provider.get_transaction_receipt(hash).await? {
+            Some(receipt) => {
+                let block_number = receipt.block_number.map(|block| block.as_u64());
+                let latest = chain.provider.get_block_number().await?.as_u64();
+                tracked.block_number = block_number;
+                tracked.confirmations = block_number.map(|block| latest.saturating_sub(block) + 1).unwrap_or(0);
+                tracked.gas_used = receipt.gas_used;
+                tracked.status = if receipt.status.is_some_and(|status| status.as_u64() == 1) {
+                    BroadcastStatus::Confirmed
+                } else {
+                    BroadcastStatus::Failed
+                };
+            }
+            None => {
+                // A reorg can drop a mined transaction back into the pool
+                tracked.block_number = None;
+                tracked.confirmations = 0;
+                tracked.status = BroadcastStatus::Pending;
+                // A mined nonce past ours means a different transaction took the slot
+                let mined = chain.provider
+                    .get_transaction_count(tracked.from, Some(BlockNumber::Latest.into()))
+                    .await?;
+                if mined > tracked.nonce {
+                    tracked.status = BroadcastStatus::Dropped;
+                }
+            }
+        }
+
+        self.transactions.write().await.insert(hash, tracked.clone());
+        Ok(tracked)
+    }
+
+    /// Poll until the transaction has the requested confirmations or leaves the pending state
+    pub async fn wait_for_confirmation(&self, hash: H256, confirmations: u64, timeout: Duration) -> Result<TrackedTransaction> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let tracked = self.refresh(hash).await?;
+            let settled = match tracked.status {
+                BroadcastStatus::Pending => false,
+                BroadcastStatus::Confirmed => tracked.confirmations >= confirmations,
+                _ => true,
+            };
+            if settled || tokio::time::Instant::now() >= deadline {
+                return Ok(tracked);
+            }
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[async_trait]
+impl ReorgHandler for TxBroadcaster {
+    fn name(&self) -> &'static str {
+        "tx_broadcaster"
+    }
+
+    /// Re-read the transactions mined in orphaned blocks and the nonces of their senders
+    async fn handle_reorg(&self, reorg: &ChainReorg) -> Result<()> {
+        let orphaned: Vec<(H256, Address)> = self.transactions.read().await.values()
+            .filter(|tracked| tracked.chain_id == reorg.chain_id)
+            .filter(|tracked| tracked.block_number.is_some_and(|block| block > reorg.fork_block))
+            .map(|tracked| (tracked.hash, tracked.from))
+            .collect();
+        for (hash, from) in orphaned {
+            self.resync_nonce(reorg.chain_id, from).await;
+            self.refresh(hash).await?;
+        }
+        Ok(())
+    }
+}
+
+fn is_underpriced(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("underpriced") || error.contains("fee too low")
+}
+
+fn bump(value: U256) -> U256 {
+    value * FEE_BUMP_PERCENT / 100 + 1
+}
+
+/// Raise every fee field of a transaction by the replacement bump
+fn bump_fees(tx: &mut TypedTransaction) {
+    if let Some(inner) = tx.as_eip1559_mut() {
+        inner.max_fee_per_gas = inner.max_fee_per_gas.map(bump);
+        inner.max_priority_fee_per_gas = inner.max_priority_fee_per_gas.map(bump);
+    } else if let Some(gas_price) = tx.gas_price() {
+        tx.set_gas_price(bump(gas_price));
+    }
+}
@@ -0,0 +1,277 @@
+// WebSocket connection of a chain carrying subscriptions, with heartbeats and reconnection
+use anyhow::{Result, anyhow};
+use ethers::{
+    providers::{Middleware, Provider, Ws},
+    types::Filter,
+};
+use futures::{Stream, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::ChainManager;
+use crate::shutdown::ShutdownSignal;
+
+/// Wait before subscribing again after a connection failed or a subscription ended
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(15);
+
+/// Heartbeat and reconnection settings shared by every chain's connection
+#[derive(Debug, Clone, Copy)]
+pub struct WsConfig {
+    /// Interval between heartbeats of an open connection
+    pub heartbeat_interval: Duration,
+    /// A heartbeat unanswered this long drops the connection
+    pub heartbeat_timeout: Duration,
+    /// Reconnections attempted by the transport before the connection is given up
+    pub max_reconnects: usize,
+    pub connect_timeout: Duration,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(30),
+            heartbeat_timeout: Duration::from_secs(10),
+            max_reconnects: 5,
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl WsConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut ws_config = Self::default();
+
+        if let Ok(secs) = config.get_int("ws_heartbeat_interval_secs") {
+            ws_config.heartbeat_interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(secs) = config.get_int("ws_heartbeat_timeout_secs") {
+            ws_config.heartbeat_timeout = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(reconnects) = config.get_int("ws_max_reconnects") {
+            ws_config.max_reconnects = reconnects.max(0) as usize;
+        }
+
+        ws_config
+    }
+}
+
+/// What a subscription listens to
+#[derive(Debug, Clone)]
+pub enum WsSubscription {
+    NewHeads,
+    Logs(Filter),
+}
+
+/// Open connection handed to a subscriber, with the means to notice when it is dropped
+pub struct WsSession {
+    pub provider: Arc<Provider<Ws>>,
+    generation: u64,
+    lost: watch::Receiver<u64>,
+}
+
+impl WsSession {
+    /// Wait until a heartbeat found the connection dead
+    pub async fn lost(&mut self) {
+        let generation = self.generation;
+        // A dropped sender means the chain was replaced, which ends the connection too
+        let _ = self.lost.wait_for(|lost| *lost >= generation).await;
+    }
+}
+
+/// Connected on first use and shared by every subscriber of the chain; requests keep going over HTTP
+pub struct WsConnection {
+    chain_id: u64,
+    url: String,
+    config: WsConfig,
+    /// Open connection and its generation, `None` until connected or after it was lost
+    current: Arc<Mutex<Option<(u64, Arc<Provider<Ws>>)>>>,
+    /// Highest generation found dead
+    lost: Arc<watch::Sender<u64>>,
+}
+
+impl WsConnection {
+    pub fn new(chain_id: u64, url: String, config: WsConfig) -> Self {
+        Self {
+            chain_id,
+            url,
+            config,
+            current: Arc::new(Mutex::new(None)),
+            lost: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    /// The open connection, connecting when there is none
+    pub async fn session(&self) -> Result<WsSession> {
+        let mut current = self.current.lock().await;
+        if let Some((generation, provider)) = current.as_ref() {
+            return Ok(self.handout(*generation, provider.clone()));
+        }
+
+        let ws = tokio::time::timeout(
+            self.config.connect_timeout,
+            Ws::connect_with_reconnects(&self.url, self.config.max_reconnects),
+        )
+        .await
+        .map_err(|_| anyhow!("WebSocket of chain {} did not connect within {:?}", self.chain_id, self.config.connect_timeout))??;
+        let provider = Arc::new(Provider::new(ws));
+        let generation = *self.lost.borrow() + 1;
+        *current = Some((generation, provider.clone()));
+        info!("WebSocket of chain {} connected", self.chain_id);
+
+        self.spawn_heartbeat(generation, provider.clone());
+        Ok(self.handout(generation, provider))
+    }
+
+    fn handout(&self, generation: u64, provider: Arc<Provider<Ws>>) -> WsSession {
+        WsSession { provider, generation, lost: self.lost.subscribe() }
+    }
+
+    /// Ping the connection until it fails to answer, then drop it so the next session reconnects
+    fn spawn_heartbeat(&self, generation: u64, provider: Arc<Provider<Ws>>) {
+        let chain_id = self.chain_id;
+        let config = self.config;
+        // Weak so the heartbeat ends with the chain's provider when it is replaced
+        let current = Arc::downgrade(&self.current);
+        let lost = self.lost.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(config.heartbeat_interval).await;
+                let Some(current) = current.upgrade() else {
+                    return;
+                };
+                if current.lock().await.as_ref().is_none_or(|(open, _)| *open != generation) {
+                    return;
+                }
+                let reason = match tokio::time::timeout(config.heartbeat_timeout, provider.get_block_number()).await {
+                    Ok(Ok(block)) => {
+                        debug!("WebSocket heartbeat of chain {} at block {}", chain_id, block);
+                        continue;
+                    }
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => format!("no answer within {:?}", config.heartbeat_timeout),
+                };
+
+                warn!("WebSocket of chain {} lost: {}", chain_id, reason);
+                let mut open = current.lock().await;
+                if open.as_ref().is_some_and(|(open, _)| *open == generation) {
+                    *open = None;
+                }
+                lost.send_modify(|lost| *lost = (*lost).max(generation));
+                return;
+            }
+        });
+    }
+}
+
+/// Send the chain id on `wake` for every notification of `subscription` on each chain with a
+/// WebSocket, so a polling loop runs as soon as there is something to read. `subscription` is asked
+/// again on every (re)subscribe and `changed` forces one, e.g. when the logs of interest changed.
+pub fn spawn_wakers<F>(
+    chain_manager: Arc<ChainManager>,
+    subscription: F,
+    changed: Option<watch::Receiver<u64>>,
+    wake: mpsc::UnboundedSender<u64>,
+    shutdown: ShutdownSignal,
+) -> JoinHandle<()>
+where
+    F: Fn(u64) -> futures::future::BoxFuture<'static, Option<WsSubscription>> + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let subscription = Arc::new(subscription);
+        let mut wakers = Vec::new();
+        for chain_id in chain_manager.chain_ids().await {
+            let chain_manager = chain_manager.clone();
+            let subscription = subscription.clone();
+            let mut changed = changed.clone();
+            let wake = wake.clone();
+            let mut shutdown = shutdown.clone();
+            wakers.push(tokio::spawn(async move {
+                loop {
+                    match wake_chain(&chain_manager, chain_id, subscription.as_ref(), changed.as_mut(), &wake, &mut shutdown).await {
+                        Ok(false) => return,
+                        Ok(true) => continue,
+                        Err(e) => debug!("WebSocket subscription on chain {} failed: {}", chain_id, e),
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => {}
+                        _ = shutdown.cancelled() => return,
+                    }
+                }
+            }));
+        }
+        for waker in wakers {
+            let _ = waker.await;
+        }
+    })
+}
+
+/// Forward one subscription until it ends; `Ok(false)` when there is nothing to subscribe to or
+/// shutdown started
+async fn wake_chain<F>(
+    chain_manager: &ChainManager,
+    chain_id: u64,
+    subscription: &F,
+    changed: Option<&mut watch::Receiver<u64>>,
+    wake: &mpsc::UnboundedSender<u64>,
+    shutdown: &mut ShutdownSignal,
+) -> Result<bool>
+where
+    F: Fn(u64) -> futures::future::BoxFuture<'static, Option<WsSubscription>>,
+{
+    let chain = chain_manager.get_provider(chain_id).await?;
+    let Some(ws) = &chain.ws else {
+        return Ok(false);
+    };
+    let mut changed = changed;
+    if let Some(changed) = changed.as_deref_mut() {
+        changed.borrow_and_update();
+    }
+    let Some(subscription) = subscription(chain_id).await else {
+        // Nothing to listen to yet, wait for a change
+        return match changed {
+            Some(changed) => Ok(tokio::select! {
+                result = changed.changed() => result.is_ok(),
+                _ = shutdown.cancelled() => false,
+            }),
+            None => Ok(false),
+        };
+    };
+
+    let mut session = ws.session().await?;
+    let provider = session.provider.clone();
+    let notifications: std::pin::Pin<Box<dyn Stream<Item = ()> + Send + '_>> = match &subscription {
+        WsSubscription::NewHeads => Box::pin(provider.subscribe_blocks().await?.map(|_| ())),
+        WsSubscription::Logs(filter) => Box::pin(provider.subscribe_logs(filter).await?.map(|_| ())),
+    };
+    let mut notifications = notifications;
+    debug!("Subscribed to {:?} on chain {}", subscription, chain_id);
+
+    let changes = async {
+        match changed {
+            Some(changed) => {
+                let _ = changed.changed().await;
+            }
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(changes);
+    loop {
+        tokio::select! {
+            notification = notifications.next() => match notification {
+                Some(()) => {
+                    if wake.send(chain_id).is_err() {
+                        return Ok(false);
+                    }
+                }
+                None => return Err(anyhow!("subscription ended")),
+            },
+            _ = session.lost() => return Err(anyhow!("connection lost")),
+            _ = &mut changes => return Ok(true),
+            _ = shutdown.cancelled() => return Ok(false),
+        }
+    }
+}
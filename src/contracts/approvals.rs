@@ -0,0 +1,368 @@
+// ERC-20 allowances required before routers and lending pools can pull tokens
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::{Abi, Token},
+    contract::Contract,
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712, TypedData},
+        Address, Bytes, NameOrAddress, Signature, TransactionRequest, H256, U256,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+
+/// Typical gas of an ERC-20 approve
+pub const APPROVE_GAS: u64 = 50_000;
+
+/// How much an approval grants when the current allowance falls short
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalPolicy {
+    /// Exactly the amount the operation spends, so a compromised spender can take nothing more
+    #[default]
+    Exact,
+    /// The maximum allowance, saving an approval on every later operation
+    Infinite,
+}
+
+impl ApprovalPolicy {
+    /// Policy from `approval_policy`, `exact` unless set to `infinite`
+    pub fn from_config(config: &config::Config) -> Self {
+        match config.get_string("approval_policy").as_deref() {
+            Ok("infinite") => Self::Infinite,
+            _ => Self::Exact,
+        }
+    }
+}
+
+/// Tokens an operation lets a spender pull from the owner
+#[derive(Debug, Clone, Copy)]
+pub struct TokenSpend {
+    pub token: Address,
+    pub spender: Address,
+    pub amount: U256,
+}
+
+/// Approval to send before the operation it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenApproval {
+    pub token: Address,
+    pub spender: Address,
+    /// Allowance left when this approval is reached, after earlier operations of the same batch
+    pub current_allowance: U256,
+    pub amount: U256,
+    pub policy: ApprovalPolicy,
+    pub transaction: TransactionRequest,
+}
+
+/// EIP-2612 permit for the owner to sign instead of sending an approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermitRequest {
+    pub typed_data: TypedData,
+    /// Hash the owner signs, for signers that cannot render typed data
+    pub digest: H256,
+    pub nonce: U256,
+    pub deadline: U256,
+}
+
+/// Permit signed by the owner, submitted by anyone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPermit {
+    pub owner: Address,
+    pub spender: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub value: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub deadline: U256,
+    /// 65-byte hex signature of the permit's typed data
+    pub signature: String,
+}
+
+/// Checks allowances and builds the approvals and permits operations need
+pub struct ApprovalManager {
+    chain_manager: Arc<ChainManager>,
+    policy: ApprovalPolicy,
+}
+
+impl ApprovalManager {
+    pub fn new(chain_manager: Arc<ChainManager>, policy: ApprovalPolicy) -> Self {
+        Self { chain_manager, policy }
+    }
+
+    pub fn policy(&self) -> ApprovalPolicy {
+        self.policy
+    }
+
+    async fn token_contract(&self, chain_id: u64, token: Address) -> Result<Contract<ethers::providers::Provider<PooledHttp>>> {
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+        Ok(Contract::new(token, Self::get_token_abi()?, provider))
+    }
+
+    pub async fn allowance(&self, chain_id: u64, token: Address, owner: Address, spender: Address) -> Result<U256> {
+        let contract = self.token_contract(chain_id, token).await?;
+        Ok(contract.method::<_, U256>("allowance", (owner, spender))?.call().await?)
+    }
+
+    /// Approve transaction granting `amount` to `spender`
+    pub fn approve_transaction(&self, chain_id: u64, token: Address, spender: Address, amount: U256) -> Result<TransactionRequest> {
+        let abi = Self::get_token_abi()?;
+        let data = abi.function("approve")?.encode_input(&[Token::Address(spender), Token::Uint(amount)])?;
+
+        Ok(TransactionRequest::new()
+            .to(token)
+            .data(data)
+            .value(U256::zero())
+            .chain_id(chain_id))
+    }
+
+    /// Approval the policy grants for `amount`, `None` when the allowance already covers it
+    pub async fn required_approval(
+        &self,
+        chain_id: u64,
+        owner: Address,
+        spend: TokenSpend,
+        policy: Option<ApprovalPolicy>,
+    ) -> Result<Option<TokenApproval>> {
+        let mut approvals = self.plan_approvals(chain_id, owner, &[spend], policy).await?;
+        Ok(approvals.pop().flatten())
+    }
+
+    /// Approvals for operations executed in order by the same owner, one entry per operation
+    ///
+    /// Each operation consumes the allowance it spends, so a later one may need a new approval
+    /// although the on-chain allowance covers it on its own.
+    pub async fn plan_approvals(
+        &self,
+        chain_id: u64,
+        owner: Address,
+        spends: &[TokenSpend],
+        policy: Option<ApprovalPolicy>,
+    ) -> Result<Vec<Option<TokenApproval>>> {
+        let policy = policy.unwrap_or(self.policy);
+        let mut remaining: HashMap<(Address, Address), U256> = HashMap::new();
+        let mut approvals = Vec::with_capacity(spends.len());
+
+        for spend in spends {
+            // The native token is sent as value and needs no approval
+            if spend.token.is_zero() || spend.amount.is_zero() {
+                approvals.push(None);
+                continue;
+            }
+
+            let key = (spend.token, spend.spender);
+            let current = match remaining.get(&key) {
+                Some(allowance) => *allowance,
+                None => self.allowance(chain_id, spend.token, owner, spend.spender).await?,
+            };
+
+            if current >= spend.amount {
+                // An infinite allowance is not decreased by transferFrom
+                remaining.insert(key, if current == U256::MAX { current } else { current - spend.amount });
+                approvals.push(None);
+                continue;
+            }
+
+            let amount = match policy {
+                ApprovalPolicy::Exact => spend.amount,
+                ApprovalPolicy::Infinite => U256::MAX,
+            };
+            info!("Allowance of {:?} for {:?} is {}, approving {}", spend.token, spend.spender, current, amount);
+            remaining.insert(key, if amount == U256::MAX { amount } else { U256::zero() });
+            approvals.push(Some(TokenApproval {
+                token: spend.token,
+                spender: spend.spender,
+                current_allowance: current,
+                amount,
+                policy,
+                transaction: self.approve_transaction(chain_id, spend.token, spend.spender, amount)?,
+            }));
+        }
+
+        Ok(approvals)
+    }
+
+    /// Typed data of an EIP-2612 permit at the owner's current nonce
+    ///
+    /// Fails for tokens without `nonces`, and when the token's domain separator does not
+    /// match the one rebuilt from its name and version, as a signature would be rejected.
+    pub async fn permit_request(
+        &self,
+        chain_id: u64,
+        token: Address,
+        owner: Address,
+        spender: Address,
+        value: U256,
+        deadline: U256,
+    ) -> Result<PermitRequest> {
+        let contract = self.token_contract(chain_id, token).await?;
+        let nonce: U256 = contract.method::<_, U256>("nonces", owner)?.call().await
+            .map_err(|e| anyhow!("Token {:?} does not support EIP-2612 permits: {}", token, e))?;
+        let name: String = contract.method::<_, String>("name", ())?.call().await?;
+        // Most permit tokens predate the `version` getter and sign with version 1
+        let version = match contract.method::<_, String>("version", ())?.call().await {
+            Ok(version) => version,
+            Err(_) => "1".to_string(),
+        };
+
+        let domain = EIP712Domain {
+            name: Some(name),
+            version: Some(version),
+            chain_id: Some(U256::from(chain_id)),
+            verifying_contract: Some(token),
+            salt: None,
+        };
+        match contract.method::<_, [u8; 32]>("DOMAIN_SEPARATOR", ())?.call().await {
+            Ok(separator) if separator != domain.separator() => {
+                return Err(anyhow!("Permit domain of {:?} does not match its name and version", token));
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Could not read DOMAIN_SEPARATOR of {:?}: {}", token, e),
+        }
+
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "domain": domain,
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Permit": [
+                    {"name": "owner", "type": "address"},
+                    {"name": "spender", "type": "address"},
+                    {"name": "value", "type": "uint256"},
+                    {"name": "nonce", "type": "uint256"},
+                    {"name": "deadline", "type": "uint256"}
+                ]
+            },
+            "primaryType": "Permit",
+            "message": {
+                "owner": owner,
+                "spender": spender,
+                "value": value.to_string(),
+                "nonce": nonce.to_string(),
+                "deadline": deadline.to_string()
+            }
+        }))?;
+        let digest = H256::from(typed_data.encode_eip712()?);
+
+        Ok(PermitRequest { typed_data, digest, nonce, deadline })
+    }
+
+    /// `permit` transaction for a signed permit, checked against the owner's current nonce
+    pub async fn permit_transaction(&self, chain_id: u64, token: Address, permit: &SignedPermit) -> Result<TransactionRequest> {
+        let signature: Signature = permit.signature.trim_start_matches("0x").parse()
+            .map_err(|e| anyhow!("Invalid permit signature: {}", e))?;
+        let request = self.permit_request(chain_id, token, permit.owner, permit.spender, permit.value, permit.deadline).await?;
+        let signer = signature.recover(request.digest)?;
+        if signer != permit.owner {
+            return Err(anyhow!("Permit is signed by {:?}, not the owner {:?}", signer, permit.owner));
+        }
+
+        let mut r = [0u8; 32];
+        let mut s = [0u8; 32];
+        signature.r.to_big_endian(&mut r);
+        signature.s.to_big_endian(&mut s);
+        let abi = Self::get_token_abi()?;
+        let data = abi.function("permit")?.encode_input(&[
+            Token::Address(permit.owner),
+            Token::Address(permit.spender),
+            Token::Uint(permit.value),
+            Token::Uint(permit.deadline),
+            Token::Uint(U256::from(signature.v)),
+            Token::FixedBytes(r.to_vec()),
+            Token::FixedBytes(s.to_vec()),
+        ])?;
+
+        Ok(TransactionRequest::new()
+            .to(token)
+            .data(Bytes::from(data))
+            .value(U256::zero())
+            .chain_id(chain_id))
+    }
+
+    fn get_token_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"name": "owner", "type": "address"},
+                    {"name": "spender", "type": "address"}
+                ],
+                "name": "allowance",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"name": "spender", "type": "address"},
+                    {"name": "amount", "type": "uint256"}
+                ],
+                "name": "approve",
+                "outputs": [{"name": "", "type": "bool"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "name",
+                "outputs": [{"name": "", "type": "string"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "version",
+                "outputs": [{"name": "", "type": "string"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"name": "owner", "type": "address"}],
+                "name": "nonces",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "DOMAIN_SEPARATOR",
+                "outputs": [{"name": "", "type": "bytes32"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"name": "owner", "type": "address"},
+                    {"name": "spender", "type": "address"},
+                    {"name": "value", "type": "uint256"},
+                    {"name": "deadline", "type": "uint256"},
+                    {"name": "v", "type": "uint8"},
+                    {"name": "r", "type": "bytes32"},
+                    {"name": "s", "type": "bytes32"}
+                ],
+                "name": "permit",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+}
+
+/// Contract a transaction calls, the spender of the tokens it moves
+pub fn transaction_target(transaction: &TransactionRequest) -> Option<Address> {
+    match &transaction.to {
+        Some(NameOrAddress::Address(address)) => Some(*address),
+        _ => None,
+    }
+}
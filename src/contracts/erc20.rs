@@ -4,13 +4,15 @@ use ethers::{
     prelude::*,
     abi::{Abi, Token, Function},
     types::{Address, U256, H256, Bytes, TransactionRequest},
-    providers::{Provider, Http},
+    providers::Provider,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{info, warn};
 use std::collections::HashMap;
 
+use crate::chains::pool::PooledHttp;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenInfo {
     pub address: Address,
@@ -24,7 +26,7 @@ pub struct TokenInfo {
 #[derive(Debug, Clone)]
 pub struct ERC20Contract {
     address: Address,
-    provider: Arc<Provider<Http>>,
+    provider: Arc<Provider<PooledHttp>>,
     chain_id: u64,
     token_info: Option<TokenInfo>,
     abi: Abi,
@@ -51,7 +53,7 @@ pub struct ApprovalEvent {
 impl ERC20Contract {
     pub async fn new(
         contract_address: Address,
-        provider: Arc<Provider<Http>>,
+        provider: Arc<Provider<PooledHttp>>,
         chain_id: u64,
     ) -> Result<Self> {
         info!("Creating ERC-20 contract instance at {:?} on chain {}", contract_address, chain_id);
@@ -1,13 +1,15 @@
 use ethers::{
     abi::{Abi, Token, Tokenize, Detokenize},
     contract::{Contract, ContractError},
-    providers::{Provider, Http},
+    providers::Provider,
     types::{Address, U256, H256, TransactionRequest},
 };
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+use crate::chains::pool::PooledHttp;
+
 /// NFT Collection information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NFTCollection {
@@ -50,16 +52,16 @@ pub struct CollectionStats {
 /// ERC721 contract interface
 #[derive(Debug, Clone)]
 pub struct ERC721Contract {
-    contract: Contract<Provider<Http>>,
+    contract: Contract<Provider<PooledHttp>>,
     address: Address,
-    provider: Arc<Provider<Http>>,
+    provider: Arc<Provider<PooledHttp>>,
 }
 
 impl ERC721Contract {
     /// Create a new ERC721 contract instance
     pub fn new(
         address: Address,
-        provider: Arc<Provider<Http>>,
+        provider: Arc<Provider<PooledHttp>>,
     ) -> Result<Self> {
         let abi = Self::get_erc721_abi()?;
         let contract = Contract::new(address, abi, provider.clone());
@@ -71,8 +73,8 @@ impl ERC721Contract {
         })
     }
 
-    /// Get ERC721 ABI
-    fn get_erc721_abi() -> Result<Abi> {
+    /// Get ERC721 ABI, with the ERC721Enumerable and ERC165 reads
+    pub(crate) fn get_erc721_abi() -> Result<Abi> {
         let abi_json = r#"[
             {
                 "inputs": [{"internalType": "uint256", "name": "tokenId", "type": "uint256"}],
@@ -109,6 +111,23 @@ impl ERC721Contract {
                 "stateMutability": "view",
                 "type": "function"
             },
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "owner", "type": "address"},
+                    {"internalType": "uint256", "name": "index", "type": "uint256"}
+                ],
+                "name": "tokenOfOwnerByIndex",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "bytes4", "name": "interfaceId", "type": "bytes4"}],
+                "name": "supportsInterface",
+                "outputs": [{"internalType": "bool", "name": "", "type": "bool"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
             {
                 "inputs": [{"internalType": "address", "name": "owner", "type": "address"}],
                 "name": "balanceOf",
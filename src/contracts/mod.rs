@@ -2,19 +2,24 @@
 use anyhow::{Result, anyhow};
 use ethers::{
     prelude::*,
-    abi::{Abi, Contract},
-    types::{Address, U256, H256, Bytes, Transaction, Log},
-    providers::{Provider, Http},
+    abi::{Abi, Function, RawLog, Token, token::{LenientTokenizer, Tokenizer}},
+    types::{transaction::eip2718::TypedTransaction, Address, U256, H256, Bytes, Log},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::sync::Arc;
 use std::collections::HashMap;
-use tracing::{info, warn, error};
+use std::time::Duration;
+use tracing::{debug, info, warn};
 use tokio::sync::RwLock;
 
+pub mod approvals;
 pub mod erc20;
 pub mod erc721;
+pub mod multicall;
 pub mod defi_contracts;
+pub mod permit2;
+pub mod probes;
 pub mod proxy;
 
 use crate::chains::ChainManager;
@@ -44,6 +49,201 @@ pub enum ContractType {
     Custom(String),
 }
 
+/// Result of calling a method resolved from a verified ABI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractCallResult {
+    pub address: Address,
+    pub chain_id: u64,
+    /// Full method signature, e.g. `balanceOf(address):(uint256)`
+    pub method: String,
+    pub state_mutability: String,
+    /// Encoded call data, ready to sign for state-changing methods
+    pub calldata: Bytes,
+    /// Decoded return values of an eth_call at the latest block
+    pub outputs: Vec<Value>,
+}
+
+/// Log decoded with its emitter's verified ABI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedEvent {
+    pub address: Address,
+    pub log_index: Option<U256>,
+    /// `None` when the emitter is unverified or the event is not in its ABI
+    pub name: Option<String>,
+    pub params: Map<String, Value>,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+}
+
+/// Block explorer API endpoints and keys per chain
+#[derive(Debug, Clone)]
+pub struct ExplorerConfig {
+    pub api_urls: HashMap<u64, String>,
+    pub api_keys: HashMap<u64, String>,
+    pub timeout: Duration,
+}
+
+impl Default for ExplorerConfig {
+    fn default() -> Self {
+        Self {
+            api_urls: HashMap::from([
+                (1, "https://api.etherscan.io/api".to_string()),
+                (137, "https://api.polygonscan.com/api".to_string()),
+                (42161, "https://api.arbiscan.io/api".to_string()),
+                (56, "https://api.bscscan.com/api".to_string()),
+                (43114, "https://api.routescan.io/v2/network/mainnet/evm/43114/etherscan/api".to_string()),
+            ]),
+            api_keys: HashMap::new(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ExplorerConfig {
+    /// Explorer API keys from `etherscan_api_key`, `polygonscan_api_key`, `arbiscan_api_key`,
+    /// `bscscan_api_key` and `snowtrace_api_key`
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut explorer_config = Self::default();
+
+        let key_names = [
+            (1, "etherscan_api_key"),
+            (137, "polygonscan_api_key"),
+            (42161, "arbiscan_api_key"),
+            (56, "bscscan_api_key"),
+            (43114, "snowtrace_api_key"),
+        ];
+        for (chain_id, key_name) in key_names {
+            if let Ok(key) = config.get_string(key_name) {
+                if !key.is_empty() {
+                    explorer_config.api_keys.insert(chain_id, key);
+                }
+            }
+        }
+
+        explorer_config
+    }
+}
+
+/// `getsourcecode` entry of an Etherscan-compatible explorer
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ExplorerSource {
+    #[serde(rename = "ABI")]
+    abi: String,
+    #[serde(default)]
+    proxy: String,
+    #[serde(default)]
+    implementation: String,
+}
+
+#[derive(Deserialize)]
+struct ExplorerResponse {
+    status: String,
+    result: Value,
+}
+
+/// Fetches verified ABIs from Etherscan, Polygonscan and Arbiscan
+pub struct AbiResolver {
+    http: reqwest::Client,
+    config: ExplorerConfig,
+    cache: Arc<RwLock<HashMap<String, Abi>>>,
+}
+
+impl AbiResolver {
+    pub fn new(config: ExplorerConfig, cache: Arc<RwLock<HashMap<String, Abi>>>) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()
+                .unwrap_or_default(),
+            config,
+            cache,
+        }
+    }
+
+    fn cache_key(chain_id: u64, address: Address) -> String {
+        format!("{}:{:?}", chain_id, address)
+    }
+
+    /// Verified ABI of a contract; proxies include their implementation's functions and events
+    pub async fn resolve(&self, chain_id: u64, address: Address) -> Result<Abi> {
+        let key = Self::cache_key(chain_id, address);
+        if let Some(abi) = self.cache.read().await.get(&key) {
+            return Ok(abi.clone());
+        }
+
+        let source = self.fetch_source(chain_id, address).await?;
+        let mut abi: Abi = serde_json::from_str(&source.abi)
+            .map_err(|_| anyhow!("Contract {:?} on chain {} is not verified: {}", address, chain_id, source.abi))?;
+
+        if source.proxy == "1" {
+            if let Ok(implementation) = source.implementation.parse::<Address>() {
+                debug!("Resolving implementation {:?} of proxy {:?}", implementation, address);
+                let implementation_source = self.fetch_source(chain_id, implementation).await?;
+                match serde_json::from_str::<Abi>(&implementation_source.abi) {
+                    Ok(implementation_abi) => Self::merge(&mut abi, implementation_abi),
+                    Err(_) => warn!("Implementation {:?} of proxy {:?} is not verified", implementation, address),
+                }
+            }
+        }
+
+        self.cache.write().await.insert(key, abi.clone());
+        Ok(abi)
+    }
+
+    async fn fetch_source(&self, chain_id: u64, address: Address) -> Result<ExplorerSource> {
+        let url = self.config.api_urls.get(&chain_id)
+            .ok_or_else(|| anyhow!("No block explorer API configured for chain {}", chain_id))?;
+        let address = format!("{:?}", address);
+
+        let mut query = vec![("module", "contract"), ("action", "getsourcecode"), ("address", address.as_str())];
+        if let Some(key) = self.config.api_keys.get(&chain_id) {
+            query.push(("apikey", key.as_str()));
+        }
+
+        let response: ExplorerResponse = self.http.get(url)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if response.status != "1" {
+            // Errors such as rate limits or invalid keys are reported in `result`
+            return Err(anyhow!("Explorer request for {} failed: {}", address, response.result));
+        }
+
+        let mut sources: Vec<ExplorerSource> = serde_json::from_value(response.result)?;
+        if sources.is_empty() {
+            return Err(anyhow!("Explorer returned no source for {}", address));
+        }
+        Ok(sources.swap_remove(0))
+    }
+
+    fn merge(abi: &mut Abi, other: Abi) {
+        for (name, functions) in other.functions {
+            let existing = abi.functions.entry(name).or_default();
+            for function in functions {
+                if !existing.iter().any(|f| f.short_signature() == function.short_signature()) {
+                    existing.push(function);
+                }
+            }
+        }
+        for (name, events) in other.events {
+            let existing = abi.events.entry(name).or_default();
+            for event in events {
+                if !existing.iter().any(|e| e.signature() == event.signature()) {
+                    existing.push(event);
+                }
+            }
+        }
+        for (name, errors) in other.errors {
+            abi.errors.entry(name).or_insert(errors);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ContractInstance {
     ERC20(ERC20Contract),
@@ -56,17 +256,24 @@ pub struct ContractManager {
     contracts: Arc<RwLock<HashMap<Address, ContractInstance>>>,
     contract_registry: Arc<RwLock<HashMap<Address, ContractInfo>>>,
     abi_cache: Arc<RwLock<HashMap<String, Abi>>>,
+    abi_resolver: AbiResolver,
 }
 
 impl ContractManager {
     pub async fn new(chain_manager: Arc<ChainManager>) -> Result<Self> {
+        Self::with_explorer(chain_manager, ExplorerConfig::default()).await
+    }
+
+    pub async fn with_explorer(chain_manager: Arc<ChainManager>, explorer_config: ExplorerConfig) -> Result<Self> {
         info!("Initializing ContractManager");
+        let abi_cache = Arc::new(RwLock::new(HashMap::new()));
 
         Ok(Self {
             chain_manager,
             contracts: Arc::new(RwLock::new(HashMap::new())),
             contract_registry: Arc::new(RwLock::new(HashMap::new())),
-            abi_cache: Arc::new(RwLock::new(HashMap::new())),
+            abi_resolver: AbiResolver::new(explorer_config, abi_cache.clone()),
+            abi_cache,
         })
     }
 
@@ -152,4 +359,140 @@ impl ContractManager {
     pub async fn get_registered_contracts(&self) -> HashMap<Address, ContractInfo> {
         self.contract_registry.read().await.clone()
     }
+
+    /// Verified ABI of any contract, fetched from the chain's block explorer
+    pub async fn get_abi(&self, chain_id: u64, address: Address) -> Result<Abi> {
+        self.abi_resolver.resolve(chain_id, address).await
+    }
+
+    pub async fn clear_abi_cache(&self) {
+        self.abi_cache.write().await.clear();
+    }
+
+    /// Call a method of any verified contract by name or signature
+    ///
+    /// Arguments are JSON values tokenized against the method's inputs: numbers or
+    /// numeric strings (also "1.5 ether"), hex strings for addresses and bytes,
+    /// nested arrays for array and tuple parameters.
+    pub async fn call_contract_method(
+        &self,
+        chain_id: u64,
+        address: Address,
+        method: &str,
+        args: &[Value],
+    ) -> Result<ContractCallResult> {
+        let abi = self.get_abi(chain_id, address).await?;
+        let function = Self::find_function(&abi, method, args.len())?;
+
+        let tokens = function.inputs.iter()
+            .zip(args)
+            .map(|(param, arg)| {
+                LenientTokenizer::tokenize(&param.kind, &Self::arg_to_string(arg))
+                    .map_err(|e| anyhow!("Invalid value for {} ({}): {}", param.name, param.kind, e))
+            })
+            .collect::<Result<Vec<Token>>>()?;
+        let calldata = Bytes::from(function.encode_input(&tokens)?);
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let tx: TypedTransaction = TransactionRequest::new().to(address).data(calldata.clone()).into();
+        let output = chain_provider.provider.call(&tx, None).await?;
+        let outputs = function.decode_output(&output)?
+            .into_iter()
+            .map(Self::token_to_json)
+            .collect();
+
+        Ok(ContractCallResult {
+            address,
+            chain_id,
+            method: function.signature(),
+            state_mutability: format!("{:?}", function.state_mutability).to_lowercase(),
+            calldata,
+            outputs,
+        })
+    }
+
+    /// Decode logs with their emitters' verified ABIs; undecodable logs keep their raw data
+    pub async fn decode_logs(&self, chain_id: u64, logs: &[Log]) -> Vec<DecodedEvent> {
+        let mut decoded = Vec::with_capacity(logs.len());
+
+        for log in logs {
+            let mut event = DecodedEvent {
+                address: log.address,
+                log_index: log.log_index,
+                name: None,
+                params: Map::new(),
+                topics: log.topics.clone(),
+                data: log.data.clone(),
+            };
+
+            let abi = match self.get_abi(chain_id, log.address).await {
+                Ok(abi) => abi,
+                Err(e) => {
+                    debug!("No ABI for log emitter {:?}: {}", log.address, e);
+                    decoded.push(event);
+                    continue;
+                }
+            };
+
+            let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+            let matching = abi.events()
+                .filter(|candidate| !candidate.anonymous && log.topics.first() == Some(&candidate.signature()))
+                .find_map(|candidate| candidate.parse_log(raw.clone()).ok().map(|parsed| (candidate, parsed)));
+
+            if let Some((abi_event, parsed)) = matching {
+                event.name = Some(abi_event.name.clone());
+                for param in parsed.params {
+                    event.params.insert(param.name, Self::token_to_json(param.value));
+                }
+            }
+            decoded.push(event);
+        }
+
+        decoded
+    }
+
+    /// Match by full signature such as `transfer(address,uint256)`, else by name and arity
+    fn find_function<'a>(abi: &'a Abi, method: &str, arg_count: usize) -> Result<&'a Function> {
+        if let Some((name, _)) = method.split_once('(') {
+            let wanted = method.replace(' ', "");
+            return abi.functions_by_name(name)?
+                .iter()
+                .find(|f| {
+                    let inputs: Vec<String> = f.inputs.iter().map(|p| p.kind.to_string()).collect();
+                    format!("{}({})", f.name, inputs.join(",")) == wanted
+                })
+                .ok_or_else(|| anyhow!("No function with signature {}", method));
+        }
+
+        let candidates = abi.functions_by_name(method)?;
+        candidates.iter()
+            .find(|f| f.inputs.len() == arg_count)
+            .ok_or_else(|| anyhow!("{} takes {} arguments, got {}", method, candidates[0].inputs.len(), arg_count))
+    }
+
+    fn arg_to_string(arg: &Value) -> String {
+        match arg {
+            Value::String(text) => text.clone(),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(Self::arg_to_string).collect();
+                format!("[{}]", items.join(","))
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// JSON form of a decoded value; unsigned integers are hex quantities, signed ones decimal strings
+    fn token_to_json(token: Token) -> Value {
+        match token {
+            Token::Address(address) => Value::String(format!("{:?}", address)),
+            Token::Uint(value) => Value::String(format!("{:#x}", value)),
+            Token::Int(value) => Value::String(I256::from_raw(value).to_string()),
+            Token::Bool(value) => Value::Bool(value),
+            Token::String(value) => Value::String(value),
+            Token::Bytes(bytes) | Token::FixedBytes(bytes) => Value::String(Bytes::from(bytes).to_string()),
+            Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+                Value::Array(items.into_iter().map(Self::token_to_json).collect())
+            }
+        }
+    }
 }
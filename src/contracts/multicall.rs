@@ -0,0 +1,54 @@
+// Batched contract reads through Multicall3
+use anyhow::Result;
+use ethers::{
+    abi::{Detokenize, Token},
+    contract::{multicall_contract::Call3, ContractCall, Multicall},
+    providers::Provider,
+};
+use std::sync::Arc;
+
+use crate::chains::batch::RpcBatch;
+use crate::chains::pool::PooledHttp;
+
+/// Calls batched into one Multicall3 `aggregate3`
+const MULTICALL_BATCH_SIZE: usize = 100;
+
+/// Run `calls` through Multicall3 in batches sent together as one JSON-RPC batch, calls that revert
+/// come back as `None`
+pub async fn multicall<D: Detokenize>(
+    provider: &Arc<Provider<PooledHttp>>,
+    chain_id: u64,
+    calls: Vec<ContractCall<Provider<PooledHttp>, D>>,
+) -> Result<Vec<Option<Token>>> {
+    if calls.is_empty() {
+        return Ok(Vec::new());
+    }
+    let functions: Vec<_> = calls.iter().map(|call| call.function.clone()).collect();
+    let multicall = Multicall::<Provider<PooledHttp>>::new_with_chain_id(provider.clone(), None, Some(chain_id))?.contract;
+    let mut aggregates = Vec::new();
+    let mut batch = RpcBatch::new();
+    for chunk in calls.chunks(MULTICALL_BATCH_SIZE) {
+        let aggregate = multicall.aggregate_3(chunk.iter()
+            .map(|call| Call3 {
+                target: call.tx.to_addr().copied().unwrap_or_default(),
+                allow_failure: true,
+                call_data: call.tx.data().cloned().unwrap_or_default(),
+            })
+            .collect());
+        aggregates.push((batch.call(&aggregate)?, aggregate));
+    }
+    let batch_results = batch.send(provider.as_ref().as_ref()).await?;
+
+    let mut results = Vec::with_capacity(functions.len());
+    for (index, aggregate) in &aggregates {
+        results.extend(batch_results.decode(*index, aggregate)?);
+    }
+    Ok(results.into_iter()
+        .zip(functions)
+        .map(|(result, function)| {
+            let mut tokens = function.decode_output(&result.return_data).ok().filter(|_| result.success)?;
+            // Like `Multicall::call_raw`, several outputs come back as a tuple
+            Some(if tokens.len() == 1 { tokens.remove(0) } else { Token::Tuple(tokens) })
+        })
+        .collect())
+}
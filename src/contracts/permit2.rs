@@ -0,0 +1,270 @@
+// Permit2 allowances, letting routers pull tokens against a signature instead of an approval each
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::{Abi, Token},
+    contract::Contract,
+    providers::Provider,
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712, TypedData},
+        Address, Signature, H256, U256,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::info;
+
+use super::approvals::{ApprovalManager, ApprovalPolicy, TokenApproval, TokenSpend};
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+
+/// Permit2 is deployed at the same address on every chain
+const PERMIT2_ADDRESS: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+/// How long a signed Permit2 allowance can be used, as in the Uniswap interface
+const ALLOWANCE_EXPIRATION_SECS: u64 = 30 * 24 * 60 * 60;
+/// How long the owner has to submit a permit signature
+const SIGNATURE_VALIDITY_SECS: u64 = 30 * 60;
+
+pub fn permit2_address() -> Address {
+    PERMIT2_ADDRESS.parse().expect("valid Permit2 address")
+}
+
+/// Allowance a spender holds through Permit2
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Permit2Allowance {
+    pub amount: U256,
+    /// Unix time after which the allowance is void
+    pub expiration: u64,
+    /// Nonce the next permit for this token and spender must carry
+    pub nonce: u64,
+}
+
+/// One token's allowance within a permit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermitDetails {
+    pub token: Address,
+    /// uint160 allowance granted to the spender
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount: U256,
+    pub expiration: u64,
+    pub nonce: u64,
+}
+
+/// Permit2 allowances for one spender, signed once: a `PermitSingle` for one token,
+/// a `PermitBatch` for several
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permit2Permit {
+    pub details: Vec<PermitDetails>,
+    pub spender: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub sig_deadline: U256,
+}
+
+impl Permit2Permit {
+    pub fn is_batch(&self) -> bool {
+        self.details.len() > 1
+    }
+
+    /// EIP-712 typed data the owner signs
+    pub fn typed_data(&self, chain_id: u64) -> Result<TypedData> {
+        let domain = EIP712Domain {
+            name: Some("Permit2".to_string()),
+            version: None,
+            chain_id: Some(U256::from(chain_id)),
+            verifying_contract: Some(permit2_address()),
+            salt: None,
+        };
+        let details: Vec<serde_json::Value> = self.details.iter()
+            .map(|details| serde_json::json!({
+                "token": details.token,
+                "amount": details.amount.to_string(),
+                "expiration": details.expiration.to_string(),
+                "nonce": details.nonce.to_string()
+            }))
+            .collect();
+        let (primary_type, details_type, details) = match details.as_slice() {
+            [single] => ("PermitSingle", "PermitDetails", single.clone()),
+            _ => ("PermitBatch", "PermitDetails[]", serde_json::Value::Array(details)),
+        };
+
+        Ok(serde_json::from_value(serde_json::json!({
+            "domain": domain,
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "PermitDetails": [
+                    {"name": "token", "type": "address"},
+                    {"name": "amount", "type": "uint160"},
+                    {"name": "expiration", "type": "uint48"},
+                    {"name": "nonce", "type": "uint48"}
+                ],
+                primary_type: [
+                    {"name": "details", "type": details_type},
+                    {"name": "spender", "type": "address"},
+                    {"name": "sigDeadline", "type": "uint256"}
+                ]
+            },
+            "primaryType": primary_type,
+            "message": {
+                "details": details,
+                "spender": self.spender,
+                "sigDeadline": self.sig_deadline.to_string()
+            }
+        }))?)
+    }
+
+    /// Fail unless the owner signed this permit
+    pub fn verify(&self, chain_id: u64, owner: Address, signature: &Signature) -> Result<()> {
+        let digest = H256::from(self.typed_data(chain_id)?.encode_eip712()?);
+        let signer = signature.recover(digest)?;
+        if signer != owner {
+            return Err(anyhow!("Permit2 permit is signed by {:?}, not the owner {:?}", signer, owner));
+        }
+        Ok(())
+    }
+
+    /// ABI-encoded `PermitSingle` or `PermitBatch` struct
+    pub fn to_token(&self) -> Token {
+        let mut details: Vec<Token> = self.details.iter()
+            .map(|details| Token::Tuple(vec![
+                Token::Address(details.token),
+                Token::Uint(details.amount),
+                Token::Uint(U256::from(details.expiration)),
+                Token::Uint(U256::from(details.nonce)),
+            ]))
+            .collect();
+        let details = if self.is_batch() { Token::Array(details) } else { details.remove(0) };
+        Token::Tuple(vec![details, Token::Address(self.spender), Token::Uint(self.sig_deadline)])
+    }
+}
+
+/// Permit with the typed data and digest to sign
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permit2Request {
+    pub permit: Permit2Permit,
+    pub typed_data: TypedData,
+    /// Hash the owner signs, for signers that cannot render typed data
+    pub digest: H256,
+}
+
+/// Reads Permit2 allowances and builds the approvals and permits swaps through it need
+pub struct Permit2Manager {
+    chain_manager: Arc<ChainManager>,
+    approvals: Arc<ApprovalManager>,
+}
+
+impl Permit2Manager {
+    pub fn new(chain_manager: Arc<ChainManager>, approvals: Arc<ApprovalManager>) -> Self {
+        Self { chain_manager, approvals }
+    }
+
+    async fn permit2_contract(&self, chain_id: u64) -> Result<Contract<Provider<PooledHttp>>> {
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+        Ok(Contract::new(permit2_address(), Self::get_permit2_abi()?, provider))
+    }
+
+    pub async fn allowance(&self, chain_id: u64, owner: Address, token: Address, spender: Address) -> Result<Permit2Allowance> {
+        let contract = self.permit2_contract(chain_id).await?;
+        let (amount, expiration, nonce): (U256, u64, u64) = contract
+            .method("allowance", (owner, token, spender))?
+            .call()
+            .await?;
+        Ok(Permit2Allowance { amount, expiration, nonce })
+    }
+
+    /// ERC-20 approvals of Permit2 itself, needed once per token; they are infinite since
+    /// Permit2 only moves what the owner's signed permits allow
+    pub async fn token_approvals(&self, chain_id: u64, owner: Address, spends: &[(Address, U256)]) -> Result<Vec<TokenApproval>> {
+        let spends: Vec<TokenSpend> = Self::totals(spends)?.into_iter()
+            .map(|(token, amount)| TokenSpend { token, spender: permit2_address(), amount })
+            .collect();
+        let approvals = self.approvals.plan_approvals(chain_id, owner, &spends, Some(ApprovalPolicy::Infinite)).await?;
+        Ok(approvals.into_iter().flatten().collect())
+    }
+
+    /// Permit for the tokens whose Permit2 allowance to `spender` does not cover what it
+    /// is about to pull, `None` when every allowance does
+    pub async fn permit_request(
+        &self,
+        chain_id: u64,
+        owner: Address,
+        spender: Address,
+        spends: &[(Address, U256)],
+    ) -> Result<Option<Permit2Request>> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let mut details = Vec::new();
+
+        for (token, amount) in Self::totals(spends)? {
+            let allowance = self.allowance(chain_id, owner, token, spender).await?;
+            if allowance.amount >= amount && allowance.expiration > now {
+                continue;
+            }
+            // Permit2 allowances are uint160, the maximum stands for unlimited
+            let amount = match self.approvals.policy() {
+                ApprovalPolicy::Exact => amount,
+                ApprovalPolicy::Infinite => (U256::one() << 160) - 1,
+            };
+            info!("Permit2 allowance of {:?} for {:?} is {}, permitting {}", token, spender, allowance.amount, amount);
+            details.push(PermitDetails {
+                token,
+                amount,
+                expiration: now + ALLOWANCE_EXPIRATION_SECS,
+                nonce: allowance.nonce,
+            });
+        }
+        if details.is_empty() {
+            return Ok(None);
+        }
+
+        let permit = Permit2Permit {
+            details,
+            spender,
+            sig_deadline: U256::from(now + SIGNATURE_VALIDITY_SECS),
+        };
+        let typed_data = permit.typed_data(chain_id)?;
+        let digest = H256::from(typed_data.encode_eip712()?);
+        Ok(Some(Permit2Request { permit, typed_data, digest }))
+    }
+
+    /// Amount pulled per token, checked against the uint160 Permit2 allowances
+    fn totals(spends: &[(Address, U256)]) -> Result<BTreeMap<Address, U256>> {
+        let mut totals: BTreeMap<Address, U256> = BTreeMap::new();
+        for (token, amount) in spends {
+            if token.is_zero() {
+                return Err(anyhow!("Permit2 only moves ERC-20 tokens, wrap the native token first"));
+            }
+            let total = totals.entry(*token).or_default();
+            *total = total.checked_add(*amount).ok_or_else(|| anyhow!("Amount of {:?} overflows", token))?;
+        }
+        if let Some((token, _)) = totals.iter().find(|(_, total)| total.bits() > 160) {
+            return Err(anyhow!("Amount of {:?} exceeds a Permit2 allowance", token));
+        }
+        Ok(totals)
+    }
+
+    fn get_permit2_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"name": "user", "type": "address"},
+                    {"name": "token", "type": "address"},
+                    {"name": "spender", "type": "address"}
+                ],
+                "name": "allowance",
+                "outputs": [
+                    {"name": "amount", "type": "uint160"},
+                    {"name": "expiration", "type": "uint48"},
+                    {"name": "nonce", "type": "uint48"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+}
@@ -0,0 +1,365 @@
+// Startup probes checking configured protocol addresses against the interface they should expose
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::{self, Token},
+    providers::{Middleware, RpcError},
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest, U256},
+    utils::id,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::chains::ChainManager;
+use crate::shutdown::ShutdownSignal;
+
+/// What a probed view call has to return for the interface to match
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    /// Any 32-byte word, the function exists
+    Word,
+    /// `true`, for self-identifying flags such as `isCToken()`
+    True,
+    /// A non-zero word, for lookups only the right version answers
+    NonZero,
+}
+
+/// View call whose answer identifies an interface
+#[derive(Debug, Clone, Copy)]
+struct SelectorProbe {
+    signature: &'static str,
+    /// Static uint arguments
+    args: &'static [u64],
+    expect: Expect,
+}
+
+const fn probe(signature: &'static str, expect: Expect) -> SelectorProbe {
+    SelectorProbe { signature, args: &[], expect }
+}
+
+/// Protocol contract interfaces the address registries point at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolInterface {
+    AaveV2LendingPool,
+    AaveV3Pool,
+    AaveV2AddressesProvider,
+    AaveV3AddressesProvider,
+    AaveProtocolDataProvider,
+    AaveV2Oracle,
+    AaveV3Oracle,
+    AaveWethGateway,
+    CompoundComptroller,
+    CompoundCToken,
+    CompoundV3Comet,
+    Erc20,
+    UniswapV3Factory,
+    UniswapV3Router,
+    UniswapV3Quoter,
+    UniswapV3PositionManager,
+    UniswapV2Factory,
+    UniswapV2Router,
+    /// Uniswap V2 router fork naming its wrapped native token `WAVAX`
+    TraderJoeRouter,
+    SushiMasterChef,
+    SushiMiniChefV2,
+    MakerVat,
+    MakerJug,
+    MakerSpotter,
+    MakerCdpManager,
+    /// Collateral adapter of one collateral type
+    MakerGemJoin,
+    MakerDaiJoin,
+    CurveStableSwap,
+    CurveGaugeController,
+    ConvexBooster,
+}
+
+impl ProtocolInterface {
+    fn probes(self) -> &'static [SelectorProbe] {
+        use Expect::*;
+        match self {
+            Self::AaveV2LendingPool => const { &[
+                probe("LENDINGPOOL_REVISION()", Word),
+                probe("getAddressesProvider()", NonZero),
+                probe("getReservesList()", Word),
+            ] },
+            Self::AaveV3Pool => const { &[
+                probe("POOL_REVISION()", Word),
+                probe("ADDRESSES_PROVIDER()", NonZero),
+                probe("getReservesList()", Word),
+            ] },
+            Self::AaveV2AddressesProvider => const { &[
+                probe("getLendingPool()", NonZero),
+                probe("getPriceOracle()", Word),
+                probe("getMarketId()", Word),
+            ] },
+            Self::AaveV3AddressesProvider => const { &[
+                probe("getPool()", NonZero),
+                probe("getPriceOracle()", Word),
+                probe("getMarketId()", Word),
+            ] },
+            Self::AaveProtocolDataProvider => const { &[
+                probe("ADDRESSES_PROVIDER()", NonZero),
+                probe("getAllReservesTokens()", Word),
+            ] },
+            Self::AaveV2Oracle => const { &[probe("WETH()", NonZero), probe("getFallbackOracle()", Word)] },
+            Self::AaveV3Oracle => const { &[probe("BASE_CURRENCY()", Word), probe("getFallbackOracle()", Word)] },
+            Self::AaveWethGateway => const { &[probe("getWETHAddress()", NonZero)] },
+            Self::CompoundComptroller => const { &[
+                probe("isComptroller()", True),
+                probe("getAllMarkets()", Word),
+                probe("oracle()", Word),
+            ] },
+            Self::CompoundCToken => const { &[
+                probe("isCToken()", True),
+                probe("comptroller()", NonZero),
+                probe("exchangeRateStored()", NonZero),
+            ] },
+            Self::CompoundV3Comet => const { &[probe("baseToken()", NonZero), probe("getUtilization()", Word)] },
+            Self::Erc20 => const { &[probe("totalSupply()", Word), probe("decimals()", Word)] },
+            Self::UniswapV3Factory => const { &[
+                probe("owner()", Word),
+                SelectorProbe { signature: "feeAmountTickSpacing(uint24)", args: &[500], expect: NonZero },
+            ] },
+            Self::UniswapV3Router | Self::UniswapV3Quoter => const { &[
+                probe("WETH9()", NonZero),
+                probe("factory()", NonZero),
+            ] },
+            Self::UniswapV3PositionManager => const { &[
+                probe("WETH9()", NonZero),
+                probe("factory()", NonZero),
+                probe("totalSupply()", Word),
+            ] },
+            Self::UniswapV2Factory => const { &[probe("allPairsLength()", Word), probe("feeToSetter()", Word)] },
+            Self::UniswapV2Router => const { &[probe("WETH()", NonZero), probe("factory()", NonZero)] },
+            Self::TraderJoeRouter => const { &[probe("WAVAX()", NonZero), probe("factory()", NonZero)] },
+            Self::SushiMasterChef => const { &[probe("sushi()", NonZero), probe("poolLength()", Word)] },
+            Self::SushiMiniChefV2 => const { &[probe("SUSHI()", NonZero), probe("poolLength()", Word)] },
+            Self::MakerVat => const { &[probe("live()", True), probe("Line()", Word), probe("debt()", Word)] },
+            Self::MakerJug => const { &[probe("vat()", NonZero), probe("base()", Word), probe("vow()", NonZero)] },
+            Self::MakerSpotter => const { &[probe("vat()", NonZero), probe("par()", NonZero)] },
+            Self::MakerCdpManager => const { &[probe("vat()", NonZero), probe("cdpi()", Word)] },
+            Self::MakerGemJoin => const { &[probe("gem()", NonZero), probe("ilk()", NonZero), probe("dec()", NonZero)] },
+            Self::MakerDaiJoin => const { &[probe("dai()", NonZero), probe("live()", True)] },
+            Self::CurveStableSwap => const { &[probe("get_virtual_price()", NonZero), probe("A()", NonZero)] },
+            Self::CurveGaugeController => const { &[probe("token()", NonZero), probe("voting_escrow()", NonZero)] },
+            Self::ConvexBooster => const { &[probe("crv()", NonZero), probe("poolLength()", NonZero)] },
+        }
+    }
+
+    /// Other versions of the same role an address may have been configured with by mistake
+    fn alternatives(self) -> &'static [ProtocolInterface] {
+        match self {
+            Self::AaveV2LendingPool => &[Self::AaveV3Pool],
+            Self::AaveV3Pool => &[Self::AaveV2LendingPool],
+            Self::AaveV2AddressesProvider => &[Self::AaveV3AddressesProvider],
+            Self::AaveV3AddressesProvider => &[Self::AaveV2AddressesProvider],
+            Self::AaveV2Oracle => &[Self::AaveV3Oracle],
+            Self::AaveV3Oracle => &[Self::AaveV2Oracle],
+            Self::CompoundComptroller => &[Self::CompoundV3Comet],
+            Self::CompoundCToken => &[Self::CompoundV3Comet],
+            Self::CompoundV3Comet => &[Self::CompoundCToken],
+            Self::UniswapV3Factory => &[Self::UniswapV2Factory],
+            Self::UniswapV2Factory => &[Self::UniswapV3Factory],
+            Self::UniswapV3Router => &[Self::UniswapV2Router],
+            Self::UniswapV2Router => &[Self::UniswapV3Router],
+            Self::SushiMasterChef => &[Self::SushiMiniChefV2],
+            Self::SushiMiniChefV2 => &[Self::SushiMasterChef],
+            _ => &[],
+        }
+    }
+}
+
+/// Address-registry entry: the contract a protocol integration calls for one role on one chain
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractDeployment {
+    pub chain_id: u64,
+    pub protocol: String,
+    /// Field of the protocol's contract registry, e.g. `lending_pool`
+    pub role: String,
+    pub address: Address,
+    pub interface: ProtocolInterface,
+}
+
+impl ContractDeployment {
+    pub fn new(chain_id: u64, protocol: &str, role: &str, address: Address, interface: ProtocolInterface) -> Self {
+        Self {
+            chain_id,
+            protocol: protocol.to_string(),
+            role: role.to_string(),
+            address,
+            interface,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentStatus {
+    /// Every expected selector answered
+    Verified,
+    /// Another version of the protocol is deployed at the address
+    WrongVersion,
+    /// The contract exposes neither the expected interface nor a known alternative
+    InterfaceMismatch,
+    /// Nothing is deployed at the address
+    NoCode,
+    /// The chain could not be reached; probed again on the next run
+    Unchecked,
+}
+
+impl DeploymentStatus {
+    /// Whether transactions built against the address would fail
+    pub fn is_misconfigured(self) -> bool {
+        matches!(self, Self::WrongVersion | Self::InterfaceMismatch | Self::NoCode)
+    }
+}
+
+/// Outcome of probing one registry entry
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentCheck {
+    #[serde(flatten)]
+    pub deployment: ContractDeployment,
+    pub status: DeploymentStatus,
+    /// Interface the probes matched, when any
+    pub detected: Option<ProtocolInterface>,
+    /// Expected functions that did not answer as they should
+    pub missing: Vec<String>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Probes the protocol address registries on startup so a wrong or outdated address is
+/// flagged before transactions built against it fail
+pub struct DeploymentProber {
+    chain_manager: Arc<ChainManager>,
+    deployments: Vec<ContractDeployment>,
+    checks: RwLock<Vec<DeploymentCheck>>,
+}
+
+impl DeploymentProber {
+    pub fn new(chain_manager: Arc<ChainManager>, deployments: Vec<ContractDeployment>) -> Self {
+        Self {
+            chain_manager,
+            deployments,
+            checks: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Probe every deployment in the background, given up on shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let checks = tokio::select! {
+                checks = self.probe_all() => checks,
+                _ = shutdown.cancelled() => return,
+            };
+            let misconfigured = checks.iter().filter(|check| check.status.is_misconfigured()).count();
+            info!("Probed {} protocol contracts, {} misconfigured", checks.len(), misconfigured);
+        })
+    }
+
+    /// Latest checks, empty until the first probe finished
+    pub async fn checks(&self) -> Vec<DeploymentCheck> {
+        self.checks.read().await.clone()
+    }
+
+    /// Probe every deployment on the chains this instance serves
+    pub async fn probe_all(&self) -> Vec<DeploymentCheck> {
+        let chain_ids = self.chain_manager.chain_ids().await;
+        let mut checks = Vec::new();
+        for deployment in self.deployments.iter().filter(|deployment| chain_ids.contains(&deployment.chain_id)) {
+            let check = self.probe(deployment).await;
+            if check.status.is_misconfigured() {
+                warn!(
+                    "{} {} on chain {} at {:?} is {:?} (expected {:?}, detected {:?}, missing {:?})",
+                    check.deployment.protocol, check.deployment.role, check.deployment.chain_id,
+                    check.deployment.address, check.status, check.deployment.interface, check.detected, check.missing
+                );
+            }
+            checks.push(check);
+        }
+        *self.checks.write().await = checks.clone();
+        checks
+    }
+
+    async fn probe(&self, deployment: &ContractDeployment) -> DeploymentCheck {
+        let mut check = DeploymentCheck {
+            deployment: deployment.clone(),
+            status: DeploymentStatus::Unchecked,
+            detected: None,
+            missing: Vec::new(),
+            error: None,
+            checked_at: Utc::now(),
+        };
+        if let Err(e) = self.classify(&mut check).await {
+            check.status = DeploymentStatus::Unchecked;
+            check.error = Some(e.to_string());
+        }
+        check
+    }
+
+    async fn classify(&self, check: &mut DeploymentCheck) -> Result<()> {
+        let (chain_id, address, expected) = (check.deployment.chain_id, check.deployment.address, check.deployment.interface);
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        if chain.provider.get_code(address, None).await?.is_empty() {
+            check.status = DeploymentStatus::NoCode;
+            return Ok(());
+        }
+
+        check.missing = self.failed_probes(chain_id, address, expected).await?;
+        if check.missing.is_empty() {
+            check.status = DeploymentStatus::Verified;
+            check.detected = Some(expected);
+            return Ok(());
+        }
+
+        for &alternative in expected.alternatives() {
+            if self.failed_probes(chain_id, address, alternative).await?.is_empty() {
+                check.status = DeploymentStatus::WrongVersion;
+                check.detected = Some(alternative);
+                return Ok(());
+            }
+        }
+        check.status = DeploymentStatus::InterfaceMismatch;
+        Ok(())
+    }
+
+    /// Signatures of the interface's probes the contract does not answer as expected;
+    /// errors only when the node cannot be reached
+    async fn failed_probes(&self, chain_id: u64, address: Address, interface: ProtocolInterface) -> Result<Vec<String>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let mut failed = Vec::new();
+
+        for probe in interface.probes() {
+            let args: Vec<Token> = probe.args.iter().map(|&arg| Token::Uint(U256::from(arg))).collect();
+            let calldata: Vec<u8> = id(probe.signature).into_iter().chain(abi::encode(&args)).collect();
+            let tx: TypedTransaction = TransactionRequest::new().to(address).data(Bytes::from(calldata)).into();
+
+            let output = match chain.provider.call(&tx, None).await {
+                Ok(output) => output,
+                // The node answered with a revert: the function is not there
+                Err(e) if e.as_error_response().is_some() => {
+                    failed.push(probe.signature.to_string());
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // Missing functions routed to a fallback return nothing rather than a word
+            let answered = output.len() >= 32 && match probe.expect {
+                Expect::Word => true,
+                Expect::True => U256::from_big_endian(&output[..32]) == U256::one(),
+                Expect::NonZero => !U256::from_big_endian(&output[..32]).is_zero(),
+            };
+            if !answered {
+                failed.push(probe.signature.to_string());
+            }
+        }
+
+        Ok(failed)
+    }
+}
@@ -2,11 +2,30 @@ use std::{sync::Arc, collections::HashMap};
 use ethers::types::{Address, U256, H256, Bytes, TransactionRequest};
 use ethers::abi::{Abi, Token, ParamType, AbiEncode};
 use ethers::contract::Contract;
+use crate::cache::{CacheManager, NamespacedCache, TimedCache};
+use crate::chains::batch::RpcBatch;
 use crate::chains::ChainManager;
+use crate::contracts::multicall::multicall;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
 use crate::dex::DexManager;
 use anyhow::{Result, anyhow};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use std::time::Duration;
+
+/// Reserve rates and indexes move every block, reserve data is refreshed after this long
+const RESERVE_CACHE_TTL: Duration = Duration::from_secs(30);
+/// Account health changes with prices as well as the account's own transactions
+const USER_DATA_CACHE_TTL: Duration = Duration::from_secs(15);
+const USER_DATA_CACHE_MAX_ENTRIES: usize = 5_000;
+/// Oracle prices, read for every health factor check, are refreshed ahead of this TTL
+const PRICE_CACHE_TTL: Duration = Duration::from_secs(30);
+const PRICE_CACHE_MAX_ENTRIES: usize = 1_000;
+
+fn reserve_key(chain_id: u64, asset: Address) -> String {
+    format!("{}:{:?}", chain_id, asset)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AaveContracts {
@@ -16,6 +35,30 @@ pub struct AaveContracts {
     pub data_provider: Address,
     pub flash_loan_receiver: Address,
     pub weth_gateway: Address,
+    /// Pays reward emissions to depositors and borrowers
+    pub incentives_controller: Address,
+    /// Token the emissions are priced as: AAVE for the stkAAVE paid on mainnet
+    pub reward_token: Address,
+    /// Token claims pay out
+    pub claimed_reward_token: Address,
+}
+
+/// Reward emissions of a reserve, per second in reward token base units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReserveEmissions {
+    pub reward_token: Address,
+    pub supply_per_second: U256,
+    pub variable_borrow_per_second: U256,
+}
+
+/// Rewards a user has accrued across every reserve
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccruedRewards {
+    /// Token a claim pays out
+    pub claimed_token: Address,
+    /// Token it is priced as
+    pub reward_token: Address,
+    pub amount: U256,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +91,19 @@ pub struct ReserveData {
     pub utilization_rate: U256,
 }
 
+/// A reserve as of a past block, read from an archive node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReserveAtBlock {
+    pub asset: Address,
+    /// APYs in percent
+    pub supply_apy: f64,
+    pub variable_borrow_apy: f64,
+    /// Share of the collateral value counted towards the health factor
+    pub liquidation_threshold: f64,
+    /// Oracle price in wei of ETH
+    pub price_eth: U256,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserAccountData {
     pub total_collateral_eth: U256,
@@ -174,12 +230,13 @@ pub struct AaveManager {
     chain_manager: Arc<ChainManager>,
     dex_manager: Arc<DexManager>,
     contracts: HashMap<u64, AaveContracts>,
-    reserves_cache: Arc<tokio::sync::RwLock<HashMap<(u64, Address), ReserveData>>>,
-    user_data_cache: Arc<tokio::sync::RwLock<HashMap<(u64, Address), UserAccountData>>>,
+    reserves_cache: NamespacedCache<ReserveData>,
+    user_data_cache: TimedCache<(u64, Address), UserAccountData>,
+    prices_cache: TimedCache<(u64, Address), U256>,
 }
 
 impl AaveManager {
-    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>) -> Result<Self> {
+    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>, caches: &CacheManager) -> Result<Self> {
         let mut contracts = HashMap::new();
         
         // Ethereum mainnet contracts
@@ -190,6 +247,10 @@ impl AaveManager {
             data_provider: "0x057835Ad21a177dbdd3090bB1CAE03EaCF78Fc6d".parse()?,
             flash_loan_receiver: "0x1234567890123456789012345678901234567890".parse()?, // Placeholder
             weth_gateway: "0xcc9a0B7c43DC2a5F023Bb9b738E45B0Ef6B06E04".parse()?,
+            incentives_controller: "0xd784927Ff2f95ba542BfC824c8a8a98F3495f6b5".parse()?,
+            reward_token: "0x7Fc66500c84A76Ad7e9c93437bFc5Ac33E2DDaE9".parse()?,
+            // stkAAVE, redeemable for AAVE after its cooldown
+            claimed_reward_token: "0x4da27a545c0c5B758a6BA100e3a049001de870f5".parse()?,
         });
 
         // Polygon contracts
@@ -200,30 +261,174 @@ impl AaveManager {
             data_provider: "0x7551b5D2763519d4e37e8B81929D336De671d46d".parse()?,
             flash_loan_receiver: "0x1234567890123456789012345678901234567890".parse()?,
             weth_gateway: "0xbEadf48d62aCC944a06EEaE0A9054A90E5A7dc97".parse()?,
+            incentives_controller: "0x357D51124f59836DeD84c8a1730D72B749d8BC23".parse()?,
+            reward_token: "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270".parse()?,
+            claimed_reward_token: "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270".parse()?,
         });
 
         Ok(Self {
             chain_manager,
             dex_manager,
             contracts,
-            reserves_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
-            user_data_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            reserves_cache: caches.namespace("aave_reserves", RESERVE_CACHE_TTL),
+            user_data_cache: caches.timed("aave_user_data", USER_DATA_CACHE_TTL, USER_DATA_CACHE_MAX_ENTRIES),
+            prices_cache: caches.timed("aave_prices", PRICE_CACHE_TTL, PRICE_CACHE_MAX_ENTRIES),
         })
     }
 
+    /// Address-registry entries probed against the Aave V2 interfaces at startup
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| [
+                ContractDeployment::new(chain_id, "aave", "lending_pool", contracts.lending_pool, ProtocolInterface::AaveV2LendingPool),
+                ContractDeployment::new(chain_id, "aave", "lending_pool_addresses_provider", contracts.lending_pool_addresses_provider, ProtocolInterface::AaveV2AddressesProvider),
+                ContractDeployment::new(chain_id, "aave", "price_oracle", contracts.price_oracle, ProtocolInterface::AaveV2Oracle),
+                ContractDeployment::new(chain_id, "aave", "data_provider", contracts.data_provider, ProtocolInterface::AaveProtocolDataProvider),
+                ContractDeployment::new(chain_id, "aave", "weth_gateway", contracts.weth_gateway, ProtocolInterface::AaveWethGateway),
+            ])
+            .collect()
+    }
+
+    /// Drop cached reserve, user account and oracle price data
+    pub async fn clear_cache(&self) {
+        self.reserves_cache.invalidate_all().await;
+        self.user_data_cache.clear().await;
+        self.prices_cache.clear().await;
+    }
+
     pub async fn get_reserve_data(&self, chain_id: u64, asset: Address) -> Result<ReserveData> {
-        // Check cache first
-        {
-            let cache = self.reserves_cache.read().await;
-            if let Some(data) = cache.get(&(chain_id, asset)) {
-                return Ok(data.clone());
-            }
-        }
+        let contracts = self.contracts.get(&chain_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.reserves_cache
+            .get_or_load(&reserve_key(chain_id, asset), move || {
+                Self::fetch_reserve_data(chain_manager, contracts, chain_id, asset).boxed()
+            })
+            .await
+    }
+
+    /// Drop the cached data of a reserve, e.g. after a supply or borrow moved its rates
+    pub async fn invalidate_reserve(&self, chain_id: u64, asset: Address) {
+        self.reserves_cache.invalidate(&reserve_key(chain_id, asset)).await;
+    }
+
+    /// Drop the cached account data of a user, e.g. after their supply or borrow was mined
+    pub async fn invalidate_user(&self, chain_id: u64, user: Address) {
+        self.user_data_cache.invalidate(&(chain_id, user)).await;
+    }
+
+    /// Lending pool deposits are approved to, `None` on chains without Aave
+    pub fn lending_pool(&self, chain_id: u64) -> Option<Address> {
+        self.contracts.get(&chain_id).map(|contracts| contracts.lending_pool)
+    }
+
+    /// Emissions to a reserve's depositors and variable rate borrowers
+    pub async fn reserve_emissions(&self, chain_id: u64, reserve: &ReserveData) -> Result<ReserveEmissions> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let controller = Contract::new(
+            contracts.incentives_controller,
+            Self::get_incentives_controller_abi()?,
+            Arc::new(provider.provider.clone()),
+        );
+        let supply_call = controller.method::<_, (U256, U256, U256)>("assets", reserve.a_token_address)?;
+        let borrow_call = controller.method::<_, (U256, U256, U256)>("assets", reserve.variable_debt_token_address)?;
+        let mut batch = RpcBatch::new();
+        let supply = batch.call(&supply_call)?;
+        let borrow = batch.call(&borrow_call)?;
+        let results = provider.batch(&batch).await?;
+
+        Ok(ReserveEmissions {
+            reward_token: contracts.reward_token,
+            supply_per_second: results.decode(supply, &supply_call)?.0,
+            variable_borrow_per_second: results.decode(borrow, &borrow_call)?.0,
+        })
+    }
 
+    /// Chains the lending pool is deployed on
+    pub fn chains(&self) -> Vec<u64> {
+        self.contracts.keys().copied().collect()
+    }
+
+    /// Assets listed as reserves of the lending pool
+    pub async fn reserves(&self, chain_id: u64) -> Result<Vec<Address>> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let lending_pool = Contract::new(
+            contracts.lending_pool,
+            Self::get_lending_pool_abi()?,
+            Arc::new(provider.provider.clone()),
+        );
+        Ok(lending_pool.method::<_, Vec<Address>>("getReservesList", ())?.call().await?)
+    }
+
+    /// aTokens and debt tokens of every reserve, the balances emissions accrue on
+    async fn incentivized_tokens(&self, chain_id: u64) -> Result<Vec<Address>> {
         let contracts = self.contracts.get(&chain_id)
             .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let reserves = self.reserves(chain_id).await?;
+        let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+        let data_provider = Contract::new(contracts.data_provider, Self::get_data_provider_abi()?, provider.clone());
+        let calls = reserves.iter()
+            .map(|asset| data_provider.method::<_, (Address, Address, Address)>("getReserveTokensAddresses", *asset))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(multicall(&provider, chain_id, calls).await?
+            .into_iter()
+            .flatten()
+            .filter_map(Token::into_tuple)
+            .flatten()
+            .filter_map(Token::into_address)
+            .filter(|token| !token.is_zero())
+            .collect())
+    }
 
+    /// Rewards `user` can claim, including what accrued since their last interaction
+    pub async fn accrued_rewards(&self, chain_id: u64, user: Address) -> Result<AccruedRewards> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let assets = self.incentivized_tokens(chain_id).await?;
         let provider = self.chain_manager.get_provider(chain_id).await?;
+        let controller = Contract::new(
+            contracts.incentives_controller,
+            Self::get_incentives_controller_abi()?,
+            Arc::new(provider.provider.clone()),
+        );
+        let amount: U256 = controller.method("getRewardsBalance", (assets, user))?.call().await?;
+
+        Ok(AccruedRewards {
+            claimed_token: contracts.claimed_reward_token,
+            reward_token: contracts.reward_token,
+            amount,
+        })
+    }
+
+    /// Claim everything `user` accrued on every reserve to themselves; only `user` can send it
+    pub async fn claim_rewards(&self, chain_id: u64, user: Address) -> Result<TransactionRequest> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let assets = self.incentivized_tokens(chain_id).await?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let controller = Contract::new(
+            contracts.incentives_controller,
+            Self::get_incentives_controller_abi()?,
+            Arc::new(provider.provider.clone()),
+        );
+
+        let tx = controller.method::<_, U256>("claimRewards", (assets, U256::MAX, user))?.tx;
+        Ok(tx.into())
+    }
+
+    async fn fetch_reserve_data(
+        chain_manager: Arc<ChainManager>,
+        contracts: AaveContracts,
+        chain_id: u64,
+        asset: Address,
+    ) -> Result<ReserveData> {
+        let provider = chain_manager.get_provider(chain_id).await?;
         let data_provider_contract = Contract::new(
             contracts.data_provider,
             Self::get_data_provider_abi()?,
@@ -252,11 +457,33 @@ impl AaveManager {
             .call()
             .await?;
 
+        // Deposits left to borrow or withdraw and the debt drawn from them, in one round trip
+        let token_abi = Self::get_token_abi()?;
+        let client = Arc::new(provider.provider.clone());
+        let available_call = Contract::new(asset, token_abi.clone(), client.clone())
+            .method::<_, U256>("balanceOf", token_addresses.0)?;
+        let stable_debt_call = Contract::new(token_addresses.1, token_abi.clone(), client.clone())
+            .method::<_, U256>("totalSupply", ())?;
+        let variable_debt_call = Contract::new(token_addresses.2, token_abi, client)
+            .method::<_, U256>("totalSupply", ())?;
+        let mut batch = RpcBatch::new();
+        let available = batch.call(&available_call)?;
+        let stable_debt = batch.call(&stable_debt_call)?;
+        let variable_debt = batch.call(&variable_debt_call)?;
+        let results = provider.batch(&batch).await?;
+        let available_liquidity = results.decode(available, &available_call)?;
+        let total_stable_debt = results.decode(stable_debt, &stable_debt_call)?;
+        let total_variable_debt = results.decode(variable_debt, &variable_debt_call)?;
+        let total_debt = total_stable_debt.saturating_add(total_variable_debt);
+        let deposits = available_liquidity.saturating_add(total_debt);
+        // In ray, like the rates
+        let utilization_rate = if deposits.is_zero() { U256::zero() } else { total_debt * U256::exp10(27) / deposits };
+
         // Get symbol and decimals (mock for now)
         let symbol = format!("TOKEN_{}", &format!("{:?}", asset)[2..6].to_uppercase());
         let decimals = 18u8;
 
-        let reserve_data = ReserveData {
+        Ok(ReserveData {
             asset,
             symbol,
             decimals,
@@ -279,22 +506,20 @@ impl AaveManager {
             variable_debt_token_address: token_addresses.2,
             interest_rate_strategy_address: "0x0000000000000000000000000000000000000000".parse()?,
             last_update_timestamp: reserve_data.5.as_u64(),
-            available_liquidity: U256::zero(),
-            total_stable_debt: U256::zero(),
-            total_variable_debt: U256::zero(),
-            utilization_rate: U256::zero(),
-        };
-
-        // Cache the result
-        {
-            let mut cache = self.reserves_cache.write().await;
-            cache.insert((chain_id, asset), reserve_data.clone());
-        }
-
-        Ok(reserve_data)
+            available_liquidity,
+            total_stable_debt,
+            total_variable_debt,
+            utilization_rate,
+        })
     }
 
     pub async fn get_user_account_data(&self, chain_id: u64, user: Address) -> Result<UserAccountData> {
+        self.user_data_cache
+            .get_or_load((chain_id, user), || self.fetch_user_account_data(chain_id, user))
+            .await
+    }
+
+    async fn fetch_user_account_data(&self, chain_id: u64, user: Address) -> Result<UserAccountData> {
         let contracts = self.contracts.get(&chain_id)
             .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
 
@@ -605,9 +830,51 @@ impl AaveManager {
 
     pub async fn get_asset_price(&self, chain_id: u64, asset: Address) -> Result<U256> {
         let contracts = self.contracts.get(&chain_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.prices_cache
+            .get_or_refresh((chain_id, asset), move || {
+                Self::fetch_asset_price(chain_manager, contracts, chain_id, asset).boxed()
+            })
+            .await
+    }
 
-        let provider = self.chain_manager.get_provider(chain_id).await?;
+    /// Rates, liquidation threshold and oracle price of a reserve at `block`; needs an archive node
+    /// for blocks older than the node keeps state of
+    pub async fn reserve_at_block(&self, chain_id: u64, asset: Address, block: u64) -> Result<ReserveAtBlock> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+        let data_provider = Contract::new(contracts.data_provider, Self::get_data_provider_abi()?, provider.clone());
+        let oracle = Contract::new(contracts.price_oracle, Self::get_price_oracle_abi()?, provider);
+
+        let (liquidity_rate, variable_borrow_rate, ..): (U256, U256, U256, U256, U256, U256, bool, bool, bool, bool) = data_provider
+            .method("getReserveData", asset)?
+            .block(block)
+            .call()
+            .await?;
+        let (_, liquidation_threshold, ..): (U256, U256, U256, U256, bool, bool, bool, bool) = data_provider
+            .method("getReserveConfigurationData", asset)?
+            .block(block)
+            .call()
+            .await?;
+        let price_eth: U256 = oracle.method("getAssetPrice", asset)?.block(block).call().await?;
+        if price_eth.is_zero() {
+            return Err(anyhow!("Aave oracle has no price for {:?} at block {}", asset, block));
+        }
+
+        Ok(ReserveAtBlock {
+            asset,
+            supply_apy: (liquidity_rate.as_u128() as f64) / 1e27 * 100.0,
+            variable_borrow_apy: (variable_borrow_rate.as_u128() as f64) / 1e27 * 100.0,
+            liquidation_threshold: liquidation_threshold.as_u64() as f64 / 10_000.0,
+            price_eth,
+        })
+    }
+
+    async fn fetch_asset_price(chain_manager: Arc<ChainManager>, contracts: AaveContracts, chain_id: u64, asset: Address) -> Result<U256> {
+        let provider = chain_manager.get_provider(chain_id).await?;
         let oracle_contract = Contract::new(
             contracts.price_oracle,
             Self::get_price_oracle_abi()?,
@@ -700,6 +967,13 @@ impl AaveManager {
                 ],
                 "stateMutability": "view",
                 "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "getReservesList",
+                "outputs": [{"internalType": "address[]", "name": "", "type": "address[]"}],
+                "stateMutability": "view",
+                "type": "function"
             }
         ]"#;
 
@@ -760,6 +1034,66 @@ impl AaveManager {
         Ok(abi)
     }
 
+    fn get_incentives_controller_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "address", "name": "asset", "type": "address"}],
+                "name": "assets",
+                "outputs": [
+                    {"internalType": "uint104", "name": "emissionPerSecond", "type": "uint104"},
+                    {"internalType": "uint104", "name": "index", "type": "uint104"},
+                    {"internalType": "uint40", "name": "lastUpdateTimestamp", "type": "uint40"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "address[]", "name": "assets", "type": "address[]"},
+                    {"internalType": "address", "name": "user", "type": "address"}
+                ],
+                "name": "getRewardsBalance",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "address[]", "name": "assets", "type": "address[]"},
+                    {"internalType": "uint256", "name": "amount", "type": "uint256"},
+                    {"internalType": "address", "name": "to", "type": "address"}
+                ],
+                "name": "claimRewards",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+
+    fn get_token_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "address", "name": "account", "type": "address"}],
+                "name": "balanceOf",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "totalSupply",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+
     fn get_price_oracle_abi() -> Result<Abi> {
         let abi_json = r#"[
             {
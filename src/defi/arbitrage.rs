@@ -0,0 +1,352 @@
+// Cross-DEX round-trip arbitrage over a configured token universe, priced from live quotes
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, U256};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::analytics::gas_costs::{GasCostEstimator, GasFeeQuote};
+use crate::analytics::price_feeds::PriceFeedService;
+use crate::chains::assets::AssetRepresentation;
+use crate::defi::closeout::{flash_loan_premium, FLASH_LOAN_GAS};
+use crate::dex::aggregator::{DexType, Quote, QuoteComparison};
+use crate::dex::DexManager;
+use crate::shutdown::ShutdownSignal;
+
+/// Scans buffered for WebSocket clients that fall behind
+const SCAN_CHANNEL_CAPACITY: usize = 16;
+/// Pairs quoted at once, each runs a quote on every venue per leg
+const CONCURRENT_PAIRS: usize = 4;
+
+/// Arbitrage engine configuration
+#[derive(Debug, Clone)]
+pub struct ArbitrageConfig {
+    pub refresh_interval: Duration,
+    pub chain_ids: Vec<u64>,
+    /// Canonical asset ids whose tokens on each chain are paired with each other
+    pub assets: Vec<String>,
+    /// Size of each round trip in USD of the borrowed token
+    pub trade_size_usd: f64,
+    /// Opportunities netting less are left out of the API unless asked for
+    pub min_net_profit_usd: f64,
+}
+
+impl Default for ArbitrageConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval: Duration::from_secs(60),
+            chain_ids: vec![1],
+            assets: ["eth", "usdc", "usdt", "dai", "btc"].map(str::to_string).to_vec(),
+            trade_size_usd: 10_000.0,
+            min_net_profit_usd: 0.0,
+        }
+    }
+}
+
+impl ArbitrageConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut arbitrage_config = Self::default();
+
+        if let Ok(secs) = config.get_int("arbitrage_refresh_interval_secs") {
+            arbitrage_config.refresh_interval = Duration::from_secs(secs.max(5) as u64);
+        }
+        if let Ok(chain_ids) = config.get_string("arbitrage_chain_ids") {
+            arbitrage_config.chain_ids = chain_ids.split(',')
+                .filter_map(|chain_id| chain_id.trim().parse().ok())
+                .collect();
+        }
+        if let Ok(assets) = config.get_string("arbitrage_assets") {
+            arbitrage_config.assets = assets.split(',')
+                .map(|asset| asset.trim().to_lowercase())
+                .filter(|asset| !asset.is_empty())
+                .collect();
+        }
+        if let Ok(size) = config.get_float("arbitrage_trade_size_usd") {
+            arbitrage_config.trade_size_usd = size;
+        }
+        if let Ok(profit) = config.get_float("arbitrage_min_net_profit_usd") {
+            arbitrage_config.min_net_profit_usd = profit;
+        }
+
+        arbitrage_config
+    }
+}
+
+/// Flash-borrow `loan_token`, buy `intermediate_token` on one venue and sell it back on another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DexArbitrage {
+    pub chain_id: u64,
+    pub loan_token: Address,
+    pub loan_symbol: String,
+    pub intermediate_token: Address,
+    pub intermediate_symbol: String,
+    pub amount_in: U256,
+    pub buy_dex: DexType,
+    pub intermediate_amount: U256,
+    pub sell_dex: DexType,
+    pub amount_out: U256,
+    /// Value of what the round trip returns above its input
+    #[serde(with = "crate::api::models::usd")]
+    pub gross_profit_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub flash_loan_fee_usd: f64,
+    pub gas_units: u64,
+    #[serde(with = "crate::api::models::usd")]
+    pub gas_cost_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_profit_usd: f64,
+}
+
+/// Every round trip priced on a chain in one refresh, most profitable first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArbitrageScan {
+    pub chain_id: u64,
+    pub scanned_at: DateTime<Utc>,
+    #[serde(with = "crate::api::models::usd")]
+    pub trade_size_usd: f64,
+    pub pairs_scanned: usize,
+    /// Pairs no venue could quote both ways
+    pub pairs_failed: usize,
+    pub opportunities: Vec<DexArbitrage>,
+}
+
+/// Token of the universe with what sizing and valuing it needs
+struct PricedToken {
+    representation: AssetRepresentation,
+    price_usd: f64,
+    trade_amount: U256,
+}
+
+impl PricedToken {
+    fn value_usd(&self, amount: U256) -> f64 {
+        amount.to_string().parse::<f64>().unwrap_or(0.0) / 10f64.powi(self.representation.decimals as i32) * self.price_usd
+    }
+}
+
+/// Refreshes round-trip arbitrage across the DEX venues on an interval and streams each scan
+pub struct ArbitrageEngine {
+    dex_manager: Arc<DexManager>,
+    price_feeds: Arc<PriceFeedService>,
+    gas_costs: Arc<GasCostEstimator>,
+    config: ArbitrageConfig,
+    scans: RwLock<HashMap<u64, ArbitrageScan>>,
+    updates: broadcast::Sender<ArbitrageScan>,
+}
+
+impl ArbitrageEngine {
+    pub fn new(
+        dex_manager: Arc<DexManager>,
+        price_feeds: Arc<PriceFeedService>,
+        gas_costs: Arc<GasCostEstimator>,
+        config: ArbitrageConfig,
+    ) -> Self {
+        info!(
+            "Initializing ArbitrageEngine ({} on chains {:?}, refresh every {:?})",
+            config.assets.join(","), config.chain_ids, config.refresh_interval,
+        );
+        let (updates, _) = broadcast::channel(SCAN_CHANNEL_CAPACITY);
+
+        Self {
+            dex_manager,
+            price_feeds,
+            gas_costs,
+            config,
+            scans: RwLock::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// Rescan every configured chain in the background until shutdown, finishing the scan in progress
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.refresh_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        for chain_id in self.config.chain_ids.clone() {
+                            if let Err(e) = self.refresh(chain_id).await {
+                                warn!("Arbitrage scan on chain {} failed: {}", chain_id, e);
+                            }
+                        }
+                    }
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Arbitrage engine stopped");
+        })
+    }
+
+    pub fn config(&self) -> &ArbitrageConfig {
+        &self.config
+    }
+
+    /// Latest scan of each chain scanned so far
+    pub async fn latest(&self, chain_id: Option<u64>) -> Vec<ArbitrageScan> {
+        let scans = self.scans.read().await;
+        let mut latest: Vec<ArbitrageScan> = scans.values()
+            .filter(|scan| chain_id.is_none_or(|chain_id| scan.chain_id == chain_id))
+            .cloned()
+            .collect();
+        latest.sort_by_key(|scan| scan.chain_id);
+        latest
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ArbitrageScan> {
+        self.updates.subscribe()
+    }
+
+    /// Quote every pair of the universe on the chain both ways and publish the scan
+    pub async fn refresh(&self, chain_id: u64) -> Result<ArbitrageScan> {
+        let tokens = self.priced_universe(chain_id).await?;
+        // Every round trip of the refresh is priced at the same fees
+        let gas = self.gas_costs.fee_quote(chain_id).await?;
+
+        let pairs: Vec<(usize, usize)> = (0..tokens.len())
+            .flat_map(|loan| (loan + 1..tokens.len()).map(move |intermediate| (loan, intermediate)))
+            .collect();
+        let results: Vec<Result<Vec<DexArbitrage>>> = stream::iter(pairs.iter().copied())
+            .map(|(loan, intermediate)| self.round_trips(chain_id, &tokens[loan], &tokens[intermediate], &gas))
+            .buffer_unordered(CONCURRENT_PAIRS)
+            .collect()
+            .await;
+
+        let mut opportunities = Vec::new();
+        let mut pairs_failed = 0;
+        for result in results {
+            match result {
+                Ok(round_trips) => opportunities.extend(round_trips),
+                Err(e) => {
+                    debug!("Arbitrage pair on chain {} skipped: {}", chain_id, e);
+                    pairs_failed += 1;
+                }
+            }
+        }
+        opportunities.sort_by(|a, b| b.net_profit_usd.total_cmp(&a.net_profit_usd));
+
+        let scan = ArbitrageScan {
+            chain_id,
+            scanned_at: Utc::now(),
+            trade_size_usd: self.config.trade_size_usd,
+            pairs_scanned: pairs.len(),
+            pairs_failed,
+            opportunities,
+        };
+        info!(
+            "Arbitrage scan on chain {}: {} routes over {} pairs, {} profitable",
+            chain_id,
+            scan.opportunities.len(),
+            scan.pairs_scanned,
+            scan.opportunities.iter().filter(|opportunity| opportunity.net_profit_usd > 0.0).count(),
+        );
+        self.scans.write().await.insert(chain_id, scan.clone());
+        // Nobody may be listening
+        let _ = self.updates.send(scan.clone());
+        Ok(scan)
+    }
+
+    /// ERC-20 tokens of the configured assets on the chain, with a trade amount worth the trade size
+    async fn priced_universe(&self, chain_id: u64) -> Result<Vec<PricedToken>> {
+        let representations: Vec<AssetRepresentation> = self.dex_manager.assets().assets().iter()
+            .filter(|asset| self.config.assets.contains(&asset.id))
+            .flat_map(|asset| asset.representations.iter())
+            .filter(|representation| representation.chain_id == chain_id && !representation.address.is_zero())
+            .cloned()
+            .collect();
+        let addresses: Vec<Address> = representations.iter().map(|representation| representation.address).collect();
+        let prices = self.price_feeds.get_prices(chain_id, &addresses).await?;
+
+        let tokens: Vec<PricedToken> = representations.into_iter()
+            .filter_map(|representation| {
+                let price_usd = prices.get(&representation.address)?.price_usd;
+                if price_usd <= 0.0 {
+                    return None;
+                }
+                let units = self.config.trade_size_usd / price_usd * 10f64.powi(representation.decimals as i32);
+                Some(PricedToken { representation, price_usd, trade_amount: U256::from(units as u128) })
+            })
+            .collect();
+        if tokens.len() < 2 {
+            return Err(anyhow!("Fewer than two priced tokens to pair on chain {}", chain_id));
+        }
+        Ok(tokens)
+    }
+
+    /// Round trips borrowing `loan`, buying `intermediate` on one venue and selling it on each other one
+    async fn round_trips(
+        &self,
+        chain_id: u64,
+        loan: &PricedToken,
+        intermediate: &PricedToken,
+        gas: &GasFeeQuote,
+    ) -> Result<Vec<DexArbitrage>> {
+        let (loan_token, intermediate_token) = (loan.representation.address, intermediate.representation.address);
+        let buys = venue_quotes(
+            self.dex_manager.get_comprehensive_quotes(chain_id, loan_token, intermediate_token, loan.trade_amount, Address::zero()).await?,
+        );
+        let premium = flash_loan_premium(loan.trade_amount);
+
+        let mut round_trips = Vec::new();
+        for buy in buys {
+            let sells = match self.dex_manager.get_comprehensive_quotes(
+                chain_id,
+                intermediate_token,
+                loan_token,
+                buy.output_amount,
+                Address::zero(),
+            ).await {
+                Ok(comparison) => venue_quotes(comparison),
+                Err(e) => {
+                    debug!("No quote selling {:?} bought on {:?}: {}", intermediate_token, buy.dex, e);
+                    continue;
+                }
+            };
+            for sell in sells.into_iter().filter(|sell| sell.dex != buy.dex) {
+                let gas_units = FLASH_LOAN_GAS + buy.gas_estimate.as_u64() + sell.gas_estimate.as_u64();
+                let gross_profit_usd = loan.value_usd(sell.output_amount) - loan.value_usd(loan.trade_amount);
+                let flash_loan_fee_usd = loan.value_usd(premium);
+                let gas_cost_usd = gas.cost_usd(U256::from(gas_units));
+                round_trips.push(DexArbitrage {
+                    chain_id,
+                    loan_token,
+                    loan_symbol: loan.representation.symbol.clone(),
+                    intermediate_token,
+                    intermediate_symbol: intermediate.representation.symbol.clone(),
+                    amount_in: loan.trade_amount,
+                    buy_dex: buy.dex.clone(),
+                    intermediate_amount: buy.output_amount,
+                    sell_dex: sell.dex,
+                    amount_out: sell.output_amount,
+                    gross_profit_usd,
+                    flash_loan_fee_usd,
+                    gas_units,
+                    gas_cost_usd,
+                    net_profit_usd: gross_profit_usd - flash_loan_fee_usd - gas_cost_usd,
+                });
+            }
+        }
+        if round_trips.is_empty() {
+            return Err(anyhow!(
+                "No two venues quote {} and {} both ways",
+                loan.representation.symbol, intermediate.representation.symbol,
+            ));
+        }
+        Ok(round_trips)
+    }
+}
+
+/// Quote of each venue that returned one
+fn venue_quotes(comparison: QuoteComparison) -> Vec<Quote> {
+    [comparison.uniswap_v3, comparison.sushiswap, comparison.uniswap_v2]
+        .into_iter()
+        .flatten()
+        .filter(|quote| !quote.output_amount.is_zero())
+        .collect()
+}
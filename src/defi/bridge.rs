@@ -0,0 +1,638 @@
+// Cross-chain transfers through Across: quotes, deposits into the origin SpokePool and the
+// status of each transfer until a relayer fills it on the destination chain
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::{parse_abi, Abi, Token},
+    providers::Middleware,
+    types::{Address, Bytes, TransactionRequest, H256, U256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::api::models::TokenAmount;
+use crate::chains::assets::{AssetRegistry, AssetRepresentation};
+use crate::chains::reorg::{ChainReorg, ReorgHandler};
+use crate::chains::ChainManager;
+use crate::contracts::approvals::{ApprovalManager, TokenApproval, TokenSpend};
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store, TransactionTracker};
+
+const DEFAULT_API_URL: &str = "https://app.across.to/api";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Store used when `bridge_transfers_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/bridge_transfers.json";
+/// Fill deadline after the quote when the API does not suggest one
+const DEFAULT_FILL_WINDOW_SECS: u64 = 4 * 3600;
+/// Transfers kept before the oldest are dropped
+const MAX_TRANSFERS: usize = 10_000;
+/// Typical gas of a SpokePool deposit
+pub const DEPOSIT_GAS: u64 = 120_000;
+
+/// Bridge configuration
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    pub api_url: String,
+    pub timeout: Duration,
+    /// Interval between status checks of transfers in flight
+    pub poll_interval: Duration,
+    /// Days a bridged position is expected to be held, over which the bridge fee is spread when
+    /// comparing yields
+    pub cost_horizon_days: u64,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            api_url: DEFAULT_API_URL.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            poll_interval: Duration::from_secs(30),
+            cost_horizon_days: 90,
+        }
+    }
+}
+
+impl BridgeConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut bridge_config = Self::default();
+
+        if let Ok(url) = config.get_string("bridge_api_url") {
+            if !url.is_empty() {
+                bridge_config.api_url = url.trim_end_matches('/').to_string();
+            }
+        }
+        if let Ok(secs) = config.get_int("bridge_status_poll_interval_secs") {
+            bridge_config.poll_interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(days) = config.get_int("bridge_cost_horizon_days") {
+            bridge_config.cost_horizon_days = days.max(1) as u64;
+        }
+
+        bridge_config
+    }
+}
+
+/// Transfer to quote; the output token defaults to the input's asset on the destination chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeQuoteRequest {
+    pub origin_chain_id: u64,
+    pub destination_chain_id: u64,
+    pub input_token: Address,
+    #[serde(default)]
+    pub output_token: Option<Address>,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount: U256,
+}
+
+/// Transfer to build, `recipient` defaulting to the depositor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeTransferRequest {
+    #[serde(flatten)]
+    pub quote: BridgeQuoteRequest,
+    pub depositor: Address,
+    #[serde(default)]
+    pub recipient: Option<Address>,
+}
+
+/// Relayer fee and deadlines Across quoted for a transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeQuote {
+    pub bridge: String,
+    pub origin_chain_id: u64,
+    pub destination_chain_id: u64,
+    pub input_token: Address,
+    pub output_token: Address,
+    pub input_amount: TokenAmount,
+    /// Received on the destination chain, in the output token
+    pub output_amount: TokenAmount,
+    /// Relayer, LP and destination gas fees, in the input token
+    pub fee: TokenAmount,
+    #[serde(with = "crate::api::models::ratio")]
+    pub fee_percentage: f64,
+    pub expected_fill_time_secs: u64,
+    /// Contract the deposit is sent to
+    pub spoke_pool: Address,
+    /// Relayer with the exclusive right to fill until `exclusivity_deadline`, zero for any relayer
+    pub exclusive_relayer: Address,
+    pub quote_timestamp: u32,
+    /// After this timestamp an unfilled deposit is refunded on the origin chain
+    pub fill_deadline: u32,
+    pub exclusivity_deadline: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BridgeTransferStatus {
+    /// Built but no deposit seen on the origin chain
+    Built,
+    /// Deposited, waiting for a relayer to fill it
+    Deposited,
+    Filled,
+    /// Unfilled at the fill deadline, the refund is pending
+    Expired,
+    Refunded,
+    /// The deposit reverted
+    Failed,
+}
+
+impl BridgeTransferStatus {
+    /// Whether the status can still change
+    pub fn is_open(self) -> bool {
+        matches!(self, Self::Built | Self::Deposited | Self::Expired)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeTransfer {
+    pub id: String,
+    pub depositor: Address,
+    pub recipient: Address,
+    pub quote: BridgeQuote,
+    pub status: BridgeTransferStatus,
+    /// Transaction history records of the approval and deposit
+    pub transaction_ids: Vec<String>,
+    pub deposit_tx: Option<H256>,
+    pub deposit_block: Option<u64>,
+    /// Id the SpokePool assigned to the deposit, read from its receipt
+    pub deposit_id: Option<U256>,
+    pub fill_tx: Option<H256>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Transactions moving the tokens, the approval first when the SpokePool's allowance falls short
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeTransferPlan {
+    pub transfer: BridgeTransfer,
+    pub approval: Option<TokenApproval>,
+    pub transaction: TransactionRequest,
+}
+
+/// Across `/suggested-fees` response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AcrossFees {
+    total_relay_fee: AcrossFee,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    timestamp: U256,
+    #[serde(default)]
+    is_amount_too_low: bool,
+    spoke_pool_address: Address,
+    #[serde(default)]
+    exclusive_relayer: Address,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    exclusivity_deadline: U256,
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    fill_deadline: Option<U256>,
+    #[serde(default, deserialize_with = "crate::api::models::option_u256_lenient::deserialize")]
+    output_amount: Option<U256>,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    estimated_fill_time_sec: U256,
+}
+
+#[derive(Deserialize)]
+struct AcrossFee {
+    /// Share of the amount, 1e18 for 100%
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pct: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    total: U256,
+}
+
+/// Across `/deposit/status` response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AcrossDepositStatus {
+    status: String,
+    #[serde(default)]
+    fill_tx: Option<H256>,
+}
+
+/// Quotes and builds Across transfers and follows them until they settle
+pub struct BridgeManager {
+    chain_manager: Arc<ChainManager>,
+    assets: Arc<AssetRegistry>,
+    approvals: Arc<ApprovalManager>,
+    transactions: Arc<TransactionTracker>,
+    config: BridgeConfig,
+    http: reqwest::Client,
+    /// JSON file holding the transfers, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    transfers: RwLock<Vec<BridgeTransfer>>,
+}
+
+impl BridgeManager {
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        assets: Arc<AssetRegistry>,
+        approvals: Arc<ApprovalManager>,
+        transactions: Arc<TransactionTracker>,
+        config: BridgeConfig,
+        store_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let transfers: Vec<BridgeTransfer> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        info!("Initializing BridgeManager with {} transfers via {}", transfers.len(), config.api_url);
+
+        Ok(Self {
+            chain_manager,
+            assets,
+            approvals,
+            transactions,
+            http: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()
+                .unwrap_or_default(),
+            config,
+            store_path,
+            transfers: RwLock::new(transfers),
+        })
+    }
+
+    /// Manager persisting transfers to `bridge_transfers_store_path`, an empty path keeps them in memory
+    pub async fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        assets: Arc<AssetRegistry>,
+        approvals: Arc<ApprovalManager>,
+        transactions: Arc<TransactionTracker>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("bridge_transfers_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(chain_manager, assets, approvals, transactions, BridgeConfig::from_config(config), store_path).await
+    }
+
+    /// Follow transfers in flight until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.refresh_open().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Bridge tracker stopped");
+        })
+    }
+
+    /// APY the bridge fee costs when spread over the holding horizon, in percentage points
+    pub fn amortized_fee_apy(&self, fee_percentage: f64) -> f64 {
+        fee_percentage * 365.0 / self.config.cost_horizon_days as f64
+    }
+
+    pub fn cost_horizon_days(&self) -> u64 {
+        self.config.cost_horizon_days
+    }
+
+    pub async fn quote(&self, request: &BridgeQuoteRequest) -> Result<BridgeQuote> {
+        if request.origin_chain_id == request.destination_chain_id {
+            return Err(anyhow!("Origin and destination chain are both {}", request.origin_chain_id));
+        }
+        if request.amount.is_zero() {
+            return Err(anyhow!("Amount must be positive"));
+        }
+        // Across moves the wrapped token; the SpokePool only wraps native value for the WETH route
+        if request.input_token.is_zero() {
+            return Err(anyhow!("Bridge the wrapped token rather than the native token"));
+        }
+        let input = self.representation(request.origin_chain_id, request.input_token)?;
+        let output = match request.output_token {
+            Some(token) => self.representation(request.destination_chain_id, token)?,
+            None => self.destination_representation(request.origin_chain_id, request.input_token, request.destination_chain_id)?,
+        };
+
+        let response = self.http
+            .get(format!("{}/suggested-fees", self.config.api_url))
+            .query(&[
+                ("inputToken", format!("{:?}", input.address)),
+                ("outputToken", format!("{:?}", output.address)),
+                ("originChainId", request.origin_chain_id.to_string()),
+                ("destinationChainId", request.destination_chain_id.to_string()),
+                ("amount", request.amount.to_string()),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Across returned {}: {}", response.status(), response.text().await.unwrap_or_default()));
+        }
+        let fees: AcrossFees = response.json().await?;
+        if fees.is_amount_too_low {
+            return Err(anyhow!("{} {} is below the minimum Across relays", TokenAmount::new(request.amount, input.decimals).formatted, input.symbol));
+        }
+
+        let fee = fees.total_relay_fee.total;
+        let output_amount = match fees.output_amount {
+            Some(amount) => amount,
+            None if input.decimals == output.decimals => request.amount.saturating_sub(fee),
+            None => return Err(anyhow!("Across did not quote the output of {} into {}", input.symbol, output.symbol)),
+        };
+        let quote_timestamp = fees.timestamp.low_u64();
+        let fill_deadline = fees.fill_deadline
+            .map(|deadline| deadline.low_u64())
+            .unwrap_or(quote_timestamp + DEFAULT_FILL_WINDOW_SECS);
+
+        Ok(BridgeQuote {
+            bridge: "across".to_string(),
+            origin_chain_id: request.origin_chain_id,
+            destination_chain_id: request.destination_chain_id,
+            input_token: input.address,
+            output_token: output.address,
+            input_amount: TokenAmount::new(request.amount, input.decimals),
+            output_amount: TokenAmount::new(output_amount, output.decimals),
+            fee: TokenAmount::new(fee, input.decimals),
+            fee_percentage: fees.total_relay_fee.pct.to_string().parse::<f64>().unwrap_or_default() / 1e16,
+            expected_fill_time_secs: fees.estimated_fill_time_sec.low_u64(),
+            spoke_pool: fees.spoke_pool_address,
+            exclusive_relayer: fees.exclusive_relayer,
+            quote_timestamp: quote_timestamp as u32,
+            fill_deadline: fill_deadline as u32,
+            exclusivity_deadline: fees.exclusivity_deadline.low_u32(),
+        })
+    }
+
+    /// Quote a transfer and build its deposit, recording both transactions and the transfer
+    pub async fn build_transfer(&self, request: &BridgeTransferRequest) -> Result<BridgeTransferPlan> {
+        let quote = self.quote(&request.quote).await?;
+        let recipient = request.recipient.unwrap_or(request.depositor);
+        let chain_id = quote.origin_chain_id;
+
+        let spend = TokenSpend { token: quote.input_token, spender: quote.spoke_pool, amount: quote.input_amount.raw };
+        let approval = self.approvals.required_approval(chain_id, request.depositor, spend, None).await?;
+        let transaction = deposit_transaction(&quote, request.depositor, recipient)?;
+
+        let mut transaction_ids = Vec::new();
+        if let Some(approval) = &approval {
+            let record = self.transactions.record_built(chain_id, Some(request.depositor), "bridge:approve", &approval.transaction).await;
+            transaction_ids.push(record.id);
+        }
+        let record = self.transactions.record_built(chain_id, Some(request.depositor), "bridge:deposit", &transaction).await;
+        transaction_ids.push(record.id);
+
+        let now = Utc::now();
+        let transfer = BridgeTransfer {
+            id: uuid::Uuid::new_v4().to_string(),
+            depositor: request.depositor,
+            recipient,
+            quote,
+            status: BridgeTransferStatus::Built,
+            transaction_ids,
+            deposit_tx: None,
+            deposit_block: None,
+            deposit_id: None,
+            fill_tx: None,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        info!(
+            "Built bridge transfer {} of {} from chain {} to chain {}",
+            transfer.id, transfer.quote.input_amount.formatted, chain_id, transfer.quote.destination_chain_id,
+        );
+
+        let mut transfers = self.transfers.write().await;
+        transfers.push(transfer.clone());
+        self.persist(&mut transfers).await;
+        Ok(BridgeTransferPlan { transfer, approval, transaction })
+    }
+
+    /// Link the broadcast deposit to its transfer and read its status right away
+    pub async fn record_deposit(&self, id: &str, tx_hash: H256) -> Result<BridgeTransfer> {
+        {
+            let mut transfers = self.transfers.write().await;
+            let transfer = transfers.iter_mut()
+                .find(|transfer| transfer.id == id)
+                .ok_or_else(|| anyhow!("Unknown bridge transfer {}", id))?;
+            if let Some(existing) = transfer.deposit_tx.filter(|hash| *hash != tx_hash) {
+                return Err(anyhow!("Bridge transfer {} already has deposit {:?}", id, existing));
+            }
+            transfer.deposit_tx = Some(tx_hash);
+            transfer.updated_at = Utc::now();
+            self.persist(&mut transfers).await;
+        }
+        self.refresh(id).await
+    }
+
+    pub async fn get(&self, id: &str) -> Option<BridgeTransfer> {
+        self.transfers.read().await.iter().find(|transfer| transfer.id == id).cloned()
+    }
+
+    /// Transfers of a depositor or all of them, newest first
+    pub async fn list(&self, depositor: Option<Address>) -> Vec<BridgeTransfer> {
+        self.transfers.read().await.iter()
+            .rev()
+            .filter(|transfer| depositor.is_none_or(|depositor| transfer.depositor == depositor))
+            .cloned()
+            .collect()
+    }
+
+    /// Advance a transfer from the deposit receipt and the relayer's fill
+    pub async fn refresh(&self, id: &str) -> Result<BridgeTransfer> {
+        let mut transfer = self.get(id).await.ok_or_else(|| anyhow!("Unknown bridge transfer {}", id))?;
+        if !transfer.status.is_open() {
+            return Ok(transfer);
+        }
+
+        match self.advance(&mut transfer).await {
+            Ok(()) => transfer.last_error = None,
+            Err(e) => {
+                debug!("Status of bridge transfer {} not read: {}", transfer.id, e);
+                transfer.last_error = Some(e.to_string());
+            }
+        }
+        transfer.updated_at = Utc::now();
+
+        let mut transfers = self.transfers.write().await;
+        if let Some(stored) = transfers.iter_mut().find(|stored| stored.id == transfer.id) {
+            *stored = transfer.clone();
+        }
+        self.persist(&mut transfers).await;
+        Ok(transfer)
+    }
+
+    async fn advance(&self, transfer: &mut BridgeTransfer) -> Result<()> {
+        if transfer.status == BridgeTransferStatus::Built {
+            let Some(hash) = transfer.deposit_tx else {
+                // A deposit sent after the deadline would revert, so the quote is dead
+                if Utc::now().timestamp() > transfer.quote.fill_deadline as i64 {
+                    transfer.status = BridgeTransferStatus::Expired;
+                }
+                return Ok(());
+            };
+            let chain = self.chain_manager.get_provider(transfer.quote.origin_chain_id).await?;
+            let Some(receipt) = chain.provider.get_transaction_receipt(hash).await? else {
+                return Ok(());
+            };
+            if receipt.status.is_none_or(|status| status.as_u64() != 1) {
+                transfer.status = BridgeTransferStatus::Failed;
+                return Ok(());
+            }
+            let deposit_id = receipt.logs.iter()
+                .filter(|log| log.address == transfer.quote.spoke_pool)
+                .find(|log| log.topics.len() == 4 && deposit_topics().contains(&log.topics[0]))
+                .map(|log| U256::from_big_endian(log.topics[2].as_bytes()))
+                .ok_or_else(|| anyhow!("Deposit {:?} emitted no SpokePool deposit event", hash))?;
+            transfer.deposit_id = Some(deposit_id);
+            transfer.deposit_block = receipt.block_number.map(|block| block.as_u64());
+            transfer.status = BridgeTransferStatus::Deposited;
+            info!("Bridge transfer {} deposited as {} on chain {}", transfer.id, deposit_id, transfer.quote.origin_chain_id);
+        }
+
+        let Some(deposit_id) = transfer.deposit_id else {
+            return Ok(());
+        };
+        let response = self.http
+            .get(format!("{}/deposit/status", self.config.api_url))
+            .query(&[
+                ("originChainId", transfer.quote.origin_chain_id.to_string()),
+                ("depositId", deposit_id.to_string()),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Across returned {}: {}", response.status(), response.text().await.unwrap_or_default()));
+        }
+        let status: AcrossDepositStatus = response.json().await?;
+        let previous = transfer.status;
+        transfer.status = match status.status.as_str() {
+            "filled" => BridgeTransferStatus::Filled,
+            "expired" => BridgeTransferStatus::Expired,
+            "refunded" => BridgeTransferStatus::Refunded,
+            _ => BridgeTransferStatus::Deposited,
+        };
+        transfer.fill_tx = status.fill_tx.or(transfer.fill_tx);
+        if transfer.status != previous {
+            info!("Bridge transfer {} is {:?}", transfer.id, transfer.status);
+        }
+        Ok(())
+    }
+
+    async fn refresh_open(&self) {
+        let open: Vec<String> = self.transfers.read().await.iter()
+            .filter(|transfer| transfer.status.is_open())
+            .map(|transfer| transfer.id.clone())
+            .collect();
+        for id in open {
+            if let Err(e) = self.refresh(&id).await {
+                warn!("Bridge transfer {} refresh failed: {}", id, e);
+            }
+        }
+    }
+
+    fn representation(&self, chain_id: u64, token: Address) -> Result<AssetRepresentation> {
+        self.assets.resolve(chain_id, token)
+            .map(|(_, representation)| representation.clone())
+            .ok_or_else(|| anyhow!("{:?} on chain {} is not in the asset registry", token, chain_id))
+    }
+
+    /// Token of the same asset on the destination chain, preferring the native issue over a
+    /// bridged copy and skipping the gas token
+    fn destination_representation(&self, chain_id: u64, token: Address, destination_chain_id: u64) -> Result<AssetRepresentation> {
+        let (asset, _) = self.assets.resolve(chain_id, token)
+            .ok_or_else(|| anyhow!("{:?} on chain {} is not in the asset registry", token, chain_id))?;
+        asset.representations.iter()
+            .filter(|representation| representation.chain_id == destination_chain_id && !representation.address.is_zero())
+            .min_by_key(|representation| representation.kind as u8)
+            .cloned()
+            .ok_or_else(|| anyhow!("{} has no token on chain {}", asset.id, destination_chain_id))
+    }
+
+    /// Trim to the retention limit and write the store, keeping the in-memory state on failure
+    async fn persist(&self, transfers: &mut Vec<BridgeTransfer>) {
+        if transfers.len() > MAX_TRANSFERS {
+            let excess = transfers.len() - MAX_TRANSFERS;
+            transfers.drain(..excess);
+        }
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        if let Err(e) = write_store(path, transfers.as_slice()).await {
+            warn!("Failed to persist bridge transfers to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl ReorgHandler for BridgeManager {
+    fn name(&self) -> &'static str {
+        "bridge"
+    }
+
+    /// Re-read deposits mined in orphaned blocks, whose deposit id may have changed
+    async fn handle_reorg(&self, reorg: &ChainReorg) -> Result<()> {
+        let mut transfers = self.transfers.write().await;
+        let mut orphaned = 0;
+        for transfer in transfers.iter_mut() {
+            let reorganized = transfer.quote.origin_chain_id == reorg.chain_id
+                && transfer.status == BridgeTransferStatus::Deposited
+                && transfer.deposit_block.is_some_and(|block| block > reorg.fork_block);
+            if reorganized {
+                transfer.status = BridgeTransferStatus::Built;
+                transfer.deposit_id = None;
+                transfer.deposit_block = None;
+                transfer.updated_at = Utc::now();
+                orphaned += 1;
+            }
+        }
+        if orphaned > 0 {
+            info!("{} bridge deposits on chain {} are re-read after the reorg", orphaned, reorg.chain_id);
+            self.persist(&mut transfers).await;
+        }
+        Ok(())
+    }
+}
+
+/// `depositV3` of the quoted transfer, sent to the origin SpokePool
+fn deposit_transaction(quote: &BridgeQuote, depositor: Address, recipient: Address) -> Result<TransactionRequest> {
+    let data = spoke_pool_abi()?.function("depositV3")?.encode_input(&[
+        Token::Address(depositor),
+        Token::Address(recipient),
+        Token::Address(quote.input_token),
+        Token::Address(quote.output_token),
+        Token::Uint(quote.input_amount.raw),
+        Token::Uint(quote.output_amount.raw),
+        Token::Uint(U256::from(quote.destination_chain_id)),
+        Token::Address(quote.exclusive_relayer),
+        Token::Uint(U256::from(quote.quote_timestamp)),
+        Token::Uint(U256::from(quote.fill_deadline)),
+        Token::Uint(U256::from(quote.exclusivity_deadline)),
+        Token::Bytes(Vec::new()),
+    ])?;
+
+    Ok(TransactionRequest::new()
+        .from(depositor)
+        .to(quote.spoke_pool)
+        .data(Bytes::from(data))
+        .value(U256::zero())
+        .gas(DEPOSIT_GAS)
+        .chain_id(quote.origin_chain_id))
+}
+
+fn spoke_pool_abi() -> Result<Abi> {
+    Ok(parse_abi(&[
+        "function depositV3(address depositor, address recipient, address inputToken, address outputToken, uint256 inputAmount, uint256 outputAmount, uint256 destinationChainId, address exclusiveRelayer, uint32 quoteTimestamp, uint32 fillDeadline, uint32 exclusivityDeadline, bytes message) payable",
+    ])?)
+}
+
+/// Deposit events of the V3 SpokePool and its successor, both indexing the destination chain,
+/// deposit id and depositor
+fn deposit_topics() -> [H256; 2] {
+    [
+        H256::from(keccak256("V3FundsDeposited(address,address,uint256,uint256,uint256,uint32,uint32,uint32,uint32,address,address,address,bytes)")),
+        H256::from(keccak256("FundsDeposited(bytes32,bytes32,uint256,uint256,uint256,uint256,uint32,uint32,uint32,bytes32,bytes32,bytes32,bytes)")),
+    ]
+}
@@ -0,0 +1,287 @@
+// Full exit of a wallet's DeFi positions into a single stablecoin
+use ethers::types::{Address, TransactionRequest, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::api::models::TokenAmount;
+use crate::dex::aggregator::min_amount_out;
+use super::collateral_optimizer::LendingMarket;
+use super::flash_loans::FlashLoanOperation;
+
+/// Aave flash loan premium
+pub const FLASH_LOAN_PREMIUM_BPS: u64 = 9;
+/// Typical gas per action; steps run after earlier ones land, so they cannot be simulated up front
+pub const UNSTAKE_GAS: u64 = 150_000;
+pub const REMOVE_LIQUIDITY_GAS: u64 = 200_000;
+pub const REPAY_GAS: u64 = 200_000;
+pub const WITHDRAW_GAS: u64 = 250_000;
+pub const FLASH_SWAP_GAS: u64 = 180_000;
+/// Flash loan overhead on top of the operations executed inside it
+pub const FLASH_LOAN_GAS: u64 = 300_000;
+
+fn default_max_slippage() -> f64 {
+    1.0
+}
+
+/// LP tokens of a SushiSwap pair held in the wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityHolding {
+    pub token_a: Address,
+    pub token_b: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub liquidity: U256,
+}
+
+/// SushiSwap farm holding the wallet's LP tokens of a pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FarmHolding {
+    pub pid: u64,
+    pub token_a: Address,
+    pub token_b: Address,
+}
+
+/// Positions to exit besides the lending positions, which are read on-chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloseoutRequest {
+    pub chain_id: u64,
+    /// Every proceed is swapped into this token
+    pub stablecoin: Address,
+    #[serde(default)]
+    pub liquidity: Vec<LiquidityHolding>,
+    #[serde(default)]
+    pub farms: Vec<FarmHolding>,
+    /// Contract running the flash loan operations, required when the wallet cannot repay a debt
+    pub flash_loan_receiver: Option<Address>,
+    #[serde(default = "default_max_slippage")]
+    pub max_slippage_percentage: f64,
+}
+
+/// Amount borrowed through the flash loan and the premium owed on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlashLoanLeg {
+    pub asset: Address,
+    pub amount: U256,
+    pub premium: U256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CloseoutAction {
+    UnstakeFarm {
+        pid: u64,
+        lp_amount: U256,
+    },
+    RemoveLiquidity {
+        token_a: Address,
+        token_b: Address,
+        liquidity: U256,
+        expected_a: U256,
+        expected_b: U256,
+    },
+    Repay {
+        market: LendingMarket,
+        asset: Address,
+        amount: U256,
+    },
+    /// Repays debts the wallet cannot cover, withdraws the collateral they locked and sells
+    /// enough of it to return the loans in the same transaction
+    FlashLoanRepay {
+        loans: Vec<FlashLoanLeg>,
+        operations: Vec<FlashLoanOperation>,
+    },
+    Withdraw {
+        market: LendingMarket,
+        asset: Address,
+        amount: U256,
+    },
+    Swap {
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        expected_output: U256,
+        dex: String,
+        /// In percent
+        price_impact: f64,
+    },
+}
+
+/// One step of the plan, to be executed after every earlier step confirmed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloseoutStep {
+    #[serde(flatten)]
+    pub action: CloseoutAction,
+    pub transactions: Vec<TransactionRequest>,
+    pub gas_estimate: U256,
+}
+
+impl CloseoutStep {
+    pub fn new(action: CloseoutAction, transactions: Vec<TransactionRequest>, gas_estimate: u64) -> Self {
+        Self { action, transactions, gas_estimate: U256::from(gas_estimate) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloseoutCosts {
+    pub gas_units: U256,
+    /// `None` when the gas price or native token price is unavailable
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub gas_cost_usd: Option<f64>,
+    #[serde(with = "crate::api::models::usd")]
+    pub flash_loan_fees_usd: f64,
+    /// Value lost to price impact across the swaps
+    #[serde(with = "crate::api::models::usd")]
+    pub price_impact_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub total_usd: f64,
+}
+
+impl CloseoutCosts {
+    pub fn new(gas_units: U256, gas_cost_usd: Option<f64>, flash_loan_fees_usd: f64, price_impact_usd: f64) -> Self {
+        Self {
+            gas_units,
+            gas_cost_usd,
+            flash_loan_fees_usd,
+            price_impact_usd,
+            total_usd: gas_cost_usd.unwrap_or(0.0) + flash_loan_fees_usd + price_impact_usd,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloseoutPlan {
+    pub user: Address,
+    pub chain_id: u64,
+    pub stablecoin: Address,
+    /// Farms, then LP, then repays, then withdrawals, then swaps into the stablecoin
+    pub steps: Vec<CloseoutStep>,
+    /// Stablecoin the wallet gains once every step executed at its quote
+    pub expected_proceeds: TokenAmount,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub expected_proceeds_usd: Option<f64>,
+    pub costs: CloseoutCosts,
+    /// Execution the steps settle under once every one of them confirmed, `None` without steps
+    pub execution_id: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Wallet balances as the plan's steps execute, sizing repays and the final swaps
+#[derive(Debug, Default)]
+pub struct WalletLedger {
+    opening: HashMap<Address, U256>,
+    balances: HashMap<Address, U256>,
+}
+
+impl WalletLedger {
+    pub fn is_opened(&self, token: Address) -> bool {
+        self.opening.contains_key(&token)
+    }
+
+    /// Record what the wallet held before the close-out, which is not swapped
+    pub fn open(&mut self, token: Address, balance: U256) {
+        let opening = self.opening.entry(token).or_default();
+        *opening = opening.saturating_add(balance);
+        self.credit(token, balance);
+    }
+
+    pub fn balance(&self, token: Address) -> U256 {
+        self.balances.get(&token).copied().unwrap_or_default()
+    }
+
+    pub fn credit(&mut self, token: Address, amount: U256) {
+        let balance = self.balances.entry(token).or_default();
+        *balance = balance.saturating_add(amount);
+    }
+
+    pub fn debit(&mut self, token: Address, amount: U256) {
+        let balance = self.balances.entry(token).or_default();
+        *balance = balance.saturating_sub(amount);
+    }
+
+    /// Gain of a token over its opening balance
+    pub fn gained(&self, token: Address) -> U256 {
+        self.balance(token).saturating_sub(self.opening.get(&token).copied().unwrap_or_default())
+    }
+
+    /// Tokens the close-out released into the wallet, by address
+    pub fn proceeds(&self) -> Vec<(Address, U256)> {
+        let mut proceeds: Vec<(Address, U256)> = self.balances.keys()
+            .map(|token| (*token, self.gained(*token)))
+            .filter(|(_, amount)| !amount.is_zero())
+            .collect();
+        proceeds.sort_by_key(|(token, _)| *token);
+        proceeds
+    }
+}
+
+/// Premium owed on a flash loan, rounded up
+pub fn flash_loan_premium(amount: U256) -> U256 {
+    (amount * U256::from(FLASH_LOAN_PREMIUM_BPS) + U256::from(9_999)) / U256::from(10_000)
+}
+
+/// Share of an amount left after the slippage tolerance
+pub fn with_slippage(amount: U256, slippage_percentage: f64) -> U256 {
+    min_amount_out(amount, slippage_percentage)
+}
+
+/// Collateral to sell for at least `owed` of another token at the given USD prices,
+/// padded by the slippage tolerance
+pub fn collateral_to_sell(
+    (owed, owed_decimals, owed_price): (U256, u8, f64),
+    (collateral_decimals, collateral_price): (u8, f64),
+    slippage_percentage: f64,
+) -> Option<U256> {
+    if owed_price <= 0.0 || collateral_price <= 0.0 {
+        return None;
+    }
+    let owed_units: f64 = ethers::utils::format_units(owed, owed_decimals as u32).ok()?.parse().ok()?;
+    let collateral_units = owed_units * owed_price / collateral_price * (1.0 + slippage_percentage / 100.0);
+    ethers::utils::parse_units(format!("{:.*}", collateral_decimals as usize, collateral_units), collateral_decimals as u32)
+        .ok()
+        .map(Into::into)
+}
+
+/// Lending position to close, with what its market needs to repay and redeem it
+#[derive(Debug, Clone)]
+pub struct LendingExit {
+    pub market: LendingMarket,
+    pub asset: Address,
+    /// `None` when they could not be read, in which case the position is also unpriced
+    pub decimals: Option<u8>,
+    pub price_usd: Option<f64>,
+    pub supplied: U256,
+    /// Debt per Aave interest rate mode, 1 stable and 2 variable; Compound debt uses mode 0
+    pub debts: Vec<(u8, U256)>,
+    /// cToken and the wallet's cToken balance on Compound
+    pub ctoken: Option<(Address, U256)>,
+    /// Share of the supplied value counted towards the health factor, 0 when not collateral
+    pub liquidation_threshold: f64,
+}
+
+impl LendingExit {
+    pub fn debt(&self) -> U256 {
+        self.debts.iter().fold(U256::zero(), |total, (_, amount)| total.saturating_add(*amount))
+    }
+
+    pub fn protocol(&self) -> String {
+        match self.market {
+            LendingMarket::Aave => "aave".to_string(),
+            LendingMarket::Compound => "compound".to_string(),
+        }
+    }
+}
+
+/// Plan under construction, steps are appended in execution order
+#[derive(Debug, Default)]
+pub struct CloseoutDraft {
+    pub ledger: WalletLedger,
+    pub steps: Vec<CloseoutStep>,
+    pub warnings: Vec<String>,
+    pub flash_loan_fees_usd: f64,
+    pub price_impact_usd: f64,
+}
+
+impl CloseoutDraft {
+    pub fn gas_units(&self) -> U256 {
+        self.steps.iter().fold(U256::zero(), |total, step| total.saturating_add(step.gas_estimate))
+    }
+}
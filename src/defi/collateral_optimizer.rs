@@ -0,0 +1,446 @@
+// Collateral allocation across lending markets
+use ethers::types::{Address, TransactionRequest, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::api::models::TokenAmount;
+
+/// Fractions are applied to raw amounts with this precision
+const FRACTION_SCALE: u64 = 1_000_000_000;
+const BISECTION_STEPS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LendingMarket {
+    Aave,
+    Compound,
+}
+
+impl LendingMarket {
+    const ALL: [LendingMarket; 2] = [LendingMarket::Aave, LendingMarket::Compound];
+
+    fn index(self) -> usize {
+        match self {
+            LendingMarket::Aave => 0,
+            LendingMarket::Compound => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptimizationObjective {
+    /// Highest lowest health factor for the given debts
+    MinimizeRisk,
+    /// Largest borrow the collateral supports at the minimum health factor
+    MaximizeBorrow,
+}
+
+/// Collateral parameters of an asset in one market, as fractions
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MarketParams {
+    pub ltv: f64,
+    pub liquidation_threshold: f64,
+}
+
+/// An asset the user holds, wherever it currently sits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralAsset {
+    pub token: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    /// cToken of the asset, required to use it on Compound
+    pub ctoken: Option<Address>,
+    /// Fetched from the price feeds when omitted
+    pub price_usd: Option<f64>,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub wallet_balance: U256,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub aave_supplied: U256,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub compound_supplied: U256,
+    /// Overrides for the on-chain market parameters
+    pub aave: Option<MarketParams>,
+    pub compound: Option<MarketParams>,
+}
+
+impl CollateralAsset {
+    fn balance(&self, location: Option<LendingMarket>) -> U256 {
+        match location {
+            None => self.wallet_balance,
+            Some(LendingMarket::Aave) => self.aave_supplied,
+            Some(LendingMarket::Compound) => self.compound_supplied,
+        }
+    }
+
+    fn total(&self) -> U256 {
+        self.wallet_balance
+            .saturating_add(self.aave_supplied)
+            .saturating_add(self.compound_supplied)
+    }
+}
+
+/// Asset with its price and resolved market parameters
+#[derive(Debug, Clone)]
+pub struct PricedCollateral {
+    pub asset: CollateralAsset,
+    pub price_usd: f64,
+    /// `None` when the asset cannot be collateral in that market
+    pub markets: [Option<MarketParams>; 2],
+}
+
+impl PricedCollateral {
+    fn value_usd(&self) -> f64 {
+        let units = ethers::utils::format_units(self.asset.total(), self.asset.decimals as u32)
+            .ok()
+            .and_then(|units| units.parse::<f64>().ok())
+            .unwrap_or(0.0);
+        units * self.price_usd
+    }
+
+    fn threshold(&self, market: LendingMarket) -> f64 {
+        self.markets[market.index()].map(|params| params.liquidation_threshold).unwrap_or(0.0)
+    }
+
+    /// Borrowing power per USD that keeps the health factor at `min_health_factor`
+    fn safe_borrow_factor(&self, market: LendingMarket, min_health_factor: f64) -> f64 {
+        self.markets[market.index()]
+            .map(|params| params.ltv.min(params.liquidation_threshold / min_health_factor))
+            .unwrap_or(0.0)
+    }
+
+    fn current_fractions(&self) -> [f64; 3] {
+        let total = self.asset.total();
+        if total.is_zero() {
+            return [0.0, 0.0, 1.0];
+        }
+        [
+            share(self.asset.aave_supplied, total),
+            share(self.asset.compound_supplied, total),
+            share(self.asset.wallet_balance, total),
+        ]
+    }
+}
+
+fn default_min_health_factor() -> f64 {
+    1.5
+}
+
+/// Collateral a user holds and the debt they want to carry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralOptimizationRequest {
+    pub chain_id: u64,
+    pub user: Address,
+    pub assets: Vec<CollateralAsset>,
+    /// Target debt per market
+    #[serde(default, with = "crate::api::models::usd")]
+    pub aave_debt_usd: f64,
+    #[serde(default, with = "crate::api::models::usd")]
+    pub compound_debt_usd: f64,
+    pub objective: OptimizationObjective,
+    #[serde(default = "default_min_health_factor")]
+    pub min_health_factor: f64,
+}
+
+/// Debt and resulting health of one market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketOutcome {
+    pub market: LendingMarket,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_value_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub debt_usd: f64,
+    /// `None` without debt
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub health_factor_before: Option<f64>,
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub health_factor_after: Option<f64>,
+    /// Total debt the new allocation supports at the minimum health factor
+    #[serde(with = "crate::api::models::usd")]
+    pub max_safe_borrow_usd: f64,
+}
+
+/// Collateral moved between the wallet and the markets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralMove {
+    pub token: Address,
+    pub symbol: String,
+    /// `None` is the wallet
+    pub from: Option<LendingMarket>,
+    pub to: Option<LendingMarket>,
+    pub amount: TokenAmount,
+    #[serde(with = "crate::api::models::usd")]
+    pub value_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralPlan {
+    pub objective: OptimizationObjective,
+    #[serde(with = "crate::api::models::ratio")]
+    pub min_health_factor: f64,
+    pub markets: Vec<MarketOutcome>,
+    /// Deposits from the wallet first, then moves between markets, then withdrawals
+    pub moves: Vec<CollateralMove>,
+    pub transactions: Vec<TransactionRequest>,
+    pub meets_min_health_factor: bool,
+    pub warnings: Vec<String>,
+}
+
+/// Allocates collateral between Aave and Compound
+pub struct CollateralOptimizer {
+    min_health_factor: f64,
+}
+
+impl CollateralOptimizer {
+    pub fn new(min_health_factor: f64) -> Self {
+        Self { min_health_factor }
+    }
+
+    /// Plan the allocation; `debts_usd` is indexed like `LendingMarket::ALL`
+    pub fn optimize(
+        &self,
+        assets: &[PricedCollateral],
+        debts_usd: [f64; 2],
+        objective: OptimizationObjective,
+    ) -> CollateralPlan {
+        let values: Vec<f64> = assets.iter().map(PricedCollateral::value_usd).collect();
+        let current: Vec<[f64; 3]> = assets.iter().map(PricedCollateral::current_fractions).collect();
+        let mut warnings = Vec::new();
+
+        let target = match objective {
+            OptimizationObjective::MinimizeRisk if debts_usd.iter().all(|debt| *debt <= 0.0) => {
+                warnings.push("No debt to protect, allocation left unchanged".to_string());
+                current.clone()
+            }
+            OptimizationObjective::MinimizeRisk => self.minimize_risk(assets, &values, &current, debts_usd),
+            OptimizationObjective::MaximizeBorrow => self.maximize_borrow(assets, &current),
+        };
+        // Report on the allocation the moves actually reach after rounding to token units
+        let target_amounts: Vec<[U256; 3]> = assets.iter().zip(&target)
+            .map(|(asset, fractions)| target_amounts(asset.asset.total(), fractions))
+            .collect();
+        let target: Vec<[f64; 3]> = assets.iter().zip(&target_amounts)
+            .zip(&current)
+            .map(|((asset, amounts), current)| {
+                let total = asset.asset.total();
+                if total.is_zero() {
+                    return *current;
+                }
+                [share(amounts[0], total), share(amounts[1], total), share(amounts[2], total)]
+            })
+            .collect();
+
+        let markets: Vec<MarketOutcome> = LendingMarket::ALL.iter()
+            .map(|market| {
+                let i = market.index();
+                let collateral = |fractions: &[[f64; 3]]| -> (f64, f64, f64) {
+                    assets.iter().zip(&values).zip(fractions).fold((0.0, 0.0, 0.0), |acc, ((asset, value), f)| {
+                        let supplied = value * f[i];
+                        (
+                            acc.0 + supplied,
+                            acc.1 + supplied * asset.threshold(*market),
+                            acc.2 + supplied * asset.safe_borrow_factor(*market, self.min_health_factor),
+                        )
+                    })
+                };
+                let (_, threshold_before, _) = collateral(&current);
+                let (collateral_value_usd, threshold_after, max_safe_borrow_usd) = collateral(&target);
+                let debt_usd = debts_usd[i];
+                let health = |threshold: f64| (debt_usd > 0.0).then(|| threshold / debt_usd);
+
+                MarketOutcome {
+                    market: *market,
+                    collateral_value_usd,
+                    debt_usd,
+                    health_factor_before: health(threshold_before),
+                    health_factor_after: health(threshold_after),
+                    max_safe_borrow_usd,
+                }
+            })
+            .collect();
+
+        let meets_min_health_factor = markets.iter()
+            .all(|outcome| outcome.health_factor_after.is_none_or(|health| health >= self.min_health_factor));
+        if !meets_min_health_factor {
+            warnings.push(format!(
+                "Collateral cannot reach a health factor of {} for the requested debt",
+                self.min_health_factor
+            ));
+        }
+
+        CollateralPlan {
+            objective,
+            min_health_factor: self.min_health_factor,
+            markets,
+            moves: Self::moves(assets, &values, &target_amounts),
+            transactions: Vec::new(),
+            meets_min_health_factor,
+            warnings,
+        }
+    }
+
+    /// Maximize the lowest health factor by bisecting on it
+    fn minimize_risk(
+        &self,
+        assets: &[PricedCollateral],
+        values: &[f64],
+        current: &[[f64; 3]],
+        debts_usd: [f64; 2],
+    ) -> Vec<[f64; 3]> {
+        let total_threshold: f64 = assets.iter().zip(values)
+            .map(|(asset, value)| value * asset.threshold(LendingMarket::Aave).max(asset.threshold(LendingMarket::Compound)))
+            .sum();
+        let smallest_debt = debts_usd.iter().copied().filter(|debt| *debt > 0.0).fold(f64::INFINITY, f64::min);
+
+        let mut low = 0.0;
+        let mut high = total_threshold / smallest_debt + 1.0;
+        for _ in 0..BISECTION_STEPS {
+            let mid = (low + high) / 2.0;
+            if Self::fill(assets, values, current, debts_usd, mid).is_some() {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        Self::fill(assets, values, current, debts_usd, low).unwrap_or_else(|| current.to_vec())
+    }
+
+    /// Allocation reaching `health_factor` in both markets, if the collateral allows it.
+    /// Assets relatively better on Aave fill Aave's requirement first, the rest covers
+    /// Compound, which is optimal for two markets. Unneeded collateral stays in place.
+    fn fill(
+        assets: &[PricedCollateral],
+        values: &[f64],
+        current: &[[f64; 3]],
+        debts_usd: [f64; 2],
+        health_factor: f64,
+    ) -> Option<Vec<[f64; 3]>> {
+        let mut order: Vec<usize> = (0..assets.len()).collect();
+        order.sort_by(|a, b| {
+            let ratio = |x: usize, y: usize| assets[x].threshold(LendingMarket::Aave) * assets[y].threshold(LendingMarket::Compound);
+            ratio(*b, *a).total_cmp(&ratio(*a, *b))
+        });
+
+        let mut fractions = vec![[0.0; 3]; assets.len()];
+        let mut remaining = vec![1.0; assets.len()];
+        let mut required = [debts_usd[0] * health_factor, debts_usd[1] * health_factor];
+
+        for (market, sequence) in [
+            (LendingMarket::Aave, order.clone()),
+            (LendingMarket::Compound, order.iter().rev().copied().collect::<Vec<_>>()),
+        ] {
+            let i = market.index();
+            for &asset in &sequence {
+                let per_fraction = values[asset] * assets[asset].threshold(market);
+                if required[i] <= 0.0 || per_fraction <= 0.0 || remaining[asset] <= 0.0 {
+                    continue;
+                }
+                let used = (required[i] / per_fraction).min(remaining[asset]);
+                fractions[asset][i] += used;
+                remaining[asset] -= used;
+                required[i] -= used * per_fraction;
+            }
+            if required[i] > 1e-9 {
+                return None;
+            }
+        }
+
+        for (asset, left) in remaining.iter().enumerate() {
+            for location in 0..3 {
+                fractions[asset][location] += left * current[asset][location];
+            }
+        }
+        Some(fractions)
+    }
+
+    /// Put each asset where it supports the most debt at the minimum health factor
+    fn maximize_borrow(&self, assets: &[PricedCollateral], current: &[[f64; 3]]) -> Vec<[f64; 3]> {
+        assets.iter().zip(current)
+            .map(|(asset, current)| {
+                let best = LendingMarket::ALL.iter()
+                    .map(|market| (*market, asset.safe_borrow_factor(*market, self.min_health_factor)))
+                    .filter(|(_, factor)| *factor > 0.0)
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                match best {
+                    Some((market, _)) => {
+                        let mut fractions = [0.0; 3];
+                        fractions[market.index()] = 1.0;
+                        fractions
+                    }
+                    None => *current,
+                }
+            })
+            .collect()
+    }
+
+    fn moves(assets: &[PricedCollateral], values: &[f64], target: &[[U256; 3]]) -> Vec<CollateralMove> {
+        let locations = [Some(LendingMarket::Aave), Some(LendingMarket::Compound), None];
+        let mut moves = Vec::new();
+
+        for ((asset, value), targets) in assets.iter().zip(values).zip(target) {
+            let total = asset.asset.total();
+            if total.is_zero() {
+                continue;
+            }
+
+            let mut surplus: Vec<(Option<LendingMarket>, U256)> = Vec::new();
+            let mut deficit: Vec<(Option<LendingMarket>, U256)> = Vec::new();
+            for (location, target) in locations.iter().zip(targets.iter().copied()) {
+                let held = asset.asset.balance(*location);
+                if held > target {
+                    surplus.push((*location, held - target));
+                } else if target > held {
+                    deficit.push((*location, target - held));
+                }
+            }
+
+            for (to, mut needed) in deficit {
+                for (from, available) in surplus.iter_mut() {
+                    if needed.is_zero() {
+                        break;
+                    }
+                    let amount = needed.min(*available);
+                    if amount.is_zero() {
+                        continue;
+                    }
+                    *available -= amount;
+                    needed -= amount;
+                    moves.push(CollateralMove {
+                        token: asset.asset.token,
+                        symbol: asset.asset.symbol.clone(),
+                        from: *from,
+                        to,
+                        amount: TokenAmount::new(amount, asset.asset.decimals),
+                        value_usd: value * share(amount, total),
+                    });
+                }
+            }
+        }
+
+        // Add collateral before anything is taken out so health never dips mid-plan
+        moves.sort_by_key(|step| match (step.from, step.to) {
+            (None, _) => 0,
+            (Some(_), Some(_)) => 1,
+            (Some(_), None) => 2,
+        });
+        moves
+    }
+}
+
+/// `part / total` without overflowing on large raw amounts
+fn share(part: U256, total: U256) -> f64 {
+    let as_f64 = |amount: U256| amount.to_string().parse::<f64>().unwrap_or(0.0);
+    as_f64(part) / as_f64(total)
+}
+
+/// Raw amounts for Aave, Compound and the wallet adding up to `total`
+fn target_amounts(total: U256, fractions: &[f64; 3]) -> [U256; 3] {
+    let aave = scale(total, fractions[0]);
+    let compound = scale(total, fractions[1]).min(total - aave);
+    [aave, compound, total - aave - compound]
+}
+
+fn scale(amount: U256, fraction: f64) -> U256 {
+    let fraction = (fraction.clamp(0.0, 1.0) * FRACTION_SCALE as f64).round() as u64;
+    amount * U256::from(fraction) / U256::from(FRACTION_SCALE)
+}
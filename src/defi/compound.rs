@@ -1,12 +1,74 @@
 use std::{sync::Arc, collections::HashMap};
 use ethers::types::{Address, U256, H256, TransactionRequest};
-use ethers::abi::{Abi, Token, ParamType, AbiEncode};
+use ethers::abi::{Abi, Token};
 use ethers::contract::Contract;
+use ethers::providers::Middleware;
+use crate::cache::{CacheManager, NamespacedCache, TimedCache};
 use crate::chains::ChainManager;
+use crate::contracts::multicall::multicall;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
+use crate::defi::compound_borrowers::CompoundBorrower;
 use crate::dex::DexManager;
 use anyhow::{Result, anyhow};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Rates and reserves move every block, market data is refreshed after this long
+const CTOKEN_CACHE_TTL: Duration = Duration::from_secs(30);
+/// Account liquidity changes with prices as well as the account's own transactions
+const USER_DATA_CACHE_TTL: Duration = Duration::from_secs(15);
+const USER_DATA_CACHE_MAX_ENTRIES: usize = 5_000;
+
+/// Gas of a `liquidateBorrow` call, besides the swap of the seized collateral
+pub const LIQUIDATION_GAS: u64 = 500_000;
+/// Price impact and swap gas assumed when no DEX quotes selling the seized collateral
+const UNQUOTED_PRICE_IMPACT_PERCENT: f64 = 5.0;
+const UNQUOTED_SWAP_GAS: u64 = 200_000;
+/// Scale of Compound mantissas and of the USD values the comptroller reports
+const MANTISSA: f64 = 1e18;
+
+fn ctoken_key(chain_id: u64, ctoken: Address) -> String {
+    format!("{}:{:?}", chain_id, ctoken)
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, word| acc * 2f64.powi(64) + *word as f64)
+}
+
+/// Underlying amount worth `value_usd` at an oracle price scaled by `1e(36 - decimals)`
+fn usd_to_amount(value_usd: f64, price: U256) -> U256 {
+    let amount = value_usd * 1e36 / u256_to_f64(price);
+    if amount.is_finite() && amount >= 1.0 {
+        U256::from_dec_str(&format!("{:.0}", amount)).unwrap_or_default()
+    } else {
+        U256::zero()
+    }
+}
+
+/// An account's balances in one market with the market's oracle price
+struct MarketSnapshot {
+    ctoken: Address,
+    ctoken_balance: U256,
+    /// cToken balance in underlying units
+    collateral: U256,
+    borrow_balance: U256,
+    exchange_rate: U256,
+    /// USD price of the underlying scaled by `1e(36 - decimals)`
+    price: U256,
+}
+
+impl MarketSnapshot {
+    fn borrow_value_usd(&self) -> f64 {
+        u256_to_f64(self.borrow_balance) * u256_to_f64(self.price) / 1e36
+    }
+
+    fn collateral_value_usd(&self) -> f64 {
+        u256_to_f64(self.collateral) * u256_to_f64(self.price) / 1e36
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompoundContracts {
@@ -17,6 +79,10 @@ pub struct CompoundContracts {
     pub cdai: Address,
     pub cusdc: Address,
     pub cwbtc: Address,
+    /// Wrapped native token cETH collateral is swapped as
+    pub weth: Address,
+    /// CompoundLens, reads the COMP a claim would pay
+    pub lens: Address,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +170,46 @@ pub struct LiquidationOpportunity {
     pub profit_estimate: U256,
     pub health_factor: f64,
     pub liquidation_incentive: f64,
+    /// Liquidity the account lacks, in USD
+    #[serde(with = "crate::api::models::usd")]
+    pub shortfall_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub repay_value_usd: f64,
+    /// Liquidation incentive on the repaid value
+    #[serde(with = "crate::api::models::usd")]
+    pub gross_profit_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub gas_cost_usd: f64,
+    /// Price impact of swapping the seized collateral into the repaid asset
+    pub price_impact_percent: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub price_impact_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_profit_usd: f64,
+}
+
+/// A liquidation as executed by a liquidator contract: what it repays, the cTokens it is left with once
+/// the protocol took its share, and the underlying it redeems them for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationLeg {
+    pub borrower: Address,
+    pub ctoken_borrowed: Address,
+    /// Token repaid, WETH when the borrow is in ETH
+    pub repay_asset: Address,
+    pub repay_amount: U256,
+    pub ctoken_collateral: Address,
+    /// Token the collateral redeems to, WETH when it is in ETH
+    pub collateral_asset: Address,
+    /// cTokens seized for the liquidator
+    pub seize_tokens: U256,
+    /// Underlying the seized cTokens redeem for at the stored exchange rate
+    pub collateral_amount: U256,
+    /// The borrow is in ETH, repaid by unwrapping WETH
+    pub native_borrow: bool,
+    /// The collateral is ETH, wrapped again after redeeming
+    pub native_collateral: bool,
+    pub liquidate: TransactionRequest,
+    pub redeem: TransactionRequest,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -130,13 +236,12 @@ pub struct CompoundManager {
     chain_manager: Arc<ChainManager>,
     dex_manager: Arc<DexManager>,
     contracts: HashMap<u64, CompoundContracts>,
-    ctoken_cache: Arc<tokio::sync::RwLock<HashMap<(u64, Address), CTokenInfo>>>,
-    user_data_cache: Arc<tokio::sync::RwLock<HashMap<(u64, Address), UserCompoundData>>>,
-    oracle_prices_cache: Arc<tokio::sync::RwLock<HashMap<Address, (U256, std::time::Instant)>>>,
+    ctoken_cache: NamespacedCache<CTokenInfo>,
+    user_data_cache: TimedCache<(u64, Address), UserCompoundData>,
 }
 
 impl CompoundManager {
-    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>) -> Result<Self> {
+    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>, caches: &CacheManager) -> Result<Self> {
         let mut contracts = HashMap::new();
         
         // Ethereum mainnet contracts
@@ -148,37 +253,86 @@ impl CompoundManager {
             cdai: "0x5d3a536E4D6DbD6114cc1Ead35777bAB948E3643".parse()?,
             cusdc: "0x39AA39c021dfbaE8faC545936693aC917d5E7563".parse()?,
             cwbtc: "0xC11b1268C1A384e55C48c2391d8d480264A3A7F4".parse()?,
+            weth: "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse()?,
+            lens: "0xdCbDb7306c6Ff46f77B349188dC18cEd9DF30299".parse()?,
         });
 
         Ok(Self {
             chain_manager,
             dex_manager,
             contracts,
-            ctoken_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
-            user_data_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
-            oracle_prices_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            ctoken_cache: caches.namespace("compound_ctokens", CTOKEN_CACHE_TTL),
+            user_data_cache: caches.timed("compound_user_data", USER_DATA_CACHE_TTL, USER_DATA_CACHE_MAX_ENTRIES),
         })
     }
 
+    /// Token the comptroller pays supply and borrow rewards in, `None` on chains without Compound
+    pub fn comp_token(&self, chain_id: u64) -> Option<Address> {
+        self.contracts.get(&chain_id).map(|contracts| contracts.comp_token)
+    }
+
+    /// cToken markets per chain
+    pub fn markets(&self) -> HashMap<u64, Vec<Address>> {
+        self.contracts.iter()
+            .map(|(chain_id, contracts)| (*chain_id, vec![contracts.ceth, contracts.cdai, contracts.cusdc, contracts.cwbtc]))
+            .collect()
+    }
+
+    /// Address-registry entries probed against the Compound V2 interfaces at startup
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| [
+                ContractDeployment::new(chain_id, "compound", "comptroller", contracts.comptroller, ProtocolInterface::CompoundComptroller),
+                ContractDeployment::new(chain_id, "compound", "comp_token", contracts.comp_token, ProtocolInterface::Erc20),
+                ContractDeployment::new(chain_id, "compound", "ceth", contracts.ceth, ProtocolInterface::CompoundCToken),
+                ContractDeployment::new(chain_id, "compound", "cdai", contracts.cdai, ProtocolInterface::CompoundCToken),
+                ContractDeployment::new(chain_id, "compound", "cusdc", contracts.cusdc, ProtocolInterface::CompoundCToken),
+                ContractDeployment::new(chain_id, "compound", "cwbtc", contracts.cwbtc, ProtocolInterface::CompoundCToken),
+            ])
+            .collect()
+    }
+
+    /// Drop cached cToken and user data
+    pub async fn clear_cache(&self) {
+        self.ctoken_cache.invalidate_all().await;
+        self.user_data_cache.clear().await;
+    }
+
     pub async fn get_ctoken_info(&self, chain_id: u64, ctoken: Address) -> Result<CTokenInfo> {
-        // Check cache first
-        {
-            let cache = self.ctoken_cache.read().await;
-            if let Some(info) = cache.get(&(chain_id, ctoken)) {
-                return Ok(info.clone());
-            }
-        }
+        let contracts = self.contracts.get(&chain_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.ctoken_cache
+            .get_or_load(&ctoken_key(chain_id, ctoken), move || {
+                Self::fetch_ctoken_info(chain_manager, contracts, chain_id, ctoken).boxed()
+            })
+            .await
+    }
 
-        let provider = self.chain_manager.get_provider(chain_id).await?;
+    /// Drop the cached market data of a cToken, e.g. after its rates or reserves changed
+    pub async fn invalidate_ctoken(&self, chain_id: u64, ctoken: Address) {
+        self.ctoken_cache.invalidate(&ctoken_key(chain_id, ctoken)).await;
+    }
+
+    /// Drop the cached account data of a user, e.g. after their supply or borrow was mined
+    pub async fn invalidate_user(&self, chain_id: u64, user: Address) {
+        self.user_data_cache.invalidate(&(chain_id, user)).await;
+    }
+
+    async fn fetch_ctoken_info(
+        chain_manager: Arc<ChainManager>,
+        contracts: CompoundContracts,
+        chain_id: u64,
+        ctoken: Address,
+    ) -> Result<CTokenInfo> {
+        let provider = chain_manager.get_provider(chain_id).await?;
         let ctoken_contract = Contract::new(
             ctoken,
             Self::get_ctoken_abi()?,
             Arc::new(provider.provider.clone()),
         );
 
-        let contracts = self.contracts.get(&chain_id)
-            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
-
         let comptroller_contract = Contract::new(
             contracts.comptroller,
             Self::get_comptroller_abi()?,
@@ -230,7 +384,7 @@ impl CompoundManager {
             .call()
             .await?;
 
-        let ctoken_info = CTokenInfo {
+        Ok(CTokenInfo {
             symbol,
             underlying_address,
             ctoken_address: ctoken,
@@ -247,18 +401,16 @@ impl CompoundManager {
             reserve_factor,
             comp_speed_supply,
             comp_speed_borrow,
-        };
-
-        // Cache the result
-        {
-            let mut cache = self.ctoken_cache.write().await;
-            cache.insert((chain_id, ctoken), ctoken_info.clone());
-        }
-
-        Ok(ctoken_info)
+        })
     }
 
     pub async fn get_user_compound_data(&self, chain_id: u64, account: Address) -> Result<UserCompoundData> {
+        self.user_data_cache
+            .get_or_load((chain_id, account), || self.fetch_user_compound_data(chain_id, account))
+            .await
+    }
+
+    async fn fetch_user_compound_data(&self, chain_id: u64, account: Address) -> Result<UserCompoundData> {
         let contracts = self.contracts.get(&chain_id)
             .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
 
@@ -508,6 +660,22 @@ impl CompoundManager {
         Ok(tx.into())
     }
 
+    /// COMP `account` would receive from claiming every market. Unlike `compAccrued` this includes
+    /// what accrued since the account last interacted with a market, as the lens claims within the call
+    pub async fn claimable_comp(&self, chain_id: u64, account: Address) -> Result<U256> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let lens = Contract::new(contracts.lens, Self::get_lens_abi()?, Arc::new(provider.provider.clone()));
+        let (_balance, _votes, _delegate, allocated): (U256, U256, Address, U256) = lens
+            .method("getCompBalanceMetadataExt", (contracts.comp_token, contracts.comptroller, account))?
+            .call()
+            .await?;
+
+        Ok(allocated)
+    }
+
     pub async fn liquidate_borrow(&self, chain_id: u64, ctoken_borrowed: Address, ctoken_collateral: Address, borrower: Address, repay_amount: U256) -> Result<TransactionRequest> {
         let provider = self.chain_manager.get_provider(chain_id).await?;
         let ctoken_contract = Contract::new(
@@ -520,6 +688,77 @@ impl CompoundManager {
         Ok(tx.into())
     }
 
+    /// Liquidation of `borrower` repaying `repay_amount` of its `ctoken_borrowed` borrow, with the cTokens
+    /// the comptroller seizes for it net of the protocol seize share
+    pub async fn liquidation_leg(
+        &self,
+        chain_id: u64,
+        borrower: Address,
+        ctoken_borrowed: Address,
+        ctoken_collateral: Address,
+        repay_amount: U256,
+    ) -> Result<LiquidationLeg> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        let markets = [contracts.ceth, contracts.cdai, contracts.cusdc, contracts.cwbtc];
+        for ctoken in [ctoken_borrowed, ctoken_collateral] {
+            if !markets.contains(&ctoken) {
+                return Err(anyhow!("{:?} is not a Compound market on chain {}", ctoken, chain_id));
+            }
+        }
+        if repay_amount.is_zero() {
+            return Err(anyhow!("Repay amount must be positive"));
+        }
+
+        let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+        let comptroller = Contract::new(contracts.comptroller, Self::get_comptroller_abi()?, provider.clone());
+        let collateral = Contract::new(ctoken_collateral, Self::get_ctoken_abi()?, provider.clone());
+
+        let (error, seize_tokens): (U256, U256) = comptroller
+            .method("liquidateCalculateSeizeTokens", (ctoken_borrowed, ctoken_collateral, repay_amount))?
+            .call()
+            .await?;
+        if !error.is_zero() {
+            return Err(anyhow!("Comptroller cannot price the seizure (error {})", error));
+        }
+        // Markets upgraded since the seize share was introduced keep part of the seized cTokens as reserves
+        let protocol_seize_share: U256 = collateral.method("protocolSeizeShareMantissa", ())?.call().await.unwrap_or_default();
+        let seize_tokens = seize_tokens - seize_tokens * protocol_seize_share / U256::exp10(18);
+        let exchange_rate: U256 = collateral.method("exchangeRateStored", ())?.call().await?;
+
+        let native_borrow = ctoken_borrowed == contracts.ceth;
+        let native_collateral = ctoken_collateral == contracts.ceth;
+        let underlying = |ctoken: Address| async move {
+            if ctoken == contracts.ceth {
+                Ok(contracts.weth)
+            } else {
+                self.get_ctoken_info(chain_id, ctoken).await.map(|info| info.underlying_address)
+            }
+        };
+        let liquidate = if native_borrow {
+            let ceth = Contract::new(contracts.ceth, Self::get_ceth_abi()?, provider);
+            let tx: TransactionRequest = ceth.method::<_, ()>("liquidateBorrow", (borrower, ctoken_collateral))?.tx.into();
+            tx.value(repay_amount)
+        } else {
+            self.liquidate_borrow(chain_id, ctoken_borrowed, ctoken_collateral, borrower, repay_amount).await?
+        };
+
+        Ok(LiquidationLeg {
+            borrower,
+            ctoken_borrowed,
+            repay_asset: underlying(ctoken_borrowed).await?,
+            repay_amount,
+            ctoken_collateral,
+            collateral_asset: underlying(ctoken_collateral).await?,
+            seize_tokens,
+            collateral_amount: seize_tokens * exchange_rate / U256::exp10(18),
+            native_borrow,
+            native_collateral,
+            liquidate,
+            redeem: self.redeem(chain_id, ctoken_collateral, seize_tokens).await?,
+        })
+    }
+
     pub async fn get_yield_strategies(&self, chain_id: u64, asset: Address, amount: U256) -> Result<Vec<CompoundYieldStrategy>> {
         let mut strategies = Vec::new();
 
@@ -586,42 +825,210 @@ impl CompoundManager {
         Ok(strategies)
     }
 
-    pub async fn find_liquidation_opportunities(&self, chain_id: u64) -> Result<Vec<LiquidationOpportunity>> {
+    /// Underwater accounts among `borrowers`, each with its largest borrow to repay and largest collateral
+    /// to seize, ranked by net profit after gas and the price impact of selling the seized collateral.
+    /// Liquidity, markets entered, balances and prices are read in multicall batches.
+    pub async fn find_liquidation_opportunities(&self, chain_id: u64, borrowers: &[CompoundBorrower]) -> Result<Vec<LiquidationOpportunity>> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Unsupported chain: {}", chain_id))?;
+        if borrowers.is_empty() {
+            return Ok(Vec::new());
+        }
+        let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+        let comptroller = Contract::new(contracts.comptroller, Self::get_comptroller_abi()?, provider.clone());
+        let oracle = Contract::new(contracts.price_oracle, Self::get_price_oracle_abi()?, provider.clone());
+        let ctoken_abi = Self::get_ctoken_abi()?;
+
+        // Accounts in shortfall
+        let calls = borrowers.iter()
+            .map(|borrower| comptroller.method::<_, (U256, U256, U256)>("getAccountLiquidity", borrower.account))
+            .collect::<Result<Vec<_>, _>>()?;
+        let liquidity = multicall(&provider, chain_id, calls).await?;
+        let underwater: Vec<(&CompoundBorrower, U256)> = borrowers.iter()
+            .zip(liquidity)
+            .filter_map(|(borrower, result)| {
+                let shortfall = result?.into_tuple()?.get(2)?.clone().into_uint()?;
+                (!shortfall.is_zero()).then_some((borrower, shortfall))
+            })
+            .collect();
+        info!("{} of {} Compound borrowers on chain {} are in shortfall", underwater.len(), borrowers.len(), chain_id);
+        if underwater.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Markets each account borrows from or entered as collateral
+        let calls = underwater.iter()
+            .map(|(borrower, _)| comptroller.method::<_, Vec<Address>>("getAssetsIn", borrower.account))
+            .collect::<Result<Vec<_>, _>>()?;
+        let assets_in = multicall(&provider, chain_id, calls).await?;
+        let account_markets: Vec<Vec<Address>> = underwater.iter()
+            .zip(assets_in)
+            .map(|((borrower, _), assets)| {
+                let mut markets: Vec<Address> = assets
+                    .and_then(Token::into_array)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(Token::into_address)
+                    .collect();
+                markets.extend(borrower.borrows.iter().map(|borrow| borrow.ctoken));
+                markets.sort();
+                markets.dedup();
+                markets
+            })
+            .collect();
+
+        // Prices of every market involved, cETH's prices gas
+        let mut markets: Vec<Address> = account_markets.iter().flatten().copied().chain([contracts.ceth]).collect();
+        markets.sort();
+        markets.dedup();
+        let calls = markets.iter()
+            .map(|market| oracle.method::<_, U256>("getUnderlyingPrice", *market))
+            .collect::<Result<Vec<_>, _>>()?;
+        let prices: HashMap<Address, U256> = markets.iter()
+            .zip(multicall(&provider, chain_id, calls).await?)
+            .filter_map(|(market, price)| Some((*market, price?.into_uint()?)))
+            .filter(|(_, price)| !price.is_zero())
+            .collect();
+        let native_price_usd = prices.get(&contracts.ceth).map(|price| u256_to_f64(*price) / MANTISSA).unwrap_or_default();
+
+        // Balances of every account in its markets
+        let positions: Vec<(usize, Address)> = account_markets.iter()
+            .enumerate()
+            .flat_map(|(index, markets)| markets.iter().map(move |market| (index, *market)))
+            .collect();
+        let calls = positions.iter()
+            .map(|(index, market)| {
+                Contract::new(*market, ctoken_abi.clone(), provider.clone())
+                    .method::<_, (U256, U256, U256, U256)>("getAccountSnapshot", underwater[*index].0.account)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut snapshots: Vec<Vec<MarketSnapshot>> = underwater.iter().map(|_| Vec::new()).collect();
+        for ((index, market), snapshot) in positions.into_iter().zip(multicall(&provider, chain_id, calls).await?) {
+            let Some(values) = snapshot.and_then(Token::into_tuple) else {
+                continue;
+            };
+            let values: Vec<U256> = values.into_iter().filter_map(Token::into_uint).collect();
+            let (Some(price), [error, ctoken_balance, borrow_balance, exchange_rate]) = (prices.get(&market), values.as_slice()) else {
+                continue;
+            };
+            if !error.is_zero() {
+                continue;
+            }
+            snapshots[index].push(MarketSnapshot {
+                ctoken: market,
+                collateral: *ctoken_balance * *exchange_rate / U256::exp10(18),
+                ctoken_balance: *ctoken_balance,
+                borrow_balance: *borrow_balance,
+                exchange_rate: *exchange_rate,
+                price: *price,
+            });
+        }
+
+        let close_factor = u256_to_f64(comptroller.method::<_, U256>("closeFactorMantissa", ())?.call().await?) / MANTISSA;
+        let incentive = u256_to_f64(comptroller.method::<_, U256>("liquidationIncentiveMantissa", ())?.call().await?) / MANTISSA;
+        let gas_price = provider.get_gas_price().await?;
+
         let mut opportunities = Vec::new();
+        for ((borrower, shortfall), snapshots) in underwater.into_iter().zip(snapshots) {
+            match self.price_liquidation(chain_id, contracts, borrower.account, shortfall, &snapshots, close_factor, incentive, gas_price, native_price_usd).await {
+                Ok(Some(opportunity)) => opportunities.push(opportunity),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to price the liquidation of {:?} on chain {}: {}", borrower.account, chain_id, e),
+            }
+        }
 
-        // Mock implementation - in production would scan all accounts
-        let mock_accounts = vec![
-            "0x1234567890123456789012345678901234567890".parse::<Address>()?,
-            "0x2345678901234567890123456789012345678901".parse::<Address>()?,
-        ];
+        opportunities.sort_by(|a, b| b.net_profit_usd.total_cmp(&a.net_profit_usd));
+        Ok(opportunities)
+    }
 
-        for account in mock_accounts {
-            let user_data = self.get_user_compound_data(chain_id, account).await?;
-            
-            if user_data.shortfall > U256::zero() {
-                // Account is under-collateralized, find liquidation opportunity
-                for position in &user_data.positions {
-                    if position.borrow_balance > U256::zero() {
-                        let opportunity = LiquidationOpportunity {
-                            account,
-                            ctoken_borrowed: position.ctoken,
-                            ctoken_collateral: position.ctoken, // Simplified
-                            repay_amount: position.borrow_balance / U256::from(2), // Max 50% can be repaid
-                            seize_amount: position.borrow_balance * U256::from(108) / U256::from(100), // 8% liquidation incentive
-                            profit_estimate: position.borrow_balance * U256::from(8) / U256::from(100),
-                            health_factor: user_data.health_factor,
-                            liquidation_incentive: 8.0,
-                        };
-                        opportunities.push(opportunity);
-                    }
+    /// Repay the largest borrow up to the close factor and what the largest collateral covers, and value
+    /// the seized collateral net of gas and the price impact of swapping it into the repaid asset
+    #[allow(clippy::too_many_arguments)]
+    async fn price_liquidation(
+        &self,
+        chain_id: u64,
+        contracts: &CompoundContracts,
+        account: Address,
+        shortfall: U256,
+        snapshots: &[MarketSnapshot],
+        close_factor: f64,
+        incentive: f64,
+        gas_price: U256,
+        native_price_usd: f64,
+    ) -> Result<Option<LiquidationOpportunity>> {
+        let borrowed = snapshots.iter()
+            .filter(|snapshot| !snapshot.borrow_balance.is_zero())
+            .max_by(|a, b| a.borrow_value_usd().total_cmp(&b.borrow_value_usd()));
+        let collateral = snapshots.iter()
+            .filter(|snapshot| !snapshot.collateral.is_zero())
+            .max_by(|a, b| a.collateral_value_usd().total_cmp(&b.collateral_value_usd()));
+        let (Some(borrowed), Some(collateral)) = (borrowed, collateral) else {
+            return Ok(None);
+        };
+
+        let repay_value_usd = (borrowed.borrow_value_usd() * close_factor).min(collateral.collateral_value_usd() / incentive);
+        let repay_amount = usd_to_amount(repay_value_usd, borrowed.price);
+        if repay_amount.is_zero() {
+            return Ok(None);
+        }
+        let seize_value_usd = repay_value_usd * incentive;
+        let seized_underlying = usd_to_amount(seize_value_usd, collateral.price);
+        let seize_amount = U256::try_from(seized_underlying.full_mul(U256::exp10(18)) / collateral.exchange_rate).unwrap_or(U256::MAX)
+            .min(collateral.ctoken_balance);
+
+        // Selling the seized collateral for the repaid asset
+        let borrowed_info = self.get_ctoken_info(chain_id, borrowed.ctoken).await?;
+        let collateral_info = self.get_ctoken_info(chain_id, collateral.ctoken).await?;
+        let (price_impact, swap_gas) = if borrowed.ctoken == collateral.ctoken {
+            (0.0, 0)
+        } else {
+            let token = |ctoken: Address, underlying: Address| if ctoken == contracts.ceth { contracts.weth } else { underlying };
+            match self.dex_manager.get_comprehensive_quotes(
+                chain_id,
+                token(collateral.ctoken, collateral_info.underlying_address),
+                token(borrowed.ctoken, borrowed_info.underlying_address),
+                seized_underlying,
+                Address::zero(),
+            ).await {
+                Ok(quotes) => (quotes.best_route.price_impact, quotes.best_route.gas_estimate.low_u64()),
+                Err(e) => {
+                    warn!("No quote for selling {} seized from {:?}, assuming {}% price impact: {}", collateral_info.symbol, account, UNQUOTED_PRICE_IMPACT_PERCENT, e);
+                    (UNQUOTED_PRICE_IMPACT_PERCENT, UNQUOTED_SWAP_GAS)
                 }
             }
+        };
+
+        let gross_profit_usd = repay_value_usd * (incentive - 1.0);
+        let gas_cost_usd = u256_to_f64(gas_price * U256::from(LIQUIDATION_GAS + swap_gas)) / MANTISSA * native_price_usd;
+        let price_impact_usd = seize_value_usd * price_impact / 100.0;
+
+        let borrow_value_usd: f64 = snapshots.iter().map(MarketSnapshot::borrow_value_usd).sum();
+        let mut liquidation_value_usd = 0.0;
+        for snapshot in snapshots.iter().filter(|snapshot| !snapshot.collateral.is_zero()) {
+            let collateral_factor = u256_to_f64(self.get_ctoken_info(chain_id, snapshot.ctoken).await?.collateral_factor) / MANTISSA;
+            liquidation_value_usd += snapshot.collateral_value_usd() * collateral_factor;
         }
 
-        Ok(opportunities)
+        Ok(Some(LiquidationOpportunity {
+            account,
+            ctoken_borrowed: borrowed.ctoken,
+            ctoken_collateral: collateral.ctoken,
+            repay_amount,
+            seize_amount,
+            profit_estimate: usd_to_amount(gross_profit_usd, borrowed.price),
+            health_factor: if borrow_value_usd > 0.0 { liquidation_value_usd / borrow_value_usd } else { f64::INFINITY },
+            liquidation_incentive: (incentive - 1.0) * 100.0,
+            shortfall_usd: u256_to_f64(shortfall) / MANTISSA,
+            repay_value_usd,
+            gross_profit_usd,
+            gas_cost_usd,
+            price_impact_percent: price_impact,
+            price_impact_usd,
+            net_profit_usd: gross_profit_usd - gas_cost_usd - price_impact_usd,
+        }))
     }
 
-    pub async fn find_arbitrage_opportunities(&self, chain_id: u64) -> Result<Vec<CompArbitrageOpportunity>> {
+    pub async fn find_arbitrage_opportunities(&self, chain_id: u64, borrowers: &[CompoundBorrower]) -> Result<Vec<CompArbitrageOpportunity>> {
         let mut opportunities = Vec::new();
 
         // Strategy 1: Rate arbitrage between Compound and Aave
@@ -656,7 +1063,7 @@ impl CompoundManager {
         }
 
         // Strategy 2: Liquidation arbitrage
-        let liquidation_ops = self.find_liquidation_opportunities(chain_id).await?;
+        let liquidation_ops = self.find_liquidation_opportunities(chain_id, borrowers).await?;
         for liq_op in liquidation_ops {
             opportunities.push(CompArbitrageOpportunity {
                 strategy_type: "Liquidation Arbitrage".to_string(),
@@ -868,6 +1275,25 @@ impl CompoundManager {
                 "outputs": [{"internalType": "uint8", "name": "", "type": "uint8"}],
                 "stateMutability": "view",
                 "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "protocolSeizeShareMantissa",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "address", "name": "account", "type": "address"}],
+                "name": "getAccountSnapshot",
+                "outputs": [
+                    {"internalType": "uint256", "name": "", "type": "uint256"},
+                    {"internalType": "uint256", "name": "", "type": "uint256"},
+                    {"internalType": "uint256", "name": "", "type": "uint256"},
+                    {"internalType": "uint256", "name": "", "type": "uint256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
             }
         ]"#;
 
@@ -957,6 +1383,92 @@ impl CompoundManager {
                 "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
                 "stateMutability": "view",
                 "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "cTokenBorrowed", "type": "address"},
+                    {"internalType": "address", "name": "cTokenCollateral", "type": "address"},
+                    {"internalType": "uint256", "name": "actualRepayAmount", "type": "uint256"}
+                ],
+                "name": "liquidateCalculateSeizeTokens",
+                "outputs": [
+                    {"internalType": "uint256", "name": "", "type": "uint256"},
+                    {"internalType": "uint256", "name": "", "type": "uint256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "closeFactorMantissa",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    /// cETH takes repayments as value, so its `liquidateBorrow` has no amount
+    fn get_ceth_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "borrower", "type": "address"},
+                    {"internalType": "address", "name": "cTokenCollateral", "type": "address"}
+                ],
+                "name": "liquidateBorrow",
+                "outputs": [],
+                "stateMutability": "payable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_price_oracle_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "address", "name": "cToken", "type": "address"}],
+                "name": "getUnderlyingPrice",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_lens_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "contract Comp", "name": "comp", "type": "address"},
+                    {"internalType": "contract ComptrollerLensInterface", "name": "comptroller", "type": "address"},
+                    {"internalType": "address", "name": "account", "type": "address"}
+                ],
+                "name": "getCompBalanceMetadataExt",
+                "outputs": [
+                    {
+                        "components": [
+                            {"internalType": "uint256", "name": "balance", "type": "uint256"},
+                            {"internalType": "uint256", "name": "votes", "type": "uint256"},
+                            {"internalType": "address", "name": "delegate", "type": "address"},
+                            {"internalType": "uint256", "name": "allocated", "type": "uint256"}
+                        ],
+                        "internalType": "struct CompoundLens.CompBalanceMetadataExt",
+                        "name": "",
+                        "type": "tuple"
+                    }
+                ],
+                "stateMutability": "nonpayable",
+                "type": "function"
             }
         ]"#;
 
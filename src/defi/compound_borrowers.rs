@@ -0,0 +1,183 @@
+// Compound borrowers indexed from Borrow and RepayBorrow events
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::{
+    providers::{Middleware, Provider},
+    types::{Address, Filter, Log, H256, U256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::chains::pool::PooledHttp;
+use crate::jobs::backfill::BackfillProcessor;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `compound_borrowers_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/compound_borrowers.json";
+
+/// Outstanding borrow of an account in one cToken market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketBorrow {
+    pub ctoken: Address,
+    /// Account borrow balance right after the last event, in underlying units
+    pub account_borrows: U256,
+    pub block_number: u64,
+    pub log_index: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompoundBorrower {
+    pub chain_id: u64,
+    pub account: Address,
+    pub borrows: Vec<MarketBorrow>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CompoundBorrower {
+    pub fn is_open(&self) -> bool {
+        self.borrows.iter().any(|borrow| !borrow.account_borrows.is_zero())
+    }
+
+    /// Apply an event unless a later one for the market was already seen
+    fn apply(&mut self, ctoken: Address, account_borrows: U256, block_number: u64, log_index: u64) {
+        match self.borrows.iter_mut().find(|borrow| borrow.ctoken == ctoken) {
+            Some(borrow) if (borrow.block_number, borrow.log_index) >= (block_number, log_index) => {}
+            Some(borrow) => {
+                borrow.account_borrows = account_borrows;
+                borrow.block_number = block_number;
+                borrow.log_index = log_index;
+            }
+            None => self.borrows.push(MarketBorrow { ctoken, account_borrows, block_number, log_index }),
+        }
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Accounts that borrowed from Compound, built by backfilling cToken events
+pub struct CompoundBorrowerIndex {
+    /// cToken markets per chain
+    markets: HashMap<u64, Vec<Address>>,
+    /// JSON file holding the index, `None` keeps it in memory only
+    store_path: Option<PathBuf>,
+    borrowers: RwLock<HashMap<(u64, Address), CompoundBorrower>>,
+}
+
+impl CompoundBorrowerIndex {
+    pub async fn new(markets: HashMap<u64, Vec<Address>>, store_path: Option<PathBuf>) -> Result<Self> {
+        let borrowers: Vec<CompoundBorrower> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !borrowers.is_empty()) {
+            info!("Loaded {} Compound borrowers from {}", borrowers.len(), path.display());
+        }
+
+        Ok(Self {
+            markets,
+            store_path,
+            borrowers: RwLock::new(borrowers.into_iter().map(|borrower| ((borrower.chain_id, borrower.account), borrower)).collect()),
+        })
+    }
+
+    /// Index persisting to `compound_borrowers_store_path`, an empty path keeps it in memory
+    pub async fn from_config(config: &config::Config, markets: HashMap<u64, Vec<Address>>) -> Result<Self> {
+        let path = config
+            .get_string("compound_borrowers_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(markets, store_path).await
+    }
+
+    /// Accounts with an outstanding borrow on the chain
+    pub async fn open_borrowers(&self, chain_id: u64) -> Vec<CompoundBorrower> {
+        let mut open: Vec<CompoundBorrower> = self.borrowers.read().await.values()
+            .filter(|borrower| borrower.chain_id == chain_id && borrower.is_open())
+            .cloned()
+            .collect();
+        open.sort_by_key(|borrower| borrower.account);
+        open
+    }
+
+    async fn persist(&self, borrowers: &HashMap<(u64, Address), CompoundBorrower>) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let borrowers: Vec<&CompoundBorrower> = borrowers.values().collect();
+        if let Err(e) = write_store(path, &borrowers).await {
+            warn!("Failed to persist Compound borrowers to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl BackfillProcessor for CompoundBorrowerIndex {
+    fn name(&self) -> &'static str {
+        "compound_borrowers"
+    }
+
+    async fn process(&self, chain_id: u64, provider: &Provider<PooledHttp>, from_block: u64, to_block: u64) -> Result<u64> {
+        let markets = self.markets.get(&chain_id)
+            .ok_or_else(|| anyhow!("Compound is not deployed on chain {}", chain_id))?;
+        let filter = Filter::new()
+            .address(markets.clone())
+            .topic0(vec![borrow_topic(), repay_borrow_topic()])
+            .from_block(from_block)
+            .to_block(to_block);
+        let logs = provider.get_logs(&filter).await?;
+
+        let mut borrowers = self.borrowers.write().await;
+        let mut indexed = 0;
+        for log in &logs {
+            let Some((account, account_borrows)) = decode_borrow_event(log) else {
+                continue;
+            };
+            let (Some(block_number), Some(log_index)) = (log.block_number, log.log_index) else {
+                continue;
+            };
+            borrowers
+                .entry((chain_id, account))
+                .or_insert_with(|| CompoundBorrower {
+                    chain_id,
+                    account,
+                    borrows: Vec::new(),
+                    updated_at: Utc::now(),
+                })
+                .apply(log.address, account_borrows, block_number.as_u64(), log_index.as_u64());
+            indexed += 1;
+        }
+
+        if indexed > 0 {
+            self.persist(&borrowers).await;
+        }
+        Ok(indexed)
+    }
+}
+
+/// `Borrow(address borrower, uint borrowAmount, uint accountBorrows, uint totalBorrows)`
+fn borrow_topic() -> H256 {
+    H256::from(keccak256("Borrow(address,uint256,uint256,uint256)"))
+}
+
+/// `RepayBorrow(address payer, address borrower, uint repayAmount, uint accountBorrows, uint totalBorrows)`
+fn repay_borrow_topic() -> H256 {
+    H256::from(keccak256("RepayBorrow(address,address,uint256,uint256,uint256)"))
+}
+
+/// Borrower and its balance after the event; cToken events carry no indexed parameters
+fn decode_borrow_event(log: &Log) -> Option<(Address, U256)> {
+    let topic = *log.topics.first()?;
+    let word = |index: usize| log.data.get(index * 32..(index + 1) * 32);
+    let (account_word, balance_word) = if topic == borrow_topic() {
+        (word(0)?, word(2)?)
+    } else if topic == repay_borrow_topic() {
+        (word(1)?, word(3)?)
+    } else {
+        return None;
+    };
+    Some((Address::from_slice(&account_word[12..]), U256::from_big_endian(balance_word)))
+}
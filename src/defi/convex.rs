@@ -0,0 +1,779 @@
+// Curve stablecoin pools and their Convex staking: adding liquidity, staking the LP token through the
+// booster, claiming CRV and CVX, and each pool's yield split into trading fees, boosted CRV and CVX
+use std::{collections::HashMap, sync::Arc};
+use ethers::types::{Address, H256, U256, U64, TransactionRequest};
+use ethers::abi::{Abi, Token};
+use ethers::contract::Contract;
+use ethers::providers::Provider;
+use crate::chains::batch::RpcBatch;
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+use crate::contracts::approvals::TokenSpend;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
+use crate::dex::DexManager;
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+use tracing::debug;
+
+use super::leverage_projection::SECONDS_PER_YEAR;
+
+/// Share of a gauge deposit earning CRV without veCRV; a full boost of 2.5x earns on all of it
+const UNBOOSTED_SHARE: f64 = 0.4;
+/// Booster fees are in basis points
+const FEE_DENOMINATOR: f64 = 10_000.0;
+/// CVX minted per CRV falls by a thousandth every 100k CVX minted, until the 100M cap
+const CVX_REDUCTION_PER_CLIFF: u64 = 100_000;
+const CVX_TOTAL_CLIFFS: u64 = 1_000;
+const CVX_MAX_SUPPLY: u64 = 100_000_000;
+/// Virtual price growth is measured over about a week of mainnet blocks
+const BASE_APY_BLOCKS: u64 = 50_400;
+const BASE_APY_DAYS: f64 = 7.0;
+
+/// Whole units of an 18-decimal amount
+fn wad_to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, word| acc * 2f64.powi(64) + *word as f64) / 1e18
+}
+
+/// CVX minted for `crv` claimed while `supply` CVX exists
+fn cvx_minted(crv: U256, supply: U256) -> U256 {
+    let cliff = supply / (U256::from(CVX_REDUCTION_PER_CLIFF) * U256::exp10(18));
+    if cliff >= U256::from(CVX_TOTAL_CLIFFS) {
+        return U256::zero();
+    }
+    let minted = crv * (U256::from(CVX_TOTAL_CLIFFS) - cliff) / U256::from(CVX_TOTAL_CLIFFS);
+    minted.min((U256::from(CVX_MAX_SUPPLY) * U256::exp10(18)).saturating_sub(supply))
+}
+
+/// Curve stablecoin pool whose LP token Convex stakes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurvePool {
+    /// Pool name, e.g. `3pool`
+    pub name: String,
+    pub pool: Address,
+    pub lp_token: Address,
+    /// Coins in the order `add_liquidity` takes their amounts
+    pub coins: Vec<Address>,
+    /// Convex pool id of the LP token
+    pub pid: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvexContracts {
+    /// Takes LP deposits, stakes them in the Curve gauges and mints CVX on claims
+    pub booster: Address,
+    /// Holds the gauge deposits of every Convex pool and the veCRV boosting them
+    pub voter_proxy: Address,
+    /// Splits CRV emissions between the gauges
+    pub gauge_controller: Address,
+    pub crv: Address,
+    pub cvx: Address,
+    pub pools: Vec<CurvePool>,
+}
+
+/// Gauge and reward pool state a Curve pool's yield on Convex is computed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvexPool {
+    #[serde(flatten)]
+    pub curve: CurvePool,
+    pub gauge: Address,
+    /// BaseRewardPool the staked LP and CRV claims go through
+    pub reward_pool: Address,
+    /// Dollar value of an LP token, counting the pool's coins at par
+    pub virtual_price: f64,
+    /// Liquidity in the pool at the virtual price
+    #[serde(with = "crate::api::models::usd")]
+    pub tvl_usd: f64,
+    /// LP tokens staked through Convex
+    pub total_staked: U256,
+    /// Multiple of the unboosted CRV rate Convex's veCRV earns in the gauge, 1 to 2.5
+    pub boost: f64,
+    /// Share of the CRV Convex keeps for cvxCRV stakers, CVX lockers and harvesters, 0 to 1
+    pub convex_fee: f64,
+    /// CRV paid to stakers per LP token over a year at the current emissions, after the fee
+    pub crv_per_lp_per_year: f64,
+    /// CVX minted per CRV claimed at the current supply
+    pub cvx_per_crv: f64,
+    /// Trading fees as the virtual price growth of the last week, annualized in percent; `None` when
+    /// the node cannot answer for past blocks
+    pub base_apy: Option<f64>,
+    /// Convex no longer stakes the pool and refuses deposits
+    pub shutdown: bool,
+}
+
+/// Yield of staking a pool's LP token on Convex, in percent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvexApy {
+    /// Trading fees, compounding in the virtual price; 0 when unknown
+    pub base_apy: f64,
+    /// CRV and CVX at their current prices, paid out rather than compounded
+    pub crv_apr: f64,
+    pub cvx_apr: f64,
+    pub total_apy: f64,
+}
+
+impl ConvexPool {
+    /// Yield at the given CRV and CVX prices
+    pub fn apy(&self, crv_price: f64, cvx_price: f64) -> ConvexApy {
+        let percent_per_lp = if self.virtual_price > 0.0 { 100.0 / self.virtual_price } else { 0.0 };
+        let crv_apr = self.crv_per_lp_per_year * crv_price * percent_per_lp;
+        let cvx_apr = self.crv_per_lp_per_year * self.cvx_per_crv * cvx_price * percent_per_lp;
+        let base_apy = self.base_apy.unwrap_or(0.0);
+        ConvexApy { base_apy, crv_apr, cvx_apr, total_apy: base_apy + crv_apr + cvx_apr }
+    }
+}
+
+/// A pool with its yield, `apy` is `None` while CRV or CVX is unpriced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvexPoolYield {
+    #[serde(flatten)]
+    pub pool: ConvexPool,
+    pub apy: Option<ConvexApy>,
+}
+
+/// LP tokens an owner stakes in one Convex pool, with the rewards accrued
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvexPosition {
+    pub pid: u64,
+    pub name: String,
+    pub lp_token: Address,
+    pub reward_pool: Address,
+    pub staked: U256,
+    /// Staked LP at the virtual price
+    #[serde(with = "crate::api::models::usd")]
+    pub value_usd: f64,
+    pub earned_crv: U256,
+    /// CVX the booster mints on claiming `earned_crv` at the current supply
+    pub earned_cvx: U256,
+}
+
+/// A Curve or Convex transaction with the token it pulls from the owner, to approve first
+#[derive(Debug, Clone)]
+pub struct ConvexPlan {
+    pub transaction: TransactionRequest,
+    pub spend: Option<TokenSpend>,
+}
+
+/// Action of the Convex endpoints, amounts in base units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ConvexAction {
+    /// Add one of the pool's coins to the Curve pool of `pid`
+    AddLiquidity {
+        pid: u64,
+        token: Address,
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        amount: U256,
+    },
+    /// Stake LP tokens through the booster
+    Deposit {
+        pid: u64,
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        amount: U256,
+    },
+    /// Unstake LP tokens back to the owner, claiming their rewards
+    Withdraw {
+        pid: u64,
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        amount: U256,
+    },
+    /// Claim CRV, the CVX it mints and any extra rewards
+    Claim { pid: u64 },
+}
+
+/// Transactions of a Convex action, for the owner to send in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvexTransactions {
+    pub chain_id: u64,
+    pub owner: Address,
+    pub action: ConvexAction,
+    pub transactions: Vec<TransactionRequest>,
+    /// Tracked transaction ids, in the order of `transactions`
+    pub transaction_ids: Vec<String>,
+}
+
+pub struct ConvexManager {
+    chain_manager: Arc<ChainManager>,
+    dex_manager: Arc<DexManager>,
+    contracts: HashMap<u64, ConvexContracts>,
+}
+
+impl ConvexManager {
+    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>) -> Result<Self> {
+        let mut contracts = HashMap::new();
+
+        // Ethereum mainnet contracts
+        let usdc: Address = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".parse()?;
+        contracts.insert(1, ConvexContracts {
+            booster: "0xF403C135812408BFbE8713b5A23a04b3D48AAE31".parse()?,
+            voter_proxy: "0x989AEb4d175e16225E39E87d0D97A3360524AD80".parse()?,
+            gauge_controller: "0x2F50D538606Fa9EDD2B11E2446BEb18C9D5846bB".parse()?,
+            crv: "0xD533a949740bb3306d119CC777fa900bA034cd52".parse()?,
+            cvx: "0x4e3FBD56CD56c3e72c1403e103b45Db9da5B9D2B".parse()?,
+            pools: vec![
+                CurvePool {
+                    name: "3pool".to_string(),
+                    pool: "0xbEbc44782C7dB0a1A60Cb6fe97d0b483032FF1C7".parse()?,
+                    lp_token: "0x6c3F90f043a72FA612cbac8115EE7e52BDe6E490".parse()?,
+                    coins: vec![
+                        "0x6B175474E89094C44Da98b954EedeAC495271d0F".parse()?, // DAI
+                        usdc,
+                        "0xdAC17F958D2ee523a2206206994597C13D831ec7".parse()?, // USDT
+                    ],
+                    pid: 9,
+                },
+                CurvePool {
+                    name: "FRAXBP".to_string(),
+                    pool: "0xDcEF968d416a41Cdac0ED8702fAC8128A64241A2".parse()?,
+                    lp_token: "0x3175Df0976dFA876431C2E9eE6Bc45b65d3473CC".parse()?,
+                    coins: vec![
+                        "0x853d955aCEf822Db058eb8505911ED77F175b99e".parse()?, // FRAX
+                        usdc,
+                    ],
+                    pid: 100,
+                },
+            ],
+        });
+
+        Ok(Self {
+            chain_manager,
+            dex_manager,
+            contracts,
+        })
+    }
+
+    /// Address-registry entries probed against the Curve and Convex interfaces at startup
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| {
+                let core = [
+                    ContractDeployment::new(chain_id, "convex", "booster", contracts.booster, ProtocolInterface::ConvexBooster),
+                    ContractDeployment::new(chain_id, "convex", "cvx", contracts.cvx, ProtocolInterface::Erc20),
+                    ContractDeployment::new(chain_id, "curve", "gauge_controller", contracts.gauge_controller, ProtocolInterface::CurveGaugeController),
+                    ContractDeployment::new(chain_id, "curve", "crv", contracts.crv, ProtocolInterface::Erc20),
+                ];
+                let pools = contracts.pools.iter().flat_map(move |pool| {
+                    let role = pool.name.to_lowercase();
+                    [
+                        ContractDeployment::new(chain_id, "curve", &format!("{}_pool", role), pool.pool, ProtocolInterface::CurveStableSwap),
+                        ContractDeployment::new(chain_id, "curve", &format!("{}_lp", role), pool.lp_token, ProtocolInterface::Erc20),
+                    ]
+                });
+                core.into_iter().chain(pools)
+            })
+            .collect()
+    }
+
+    /// CRV and CVX on chains Convex is deployed on
+    pub fn reward_tokens(&self, chain_id: u64) -> Option<(Address, Address)> {
+        self.contracts.get(&chain_id).map(|contracts| (contracts.crv, contracts.cvx))
+    }
+
+    /// Pools `token` can be added to as one of their coins
+    pub fn pools_with_coin(&self, chain_id: u64, token: Address) -> Vec<&CurvePool> {
+        self.contracts.get(&chain_id)
+            .map(|contracts| contracts.pools.iter().filter(|pool| pool.coins.contains(&token)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Configured pool of a Curve pool address
+    pub fn curve_pool(&self, chain_id: u64, pool: Address) -> Option<&CurvePool> {
+        self.contracts.get(&chain_id)?.pools.iter().find(|curve| curve.pool == pool)
+    }
+
+    /// Configured pool minting an LP token
+    pub fn pool_by_lp(&self, chain_id: u64, lp_token: Address) -> Option<&CurvePool> {
+        self.contracts.get(&chain_id)?.pools.iter().find(|curve| curve.lp_token == lp_token)
+    }
+
+    /// State of every configured pool
+    pub async fn pools(&self, chain_id: u64) -> Result<Vec<ConvexPool>> {
+        let contracts = self.contracts(chain_id)?;
+        let mut pools = Vec::with_capacity(contracts.pools.len());
+        for curve in &contracts.pools {
+            pools.push(self.pool(chain_id, curve.pid).await?);
+        }
+        Ok(pools)
+    }
+
+    /// Gauge, reward pool, boost and emissions of the pool staked as `pid`
+    pub async fn pool(&self, chain_id: u64, pid: u64) -> Result<ConvexPool> {
+        let contracts = self.contracts(chain_id)?;
+        let curve = self.configured(chain_id, pid)?.clone();
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let booster = Contract::new(contracts.booster, Self::get_booster_abi()?, client.clone());
+        let curve_pool = Contract::new(curve.pool, Self::get_curve_pool_abi(curve.coins.len())?, client.clone());
+        let lp_token = Contract::new(curve.lp_token, Self::get_token_abi()?, client.clone());
+        let crv = Contract::new(contracts.crv, Self::get_token_abi()?, client.clone());
+        let cvx = Contract::new(contracts.cvx, Self::get_token_abi()?, client.clone());
+
+        // Pool addresses, fees, prices and emissions in one round trip
+        let info_call = booster.method::<_, (Address, Address, Address, Address, Address, bool)>("poolInfo", U256::from(pid))?;
+        let fee_calls = ["lockIncentive", "stakerIncentive", "earmarkIncentive", "platformFee"].into_iter()
+            .map(|fee| booster.method::<_, U256>(fee, ()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let virtual_price_call = curve_pool.method::<_, U256>("get_virtual_price", ())?;
+        let lp_supply_call = lp_token.method::<_, U256>("totalSupply", ())?;
+        let crv_rate_call = crv.method::<_, U256>("rate", ())?;
+        let cvx_supply_call = cvx.method::<_, U256>("totalSupply", ())?;
+        let mut batch = RpcBatch::new();
+        let block = batch.request("eth_blockNumber", ())?;
+        let info = batch.call(&info_call)?;
+        let fees = fee_calls.iter().map(|call| batch.call(call)).collect::<Result<Vec<_>>>()?;
+        let virtual_price = batch.call(&virtual_price_call)?;
+        let lp_supply = batch.call(&lp_supply_call)?;
+        let crv_rate = batch.call(&crv_rate_call)?;
+        let cvx_supply = batch.call(&cvx_supply_call)?;
+        let results = provider.batch(&batch).await?;
+
+        let (lp, _, gauge_address, reward_pool_address, _, shutdown) = results.decode(info, &info_call)?;
+        if lp != curve.lp_token {
+            return Err(anyhow!("Convex pool {} stakes {:?}, not the {} LP token {:?}", pid, lp, curve.name, curve.lp_token));
+        }
+        let mut fee_bps = U256::zero();
+        for (index, call) in fees.into_iter().zip(&fee_calls) {
+            fee_bps += results.decode(index, call)?;
+        }
+        let block: U64 = results.get(block)?;
+        let virtual_price = wad_to_f64(results.decode(virtual_price, &virtual_price_call)?);
+        let lp_supply = results.decode(lp_supply, &lp_supply_call)?;
+        let crv_rate = results.decode(crv_rate, &crv_rate_call)?;
+        let cvx_supply = results.decode(cvx_supply, &cvx_supply_call)?;
+
+        // Convex's share of the gauge and the virtual price a week ago
+        let controller = Contract::new(contracts.gauge_controller, Self::get_gauge_controller_abi()?, client.clone());
+        let gauge = Contract::new(gauge_address, Self::get_gauge_abi()?, client.clone());
+        let reward_pool = Contract::new(reward_pool_address, Self::get_reward_pool_abi()?, client);
+        let weight_call = controller.method::<_, U256>("gauge_relative_weight", gauge_address)?;
+        let working_supply_call = gauge.method::<_, U256>("working_supply", ())?;
+        let working_balance_call = gauge.method::<_, U256>("working_balances", contracts.voter_proxy)?;
+        let voter_balance_call = gauge.method::<_, U256>("balanceOf", contracts.voter_proxy)?;
+        let staked_call = reward_pool.method::<_, U256>("totalSupply", ())?;
+        let past_price_call = curve_pool.method::<_, U256>("get_virtual_price", ())?
+            .block(block.as_u64().saturating_sub(BASE_APY_BLOCKS));
+        let mut batch = RpcBatch::new();
+        let weight = batch.call(&weight_call)?;
+        let working_supply = batch.call(&working_supply_call)?;
+        let working_balance = batch.call(&working_balance_call)?;
+        let voter_balance = batch.call(&voter_balance_call)?;
+        let staked = batch.call(&staked_call)?;
+        let past_price = batch.call(&past_price_call)?;
+        let results = provider.batch(&batch).await?;
+
+        let gauge_crv_per_second = wad_to_f64(crv_rate) * wad_to_f64(results.decode(weight, &weight_call)?);
+        let working_supply = wad_to_f64(results.decode(working_supply, &working_supply_call)?);
+        let working_balance = wad_to_f64(results.decode(working_balance, &working_balance_call)?);
+        let voter_balance = wad_to_f64(results.decode(voter_balance, &voter_balance_call)?);
+        let boost = if voter_balance > 0.0 { working_balance / (UNBOOSTED_SHARE * voter_balance) } else { 1.0 };
+        let unboosted_crv_per_lp = if working_supply > 0.0 {
+            gauge_crv_per_second * UNBOOSTED_SHARE / working_supply
+        } else {
+            0.0
+        };
+        let convex_fee = fee_bps.as_u64() as f64 / FEE_DENOMINATOR;
+        let base_apy = match results.decode(past_price, &past_price_call) {
+            Ok(past) if !past.is_zero() => {
+                Some(((virtual_price / wad_to_f64(past)).powf(365.0 / BASE_APY_DAYS) - 1.0) * 100.0)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                debug!("No past virtual price of Curve {} on chain {}: {}", curve.name, chain_id, e);
+                None
+            }
+        };
+
+        Ok(ConvexPool {
+            gauge: gauge_address,
+            reward_pool: reward_pool_address,
+            virtual_price,
+            tvl_usd: wad_to_f64(lp_supply) * virtual_price,
+            total_staked: results.decode(staked, &staked_call)?,
+            boost,
+            convex_fee,
+            crv_per_lp_per_year: unboosted_crv_per_lp * boost * (1.0 - convex_fee) * SECONDS_PER_YEAR,
+            cvx_per_crv: wad_to_f64(cvx_minted(U256::exp10(18), cvx_supply)),
+            base_apy,
+            shutdown,
+            curve,
+        })
+    }
+
+    /// Staked LP and accrued rewards of `owner` in every configured pool, leaving out empty ones
+    pub async fn positions(&self, chain_id: u64, owner: Address) -> Result<Vec<ConvexPosition>> {
+        let Some(contracts) = self.contracts.get(&chain_id) else {
+            return Ok(Vec::new());
+        };
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let booster = Contract::new(contracts.booster, Self::get_booster_abi()?, client.clone());
+        let cvx = Contract::new(contracts.cvx, Self::get_token_abi()?, client.clone());
+
+        let cvx_supply_call = cvx.method::<_, U256>("totalSupply", ())?;
+        let mut batch = RpcBatch::new();
+        let cvx_supply = batch.call(&cvx_supply_call)?;
+        let mut reads = Vec::new();
+        for curve in &contracts.pools {
+            let info_call = booster.method::<_, (Address, Address, Address, Address, Address, bool)>("poolInfo", U256::from(curve.pid))?;
+            let curve_pool = Contract::new(curve.pool, Self::get_curve_pool_abi(curve.coins.len())?, client.clone());
+            let price_call = curve_pool.method::<_, U256>("get_virtual_price", ())?;
+            reads.push((curve, batch.call(&info_call)?, info_call, batch.call(&price_call)?, price_call));
+        }
+        let results = provider.batch(&batch).await?;
+        let cvx_supply = results.decode(cvx_supply, &cvx_supply_call)?;
+
+        let reward_pool_abi = Self::get_reward_pool_abi()?;
+        let mut batch = RpcBatch::new();
+        let mut balances = Vec::new();
+        for (curve, info, info_call, price, price_call) in reads {
+            let reward_pool = results.decode(info, &info_call)?.3;
+            let virtual_price = wad_to_f64(results.decode(price, &price_call)?);
+            let contract = Contract::new(reward_pool, reward_pool_abi.clone(), client.clone());
+            let staked_call = contract.method::<_, U256>("balanceOf", owner)?;
+            let earned_call = contract.method::<_, U256>("earned", owner)?;
+            balances.push((curve, reward_pool, virtual_price, batch.call(&staked_call)?, staked_call, batch.call(&earned_call)?, earned_call));
+        }
+        let results = provider.batch(&batch).await?;
+
+        let mut positions = Vec::new();
+        for (curve, reward_pool, virtual_price, staked, staked_call, earned, earned_call) in balances {
+            let staked = results.decode(staked, &staked_call)?;
+            let earned_crv = results.decode(earned, &earned_call)?;
+            if staked.is_zero() && earned_crv.is_zero() {
+                continue;
+            }
+            positions.push(ConvexPosition {
+                pid: curve.pid,
+                name: curve.name.clone(),
+                lp_token: curve.lp_token,
+                reward_pool,
+                staked,
+                value_usd: wad_to_f64(staked) * virtual_price,
+                earned_crv,
+                earned_cvx: cvx_minted(earned_crv, cvx_supply),
+            });
+        }
+        Ok(positions)
+    }
+
+    /// LP tokens adding `amount` of `token` to the Curve pool of `pid` mints, before the pool's fee
+    pub async fn quote_liquidity(&self, chain_id: u64, pid: u64, token: Address, amount: U256) -> Result<U256> {
+        let (curve, amounts) = self.coin_amounts(chain_id, pid, token, amount)?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let pool = Contract::new(curve.pool, Self::get_curve_pool_abi(curve.coins.len())?, Arc::new(provider.provider.clone()));
+        Ok(pool.method::<_, U256>("calc_token_amount", (amounts, true))?.call().await?)
+    }
+
+    /// Add `amount` of one coin to the Curve pool of `pid`, reverting below `min_lp_amount` LP tokens
+    pub async fn add_liquidity(&self, chain_id: u64, pid: u64, token: Address, amount: U256, min_lp_amount: U256) -> Result<ConvexPlan> {
+        if amount.is_zero() {
+            return Err(anyhow!("Liquidity to add must be positive"));
+        }
+        let (curve, amounts) = self.coin_amounts(chain_id, pid, token, amount)?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let pool = Contract::new(curve.pool, Self::get_curve_pool_abi(curve.coins.len())?, Arc::new(provider.provider.clone()));
+        let tx = pool.method::<_, H256>("add_liquidity", (amounts, min_lp_amount))?.tx;
+
+        Ok(ConvexPlan {
+            transaction: tx.into(),
+            spend: Some(TokenSpend { token, spender: curve.pool, amount }),
+        })
+    }
+
+    /// Stake `amount` LP tokens of `pid` through the booster, which deposits them in the gauge
+    pub async fn deposit(&self, chain_id: u64, pid: u64, amount: U256) -> Result<ConvexPlan> {
+        if amount.is_zero() {
+            return Err(anyhow!("LP tokens to stake must be positive"));
+        }
+        let contracts = self.contracts(chain_id)?;
+        let curve = self.configured(chain_id, pid)?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let booster = Contract::new(contracts.booster, Self::get_booster_abi()?, Arc::new(provider.provider.clone()));
+        let tx = booster.method::<_, H256>("deposit", (U256::from(pid), amount, true))?.tx;
+
+        Ok(ConvexPlan {
+            transaction: tx.into(),
+            spend: Some(TokenSpend { token: curve.lp_token, spender: contracts.booster, amount }),
+        })
+    }
+
+    /// Unstake `amount` LP tokens of `pid` back to `owner`, claiming the rewards on the way
+    pub async fn withdraw(&self, chain_id: u64, owner: Address, pid: u64, amount: U256) -> Result<ConvexPlan> {
+        if amount.is_zero() {
+            return Err(anyhow!("LP tokens to unstake must be positive"));
+        }
+        let reward_pool = self.reward_pool(chain_id, pid).await?;
+        let staked: U256 = reward_pool.method("balanceOf", owner)?.call().await?;
+        if amount > staked {
+            return Err(anyhow!("{:?} stakes {} LP tokens in Convex pool {}, not {}", owner, staked, pid, amount));
+        }
+        let tx = reward_pool.method::<_, H256>("withdrawAndUnwrap", (amount, true))?.tx;
+        Ok(ConvexPlan { transaction: tx.into(), spend: None })
+    }
+
+    /// Claim `owner`'s CRV from `pid`, which mints their CVX, with the pool's extra rewards
+    pub async fn claim_rewards(&self, chain_id: u64, owner: Address, pid: u64) -> Result<TransactionRequest> {
+        let reward_pool = self.reward_pool(chain_id, pid).await?;
+        let tx = reward_pool.method::<_, H256>("getReward", (owner, true))?.tx;
+        Ok(tx.into())
+    }
+
+    /// Approval the plan's spend needs first, if any, followed by its transaction
+    pub async fn with_approval(&self, chain_id: u64, owner: Address, plan: &ConvexPlan) -> Result<Vec<TransactionRequest>> {
+        let mut transactions = Vec::with_capacity(2);
+        if let Some(spend) = plan.spend {
+            if let Some(approval) = self.dex_manager.approvals().required_approval(chain_id, owner, spend, None).await? {
+                transactions.push(approval.transaction);
+            }
+        }
+        transactions.push(plan.transaction.clone());
+        Ok(transactions)
+    }
+
+    /// Reward pool of `pid` as the booster lists it
+    async fn reward_pool(&self, chain_id: u64, pid: u64) -> Result<Contract<Provider<PooledHttp>>> {
+        let contracts = self.contracts(chain_id)?;
+        self.configured(chain_id, pid)?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let booster = Contract::new(contracts.booster, Self::get_booster_abi()?, client.clone());
+        let (_, _, _, reward_pool, _, _): (Address, Address, Address, Address, Address, bool) =
+            booster.method("poolInfo", U256::from(pid))?.call().await?;
+        Ok(Contract::new(reward_pool, Self::get_reward_pool_abi()?, client))
+    }
+
+    /// The pool of `pid` with `amount` at the index of `token` among its coins
+    fn coin_amounts(&self, chain_id: u64, pid: u64, token: Address, amount: U256) -> Result<(&CurvePool, Token)> {
+        let curve = self.configured(chain_id, pid)?;
+        let index = curve.coins.iter()
+            .position(|coin| *coin == token)
+            .ok_or_else(|| anyhow!("{:?} is not a coin of Curve {}", token, curve.name))?;
+        let amounts = (0..curve.coins.len())
+            .map(|i| Token::Uint(if i == index { amount } else { U256::zero() }))
+            .collect();
+        Ok((curve, Token::FixedArray(amounts)))
+    }
+
+    fn contracts(&self, chain_id: u64) -> Result<&ConvexContracts> {
+        self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Convex is not deployed on chain {}", chain_id))
+    }
+
+    fn configured(&self, chain_id: u64, pid: u64) -> Result<&CurvePool> {
+        self.contracts(chain_id)?.pools.iter()
+            .find(|curve| curve.pid == pid)
+            .ok_or_else(|| anyhow!("Convex pool {} is not configured on chain {}", pid, chain_id))
+    }
+
+    fn get_booster_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "name": "poolInfo",
+                "outputs": [
+                    {"internalType": "address", "name": "lptoken", "type": "address"},
+                    {"internalType": "address", "name": "token", "type": "address"},
+                    {"internalType": "address", "name": "gauge", "type": "address"},
+                    {"internalType": "address", "name": "crvRewards", "type": "address"},
+                    {"internalType": "address", "name": "stash", "type": "address"},
+                    {"internalType": "bool", "name": "shutdown", "type": "bool"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "lockIncentive",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "stakerIncentive",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "earmarkIncentive",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "platformFee",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "_pid", "type": "uint256"},
+                    {"internalType": "uint256", "name": "_amount", "type": "uint256"},
+                    {"internalType": "bool", "name": "_stake", "type": "bool"}
+                ],
+                "name": "deposit",
+                "outputs": [{"internalType": "bool", "name": "", "type": "bool"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_reward_pool_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "totalSupply",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "address", "name": "account", "type": "address"}],
+                "name": "balanceOf",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "address", "name": "account", "type": "address"}],
+                "name": "earned",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "_account", "type": "address"},
+                    {"internalType": "bool", "name": "_claimExtras", "type": "bool"}
+                ],
+                "name": "getReward",
+                "outputs": [{"internalType": "bool", "name": "", "type": "bool"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "amount", "type": "uint256"},
+                    {"internalType": "bool", "name": "claim", "type": "bool"}
+                ],
+                "name": "withdrawAndUnwrap",
+                "outputs": [{"internalType": "bool", "name": "", "type": "bool"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    /// Stableswap pool of `coins` coins, whose amounts are fixed-size arrays
+    fn get_curve_pool_abi(coins: usize) -> Result<Abi> {
+        let abi_json = format!(r#"[
+            {{
+                "inputs": [],
+                "name": "get_virtual_price",
+                "outputs": [{{"name": "", "type": "uint256"}}],
+                "stateMutability": "view",
+                "type": "function"
+            }},
+            {{
+                "inputs": [
+                    {{"name": "amounts", "type": "uint256[{coins}]"}},
+                    {{"name": "is_deposit", "type": "bool"}}
+                ],
+                "name": "calc_token_amount",
+                "outputs": [{{"name": "", "type": "uint256"}}],
+                "stateMutability": "view",
+                "type": "function"
+            }},
+            {{
+                "inputs": [
+                    {{"name": "amounts", "type": "uint256[{coins}]"}},
+                    {{"name": "min_mint_amount", "type": "uint256"}}
+                ],
+                "name": "add_liquidity",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }}
+        ]"#);
+
+        let abi: Abi = serde_json::from_str(&abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_gauge_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "working_supply",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"name": "arg0", "type": "address"}],
+                "name": "working_balances",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"name": "arg0", "type": "address"}],
+                "name": "balanceOf",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_gauge_controller_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"name": "addr", "type": "address"}],
+                "name": "gauge_relative_weight",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    /// Supply of LP tokens and CVX, and CRV's emission rate per second
+    fn get_token_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "totalSupply",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "rate",
+                "outputs": [{"name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+}
@@ -1,8 +1,11 @@
 use std::{sync::Arc, collections::HashMap};
 use ethers::types::{Address, U256, H256, Bytes, TransactionRequest};
-use ethers::abi::{Abi, Token, ParamType, AbiEncode};
+use ethers::abi::{self, Abi, Token};
 use ethers::contract::Contract;
 use crate::chains::ChainManager;
+use crate::contracts::approvals::transaction_target;
+use crate::defi::closeout::{flash_loan_premium, FlashLoanLeg, FLASH_LOAN_GAS, WITHDRAW_GAS};
+use crate::defi::compound::{LiquidationLeg, LIQUIDATION_GAS};
 use crate::dex::DexManager;
 use anyhow::{Result, anyhow};
 use serde::{Serialize, Deserialize};
@@ -45,6 +48,54 @@ pub enum ArbitrageOperation {
     LiquidationArbitrage { protocol: String, borrower: Address, asset: Address, amount: U256 },
 }
 
+/// A call the flash loan receiver makes while it holds the loan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiverCall {
+    pub target: Address,
+    pub value: U256,
+    pub data: Bytes,
+    pub description: String,
+}
+
+impl ReceiverCall {
+    fn new(transaction: &TransactionRequest, description: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            target: transaction_target(transaction).ok_or_else(|| anyhow!("Receiver call has no target"))?,
+            value: transaction.value.unwrap_or_default(),
+            data: transaction.data.clone().unwrap_or_default(),
+            description: description.into(),
+        })
+    }
+
+    fn token(&self) -> Token {
+        Token::Tuple(vec![Token::Address(self.target), Token::Uint(self.value), Token::Bytes(self.data.to_vec())])
+    }
+}
+
+/// A liquidation funded by an Aave flash loan of the repaid asset, ready to send
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlashLiquidation {
+    pub chain_id: u64,
+    pub borrower: Address,
+    pub receiver: Address,
+    pub beneficiary: Address,
+    pub loan: FlashLoanLeg,
+    pub collateral_asset: Address,
+    pub collateral_amount: U256,
+    /// Venue the seized collateral is sold on, `None` when it already is the loaned asset
+    pub dex: Option<String>,
+    /// Loaned asset the collateral is expected to return
+    pub expected_output: U256,
+    /// What is left of the output once the loan and premium are repaid
+    pub expected_profit: U256,
+    pub min_profit: U256,
+    pub gas_estimate: u64,
+    pub operations: Vec<FlashLoanOperation>,
+    /// Calls the receiver makes in `executeOperation`, in order
+    pub calls: Vec<ReceiverCall>,
+    pub transaction: TransactionRequest,
+}
+
 pub struct FlashLoanManager {
     chain_manager: Arc<ChainManager>,
     dex_manager: Arc<DexManager>,
@@ -86,9 +137,10 @@ impl FlashLoanManager {
         let flash_loan_tx = self.create_aave_flash_loan(
             chain_id,
             aave_address,
+            "0x1234567890123456789012345678901234567890".parse()?, // Receiver address
             flash_loan_assets,
             flash_loan_amounts,
-            strategy.clone(),
+            Bytes::from(serde_json::to_vec(&strategy)?),
         ).await?;
 
         transactions.push(flash_loan_tx);
@@ -231,13 +283,141 @@ impl FlashLoanManager {
         Ok(opportunities)
     }
 
+    /// Flash loan of the repaid asset running `leg` through `receiver`: unwrap or approve the repayment,
+    /// liquidate, redeem the seized cTokens, sell the collateral back into the loaned asset and approve
+    /// the pool to take the loan and premium back.
+    ///
+    /// The receiver gets the calls ABI-encoded as its `params`, as
+    /// `((address target, uint256 value, bytes data)[] calls, address asset, uint256 minProfit, address beneficiary)`.
+    /// It is expected to make them in order from `executeOperation`, revert unless it then holds the
+    /// amount owed plus `minProfit` of the asset, and send the rest to the beneficiary.
+    pub async fn build_liquidation(
+        &self,
+        chain_id: u64,
+        leg: &LiquidationLeg,
+        receiver: Address,
+        beneficiary: Address,
+        min_profit: U256,
+    ) -> Result<FlashLiquidation> {
+        let lending_pool = self.get_aave_lending_pool(chain_id)?;
+        let approvals = self.dex_manager.approvals();
+        let premium = flash_loan_premium(leg.repay_amount);
+        let owed = leg.repay_amount + premium;
+        let required = owed + min_profit;
+
+        let mut calls = Vec::new();
+        let mut operations = vec![FlashLoanOperation::Liquidate {
+            protocol: "Compound".to_string(),
+            borrower: leg.borrower,
+            asset: leg.repay_asset,
+            amount: leg.repay_amount,
+        }];
+        if leg.native_borrow {
+            let unwrap = self.weth_call(chain_id, leg.repay_asset, "withdraw", vec![Token::Uint(leg.repay_amount)], U256::zero())?;
+            calls.push(ReceiverCall::new(&unwrap, "Unwrap the loaned WETH")?);
+        } else {
+            let approve = approvals.approve_transaction(chain_id, leg.repay_asset, leg.ctoken_borrowed, leg.repay_amount)?;
+            calls.push(ReceiverCall::new(&approve, "Approve the borrowed market to take the repayment")?);
+        }
+        calls.push(ReceiverCall::new(&leg.liquidate, format!("Liquidate {:?}", leg.borrower))?);
+        calls.push(ReceiverCall::new(&leg.redeem, "Redeem the seized cTokens")?);
+        operations.push(FlashLoanOperation::Withdraw {
+            protocol: "Compound".to_string(),
+            asset: leg.collateral_asset,
+            amount: leg.collateral_amount,
+        });
+        if leg.native_collateral {
+            // Redeeming accrues interest first, so at least the estimated ETH comes out
+            let wrap = self.weth_call(chain_id, leg.collateral_asset, "deposit", vec![], leg.collateral_amount)?;
+            calls.push(ReceiverCall::new(&wrap, "Wrap the redeemed ETH")?);
+        }
+
+        let mut gas_estimate = FLASH_LOAN_GAS + LIQUIDATION_GAS + WITHDRAW_GAS;
+        let (dex, expected_output) = if leg.collateral_asset == leg.repay_asset {
+            (None, leg.collateral_amount)
+        } else {
+            let quotes = self.dex_manager.get_comprehensive_quotes(
+                chain_id,
+                leg.collateral_asset,
+                leg.repay_asset,
+                leg.collateral_amount,
+                receiver,
+            ).await?;
+            let route = quotes.best_route;
+            let router = transaction_target(&route.transaction)
+                .ok_or_else(|| anyhow!("{:?} route has no router to approve", route.dex))?;
+            let dex = format!("{:?}", route.dex);
+            let approve = approvals.approve_transaction(chain_id, leg.collateral_asset, router, leg.collateral_amount)?;
+            calls.push(ReceiverCall::new(&approve, format!("Approve {} to take the collateral", dex))?);
+            calls.push(ReceiverCall::new(&route.transaction, format!("Sell the collateral on {}", dex))?);
+            operations.push(FlashLoanOperation::Swap {
+                dex: dex.clone(),
+                token_in: leg.collateral_asset,
+                token_out: leg.repay_asset,
+                amount_in: leg.collateral_amount,
+                min_amount_out: required,
+            });
+            gas_estimate += route.gas_estimate.as_u64();
+            (Some(dex), route.output_amount)
+        };
+        if expected_output < required {
+            return Err(anyhow!(
+                "Seized collateral returns {} of {:?}, short of the {} owed plus {} minimum profit",
+                expected_output, leg.repay_asset, owed, min_profit,
+            ));
+        }
+
+        let approve = approvals.approve_transaction(chain_id, leg.repay_asset, lending_pool, owed)?;
+        calls.push(ReceiverCall::new(&approve, "Approve the pool to take the loan and premium back")?);
+        operations.push(FlashLoanOperation::Repay {
+            protocol: "Aave".to_string(),
+            asset: leg.repay_asset,
+            amount: owed,
+            interest_rate_mode: 0,
+        });
+
+        let params = abi::encode(&[
+            Token::Array(calls.iter().map(ReceiverCall::token).collect()),
+            Token::Address(leg.repay_asset),
+            Token::Uint(min_profit),
+            Token::Address(beneficiary),
+        ]);
+        let transaction = self.create_aave_flash_loan(
+            chain_id,
+            lending_pool,
+            receiver,
+            vec![leg.repay_asset],
+            vec![leg.repay_amount],
+            Bytes::from(params),
+        ).await?;
+
+        Ok(FlashLiquidation {
+            chain_id,
+            borrower: leg.borrower,
+            receiver,
+            beneficiary,
+            loan: FlashLoanLeg { asset: leg.repay_asset, amount: leg.repay_amount, premium },
+            collateral_asset: leg.collateral_asset,
+            collateral_amount: leg.collateral_amount,
+            dex,
+            expected_output,
+            expected_profit: expected_output - owed,
+            min_profit,
+            gas_estimate,
+            operations,
+            calls,
+            transaction,
+        })
+    }
+
     async fn create_aave_flash_loan(
         &self,
         chain_id: u64,
         lending_pool: Address,
+        receiver: Address,
         assets: Vec<Address>,
         amounts: Vec<U256>,
-        strategy: FlashLoanStrategy,
+        params: Bytes,
     ) -> Result<TransactionRequest> {
         let provider = self.chain_manager.get_provider(chain_id).await?;
         let lending_pool_contract = Contract::new(
@@ -246,18 +426,16 @@ impl FlashLoanManager {
             Arc::new(provider.provider.clone()),
         );
 
-        // Encode strategy as params
-        let params = serde_json::to_vec(&strategy)?;
         let modes = vec![0u8; assets.len()]; // No debt mode
 
         let tx = lending_pool_contract
             .method::<_, H256>("flashLoan", (
-                "0x1234567890123456789012345678901234567890".parse::<Address>()?, // Receiver address
+                receiver,
                 assets,
                 amounts,
                 modes,
                 Address::zero(),
-                Bytes::from(params),
+                params,
                 0u16, // Referral code
             ))?
             .tx;
@@ -265,6 +443,11 @@ impl FlashLoanManager {
         Ok(tx.into())
     }
 
+    fn weth_call(&self, chain_id: u64, weth: Address, function: &str, args: Vec<Token>, value: U256) -> Result<TransactionRequest> {
+        let data = Self::get_weth_abi()?.function(function)?.encode_input(&args)?;
+        Ok(TransactionRequest::new().to(weth).data(data).value(value).chain_id(chain_id))
+    }
+
     pub async fn execute_arbitrage_strategy(&self, chain_id: u64, strategy: ArbitrageStrategy) -> Result<Vec<TransactionRequest>> {
         let mut transactions = Vec::new();
 
@@ -355,6 +538,28 @@ impl FlashLoanManager {
         }
     }
 
+    fn get_weth_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "deposit",
+                "outputs": [],
+                "stateMutability": "payable",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "uint256", "name": "wad", "type": "uint256"}],
+                "name": "withdraw",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
     fn get_aave_lending_pool_abi() -> Result<Abi> {
         let abi_json = r#"[
             {
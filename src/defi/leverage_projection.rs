@@ -0,0 +1,222 @@
+// Interest accrual, reward emissions, break-even and liquidation price path of leveraged lending strategies
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+use super::aave::YieldStep;
+use super::compound::CompoundStep;
+use crate::contracts::approvals::APPROVE_GAS;
+
+/// Days the projected path is reported at
+const PATH_DAYS: [u32; 6] = [0, 7, 30, 90, 180, 365];
+/// Furthest break-even and interest-driven liquidation are searched for
+const MAX_HORIZON_DAYS: u32 = 3650;
+/// Aave emissions are paid per second
+pub const SECONDS_PER_YEAR: f64 = 31_536_000.0;
+const SUPPLY_GAS: u64 = 250_000;
+const BORROW_GAS: u64 = 300_000;
+
+/// Supply and borrow legs of a leveraged strategy, in multiples of the deposit
+#[derive(Debug, Clone)]
+pub struct LeverageLegs {
+    /// Reserve asset on Aave, cToken on Compound
+    pub collateral_market: Address,
+    pub debt_market: Address,
+    /// Supplied to the collateral market, including borrowed funds supplied back
+    pub collateral_ratio: f64,
+    pub debt_ratio: f64,
+    supplies: u64,
+    borrows: u64,
+}
+
+impl LeverageLegs {
+    /// Legs of an Aave strategy, `None` unless it both supplies and borrows
+    pub fn from_aave_steps(steps: &[YieldStep]) -> Option<Self> {
+        Self::from_legs(steps.iter().filter_map(|step| match step {
+            YieldStep::Supply { asset, amount_ratio, .. } => Some((true, *asset, *amount_ratio)),
+            YieldStep::Borrow { asset, amount_ratio, .. } => Some((false, *asset, *amount_ratio)),
+            _ => None,
+        }))
+    }
+
+    /// Legs of a Compound strategy, `None` unless it both supplies and borrows
+    pub fn from_compound_steps(steps: &[CompoundStep]) -> Option<Self> {
+        Self::from_legs(steps.iter().filter_map(|step| match step {
+            CompoundStep::Supply { ctoken, amount_ratio } => Some((true, *ctoken, *amount_ratio)),
+            CompoundStep::Borrow { ctoken, amount_ratio } => Some((false, *ctoken, *amount_ratio)),
+            _ => None,
+        }))
+    }
+
+    /// `(supply, market, ratio)` legs; the first market supplied and the first borrowed from are followed
+    fn from_legs(legs: impl IntoIterator<Item = (bool, Address, f64)>) -> Option<Self> {
+        let (mut collateral, mut debt) = (None, None);
+        let (mut collateral_ratio, mut debt_ratio) = (0.0, 0.0);
+        let (mut supplies, mut borrows) = (0, 0);
+        for (supply, market, ratio) in legs {
+            if supply {
+                supplies += 1;
+                if *collateral.get_or_insert(market) == market {
+                    collateral_ratio += ratio;
+                }
+            } else {
+                borrows += 1;
+                if *debt.get_or_insert(market) == market {
+                    debt_ratio += ratio;
+                }
+            }
+        }
+
+        Some(Self {
+            collateral_market: collateral?,
+            debt_market: debt?,
+            collateral_ratio,
+            debt_ratio,
+            supplies,
+            borrows,
+        })
+    }
+
+    /// Borrowing from the market supplied to, so prices move both legs alike
+    pub fn same_market(&self) -> bool {
+        self.collateral_market == self.debt_market
+    }
+
+    /// Borrowed value held rather than supplied back, as a multiple of the deposit
+    pub fn idle_debt_ratio(&self) -> f64 {
+        if self.same_market() {
+            (self.debt_ratio - (self.collateral_ratio - 1.0)).max(0.0)
+        } else {
+            self.debt_ratio
+        }
+    }
+
+    /// Gas of the supplies, their approvals and the borrows
+    pub fn gas_units(&self) -> u64 {
+        self.supplies * (SUPPLY_GAS + APPROVE_GAS) + self.borrows * BORROW_GAS
+    }
+}
+
+/// Rates of a lending market the projection accrues, in percent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LendingMarketRates {
+    pub asset: Address,
+    /// 7-day average once the market was sampled, else the spot rate
+    pub supply_apy: f64,
+    /// Variable rate, averaged like `supply_apy`
+    pub borrow_apy: f64,
+    /// Reward emissions valued at the feed price, 0 when unpriced
+    pub supply_reward_apy: f64,
+    pub borrow_reward_apy: f64,
+    /// Share of the collateral value that can be borrowed before liquidation, 0 to 1
+    pub liquidation_threshold: f64,
+    pub price_usd: Option<f64>,
+}
+
+/// APY of yearly reward emissions on the deposits or debt of a market, 0 when either token is
+/// unpriced or the market is empty
+pub fn reward_apy(rewards_per_year: f64, reward_price_usd: Option<f64>, market_units: f64, asset_price_usd: Option<f64>) -> f64 {
+    match (reward_price_usd, asset_price_usd) {
+        (Some(reward_price), Some(asset_price)) if market_units > 0.0 && asset_price > 0.0 => {
+            rewards_per_year * reward_price / (market_units * asset_price) * 100.0
+        }
+        _ => 0.0,
+    }
+}
+
+pub struct LeverageInputs {
+    pub collateral_usd: f64,
+    pub debt_usd: f64,
+    /// Borrowed value held rather than supplied back
+    pub idle_debt_usd: f64,
+    pub collateral: LendingMarketRates,
+    pub debt: LendingMarketRates,
+    pub same_market: bool,
+    pub entry_cost_usd: Option<f64>,
+}
+
+/// State of the position a number of days after entry, at entry prices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectionPoint {
+    pub day: u32,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub debt_usd: f64,
+    /// Rewards accrued since entry, claimed apart from the position
+    #[serde(with = "crate::api::models::usd")]
+    pub rewards_usd: f64,
+    /// Collateral and held borrowed funds less debt, plus rewards
+    #[serde(with = "crate::api::models::usd")]
+    pub equity_usd: f64,
+    pub health_factor: f64,
+    /// Collateral price liquidating the position, `None` when borrowing from the market supplied to
+    pub liquidation_price_usd: Option<f64>,
+}
+
+/// Interest accrued on both legs and rewards earned over time, at constant rates and prices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeverageProjection {
+    pub collateral: LendingMarketRates,
+    pub debt: LendingMarketRates,
+    /// Collateral over the deposit
+    pub leverage: f64,
+    /// Equity gained over the first year on the deposit, in percent
+    pub net_apy: f64,
+    /// Gas of the supplies, approvals and borrows entering the position, `None` without a gas price
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub entry_cost_usd: Option<f64>,
+    /// Days until the equity gained covers the entry cost, `None` when it does not within ten years
+    /// or the cost is unknown
+    pub break_even_days: Option<u32>,
+    /// Days until debt interest outgrows the collateral, `None` when it does not within ten years
+    pub liquidation_days: Option<u32>,
+    pub path: Vec<ProjectionPoint>,
+}
+
+impl LeverageProjection {
+    pub fn new(inputs: LeverageInputs) -> Self {
+        let deposit_usd = inputs.collateral_usd + inputs.idle_debt_usd - inputs.debt_usd;
+        let rewards_per_year_usd = (inputs.collateral_usd * inputs.collateral.supply_reward_apy
+            + inputs.debt_usd * inputs.debt.borrow_reward_apy) / 100.0;
+        let collateral_price = inputs.collateral.price_usd.filter(|_| !inputs.same_market);
+
+        // APYs already compound, so each leg grows by its APY per year
+        let point = |day: u32| {
+            let years = day as f64 / 365.0;
+            let collateral_usd = inputs.collateral_usd * (1.0 + inputs.collateral.supply_apy / 100.0).powf(years);
+            let debt_usd = inputs.debt_usd * (1.0 + inputs.debt.borrow_apy / 100.0).powf(years);
+            let rewards_usd = rewards_per_year_usd * years;
+            let health_factor = collateral_usd * inputs.collateral.liquidation_threshold / debt_usd;
+            ProjectionPoint {
+                day,
+                collateral_usd,
+                debt_usd,
+                rewards_usd,
+                equity_usd: collateral_usd + inputs.idle_debt_usd - debt_usd + rewards_usd,
+                health_factor,
+                liquidation_price_usd: collateral_price.map(|price| price / health_factor),
+            }
+        };
+
+        let break_even_days = inputs.entry_cost_usd.and_then(|cost| {
+            (0..=MAX_HORIZON_DAYS).find(|day| point(*day).equity_usd - deposit_usd >= cost)
+        });
+        let liquidation_days = (0..=MAX_HORIZON_DAYS).find(|day| point(*day).health_factor < 1.0);
+        let net_apy = if deposit_usd > 0.0 {
+            (point(365).equity_usd - deposit_usd) / deposit_usd * 100.0
+        } else {
+            0.0
+        };
+
+        Self {
+            leverage: if deposit_usd > 0.0 { inputs.collateral_usd / deposit_usd } else { 0.0 },
+            net_apy,
+            entry_cost_usd: inputs.entry_cost_usd,
+            break_even_days,
+            liquidation_days,
+            path: PATH_DAYS.into_iter().map(point).collect(),
+            collateral: inputs.collateral,
+            debt: inputs.debt,
+        }
+    }
+}
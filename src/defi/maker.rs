@@ -0,0 +1,794 @@
+// MakerDAO vaults: opening them, locking collateral, drawing and repaying DAI, and reading their
+// collateralization against the liquidation ratio of their collateral type
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+use ethers::types::{Address, I256, U256, H256, TransactionRequest};
+use ethers::abi::Abi;
+use ethers::contract::Contract;
+use ethers::utils::{format_bytes32_string, parse_bytes32_string};
+use crate::chains::batch::RpcBatch;
+use crate::chains::ChainManager;
+use crate::contracts::approvals::TokenSpend;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
+use crate::dex::DexManager;
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+use tracing::debug;
+
+use super::leverage_projection::SECONDS_PER_YEAR;
+
+/// Maker's fixed-point unit of rates and prices; amounts are in wad (1e18) and their products in rad (1e45)
+const RAY_DECIMALS: usize = 27;
+
+fn ray() -> U256 {
+    U256::exp10(RAY_DECIMALS)
+}
+
+/// Collateralization strategies open vaults at, as a multiple of the liquidation ratio, so the
+/// collateral price can halve before liquidation
+pub const MAKER_TARGET_HEALTH: f64 = 2.0;
+
+/// Whole units of a fixed-point amount
+fn units(amount: U256, decimals: u32) -> f64 {
+    ethers::utils::format_units(amount, decimals)
+        .ok()
+        .and_then(|units| units.parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// Token a collateral type locks and the adapter it is joined to the Vat through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralType {
+    /// Collateral type name, e.g. `ETH-A`
+    pub ilk: String,
+    pub token: Address,
+    pub join: Address,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MakerContracts {
+    /// Core accounting of every vault
+    pub vat: Address,
+    /// Accrues stability fees
+    pub jug: Address,
+    /// Holds the liquidation ratio of each collateral type
+    pub spotter: Address,
+    /// DssCdpManager, owns the vaults it opens for users
+    pub cdp_manager: Address,
+    /// Lists the vaults the CDP manager holds for an owner
+    pub get_cdps: Address,
+    pub dai_join: Address,
+    pub dai: Address,
+    pub collateral_types: Vec<CollateralType>,
+}
+
+/// Risk parameters and fee of a collateral type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IlkInfo {
+    #[serde(flatten)]
+    pub collateral: CollateralType,
+    /// DAI owed per unit of normalized debt, in ray; grows with the stability fee
+    pub rate: U256,
+    /// Collateral price over the liquidation ratio, in ray; a vault is safe while its debt stays
+    /// within its collateral times `spot`
+    pub spot: U256,
+    /// Oracle price vaults are valued at, which trails the market by an hour
+    pub price_usd: f64,
+    /// Collateral value over debt below which vaults are liquidated, e.g. 1.5
+    pub liquidation_ratio: f64,
+    /// Stability fee compounded over a year, in percent
+    pub stability_fee_apy: f64,
+    /// Least DAI a vault with debt must owe, in wad
+    pub dust: U256,
+    /// DAI that can be drawn before the debt ceiling, in wad
+    pub available_debt: U256,
+}
+
+impl IlkInfo {
+    fn new(collateral: CollateralType, vat: (U256, U256, U256, U256, U256), mat: U256, duty: U256, base: U256) -> Self {
+        let (total_art, rate, spot, line, dust) = vat;
+        let liquidation_ratio = units(mat, RAY_DECIMALS as u32);
+        // Fees accrue per second at duty + base - 1
+        let fee_per_second = units(duty.saturating_add(base).saturating_sub(ray()), RAY_DECIMALS as u32);
+
+        Self {
+            collateral,
+            rate,
+            spot,
+            price_usd: units(spot, RAY_DECIMALS as u32) * liquidation_ratio,
+            liquidation_ratio,
+            stability_fee_apy: (fee_per_second.ln_1p() * SECONDS_PER_YEAR).exp_m1() * 100.0,
+            dust: dust / ray(),
+            available_debt: line.saturating_sub(total_art.saturating_mul(rate)) / ray(),
+        }
+    }
+
+    /// DAI `amount` of collateral can back at the liquidation ratio, in wad
+    pub fn max_debt(&self, amount: U256) -> U256 {
+        self.to_wad(amount).saturating_mul(self.spot) / ray()
+    }
+
+    /// Collateral in the collateral token's base units as the Vat counts it, in wad
+    fn to_wad(&self, amount: U256) -> U256 {
+        amount * U256::exp10(18 - self.collateral.decimals as usize)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MakerVault {
+    pub id: U256,
+    pub ilk: String,
+    /// Address holding the vault's collateral and debt in the Vat
+    pub urn: Address,
+    pub collateral_token: Address,
+    /// Locked collateral, in the collateral token's base units
+    pub collateral: U256,
+    /// DAI owed including the stability fee accrued until the last drip, in wad
+    pub debt: U256,
+    /// Debt before the rate is applied, as the Vat stores it
+    pub normalized_debt: U256,
+    /// Oracle price of the collateral
+    pub price_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_usd: f64,
+    /// DAI is valued at par, as Maker does
+    #[serde(with = "crate::api::models::usd")]
+    pub debt_usd: f64,
+    /// Collateral value over debt, `None` without debt
+    pub collateralization_ratio: Option<f64>,
+    pub liquidation_ratio: f64,
+    /// Collateralization ratio over the liquidation ratio, liquidated below 1; `None` without debt
+    pub health_factor: Option<f64>,
+    /// Collateral price liquidating the vault, `None` without debt
+    pub liquidation_price_usd: Option<f64>,
+    /// DAI that can still be drawn before the liquidation ratio, in wad
+    pub available_dai: U256,
+    pub stability_fee_apy: f64,
+}
+
+impl MakerVault {
+    /// Vault of `ink` collateral and `art` normalized debt, both in wad as the Vat stores them
+    fn new(id: U256, urn: Address, ilk: &IlkInfo, ink: U256, art: U256) -> Self {
+        let collateral_units = units(ink, 18);
+        let debt_usd = units(art.saturating_mul(ilk.rate), 45);
+        let collateral_usd = collateral_units * ilk.price_usd;
+        let collateralization_ratio = (debt_usd > 0.0).then(|| collateral_usd / debt_usd);
+
+        Self {
+            id,
+            ilk: ilk.collateral.ilk.clone(),
+            urn,
+            collateral_token: ilk.collateral.token,
+            collateral: ink / U256::exp10(18 - ilk.collateral.decimals as usize),
+            debt: art.saturating_mul(ilk.rate) / ray(),
+            normalized_debt: art,
+            price_usd: ilk.price_usd,
+            collateral_usd,
+            debt_usd,
+            collateralization_ratio,
+            liquidation_ratio: ilk.liquidation_ratio,
+            health_factor: collateralization_ratio.map(|ratio| ratio / ilk.liquidation_ratio),
+            liquidation_price_usd: (debt_usd > 0.0 && collateral_units > 0.0)
+                .then(|| debt_usd * ilk.liquidation_ratio / collateral_units),
+            available_dai: ink.saturating_mul(ilk.spot).saturating_sub(art.saturating_mul(ilk.rate)) / ray(),
+            stability_fee_apy: ilk.stability_fee_apy,
+        }
+    }
+
+    /// Name the vault is reported under in portfolio risk
+    pub fn label(&self) -> String {
+        format!("Maker vault {}", self.id)
+    }
+
+    fn ink(&self, ilk: &IlkInfo) -> U256 {
+        ilk.to_wad(self.collateral)
+    }
+}
+
+/// Transactions of one vault action in the order they are sent, with the vault they leave behind
+/// at current prices and rates
+#[derive(Debug, Clone)]
+pub struct VaultPlan {
+    pub transactions: Vec<TransactionRequest>,
+    /// Token an adapter pulls from the owner, to approve before the transactions
+    pub spend: Option<TokenSpend>,
+    pub vault_after: MakerVault,
+}
+
+/// Vault action of the Maker endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum VaultAction {
+    /// Open an empty vault of a collateral type, e.g. `ETH-A`
+    Open { ilk: String },
+    /// Lock collateral, in the collateral token's base units
+    Deposit {
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        vault_id: U256,
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        amount: U256,
+    },
+    /// Draw DAI to the owner, in wad
+    Draw {
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        vault_id: U256,
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        amount: U256,
+    },
+    /// Pay back DAI from the owner, in wad
+    Repay {
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        vault_id: U256,
+        #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+        amount: U256,
+    },
+}
+
+/// Transactions of a vault action, for the owner to send in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultTransactions {
+    pub chain_id: u64,
+    pub owner: Address,
+    pub action: VaultAction,
+    /// The vault once they settle at current prices and rates, `None` when opening
+    pub vault_after: Option<MakerVault>,
+    pub transactions: Vec<TransactionRequest>,
+    /// Tracked transaction ids, in the order of `transactions`
+    pub transaction_ids: Vec<String>,
+}
+
+pub struct MakerManager {
+    chain_manager: Arc<ChainManager>,
+    dex_manager: Arc<DexManager>,
+    contracts: HashMap<u64, MakerContracts>,
+}
+
+impl MakerManager {
+    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>) -> Result<Self> {
+        let mut contracts = HashMap::new();
+
+        // Ethereum mainnet contracts
+        let weth: Address = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse()?;
+        contracts.insert(1, MakerContracts {
+            vat: "0x35D1b3F3D7966A1DFe207aa4514C12a259A0492B".parse()?,
+            jug: "0x19c0976f590D67707E62397C87829d896Dc0f1F1".parse()?,
+            spotter: "0x65C79fcB50Ca1594B025960e539eD7A9a6D434A3".parse()?,
+            cdp_manager: "0x5ef30b9986345249bc32d8928B7ee64DE9435E39".parse()?,
+            get_cdps: "0x36a724Bd100c39f0Ea4D3A20F7097eE01A8Ff573".parse()?,
+            dai_join: "0x9759A6Ac90977b93B58547b4A71c78317f391A28".parse()?,
+            dai: "0x6B175474E89094C44Da98b954EedeAC495271d0F".parse()?,
+            collateral_types: vec![
+                CollateralType { ilk: "ETH-A".to_string(), token: weth, join: "0x2F0b23f53734252Bda2277357e97e1517d6B042A".parse()?, decimals: 18 },
+                CollateralType { ilk: "ETH-B".to_string(), token: weth, join: "0x08638eF1A205bE6762A8b935F5da9b700Cf7322c".parse()?, decimals: 18 },
+                CollateralType { ilk: "ETH-C".to_string(), token: weth, join: "0xF04a5cC80B1E94C69B48f5ee68a08CD2F09A7c3E".parse()?, decimals: 18 },
+                CollateralType {
+                    ilk: "WBTC-A".to_string(),
+                    token: "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599".parse()?,
+                    join: "0xBF72Da2Bd84c5170618Fbe5914B0ECA9638d5eb5".parse()?,
+                    decimals: 8,
+                },
+            ],
+        });
+
+        Ok(Self {
+            chain_manager,
+            dex_manager,
+            contracts,
+        })
+    }
+
+    /// Address-registry entries probed against the Maker interfaces at startup
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| {
+                let core = [
+                    ContractDeployment::new(chain_id, "maker", "vat", contracts.vat, ProtocolInterface::MakerVat),
+                    ContractDeployment::new(chain_id, "maker", "jug", contracts.jug, ProtocolInterface::MakerJug),
+                    ContractDeployment::new(chain_id, "maker", "spotter", contracts.spotter, ProtocolInterface::MakerSpotter),
+                    ContractDeployment::new(chain_id, "maker", "cdp_manager", contracts.cdp_manager, ProtocolInterface::MakerCdpManager),
+                    ContractDeployment::new(chain_id, "maker", "dai_join", contracts.dai_join, ProtocolInterface::MakerDaiJoin),
+                    ContractDeployment::new(chain_id, "maker", "dai", contracts.dai, ProtocolInterface::Erc20),
+                ];
+                let joins = contracts.collateral_types.iter().map(move |collateral| ContractDeployment::new(
+                    chain_id,
+                    "maker",
+                    &format!("{}_join", collateral.ilk.to_lowercase().replace('-', "_")),
+                    collateral.join,
+                    ProtocolInterface::MakerGemJoin,
+                ));
+                core.into_iter().chain(joins)
+            })
+            .collect()
+    }
+
+    /// DAI on chains Maker is deployed on
+    pub fn dai(&self, chain_id: u64) -> Option<Address> {
+        self.contracts.get(&chain_id).map(|contracts| contracts.dai)
+    }
+
+    /// First collateral type locking `token`, the one new vaults of it are opened as
+    pub fn collateral_type(&self, chain_id: u64, token: Address) -> Option<&CollateralType> {
+        self.contracts.get(&chain_id)?.collateral_types.iter().find(|collateral| collateral.token == token)
+    }
+
+    /// Parameters of every collateral type with a configured adapter
+    pub async fn ilks(&self, chain_id: u64) -> Result<Vec<IlkInfo>> {
+        let contracts = self.contracts(chain_id)?;
+        let mut ilks = Vec::with_capacity(contracts.collateral_types.len());
+        for collateral in &contracts.collateral_types {
+            ilks.push(self.ilk(chain_id, &collateral.ilk).await?);
+        }
+        Ok(ilks)
+    }
+
+    pub async fn ilk(&self, chain_id: u64, ilk: &str) -> Result<IlkInfo> {
+        let contracts = self.contracts(chain_id)?;
+        let collateral = contracts.collateral_types.iter()
+            .find(|collateral| collateral.ilk.eq_ignore_ascii_case(ilk))
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown Maker collateral type {} on chain {}", ilk, chain_id))?;
+        let name = format_bytes32_string(&collateral.ilk)?;
+
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let vat = Contract::new(contracts.vat, Self::get_vat_abi()?, client.clone());
+        let spotter = Contract::new(contracts.spotter, Self::get_spotter_abi()?, client.clone());
+        let jug = Contract::new(contracts.jug, Self::get_jug_abi()?, client);
+        let vat_call = vat.method::<_, (U256, U256, U256, U256, U256)>("ilks", name)?;
+        let spotter_call = spotter.method::<_, (Address, U256)>("ilks", name)?;
+        let jug_call = jug.method::<_, (U256, U256)>("ilks", name)?;
+        let base_call = jug.method::<_, U256>("base", ())?;
+        let mut batch = RpcBatch::new();
+        let vat_ilk = batch.call(&vat_call)?;
+        let spotter_ilk = batch.call(&spotter_call)?;
+        let jug_ilk = batch.call(&jug_call)?;
+        let base = batch.call(&base_call)?;
+        let results = provider.batch(&batch).await?;
+
+        Ok(IlkInfo::new(
+            collateral,
+            results.decode(vat_ilk, &vat_call)?,
+            results.decode(spotter_ilk, &spotter_call)?.1,
+            results.decode(jug_ilk, &jug_call)?.0,
+            results.decode(base, &base_call)?,
+        ))
+    }
+
+    /// Vaults the CDP manager holds for `owner`, empty on chains without Maker. Vaults opened
+    /// through a DSProxy are listed under the proxy, and vaults of collateral types without a
+    /// configured adapter are left out
+    pub async fn vaults(&self, chain_id: u64, owner: Address) -> Result<Vec<MakerVault>> {
+        let Some(contracts) = self.contracts.get(&chain_id) else {
+            return Ok(Vec::new());
+        };
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let get_cdps = Contract::new(contracts.get_cdps, Self::get_get_cdps_abi()?, client.clone());
+        let (ids, urns, ilk_names): (Vec<U256>, Vec<Address>, Vec<H256>) = get_cdps
+            .method("getCdpsAsc", (contracts.cdp_manager, owner))?
+            .call()
+            .await?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let names: Vec<String> = ilk_names.iter()
+            .map(|name| parse_bytes32_string(&name.0).map(str::to_string))
+            .collect::<Result<_, _>>()?;
+        let mut ilks = HashMap::new();
+        for name in names.iter().collect::<HashSet<_>>() {
+            if contracts.collateral_types.iter().any(|collateral| &collateral.ilk == name) {
+                ilks.insert(name.clone(), self.ilk(chain_id, name).await?);
+            }
+        }
+
+        let vat = Contract::new(contracts.vat, Self::get_vat_abi()?, client);
+        let mut batch = RpcBatch::new();
+        let mut reads = Vec::new();
+        for ((id, urn), name) in ids.into_iter().zip(urns).zip(names) {
+            let Some(ilk) = ilks.get(&name) else {
+                debug!("Skipping Maker vault {} of unconfigured collateral type {}", id, name);
+                continue;
+            };
+            let call = vat.method::<_, (U256, U256)>("urns", (format_bytes32_string(&name)?, urn))?;
+            reads.push((id, urn, ilk, batch.call(&call)?, call));
+        }
+        let results = provider.batch(&batch).await?;
+
+        reads.into_iter()
+            .map(|(id, urn, ilk, index, call)| {
+                let (ink, art) = results.decode(index, &call)?;
+                Ok(MakerVault::new(id, urn, ilk, ink, art))
+            })
+            .collect()
+    }
+
+    /// Vault of `owner` a strategy step goes through: the first one locking `collateral` when
+    /// depositing, else the one with the most DAI left to draw
+    pub async fn strategy_vault(&self, chain_id: u64, owner: Address, collateral: Option<Address>) -> Result<MakerVault> {
+        let vaults = self.vaults(chain_id, owner).await?;
+        let vault = match collateral {
+            Some(token) => vaults.into_iter().find(|vault| vault.collateral_token == token),
+            None => vaults.into_iter().max_by_key(|vault| vault.available_dai),
+        };
+        vault.ok_or_else(|| match collateral {
+            Some(token) => anyhow!("{:?} has no Maker vault locking {:?} on chain {}, open one first", owner, token, chain_id),
+            None => anyhow!("{:?} has no Maker vault to draw DAI from on chain {}", owner, chain_id),
+        })
+    }
+
+    /// A vault with its collateral type, failing unless `owner` owns it through the CDP manager
+    pub async fn owned_vault(&self, chain_id: u64, owner: Address, id: U256) -> Result<(MakerVault, IlkInfo)> {
+        let contracts = self.contracts(chain_id)?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let cdp_manager = Contract::new(contracts.cdp_manager, Self::get_cdp_manager_abi()?, client.clone());
+        let owns_call = cdp_manager.method::<_, Address>("owns", id)?;
+        let urns_call = cdp_manager.method::<_, Address>("urns", id)?;
+        let ilks_call = cdp_manager.method::<_, H256>("ilks", id)?;
+        let mut batch = RpcBatch::new();
+        let owns = batch.call(&owns_call)?;
+        let urns = batch.call(&urns_call)?;
+        let ilks = batch.call(&ilks_call)?;
+        let results = provider.batch(&batch).await?;
+
+        let vault_owner = results.decode(owns, &owns_call)?;
+        if vault_owner.is_zero() {
+            return Err(anyhow!("Maker vault {} does not exist on chain {}", id, chain_id));
+        }
+        if vault_owner != owner {
+            return Err(anyhow!("Maker vault {} is owned by {:?}, not {:?}", id, vault_owner, owner));
+        }
+        let urn = results.decode(urns, &urns_call)?;
+        let name = results.decode(ilks, &ilks_call)?;
+        let ilk = self.ilk(chain_id, parse_bytes32_string(&name.0)?).await?;
+
+        let vat = Contract::new(contracts.vat, Self::get_vat_abi()?, client);
+        let (ink, art): (U256, U256) = vat.method("urns", (name, urn))?.call().await?;
+        Ok((MakerVault::new(id, urn, &ilk, ink, art), ilk))
+    }
+
+    /// Open an empty vault of collateral type `ilk` owned by `owner`
+    pub async fn open_vault(&self, chain_id: u64, ilk: &str, owner: Address) -> Result<TransactionRequest> {
+        let ilk = self.ilk(chain_id, ilk).await?;
+        let contracts = self.contracts(chain_id)?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let cdp_manager = Contract::new(contracts.cdp_manager, Self::get_cdp_manager_abi()?, Arc::new(provider.provider.clone()));
+        let tx = cdp_manager.method::<_, H256>("open", (format_bytes32_string(&ilk.collateral.ilk)?, owner))?.tx;
+        Ok(tx.into())
+    }
+
+    /// Join `amount` of collateral to the vault's urn and lock it in the vault
+    pub async fn deposit_collateral(&self, chain_id: u64, owner: Address, id: U256, amount: U256) -> Result<VaultPlan> {
+        if amount.is_zero() {
+            return Err(anyhow!("Collateral to deposit must be positive"));
+        }
+        let contracts = self.contracts(chain_id)?;
+        let (vault, ilk) = self.owned_vault(chain_id, owner, id).await?;
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let join = Contract::new(ilk.collateral.join, Self::get_join_abi()?, client.clone());
+        let cdp_manager = Contract::new(contracts.cdp_manager, Self::get_cdp_manager_abi()?, client);
+        let dink = ilk.to_wad(amount);
+
+        let join_tx = join.method::<_, H256>("join", (vault.urn, amount))?.tx;
+        let frob_tx = cdp_manager.method::<_, H256>("frob", (id, I256::from_raw(dink), I256::zero()))?.tx;
+        Ok(VaultPlan {
+            transactions: vec![join_tx.into(), frob_tx.into()],
+            spend: Some(TokenSpend { token: ilk.collateral.token, spender: ilk.collateral.join, amount }),
+            vault_after: MakerVault::new(id, vault.urn, &ilk, vault.ink(&ilk) + dink, vault.normalized_debt),
+        })
+    }
+
+    /// Draw `amount` DAI against the vault's collateral to `owner`. Fees are dripped first so the debt
+    /// is counted at the current rate, and the DAI adapter is allowed to move the vault's DAI the
+    /// first time
+    pub async fn draw_dai(&self, chain_id: u64, owner: Address, id: U256, amount: U256) -> Result<VaultPlan> {
+        if amount.is_zero() {
+            return Err(anyhow!("DAI to draw must be positive"));
+        }
+        let contracts = self.contracts(chain_id)?;
+        let (vault, ilk) = self.owned_vault(chain_id, owner, id).await?;
+        if amount > ilk.available_debt {
+            return Err(anyhow!("{} has {} DAI left under its debt ceiling", ilk.collateral.ilk, ilk.available_debt));
+        }
+        // Rounded up so the vault is credited at least `amount`
+        let dart = (amount * ray() + ilk.rate - U256::one()) / ilk.rate;
+        let vault_after = MakerVault::new(id, vault.urn, &ilk, vault.ink(&ilk), vault.normalized_debt + dart);
+        if amount > vault.available_dai {
+            return Err(anyhow!(
+                "Drawing {} DAI would take vault {} below its liquidation ratio of {:.0}%, {} DAI can be drawn",
+                amount, id, ilk.liquidation_ratio * 100.0, vault.available_dai,
+            ));
+        }
+        if vault_after.debt < ilk.dust {
+            return Err(anyhow!("Vault {} must owe at least {} DAI", id, ilk.dust));
+        }
+
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let vat = Contract::new(contracts.vat, Self::get_vat_abi()?, client.clone());
+        let jug = Contract::new(contracts.jug, Self::get_jug_abi()?, client.clone());
+        let cdp_manager = Contract::new(contracts.cdp_manager, Self::get_cdp_manager_abi()?, client.clone());
+        let dai_join = Contract::new(contracts.dai_join, Self::get_join_abi()?, client);
+        let name = format_bytes32_string(&ilk.collateral.ilk)?;
+
+        let mut transactions: Vec<TransactionRequest> = vec![
+            jug.method::<_, H256>("drip", name)?.tx.into(),
+            cdp_manager.method::<_, H256>("frob", (id, I256::zero(), I256::from_raw(dart)))?.tx.into(),
+            // The Vat counts DAI in rad
+            cdp_manager.method::<_, H256>("move", (id, owner, amount * ray()))?.tx.into(),
+        ];
+        let allowed: U256 = vat.method("can", (owner, contracts.dai_join))?.call().await?;
+        if allowed.is_zero() {
+            transactions.push(vat.method::<_, H256>("hope", contracts.dai_join)?.tx.into());
+        }
+        transactions.push(dai_join.method::<_, H256>("exit", (owner, amount))?.tx.into());
+
+        Ok(VaultPlan { transactions, spend: None, vault_after })
+    }
+
+    /// Repay `amount` DAI of the vault's debt; at least the whole debt wipes it, the excess staying
+    /// in the vault's DAI balance, so a full repayment can leave room for fees accruing until mined
+    pub async fn repay_dai(&self, chain_id: u64, owner: Address, id: U256, amount: U256) -> Result<VaultPlan> {
+        if amount.is_zero() {
+            return Err(anyhow!("DAI to repay must be positive"));
+        }
+        let contracts = self.contracts(chain_id)?;
+        let (vault, ilk) = self.owned_vault(chain_id, owner, id).await?;
+        if vault.normalized_debt.is_zero() {
+            return Err(anyhow!("Vault {} has no debt to repay", id));
+        }
+        let dart = (amount * ray() / ilk.rate).min(vault.normalized_debt);
+        let vault_after = MakerVault::new(id, vault.urn, &ilk, vault.ink(&ilk), vault.normalized_debt - dart);
+        if !vault_after.debt.is_zero() && vault_after.debt < ilk.dust {
+            return Err(anyhow!(
+                "Repaying {} DAI would leave vault {} owing less than the {} DAI minimum, repay it all instead",
+                amount, id, ilk.dust,
+            ));
+        }
+
+        let provider = self.chain_manager.get_provider(chain_id).await?;
+        let client = Arc::new(provider.provider.clone());
+        let cdp_manager = Contract::new(contracts.cdp_manager, Self::get_cdp_manager_abi()?, client.clone());
+        let dai_join = Contract::new(contracts.dai_join, Self::get_join_abi()?, client);
+        let join_tx = dai_join.method::<_, H256>("join", (vault.urn, amount))?.tx;
+        let frob_tx = cdp_manager.method::<_, H256>("frob", (id, I256::zero(), -I256::from_raw(dart)))?.tx;
+
+        Ok(VaultPlan {
+            transactions: vec![join_tx.into(), frob_tx.into()],
+            spend: Some(TokenSpend { token: contracts.dai, spender: contracts.dai_join, amount }),
+            vault_after,
+        })
+    }
+
+    /// Approval the plan's spend needs first, if any
+    pub async fn with_approval(&self, chain_id: u64, owner: Address, plan: &VaultPlan) -> Result<Vec<TransactionRequest>> {
+        let mut transactions = Vec::with_capacity(plan.transactions.len() + 1);
+        if let Some(spend) = plan.spend {
+            if let Some(approval) = self.dex_manager.approvals().required_approval(chain_id, owner, spend, None).await? {
+                transactions.push(approval.transaction);
+            }
+        }
+        transactions.extend(plan.transactions.iter().cloned());
+        Ok(transactions)
+    }
+
+    fn contracts(&self, chain_id: u64) -> Result<&MakerContracts> {
+        self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Maker is not deployed on chain {}", chain_id))
+    }
+
+    fn get_vat_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "bytes32", "name": "", "type": "bytes32"}],
+                "name": "ilks",
+                "outputs": [
+                    {"internalType": "uint256", "name": "Art", "type": "uint256"},
+                    {"internalType": "uint256", "name": "rate", "type": "uint256"},
+                    {"internalType": "uint256", "name": "spot", "type": "uint256"},
+                    {"internalType": "uint256", "name": "line", "type": "uint256"},
+                    {"internalType": "uint256", "name": "dust", "type": "uint256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "bytes32", "name": "", "type": "bytes32"},
+                    {"internalType": "address", "name": "", "type": "address"}
+                ],
+                "name": "urns",
+                "outputs": [
+                    {"internalType": "uint256", "name": "ink", "type": "uint256"},
+                    {"internalType": "uint256", "name": "art", "type": "uint256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "", "type": "address"},
+                    {"internalType": "address", "name": "", "type": "address"}
+                ],
+                "name": "can",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "address", "name": "usr", "type": "address"}],
+                "name": "hope",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_jug_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "bytes32", "name": "", "type": "bytes32"}],
+                "name": "ilks",
+                "outputs": [
+                    {"internalType": "uint256", "name": "duty", "type": "uint256"},
+                    {"internalType": "uint256", "name": "rho", "type": "uint256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "base",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "bytes32", "name": "ilk", "type": "bytes32"}],
+                "name": "drip",
+                "outputs": [{"internalType": "uint256", "name": "rate", "type": "uint256"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_spotter_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [{"internalType": "bytes32", "name": "", "type": "bytes32"}],
+                "name": "ilks",
+                "outputs": [
+                    {"internalType": "contract PipLike", "name": "pip", "type": "address"},
+                    {"internalType": "uint256", "name": "mat", "type": "uint256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_cdp_manager_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "bytes32", "name": "ilk", "type": "bytes32"},
+                    {"internalType": "address", "name": "usr", "type": "address"}
+                ],
+                "name": "open",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "name": "owns",
+                "outputs": [{"internalType": "address", "name": "", "type": "address"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "name": "urns",
+                "outputs": [{"internalType": "address", "name": "", "type": "address"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "name": "ilks",
+                "outputs": [{"internalType": "bytes32", "name": "", "type": "bytes32"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "cdp", "type": "uint256"},
+                    {"internalType": "int256", "name": "dink", "type": "int256"},
+                    {"internalType": "int256", "name": "dart", "type": "int256"}
+                ],
+                "name": "frob",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "cdp", "type": "uint256"},
+                    {"internalType": "address", "name": "dst", "type": "address"},
+                    {"internalType": "uint256", "name": "rad", "type": "uint256"}
+                ],
+                "name": "move",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_get_cdps_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "manager", "type": "address"},
+                    {"internalType": "address", "name": "guy", "type": "address"}
+                ],
+                "name": "getCdpsAsc",
+                "outputs": [
+                    {"internalType": "uint256[]", "name": "ids", "type": "uint256[]"},
+                    {"internalType": "address[]", "name": "urns", "type": "address[]"},
+                    {"internalType": "bytes32[]", "name": "ilks", "type": "bytes32[]"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    /// Shared by the collateral adapters and the DAI adapter
+    fn get_join_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "usr", "type": "address"},
+                    {"internalType": "uint256", "name": "wad", "type": "uint256"}
+                ],
+                "name": "join",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "usr", "type": "address"},
+                    {"internalType": "uint256", "name": "wad", "type": "uint256"}
+                ],
+                "name": "exit",
+                "outputs": [],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        let abi: Abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+}
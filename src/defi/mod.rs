@@ -1,34 +1,217 @@
 use std::sync::Arc;
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService, PriceSource};
+use crate::api::models::{ArchiveFilter, TokenAmount};
+use crate::cache::CacheManager;
 use crate::chains::ChainManager;
+use crate::chains::assets::AssetEquivalent;
+use crate::contracts::approvals::{transaction_target, TokenSpend, APPROVE_GAS};
+use crate::contracts::probes::ContractDeployment;
+use crate::contracts::erc20::ERC20Contract;
+use crate::dex::aggregator::{swap_deadline, SlippageSettings, DEFAULT_DEADLINE_MINUTES};
 use crate::dex::DexManager;
+use crate::transactions::{settlement::SwapQuote, TransactionStatus, TransactionTracker};
 use anyhow::Result;
-use ethers::types::{Address, U256, TransactionRequest};
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+use ethers::types::{Address, Bytes, U256, TransactionRequest};
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 use chrono::{DateTime, Utc};
-use tracing::info;
+use tracing::{info, instrument, warn};
 
 pub mod aave;
+pub mod arbitrage;
+pub mod bridge;
+pub mod closeout;
+pub mod collateral_optimizer;
 pub mod compound;
+pub mod compound_borrowers;
+pub mod convex;
 pub mod flash_loans;
+pub mod leverage_projection;
+pub mod maker;
+pub mod protection;
+pub mod rate_history;
+pub mod reward_compounder;
+pub mod strategy_bundle;
+pub mod strategy_executor;
+pub mod strategy_gas;
+pub mod strategy_registry;
+pub mod strategy_templates;
+pub mod yield_risk;
 
-use aave::{AaveManager, LendingPosition as AaveLendingPosition, YieldStrategy as AaveYieldStrategy};
+use bridge::{BridgeManager, BridgeQuoteRequest};
+use aave::{AaveManager, AccruedRewards, FlashLoanParams, LendingPosition as AaveLendingPosition};
+use closeout::{
+    collateral_to_sell, flash_loan_premium, with_slippage, CloseoutAction, CloseoutCosts, CloseoutDraft, CloseoutPlan,
+    CloseoutRequest, CloseoutStep, FlashLoanLeg, LendingExit, LiquidityHolding, FLASH_LOAN_GAS, FLASH_SWAP_GAS,
+    REMOVE_LIQUIDITY_GAS, REPAY_GAS, UNSTAKE_GAS, WITHDRAW_GAS,
+};
+use collateral_optimizer::{
+    CollateralMove, CollateralOptimizationRequest, CollateralOptimizer, CollateralPlan, LendingMarket, MarketParams,
+    PricedCollateral,
+};
 use compound::{CompoundManager, UserCompoundData, CompoundYieldStrategy, LiquidationOpportunity, CompArbitrageOpportunity};
-use flash_loans::{FlashLoanManager, FlashLoanStrategy, ArbitrageStrategy};
+use protection::{MarketHealth, ProtectionPlan};
+use rate_history::{aave_apy, compound_apy, RateHistory, RateSample, RateTrend, COMPOUND_BLOCKS_PER_YEAR};
+use convex::{ConvexAction, ConvexManager, ConvexPoolYield, ConvexTransactions};
+use maker::{MakerManager, MakerVault, VaultAction, VaultTransactions, MAKER_TARGET_HEALTH};
+use leverage_projection::{reward_apy, LendingMarketRates, LeverageInputs, LeverageLegs, LeverageProjection, SECONDS_PER_YEAR};
+use flash_loans::{FlashLoanManager, FlashLoanOperation, FlashLoanStrategy, FlashLiquidation};
+use strategy_bundle::{BundleDraft, BundledCall, BundledSwap, StrategyBundle};
+use strategy_gas::StrategyGasReport;
+use strategy_registry::StrategyRegistry;
+use strategy_templates::StrategyTemplateLibrary;
+use yield_risk::{MarketDepth, ProtocolRegistry, RiskScoreFactor};
+
+/// Supply rate of one representation of an asset on one lending market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossChainYield {
+    #[serde(flatten)]
+    pub token: AssetEquivalent,
+    pub protocol: String,
+    /// Spot rate
+    #[serde(with = "crate::api::models::ratio")]
+    pub supply_apy: f64,
+    /// Average of the rates sampled over the last 7 days, `None` before the market was sampled in them
+    #[serde(default, with = "crate::api::models::option_ratio")]
+    pub supply_apy_7d: Option<f64>,
+    #[serde(default, with = "crate::api::models::option_ratio")]
+    pub supply_apy_30d: Option<f64>,
+    /// Cost of bridging the compared amount there, `None` on the caller's chain or without an amount
+    pub bridge_cost: Option<BridgeCost>,
+    /// Trailing supply APY, or the spot one before the market was sampled, less the bridge fee
+    /// spread over the holding horizon
+    #[serde(with = "crate::api::models::ratio")]
+    pub net_apy: f64,
+}
+
+/// Across fee of moving the compared amount to another chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeCost {
+    #[serde(with = "crate::api::models::ratio")]
+    pub fee_percentage: f64,
+    /// Fee as APY over `horizon_days`, in percentage points
+    #[serde(with = "crate::api::models::ratio")]
+    pub amortized_apy: f64,
+    pub horizon_days: u64,
+    pub expected_fill_time_secs: u64,
+}
+
+/// Supply rates of every representation of an asset across chains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossChainYieldComparison {
+    pub asset_id: String,
+    /// Highest net APY first
+    pub yields: Vec<CrossChainYield>,
+    /// Markets whose rate could not be read and routes the bridge could not quote
+    pub unavailable: Vec<String>,
+    /// Yield on another chain beating the best one on the caller's chain after the bridge fee
+    pub recommendation: Option<String>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DefiPortfolio {
     pub user: Address,
+    #[serde(with = "crate::api::models::usd")]
     pub total_supplied_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
     pub total_borrowed_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
     pub net_worth_usd: f64,
+    /// Lowest health factor across protocols; each protocol is liquidated independently
     pub overall_health_factor: f64,
+    pub risk: PortfolioRisk,
     pub aave_positions: Vec<AaveLendingPosition>,
     pub compound_positions: Vec<compound::UserCTokenPosition>,
+    /// Counted in the totals and, one per vault, in the risk, as each vault is liquidated on its own
+    #[serde(default)]
+    pub maker_vaults: Vec<MakerVault>,
+    pub positions_usd: Vec<PositionValuation>,
+    /// Assets no price source could value or whose decimals could not be read; they are excluded
+    /// from the USD totals
+    pub unpriced_assets: Vec<Address>,
     pub active_strategies: Vec<ActiveStrategy>,
     pub yield_earned_24h: f64,
     pub last_updated: DateTime<Utc>,
 }
 
+/// USD valuation of a single lending position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionValuation {
+    pub protocol: String,
+    pub asset: Address,
+    /// `None` when they could not be read, which leaves the position unpriced
+    pub decimals: Option<u8>,
+    /// Raw amounts in the underlying asset's smallest unit
+    pub supplied_amount: U256,
+    pub borrowed_amount: U256,
+    /// Amounts in whole token units, `None` without decimals
+    pub supplied: Option<f64>,
+    pub borrowed: Option<f64>,
+    pub supply_apy: f64,
+    pub borrow_apy: f64,
+    /// Incentive token APYs paid on top of interest, in percent
+    pub supply_reward_apy: f64,
+    pub borrow_reward_apy: f64,
+    pub price_usd: Option<f64>,
+    pub price_source: Option<PriceSource>,
+    #[serde(with = "crate::api::models::usd")]
+    pub supplied_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub borrowed_usd: f64,
+    /// Share of the supplied value counted towards the health factor, 0 when not collateral
+    pub liquidation_threshold: f64,
+}
+
+/// Health of the positions held in one lending protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolHealth {
+    pub protocol: String,
+    /// `None` when the protocol holds no debt and cannot be liquidated
+    pub health_factor: Option<f64>,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub weighted_collateral_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub debt_usd: f64,
+    /// True when unpriced assets forced a fallback to the protocol-reported health factor
+    pub reported_by_protocol: bool,
+}
+
+/// How far a single collateral asset's price can fall before its protocol is liquidatable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralRisk {
+    pub protocol: String,
+    pub asset: Address,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_usd: f64,
+    pub price_usd: Option<f64>,
+    /// Price at which the protocol's health factor reaches 1, other prices unchanged
+    pub liquidation_price_usd: Option<f64>,
+    /// Price drop in percent that triggers liquidation, `None` if this asset alone cannot
+    pub liquidation_distance_pct: Option<f64>,
+}
+
+/// Cross-protocol liquidation risk of a portfolio
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioRisk {
+    pub protocols: Vec<ProtocolHealth>,
+    pub min_health_factor: Option<f64>,
+    pub weakest_protocol: Option<String>,
+    pub collateral: Vec<CollateralRisk>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrategyStatus {
+    #[default]
+    Active,
+    /// Exited by the user, kept for history
+    Closed,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActiveStrategy {
     pub strategy_id: String,
@@ -40,6 +223,21 @@ pub struct ActiveStrategy {
     pub risk_level: String,
     pub start_date: DateTime<Utc>,
     pub profit_loss: f64,
+    /// Template the strategy was instantiated from
+    #[serde(default)]
+    pub template_id: Option<String>,
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Template parameters in effect, defaults merged with overrides
+    #[serde(default, with = "crate::api::models::sorted_map")]
+    pub parameters: HashMap<String, f64>,
+    #[serde(default)]
+    pub status: StrategyStatus,
+    #[serde(default)]
+    pub closed_at: Option<DateTime<Utc>>,
+    /// Tracked transactions executing the strategy, whose gas it is charged
+    #[serde(default)]
+    pub transaction_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,20 +248,98 @@ pub struct OptimalYieldOpportunity {
     pub risk_level: String,
     pub min_deposit: U256,
     pub max_deposit: U256,
+    /// 0 to 1, from the depth of the market deposited into
     pub liquidity_risk: f64,
     pub impermanent_loss_risk: f64,
+    /// 0 to 1, the chance any protocol involved fails given its audits, age and exploits
     pub smart_contract_risk: f64,
+    /// Inputs of both risk scores with their weights
+    #[serde(default)]
+    pub risk_factors: Vec<RiskScoreFactor>,
+    /// Interest, rewards, break-even and liquidation price path of Aave and Compound strategies that
+    /// borrow from the protocol they supply to
+    #[serde(default)]
+    pub projection: Option<LeverageProjection>,
     pub description: String,
     pub steps: Vec<YieldOpportunityStep>,
 }
 
+impl OptimalYieldOpportunity {
+    /// Protocols and venues the opportunity goes through, in order of first use
+    fn protocols(&self) -> Vec<String> {
+        let mut protocols: Vec<String> = Vec::new();
+        let named = self.protocol.split('+').map(str::trim);
+        let stepped = self.steps.iter().map(|step| match step {
+            YieldOpportunityStep::Supply { protocol, .. }
+            | YieldOpportunityStep::Borrow { protocol, .. }
+            | YieldOpportunityStep::Farm { protocol, .. }
+            | YieldOpportunityStep::Stake { protocol, .. }
+            | YieldOpportunityStep::Claim { protocol, .. } => protocol.as_str(),
+            YieldOpportunityStep::Swap { dex, .. } => dex.as_str(),
+        });
+        for protocol in named.chain(stepped) {
+            if !protocol.is_empty() && !protocols.iter().any(|known| known.eq_ignore_ascii_case(protocol)) {
+                protocols.push(protocol.to_string());
+            }
+        }
+        protocols
+    }
+
+    /// Protocol of the first deposit, the named protocol without steps
+    fn deposit_protocol(&self) -> &str {
+        self.steps.iter()
+            .find_map(|step| match step {
+                YieldOpportunityStep::Supply { protocol, .. }
+                | YieldOpportunityStep::Farm { protocol, .. }
+                | YieldOpportunityStep::Stake { protocol, .. } => Some(protocol.as_str()),
+                _ => None,
+            })
+            .unwrap_or_else(|| self.protocol.split('+').next().unwrap_or_default().trim())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum YieldOpportunityStep {
     Supply { protocol: String, asset: Address, amount: U256 },
     Borrow { protocol: String, asset: Address, amount: U256 },
     Swap { dex: String, token_in: Address, token_out: Address, amount: U256 },
-    Farm { protocol: String, pool: Address, amount: U256 },
+    /// Deposit `amount` into `pool`: of `token` when adding it to a Curve pool, of the pool's LP
+    /// token without one
+    Farm {
+        protocol: String,
+        pool: Address,
+        #[serde(default)]
+        token: Option<Address>,
+        amount: U256,
+    },
     Stake { protocol: String, token: Address, amount: U256 },
+    /// Claim the lending rewards accrued to the owner, at least `amount` of `token`
+    Claim { protocol: String, token: Address, amount: U256 },
+}
+
+/// Lending rewards a user can claim from one protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewardBalance {
+    /// `aave` or `compound`
+    pub protocol: String,
+    /// Token a claim pays out
+    pub token: Address,
+    pub claimable: TokenAmount,
+    /// Valued as the token the rewards are priced as, `None` when unpriced
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub value_usd: Option<f64>,
+    /// Whether the claimed token can be swapped right away; stkAAVE must cool down before it
+    /// redeems for AAVE
+    pub compoundable: bool,
+}
+
+/// Transaction claiming a user's rewards from one protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewardClaim {
+    pub rewards: RewardBalance,
+    pub transaction: TransactionRequest,
+    /// Tracked transaction id, to link the hash once broadcast
+    pub transaction_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,55 +378,115 @@ pub struct ProtocolStats {
 pub struct DefiManager {
     chain_manager: Arc<ChainManager>,
     dex_manager: Arc<DexManager>,
+    price_feeds: Arc<PriceFeedService>,
     aave: aave::AaveManager,
     compound: compound::CompoundManager,
+    maker: maker::MakerManager,
+    convex: convex::ConvexManager,
     flash_loans: flash_loans::FlashLoanManager,
+    strategies: Arc<StrategyRegistry>,
+    templates: Arc<StrategyTemplateLibrary>,
+    /// Track records yield opportunities are risk-scored from
+    protocols: Arc<ProtocolRegistry>,
+    transactions: Arc<TransactionTracker>,
+    /// Prices moving funds to yields on other chains, `None` compares rates alone
+    bridge: Option<Arc<BridgeManager>>,
+    /// Sampled lending rates yields are ranked by, `None` ranks by spot rates
+    rate_history: Option<Arc<RateHistory>>,
 }
 
 impl DefiManager {
-    pub async fn new(chain_manager: Arc<ChainManager>, dex_manager: Arc<DexManager>) -> Result<Self> {
-        let aave = AaveManager::new(chain_manager.clone(), dex_manager.clone()).await?;
-        let compound = CompoundManager::new(chain_manager.clone(), dex_manager.clone()).await?;
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        dex_manager: Arc<DexManager>,
+        price_feeds: Arc<PriceFeedService>,
+        transactions: Arc<TransactionTracker>,
+        caches: &CacheManager,
+    ) -> Result<Self> {
+        let aave = AaveManager::new(chain_manager.clone(), dex_manager.clone(), caches).await?;
+        let compound = CompoundManager::new(chain_manager.clone(), dex_manager.clone(), caches).await?;
+        let maker = MakerManager::new(chain_manager.clone(), dex_manager.clone()).await?;
+        let convex = ConvexManager::new(chain_manager.clone(), dex_manager.clone()).await?;
         let flash_loans = FlashLoanManager::new(chain_manager.clone(), dex_manager.clone()).await?;
+        let strategies = Arc::new(StrategyRegistry::new().await?);
+        let templates = Arc::new(StrategyTemplateLibrary::builtin()?);
+        let protocols = Arc::new(ProtocolRegistry::builtin()?);
 
         Ok(Self {
             chain_manager,
             dex_manager,
+            price_feeds,
             aave,
             compound,
+            maker,
+            convex,
             flash_loans,
+            strategies,
+            templates,
+            protocols,
+            transactions,
+            bridge: None,
+            rate_history: None,
         })
     }
 
-    pub async fn new_demo() -> Result<Self> {
+    pub async fn new_demo(price_feeds: Arc<PriceFeedService>, transactions: Arc<TransactionTracker>) -> Result<Self> {
         info!("Creating DefiManager in demo mode");
         
         // Use empty chain manager for demo
         let chain_manager = Arc::new(ChainManager::new_demo().await?);
-        let dex_manager = Arc::new(DexManager::new_demo().await?);
+        let dex_manager = Arc::new(DexManager::new_demo(transactions.clone()).await?);
         
         // For now, try to create the managers normally but catch errors
         // In a production demo mode, we'd have proper mock implementations
-        match Self::new(chain_manager.clone(), dex_manager.clone()).await {
+        let caches = CacheManager::memory();
+        match Self::new(chain_manager.clone(), dex_manager.clone(), price_feeds.clone(), transactions.clone(), &caches).await {
             Ok(manager) => Ok(manager),
             Err(_) => {
                 // Fallback: create with empty managers for demo
-                let aave = AaveManager::new(chain_manager.clone(), dex_manager.clone()).await?;
-                let compound = CompoundManager::new(chain_manager.clone(), dex_manager.clone()).await?;
+                let aave = AaveManager::new(chain_manager.clone(), dex_manager.clone(), &caches).await?;
+                let compound = CompoundManager::new(chain_manager.clone(), dex_manager.clone(), &caches).await?;
+                let maker = MakerManager::new(chain_manager.clone(), dex_manager.clone()).await?;
+                let convex = ConvexManager::new(chain_manager.clone(), dex_manager.clone()).await?;
                 let flash_loans = FlashLoanManager::new(chain_manager.clone(), dex_manager.clone()).await?;
+                let strategies = Arc::new(StrategyRegistry::new().await?);
+                let templates = Arc::new(StrategyTemplateLibrary::builtin()?);
+                let protocols = Arc::new(ProtocolRegistry::builtin()?);
                 
                 Ok(Self {
                     chain_manager,
                     dex_manager,
+                    price_feeds,
                     aave,
                     compound,
+                    maker,
+                    convex,
                     flash_loans,
+                    strategies,
+                    templates,
+                    protocols,
+                    transactions,
+                    bridge: None,
+                    rate_history: None,
                 })
             }
         }
     }
 
+    /// Weigh yields on other chains against the cost of bridging there
+    pub fn with_bridge(mut self, bridge: Arc<BridgeManager>) -> Self {
+        self.bridge = Some(bridge);
+        self
+    }
+
+    /// Rank yields by the trailing averages of sampled rates rather than the spot rate
+    pub fn with_rate_history(mut self, rate_history: Arc<RateHistory>) -> Self {
+        self.rate_history = Some(rate_history);
+        self
+    }
+
     /// Get comprehensive DeFi portfolio overview for a user
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?user))]
     pub async fn get_portfolio_overview(&self, chain_id: u64, user: Address) -> Result<DefiPortfolio> {
         // Get Aave positions
         let aave_positions = self.aave.get_lending_position(chain_id, user).await?;
@@ -158,29 +494,41 @@ impl DefiManager {
         // Get Compound positions
         let compound_data = self.compound.get_user_compound_data(chain_id, user).await?;
         
-        // Calculate totals
-        let mut total_supplied_usd = 0.0;
-        let mut total_borrowed_usd = 0.0;
-        
-        for position in &aave_positions {
-            total_supplied_usd += (position.supplied_amount.as_u128() as f64) / 1e18;
-            total_borrowed_usd += (position.borrowed_amount_variable.as_u128() as f64) / 1e18;
-        }
-        
-        for position in &compound_data.positions {
-            total_supplied_usd += (position.supply_balance.as_u128() as f64) / 1e18;
-            total_borrowed_usd += (position.borrow_balance.as_u128() as f64) / 1e18;
-        }
+        // Vaults are valued at Maker's own oracle price; one unreadable does not hide the other positions
+        let maker_vaults = match self.maker.vaults(chain_id, user).await {
+            Ok(vaults) => vaults,
+            Err(e) => {
+                warn!("Failed to read Maker vaults of {:?} on chain {}: {}", user, chain_id, e);
+                Vec::new()
+            }
+        };
+
+        // Value every position in USD using token decimals and the price feeds
+        let positions_usd = self.value_positions(chain_id, &aave_positions, &compound_data.positions).await?;
 
+        let total_supplied_usd: f64 = positions_usd.iter().map(|p| p.supplied_usd).sum::<f64>()
+            + maker_vaults.iter().map(|vault| vault.collateral_usd).sum::<f64>();
+        let total_borrowed_usd: f64 = positions_usd.iter().map(|p| p.borrowed_usd).sum::<f64>()
+            + maker_vaults.iter().map(|vault| vault.debt_usd).sum::<f64>();
         let net_worth_usd = total_supplied_usd - total_borrowed_usd;
+
+        let mut unpriced_assets: Vec<Address> = positions_usd.iter()
+            .filter(|p| p.price_usd.is_none())
+            .map(|p| p.asset)
+            .collect();
+        unpriced_assets.sort();
+        unpriced_assets.dedup();
         
-        // Calculate overall health factor (weighted average)
-        let aave_health = if !aave_positions.is_empty() {
-            aave_positions[0].health_factor.as_u128() as f64 / 1e18
-        } else { f64::INFINITY };
-        
-        let compound_health = compound_data.health_factor;
-        let overall_health_factor = (aave_health + compound_health) / 2.0;
+        // Protocols are liquidated independently, so risk is the weakest one, never an average
+        // Aave reports uint256::MAX when the account has no debt
+        let aave_reported = aave_positions.first()
+            .filter(|position| position.health_factor != U256::MAX)
+            .map(|position| Self::to_token_units(position.health_factor, 18));
+        let risk = Self::assess_portfolio_risk(&positions_usd, &[
+            ("Aave", aave_reported),
+            ("Compound", Some(compound_data.health_factor)),
+        ], &maker_vaults);
+        let overall_health_factor = risk.min_health_factor.unwrap_or(f64::INFINITY);
 
         Ok(DefiPortfolio {
             user,
@@ -188,9 +536,13 @@ impl DefiManager {
             total_borrowed_usd,
             net_worth_usd,
             overall_health_factor,
+            risk,
             aave_positions,
             compound_positions: compound_data.positions,
-            active_strategies: Vec::new(), // Would be populated from strategy tracking
+            maker_vaults,
+            positions_usd,
+            unpriced_assets,
+            active_strategies: self.strategies.list(user, ArchiveFilter::Active).await,
             yield_earned_24h: 150.75, // Mock value
             last_updated: chrono::Utc::now(),
         })
@@ -202,22 +554,30 @@ impl DefiManager {
 
         // Get Aave strategies
         let aave_strategies = self.aave.get_yield_strategies(chain_id, asset, amount).await?;
+        let aave_supply_apy = self.trailing_supply_apy(chain_id, "aave", asset).await;
         for strategy in aave_strategies {
+            // Plain supplies earn the market rate, averaged so a momentary spike does not rank them first
+            let estimated_apy = match aave_supply_apy {
+                Some(apy) if strategy.strategy_id == "aave_supply" => apy,
+                _ => strategy.estimated_apy,
+            };
+            let projection = match LeverageLegs::from_aave_steps(&strategy.steps) {
+                Some(legs) => self.project_leverage(chain_id, "aave", asset, amount, &legs).await,
+                None => None,
+            };
             opportunities.push(OptimalYieldOpportunity {
                 strategy_type: strategy.name.clone(),
                 protocol: "Aave".to_string(),
-                estimated_apy: strategy.estimated_apy,
+                estimated_apy,
                 risk_level: format!("{:?}", strategy.risk_level),
                 min_deposit: strategy.min_deposit,
                 max_deposit: amount * U256::from(10), // 10x leverage max
-                liquidity_risk: match strategy.risk_level {
-                    aave::RiskLevel::Low => 0.1,
-                    aave::RiskLevel::Medium => 0.3,
-                    aave::RiskLevel::High => 0.6,
-                    aave::RiskLevel::VeryHigh => 0.9,
-                },
+                // Risks are scored once every opportunity is listed
+                liquidity_risk: 0.0,
                 impermanent_loss_risk: 0.0, // No IL risk for lending
-                smart_contract_risk: 0.15, // Aave has good security record
+                smart_contract_risk: 0.0,
+                risk_factors: Vec::new(),
+                projection,
                 description: strategy.description,
                 steps: strategy.steps.into_iter().map(|step| match step {
                     aave::YieldStep::Supply { asset, .. } => YieldOpportunityStep::Supply { 
@@ -239,6 +599,7 @@ impl DefiManager {
                     aave::YieldStep::Farm { pool_address, .. } => YieldOpportunityStep::Farm { 
                         protocol: "SushiSwap".to_string(), 
                         pool: pool_address, 
+                        token: None,
                         amount 
                     },
                 }).collect(),
@@ -247,37 +608,375 @@ impl DefiManager {
 
         // Get Compound strategies
         let compound_strategies = self.compound.get_yield_strategies(chain_id, asset, amount).await?;
+        let compound_supply_apy = self.trailing_supply_apy(chain_id, "compound", asset).await;
         for strategy in compound_strategies {
+            let estimated_apy = match compound_supply_apy {
+                Some(apy) if strategy.strategy_id == "compound_supply" => apy,
+                _ => strategy.estimated_apy,
+            };
+            let projection = match LeverageLegs::from_compound_steps(&strategy.steps) {
+                Some(legs) => self.project_leverage(chain_id, "compound", asset, amount, &legs).await,
+                None => None,
+            };
             opportunities.push(OptimalYieldOpportunity {
                 strategy_type: strategy.name.clone(),
                 protocol: "Compound".to_string(),
-                estimated_apy: strategy.estimated_apy,
+                estimated_apy,
                 risk_level: format!("{:?}", strategy.risk_level),
                 min_deposit: strategy.min_deposit,
                 max_deposit: amount * U256::from(5), // 5x leverage max for Compound
-                liquidity_risk: match strategy.risk_level {
-                    compound::RiskLevel::Low => 0.05,
-                    compound::RiskLevel::Medium => 0.25,
-                    compound::RiskLevel::High => 0.55,
-                    compound::RiskLevel::VeryHigh => 0.85,
-                },
+                liquidity_risk: 0.0,
                 impermanent_loss_risk: 0.0,
-                smart_contract_risk: 0.1, // Compound also has good security
+                smart_contract_risk: 0.0,
+                risk_factors: Vec::new(),
+                projection,
                 description: strategy.description,
                 steps: Vec::new(), // Would convert from compound steps
             });
         }
 
+        // Lock the asset in a Maker vault and lend the DAI drawn against it, where that out-earns the fee
+        if let Some(opportunity) = self.maker_dai_strategy(chain_id, asset, amount).await {
+            opportunities.push(opportunity);
+        }
+
         // Add cross-protocol strategies
         opportunities.push(self.create_cross_protocol_strategy(chain_id, asset, amount).await?);
 
+        // Score the protocols' track records and the depth of the market each opportunity deposits into
+        let depths = self.lending_market_depths(chain_id, asset, amount).await;
+        for opportunity in &mut opportunities {
+            let depth = depths.iter()
+                .find(|(protocol, _)| protocol.eq_ignore_ascii_case(opportunity.deposit_protocol()))
+                .map(|(_, depth)| depth.clone())
+                .unwrap_or_default();
+            let score = self.protocols.score(&opportunity.protocols(), &depth);
+            opportunity.liquidity_risk = score.liquidity_risk;
+            opportunity.smart_contract_risk = score.smart_contract_risk;
+            opportunity.risk_factors = score.factors;
+        }
+
+        // Stablecoin LP farming is scored against the depth of its Curve pool rather than a lending market
+        for (mut opportunity, depth) in self.convex_farming_strategies(chain_id, asset, amount).await {
+            let score = self.protocols.score(&opportunity.protocols(), &depth);
+            opportunity.liquidity_risk = score.liquidity_risk;
+            opportunity.smart_contract_risk = score.smart_contract_risk;
+            opportunity.risk_factors = score.factors;
+            opportunities.push(opportunity);
+        }
+
         // Sort by estimated APY descending
         opportunities.sort_by(|a, b| b.estimated_apy.partial_cmp(&a.estimated_apy).unwrap());
 
         Ok(opportunities)
     }
 
+    /// Aave and Compound supply rates of every token standing for the same asset as `asset`,
+    /// on any chain, so native USDC is not mistaken for USDC.e or a bridged copy, ranked by their
+    /// trailing averages once sampled. With an `amount`, yields on other chains are net of the fee
+    /// of bridging it there.
+    pub async fn compare_yields_across_chains(
+        &self,
+        chain_id: u64,
+        asset: Address,
+        amount: Option<U256>,
+    ) -> Result<CrossChainYieldComparison> {
+        let equivalents = self.dex_manager.assets().equivalents(chain_id, asset)
+            .ok_or_else(|| anyhow::anyhow!("{:?} on chain {} is not in the asset registry", asset, chain_id))?;
+        let asset_id = equivalents.first().map(|equivalent| equivalent.asset_id.clone()).unwrap_or_default();
+        let compound_markets = self.compound.markets();
+
+        let mut yields = Vec::new();
+        let mut unavailable = Vec::new();
+        for equivalent in equivalents {
+            let representation = &equivalent.representation;
+            // Lending markets list the wrapped token rather than the gas token
+            if representation.address.is_zero() {
+                continue;
+            }
+
+            let bridge_cost = match (&self.bridge, amount) {
+                (Some(bridge), Some(amount)) if representation.chain_id != chain_id => {
+                    let request = BridgeQuoteRequest {
+                        origin_chain_id: chain_id,
+                        destination_chain_id: representation.chain_id,
+                        input_token: asset,
+                        output_token: Some(representation.address),
+                        amount,
+                    };
+                    match bridge.quote(&request).await {
+                        Ok(quote) => Some(BridgeCost {
+                            fee_percentage: quote.fee_percentage,
+                            amortized_apy: bridge.amortized_fee_apy(quote.fee_percentage),
+                            horizon_days: bridge.cost_horizon_days(),
+                            expected_fill_time_secs: quote.expected_fill_time_secs,
+                        }),
+                        Err(e) => {
+                            unavailable.push(format!("bridge to {} on chain {}: {}", representation.symbol, representation.chain_id, e));
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+            let market_yield = |protocol: &str, supply_apy: f64, trend: Option<RateTrend>| {
+                let supply_apy_7d = trend.as_ref().and_then(|trend| trend.supply_apy_7d);
+                let supply_apy_30d = trend.as_ref().and_then(|trend| trend.supply_apy_30d);
+                let ranked_apy = trend.as_ref().and_then(RateTrend::trailing_supply_apy).unwrap_or(supply_apy);
+                CrossChainYield {
+                    token: equivalent.clone(),
+                    protocol: protocol.to_string(),
+                    supply_apy,
+                    supply_apy_7d,
+                    supply_apy_30d,
+                    net_apy: ranked_apy - bridge_cost.as_ref().map_or(0.0, |cost| cost.amortized_apy),
+                    bridge_cost: bridge_cost.clone(),
+                }
+            };
+
+            match self.aave.get_reserve_data(representation.chain_id, representation.address).await {
+                Ok(reserve) => {
+                    let trend = self.rate_trend(representation.chain_id, "aave", representation.address).await;
+                    yields.push(market_yield("aave", aave_apy(reserve.liquidity_rate.as_u128()), trend));
+                }
+                Err(e) => unavailable.push(format!("aave {} on chain {}: {}", representation.symbol, representation.chain_id, e)),
+            }
+
+            for ctoken in compound_markets.get(&representation.chain_id).into_iter().flatten() {
+                match self.compound.get_ctoken_info(representation.chain_id, *ctoken).await {
+                    Ok(info) if info.underlying_address == representation.address => {
+                        let trend = self.rate_trend(representation.chain_id, "compound", representation.address).await;
+                        yields.push(market_yield("compound", compound_apy(info.supply_rate_per_block.as_u128()), trend));
+                    }
+                    Ok(_) => {}
+                    Err(e) => unavailable.push(format!("compound {:?} on chain {}: {}", ctoken, representation.chain_id, e)),
+                }
+            }
+        }
+
+        // Among equal rates, prefer what the caller holds over what needs a swap or bridge first
+        yields.sort_by(|a, b| {
+            b.net_apy.total_cmp(&a.net_apy)
+                .then_with(|| a.token.compatibility.cmp(&b.token.compatibility))
+        });
+
+        // Only yields reachable at a known cost are recommended
+        let best_local = yields.iter().find(|candidate| candidate.token.representation.chain_id == chain_id);
+        let best_bridged = yields.iter().find(|candidate| candidate.bridge_cost.is_some());
+        let recommendation = match (best_bridged, best_local) {
+            (Some(bridged), local) if local.is_none_or(|local| bridged.net_apy > local.net_apy) => {
+                let fee = bridged.bridge_cost.as_ref().map_or(0.0, |cost| cost.fee_percentage);
+                Some(match local {
+                    Some(local) => format!(
+                        "Supply {} on chain {} ({}) instead: {:.2}% higher APY after a {:.2}% bridge fee",
+                        bridged.token.representation.symbol,
+                        bridged.token.representation.chain_id,
+                        bridged.protocol,
+                        bridged.net_apy - local.net_apy,
+                        fee,
+                    ),
+                    None => format!(
+                        "Supply {} on chain {} ({}): {:.2}% APY after a {:.2}% bridge fee",
+                        bridged.token.representation.symbol,
+                        bridged.token.representation.chain_id,
+                        bridged.protocol,
+                        bridged.net_apy,
+                        fee,
+                    ),
+                })
+            }
+            _ => None,
+        };
+
+        Ok(CrossChainYieldComparison { asset_id, yields, unavailable, recommendation })
+    }
+
+    /// Current supply and borrow rates of every Aave reserve and Compound market; markets that
+    /// cannot be read are left out until the next round
+    pub async fn sample_lending_rates(&self) -> Vec<RateSample> {
+        let mut samples = Vec::new();
+
+        for chain_id in self.aave.chains() {
+            let reserves = match self.aave.reserves(chain_id).await {
+                Ok(reserves) => reserves,
+                Err(e) => {
+                    warn!("Failed to list Aave reserves on chain {}: {}", chain_id, e);
+                    continue;
+                }
+            };
+            for asset in reserves {
+                match self.aave.get_reserve_data(chain_id, asset).await {
+                    Ok(reserve) if reserve.is_active => samples.push(RateSample::aave(chain_id, &reserve)),
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to sample Aave reserve {:?} on chain {}: {}", asset, chain_id, e),
+                }
+            }
+        }
+
+        for (chain_id, ctokens) in self.compound.markets() {
+            for ctoken in ctokens {
+                match self.compound.get_ctoken_info(chain_id, ctoken).await {
+                    Ok(info) => samples.push(RateSample::compound(chain_id, &info)),
+                    Err(e) => warn!("Failed to sample Compound market {:?} on chain {}: {}", ctoken, chain_id, e),
+                }
+            }
+        }
+
+        samples
+    }
+
+    /// Rewards `user` can claim from the lending protocols deployed on a chain; protocols that
+    /// cannot be read are left out
+    pub async fn claimable_rewards(&self, chain_id: u64, user: Address) -> Vec<RewardBalance> {
+        let deployed = [
+            ("aave", self.aave.lending_pool(chain_id).is_some()),
+            ("compound", self.compound.comp_token(chain_id).is_some()),
+        ];
+        let mut rewards = Vec::new();
+        for (protocol, _) in deployed.into_iter().filter(|(_, deployed)| *deployed) {
+            match self.reward_balance(chain_id, protocol, user).await {
+                Ok(balance) => rewards.push(balance),
+                Err(e) => warn!("Failed to read {} rewards of {:?} on chain {}: {}", protocol, user, chain_id, e),
+            }
+        }
+        rewards
+    }
+
+    /// Claim transaction for everything `user` accrued from `protocol`, tracked as built
+    pub async fn build_reward_claim(&self, chain_id: u64, protocol: &str, user: Address) -> Result<RewardClaim> {
+        let rewards = self.reward_balance(chain_id, protocol, user).await?;
+        if rewards.claimable.raw.is_zero() {
+            return Err(anyhow::anyhow!("{:?} has no {} rewards to claim on chain {}", user, rewards.protocol, chain_id));
+        }
+        let transaction = self.reward_claim_transaction(chain_id, protocol, user).await?;
+        let record = self.transactions.record_built(chain_id, Some(user), "defi:reward_claim", &transaction).await;
+
+        Ok(RewardClaim { rewards, transaction, transaction_id: record.id })
+    }
+
+    /// Transaction claiming everything `user` accrued from `protocol`, to be sent by `user`
+    pub async fn reward_claim_transaction(&self, chain_id: u64, protocol: &str, user: Address) -> Result<TransactionRequest> {
+        match Self::reward_protocol(protocol)? {
+            "Aave" => self.aave.claim_rewards(chain_id, user).await,
+            _ => {
+                // Suppliers accrue COMP without entering a market, so every market is claimed
+                let markets = self.compound.markets().remove(&chain_id)
+                    .ok_or_else(|| anyhow::anyhow!("Compound is not deployed on chain {}", chain_id))?;
+                self.compound.claim_comp(chain_id, user, markets).await
+            }
+        }
+    }
+
+    /// Strategy claiming `owner`'s rewards from `protocol`, swapping them to `asset` and supplying it
+    /// to the same protocol, `None` while the rewards are worth less than `min_reward_usd`. The supply
+    /// is the swap quote less `max_slippage_percentage`, so a fill that slipped still covers it
+    pub async fn plan_reward_compound(
+        &self,
+        chain_id: u64,
+        protocol: &str,
+        owner: Address,
+        asset: Address,
+        min_reward_usd: f64,
+        max_slippage_percentage: f64,
+    ) -> Result<Option<OptimalYieldOpportunity>> {
+        let name = Self::reward_protocol(protocol)?;
+        let rewards = self.reward_balance(chain_id, protocol, owner).await?;
+        if !rewards.compoundable {
+            return Err(anyhow::anyhow!(
+                "{} rewards on chain {} are paid in {:?}, which cannot be swapped before its cooldown",
+                name, chain_id, rewards.token,
+            ));
+        }
+        if rewards.claimable.raw.is_zero() || rewards.value_usd.unwrap_or(0.0) < min_reward_usd {
+            return Ok(None);
+        }
+
+        let amount = rewards.claimable.raw;
+        let mut steps = vec![YieldOpportunityStep::Claim { protocol: name.to_string(), token: rewards.token, amount }];
+        let supply_amount = if rewards.token == asset {
+            amount
+        } else {
+            let route = self.dex_manager
+                .get_comprehensive_quotes(chain_id, rewards.token, asset, amount, owner)
+                .await?
+                .best_route;
+            steps.push(YieldOpportunityStep::Swap {
+                dex: format!("{:?}", route.dex),
+                token_in: rewards.token,
+                token_out: asset,
+                amount,
+            });
+            route.output_amount * U256::from(((100.0 - max_slippage_percentage) * 100.0) as u64) / U256::from(10_000)
+        };
+        steps.push(YieldOpportunityStep::Supply { protocol: name.to_string(), asset, amount: supply_amount });
+
+        let mut opportunity = OptimalYieldOpportunity {
+            strategy_type: "Reward Compounding".to_string(),
+            protocol: name.to_string(),
+            estimated_apy: self.trailing_supply_apy(chain_id, &rewards.protocol, asset).await.unwrap_or(0.0),
+            risk_level: "Low".to_string(),
+            min_deposit: U256::zero(),
+            max_deposit: supply_amount,
+            liquidity_risk: 0.0,
+            impermanent_loss_risk: 0.0,
+            smart_contract_risk: 0.0,
+            risk_factors: Vec::new(),
+            projection: None,
+            description: format!(
+                "Claim {} of {:?} from {} and supply it back as {:?}",
+                rewards.claimable.formatted, rewards.token, name, asset,
+            ),
+            steps,
+        };
+        let score = self.protocols.score(&opportunity.protocols(), &MarketDepth::default());
+        opportunity.liquidity_risk = score.liquidity_risk;
+        opportunity.smart_contract_risk = score.smart_contract_risk;
+        opportunity.risk_factors = score.factors;
+
+        Ok(Some(opportunity))
+    }
+
+    /// Claimable rewards of `user` from one protocol, valued as the token they are priced as
+    async fn reward_balance(&self, chain_id: u64, protocol: &str, user: Address) -> Result<RewardBalance> {
+        let name = Self::reward_protocol(protocol)?;
+        let rewards = match name {
+            "Aave" => self.aave.accrued_rewards(chain_id, user).await?,
+            _ => {
+                let comp = self.compound.comp_token(chain_id)
+                    .ok_or_else(|| anyhow::anyhow!("Compound is not deployed on chain {}", chain_id))?;
+                AccruedRewards { claimed_token: comp, reward_token: comp, amount: self.compound.claimable_comp(chain_id, user).await? }
+            }
+        };
+
+        let decimals = self.underlying_decimals(chain_id, rewards.claimed_token).await
+            .ok_or_else(|| anyhow::anyhow!("Decimals of the {} reward token {:?} are unknown", name, rewards.claimed_token))?;
+        let value_usd = match self.price_feeds.get_prices(chain_id, &[rewards.reward_token]).await {
+            Ok(prices) => prices.get(&rewards.reward_token)
+                .map(|price| Self::to_token_units(rewards.amount, decimals) * price.price_usd),
+            Err(e) => {
+                warn!("Failed to price {} rewards on chain {}: {}", name, chain_id, e);
+                None
+            }
+        };
+
+        Ok(RewardBalance {
+            protocol: name.to_lowercase(),
+            token: rewards.claimed_token,
+            claimable: TokenAmount::new(rewards.amount, decimals),
+            value_usd,
+            compoundable: rewards.claimed_token == rewards.reward_token,
+        })
+    }
+
+    /// Step name of a lending protocol paying claimable rewards
+    fn reward_protocol(protocol: &str) -> Result<&'static str> {
+        ["Aave", "Compound"].into_iter()
+            .find(|name| name.eq_ignore_ascii_case(protocol))
+            .ok_or_else(|| anyhow::anyhow!("{} pays no claimable rewards", protocol))
+    }
+
     /// Execute optimal yield strategy automatically
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?user))]
     pub async fn execute_optimal_yield_strategy(&self, chain_id: u64, strategy: OptimalYieldOpportunity, user: Address) -> Result<Vec<TransactionRequest>> {
         let mut transactions = Vec::new();
 
@@ -293,6 +992,7 @@ impl DefiManager {
                         },
                         _ => return Err(anyhow::anyhow!("Unsupported protocol: {}", protocol)),
                     };
+                    self.transactions.record_built(chain_id, Some(user), "defi:yield_strategy", &tx).await;
                     transactions.push(tx);
                 },
                 YieldOpportunityStep::Borrow { protocol, asset, amount } => {
@@ -304,10 +1004,11 @@ impl DefiManager {
                         },
                         _ => return Err(anyhow::anyhow!("Unsupported protocol: {}", protocol)),
                     };
+                    self.transactions.record_built(chain_id, Some(user), "defi:yield_strategy", &tx).await;
                     transactions.push(tx);
                 },
                 YieldOpportunityStep::Swap { token_in, token_out, amount, .. } => {
-                    // Use DEX manager for optimal swapping
+                    // Use DEX manager for optimal swapping, which tracks the swap itself
                     let swap_result = self.dex_manager.execute_optimal_swap(
                         chain_id,
                         *token_in,
@@ -318,7 +1019,22 @@ impl DefiManager {
                     ).await?;
                     transactions.push(swap_result.transaction);
                 },
-                YieldOpportunityStep::Farm { protocol, pool, amount } => {
+                YieldOpportunityStep::Farm { protocol, pool, token: Some(token), amount } if protocol == "Curve" => {
+                    let curve = self.convex.curve_pool(chain_id, *pool)
+                        .ok_or_else(|| anyhow::anyhow!("Curve pool {:?} is not configured on chain {}", pool, chain_id))?;
+                    let min_lp_amount = self.convex_min_lp_amount(chain_id, curve.pid, *token, *amount).await?;
+                    let tx = self.convex.add_liquidity(chain_id, curve.pid, *token, *amount, min_lp_amount).await?.transaction;
+                    self.transactions.record_built(chain_id, Some(user), "defi:yield_strategy", &tx).await;
+                    transactions.push(tx);
+                },
+                YieldOpportunityStep::Stake { protocol, token, amount } if protocol == "Convex" => {
+                    let curve = self.convex.pool_by_lp(chain_id, *token)
+                        .ok_or_else(|| anyhow::anyhow!("No Convex pool stakes {:?} on chain {}", token, chain_id))?;
+                    let tx = self.convex.deposit(chain_id, curve.pid, *amount).await?.transaction;
+                    self.transactions.record_built(chain_id, Some(user), "defi:yield_strategy", &tx).await;
+                    transactions.push(tx);
+                },
+                YieldOpportunityStep::Farm { protocol, pool, amount, .. } => {
                     // Add liquidity to farming pool
                     if protocol == "SushiSwap" {
                         // Would integrate with SushiSwap farming
@@ -329,14 +1045,123 @@ impl DefiManager {
                     // Handle staking operations
                     println!("Staking {} of token {} on {}", amount, token, protocol);
                 },
+                YieldOpportunityStep::Claim { protocol, .. } => {
+                    let tx = self.reward_claim_transaction(chain_id, protocol, user).await?;
+                    self.transactions.record_built(chain_id, Some(user), "defi:yield_strategy", &tx).await;
+                    transactions.push(tx);
+                },
             }
         }
 
         Ok(transactions)
     }
 
-    /// Find cross-protocol arbitrage opportunities
-    pub async fn find_cross_protocol_arbitrage(&self, chain_id: u64) -> Result<Vec<CrossProtocolArbitrage>> {
+    /// Encode a strategy's steps with the approvals they need as the calls of one smart account batch.
+    /// The account supplies, borrows and swaps for itself, so the batch reverts as a whole when any
+    /// step fails instead of leaving a borrow without the supply it was meant to fund
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?account))]
+    pub async fn bundle_optimal_yield_strategy(&self, chain_id: u64, strategy: &OptimalYieldOpportunity, account: Address) -> Result<StrategyBundle> {
+        let mut draft = BundleDraft::default();
+
+        for step in &strategy.steps {
+            match step {
+                YieldOpportunityStep::Supply { protocol, asset, amount } => {
+                    let (spender, tx) = match protocol.as_str() {
+                        "Aave" => {
+                            let pool = self.aave.lending_pool(chain_id)
+                                .ok_or_else(|| anyhow::anyhow!("Aave is not deployed on chain {}", chain_id))?;
+                            (pool, self.aave.supply(chain_id, *asset, *amount, account, 0).await?)
+                        }
+                        "Compound" => {
+                            let ctoken = self.find_ctoken_for_asset(chain_id, *asset).await?;
+                            (ctoken, self.compound.supply(chain_id, ctoken, *amount).await?)
+                        }
+                        _ => return Err(anyhow::anyhow!("Unsupported protocol: {}", protocol)),
+                    };
+                    let spend = TokenSpend { token: *asset, spender, amount: *amount };
+                    draft.actions.push((Some(spend), BundledCall::new(&tx, format!("Supply {} of {:?} to {}", amount, asset, protocol))?));
+                    if protocol == "Compound" {
+                        // Later borrows in the batch need the supply counted as collateral
+                        let enter = self.compound.enter_markets(chain_id, vec![spender]).await?;
+                        draft.actions.push((None, BundledCall::new(&enter, "Enter the Compound market as collateral")?));
+                    }
+                },
+                YieldOpportunityStep::Borrow { protocol, asset, amount } => {
+                    let tx = match protocol.as_str() {
+                        "Aave" => self.aave.borrow(chain_id, *asset, *amount, 2, 0, account).await?,
+                        "Compound" => {
+                            let ctoken = self.find_ctoken_for_asset(chain_id, *asset).await?;
+                            self.compound.borrow(chain_id, ctoken, *amount).await?
+                        },
+                        _ => return Err(anyhow::anyhow!("Unsupported protocol: {}", protocol)),
+                    };
+                    draft.actions.push((None, BundledCall::new(&tx, format!("Borrow {} of {:?} from {}", amount, asset, protocol))?));
+                },
+                YieldOpportunityStep::Swap { token_in, token_out, amount, .. } => {
+                    // Quoted rather than built through `execute_optimal_swap`, the account may only
+                    // receive the input from an earlier step of the batch
+                    let route = self.dex_manager
+                        .get_comprehensive_quotes(chain_id, *token_in, *token_out, *amount, account)
+                        .await?
+                        .best_route;
+                    let router = transaction_target(&route.transaction)
+                        .ok_or_else(|| anyhow::anyhow!("{:?} route has no router to approve", route.dex))?;
+                    let dex = format!("{:?}", route.dex);
+                    let spend = TokenSpend { token: *token_in, spender: router, amount: *amount };
+                    draft.actions.push((Some(spend), BundledCall::new(&route.transaction, format!("Swap {} of {:?} for {:?} on {}", amount, token_in, token_out, dex))?));
+                    draft.swaps.push(BundledSwap {
+                        dex,
+                        token_in: *token_in,
+                        token_out: *token_out,
+                        amount_in: *amount,
+                        expected_output: route.output_amount,
+                    });
+                },
+                YieldOpportunityStep::Claim { protocol, .. } => {
+                    let tx = self.reward_claim_transaction(chain_id, protocol, account).await?;
+                    draft.actions.push((None, BundledCall::new(&tx, format!("Claim {} rewards", protocol))?));
+                },
+                YieldOpportunityStep::Farm { protocol, pool, token: Some(token), amount } if protocol == "Curve" => {
+                    let curve = self.convex.curve_pool(chain_id, *pool)
+                        .ok_or_else(|| anyhow::anyhow!("Curve pool {:?} is not configured on chain {}", pool, chain_id))?;
+                    let min_lp_amount = self.convex_min_lp_amount(chain_id, curve.pid, *token, *amount).await?;
+                    let plan = self.convex.add_liquidity(chain_id, curve.pid, *token, *amount, min_lp_amount).await?;
+                    draft.actions.push((plan.spend, BundledCall::new(&plan.transaction, format!("Add {} of {:?} to Curve {}", amount, token, curve.name))?));
+                },
+                YieldOpportunityStep::Stake { protocol, token, amount } if protocol == "Convex" => {
+                    let curve = self.convex.pool_by_lp(chain_id, *token)
+                        .ok_or_else(|| anyhow::anyhow!("No Convex pool stakes {:?} on chain {}", token, chain_id))?;
+                    let plan = self.convex.deposit(chain_id, curve.pid, *amount).await?;
+                    draft.actions.push((plan.spend, BundledCall::new(&plan.transaction, format!("Stake {} {} LP on Convex", amount, curve.name))?));
+                },
+                YieldOpportunityStep::Farm { .. } | YieldOpportunityStep::Stake { .. } => {
+                    return Err(anyhow::anyhow!("Only Curve farm and Convex stake steps can be bundled"));
+                },
+            }
+        }
+
+        // Planned together so an allowance used up by an earlier step is approved again
+        let spends: Vec<TokenSpend> = draft.actions.iter().filter_map(|(spend, _)| *spend).collect();
+        let mut approvals = self.dex_manager.approvals().plan_approvals(chain_id, account, &spends, None).await?.into_iter();
+        let mut calls = Vec::with_capacity(draft.actions.len());
+        for (spend, action) in draft.actions {
+            if let Some(approval) = spend.and_then(|_| approvals.next().flatten()) {
+                calls.push(BundledCall::new(&approval.transaction, format!("Approve {:?} to spend {:?}", approval.spender, approval.token))?);
+            }
+            calls.push(action);
+        }
+
+        Ok(StrategyBundle {
+            chain_id,
+            account,
+            strategy_type: strategy.strategy_type.clone(),
+            calls,
+            swaps: draft.swaps,
+        })
+    }
+
+    /// Find cross-protocol arbitrage opportunities, liquidations among the indexed Compound `borrowers`
+    pub async fn find_cross_protocol_arbitrage(&self, chain_id: u64, borrowers: &[compound_borrowers::CompoundBorrower]) -> Result<Vec<CrossProtocolArbitrage>> {
         let mut opportunities = Vec::new();
 
         // Rate arbitrage between Aave and Compound
@@ -377,7 +1202,7 @@ impl DefiManager {
         }
 
         // Liquidation arbitrage opportunities
-        let compound_liquidations = self.compound.find_liquidation_opportunities(chain_id).await?;
+        let compound_liquidations = self.compound.find_liquidation_opportunities(chain_id, borrowers).await?;
         for liq in compound_liquidations {
             opportunities.push(CrossProtocolArbitrage {
                 arbitrage_type: "Liquidation Arbitrage".to_string(),
@@ -453,10 +1278,36 @@ impl DefiManager {
         // Execute flash loan strategy
         let flash_loan_txs = self.flash_loans.execute_flash_loan_strategy(chain_id, flash_loan_strategy).await?;
         transactions.extend(flash_loan_txs);
+        self.transactions.record_built_all(chain_id, None, "defi:flash_loan_arbitrage", &transactions).await;
 
         Ok(transactions)
     }
 
+    /// Liquidate a Compound borrower with an Aave flash loan of the repaid asset, executed by `receiver`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn build_flash_liquidation(
+        &self,
+        chain_id: u64,
+        borrower: Address,
+        ctoken_borrowed: Address,
+        ctoken_collateral: Address,
+        repay_amount: U256,
+        receiver: Address,
+        beneficiary: Address,
+        min_profit: U256,
+    ) -> Result<FlashLiquidation> {
+        let leg = self.compound.liquidation_leg(chain_id, borrower, ctoken_borrowed, ctoken_collateral, repay_amount).await?;
+        let liquidation = self.flash_loans.build_liquidation(chain_id, &leg, receiver, beneficiary, min_profit).await?;
+        self.transactions.record_built_all(
+            chain_id,
+            Some(beneficiary),
+            "defi:flash_liquidation",
+            std::slice::from_ref(&liquidation.transaction),
+        ).await;
+
+        Ok(liquidation)
+    }
+
     /// Rebalance portfolio to optimize yield
     pub async fn rebalance_portfolio(&self, chain_id: u64, user: Address, target_allocation: std::collections::HashMap<String, f64>) -> Result<Vec<TransactionRequest>> {
         let mut transactions = Vec::new();
@@ -514,34 +1365,43 @@ impl DefiManager {
                 }
             }
         }
-        
+        self.transactions.record_built_all(chain_id, Some(user), "defi:rebalance", &transactions).await;
+
         Ok(transactions)
     }
 
     /// Monitor and alert for liquidation risks
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?user))]
     pub async fn monitor_liquidation_risks(&self, chain_id: u64, user: Address) -> Result<Vec<String>> {
         let mut alerts = Vec::new();
         
         let portfolio = self.get_portfolio_overview(chain_id, user).await?;
         
-        // Check Aave health factors
-        for position in &portfolio.aave_positions {
-            let health_factor = (position.health_factor.as_u128() as f64) / 1e18;
-            if health_factor < 1.5 {
-                alerts.push(format!(
-                    "⚠️ Aave position for {} at risk! Health factor: {:.2}",
-                    format!("{:?}", position.asset)[2..8].to_uppercase(),
-                    health_factor
-                ));
+        // Check each protocol's health factor on its own
+        for protocol in &portfolio.risk.protocols {
+            if let Some(health_factor) = protocol.health_factor {
+                if health_factor < 1.5 {
+                    alerts.push(format!(
+                        "⚠️ {} positions at risk! Health factor: {:.2}",
+                        protocol.protocol,
+                        health_factor
+                    ));
+                }
             }
         }
-        
-        // Check Compound health factor
-        if portfolio.overall_health_factor < 1.3 {
-            alerts.push(format!(
-                "⚠️ Compound positions at risk! Health factor: {:.2}",
-                portfolio.overall_health_factor
-            ));
+
+        // Check collateral close to its liquidation price
+        for collateral in &portfolio.risk.collateral {
+            if let Some(distance) = collateral.liquidation_distance_pct {
+                if distance < 15.0 {
+                    alerts.push(format!(
+                        "⚠️ {} collateral {} is {:.1}% from liquidation",
+                        collateral.protocol,
+                        format!("{:?}", collateral.asset)[2..8].to_uppercase(),
+                        distance
+                    ));
+                }
+            }
         }
         
         // Check for high borrowing ratios
@@ -553,59 +1413,1472 @@ impl DefiManager {
     }
 
     // Helper methods
-    async fn create_cross_protocol_strategy(&self, chain_id: u64, asset: Address, amount: U256) -> Result<OptimalYieldOpportunity> {
-        Ok(OptimalYieldOpportunity {
-            strategy_type: "Cross-Protocol Yield Maximization".to_string(),
-            protocol: "Aave + Compound".to_string(),
-            estimated_apy: 18.5,
-            risk_level: "High".to_string(),
-            min_deposit: U256::from(50000u64),
-            max_deposit: amount * U256::from(3),
-            liquidity_risk: 0.4,
-            impermanent_loss_risk: 0.0,
-            smart_contract_risk: 0.25,
-            description: "Supply on Aave, borrow stablecoin, supply on Compound for rate arbitrage".to_string(),
-            steps: vec![
-                YieldOpportunityStep::Supply { protocol: "Aave".to_string(), asset, amount },
-                YieldOpportunityStep::Borrow { 
-                    protocol: "Aave".to_string(), 
-                    asset: "0xA0b86a33E6441E5A3D3CdeC19A4F6BbBc2A906b4".parse()?, // USDC
-                    amount: amount * U256::from(75) / U256::from(100) 
-                },
-                YieldOpportunityStep::Supply { 
-                    protocol: "Compound".to_string(), 
-                    asset: "0xA0b86a33E6441E5A3D3CdeC19A4F6BbBc2A906b4".parse()?, 
-                    amount: amount * U256::from(75) / U256::from(100) 
-                },
-            ],
-        })
-    }
+    async fn value_positions(
+        &self,
+        chain_id: u64,
+        aave_positions: &[AaveLendingPosition],
+        compound_positions: &[compound::UserCTokenPosition],
+    ) -> Result<Vec<PositionValuation>> {
+        let mut valuations = Vec::new();
 
-    async fn get_aave_rates(&self, chain_id: u64) -> Result<Vec<(Address, U256)>> {
-        // Mock implementation - would get actual rates from Aave
-        Ok(vec![
-            ("0xA0b86a33E6441E5A3D3CdeC19A4F6BbBc2A906b4".parse()?, U256::from(35000000000000000u64)), // 3.5%
-            ("0x2170Ed0880ac9A755fd29B2688956BD959F933F8".parse()?, U256::from(25000000000000000u64)), // 2.5%
-        ])
-    }
+        for position in aave_positions {
+            let (decimals, liquidation_threshold) = match self.aave.get_reserve_data(chain_id, position.asset).await {
+                Ok(reserve) => {
+                    // Aave reports thresholds in basis points
+                    let threshold = if reserve.usage_as_collateral_enabled {
+                        reserve.liquidation_threshold as f64 / 10_000.0
+                    } else {
+                        0.0
+                    };
+                    (Some(reserve.decimals), threshold)
+                }
+                Err(e) => {
+                    warn!("Failed to read the Aave reserve of {:?} on chain {}: {}", position.asset, chain_id, e);
+                    (None, 0.0)
+                }
+            };
+            valuations.push(Self::unpriced_valuation(
+                "Aave",
+                position.asset,
+                decimals,
+                (position.supplied_amount, position.borrowed_amount_stable + position.borrowed_amount_variable),
+                (position.apy_supplied, position.apy_borrowed_variable),
+                (0.0, 0.0),
+                liquidation_threshold,
+            ));
+        }
 
-    async fn find_ctoken_for_asset(&self, chain_id: u64, asset: Address) -> Result<Address> {
-        // Mock implementation - would have proper asset to cToken mapping
-        Ok("0x5d3a536E4D6DbD6114cc1Ead35777bAB948E3643".parse()?) // cDAI
-    }
+        for position in compound_positions {
+            let ctoken_info = self.compound.get_ctoken_info(chain_id, position.ctoken).await?;
+            // cToken balances convert to underlying via the 1e18-scaled exchange rate
+            let supplied = position.supply_balance * ctoken_info.exchange_rate / U256::exp10(18);
+            let decimals = self.underlying_decimals(chain_id, ctoken_info.underlying_address).await;
+            // Compound collateral factors are 1e18-scaled
+            let liquidation_threshold = if position.is_collateral {
+                Self::to_token_units(position.collateral_factor, 18)
+            } else {
+                0.0
+            };
+            valuations.push(Self::unpriced_valuation(
+                "Compound",
+                ctoken_info.underlying_address,
+                decimals,
+                (supplied, position.borrow_balance),
+                (position.supply_apy, position.borrow_apy),
+                (position.comp_apy_supply, position.comp_apy_borrow),
+                liquidation_threshold,
+            ));
+        }
 
-    pub fn aave(&self) -> &AaveManager {
-        &self.aave
+        let mut price_tokens: Vec<Address> = valuations.iter()
+            .map(|valuation| pricing_address(chain_id, valuation.asset))
+            .collect();
+        price_tokens.sort();
+        price_tokens.dedup();
+        let prices = self.price_feeds.get_prices(chain_id, &price_tokens).await?;
+
+        for valuation in &mut valuations {
+            let price = prices.get(&pricing_address(chain_id, valuation.asset));
+            if let (Some(price), Some(supplied), Some(borrowed)) = (price, valuation.supplied, valuation.borrowed) {
+                valuation.price_usd = Some(price.price_usd);
+                valuation.price_source = Some(price.source);
+                valuation.supplied_usd = supplied * price.price_usd;
+                valuation.borrowed_usd = borrowed * price.price_usd;
+            }
+        }
+
+        Ok(valuations)
     }
 
-    pub fn compound(&self) -> &CompoundManager {
+    /// Position valuation with token amounts filled in and USD fields left for pricing
+    fn unpriced_valuation(
+        protocol: &str,
+        asset: Address,
+        decimals: Option<u8>,
+        (supplied_amount, borrowed_amount): (U256, U256),
+        (supply_apy, borrow_apy): (f64, f64),
+        (supply_reward_apy, borrow_reward_apy): (f64, f64),
+        liquidation_threshold: f64,
+    ) -> PositionValuation {
+        PositionValuation {
+            protocol: protocol.to_string(),
+            asset,
+            decimals,
+            supplied_amount,
+            borrowed_amount,
+            supplied: decimals.map(|decimals| Self::to_token_units(supplied_amount, decimals)),
+            borrowed: decimals.map(|decimals| Self::to_token_units(borrowed_amount, decimals)),
+            supply_apy,
+            borrow_apy,
+            supply_reward_apy,
+            borrow_reward_apy,
+            price_usd: None,
+            price_source: None,
+            supplied_usd: 0.0,
+            borrowed_usd: 0.0,
+            liquidation_threshold,
+        }
+    }
+
+    /// Build per-protocol health factors and per-collateral liquidation distances.
+    /// `reported` holds each protocol's own health factor, used when its positions cannot all be priced.
+    fn assess_portfolio_risk(positions: &[PositionValuation], reported: &[(&str, Option<f64>)], vaults: &[MakerVault]) -> PortfolioRisk {
+        let mut protocols = Vec::new();
+        let mut collateral = Vec::new();
+
+        for (protocol, reported_health) in reported {
+            let held: Vec<&PositionValuation> = positions.iter()
+                .filter(|p| p.protocol == *protocol)
+                .collect();
+            if held.is_empty() {
+                continue;
+            }
+
+            let collateral_usd: f64 = held.iter().map(|p| p.supplied_usd).sum();
+            let weighted_collateral_usd: f64 = held.iter().map(|p| p.supplied_usd * p.liquidation_threshold).sum();
+            let debt_usd: f64 = held.iter().map(|p| p.borrowed_usd).sum();
+            let fully_priced = held.iter().all(|p| p.price_usd.is_some());
+
+            let health_factor = if !fully_priced {
+                reported_health.filter(|hf| hf.is_finite())
+            } else if debt_usd > 0.0 {
+                Some(weighted_collateral_usd / debt_usd)
+            } else {
+                None
+            };
+
+            for position in held.iter().filter(|p| p.liquidation_threshold > 0.0 && p.supplied_usd > 0.0) {
+                // Solve weighted - drop * c*t = debt - drop * b for the fractional price drop,
+                // the asset's own debt shrinks with its price too
+                let distance = if !fully_priced || debt_usd <= 0.0 {
+                    None
+                } else {
+                    let sensitivity = position.supplied_usd * position.liquidation_threshold - position.borrowed_usd;
+                    if sensitivity <= 0.0 {
+                        None
+                    } else {
+                        let drop = (weighted_collateral_usd - debt_usd) / sensitivity;
+                        if drop >= 1.0 { None } else { Some(drop.max(0.0)) }
+                    }
+                };
+
+                collateral.push(CollateralRisk {
+                    protocol: protocol.to_string(),
+                    asset: position.asset,
+                    collateral_usd: position.supplied_usd,
+                    price_usd: position.price_usd,
+                    liquidation_price_usd: distance.zip(position.price_usd).map(|(drop, price)| price * (1.0 - drop)),
+                    liquidation_distance_pct: distance.map(|drop| drop * 100.0),
+                });
+            }
+
+            protocols.push(ProtocolHealth {
+                protocol: protocol.to_string(),
+                health_factor,
+                collateral_usd,
+                weighted_collateral_usd,
+                debt_usd,
+                reported_by_protocol: !fully_priced,
+            });
+        }
+
+        // A vault's collateral only backs its own debt, so its price drop alone liquidates it
+        for vault in vaults {
+            protocols.push(ProtocolHealth {
+                protocol: vault.label(),
+                health_factor: vault.health_factor,
+                collateral_usd: vault.collateral_usd,
+                weighted_collateral_usd: vault.collateral_usd / vault.liquidation_ratio,
+                debt_usd: vault.debt_usd,
+                reported_by_protocol: false,
+            });
+            if vault.collateral_usd > 0.0 {
+                collateral.push(CollateralRisk {
+                    protocol: vault.label(),
+                    asset: vault.collateral_token,
+                    collateral_usd: vault.collateral_usd,
+                    price_usd: Some(vault.price_usd),
+                    liquidation_price_usd: vault.liquidation_price_usd,
+                    liquidation_distance_pct: vault.liquidation_price_usd
+                        .map(|price| ((1.0 - price / vault.price_usd) * 100.0).max(0.0)),
+                });
+            }
+        }
+
+        let weakest = protocols.iter()
+            .filter_map(|p| p.health_factor.map(|hf| (hf, p.protocol.clone())))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        collateral.sort_by(|a, b| {
+            let a = a.liquidation_distance_pct.unwrap_or(f64::INFINITY);
+            let b = b.liquidation_distance_pct.unwrap_or(f64::INFINITY);
+            a.total_cmp(&b)
+        });
+
+        PortfolioRisk {
+            protocols,
+            min_health_factor: weakest.as_ref().map(|w| w.0),
+            weakest_protocol: weakest.map(|w| w.1),
+            collateral,
+        }
+    }
+
+    /// Decimals of a token from the asset registry, else read from its contract; `None` when they
+    /// cannot be read, as amounts scaled by a guess would be off by orders of magnitude
+    async fn underlying_decimals(&self, chain_id: u64, token: Address) -> Option<u8> {
+        if token.is_zero() {
+            return Some(18); // native ETH
+        }
+        if let Some((_, representation)) = self.dex_manager.assets().resolve(chain_id, token) {
+            return Some(representation.decimals);
+        }
+
+        let decimals = async {
+            let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+            let erc20 = Contract::new(token, parse_abi(&["function decimals() view returns (uint8)"])?, provider);
+            Ok::<_, anyhow::Error>(erc20.method::<_, u8>("decimals", ())?.call().await?)
+        };
+        match decimals.await {
+            Ok(decimals) => Some(decimals),
+            Err(e) => {
+                warn!("Failed to read the decimals of {:?} on chain {}: {}", token, chain_id, e);
+                None
+            }
+        }
+    }
+
+    fn to_token_units(amount: U256, decimals: u8) -> f64 {
+        ethers::utils::format_units(amount, decimals as u32)
+            .ok()
+            .and_then(|units| units.parse().ok())
+            .unwrap_or(0.0)
+    }
+
+    async fn create_cross_protocol_strategy(&self, chain_id: u64, asset: Address, amount: U256) -> Result<OptimalYieldOpportunity> {
+        Ok(OptimalYieldOpportunity {
+            strategy_type: "Cross-Protocol Yield Maximization".to_string(),
+            protocol: "Aave + Compound".to_string(),
+            estimated_apy: 18.5,
+            risk_level: "High".to_string(),
+            min_deposit: U256::from(50000u64),
+            max_deposit: amount * U256::from(3),
+            liquidity_risk: 0.0,
+            impermanent_loss_risk: 0.0,
+            smart_contract_risk: 0.0,
+            risk_factors: Vec::new(),
+            projection: None,
+            description: "Supply on Aave, borrow stablecoin, supply on Compound for rate arbitrage".to_string(),
+            steps: vec![
+                YieldOpportunityStep::Supply { protocol: "Aave".to_string(), asset, amount },
+                YieldOpportunityStep::Borrow { 
+                    protocol: "Aave".to_string(), 
+                    asset: "0xA0b86a33E6441E5A3D3CdeC19A4F6BbBc2A906b4".parse()?, // USDC
+                    amount: amount * U256::from(75) / U256::from(100) 
+                },
+                YieldOpportunityStep::Supply { 
+                    protocol: "Compound".to_string(), 
+                    asset: "0xA0b86a33E6441E5A3D3CdeC19A4F6BbBc2A906b4".parse()?, 
+                    amount: amount * U256::from(75) / U256::from(100) 
+                },
+            ],
+        })
+    }
+
+    /// Lock `amount` of `asset` in a Maker vault at `MAKER_TARGET_HEALTH` times its liquidation ratio
+    /// and supply the DAI drawn to whichever of Aave and Compound pays more. `None` unless the asset is
+    /// Maker collateral and the DAI supply rate beats the stability fee
+    async fn maker_dai_strategy(&self, chain_id: u64, asset: Address, amount: U256) -> Option<OptimalYieldOpportunity> {
+        let collateral = self.maker.collateral_type(chain_id, asset)?.clone();
+        let dai = self.maker.dai(chain_id)?;
+        let ilk = match self.maker.ilk(chain_id, &collateral.ilk).await {
+            Ok(ilk) => ilk,
+            Err(e) => {
+                warn!("Failed to read Maker {} on chain {}: {}", collateral.ilk, chain_id, e);
+                return None;
+            }
+        };
+
+        let aave_rate = match self.aave.get_reserve_data(chain_id, dai).await {
+            Ok(reserve) => Some(self.trailing_supply_apy(chain_id, "aave", dai).await
+                .unwrap_or_else(|| aave_apy(reserve.liquidity_rate.as_u128()))),
+            Err(e) => {
+                warn!("No Aave DAI rate on chain {}: {}", chain_id, e);
+                None
+            }
+        };
+        let compound_info = async {
+            let ctoken = self.find_ctoken_for_asset(chain_id, dai).await?;
+            self.compound.get_ctoken_info(chain_id, ctoken).await
+        };
+        let compound_rate = match compound_info.await {
+            Ok(info) => Some(self.trailing_supply_apy(chain_id, "compound", dai).await
+                .unwrap_or_else(|| compound_apy(info.supply_rate_per_block.as_u128()))),
+            Err(e) => {
+                warn!("No Compound DAI rate on chain {}: {}", chain_id, e);
+                None
+            }
+        };
+        let (lender, supply_apy) = [("Aave", aave_rate), ("Compound", compound_rate)].into_iter()
+            .filter_map(|(lender, apy)| Some((lender, apy?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        let spread = supply_apy - ilk.stability_fee_apy;
+        if spread <= 0.0 {
+            return None;
+        }
+
+        let drawn = ilk.max_debt(amount) * U256::from(100) / U256::from((MAKER_TARGET_HEALTH * 100.0) as u64);
+        if drawn.is_zero() || drawn < ilk.dust || drawn > ilk.available_debt {
+            return None;
+        }
+        let collateral_usd = Self::to_token_units(amount, collateral.decimals) * ilk.price_usd;
+        let drawn_dai = Self::to_token_units(drawn, 18);
+
+        Some(OptimalYieldOpportunity {
+            strategy_type: "Maker Vault DAI Lending".to_string(),
+            protocol: format!("Maker + {}", lender),
+            // Only the drawn DAI earns the spread, the locked collateral earns nothing
+            estimated_apy: spread * drawn_dai / collateral_usd,
+            risk_level: "Medium".to_string(),
+            // Smallest deposit drawing the vault minimum at the target ratio
+            min_deposit: amount * ilk.dust / drawn,
+            max_deposit: amount * ilk.available_debt / drawn,
+            liquidity_risk: 0.0,
+            impermanent_loss_risk: 0.0,
+            smart_contract_risk: 0.0,
+            risk_factors: Vec::new(),
+            projection: None,
+            description: format!(
+                "Lock {} in a Maker {} vault at {:.0}% collateralization, draw {:.2} DAI and supply it to {} at {:.2}% against a {:.2}% stability fee",
+                collateral.ilk.split('-').next().unwrap_or_default(),
+                collateral.ilk,
+                ilk.liquidation_ratio * MAKER_TARGET_HEALTH * 100.0,
+                drawn_dai,
+                lender,
+                supply_apy,
+                ilk.stability_fee_apy,
+            ),
+            steps: vec![
+                YieldOpportunityStep::Supply { protocol: "Maker".to_string(), asset, amount },
+                YieldOpportunityStep::Borrow { protocol: "Maker".to_string(), asset: dai, amount: drawn },
+                YieldOpportunityStep::Supply { protocol: lender.to_string(), asset: dai, amount: drawn },
+            ],
+        })
+    }
+
+    /// Transactions of a Maker vault action for `owner` to send, approvals first, tracked as built
+    pub async fn build_vault_action(&self, chain_id: u64, owner: Address, action: VaultAction) -> Result<VaultTransactions> {
+        let (transactions, vault_after) = match &action {
+            VaultAction::Open { ilk } => (vec![self.maker.open_vault(chain_id, ilk, owner).await?], None),
+            VaultAction::Deposit { vault_id, amount } => {
+                let plan = self.maker.deposit_collateral(chain_id, owner, *vault_id, *amount).await?;
+                (self.maker.with_approval(chain_id, owner, &plan).await?, Some(plan.vault_after))
+            }
+            VaultAction::Draw { vault_id, amount } => {
+                let plan = self.maker.draw_dai(chain_id, owner, *vault_id, *amount).await?;
+                (self.maker.with_approval(chain_id, owner, &plan).await?, Some(plan.vault_after))
+            }
+            VaultAction::Repay { vault_id, amount } => {
+                let plan = self.maker.repay_dai(chain_id, owner, *vault_id, *amount).await?;
+                (self.maker.with_approval(chain_id, owner, &plan).await?, Some(plan.vault_after))
+            }
+        };
+        let records = self.transactions.record_built_all(chain_id, Some(owner), "defi:maker", &transactions).await;
+
+        Ok(VaultTransactions {
+            chain_id,
+            owner,
+            action,
+            vault_after,
+            transactions,
+            transaction_ids: records.into_iter().map(|record| record.id).collect(),
+        })
+    }
+
+    /// Configured Curve pools staked on Convex, with their yield at the current CRV and CVX prices
+    pub async fn convex_pools(&self, chain_id: u64) -> Result<Vec<ConvexPoolYield>> {
+        let pools = self.convex.pools(chain_id).await?;
+        let prices = self.convex_reward_prices(chain_id).await;
+        Ok(pools.into_iter()
+            .map(|pool| ConvexPoolYield { apy: prices.map(|(crv, cvx)| pool.apy(crv, cvx)), pool })
+            .collect())
+    }
+
+    /// Add `asset` to each Curve pool holding it and stake the LP on Convex, paired with the pool's
+    /// depth. Pools that are shut down or cannot be read are left out, and none are offered while
+    /// CRV or CVX is unpriced
+    async fn convex_farming_strategies(&self, chain_id: u64, asset: Address, amount: U256) -> Vec<(OptimalYieldOpportunity, MarketDepth)> {
+        let curves = self.convex.pools_with_coin(chain_id, asset);
+        if curves.is_empty() || amount.is_zero() {
+            return Vec::new();
+        }
+        let Some((crv_price, cvx_price)) = self.convex_reward_prices(chain_id).await else {
+            warn!("CRV or CVX is unpriced on chain {}, Convex farming is not offered", chain_id);
+            return Vec::new();
+        };
+        let Some(decimals) = self.underlying_decimals(chain_id, asset).await else {
+            return Vec::new();
+        };
+        // Coins are counted at par, as the virtual price is
+        let deposit_usd = Self::to_token_units(amount, decimals);
+
+        let mut strategies = Vec::new();
+        for curve in curves {
+            let pool = match self.convex.pool(chain_id, curve.pid).await {
+                Ok(pool) if !pool.shutdown => pool,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("Failed to read Convex pool {} on chain {}: {}", curve.name, chain_id, e);
+                    continue;
+                }
+            };
+            let min_lp_amount = match self.convex_min_lp_amount(chain_id, curve.pid, asset, amount).await {
+                Ok(min_lp_amount) => min_lp_amount,
+                Err(e) => {
+                    warn!("Failed to quote Curve {} liquidity on chain {}: {}", curve.name, chain_id, e);
+                    continue;
+                }
+            };
+            let apy = pool.apy(crv_price, cvx_price);
+            let depth = MarketDepth { tvl_usd: Some(pool.tvl_usd), utilization: None, deposit_usd: Some(deposit_usd) };
+
+            strategies.push((OptimalYieldOpportunity {
+                strategy_type: "Convex Stablecoin LP Farming".to_string(),
+                protocol: "Curve + Convex".to_string(),
+                estimated_apy: apy.total_apy,
+                risk_level: "Medium".to_string(),
+                min_deposit: U256::zero(),
+                // Quoted for this deposit, larger ones move the pool further
+                max_deposit: amount,
+                liquidity_risk: 0.0,
+                // Pegged coins only drift apart in a depeg, which leaves the pool holding the depegged one
+                impermanent_loss_risk: 0.1,
+                smart_contract_risk: 0.0,
+                risk_factors: Vec::new(),
+                projection: None,
+                description: format!(
+                    "Add {:?} to Curve {} and stake the LP on Convex at a {:.2}x CRV boost: {:.2}% trading fees, {:.2}% CRV and {:.2}% CVX after Convex's {:.0}% fee",
+                    asset, curve.name, pool.boost, apy.base_apy, apy.crv_apr, apy.cvx_apr, pool.convex_fee * 100.0,
+                ),
+                steps: vec![
+                    YieldOpportunityStep::Farm { protocol: "Curve".to_string(), pool: curve.pool, token: Some(asset), amount },
+                    YieldOpportunityStep::Stake { protocol: "Convex".to_string(), token: curve.lp_token, amount: min_lp_amount },
+                ],
+            }, depth));
+        }
+        strategies
+    }
+
+    /// CRV and CVX prices, `None` while either is unpriced
+    async fn convex_reward_prices(&self, chain_id: u64) -> Option<(f64, f64)> {
+        let (crv, cvx) = self.convex.reward_tokens(chain_id)?;
+        match self.price_feeds.get_prices(chain_id, &[crv, cvx]).await {
+            Ok(prices) => Some((prices.get(&crv)?.price_usd, prices.get(&cvx)?.price_usd)),
+            Err(e) => {
+                warn!("Failed to price CRV and CVX on chain {}: {}", chain_id, e);
+                None
+            }
+        }
+    }
+
+    /// LP tokens adding `amount` of `token` to the Curve pool of `pid` must mint: the quote less the
+    /// default slippage
+    async fn convex_min_lp_amount(&self, chain_id: u64, pid: u64, token: Address, amount: U256) -> Result<U256> {
+        let quote = self.convex.quote_liquidity(chain_id, pid, token, amount).await?;
+        let slippage = SlippageSettings::default().max_slippage_percentage;
+        Ok(quote * U256::from(((100.0 - slippage) * 100.0) as u64) / U256::from(10_000))
+    }
+
+    /// Transactions of a Curve or Convex action for `owner` to send, approvals first, tracked as built
+    pub async fn build_convex_action(&self, chain_id: u64, owner: Address, action: ConvexAction) -> Result<ConvexTransactions> {
+        let transactions = match &action {
+            ConvexAction::AddLiquidity { pid, token, amount } => {
+                let min_lp_amount = self.convex_min_lp_amount(chain_id, *pid, *token, *amount).await?;
+                let plan = self.convex.add_liquidity(chain_id, *pid, *token, *amount, min_lp_amount).await?;
+                self.convex.with_approval(chain_id, owner, &plan).await?
+            }
+            ConvexAction::Deposit { pid, amount } => {
+                let plan = self.convex.deposit(chain_id, *pid, *amount).await?;
+                self.convex.with_approval(chain_id, owner, &plan).await?
+            }
+            ConvexAction::Withdraw { pid, amount } => vec![self.convex.withdraw(chain_id, owner, *pid, *amount).await?.transaction],
+            ConvexAction::Claim { pid } => vec![self.convex.claim_rewards(chain_id, owner, *pid).await?],
+        };
+        let records = self.transactions.record_built_all(chain_id, Some(owner), "defi:convex", &transactions).await;
+
+        Ok(ConvexTransactions {
+            chain_id,
+            owner,
+            action,
+            transactions,
+            transaction_ids: records.into_iter().map(|record| record.id).collect(),
+        })
+    }
+
+    async fn get_aave_rates(&self, chain_id: u64) -> Result<Vec<(Address, U256)>> {
+        // Mock implementation - would get actual rates from Aave
+        Ok(vec![
+            ("0xA0b86a33E6441E5A3D3CdeC19A4F6BbBc2A906b4".parse()?, U256::from(35000000000000000u64)), // 3.5%
+            ("0x2170Ed0880ac9A755fd29B2688956BD959F933F8".parse()?, U256::from(25000000000000000u64)), // 2.5%
+        ])
+    }
+
+    /// Project a leveraged strategy's legs from their markets' rates, rewards and liquidation
+    /// threshold; `None` when a market cannot be read or the deposit is unpriced
+    async fn project_leverage(
+        &self,
+        chain_id: u64,
+        protocol: &str,
+        asset: Address,
+        amount: U256,
+        legs: &LeverageLegs,
+    ) -> Option<LeverageProjection> {
+        let projection = async {
+            let collateral = self.lending_market_rates(chain_id, protocol, legs.collateral_market).await?;
+            let debt = if legs.same_market() {
+                collateral.clone()
+            } else {
+                self.lending_market_rates(chain_id, protocol, legs.debt_market).await?
+            };
+
+            let price_token = pricing_address(chain_id, asset);
+            let native_token = pricing_address(chain_id, Address::zero());
+            let prices = self.price_feeds.get_prices(chain_id, &[price_token, native_token]).await?;
+            let price_usd = prices.get(&price_token)
+                .map(|price| price.price_usd)
+                .ok_or_else(|| anyhow::anyhow!("{:?} is unpriced", asset))?;
+            let decimals = self.underlying_decimals(chain_id, asset).await
+                .ok_or_else(|| anyhow::anyhow!("Decimals of {:?} are unknown", asset))?;
+            let deposit_usd = Self::to_token_units(amount, decimals) * price_usd;
+            let entry_cost_usd = match (self.chain_manager.get_gas_price(chain_id).await, prices.get(&native_token)) {
+                (Ok(gas_price), Some(native_price)) => Some(
+                    Self::to_token_units(U256::from(legs.gas_units()).saturating_mul(gas_price), 18) * native_price.price_usd,
+                ),
+                _ => None,
+            };
+
+            anyhow::Ok(LeverageProjection::new(LeverageInputs {
+                collateral_usd: deposit_usd * legs.collateral_ratio,
+                debt_usd: deposit_usd * legs.debt_ratio,
+                idle_debt_usd: deposit_usd * legs.idle_debt_ratio(),
+                collateral,
+                debt,
+                same_market: legs.same_market(),
+                entry_cost_usd,
+            }))
+        };
+        match projection.await {
+            Ok(projection) => Some(projection),
+            Err(e) => {
+                warn!("Failed to project leveraged {} strategy for {:?} on chain {}: {}", protocol, asset, chain_id, e);
+                None
+            }
+        }
+    }
+
+    /// Trailing rates, reward APYs and liquidation threshold of an Aave reserve or Compound cToken
+    async fn lending_market_rates(&self, chain_id: u64, protocol: &str, market: Address) -> Result<LendingMarketRates> {
+        let (asset, spot_supply_apy, spot_borrow_apy, liquidation_threshold, reward_token, rewards_per_year, deposits, borrows) =
+            if protocol == "compound" {
+                let info = self.compound.get_ctoken_info(chain_id, market).await?;
+                let comp = self.compound.comp_token(chain_id)
+                    .ok_or_else(|| anyhow::anyhow!("No Compound deployment on chain {}", chain_id))?;
+                let per_year = |speed: U256| Self::to_token_units(speed, 18) * COMPOUND_BLOCKS_PER_YEAR;
+                (
+                    info.underlying_address,
+                    compound_apy(info.supply_rate_per_block.as_u128()),
+                    compound_apy(info.borrow_rate_per_block.as_u128()),
+                    Self::to_token_units(info.collateral_factor, 18),
+                    comp,
+                    (per_year(info.comp_speed_supply), per_year(info.comp_speed_borrow)),
+                    info.cash.saturating_add(info.total_borrows).saturating_sub(info.total_reserves),
+                    info.total_borrows,
+                )
+            } else {
+                let reserve = self.aave.get_reserve_data(chain_id, market).await?;
+                let emissions = self.aave.reserve_emissions(chain_id, &reserve).await?;
+                let per_year = |per_second: U256| Self::to_token_units(per_second, 18) * SECONDS_PER_YEAR;
+                (
+                    market,
+                    aave_apy(reserve.liquidity_rate.as_u128()),
+                    aave_apy(reserve.variable_borrow_rate.as_u128()),
+                    reserve.liquidation_threshold as f64 / 10_000.0,
+                    emissions.reward_token,
+                    (per_year(emissions.supply_per_second), per_year(emissions.variable_borrow_per_second)),
+                    reserve.available_liquidity
+                        .saturating_add(reserve.total_stable_debt)
+                        .saturating_add(reserve.total_variable_debt),
+                    reserve.total_variable_debt,
+                )
+            };
+
+        let decimals = self.underlying_decimals(chain_id, asset).await;
+        let price_token = pricing_address(chain_id, asset);
+        let prices = self.price_feeds.get_prices(chain_id, &[price_token, reward_token]).await?;
+        let price_usd = prices.get(&price_token).map(|price| price.price_usd);
+        let reward_price_usd = prices.get(&reward_token).map(|price| price.price_usd);
+        let trend = self.rate_trend(chain_id, protocol, asset).await;
+        // Emissions cannot be measured against a market of unknown decimals
+        let market_units = |amount: U256| decimals.map(|decimals| Self::to_token_units(amount, decimals));
+
+        Ok(LendingMarketRates {
+            asset: price_token,
+            supply_apy: trend.as_ref().and_then(RateTrend::trailing_supply_apy).unwrap_or(spot_supply_apy),
+            borrow_apy: trend.as_ref().and_then(RateTrend::trailing_borrow_apy).unwrap_or(spot_borrow_apy),
+            supply_reward_apy: market_units(deposits).map_or(0.0, |units| reward_apy(rewards_per_year.0, reward_price_usd, units, price_usd)),
+            borrow_reward_apy: market_units(borrows).map_or(0.0, |units| reward_apy(rewards_per_year.1, reward_price_usd, units, price_usd)),
+            liquidation_threshold,
+            price_usd,
+        })
+    }
+
+    /// Trailing averages of a lending market's sampled rates, `None` without rate history
+    async fn rate_trend(&self, chain_id: u64, protocol: &str, asset: Address) -> Option<RateTrend> {
+        self.rate_history.as_ref()?.trend(chain_id, protocol, pricing_address(chain_id, asset)).await
+    }
+
+    async fn trailing_supply_apy(&self, chain_id: u64, protocol: &str, asset: Address) -> Option<f64> {
+        self.rate_trend(chain_id, protocol, asset).await?.trailing_supply_apy()
+    }
+
+    /// Deposits and utilization of the Aave and Compound markets of `asset`, valued at its feed price
+    /// along with a deposit of `amount`; markets that cannot be read are left out
+    async fn lending_market_depths(&self, chain_id: u64, asset: Address, amount: U256) -> Vec<(&'static str, MarketDepth)> {
+        let decimals = self.underlying_decimals(chain_id, asset).await;
+        let price_token = pricing_address(chain_id, asset);
+        let price_usd = match self.price_feeds.get_prices(chain_id, &[price_token]).await {
+            Ok(prices) => prices.get(&price_token).map(|price| price.price_usd),
+            Err(e) => {
+                warn!("Failed to price {:?} on chain {} for market depth: {}", asset, chain_id, e);
+                None
+            }
+        };
+        let usd = |amount: U256| price_usd.zip(decimals).map(|(price, decimals)| Self::to_token_units(amount, decimals) * price);
+        let depth = |deposits: U256, borrowed: U256| MarketDepth {
+            tvl_usd: usd(deposits),
+            utilization: decimals.filter(|_| !deposits.is_zero())
+                .map(|decimals| Self::to_token_units(borrowed, decimals) / Self::to_token_units(deposits, decimals)),
+            deposit_usd: usd(amount),
+        };
+
+        let mut depths = Vec::new();
+        // Aave lists the wrapped token of the native asset
+        match self.aave.get_reserve_data(chain_id, price_token).await {
+            Ok(reserve) => {
+                let borrowed = reserve.total_stable_debt.saturating_add(reserve.total_variable_debt);
+                depths.push(("Aave", depth(reserve.available_liquidity.saturating_add(borrowed), borrowed)));
+            }
+            Err(e) => warn!("No Aave market depth for {:?} on chain {}: {}", asset, chain_id, e),
+        }
+        let ctoken_info = async {
+            let ctoken = self.find_ctoken_for_asset(chain_id, asset).await?;
+            self.compound.get_ctoken_info(chain_id, ctoken).await
+        };
+        match ctoken_info.await {
+            Ok(info) => {
+                let deposits = info.cash.saturating_add(info.total_borrows).saturating_sub(info.total_reserves);
+                depths.push(("Compound", depth(deposits, info.total_borrows)));
+            }
+            Err(e) => warn!("No Compound market depth for {:?} on chain {}: {}", asset, chain_id, e),
+        }
+        depths
+    }
+
+    /// Compound market of an asset, the zero address standing for the native asset of cETH
+    async fn find_ctoken_for_asset(&self, chain_id: u64, asset: Address) -> Result<Address> {
+        let markets = self.compound.markets().remove(&chain_id).unwrap_or_default();
+        for ctoken in markets {
+            if self.compound.get_ctoken_info(chain_id, ctoken).await?.underlying_address == asset {
+                return Ok(ctoken);
+            }
+        }
+        Err(anyhow::anyhow!("No Compound market for {:?} on chain {}", asset, chain_id))
+    }
+
+    /// Plan collateral placement across Aave and Compound and the transactions to reach it
+    #[instrument(skip_all, fields(chain_id = request.chain_id, wallet = ?request.user))]
+    pub async fn optimize_collateral(&self, request: CollateralOptimizationRequest) -> Result<CollateralPlan> {
+        if !request.min_health_factor.is_finite() || request.min_health_factor < 1.0 {
+            return Err(anyhow::anyhow!("Minimum health factor must be at least 1.0"));
+        }
+
+        let chain_id = request.chain_id;
+        let mut priced = Vec::new();
+        let mut warnings = Vec::new();
+
+        for asset in request.assets {
+            let price_usd = match asset.price_usd {
+                Some(price) => price,
+                None => self.price_feeds.get_price(chain_id, pricing_address(chain_id, asset.token)).await
+                    .map_err(|e| anyhow::anyhow!("No price for {}: {}", asset.symbol, e))?
+                    .price_usd,
+            };
+
+            let aave = match asset.aave {
+                Some(params) => Some(params),
+                None => match self.aave.get_reserve_data(chain_id, asset.token).await {
+                    Ok(reserve) => reserve.usage_as_collateral_enabled.then(|| MarketParams {
+                        ltv: reserve.ltv as f64 / 10_000.0,
+                        liquidation_threshold: reserve.liquidation_threshold as f64 / 10_000.0,
+                    }),
+                    Err(e) => {
+                        warnings.push(format!("Aave parameters for {} unavailable: {}", asset.symbol, e));
+                        None
+                    }
+                },
+            };
+
+            // Compound v2 liquidates at the collateral factor, so it is both LTV and threshold
+            let compound = match (asset.compound, asset.ctoken) {
+                (Some(params), _) => Some(params),
+                (None, Some(ctoken)) => match self.compound.get_ctoken_info(chain_id, ctoken).await {
+                    Ok(info) => {
+                        let collateral_factor = Self::to_token_units(info.collateral_factor, 18);
+                        (collateral_factor > 0.0).then_some(MarketParams {
+                            ltv: collateral_factor,
+                            liquidation_threshold: collateral_factor,
+                        })
+                    }
+                    Err(e) => {
+                        warnings.push(format!("Compound parameters for {} unavailable: {}", asset.symbol, e));
+                        None
+                    }
+                },
+                (None, None) => None,
+            };
+
+            priced.push(PricedCollateral { asset, price_usd, markets: [aave, compound] });
+        }
+
+        let optimizer = CollateralOptimizer::new(request.min_health_factor);
+        let mut plan = optimizer.optimize(
+            &priced,
+            [request.aave_debt_usd, request.compound_debt_usd],
+            request.objective,
+        );
+        plan.warnings.extend(warnings);
+        plan.transactions = self.collateral_transactions(chain_id, request.user, &priced, &plan.moves).await?;
+        self.transactions.record_built_all(chain_id, Some(request.user), "defi:collateral", &plan.transactions).await;
+
+        Ok(plan)
+    }
+
+    /// Withdraw and supply transactions carrying out collateral moves in order
+    async fn collateral_transactions(
+        &self,
+        chain_id: u64,
+        user: Address,
+        assets: &[PricedCollateral],
+        moves: &[CollateralMove],
+    ) -> Result<Vec<TransactionRequest>> {
+        let mut transactions = Vec::new();
+        let mut entered_markets = Vec::new();
+
+        for step in moves {
+            let amount = step.amount.raw;
+            let ctoken = || {
+                assets.iter()
+                    .find(|asset| asset.asset.token == step.token)
+                    .and_then(|asset| asset.asset.ctoken)
+                    .ok_or_else(|| anyhow::anyhow!("{} has no cToken for Compound", step.symbol))
+            };
+
+            match step.from {
+                Some(LendingMarket::Aave) => transactions.push(self.aave.withdraw(chain_id, step.token, amount, user).await?),
+                Some(LendingMarket::Compound) => {
+                    transactions.push(self.compound.redeem_underlying(chain_id, ctoken()?, amount).await?);
+                }
+                None => {}
+            }
+
+            match step.to {
+                Some(LendingMarket::Aave) => transactions.push(self.aave.supply(chain_id, step.token, amount, user, 0).await?),
+                Some(LendingMarket::Compound) => {
+                    let ctoken = ctoken()?;
+                    transactions.push(self.compound.supply(chain_id, ctoken, amount).await?);
+                    // Supplied cTokens only count as collateral once their market is entered
+                    if !entered_markets.contains(&ctoken) {
+                        transactions.push(self.compound.enter_markets(chain_id, vec![ctoken]).await?);
+                        entered_markets.push(ctoken);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        Ok(transactions)
+    }
+
+    /// Plan a full exit of the user's farms, LP and lending positions into a stablecoin
+    #[instrument(skip_all, fields(chain_id = request.chain_id, wallet = ?user))]
+    pub async fn plan_closeout(&self, user: Address, request: CloseoutRequest) -> Result<CloseoutPlan> {
+        if request.stablecoin.is_zero() {
+            return Err(anyhow::anyhow!("Close-out needs an ERC20 stablecoin"));
+        }
+        if !(request.max_slippage_percentage > 0.0 && request.max_slippage_percentage <= 50.0) {
+            return Err(anyhow::anyhow!("Maximum slippage must be above 0% and at most 50%"));
+        }
+
+        let chain_id = request.chain_id;
+        let mut draft = CloseoutDraft::default();
+
+        self.closeout_liquidity(user, &request, &mut draft).await?;
+        let exits = self.lending_exits(chain_id, user).await?;
+        let withdrawn = self.closeout_debts(user, &request, &exits, &mut draft).await?;
+
+        for (exit, _) in exits.iter().zip(&withdrawn).filter(|(exit, withdrawn)| !**withdrawn && !exit.supplied.is_zero()) {
+            // uint max withdraws the whole balance including interest accrued until execution
+            let transaction = match exit.ctoken {
+                Some((ctoken, ctoken_balance)) => self.compound.redeem(chain_id, ctoken, ctoken_balance).await?,
+                None => self.aave.withdraw(chain_id, exit.asset, U256::MAX, user).await?,
+            };
+            draft.ledger.credit(exit.asset, exit.supplied);
+            draft.steps.push(CloseoutStep::new(
+                CloseoutAction::Withdraw { market: exit.market, asset: exit.asset, amount: exit.supplied },
+                vec![transaction],
+                WITHDRAW_GAS,
+            ));
+        }
+
+        let transactions: Vec<TransactionRequest> = draft.steps.iter()
+            .flat_map(|step| step.transactions.iter().cloned())
+            .collect();
+        let records = self.transactions.record_built_all(chain_id, Some(user), "defi:closeout", &transactions).await;
+        // A swap step's quote goes with its last transaction, the swap following any approval
+        let mut step_records = records.iter();
+        for step in &draft.steps {
+            let record = step_records.by_ref().take(step.transactions.len()).last();
+            if let (CloseoutAction::Swap { token_in, token_out, amount_in, expected_output, dex, .. }, Some(record)) = (&step.action, record) {
+                let quote = SwapQuote {
+                    token_in: *token_in,
+                    token_out: *token_out,
+                    amount_in: *amount_in,
+                    expected_output: *expected_output,
+                    dex: dex.parse().ok(),
+                };
+                self.transactions.quote_swaps(&record.id, vec![quote]).await?;
+            }
+        }
+
+        let native = pricing_address(chain_id, Address::zero());
+        let mut price_tokens: Vec<Address> = draft.ledger.proceeds().iter()
+            .map(|(token, _)| pricing_address(chain_id, *token))
+            .chain([native, request.stablecoin])
+            .collect();
+        price_tokens.sort();
+        price_tokens.dedup();
+        let prices = self.price_feeds.get_prices(chain_id, &price_tokens).await.unwrap_or_else(|e| {
+            draft.warnings.push(format!("Prices unavailable, costs are incomplete: {}", e));
+            HashMap::new()
+        });
+        let price = |token: Address| prices.get(&pricing_address(chain_id, token)).map(|price| price.price_usd);
+
+        // Swaps come last, they spend everything the earlier steps released
+        for (token, amount) in draft.ledger.proceeds() {
+            if token == request.stablecoin {
+                continue;
+            }
+            let settings = SlippageSettings {
+                max_slippage_percentage: request.max_slippage_percentage,
+                ..SlippageSettings::default()
+            };
+            let swap = match self.dex_manager.execute_optimal_swap(chain_id, token, request.stablecoin, amount, user, Some(settings)).await {
+                Ok(swap) => swap,
+                Err(e) => {
+                    draft.warnings.push(format!("No route swaps {:?} into the stablecoin, it stays in the wallet: {}", token, e));
+                    continue;
+                }
+            };
+            match (price(token), self.underlying_decimals(chain_id, token).await) {
+                (Some(price_usd), Some(decimals)) => {
+                    let amount_usd = Self::to_token_units(amount, decimals) * price_usd;
+                    draft.price_impact_usd += amount_usd * swap.price_impact / 100.0;
+                }
+                _ => draft.warnings.push(format!("{:?} is unpriced, its price impact is not counted", token)),
+            }
+            draft.ledger.debit(token, amount);
+            draft.ledger.credit(request.stablecoin, swap.expected_output);
+            let gas_estimate = match swap.approval {
+                Some(_) => swap.gas_estimate.saturating_add(U256::from(APPROVE_GAS)),
+                None => swap.gas_estimate,
+            };
+            draft.steps.push(CloseoutStep {
+                action: CloseoutAction::Swap {
+                    token_in: token,
+                    token_out: request.stablecoin,
+                    amount_in: amount,
+                    expected_output: swap.expected_output,
+                    dex: swap.dex_used,
+                    price_impact: swap.price_impact,
+                },
+                transactions: swap.approval.map(|approval| approval.transaction).into_iter()
+                    .chain([swap.transaction])
+                    .collect(),
+                gas_estimate,
+            });
+        }
+
+        let gas_units = draft.gas_units();
+        let gas_cost_usd = match (self.chain_manager.get_gas_price(chain_id).await, price(Address::zero())) {
+            (Ok(gas_price), Some(native_price)) => {
+                Some(Self::to_token_units(gas_units.saturating_mul(gas_price), 18) * native_price)
+            }
+            _ => None,
+        };
+        let stablecoin_decimals = self.underlying_decimals(chain_id, request.stablecoin).await
+            .ok_or_else(|| anyhow::anyhow!("Decimals of the stablecoin {:?} are unknown", request.stablecoin))?;
+        let proceeds = draft.ledger.gained(request.stablecoin);
+
+        Ok(CloseoutPlan {
+            user,
+            chain_id,
+            stablecoin: request.stablecoin,
+            expected_proceeds: TokenAmount::new(proceeds, stablecoin_decimals),
+            expected_proceeds_usd: price(request.stablecoin)
+                .map(|price_usd| Self::to_token_units(proceeds, stablecoin_decimals) * price_usd),
+            costs: CloseoutCosts::new(gas_units, gas_cost_usd, draft.flash_loan_fees_usd, draft.price_impact_usd),
+            steps: draft.steps,
+            execution_id: records.first().and_then(|record| record.execution_id.clone()),
+            warnings: draft.warnings,
+        })
+    }
+
+    /// Unstake the requested farms and remove their LP along with the LP held in the wallet
+    async fn closeout_liquidity(&self, user: Address, request: &CloseoutRequest, draft: &mut CloseoutDraft) -> Result<()> {
+        let chain_id = request.chain_id;
+        let sushiswap = self.dex_manager.sushiswap();
+        let mut holdings = request.liquidity.clone();
+
+        for farm in &request.farms {
+            let position = sushiswap.get_user_position(chain_id, farm.pid, user).await?;
+            if position.amount.is_zero() {
+                draft.warnings.push(format!("Nothing is staked in farm {}", farm.pid));
+                continue;
+            }
+            if !position.pending_rewards.is_zero() {
+                draft.warnings.push(format!(
+                    "Unstaking farm {} harvests {} pending SUSHI, which stays in the wallet",
+                    farm.pid, position.pending_rewards
+                ));
+            }
+
+            let transaction = sushiswap.unstake_from_farm(chain_id, farm.pid, position.amount).await?;
+            draft.steps.push(CloseoutStep::new(
+                CloseoutAction::UnstakeFarm { pid: farm.pid, lp_amount: position.amount },
+                vec![transaction],
+                UNSTAKE_GAS,
+            ));
+
+            let same_pair = |holding: &&mut LiquidityHolding| {
+                (holding.token_a, holding.token_b) == (farm.token_a, farm.token_b)
+                    || (holding.token_a, holding.token_b) == (farm.token_b, farm.token_a)
+            };
+            match holdings.iter_mut().find(same_pair) {
+                Some(holding) => holding.liquidity = holding.liquidity.saturating_add(position.amount),
+                None => holdings.push(LiquidityHolding {
+                    token_a: farm.token_a,
+                    token_b: farm.token_b,
+                    liquidity: position.amount,
+                }),
+            }
+        }
+
+        let deadline = swap_deadline(DEFAULT_DEADLINE_MINUTES);
+        for holding in holdings.iter().filter(|holding| !holding.liquidity.is_zero()) {
+            let pair = sushiswap.get_pair_info(chain_id, holding.token_a, holding.token_b).await?;
+            let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+            let total_supply = ERC20Contract::new(pair.address, provider, chain_id).await?.total_supply().await?;
+            if total_supply.is_zero() {
+                return Err(anyhow::anyhow!("Pair {:?} has no liquidity to remove", pair.address));
+            }
+
+            // Pairs order their reserves by token address
+            let (reserve_a, reserve_b) = if holding.token_a < holding.token_b {
+                (pair.reserves.0, pair.reserves.1)
+            } else {
+                (pair.reserves.1, pair.reserves.0)
+            };
+            let expected_a = holding.liquidity * reserve_a / total_supply;
+            let expected_b = holding.liquidity * reserve_b / total_supply;
+            let transaction = sushiswap.remove_liquidity(
+                chain_id,
+                holding.token_a,
+                holding.token_b,
+                holding.liquidity,
+                with_slippage(expected_a, request.max_slippage_percentage),
+                with_slippage(expected_b, request.max_slippage_percentage),
+                user,
+                deadline,
+            ).await?;
+
+            draft.ledger.credit(holding.token_a, expected_a);
+            draft.ledger.credit(holding.token_b, expected_b);
+            draft.steps.push(CloseoutStep::new(
+                CloseoutAction::RemoveLiquidity {
+                    token_a: holding.token_a,
+                    token_b: holding.token_b,
+                    liquidity: holding.liquidity,
+                    expected_a,
+                    expected_b,
+                },
+                vec![transaction],
+                REMOVE_LIQUIDITY_GAS,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Plan repayments from the wallet's balances lifting each market's health factor to
+    /// `target_health_factor`, and a full exit of `exit_markets`: every debt repaid, then every supply withdrawn
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?user))]
+    pub async fn plan_protection(
+        &self,
+        chain_id: u64,
+        user: Address,
+        target_health_factor: f64,
+        exit_markets: &[LendingMarket],
+    ) -> Result<ProtectionPlan> {
+        if target_health_factor.is_nan() || target_health_factor <= 1.0 {
+            return Err(anyhow::anyhow!("Target health factor must be above 1"));
+        }
+        let exits = self.lending_exits(chain_id, user).await?;
+        let mut draft = CloseoutDraft::default();
+        let mut markets = Vec::new();
+        let value = |amount: U256, exit: &LendingExit| {
+            exit.price_usd.zip(exit.decimals).map(|(price_usd, decimals)| Self::to_token_units(amount, decimals) * price_usd)
+        };
+
+        for market in [LendingMarket::Aave, LendingMarket::Compound] {
+            let positions: Vec<&LendingExit> = exits.iter().filter(|exit| exit.market == market).collect();
+            if positions.is_empty() {
+                continue;
+            }
+            let exiting = exit_markets.contains(&market);
+            for exit in positions.iter().filter(|exit| exit.price_usd.is_none()) {
+                draft.warnings.push(format!("{:?} on {:?} is unpriced and left out of its health factor", exit.asset, market));
+            }
+            let collateral_usd: f64 = positions.iter()
+                .filter_map(|exit| value(exit.supplied, exit).map(|usd| usd * exit.liquidation_threshold))
+                .sum();
+            let debt_usd: f64 = positions.iter().filter_map(|exit| value(exit.debt(), exit)).sum();
+            let health = |debt_usd: f64| (debt_usd > 0.0).then(|| collateral_usd / debt_usd);
+            let before = health(debt_usd);
+
+            // Debt value to repay, whatever brings the market to the target unless it is exited
+            let mut to_repay_usd = match before {
+                Some(factor) if !exiting && factor < target_health_factor => debt_usd - collateral_usd / target_health_factor,
+                _ => 0.0,
+            };
+            let mut repaid_usd = 0.0;
+            let mut fully_repaid = true;
+
+            // Largest debts first, fewer transactions for the same gain
+            let mut debts: Vec<&LendingExit> = positions.iter().copied().filter(|exit| !exit.debts.is_empty()).collect();
+            debts.sort_by(|a, b| value(b.debt(), b).unwrap_or(0.0).total_cmp(&value(a.debt(), a).unwrap_or(0.0)));
+            for exit in debts {
+                let debt = exit.debt();
+                let wanted = if exiting {
+                    debt
+                } else {
+                    match exit.price_usd.zip(exit.decimals) {
+                        Some((price_usd, decimals)) if to_repay_usd > 0.0 => protection::to_raw(to_repay_usd / price_usd, decimals)
+                            .map_or(debt, |needed| needed.min(debt)),
+                        _ => continue,
+                    }
+                };
+                if !draft.ledger.is_opened(exit.asset) {
+                    let balance = self.wallet_balance(chain_id, exit.asset, user).await?;
+                    draft.ledger.open(exit.asset, balance);
+                }
+                let amount = wanted.min(draft.ledger.balance(exit.asset));
+                if amount < debt {
+                    fully_repaid = false;
+                }
+                if amount.is_zero() {
+                    draft.warnings.push(format!("The wallet holds no {:?} to repay its {:?} debt", exit.asset, market));
+                    continue;
+                }
+
+                let mut transactions = Vec::new();
+                let mut remaining = amount;
+                for (mode, mode_debt) in &exit.debts {
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    let part = remaining.min(*mode_debt);
+                    remaining -= part;
+                    // uint max repays a whole debt including interest accrued until execution,
+                    // native repays carry the exact value instead
+                    let repay_amount = if part == *mode_debt && !exit.asset.is_zero() { U256::MAX } else { part };
+                    transactions.push(match exit.ctoken {
+                        Some((ctoken, _)) => self.compound.repay(chain_id, ctoken, repay_amount).await?,
+                        None => self.aave.repay(chain_id, exit.asset, repay_amount, *mode, user).await?,
+                    });
+                }
+                draft.ledger.debit(exit.asset, amount);
+                let repaid = value(amount, exit).unwrap_or(0.0);
+                repaid_usd += repaid;
+                to_repay_usd -= repaid;
+                let gas = REPAY_GAS * transactions.len() as u64;
+                draft.steps.push(CloseoutStep::new(
+                    CloseoutAction::Repay { market, asset: exit.asset, amount },
+                    transactions,
+                    gas,
+                ));
+            }
+
+            let after = health((debt_usd - repaid_usd).max(0.0));
+            if !exiting && after.is_some_and(|factor| factor < target_health_factor) && before != after {
+                draft.warnings.push(format!(
+                    "The wallet cannot repay enough to lift its {:?} health factor to {}, it reaches {:.2}",
+                    market, target_health_factor, after.unwrap_or_default(),
+                ));
+            }
+            if exiting {
+                if fully_repaid {
+                    for exit in positions.iter().filter(|exit| !exit.supplied.is_zero()) {
+                        // uint max withdraws the whole balance including interest accrued until execution
+                        let transaction = match exit.ctoken {
+                            Some((ctoken, ctoken_balance)) => self.compound.redeem(chain_id, ctoken, ctoken_balance).await?,
+                            None => self.aave.withdraw(chain_id, exit.asset, U256::MAX, user).await?,
+                        };
+                        draft.ledger.credit(exit.asset, exit.supplied);
+                        draft.steps.push(CloseoutStep::new(
+                            CloseoutAction::Withdraw { market, asset: exit.asset, amount: exit.supplied },
+                            vec![transaction],
+                            WITHDRAW_GAS,
+                        ));
+                    }
+                } else {
+                    draft.warnings.push(format!(
+                        "Debt the wallet cannot repay remains on {:?}, withdraw its collateral once that is repaid",
+                        market,
+                    ));
+                }
+            }
+            markets.push(MarketHealth { market, before, after: if exiting && fully_repaid { None } else { after }, exiting });
+        }
+
+        Ok(ProtectionPlan {
+            chain_id,
+            user,
+            target_health_factor,
+            markets,
+            steps: draft.steps,
+            warnings: draft.warnings,
+        })
+    }
+
+    /// Open Aave and Compound positions of the user
+    async fn lending_exits(&self, chain_id: u64, user: Address) -> Result<Vec<LendingExit>> {
+        let portfolio = self.get_portfolio_overview(chain_id, user).await?;
+        // Valuations list the Aave positions first, then Compound, one per position
+        let (aave_values, compound_values) = portfolio.positions_usd.split_at(portfolio.aave_positions.len());
+
+        let aave = portfolio.aave_positions.iter().zip(aave_values).map(|(position, value)| LendingExit {
+            market: LendingMarket::Aave,
+            asset: value.asset,
+            decimals: value.decimals,
+            price_usd: value.price_usd,
+            supplied: position.supplied_amount,
+            debts: [(1, position.borrowed_amount_stable), (2, position.borrowed_amount_variable)]
+                .into_iter()
+                .filter(|(_, debt)| !debt.is_zero())
+                .collect(),
+            ctoken: None,
+            liquidation_threshold: value.liquidation_threshold,
+        });
+        let compound = portfolio.compound_positions.iter().zip(compound_values).map(|(position, value)| LendingExit {
+            market: LendingMarket::Compound,
+            asset: value.asset,
+            decimals: value.decimals,
+            price_usd: value.price_usd,
+            supplied: value.supplied_amount,
+            debts: (!position.borrow_balance.is_zero())
+                .then_some((0, position.borrow_balance))
+                .into_iter()
+                .collect(),
+            ctoken: Some((position.ctoken, position.supply_balance)),
+            liquidation_threshold: value.liquidation_threshold,
+        });
+
+        Ok(aave.chain(compound)
+            .filter(|exit| !exit.supplied.is_zero() || !exit.debts.is_empty())
+            .collect())
+    }
+
+    /// Repay every debt, from the wallet where it holds enough and through one flash loan
+    /// otherwise; returns which positions the flash loan already withdrew
+    async fn closeout_debts(
+        &self,
+        user: Address,
+        request: &CloseoutRequest,
+        exits: &[LendingExit],
+        draft: &mut CloseoutDraft,
+    ) -> Result<Vec<bool>> {
+        let chain_id = request.chain_id;
+        let mut withdrawn = vec![false; exits.len()];
+        let mut shortfalls = Vec::new();
+
+        for exit in exits.iter().filter(|exit| !exit.debts.is_empty()) {
+            if !draft.ledger.is_opened(exit.asset) {
+                let balance = self.wallet_balance(chain_id, exit.asset, user).await?;
+                draft.ledger.open(exit.asset, balance);
+            }
+            let debt = exit.debt();
+            let available = draft.ledger.balance(exit.asset);
+            draft.ledger.debit(exit.asset, debt);
+            if available < debt {
+                shortfalls.push((exit, debt - available));
+                continue;
+            }
+
+            let mut transactions = Vec::new();
+            for (mode, amount) in &exit.debts {
+                // uint max repays the debt including interest accrued until execution,
+                // native repays carry the exact value instead
+                let repay_amount = if exit.asset.is_zero() { *amount } else { U256::MAX };
+                transactions.push(match exit.ctoken {
+                    Some((ctoken, _)) => self.compound.repay(chain_id, ctoken, repay_amount).await?,
+                    None => self.aave.repay(chain_id, exit.asset, repay_amount, *mode, user).await?,
+                });
+            }
+            let gas = REPAY_GAS * transactions.len() as u64;
+            draft.steps.push(CloseoutStep::new(
+                CloseoutAction::Repay { market: exit.market, asset: exit.asset, amount: debt },
+                transactions,
+                gas,
+            ));
+        }
+
+        if shortfalls.is_empty() {
+            return Ok(withdrawn);
+        }
+        let receiver = request.flash_loan_receiver.ok_or_else(|| {
+            anyhow::anyhow!("The wallet cannot repay every debt, a flash_loan_receiver contract is required to repay with a flash loan")
+        })?;
+
+        let mut operations = Vec::new();
+        let mut loans = Vec::new();
+        for (exit, amount) in &shortfalls {
+            for (mode, debt) in &exit.debts {
+                operations.push(FlashLoanOperation::Repay {
+                    protocol: exit.protocol(),
+                    asset: exit.asset,
+                    amount: *debt,
+                    interest_rate_mode: *mode,
+                });
+            }
+            loans.push(FlashLoanLeg { asset: exit.asset, amount: *amount, premium: flash_loan_premium(*amount) });
+        }
+
+        // With the debts gone every collateral is free to leave in the same transaction
+        for (i, exit) in exits.iter().enumerate().filter(|(_, exit)| !exit.supplied.is_zero()) {
+            operations.push(FlashLoanOperation::Withdraw { protocol: exit.protocol(), asset: exit.asset, amount: exit.supplied });
+            draft.ledger.credit(exit.asset, exit.supplied);
+            withdrawn[i] = true;
+        }
+
+        // Sell collateral for whatever the withdrawals do not return of each loan
+        for ((exit, _), leg) in shortfalls.iter().zip(&loans) {
+            let owed = leg.amount + leg.premium;
+            match exit.price_usd.zip(exit.decimals) {
+                Some((price_usd, decimals)) => draft.flash_loan_fees_usd += Self::to_token_units(leg.premium, decimals) * price_usd,
+                None => draft.warnings.push(format!("Flash loan premium in {:?} is unpriced and not counted", exit.asset)),
+            }
+
+            let missing = owed.saturating_sub(draft.ledger.balance(exit.asset));
+            if !missing.is_zero() {
+                let (owed_price, owed_decimals) = exit.price_usd.zip(exit.decimals)
+                    .ok_or_else(|| anyhow::anyhow!("Cannot size the collateral sale for unpriced {:?}", exit.asset))?;
+                // Sell the collateral the wallet holds the most value of
+                let collateral = exits.iter()
+                    .filter(|candidate| candidate.asset != exit.asset && !candidate.supplied.is_zero())
+                    .filter_map(|candidate| candidate.price_usd.zip(candidate.decimals).map(|priced| (candidate, priced)))
+                    .max_by(|(a, a_priced), (b, b_priced)| {
+                        let value = |candidate: &LendingExit, (price, decimals): (f64, u8)| {
+                            Self::to_token_units(draft.ledger.balance(candidate.asset), decimals) * price
+                        };
+                        value(a, *a_priced).total_cmp(&value(b, *b_priced))
+                    });
+                let amount_in = collateral.and_then(|(candidate, (price, decimals))| collateral_to_sell(
+                    (missing, owed_decimals, owed_price),
+                    (decimals, price),
+                    request.max_slippage_percentage,
+                ).map(|amount_in| (candidate, amount_in)));
+                let Some((candidate, amount_in)) = amount_in.filter(|(candidate, amount_in)| *amount_in <= draft.ledger.balance(candidate.asset)) else {
+                    return Err(anyhow::anyhow!("Collateral cannot cover the flash loan of {:?}", exit.asset));
+                };
+
+                operations.push(FlashLoanOperation::Swap {
+                    dex: "SushiSwap".to_string(),
+                    token_in: candidate.asset,
+                    token_out: exit.asset,
+                    amount_in,
+                    min_amount_out: missing,
+                });
+                draft.ledger.debit(candidate.asset, amount_in);
+                draft.ledger.credit(exit.asset, missing);
+            }
+            draft.ledger.debit(exit.asset, owed);
+        }
+
+        let gas = FLASH_LOAN_GAS + operations.iter()
+            .map(|operation| match operation {
+                FlashLoanOperation::Repay { .. } => REPAY_GAS,
+                FlashLoanOperation::Withdraw { .. } => WITHDRAW_GAS,
+                _ => FLASH_SWAP_GAS,
+            })
+            .sum::<u64>();
+        let transaction = self.aave.flash_loan(chain_id, FlashLoanParams {
+            assets: loans.iter().map(|leg| leg.asset).collect(),
+            amounts: loans.iter().map(|leg| leg.amount).collect(),
+            modes: vec![0; loans.len()],
+            receiver,
+            params: Bytes::from(serde_json::to_vec(&operations)?),
+            referral_code: 0,
+        }).await?;
+        draft.steps.push(CloseoutStep::new(
+            CloseoutAction::FlashLoanRepay { loans, operations },
+            vec![transaction],
+            gas,
+        ));
+
+        Ok(withdrawn)
+    }
+
+    async fn wallet_balance(&self, chain_id: u64, token: Address, user: Address) -> Result<U256> {
+        if token.is_zero() {
+            return self.chain_manager.get_balance(chain_id, user).await;
+        }
+        let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+        ERC20Contract::new(token, provider, chain_id).await?.balance_of(user).await
+    }
+
+    /// Charge tracked transactions of the user to one of their strategies, `None` if they have no such strategy
+    #[instrument(skip_all, fields(wallet = ?user, strategy_id))]
+    pub async fn link_strategy_transactions(
+        &self,
+        user: Address,
+        strategy_id: &str,
+        transaction_ids: &[String],
+    ) -> Result<Option<ActiveStrategy>> {
+        let Some(strategy) = self.strategies.get(user, strategy_id).await else {
+            return Ok(None);
+        };
+        for id in transaction_ids {
+            let record = self.transactions.get(id).await
+                .ok_or_else(|| anyhow::anyhow!("Unknown transaction record {}", id))?;
+            if record.user != Some(user) {
+                return Err(anyhow::anyhow!("Transaction record {} is not a transaction of {:?}", id, user));
+            }
+            if strategy.chain_id.is_some_and(|chain_id| chain_id != record.chain_id) {
+                return Err(anyhow::anyhow!("Transaction record {} is on chain {}, not the strategy's", id, record.chain_id));
+            }
+        }
+
+        Ok(self.strategies.link_transactions(user, strategy_id, transaction_ids).await)
+    }
+
+    /// Gas spent by a strategy, `None` if the user has no such strategy
+    #[instrument(skip_all, fields(wallet = ?user, strategy_id))]
+    pub async fn strategy_gas_report(&self, user: Address, strategy_id: &str) -> Option<StrategyGasReport> {
+        let strategy = self.strategies.get(user, strategy_id).await?;
+        Some(self.account_strategy_gas(&strategy).await)
+    }
+
+    /// Gas spent by the user's open strategies on a chain that have transactions linked
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?user))]
+    pub async fn strategy_gas_reports(&self, chain_id: u64, user: Address) -> Vec<StrategyGasReport> {
+        let mut reports = Vec::new();
+        for strategy in self.strategies.list(user, ArchiveFilter::Active).await {
+            if strategy.chain_id == Some(chain_id) && !strategy.transaction_ids.is_empty() {
+                reports.push(self.account_strategy_gas(&strategy).await);
+            }
+        }
+        reports
+    }
+
+    /// Gas of the transactions that settled the strategy's linked ones, with pending receipts refreshed
+    #[instrument(skip_all, fields(chain_id = strategy.chain_id, strategy_id = %strategy.strategy_id))]
+    async fn account_strategy_gas(&self, strategy: &ActiveStrategy) -> StrategyGasReport {
+        let mut records = Vec::new();
+        for id in &strategy.transaction_ids {
+            // Records past the tracker's retention limit are gone
+            let Some(mut record) = self.transactions.get(id).await else {
+                continue;
+            };
+            // A sped-up or cancelled transaction is paid for by its replacement
+            while let Some(replacement) = record.replaced_by {
+                match self.transactions.get_by_hash(replacement).await {
+                    Some(next) => record = next,
+                    None => break,
+                }
+            }
+            if let (TransactionStatus::Pending, Some(hash)) = (record.status, record.hash) {
+                match self.transactions.refresh(hash).await {
+                    Ok(refreshed) => record = refreshed,
+                    Err(e) => warn!("Failed to refresh transaction {:?} of strategy {}: {}", hash, strategy.strategy_id, e),
+                }
+            }
+            records.push(record);
+        }
+
+        let chain_id = strategy.chain_id.or(records.first().map(|record| record.chain_id));
+        let native_price_usd = match chain_id {
+            Some(chain_id) => match self.price_feeds.get_price(chain_id, pricing_address(chain_id, Address::zero())).await {
+                Ok(price) => Some(price.price_usd),
+                Err(e) => {
+                    warn!("No native token price on chain {} for strategy gas: {}", chain_id, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        StrategyGasReport::build(strategy, &records, native_price_usd)
+    }
+
+    /// Address-registry entries of the lending protocols, Maker, Curve and Convex
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        let mut deployments = self.aave.deployments();
+        deployments.extend(self.compound.deployments());
+        deployments.extend(self.maker.deployments());
+        deployments.extend(self.convex.deployments());
+        deployments
+    }
+
+    pub fn aave(&self) -> &AaveManager {
+        &self.aave
+    }
+
+    pub fn compound(&self) -> &CompoundManager {
         &self.compound
     }
 
+    pub fn maker(&self) -> &MakerManager {
+        &self.maker
+    }
+
+    pub fn convex(&self) -> &ConvexManager {
+        &self.convex
+    }
+
     pub fn flash_loans(&self) -> &FlashLoanManager {
         &self.flash_loans
     }
 
+    pub fn strategies(&self) -> &Arc<StrategyRegistry> {
+        &self.strategies
+    }
+
+    pub fn templates(&self) -> &Arc<StrategyTemplateLibrary> {
+        &self.templates
+    }
+
+    pub fn bridge(&self) -> Option<&Arc<BridgeManager>> {
+        self.bridge.as_ref()
+    }
+
     pub fn dex_manager(&self) -> &Arc<DexManager> {
         &self.dex_manager
     }
@@ -636,9 +2909,10 @@ impl DefiManager {
         match protocol.as_str() {
             "aave" => {
                 // Use Aave manager
-                let _tx = self.aave.supply_asset(chain_id, asset, amount, user).await?;
-                // Return a mock transaction hash since TransactionRequest doesn't have .hash()
-                Ok(format!("0x{:x}", rand::random::<u64>()))
+                let tx = self.aave.supply_asset(chain_id, asset, amount, user).await?;
+                // The id lets the caller link the hash once they broadcast it
+                let record = self.transactions.record_built(chain_id, Some(user), "defi:supply", &tx).await;
+                Ok(record.id)
             }
             _ => {
                 // Placeholder implementation
@@ -659,9 +2933,10 @@ impl DefiManager {
         match protocol.as_str() {
             "aave" => {
                 // Use Aave manager
-                let _tx = self.aave.withdraw_asset(chain_id, asset, amount, user).await?;
-                // Return a mock transaction hash since TransactionRequest doesn't have .hash()
-                Ok(format!("0x{:x}", rand::random::<u64>()))
+                let tx = self.aave.withdraw_asset(chain_id, asset, amount, user).await?;
+                // The id lets the caller link the hash once they broadcast it
+                let record = self.transactions.record_built(chain_id, Some(user), "defi:withdraw", &tx).await;
+                Ok(record.id)
             }
             _ => {
                 // Placeholder implementation
@@ -682,9 +2957,10 @@ impl DefiManager {
         match protocol.as_str() {
             "aave" => {
                 // Use Aave manager for borrowing
-                let _tx = self.aave.borrow_asset(chain_id, asset, amount, user).await?;
-                // Return a mock transaction hash since TransactionRequest doesn't have .hash()
-                Ok(format!("0x{:x}", rand::random::<u64>()))
+                let tx = self.aave.borrow_asset(chain_id, asset, amount, user).await?;
+                // The id lets the caller link the hash once they broadcast it
+                let record = self.transactions.record_built(chain_id, Some(user), "defi:borrow", &tx).await;
+                Ok(record.id)
             }
             _ => {
                 // Placeholder implementation
@@ -705,9 +2981,10 @@ impl DefiManager {
         match protocol.as_str() {
             "aave" => {
                 // Use Aave manager for repayment
-                let _tx = self.aave.repay_asset(chain_id, asset, amount, user).await?;
-                // Return a mock transaction hash since TransactionRequest doesn't have .hash()
-                Ok(format!("0x{:x}", rand::random::<u64>()))
+                let tx = self.aave.repay_asset(chain_id, asset, amount, user).await?;
+                // The id lets the caller link the hash once they broadcast it
+                let record = self.transactions.record_built(chain_id, Some(user), "defi:repay", &tx).await;
+                Ok(record.id)
             }
             _ => {
                 // Placeholder implementation
@@ -0,0 +1,38 @@
+// Repayments and withdrawals protecting a wallet's lending positions during an emergency
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use super::closeout::CloseoutStep;
+use super::collateral_optimizer::LendingMarket;
+
+/// Health factor of the wallet's positions on one market before and after the plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketHealth {
+    pub market: LendingMarket,
+    /// `None` without priced debt
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+    /// The plan withdraws everything from this market
+    pub exiting: bool,
+}
+
+/// Protective steps for one wallet on one chain, to be executed in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtectionPlan {
+    pub chain_id: u64,
+    pub user: Address,
+    pub target_health_factor: f64,
+    pub markets: Vec<MarketHealth>,
+    pub steps: Vec<CloseoutStep>,
+    pub warnings: Vec<String>,
+}
+
+/// Raw amount of `units` whole tokens, `None` for a negative or unrepresentable amount
+pub fn to_raw(units: f64, decimals: u8) -> Option<U256> {
+    if !units.is_finite() || units < 0.0 {
+        return None;
+    }
+    ethers::utils::parse_units(format!("{:.*}", decimals as usize, units), decimals as u32)
+        .ok()
+        .map(Into::into)
+}
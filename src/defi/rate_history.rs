@@ -0,0 +1,306 @@
+// Aave and Compound supply and borrow rates sampled over time, with their trailing averages
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::aave::ReserveData;
+use super::compound::CTokenInfo;
+use super::DefiManager;
+use crate::analytics::price_feeds::pricing_address;
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `rate_history_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/rate_history.json";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(3600);
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+/// Oldest samples are dropped beyond this many, whatever their age
+const MAX_SAMPLES: usize = 250_000;
+/// Approximate Ethereum blocks per year Compound's per-block rates compound over
+pub const COMPOUND_BLOCKS_PER_YEAR: f64 = 2_102_400.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateSample {
+    pub chain_id: u64,
+    /// `aave` or `compound`
+    pub protocol: String,
+    /// Underlying token supplied and borrowed, the wrapped token for cETH
+    pub asset: Address,
+    pub symbol: String,
+    /// Contract holding the deposits: the aToken or cToken
+    pub market: Address,
+    /// In percent
+    pub supply_apy: f64,
+    /// Variable rate, in percent
+    pub borrow_apy: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl RateSample {
+    pub fn aave(chain_id: u64, reserve: &ReserveData) -> Self {
+        Self {
+            chain_id,
+            protocol: "aave".to_string(),
+            asset: reserve.asset,
+            symbol: reserve.symbol.clone(),
+            market: reserve.a_token_address,
+            supply_apy: aave_apy(reserve.liquidity_rate.as_u128()),
+            borrow_apy: aave_apy(reserve.variable_borrow_rate.as_u128()),
+            recorded_at: Utc::now(),
+        }
+    }
+
+    pub fn compound(chain_id: u64, info: &CTokenInfo) -> Self {
+        Self {
+            chain_id,
+            protocol: "compound".to_string(),
+            asset: pricing_address(chain_id, info.underlying_address),
+            symbol: info.symbol.clone(),
+            market: info.ctoken_address,
+            supply_apy: compound_apy(info.supply_rate_per_block.as_u128()),
+            borrow_apy: compound_apy(info.borrow_rate_per_block.as_u128()),
+            recorded_at: Utc::now(),
+        }
+    }
+
+    fn is_market(&self, chain_id: u64, protocol: &str, asset: Address) -> bool {
+        self.chain_id == chain_id && self.asset == asset && self.protocol.eq_ignore_ascii_case(protocol)
+    }
+}
+
+/// Aave rates are per year in ray
+pub fn aave_apy(rate: u128) -> f64 {
+    rate as f64 / 1e27 * 100.0
+}
+
+/// Compound rates are per block, scaled by 1e18
+pub fn compound_apy(rate_per_block: u128) -> f64 {
+    rate_per_block as f64 * COMPOUND_BLOCKS_PER_YEAR / 1e18 * 100.0
+}
+
+/// Samples of the history endpoint; every bound is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RateHistoryFilter {
+    pub chain_id: Option<u64>,
+    pub protocol: Option<String>,
+    pub asset: Option<Address>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl RateHistoryFilter {
+    fn matches_market(&self, sample: &RateSample) -> bool {
+        self.chain_id.is_none_or(|chain_id| sample.chain_id == chain_id)
+            && self.protocol.as_ref().is_none_or(|protocol| sample.protocol.eq_ignore_ascii_case(protocol))
+            && self.asset.is_none_or(|asset| sample.asset == asset)
+    }
+
+    fn matches(&self, sample: &RateSample) -> bool {
+        self.matches_market(sample)
+            && self.from.is_none_or(|from| sample.recorded_at >= from)
+            && self.to.is_none_or(|to| sample.recorded_at < to)
+    }
+}
+
+/// Trailing averages of one market's rates, in percent; `None` without samples in the window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateTrend {
+    pub chain_id: u64,
+    pub protocol: String,
+    pub asset: Address,
+    pub symbol: String,
+    pub latest_supply_apy: f64,
+    pub latest_borrow_apy: f64,
+    pub supply_apy_7d: Option<f64>,
+    pub supply_apy_30d: Option<f64>,
+    pub borrow_apy_7d: Option<f64>,
+    pub borrow_apy_30d: Option<f64>,
+    /// 7-day less 30-day average supply APY, positive while rates are rising
+    pub supply_trend: Option<f64>,
+    pub samples_30d: usize,
+    pub last_sampled: DateTime<Utc>,
+}
+
+impl RateTrend {
+    /// Supply APY the optimizer ranks by: the 7-day average, else the 30-day one
+    pub fn trailing_supply_apy(&self) -> Option<f64> {
+        self.supply_apy_7d.or(self.supply_apy_30d)
+    }
+
+    pub fn trailing_borrow_apy(&self) -> Option<f64> {
+        self.borrow_apy_7d.or(self.borrow_apy_30d)
+    }
+
+    /// Trend of a market's samples, oldest first; `None` without any
+    fn from_samples(samples: &[&RateSample], now: DateTime<Utc>) -> Option<Self> {
+        let latest = samples.last()?;
+        let window = |days: i64| {
+            let since = now - ChronoDuration::days(days);
+            samples.iter().copied().filter(|sample| sample.recorded_at >= since).collect::<Vec<_>>()
+        };
+        let average = |samples: &[&RateSample], rate: fn(&RateSample) -> f64| {
+            (!samples.is_empty()).then(|| samples.iter().map(|sample| rate(sample)).sum::<f64>() / samples.len() as f64)
+        };
+        let (week, month) = (window(7), window(30));
+        let supply_apy_7d = average(&week, |sample| sample.supply_apy);
+        let supply_apy_30d = average(&month, |sample| sample.supply_apy);
+
+        Some(Self {
+            chain_id: latest.chain_id,
+            protocol: latest.protocol.clone(),
+            asset: latest.asset,
+            symbol: latest.symbol.clone(),
+            latest_supply_apy: latest.supply_apy,
+            latest_borrow_apy: latest.borrow_apy,
+            supply_apy_7d,
+            supply_apy_30d,
+            borrow_apy_7d: average(&week, |sample| sample.borrow_apy),
+            borrow_apy_30d: average(&month, |sample| sample.borrow_apy),
+            supply_trend: supply_apy_7d.zip(supply_apy_30d).map(|(week, month)| week - month),
+            samples_30d: month.len(),
+            last_sampled: latest.recorded_at,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateHistoryReport {
+    /// Oldest first
+    pub samples: Vec<RateSample>,
+    /// One per market matching the filter, whatever the time bounds
+    pub trends: Vec<RateTrend>,
+}
+
+/// Sampled lending rates of the last `rate_history_retention_days`
+pub struct RateHistory {
+    /// JSON file holding the samples, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    retention: ChronoDuration,
+    samples: RwLock<Vec<RateSample>>,
+}
+
+impl RateHistory {
+    pub async fn new(store_path: Option<PathBuf>, retention: ChronoDuration) -> Result<Self> {
+        let samples: Vec<RateSample> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !samples.is_empty()) {
+            info!("Loaded {} lending rate samples from {}", samples.len(), path.display());
+        }
+
+        Ok(Self { store_path, retention, samples: RwLock::new(samples) })
+    }
+
+    /// History persisting to `rate_history_store_path`, an empty path keeps samples in memory
+    pub async fn from_config(config: &config::Config) -> Result<Self> {
+        let path = config
+            .get_string("rate_history_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let retention_days = config
+            .get_int("rate_history_retention_days")
+            .ok()
+            .filter(|days| *days > 0)
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        Self::new((!path.is_empty()).then(|| PathBuf::from(path)), ChronoDuration::days(retention_days)).await
+    }
+
+    /// Append a round of samples, drop those past retention and persist
+    pub async fn record(&self, new_samples: Vec<RateSample>) {
+        if new_samples.is_empty() {
+            return;
+        }
+        let mut samples = self.samples.write().await;
+        samples.extend(new_samples);
+        let cutoff = Utc::now() - self.retention;
+        samples.retain(|sample| sample.recorded_at >= cutoff);
+        if samples.len() > MAX_SAMPLES {
+            let excess = samples.len() - MAX_SAMPLES;
+            samples.drain(..excess);
+        }
+        if let Some(path) = &self.store_path {
+            if let Err(e) = write_store(path, &*samples).await {
+                warn!("Failed to persist lending rate samples to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    pub async fn query(&self, filter: &RateHistoryFilter) -> RateHistoryReport {
+        let samples = self.samples.read().await;
+        let now = Utc::now();
+
+        let mut markets: BTreeMap<(u64, String, Address), Vec<&RateSample>> = BTreeMap::new();
+        for sample in samples.iter().filter(|sample| filter.matches_market(sample)) {
+            markets.entry((sample.chain_id, sample.protocol.to_lowercase(), sample.asset)).or_default().push(sample);
+        }
+
+        RateHistoryReport {
+            samples: samples.iter().filter(|sample| filter.matches(sample)).cloned().collect(),
+            trends: markets.values().filter_map(|market| RateTrend::from_samples(market, now)).collect(),
+        }
+    }
+
+    /// Trailing averages of one market, `None` before it was first sampled
+    pub async fn trend(&self, chain_id: u64, protocol: &str, asset: Address) -> Option<RateTrend> {
+        let samples = self.samples.read().await;
+        let market: Vec<&RateSample> = samples.iter().filter(|sample| sample.is_market(chain_id, protocol, asset)).collect();
+        RateTrend::from_samples(&market, Utc::now())
+    }
+}
+
+/// Samples every Aave reserve and Compound market into the rate history
+pub struct RateHistoryCollector {
+    defi_manager: Arc<DefiManager>,
+    history: Arc<RateHistory>,
+    poll_interval: Duration,
+}
+
+impl RateHistoryCollector {
+    pub fn new(defi_manager: Arc<DefiManager>, history: Arc<RateHistory>, poll_interval: Duration) -> Self {
+        Self { defi_manager, history, poll_interval }
+    }
+
+    /// Collector sampling every `rate_history_poll_interval_secs`
+    pub fn from_config(config: &config::Config, defi_manager: Arc<DefiManager>, history: Arc<RateHistory>) -> Self {
+        let poll_interval = config
+            .get_int("rate_history_poll_interval_secs")
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        Self::new(defi_manager, history, poll_interval)
+    }
+
+    pub fn history(&self) -> &Arc<RateHistory> {
+        &self.history
+    }
+
+    /// Sample lending rates in the background until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.sample().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Lending rate collector stopped");
+        })
+    }
+
+    async fn sample(&self) {
+        let samples = self.defi_manager.sample_lending_rates().await;
+        debug!("Sampled {} lending rates", samples.len());
+        self.history.record(samples).await;
+    }
+}
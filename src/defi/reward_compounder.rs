@@ -0,0 +1,270 @@
+// Scheduled compounding of lending rewards: claim, swap to the supplied asset and supply it back,
+// each round run by the strategy executor
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::strategy_executor::{ExecutionStatus, StrategyExecutionRequest, StrategyExecutor};
+use super::DefiManager;
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `reward_compounding_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/reward_compounding.json";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(300);
+/// Rounds of schedules that do not set their own interval
+const DEFAULT_INTERVAL_SECS: u64 = 86_400;
+/// Shorter rounds would mostly pay gas for dust
+const MIN_INTERVAL_SECS: u64 = 3_600;
+
+/// Compound the rewards of an owner's lending positions on a schedule
+#[derive(Debug, Clone, Deserialize)]
+pub struct RewardCompoundingRequest {
+    pub owner: Address,
+    pub chain_id: u64,
+    /// `aave` or `compound`
+    pub protocol: String,
+    /// Asset the rewards are swapped to and supplied as
+    pub asset: Address,
+    pub interval_secs: Option<u64>,
+    /// Rounds skip rewards worth less, default 0
+    pub min_reward_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewardCompounding {
+    pub id: String,
+    pub owner: Address,
+    pub chain_id: u64,
+    pub protocol: String,
+    pub asset: Address,
+    pub interval_secs: u64,
+    #[serde(with = "crate::api::models::usd")]
+    pub min_reward_usd: f64,
+    pub created_at: DateTime<Utc>,
+    pub next_run_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Strategy execution of the latest round that had rewards to compound
+    pub last_execution_id: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Plans a claim, swap and supply for every due schedule and hands it to the strategy executor
+pub struct RewardCompounder {
+    defi_manager: Arc<DefiManager>,
+    executor: Arc<StrategyExecutor>,
+    /// JSON file holding the schedules, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    poll_interval: Duration,
+    schedules: RwLock<HashMap<String, RewardCompounding>>,
+}
+
+impl RewardCompounder {
+    pub async fn new(
+        defi_manager: Arc<DefiManager>,
+        executor: Arc<StrategyExecutor>,
+        store_path: Option<PathBuf>,
+        poll_interval: Duration,
+    ) -> Result<Self> {
+        let stored: Vec<RewardCompounding> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !stored.is_empty()) {
+            info!("Loaded {} reward compounding schedules from {}", stored.len(), path.display());
+        }
+
+        Ok(Self {
+            defi_manager,
+            executor,
+            store_path,
+            poll_interval,
+            schedules: RwLock::new(stored.into_iter().map(|schedule| (schedule.id.clone(), schedule)).collect()),
+        })
+    }
+
+    /// Compounder persisting schedules to `reward_compounding_store_path` (empty keeps them in memory)
+    /// and looking for due ones every `reward_compounding_poll_interval_secs`
+    pub async fn from_config(
+        config: &config::Config,
+        defi_manager: Arc<DefiManager>,
+        executor: Arc<StrategyExecutor>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("reward_compounding_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let poll_interval = config
+            .get_int("reward_compounding_poll_interval_secs")
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        Self::new(defi_manager, executor, (!path.is_empty()).then(|| PathBuf::from(path)), poll_interval).await
+    }
+
+    /// Run due schedules in the background until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.run_due().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Reward compounder stopped");
+        })
+    }
+
+    /// Schedule compounding, the first round runs on the next poll
+    pub async fn register(&self, request: RewardCompoundingRequest) -> Result<RewardCompounding> {
+        let interval_secs = request.interval_secs.unwrap_or(DEFAULT_INTERVAL_SECS);
+        if interval_secs < MIN_INTERVAL_SECS {
+            return Err(anyhow!("interval_secs must be at least {}", MIN_INTERVAL_SECS));
+        }
+        let min_reward_usd = request.min_reward_usd.unwrap_or(0.0);
+        if !min_reward_usd.is_finite() || min_reward_usd < 0.0 {
+            return Err(anyhow!("min_reward_usd must not be negative"));
+        }
+        // Planning against an unreachable minimum checks the protocol, chain and reward token
+        // without quoting a swap
+        self.defi_manager
+            .plan_reward_compound(request.chain_id, &request.protocol, request.owner, request.asset, f64::INFINITY, 0.0)
+            .await?;
+
+        let now = Utc::now();
+        let schedule = RewardCompounding {
+            id: Uuid::new_v4().to_string(),
+            owner: request.owner,
+            chain_id: request.chain_id,
+            protocol: request.protocol.to_lowercase(),
+            asset: request.asset,
+            interval_secs,
+            min_reward_usd,
+            created_at: now,
+            next_run_at: now,
+            last_run_at: None,
+            last_execution_id: None,
+            last_error: None,
+        };
+        info!("Compounding {} rewards of {:?} on chain {} as {}", schedule.protocol, schedule.owner, schedule.chain_id, schedule.id);
+
+        let mut schedules = self.schedules.write().await;
+        schedules.insert(schedule.id.clone(), schedule.clone());
+        self.persist(&schedules).await;
+        Ok(schedule)
+    }
+
+    pub async fn list(&self, owner: Option<Address>) -> Vec<RewardCompounding> {
+        let mut schedules: Vec<RewardCompounding> = self.schedules.read().await.values()
+            .filter(|schedule| owner.is_none_or(|owner| schedule.owner == owner))
+            .cloned()
+            .collect();
+        schedules.sort_by_key(|schedule| schedule.created_at);
+        schedules
+    }
+
+    pub async fn get(&self, id: &str) -> Option<RewardCompounding> {
+        self.schedules.read().await.get(id).cloned()
+    }
+
+    /// Stop compounding, returning whether the schedule existed; a running round is not cancelled
+    pub async fn remove(&self, id: &str) -> bool {
+        let mut schedules = self.schedules.write().await;
+        let removed = schedules.remove(id).is_some();
+        if removed {
+            self.persist(&schedules).await;
+        }
+        removed
+    }
+
+    async fn run_due(&self) {
+        let now = Utc::now();
+        let due: Vec<String> = self.schedules.read().await.values()
+            .filter(|schedule| schedule.next_run_at <= now)
+            .map(|schedule| schedule.id.clone())
+            .collect();
+        for id in due {
+            if let Err(e) = self.run(&id).await {
+                warn!("Reward compounding round of {} failed: {}", id, e);
+            }
+        }
+    }
+
+    /// Run a round now: plan the claim, swap and supply and submit it unless the previous round is
+    /// still executing or the rewards are below the schedule's minimum
+    pub async fn run(&self, id: &str) -> Result<RewardCompounding> {
+        let mut schedule = self.get(id).await.ok_or_else(|| anyhow!("Reward compounding schedule {} not found", id))?;
+        if let Some(execution_id) = &schedule.last_execution_id {
+            let running = self.executor.get(execution_id).await
+                .is_some_and(|execution| execution.status == ExecutionStatus::Running);
+            if running {
+                debug!("Reward compounding {} waits for execution {}", id, execution_id);
+                return Ok(schedule);
+            }
+        }
+
+        let result = self.compound(&schedule).await;
+        let now = Utc::now();
+        schedule.last_run_at = Some(now);
+        schedule.next_run_at = now + ChronoDuration::seconds(schedule.interval_secs as i64);
+        if let Ok(Some(execution_id)) = &result {
+            schedule.last_execution_id = Some(execution_id.clone());
+        }
+        schedule.last_error = result.as_ref().err().map(|e| e.to_string());
+
+        let mut schedules = self.schedules.write().await;
+        // Removed while it ran
+        if !schedules.contains_key(id) {
+            return Err(anyhow!("Reward compounding schedule {} not found", id));
+        }
+        schedules.insert(schedule.id.clone(), schedule.clone());
+        self.persist(&schedules).await;
+        result.map(|_| schedule)
+    }
+
+    /// Id of the execution submitted, `None` when the rewards were below the minimum
+    async fn compound(&self, schedule: &RewardCompounding) -> Result<Option<String>> {
+        let plan = self.defi_manager.plan_reward_compound(
+            schedule.chain_id,
+            &schedule.protocol,
+            schedule.owner,
+            schedule.asset,
+            schedule.min_reward_usd,
+            self.executor.max_slippage_percentage(),
+        ).await?;
+        let Some(strategy) = plan else {
+            debug!("Rewards of reward compounding {} are below ${}", schedule.id, schedule.min_reward_usd);
+            return Ok(None);
+        };
+
+        let execution = self.executor.submit(StrategyExecutionRequest {
+            owner: schedule.owner,
+            chain_id: schedule.chain_id,
+            strategy,
+            min_health_factor: None,
+        }).await?;
+        info!("Reward compounding {} submitted execution {}", schedule.id, execution.id);
+        Ok(Some(execution.id))
+    }
+
+    async fn persist(&self, schedules: &HashMap<String, RewardCompounding>) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let stored: Vec<&RewardCompounding> = schedules.values().collect();
+        if let Err(e) = write_store(path, &stored).await {
+            warn!("Failed to persist reward compounding schedules to {}: {}", path.display(), e);
+        }
+    }
+}
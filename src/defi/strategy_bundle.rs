@@ -0,0 +1,72 @@
+// Yield strategies encoded as one smart account batch, so a failing step reverts the steps before it
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, Bytes, TransactionRequest, U256};
+use serde::Serialize;
+
+use crate::contracts::approvals::{transaction_target, TokenSpend};
+use crate::wallets::smart_account::SmartAccountCall;
+
+/// A call of the batch, in the shape the smart account user operation endpoints take
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledCall {
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+    pub description: String,
+}
+
+impl BundledCall {
+    pub(super) fn new(transaction: &TransactionRequest, description: impl Into<String>) -> Result<Self> {
+        let value = transaction.value.unwrap_or_default();
+        // SimpleAccount `executeBatch` forwards no value
+        if !value.is_zero() {
+            return Err(anyhow!("Steps paying in the native asset cannot be bundled, wrap it first"));
+        }
+        Ok(Self {
+            to: transaction_target(transaction).ok_or_else(|| anyhow!("Bundled call has no target"))?,
+            value,
+            data: transaction.data.clone().unwrap_or_default(),
+            description: description.into(),
+        })
+    }
+}
+
+impl From<&BundledCall> for SmartAccountCall {
+    fn from(call: &BundledCall) -> Self {
+        Self { to: call.to, value: call.value, data: call.data.clone() }
+    }
+}
+
+/// Swap of the batch with the output it was quoted at; the router call reverts below its minimum
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledSwap {
+    pub dex: String,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub expected_output: U256,
+}
+
+/// Step actions before their approvals are planned, each with the spend it needs approved
+#[derive(Default)]
+pub(super) struct BundleDraft {
+    pub actions: Vec<(Option<TokenSpend>, BundledCall)>,
+    pub swaps: Vec<BundledSwap>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StrategyBundle {
+    pub chain_id: u64,
+    /// Smart account making every call, holding the resulting positions
+    pub account: Address,
+    pub strategy_type: String,
+    /// Approvals first where a step needs one, in execution order
+    pub calls: Vec<BundledCall>,
+    pub swaps: Vec<BundledSwap>,
+}
+
+impl StrategyBundle {
+    pub fn smart_account_calls(&self) -> Vec<SmartAccountCall> {
+        self.calls.iter().map(SmartAccountCall::from).collect()
+    }
+}
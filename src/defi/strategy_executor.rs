@@ -0,0 +1,619 @@
+// Step-by-step execution of yield strategies: each step is sent, confirmed and checked before the
+// next one, with the progress persisted so a restart or a resumed failure picks up where it stopped
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ethers::providers::Middleware;
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, instrument, warn};
+
+use super::{DefiManager, OptimalYieldOpportunity, YieldOpportunityStep};
+use crate::chains::tx_broadcaster::TxBroadcaster;
+use crate::contracts::approvals::TokenSpend;
+use crate::contracts::erc20::ERC20Contract;
+use crate::dex::aggregator::SlippageSettings;
+use crate::security::{CircuitBreakers, PolicyContext};
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store};
+use crate::wallets::{labels::WalletUse, WalletManager, WalletType};
+
+/// Store used when `strategy_executions_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/strategy_executions.json";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_CONFIRMATIONS: u64 = 1;
+const DEFAULT_STEP_TIMEOUT_SECS: i64 = 1_800;
+const DEFAULT_MIN_HEALTH_FACTOR: f64 = 1.5;
+/// Slippage allowed on swap steps, and on the amounts supply and borrow steps must move
+const DEFAULT_MAX_SLIPPAGE_PERCENTAGE: f64 = 1.0;
+const SOURCE: &str = "defi:strategy_execution";
+
+#[derive(Debug, Clone)]
+pub struct StrategyExecutorConfig {
+    /// JSON file holding the executions, `None` keeps them in memory only
+    pub store_path: Option<PathBuf>,
+    pub poll_interval: Duration,
+    /// Blocks a step's transactions need before its post-conditions are checked
+    pub confirmations: u64,
+    /// A step whose transactions are not mined within this long fails
+    pub step_timeout: ChronoDuration,
+    /// Lowest health factor a step may leave the owner at, unless the execution sets its own
+    pub min_health_factor: f64,
+    pub max_slippage_percentage: f64,
+}
+
+impl Default for StrategyExecutorConfig {
+    fn default() -> Self {
+        Self {
+            store_path: Some(PathBuf::from(DEFAULT_STORE_PATH)),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            step_timeout: ChronoDuration::seconds(DEFAULT_STEP_TIMEOUT_SECS),
+            min_health_factor: DEFAULT_MIN_HEALTH_FACTOR,
+            max_slippage_percentage: DEFAULT_MAX_SLIPPAGE_PERCENTAGE,
+        }
+    }
+}
+
+impl StrategyExecutorConfig {
+    /// Executions persisted to `strategy_executions_store_path` (empty keeps them in memory) and
+    /// advanced every `strategy_execution_poll_interval_secs`, each step waiting for
+    /// `strategy_execution_confirmations` within `strategy_execution_step_timeout_secs`
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut executor_config = Self::default();
+
+        if let Ok(path) = config.get_string("strategy_executions_store_path") {
+            executor_config.store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        }
+        if let Ok(secs) = config.get_int("strategy_execution_poll_interval_secs") {
+            executor_config.poll_interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Ok(confirmations) = config.get_int("strategy_execution_confirmations") {
+            executor_config.confirmations = confirmations.max(1) as u64;
+        }
+        if let Ok(secs) = config.get_int("strategy_execution_step_timeout_secs") {
+            executor_config.step_timeout = ChronoDuration::seconds(secs.max(60));
+        }
+        if let Ok(health_factor) = config.get_float("strategy_execution_min_health_factor") {
+            executor_config.min_health_factor = health_factor.max(1.0);
+        }
+        if let Ok(slippage) = config.get_float("strategy_execution_max_slippage_percentage") {
+            executor_config.max_slippage_percentage = slippage.clamp(0.0, 50.0);
+        }
+
+        executor_config
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionStatus {
+    Running,
+    Completed,
+    /// Stopped at a failed step, resumable
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Pending,
+    /// Transactions sent, waiting for confirmations
+    Submitted,
+    /// Confirmed with its post-conditions met
+    Completed,
+    Failed,
+    /// Failed and passed over when the execution was resumed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionStep {
+    pub step: YieldOpportunityStep,
+    pub status: StepStatus,
+    /// Approval or other preparation first, the step's own transaction last
+    pub tx_hashes: Vec<H256>,
+    /// Token whose balance in the owner's wallet the step moves
+    pub checked_token: Option<Address>,
+    pub balance_before: Option<U256>,
+    /// Least the balance must fall by (supply) or rise by (borrow, swap, claim)
+    pub expected_change: Option<U256>,
+    /// Owner's lowest health factor after the step
+    pub health_factor: Option<f64>,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl ExecutionStep {
+    fn new(step: YieldOpportunityStep) -> Self {
+        Self {
+            step,
+            status: StepStatus::Pending,
+            tx_hashes: Vec::new(),
+            checked_token: None,
+            balance_before: None,
+            expected_change: None,
+            health_factor: None,
+            attempts: 0,
+            error: None,
+            submitted_at: None,
+            completed_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyExecution {
+    pub id: String,
+    pub owner: Address,
+    pub chain_id: u64,
+    pub strategy_type: String,
+    pub protocol: String,
+    pub min_health_factor: f64,
+    pub status: ExecutionStatus,
+    pub steps: Vec<ExecutionStep>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StrategyExecution {
+    /// Index of the first step not yet done
+    fn current_step(&self) -> Option<usize> {
+        self.steps.iter().position(|step| !matches!(step.status, StepStatus::Completed | StepStatus::Skipped))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StrategyExecutionRequest {
+    pub owner: Address,
+    pub chain_id: u64,
+    pub strategy: OptimalYieldOpportunity,
+    /// Overrides the configured minimum health factor
+    pub min_health_factor: Option<f64>,
+}
+
+/// What checking a submitted step found
+enum StepCheck {
+    Waiting,
+    Passed { health_factor: Option<f64> },
+    Failed(String),
+}
+
+/// Runs yield strategies one confirmed step at a time with the owner's server-side wallet
+pub struct StrategyExecutor {
+    defi_manager: Arc<DefiManager>,
+    wallet_manager: Arc<WalletManager>,
+    broadcaster: Arc<TxBroadcaster>,
+    /// Swaps wait while a breaker halts trading
+    circuit_breakers: Arc<CircuitBreakers>,
+    config: StrategyExecutorConfig,
+    executions: RwLock<Vec<StrategyExecution>>,
+}
+
+impl StrategyExecutor {
+    pub async fn new(
+        defi_manager: Arc<DefiManager>,
+        wallet_manager: Arc<WalletManager>,
+        broadcaster: Arc<TxBroadcaster>,
+        circuit_breakers: Arc<CircuitBreakers>,
+        config: StrategyExecutorConfig,
+    ) -> Result<Self> {
+        let executions: Vec<StrategyExecution> = match &config.store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = config.store_path.as_ref().filter(|_| !executions.is_empty()) {
+            info!("Loaded {} strategy executions from {}", executions.len(), path.display());
+        }
+
+        Ok(Self {
+            defi_manager,
+            wallet_manager,
+            broadcaster,
+            circuit_breakers,
+            config,
+            executions: RwLock::new(executions),
+        })
+    }
+
+    /// Advance running executions in the background until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.advance_running().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Strategy executor stopped");
+        })
+    }
+
+    /// Accept a strategy for execution, its first step is sent on the next poll
+    pub async fn submit(&self, request: StrategyExecutionRequest) -> Result<StrategyExecution> {
+        let strategy = request.strategy;
+        if strategy.steps.is_empty() {
+            return Err(anyhow!("Strategy {} has no steps", strategy.strategy_type));
+        }
+        let unsupported = |step: &&YieldOpportunityStep| match step {
+            YieldOpportunityStep::Farm { protocol, token, .. } => protocol != "Curve" || token.is_none(),
+            YieldOpportunityStep::Stake { protocol, .. } => protocol != "Convex",
+            _ => false,
+        };
+        if let Some(step) = strategy.steps.iter().find(unsupported) {
+            return Err(anyhow!("Strategy {} has a step that cannot be executed yet: {:?}", strategy.strategy_type, step));
+        }
+        let min_health_factor = request.min_health_factor.unwrap_or(self.config.min_health_factor);
+        if !min_health_factor.is_finite() || min_health_factor < 1.0 {
+            return Err(anyhow!("Minimum health factor must be at least 1.0"));
+        }
+        self.wallet_manager.labels().require(request.owner, WalletUse::AutoExecution).await?;
+        let wallet = self.wallet_manager.get_wallet_info(request.owner).await?;
+        if !wallet.is_connected || !matches!(wallet.wallet_type, WalletType::LocalWallet) {
+            return Err(anyhow!("Strategy executions need a connected local wallet for {:?}", request.owner));
+        }
+
+        let now = Utc::now();
+        let execution = StrategyExecution {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner: request.owner,
+            chain_id: request.chain_id,
+            strategy_type: strategy.strategy_type,
+            protocol: strategy.protocol,
+            min_health_factor,
+            status: ExecutionStatus::Running,
+            steps: strategy.steps.into_iter().map(ExecutionStep::new).collect(),
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut executions = self.executions.write().await;
+        executions.push(execution.clone());
+        self.persist(&executions).await;
+        info!("Accepted strategy execution {} of {} steps for {:?}", execution.id, execution.steps.len(), execution.owner);
+        Ok(execution)
+    }
+
+    /// Slippage swap steps are sent with, and allowed on the amounts steps move
+    pub fn max_slippage_percentage(&self) -> f64 {
+        self.config.max_slippage_percentage
+    }
+
+    pub async fn get(&self, id: &str) -> Option<StrategyExecution> {
+        self.executions.read().await.iter().find(|execution| execution.id == id).cloned()
+    }
+
+    /// Executions of an owner, newest first
+    pub async fn list(&self, owner: Address) -> Vec<StrategyExecution> {
+        self.executions.read().await.iter()
+            .rev()
+            .filter(|execution| execution.owner == owner)
+            .cloned()
+            .collect()
+    }
+
+    /// Continue a failed execution from its failed step, or after it with `skip_failed_step`.
+    /// A failed step whose transaction was mined is checked again rather than sent twice
+    pub async fn resume(&self, id: &str, skip_failed_step: bool) -> Result<StrategyExecution> {
+        let execution = self.get(id).await.ok_or_else(|| anyhow!("Unknown strategy execution {}", id))?;
+        if execution.status != ExecutionStatus::Failed {
+            return Err(anyhow!("Strategy execution {} is {:?}, only failed executions resume", id, execution.status));
+        }
+        let index = execution.current_step().ok_or_else(|| anyhow!("Strategy execution {} has no step left", id))?;
+        let mined = match execution.steps[index].tx_hashes.last() {
+            Some(hash) if !skip_failed_step => self.receipt_succeeded(execution.chain_id, *hash).await?,
+            _ => false,
+        };
+
+        self.update(id, |stored| {
+            let step = &mut stored.steps[index];
+            step.status = match (skip_failed_step, mined) {
+                (true, _) => StepStatus::Skipped,
+                (false, true) => StepStatus::Submitted,
+                (false, false) => StepStatus::Pending,
+            };
+            if step.status == StepStatus::Pending {
+                step.tx_hashes.clear();
+            }
+            step.error = None;
+            stored.status = ExecutionStatus::Running;
+            stored.last_error = None;
+        })
+        .await
+        .ok_or_else(|| anyhow!("Strategy execution {} changed while resuming", id))
+    }
+
+    /// Stop an execution between steps, transactions already sent are not recalled
+    pub async fn cancel(&self, id: &str) -> Result<StrategyExecution> {
+        let execution = self.get(id).await.ok_or_else(|| anyhow!("Unknown strategy execution {}", id))?;
+        if !matches!(execution.status, ExecutionStatus::Running | ExecutionStatus::Failed) {
+            return Err(anyhow!("Strategy execution {} is already {:?}", id, execution.status));
+        }
+        self.update(id, |stored| stored.status = ExecutionStatus::Cancelled).await
+            .ok_or_else(|| anyhow!("Unknown strategy execution {}", id))
+    }
+
+    /// Move every running execution one transition forward, one at a time so an owner's nonces stay in order
+    async fn advance_running(&self) {
+        let running: Vec<StrategyExecution> = self.executions.read().await.iter()
+            .filter(|execution| execution.status == ExecutionStatus::Running)
+            .cloned()
+            .collect();
+        for execution in running {
+            self.advance(execution).await;
+        }
+    }
+
+    #[instrument(skip_all, fields(chain_id = execution.chain_id, wallet = ?execution.owner, execution_id = %execution.id))]
+    async fn advance(&self, execution: StrategyExecution) {
+        let Some(index) = execution.current_step() else {
+            info!("Strategy execution {} completed", execution.id);
+            self.update(&execution.id, |stored| stored.status = ExecutionStatus::Completed).await;
+            return;
+        };
+        let step = &execution.steps[index];
+        match step.status {
+            StepStatus::Pending => {
+                if matches!(step.step, YieldOpportunityStep::Swap { .. }) {
+                    if let Some(halt) = self.circuit_breakers.halt().await {
+                        debug!("Holding swap step of {}, trading halted by the {} circuit breaker", execution.id, halt.breaker);
+                        return;
+                    }
+                }
+                match self.send_step(&execution, step).await {
+                    Ok(sent) => {
+                        info!("Strategy execution {} sent step {} in {} transactions", execution.id, index + 1, sent.tx_hashes.len());
+                        self.update(&execution.id, |stored| stored.steps[index] = sent).await;
+                    }
+                    Err(e) => self.fail(&execution.id, index, format!("Step {} could not be sent: {}", index + 1, e)).await,
+                }
+            }
+            StepStatus::Submitted => match self.check_step(&execution, step).await {
+                Ok(StepCheck::Waiting) => {}
+                Ok(StepCheck::Passed { health_factor }) => {
+                    info!("Strategy execution {} completed step {}", execution.id, index + 1);
+                    self.update(&execution.id, |stored| {
+                        let step = &mut stored.steps[index];
+                        step.status = StepStatus::Completed;
+                        step.health_factor = health_factor;
+                        step.completed_at = Some(Utc::now());
+                    })
+                    .await;
+                }
+                Ok(StepCheck::Failed(error)) => self.fail(&execution.id, index, format!("Step {} failed: {}", index + 1, error)).await,
+                // Node errors only delay the check
+                Err(e) => warn!("Checking step {} of strategy execution {} failed: {}", index + 1, execution.id, e),
+            },
+            StepStatus::Failed => {
+                self.update(&execution.id, |stored| stored.status = ExecutionStatus::Failed).await;
+            }
+            StepStatus::Completed | StepStatus::Skipped => {}
+        }
+    }
+
+    /// Build and send a step's transactions, recording the balance its post-condition is measured from
+    async fn send_step(&self, execution: &StrategyExecution, step: &ExecutionStep) -> Result<ExecutionStep> {
+        // The owner's label may have changed since the execution was accepted
+        self.wallet_manager.labels().require(execution.owner, WalletUse::AutoExecution).await?;
+        let (chain_id, owner) = (execution.chain_id, execution.owner);
+        let defi = &self.defi_manager;
+        let approvals = defi.dex_manager.approvals();
+        let tolerance = |amount: U256| {
+            amount * U256::from(((100.0 - self.config.max_slippage_percentage) * 100.0) as u64) / U256::from(10_000)
+        };
+
+        let (checked_token, expected_change, transactions) = match &step.step {
+            YieldOpportunityStep::Supply { protocol, asset, amount } => {
+                let (spender, mut transactions) = match protocol.as_str() {
+                    "Aave" => {
+                        let pool = defi.aave.lending_pool(chain_id).ok_or_else(|| anyhow!("Aave is not deployed on chain {}", chain_id))?;
+                        (pool, vec![defi.aave.supply(chain_id, *asset, *amount, owner, 0).await?])
+                    }
+                    "Compound" => {
+                        let ctoken = defi.find_ctoken_for_asset(chain_id, *asset).await?;
+                        // Supplied assets only back borrows once their market is entered
+                        (ctoken, vec![
+                            defi.compound.supply(chain_id, ctoken, *amount).await?,
+                            defi.compound.enter_markets(chain_id, vec![ctoken]).await?,
+                        ])
+                    }
+                    "Maker" => {
+                        let vault = defi.maker.strategy_vault(chain_id, owner, Some(*asset)).await?;
+                        let plan = defi.maker.deposit_collateral(chain_id, owner, vault.id, *amount).await?;
+                        let spend = plan.spend.ok_or_else(|| anyhow!("Maker deposit into vault {} has no adapter to approve", vault.id))?;
+                        (spend.spender, plan.transactions)
+                    }
+                    _ => return Err(anyhow!("Unsupported protocol: {}", protocol)),
+                };
+                let spend = TokenSpend { token: *asset, spender, amount: *amount };
+                if let Some(approval) = approvals.required_approval(chain_id, owner, spend, None).await? {
+                    transactions.insert(0, approval.transaction);
+                }
+                (*asset, *amount, transactions)
+            }
+            YieldOpportunityStep::Borrow { protocol, asset, amount } => {
+                let transactions = match protocol.as_str() {
+                    "Aave" => vec![defi.aave.borrow(chain_id, *asset, *amount, 2, 0, owner).await?],
+                    "Compound" => {
+                        let ctoken = defi.find_ctoken_for_asset(chain_id, *asset).await?;
+                        vec![defi.compound.borrow(chain_id, ctoken, *amount).await?]
+                    }
+                    // The vault with the most DAI left to draw, which the strategy's deposit just topped up
+                    "Maker" => {
+                        let vault = defi.maker.strategy_vault(chain_id, owner, None).await?;
+                        defi.maker.draw_dai(chain_id, owner, vault.id, *amount).await?.transactions
+                    }
+                    _ => return Err(anyhow!("Unsupported protocol: {}", protocol)),
+                };
+                // Gas paid in a borrowed native asset comes out of the amount received
+                (*asset, tolerance(*amount), transactions)
+            }
+            YieldOpportunityStep::Swap { token_in, token_out, amount, .. } => {
+                let settings = SlippageSettings {
+                    max_slippage_percentage: self.config.max_slippage_percentage,
+                    ..SlippageSettings::default()
+                };
+                let swap = defi.dex_manager.execute_optimal_swap(chain_id, *token_in, *token_out, *amount, owner, Some(settings)).await?;
+                let transactions = swap.approval.map(|approval| approval.transaction).into_iter()
+                    .chain([swap.transaction])
+                    .collect();
+                (*token_out, tolerance(swap.expected_output), transactions)
+            }
+            YieldOpportunityStep::Claim { protocol, token, amount } => {
+                // Rewards only grow after planning, so the claim pays at least the planned amount
+                (*token, *amount, vec![defi.reward_claim_transaction(chain_id, protocol, owner).await?])
+            }
+            YieldOpportunityStep::Farm { protocol, pool, token: Some(token), amount } if protocol == "Curve" => {
+                let curve = defi.convex.curve_pool(chain_id, *pool)
+                    .ok_or_else(|| anyhow!("Curve pool {:?} is not configured on chain {}", pool, chain_id))?;
+                // The LP minted is checked against the quote at sending, less the slippage allowed
+                let min_lp_amount = tolerance(defi.convex.quote_liquidity(chain_id, curve.pid, *token, *amount).await?);
+                let plan = defi.convex.add_liquidity(chain_id, curve.pid, *token, *amount, min_lp_amount).await?;
+                (curve.lp_token, min_lp_amount, defi.convex.with_approval(chain_id, owner, &plan).await?)
+            }
+            YieldOpportunityStep::Stake { protocol, token, amount } if protocol == "Convex" => {
+                let curve = defi.convex.pool_by_lp(chain_id, *token)
+                    .ok_or_else(|| anyhow!("No Convex pool stakes {:?} on chain {}", token, chain_id))?;
+                let plan = defi.convex.deposit(chain_id, curve.pid, *amount).await?;
+                (*token, *amount, defi.convex.with_approval(chain_id, owner, &plan).await?)
+            }
+            YieldOpportunityStep::Farm { .. } | YieldOpportunityStep::Stake { .. } => {
+                return Err(anyhow!("Only Curve farm and Convex stake steps can be executed"));
+            }
+        };
+
+        let balance_before = self.balance(chain_id, checked_token, owner).await?;
+        // Spending is counted against the policy of the execution's strategy id
+        let policy = PolicyContext { strategy_id: Some(execution.id.clone()), override_id: None };
+        let mut sent = step.clone();
+        sent.tx_hashes.clear();
+        for transaction in &transactions {
+            // Swaps are recorded by the DEX manager when built
+            if !matches!(step.step, YieldOpportunityStep::Swap { .. }) {
+                defi.transactions.record_built(chain_id, Some(owner), SOURCE, transaction).await;
+            }
+            let tx: TypedTransaction = transaction.clone().into();
+            let signer = self.wallet_manager.local_signer(owner, &tx, &policy).await?;
+            // The broadcaster keeps the owner's nonces in order, so later transactions land after the approval
+            sent.tx_hashes.push(self.broadcaster.send_with_signer(chain_id, tx, &signer).await?.hash);
+        }
+
+        sent.status = StepStatus::Submitted;
+        sent.checked_token = Some(checked_token);
+        sent.balance_before = Some(balance_before);
+        sent.expected_change = Some(expected_change);
+        sent.attempts += 1;
+        sent.error = None;
+        sent.submitted_at = Some(Utc::now());
+        Ok(sent)
+    }
+
+    /// Wait for the step's transactions, then check the owner's balance and health factor
+    async fn check_step(&self, execution: &StrategyExecution, step: &ExecutionStep) -> Result<StepCheck> {
+        let chain_id = execution.chain_id;
+        let provider = self.defi_manager.chain_manager.get_provider(chain_id).await?;
+        let latest = provider.provider.get_block_number().await?.as_u64();
+        for hash in &step.tx_hashes {
+            let Some(receipt) = provider.provider.get_transaction_receipt(*hash).await? else {
+                let timed_out = step.submitted_at.is_some_and(|at| Utc::now() - at > self.config.step_timeout);
+                return Ok(match timed_out {
+                    true => StepCheck::Failed(format!("{:?} was not mined within {} seconds", hash, self.config.step_timeout.num_seconds())),
+                    false => StepCheck::Waiting,
+                });
+            };
+            if receipt.status.is_none_or(|status| status.as_u64() != 1) {
+                return Ok(StepCheck::Failed(format!("{:?} reverted", hash)));
+            }
+            let confirmations = receipt.block_number.map_or(0, |block| latest.saturating_sub(block.as_u64()) + 1);
+            if confirmations < self.config.confirmations {
+                return Ok(StepCheck::Waiting);
+            }
+        }
+
+        if let (Some(token), Some(before), Some(expected)) = (step.checked_token, step.balance_before, step.expected_change) {
+            let after = self.balance(chain_id, token, execution.owner).await?;
+            let (moved, direction) = match step.step {
+                YieldOpportunityStep::Supply { .. } | YieldOpportunityStep::Stake { .. } => (before.saturating_sub(after), "fell"),
+                _ => (after.saturating_sub(before), "rose"),
+            };
+            if moved < expected {
+                return Ok(StepCheck::Failed(format!(
+                    "balance of {:?} {} by {}, expected at least {}", token, direction, moved, expected,
+                )));
+            }
+        }
+
+        // Position reads are cached briefly, the step just changed them
+        self.defi_manager.aave.invalidate_user(chain_id, execution.owner).await;
+        self.defi_manager.compound.invalidate_user(chain_id, execution.owner).await;
+        let portfolio = self.defi_manager.get_portfolio_overview(chain_id, execution.owner).await?;
+        let health_factor = portfolio.risk.min_health_factor;
+        if let Some(health_factor) = health_factor.filter(|health_factor| *health_factor < execution.min_health_factor) {
+            return Ok(StepCheck::Failed(format!(
+                "health factor {:.3} is below the minimum of {:.3}", health_factor, execution.min_health_factor,
+            )));
+        }
+        Ok(StepCheck::Passed { health_factor })
+    }
+
+    /// Whether a transaction is mined and succeeded
+    async fn receipt_succeeded(&self, chain_id: u64, hash: H256) -> Result<bool> {
+        let provider = self.defi_manager.chain_manager.get_provider(chain_id).await?;
+        Ok(provider.provider.get_transaction_receipt(hash).await?
+            .is_some_and(|receipt| receipt.status.is_some_and(|status| status.as_u64() == 1)))
+    }
+
+    /// Wallet balance of a token, the zero address standing for the native asset
+    async fn balance(&self, chain_id: u64, token: Address, owner: Address) -> Result<U256> {
+        let chain = self.defi_manager.chain_manager.get_provider(chain_id).await?;
+        if token.is_zero() {
+            return Ok(chain.provider.get_balance(owner, None).await?);
+        }
+        ERC20Contract::new(token, Arc::new(chain.provider.clone()), chain_id).await?
+            .balance_of(owner)
+            .await
+    }
+
+    async fn fail(&self, id: &str, index: usize, error: String) {
+        warn!("Strategy execution {} stopped: {}", id, error);
+        self.update(id, |stored| {
+            stored.steps[index].status = StepStatus::Failed;
+            stored.steps[index].error = Some(error.clone());
+            stored.status = ExecutionStatus::Failed;
+            stored.last_error = Some(error);
+        })
+        .await;
+    }
+
+    /// Apply a change unless the execution was cancelled meanwhile
+    async fn update(&self, id: &str, apply: impl FnOnce(&mut StrategyExecution)) -> Option<StrategyExecution> {
+        let mut executions = self.executions.write().await;
+        let execution = executions.iter_mut()
+            .find(|execution| execution.id == id && execution.status != ExecutionStatus::Cancelled)?;
+        apply(execution);
+        execution.updated_at = Utc::now();
+        let execution = execution.clone();
+        self.persist(&executions).await;
+        Some(execution)
+    }
+
+    async fn persist(&self, executions: &[StrategyExecution]) {
+        let Some(path) = &self.config.store_path else {
+            return;
+        };
+        if let Err(e) = write_store(path, executions).await {
+            warn!("Failed to persist strategy executions to {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// Gas spent by a strategy's transactions, amortized against what it earned
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+
+use super::ActiveStrategy;
+use crate::transactions::{TransactionRecord, TransactionStatus};
+
+/// Cumulative gas of a strategy and its APY once gas is paid out of its returns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyGasReport {
+    pub strategy_id: String,
+    pub chain_id: Option<u64>,
+    /// Linked transactions with a receipt, reverted ones included since they paid gas
+    pub mined_transactions: usize,
+    /// Linked transactions not mined yet, or dropped, which cost nothing so far
+    pub unmined_transactions: usize,
+    pub gas_used: U256,
+    /// Native token spent on gas, in wei
+    pub gas_cost: U256,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub gas_cost_usd: Option<f64>,
+    /// Profit the strategy reports, which gas is amortized against
+    #[serde(with = "crate::api::models::usd")]
+    pub returns_usd: f64,
+    /// Gas cost as a percentage of the returns, `None` without positive returns
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub gas_share_percentage: Option<f64>,
+    #[serde(with = "crate::api::models::ratio")]
+    pub apy: f64,
+    /// APY left after gas, in proportion to the share of returns it consumed
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub net_apy: Option<f64>,
+}
+
+impl StrategyGasReport {
+    /// Account the strategy's transactions, `records` being the ones that settled each linked
+    /// transaction (the replacement when a linked one was sped up or cancelled)
+    pub fn build(strategy: &ActiveStrategy, records: &[TransactionRecord], native_price_usd: Option<f64>) -> Self {
+        let mut gas_used = U256::zero();
+        let mut gas_cost = U256::zero();
+        let mut mined_transactions = 0;
+
+        for record in records {
+            let mined = matches!(record.status, TransactionStatus::Confirmed | TransactionStatus::Failed);
+            let (true, Some(used)) = (mined, record.gas_used) else {
+                continue;
+            };
+            mined_transactions += 1;
+            gas_used = gas_used.saturating_add(used);
+            gas_cost = gas_cost.saturating_add(used.saturating_mul(record.effective_gas_price.unwrap_or_default()));
+        }
+
+        let gas_cost_usd = native_price_usd.map(|price| {
+            let native: f64 = ethers::utils::format_ether(gas_cost).parse().unwrap_or(0.0);
+            native * price
+        });
+        let returns_usd = strategy.profit_loss;
+        let gas_share_percentage = gas_cost_usd
+            .filter(|_| returns_usd > 0.0)
+            .map(|gas_cost_usd| gas_cost_usd / returns_usd * 100.0);
+
+        Self {
+            strategy_id: strategy.strategy_id.clone(),
+            chain_id: strategy.chain_id,
+            mined_transactions,
+            unmined_transactions: records.len() - mined_transactions,
+            gas_used,
+            gas_cost,
+            gas_cost_usd,
+            returns_usd,
+            gas_share_percentage,
+            apy: strategy.apy,
+            net_apy: gas_share_percentage.map(|share| strategy.apy * (1.0 - share / 100.0)),
+        }
+    }
+
+    /// Gas the strategy may spend before `threshold_percentage` of its returns is consumed
+    pub fn gas_budget_usd(&self, threshold_percentage: f64) -> f64 {
+        self.returns_usd.max(0.0) * threshold_percentage / 100.0
+    }
+}
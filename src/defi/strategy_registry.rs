@@ -0,0 +1,81 @@
+// Per-user registry of running strategies
+use anyhow::Result;
+use chrono::Utc;
+use ethers::types::Address;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::{ActiveStrategy, StrategyStatus};
+use crate::api::models::ArchiveFilter;
+
+/// Strategies each user is running, shown in their portfolio overview.
+/// Closed strategies stay in the registry for history.
+pub struct StrategyRegistry {
+    strategies: Arc<RwLock<HashMap<Address, Vec<ActiveStrategy>>>>,
+}
+
+impl StrategyRegistry {
+    pub async fn new() -> Result<Self> {
+        Ok(Self {
+            strategies: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Add a strategy, replacing any existing one with the same id
+    pub async fn register(&self, user: Address, strategy: ActiveStrategy) {
+        let mut strategies = self.strategies.write().await;
+        let user_strategies = strategies.entry(user).or_default();
+        user_strategies.retain(|existing| existing.strategy_id != strategy.strategy_id);
+        user_strategies.push(strategy);
+    }
+
+    pub async fn list(&self, user: Address, filter: ArchiveFilter) -> Vec<ActiveStrategy> {
+        self.strategies.read().await
+            .get(&user)
+            .map(|strategies| {
+                strategies.iter()
+                    .filter(|strategy| filter.includes(strategy.status == StrategyStatus::Closed))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub async fn get(&self, user: Address, strategy_id: &str) -> Option<ActiveStrategy> {
+        self.strategies.read().await
+            .get(&user)?
+            .iter()
+            .find(|strategy| strategy.strategy_id == strategy_id)
+            .cloned()
+    }
+
+    /// Charge tracked transactions to a strategy, `None` if the user has no such strategy
+    pub async fn link_transactions(&self, user: Address, strategy_id: &str, transaction_ids: &[String]) -> Option<ActiveStrategy> {
+        let mut strategies = self.strategies.write().await;
+        let strategy = strategies.get_mut(&user)?
+            .iter_mut()
+            .find(|strategy| strategy.strategy_id == strategy_id)?;
+
+        for id in transaction_ids {
+            if !strategy.transaction_ids.contains(id) {
+                strategy.transaction_ids.push(id.clone());
+            }
+        }
+        Some(strategy.clone())
+    }
+
+    /// Mark a strategy closed, `None` if the user has no such strategy
+    pub async fn close(&self, user: Address, strategy_id: &str) -> Option<ActiveStrategy> {
+        let mut strategies = self.strategies.write().await;
+        let strategy = strategies.get_mut(&user)?
+            .iter_mut()
+            .find(|strategy| strategy.strategy_id == strategy_id)?;
+
+        if strategy.status != StrategyStatus::Closed {
+            strategy.status = StrategyStatus::Closed;
+            strategy.closed_at = Some(Utc::now());
+        }
+        Some(strategy.clone())
+    }
+}
@@ -0,0 +1,168 @@
+// Curated strategy template library
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{ActiveStrategy, StrategyStatus};
+
+/// Templates shipped with the repo
+const BUILTIN_TEMPLATES: &str = include_str!("strategy_templates.yaml");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskClass {
+    Low,
+    Medium,
+    High,
+}
+
+impl RiskClass {
+    /// Risk level label used by active strategies
+    pub fn label(&self) -> &'static str {
+        match self {
+            RiskClass::Low => "Low",
+            RiskClass::Medium => "Medium",
+            RiskClass::High => "High",
+        }
+    }
+}
+
+/// Expected APY range in percent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApyRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Tunable parameter of a template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateParameter {
+    pub name: String,
+    pub description: String,
+    pub default: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub protocol: String,
+    pub strategy_type: String,
+    pub risk_class: RiskClass,
+    pub expected_apy: ApyRange,
+    /// Symbols of the assets the strategy needs, the first one is deposited
+    pub required_assets: Vec<String>,
+    pub supported_chains: Vec<u64>,
+    #[serde(default)]
+    pub parameters: Vec<TemplateParameter>,
+}
+
+/// Browse filter, unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TemplateFilter {
+    pub chain_id: Option<u64>,
+    pub risk_class: Option<RiskClass>,
+    /// Asset symbol the template must use
+    pub asset: Option<String>,
+}
+
+/// Library of strategy templates users can instantiate
+pub struct StrategyTemplateLibrary {
+    templates: Vec<StrategyTemplate>,
+}
+
+impl StrategyTemplateLibrary {
+    pub fn builtin() -> Result<Self> {
+        Self::from_yaml(BUILTIN_TEMPLATES)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let templates: Vec<StrategyTemplate> = serde_yaml::from_str(yaml)
+            .map_err(|e| anyhow!("Invalid strategy templates: {}", e))?;
+
+        for template in &templates {
+            if template.expected_apy.min > template.expected_apy.max {
+                return Err(anyhow!("Template {} has an inverted APY range", template.id));
+            }
+            if let Some(parameter) = template.parameters.iter()
+                .find(|p| p.min > p.max || p.default < p.min || p.default > p.max)
+            {
+                return Err(anyhow!("Template {} parameter {} has an invalid range", template.id, parameter.name));
+            }
+        }
+
+        Ok(Self { templates })
+    }
+
+    pub fn list(&self, filter: &TemplateFilter) -> Vec<StrategyTemplate> {
+        self.templates.iter()
+            .filter(|t| filter.chain_id.is_none_or(|chain_id| t.supported_chains.contains(&chain_id)))
+            .filter(|t| filter.risk_class.is_none_or(|risk_class| t.risk_class == risk_class))
+            .filter(|t| {
+                filter.asset.as_ref().is_none_or(|asset| {
+                    t.required_assets.iter().any(|required| required.eq_ignore_ascii_case(asset))
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&StrategyTemplate> {
+        self.templates.iter().find(|template| template.id == id)
+    }
+
+    /// Create an active strategy from a template, applying parameter overrides
+    pub fn instantiate(
+        &self,
+        id: &str,
+        chain_id: u64,
+        amount: U256,
+        overrides: &HashMap<String, f64>,
+    ) -> Result<ActiveStrategy> {
+        let template = self.get(id).ok_or_else(|| anyhow!("Unknown strategy template: {}", id))?;
+        if !template.supported_chains.contains(&chain_id) {
+            return Err(anyhow!("Template {} does not support chain {}", id, chain_id));
+        }
+        if amount.is_zero() {
+            return Err(anyhow!("Strategy amount must be positive"));
+        }
+
+        let mut parameters: HashMap<String, f64> = template.parameters.iter()
+            .map(|p| (p.name.clone(), p.default))
+            .collect();
+        for (name, value) in overrides {
+            let parameter = template.parameters.iter()
+                .find(|p| &p.name == name)
+                .ok_or_else(|| anyhow!("Template {} has no parameter {}", id, name))?;
+            if !value.is_finite() || *value < parameter.min || *value > parameter.max {
+                return Err(anyhow!(
+                    "Parameter {} must be between {} and {}", name, parameter.min, parameter.max
+                ));
+            }
+            parameters.insert(name.clone(), *value);
+        }
+
+        Ok(ActiveStrategy {
+            strategy_id: format!("{}-{}", template.id, uuid::Uuid::new_v4()),
+            protocol: template.protocol.clone(),
+            strategy_type: template.strategy_type.clone(),
+            invested_amount: amount,
+            current_value: amount,
+            apy: (template.expected_apy.min + template.expected_apy.max) / 2.0,
+            risk_level: template.risk_class.label().to_string(),
+            start_date: Utc::now(),
+            profit_loss: 0.0,
+            template_id: Some(template.id.clone()),
+            chain_id: Some(chain_id),
+            parameters,
+            status: StrategyStatus::Active,
+            closed_at: None,
+            transaction_ids: Vec::new(),
+        })
+    }
+}
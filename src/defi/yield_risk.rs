@@ -0,0 +1,268 @@
+// Risk scores of yield opportunities from protocol track records and market depth, factor by factor
+use anyhow::{Result, anyhow};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Track records shipped with the repo
+const BUILTIN_PROTOCOLS: &str = include_str!("protocol_registry.yaml");
+
+const AUDITS_WEIGHT: f64 = 0.35;
+const AGE_WEIGHT: f64 = 0.3;
+const EXPLOITS_WEIGHT: f64 = 0.35;
+const TVL_WEIGHT: f64 = 0.4;
+const UTILIZATION_WEIGHT: f64 = 0.4;
+const POSITION_SHARE_WEIGHT: f64 = 0.2;
+/// Years after which a protocol's age risk halves
+const AGE_HALF_LIFE_YEARS: f64 = 2.0;
+/// Years after which an exploit counts half as much
+const EXPLOIT_HALF_LIFE_YEARS: f64 = 3.0;
+/// Markets this deep or shallower score the highest TVL risk, each tenfold deeper scores a quarter less
+const SHALLOW_TVL_USD: f64 = 1_000_000.0;
+/// Utilization past which withdrawals start competing for the remaining cash
+const UTILIZATION_KINK: f64 = 0.8;
+/// Share of a market a deposit can take before exiting it is scored the highest risk
+const MAX_POSITION_SHARE: f64 = 0.1;
+/// Liquidity risk when nothing about the market's depth is known
+const UNKNOWN_LIQUIDITY_RISK: f64 = 0.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolAudit {
+    pub auditor: String,
+    pub date: NaiveDate,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolExploit {
+    pub date: NaiveDate,
+    #[serde(with = "crate::api::models::usd")]
+    pub loss_usd: f64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolRecord {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub launched: NaiveDate,
+    #[serde(default)]
+    pub audits: Vec<ProtocolAudit>,
+    #[serde(default)]
+    pub exploits: Vec<ProtocolExploit>,
+}
+
+impl ProtocolRecord {
+    fn matches(&self, name: &str) -> bool {
+        let name = normalize(name);
+        [&self.id, &self.name].into_iter().chain(&self.aliases).any(|known| normalize(known) == name)
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| !c.is_whitespace() && *c != '_').flat_map(char::to_lowercase).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskCategory {
+    Liquidity,
+    SmartContract,
+}
+
+/// One input of a risk score and how much it moved it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskScoreFactor {
+    /// `tvl`, `utilization`, `position_share`, `audits`, `protocol_age`, `exploits` or `unknown_protocol`
+    pub name: String,
+    pub category: RiskCategory,
+    /// Protocol the factor is about, `None` for market depth
+    pub protocol: Option<String>,
+    /// 0 (safest) to 1, `None` when the input is unknown and the factor is left out
+    pub score: Option<f64>,
+    /// Weight within its category and protocol
+    pub weight: f64,
+    /// The input the score was derived from
+    pub detail: String,
+}
+
+/// Depth of the market an opportunity deposits into; unknown values are left out of the score
+#[derive(Debug, Clone, Default)]
+pub struct MarketDepth {
+    /// Deposits of the market in USD
+    pub tvl_usd: Option<f64>,
+    /// Share of the deposits lent out, 0 to 1
+    pub utilization: Option<f64>,
+    pub deposit_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct YieldRiskScore {
+    pub liquidity_risk: f64,
+    pub smart_contract_risk: f64,
+    pub factors: Vec<RiskScoreFactor>,
+}
+
+/// Audits, launch dates and exploits of the protocols opportunities deposit into
+pub struct ProtocolRegistry {
+    protocols: Vec<ProtocolRecord>,
+}
+
+impl ProtocolRegistry {
+    pub fn builtin() -> Result<Self> {
+        Self::from_yaml(BUILTIN_PROTOCOLS)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let protocols: Vec<ProtocolRecord> = serde_yaml::from_str(yaml)
+            .map_err(|e| anyhow!("Invalid protocol registry: {}", e))?;
+
+        for protocol in &protocols {
+            if let Some(exploit) = protocol.exploits.iter().find(|exploit| exploit.loss_usd < 0.0) {
+                return Err(anyhow!("Exploit of {} on {} has a negative loss", protocol.id, exploit.date));
+            }
+        }
+
+        Ok(Self { protocols })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProtocolRecord> {
+        self.protocols.iter().find(|protocol| protocol.matches(name))
+    }
+
+    /// Score an opportunity depositing through `protocols` into a market of `depth`. Smart contract
+    /// risk is the chance any of the protocols fails, each scored from its audits, age and exploits;
+    /// liquidity risk is the weighted mean of the known depth factors
+    pub fn score(&self, protocols: &[String], depth: &MarketDepth) -> YieldRiskScore {
+        let today = Utc::now().date_naive();
+        let mut factors = Vec::new();
+
+        let mut safe = 1.0;
+        for protocol in protocols {
+            let protocol_factors = match self.get(protocol) {
+                Some(record) => record_factors(record, today),
+                None => vec![RiskScoreFactor {
+                    name: "unknown_protocol".to_string(),
+                    category: RiskCategory::SmartContract,
+                    protocol: Some(protocol.clone()),
+                    score: Some(1.0),
+                    weight: 1.0,
+                    detail: format!("{} is not in the protocol registry", protocol),
+                }],
+            };
+            safe *= 1.0 - weighted_mean(&protocol_factors).unwrap_or(1.0);
+            factors.extend(protocol_factors);
+        }
+
+        let depth_factors = depth_factors(depth);
+        let liquidity_risk = weighted_mean(&depth_factors).unwrap_or(UNKNOWN_LIQUIDITY_RISK);
+        factors.extend(depth_factors);
+
+        YieldRiskScore {
+            liquidity_risk,
+            smart_contract_risk: 1.0 - safe,
+            factors,
+        }
+    }
+}
+
+fn years_between(from: NaiveDate, to: NaiveDate) -> f64 {
+    ((to - from).num_days() as f64 / 365.25).max(0.0)
+}
+
+fn record_factors(record: &ProtocolRecord, today: NaiveDate) -> Vec<RiskScoreFactor> {
+    let factor = |name: &str, score: f64, weight: f64, detail: String| RiskScoreFactor {
+        name: name.to_string(),
+        category: RiskCategory::SmartContract,
+        protocol: Some(record.name.clone()),
+        score: Some(score.clamp(0.0, 1.0)),
+        weight,
+        detail,
+    };
+
+    let audits = record.audits.len();
+    let audit_detail = match record.audits.iter().max_by_key(|audit| audit.date) {
+        Some(latest) => format!("{} audits, latest by {} on {}", audits, latest.auditor, latest.date),
+        None => "No audits".to_string(),
+    };
+
+    let age = years_between(record.launched, today);
+
+    // Each exploit counts for less as the code around it matures
+    let exploit_weight: f64 = record.exploits.iter()
+        .map(|exploit| 0.5f64.powf(years_between(exploit.date, today) / EXPLOIT_HALF_LIFE_YEARS))
+        .sum();
+    let exploit_detail = match record.exploits.iter().max_by_key(|exploit| exploit.date) {
+        Some(latest) => format!(
+            "{} exploits, latest on {} losing ${:.0}: {}",
+            record.exploits.len(), latest.date, latest.loss_usd, latest.description
+        ),
+        None => "No known exploits".to_string(),
+    };
+
+    vec![
+        factor("audits", 1.0 / (1.0 + audits as f64), AUDITS_WEIGHT, audit_detail),
+        factor(
+            "protocol_age",
+            0.5f64.powf(age / AGE_HALF_LIFE_YEARS),
+            AGE_WEIGHT,
+            format!("Launched {}, {:.1} years ago", record.launched, age),
+        ),
+        factor("exploits", 1.0 - 0.5f64.powf(exploit_weight), EXPLOITS_WEIGHT, exploit_detail),
+    ]
+}
+
+fn depth_factors(depth: &MarketDepth) -> Vec<RiskScoreFactor> {
+    let factor = |name: &str, score: Option<f64>, weight: f64, detail: String| RiskScoreFactor {
+        name: name.to_string(),
+        category: RiskCategory::Liquidity,
+        protocol: None,
+        score: score.map(|score| score.clamp(0.0, 1.0)),
+        weight,
+        detail,
+    };
+    let tvl = depth.tvl_usd.filter(|tvl| *tvl > 0.0);
+
+    vec![
+        factor(
+            "tvl",
+            tvl.map(|tvl| 1.0 - (tvl / SHALLOW_TVL_USD).max(1.0).log10() / 4.0),
+            TVL_WEIGHT,
+            tvl.map_or("Market deposits unknown".to_string(), |tvl| format!("${:.0} deposited in the market", tvl)),
+        ),
+        factor(
+            "utilization",
+            depth.utilization.map(|utilization| {
+                // Gentle up to the kink, steep past it as withdrawals may find no cash
+                if utilization <= UTILIZATION_KINK {
+                    utilization / UTILIZATION_KINK * 0.3
+                } else {
+                    0.3 + (utilization - UTILIZATION_KINK) / (1.0 - UTILIZATION_KINK) * 0.7
+                }
+            }),
+            UTILIZATION_WEIGHT,
+            depth.utilization.map_or("Utilization unknown".to_string(), |utilization| {
+                format!("{:.1}% of deposits lent out", utilization * 100.0)
+            }),
+        ),
+        factor(
+            "position_share",
+            tvl.zip(depth.deposit_usd).map(|(tvl, deposit)| deposit / tvl / MAX_POSITION_SHARE),
+            POSITION_SHARE_WEIGHT,
+            match (tvl, depth.deposit_usd) {
+                (Some(tvl), Some(deposit)) => format!("The deposit would be {:.2}% of the market", deposit / tvl * 100.0),
+                _ => "Deposit value or market deposits unknown".to_string(),
+            },
+        ),
+    ]
+}
+
+/// Mean of the known factor scores by weight, `None` when none is known
+fn weighted_mean(factors: &[RiskScoreFactor]) -> Option<f64> {
+    let (sum, weights) = factors.iter()
+        .filter_map(|factor| Some((factor.score? * factor.weight, factor.weight)))
+        .fold((0.0, 0.0), |(sum, weights), (score, weight)| (sum + score, weights + weight));
+    (weights > 0.0).then(|| sum / weights)
+}
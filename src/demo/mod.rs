@@ -0,0 +1,327 @@
+// Scripted end-to-end demo scenarios
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::defi::DefiManager;
+use crate::dex::DexManager;
+use crate::security::PolicyContext;
+use crate::wallets::WalletManager;
+
+/// Mainnet WETH
+const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+/// Mainnet USDC
+const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+/// A single scripted action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioStep {
+    ConnectWallet,
+    /// Credit the scenario wallet's simulated balance
+    Fund { token: Address, amount: U256 },
+    Quote { token_in: Address, token_out: Address, amount: U256 },
+    Swap { token_in: Address, token_out: Address, amount: U256 },
+    Supply { protocol: String, asset: Address, amount: U256 },
+    Rebalance {
+        #[serde(with = "crate::api::models::sorted_map")]
+        target_allocation: HashMap<String, f64>,
+    },
+    Report,
+}
+
+impl ScenarioStep {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScenarioStep::ConnectWallet => "connect_wallet",
+            ScenarioStep::Fund { .. } => "fund",
+            ScenarioStep::Quote { .. } => "quote",
+            ScenarioStep::Swap { .. } => "swap",
+            ScenarioStep::Supply { .. } => "supply",
+            ScenarioStep::Rebalance { .. } => "rebalance",
+            ScenarioStep::Report => "report",
+        }
+    }
+}
+
+/// Outcome of a scenario step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// Trace entry for one executed step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepTrace {
+    pub index: usize,
+    pub step: ScenarioStep,
+    pub name: String,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    pub output: Value,
+    pub error: Option<String>,
+}
+
+/// Step-by-step result of a scenario run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioTrace {
+    pub scenario: String,
+    pub chain_id: u64,
+    pub wallet: Option<Address>,
+    pub success: bool,
+    pub steps: Vec<StepTrace>,
+    /// Simulated token balances of the scenario wallet after the run
+    #[serde(with = "crate::api::models::sorted_map")]
+    pub balances: HashMap<Address, U256>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Scenario run options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioOptions {
+    pub chain_id: u64,
+    /// Skip the remaining steps after the first failure
+    pub stop_on_failure: bool,
+}
+
+impl Default for ScenarioOptions {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            stop_on_failure: true,
+        }
+    }
+}
+
+/// Mutable state threaded through a scenario's steps
+#[derive(Default)]
+struct ScenarioContext {
+    /// Name of the scenario, the strategy its transactions are signed for
+    scenario: String,
+    wallet: Option<Address>,
+    balances: HashMap<Address, U256>,
+}
+
+impl ScenarioContext {
+    fn wallet(&self) -> Result<Address> {
+        self.wallet.ok_or_else(|| anyhow!("No wallet connected, run connect_wallet first"))
+    }
+
+    fn debit(&mut self, token: Address, amount: U256) -> Result<()> {
+        let balance = self.balances.entry(token).or_default();
+        if *balance < amount {
+            return Err(anyhow!("Insufficient simulated balance of {:?}: have {}, need {}", token, balance, amount));
+        }
+        *balance -= amount;
+        Ok(())
+    }
+
+    fn credit(&mut self, token: Address, amount: U256) {
+        *self.balances.entry(token).or_default() += amount;
+    }
+}
+
+/// Runs named end-to-end flows against the demo managers and records a trace
+pub struct ScenarioRunner {
+    wallet_manager: Arc<WalletManager>,
+    dex_manager: Arc<DexManager>,
+    defi_manager: Arc<DefiManager>,
+}
+
+impl ScenarioRunner {
+    pub fn new(
+        wallet_manager: Arc<WalletManager>,
+        dex_manager: Arc<DexManager>,
+        defi_manager: Arc<DefiManager>,
+    ) -> Self {
+        Self {
+            wallet_manager,
+            dex_manager,
+            defi_manager,
+        }
+    }
+
+    /// Names of the built-in scenarios
+    pub fn list_scenarios() -> Vec<&'static str> {
+        vec!["full_flow", "swap", "lending"]
+    }
+
+    /// Steps of a built-in scenario
+    pub fn scenario(name: &str) -> Option<Vec<ScenarioStep>> {
+        let weth: Address = WETH.parse().ok()?;
+        let usdc: Address = USDC.parse().ok()?;
+        let one_eth = U256::exp10(18);
+        let thousand_usdc = U256::from(1000) * U256::exp10(6);
+
+        let steps = match name {
+            "full_flow" => vec![
+                ScenarioStep::ConnectWallet,
+                ScenarioStep::Fund { token: weth, amount: one_eth * 5 },
+                ScenarioStep::Quote { token_in: weth, token_out: usdc, amount: one_eth },
+                ScenarioStep::Swap { token_in: weth, token_out: usdc, amount: one_eth },
+                ScenarioStep::Supply { protocol: "aave".to_string(), asset: weth, amount: one_eth * 2 },
+                ScenarioStep::Rebalance {
+                    target_allocation: HashMap::from([("aave".to_string(), 0.6), ("compound".to_string(), 0.4)]),
+                },
+                ScenarioStep::Report,
+            ],
+            "swap" => vec![
+                ScenarioStep::ConnectWallet,
+                ScenarioStep::Fund { token: weth, amount: one_eth },
+                ScenarioStep::Quote { token_in: weth, token_out: usdc, amount: one_eth },
+                ScenarioStep::Swap { token_in: weth, token_out: usdc, amount: one_eth },
+                ScenarioStep::Report,
+            ],
+            "lending" => vec![
+                ScenarioStep::ConnectWallet,
+                ScenarioStep::Fund { token: usdc, amount: thousand_usdc * 10 },
+                ScenarioStep::Supply { protocol: "aave".to_string(), asset: usdc, amount: thousand_usdc * 5 },
+                ScenarioStep::Supply { protocol: "compound".to_string(), asset: usdc, amount: thousand_usdc * 5 },
+                ScenarioStep::Report,
+            ],
+            _ => return None,
+        };
+
+        Some(steps)
+    }
+
+    /// Run a built-in scenario by name
+    pub async fn run(&self, name: &str, options: ScenarioOptions) -> Result<ScenarioTrace> {
+        let steps = Self::scenario(name).ok_or_else(|| anyhow!("Unknown scenario: {}", name))?;
+        Ok(self.run_steps(name, steps, options).await)
+    }
+
+    /// Run a list of steps, recording the outcome of each
+    pub async fn run_steps(&self, name: &str, steps: Vec<ScenarioStep>, options: ScenarioOptions) -> ScenarioTrace {
+        info!("Running demo scenario {} on chain {}", name, options.chain_id);
+        let started_at = Utc::now();
+        let mut context = ScenarioContext { scenario: name.to_string(), ..ScenarioContext::default() };
+        let mut traces = Vec::with_capacity(steps.len());
+        let mut failed = false;
+
+        for (index, step) in steps.into_iter().enumerate() {
+            let step_name = step.name().to_string();
+
+            if failed && options.stop_on_failure {
+                traces.push(StepTrace {
+                    index,
+                    step,
+                    name: step_name.clone(),
+                    status: StepStatus::Skipped,
+                    duration_ms: 0,
+                    output: Value::Null,
+                    error: None,
+                });
+                continue;
+            }
+
+            let started = Instant::now();
+            let result = self.run_step(options.chain_id, &step, &mut context).await;
+            let duration_ms = started.elapsed().as_millis() as u64;
+
+            let trace = match result {
+                Ok(output) => StepTrace {
+                    index,
+                    step,
+                    name: step_name.clone(),
+                    status: StepStatus::Passed,
+                    duration_ms,
+                    output,
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("Scenario {} step {} ({}) failed: {}", name, index, step_name, e);
+                    failed = true;
+                    StepTrace {
+                        index,
+                        step,
+                        name: step_name,
+                        status: StepStatus::Failed,
+                        duration_ms,
+                        output: Value::Null,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            traces.push(trace);
+        }
+
+        ScenarioTrace {
+            scenario: name.to_string(),
+            chain_id: options.chain_id,
+            wallet: context.wallet,
+            success: !failed,
+            steps: traces,
+            balances: context.balances,
+            started_at,
+            finished_at: Utc::now(),
+        }
+    }
+
+    async fn run_step(&self, chain_id: u64, step: &ScenarioStep, context: &mut ScenarioContext) -> Result<Value> {
+        match step {
+            ScenarioStep::ConnectWallet => {
+                let address = self.wallet_manager.create_local_wallet(None).await?;
+                context.wallet = Some(address);
+                Ok(json!({ "address": address }))
+            }
+            ScenarioStep::Fund { token, amount } => {
+                context.wallet()?;
+                context.credit(*token, *amount);
+                Ok(json!({ "token": token, "amount": amount, "balance": context.balances[token] }))
+            }
+            ScenarioStep::Quote { token_in, token_out, amount } => {
+                let wallet = context.wallet()?;
+                let quotes = self.dex_manager
+                    .get_comprehensive_quotes(chain_id, *token_in, *token_out, *amount, wallet)
+                    .await?;
+                Ok(serde_json::to_value(quotes)?)
+            }
+            ScenarioStep::Swap { token_in, token_out, amount } => {
+                let wallet = context.wallet()?;
+                context.debit(*token_in, *amount)?;
+
+                let swap = self.dex_manager
+                    .execute_optimal_swap(chain_id, *token_in, *token_out, *amount, wallet, None)
+                    .await?;
+                // Signing runs the security manager's transaction checks
+                let tx = TypedTransaction::Legacy(swap.transaction.clone().chain_id(chain_id));
+                let policy = PolicyContext { strategy_id: Some(context.scenario.clone()), override_id: None };
+                let signature = self.wallet_manager.sign_transaction(wallet, tx, &policy).await?;
+
+                context.credit(*token_out, swap.expected_output);
+                Ok(json!({ "swap": swap, "signature": signature.to_string() }))
+            }
+            ScenarioStep::Supply { protocol, asset, amount } => {
+                let wallet = context.wallet()?;
+                context.debit(*asset, *amount)?;
+
+                let tx_hash = self.defi_manager
+                    .supply_asset(chain_id, protocol.clone(), *asset, *amount, wallet)
+                    .await?;
+                Ok(json!({ "protocol": protocol, "asset": asset, "amount": amount, "tx_hash": tx_hash }))
+            }
+            ScenarioStep::Rebalance { target_allocation } => {
+                let wallet = context.wallet()?;
+                let transactions = self.defi_manager
+                    .rebalance_portfolio(chain_id, wallet, target_allocation.clone())
+                    .await?;
+                Ok(json!({ "transactions": transactions }))
+            }
+            ScenarioStep::Report => {
+                let wallet = context.wallet()?;
+                let portfolio = self.defi_manager.get_portfolio_overview(chain_id, wallet).await?;
+                Ok(json!({ "portfolio": portfolio, "balances": context.balances }))
+            }
+        }
+    }
+}
@@ -1,11 +1,50 @@
 use anyhow::{Result, anyhow};
-use ethers::types::{Address, U256, TransactionRequest};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, U256, U512, TransactionRequest};
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
 use tracing::{info, warn, error};
 
+pub mod external;
+
+use self::external::{ExternalAdvantage, ExternalAggregatorConfig, ExternalAggregators, ExternalQuote, ExternalQuoteRequest};
+use crate::chains::assets::{AssetCompatibility, AssetRegistry};
 use crate::dex::uniswap::{UniswapV3Manager, SwapParams as UniswapSwapParams};
 use crate::dex::sushiswap::SushiSwapManager;
+use crate::dex::uniswap_v2::UniswapV2Manager;
+use crate::dex::quote_accuracy::{QuoteSample, VenueQuoteAccuracy, MAX_SAMPLES_PER_VENUE};
+use crate::security::MevThreat;
+
+/// How long a single venue may take to quote before it is skipped
+const DEFAULT_VENUE_QUOTE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Mid-price samples kept per pool for the volatility estimate
+const MAX_PRICE_SAMPLES: usize = 32;
+/// Samples older than this no longer describe a pool's recent volatility
+const PRICE_SAMPLE_WINDOW: Duration = Duration::from_secs(3600);
+/// Standard deviations of price movement a tolerance should absorb before inclusion
+const VOLATILITY_BUFFER_MULTIPLIER: f64 = 2.0;
+/// Share of the trade's own price impact added as a buffer against competing flow in thin pools
+const DEPTH_BUFFER_FACTOR: f64 = 0.25;
+/// Headroom against reverts when no sandwiches can take advantage of it
+const REVERT_HEADROOM_MULTIPLIER: f64 = 1.25;
+/// Sandwich rate above which private submission is recommended
+const PRIVATE_SUBMISSION_SANDWICH_RATE: f64 = 0.1;
+/// Oldest comparison a fast quote may be scaled from
+const FAST_QUOTE_MAX_AGE: Duration = Duration::from_secs(300);
+const MIN_RECOMMENDED_SLIPPAGE: f64 = 0.1;
+const MAX_RECOMMENDED_SLIPPAGE: f64 = 5.0;
+/// Widest tolerance a swap may be built with
+pub const MAX_SLIPPAGE_PERCENTAGE: f64 = 50.0;
+pub const DEFAULT_DEADLINE_MINUTES: u64 = 20;
+/// Longest a signed swap may stay valid, so a stale transaction cannot settle at old prices
+pub const MAX_DEADLINE_MINUTES: u64 = 60;
 
 /// Best route information
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,14 +55,54 @@ pub struct BestRoute {
     pub price_impact: f64,
     pub gas_estimate: U256,
     pub path: Vec<Address>,
+    /// Uniswap V3 pool fee the route swaps through
+    #[serde(default)]
+    pub fee_tier: Option<u32>,
     pub transaction: TransactionRequest,
 }
 
+impl BestRoute {
+    pub fn quote(&self) -> Quote {
+        Quote {
+            dex: self.dex.clone(),
+            input_amount: self.input_amount,
+            output_amount: self.output_amount,
+            price_impact: self.price_impact,
+            gas_estimate: self.gas_estimate,
+            path: self.path.clone(),
+            fee_tier: self.fee_tier,
+        }
+    }
+}
+
 /// Available DEX types
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum DexType {
     UniswapV3,
     SushiSwap,
+    UniswapV2,
+}
+
+/// Parses the names venues are reported under, e.g. `dex_used`
+impl FromStr for DexType {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "UniswapV3" => Ok(Self::UniswapV3),
+            "SushiSwap" => Ok(Self::SushiSwap),
+            "UniswapV2" => Ok(Self::UniswapV2),
+            _ => Err(anyhow!("Unknown DEX {}", name)),
+        }
+    }
+}
+
+/// Managers of the venues the aggregator quotes and routes through
+#[derive(Clone, Copy)]
+pub struct DexVenues<'a> {
+    pub uniswap: &'a UniswapV3Manager,
+    pub sushiswap: &'a SushiSwapManager,
+    pub uniswap_v2: &'a UniswapV2Manager,
 }
 
 /// Quote comparison result
@@ -31,8 +110,87 @@ pub enum DexType {
 pub struct QuoteComparison {
     pub uniswap_v3: Option<Quote>,
     pub sushiswap: Option<Quote>,
+    pub uniswap_v2: Option<Quote>,
     pub best_route: BestRoute,
+    /// Expected output of the best route over the best other venue's, both discounted by the
+    /// venues' measured quote shortfall; zero with a single venue
     pub savings_percentage: f64,
+    /// Share of the best venue's measured swaps that paid within the slippage tolerance, `None`
+    /// until enough were measured
+    #[serde(default)]
+    pub routing_confidence: Option<f64>,
+    /// Outcome of every venue queried, including the ones that failed
+    pub venues: Vec<VenueQuoteResult>,
+    /// Quotes from the configured external aggregators
+    pub external_quotes: Vec<ExternalQuote>,
+    /// Best external quote when it pays more than `best_route`, with its router calldata
+    pub external_advantage: Option<ExternalAdvantage>,
+}
+
+/// Trade-off between latency and accuracy of a quote
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteMode {
+    /// Served from cached comparisons and approximate math without chain reads
+    Fast,
+    /// Fresh quotes from every venue
+    #[default]
+    Exact,
+}
+
+/// How current the data behind a quote is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteFreshness {
+    /// When the venues were quoted
+    pub quoted_at: DateTime<Utc>,
+    pub age_ms: u64,
+    /// Scaled from a comparison for another amount, external quotes are left out
+    pub approximate: bool,
+}
+
+/// Comparison with the mode it was served in; fast requests without cached data are served exact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServedQuote {
+    pub requested_mode: QuoteMode,
+    pub mode: QuoteMode,
+    pub freshness: QuoteFreshness,
+    #[serde(flatten)]
+    pub comparison: QuoteComparison,
+}
+
+/// Swap a comparison is made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RouteKey {
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub recipient: Address,
+}
+
+/// Exact comparison kept for fast quotes
+#[derive(Debug, Clone)]
+struct CachedComparison {
+    comparison: QuoteComparison,
+    quoted_at: DateTime<Utc>,
+    fetched: Instant,
+}
+
+/// Outcome of querying a single venue
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VenueQuoteStatus {
+    Ok,
+    Timeout,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VenueQuoteResult {
+    pub dex: DexType,
+    pub status: VenueQuoteStatus,
+    pub latency_ms: u64,
+    pub error: Option<String>,
 }
 
 /// Individual DEX quote
@@ -44,6 +202,9 @@ pub struct Quote {
     pub price_impact: f64,
     pub gas_estimate: U256,
     pub path: Vec<Address>,
+    /// Uniswap V3 pool fee the quote was taken from
+    #[serde(default)]
+    pub fee_tier: Option<u32>,
 }
 
 /// Slippage protection settings
@@ -58,12 +219,126 @@ impl Default for SlippageSettings {
     fn default() -> Self {
         Self {
             max_slippage_percentage: 0.5, // 0.5%
-            deadline_minutes: 20,         // 20 minutes
+            deadline_minutes: DEFAULT_DEADLINE_MINUTES,
             mev_protection: true,
         }
     }
 }
 
+impl SlippageSettings {
+    /// Tolerance from 0% to 50% and a deadline of 1 to 60 minutes
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=MAX_SLIPPAGE_PERCENTAGE).contains(&self.max_slippage_percentage) {
+            return Err(anyhow!("Maximum slippage must be between 0% and {}%", MAX_SLIPPAGE_PERCENTAGE));
+        }
+        if !(1..=MAX_DEADLINE_MINUTES).contains(&self.deadline_minutes) {
+            return Err(anyhow!("Swap deadline must be between 1 and {} minutes", MAX_DEADLINE_MINUTES));
+        }
+        Ok(())
+    }
+
+    /// Least a swap quoted at `quoted` may return
+    pub fn min_amount_out(&self, quoted: U256) -> U256 {
+        min_amount_out(quoted, self.max_slippage_percentage)
+    }
+
+    /// Unix time a swap built now stops being valid
+    pub fn deadline(&self) -> u64 {
+        swap_deadline(self.deadline_minutes)
+    }
+
+    /// Tolerance covering the price movement expected before inclusion, without the slack a
+    /// sandwich could extract. The static `max_slippage_percentage` is only used when the pool
+    /// has no price history yet.
+    pub fn recommend(&self, dex: DexType, signals: PoolSlippageSignals) -> SlippageRecommendation {
+        let mut rationale = Vec::new();
+
+        let volatility_buffer = if signals.price_samples < 2 {
+            rationale.push(format!(
+                "No recent price history for this pool, using the default {:.2}% as the volatility buffer",
+                self.max_slippage_percentage
+            ));
+            self.max_slippage_percentage
+        } else {
+            let buffer = signals.volatility_percentage * VOLATILITY_BUFFER_MULTIPLIER;
+            rationale.push(format!(
+                "Mid price moved {:.3}% between the last {} quotes, {:.2}% covers {} standard deviations",
+                signals.volatility_percentage, signals.price_samples, buffer, VOLATILITY_BUFFER_MULTIPLIER
+            ));
+            buffer
+        };
+
+        let depth_buffer = signals.price_impact * DEPTH_BUFFER_FACTOR;
+        if depth_buffer >= 0.01 {
+            rationale.push(format!(
+                "Trade moves the pool {:.2}%, competing flow in a pool this thin adds {:.2}%",
+                signals.price_impact, depth_buffer
+            ));
+        }
+
+        let needed = volatility_buffer + depth_buffer;
+        let mev_protection = self.mev_protection || signals.sandwich_rate >= PRIVATE_SUBMISSION_SANDWICH_RATE;
+        let slippage = if signals.sandwich_rate > 0.0 && !mev_protection {
+            rationale.push(format!(
+                "{:.0}% of analyzed executions on {:?} were sandwiched (average loss {:.1} bps), \
+                 no headroom added since any tolerance beyond the expected movement is extractable",
+                signals.sandwich_rate * 100.0, dex, signals.mev_loss_bps
+            ));
+            needed
+        } else {
+            if signals.sandwich_rate > 0.0 {
+                rationale.push(format!(
+                    "{:.0}% of analyzed executions on {:?} were sandwiched (average loss {:.1} bps), \
+                     submit privately so the headroom is not visible to searchers",
+                    signals.sandwich_rate * 100.0, dex, signals.mev_loss_bps
+                ));
+            } else {
+                rationale.push(format!("No sandwiches observed on {:?}, adding headroom against reverts", dex));
+            }
+            needed * REVERT_HEADROOM_MULTIPLIER
+        };
+
+        let clamped = slippage.clamp(MIN_RECOMMENDED_SLIPPAGE, MAX_RECOMMENDED_SLIPPAGE);
+        if clamped != slippage {
+            rationale.push(format!(
+                "Clamped {:.2}% to the {:.1}%-{:.1}% range",
+                slippage, MIN_RECOMMENDED_SLIPPAGE, MAX_RECOMMENDED_SLIPPAGE
+            ));
+        }
+
+        SlippageRecommendation {
+            dex,
+            slippage_percentage: (clamped * 100.0).round() / 100.0,
+            mev_protection,
+            signals,
+            rationale,
+        }
+    }
+}
+
+/// Pool conditions a slippage recommendation is derived from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoolSlippageSignals {
+    /// Root mean square change of the pool's mid price between recent quotes, in percent
+    pub volatility_percentage: f64,
+    pub price_samples: usize,
+    /// Price impact of the trade itself, a proxy for pool depth
+    pub price_impact: f64,
+    /// Share of analyzed executions on the venue that were sandwiched
+    pub sandwich_rate: f64,
+    pub mev_loss_bps: f64,
+}
+
+/// Recommended slippage for a pool and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlippageRecommendation {
+    pub dex: DexType,
+    pub slippage_percentage: f64,
+    pub mev_protection: bool,
+    pub signals: PoolSlippageSignals,
+    pub rationale: Vec<String>,
+}
+
 /// MEV protection strategy
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MevProtection {
@@ -73,28 +348,70 @@ pub enum MevProtection {
     CommitReveal,
 }
 
+/// Post-execution MEV history for a venue
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VenueMevStats {
+    pub executions_analyzed: u64,
+    pub sandwiches_detected: u64,
+    pub total_extracted_bps: f64,
+}
+
+impl VenueMevStats {
+    /// Average value lost to MEV per execution, in basis points of output
+    pub fn expected_loss_bps(&self) -> f64 {
+        if self.executions_analyzed == 0 {
+            return 0.0;
+        }
+        self.total_extracted_bps / self.executions_analyzed as f64
+    }
+
+    /// Share of analyzed executions that were sandwiched
+    pub fn sandwich_rate(&self) -> f64 {
+        if self.executions_analyzed == 0 {
+            return 0.0;
+        }
+        self.sandwiches_detected as f64 / self.executions_analyzed as f64
+    }
+}
+
 pub struct DexAggregator {
-    price_cache: HashMap<String, (U256, std::time::Instant)>,
+    /// Exact comparisons, served unchanged for the same swap within `cache_duration`
+    quote_cache: Arc<RwLock<HashMap<RouteKey, CachedComparison>>>,
     cache_duration: std::time::Duration,
     slippage_settings: SlippageSettings,
+    venue_mev_stats: Arc<RwLock<HashMap<DexType, VenueMevStats>>>,
+    /// Quoted outputs against simulated and settled ones, most recent last
+    quote_samples: Arc<RwLock<HashMap<DexType, VecDeque<QuoteSample>>>>,
+    /// Recent mid prices per (venue, token in, token out)
+    price_samples: Arc<RwLock<HashMap<(DexType, Address, Address), VecDeque<(Instant, f64)>>>>,
+    venue_quote_timeout: Duration,
+    /// 1inch and 0x, queried alongside the local venues when keys are configured
+    external: ExternalAggregators,
+    /// Tells which pairs are the same asset and should trade at parity
+    assets: Arc<AssetRegistry>,
 }
 
 impl DexAggregator {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(external: ExternalAggregatorConfig, assets: Arc<AssetRegistry>) -> Result<Self> {
         info!("Initializing DEX Aggregator");
 
         Ok(Self {
-            price_cache: HashMap::new(),
+            quote_cache: Arc::new(RwLock::new(HashMap::new())),
             cache_duration: std::time::Duration::from_secs(30), // 30 second cache
             slippage_settings: SlippageSettings::default(),
+            venue_mev_stats: Arc::new(RwLock::new(HashMap::new())),
+            quote_samples: Arc::new(RwLock::new(HashMap::new())),
+            price_samples: Arc::new(RwLock::new(HashMap::new())),
+            venue_quote_timeout: DEFAULT_VENUE_QUOTE_TIMEOUT,
+            external: ExternalAggregators::new(external),
+            assets,
         })
     }
 
     /// Find the best route for a swap across all DEXes
     pub async fn find_best_route(
         &self,
-        uniswap: &UniswapV3Manager,
-        sushiswap: &SushiSwapManager,
+        dexes: DexVenues<'_>,
         chain_id: u64,
         token_in: Address,
         token_out: Address,
@@ -103,57 +420,83 @@ impl DexAggregator {
     ) -> Result<QuoteComparison> {
         info!("Finding best route for swap: {} {} -> {}", amount_in, token_in, token_out);
 
-        let mut quotes = Vec::new();
-
-        // Get Uniswap V3 quote (try different fee tiers)
-        let uniswap_quote = self.get_uniswap_quote(
-            uniswap, chain_id, token_in, token_out, amount_in, recipient
-        ).await;
-
-        if let Ok(quote) = uniswap_quote {
-            quotes.push(quote);
-        }
+        let external_request = ExternalQuoteRequest {
+            chain_id,
+            token_in,
+            token_out,
+            amount_in,
+            taker: recipient,
+            slippage_percentage: self.slippage_settings.max_slippage_percentage,
+        };
 
-        // Get SushiSwap quote
-        let sushiswap_quote = self.get_sushiswap_quote(
-            sushiswap, chain_id, token_in, token_out, amount_in, recipient
-        ).await;
+        // Query every venue at once so a slow or failing venue cannot hold up the others
+        let (uniswap_result, sushiswap_result, uniswap_v2_result, external_quotes) = tokio::join!(
+            self.quote_venue(
+                DexType::UniswapV3,
+                self.get_uniswap_quote(dexes.uniswap, chain_id, token_in, token_out, amount_in, recipient),
+            ),
+            self.quote_venue(
+                DexType::SushiSwap,
+                self.get_sushiswap_quote(dexes.sushiswap, chain_id, token_in, token_out, amount_in, recipient),
+            ),
+            // Long-tail tokens often have no other pool
+            self.quote_venue(
+                DexType::UniswapV2,
+                self.get_uniswap_v2_quote(dexes.uniswap_v2, chain_id, token_in, token_out, amount_in),
+            ),
+            async {
+                if self.external.is_enabled() {
+                    self.external.quote_all(&external_request).await
+                } else {
+                    Vec::new()
+                }
+            },
+        );
 
-        if let Ok(quote) = sushiswap_quote {
-            quotes.push(quote);
+        let mut quotes = Vec::new();
+        let mut venues = Vec::new();
+        for (quote, venue) in [uniswap_result, sushiswap_result, uniswap_v2_result] {
+            quotes.extend(quote);
+            venues.push(venue);
         }
 
         if quotes.is_empty() {
-            return Err(anyhow!("No valid quotes found from any DEX"));
+            let failures = venues.iter()
+                .map(|venue| format!("{:?}: {}", venue.dex, venue.error.as_deref().unwrap_or("no quote")))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(anyhow!("No valid quotes found from any DEX ({})", failures));
         }
+        self.record_price_samples(&quotes, token_in, token_out).await;
 
-        // Find best quote (highest output amount considering gas costs)
+        // Find best quote (highest output amount considering gas costs, observed MEV losses and
+        // how far the venue's quotes have fallen short)
+        let mev_stats = self.venue_mev_stats.read().await.clone();
+        let accuracy = self.get_quote_accuracy().await;
         let best_quote = quotes
             .clone()
             .into_iter()
             .max_by(|a, b| {
-                // Adjust for gas costs (simplified calculation)
-                let a_adjusted = a.output_amount.saturating_sub(a.gas_estimate * U256::from(20_000_000_000u64));
-                let b_adjusted = b.output_amount.saturating_sub(b.gas_estimate * U256::from(20_000_000_000u64));
+                let a_adjusted = self.adjusted_output(a, &mev_stats, &accuracy);
+                let b_adjusted = self.adjusted_output(b, &mev_stats, &accuracy);
                 a_adjusted.cmp(&b_adjusted)
             })
             .unwrap();
 
-        // Calculate savings compared to worst option
-        let worst_output = quotes.iter()
-            .min_by_key(|q| q.output_amount)
-            .map(|q| q.output_amount)
-            .unwrap_or_else(|| best_quote.output_amount);
-
-        let savings_percentage = if worst_output > U256::zero() {
-            ((best_quote.output_amount - worst_output).as_u128() as f64 / worst_output.as_u128() as f64) * 100.0
-        } else {
-            0.0
-        };
+        // Savings against the best alternative, not the worst venue, counting only what each is expected to pay
+        let best_expected = expected_output(&best_quote, &accuracy);
+        let savings_percentage = quotes.iter()
+            .filter(|quote| quote.dex != best_quote.dex)
+            .map(|quote| expected_output(quote, &accuracy))
+            .max()
+            .filter(|alternative| !alternative.is_zero() && *alternative < best_expected)
+            .map(|alternative| u256_to_f64(best_expected - alternative) / u256_to_f64(alternative) * 100.0)
+            .unwrap_or(0.0);
+        let routing_confidence = accuracy.get(&best_quote.dex).and_then(|venue| venue.confidence);
 
         // Create transaction for best route
         let transaction = self.create_transaction_for_quote(
-            uniswap, sushiswap, chain_id, &best_quote, recipient
+            dexes, chain_id, &best_quote, recipient, &self.slippage_settings
         ).await?;
 
         let best_route = BestRoute {
@@ -163,25 +506,124 @@ impl DexAggregator {
             price_impact: best_quote.price_impact,
             gas_estimate: best_quote.gas_estimate,
             path: best_quote.path.clone(),
+            fee_tier: best_quote.fee_tier,
             transaction,
         };
 
+        let external_advantage = external_quotes.iter()
+            .filter(|quote| quote.output_amount > best_route.output_amount)
+            .max_by_key(|quote| quote.output_amount)
+            .map(|quote| ExternalAdvantage {
+                quote: quote.clone(),
+                improvement_percentage: u256_to_f64(quote.output_amount - best_route.output_amount)
+                    / u256_to_f64(best_route.output_amount).max(1.0) * 100.0,
+            });
+        if let Some(advantage) = &external_advantage {
+            info!(
+                "{:?} beats the local {:?} route by {:.3}%",
+                advantage.quote.aggregator, best_route.dex, advantage.improvement_percentage
+            );
+        }
+
         let comparison = QuoteComparison {
             uniswap_v3: quotes.iter().find(|q| q.dex == DexType::UniswapV3).cloned(),
             sushiswap: quotes.iter().find(|q| q.dex == DexType::SushiSwap).cloned(),
+            uniswap_v2: quotes.iter().find(|q| q.dex == DexType::UniswapV2).cloned(),
             best_route,
             savings_percentage,
+            routing_confidence,
+            venues,
+            external_quotes,
+            external_advantage,
         };
 
         info!("Best route found: {:?} with {}% savings", comparison.best_route.dex, savings_percentage);
+        self.cache_comparison(
+            RouteKey { chain_id, token_in, token_out, amount_in, recipient },
+            &comparison,
+        ).await;
         Ok(comparison)
     }
 
+    /// Quote from the cache without chain reads: the comparison for the same swap while younger
+    /// than the cache duration, else the freshest one for the pair scaled to the amount
+    pub async fn fast_route(
+        &self,
+        dexes: DexVenues<'_>,
+        key: RouteKey,
+    ) -> Option<(QuoteComparison, QuoteFreshness)> {
+        let cached = {
+            let cache = self.quote_cache.read().await;
+            match cache.get(&key).filter(|cached| cached.fetched.elapsed() <= self.cache_duration) {
+                Some(cached) => return Some((cached.comparison.clone(), cached.freshness(false))),
+                None => cache.iter()
+                    .filter(|(cached_key, cached)| {
+                        (cached_key.chain_id, cached_key.token_in, cached_key.token_out) == (key.chain_id, key.token_in, key.token_out)
+                            && !cached_key.amount_in.is_zero()
+                            && cached.fetched.elapsed() <= FAST_QUOTE_MAX_AGE
+                    })
+                    .max_by_key(|(_, cached)| cached.fetched)
+                    .map(|(cached_key, cached)| (cached_key.amount_in, cached.clone()))?,
+            }
+        };
+        let (cached_amount, cached) = cached;
+        let freshness = cached.freshness(true);
+
+        // Output scales with the amount; impact grows roughly linearly for trades the pool can absorb
+        let ratio = u256_to_f64(key.amount_in) / u256_to_f64(cached_amount);
+        let scale = |quote: &Quote| Quote {
+            input_amount: key.amount_in,
+            output_amount: scale_amount(quote.output_amount, key.amount_in, cached_amount),
+            price_impact: (quote.price_impact * ratio).min(50.0),
+            ..quote.clone()
+        };
+        let comparison = cached.comparison;
+        let best_route = &comparison.best_route;
+        let best_quote = scale(&best_route.quote());
+        // Calldata is encoded locally, the minimum output follows the scaled quote
+        let transaction = match self.create_transaction_for_quote(dexes, key.chain_id, &best_quote, key.recipient, &self.slippage_settings).await {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                warn!("Could not build a fast quote transaction: {}", e);
+                return None;
+            }
+        };
+
+        let scaled = QuoteComparison {
+            uniswap_v3: comparison.uniswap_v3.as_ref().map(scale),
+            sushiswap: comparison.sushiswap.as_ref().map(scale),
+            uniswap_v2: comparison.uniswap_v2.as_ref().map(scale),
+            best_route: BestRoute {
+                input_amount: best_quote.input_amount,
+                output_amount: best_quote.output_amount,
+                price_impact: best_quote.price_impact,
+                transaction,
+                ..comparison.best_route.clone()
+            },
+            savings_percentage: comparison.savings_percentage,
+            routing_confidence: comparison.routing_confidence,
+            venues: comparison.venues.clone(),
+            // External calldata is bound to the amount it was quoted for
+            external_quotes: Vec::new(),
+            external_advantage: None,
+        };
+        Some((scaled, freshness))
+    }
+
+    async fn cache_comparison(&self, key: RouteKey, comparison: &QuoteComparison) {
+        let mut cache = self.quote_cache.write().await;
+        cache.retain(|_, cached| cached.fetched.elapsed() <= FAST_QUOTE_MAX_AGE);
+        cache.insert(key, CachedComparison {
+            comparison: comparison.clone(),
+            quoted_at: Utc::now(),
+            fetched: Instant::now(),
+        });
+    }
+
     /// Execute optimal swap with slippage protection
     pub async fn execute_optimal_swap(
         &self,
-        uniswap: &UniswapV3Manager,
-        sushiswap: &SushiSwapManager,
+        dexes: DexVenues<'_>,
         chain_id: u64,
         token_in: Address,
         token_out: Address,
@@ -189,24 +631,40 @@ impl DexAggregator {
         recipient: Address,
         slippage_settings: Option<SlippageSettings>,
     ) -> Result<TransactionRequest> {
-        let settings = slippage_settings.unwrap_or_else(|| self.slippage_settings.clone());
-        
+        if let Some(settings) = &slippage_settings {
+            settings.validate()?;
+        }
+
         // Find best route
         let comparison = self.find_best_route(
-            uniswap, sushiswap, chain_id, token_in, token_out, amount_in, recipient
+            dexes, chain_id, token_in, token_out, amount_in, recipient
         ).await?;
 
-        // Apply slippage protection
-        let min_amount_out = self.calculate_min_amount_out(
-            comparison.best_route.output_amount,
-            settings.max_slippage_percentage,
-        );
+        // Without explicit settings the tolerance follows the pool's conditions
+        let settings = match slippage_settings {
+            Some(settings) => settings,
+            None => {
+                let recommendation = self.recommend_slippage(
+                    comparison.best_route.dex.clone(), token_in, token_out, comparison.best_route.price_impact
+                ).await;
+                info!("Recommended slippage {}%: {}", recommendation.slippage_percentage, recommendation.rationale.join("; "));
+                SlippageSettings {
+                    max_slippage_percentage: recommendation.slippage_percentage,
+                    mev_protection: recommendation.mev_protection,
+                    ..self.slippage_settings.clone()
+                }
+            }
+        };
 
-        info!("Executing optimal swap with slippage protection: min_amount_out = {}", min_amount_out);
+        // The route was built with the default tolerance, rebuild it with the swap's own
+        let mut tx = self.create_transaction_for_quote(
+            dexes, chain_id, &comparison.best_route.quote(), recipient, &settings
+        ).await?;
+        info!(
+            "Executing optimal swap with slippage protection: min_amount_out = {}",
+            settings.min_amount_out(comparison.best_route.output_amount)
+        );
 
-        // Create protected transaction
-        let mut tx = comparison.best_route.transaction;
-        
         // Add MEV protection if enabled
         if settings.mev_protection {
             tx = self.add_mev_protection(tx, MevProtection::PrivateMempool).await?;
@@ -215,11 +673,45 @@ impl DexAggregator {
         Ok(tx)
     }
 
+    /// Best Uniswap V3 or V2 quote for a swap with the minimum output its slippage tolerance
+    /// allows, for execution through the Universal Router
+    pub async fn uniswap_route(
+        &self,
+        dexes: DexVenues<'_>,
+        key: RouteKey,
+        slippage_settings: Option<SlippageSettings>,
+    ) -> Result<(Quote, U256)> {
+        let comparison = self.find_best_route(
+            dexes, key.chain_id, key.token_in, key.token_out, key.amount_in, key.recipient
+        ).await?;
+
+        let mev_stats = self.venue_mev_stats.read().await.clone();
+        let accuracy = self.get_quote_accuracy().await;
+        let quote = [comparison.uniswap_v3, comparison.uniswap_v2]
+            .into_iter()
+            .flatten()
+            .max_by_key(|quote| self.adjusted_output(quote, &mev_stats, &accuracy))
+            .ok_or_else(|| anyhow!("No Uniswap route for {:?} -> {:?} on chain {}", key.token_in, key.token_out, key.chain_id))?;
+
+        let slippage_percentage = match slippage_settings {
+            Some(settings) => {
+                settings.validate()?;
+                settings.max_slippage_percentage
+            }
+            None => self.recommend_slippage(quote.dex.clone(), key.token_in, key.token_out, quote.price_impact).await
+                .slippage_percentage,
+        };
+        let min_amount_out = min_amount_out(quote.output_amount, slippage_percentage);
+        if min_amount_out.is_zero() {
+            return Err(anyhow!("Quote for {:?} -> {:?} returns nothing", key.token_in, key.token_out));
+        }
+        Ok((quote, min_amount_out))
+    }
+
     /// Batch multiple swaps for gas optimization
     pub async fn batch_swaps(
         &self,
-        uniswap: &UniswapV3Manager,
-        sushiswap: &SushiSwapManager,
+        dexes: DexVenues<'_>,
         chain_id: u64,
         swaps: Vec<(Address, Address, U256)>, // (token_in, token_out, amount_in)
         recipient: Address,
@@ -230,7 +722,7 @@ impl DexAggregator {
 
         for (token_in, token_out, amount_in) in swaps {
             let comparison = self.find_best_route(
-                uniswap, sushiswap, chain_id, token_in, token_out, amount_in, recipient
+                dexes, chain_id, token_in, token_out, amount_in, recipient
             ).await?;
 
             transactions.push(comparison.best_route.transaction);
@@ -242,8 +734,7 @@ impl DexAggregator {
     /// Monitor price impact and suggest better timing
     pub async fn analyze_price_impact(
         &self,
-        uniswap: &UniswapV3Manager,
-        sushiswap: &SushiSwapManager,
+        dexes: DexVenues<'_>,
         chain_id: u64,
         token_in: Address,
         token_out: Address,
@@ -254,11 +745,11 @@ impl DexAggregator {
         let double_amount = amount_in * U256::from(2);
 
         let small_quote = self.find_best_route(
-            uniswap, sushiswap, chain_id, token_in, token_out, base_amount, Address::zero()
+            dexes, chain_id, token_in, token_out, base_amount, Address::zero()
         ).await?;
 
         let large_quote = self.find_best_route(
-            uniswap, sushiswap, chain_id, token_in, token_out, double_amount, Address::zero()
+            dexes, chain_id, token_in, token_out, double_amount, Address::zero()
         ).await?;
 
         // Calculate price impact curve
@@ -269,22 +760,193 @@ impl DexAggregator {
             double_amount,
         );
 
+        // Linear estimate of the impact at the trade's own size, between the 10% and 2x quotes
+        let small_impact = small_quote.best_route.price_impact;
+        let large_impact = large_quote.best_route.price_impact;
+        let trade_impact = small_impact + (large_impact - small_impact) * (0.9 / 1.9);
+        let recommended_slippage = self.recommend_slippage(
+            large_quote.best_route.dex.clone(), token_in, token_out, trade_impact
+        ).await;
+
         let analysis = PriceImpactAnalysis {
-            current_impact: small_quote.best_route.price_impact,
-            impact_at_2x: large_quote.best_route.price_impact,
+            current_impact: small_impact,
+            impact_at_2x: large_impact,
             recommended_split: if large_quote.best_route.price_impact > 2.0 {
                 Some(self.calculate_optimal_split(amount_in, token_in, token_out))
             } else {
                 None
             },
             better_timing_suggestion: self.suggest_better_timing(&price_impact_curve),
+            recommended_slippage,
         };
 
         Ok(analysis)
     }
 
+    /// Record the post-execution MEV analysis of a swap routed through a venue
+    pub async fn record_execution_outcome(&self, dex: DexType, amount_out: U256, threat: Option<&MevThreat>) {
+        let mut stats = self.venue_mev_stats.write().await;
+        let venue = stats.entry(dex.clone()).or_default();
+        venue.executions_analyzed += 1;
+
+        if let Some(threat) = threat {
+            venue.sandwiches_detected += 1;
+            if !amount_out.is_zero() {
+                let extracted_bps = u256_to_f64(threat.potential_value) / u256_to_f64(amount_out) * 10_000.0;
+                venue.total_extracted_bps += extracted_bps;
+            }
+            warn!("Sandwich recorded on {:?}, expected loss now {:.1} bps", dex, venue.expected_loss_bps());
+        }
+    }
+
+    /// Get post-execution MEV statistics per venue
+    pub async fn get_venue_mev_stats(&self) -> HashMap<DexType, VenueMevStats> {
+        self.venue_mev_stats.read().await.clone()
+    }
+
+    /// Forget observed MEV losses so every venue is ranked on quotes alone
+    pub async fn reset_venue_mev_stats(&self) {
+        self.venue_mev_stats.write().await.clear();
+    }
+
+    /// Record a quoted output against what the swap paid, keeping the most recent samples per venue
+    pub async fn record_quote_sample(&self, sample: QuoteSample) {
+        if sample.error_bps.abs() >= 100.0 {
+            warn!("{:?} quote missed by {:.1} bps", sample.dex, sample.error_bps);
+        }
+        let mut samples = self.quote_samples.write().await;
+        let venue = samples.entry(sample.dex.clone()).or_default();
+        venue.push_back(sample);
+        while venue.len() > MAX_SAMPLES_PER_VENUE {
+            venue.pop_front();
+        }
+    }
+
+    /// Quote error distribution per venue, confidence measured against the default slippage tolerance
+    pub async fn get_quote_accuracy(&self) -> HashMap<DexType, VenueQuoteAccuracy> {
+        let tolerance_bps = self.slippage_settings.max_slippage_percentage * 100.0;
+        self.quote_samples.read().await.iter()
+            .map(|(dex, samples)| (dex.clone(), VenueQuoteAccuracy::from_samples(samples, tolerance_bps)))
+            .collect()
+    }
+
+    /// Every kept sample, oldest first per venue
+    pub async fn quote_samples(&self) -> Vec<QuoteSample> {
+        self.quote_samples.read().await.values().flatten().cloned().collect()
+    }
+
+    /// Replace the kept samples with stored ones
+    pub async fn restore_quote_samples(&self, stored: Vec<QuoteSample>) {
+        let mut samples = self.quote_samples.write().await;
+        samples.clear();
+        for sample in stored {
+            samples.entry(sample.dex.clone()).or_default().push_back(sample);
+        }
+        for venue in samples.values_mut() {
+            venue.make_contiguous().sort_by_key(|sample| sample.recorded_at);
+            while venue.len() > MAX_SAMPLES_PER_VENUE {
+                venue.pop_front();
+            }
+        }
+    }
+
+    /// Slippage recommendation for a pool from its recent volatility, the trade's price impact
+    /// and the MEV observed on the venue
+    pub async fn recommend_slippage(
+        &self,
+        dex: DexType,
+        token_in: Address,
+        token_out: Address,
+        price_impact: f64,
+    ) -> SlippageRecommendation {
+        let (volatility_percentage, price_samples) = self.price_samples.read().await
+            .get(&(dex.clone(), token_in, token_out))
+            .map(|samples| (mid_price_volatility(samples), samples.len()))
+            .unwrap_or_default();
+        let mev_stats = self.venue_mev_stats.read().await.get(&dex).cloned().unwrap_or_default();
+
+        let signals = PoolSlippageSignals {
+            volatility_percentage,
+            price_samples,
+            price_impact,
+            sandwich_rate: mev_stats.sandwich_rate(),
+            mev_loss_bps: mev_stats.expected_loss_bps(),
+        };
+        self.slippage_settings.recommend(dex, signals)
+    }
+
     // Private helper methods
 
+    /// Keep each venue's mid price, backing the trade's own impact out of the quoted rate
+    async fn record_price_samples(&self, quotes: &[Quote], token_in: Address, token_out: Address) {
+        let now = Instant::now();
+        let mut samples = self.price_samples.write().await;
+        for quote in quotes {
+            if quote.input_amount.is_zero() || quote.output_amount.is_zero() || quote.price_impact >= 100.0 {
+                continue;
+            }
+            let rate = u256_to_f64(quote.output_amount) / u256_to_f64(quote.input_amount);
+            let mid_price = rate / (1.0 - quote.price_impact / 100.0);
+
+            let pool = samples.entry((quote.dex.clone(), token_in, token_out)).or_default();
+            while pool.front().is_some_and(|(seen, _)| now.duration_since(*seen) > PRICE_SAMPLE_WINDOW) {
+                pool.pop_front();
+            }
+            if pool.len() == MAX_PRICE_SAMPLES {
+                pool.pop_front();
+            }
+            pool.push_back((now, mid_price));
+        }
+    }
+
+
+    /// Run one venue's quote with a timeout, turning errors and panics into a tagged result
+    async fn quote_venue<F>(&self, dex: DexType, quote: F) -> (Option<Quote>, VenueQuoteResult)
+    where
+        F: Future<Output = Result<Quote>>,
+    {
+        let started = Instant::now();
+        let outcome = tokio::time::timeout(self.venue_quote_timeout, AssertUnwindSafe(quote).catch_unwind()).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let (quote, status, error) = match outcome {
+            Ok(Ok(Ok(quote))) => (Some(quote), VenueQuoteStatus::Ok, None),
+            Ok(Ok(Err(e))) => (None, VenueQuoteStatus::Error, Some(e.to_string())),
+            Ok(Err(_)) => (None, VenueQuoteStatus::Error, Some("Venue quote panicked".to_string())),
+            Err(_) => (
+                None,
+                VenueQuoteStatus::Timeout,
+                Some(format!("No quote within {}ms", self.venue_quote_timeout.as_millis())),
+            ),
+        };
+
+        if let Some(error) = &error {
+            warn!("{:?} quote failed after {}ms: {}", dex, latency_ms, error);
+        }
+
+        (quote, VenueQuoteResult { dex, status, latency_ms, error })
+    }
+
+    fn adjusted_output(
+        &self,
+        quote: &Quote,
+        mev_stats: &HashMap<DexType, VenueMevStats>,
+        accuracy: &HashMap<DexType, VenueQuoteAccuracy>,
+    ) -> U256 {
+        // Adjust for gas costs (simplified calculation)
+        let after_gas = expected_output(quote, accuracy)
+            .saturating_sub(quote.gas_estimate * U256::from(20_000_000_000u64));
+
+        let loss_bps = mev_stats
+            .get(&quote.dex)
+            .map(|stats| stats.expected_loss_bps())
+            .unwrap_or(0.0)
+            .min(10_000.0);
+        let expected_loss = after_gas * U256::from((loss_bps * 100.0) as u64) / U256::from(1_000_000u64);
+
+        after_gas.saturating_sub(expected_loss)
+    }
+
     async fn get_uniswap_quote(
         &self,
         uniswap: &UniswapV3Manager,
@@ -311,7 +973,7 @@ impl DexAggregator {
         }
 
         if let Some((output, fee)) = best_quote {
-            let price_impact = self.calculate_price_impact(amount_in, output, token_in, token_out);
+            let price_impact = self.calculate_price_impact(chain_id, amount_in, output, token_in, token_out);
             
             Ok(Quote {
                 dex: DexType::UniswapV3,
@@ -320,6 +982,7 @@ impl DexAggregator {
                 price_impact,
                 gas_estimate: U256::from(150_000), // Estimated gas for Uniswap V3
                 path: vec![token_in, token_out],
+                fee_tier: Some(fee),
             })
         } else {
             Err(anyhow!("No valid Uniswap V3 quote found"))
@@ -343,7 +1006,7 @@ impl DexAggregator {
         }
 
         let output_amount = amounts[1];
-        let price_impact = self.calculate_price_impact(amount_in, output_amount, token_in, token_out);
+        let price_impact = self.calculate_price_impact(chain_id, amount_in, output_amount, token_in, token_out);
 
         Ok(Quote {
             dex: DexType::SushiSwap,
@@ -352,18 +1015,43 @@ impl DexAggregator {
             price_impact,
             gas_estimate: U256::from(120_000), // Estimated gas for SushiSwap
             path,
+            fee_tier: None,
+        })
+    }
+
+    async fn get_uniswap_v2_quote(
+        &self,
+        uniswap_v2: &UniswapV2Manager,
+        chain_id: u64,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<Quote> {
+        // Reserves are read directly, so the impact is exact rather than estimated
+        let (output_amount, price_impact) = uniswap_v2.quote_exact_input(chain_id, token_in, token_out, amount_in).await?;
+
+        Ok(Quote {
+            dex: DexType::UniswapV2,
+            input_amount: amount_in,
+            output_amount,
+            price_impact,
+            gas_estimate: U256::from(120_000), // Estimated gas for Uniswap V2
+            path: vec![token_in, token_out],
+            fee_tier: None,
         })
     }
 
     async fn create_transaction_for_quote(
         &self,
-        uniswap: &UniswapV3Manager,
-        sushiswap: &SushiSwapManager,
+        dexes: DexVenues<'_>,
         chain_id: u64,
         quote: &Quote,
         recipient: Address,
+        settings: &SlippageSettings,
     ) -> Result<TransactionRequest> {
-        let deadline = self.calculate_deadline();
+        settings.validate()?;
+        let deadline = settings.deadline();
+        let min_amount_out = settings.min_amount_out(quote.output_amount);
 
         match quote.dex {
             DexType::UniswapV3 => {
@@ -371,19 +1059,27 @@ impl DexAggregator {
                     token_in: quote.path[0],
                     token_out: quote.path[1],
                     amount_in: quote.input_amount,
-                    amount_out_minimum: self.calculate_min_amount_out(quote.output_amount, self.slippage_settings.max_slippage_percentage),
-                    fee: 3000, // Default to 0.3% fee tier
+                    amount_out_minimum: min_amount_out,
+                    fee: quote.fee_tier.unwrap_or(3000),
                     recipient,
                     deadline,
                     sqrt_price_limit_x96: U256::zero(),
                 };
 
-                uniswap.swap_exact_input_single(chain_id, params).await
+                dexes.uniswap.swap_exact_input_single(chain_id, params).await
             },
             DexType::SushiSwap => {
-                let min_amount_out = self.calculate_min_amount_out(quote.output_amount, self.slippage_settings.max_slippage_percentage);
-                
-                sushiswap.swap_exact_tokens_for_tokens(
+                dexes.sushiswap.swap_exact_tokens_for_tokens(
+                    chain_id,
+                    quote.input_amount,
+                    min_amount_out,
+                    quote.path.clone(),
+                    recipient,
+                    deadline,
+                ).await
+            },
+            DexType::UniswapV2 => {
+                dexes.uniswap_v2.swap_exact_tokens_for_tokens(
                     chain_id,
                     quote.input_amount,
                     min_amount_out,
@@ -395,13 +1091,23 @@ impl DexAggregator {
         }
     }
 
-    fn calculate_price_impact(&self, amount_in: U256, amount_out: U256, _token_in: Address, _token_out: Address) -> f64 {
+    fn calculate_price_impact(&self, chain_id: u64, amount_in: U256, amount_out: U256, token_in: Address, token_out: Address) -> f64 {
         // Simplified price impact calculation
         // In reality, you'd need to know the pool reserves and calculate the exact impact
         if amount_in.is_zero() || amount_out.is_zero() {
             return 0.0;
         }
 
+        // Representations of the same asset, e.g. USDC and USDC.e, are worth the same
+        if self.assets.compatibility((chain_id, token_in), (chain_id, token_out)) != AssetCompatibility::Incompatible {
+            if let (Some((_, input)), Some((_, output))) =
+                (self.assets.resolve(chain_id, token_in), self.assets.resolve(chain_id, token_out))
+            {
+                let parity = u256_to_f64(amount_in) * 10f64.powi(output.decimals as i32 - input.decimals as i32);
+                return ((parity - u256_to_f64(amount_out)) / parity * 100.0).clamp(0.0, 50.0);
+            }
+        }
+
         // Mock calculation - replace with actual price impact formula
         let input_value = amount_in.as_u128() as f64;
         let output_value = amount_out.as_u128() as f64;
@@ -413,20 +1119,6 @@ impl DexAggregator {
         impact.min(50.0) // Cap at 50%
     }
 
-    fn calculate_min_amount_out(&self, amount_out: U256, slippage_percentage: f64) -> U256 {
-        let slippage_multiplier = 1.0 - (slippage_percentage / 100.0);
-        let min_amount = (amount_out.as_u128() as f64 * slippage_multiplier) as u128;
-        U256::from(min_amount)
-    }
-
-    fn calculate_deadline(&self) -> u64 {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        now + (self.slippage_settings.deadline_minutes * 60)
-    }
-
     async fn add_mev_protection(&self, mut tx: TransactionRequest, protection: MevProtection) -> Result<TransactionRequest> {
         match protection {
             MevProtection::PrivateMempool => {
@@ -486,6 +1178,74 @@ pub struct PriceImpactAnalysis {
     pub impact_at_2x: f64,
     pub recommended_split: Option<Vec<U256>>,
     pub better_timing_suggestion: Option<TimingSuggestion>,
+    pub recommended_slippage: SlippageRecommendation,
+}
+
+/// Root mean square percentage change between consecutive mid prices
+fn mid_price_volatility(samples: &VecDeque<(Instant, f64)>) -> f64 {
+    let changes: Vec<f64> = samples.iter()
+        .zip(samples.iter().skip(1))
+        .map(|((_, previous), (_, next))| (next / previous - 1.0) * 100.0)
+        .collect();
+    if changes.is_empty() {
+        return 0.0;
+    }
+    (changes.iter().map(|change| change * change).sum::<f64>() / changes.len() as f64).sqrt()
+}
+
+impl CachedComparison {
+    fn freshness(&self, approximate: bool) -> QuoteFreshness {
+        QuoteFreshness {
+            quoted_at: self.quoted_at,
+            age_ms: self.fetched.elapsed().as_millis() as u64,
+            approximate,
+        }
+    }
+}
+
+/// `amount * numerator / denominator` without intermediate overflow
+fn scale_amount(amount: U256, numerator: U256, denominator: U256) -> U256 {
+    if denominator.is_zero() {
+        return U256::zero();
+    }
+    U256::try_from(amount.full_mul(numerator) / U512::from(denominator)).unwrap_or(U256::MAX)
+}
+
+/// `quoted` less the slippage tolerance, rounded to whole basis points
+pub fn min_amount_out(quoted: U256, slippage_percentage: f64) -> U256 {
+    let bps = (slippage_percentage * 100.0).round().clamp(0.0, 10_000.0) as u64;
+    scale_amount(quoted, U256::from(10_000 - bps), U256::from(10_000))
+}
+
+/// Unix time `minutes` from now
+pub fn swap_deadline(minutes: u64) -> u64 {
+    Utc::now().timestamp().max(0) as u64 + minutes * 60
+}
+
+/// Reject a swap that accepts any output or whose deadline is past or further out than allowed
+pub fn check_swap_bounds(amount_out_min: U256, deadline: u64) -> Result<()> {
+    if amount_out_min.is_zero() {
+        return Err(anyhow!("Swap without a minimum output, quote it first"));
+    }
+    let now = Utc::now().timestamp().max(0) as u64;
+    if deadline <= now {
+        return Err(anyhow!("Swap deadline {} has passed", deadline));
+    }
+    if deadline > now + MAX_DEADLINE_MINUTES * 60 {
+        return Err(anyhow!("Swap deadline is more than {} minutes away", MAX_DEADLINE_MINUTES));
+    }
+    Ok(())
+}
+
+/// Quoted output less the venue's measured average shortfall
+fn expected_output(quote: &Quote, accuracy: &HashMap<DexType, VenueQuoteAccuracy>) -> U256 {
+    let shortfall_bps = accuracy.get(&quote.dex).map(|venue| venue.expected_shortfall_bps()).unwrap_or(0.0);
+    min_amount_out(quote.output_amount, shortfall_bps / 100.0)
+}
+
+/// Lossy conversion that, unlike `as_u128`, cannot panic on large amounts
+pub(crate) fn u256_to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, word| acc * 2f64.powi(64) + *word as f64)
 }
 
 /// Price impact curve data
@@ -503,3 +1263,75 @@ pub struct TimingSuggestion {
     pub reason: String,
     pub expected_improvement: f64, // percentage
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slippage_settings_reject_tolerance_above_cap() {
+        let settings = SlippageSettings {
+            max_slippage_percentage: MAX_SLIPPAGE_PERCENTAGE + 0.01,
+            ..SlippageSettings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn slippage_settings_accept_zero_tolerance() {
+        let settings = SlippageSettings {
+            max_slippage_percentage: 0.0,
+            ..SlippageSettings::default()
+        };
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.min_amount_out(U256::from(1_000_000u64)), U256::from(1_000_000u64));
+    }
+
+    #[test]
+    fn slippage_settings_reject_deadline_out_of_range() {
+        for deadline_minutes in [0, MAX_DEADLINE_MINUTES + 1] {
+            let settings = SlippageSettings { deadline_minutes, ..SlippageSettings::default() };
+            assert!(settings.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn min_amount_out_rounds_to_whole_basis_points() {
+        let quoted = U256::from(1_000_000u64);
+        // 0.504% rounds down to 50 bps, 0.506% up to 51 bps
+        assert_eq!(min_amount_out(quoted, 0.504), U256::from(995_000u64));
+        assert_eq!(min_amount_out(quoted, 0.506), U256::from(994_900u64));
+        // Truncated, never rounded up past the tolerance
+        assert_eq!(min_amount_out(U256::from(999u64), 0.5), U256::from(994u64));
+    }
+
+    #[test]
+    fn min_amount_out_clamps_tolerance() {
+        let quoted = U256::from(1_000u64);
+        assert_eq!(min_amount_out(quoted, 150.0), U256::zero());
+        assert_eq!(min_amount_out(quoted, -1.0), quoted);
+        assert_eq!(min_amount_out(U256::MAX, 1.0), U256::MAX / 100 * 99 + U256::MAX % 100 * 99 / 100);
+    }
+
+    #[test]
+    fn swap_bounds_accept_default_deadline() {
+        assert!(check_swap_bounds(U256::one(), swap_deadline(DEFAULT_DEADLINE_MINUTES)).is_ok());
+    }
+
+    #[test]
+    fn swap_bounds_reject_past_deadline() {
+        let past = Utc::now().timestamp() as u64 - 1;
+        assert!(check_swap_bounds(U256::one(), past).is_err());
+    }
+
+    #[test]
+    fn swap_bounds_reject_deadline_too_far_out() {
+        assert!(check_swap_bounds(U256::one(), swap_deadline(MAX_DEADLINE_MINUTES + 1)).is_err());
+    }
+
+    #[test]
+    fn swap_bounds_reject_zero_minimum_output() {
+        assert!(check_swap_bounds(U256::zero(), swap_deadline(DEFAULT_DEADLINE_MINUTES)).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+// 1inch and 0x quotes compared against the local route
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, Bytes, TransactionRequest, U256};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const DEFAULT_ONEINCH_API_URL: &str = "https://api.1inch.dev/swap/v6.0";
+const DEFAULT_ZEROX_API_URL: &str = "https://api.0x.org";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Placeholder both APIs use for the native token, which this codebase addresses as zero
+const NATIVE_TOKEN: &str = "0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExternalAggregator {
+    #[serde(rename = "1inch")]
+    OneInch,
+    #[serde(rename = "0x")]
+    ZeroX,
+}
+
+/// API keys per aggregator; an aggregator without a key is not queried
+#[derive(Debug, Clone)]
+pub struct ExternalAggregatorConfig {
+    pub oneinch_api_key: Option<String>,
+    pub zerox_api_key: Option<String>,
+    pub oneinch_api_url: String,
+    pub zerox_api_url: String,
+    pub timeout: Duration,
+}
+
+impl Default for ExternalAggregatorConfig {
+    fn default() -> Self {
+        Self {
+            oneinch_api_key: None,
+            zerox_api_key: None,
+            oneinch_api_url: DEFAULT_ONEINCH_API_URL.to_string(),
+            zerox_api_url: DEFAULT_ZEROX_API_URL.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl ExternalAggregatorConfig {
+    /// Keys from `oneinch_api_key` and `zerox_api_key`
+    pub fn from_config(config: &config::Config) -> Self {
+        let key = |name: &str| config.get_string(name).ok().filter(|key| !key.is_empty());
+        Self {
+            oneinch_api_key: key("oneinch_api_key"),
+            zerox_api_key: key("zerox_api_key"),
+            ..Self::default()
+        }
+    }
+}
+
+/// Swap to quote externally, `taker` receives the output and signs the transaction
+#[derive(Debug, Clone, Copy)]
+pub struct ExternalQuoteRequest {
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub taker: Address,
+    pub slippage_percentage: f64,
+}
+
+/// Swap quoted by an external aggregator, with calldata for its router
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalQuote {
+    pub aggregator: ExternalAggregator,
+    pub output_amount: U256,
+    pub gas_estimate: U256,
+    pub transaction: TransactionRequest,
+    pub latency_ms: u64,
+}
+
+/// External quote paying more than the local best route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalAdvantage {
+    pub quote: ExternalQuote,
+    /// Output above the local route, in percent
+    pub improvement_percentage: f64,
+}
+
+/// 1inch `/swap` response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OneInchSwap {
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    dst_amount: U256,
+    tx: AggregatorTransaction,
+}
+
+/// 0x `/swap/allowance-holder/quote` response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZeroXQuote {
+    liquidity_available: bool,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    buy_amount: U256,
+    transaction: Option<AggregatorTransaction>,
+}
+
+#[derive(Deserialize)]
+struct AggregatorTransaction {
+    to: Address,
+    data: Bytes,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    value: U256,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    gas: U256,
+}
+
+impl AggregatorTransaction {
+    fn into_request(self, chain_id: u64, taker: Address) -> TransactionRequest {
+        TransactionRequest::new()
+            .from(taker)
+            .to(self.to)
+            .data(self.data)
+            .value(self.value)
+            .gas(self.gas)
+            .chain_id(chain_id)
+    }
+}
+
+/// Client for the configured external aggregators
+pub struct ExternalAggregators {
+    config: ExternalAggregatorConfig,
+    http: reqwest::Client,
+}
+
+impl ExternalAggregators {
+    pub fn new(config: ExternalAggregatorConfig) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()
+                .unwrap_or_default(),
+            config,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.oneinch_api_key.is_some() || self.config.zerox_api_key.is_some()
+    }
+
+    /// Quotes from every configured aggregator; failing ones are logged and left out
+    pub async fn quote_all(&self, request: &ExternalQuoteRequest) -> Vec<ExternalQuote> {
+        let (oneinch, zerox) = tokio::join!(
+            self.timed(ExternalAggregator::OneInch, async {
+                match &self.config.oneinch_api_key {
+                    Some(key) => self.quote_oneinch(key, request).await.map(Some),
+                    None => Ok(None),
+                }
+            }),
+            self.timed(ExternalAggregator::ZeroX, async {
+                match &self.config.zerox_api_key {
+                    Some(key) => self.quote_zerox(key, request).await,
+                    None => Ok(None),
+                }
+            }),
+        );
+        oneinch.into_iter().chain(zerox).collect()
+    }
+
+    async fn timed<F>(&self, aggregator: ExternalAggregator, quote: F) -> Option<ExternalQuote>
+    where
+        F: std::future::Future<Output = Result<Option<(U256, TransactionRequest)>>>,
+    {
+        let started = Instant::now();
+        match quote.await {
+            Ok(Some((output_amount, transaction))) => Some(ExternalQuote {
+                aggregator,
+                output_amount,
+                gas_estimate: transaction.gas.unwrap_or_default(),
+                transaction,
+                latency_ms: started.elapsed().as_millis() as u64,
+            }),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("{:?} quote failed after {}ms: {}", aggregator, started.elapsed().as_millis(), e);
+                None
+            }
+        }
+    }
+
+    async fn quote_oneinch(&self, api_key: &str, request: &ExternalQuoteRequest) -> Result<(U256, TransactionRequest)> {
+        let response = self.http
+            .get(format!("{}/{}/swap", self.config.oneinch_api_url, request.chain_id))
+            .bearer_auth(api_key)
+            .query(&[
+                ("src", token_param(request.token_in)),
+                ("dst", token_param(request.token_out)),
+                ("amount", request.amount_in.to_string()),
+                ("from", format!("{:?}", request.taker)),
+                ("origin", format!("{:?}", request.taker)),
+                ("slippage", request.slippage_percentage.to_string()),
+                ("disableEstimate", "true".to_string()),
+                ("includeGas", "true".to_string()),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("1inch returned {}: {}", response.status(), response.text().await.unwrap_or_default()));
+        }
+
+        let swap: OneInchSwap = response.json().await?;
+        Ok((swap.dst_amount, swap.tx.into_request(request.chain_id, request.taker)))
+    }
+
+    /// `None` when 0x has no liquidity for the pair
+    async fn quote_zerox(&self, api_key: &str, request: &ExternalQuoteRequest) -> Result<Option<(U256, TransactionRequest)>> {
+        let response = self.http
+            .get(format!("{}/swap/allowance-holder/quote", self.config.zerox_api_url))
+            .header("0x-api-key", api_key)
+            .header("0x-version", "v2")
+            .query(&[
+                ("chainId", request.chain_id.to_string()),
+                ("sellToken", token_param(request.token_in)),
+                ("buyToken", token_param(request.token_out)),
+                ("sellAmount", request.amount_in.to_string()),
+                ("taker", format!("{:?}", request.taker)),
+                ("slippageBps", ((request.slippage_percentage * 100.0).round() as u64).to_string()),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("0x returned {}: {}", response.status(), response.text().await.unwrap_or_default()));
+        }
+
+        let quote: ZeroXQuote = response.json().await?;
+        match quote.transaction.filter(|_| quote.liquidity_available) {
+            Some(transaction) => Ok(Some((quote.buy_amount, transaction.into_request(request.chain_id, request.taker)))),
+            None => Ok(None),
+        }
+    }
+}
+
+fn token_param(token: Address) -> String {
+    if token.is_zero() {
+        NATIVE_TOKEN.to_string()
+    } else {
+        format!("{:?}", token)
+    }
+}
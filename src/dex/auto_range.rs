@@ -0,0 +1,494 @@
+// Managed Uniswap V3 positions recentered when the price drifts to the edge of their range
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ethers::types::{Address, TransactionRequest, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::analytics::impermanent_loss::{liquidity_amounts, tick_to_price};
+use crate::contracts::approvals::{transaction_target, TokenApproval, TokenSpend};
+use crate::dex::uniswap::LiquidityPosition;
+use crate::dex::DexManager;
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store, TransactionTracker};
+
+/// Store used when `auto_range_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/auto_ranges.json";
+/// Rebalances kept per strategy
+const REBALANCE_HISTORY: usize = 20;
+/// Rebalance transactions expire after this long
+const REBALANCE_DEADLINE_SECS: i64 = 1800;
+
+/// Auto-range configuration, the defaults of strategies that do not set their own
+#[derive(Debug, Clone)]
+pub struct AutoRangeConfig {
+    pub check_interval: Duration,
+    /// Width of recentered ranges, scaling the fee tier's default width
+    pub range_factor: f64,
+    /// Rebalance once the price is this close to a bound, in percent of the range width; 0 waits until out of range
+    pub edge_threshold_percentage: f64,
+    /// Minimum time between two rebalances of a strategy
+    pub cooldown: Duration,
+    pub slippage_bps: u32,
+}
+
+impl Default for AutoRangeConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(60),
+            range_factor: 1.0,
+            edge_threshold_percentage: 10.0,
+            cooldown: Duration::from_secs(3600),
+            slippage_bps: 50,
+        }
+    }
+}
+
+impl AutoRangeConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut auto_range_config = Self::default();
+
+        if let Ok(secs) = config.get_int("auto_range_check_interval_secs") {
+            auto_range_config.check_interval = Duration::from_secs(secs.max(5) as u64);
+        }
+        if let Ok(factor) = config.get_float("auto_range_range_factor") {
+            auto_range_config.range_factor = factor;
+        }
+        if let Ok(threshold) = config.get_float("auto_range_edge_threshold_percentage") {
+            auto_range_config.edge_threshold_percentage = threshold;
+        }
+        if let Ok(secs) = config.get_int("auto_range_cooldown_secs") {
+            auto_range_config.cooldown = Duration::from_secs(secs.max(0) as u64);
+        }
+        if let Ok(bps) = config.get_int("auto_range_slippage_bps") {
+            auto_range_config.slippage_bps = bps.clamp(0, 10_000) as u32;
+        }
+
+        auto_range_config
+    }
+}
+
+/// Register a position for auto-range management
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoRangeRequest {
+    pub chain_id: u64,
+    pub owner: Address,
+    pub token_id: U256,
+    pub range_factor: Option<f64>,
+    pub edge_threshold_percentage: Option<f64>,
+    pub cooldown_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RangeTrigger {
+    OutOfRange,
+    NearEdge,
+}
+
+/// Transactions recentering a position: decreaseLiquidity, collect, the approvals the mint needs, then mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RangeRebalance {
+    pub proposed_at: DateTime<Utc>,
+    pub trigger: RangeTrigger,
+    pub token_id: U256,
+    pub current_tick: i32,
+    pub old_tick_lower: i32,
+    pub old_tick_upper: i32,
+    pub new_tick_lower: i32,
+    pub new_tick_upper: i32,
+    /// Liquidity withdrawn plus the fees collected, in raw token units
+    pub withdrawn_amount0: U256,
+    pub withdrawn_amount1: U256,
+    /// Amounts the recentered position is expected to take; the rest stays with the owner
+    pub minted_amount0: U256,
+    pub minted_amount1: U256,
+    pub deadline: DateTime<Utc>,
+    pub approvals: Vec<TokenApproval>,
+    pub transactions: Vec<TransactionRequest>,
+    /// Transaction history records, in the order of `transactions`
+    pub tracking_ids: Vec<String>,
+    pub execution_id: Option<String>,
+    /// Position minted by the rebalance once it was seen on chain
+    pub new_token_id: Option<U256>,
+}
+
+/// A position kept in range by the manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoRangeStrategy {
+    pub id: String,
+    pub chain_id: u64,
+    pub owner: Address,
+    /// Position currently managed, follows the position each rebalance mints
+    pub token_id: U256,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub range_factor: f64,
+    pub edge_threshold_percentage: f64,
+    pub cooldown_secs: u64,
+    pub created_at: DateTime<Utc>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_rebalanced_at: Option<DateTime<Utc>>,
+    /// Rebalance waiting for the owner to send its transactions
+    pub pending: Option<RangeRebalance>,
+    /// Completed and expired rebalances, newest last
+    #[serde(default)]
+    pub history: Vec<RangeRebalance>,
+}
+
+/// Watches managed positions on an interval and builds rebalances of those drifting out of range
+pub struct AutoRangeManager {
+    dex_manager: Arc<DexManager>,
+    transactions: Arc<TransactionTracker>,
+    config: AutoRangeConfig,
+    store_path: Option<PathBuf>,
+    strategies: RwLock<HashMap<String, AutoRangeStrategy>>,
+}
+
+impl AutoRangeManager {
+    pub async fn new(
+        dex_manager: Arc<DexManager>,
+        transactions: Arc<TransactionTracker>,
+        config: AutoRangeConfig,
+        store_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let stored: Vec<AutoRangeStrategy> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        info!("Initializing AutoRangeManager with {} strategies (check every {:?})", stored.len(), config.check_interval);
+
+        Ok(Self {
+            dex_manager,
+            transactions,
+            config,
+            store_path,
+            strategies: RwLock::new(stored.into_iter().map(|strategy| (strategy.id.clone(), strategy)).collect()),
+        })
+    }
+
+    /// Manager persisting strategies to `auto_range_store_path`, an empty path keeps them in memory
+    pub async fn from_config(
+        config: &config::Config,
+        dex_manager: Arc<DexManager>,
+        transactions: Arc<TransactionTracker>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("auto_range_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(dex_manager, transactions, AutoRangeConfig::from_config(config), store_path).await
+    }
+
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.config.check_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.check_all().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Auto-range manager stopped");
+        })
+    }
+
+    pub async fn register(&self, request: AutoRangeRequest) -> Result<AutoRangeStrategy> {
+        let range_factor = request.range_factor.unwrap_or(self.config.range_factor);
+        let edge_threshold_percentage = request.edge_threshold_percentage.unwrap_or(self.config.edge_threshold_percentage);
+        if !range_factor.is_finite() || range_factor <= 0.0 {
+            return Err(anyhow!("range_factor must be positive"));
+        }
+        if !(0.0..50.0).contains(&edge_threshold_percentage) {
+            return Err(anyhow!("edge_threshold_percentage must be at least 0 and below 50"));
+        }
+
+        let position = self.dex_manager.uniswap().get_position(request.chain_id, request.token_id).await?;
+        if position.liquidity.is_zero() {
+            return Err(anyhow!("Position {} has no liquidity", request.token_id));
+        }
+        if self.strategies.read().await.values().any(|strategy| strategy.chain_id == request.chain_id && strategy.token_id == request.token_id) {
+            return Err(anyhow!("Position {} is already managed", request.token_id));
+        }
+
+        let strategy = AutoRangeStrategy {
+            id: Uuid::new_v4().to_string(),
+            chain_id: request.chain_id,
+            owner: request.owner,
+            token_id: request.token_id,
+            token0: position.token0,
+            token1: position.token1,
+            fee: position.fee,
+            tick_lower: position.tick_lower,
+            tick_upper: position.tick_upper,
+            range_factor,
+            edge_threshold_percentage,
+            cooldown_secs: request.cooldown_secs.unwrap_or(self.config.cooldown.as_secs()),
+            created_at: Utc::now(),
+            last_checked: None,
+            last_error: None,
+            last_rebalanced_at: None,
+            pending: None,
+            history: Vec::new(),
+        };
+        info!("Managing range of position {} on chain {} as {}", strategy.token_id, strategy.chain_id, strategy.id);
+
+        let mut strategies = self.strategies.write().await;
+        strategies.insert(strategy.id.clone(), strategy.clone());
+        self.persist(&strategies).await;
+        Ok(strategy)
+    }
+
+    pub async fn list(&self, owner: Option<Address>) -> Vec<AutoRangeStrategy> {
+        let mut strategies: Vec<AutoRangeStrategy> = self.strategies.read().await.values()
+            .filter(|strategy| owner.is_none_or(|owner| strategy.owner == owner))
+            .cloned()
+            .collect();
+        strategies.sort_by_key(|strategy| strategy.created_at);
+        strategies
+    }
+
+    pub async fn get(&self, id: &str) -> Option<AutoRangeStrategy> {
+        self.strategies.read().await.get(id).cloned()
+    }
+
+    /// Stop managing a position, returning whether it was managed
+    pub async fn remove(&self, id: &str) -> bool {
+        let mut strategies = self.strategies.write().await;
+        let removed = strategies.remove(id).is_some();
+        if removed {
+            self.persist(&strategies).await;
+        }
+        removed
+    }
+
+    async fn check_all(&self) {
+        let ids: Vec<String> = self.strategies.read().await.keys().cloned().collect();
+        for id in ids {
+            if let Err(e) = self.check(&id).await {
+                warn!("Auto-range check of {} failed: {}", id, e);
+            }
+        }
+    }
+
+    /// Follow a pending rebalance, or build one when the price drifted to the edge of the range
+    pub async fn check(&self, id: &str) -> Result<AutoRangeStrategy> {
+        let mut strategy = self.get(id).await.ok_or_else(|| anyhow!("Auto-range strategy {} not found", id))?;
+        let result = self.evaluate(&mut strategy).await;
+        strategy.last_checked = Some(Utc::now());
+        strategy.last_error = result.as_ref().err().map(|e| e.to_string());
+
+        let mut strategies = self.strategies.write().await;
+        // Removed while it was checked
+        if !strategies.contains_key(id) {
+            return Err(anyhow!("Auto-range strategy {} not found", id));
+        }
+        strategies.insert(strategy.id.clone(), strategy.clone());
+        self.persist(&strategies).await;
+        result.map(|_| strategy)
+    }
+
+    async fn evaluate(&self, strategy: &mut AutoRangeStrategy) -> Result<()> {
+        let uniswap = self.dex_manager.uniswap();
+        let position = uniswap.get_position(strategy.chain_id, strategy.token_id).await?;
+
+        if let Some(pending) = strategy.pending.clone() {
+            if position.liquidity.is_zero() {
+                let minted = uniswap.get_positions(strategy.chain_id, strategy.owner).await?.into_iter()
+                    .filter(|minted| minted.token0 == strategy.token0 && minted.token1 == strategy.token1 && minted.fee == strategy.fee)
+                    .filter(|minted| minted.tick_lower == pending.new_tick_lower && minted.tick_upper == pending.new_tick_upper)
+                    .filter(|minted| !minted.liquidity.is_zero())
+                    .max_by_key(|minted| minted.token_id);
+                match minted {
+                    Some(minted) => {
+                        info!("Auto-range {} moved from position {} to {}", strategy.id, strategy.token_id, minted.token_id);
+                        strategy.token_id = minted.token_id;
+                        strategy.tick_lower = minted.tick_lower;
+                        strategy.tick_upper = minted.tick_upper;
+                        strategy.last_rebalanced_at = Some(Utc::now());
+                        archive(strategy, RangeRebalance { new_token_id: Some(minted.token_id), ..pending });
+                        return Ok(());
+                    }
+                    None if Utc::now() > pending.deadline => {
+                        archive(strategy, pending);
+                        return Err(anyhow!("Position {} was emptied but the recentered position was not minted", strategy.token_id));
+                    }
+                    // Withdrawn, the mint may still be on its way
+                    None => return Ok(()),
+                }
+            }
+            if Utc::now() <= pending.deadline {
+                return Ok(());
+            }
+            info!("Rebalance of auto-range {} expired unsent", strategy.id);
+            archive(strategy, pending);
+        }
+
+        if position.liquidity.is_zero() {
+            return Err(anyhow!("Position {} has no liquidity", strategy.token_id));
+        }
+        let cooling_down = strategy.last_rebalanced_at
+            .is_some_and(|at| Utc::now() < at + ChronoDuration::seconds(strategy.cooldown_secs as i64));
+        if cooling_down {
+            return Ok(());
+        }
+
+        let pool = uniswap.get_pool_info(strategy.chain_id, strategy.token0, strategy.token1, strategy.fee).await?;
+        let Some(trigger) = range_trigger(pool.tick, position.tick_lower, position.tick_upper, strategy.edge_threshold_percentage) else {
+            return Ok(());
+        };
+        let (new_tick_lower, new_tick_upper) = uniswap
+            .calculate_optimal_range(strategy.chain_id, strategy.token0, strategy.token1, strategy.fee, strategy.range_factor)
+            .await?;
+        if (new_tick_lower, new_tick_upper) == (position.tick_lower, position.tick_upper) {
+            return Ok(());
+        }
+
+        let rebalance = self.build_rebalance(strategy, &position, trigger, pool.tick, (new_tick_lower, new_tick_upper)).await?;
+        info!(
+            "Auto-range {} {:?} at tick {}: recentering position {} from [{}, {}] to [{}, {}]",
+            strategy.id, trigger, pool.tick, position.token_id, position.tick_lower, position.tick_upper, new_tick_lower, new_tick_upper,
+        );
+        strategy.pending = Some(rebalance);
+        Ok(())
+    }
+
+    async fn build_rebalance(
+        &self,
+        strategy: &AutoRangeStrategy,
+        position: &LiquidityPosition,
+        trigger: RangeTrigger,
+        current_tick: i32,
+        (new_tick_lower, new_tick_upper): (i32, i32),
+    ) -> Result<RangeRebalance> {
+        let uniswap = self.dex_manager.uniswap();
+        let price = position.current_price.unwrap_or_else(|| tick_to_price(current_tick));
+        let raw = |amount: U256| amount.to_string().parse::<f64>().unwrap_or_default();
+        let to_u256 = |amount: f64| U256::from_dec_str(&format!("{:.0}", amount.max(0.0).floor())).unwrap_or_default();
+        let keep = 1.0 - self.config.slippage_bps as f64 / 10_000.0;
+
+        let (amount0, amount1) = liquidity_amounts(
+            raw(position.liquidity),
+            price,
+            tick_to_price(position.tick_lower),
+            tick_to_price(position.tick_upper),
+        );
+        let available0 = amount0 + raw(position.uncollected_fees0);
+        let available1 = amount1 + raw(position.uncollected_fees1);
+
+        // Liquidity the recentered range can take from what is withdrawn, limited by the scarcer token
+        let (lower, upper) = (tick_to_price(new_tick_lower), tick_to_price(new_tick_upper));
+        let (unit0, unit1) = liquidity_amounts(1.0, price, lower, upper);
+        let liquidity = match (unit0 > 0.0, unit1 > 0.0) {
+            (true, true) => (available0 / unit0).min(available1 / unit1),
+            (true, false) => available0 / unit0,
+            (false, true) => available1 / unit1,
+            (false, false) => 0.0,
+        };
+        let (minted0, minted1) = liquidity_amounts(liquidity, price, lower, upper);
+
+        let deadline = Utc::now() + ChronoDuration::seconds(REBALANCE_DEADLINE_SECS);
+        let decrease = uniswap.remove_liquidity(
+            strategy.chain_id,
+            position.token_id,
+            position.liquidity,
+            to_u256(amount0 * keep),
+            to_u256(amount1 * keep),
+            deadline.timestamp() as u64,
+        ).await?;
+        let collect = uniswap.collect(strategy.chain_id, position.token_id, strategy.owner).await?;
+        let mint = uniswap.add_liquidity(
+            strategy.chain_id,
+            strategy.token0,
+            strategy.token1,
+            strategy.fee,
+            new_tick_lower,
+            new_tick_upper,
+            to_u256(available0),
+            to_u256(available1),
+            to_u256(minted0 * keep),
+            to_u256(minted1 * keep),
+            strategy.owner,
+            deadline.timestamp() as u64,
+        ).await?;
+
+        let position_manager = transaction_target(&mint).ok_or_else(|| anyhow!("Mint transaction without a position manager"))?;
+        let spends = [
+            TokenSpend { token: strategy.token0, spender: position_manager, amount: to_u256(available0) },
+            TokenSpend { token: strategy.token1, spender: position_manager, amount: to_u256(available1) },
+        ];
+        let approvals: Vec<TokenApproval> = self.dex_manager.approvals()
+            .plan_approvals(strategy.chain_id, strategy.owner, &spends, None).await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut transactions = vec![decrease, collect];
+        transactions.extend(approvals.iter().map(|approval| approval.transaction.clone()));
+        transactions.push(mint);
+        let records = self.transactions.record_built_all(strategy.chain_id, Some(strategy.owner), "dex:auto_range", &transactions).await;
+
+        Ok(RangeRebalance {
+            proposed_at: Utc::now(),
+            trigger,
+            token_id: position.token_id,
+            current_tick,
+            old_tick_lower: position.tick_lower,
+            old_tick_upper: position.tick_upper,
+            new_tick_lower,
+            new_tick_upper,
+            withdrawn_amount0: to_u256(available0),
+            withdrawn_amount1: to_u256(available1),
+            minted_amount0: to_u256(minted0),
+            minted_amount1: to_u256(minted1),
+            deadline,
+            approvals,
+            transactions,
+            execution_id: records.first().and_then(|record| record.execution_id.clone()),
+            tracking_ids: records.into_iter().map(|record| record.id).collect(),
+            new_token_id: None,
+        })
+    }
+
+    async fn persist(&self, strategies: &HashMap<String, AutoRangeStrategy>) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let stored: Vec<&AutoRangeStrategy> = strategies.values().collect();
+        if let Err(e) = write_store(path, &stored).await {
+            warn!("Failed to persist auto-range strategies to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Move a rebalance that completed or expired from pending to the history
+fn archive(strategy: &mut AutoRangeStrategy, rebalance: RangeRebalance) {
+    strategy.pending = None;
+    strategy.history.push(rebalance);
+    if strategy.history.len() > REBALANCE_HISTORY {
+        strategy.history.remove(0);
+    }
+}
+
+/// Why a range needs recentering at `tick`, `None` while the price is comfortably inside it
+fn range_trigger(tick: i32, tick_lower: i32, tick_upper: i32, edge_threshold_percentage: f64) -> Option<RangeTrigger> {
+    if tick < tick_lower || tick >= tick_upper {
+        return Some(RangeTrigger::OutOfRange);
+    }
+    let margin = (tick_upper - tick_lower) as f64 * edge_threshold_percentage / 100.0;
+    let distance = (tick - tick_lower).min(tick_upper - tick) as f64;
+    (distance < margin).then_some(RangeTrigger::NearEdge)
+}
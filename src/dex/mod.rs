@@ -1,34 +1,99 @@
-use anyhow::Result;
-use ethers::types::{Address, U256, H256, TransactionRequest};
+use anyhow::{Result, anyhow};
+use ethers::providers::Middleware;
+use ethers::abi::{self, ParamType, Token};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, U256, H256, Signature, TransactionRequest};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{info, error};
+use tracing::{debug, info, warn, error, instrument};
 
+use crate::analytics::impermanent_loss::{constant_product_projections, IlProjection};
+use crate::analytics::price_feeds::PriceFeedService;
+use crate::cache::CacheManager;
+use crate::chains::pool::PooledHttp;
 use crate::chains::ChainManager;
+use crate::chains::assets::AssetRegistry;
+use crate::contracts::approvals::{transaction_target, ApprovalManager, ApprovalPolicy, TokenApproval, TokenSpend};
+use crate::contracts::permit2::{Permit2Manager, Permit2Request};
+use crate::contracts::probes::ContractDeployment;
+use crate::security::{ExecutionPriceSample, MevProtection, MevThreat};
+use crate::transactions::{settlement::SwapQuote, TransactionRecord, TransactionTracker};
 
 pub mod uniswap;
+pub mod auto_range;
 pub mod sushiswap;
+pub mod uniswap_v2;
 pub mod aggregator;
-
-use self::aggregator::{DexAggregator, QuoteComparison, SlippageSettings, PriceImpactAnalysis};
+pub mod orders;
+pub mod pool_discovery;
+pub mod universal_router;
+pub mod quote_accuracy;
+pub mod swap_stats;
+
+use self::aggregator::{
+    BestRoute, DexAggregator, DexType, DexVenues, PriceImpactAnalysis, Quote, QuoteComparison, QuoteFreshness, QuoteMode, RouteKey,
+    ServedQuote, SlippageSettings, DEFAULT_DEADLINE_MINUTES, swap_deadline,
+};
+use self::aggregator::external::ExternalAggregatorConfig;
+use self::quote_accuracy::{QuoteSample, QuoteSampleSource};
+use self::swap_stats::{DexStats, SwapRecord, SwapRecordKind, SwapRecorder, SwapStatsFilter};
+use self::universal_router::UniversalRouterCall;
 
 /// Comprehensive DEX management system
 pub struct DexManager {
     chain_manager: Arc<ChainManager>,
     uniswap: uniswap::UniswapV3Manager,
     sushiswap: sushiswap::SushiSwapManager,
+    uniswap_v2: uniswap_v2::UniswapV2Manager,
     aggregator: DexAggregator,
+    approvals: Arc<ApprovalManager>,
+    permit2: Permit2Manager,
+    assets: Arc<AssetRegistry>,
+    transactions: Arc<TransactionTracker>,
+    /// Quoted and built swaps behind the DEX statistics
+    swaps: SwapRecorder,
 }
 
 /// DEX operation result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DexOperationResult {
+    /// Approval to send first when the router's allowance does not cover the input
+    pub approval: Option<TokenApproval>,
     pub transaction: TransactionRequest,
     pub expected_output: U256,
     pub price_impact: f64,
     pub gas_estimate: U256,
     pub dex_used: String,
     pub savings_percentage: f64,
+    /// Gas cost of the swap and its approval at current fees, `None` when either is unpriced
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub estimated_cost_usd: Option<f64>,
+    /// Transaction history record, linked to the hash once broadcast
+    pub tracking_id: String,
+}
+
+/// Swap of a Permit2 plan, routed through the Uniswap venue quoting best
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permit2Swap {
+    pub quote: Quote,
+    pub min_amount_out: U256,
+}
+
+/// Uniswap swaps paid through Permit2, so the owner signs a permit instead of approving each router
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permit2SwapPlan {
+    pub chain_id: u64,
+    pub owner: Address,
+    /// Approvals of Permit2 itself, needed once per token
+    pub approvals: Vec<TokenApproval>,
+    /// Permit to sign, `None` when the router's Permit2 allowances cover every swap
+    pub permit: Option<Permit2Request>,
+    pub swaps: Vec<Permit2Swap>,
+    /// Unix time after which the router rejects the swaps
+    pub deadline: u64,
+    /// Universal Router transaction, built once the permit is signed
+    pub transaction: Option<TransactionRequest>,
+    pub tracking_id: Option<String>,
 }
 
 /// Liquidity provision result
@@ -39,41 +104,31 @@ pub struct LiquidityResult {
     pub pool_address: Address,
     pub liquidity_amount: U256,
     pub token_amounts: (U256, U256),
-}
-
-/// DEX statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DexStats {
-    pub total_swaps: u64,
-    pub total_volume: U256,
-    pub average_savings: f64,
-    pub best_dex_performance: String,
-    pub price_impact_distribution: Vec<f64>,
+    pub tracking_id: String,
 }
 
 /// Farming opportunity information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FarmingOpportunity {
     pub dex: String,
+    /// Farm id in the chef
+    pub pid: u64,
+    /// LP token staked in the farm
     pub pool_address: Address,
+    /// Underlying tokens of the LP, zero when it is not a pair
     pub token_a: Address,
     pub token_b: Address,
+    /// Reward yield in percent, zero while the LP or the reward token is unpriced
     pub apy: f64,
+    /// LP tokens staked in the farm
     pub total_liquidity: U256,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub total_liquidity_usd: Option<f64>,
     pub reward_token: Address,
+    /// LP tokens the user has staked, zero without a user
     pub user_staked: U256,
-}
-
-/// Trading pair information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TradingPair {
-    pub token_a: Address,
-    pub token_b: Address,
-    pub dex: String,
-    pub pool_address: Address,
-    pub liquidity: U256,
-    pub volume_24h: U256,
-    pub fee_tier: u32,
+    /// Impermanent loss a new deposit would take over a range of price moves of the pair
+    pub impermanent_loss_projections: Vec<IlProjection>,
 }
 
 /// Protocol statistics
@@ -106,39 +161,67 @@ pub struct TokenInfo {
 }
 
 impl DexManager {
-    pub async fn new(chain_manager: Arc<ChainManager>) -> Result<Self> {
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        transactions: Arc<TransactionTracker>,
+        external_aggregators: ExternalAggregatorConfig,
+        approval_policy: ApprovalPolicy,
+        assets: Arc<AssetRegistry>,
+        caches: &CacheManager,
+        swaps: SwapRecorder,
+    ) -> Result<Self> {
         info!("Initializing comprehensive DEX manager");
 
-        let uniswap = uniswap::UniswapV3Manager::new(chain_manager.clone()).await?;
-        let sushiswap = sushiswap::SushiSwapManager::new(chain_manager.clone()).await?;
-        let aggregator = aggregator::DexAggregator::new().await?;
+        let uniswap = uniswap::UniswapV3Manager::new(chain_manager.clone(), caches).await?;
+        let sushiswap = sushiswap::SushiSwapManager::new(chain_manager.clone(), caches).await?;
+        let uniswap_v2 = uniswap_v2::UniswapV2Manager::new(chain_manager.clone()).await?;
+        let aggregator = aggregator::DexAggregator::new(external_aggregators, assets.clone()).await?;
+        let approvals = Arc::new(ApprovalManager::new(chain_manager.clone(), approval_policy));
+        let permit2 = Permit2Manager::new(chain_manager.clone(), approvals.clone());
 
         Ok(Self {
             chain_manager,
             uniswap,
             sushiswap,
+            uniswap_v2,
             aggregator,
+            approvals,
+            permit2,
+            assets,
+            transactions,
+            swaps,
         })
     }
 
-    pub async fn new_demo() -> Result<Self> {
+    pub async fn new_demo(transactions: Arc<TransactionTracker>) -> Result<Self> {
         info!("Creating DexManager in demo mode");
         
         // Create a minimal chain manager for demo
         let chain_manager = Arc::new(ChainManager::new_demo().await?);
         let uniswap = uniswap::UniswapV3Manager::new_demo().await?;
         let sushiswap = sushiswap::SushiSwapManager::new_demo().await?;
-        let aggregator = aggregator::DexAggregator::new().await?;
+        let uniswap_v2 = uniswap_v2::UniswapV2Manager::new_demo().await?;
+        let assets = Arc::new(AssetRegistry::builtin());
+        let aggregator = aggregator::DexAggregator::new(ExternalAggregatorConfig::default(), assets.clone()).await?;
+        let approvals = Arc::new(ApprovalManager::new(chain_manager.clone(), ApprovalPolicy::default()));
+        let permit2 = Permit2Manager::new(chain_manager.clone(), approvals.clone());
 
         Ok(Self {
             chain_manager,
             uniswap,
             sushiswap,
+            uniswap_v2,
             aggregator,
+            approvals,
+            permit2,
+            assets,
+            transactions,
+            swaps: SwapRecorder::new(None).await?,
         })
     }
 
     /// Execute optimal swap with automatic DEX selection
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?recipient))]
     pub async fn execute_optimal_swap(
         &self,
         chain_id: u64,
@@ -153,8 +236,7 @@ impl DexManager {
 
         // Find best route across all DEXes
         let comparison = self.aggregator.find_best_route(
-            &self.uniswap,
-            &self.sushiswap,
+            self.venues(),
             chain_id,
             token_in,
             token_out,
@@ -164,8 +246,7 @@ impl DexManager {
 
         // Execute with slippage protection
         let transaction = self.aggregator.execute_optimal_swap(
-            &self.uniswap,
-            &self.sushiswap,
+            self.venues(),
             chain_id,
             token_in,
             token_out,
@@ -174,13 +255,53 @@ impl DexManager {
             slippage_settings,
         ).await?;
 
+        let approval = match transaction_target(&transaction) {
+            Some(spender) => {
+                let spend = TokenSpend { token: token_in, spender, amount: amount_in };
+                self.approvals.required_approval(chain_id, recipient, spend, None).await?
+            }
+            None => None,
+        };
+        let approval_record = match &approval {
+            Some(approval) => {
+                Some(self.transactions.record_built(chain_id, Some(recipient), "dex:approve", &approval.transaction).await)
+            }
+            None => None,
+        };
+
+        let record = self.transactions.record_built_with_gas(
+            chain_id,
+            Some(recipient),
+            "dex:swap",
+            &transaction,
+            Some(comparison.best_route.gas_estimate),
+        ).await;
+        let quote = SwapQuote {
+            token_in,
+            token_out,
+            amount_in,
+            expected_output: comparison.best_route.output_amount,
+            dex: Some(comparison.best_route.dex.clone()),
+        };
+        self.transactions.quote_swaps(&record.id, vec![quote]).await?;
+        // Without an approval to wait for, the swap can be simulated from the recipient right away
+        if approval.is_none() {
+            self.sample_simulated_output(chain_id, &comparison.best_route, recipient, &transaction).await;
+        }
+        self.swaps.record(SwapRecord {
+            tracking_id: Some(record.id.clone()),
+            ..SwapRecord::new(SwapRecordKind::Swap, chain_id, token_in, token_out, &comparison)
+        }).await;
         let result = DexOperationResult {
+            approval,
             transaction,
             expected_output: comparison.best_route.output_amount,
             price_impact: comparison.best_route.price_impact,
             gas_estimate: comparison.best_route.gas_estimate,
             dex_used: format!("{:?}", comparison.best_route.dex),
             savings_percentage: comparison.savings_percentage,
+            estimated_cost_usd: swap_cost_usd(approval_record.as_ref(), &record),
+            tracking_id: record.id,
         };
 
         info!("Optimal swap prepared using {:?} with {}% savings", 
@@ -202,8 +323,7 @@ impl DexManager {
                amount_in, token_in, token_out, chain_id);
 
         self.aggregator.find_best_route(
-            &self.uniswap,
-            &self.sushiswap,
+            self.venues(),
             chain_id,
             token_in,
             token_out,
@@ -212,6 +332,36 @@ impl DexManager {
         ).await
     }
 
+    /// Quotes in the requested mode; fast ones fall back to exact when nothing is cached for the pair
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?recipient))]
+    pub async fn get_quotes(
+        &self,
+        chain_id: u64,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        recipient: Address,
+        mode: QuoteMode,
+    ) -> Result<ServedQuote> {
+        if mode == QuoteMode::Fast {
+            let key = RouteKey { chain_id, token_in, token_out, amount_in, recipient };
+            if let Some((comparison, freshness)) = self.aggregator.fast_route(self.venues(), key).await {
+                self.swaps.record(SwapRecord::new(SwapRecordKind::Quote, chain_id, token_in, token_out, &comparison)).await;
+                return Ok(ServedQuote { requested_mode: mode, mode, freshness, comparison });
+            }
+            info!("No cached quote for {} -> {} on chain {}, serving an exact one", token_in, token_out, chain_id);
+        }
+
+        let comparison = self.get_comprehensive_quotes(chain_id, token_in, token_out, amount_in, recipient).await?;
+        self.swaps.record(SwapRecord::new(SwapRecordKind::Quote, chain_id, token_in, token_out, &comparison)).await;
+        Ok(ServedQuote {
+            requested_mode: mode,
+            mode: QuoteMode::Exact,
+            freshness: QuoteFreshness { quoted_at: chrono::Utc::now(), age_ms: 0, approximate: false },
+            comparison,
+        })
+    }
+
     /// Analyze price impact and provide trading recommendations
     pub async fn analyze_trade_impact(
         &self,
@@ -224,8 +374,7 @@ impl DexManager {
                amount_in, token_in, token_out, chain_id);
 
         self.aggregator.analyze_price_impact(
-            &self.uniswap,
-            &self.sushiswap,
+            self.venues(),
             chain_id,
             token_in,
             token_out,
@@ -234,6 +383,7 @@ impl DexManager {
     }
 
     /// Batch multiple swaps for gas optimization
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?recipient))]
     pub async fn batch_optimal_swaps(
         &self,
         chain_id: u64,
@@ -243,36 +393,151 @@ impl DexManager {
         info!("Batching {} swaps for gas optimization on chain {}", swaps.len(), chain_id);
 
         let transactions = self.aggregator.batch_swaps(
-            &self.uniswap,
-            &self.sushiswap,
+            self.venues(),
             chain_id,
             swaps.clone(),
             recipient,
         ).await?;
 
+        // Earlier swaps of the batch consume allowance later ones may have counted on
+        let spends: Vec<TokenSpend> = swaps.iter()
+            .zip(&transactions)
+            .filter_map(|((token_in, _, amount_in), tx)| {
+                transaction_target(tx).map(|spender| TokenSpend { token: *token_in, spender, amount: *amount_in })
+            })
+            .collect();
+        if spends.len() != swaps.len() {
+            return Err(anyhow!("Batched swap transaction without a router address"));
+        }
+        let mut approvals = self.approvals.plan_approvals(chain_id, recipient, &spends, None).await?.into_iter();
+
         let mut results = Vec::new();
         for (i, tx) in transactions.into_iter().enumerate() {
             let (token_in, token_out, amount_in) = &swaps[i];
+            let approval = approvals.next().flatten();
+            let approval_record = match &approval {
+                Some(approval) => {
+                    Some(self.transactions.record_built(chain_id, Some(recipient), "dex:approve", &approval.transaction).await)
+                }
+                None => None,
+            };
             
             // Get quote for this specific swap to get the details
             let comparison = self.get_comprehensive_quotes(
                 chain_id, *token_in, *token_out, *amount_in, recipient
             ).await?;
 
+            let record = self.transactions.record_built_with_gas(
+                chain_id,
+                Some(recipient),
+                "dex:batch_swap",
+                &tx,
+                Some(comparison.best_route.gas_estimate),
+            ).await;
             results.push(DexOperationResult {
+                approval,
                 transaction: tx,
                 expected_output: comparison.best_route.output_amount,
                 price_impact: comparison.best_route.price_impact,
                 gas_estimate: comparison.best_route.gas_estimate,
                 dex_used: format!("{:?}", comparison.best_route.dex),
                 savings_percentage: comparison.savings_percentage,
+                estimated_cost_usd: swap_cost_usd(approval_record.as_ref(), &record),
+                tracking_id: record.id,
             });
         }
 
         Ok(results)
     }
 
+    /// Plan Uniswap swaps through the Universal Router, paid with a Permit2 signature
+    ///
+    /// The transaction is built right away when no permit is needed, otherwise once
+    /// `complete_permit2_swaps` receives the owner's signature.
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?owner))]
+    pub async fn plan_permit2_swaps(
+        &self,
+        chain_id: u64,
+        owner: Address,
+        swaps: Vec<(Address, Address, U256)>, // (token_in, token_out, amount_in)
+        slippage_settings: Option<SlippageSettings>,
+    ) -> Result<Permit2SwapPlan> {
+        info!("Planning {} Permit2 swaps for {:?} on chain {}", swaps.len(), owner, chain_id);
+        if swaps.is_empty() {
+            return Err(anyhow!("No swaps to plan"));
+        }
+        if let Some(settings) = &slippage_settings {
+            settings.validate()?;
+        }
+        let router = universal_router::router_address(chain_id)?;
+
+        let mut routed = Vec::with_capacity(swaps.len());
+        for (token_in, token_out, amount_in) in &swaps {
+            let key = RouteKey { chain_id, token_in: *token_in, token_out: *token_out, amount_in: *amount_in, recipient: owner };
+            let (quote, min_amount_out) = self.aggregator.uniswap_route(self.venues(), key, slippage_settings.clone()).await?;
+            routed.push(Permit2Swap { quote, min_amount_out });
+        }
+
+        let spends: Vec<(Address, U256)> = swaps.iter().map(|(token_in, _, amount_in)| (*token_in, *amount_in)).collect();
+        let approvals = self.permit2.token_approvals(chain_id, owner, &spends).await?;
+        let permit = self.permit2.permit_request(chain_id, owner, router, &spends).await?;
+        let deadline_minutes = slippage_settings.map_or(DEFAULT_DEADLINE_MINUTES, |s| s.deadline_minutes);
+
+        let plan = Permit2SwapPlan {
+            chain_id,
+            owner,
+            approvals,
+            permit,
+            swaps: routed,
+            deadline: swap_deadline(deadline_minutes),
+            transaction: None,
+            tracking_id: None,
+        };
+        if plan.permit.is_some() {
+            return Ok(plan);
+        }
+        self.complete_permit2_swaps(plan, None).await
+    }
+
+    /// Build the Universal Router transaction of a plan, with the owner's permit signature
+    /// when the plan carries a permit
+    #[instrument(skip_all, fields(chain_id = plan.chain_id, wallet = ?plan.owner))]
+    pub async fn complete_permit2_swaps(&self, mut plan: Permit2SwapPlan, signature: Option<Signature>) -> Result<Permit2SwapPlan> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        if plan.deadline <= now {
+            return Err(anyhow!("Permit2 swap plan expired, plan the swaps again"));
+        }
+
+        let mut call = UniversalRouterCall::default();
+        match (&plan.permit, signature) {
+            (Some(request), Some(signature)) => {
+                if request.permit.sig_deadline <= U256::from(now) {
+                    return Err(anyhow!("Permit2 signature deadline passed, plan the swaps again"));
+                }
+                request.permit.verify(plan.chain_id, plan.owner, &signature)?;
+                call = call.permit(&request.permit, &signature);
+            }
+            (Some(_), None) => return Err(anyhow!("Permit2 swaps need the owner's permit signature")),
+            (None, _) => {}
+        }
+        for swap in &plan.swaps {
+            call = call.swap_exact_in(&swap.quote, swap.min_amount_out, plan.owner)?;
+        }
+        let transaction = call.into_transaction(plan.chain_id, plan.deadline)?.from(plan.owner);
+
+        for approval in &plan.approvals {
+            self.transactions.record_built(plan.chain_id, Some(plan.owner), "dex:approve", &approval.transaction).await;
+        }
+        let record = self.transactions.record_built(plan.chain_id, Some(plan.owner), "dex:permit2_swap", &transaction).await;
+        info!("Permit2 swap transaction built for {:?} with {} swaps", plan.owner, plan.swaps.len());
+
+        plan.transaction = Some(transaction);
+        plan.tracking_id = Some(record.id);
+        Ok(plan)
+    }
+
     /// Add liquidity to the best available pool
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?recipient))]
     pub async fn add_optimal_liquidity(
         &self,
         chain_id: u64,
@@ -289,17 +554,20 @@ impl DexManager {
         match self.uniswap.add_liquidity(
             chain_id, token_a, token_b, 3000, -887220, 887220, // Full range
             amount_a, amount_b, U256::zero(), U256::zero(), recipient, 
-            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 1800
+            swap_deadline(DEFAULT_DEADLINE_MINUTES)
         ).await {
             Ok(uniswap_tx) => {
                 let pool_info = self.uniswap.get_pool_info(chain_id, token_a, token_b, 3000).await?;
                 
+                let record = self.transactions.record_built(chain_id, Some(recipient), "dex:add_liquidity", &uniswap_tx).await;
+
                 Ok(LiquidityResult {
                     add_transaction: Some(uniswap_tx),
                     remove_transaction: None,
                     pool_address: pool_info.address,
                     liquidity_amount: U256::zero(), // Would be calculated based on pool response
                     token_amounts: (amount_a, amount_b),
+                    tracking_id: record.id,
                 })
             },
             Err(_) => {
@@ -308,23 +576,27 @@ impl DexManager {
                 
                 let sushiswap_tx = self.sushiswap.add_liquidity(
                     chain_id, token_a, token_b, amount_a, amount_b, U256::zero(), U256::zero(), recipient,
-                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 1800
+                    swap_deadline(DEFAULT_DEADLINE_MINUTES)
                 ).await?;
 
                 let pair_info = self.sushiswap.get_pair_info(chain_id, token_a, token_b).await?;
 
+                let record = self.transactions.record_built(chain_id, Some(recipient), "dex:add_liquidity", &sushiswap_tx).await;
+
                 Ok(LiquidityResult {
                     add_transaction: Some(sushiswap_tx),
                     remove_transaction: None,
                     pool_address: pair_info.address,
                     liquidity_amount: U256::zero(),
                     token_amounts: (amount_a, amount_b),
+                    tracking_id: record.id,
                 })
             }
         }
     }
 
     /// Remove liquidity from pools
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?recipient))]
     pub async fn remove_optimal_liquidity(
         &self,
         chain_id: u64,
@@ -340,44 +612,191 @@ impl DexManager {
         // Try Uniswap V3 first
         match self.uniswap.remove_liquidity(
             chain_id, U256::from(1), liquidity_amount, U256::zero(), U256::zero(), // token_id would need to be tracked
-            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 1800
+            swap_deadline(DEFAULT_DEADLINE_MINUTES)
         ).await {
             Ok(uniswap_tx) => {
                 let pool_info = self.uniswap.get_pool_info(chain_id, token_a, token_b, 3000).await?;
                 
+                let record = self.transactions.record_built(chain_id, Some(recipient), "dex:remove_liquidity", &uniswap_tx).await;
+
                 Ok(LiquidityResult {
                     add_transaction: None,
                     remove_transaction: Some(uniswap_tx),
                     pool_address: pool_info.address,
                     liquidity_amount,
                     token_amounts: (U256::zero(), U256::zero()),
+                    tracking_id: record.id,
                 })
             },
             Err(_) => {
                 // Try SushiSwap
                 let sushiswap_tx = self.sushiswap.remove_liquidity(
                     chain_id, token_a, token_b, liquidity_amount, U256::zero(), U256::zero(), recipient,
-                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 1800
+                    swap_deadline(DEFAULT_DEADLINE_MINUTES)
                 ).await?;
 
                 let pair_info = self.sushiswap.get_pair_info(chain_id, token_a, token_b).await?;
 
+                let record = self.transactions.record_built(chain_id, Some(recipient), "dex:remove_liquidity", &sushiswap_tx).await;
+
                 Ok(LiquidityResult {
                     add_transaction: None,
                     remove_transaction: Some(sushiswap_tx),
                     pool_address: pair_info.address,
                     liquidity_amount,
                     token_amounts: (U256::zero(), U256::zero()),
+                    tracking_id: record.id,
                 })
             }
         }
     }
 
-    /// Get farming opportunities across all DEXes
+    /// Check an executed swap for sandwiching and feed the result into venue selection
+    pub async fn analyze_execution(
+        &self,
+        chain_id: u64,
+        dex: DexType,
+        pool: Address,
+        tx_hash: H256,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        amount_out: U256,
+        expected_price_impact: f64,
+    ) -> Result<Option<MevThreat>> {
+        info!("Analyzing execution {:?} on {:?} pool {:?}", tx_hash, dex, pool);
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = &chain_provider.provider;
+
+        let receipt = provider
+            .get_transaction_receipt(tx_hash)
+            .await?
+            .ok_or_else(|| anyhow!("Transaction {:?} not yet mined", tx_hash))?;
+        let block_number = receipt
+            .block_number
+            .ok_or_else(|| anyhow!("Receipt for {:?} has no block number", tx_hash))?
+            .as_u64();
+
+        // Pool prices are token1 per token0; token0 is always the lower address
+        let (pre_raw, post_raw) = match dex {
+            DexType::UniswapV3 => (
+                self.uniswap.get_pool_price_at_block(chain_id, pool, block_number - 1).await?,
+                self.uniswap.get_pool_price_at_block(chain_id, pool, block_number + 1).await?,
+            ),
+            DexType::SushiSwap => (
+                self.sushiswap.get_pair_price_at_block(chain_id, pool, block_number - 1).await?,
+                self.sushiswap.get_pair_price_at_block(chain_id, pool, block_number + 1).await?,
+            ),
+            DexType::UniswapV2 => (
+                self.uniswap_v2.get_pair_price_at_block(chain_id, pool, block_number - 1).await?,
+                self.uniswap_v2.get_pair_price_at_block(chain_id, pool, block_number + 1).await?,
+            ),
+        };
+        let orient = |price: f64| if token_in < token_out || price == 0.0 { price } else { 1.0 / price };
+
+        let suspected_attacker = self
+            .find_sandwich_sender(provider, block_number, receipt.transaction_index.as_u64())
+            .await
+            .unwrap_or(None);
+
+        let sample = ExecutionPriceSample {
+            transaction_hash: tx_hash,
+            block_number,
+            pool,
+            amount_in,
+            amount_out,
+            pre_trade_price: orient(pre_raw),
+            post_trade_price: orient(post_raw),
+            expected_price_impact,
+            suspected_attacker,
+        };
+
+        let threat = MevProtection::detect_sandwich_from_prices(&sample);
+        self.aggregator.record_execution_outcome(dex, amount_out, threat.as_ref()).await;
+
+        Ok(threat)
+    }
+
+    /// Call a built swap from its sender and record the output against the route's quote
+    async fn sample_simulated_output(&self, chain_id: u64, route: &BestRoute, from: Address, transaction: &TransactionRequest) {
+        let output = match self.simulate_swap_output(chain_id, &route.dex, from, transaction).await {
+            Ok(output) => output,
+            Err(e) => {
+                debug!("Could not simulate the {:?} swap: {}", route.dex, e);
+                return;
+            }
+        };
+        let (Some(token_in), Some(token_out)) = (route.path.first(), route.path.last()) else {
+            return;
+        };
+        let sample = QuoteSample::new(
+            route.dex.clone(), QuoteSampleSource::Simulated, *token_in, *token_out, route.output_amount, output, None,
+        );
+        if let Some(sample) = sample {
+            self.aggregator.record_quote_sample(sample).await;
+        }
+    }
+
+    /// Output of a router swap called at the latest block; V3 routers return it, V2 routers return
+    /// the amounts of every hop
+    async fn simulate_swap_output(&self, chain_id: u64, dex: &DexType, from: Address, transaction: &TransactionRequest) -> Result<U256> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let call: TypedTransaction = transaction.clone().from(from).into();
+        let output = chain.provider.call(&call, None).await?;
+        let outputs = match dex {
+            DexType::UniswapV3 => vec![ParamType::Uint(256)],
+            DexType::SushiSwap | DexType::UniswapV2 => vec![ParamType::Array(Box::new(ParamType::Uint(256)))],
+        };
+        let amount = match abi::decode(&outputs, &output)?.pop() {
+            Some(Token::Uint(amount)) => Some(amount),
+            Some(Token::Array(amounts)) => amounts.into_iter().last().and_then(Token::into_uint),
+            _ => None,
+        };
+        amount.ok_or_else(|| anyhow!("Unexpected {:?} swap output", dex))
+    }
+
+    /// Look for one sender trading through the same contract right before and after our transaction
+    async fn find_sandwich_sender(
+        &self,
+        provider: &ethers::providers::Provider<PooledHttp>,
+        block_number: u64,
+        our_index: u64,
+    ) -> Result<Option<Address>> {
+        let block = match provider.get_block_with_txs(block_number).await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        let window = 3;
+        let before: Vec<_> = block.transactions.iter()
+            .filter(|tx| {
+                let index = tx.transaction_index.map(|i| i.as_u64()).unwrap_or(u64::MAX);
+                index < our_index && our_index - index <= window
+            })
+            .collect();
+        let after: Vec<_> = block.transactions.iter()
+            .filter(|tx| {
+                let index = tx.transaction_index.map(|i| i.as_u64()).unwrap_or(0);
+                index > our_index && index - our_index <= window
+            })
+            .collect();
+
+        for front in &before {
+            if after.iter().any(|back| back.from == front.from && back.to == front.to) {
+                return Ok(Some(front.from));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get farming opportunities across all DEXes, with reward yields priced by `price_feeds`
     pub async fn get_farming_opportunities(
         &self,
         chain_id: u64,
         user_address: Address,
+        price_feeds: &PriceFeedService,
     ) -> Result<Vec<FarmingOpportunity>> {
         info!("Getting farming opportunities for user {} on chain {}", user_address, chain_id);
 
@@ -386,16 +805,46 @@ impl DexManager {
         // Get SushiSwap farming opportunities
         match self.sushiswap.get_all_farms(chain_id).await {
             Ok(farms) => {
+                let stakes = if user_address.is_zero() {
+                    HashMap::new()
+                } else {
+                    let pids: Vec<u64> = farms.iter().map(|farm| farm.pid).collect();
+                    self.sushiswap.get_user_stakes(chain_id, user_address, &pids).await.unwrap_or_else(|e| {
+                        warn!("Failed to read SushiSwap stakes of {}: {}", user_address, e);
+                        HashMap::new()
+                    })
+                };
+
+                let mut tokens: Vec<Address> = farms.iter()
+                    .flat_map(|farm| {
+                        let pair = farm.pair.as_ref();
+                        [Some(farm.reward_token), pair.map(|pair| pair.token0), pair.map(|pair| pair.token1)]
+                    })
+                    .flatten()
+                    .collect();
+                tokens.sort();
+                tokens.dedup();
+                let prices: HashMap<Address, f64> = match price_feeds.get_prices(chain_id, &tokens).await {
+                    Ok(prices) => prices.into_iter().map(|(token, price)| (token, price.price_usd)).collect(),
+                    Err(e) => {
+                        warn!("Failed to price SushiSwap farms on chain {}: {}", chain_id, e);
+                        HashMap::new()
+                    }
+                };
+
                 for farm in farms {
                     opportunities.push(FarmingOpportunity {
                         dex: "SushiSwap".to_string(),
+                        pid: farm.pid,
                         pool_address: farm.lp_token,
-                        token_a: Address::zero(), // Would be extracted from pair info
-                        token_b: Address::zero(),
-                        apy: farm.apy,
+                        token_a: farm.pair.as_ref().map(|pair| pair.token0).unwrap_or_default(),
+                        token_b: farm.pair.as_ref().map(|pair| pair.token1).unwrap_or_default(),
+                        apy: farm.apy(&prices).unwrap_or_default(),
                         total_liquidity: farm.total_staked,
-                        reward_token: Address::zero(), // SUSHI token address - would be fetched
-                        user_staked: U256::zero(), // Would be queried
+                        total_liquidity_usd: farm.staked_value_usd(&prices),
+                        reward_token: farm.reward_token,
+                        user_staked: stakes.get(&farm.pid).copied().unwrap_or_default(),
+                        impermanent_loss_projections: constant_product_projections(),
                     });
                 }
             },
@@ -407,39 +856,46 @@ impl DexManager {
         Ok(opportunities)
     }
 
-    /// Get comprehensive DEX statistics
-    pub async fn get_dex_statistics(&self, chain_id: u64) -> Result<DexStats> {
-        info!("Getting DEX statistics for chain {}", chain_id);
+    /// DEX statistics computed from the recorded quotes and swaps
+    pub async fn get_dex_statistics(&self, filter: &SwapStatsFilter) -> DexStats {
+        self.swaps.stats(filter).await
+    }
 
-        // This would be implemented with actual data tracking
-        Ok(DexStats {
-            total_swaps: 1000,
-            total_volume: U256::from(1_000_000),
-            average_savings: 0.75, // 0.75% average savings
-            best_dex_performance: "UniswapV3".to_string(),
-            price_impact_distribution: vec![0.1, 0.2, 0.5, 0.8, 1.2],
-        })
+    // Utility methods for direct DEX access
+    pub fn uniswap(&self) -> &uniswap::UniswapV3Manager {
+        &self.uniswap
     }
 
-    /// Get all available trading pairs across DEXes
-    pub async fn get_available_pairs(&self, chain_id: u64) -> Result<Vec<TradingPair>> {
-        info!("Getting available trading pairs for chain {}", chain_id);
+    pub fn sushiswap(&self) -> &sushiswap::SushiSwapManager {
+        &self.sushiswap
+    }
 
-        let mut pairs = Vec::new();
+    pub fn uniswap_v2(&self) -> &uniswap_v2::UniswapV2Manager {
+        &self.uniswap_v2
+    }
 
-        // This would query each DEX for their available pairs
-        // Implementation would depend on the specific DEX APIs/subgraphs
+    pub fn approvals(&self) -> &Arc<ApprovalManager> {
+        &self.approvals
+    }
 
-        Ok(pairs)
+    pub fn assets(&self) -> &Arc<AssetRegistry> {
+        &self.assets
     }
 
-    // Utility methods for direct DEX access
-    pub fn uniswap(&self) -> &uniswap::UniswapV3Manager {
-        &self.uniswap
+    /// Address-registry entries of every venue
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        let mut deployments = self.uniswap.deployments();
+        deployments.extend(self.sushiswap.deployments());
+        deployments.extend(self.uniswap_v2.deployments());
+        deployments
     }
 
-    pub fn sushiswap(&self) -> &sushiswap::SushiSwapManager {
-        &self.sushiswap
+    fn venues(&self) -> DexVenues<'_> {
+        DexVenues {
+            uniswap: &self.uniswap,
+            sushiswap: &self.sushiswap,
+            uniswap_v2: &self.uniswap_v2,
+        }
     }
 
     pub fn aggregator(&self) -> &DexAggregator {
@@ -524,3 +980,12 @@ impl DexManager {
         Ok(vec![])
     }
 }
+
+/// Gas cost of a swap and the approval sent before it, `None` when either is unpriced
+fn swap_cost_usd(approval: Option<&TransactionRecord>, swap: &TransactionRecord) -> Option<f64> {
+    let approval_cost = match approval {
+        Some(approval) => approval.estimated_cost_usd?,
+        None => 0.0,
+    };
+    swap.estimated_cost_usd.map(|cost| cost + approval_cost)
+}
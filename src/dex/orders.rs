@@ -0,0 +1,620 @@
+// TWAP, limit and DCA orders executed in the background through the aggregator
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use cron::Schedule;
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, instrument, warn};
+
+use super::aggregator::{SlippageSettings, MAX_SLIPPAGE_PERCENTAGE};
+use super::DexManager;
+use crate::analytics::time_zones::TimeZoneSettings;
+use crate::chains::tx_broadcaster::TxBroadcaster;
+use crate::contracts::erc20::ERC20Contract;
+use crate::security::{CircuitBreakers, PolicyContext, TradePriceGuard};
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::{read_store, write_store};
+use crate::wallets::{labels::WalletUse, WalletManager, WalletType};
+
+/// Store used when `orders_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/orders.json";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Wait before retrying a slice whose swap could not be built or submitted
+const RETRY_DELAY_SECONDS: i64 = 60;
+/// Consecutive execution failures before an order is given up
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+const MAX_TWAP_SLICES: u32 = 1_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OrderKind {
+    /// Split the amount into equal slices swapped `interval_seconds` apart
+    Twap { slices: u32, interval_seconds: u64 },
+    /// Swap the whole amount once the best quote pays at least `limit_price`
+    Limit {
+        /// Whole `token_out` per whole `token_in`
+        limit_price: f64,
+        /// Output for the full amount at the limit price, in `token_out` base units
+        min_amount_out: U256,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    /// Buy with `amount_per_buy` on a cron schedule in the owner's time zone until the budget is spent
+    Dca {
+        /// `min hour day-of-month month day-of-week`, optionally prefixed with seconds
+        schedule: String,
+        amount_per_buy: U256,
+        token_in_decimals: u8,
+        token_out_decimals: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Open,
+    Filled,
+    Cancelled,
+    Expired,
+    Failed,
+}
+
+/// A swap built, and submitted when the order allows it, for part or all of an order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderFill {
+    pub amount_in: U256,
+    pub expected_output: U256,
+    pub dex: String,
+    /// Transaction history record of the swap
+    pub tracking_id: String,
+    /// Hash of the submitted swap, `None` when it was only built for the owner to sign
+    pub tx_hash: Option<H256>,
+    pub executed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub id: String,
+    pub owner: Address,
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub kind: OrderKind,
+    /// Sign and broadcast fills with the owner's server-side wallet instead of only building them
+    pub auto_submit: bool,
+    /// Fixed tolerance per fill, the pool's recommended slippage when `None`
+    pub max_slippage_percentage: Option<f64>,
+    pub status: OrderStatus,
+    pub amount_filled: U256,
+    pub fills: Vec<OrderFill>,
+    pub next_execution_at: DateTime<Utc>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Order {
+    /// Amount the next execution swaps, the last TWAP slice takes the rounding remainder
+    fn next_amount(&self) -> U256 {
+        let remaining = self.amount_in.saturating_sub(self.amount_filled);
+        match &self.kind {
+            OrderKind::Twap { slices, .. } if (self.fills.len() as u32) + 1 < *slices => {
+                (self.amount_in / U256::from(*slices)).min(remaining)
+            }
+            OrderKind::Dca { amount_per_buy, .. } => (*amount_per_buy).min(remaining),
+            _ => remaining,
+        }
+    }
+
+    /// Record a fill; `next_run` is the next scheduled buy of a DCA order
+    fn record_fill(&mut self, fill: OrderFill, now: DateTime<Utc>, next_run: Option<DateTime<Utc>>) {
+        self.amount_filled = self.amount_filled.saturating_add(fill.amount_in);
+        self.fills.push(fill);
+        self.consecutive_failures = 0;
+        self.last_error = None;
+        if self.amount_filled >= self.amount_in {
+            self.status = OrderStatus::Filled;
+        } else if let OrderKind::Twap { interval_seconds, .. } = &self.kind {
+            self.next_execution_at = now + ChronoDuration::seconds(*interval_seconds as i64);
+        } else if let Some(next_run) = next_run {
+            self.next_execution_at = next_run;
+        }
+        self.updated_at = now;
+    }
+
+    /// Record a failed execution; a DCA order skips to `next_run` instead of failing
+    fn record_failure(&mut self, error: String, now: DateTime<Utc>, next_run: Option<DateTime<Utc>>) {
+        self.consecutive_failures += 1;
+        self.last_error = Some(error);
+        self.next_execution_at = now + ChronoDuration::seconds(RETRY_DELAY_SECONDS);
+        if self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            match next_run {
+                Some(next_run) => {
+                    self.consecutive_failures = 0;
+                    self.next_execution_at = next_run;
+                }
+                None => self.status = OrderStatus::Failed,
+            }
+        }
+        self.updated_at = now;
+    }
+
+    /// `None` unless this is a DCA order
+    pub fn dca_summary(&self) -> Option<DcaSummary> {
+        let OrderKind::Dca { token_in_decimals, token_out_decimals, .. } = &self.kind else {
+            return None;
+        };
+        let amount_bought = self.fills.iter().fold(U256::zero(), |total, fill| total.saturating_add(fill.expected_output));
+        let whole = |amount: U256, decimals: u8| {
+            ethers::utils::format_units(amount, decimals as u32)
+                .ok()
+                .and_then(|units| units.parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+        let bought = whole(amount_bought, *token_out_decimals);
+
+        Some(DcaSummary {
+            buys: self.fills.len(),
+            amount_spent: self.amount_filled,
+            amount_bought,
+            remaining_budget: self.amount_in.saturating_sub(self.amount_filled),
+            average_entry_price: (bought > 0.0).then(|| whole(self.amount_filled, *token_in_decimals) / bought),
+            next_buy_at: (self.status == OrderStatus::Open).then_some(self.next_execution_at),
+        })
+    }
+}
+
+/// Spending and average entry price of a DCA plan, from the quoted output of each buy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DcaSummary {
+    pub buys: usize,
+    pub amount_spent: U256,
+    pub amount_bought: U256,
+    pub remaining_budget: U256,
+    /// Whole `token_in` paid per whole `token_out`, `None` before the first buy
+    pub average_entry_price: Option<f64>,
+    pub next_buy_at: Option<DateTime<Utc>>,
+}
+
+/// A DCA order with its summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DcaPlan {
+    #[serde(flatten)]
+    pub order: Order,
+    pub summary: DcaSummary,
+}
+
+/// Fields shared by TWAP, limit and DCA order submissions
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrderRequest {
+    pub owner: Address,
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub amount_in: U256,
+    #[serde(default)]
+    pub auto_submit: bool,
+    pub max_slippage_percentage: Option<f64>,
+}
+
+/// Accepts TWAP and limit orders and executes them when due
+pub struct OrderEngine {
+    dex_manager: Arc<DexManager>,
+    wallet_manager: Arc<WalletManager>,
+    broadcaster: Arc<TxBroadcaster>,
+    /// Owners' time zones DCA schedules run in
+    time_zones: Arc<TimeZoneSettings>,
+    /// Refuses fills trading too far from the oracle prices
+    price_guard: Arc<TradePriceGuard>,
+    /// Due orders wait while a breaker halts trading
+    circuit_breakers: Arc<CircuitBreakers>,
+    /// JSON file holding the orders, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    poll_interval: Duration,
+    orders: RwLock<Vec<Order>>,
+}
+
+impl OrderEngine {
+    pub async fn new(
+        dex_manager: Arc<DexManager>,
+        wallet_manager: Arc<WalletManager>,
+        broadcaster: Arc<TxBroadcaster>,
+        time_zones: Arc<TimeZoneSettings>,
+        price_guard: Arc<TradePriceGuard>,
+        circuit_breakers: Arc<CircuitBreakers>,
+        store_path: Option<PathBuf>,
+        poll_interval: Duration,
+    ) -> Result<Self> {
+        let orders: Vec<Order> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !orders.is_empty()) {
+            info!("Loaded {} orders from {}", orders.len(), path.display());
+        }
+
+        Ok(Self {
+            dex_manager,
+            wallet_manager,
+            broadcaster,
+            time_zones,
+            price_guard,
+            circuit_breakers,
+            store_path,
+            poll_interval,
+            orders: RwLock::new(orders),
+        })
+    }
+
+    /// Engine persisting to `orders_store_path` and checking orders every `order_poll_interval_secs`
+    pub async fn from_config(
+        config: &config::Config,
+        dex_manager: Arc<DexManager>,
+        wallet_manager: Arc<WalletManager>,
+        broadcaster: Arc<TxBroadcaster>,
+        time_zones: Arc<TimeZoneSettings>,
+        price_guard: Arc<TradePriceGuard>,
+        circuit_breakers: Arc<CircuitBreakers>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("orders_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        let poll_interval = config
+            .get_int("order_poll_interval_secs")
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        Self::new(
+            dex_manager, wallet_manager, broadcaster, time_zones, price_guard, circuit_breakers, store_path, poll_interval,
+        ).await
+    }
+
+    /// Execute due orders in the background until shutdown, never leaving a swap half built
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.execute_due().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Order engine stopped");
+        })
+    }
+
+    /// Accept a TWAP order, its first slice executes on the next poll
+    pub async fn submit_twap(&self, request: OrderRequest, slices: u32, interval_seconds: u64) -> Result<Order> {
+        if slices == 0 || slices > MAX_TWAP_SLICES {
+            return Err(anyhow!("A TWAP order needs between 1 and {} slices", MAX_TWAP_SLICES));
+        }
+        if request.amount_in < U256::from(slices) {
+            return Err(anyhow!("Amount {} cannot be split into {} slices", request.amount_in, slices));
+        }
+        self.insert(request, OrderKind::Twap { slices, interval_seconds }, Utc::now()).await
+    }
+
+    /// Accept a limit order, the target price is converted to a minimum output using the tokens' decimals
+    pub async fn submit_limit(
+        &self,
+        request: OrderRequest,
+        limit_price: f64,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Order> {
+        if !limit_price.is_finite() || limit_price <= 0.0 {
+            return Err(anyhow!("Limit price must be positive"));
+        }
+        if expires_at.is_some_and(|expiry| expiry <= Utc::now()) {
+            return Err(anyhow!("Limit order expiry is in the past"));
+        }
+
+        let decimals_in = self.token_decimals(request.chain_id, request.token_in).await;
+        let decimals_out = self.token_decimals(request.chain_id, request.token_out).await;
+        let amount_in = ethers::utils::format_units(request.amount_in, decimals_in as u32)?
+            .parse::<f64>()?;
+        let min_amount_out = ethers::utils::parse_units(
+            format!("{:.*}", decimals_out as usize, amount_in * limit_price),
+            decimals_out as u32,
+        )?
+        .into();
+
+        self.insert(request, OrderKind::Limit { limit_price, min_amount_out, expires_at }, Utc::now()).await
+    }
+
+    /// Accept a DCA plan spending `amount_in` in buys of `amount_per_buy`, the first at the next scheduled time
+    pub async fn submit_dca(&self, request: OrderRequest, schedule: String, amount_per_buy: U256) -> Result<Order> {
+        if amount_per_buy.is_zero() || amount_per_buy > request.amount_in {
+            return Err(anyhow!("Amount per buy must be positive and within the budget of {}", request.amount_in));
+        }
+        let parsed = parse_schedule(&schedule)?;
+        let settings = self.time_zones.get(request.owner).await;
+        let first_run = next_run(&parsed, settings.time_zone, Utc::now())
+            .ok_or_else(|| anyhow!("Schedule {} never runs", schedule))?;
+
+        let kind = OrderKind::Dca {
+            schedule,
+            amount_per_buy,
+            token_in_decimals: self.token_decimals(request.chain_id, request.token_in).await,
+            token_out_decimals: self.token_decimals(request.chain_id, request.token_out).await,
+        };
+        self.insert(request, kind, first_run).await
+    }
+
+    pub async fn get(&self, id: &str) -> Option<Order> {
+        self.orders.read().await.iter().find(|order| order.id == id).cloned()
+    }
+
+    /// Orders of an owner, newest first
+    pub async fn list(&self, owner: Address, status: Option<OrderStatus>) -> Vec<Order> {
+        self.orders.read().await.iter()
+            .rev()
+            .filter(|order| order.owner == owner && status.is_none_or(|status| order.status == status))
+            .cloned()
+            .collect()
+    }
+
+    /// DCA plans of an owner with their summaries, newest first
+    pub async fn dca_plans(&self, owner: Address, status: Option<OrderStatus>) -> Vec<DcaPlan> {
+        self.list(owner, status).await
+            .into_iter()
+            .filter_map(|order| order.dca_summary().map(|summary| DcaPlan { order, summary }))
+            .collect()
+    }
+
+    pub async fn dca_plan(&self, id: &str) -> Option<DcaPlan> {
+        let order = self.get(id).await?;
+        order.dca_summary().map(|summary| DcaPlan { order, summary })
+    }
+
+    /// Stop an open order, fills already made stay on record
+    pub async fn cancel(&self, id: &str) -> Result<Order> {
+        let mut orders = self.orders.write().await;
+        let order = orders.iter_mut()
+            .find(|order| order.id == id)
+            .ok_or_else(|| anyhow!("Unknown order {}", id))?;
+        if order.status != OrderStatus::Open {
+            return Err(anyhow!("Order {} is already {:?}", id, order.status));
+        }
+        order.status = OrderStatus::Cancelled;
+        order.updated_at = Utc::now();
+        let order = order.clone();
+        self.persist(&orders).await;
+        Ok(order)
+    }
+
+    async fn insert(&self, request: OrderRequest, kind: OrderKind, next_execution_at: DateTime<Utc>) -> Result<Order> {
+        if request.amount_in.is_zero() {
+            return Err(anyhow!("Order amount must be positive"));
+        }
+        if request.token_in == request.token_out {
+            return Err(anyhow!("Order must swap between two different tokens"));
+        }
+        if request.max_slippage_percentage.is_some_and(|slippage| !(0.0..=MAX_SLIPPAGE_PERCENTAGE).contains(&slippage)) {
+            return Err(anyhow!("Slippage must be between 0% and 50%"));
+        }
+        if request.auto_submit {
+            self.wallet_manager.labels().require(request.owner, WalletUse::AutoExecution).await?;
+            let wallet = self.wallet_manager.get_wallet_info(request.owner).await?;
+            if !wallet.is_connected || !matches!(wallet.wallet_type, WalletType::LocalWallet) {
+                return Err(anyhow!("Auto-submitted orders need a connected local wallet for {:?}", request.owner));
+            }
+        }
+
+        let now = Utc::now();
+        let order = Order {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner: request.owner,
+            chain_id: request.chain_id,
+            token_in: request.token_in,
+            token_out: request.token_out,
+            amount_in: request.amount_in,
+            kind,
+            auto_submit: request.auto_submit,
+            max_slippage_percentage: request.max_slippage_percentage,
+            status: OrderStatus::Open,
+            amount_filled: U256::zero(),
+            fills: Vec::new(),
+            next_execution_at,
+            consecutive_failures: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut orders = self.orders.write().await;
+        orders.push(order.clone());
+        self.persist(&orders).await;
+        info!("Accepted order {} from {:?} on chain {}", order.id, order.owner, order.chain_id);
+        Ok(order)
+    }
+
+    /// Execute every open order that is due, one at a time so an owner's nonces stay in order
+    async fn execute_due(&self) {
+        if let Some(halt) = self.circuit_breakers.halt().await {
+            debug!("Holding due orders, trading halted by the {} circuit breaker", halt.breaker);
+            return;
+        }
+        let now = Utc::now();
+        let due: Vec<Order> = self.orders.read().await.iter()
+            .filter(|order| order.status == OrderStatus::Open && order.next_execution_at <= now)
+            .cloned()
+            .collect();
+
+        for order in due {
+            self.execute(order).await;
+        }
+    }
+
+    #[instrument(skip_all, fields(chain_id = order.chain_id, wallet = ?order.owner, order_id = %order.id))]
+    async fn execute(&self, order: Order) {
+        let now = Utc::now();
+        let next_run = self.next_dca_run(&order, now).await;
+        let outcome = match &order.kind {
+            OrderKind::Twap { .. } | OrderKind::Dca { .. } => self.fill(&order, order.next_amount(), None).await.map(Some),
+            OrderKind::Limit { expires_at, .. } if expires_at.is_some_and(|expiry| expiry <= now) => {
+                self.update(&order.id, |stored| {
+                    stored.status = OrderStatus::Expired;
+                    stored.updated_at = now;
+                })
+                .await;
+                return;
+            }
+            OrderKind::Limit { min_amount_out, .. } => self.fill_at_limit(&order, *min_amount_out).await,
+        };
+
+        match outcome {
+            Ok(Some(fill)) => {
+                info!("Order {} filled {} of {}", order.id, fill.amount_in, order.amount_in);
+                self.update(&order.id, |stored| stored.record_fill(fill, now, next_run)).await;
+            }
+            Ok(None) => {
+                self.update(&order.id, |stored| stored.next_execution_at = now + self.poll_interval_chrono()).await;
+            }
+            Err(e) => {
+                warn!("Order {} execution failed: {}", order.id, e);
+                self.update(&order.id, |stored| stored.record_failure(e.to_string(), now, next_run)).await;
+            }
+        }
+    }
+
+    /// Fill a limit order once the best quote reaches its minimum output; quote errors only delay it
+    async fn fill_at_limit(&self, order: &Order, min_amount_out: U256) -> Result<Option<OrderFill>> {
+        let quote = match self.dex_manager.get_comprehensive_quotes(
+            order.chain_id, order.token_in, order.token_out, order.amount_in, order.owner,
+        ).await {
+            Ok(comparison) => comparison.best_route.output_amount,
+            Err(e) => {
+                warn!("Quote for limit order {} failed: {}", order.id, e);
+                return Ok(None);
+            }
+        };
+        // A zero quote has no route to fill through, even for an order without a minimum
+        if quote.is_zero() || quote < min_amount_out {
+            return Ok(None);
+        }
+
+        // Never allow the swap to settle below the limit, whatever the configured tolerance
+        let headroom = ((quote - min_amount_out) * U256::from(10_000) / quote).as_u64() as f64 / 100.0;
+        let settings = SlippageSettings {
+            max_slippage_percentage: order.max_slippage_percentage.map_or(headroom, |slippage| slippage.min(headroom)),
+            ..SlippageSettings::default()
+        };
+        self.fill(order, order.amount_in, Some(settings)).await.map(Some)
+    }
+
+    /// Build the swap for `amount_in` and submit it when the order is auto-submitted
+    async fn fill(&self, order: &Order, amount_in: U256, settings: Option<SlippageSettings>) -> Result<OrderFill> {
+        if order.auto_submit {
+            // The owner's label may have changed since the order was accepted
+            self.wallet_manager.labels().require(order.owner, WalletUse::AutoExecution).await?;
+        }
+        let settings = settings.or_else(|| {
+            order.max_slippage_percentage.map(|slippage| SlippageSettings {
+                max_slippage_percentage: slippage,
+                ..SlippageSettings::default()
+            })
+        });
+        let swap = self.dex_manager.execute_optimal_swap(
+            order.chain_id, order.token_in, order.token_out, amount_in, order.owner, settings,
+        ).await?;
+        // Prices are checked when the fill executes, the market may have moved since the order was accepted
+        self.circuit_breakers.observe_trade(self.price_guard.validate_trade(
+            order.chain_id, order.token_in, amount_in, order.token_out, swap.expected_output,
+        ).await).await?;
+
+        let tx_hash = if order.auto_submit {
+            // Spending is counted against the policy of the order's id
+            let policy = PolicyContext { strategy_id: Some(order.id.clone()), override_id: None };
+            // The broadcaster keeps the owner's nonces in order, so the swap lands after its approval
+            if let Some(approval) = &swap.approval {
+                let tx: TypedTransaction = approval.transaction.clone().into();
+                let signer = self.wallet_manager.local_signer(order.owner, &tx, &policy).await?;
+                self.broadcaster.send_with_signer(order.chain_id, tx, &signer).await?;
+            }
+            let tx: TypedTransaction = swap.transaction.clone().into();
+            let signer = self.wallet_manager.local_signer(order.owner, &tx, &policy).await?;
+            Some(self.broadcaster.send_with_signer(order.chain_id, tx, &signer).await?.hash)
+        } else {
+            None
+        };
+
+        Ok(OrderFill {
+            amount_in,
+            expected_output: swap.expected_output,
+            dex: swap.dex_used,
+            tracking_id: swap.tracking_id,
+            tx_hash,
+            executed_at: Utc::now(),
+        })
+    }
+
+    /// Apply an execution outcome unless the order was cancelled meanwhile
+    async fn update(&self, id: &str, apply: impl FnOnce(&mut Order)) {
+        let mut orders = self.orders.write().await;
+        let Some(order) = orders.iter_mut().find(|order| order.id == id && order.status == OrderStatus::Open) else {
+            return;
+        };
+        apply(order);
+        self.persist(&orders).await;
+    }
+
+    /// Scheduled buy after `now` of a DCA order in its owner's time zone
+    async fn next_dca_run(&self, order: &Order, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let OrderKind::Dca { schedule, .. } = &order.kind else {
+            return None;
+        };
+        let schedule = parse_schedule(schedule).ok()?;
+        next_run(&schedule, self.time_zones.get(order.owner).await.time_zone, now)
+    }
+
+    fn poll_interval_chrono(&self) -> ChronoDuration {
+        ChronoDuration::from_std(self.poll_interval).unwrap_or_else(|_| ChronoDuration::seconds(15))
+    }
+
+    async fn token_decimals(&self, chain_id: u64, token: Address) -> u8 {
+        if token.is_zero() {
+            return 18; // native ETH
+        }
+        let Ok(chain) = self.dex_manager.chain_manager().get_provider(chain_id).await else {
+            return 18;
+        };
+        match ERC20Contract::new(token, Arc::new(chain.provider.clone()), chain_id).await {
+            Ok(contract) => contract.get_token_info().map(|info| info.decimals).unwrap_or(18),
+            Err(_) => 18,
+        }
+    }
+
+    async fn persist(&self, orders: &[Order]) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        if let Err(e) = write_store(path, orders).await {
+            warn!("Failed to persist orders to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Parse a cron schedule, accepting the five-field form without seconds
+fn parse_schedule(spec: &str) -> Result<Schedule> {
+    let spec = spec.trim();
+    let expression = match spec.split_whitespace().count() {
+        5 => format!("0 {}", spec),
+        _ => spec.to_string(),
+    };
+    Schedule::from_str(&expression).map_err(|e| anyhow!("Invalid schedule {}: {}", spec, e))
+}
+
+fn next_run(schedule: &Schedule, time_zone: chrono_tz::Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    schedule.after(&after.with_timezone(&time_zone)).next().map(|run| run.with_timezone(&Utc))
+}
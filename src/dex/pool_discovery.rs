@@ -0,0 +1,411 @@
+// Uniswap V3 pools and SushiSwap pairs of a chain, discovered through subgraphs or factory events
+// and ranked by value locked
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::{self, Token},
+    providers::Middleware,
+    types::{Address, Bytes, Filter, Log, TransactionRequest, H256, U256},
+    utils::{id, keccak256},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use super::sushiswap::SushiSwapContracts;
+use super::uniswap::UniswapContracts;
+use crate::analytics::price_feeds::PriceFeedService;
+use crate::cache::{CacheManager, TimedCache};
+use crate::chains::batch::RpcBatch;
+use crate::chains::tokens::TokenRegistry;
+use crate::chains::ChainManager;
+
+/// Chains both venues are deployed on
+pub const SUPPORTED_CHAINS: [u64; 3] = [1, 137, 42161];
+/// Discovered pools change slowly, a listing is rebuilt after this long
+const PAIRS_CACHE_TTL: Duration = Duration::from_secs(600);
+const PAIRS_CACHE_MAX_ENTRIES: usize = 16;
+const DEFAULT_LOOKBACK_BLOCKS: u64 = 100_000;
+/// Blocks per `eth_getLogs` request, within what public RPCs accept
+const LOG_CHUNK_BLOCKS: u64 = 10_000;
+/// Newest pools per venue whose balances are read when discovering from factory events
+const DEFAULT_MAX_POOLS: usize = 200;
+/// Pools per venue asked of a subgraph
+const SUBGRAPH_POOLS: usize = 200;
+const SUBGRAPH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Balance reads per JSON-RPC batch
+const BALANCE_BATCH_SIZE: usize = 100;
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// `PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool)`
+fn pool_created_topic() -> H256 {
+    H256::from(keccak256("PoolCreated(address,address,uint24,int24,address)"))
+}
+
+/// `PairCreated(address indexed token0, address indexed token1, address pair, uint256)`
+fn pair_created_topic() -> H256 {
+    H256::from(keccak256("PairCreated(address,address,address,uint256)"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolVenue {
+    UniswapV3,
+    #[serde(rename = "sushiswap")]
+    SushiSwap,
+}
+
+impl PoolVenue {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::UniswapV3 => "uniswap_v3",
+            Self::SushiSwap => "sushiswap",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolSource {
+    Subgraph,
+    /// Pools created within the lookback window, valued from their token balances
+    FactoryEvents,
+}
+
+/// A pool of two tokens on one venue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradingPair {
+    pub dex: PoolVenue,
+    pub pool_address: Address,
+    pub token_a: Address,
+    pub token_b: Address,
+    pub symbol_a: Option<String>,
+    pub symbol_b: Option<String>,
+    /// Uniswap V3 fee in hundredths of a basis point, 3000 for SushiSwap pairs
+    pub fee_tier: u32,
+    /// Pool balances of `token_a` and `token_b`, `None` from subgraphs
+    pub reserve_a: Option<U256>,
+    pub reserve_b: Option<U256>,
+    /// `None` when neither token is priced; a V2 pair with one priced side counts it twice, a V3
+    /// pool only its priced side
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub tvl_usd: Option<f64>,
+    /// Last day's volume, only known from subgraphs
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub volume_24h_usd: Option<f64>,
+    pub source: PoolSource,
+}
+
+/// One page of a chain's pools, highest value locked first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradingPairsPage {
+    pub chain_id: u64,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub pairs: Vec<TradingPair>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolDiscoveryConfig {
+    /// Subgraph endpoints by venue and chain; venues without one fall back to factory events
+    pub subgraphs: HashMap<(PoolVenue, u64), String>,
+    pub lookback_blocks: u64,
+    pub max_pools: usize,
+}
+
+impl Default for PoolDiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            subgraphs: HashMap::new(),
+            lookback_blocks: DEFAULT_LOOKBACK_BLOCKS,
+            max_pools: DEFAULT_MAX_POOLS,
+        }
+    }
+}
+
+impl PoolDiscoveryConfig {
+    /// Subgraphs from `dex_subgraphs` as `venue:chain_id=url` entries, e.g.
+    /// `uniswap_v3:1=https://gateway.thegraph.com/api/<key>/subgraphs/id/<id>`; the factory event
+    /// window from `pool_discovery_lookback_blocks` and `pool_discovery_max_pools`
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut discovery_config = Self::default();
+
+        if let Ok(subgraphs) = config.get_string("dex_subgraphs") {
+            for entry in subgraphs.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                match parse_subgraph(entry) {
+                    Ok((key, url)) => {
+                        discovery_config.subgraphs.insert(key, url);
+                    }
+                    Err(e) => warn!("Ignoring DEX subgraph '{}': {}", entry, e),
+                }
+            }
+        }
+        if let Ok(blocks) = config.get_int("pool_discovery_lookback_blocks") {
+            discovery_config.lookback_blocks = blocks.max(1) as u64;
+        }
+        if let Ok(pools) = config.get_int("pool_discovery_max_pools") {
+            discovery_config.max_pools = pools.max(1) as usize;
+        }
+
+        discovery_config
+    }
+}
+
+fn parse_subgraph(entry: &str) -> Result<((PoolVenue, u64), String)> {
+    let (key, url) = entry.split_once('=').ok_or_else(|| anyhow!("expected venue:chain_id=url"))?;
+    let (venue, chain_id) = key.split_once(':').ok_or_else(|| anyhow!("expected venue:chain_id"))?;
+    let venue = [PoolVenue::UniswapV3, PoolVenue::SushiSwap]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == venue.trim())
+        .ok_or_else(|| anyhow!("unknown venue {}", venue))?;
+    Ok(((venue, chain_id.trim().parse()?), url.trim().to_string()))
+}
+
+/// Lists the pools of a chain, cached per chain
+pub struct PoolDiscovery {
+    chain_manager: Arc<ChainManager>,
+    price_feeds: Arc<PriceFeedService>,
+    tokens: Arc<TokenRegistry>,
+    config: PoolDiscoveryConfig,
+    http: reqwest::Client,
+    pairs: TimedCache<u64, Arc<Vec<TradingPair>>>,
+}
+
+impl PoolDiscovery {
+    pub fn new(
+        chain_manager: Arc<ChainManager>,
+        price_feeds: Arc<PriceFeedService>,
+        tokens: Arc<TokenRegistry>,
+        caches: &CacheManager,
+        config: PoolDiscoveryConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            chain_manager,
+            price_feeds,
+            tokens,
+            config,
+            http: reqwest::Client::builder().timeout(SUBGRAPH_TIMEOUT).build()?,
+            pairs: caches.timed("dex_pairs", PAIRS_CACHE_TTL, PAIRS_CACHE_MAX_ENTRIES),
+        })
+    }
+
+    /// A page of the chain's pools across venues, highest value locked first, then highest volume
+    pub async fn pairs(&self, chain_id: u64, offset: usize, limit: usize) -> Result<TradingPairsPage> {
+        if !SUPPORTED_CHAINS.contains(&chain_id) {
+            return Err(anyhow!("Pool discovery does not support chain {}", chain_id));
+        }
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let pairs = self.pairs.get_or_load(chain_id, || self.discover(chain_id)).await?;
+        Ok(TradingPairsPage {
+            chain_id,
+            total: pairs.len(),
+            offset,
+            limit,
+            pairs: pairs.iter().skip(offset).take(limit).cloned().collect(),
+        })
+    }
+
+    async fn discover(&self, chain_id: u64) -> Result<Arc<Vec<TradingPair>>> {
+        let mut pairs = Vec::new();
+        for venue in [PoolVenue::UniswapV3, PoolVenue::SushiSwap] {
+            let discovered = match self.config.subgraphs.get(&(venue, chain_id)) {
+                Some(url) => self.from_subgraph(venue, url).await,
+                None => self.from_factory_events(chain_id, venue).await,
+            };
+            match discovered {
+                Ok(discovered) => pairs.extend(discovered),
+                Err(e) => warn!("Could not discover {} pools on chain {}: {}", venue.as_str(), chain_id, e),
+            }
+        }
+        if pairs.is_empty() {
+            return Err(anyhow!("No pools discovered on chain {}", chain_id));
+        }
+        for pair in &mut pairs {
+            pair.symbol_a = pair.symbol_a.take().or(self.tokens.get(chain_id, pair.token_a).await.map(|token| token.symbol));
+            pair.symbol_b = pair.symbol_b.take().or(self.tokens.get(chain_id, pair.token_b).await.map(|token| token.symbol));
+        }
+
+        let rank = |pair: &TradingPair| (pair.tvl_usd.unwrap_or(-1.0), pair.volume_24h_usd.unwrap_or(-1.0));
+        pairs.sort_by(|a, b| {
+            let (a, b) = (rank(a), rank(b));
+            b.0.total_cmp(&a.0).then(b.1.total_cmp(&a.1))
+        });
+        info!("Discovered {} pools on chain {}", pairs.len(), chain_id);
+        Ok(Arc::new(pairs))
+    }
+
+    /// Top pools by value locked from a Uniswap V3 or V2-style subgraph
+    async fn from_subgraph(&self, venue: PoolVenue, url: &str) -> Result<Vec<TradingPair>> {
+        let query = match venue {
+            PoolVenue::UniswapV3 => format!(
+                "{{ pools(first: {}, orderBy: totalValueLockedUSD, orderDirection: desc) {{ id feeTier totalValueLockedUSD \
+                 token0 {{ id symbol }} token1 {{ id symbol }} \
+                 poolDayData(first: 1, orderBy: date, orderDirection: desc) {{ volumeUSD }} }} }}",
+                SUBGRAPH_POOLS
+            ),
+            PoolVenue::SushiSwap => format!(
+                "{{ pairs(first: {}, orderBy: reserveUSD, orderDirection: desc) {{ id reserveUSD \
+                 token0 {{ id symbol }} token1 {{ id symbol }} \
+                 dayData(first: 1, orderBy: date, orderDirection: desc) {{ volumeUSD }} }} }}",
+                SUBGRAPH_POOLS
+            ),
+        };
+        let response: Value = self.http.post(url)
+            .json(&json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(errors) = response.get("errors") {
+            return Err(anyhow!("Subgraph query failed: {}", errors));
+        }
+
+        let (collection, tvl_field, day_field) = match venue {
+            PoolVenue::UniswapV3 => ("pools", "totalValueLockedUSD", "poolDayData"),
+            PoolVenue::SushiSwap => ("pairs", "reserveUSD", "dayData"),
+        };
+        let entries = response["data"][collection].as_array()
+            .ok_or_else(|| anyhow!("Subgraph returned no {}", collection))?;
+        let number = |value: &Value| value.as_str().and_then(|value| value.parse::<f64>().ok());
+        let address = |value: &Value| value.as_str().and_then(|value| value.parse::<Address>().ok());
+        let symbol = |value: &Value| value.as_str().map(str::to_string);
+
+        Ok(entries.iter()
+            .filter_map(|entry| {
+                Some(TradingPair {
+                    dex: venue,
+                    pool_address: address(&entry["id"])?,
+                    token_a: address(&entry["token0"]["id"])?,
+                    token_b: address(&entry["token1"]["id"])?,
+                    symbol_a: symbol(&entry["token0"]["symbol"]),
+                    symbol_b: symbol(&entry["token1"]["symbol"]),
+                    fee_tier: match venue {
+                        PoolVenue::UniswapV3 => number(&entry["feeTier"])? as u32,
+                        PoolVenue::SushiSwap => 3000,
+                    },
+                    reserve_a: None,
+                    reserve_b: None,
+                    tvl_usd: number(&entry[tvl_field]),
+                    volume_24h_usd: number(&entry[day_field][0]["volumeUSD"]),
+                    source: PoolSource::Subgraph,
+                })
+            })
+            .collect())
+    }
+
+    /// Pools the venue's factory created within the lookback window, newest first, valued from
+    /// their token balances
+    async fn from_factory_events(&self, chain_id: u64, venue: PoolVenue) -> Result<Vec<TradingPair>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let (factory, topic) = match venue {
+            PoolVenue::UniswapV3 => (UniswapContracts::for_chain(chain_id).factory, pool_created_topic()),
+            PoolVenue::SushiSwap => (SushiSwapContracts::for_chain(chain_id).factory, pair_created_topic()),
+        };
+        let latest = chain.provider.get_block_number().await?.as_u64();
+        let start = latest.saturating_sub(self.config.lookback_blocks);
+
+        // Newest chunks first, stopping once enough pools were seen
+        let mut pairs = Vec::new();
+        let mut to = latest;
+        while to >= start && pairs.len() < self.config.max_pools {
+            let from = to.saturating_sub(LOG_CHUNK_BLOCKS - 1).max(start);
+            let filter = Filter::new().address(factory).topic0(topic).from_block(from).to_block(to);
+            let logs = chain.provider.get_logs(&filter).await?;
+            pairs.extend(logs.iter().rev().filter_map(|log| created_pair(venue, log)));
+            if from == 0 {
+                break;
+            }
+            to = from - 1;
+        }
+        pairs.truncate(self.config.max_pools);
+        debug!("{} {} pools created in the last {} blocks of chain {}", pairs.len(), venue.as_str(), latest - start, chain_id);
+
+        self.value_pairs(chain_id, &mut pairs).await?;
+        Ok(pairs)
+    }
+
+    /// Read each pool's token balances and value them at the tokens' USD prices
+    async fn value_pairs(&self, chain_id: u64, pairs: &mut [TradingPair]) -> Result<()> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let reads: Vec<(Address, Address)> = pairs.iter()
+            .flat_map(|pair| [(pair.token_a, pair.pool_address), (pair.token_b, pair.pool_address)])
+            .collect();
+        let mut balances = HashMap::new();
+        for chunk in reads.chunks(BALANCE_BATCH_SIZE) {
+            let mut batch = RpcBatch::new();
+            let mut calls = Vec::with_capacity(chunk.len());
+            for (token, holder) in chunk {
+                let mut data = id("balanceOf(address)").to_vec();
+                data.extend(abi::encode(&[Token::Address(*holder)]));
+                let call = TransactionRequest::new().to(*token).data(data);
+                calls.push((*token, *holder, batch.request("eth_call", (call, "latest"))?));
+            }
+            let results = chain.batch(&batch).await?;
+            for (token, holder, index) in calls {
+                if let Some(output) = results.get::<Bytes>(index).ok().filter(|output| output.len() >= 32) {
+                    balances.insert((token, holder), U256::from_big_endian(&output[..32]));
+                }
+            }
+        }
+
+        let tokens: Vec<Address> = pairs.iter()
+            .flat_map(|pair| [pair.token_a, pair.token_b])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let prices = self.price_feeds.get_prices(chain_id, &tokens).await.unwrap_or_default();
+        let mut decimals = HashMap::new();
+        for token in prices.keys() {
+            if let Some(metadata) = self.tokens.get(chain_id, *token).await {
+                decimals.insert(*token, metadata.decimals);
+            }
+        }
+        let value = |token: Address, amount: Option<U256>| -> Option<f64> {
+            let amount = amount?.to_string().parse::<f64>().ok()?;
+            Some(amount / 10f64.powi(*decimals.get(&token)? as i32) * prices.get(&token)?.price_usd)
+        };
+
+        for pair in pairs.iter_mut() {
+            pair.reserve_a = balances.get(&(pair.token_a, pair.pool_address)).copied();
+            pair.reserve_b = balances.get(&(pair.token_b, pair.pool_address)).copied();
+            pair.tvl_usd = match (value(pair.token_a, pair.reserve_a), value(pair.token_b, pair.reserve_b)) {
+                (Some(a), Some(b)) => Some(a + b),
+                (Some(side), None) | (None, Some(side)) if pair.dex == PoolVenue::SushiSwap => Some(side * 2.0),
+                (Some(side), None) | (None, Some(side)) => Some(side),
+                (None, None) => None,
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Pool of a `PoolCreated` or `PairCreated` log
+fn created_pair(venue: PoolVenue, log: &Log) -> Option<TradingPair> {
+    let token_a = Address::from(*log.topics.get(1)?);
+    let token_b = Address::from(*log.topics.get(2)?);
+    // Both events carry the pool in their data: after the tick spacing for V3, first for V2
+    let (pool_offset, fee_tier) = match venue {
+        PoolVenue::UniswapV3 => (32, U256::from_big_endian(log.topics.get(3)?.as_bytes()).low_u32()),
+        PoolVenue::SushiSwap => (0, 3000),
+    };
+    let pool = log.data.get(pool_offset..pool_offset + 32)?;
+    Some(TradingPair {
+        dex: venue,
+        pool_address: Address::from_slice(&pool[12..]),
+        token_a,
+        token_b,
+        symbol_a: None,
+        symbol_b: None,
+        fee_tier,
+        reserve_a: None,
+        reserve_b: None,
+        tvl_usd: None,
+        volume_24h_usd: None,
+        source: PoolSource::FactoryEvents,
+    })
+}
@@ -0,0 +1,283 @@
+// Quoted outputs measured against simulated and settled swap outputs, per venue
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::aggregator::{u256_to_f64, DexType};
+use super::DexManager;
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::settlement::SettlementReporter;
+use crate::transactions::{read_store, write_store, TransactionTracker};
+
+/// Store used when `quote_accuracy_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/quote_accuracy.json";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Most recent samples kept per venue
+pub const MAX_SAMPLES_PER_VENUE: usize = 500;
+/// Fewest samples a venue's error distribution is trusted from
+pub const MIN_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteSampleSource {
+    /// The built swap called against the latest block before it was signed
+    Simulated,
+    /// The output of the settled swap, read from its receipt
+    Executed,
+}
+
+/// One quoted output and the output the swap actually paid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteSample {
+    pub dex: DexType,
+    pub source: QuoteSampleSource,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub quoted_output: U256,
+    pub realized_output: U256,
+    /// Shortfall of the realized output against the quote in basis points, negative when it beat the quote
+    pub error_bps: f64,
+    pub transaction: Option<H256>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl QuoteSample {
+    /// `None` for a zero quote, which has no relative error
+    pub fn new(
+        dex: DexType,
+        source: QuoteSampleSource,
+        token_in: Address,
+        token_out: Address,
+        quoted_output: U256,
+        realized_output: U256,
+        transaction: Option<H256>,
+    ) -> Option<Self> {
+        let quoted = u256_to_f64(quoted_output);
+        (quoted > 0.0).then(|| Self {
+            dex,
+            source,
+            token_in,
+            token_out,
+            quoted_output,
+            realized_output,
+            error_bps: (quoted - u256_to_f64(realized_output)) / quoted * 10_000.0,
+            transaction,
+            recorded_at: Utc::now(),
+        })
+    }
+}
+
+/// Distribution of a venue's quote errors, in basis points of the quoted output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VenueQuoteAccuracy {
+    pub samples: usize,
+    pub simulated: usize,
+    pub executed: usize,
+    pub mean_error_bps: f64,
+    pub std_dev_bps: f64,
+    pub median_error_bps: f64,
+    pub p90_error_bps: f64,
+    pub worst_error_bps: f64,
+    /// Share of samples that paid within the slippage tolerance, `None` below `MIN_SAMPLES`
+    pub confidence: Option<f64>,
+}
+
+impl VenueQuoteAccuracy {
+    pub fn from_samples<'a>(samples: impl IntoIterator<Item = &'a QuoteSample>, tolerance_bps: f64) -> Self {
+        let mut accuracy = Self::default();
+        let mut errors = Vec::new();
+        for sample in samples {
+            match sample.source {
+                QuoteSampleSource::Simulated => accuracy.simulated += 1,
+                QuoteSampleSource::Executed => accuracy.executed += 1,
+            }
+            errors.push(sample.error_bps);
+        }
+        if errors.is_empty() {
+            return accuracy;
+        }
+        errors.sort_by(f64::total_cmp);
+
+        let n = errors.len() as f64;
+        let percentile = |p: f64| errors[((n - 1.0) * p).round() as usize];
+        accuracy.samples = errors.len();
+        accuracy.mean_error_bps = errors.iter().sum::<f64>() / n;
+        accuracy.std_dev_bps = (errors.iter().map(|e| (e - accuracy.mean_error_bps).powi(2)).sum::<f64>() / n).sqrt();
+        accuracy.median_error_bps = percentile(0.5);
+        accuracy.p90_error_bps = percentile(0.9);
+        accuracy.worst_error_bps = errors[errors.len() - 1];
+        accuracy.confidence = (errors.len() >= MIN_SAMPLES)
+            .then(|| errors.iter().filter(|error| **error <= tolerance_bps).count() as f64 / n);
+        accuracy
+    }
+
+    /// Average shortfall a quote from the venue should be discounted by, zero until there are enough
+    /// samples or when the venue pays at least its quotes
+    pub fn expected_shortfall_bps(&self) -> f64 {
+        if self.samples < MIN_SAMPLES {
+            return 0.0;
+        }
+        self.mean_error_bps.clamp(0.0, 10_000.0)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct QuoteAccuracyStore {
+    samples: Vec<QuoteSample>,
+    /// Transaction history records whose settled output was already sampled
+    measured: BTreeSet<String>,
+}
+
+/// Samples the settled output of quoted swaps and keeps the aggregator's error distribution on disk
+pub struct QuoteAccuracyMonitor {
+    dex_manager: Arc<DexManager>,
+    transactions: Arc<TransactionTracker>,
+    settlements: Arc<SettlementReporter>,
+    /// JSON file holding the samples, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    poll_interval: Duration,
+    measured: RwLock<BTreeSet<String>>,
+    /// Newest sample written to the store
+    persisted_through: RwLock<Option<DateTime<Utc>>>,
+}
+
+impl QuoteAccuracyMonitor {
+    pub async fn new(
+        dex_manager: Arc<DexManager>,
+        transactions: Arc<TransactionTracker>,
+        settlements: Arc<SettlementReporter>,
+        store_path: Option<PathBuf>,
+        poll_interval: Duration,
+    ) -> Result<Self> {
+        let store: QuoteAccuracyStore = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => QuoteAccuracyStore::default(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !store.samples.is_empty()) {
+            info!("Loaded {} quote accuracy samples from {}", store.samples.len(), path.display());
+        }
+        let persisted_through = store.samples.iter().map(|sample| sample.recorded_at).max();
+        dex_manager.aggregator().restore_quote_samples(store.samples).await;
+
+        Ok(Self {
+            dex_manager,
+            transactions,
+            settlements,
+            store_path,
+            poll_interval,
+            measured: RwLock::new(store.measured),
+            persisted_through: RwLock::new(persisted_through),
+        })
+    }
+
+    /// Monitor persisting to `quote_accuracy_store_path` and sampling settled swaps every
+    /// `quote_accuracy_poll_interval_secs`
+    pub async fn from_config(
+        config: &config::Config,
+        dex_manager: Arc<DexManager>,
+        transactions: Arc<TransactionTracker>,
+        settlements: Arc<SettlementReporter>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("quote_accuracy_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        let poll_interval = config
+            .get_int("quote_accuracy_poll_interval_secs")
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        Self::new(dex_manager, transactions, settlements, store_path, poll_interval).await
+    }
+
+    /// Sample settled swaps in the background until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.measure().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            self.persist().await;
+            info!("Quote accuracy monitor stopped");
+        })
+    }
+
+    /// Compare the legs of every newly settled quoted swap against their quotes
+    async fn measure(&self) {
+        let measured = self.measured.read().await.clone();
+        let pending: Vec<_> = self.transactions.quoted().await.into_iter()
+            .filter(|record| !measured.contains(&record.id))
+            .collect();
+        // Bundled swaps settle with their execution
+        let executions: BTreeSet<String> = pending.iter()
+            .map(|record| record.execution_id.clone().unwrap_or_else(|| record.id.clone()))
+            .collect();
+
+        let mut done = HashSet::new();
+        for execution in executions {
+            let report = match self.settlements.report(&execution).await {
+                Ok(Some(report)) => report,
+                Ok(None) => continue,
+                Err(e) => {
+                    // Sampled as done so an execution that never settles is not retried forever
+                    debug!("No settlement of {} to sample quotes from: {}", execution, e);
+                    done.insert(execution);
+                    continue;
+                }
+            };
+            for leg in &report.legs {
+                let (Some(dex), Some(expected)) = (leg.dex.clone(), leg.expected_output) else {
+                    continue;
+                };
+                let sample = QuoteSample::new(
+                    dex, QuoteSampleSource::Executed, leg.token_in, leg.token_out, expected, leg.amount_out, Some(leg.transaction),
+                );
+                if let Some(sample) = sample {
+                    self.dex_manager.aggregator().record_quote_sample(sample).await;
+                }
+            }
+            done.insert(execution);
+        }
+
+        if !done.is_empty() {
+            let mut measured = self.measured.write().await;
+            measured.extend(
+                pending.into_iter()
+                    .filter(|record| done.contains(record.execution_id.as_ref().unwrap_or(&record.id)))
+                    .map(|record| record.id),
+            );
+        }
+        self.persist().await;
+    }
+
+    /// Write the samples once a newer one was recorded, including those simulated when swaps were built
+    async fn persist(&self) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let samples = self.dex_manager.aggregator().quote_samples().await;
+        let newest = samples.iter().map(|sample| sample.recorded_at).max();
+        let mut persisted_through = self.persisted_through.write().await;
+        if newest <= *persisted_through {
+            return;
+        }
+        let store = QuoteAccuracyStore { samples, measured: self.measured.read().await.clone() };
+        match write_store(path, &store).await {
+            Ok(()) => *persisted_through = newest,
+            Err(e) => warn!("Failed to persist quote accuracy samples to {}: {}", path.display(), e),
+        }
+    }
+}
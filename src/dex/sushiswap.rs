@@ -8,9 +8,29 @@ use ethers::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::collections::HashMap;
+use std::time::Duration;
+use futures::FutureExt;
 use tracing::{info, warn, error};
 
+use crate::cache::{CacheManager, TimedCache};
+use crate::chains::batch::RpcBatch;
+use crate::chains::pool::PooledHttp;
 use crate::chains::ChainManager;
+use crate::contracts::multicall::multicall;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
+use crate::dex::aggregator::{check_swap_bounds, u256_to_f64};
+
+/// Reserves change with every swap, pair state is refreshed ahead of this TTL
+const PAIR_CACHE_TTL: Duration = Duration::from_secs(15);
+const PAIR_CACHE_MAX_ENTRIES: usize = 2_000;
+/// Farm allocations and rewards change rarely
+const FARM_CACHE_TTL: Duration = Duration::from_secs(300);
+const FARM_CACHE_MAX_ENTRIES: usize = 1_000;
+const CHEF_CACHE_MAX_ENTRIES: usize = 16;
+/// MasterChef emits per block, about every 12 seconds on mainnet
+const BLOCKS_PER_YEAR: u64 = 2_628_000;
+/// MiniChef V2 emits per second
+const SECONDS_PER_YEAR: u64 = 31_536_000;
 
 /// SushiSwap pair information
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,11 +50,66 @@ pub struct FarmInfo {
     pub pid: u64,
     pub lp_token: Address,
     pub alloc_point: U256,
+    /// Block of the last reward update, a timestamp on MiniChef V2 chains
     pub last_reward_block: u64,
     pub acc_sushi_per_share: U256,
+    /// SUSHI the chef emits across all farms per block, per second on MiniChef V2 chains
     pub reward_per_block: U256,
+    /// This farm's share of the emissions over a year at the current rate
+    pub rewards_per_year: U256,
+    pub reward_token: Address,
+    /// LP tokens deposited in the chef for this farm
     pub total_staked: U256,
-    pub apy: f64,
+    /// Pair the LP token redeems for, `None` when it is not a SushiSwap pair
+    pub pair: Option<FarmPair>,
+}
+
+/// Underlying tokens of a farm's LP token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FarmPair {
+    pub token0: Address,
+    pub token1: Address,
+    pub decimals0: u8,
+    pub decimals1: u8,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    /// LP tokens minted by the pair
+    pub total_supply: U256,
+}
+
+impl FarmPair {
+    /// Amounts of `token0` and `token1` that `liquidity` LP tokens redeem for
+    pub fn underlying(&self, liquidity: U256) -> (U256, U256) {
+        if self.total_supply.is_zero() {
+            return (U256::zero(), U256::zero());
+        }
+        (liquidity * self.reserve0 / self.total_supply, liquidity * self.reserve1 / self.total_supply)
+    }
+}
+
+impl FarmInfo {
+    /// USD value of the LP staked in the farm; a pair with one priced token is valued at twice that side
+    pub fn staked_value_usd(&self, prices: &HashMap<Address, f64>) -> Option<f64> {
+        let pair = self.pair.as_ref()?;
+        let (amount0, amount1) = pair.underlying(self.total_staked);
+        let value = |amount: U256, token: Address, decimals: u8| {
+            prices.get(&token).map(|price| u256_to_f64(amount) / 10f64.powi(decimals as i32) * price)
+        };
+        match (value(amount0, pair.token0, pair.decimals0), value(amount1, pair.token1, pair.decimals1)) {
+            (Some(value0), Some(value1)) => Some(value0 + value1),
+            (Some(side), None) | (None, Some(side)) => Some(side * 2.0),
+            (None, None) => None,
+        }
+    }
+
+    /// Yield of the farm's SUSHI emissions on its staked value in percent, compounded daily; `None` while
+    /// the staked LP or SUSHI is unpriced or nothing is staked
+    pub fn apy(&self, prices: &HashMap<Address, f64>) -> Option<f64> {
+        let staked_usd = self.staked_value_usd(prices).filter(|value| *value > 0.0)?;
+        let rewards_usd = u256_to_f64(self.rewards_per_year) / 1e18 * prices.get(&self.reward_token)?;
+        let apr = rewards_usd / staked_usd;
+        Some(((1.0 + apr / 365.0).powi(365) - 1.0) * 100.0)
+    }
 }
 
 /// User farming position
@@ -96,12 +171,14 @@ impl SushiSwapContracts {
 pub struct SushiSwapManager {
     chain_manager: Arc<ChainManager>,
     contracts: HashMap<u64, SushiSwapContracts>,
-    pairs_cache: Arc<tokio::sync::RwLock<HashMap<Address, PairInfo>>>,
-    farms_cache: Arc<tokio::sync::RwLock<HashMap<u64, FarmInfo>>>,
+    pairs_cache: TimedCache<(u64, Address, Address), PairInfo>,
+    farms_cache: TimedCache<(u64, u64), FarmInfo>,
+    /// Every farm earning rewards, per chain
+    chef_cache: TimedCache<u64, Vec<FarmInfo>>,
 }
 
 impl SushiSwapManager {
-    pub async fn new(chain_manager: Arc<ChainManager>) -> Result<Self> {
+    pub async fn new(chain_manager: Arc<ChainManager>, caches: &CacheManager) -> Result<Self> {
         info!("Initializing SushiSwap Manager");
 
         let mut contracts = HashMap::new();
@@ -112,8 +189,9 @@ impl SushiSwapManager {
         Ok(Self {
             chain_manager,
             contracts,
-            pairs_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
-            farms_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            pairs_cache: caches.timed("sushiswap_pairs", PAIR_CACHE_TTL, PAIR_CACHE_MAX_ENTRIES),
+            farms_cache: caches.timed("sushiswap_farms", FARM_CACHE_TTL, FARM_CACHE_MAX_ENTRIES),
+            chef_cache: caches.timed("sushiswap_chef_farms", FARM_CACHE_TTL, CHEF_CACHE_MAX_ENTRIES),
         })
     }
 
@@ -126,19 +204,57 @@ impl SushiSwapManager {
         Ok(Self {
             chain_manager,
             contracts,
-            pairs_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
-            farms_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            pairs_cache: TimedCache::new("sushiswap_pairs", PAIR_CACHE_TTL, PAIR_CACHE_MAX_ENTRIES),
+            farms_cache: TimedCache::new("sushiswap_farms", FARM_CACHE_TTL, FARM_CACHE_MAX_ENTRIES),
+            chef_cache: TimedCache::new("sushiswap_chef_farms", FARM_CACHE_TTL, CHEF_CACHE_MAX_ENTRIES),
         })
     }
 
+    /// Address-registry entries probed at startup; only mainnet runs the original MasterChef
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| {
+                let chef = if chain_id == 1 { ProtocolInterface::SushiMasterChef } else { ProtocolInterface::SushiMiniChefV2 };
+                [
+                    ContractDeployment::new(chain_id, "sushiswap", "factory", contracts.factory, ProtocolInterface::UniswapV2Factory),
+                    ContractDeployment::new(chain_id, "sushiswap", "router", contracts.router, ProtocolInterface::UniswapV2Router),
+                    ContractDeployment::new(chain_id, "sushiswap", "master_chef", contracts.master_chef, chef),
+                    ContractDeployment::new(chain_id, "sushiswap", "sushi_token", contracts.sushi_token, ProtocolInterface::Erc20),
+                ]
+            })
+            .collect()
+    }
+
+    /// Drop cached pair and farm data
+    pub async fn clear_cache(&self) {
+        self.pairs_cache.clear().await;
+        self.farms_cache.clear().await;
+        self.chef_cache.clear().await;
+    }
+
     /// Get pair information
     pub async fn get_pair_info(&self, chain_id: u64, token0: Address, token1: Address) -> Result<PairInfo> {
         info!("Getting pair info for tokens {:?}/{:?} on chain {}", token0, token1, chain_id);
 
         let contracts = self.contracts.get(&chain_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.pairs_cache
+            .get_or_refresh((chain_id, token0, token1), move || {
+                Self::fetch_pair_info(chain_manager, contracts, chain_id, token0, token1).boxed()
+            })
+            .await
+    }
 
-        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+    async fn fetch_pair_info(
+        chain_manager: Arc<ChainManager>,
+        contracts: SushiSwapContracts,
+        chain_id: u64,
+        token0: Address,
+        token1: Address,
+    ) -> Result<PairInfo> {
+        let chain_provider = chain_manager.get_provider(chain_id).await?;
         let provider = Arc::new(chain_provider.provider.clone());
 
         // Get factory contract
@@ -159,28 +275,25 @@ impl SushiSwapManager {
         let pair_abi = Self::get_pair_abi()?;
         let pair_contract = Contract::new(pair_address, pair_abi, provider);
 
-        // Get reserves
-        let reserves: (U256, U256, u32) = pair_contract
-            .method::<_, (U256, U256, u32)>("getReserves", ())?
-            .call()
-            .await?;
-
-        let price0_cumulative_last: U256 = pair_contract
-            .method::<_, U256>("price0CumulativeLast", ())?
-            .call()
-            .await?;
-
-        let price1_cumulative_last: U256 = pair_contract
-            .method::<_, U256>("price1CumulativeLast", ())?
-            .call()
-            .await?;
-
-        let k_last: U256 = pair_contract
-            .method::<_, U256>("kLast", ())?
-            .call()
-            .await?;
-
-        let pair_info = PairInfo {
+        // Get reserves and accumulators in one round trip
+        let reserves_call = pair_contract.method::<_, (U256, U256, u32)>("getReserves", ())?;
+        let price0_call = pair_contract.method::<_, U256>("price0CumulativeLast", ())?;
+        let price1_call = pair_contract.method::<_, U256>("price1CumulativeLast", ())?;
+        let k_last_call = pair_contract.method::<_, U256>("kLast", ())?;
+
+        let mut batch = RpcBatch::new();
+        let reserves = batch.call(&reserves_call)?;
+        let price0 = batch.call(&price0_call)?;
+        let price1 = batch.call(&price1_call)?;
+        let k_last = batch.call(&k_last_call)?;
+        let results = chain_provider.batch(&batch).await?;
+
+        let reserves = results.decode(reserves, &reserves_call)?;
+        let price0_cumulative_last = results.decode(price0, &price0_call)?;
+        let price1_cumulative_last = results.decode(price1, &price1_call)?;
+        let k_last = results.decode(k_last, &k_last_call)?;
+
+        Ok(PairInfo {
             address: pair_address,
             token0,
             token1,
@@ -188,12 +301,7 @@ impl SushiSwapManager {
             price0_cumulative_last,
             price1_cumulative_last,
             k_last,
-        };
-
-        // Cache the pair info
-        self.pairs_cache.write().await.insert(pair_address, pair_info.clone());
-
-        Ok(pair_info)
+        })
     }
 
     /// Swap exact tokens for tokens
@@ -207,6 +315,7 @@ impl SushiSwapManager {
         deadline: u64,
     ) -> Result<TransactionRequest> {
         info!("Creating swap transaction for {} tokens", amount_in);
+        check_swap_bounds(amount_out_min, deadline)?;
 
         let contracts = self.contracts.get(&chain_id)
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
@@ -322,54 +431,183 @@ impl SushiSwapManager {
         info!("Getting farm info for pool {}", pid);
 
         let contracts = self.contracts.get(&chain_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.farms_cache
+            .get_or_refresh((chain_id, pid), move || {
+                async move {
+                    Self::fetch_farms(chain_manager, contracts, chain_id, Some(pid)).await?
+                        .pop()
+                        .ok_or_else(|| anyhow!("Farm {} could not be read", pid))
+                }
+                .boxed()
+            })
+            .await
+    }
 
-        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+    /// Read farms from the chef with their LP decomposed into the underlying pair: only `pid` when
+    /// given, otherwise every farm with allocation points
+    async fn fetch_farms(
+        chain_manager: Arc<ChainManager>,
+        contracts: SushiSwapContracts,
+        chain_id: u64,
+        only: Option<u64>,
+    ) -> Result<Vec<FarmInfo>> {
+        let chain_provider = chain_manager.get_provider(chain_id).await?;
         let provider = Arc::new(chain_provider.provider.clone());
+        let mini_chef = Self::runs_mini_chef(chain_id);
+        let chef = Contract::new(contracts.master_chef, Self::get_chef_abi(chain_id)?, provider.clone());
+
+        // Pool count and emission rate in one round trip
+        let length_call = chef.method::<_, U256>("poolLength", ())?;
+        let rate_call = chef.method::<_, U256>(if mini_chef { "sushiPerSecond" } else { "sushiPerBlock" }, ())?;
+        let total_alloc_call = chef.method::<_, U256>("totalAllocPoint", ())?;
+        let mut batch = RpcBatch::new();
+        let length = batch.call(&length_call)?;
+        let rate = batch.call(&rate_call)?;
+        let total_alloc = batch.call(&total_alloc_call)?;
+        let results = chain_provider.batch(&batch).await?;
+        let pool_length = results.decode(length, &length_call)?.as_u64();
+        let reward_per_block = results.decode(rate, &rate_call)?;
+        let total_alloc_point = results.decode(total_alloc, &total_alloc_call)?;
+        let periods_per_year = if mini_chef { SECONDS_PER_YEAR } else { BLOCKS_PER_YEAR };
+
+        let pids: Vec<u64> = match only {
+            Some(pid) if pid >= pool_length => {
+                return Err(anyhow!("Farm {} does not exist, the chef has {} pools", pid, pool_length));
+            }
+            Some(pid) => vec![pid],
+            None => (0..pool_length).collect(),
+        };
 
-        let master_chef_abi = Self::get_master_chef_abi()?;
-        let master_chef = Contract::new(contracts.master_chef, master_chef_abi, provider);
+        // MasterChef keeps the LP token in `poolInfo`, MiniChef V2 in a separate `lpToken` array and
+        // orders `poolInfo` as (accSushiPerShare, lastRewardTime, allocPoint)
+        let info_calls = pids.iter()
+            .map(|pid| chef.method::<_, Token>("poolInfo", *pid))
+            .collect::<Result<Vec<_>, _>>()?;
+        let lp_calls = pids.iter()
+            .filter(|_| mini_chef)
+            .map(|pid| chef.method::<_, Address>("lpToken", *pid))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (infos, lp_tokens) = tokio::try_join!(
+            multicall(&provider, chain_id, info_calls),
+            multicall(&provider, chain_id, lp_calls),
+        )?;
 
-        // Get pool info
-        let pool_info: (Address, U256, u64, U256) = master_chef
-            .method::<_, (Address, U256, u64, U256)>("poolInfo", pid)?
-            .call()
-            .await?;
+        let mut farms = Vec::new();
+        for (index, (pid, info)) in pids.iter().zip(infos).enumerate() {
+            let Some(info) = info.and_then(Token::into_tuple) else {
+                warn!("Could not read farm {} on chain {}", pid, chain_id);
+                continue;
+            };
+            let lp_token = match mini_chef {
+                true => lp_tokens.get(index).cloned().flatten().and_then(Token::into_address),
+                false => info.first().cloned().and_then(Token::into_address),
+            };
+            let values: Vec<U256> = info.into_iter().filter_map(Token::into_uint).collect();
+            let (Some(lp_token), (true, [acc, last, alloc]) | (false, [alloc, last, acc])) = (lp_token, (mini_chef, values.as_slice())) else {
+                warn!("Unexpected pool info of farm {} on chain {}", pid, chain_id);
+                continue;
+            };
+            let (acc_sushi_per_share, last_reward, alloc_point) = (*acc, *last, *alloc);
+            // Farms without allocation earn nothing and are only listed when asked for
+            if only.is_none() && alloc_point.is_zero() {
+                continue;
+            }
+            let rewards_per_year = if total_alloc_point.is_zero() {
+                U256::zero()
+            } else {
+                reward_per_block * alloc_point / total_alloc_point * U256::from(periods_per_year)
+            };
 
-        let reward_per_block: U256 = master_chef
-            .method::<_, U256>("sushiPerBlock", ())?
-            .call()
-            .await
-            .unwrap_or_default();
+            farms.push(FarmInfo {
+                pid: *pid,
+                lp_token,
+                alloc_point,
+                last_reward_block: last_reward.low_u64(),
+                acc_sushi_per_share,
+                reward_per_block,
+                rewards_per_year,
+                reward_token: contracts.sushi_token,
+                total_staked: U256::zero(),
+                pair: None,
+            });
+        }
 
-        let total_alloc_point: U256 = master_chef
-            .method::<_, U256>("totalAllocPoint", ())?
-            .call()
-            .await
-            .unwrap_or_default();
-
-        // Calculate APY (simplified)
-        let apy = if total_alloc_point > U256::zero() {
-            let pool_reward_per_block = reward_per_block * pool_info.1 / total_alloc_point;
-            // This is a simplified APY calculation - in reality you'd need token prices
-            pool_reward_per_block.as_u64() as f64 * 0.1 // Mock calculation
-        } else {
-            0.0
-        };
+        Self::decompose_lp_tokens(&provider, chain_id, contracts.master_chef, &mut farms).await?;
+        Ok(farms)
+    }
 
-        let farm_info = FarmInfo {
-            pid,
-            lp_token: pool_info.0,
-            alloc_point: pool_info.1,
-            last_reward_block: pool_info.2,
-            acc_sushi_per_share: pool_info.3,
-            reward_per_block,
-            total_staked: U256::zero(), // Would need additional call
-            apy,
-        };
+    /// Fill in the LP staked in each farm and, for LP tokens that are SushiSwap pairs, their tokens,
+    /// reserves and supply
+    async fn decompose_lp_tokens(
+        provider: &Arc<Provider<PooledHttp>>,
+        chain_id: u64,
+        chef: Address,
+        farms: &mut [FarmInfo],
+    ) -> Result<()> {
+        let pair_abi = Self::get_pair_abi()?;
+        let lps: Vec<_> = farms.iter().map(|farm| Contract::new(farm.lp_token, pair_abi.clone(), provider.clone())).collect();
+        let staked_calls = lps.iter().map(|lp| lp.method::<_, U256>("balanceOf", chef)).collect::<Result<Vec<_>, _>>()?;
+        let supply_calls = lps.iter().map(|lp| lp.method::<_, U256>("totalSupply", ())).collect::<Result<Vec<_>, _>>()?;
+        let token0_calls = lps.iter().map(|lp| lp.method::<_, Address>("token0", ())).collect::<Result<Vec<_>, _>>()?;
+        let token1_calls = lps.iter().map(|lp| lp.method::<_, Address>("token1", ())).collect::<Result<Vec<_>, _>>()?;
+        let reserves_calls = lps.iter()
+            .map(|lp| lp.method::<_, (U256, U256, u32)>("getReserves", ()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (staked, supplies, tokens0, tokens1, reserves) = tokio::try_join!(
+            multicall(provider, chain_id, staked_calls),
+            multicall(provider, chain_id, supply_calls),
+            multicall(provider, chain_id, token0_calls),
+            multicall(provider, chain_id, token1_calls),
+            multicall(provider, chain_id, reserves_calls),
+        )?;
 
-        self.farms_cache.write().await.insert(pid, farm_info.clone());
-        Ok(farm_info)
+        // Pairs answer all of token0, token1, getReserves and totalSupply
+        let pairs: Vec<Option<(Address, Address, U256, U256, U256)>> = tokens0.into_iter()
+            .zip(tokens1)
+            .zip(reserves)
+            .zip(supplies)
+            .map(|(((token0, token1), reserves), supply)| {
+                let reserves: Vec<U256> = reserves?.into_tuple()?.into_iter().filter_map(Token::into_uint).collect();
+                Some((
+                    token0?.into_address()?,
+                    token1?.into_address()?,
+                    *reserves.first()?,
+                    *reserves.get(1)?,
+                    supply?.into_uint()?,
+                ))
+            })
+            .collect();
+
+        let mut tokens: Vec<Address> = pairs.iter().flatten().flat_map(|(token0, token1, ..)| [*token0, *token1]).collect();
+        tokens.sort();
+        tokens.dedup();
+        let token_abi = Self::get_token_abi()?;
+        let decimals_calls = tokens.iter()
+            .map(|token| Contract::new(*token, token_abi.clone(), provider.clone()).method::<_, u8>("decimals", ()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let decimals: HashMap<Address, u8> = tokens.iter()
+            .zip(multicall(provider, chain_id, decimals_calls).await?)
+            .filter_map(|(token, decimals)| Some((*token, decimals?.into_uint()?.low_u32() as u8)))
+            .collect();
+
+        for ((farm, staked), pair) in farms.iter_mut().zip(staked).zip(pairs) {
+            farm.total_staked = staked.and_then(Token::into_uint).unwrap_or_default();
+            farm.pair = pair.and_then(|(token0, token1, reserve0, reserve1, total_supply)| {
+                Some(FarmPair {
+                    token0,
+                    token1,
+                    decimals0: *decimals.get(&token0)?,
+                    decimals1: *decimals.get(&token1)?,
+                    reserve0,
+                    reserve1,
+                    total_supply,
+                })
+            });
+        }
+        Ok(())
     }
 
     /// Stake LP tokens in farm
@@ -436,8 +674,7 @@ impl SushiSwapManager {
         let chain_provider = self.chain_manager.get_provider(chain_id).await?;
         let provider = Arc::new(chain_provider.provider.clone());
 
-        let master_chef_abi = Self::get_master_chef_abi()?;
-        let master_chef = Contract::new(contracts.master_chef, master_chef_abi, provider);
+        let master_chef = Contract::new(contracts.master_chef, Self::get_chef_abi(chain_id)?, provider);
 
         let user_info: (U256, U256) = master_chef
             .method::<_, (U256, U256)>("userInfo", (pid, user))?
@@ -476,37 +713,59 @@ impl SushiSwapManager {
         Ok(amounts)
     }
 
-    /// Get all available farms
+    /// Get the raw reserve1/reserve0 spot price of a pair at a given block
+    pub async fn get_pair_price_at_block(&self, chain_id: u64, pair: Address, block_number: u64) -> Result<f64> {
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let pair_abi = Self::get_pair_abi()?;
+        let pair_contract = Contract::new(pair, pair_abi, provider);
+
+        let reserves: (U256, U256, u32) = pair_contract
+            .method::<_, (U256, U256, u32)>("getReserves", ())?
+            .block(block_number)
+            .call()
+            .await?;
+
+        if reserves.0.is_zero() {
+            return Err(anyhow!("Pair has no liquidity at block {}", block_number));
+        }
+
+        Ok(reserves.1.as_u128() as f64 / reserves.0.as_u128() as f64)
+    }
+
+    /// Every farm of the chain's chef earning rewards, with its LP decomposed into the underlying pair
     pub async fn get_all_farms(&self, chain_id: u64) -> Result<Vec<FarmInfo>> {
         info!("Getting all farms for chain {}", chain_id);
-        
+
         let contracts = self.contracts.get(&chain_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.chef_cache
+            .get_or_refresh(chain_id, move || Self::fetch_farms(chain_manager, contracts, chain_id, None).boxed())
+            .await
+    }
 
-        let _provider = self.chain_manager.get_provider(chain_id).await?;
-        // Note: Contract interaction would be implemented here in production
-
-        // Get the number of pools (mock implementation for now)
-        let pool_length = 10u64; // In reality, this would be fetched from the contract
-
-        let mut farms = Vec::new();
-        
-        for i in 0..pool_length.min(10) { // Limit to first 10 for demo
-            // Create mock farm info - in reality this would be fetched from contract
-            let farm = FarmInfo {
-                pid: i,
-                lp_token: Address::from_low_u64_be(0x1000 + i), // Mock address
-                alloc_point: U256::from(100),
-                last_reward_block: 1000000 + i,
-                acc_sushi_per_share: U256::zero(),
-                reward_per_block: U256::from(1000),
-                total_staked: U256::from(10000000), // Mock 10M tokens staked
-                apy: 15.5, // Mock 15.5% APY
-            };
-            farms.push(farm);
-        }
+    /// LP tokens `user` has staked in each of `pids`, read in multicall batches
+    pub async fn get_user_stakes(&self, chain_id: u64, user: Address, pids: &[u64]) -> Result<HashMap<u64, U256>> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+        let provider = Arc::new(self.chain_manager.get_provider(chain_id).await?.provider.clone());
+        let chef = Contract::new(contracts.master_chef, Self::get_chef_abi(chain_id)?, provider.clone());
+
+        let calls = pids.iter()
+            .map(|pid| chef.method::<_, (U256, U256)>("userInfo", (*pid, user)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(pids.iter()
+            .zip(multicall(&provider, chain_id, calls).await?)
+            .filter_map(|(pid, info)| Some((*pid, info?.into_tuple()?.into_iter().next()?.into_uint()?)))
+            .collect())
+    }
 
-        Ok(farms)
+    /// Only mainnet runs the original MasterChef, other chains MiniChef V2
+    fn runs_mini_chef(chain_id: u64) -> bool {
+        chain_id != 1
     }
 
     // ABI helper methods
@@ -560,6 +819,34 @@ impl SushiSwapManager {
                 "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
                 "stateMutability": "view",
                 "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "token0",
+                "outputs": [{"internalType": "address", "name": "", "type": "address"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "token1",
+                "outputs": [{"internalType": "address", "name": "", "type": "address"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "totalSupply",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "address", "name": "", "type": "address"}],
+                "name": "balanceOf",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
             }
         ]"#;
         
@@ -634,6 +921,10 @@ impl SushiSwapManager {
         Ok(serde_json::from_str(abi_json)?)
     }
 
+    fn get_chef_abi(chain_id: u64) -> Result<Abi> {
+        if Self::runs_mini_chef(chain_id) { Self::get_mini_chef_abi() } else { Self::get_master_chef_abi() }
+    }
+
     fn get_master_chef_abi() -> Result<Abi> {
         let abi_json = r#"[
             {
@@ -691,6 +982,13 @@ impl SushiSwapManager {
                 "stateMutability": "view",
                 "type": "function"
             },
+            {
+                "inputs": [],
+                "name": "poolLength",
+                "outputs": [{"internalType": "uint256", "name": "pools", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
             {
                 "inputs": [],
                 "name": "sushiPerBlock",
@@ -709,4 +1007,87 @@ impl SushiSwapManager {
         
         Ok(serde_json::from_str(abi_json)?)
     }
+
+    fn get_mini_chef_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "poolLength",
+                "outputs": [{"internalType": "uint256", "name": "pools", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "name": "lpToken",
+                "outputs": [{"internalType": "contract IERC20", "name": "", "type": "address"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "name": "poolInfo",
+                "outputs": [
+                    {"internalType": "uint128", "name": "accSushiPerShare", "type": "uint128"},
+                    {"internalType": "uint64", "name": "lastRewardTime", "type": "uint64"},
+                    {"internalType": "uint64", "name": "allocPoint", "type": "uint64"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "", "type": "uint256"},
+                    {"internalType": "address", "name": "", "type": "address"}
+                ],
+                "name": "userInfo",
+                "outputs": [
+                    {"internalType": "uint256", "name": "amount", "type": "uint256"},
+                    {"internalType": "int256", "name": "rewardDebt", "type": "int256"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "_pid", "type": "uint256"},
+                    {"internalType": "address", "name": "_user", "type": "address"}
+                ],
+                "name": "pendingSushi",
+                "outputs": [{"internalType": "uint256", "name": "pending", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "sushiPerSecond",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            },
+            {
+                "inputs": [],
+                "name": "totalAllocPoint",
+                "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+
+    fn get_token_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "decimals",
+                "outputs": [{"internalType": "uint8", "name": "", "type": "uint8"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
 }
@@ -0,0 +1,288 @@
+// Quoted and built swaps kept for DEX statistics, broken down by venue, pair and chain
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use super::aggregator::{DexType, QuoteComparison};
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `swap_stats_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/swap_stats.json";
+/// Oldest records are dropped beyond this many
+const MAX_RECORDS: usize = 20_000;
+/// Percentiles of `price_impact_distribution`
+const IMPACT_PERCENTILES: [f64; 5] = [0.1, 0.25, 0.5, 0.75, 0.9];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwapRecordKind {
+    /// Quote served to a client
+    Quote,
+    /// Swap transaction built for execution
+    Swap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapRecord {
+    pub kind: SwapRecordKind,
+    pub chain_id: u64,
+    /// Venue of the best route
+    pub dex: DexType,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub expected_output: U256,
+    pub savings_percentage: f64,
+    /// In percent
+    pub price_impact: f64,
+    /// Transaction history record of a built swap
+    pub tracking_id: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl SwapRecord {
+    pub fn new(kind: SwapRecordKind, chain_id: u64, token_in: Address, token_out: Address, comparison: &QuoteComparison) -> Self {
+        let route = &comparison.best_route;
+        Self {
+            kind,
+            chain_id,
+            dex: route.dex.clone(),
+            token_in,
+            token_out,
+            amount_in: route.input_amount,
+            expected_output: route.output_amount,
+            savings_percentage: comparison.savings_percentage,
+            price_impact: route.price_impact,
+            tracking_id: None,
+            recorded_at: Utc::now(),
+        }
+    }
+}
+
+/// Records statistics are computed from; every bound is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SwapStatsFilter {
+    pub chain_id: Option<u64>,
+    pub dex: Option<DexType>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl SwapStatsFilter {
+    fn matches(&self, record: &SwapRecord) -> bool {
+        self.chain_id.is_none_or(|chain_id| record.chain_id == chain_id)
+            && self.dex.as_ref().is_none_or(|dex| record.dex == *dex)
+            && self.from.is_none_or(|from| record.recorded_at >= from)
+            && self.to.is_none_or(|to| record.recorded_at < to)
+    }
+}
+
+/// DEX statistics over the recorded quotes and swaps; averages are over built swaps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DexStats {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Swap transactions built
+    pub total_swaps: u64,
+    pub total_quotes: u64,
+    pub average_savings: f64,
+    pub average_price_impact: f64,
+    /// Venue routing the most swaps, `None` without swaps
+    pub best_dex_performance: Option<DexType>,
+    /// Price impact of swaps at the 10th, 25th, 50th, 75th and 90th percentiles, empty without swaps
+    pub price_impact_distribution: Vec<f64>,
+    pub by_dex: Vec<DexBreakdown>,
+    pub by_pair: Vec<PairBreakdown>,
+    pub by_chain: Vec<ChainBreakdown>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DexBreakdown {
+    pub dex: DexType,
+    pub swaps: u64,
+    pub quotes: u64,
+    pub average_savings: f64,
+    pub average_price_impact: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairBreakdown {
+    pub chain_id: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub swaps: u64,
+    pub quotes: u64,
+    /// Input of the built swaps in `token_in` base units
+    pub volume_in: U256,
+    /// Quoted output of the built swaps in `token_out` base units
+    pub volume_out: U256,
+    pub average_price_impact: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainBreakdown {
+    pub chain_id: u64,
+    pub swaps: u64,
+    pub quotes: u64,
+    pub average_savings: f64,
+}
+
+/// Counts and sums of one group of records
+#[derive(Default)]
+struct Tally {
+    swaps: u64,
+    quotes: u64,
+    savings: f64,
+    price_impact: f64,
+    volume_in: U256,
+    volume_out: U256,
+}
+
+impl Tally {
+    fn add(&mut self, record: &SwapRecord) {
+        match record.kind {
+            SwapRecordKind::Quote => self.quotes += 1,
+            SwapRecordKind::Swap => {
+                self.swaps += 1;
+                self.savings += record.savings_percentage;
+                self.price_impact += record.price_impact;
+                self.volume_in = self.volume_in.saturating_add(record.amount_in);
+                self.volume_out = self.volume_out.saturating_add(record.expected_output);
+            }
+        }
+    }
+
+    fn average_savings(&self) -> f64 {
+        if self.swaps == 0 { 0.0 } else { self.savings / self.swaps as f64 }
+    }
+
+    fn average_price_impact(&self) -> f64 {
+        if self.swaps == 0 { 0.0 } else { self.price_impact / self.swaps as f64 }
+    }
+}
+
+/// Keeps the most recent quoted and built swaps
+pub struct SwapRecorder {
+    /// JSON file holding the records, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    records: RwLock<Vec<SwapRecord>>,
+}
+
+impl SwapRecorder {
+    pub async fn new(store_path: Option<PathBuf>) -> Result<Self> {
+        let records: Vec<SwapRecord> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !records.is_empty()) {
+            info!("Loaded {} swap records from {}", records.len(), path.display());
+        }
+
+        Ok(Self { store_path, records: RwLock::new(records) })
+    }
+
+    /// Recorder persisting to `swap_stats_store_path`, an empty path keeps records in memory
+    pub async fn from_config(config: &config::Config) -> Result<Self> {
+        let path = config
+            .get_string("swap_stats_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        Self::new((!path.is_empty()).then(|| PathBuf::from(path))).await
+    }
+
+    pub async fn record(&self, record: SwapRecord) {
+        let mut records = self.records.write().await;
+        records.push(record);
+        if records.len() > MAX_RECORDS {
+            let excess = records.len() - MAX_RECORDS;
+            records.drain(..excess);
+        }
+        if let Some(path) = &self.store_path {
+            if let Err(e) = write_store(path, &*records).await {
+                warn!("Failed to persist swap records to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    pub async fn stats(&self, filter: &SwapStatsFilter) -> DexStats {
+        let records = self.records.read().await;
+        let matching: Vec<&SwapRecord> = records.iter().filter(|record| filter.matches(record)).collect();
+
+        let mut total = Tally::default();
+        let mut by_dex: BTreeMap<String, (DexType, Tally)> = BTreeMap::new();
+        let mut by_pair: BTreeMap<(u64, Address, Address), Tally> = BTreeMap::new();
+        let mut by_chain: BTreeMap<u64, Tally> = BTreeMap::new();
+        for record in &matching {
+            total.add(record);
+            by_dex.entry(format!("{:?}", record.dex))
+                .or_insert_with(|| (record.dex.clone(), Tally::default()))
+                .1
+                .add(record);
+            by_pair.entry((record.chain_id, record.token_in, record.token_out)).or_default().add(record);
+            by_chain.entry(record.chain_id).or_default().add(record);
+        }
+
+        let mut impacts: Vec<f64> = matching.iter()
+            .filter(|record| record.kind == SwapRecordKind::Swap)
+            .map(|record| record.price_impact)
+            .collect();
+        impacts.sort_by(f64::total_cmp);
+        let price_impact_distribution = if impacts.is_empty() {
+            Vec::new()
+        } else {
+            IMPACT_PERCENTILES.iter()
+                .map(|p| impacts[((impacts.len() - 1) as f64 * p).round() as usize])
+                .collect()
+        };
+
+        let mut pairs: Vec<PairBreakdown> = by_pair.into_iter()
+            .map(|((chain_id, token_in, token_out), tally)| PairBreakdown {
+                chain_id,
+                token_in,
+                token_out,
+                swaps: tally.swaps,
+                quotes: tally.quotes,
+                volume_in: tally.volume_in,
+                volume_out: tally.volume_out,
+                average_price_impact: tally.average_price_impact(),
+            })
+            .collect();
+        pairs.sort_by(|a, b| (b.swaps, b.quotes).cmp(&(a.swaps, a.quotes)));
+
+        DexStats {
+            from: filter.from,
+            to: filter.to,
+            total_swaps: total.swaps,
+            total_quotes: total.quotes,
+            average_savings: total.average_savings(),
+            average_price_impact: total.average_price_impact(),
+            best_dex_performance: by_dex.values()
+                .filter(|(_, tally)| tally.swaps > 0)
+                .max_by_key(|(_, tally)| tally.swaps)
+                .map(|(dex, _)| dex.clone()),
+            price_impact_distribution,
+            by_dex: by_dex.into_values()
+                .map(|(dex, tally)| DexBreakdown {
+                    dex,
+                    swaps: tally.swaps,
+                    quotes: tally.quotes,
+                    average_savings: tally.average_savings(),
+                    average_price_impact: tally.average_price_impact(),
+                })
+                .collect(),
+            by_pair: pairs,
+            by_chain: by_chain.into_iter()
+                .map(|(chain_id, tally)| ChainBreakdown {
+                    chain_id,
+                    swaps: tally.swaps,
+                    quotes: tally.quotes,
+                    average_savings: tally.average_savings(),
+                })
+                .collect(),
+        }
+    }
+}
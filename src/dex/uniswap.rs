@@ -1,18 +1,74 @@
 use anyhow::{Result, anyhow};
 use ethers::{
-    abi::{Abi, Token},
+    abi::{self, Abi, ParamType},
     contract::Contract,
-    providers::{Provider, Http},
-    types::{Address, U256, TransactionRequest, Bytes, H256},
+    providers::{Middleware, Provider},
+    types::{Address, BlockNumber, Filter, I256, U256, TransactionRequest, H256},
+    utils::keccak256,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::collections::HashMap;
+use std::time::Duration;
+use futures::FutureExt;
 use tracing::{info, warn, error};
 
+use crate::analytics::impermanent_loss::{entry_price_from_deposit, impermanent_loss_percentage, tick_to_price};
+use crate::cache::{CacheManager, NamespacedCache, TimedCache};
+use crate::chains::batch::RpcBatch;
+use crate::chains::pool::PooledHttp;
 use crate::chains::ChainManager;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
+use crate::dex::aggregator::check_swap_bounds;
 use crate::contracts::erc20::ERC20Contract;
 
+/// Pool price and liquidity change with every swap, pool state is refreshed after this long
+const POOL_CACHE_TTL: Duration = Duration::from_secs(15);
+/// Pools never move, but a missing pool may be created later
+const POOL_ADDRESS_CACHE_TTL: Duration = Duration::from_secs(3600);
+const POOL_ADDRESS_CACHE_MAX_ENTRIES: usize = 10_000;
+/// Pool volume drives fee APR estimates, which do not need every block
+const POOL_VOLUME_CACHE_TTL: Duration = Duration::from_secs(300);
+const POOL_VOLUME_CACHE_MAX_ENTRIES: usize = 1_000;
+
+/// `ticks(int24)`: liquidityGross, liquidityNet, feeGrowthOutside0X128, feeGrowthOutside1X128, tickCumulativeOutside,
+/// secondsPerLiquidityOutsideX128, secondsOutside, initialized
+type PoolTick = (u128, i128, U256, U256, i64, U256, u32, bool);
+
+fn pool_key(chain_id: u64, token0: Address, token1: Address, fee: u32) -> String {
+    format!("{}:{:?}:{:?}:{}", chain_id, token0, token1, fee)
+}
+
+/// Raw token1/token0 price of a pool's `sqrtPriceX96`
+fn sqrt_price_x96_to_price(sqrt_price_x96: U256) -> f64 {
+    // sqrtPriceX96 is a uint160, so shifting by 32 keeps it within u128
+    let sqrt_price = (sqrt_price_x96 >> 32).as_u128() as f64 / 2f64.powi(64);
+    sqrt_price * sqrt_price
+}
+
+/// `Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)`
+fn swap_topic() -> H256 {
+    H256::from(keccak256("Swap(address,address,int256,int256,uint160,uint128,int24)"))
+}
+
+/// Fee growth per unit of liquidity inside a tick range, wrapping like the pool's own arithmetic
+fn fee_growth_inside(current_tick: i32, tick_lower: i32, tick_upper: i32, global: U256, lower_outside: U256, upper_outside: U256) -> U256 {
+    let below = if current_tick >= tick_lower { lower_outside } else { global.overflowing_sub(lower_outside).0 };
+    let above = if current_tick < tick_upper { upper_outside } else { global.overflowing_sub(upper_outside).0 };
+    global.overflowing_sub(below).0.overflowing_sub(above).0
+}
+
+/// Fees earned by `liquidity` since the range's fee growth was `inside_last`
+fn fees_accrued(liquidity: U256, inside: U256, inside_last: U256) -> U256 {
+    let fees = liquidity.full_mul(inside.overflowing_sub(inside_last).0) >> 128;
+    U256::try_from(fees).unwrap_or(U256::MAX)
+}
+
+/// `IncreaseLiquidity(uint256 indexed tokenId, uint128 liquidity, uint256 amount0, uint256 amount1)`
+fn increase_liquidity_topic() -> H256 {
+    H256::from(keccak256("IncreaseLiquidity(uint256,uint128,uint256,uint256)"))
+}
+
 /// Uniswap V3 pool information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PoolInfo {
@@ -56,17 +112,26 @@ pub struct LiquidityPosition {
     pub fee_growth_inside1_last_x128: U256,
     pub tokens_owed0: U256,
     pub tokens_owed1: U256,
+    /// Owed fees plus those accrued since the position was last touched, `tokens_owed` when the pool could not be read
+    pub uncollected_fees0: U256,
+    pub uncollected_fees1: U256,
+    /// Raw token1/token0 price the position was opened at, from its first `IncreaseLiquidity` event
+    pub entry_price: Option<f64>,
+    pub current_price: Option<f64>,
+    pub in_range: Option<bool>,
+    /// Value against holding the tokens first deposited, negative for a loss; collected fees are not included
+    pub impermanent_loss_percentage: Option<f64>,
 }
 
-/// Price and liquidity data for a pool
+/// Swap volume of a pool over a block window, by the token swapped in
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PoolData {
-    pub pool_info: PoolInfo,
-    pub token0_price: f64,
-    pub token1_price: f64,
-    pub volume_24h: U256,
-    pub tvl: U256,
-    pub fee_apr: f64,
+pub struct PoolVolume {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub elapsed_secs: u64,
+    pub swaps: usize,
+    pub volume0: U256,
+    pub volume1: U256,
 }
 
 /// Uniswap V3 contract addresses for different chains
@@ -120,11 +185,13 @@ impl UniswapContracts {
 pub struct UniswapV3Manager {
     chain_manager: Arc<ChainManager>,
     contracts: HashMap<u64, UniswapContracts>,
-    pools_cache: Arc<tokio::sync::RwLock<HashMap<Address, PoolData>>>,
+    pools_cache: NamespacedCache<PoolInfo>,
+    pool_addresses: TimedCache<(u64, Address, Address, u32), Address>,
+    volumes: TimedCache<(u64, Address, u64), PoolVolume>,
 }
 
 impl UniswapV3Manager {
-    pub async fn new(chain_manager: Arc<ChainManager>) -> Result<Self> {
+    pub async fn new(chain_manager: Arc<ChainManager>, caches: &CacheManager) -> Result<Self> {
         info!("Initializing Uniswap V3 Manager");
 
         let mut contracts = HashMap::new();
@@ -137,7 +204,9 @@ impl UniswapV3Manager {
         Ok(Self {
             chain_manager,
             contracts,
-            pools_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            pools_cache: caches.namespace("uniswap_v3_pools", POOL_CACHE_TTL),
+            pool_addresses: caches.timed("uniswap_v3_pool_addresses", POOL_ADDRESS_CACHE_TTL, POOL_ADDRESS_CACHE_MAX_ENTRIES),
+            volumes: caches.timed("uniswap_v3_pool_volumes", POOL_VOLUME_CACHE_TTL, POOL_VOLUME_CACHE_MAX_ENTRIES),
         })
     }
 
@@ -150,18 +219,60 @@ impl UniswapV3Manager {
         Ok(Self {
             chain_manager,
             contracts,
-            pools_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            pools_cache: CacheManager::memory().namespace("uniswap_v3_pools", POOL_CACHE_TTL),
+            pool_addresses: TimedCache::new("uniswap_v3_pool_addresses", POOL_ADDRESS_CACHE_TTL, POOL_ADDRESS_CACHE_MAX_ENTRIES),
+            volumes: TimedCache::new("uniswap_v3_pool_volumes", POOL_VOLUME_CACHE_TTL, POOL_VOLUME_CACHE_MAX_ENTRIES),
         })
     }
 
+    /// Address-registry entries probed against the Uniswap V3 interfaces at startup
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| [
+                ContractDeployment::new(chain_id, "uniswap_v3", "factory", contracts.factory, ProtocolInterface::UniswapV3Factory),
+                ContractDeployment::new(chain_id, "uniswap_v3", "router", contracts.router, ProtocolInterface::UniswapV3Router),
+                ContractDeployment::new(chain_id, "uniswap_v3", "position_manager", contracts.position_manager, ProtocolInterface::UniswapV3PositionManager),
+                ContractDeployment::new(chain_id, "uniswap_v3", "quoter", contracts.quoter, ProtocolInterface::UniswapV3Quoter),
+            ])
+            .collect()
+    }
+
+    /// Drop cached pool data, addresses and volumes
+    pub async fn clear_cache(&self) {
+        self.pools_cache.invalidate_all().await;
+        self.pool_addresses.clear().await;
+        self.volumes.clear().await;
+    }
+
     /// Get pool information for a trading pair
     pub async fn get_pool_info(&self, chain_id: u64, token0: Address, token1: Address, fee: u32) -> Result<PoolInfo> {
         info!("Getting pool info for tokens {:?}/{:?} on chain {}", token0, token1, chain_id);
 
         let contracts = self.contracts.get(&chain_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+        let chain_manager = self.chain_manager.clone();
+        self.pools_cache
+            .get_or_load(&pool_key(chain_id, token0, token1, fee), move || {
+                Self::fetch_pool_info(chain_manager, contracts, chain_id, token0, token1, fee).boxed()
+            })
+            .await
+    }
 
-        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+    /// Drop the cached state of a pool, e.g. after a swap through it
+    pub async fn invalidate_pool(&self, chain_id: u64, token0: Address, token1: Address, fee: u32) {
+        self.pools_cache.invalidate(&pool_key(chain_id, token0, token1, fee)).await;
+    }
+
+    async fn fetch_pool_info(
+        chain_manager: Arc<ChainManager>,
+        contracts: UniswapContracts,
+        chain_id: u64,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+    ) -> Result<PoolInfo> {
+        let chain_provider = chain_manager.get_provider(chain_id).await?;
         let provider = Arc::new(chain_provider.provider.clone());
 
         // Get factory contract
@@ -182,31 +293,26 @@ impl UniswapV3Manager {
         let pool_abi = Self::get_pool_abi()?;
         let pool_contract = Contract::new(pool_address, pool_abi, provider);
 
-        // Get pool state
-        let slot0: (U256, i32, u16, u16, u16, u8, bool) = pool_contract
-            .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
-            .call()
-            .await?;
-
-        let liquidity: U256 = pool_contract
-            .method::<_, U256>("liquidity", ())?
-            .call()
-            .await?;
-
-        let tick_spacing: i32 = pool_contract
-            .method::<_, i32>("tickSpacing", ())?
-            .call()
-            .await?;
-
-        let fee_growth_global0_x128: U256 = pool_contract
-            .method::<_, U256>("feeGrowthGlobal0X128", ())?
-            .call()
-            .await?;
-
-        let fee_growth_global1_x128: U256 = pool_contract
-            .method::<_, U256>("feeGrowthGlobal1X128", ())?
-            .call()
-            .await?;
+        // Get pool state in one round trip
+        let slot0_call = pool_contract.method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?;
+        let liquidity_call = pool_contract.method::<_, U256>("liquidity", ())?;
+        let tick_spacing_call = pool_contract.method::<_, i32>("tickSpacing", ())?;
+        let fee_growth0_call = pool_contract.method::<_, U256>("feeGrowthGlobal0X128", ())?;
+        let fee_growth1_call = pool_contract.method::<_, U256>("feeGrowthGlobal1X128", ())?;
+
+        let mut batch = RpcBatch::new();
+        let slot0 = batch.call(&slot0_call)?;
+        let liquidity = batch.call(&liquidity_call)?;
+        let tick_spacing = batch.call(&tick_spacing_call)?;
+        let fee_growth0 = batch.call(&fee_growth0_call)?;
+        let fee_growth1 = batch.call(&fee_growth1_call)?;
+        let results = chain_provider.batch(&batch).await?;
+
+        let slot0 = results.decode(slot0, &slot0_call)?;
+        let liquidity = results.decode(liquidity, &liquidity_call)?;
+        let tick_spacing = results.decode(tick_spacing, &tick_spacing_call)?;
+        let fee_growth_global0_x128 = results.decode(fee_growth0, &fee_growth0_call)?;
+        let fee_growth_global1_x128 = results.decode(fee_growth1, &fee_growth1_call)?;
 
         let pool_info = PoolInfo {
             address: pool_address,
@@ -233,6 +339,7 @@ impl UniswapV3Manager {
     ) -> Result<TransactionRequest> {
         info!("Creating swap transaction for {} -> {} on chain {}", 
               params.token_in, params.token_out, chain_id);
+        check_swap_bounds(params.amount_out_minimum, params.deadline)?;
 
         let contracts = self.contracts.get(&chain_id)
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
@@ -344,7 +451,7 @@ impl UniswapV3Manager {
         );
 
         let call = position_manager
-            .method::<_, (U256, U256, U256, U256)>("mint", mint_params)?;
+            .method::<_, (U256, U256, U256, U256)>("mint", (mint_params,))?;
 
         let tx = TransactionRequest::new()
             .to(contracts.position_manager)
@@ -383,7 +490,7 @@ impl UniswapV3Manager {
         );
 
         let call = position_manager
-            .method::<_, (U256, U256)>("decreaseLiquidity", decrease_params)?;
+            .method::<_, (U256, U256)>("decreaseLiquidity", (decrease_params,))?;
 
         let tx = TransactionRequest::new()
             .to(contracts.position_manager)
@@ -392,6 +499,26 @@ impl UniswapV3Manager {
         Ok(tx)
     }
 
+    /// Collect everything a position is owed, withdrawn liquidity included, to `recipient`
+    pub async fn collect(&self, chain_id: u64, token_id: U256, recipient: Address) -> Result<TransactionRequest> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let position_manager_abi = Self::get_position_manager_abi()?;
+        let position_manager = Contract::new(contracts.position_manager, position_manager_abi, provider);
+
+        let collect_params = (token_id, recipient, u128::MAX, u128::MAX);
+        let call = position_manager
+            .method::<_, (U256, U256)>("collect", (collect_params,))?;
+
+        Ok(TransactionRequest::new()
+            .to(contracts.position_manager)
+            .data(call.calldata().unwrap_or_default()))
+    }
+
     /// Get all liquidity positions for an address
     pub async fn get_positions(&self, chain_id: u64, owner: Address) -> Result<Vec<LiquidityPosition>> {
         info!("Getting liquidity positions for address {:?}", owner);
@@ -419,39 +546,172 @@ impl UniswapV3Manager {
                 .call()
                 .await?;
 
-            // Get position details
-            let position_data: (
-                U256, Address, Address, Address, u32, i32, i32, u128, U256, U256, u128, u128
-            ) = position_manager
-                .method("positions", token_id)?
-                .call()
-                .await?;
-
-            // Find pool address
-            let pool_address = self.get_pool_address(chain_id, position_data.2, position_data.3, position_data.4).await?;
-
-            let position = LiquidityPosition {
-                token_id,
-                pool: pool_address,
-                token0: position_data.2,
-                token1: position_data.3,
-                fee: position_data.4,
-                tick_lower: position_data.5,
-                tick_upper: position_data.6,
-                liquidity: U256::from(position_data.7),
-                fee_growth_inside0_last_x128: position_data.9,
-                fee_growth_inside1_last_x128: position_data.10.into(),
-                tokens_owed0: U256::from(position_data.11),
-                tokens_owed1: U256::from(position_data.11), // Note: This should be different for token1
-            };
-
-            positions.push(position);
+            positions.push(self.get_position(chain_id, token_id).await?);
         }
 
         info!("Found {} positions for owner", positions.len());
         Ok(positions)
     }
 
+    /// A liquidity position with its uncollected fees, entry price and live impermanent loss
+    pub async fn get_position(&self, chain_id: u64, token_id: U256) -> Result<LiquidityPosition> {
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let position_manager_abi = Self::get_position_manager_abi()?;
+        let position_manager = Contract::new(contracts.position_manager, position_manager_abi, provider.clone());
+
+        // Get position details
+        let position_data: (
+            U256, Address, Address, Address, u32, i32, i32, u128, U256, U256, u128, u128
+        ) = position_manager
+            .method("positions", token_id)?
+            .call()
+            .await?;
+
+        // Find pool address
+        let pool_address = self.get_pool_address(chain_id, position_data.2, position_data.3, position_data.4).await?;
+
+        let entry_price = self.entry_price(&chain_provider.provider, contracts.position_manager, token_id, position_data.5, position_data.6).await
+            .unwrap_or_else(|e| {
+                warn!("Could not find the entry price of position {}: {}", token_id, e);
+                None
+            });
+        let current_price = match self.get_pool_info(chain_id, position_data.2, position_data.3, position_data.4).await {
+            Ok(pool) => Some(sqrt_price_x96_to_price(pool.sqrt_price_x96)),
+            Err(e) => {
+                warn!("Could not price pool {:?}: {}", pool_address, e);
+                None
+            }
+        };
+        let range = (tick_to_price(position_data.5), tick_to_price(position_data.6));
+
+        let mut position = LiquidityPosition {
+            token_id,
+            pool: pool_address,
+            token0: position_data.2,
+            token1: position_data.3,
+            fee: position_data.4,
+            tick_lower: position_data.5,
+            tick_upper: position_data.6,
+            liquidity: U256::from(position_data.7),
+            fee_growth_inside0_last_x128: position_data.8,
+            fee_growth_inside1_last_x128: position_data.9,
+            tokens_owed0: U256::from(position_data.10),
+            tokens_owed1: U256::from(position_data.11),
+            uncollected_fees0: U256::from(position_data.10),
+            uncollected_fees1: U256::from(position_data.11),
+            entry_price,
+            current_price,
+            in_range: current_price.map(|price| (range.0..=range.1).contains(&price)),
+            impermanent_loss_percentage: entry_price.zip(current_price)
+                .map(|(entry, current)| impermanent_loss_percentage(entry, current, Some(range))),
+        };
+
+        match self.uncollected_fees(provider, &position).await {
+            Ok((fees0, fees1)) => {
+                position.uncollected_fees0 = fees0;
+                position.uncollected_fees1 = fees1;
+            }
+            Err(e) => warn!("Could not read the fees accrued by position {}: {}", token_id, e),
+        }
+
+        Ok(position)
+    }
+
+    /// Fees owed to a position plus those accrued in its range since it was last touched
+    async fn uncollected_fees(&self, provider: Arc<Provider<PooledHttp>>, position: &LiquidityPosition) -> Result<(U256, U256)> {
+        let pool = Contract::new(position.pool, Self::get_pool_abi()?, provider);
+
+        // Read fresh: fee growth outside a tick only matches the global growth of the same block
+        let slot0: (U256, i32, u16, u16, u16, u8, bool) = pool
+            .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
+            .call()
+            .await?;
+        let global0: U256 = pool.method::<_, U256>("feeGrowthGlobal0X128", ())?.call().await?;
+        let global1: U256 = pool.method::<_, U256>("feeGrowthGlobal1X128", ())?.call().await?;
+        let lower: PoolTick = pool.method::<_, PoolTick>("ticks", position.tick_lower)?.call().await?;
+        let upper: PoolTick = pool.method::<_, PoolTick>("ticks", position.tick_upper)?.call().await?;
+
+        let inside0 = fee_growth_inside(slot0.1, position.tick_lower, position.tick_upper, global0, lower.2, upper.2);
+        let inside1 = fee_growth_inside(slot0.1, position.tick_lower, position.tick_upper, global1, lower.3, upper.3);
+        Ok((
+            position.tokens_owed0.saturating_add(fees_accrued(position.liquidity, inside0, position.fee_growth_inside0_last_x128)),
+            position.tokens_owed1.saturating_add(fees_accrued(position.liquidity, inside1, position.fee_growth_inside1_last_x128)),
+        ))
+    }
+
+    /// Swap volume of a pool over the last `window_blocks` blocks, in raw token units
+    pub async fn get_pool_volume(&self, chain_id: u64, pool: Address, window_blocks: u64) -> Result<PoolVolume> {
+        let chain_manager = self.chain_manager.clone();
+        self.volumes
+            .get_or_load((chain_id, pool, window_blocks), move || async move {
+                let provider = chain_manager.get_provider(chain_id).await?.provider.clone();
+                let to_block = provider.get_block_number().await?.as_u64();
+                let from_block = to_block.saturating_sub(window_blocks);
+                let (from, to) = futures::try_join!(provider.get_block(from_block), provider.get_block(to_block))?;
+                let (from, to) = from.zip(to).ok_or_else(|| anyhow!("Blocks {}-{} not found", from_block, to_block))?;
+
+                let filter = Filter::new()
+                    .address(pool)
+                    .topic0(swap_topic())
+                    .from_block(from_block)
+                    .to_block(to_block);
+                let logs = provider.get_logs(&filter).await?;
+
+                let mut volume = PoolVolume {
+                    from_block,
+                    to_block,
+                    elapsed_secs: to.timestamp.saturating_sub(from.timestamp).as_u64(),
+                    swaps: logs.len(),
+                    volume0: U256::zero(),
+                    volume1: U256::zero(),
+                };
+                for log in &logs {
+                    let tokens = abi::decode(&[ParamType::Int(256), ParamType::Int(256)], &log.data[..64.min(log.data.len())])?;
+                    let amount = |index: usize| tokens[index].clone().into_int().map(I256::from_raw).unwrap_or_default();
+                    // The positive amount is what the swapper paid in, the pool fee is taken from it
+                    let (amount0, amount1) = (amount(0), amount(1));
+                    if amount0.is_positive() {
+                        volume.volume0 = volume.volume0.saturating_add(amount0.into_raw());
+                    } else if amount1.is_positive() {
+                        volume.volume1 = volume.volume1.saturating_add(amount1.into_raw());
+                    }
+                }
+                Ok(volume)
+            })
+            .await
+    }
+
+    /// Price a position was opened at, solved from the amounts of its first deposit
+    async fn entry_price(
+        &self,
+        provider: &Provider<PooledHttp>,
+        position_manager: Address,
+        token_id: U256,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<Option<f64>> {
+        let mut token_topic = [0u8; 32];
+        token_id.to_big_endian(&mut token_topic);
+        let filter = Filter::new()
+            .address(position_manager)
+            .topic0(increase_liquidity_topic())
+            .topic1(H256::from(token_topic))
+            .from_block(BlockNumber::Earliest);
+        let logs = provider.get_logs(&filter).await?;
+        let Some(log) = logs.first() else {
+            return Ok(None);
+        };
+
+        let tokens = abi::decode(&[ParamType::Uint(128), ParamType::Uint(256), ParamType::Uint(256)], &log.data)?;
+        let amount = |index: usize| tokens[index].clone().into_uint().map(|amount| amount.as_u128() as f64).unwrap_or_default();
+        Ok(entry_price_from_deposit(amount(0), amount(1), amount(2), tick_lower, tick_upper))
+    }
+
     /// Calculate optimal tick range for liquidity provision
     pub async fn calculate_optimal_range(
         &self,
@@ -475,20 +735,51 @@ impl UniswapV3Manager {
             _ => (range_factor * 600.0) as i32,
         };
 
-        // Align to tick spacing
-        let tick_lower = ((current_tick - range_ticks) / tick_spacing) * tick_spacing;
-        let tick_upper = ((current_tick + range_ticks) / tick_spacing) * tick_spacing;
+        // Align to tick spacing, rounding outwards so negative ticks do not shift the range up
+        let tick_lower = (current_tick - range_ticks).div_euclid(tick_spacing) * tick_spacing;
+        let tick_upper = ((current_tick + range_ticks).div_euclid(tick_spacing) * tick_spacing).max(tick_lower + tick_spacing);
 
         info!("Calculated optimal range: {} to {} (current: {})", tick_lower, tick_upper, current_tick);
         Ok((tick_lower, tick_upper))
     }
 
+    /// Get the raw token1/token0 spot price of a pool at a given block
+    pub async fn get_pool_price_at_block(&self, chain_id: u64, pool: Address, block_number: u64) -> Result<f64> {
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let pool_abi = Self::get_pool_abi()?;
+        let pool_contract = Contract::new(pool, pool_abi, provider);
+
+        let slot0: (U256, i32, u16, u16, u16, u8, bool) = pool_contract
+            .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
+            .block(block_number)
+            .call()
+            .await?;
+
+        Ok(sqrt_price_x96_to_price(slot0.0))
+    }
+
     // Helper methods for getting pool address
     async fn get_pool_address(&self, chain_id: u64, token0: Address, token1: Address, fee: u32) -> Result<Address> {
         let contracts = self.contracts.get(&chain_id)
             .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+        self.pool_addresses
+            .get_or_load((chain_id, token0, token1, fee), || {
+                Self::fetch_pool_address(&self.chain_manager, contracts, chain_id, token0, token1, fee)
+            })
+            .await
+    }
 
-        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+    async fn fetch_pool_address(
+        chain_manager: &ChainManager,
+        contracts: &UniswapContracts,
+        chain_id: u64,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+    ) -> Result<Address> {
+        let chain_provider = chain_manager.get_provider(chain_id).await?;
         let provider = Arc::new(chain_provider.provider.clone());
 
         let factory_abi = Self::get_factory_abi()?;
@@ -565,6 +856,22 @@ impl UniswapV3Manager {
                 "outputs": [{"internalType": "uint256", "name": "", "type": "uint256"}],
                 "stateMutability": "view",
                 "type": "function"
+            },
+            {
+                "inputs": [{"internalType": "int24", "name": "tick", "type": "int24"}],
+                "name": "ticks",
+                "outputs": [
+                    {"internalType": "uint128", "name": "liquidityGross", "type": "uint128"},
+                    {"internalType": "int128", "name": "liquidityNet", "type": "int128"},
+                    {"internalType": "uint256", "name": "feeGrowthOutside0X128", "type": "uint256"},
+                    {"internalType": "uint256", "name": "feeGrowthOutside1X128", "type": "uint256"},
+                    {"internalType": "int56", "name": "tickCumulativeOutside", "type": "int56"},
+                    {"internalType": "uint160", "name": "secondsPerLiquidityOutsideX128", "type": "uint160"},
+                    {"internalType": "uint32", "name": "secondsOutside", "type": "uint32"},
+                    {"internalType": "bool", "name": "initialized", "type": "bool"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
             }
         ]"#;
         
@@ -713,6 +1020,28 @@ impl UniswapV3Manager {
                 ],
                 "stateMutability": "payable",
                 "type": "function"
+            },
+            {
+                "inputs": [
+                    {
+                        "components": [
+                            {"internalType": "uint256", "name": "tokenId", "type": "uint256"},
+                            {"internalType": "address", "name": "recipient", "type": "address"},
+                            {"internalType": "uint128", "name": "amount0Max", "type": "uint128"},
+                            {"internalType": "uint128", "name": "amount1Max", "type": "uint128"}
+                        ],
+                        "internalType": "struct INonfungiblePositionManager.CollectParams",
+                        "name": "params",
+                        "type": "tuple"
+                    }
+                ],
+                "name": "collect",
+                "outputs": [
+                    {"internalType": "uint256", "name": "amount0", "type": "uint256"},
+                    {"internalType": "uint256", "name": "amount1", "type": "uint256"}
+                ],
+                "stateMutability": "payable",
+                "type": "function"
             }
         ]"#;
         
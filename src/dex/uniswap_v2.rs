@@ -0,0 +1,339 @@
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::Abi,
+    contract::Contract,
+    types::{Address, U256, TransactionRequest},
+};
+use std::sync::Arc;
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::chains::ChainManager;
+use crate::contracts::probes::{ContractDeployment, ProtocolInterface};
+use crate::dex::aggregator::check_swap_bounds;
+
+/// Uniswap V2 contract addresses for different chains, the chain's main V2 fork where Uniswap
+/// itself is not the venue (PancakeSwap on BSC, Trader Joe on Avalanche)
+#[derive(Debug, Clone)]
+pub struct UniswapV2Contracts {
+    /// Protocol reported in the address registry
+    pub protocol: &'static str,
+    pub factory: Address,
+    pub router: Address,
+    /// Router naming the wrapped native token differently from `WETH()`
+    pub router_interface: ProtocolInterface,
+    /// Swap fee in basis points, 30 for Uniswap V2
+    pub fee_bps: u32,
+}
+
+impl UniswapV2Contracts {
+    pub fn for_chain(chain_id: u64) -> Self {
+        match chain_id {
+            1 => Self::ethereum_mainnet(),
+            137 => Self::polygon(),
+            42161 => Self::arbitrum(),
+            56 => Self::bsc(),
+            43114 => Self::avalanche(),
+            _ => Self::ethereum_mainnet(),
+        }
+    }
+
+    fn ethereum_mainnet() -> Self {
+        Self::uniswap(
+            "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f",
+            "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+        )
+    }
+
+    fn polygon() -> Self {
+        Self::uniswap(
+            "0x9e5A52f57b3038F1B8EeE45F28b3C1967e22799C",
+            "0xedf6066a2b290C185783862C7F4776A2C8077AD1",
+        )
+    }
+
+    fn arbitrum() -> Self {
+        Self::uniswap(
+            "0xf1D7CC64Fb4452F05c498126312eBE29f30Fbcf9",
+            "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24",
+        )
+    }
+
+    /// PancakeSwap V2, charging 0.25%
+    fn bsc() -> Self {
+        Self {
+            protocol: "pancakeswap_v2",
+            factory: "0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73".parse().unwrap(),
+            router: "0x10ED43C718714eb63d5aA57B78B54704E256024E".parse().unwrap(),
+            router_interface: ProtocolInterface::UniswapV2Router,
+            fee_bps: 25,
+        }
+    }
+
+    /// Trader Joe V1
+    fn avalanche() -> Self {
+        Self {
+            protocol: "trader_joe",
+            factory: "0x9Ad6C38BE94206cA50bb0d90783181662f0Cfa10".parse().unwrap(),
+            router: "0x60aE616a2155Ee3d9A68541Ba4544862310933d4".parse().unwrap(),
+            router_interface: ProtocolInterface::TraderJoeRouter,
+            fee_bps: 30,
+        }
+    }
+
+    fn uniswap(factory: &str, router: &str) -> Self {
+        Self {
+            protocol: "uniswap_v2",
+            factory: factory.parse().unwrap(),
+            router: router.parse().unwrap(),
+            router_interface: ProtocolInterface::UniswapV2Router,
+            fee_bps: 30,
+        }
+    }
+}
+
+/// Chain and the pair's tokens in address order
+type PairKey = (u64, Address, Address);
+
+/// Uniswap V2 pairs, where most long-tail tokens keep their only liquidity, and the V2 forks
+/// standing in for it on BSC and Avalanche
+pub struct UniswapV2Manager {
+    chain_manager: Arc<ChainManager>,
+    contracts: HashMap<u64, UniswapV2Contracts>,
+    /// Pair addresses never change once created, keyed by chain and sorted tokens
+    pairs_cache: Arc<tokio::sync::RwLock<HashMap<PairKey, Address>>>,
+}
+
+impl UniswapV2Manager {
+    pub async fn new(chain_manager: Arc<ChainManager>) -> Result<Self> {
+        info!("Initializing Uniswap V2 Manager");
+
+        let mut contracts = HashMap::new();
+        contracts.insert(1, UniswapV2Contracts::for_chain(1));
+        contracts.insert(137, UniswapV2Contracts::for_chain(137));
+        contracts.insert(42161, UniswapV2Contracts::for_chain(42161));
+        contracts.insert(56, UniswapV2Contracts::for_chain(56));
+        contracts.insert(43114, UniswapV2Contracts::for_chain(43114));
+
+        Ok(Self {
+            chain_manager,
+            contracts,
+            pairs_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+        })
+    }
+
+    pub async fn new_demo() -> Result<Self> {
+        info!("Creating UniswapV2Manager in demo mode");
+
+        let chain_manager = Arc::new(ChainManager::new_demo().await?);
+        let contracts = HashMap::new(); // Empty contracts for demo
+
+        Ok(Self {
+            chain_manager,
+            contracts,
+            pairs_cache: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Address-registry entries probed against the Uniswap V2 interfaces at startup
+    pub fn deployments(&self) -> Vec<ContractDeployment> {
+        self.contracts.iter()
+            .flat_map(|(&chain_id, contracts)| [
+                ContractDeployment::new(chain_id, contracts.protocol, "factory", contracts.factory, ProtocolInterface::UniswapV2Factory),
+                ContractDeployment::new(chain_id, contracts.protocol, "router", contracts.router, contracts.router_interface),
+            ])
+            .collect()
+    }
+
+    /// Drop cached pair addresses
+    pub async fn clear_cache(&self) {
+        self.pairs_cache.write().await.clear();
+    }
+
+    /// Pair of two tokens, an error when the factory never created one
+    pub async fn get_pair(&self, chain_id: u64, token_a: Address, token_b: Address) -> Result<Address> {
+        let (token0, token1) = sort_tokens(token_a, token_b);
+        if let Some(pair) = self.pairs_cache.read().await.get(&(chain_id, token0, token1)) {
+            return Ok(*pair);
+        }
+
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let factory = Contract::new(contracts.factory, Self::get_factory_abi()?, provider);
+        let pair: Address = factory
+            .method::<_, Address>("getPair", (token0, token1))?
+            .call()
+            .await?;
+
+        if pair == Address::zero() {
+            return Err(anyhow!("No Uniswap V2 pair for {:?}/{:?}", token_a, token_b));
+        }
+
+        self.pairs_cache.write().await.insert((chain_id, token0, token1), pair);
+        Ok(pair)
+    }
+
+    /// Reserves of the pair ordered as (`token_in`, `token_out`)
+    pub async fn get_reserves(&self, chain_id: u64, token_in: Address, token_out: Address) -> Result<(U256, U256)> {
+        let pair = self.get_pair(chain_id, token_in, token_out).await?;
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let pair_contract = Contract::new(pair, Self::get_pair_abi()?, provider);
+        let (reserve0, reserve1, _): (U256, U256, u32) = pair_contract
+            .method::<_, (U256, U256, u32)>("getReserves", ())?
+            .call()
+            .await?;
+
+        // token0 is always the lower address
+        Ok(if token_in < token_out { (reserve0, reserve1) } else { (reserve1, reserve0) })
+    }
+
+    /// Output of swapping `amount_in` through the pair and the trade's price impact in percent
+    pub async fn quote_exact_input(
+        &self,
+        chain_id: u64,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<(U256, f64)> {
+        let (reserve_in, reserve_out) = self.get_reserves(chain_id, token_in, token_out).await?;
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(anyhow!("Uniswap V2 pair for {:?}/{:?} has no liquidity", token_in, token_out));
+        }
+
+        let fee_bps = self.contracts.get(&chain_id).map_or(30, |contracts| contracts.fee_bps);
+        let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, fee_bps);
+        // On a constant product curve the execution price falls short of the mid price by a / (R + a), before fees
+        let amount = amount_in.low_u128() as f64;
+        let price_impact = amount / (reserve_in.low_u128() as f64 + amount) * 100.0;
+
+        Ok((amount_out, price_impact))
+    }
+
+    /// Swap exact tokens for tokens
+    pub async fn swap_exact_tokens_for_tokens(
+        &self,
+        chain_id: u64,
+        amount_in: U256,
+        amount_out_min: U256,
+        path: Vec<Address>,
+        to: Address,
+        deadline: u64,
+    ) -> Result<TransactionRequest> {
+        info!("Creating Uniswap V2 swap transaction for {} tokens", amount_in);
+        check_swap_bounds(amount_out_min, deadline)?;
+
+        let contracts = self.contracts.get(&chain_id)
+            .ok_or_else(|| anyhow!("Chain {} not supported", chain_id))?;
+
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let router = Contract::new(contracts.router, Self::get_router_abi()?, provider);
+        let call = router.method::<_, Vec<U256>>(
+            "swapExactTokensForTokens",
+            (amount_in, amount_out_min, path, to, deadline),
+        )?;
+
+        let tx = TransactionRequest::new()
+            .to(contracts.router)
+            .data(call.calldata().unwrap_or_default());
+
+        Ok(tx)
+    }
+
+    /// Get the raw reserve1/reserve0 spot price of a pair at a given block
+    pub async fn get_pair_price_at_block(&self, chain_id: u64, pair: Address, block_number: u64) -> Result<f64> {
+        let chain_provider = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain_provider.provider.clone());
+
+        let pair_contract = Contract::new(pair, Self::get_pair_abi()?, provider);
+        let reserves: (U256, U256, u32) = pair_contract
+            .method::<_, (U256, U256, u32)>("getReserves", ())?
+            .block(block_number)
+            .call()
+            .await?;
+
+        if reserves.0.is_zero() {
+            return Err(anyhow!("Pair has no liquidity at block {}", block_number));
+        }
+
+        Ok(reserves.1.as_u128() as f64 / reserves.0.as_u128() as f64)
+    }
+
+    // ABI helper methods
+    fn get_factory_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "address", "name": "tokenA", "type": "address"},
+                    {"internalType": "address", "name": "tokenB", "type": "address"}
+                ],
+                "name": "getPair",
+                "outputs": [{"internalType": "address", "name": "pair", "type": "address"}],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+
+    fn get_pair_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [],
+                "name": "getReserves",
+                "outputs": [
+                    {"internalType": "uint112", "name": "reserve0", "type": "uint112"},
+                    {"internalType": "uint112", "name": "reserve1", "type": "uint112"},
+                    {"internalType": "uint32", "name": "blockTimestampLast", "type": "uint32"}
+                ],
+                "stateMutability": "view",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+
+    fn get_router_abi() -> Result<Abi> {
+        let abi_json = r#"[
+            {
+                "inputs": [
+                    {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
+                    {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
+                    {"internalType": "address[]", "name": "path", "type": "address[]"},
+                    {"internalType": "address", "name": "to", "type": "address"},
+                    {"internalType": "uint256", "name": "deadline", "type": "uint256"}
+                ],
+                "name": "swapExactTokensForTokens",
+                "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
+                "stateMutability": "nonpayable",
+                "type": "function"
+            }
+        ]"#;
+
+        Ok(serde_json::from_str(abi_json)?)
+    }
+}
+
+fn sort_tokens(token_a: Address, token_b: Address) -> (Address, Address) {
+    if token_a < token_b { (token_a, token_b) } else { (token_b, token_a) }
+}
+
+/// `UniswapV2Library.getAmountOut`, charging the pair's fee on the input
+pub fn get_amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256, fee_bps: u32) -> U256 {
+    let amount_in_with_fee = amount_in * U256::from(10_000 - fee_bps.min(10_000));
+    let denominator = reserve_in * U256::from(10_000) + amount_in_with_fee;
+    if denominator.is_zero() {
+        return U256::zero();
+    }
+    amount_in_with_fee * reserve_out / denominator
+}
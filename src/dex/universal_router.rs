@@ -0,0 +1,96 @@
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::{self, Token},
+    types::{Address, Bytes, Signature, TransactionRequest, U256},
+    utils::id,
+};
+
+use super::aggregator::{DexType, Quote};
+use crate::contracts::permit2::Permit2Permit;
+
+// Universal Router commands used here, see the router's Commands library
+const V3_SWAP_EXACT_IN: u8 = 0x00;
+const PERMIT2_PERMIT_BATCH: u8 = 0x03;
+const V2_SWAP_EXACT_IN: u8 = 0x08;
+const PERMIT2_PERMIT: u8 = 0x0a;
+
+/// Uniswap Universal Router, which pulls swap inputs through Permit2
+pub fn router_address(chain_id: u64) -> Result<Address> {
+    let address = match chain_id {
+        1 => "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD",
+        137 => "0xec7BE89e9d109e7e3Fec59c222CF297125FEFda2",
+        42161 => "0x5E325eDA8064b456f4781070C0738d849c824258",
+        _ => return Err(anyhow!("No Universal Router on chain {}", chain_id)),
+    };
+    Ok(address.parse()?)
+}
+
+/// Commands executed in one Universal Router call
+#[derive(Debug, Default)]
+pub struct UniversalRouterCall {
+    commands: Vec<u8>,
+    inputs: Vec<Bytes>,
+}
+
+impl UniversalRouterCall {
+    /// Grant the router the permit's Permit2 allowances before the swaps pull from them
+    pub fn permit(mut self, permit: &Permit2Permit, signature: &Signature) -> Self {
+        let command = if permit.is_batch() { PERMIT2_PERMIT_BATCH } else { PERMIT2_PERMIT };
+        self.commands.push(command);
+        self.inputs.push(abi::encode(&[permit.to_token(), Token::Bytes(signature.to_vec())]).into());
+        self
+    }
+
+    /// Swap a quote's exact input, paid by the caller through Permit2
+    pub fn swap_exact_in(mut self, quote: &Quote, min_amount_out: U256, recipient: Address) -> Result<Self> {
+        let [token_in, token_out] = quote.path[..] else {
+            return Err(anyhow!("Universal Router swaps take a single-hop path"));
+        };
+        if min_amount_out.is_zero() {
+            return Err(anyhow!("Swap without a minimum output, quote it first"));
+        }
+        let (command, path) = match quote.dex {
+            DexType::UniswapV3 => {
+                let fee = quote.fee_tier.ok_or_else(|| anyhow!("Uniswap V3 quote without a fee tier"))?;
+                // Packed path: token in, 3-byte pool fee, token out
+                let mut path = token_in.as_bytes().to_vec();
+                path.extend_from_slice(&fee.to_be_bytes()[1..]);
+                path.extend_from_slice(token_out.as_bytes());
+                (V3_SWAP_EXACT_IN, Token::Bytes(path))
+            }
+            DexType::UniswapV2 => (
+                V2_SWAP_EXACT_IN,
+                Token::Array(vec![Token::Address(token_in), Token::Address(token_out)]),
+            ),
+            DexType::SushiSwap => return Err(anyhow!("SushiSwap is not routed through the Universal Router")),
+        };
+
+        self.commands.push(command);
+        self.inputs.push(abi::encode(&[
+            Token::Address(recipient),
+            Token::Uint(quote.input_amount),
+            Token::Uint(min_amount_out),
+            path,
+            Token::Bool(true),
+        ]).into());
+        Ok(self)
+    }
+
+    /// `execute(commands, inputs, deadline)` transaction to the chain's router
+    pub fn into_transaction(self, chain_id: u64, deadline: u64) -> Result<TransactionRequest> {
+        if self.commands.is_empty() {
+            return Err(anyhow!("Universal Router call without commands"));
+        }
+        let mut data = id("execute(bytes,bytes[],uint256)").to_vec();
+        data.extend(abi::encode(&[
+            Token::Bytes(self.commands),
+            Token::Array(self.inputs.into_iter().map(|input| Token::Bytes(input.to_vec())).collect()),
+            Token::Uint(U256::from(deadline)),
+        ]));
+
+        Ok(TransactionRequest::new()
+            .to(router_address(chain_id)?)
+            .data(data)
+            .chain_id(chain_id))
+    }
+}
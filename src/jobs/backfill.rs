@@ -0,0 +1,332 @@
+// Chunked, checkpointed processing of historical block ranges
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::providers::Provider;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::{JobContext, JobManager, JobRecord, JobStatus, JobTask};
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `backfill_checkpoint_path` is not configured
+const DEFAULT_CHECKPOINT_PATH: &str = "data/backfill_checkpoints.json";
+const DEFAULT_REQUESTS_PER_SECOND: i64 = 4;
+const DEFAULT_CHUNK_SIZE: u64 = 2_000;
+/// Consecutive failures that cannot be helped by a smaller chunk before the backfill gives up
+const MAX_CHUNK_FAILURES: u32 = 5;
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Consumer of historical blocks, e.g. an event indexer
+#[async_trait]
+pub trait BackfillProcessor: Send + Sync {
+    /// Name backfills of this processor are requested under
+    fn name(&self) -> &'static str;
+
+    /// Process the inclusive block range and return the number of items stored. Ranges are
+    /// handed out in order and retried with smaller chunks on failure, so processing must be
+    /// idempotent.
+    async fn process(&self, chain_id: u64, provider: &Provider<PooledHttp>, from_block: u64, to_block: u64) -> Result<u64>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackfillStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Resumable position of a backfill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillCheckpoint {
+    pub id: String,
+    pub processor: String,
+    pub chain_id: u64,
+    pub from_block: u64,
+    pub to_block: u64,
+    /// First block not processed yet
+    pub next_block: u64,
+    /// Current chunk size, shrunk while the provider rejects ranges
+    pub chunk_size: u64,
+    pub items_processed: u64,
+    pub status: BackfillStatus,
+    pub job_id: Option<String>,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl BackfillCheckpoint {
+    fn total_blocks(&self) -> u64 {
+        self.to_block - self.from_block + 1
+    }
+
+    fn completed_blocks(&self) -> u64 {
+        self.next_block - self.from_block
+    }
+}
+
+/// Historical range to process
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackfillRequest {
+    pub processor: String,
+    pub chain_id: u64,
+    pub from_block: u64,
+    /// Latest block when omitted
+    pub to_block: Option<u64>,
+    pub chunk_size: Option<u64>,
+}
+
+/// Runs backfills as jobs, one chunk at a time, persisting a checkpoint after each chunk
+pub struct BackfillOrchestrator {
+    chain_manager: Arc<ChainManager>,
+    jobs: Arc<JobManager>,
+    processors: HashMap<&'static str, Arc<dyn BackfillProcessor>>,
+    /// JSON file holding the checkpoints, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    checkpoints: RwLock<HashMap<String, BackfillCheckpoint>>,
+    /// Minimum spacing of RPC requests per chain, shared by all backfills on the chain
+    request_interval: Duration,
+    last_request: Mutex<HashMap<u64, Instant>>,
+}
+
+impl BackfillOrchestrator {
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        jobs: Arc<JobManager>,
+        processors: Vec<Arc<dyn BackfillProcessor>>,
+        store_path: Option<PathBuf>,
+        requests_per_second: u32,
+    ) -> Result<Self> {
+        let checkpoints: Vec<BackfillCheckpoint> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            chain_manager,
+            jobs,
+            processors: processors.into_iter().map(|processor| (processor.name(), processor)).collect(),
+            store_path,
+            checkpoints: RwLock::new(checkpoints.into_iter().map(|checkpoint| (checkpoint.id.clone(), checkpoint)).collect()),
+            request_interval: Duration::from_secs(1) / requests_per_second.max(1),
+            last_request: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Orchestrator persisting to `backfill_checkpoint_path` (empty keeps checkpoints in memory),
+    /// limited to `backfill_requests_per_second` per chain
+    pub async fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        jobs: Arc<JobManager>,
+        processors: Vec<Arc<dyn BackfillProcessor>>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("backfill_checkpoint_path")
+            .unwrap_or_else(|_| DEFAULT_CHECKPOINT_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        let requests_per_second = config
+            .get_int("backfill_requests_per_second")
+            .unwrap_or(DEFAULT_REQUESTS_PER_SECOND)
+            .max(1) as u32;
+        Self::new(chain_manager, jobs, processors, store_path, requests_per_second).await
+    }
+
+    /// Resume backfills that were running when the process stopped
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interrupted: Vec<String> = self.checkpoints.read().await.values()
+                .filter(|checkpoint| checkpoint.status == BackfillStatus::Running)
+                .map(|checkpoint| checkpoint.id.clone())
+                .collect();
+            for id in interrupted {
+                info!("Resuming interrupted backfill {}", id);
+                if let Err(e) = self.spawn_job(&id).await {
+                    warn!("Failed to resume backfill {}: {}", id, e);
+                }
+            }
+        })
+    }
+
+    /// Start a backfill, continuing from its checkpoint when the same range ran before
+    pub async fn submit(self: &Arc<Self>, request: BackfillRequest) -> Result<JobRecord> {
+        if !self.processors.contains_key(request.processor.as_str()) {
+            return Err(anyhow!("Unknown backfill processor {}", request.processor));
+        }
+        let to_block = match request.to_block {
+            Some(block) => block,
+            None => self.chain_manager.get_block_number(request.chain_id).await?,
+        };
+        if request.from_block > to_block {
+            return Err(anyhow!("Block range {}-{} is empty", request.from_block, to_block));
+        }
+
+        let id = format!("{}:{}:{}-{}", request.processor, request.chain_id, request.from_block, to_block);
+        {
+            let mut checkpoints = self.checkpoints.write().await;
+            let checkpoint = checkpoints.entry(id.clone()).or_insert_with(|| BackfillCheckpoint {
+                id: id.clone(),
+                processor: request.processor.clone(),
+                chain_id: request.chain_id,
+                from_block: request.from_block,
+                to_block,
+                next_block: request.from_block,
+                chunk_size: DEFAULT_CHUNK_SIZE,
+                items_processed: 0,
+                status: BackfillStatus::Running,
+                job_id: None,
+                last_error: None,
+                updated_at: Utc::now(),
+            });
+            if checkpoint.status == BackfillStatus::Completed {
+                return Err(anyhow!("Backfill {} already completed", id));
+            }
+            if let Some(job_id) = &checkpoint.job_id {
+                if let Some(job) = self.jobs.get_job(job_id).await.filter(|job| job.status == JobStatus::Running) {
+                    return Ok(job);
+                }
+            }
+            if let Some(chunk_size) = request.chunk_size {
+                checkpoint.chunk_size = chunk_size.max(1);
+            }
+            checkpoint.status = BackfillStatus::Running;
+        }
+
+        self.spawn_job(&id).await
+    }
+
+    /// Checkpoints of every backfill, most recently updated first
+    pub async fn list(&self) -> Vec<BackfillCheckpoint> {
+        let mut checkpoints: Vec<BackfillCheckpoint> = self.checkpoints.read().await.values().cloned().collect();
+        checkpoints.sort_by_key(|checkpoint| std::cmp::Reverse(checkpoint.updated_at));
+        checkpoints
+    }
+
+    async fn spawn_job(self: &Arc<Self>, id: &str) -> Result<JobRecord> {
+        let orchestrator = Arc::clone(self);
+        let backfill_id = id.to_string();
+        let task: JobTask = Arc::new(move |context| {
+            let orchestrator = Arc::clone(&orchestrator);
+            let backfill_id = backfill_id.clone();
+            async move { orchestrator.run(&backfill_id, context).await }.boxed()
+        });
+
+        let job = self.jobs.submit(&format!("backfill:{}", id), task).await;
+        let mut checkpoints = self.checkpoints.write().await;
+        if let Some(checkpoint) = checkpoints.get_mut(id) {
+            checkpoint.job_id = Some(job.id.clone());
+        }
+        self.persist(&checkpoints).await;
+        Ok(job)
+    }
+
+    /// Work through the remaining range from the checkpoint
+    async fn run(&self, id: &str, context: JobContext) -> Result<()> {
+        let mut checkpoint = self.checkpoints.read().await.get(id).cloned()
+            .ok_or_else(|| anyhow!("Unknown backfill {}", id))?;
+        let processor = self.processors.get(checkpoint.processor.as_str()).cloned()
+            .ok_or_else(|| anyhow!("Unknown backfill processor {}", checkpoint.processor))?;
+        // Re-runs of a failed job pick up where it stopped
+        checkpoint.status = BackfillStatus::Running;
+        checkpoint.job_id = Some(context.id().to_string());
+
+        let mut failures = 0;
+        let mut backoff = RETRY_BACKOFF;
+        while checkpoint.next_block <= checkpoint.to_block {
+            let from = checkpoint.next_block;
+            let to = (from + checkpoint.chunk_size - 1).min(checkpoint.to_block);
+
+            self.throttle(checkpoint.chain_id).await;
+            let processed = match self.chain_manager.get_provider(checkpoint.chain_id).await {
+                Ok(chain) => processor.process(checkpoint.chain_id, &chain.provider, from, to).await
+                    .map_err(|e| (e, true)),
+                Err(e) => Err((e, false)),
+            };
+
+            match processed {
+                Ok(items) => {
+                    failures = 0;
+                    backoff = RETRY_BACKOFF;
+                    checkpoint.next_block = to + 1;
+                    checkpoint.items_processed += items;
+                    checkpoint.last_error = None;
+                }
+                Err((e, range_rejected)) => {
+                    warn!("Backfill {} failed on blocks {}-{}: {}", id, from, to, e);
+                    checkpoint.last_error = Some(e.to_string());
+                    // Providers cap log ranges and results, so shrink the chunk before retrying;
+                    // an unreachable chain is retried as is
+                    if range_rejected && checkpoint.chunk_size > 1 {
+                        checkpoint.chunk_size = (checkpoint.chunk_size / 2).max(1);
+                    } else {
+                        failures += 1;
+                    }
+                    if failures >= MAX_CHUNK_FAILURES {
+                        checkpoint.status = BackfillStatus::Failed;
+                        self.save(&checkpoint).await;
+                        return Err(anyhow!("Backfill {} stopped at block {}: {}", id, from, e));
+                    }
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+                }
+            }
+
+            self.save(&checkpoint).await;
+            context.report_progress(
+                checkpoint.completed_blocks(),
+                checkpoint.total_blocks(),
+                Some(format!("next block {}, {} items", checkpoint.next_block, checkpoint.items_processed)),
+            ).await;
+        }
+
+        checkpoint.status = BackfillStatus::Completed;
+        self.save(&checkpoint).await;
+        info!("Backfill {} completed with {} items", id, checkpoint.items_processed);
+        Ok(())
+    }
+
+    /// Wait until the chain's request budget allows another chunk
+    async fn throttle(&self, chain_id: u64) {
+        let wait_until = {
+            let mut last_request = self.last_request.lock().await;
+            let now = Instant::now();
+            let slot = last_request.get(&chain_id)
+                .map(|previous| (*previous + self.request_interval).max(now))
+                .unwrap_or(now);
+            last_request.insert(chain_id, slot);
+            slot
+        };
+        tokio::time::sleep_until(wait_until).await;
+    }
+
+    async fn save(&self, checkpoint: &BackfillCheckpoint) {
+        let mut checkpoint = checkpoint.clone();
+        checkpoint.updated_at = Utc::now();
+        let mut checkpoints = self.checkpoints.write().await;
+        checkpoints.insert(checkpoint.id.clone(), checkpoint);
+        self.persist(&checkpoints).await;
+    }
+
+    /// Write the store while the caller holds the lock, so concurrent backfills never interleave writes
+    async fn persist(&self, checkpoints: &HashMap<String, BackfillCheckpoint>) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let checkpoints: Vec<&BackfillCheckpoint> = checkpoints.values().collect();
+        if let Err(e) = write_store(path, &checkpoints).await {
+            warn!("Failed to persist backfill checkpoints to {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// Background job tracking
+pub mod backfill;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, error};
+use uuid::Uuid;
+
+use crate::shutdown::TaskSet;
+
+/// Background job status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Background job record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub name: String,
+    pub status: JobStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Last progress reported by the task, kept across re-runs
+    pub progress: Option<JobProgress>,
+}
+
+/// Progress of a long-running job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub completed: u64,
+    pub total: u64,
+    pub message: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Handle given to a running task for reporting its progress
+#[derive(Clone)]
+pub struct JobContext {
+    id: String,
+    jobs: Arc<RwLock<HashMap<String, JobRecord>>>,
+}
+
+impl JobContext {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub async fn report_progress(&self, completed: u64, total: u64, message: Option<String>) {
+        if let Some(job) = self.jobs.write().await.get_mut(&self.id) {
+            job.progress = Some(JobProgress {
+                completed,
+                total,
+                message,
+                updated_at: Utc::now(),
+            });
+        }
+    }
+}
+
+/// Re-runnable unit of background work
+pub type JobTask = Arc<dyn Fn(JobContext) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Runs background jobs and keeps their history so failed ones can be retried
+pub struct JobManager {
+    jobs: Arc<RwLock<HashMap<String, JobRecord>>>,
+    tasks: Arc<RwLock<HashMap<String, JobTask>>>,
+    /// Runs in flight, for shutdown to wait for
+    runs: TaskSet,
+}
+
+impl JobManager {
+    pub async fn new() -> Result<Self> {
+        info!("Initializing JobManager");
+
+        Ok(Self {
+            jobs: Arc::new(RwLock::new(HashMap::new())),
+            tasks: Arc::new(RwLock::new(HashMap::new())),
+            runs: TaskSet::default(),
+        })
+    }
+
+    /// Submit a job and run it in the background
+    pub async fn submit(&self, name: &str, task: JobTask) -> JobRecord {
+        let mut record = JobRecord {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            status: JobStatus::Running,
+            attempts: 0,
+            last_error: None,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            progress: None,
+        };
+        Self::start(&mut record);
+
+        self.jobs.write().await.insert(record.id.clone(), record.clone());
+        self.tasks.write().await.insert(record.id.clone(), task.clone());
+        self.spawn(record.id.clone(), task);
+
+        info!("Submitted job {} ({})", record.name, record.id);
+        record
+    }
+
+    /// Re-run a failed job with its original task
+    pub async fn rerun(&self, id: &str) -> Result<JobRecord> {
+        let task = self.tasks.read().await.get(id).cloned();
+        let (record, task) = {
+            let mut jobs = self.jobs.write().await;
+            let job = jobs.get_mut(id).ok_or_else(|| anyhow!("Job {} not found", id))?;
+            if job.status != JobStatus::Failed {
+                return Err(anyhow!("Job {} is {:?}, only failed jobs can be re-run", id, job.status));
+            }
+            let task = task.ok_or_else(|| anyhow!("Job {} has no task registered", id))?;
+
+            // Marked running before the lock is released, so a concurrent re-run is refused
+            Self::start(job);
+            (job.clone(), task)
+        };
+
+        self.spawn(id.to_string(), task);
+        Ok(record)
+    }
+
+    /// Job runs in flight, for shutdown to wait for
+    pub fn runs(&self) -> TaskSet {
+        self.runs.clone()
+    }
+
+    pub async fn get_job(&self, id: &str) -> Option<JobRecord> {
+        self.jobs.read().await.get(id).cloned()
+    }
+
+    /// List jobs, most recent first
+    pub async fn list_jobs(&self) -> Vec<JobRecord> {
+        let mut jobs: Vec<JobRecord> = self.jobs.read().await.values().cloned().collect();
+        jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        jobs
+    }
+
+    /// Mark a job as running another attempt
+    fn start(job: &mut JobRecord) {
+        job.status = JobStatus::Running;
+        job.attempts += 1;
+        job.started_at = Some(Utc::now());
+        job.finished_at = None;
+    }
+
+    /// Run the task of a job already marked as running, recording how it ends
+    fn spawn(&self, id: String, task: JobTask) {
+        let jobs = self.jobs.clone();
+        let context = JobContext {
+            id: id.clone(),
+            jobs: jobs.clone(),
+        };
+        self.runs.spawn(async move {
+            // Run in a nested task so a panicking job is recorded as failed
+            let result = match tokio::spawn(task(context)).await {
+                Ok(result) => result,
+                Err(e) => Err(anyhow!("Job panicked: {}", e)),
+            };
+
+            let mut jobs = jobs.write().await;
+            if let Some(job) = jobs.get_mut(&id) {
+                job.finished_at = Some(Utc::now());
+                match result {
+                    Ok(()) => {
+                        job.status = JobStatus::Succeeded;
+                        job.last_error = None;
+                        info!("Job {} ({}) succeeded", job.name, id);
+                    }
+                    Err(e) => {
+                        job.status = JobStatus::Failed;
+                        job.last_error = Some(e.to_string());
+                        error!("Job {} ({}) failed: {}", job.name, id, e);
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,59 @@
+// Tracing setup with a log level per subsystem
+//
+// Spans opened by the subsystems name their context fields the same way so logs can be
+// filtered across them: `chain_id`, `wallet` (the user or signer address) and `strategy_id`.
+use tracing::warn;
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+const DEFAULT_LOG_LEVEL: &str = "info";
+/// Modules whose level can be set on its own through `log_level_<subsystem>`
+const SUBSYSTEMS: [&str; 4] = ["dex", "defi", "security", "chains"];
+
+/// Install the global subscriber.
+///
+/// `RUST_LOG` takes over entirely when set; otherwise the app logs at `log_level` and each
+/// subsystem at its `log_level_<subsystem>` when configured, e.g. `log_level_dex=debug`
+/// turns up DEX debugging while security stays at the app level.
+pub fn init(config: &config::Config) {
+    let mut invalid = Vec::new();
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::new(directives(config, &mut invalid)),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    // Reported once the subscriber is installed so the warnings are not lost
+    for (key, value) in invalid {
+        warn!("Ignoring invalid {} {:?}, expected off, error, warn, info, debug or trace", key, value);
+    }
+}
+
+/// Filter directives from the configured levels, collecting the keys with invalid values
+fn directives(config: &config::Config, invalid: &mut Vec<(String, String)>) -> String {
+    let mut level = |key: String| -> Option<String> {
+        let value = config.get_string(&key).ok().filter(|value| !value.is_empty())?;
+        match value.parse::<LevelFilter>() {
+            Ok(level) => Some(level.to_string().to_lowercase()),
+            Err(_) => {
+                invalid.push((key, value));
+                None
+            }
+        }
+    };
+
+    let app_level = level("log_level".to_string()).unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string());
+    let mut directives = vec![
+        format!("blockchain_demo={}", app_level),
+        format!("tower_http={}", app_level),
+    ];
+    for subsystem in SUBSYSTEMS {
+        if let Some(subsystem_level) = level(format!("log_level_{}", subsystem)) {
+            directives.push(format!("blockchain_demo::{}={}", subsystem, subsystem_level));
+        }
+    }
+    directives.join(",")
+}
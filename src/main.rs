@@ -1,32 +1,42 @@
 use anyhow::Result;
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{HeaderValue, StatusCode},
     response::{Json, Redirect},
     routing::{get, post},
     Router,
 };
 use serde_json::{json, Value};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tower_http::cors::CorsLayer;
-use tracing::{info, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::{error, info, warn};
 use utoipa::{OpenApi, openapi::OpenApiVersion};
 use utoipa_swagger_ui::SwaggerUi;
 
 mod api;
 mod analytics;
 mod app_config;
+mod cache;
 mod chains;
 mod contracts;
 mod defi;
+mod demo;
 mod dex;
+mod jobs;
+mod logging;
+mod monitor;
 mod security;
+mod settings;
+mod shutdown;
+mod transactions;
 mod wallets;
 // mod websocket; // Temporarily disabled due to compilation issues
 
 use crate::api::ApiState;
+use crate::settings::ConfigWatcher;
+use crate::shutdown::Shutdown;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -39,6 +49,8 @@ use crate::api::ApiState;
         api::models::HealthResponse,
         api::models::Portfolio,
         api::models::SwapQuote,
+        api::models::TokenAmount,
+        api::error::ProblemDetails,
     )),
     tags(
         (name = "health", description = "Health check endpoints"),
@@ -66,23 +78,88 @@ struct ApiDoc;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Load configuration, it sets the log levels
+    let config_file = settings::config_file();
+    let config = settings::load(config_file.as_deref())?;
+
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "blockchain_demo=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    logging::init(&config);
 
     info!("Starting Blockchain Demo application...");
+    if let Some(file) = &config_file {
+        info!("Loaded configuration from {}", file.display());
+    }
+
+    let cors = cors_layer(&config);
+    let mut shutdown = Shutdown::from_config(&config);
+    let config_watcher = ConfigWatcher::new(&config, config_file);
 
-    // Load configuration
-    let config = load_config().await?;
-    
     // Initialize application state
     let state = Arc::new(ApiState::new(config).await?);
 
+    // Start background position monitoring
+    shutdown.track("Position monitor", Arc::clone(&state.monitor).start(shutdown.signal()));
+
+    // Re-quote cross-DEX round trips for the arbitrage feed
+    shutdown.track("Arbitrage engine", Arc::clone(&state.arbitrage).start(shutdown.signal()));
+
+    // Record the value, allocation and health factors of tracked wallets
+    shutdown.track("Portfolio snapshotter", Arc::clone(&state.snapshotter).start(shutdown.signal()));
+
+    // Recenter managed Uniswap V3 positions drifting out of range
+    shutdown.track("Auto-range manager", Arc::clone(&state.auto_range).start(shutdown.signal()));
+
+    // Stream pending transactions into MEV detection when enabled
+    shutdown.track("Mempool watcher", Arc::clone(&state.mempool).start(shutdown.signal()));
+
+    // Check the configured protocol addresses expose the interfaces they are used as
+    shutdown.track("Deployment prober", Arc::clone(&state.deployments).start(shutdown.signal()));
+
+    // Index the events of registered contracts as blocks arrive
+    shutdown.track("Event indexer", Arc::clone(&state.indexer).start(shutdown.signal()));
+
+    // Follow bridge transfers until relayers fill them
+    shutdown.track("Bridge tracker", Arc::clone(&state.bridge).start(shutdown.signal()));
+
+    // Load the configured token lists and keep them current
+    shutdown.track("Token list refresher", Arc::clone(&state.tokens).start(shutdown.signal()));
+
+    // Load the sanctions lists counterparties are screened against and keep them current
+    shutdown.track("Sanctions list refresher", Arc::clone(&state.sanctions).start(shutdown.signal()));
+
+    // Sample prices and pair liquidity for the risk engine's volatility, depth and correlations
+    shutdown.track("Market data collector", state.security.advanced.risk_engine().start(shutdown.signal()));
+
+    // Detect chain reorganizations and invalidate data read from orphaned blocks
+    shutdown.track("Reorg monitor", Arc::clone(&state.reorgs).start(shutdown.signal()));
+
+    // Resume backfills interrupted by the last shutdown
+    shutdown.track("Backfill resumer", Arc::clone(&state.backfills).start());
+    // Let job runs, backfills included, and notification deliveries in flight finish
+    shutdown.track_set("Background jobs", state.jobs.runs());
+    shutdown.track_set("Notification deliveries", state.security.advanced.notifications().deliveries());
+
+    // Execute TWAP slices and limit orders as they come due
+    shutdown.track("Order engine", Arc::clone(&state.orders).start(shutdown.signal()));
+    // Advance yield strategy executions one confirmed step at a time
+    shutdown.track("Strategy executor", Arc::clone(&state.strategy_executions).start(shutdown.signal()));
+    // Submit reward claims, swaps and supplies of due compounding schedules
+    shutdown.track("Reward compounder", Arc::clone(&state.reward_compounder).start(shutdown.signal()));
+    // Measure settled swap outputs against their quotes for venue selection
+    shutdown.track("Quote accuracy monitor", Arc::clone(&state.quote_accuracy).start(shutdown.signal()));
+    // Sample lending rates for the trailing averages yields are ranked by
+    shutdown.track("Lending rate collector", Arc::clone(&state.rate_collector).start(shutdown.signal()));
+
+    // Restore WalletConnect sessions and listen for wallets approving new pairings
+    if let Some(task) = Arc::clone(&state.wallet_manager).start(shutdown.signal()) {
+        shutdown.track("WalletConnect listener", task);
+    }
+
+    // Apply changed alert thresholds and rate limits from the config file
+    if let Some(watcher) = config_watcher {
+        shutdown.track("Config watcher", watcher.start(state.clone(), shutdown.signal()));
+    }
+
     // Start real-time updates
     // WebSocket support temporarily disabled
     // websocket::start_real_time_updates(Arc::clone(&state.websocket)).await;
@@ -91,19 +168,44 @@ async fn main() -> Result<()> {
     let app = Router::new()
         .route("/", get(root_handler))
         // .route("/ws", get(websocket::websocket_handler)) // WebSocket disabled
-        .nest("/api/v1", api::routes())
+        .nest("/api/v1", api::routes(state.clone()))
         .nest("/docs", api::docs::routes())
         .route("/docs/openapi.json", get(openapi_spec_handler))
         .route("/swagger-ui", get(swagger_ui_redirect))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+        .layer(cors)
+        .with_state(state.clone());
 
     // Start the server
     let listener = TcpListener::bind("0.0.0.0:3000").await?;
     info!("Server running on http://0.0.0.0:3000");
     info!("Swagger UI available at http://0.0.0.0:3000/swagger-ui");
 
-    axum::serve(listener, app).await?;
+    // Stop accepting connections on shutdown, requests in flight are drained with the background tasks
+    let mut server_shutdown = shutdown.signal();
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move { server_shutdown.cancelled().await });
+    let mut server = tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Server failed: {}", e);
+        }
+    });
+
+    tokio::select! {
+        _ = shutdown::wait_for_signal() => shutdown.track("HTTP server", server),
+        _ = &mut server => warn!("Server stopped unexpectedly, shutting down"),
+    }
+    shutdown.drain().await;
+
+    // Stop the anvil node the API spawned, nothing sends to it anymore
+    if let Some(fork) = state.chain_manager.fork() {
+        fork.shutdown().await;
+    }
+
+    // Nothing writes to the audit trail anymore
+    if let Err(e) = state.security.shutdown().await {
+        warn!("Failed to close the audit trail: {}", e);
+    }
+    info!("Shutdown complete");
 
     Ok(())
 }
@@ -139,17 +241,30 @@ async fn swagger_ui_redirect() -> Redirect {
     Redirect::permanent("/docs/swagger")
 }
 
-async fn load_config() -> Result<config::Config> {
-    // For demo purposes, create a minimal configuration
-    let settings = config::Config::builder()
-        .set_default("demo_mode", true)?
-        .set_default("server.host", "0.0.0.0")?
-        .set_default("server.port", 3000)?
-        .set_default("ethereum.rpc_url", "https://mainnet.infura.io/v3/demo")?
-        .set_default("polygon.rpc_url", "https://polygon-rpc.com")?
-        .set_default("arbitrum.rpc_url", "https://arb1.arbitrum.io/rpc")?
-        .add_source(config::Environment::with_prefix("BLOCKCHAIN_DEMO"))
-        .build()?;
-    
-    Ok(settings)
+/// Browser origins allowed by `cors_allowed_origins` (comma separated), any origin when unset
+fn cors_layer(config: &config::Config) -> CorsLayer {
+    let origins: Vec<HeaderValue> = config
+        .get_string("cors_allowed_origins")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .filter_map(|origin| match origin.parse() {
+            Ok(origin) => Some(origin),
+            Err(_) => {
+                warn!("Ignoring invalid CORS origin {}", origin);
+                None
+            }
+        })
+        .collect();
+    if origins.is_empty() {
+        warn!("cors_allowed_origins is not set, any website may call the API from a browser");
+        return CorsLayer::permissive();
+    }
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(Any)
+        .allow_headers(Any)
 }
+
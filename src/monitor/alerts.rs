@@ -0,0 +1,156 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use lettre::{
+    message::Mailbox,
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+/// Condition that triggered a position alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AlertKind {
+    HealthFactor,
+    BorrowRatio,
+    /// A strategy's gas costs consume too much of its returns
+    StrategyGas,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    #[default]
+    Active,
+    /// The condition recovered or the position is no longer monitored
+    Resolved,
+}
+
+/// Alert raised by the position monitor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionAlert {
+    pub id: String,
+    pub chain_id: u64,
+    pub user: Address,
+    pub kind: AlertKind,
+    /// Lending protocol or strategy the alert refers to, `None` for portfolio-wide alerts
+    pub protocol: Option<String>,
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+    pub triggered_at: DateTime<Utc>,
+    #[serde(default)]
+    pub status: AlertStatus,
+    #[serde(default)]
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// SMTP settings for email alerts
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+struct EmailChannel {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailChannel {
+    fn new(config: &SmtpConfig) -> Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            .port(config.port);
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let to = config.to.iter()
+            .map(|address| address.parse())
+            .collect::<std::result::Result<Vec<Mailbox>, _>>()?;
+        if to.is_empty() {
+            return Err(anyhow!("SMTP alerts need at least one recipient"));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            to,
+        })
+    }
+
+    async fn send(&self, alert: &PositionAlert) -> Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("[position alert] {:?} on chain {}", alert.kind, alert.chain_id));
+        for recipient in &self.to {
+            builder = builder.to(recipient.clone());
+        }
+
+        let body = format!(
+            "{}\n\nUser: {:?}\nValue: {:.4}\nThreshold: {:.4}\nTriggered at: {}",
+            alert.message, alert.user, alert.value, alert.threshold, alert.triggered_at
+        );
+        self.transport.send(builder.body(body)?).await?;
+        Ok(())
+    }
+}
+
+/// Fans alerts out to webhooks, email and WebSocket subscribers
+pub struct AlertDispatcher {
+    http: reqwest::Client,
+    webhook_urls: Vec<String>,
+    email: Option<EmailChannel>,
+    hub: broadcast::Sender<PositionAlert>,
+}
+
+impl AlertDispatcher {
+    pub fn new(webhook_urls: Vec<String>, smtp: Option<&SmtpConfig>) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        let email = smtp.map(EmailChannel::new).transpose()?;
+        let (hub, _) = broadcast::channel(256);
+
+        Ok(Self {
+            http,
+            webhook_urls,
+            email,
+            hub,
+        })
+    }
+
+    /// Subscribe to alerts as they are dispatched
+    pub fn subscribe(&self) -> broadcast::Receiver<PositionAlert> {
+        self.hub.subscribe()
+    }
+
+    /// Deliver an alert on every configured channel; a failing channel does not block the others
+    pub async fn dispatch(&self, alert: &PositionAlert) {
+        // No WebSocket subscribers is not an error
+        let _ = self.hub.send(alert.clone());
+
+        for url in &self.webhook_urls {
+            let result = self.http.post(url).json(alert).send().await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => debug!("Delivered alert {} to webhook", alert.id),
+                Err(e) => warn!("Webhook delivery of alert {} failed: {}", alert.id, e),
+            }
+        }
+
+        if let Some(email) = &self.email {
+            if let Err(e) = email.send(alert).await {
+                warn!("Email delivery of alert {} failed: {}", alert.id, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,430 @@
+// Background monitoring of registered lending positions
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::models::ArchiveFilter;
+use crate::defi::strategy_gas::StrategyGasReport;
+use crate::defi::{DefiManager, DefiPortfolio};
+use crate::security::{NotificationKind, NotificationService, NotificationSeverity, SecurityNotification};
+use crate::shutdown::ShutdownSignal;
+
+pub mod alerts;
+
+pub use alerts::{AlertDispatcher, AlertKind, AlertStatus, PositionAlert, SmtpConfig};
+
+/// Number of dispatched alerts kept for the API
+const ALERT_HISTORY: usize = 500;
+/// Health factor below which a liquidation risk notification is critical rather than a warning
+const CRITICAL_HEALTH_FACTOR: f64 = 1.1;
+
+/// Position monitor configuration
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    pub poll_interval: Duration,
+    pub health_factor_threshold: f64,
+    pub borrow_ratio_threshold: f64,
+    /// Percentage of a strategy's returns its gas costs may consume
+    pub gas_cost_threshold_percentage: f64,
+    /// Minimum time before the same condition is alerted again while it persists
+    pub alert_cooldown: Duration,
+    pub webhook_urls: Vec<String>,
+    pub smtp: Option<SmtpConfig>,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(60),
+            health_factor_threshold: 1.5,
+            borrow_ratio_threshold: 0.8,
+            gas_cost_threshold_percentage: 25.0,
+            alert_cooldown: Duration::from_secs(3600),
+            webhook_urls: Vec::new(),
+            smtp: None,
+        }
+    }
+}
+
+impl MonitorConfig {
+    /// Build from application config, falling back to defaults for unset keys
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut monitor_config = Self::default();
+
+        if let Ok(secs) = config.get_int("monitor_poll_interval_secs") {
+            monitor_config.poll_interval = Duration::from_secs(secs.max(5) as u64);
+        }
+        if let Ok(threshold) = config.get_float("monitor_health_factor_threshold") {
+            monitor_config.health_factor_threshold = threshold;
+        }
+        if let Ok(threshold) = config.get_float("monitor_borrow_ratio_threshold") {
+            monitor_config.borrow_ratio_threshold = threshold;
+        }
+        if let Ok(threshold) = config.get_float("monitor_gas_cost_threshold_percentage") {
+            monitor_config.gas_cost_threshold_percentage = threshold;
+        }
+        if let Ok(secs) = config.get_int("monitor_alert_cooldown_secs") {
+            monitor_config.alert_cooldown = Duration::from_secs(secs.max(0) as u64);
+        }
+        if let Ok(urls) = config.get_string("monitor_webhook_urls") {
+            monitor_config.webhook_urls = split_list(&urls);
+        }
+        if let (Ok(host), Ok(from), Ok(to)) = (
+            config.get_string("smtp_host"),
+            config.get_string("alert_email_from"),
+            config.get_string("alert_email_to"),
+        ) {
+            monitor_config.smtp = Some(SmtpConfig {
+                host,
+                port: config.get_int("smtp_port").map(|port| port as u16).unwrap_or(587),
+                username: config.get_string("smtp_username").ok(),
+                password: config.get_string("smtp_password").ok(),
+                from,
+                to: split_list(&to),
+            });
+        }
+
+        monitor_config
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Position registered for background monitoring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedPosition {
+    pub chain_id: u64,
+    pub user: Address,
+    pub health_factor_threshold: f64,
+    pub borrow_ratio_threshold: f64,
+    pub registered_at: DateTime<Utc>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+type AlertKey = (u64, Address, AlertKind, Option<String>);
+
+/// Polls registered positions on an interval and dispatches threshold alerts
+pub struct PositionMonitor {
+    defi_manager: Arc<DefiManager>,
+    /// Thresholds and cooldown are reloaded from the config file, the rest applies on restart
+    config: RwLock<MonitorConfig>,
+    dispatcher: AlertDispatcher,
+    /// Security notification channels, told about health factor and borrow ratio breaches
+    notifications: Arc<NotificationService>,
+    watched: Arc<RwLock<HashMap<(u64, Address), WatchedPosition>>>,
+    recent_alerts: Arc<RwLock<VecDeque<PositionAlert>>>,
+    last_alerted: Arc<RwLock<HashMap<AlertKey, DateTime<Utc>>>>,
+}
+
+impl PositionMonitor {
+    pub fn new(defi_manager: Arc<DefiManager>, config: MonitorConfig, notifications: Arc<NotificationService>) -> Result<Self> {
+        info!("Initializing PositionMonitor (poll every {:?})", config.poll_interval);
+        let dispatcher = AlertDispatcher::new(config.webhook_urls.clone(), config.smtp.as_ref())?;
+
+        Ok(Self {
+            defi_manager,
+            config: RwLock::new(config),
+            dispatcher,
+            notifications,
+            watched: Arc::new(RwLock::new(HashMap::new())),
+            recent_alerts: Arc::new(RwLock::new(VecDeque::new())),
+            last_alerted: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Run the polling loop in the background until shutdown, finishing the poll in progress
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let poll_interval = self.config.read().await.poll_interval;
+            let mut ticker = tokio::time::interval(poll_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.poll_once().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Position monitor stopped");
+        })
+    }
+
+    /// Register a position, using the configured thresholds unless overridden
+    pub async fn watch(
+        &self,
+        chain_id: u64,
+        user: Address,
+        health_factor_threshold: Option<f64>,
+        borrow_ratio_threshold: Option<f64>,
+    ) -> WatchedPosition {
+        let config = self.config.read().await;
+        let position = WatchedPosition {
+            chain_id,
+            user,
+            health_factor_threshold: health_factor_threshold.unwrap_or(config.health_factor_threshold),
+            borrow_ratio_threshold: borrow_ratio_threshold.unwrap_or(config.borrow_ratio_threshold),
+            registered_at: Utc::now(),
+            last_checked: None,
+            last_error: None,
+        };
+
+        drop(config);
+
+        self.watched.write().await.insert((chain_id, user), position.clone());
+        info!("Watching positions of {:?} on chain {}", user, chain_id);
+        position
+    }
+
+    pub async fn unwatch(&self, chain_id: u64, user: Address) -> bool {
+        self.last_alerted.write().await.retain(|key, _| key.0 != chain_id || key.1 != user);
+        self.resolve_alerts(chain_id, user, &[]).await;
+        self.watched.write().await.remove(&(chain_id, user)).is_some()
+    }
+
+    pub async fn list_watched(&self) -> Vec<WatchedPosition> {
+        self.watched.read().await.values().cloned().collect()
+    }
+
+    /// Dispatched alerts matching the filter, most recent first
+    pub async fn recent_alerts(&self, limit: usize, filter: ArchiveFilter) -> Vec<PositionAlert> {
+        self.recent_alerts.read().await.iter()
+            .filter(|alert| filter.includes(alert.status == AlertStatus::Resolved))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PositionAlert> {
+        self.dispatcher.subscribe()
+    }
+
+    /// Apply reloaded alert thresholds and cooldown; positions watched with the previous default
+    /// thresholds follow the new ones, custom thresholds are kept
+    pub async fn reload(&self, reloaded: &MonitorConfig) {
+        let mut config = self.config.write().await;
+        let mut watched = self.watched.write().await;
+        for position in watched.values_mut() {
+            if position.health_factor_threshold == config.health_factor_threshold {
+                position.health_factor_threshold = reloaded.health_factor_threshold;
+            }
+            if position.borrow_ratio_threshold == config.borrow_ratio_threshold {
+                position.borrow_ratio_threshold = reloaded.borrow_ratio_threshold;
+            }
+        }
+        config.health_factor_threshold = reloaded.health_factor_threshold;
+        config.borrow_ratio_threshold = reloaded.borrow_ratio_threshold;
+        config.gas_cost_threshold_percentage = reloaded.gas_cost_threshold_percentage;
+        config.alert_cooldown = reloaded.alert_cooldown;
+        info!(
+            "Monitor thresholds reloaded: health factor {}, borrow ratio {}, gas {}%, cooldown {:?}",
+            config.health_factor_threshold, config.borrow_ratio_threshold, config.gas_cost_threshold_percentage, config.alert_cooldown
+        );
+    }
+
+    /// Check every watched position once
+    pub async fn poll_once(&self) {
+        let watched: Vec<WatchedPosition> = self.list_watched().await;
+
+        for position in watched {
+            self.check_position(position).await;
+        }
+    }
+
+    #[instrument(skip_all, fields(chain_id = position.chain_id, wallet = ?position.user))]
+    async fn check_position(&self, position: WatchedPosition) {
+        let key = (position.chain_id, position.user);
+        let result = self.defi_manager.get_portfolio_overview(position.chain_id, position.user).await;
+
+        let error = match result {
+            Ok(portfolio) => {
+                let mut alerts = Self::evaluate(&position, &portfolio);
+                let gas_reports = self.defi_manager.strategy_gas_reports(position.chain_id, position.user).await;
+                let gas_threshold = self.config.read().await.gas_cost_threshold_percentage;
+                alerts.extend(Self::evaluate_gas(&position, &gas_reports, gas_threshold));
+                self.clear_resolved(&position, &alerts).await;
+                for alert in alerts {
+                    self.raise(alert).await;
+                }
+                None
+            }
+            Err(e) => {
+                warn!("Failed to check positions of {:?} on chain {}: {}", position.user, position.chain_id, e);
+                Some(e.to_string())
+            }
+        };
+
+        if let Some(watched) = self.watched.write().await.get_mut(&key) {
+            watched.last_checked = Some(Utc::now());
+            watched.last_error = error;
+        }
+    }
+
+    /// Compare a portfolio against the position's thresholds
+    pub fn evaluate(position: &WatchedPosition, portfolio: &DefiPortfolio) -> Vec<PositionAlert> {
+        let mut alerts = Vec::new();
+
+        for protocol in &portfolio.risk.protocols {
+            if let Some(health_factor) = protocol.health_factor {
+                if health_factor < position.health_factor_threshold {
+                    alerts.push(Self::alert(
+                        position,
+                        AlertKind::HealthFactor,
+                        Some(protocol.protocol.clone()),
+                        health_factor,
+                        position.health_factor_threshold,
+                        format!(
+                            "{} health factor {:.2} is below {:.2}",
+                            protocol.protocol, health_factor, position.health_factor_threshold
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(ratio) = Self::borrow_ratio(portfolio) {
+            if ratio > position.borrow_ratio_threshold {
+                alerts.push(Self::alert(
+                    position,
+                    AlertKind::BorrowRatio,
+                    None,
+                    ratio,
+                    position.borrow_ratio_threshold,
+                    format!("Borrow ratio {:.1}% exceeds {:.1}%", ratio * 100.0, position.borrow_ratio_threshold * 100.0),
+                ));
+            }
+        }
+
+        alerts
+    }
+
+    /// Strategies whose gas costs exceed the configured share of their returns
+    fn evaluate_gas(position: &WatchedPosition, reports: &[StrategyGasReport], threshold: f64) -> Vec<PositionAlert> {
+        reports.iter()
+            .filter_map(|report| {
+                let gas_cost_usd = report.gas_cost_usd?;
+                let budget = report.gas_budget_usd(threshold);
+                (gas_cost_usd > budget).then(|| Self::alert(
+                    position,
+                    AlertKind::StrategyGas,
+                    Some(report.strategy_id.clone()),
+                    gas_cost_usd,
+                    budget,
+                    format!(
+                        "Strategy {} spent ${:.2} on gas, over {:.1}% of its ${:.2} returns",
+                        report.strategy_id, gas_cost_usd, threshold, report.returns_usd
+                    ),
+                ))
+            })
+            .collect()
+    }
+
+    fn borrow_ratio(portfolio: &DefiPortfolio) -> Option<f64> {
+        if portfolio.total_supplied_usd > 0.0 {
+            Some(portfolio.total_borrowed_usd / portfolio.total_supplied_usd)
+        } else {
+            None
+        }
+    }
+
+    fn alert(
+        position: &WatchedPosition,
+        kind: AlertKind,
+        protocol: Option<String>,
+        value: f64,
+        threshold: f64,
+        message: String,
+    ) -> PositionAlert {
+        PositionAlert {
+            id: Uuid::new_v4().to_string(),
+            chain_id: position.chain_id,
+            user: position.user,
+            kind,
+            protocol,
+            value,
+            threshold,
+            message,
+            triggered_at: Utc::now(),
+            status: AlertStatus::Active,
+            resolved_at: None,
+        }
+    }
+
+    /// Dispatch an alert unless the same condition was alerted within the cooldown
+    async fn raise(&self, alert: PositionAlert) {
+        let key = (alert.chain_id, alert.user, alert.kind, alert.protocol.clone());
+        let cooldown = chrono::Duration::from_std(self.config.read().await.alert_cooldown).unwrap_or_default();
+        {
+            let mut last_alerted = self.last_alerted.write().await;
+            if let Some(previous) = last_alerted.get(&key) {
+                if alert.triggered_at - *previous < cooldown {
+                    return;
+                }
+            }
+            last_alerted.insert(key, alert.triggered_at);
+        }
+
+        warn!("Position alert for {:?} on chain {}: {}", alert.user, alert.chain_id, alert.message);
+        self.dispatcher.dispatch(&alert).await;
+        if let Some(notification) = Self::liquidation_risk(&alert) {
+            self.notifications.notify(notification);
+        }
+
+        let mut recent = self.recent_alerts.write().await;
+        recent.push_front(alert);
+        recent.truncate(ALERT_HISTORY);
+    }
+
+    /// Liquidation risk notification of a health factor or borrow ratio alert
+    fn liquidation_risk(alert: &PositionAlert) -> Option<SecurityNotification> {
+        let severity = match alert.kind {
+            AlertKind::HealthFactor if alert.value < CRITICAL_HEALTH_FACTOR => NotificationSeverity::Critical,
+            AlertKind::HealthFactor | AlertKind::BorrowRatio => NotificationSeverity::Warning,
+            AlertKind::StrategyGas => return None,
+        };
+        let mut notification = SecurityNotification::new(
+            NotificationKind::LiquidationRisk,
+            severity,
+            format!("Liquidation risk on chain {}", alert.chain_id),
+            alert.message.clone(),
+        );
+        notification.address = Some(alert.user);
+        notification.chain_id = Some(alert.chain_id);
+        Some(notification)
+    }
+
+    /// Forget conditions that have recovered so a new breach alerts immediately
+    async fn clear_resolved(&self, position: &WatchedPosition, active: &[PositionAlert]) {
+        self.last_alerted.write().await.retain(|key, _| {
+            key.0 != position.chain_id
+                || key.1 != position.user
+                || active.iter().any(|alert| alert.kind == key.2 && alert.protocol == key.3)
+        });
+        self.resolve_alerts(position.chain_id, position.user, active).await;
+    }
+
+    /// Mark a user's alerts resolved unless their condition is still in `active`
+    async fn resolve_alerts(&self, chain_id: u64, user: Address, active: &[PositionAlert]) {
+        let now = Utc::now();
+        for alert in self.recent_alerts.write().await.iter_mut() {
+            let still_active = active.iter().any(|current| current.kind == alert.kind && current.protocol == alert.protocol);
+            if alert.chain_id == chain_id && alert.user == user && alert.status == AlertStatus::Active && !still_active {
+                alert.status = AlertStatus::Resolved;
+                alert.resolved_at = Some(now);
+            }
+        }
+    }
+}
@@ -0,0 +1,431 @@
+// Outstanding ERC-20 allowances and ERC-721 approvals of wallets, found from Approval logs and
+// checked against current on-chain state
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::{parse_abi, Abi, Token},
+    contract::Contract,
+    providers::{Middleware, Provider},
+    types::{Address, Filter, TransactionRequest, H256, U256},
+    utils::keccak256,
+};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::analytics::token_balances::{logs_in_ranges, ChainScanError};
+use crate::chains::pool::PooledHttp;
+use crate::chains::ChainManager;
+use crate::contracts::approvals::APPROVE_GAS;
+use crate::contracts::multicall::multicall;
+use crate::contracts::ContractManager;
+
+/// Blocks searched for approvals the first time a wallet is scanned on a chain
+const DEFAULT_SCAN_BLOCKS: u64 = 100_000;
+/// Allowances from 2^96 - 1 count as unlimited, the cap of tokens storing them as uint96
+pub(super) const UNLIMITED_ALLOWANCE_BITS: usize = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalKind {
+    /// ERC-20 allowance
+    Erc20,
+    /// One ERC-721 token
+    Erc721,
+    /// `setApprovalForAll` over a whole ERC-721 collection
+    Erc721All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpenderKind {
+    /// Externally owned account, which can move the tokens at will
+    Account,
+    /// Contract with source verified on the chain's block explorer
+    VerifiedContract,
+    UnverifiedContract,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalRisk {
+    Low,
+    Medium,
+    /// Unlimited access for an account or an unverified contract
+    High,
+}
+
+/// Which approvals get revoke transactions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevokeScope {
+    /// High-risk approvals only
+    #[default]
+    Risky,
+    All,
+    None,
+}
+
+/// Approval still granted by the wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutstandingApproval {
+    pub chain_id: u64,
+    pub kind: ApprovalKind,
+    pub token: Address,
+    /// Token symbol, filled in by the API
+    pub symbol: Option<String>,
+    pub spender: Address,
+    pub spender_kind: SpenderKind,
+    /// Approved ERC-721 token
+    pub token_id: Option<U256>,
+    /// Current ERC-20 allowance
+    pub allowance: Option<U256>,
+    /// Unlimited allowance, or a whole collection
+    pub unlimited: bool,
+    pub risk: ApprovalRisk,
+    pub reasons: Vec<String>,
+    /// Block of the latest Approval log of this grant
+    pub approved_at_block: u64,
+    /// Transaction resetting the approval, to be signed by the wallet
+    pub revoke: TransactionRequest,
+}
+
+/// Revoke transactions of one chain, to send in order from the wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokeBatch {
+    pub chain_id: u64,
+    pub transactions: Vec<TransactionRequest>,
+    pub estimated_gas: u64,
+}
+
+/// Outstanding approvals of a wallet across chains, riskiest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalReport {
+    pub owner: Address,
+    /// Primary ENS name of the owner, filled in by the API
+    pub ens_name: Option<String>,
+    pub chain_ids: Vec<u64>,
+    pub approvals: Vec<OutstandingApproval>,
+    pub high_risk: usize,
+    pub revoke_batches: Vec<RevokeBatch>,
+    pub errors: Vec<ChainScanError>,
+    pub scanned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ApprovalKey {
+    kind: ApprovalKind,
+    token: Address,
+    spender: Address,
+    token_id: Option<U256>,
+}
+
+/// Approvals a wallet granted on one chain and the last block searched
+#[derive(Debug, Clone, Default)]
+struct ApprovalHistory {
+    /// Latest block each approval was granted in
+    grants: BTreeMap<ApprovalKey, u64>,
+    scanned_to: Option<u64>,
+}
+
+/// Finds approvals from Approval and ApprovalForAll logs, keeps those still in force and rates their
+/// spenders
+pub struct ApprovalScanner {
+    chain_manager: Arc<ChainManager>,
+    contracts: Arc<ContractManager>,
+    scan_blocks: u64,
+    history: RwLock<HashMap<(u64, Address), ApprovalHistory>>,
+}
+
+impl ApprovalScanner {
+    pub fn new(chain_manager: Arc<ChainManager>, contracts: Arc<ContractManager>, scan_blocks: u64) -> Self {
+        Self {
+            chain_manager,
+            contracts,
+            scan_blocks,
+            history: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Scanner searching the last `approval_scan_blocks` blocks of a wallet's first scan
+    pub fn from_config(config: &config::Config, chain_manager: Arc<ChainManager>, contracts: Arc<ContractManager>) -> Self {
+        let scan_blocks = config
+            .get_int("approval_scan_blocks")
+            .map(|blocks| blocks.max(1) as u64)
+            .unwrap_or(DEFAULT_SCAN_BLOCKS);
+        Self::new(chain_manager, contracts, scan_blocks)
+    }
+
+    /// Approvals of a wallet on `chain_ids`, every supported chain when `None`, with revoke batches
+    /// for the approvals in `revoke`
+    pub async fn scan(&self, owner: Address, chain_ids: Option<Vec<u64>>, revoke: RevokeScope) -> ApprovalReport {
+        let chain_ids = match chain_ids {
+            Some(chain_ids) => chain_ids,
+            None => self.chain_manager.chain_ids().await,
+        };
+        let scans = join_all(chain_ids.iter().map(|chain_id| self.scan_chain(*chain_id, owner))).await;
+
+        let mut approvals = Vec::new();
+        let mut errors = Vec::new();
+        for (chain_id, scan) in chain_ids.iter().zip(scans) {
+            match scan {
+                Ok(found) => approvals.extend(found),
+                Err(e) => {
+                    warn!("Approval scan of {:?} on chain {} failed: {}", owner, chain_id, e);
+                    errors.push(ChainScanError { chain_id: *chain_id, error: e.to_string() });
+                }
+            }
+        }
+        approvals.sort_by(|a, b| b.risk.cmp(&a.risk).then(b.unlimited.cmp(&a.unlimited)));
+
+        let mut revoke_batches: BTreeMap<u64, RevokeBatch> = BTreeMap::new();
+        let revoked = approvals.iter().filter(|approval| match revoke {
+            RevokeScope::Risky => approval.risk == ApprovalRisk::High,
+            RevokeScope::All => true,
+            RevokeScope::None => false,
+        });
+        for approval in revoked {
+            let batch = revoke_batches.entry(approval.chain_id).or_insert_with(|| RevokeBatch {
+                chain_id: approval.chain_id,
+                transactions: Vec::new(),
+                estimated_gas: 0,
+            });
+            batch.transactions.push(approval.revoke.clone());
+            batch.estimated_gas += APPROVE_GAS;
+        }
+
+        ApprovalReport {
+            owner,
+            ens_name: None,
+            chain_ids,
+            high_risk: approvals.iter().filter(|approval| approval.risk == ApprovalRisk::High).count(),
+            approvals,
+            revoke_batches: revoke_batches.into_values().collect(),
+            errors,
+            scanned_at: Utc::now(),
+        }
+    }
+
+    async fn scan_chain(&self, chain_id: u64, owner: Address) -> Result<Vec<OutstandingApproval>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain.provider.clone());
+        let grants = self.grants(chain_id, &provider, owner).await?;
+        if grants.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let abi = approvals_abi()?;
+        let token = Contract::new(Address::zero(), abi.clone(), provider.clone());
+        let mut current = Vec::with_capacity(grants.len());
+        for kind in [ApprovalKind::Erc20, ApprovalKind::Erc721All, ApprovalKind::Erc721] {
+            let keys: Vec<(ApprovalKey, u64)> = grants.iter()
+                .filter(|(key, _)| key.kind == kind)
+                .map(|(key, block)| (*key, *block))
+                .collect();
+            if keys.is_empty() {
+                continue;
+            }
+            // Each approval is checked with a read that reflects later transfers, approvals and resets
+            let states = match kind {
+                ApprovalKind::Erc20 => {
+                    let calls = keys.iter()
+                        .map(|(key, _)| token.at(key.token).method::<_, U256>("allowance", (owner, key.spender)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    multicall(&provider, chain_id, calls).await?.into_iter()
+                        .map(|allowance| allowance.and_then(Token::into_uint).filter(|allowance| !allowance.is_zero()).map(Some))
+                        .collect::<Vec<_>>()
+                }
+                ApprovalKind::Erc721All => {
+                    let calls = keys.iter()
+                        .map(|(key, _)| token.at(key.token).method::<_, bool>("isApprovedForAll", (owner, key.spender)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    multicall(&provider, chain_id, calls).await?.into_iter()
+                        .map(|approved| approved.and_then(Token::into_bool).filter(|approved| *approved).map(|_| None))
+                        .collect()
+                }
+                ApprovalKind::Erc721 => {
+                    let token_id = |key: &ApprovalKey| key.token_id.unwrap_or_default();
+                    let approved_calls = keys.iter()
+                        .map(|(key, _)| token.at(key.token).method::<_, Address>("getApproved", token_id(key)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let owner_calls = keys.iter()
+                        .map(|(key, _)| token.at(key.token).method::<_, Address>("ownerOf", token_id(key)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let approved = multicall(&provider, chain_id, approved_calls).await?;
+                    let owners = multicall(&provider, chain_id, owner_calls).await?;
+                    keys.iter()
+                        .zip(approved.into_iter().zip(owners))
+                        .map(|((key, _), (approved, holder))| {
+                            let approved = approved.and_then(Token::into_address) == Some(key.spender);
+                            let held = holder.and_then(Token::into_address) == Some(owner);
+                            (approved && held).then_some(None)
+                        })
+                        .collect()
+                }
+            };
+            current.extend(keys.into_iter()
+                .zip(states)
+                .filter_map(|((key, block), state)| Some((key, block, state?))));
+        }
+        debug!("{} of {} approvals of {:?} on chain {} still in force", current.len(), grants.len(), owner, chain_id);
+
+        let spenders: HashSet<Address> = current.iter().map(|(key, _, _)| key.spender).collect();
+        let provider = &provider;
+        let kinds: HashMap<Address, SpenderKind> = join_all(spenders.into_iter().map(|spender| async move {
+            (spender, self.spender_kind(chain_id, provider, spender).await)
+        })).await.into_iter().collect();
+
+        current.into_iter()
+            .map(|(key, block, allowance)| {
+                let spender_kind = kinds.get(&key.spender).copied().unwrap_or(SpenderKind::UnverifiedContract);
+                let unlimited = match key.kind {
+                    ApprovalKind::Erc20 => allowance.unwrap_or_default() >= (U256::one() << UNLIMITED_ALLOWANCE_BITS) - 1,
+                    ApprovalKind::Erc721 => false,
+                    ApprovalKind::Erc721All => true,
+                };
+                let (risk, reasons) = rate(key.kind, spender_kind, unlimited);
+                Ok(OutstandingApproval {
+                    chain_id,
+                    kind: key.kind,
+                    token: key.token,
+                    symbol: None,
+                    spender: key.spender,
+                    spender_kind,
+                    token_id: key.token_id,
+                    allowance,
+                    unlimited,
+                    risk,
+                    reasons,
+                    approved_at_block: block,
+                    revoke: revoke_transaction(&abi, chain_id, owner, &key)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Approvals granted by `owner`, from the Approval logs added since the last scan
+    async fn grants(&self, chain_id: u64, provider: &Provider<PooledHttp>, owner: Address) -> Result<BTreeMap<ApprovalKey, u64>> {
+        let known = self.history.read().await.get(&(chain_id, owner)).cloned().unwrap_or_default();
+        let latest = provider.get_block_number().await?.as_u64();
+        let from = match known.scanned_to {
+            Some(scanned_to) => scanned_to + 1,
+            None => latest.saturating_sub(self.scan_blocks - 1),
+        };
+        if from > latest {
+            return Ok(known.grants);
+        }
+
+        let approval = H256::from(keccak256("Approval(address,address,uint256)"));
+        let approval_for_all = H256::from(keccak256("ApprovalForAll(address,address,bool)"));
+        let filter = Filter::new()
+            .topic0(vec![approval, approval_for_all])
+            .topic1(H256::from(owner));
+        let logs = logs_in_ranges(provider, filter, from, latest).await?;
+
+        let mut grants = known.grants;
+        for log in logs {
+            let spender = match log.topics.get(2) {
+                Some(topic) => Address::from(*topic),
+                None => continue,
+            };
+            // ERC-721 Approval logs index the token id, ERC-20 ones carry the amount as data
+            let key = match (log.topics[0] == approval, log.topics.len()) {
+                (true, 3) => ApprovalKey { kind: ApprovalKind::Erc20, token: log.address, spender, token_id: None },
+                (true, 4) => ApprovalKey {
+                    kind: ApprovalKind::Erc721,
+                    token: log.address,
+                    spender,
+                    token_id: Some(U256::from_big_endian(log.topics[3].as_bytes())),
+                },
+                (false, 3) => ApprovalKey { kind: ApprovalKind::Erc721All, token: log.address, spender, token_id: None },
+                _ => continue,
+            };
+            // Clearing a single-token approval names the zero address, which is never a spender
+            if spender.is_zero() {
+                continue;
+            }
+            let block = log.block_number.map(|block| block.as_u64()).unwrap_or(latest);
+            grants.insert(key, block);
+        }
+        info!("{} approvals known for {:?} on chain {} up to block {}", grants.len(), owner, chain_id, latest);
+
+        self.history.write().await.insert(
+            (chain_id, owner),
+            ApprovalHistory { grants: grants.clone(), scanned_to: Some(latest) },
+        );
+        Ok(grants)
+    }
+
+    /// Whether a spender is an account, or a contract with or without verified source
+    async fn spender_kind(&self, chain_id: u64, provider: &Provider<PooledHttp>, spender: Address) -> SpenderKind {
+        match provider.get_code(spender, None).await {
+            Ok(code) if code.is_empty() => SpenderKind::Account,
+            Ok(_) => match self.contracts.get_abi(chain_id, spender).await {
+                Ok(_) => SpenderKind::VerifiedContract,
+                Err(e) => {
+                    debug!("Spender {:?} on chain {} counted as unverified: {}", spender, chain_id, e);
+                    SpenderKind::UnverifiedContract
+                }
+            },
+            Err(e) => {
+                debug!("Could not read code of spender {:?} on chain {}: {}", spender, chain_id, e);
+                SpenderKind::UnverifiedContract
+            }
+        }
+    }
+}
+
+/// Risk of an approval and why, unlimited access for an account or unverified contract being high
+fn rate(kind: ApprovalKind, spender: SpenderKind, unlimited: bool) -> (ApprovalRisk, Vec<String>) {
+    let mut reasons = Vec::new();
+    match kind {
+        ApprovalKind::Erc20 if unlimited => reasons.push("Unlimited allowance".to_string()),
+        ApprovalKind::Erc721All => reasons.push("Every token of the collection, including ones received later".to_string()),
+        _ => {}
+    }
+    match spender {
+        SpenderKind::Account => reasons.push("Spender is an externally owned account".to_string()),
+        SpenderKind::UnverifiedContract => reasons.push("Spender contract has no verified source".to_string()),
+        SpenderKind::VerifiedContract => {}
+    }
+
+    let risk = match (spender, unlimited) {
+        (SpenderKind::VerifiedContract, false) => ApprovalRisk::Low,
+        (SpenderKind::VerifiedContract, true) | (_, false) => ApprovalRisk::Medium,
+        (_, true) => ApprovalRisk::High,
+    };
+    (risk, reasons)
+}
+
+/// Transaction from the owner resetting an approval
+fn revoke_transaction(abi: &Abi, chain_id: u64, owner: Address, key: &ApprovalKey) -> Result<TransactionRequest> {
+    let data = match key.kind {
+        ApprovalKind::Erc20 => abi.function("approve")?
+            .encode_input(&[Token::Address(key.spender), Token::Uint(U256::zero())])?,
+        ApprovalKind::Erc721 => abi.function("approve")?
+            .encode_input(&[Token::Address(Address::zero()), Token::Uint(key.token_id.unwrap_or_default())])?,
+        ApprovalKind::Erc721All => abi.function("setApprovalForAll")?
+            .encode_input(&[Token::Address(key.spender), Token::Bool(false)])?,
+    };
+
+    Ok(TransactionRequest::new()
+        .from(owner)
+        .to(key.token)
+        .data(data)
+        .value(U256::zero())
+        .chain_id(chain_id))
+}
+
+fn approvals_abi() -> Result<Abi> {
+    Ok(parse_abi(&[
+        "function allowance(address owner, address spender) view returns (uint256)",
+        "function isApprovedForAll(address owner, address operator) view returns (bool)",
+        "function getApproved(uint256 tokenId) view returns (address)",
+        "function ownerOf(uint256 tokenId) view returns (address)",
+        "function approve(address spender, uint256 amount)",
+        "function setApprovalForAll(address operator, bool approved)",
+    ])?)
+}
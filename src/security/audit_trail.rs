@@ -3,12 +3,20 @@ use ethers::{
     prelude::*,
     types::{Address, U256, TransactionRequest, H256, Bytes, TransactionReceipt},
 };
-use std::collections::{HashMap, VecDeque};
+use ethers::utils::keccak256;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 
+/// Store used when `audit_log_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/audit_log.jsonl";
+/// Entries above this risk score, and security violations, are kept for the high-risk retention
+const HIGH_RISK_SCORE: f64 = 0.7;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditEntry {
     pub id: String,
@@ -18,6 +26,7 @@ pub struct AuditEntry {
     pub transaction_hash: Option<H256>,
     pub contract_address: Option<Address>,
     pub function_called: Option<String>,
+    #[serde(with = "crate::api::models::sorted_map")]
     pub parameters: HashMap<String, String>,
     pub gas_used: Option<U256>,
     pub gas_price: Option<U256>,
@@ -26,7 +35,18 @@ pub struct AuditEntry {
     pub error_message: Option<String>,
     pub risk_score: Option<f64>,
     pub security_flags: Vec<String>,
+    #[serde(with = "crate::api::models::sorted_map")]
     pub metadata: HashMap<String, String>,
+    /// Position in the hash chain, assigned when the entry is logged
+    #[serde(default)]
+    pub sequence: u64,
+    /// Hash of the previous entry, zero for the first entry ever logged
+    #[serde(default)]
+    pub prev_hash: H256,
+    /// Keccak-256 of the entry's JSON with this field zeroed, sealing it and, through `prev_hash`,
+    /// every entry before it
+    #[serde(default)]
+    pub hash: H256,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -41,6 +61,7 @@ pub enum AuditEntryType {
     SuspiciousActivity,
     RiskAssessment,
     ThreatDetected,
+    SanctionsScreening,
     
     // System events
     SystemStart,
@@ -77,6 +98,9 @@ pub struct AuditQuery {
     pub end_time: Option<DateTime<Utc>>,
     pub entry_types: Vec<AuditEntryType>,
     pub user_address: Option<Address>,
+    /// Address the entry is about, or operator named in an `actor:` flag
+    #[serde(default)]
+    pub actor: Option<String>,
     pub contract_address: Option<Address>,
     pub transaction_hash: Option<H256>,
     pub risk_score_min: Option<f64>,
@@ -102,12 +126,87 @@ pub struct ComplianceReport {
 
 pub struct AuditTrail {
     provider: Arc<Provider<Http>>,
-    audit_log: Arc<RwLock<VecDeque<AuditEntry>>>,
+    audit_log: Arc<RwLock<AuditLog>>,
     indexed_entries: Arc<RwLock<HashMap<String, Vec<String>>>>, // Index by different fields
     compliance_rules: Arc<RwLock<HashMap<String, ComplianceRule>>>,
     retention_policy: Arc<RwLock<RetentionPolicy>>,
     encryption_key: Arc<RwLock<Vec<u8>>>,
-    storage_backend: Arc<RwLock<StorageBackend>>,
+    /// Append-only JSON Lines file of the entries, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+}
+
+/// Entries kept in memory with the head of their hash chain
+#[derive(Default)]
+struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    next_sequence: u64,
+    /// Hash of the last entry logged, kept when retention prunes every entry
+    head: H256,
+}
+
+/// Where audit entries are persisted and how long they are kept
+#[derive(Debug, Clone)]
+pub struct AuditTrailConfig {
+    /// Append-only JSON Lines file, `None` keeps entries in memory only
+    pub store_path: Option<PathBuf>,
+    pub retention_days: i64,
+    /// Retention of high-risk entries and security violations
+    pub high_risk_retention_days: i64,
+}
+
+impl Default for AuditTrailConfig {
+    fn default() -> Self {
+        Self {
+            store_path: None,
+            retention_days: 90,
+            high_risk_retention_days: 365,
+        }
+    }
+}
+
+impl AuditTrailConfig {
+    /// Entries persisted to `audit_log_store_path` (empty for memory only), kept `audit_retention_days`
+    /// or `audit_high_risk_retention_days` for high-risk entries
+    pub fn from_config(config: &config::Config) -> Self {
+        let defaults = Self::default();
+        let path = config
+            .get_string("audit_log_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        Self {
+            store_path: (!path.is_empty()).then(|| PathBuf::from(path)),
+            retention_days: config
+                .get_int("audit_retention_days")
+                .map(|days| days.max(1))
+                .unwrap_or(defaults.retention_days),
+            high_risk_retention_days: config
+                .get_int("audit_high_risk_retention_days")
+                .map(|days| days.max(1))
+                .unwrap_or(defaults.high_risk_retention_days),
+        }
+    }
+}
+
+/// Result of recomputing the hash chain of the retained entries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainVerification {
+    pub entries: usize,
+    pub valid: bool,
+    /// `prev_hash` of the oldest retained entry, zero unless retention pruned older entries
+    pub anchor: H256,
+    pub head: H256,
+    /// Sequence of the first entry that fails to verify
+    pub first_invalid: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Layout of an audit log export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditExportFormat {
+    /// One JSON entry per line with its hashes, verifiable on its own
+    #[default]
+    Jsonl,
+    Csv,
 }
 
 #[derive(Debug, Clone)]
@@ -147,31 +246,66 @@ struct RetentionPolicy {
     delete_after_days: i64,
 }
 
-#[derive(Debug, Clone)]
-enum StorageBackend {
-    Memory,
-    Database(String), // Connection string
-    IPFS(String),     // IPFS node
-    S3(String),       // S3 bucket
-}
-
 impl AuditTrail {
+    /// Audit trail kept in memory with the default retention
     pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self::with_config(provider, AuditTrailConfig::default())
+    }
+
+    fn with_config(provider: Arc<Provider<Http>>, config: AuditTrailConfig) -> Self {
         Self {
             provider,
-            audit_log: Arc::new(RwLock::new(VecDeque::with_capacity(100000))),
+            audit_log: Arc::new(RwLock::new(AuditLog::default())),
             indexed_entries: Arc::new(RwLock::new(HashMap::new())),
             compliance_rules: Arc::new(RwLock::new(HashMap::new())),
             retention_policy: Arc::new(RwLock::new(RetentionPolicy {
-                default_retention_days: 90,
-                high_risk_retention_days: 365,
+                default_retention_days: config.retention_days,
+                high_risk_retention_days: config.high_risk_retention_days,
                 compliance_retention_days: 2555, // 7 years
                 archive_after_days: 30,
                 delete_after_days: 2555,
             })),
             encryption_key: Arc::new(RwLock::new(vec![0u8; 32])), // Would use proper key management
-            storage_backend: Arc::new(RwLock::new(StorageBackend::Memory)),
+            store_path: config.store_path,
+        }
+    }
+
+    /// Audit trail continuing the entries persisted in the configured store
+    ///
+    /// A chain that fails to verify is reported rather than refused, so tampering is visible
+    /// without stopping the service.
+    pub async fn open(provider: Arc<Provider<Http>>, config: AuditTrailConfig) -> Result<Self> {
+        let trail = Self::with_config(provider, config);
+        let Some(path) = trail.store_path.clone() else {
+            return Ok(trail);
+        };
+        if tokio::fs::try_exists(&path).await? {
+            let contents = tokio::fs::read_to_string(&path).await?;
+            let mut log = trail.audit_log.write().await;
+            for (line_number, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                let entry: AuditEntry = serde_json::from_str(line)
+                    .map_err(|e| anyhow!("Invalid audit entry on line {} of {}: {}", line_number + 1, path.display(), e))?;
+                log.next_sequence = entry.sequence + 1;
+                log.head = entry.hash;
+                log.entries.push_back(entry);
+            }
+            for entry in &log.entries {
+                trail.update_indices(entry).await?;
+            }
+        }
+
+        let verification = trail.verify().await;
+        if verification.valid {
+            tracing::info!("Audit trail opened with {} entries from {}", verification.entries, path.display());
+        } else {
+            tracing::error!(
+                "Audit trail {} fails verification at entry {:?}: {}",
+                path.display(),
+                verification.first_invalid,
+                verification.error.as_deref().unwrap_or("unknown"),
+            );
         }
+        Ok(trail)
     }
 
     /// Initialize audit trail system
@@ -198,12 +332,43 @@ impl AuditTrail {
             risk_score: None,
             security_flags: Vec::new(),
             metadata: [("system".to_string(), "audit_trail".to_string())].into(),
+            sequence: 0,
+            prev_hash: H256::zero(),
+            hash: H256::zero(),
         }).await?;
         
         tracing::info!("Audit trail system initialized");
         Ok(())
     }
 
+    /// Record the system stopping; entries are persisted as they are logged, so this is the last one
+    pub async fn shutdown(&self) -> Result<()> {
+        self.log_entry(AuditEntry {
+            id: self.generate_id(),
+            entry_type: AuditEntryType::SystemStop,
+            timestamp: Utc::now(),
+            user_address: None,
+            transaction_hash: None,
+            contract_address: None,
+            function_called: None,
+            parameters: HashMap::new(),
+            gas_used: None,
+            gas_price: None,
+            value: None,
+            success: true,
+            error_message: None,
+            risk_score: None,
+            security_flags: Vec::new(),
+            metadata: [("system".to_string(), "audit_trail".to_string())].into(),
+            sequence: 0,
+            prev_hash: H256::zero(),
+            hash: H256::zero(),
+        }).await?;
+
+        tracing::info!("Audit trail closed");
+        Ok(())
+    }
+
     /// Log an audit entry
     pub async fn log_entry(&self, entry: AuditEntry) -> Result<()> {
         let entry_id = entry.id.clone();
@@ -212,20 +377,25 @@ impl AuditTrail {
         self.check_compliance_rules(&entry).await?;
         
         // Encrypt sensitive data if needed
-        let encrypted_entry = self.encrypt_entry(entry).await?;
-        
-        // Store in memory log
-        let mut log = self.audit_log.write().await;
-        log.push_back(encrypted_entry.clone());
+        let mut encrypted_entry = self.encrypt_entry(entry).await?;
         
         // Apply retention policy
+        let mut log = self.audit_log.write().await;
         self.apply_retention_policy(&mut log).await?;
         
-        // Update indices
-        self.update_indices(&encrypted_entry).await?;
+        // Seal the entry onto the hash chain
+        encrypted_entry.sequence = log.next_sequence;
+        encrypted_entry.prev_hash = log.head;
+        encrypted_entry.hash = entry_hash(&encrypted_entry)?;
         
-        // Persist to storage backend if configured
+        // Persist before the entry becomes visible, so memory never holds unpersisted entries
         self.persist_to_backend(&encrypted_entry).await?;
+        log.next_sequence += 1;
+        log.head = encrypted_entry.hash;
+        log.entries.push_back(encrypted_entry.clone());
+        
+        // Update indices
+        self.update_indices(&encrypted_entry).await?;
         
         tracing::debug!("Audit entry logged: {}", entry_id);
         Ok(())
@@ -256,6 +426,9 @@ impl AuditTrail {
             risk_score,
             security_flags: Vec::new(), // Would be populated by security modules
             metadata: HashMap::new(),
+            sequence: 0,
+            prev_hash: H256::zero(),
+            hash: H256::zero(),
         };
         
         self.log_entry(entry).await
@@ -280,6 +453,9 @@ impl AuditTrail {
             risk_score: Some(risk_score),
             security_flags: flags,
             metadata: HashMap::new(),
+            sequence: 0,
+            prev_hash: H256::zero(),
+            hash: H256::zero(),
         };
         
         self.log_entry(entry).await
@@ -290,14 +466,7 @@ impl AuditTrail {
         let log = self.audit_log.read().await;
         let mut results = Vec::new();
         
-        let entries: Vec<_> = if let Some(limit) = query.limit {
-            let start = query.offset.unwrap_or(0);
-            log.iter().skip(start).take(limit).collect()
-        } else {
-            log.iter().collect()
-        };
-        
-        for entry in entries {
+        for entry in log.entries.iter() {
             let decrypted_entry = self.decrypt_entry(entry.clone()).await?;
             
             if self.matches_query(&decrypted_entry, &query) {
@@ -305,12 +474,107 @@ impl AuditTrail {
             }
         }
         
-        // Sort by timestamp (newest first)
-        results.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        // Sort by timestamp (newest first), then page through the matches
+        results.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(b.sequence.cmp(&a.sequence)));
+        let results = results.into_iter()
+            .skip(query.offset.unwrap_or(0))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect();
         
         Ok(results)
     }
 
+    /// Export the entries matching `query` oldest first, with their hashes so an export can be
+    /// checked against the chain
+    pub async fn export(&self, query: AuditQuery, format: AuditExportFormat) -> Result<String> {
+        let mut entries = self.query_entries(query).await?;
+        entries.sort_by_key(|entry| entry.sequence);
+        
+        match format {
+            AuditExportFormat::Jsonl => {
+                let mut lines = String::new();
+                for entry in &entries {
+                    lines.push_str(&serde_json::to_string(entry)?);
+                    lines.push('\n');
+                }
+                Ok(lines)
+            }
+            AuditExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                writer.write_record([
+                    "sequence", "id", "entry_type", "timestamp", "user_address", "transaction_hash",
+                    "contract_address", "function_called", "value", "success", "error_message",
+                    "risk_score", "security_flags", "parameters", "prev_hash", "hash",
+                ])?;
+                for entry in &entries {
+                    writer.write_record([
+                        entry.sequence.to_string(),
+                        entry.id.clone(),
+                        format!("{:?}", entry.entry_type),
+                        entry.timestamp.to_rfc3339(),
+                        entry.user_address.map(|address| format!("{:?}", address)).unwrap_or_default(),
+                        entry.transaction_hash.map(|hash| format!("{:?}", hash)).unwrap_or_default(),
+                        entry.contract_address.map(|address| format!("{:?}", address)).unwrap_or_default(),
+                        entry.function_called.clone().unwrap_or_default(),
+                        entry.value.map(|value| value.to_string()).unwrap_or_default(),
+                        entry.success.to_string(),
+                        entry.error_message.clone().unwrap_or_default(),
+                        entry.risk_score.map(|score| score.to_string()).unwrap_or_default(),
+                        entry.security_flags.join(";"),
+                        serde_json::to_string(&entry.parameters.iter().collect::<BTreeMap<_, _>>())?,
+                        format!("{:?}", entry.prev_hash),
+                        format!("{:?}", entry.hash),
+                    ])?;
+                }
+                Ok(String::from_utf8(writer.into_inner()?)?)
+            }
+        }
+    }
+
+    /// Recompute the hash chain of the retained entries
+    pub async fn verify(&self) -> ChainVerification {
+        let log = self.audit_log.read().await;
+        let anchor = log.entries.front().map(|entry| entry.prev_hash).unwrap_or(log.head);
+        let mut verification = ChainVerification {
+            entries: log.entries.len(),
+            valid: true,
+            anchor,
+            head: log.head,
+            first_invalid: None,
+            error: None,
+        };
+        
+        let mut expected_prev = anchor;
+        let mut expected_sequence = log.entries.front().map(|entry| entry.sequence);
+        for entry in log.entries.iter() {
+            let error = if Some(entry.sequence) != expected_sequence {
+                Some(format!("expected sequence {:?}, found {}", expected_sequence, entry.sequence))
+            } else if entry.prev_hash != expected_prev {
+                Some(format!("prev_hash {:?} does not match the previous entry {:?}", entry.prev_hash, expected_prev))
+            } else {
+                match entry_hash(entry) {
+                    Ok(hash) if hash == entry.hash => None,
+                    Ok(hash) => Some(format!("content hashes to {:?}, recorded {:?}", hash, entry.hash)),
+                    Err(e) => Some(e.to_string()),
+                }
+            };
+            if let Some(error) = error {
+                verification.valid = false;
+                verification.first_invalid = Some(entry.sequence);
+                verification.error = Some(format!("entry {}: {}", entry.id, error));
+                break;
+            }
+            expected_prev = entry.hash;
+            expected_sequence = Some(entry.sequence + 1);
+        }
+        
+        if verification.valid && log.entries.back().is_some_and(|entry| entry.hash != log.head) {
+            verification.valid = false;
+            verification.error = Some("last entry is not the head of the chain".to_string());
+        }
+        verification
+    }
+
     /// Generate compliance report
     pub async fn generate_compliance_report(&self, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> Result<ComplianceReport> {
         let query = AuditQuery {
@@ -322,6 +586,7 @@ impl AuditTrail {
                 AuditEntryType::SuspiciousActivity,
             ],
             user_address: None,
+            actor: None,
             contract_address: None,
             transaction_hash: None,
             risk_score_min: None,
@@ -365,6 +630,7 @@ impl AuditTrail {
         let now = Utc::now();
         let last_24h = now - chrono::Duration::hours(24);
         
+        let log = &log.entries;
         let total_entries = log.len();
         let recent_entries = log.iter().filter(|e| e.timestamp >= last_24h).count();
         
@@ -496,6 +762,23 @@ impl AuditTrail {
             }
         }
         
+        if let Some(hash) = query.transaction_hash {
+            if entry.transaction_hash != Some(hash) {
+                return false;
+            }
+        }
+        
+        // Actor check: an address the entry is about, or an operator named in its flags
+        if let Some(actor) = &query.actor {
+            let matches = match actor.parse::<Address>() {
+                Ok(addr) => entry.user_address == Some(addr),
+                Err(_) => entry.security_flags.iter().any(|flag| flag.strip_prefix("actor:") == Some(actor.as_str())),
+            };
+            if !matches {
+                return false;
+            }
+        }
+        
         // Risk score checks
         if let Some(min_risk) = query.risk_score_min {
             if entry.risk_score.unwrap_or(0.0) < min_risk {
@@ -520,26 +803,37 @@ impl AuditTrail {
     }
 
     /// Apply retention policy to log
-    async fn apply_retention_policy(&self, log: &mut VecDeque<AuditEntry>) -> Result<()> {
+    ///
+    /// Only the oldest entries are pruned, so the retained entries stay one unbroken chain anchored
+    /// at the `prev_hash` of the first of them.
+    async fn apply_retention_policy(&self, log: &mut AuditLog) -> Result<()> {
         let policy = self.retention_policy.read().await;
-        let cutoff_time = Utc::now() - chrono::Duration::days(policy.default_retention_days);
-        
-        while let Some(entry) = log.front() {
-            if entry.timestamp < cutoff_time {
-                // Check if entry should be retained longer
-                let should_retain = entry.risk_score.unwrap_or(0.0) > 0.7 || 
-                                   matches!(entry.entry_type, AuditEntryType::SecurityViolation);
-                
-                if !should_retain {
-                    log.pop_front();
-                } else {
-                    break;
-                }
+        let now = Utc::now();
+        let cutoff_time = now - chrono::Duration::days(policy.default_retention_days);
+        let high_risk_cutoff_time = now - chrono::Duration::days(policy.high_risk_retention_days);
+        
+        let mut pruned = 0;
+        while let Some(entry) = log.entries.front() {
+            // Check if entry should be retained longer
+            let should_retain = entry.risk_score.unwrap_or(0.0) > HIGH_RISK_SCORE || 
+                               matches!(entry.entry_type, AuditEntryType::SecurityViolation);
+            let cutoff = if should_retain { high_risk_cutoff_time } else { cutoff_time };
+            
+            if entry.timestamp < cutoff {
+                log.entries.pop_front();
+                pruned += 1;
             } else {
                 break;
             }
         }
         
+        if pruned > 0 {
+            tracing::info!("Audit retention pruned {} entries", pruned);
+            if let Some(path) = &self.store_path {
+                rewrite_store(path, &log.entries).await?;
+            }
+        }
+        
         Ok(())
     }
 
@@ -622,8 +916,23 @@ impl AuditTrail {
         Ok(())
     }
 
-    async fn persist_to_backend(&self, _entry: &AuditEntry) -> Result<()> {
-        // Would persist to configured storage backend
+    /// Append the entry as one line of the store
+    async fn persist_to_backend(&self, entry: &AuditEntry) -> Result<()> {
+        let Some(path) = &self.store_path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
         Ok(())
     }
 
@@ -645,12 +954,33 @@ impl AuditTrail {
     }
 }
 
+/// Keccak-256 of the entry's JSON with `hash` zeroed
+fn entry_hash(entry: &AuditEntry) -> Result<H256> {
+    let mut unsealed = entry.clone();
+    unsealed.hash = H256::zero();
+    Ok(H256::from(keccak256(serde_json::to_vec(&unsealed)?)))
+}
+
+/// Replace the store with the retained entries, written to a temporary file first
+async fn rewrite_store(path: &Path, entries: &VecDeque<AuditEntry>) -> Result<()> {
+    let mut contents = String::new();
+    for entry in entries {
+        contents.push_str(&serde_json::to_string(entry)?);
+        contents.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp, contents).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuditStats {
     pub total_entries: usize,
     pub entries_last_24h: usize,
     pub high_risk_entries: usize,
     pub security_events: usize,
+    #[serde(with = "crate::api::models::sorted_map")]
     pub entry_type_counts: HashMap<String, usize>,
     pub oldest_entry: Option<DateTime<Utc>>,
     pub newest_entry: Option<DateTime<Utc>>,
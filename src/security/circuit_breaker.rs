@@ -0,0 +1,394 @@
+// Circuit breakers halting trading when threats, failed simulations, gas spikes or oracle deviations pile up
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use super::audit_trail::{AuditEntryType, AuditTrail};
+use super::oracle_security::{PriceDeviation, TradePriceCheck};
+
+/// Gas prices are sampled at most this often per chain, so busy mempools do not crowd out the baseline
+const GAS_SAMPLE_INTERVAL_SECS: i64 = 1;
+/// Samples needed both in the window and before it before gas prices are compared
+const MIN_GAS_SAMPLES: usize = 10;
+
+/// Condition a breaker watches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerKind {
+    /// Sum of the risk scores of detected threats
+    ThreatScore,
+    FailedSimulations,
+    /// Median gas price of the window relative to the median before it
+    GasSpike,
+    /// Trades refused for deviating from the oracles
+    OracleDeviation,
+}
+
+impl fmt::Display for BreakerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BreakerKind::ThreatScore => "threat_score",
+            BreakerKind::FailedSimulations => "failed_simulations",
+            BreakerKind::GasSpike => "gas_spike",
+            BreakerKind::OracleDeviation => "oracle_deviation",
+        })
+    }
+}
+
+/// Thresholds of the breakers, each disabled by a zero
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Period the threat, simulation and oracle counts and the recent gas prices cover
+    pub window: Duration,
+    pub max_threat_score: f64,
+    pub max_failed_simulations: usize,
+    /// Ratio of the window's median gas price to the earlier median that counts as a spike
+    pub gas_spike_multiplier: f64,
+    pub max_oracle_deviations: usize,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::minutes(5),
+            max_threat_score: 5.0,
+            max_failed_simulations: 5,
+            gas_spike_multiplier: 3.0,
+            max_oracle_deviations: 3,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Thresholds from `circuit_breaker_window_secs`, `circuit_breaker_max_threat_score`,
+    /// `circuit_breaker_max_failed_simulations`, `circuit_breaker_gas_spike_multiplier` and
+    /// `circuit_breaker_max_oracle_deviations`
+    pub fn from_config(config: &config::Config) -> Self {
+        let defaults = Self::default();
+        let count = |key: &str, default: usize| config
+            .get_int(key)
+            .map(|value| value.max(0) as usize)
+            .unwrap_or(default);
+        Self {
+            window: config
+                .get_int("circuit_breaker_window_secs")
+                .map(|secs| Duration::seconds(secs.max(1)))
+                .unwrap_or(defaults.window),
+            max_threat_score: config
+                .get_float("circuit_breaker_max_threat_score")
+                .map(|value| value.max(0.0))
+                .unwrap_or(defaults.max_threat_score),
+            max_failed_simulations: count("circuit_breaker_max_failed_simulations", defaults.max_failed_simulations),
+            gas_spike_multiplier: config
+                .get_float("circuit_breaker_gas_spike_multiplier")
+                .map(|value| value.max(0.0))
+                .unwrap_or(defaults.gas_spike_multiplier),
+            max_oracle_deviations: count("circuit_breaker_max_oracle_deviations", defaults.max_oracle_deviations),
+        }
+    }
+}
+
+/// Why trading was halted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradingHalt {
+    pub breaker: BreakerKind,
+    pub reason: String,
+    pub observed: f64,
+    pub threshold: f64,
+    pub tripped_at: DateTime<Utc>,
+}
+
+/// Current reading of one breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerState {
+    pub breaker: BreakerKind,
+    pub enabled: bool,
+    pub observed: f64,
+    pub threshold: f64,
+}
+
+/// Whether trading is halted and how close each breaker is to tripping
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerStatus {
+    pub halted: bool,
+    pub halt: Option<TradingHalt>,
+    pub window_secs: i64,
+    pub breakers: Vec<BreakerState>,
+}
+
+/// A trading operation refused while a breaker is tripped
+#[derive(Debug, Clone)]
+pub struct TradingHalted(pub TradingHalt);
+
+impl fmt::Display for TradingHalted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Trading halted since {} by the {} circuit breaker: {}",
+            self.0.tripped_at.to_rfc3339(),
+            self.0.breaker,
+            self.0.reason,
+        )
+    }
+}
+
+impl std::error::Error for TradingHalted {}
+
+/// Events inside the window and the halt they caused
+#[derive(Default)]
+struct BreakerEvents {
+    threats: VecDeque<(DateTime<Utc>, f64)>,
+    failed_simulations: VecDeque<DateTime<Utc>>,
+    oracle_deviations: VecDeque<DateTime<Utc>>,
+    gas_prices: HashMap<u64, VecDeque<(DateTime<Utc>, f64)>>,
+    halt: Option<TradingHalt>,
+}
+
+/// Trips into a halt that holds until an operator resets it
+pub struct CircuitBreakers {
+    config: CircuitBreakerConfig,
+    audit_trail: Arc<AuditTrail>,
+    events: RwLock<BreakerEvents>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: CircuitBreakerConfig, audit_trail: Arc<AuditTrail>) -> Self {
+        info!(
+            "Circuit breakers over {}s: threat score {}, failed simulations {}, gas x{}, oracle deviations {}",
+            config.window.num_seconds(),
+            config.max_threat_score,
+            config.max_failed_simulations,
+            config.gas_spike_multiplier,
+            config.max_oracle_deviations,
+        );
+        Self { config, audit_trail, events: RwLock::new(BreakerEvents::default()) }
+    }
+
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    /// Halt in force, if any
+    pub async fn halt(&self) -> Option<TradingHalt> {
+        self.events.read().await.halt.clone()
+    }
+
+    /// Fail with `TradingHalted` while a breaker is tripped
+    pub async fn ensure_trading(&self) -> Result<()> {
+        match self.halt().await {
+            Some(halt) => Err(TradingHalted(halt).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the risk score of a detected threat
+    pub async fn record_threat(&self, score: f64) -> Result<()> {
+        if self.config.max_threat_score <= 0.0 || score <= 0.0 {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let total = {
+            let mut events = self.events.write().await;
+            events.threats.push_back((now, score));
+            prune(&mut events.threats, now - self.config.window, |(at, _)| *at);
+            events.threats.iter().map(|(_, score)| score).sum::<f64>()
+        };
+        if total >= self.config.max_threat_score {
+            self.trip(
+                BreakerKind::ThreatScore,
+                format!("threat score {:.2} within {}s", total, self.config.window.num_seconds()),
+                total,
+                self.config.max_threat_score,
+            ).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn record_failed_simulation(&self) -> Result<()> {
+        let limit = self.config.max_failed_simulations;
+        if limit == 0 {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let failed = {
+            let mut events = self.events.write().await;
+            events.failed_simulations.push_back(now);
+            prune(&mut events.failed_simulations, now - self.config.window, |at| *at);
+            events.failed_simulations.len()
+        };
+        if failed >= limit {
+            self.trip(
+                BreakerKind::FailedSimulations,
+                format!("{} failed simulations within {}s", failed, self.config.window.num_seconds()),
+                failed as f64,
+                limit as f64,
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Add a gas price seen on a chain
+    pub async fn record_gas_price(&self, chain_id: u64, gas_price: U256) -> Result<()> {
+        let gwei = gas_price.low_u128() as f64 / 1e9;
+        if self.config.gas_spike_multiplier <= 0.0 || gwei <= 0.0 {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let ratio = {
+            let mut events = self.events.write().await;
+            let samples = events.gas_prices.entry(chain_id).or_default();
+            if samples.back().is_some_and(|(at, _)| now - *at < Duration::seconds(GAS_SAMPLE_INTERVAL_SECS)) {
+                return Ok(());
+            }
+            samples.push_back((now, gwei));
+            // The window's samples plus one window before it as the baseline
+            prune(samples, now - self.config.window * 2, |(at, _)| *at);
+            gas_ratio(samples, now - self.config.window)
+        };
+        if let Some(ratio) = ratio.filter(|ratio| *ratio >= self.config.gas_spike_multiplier) {
+            self.trip(
+                BreakerKind::GasSpike,
+                format!("gas prices on chain {} at {:.1}x their earlier median", chain_id, ratio),
+                ratio,
+                self.config.gas_spike_multiplier,
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Count a trade refused for deviating from the oracles
+    pub async fn record_oracle_deviation(&self) -> Result<()> {
+        let limit = self.config.max_oracle_deviations;
+        if limit == 0 {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let deviations = {
+            let mut events = self.events.write().await;
+            events.oracle_deviations.push_back(now);
+            prune(&mut events.oracle_deviations, now - self.config.window, |at| *at);
+            events.oracle_deviations.len()
+        };
+        if deviations >= limit {
+            self.trip(
+                BreakerKind::OracleDeviation,
+                format!("{} trades beyond the oracle tolerance within {}s", deviations, self.config.window.num_seconds()),
+                deviations as f64,
+                limit as f64,
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Pass a trade price validation through, counting a `PriceDeviation` refusal
+    pub async fn observe_trade(&self, result: Result<TradePriceCheck>) -> Result<TradePriceCheck> {
+        if let Err(e) = &result {
+            if e.downcast_ref::<PriceDeviation>().is_some_and(|deviation| deviation.0.deviation_percentage.is_some()) {
+                if let Err(record_error) = self.record_oracle_deviation().await {
+                    warn!("Failed to record oracle deviation: {}", record_error);
+                }
+            }
+        }
+        result
+    }
+
+    /// Halt trading, keeping the first halt when one is already in force
+    pub async fn trip(&self, breaker: BreakerKind, reason: String, observed: f64, threshold: f64) -> Result<()> {
+        let halt = {
+            let mut events = self.events.write().await;
+            if events.halt.is_some() {
+                return Ok(());
+            }
+            let halt = TradingHalt { breaker, reason, observed, threshold, tripped_at: Utc::now() };
+            events.halt = Some(halt.clone());
+            halt
+        };
+        warn!("Circuit breaker {} tripped, trading halted: {}", breaker, halt.reason);
+        self.audit_trail.log_security_event(
+            AuditEntryType::EmergencyAction,
+            None,
+            format!("Trading halted by the {} circuit breaker: {}", breaker, halt.reason),
+            1.0,
+            vec!["circuit_breaker".to_string(), breaker.to_string()],
+        ).await
+    }
+
+    /// Lift the halt and clear the windows so the same events do not trip it again, returning the lifted halt
+    pub async fn reset(&self) -> Option<TradingHalt> {
+        let mut events = self.events.write().await;
+        let halt = events.halt.take();
+        events.threats.clear();
+        events.failed_simulations.clear();
+        events.oracle_deviations.clear();
+        events.gas_prices.clear();
+        if let Some(halt) = &halt {
+            info!("Circuit breaker {} reset, trading resumed", halt.breaker);
+        }
+        halt
+    }
+
+    pub async fn status(&self) -> CircuitBreakerStatus {
+        let cutoff = Utc::now() - self.config.window;
+        let events = self.events.read().await;
+        let threat_score = events.threats.iter()
+            .filter(|(at, _)| *at >= cutoff)
+            .map(|(_, score)| score)
+            .sum::<f64>();
+        let failed_simulations = events.failed_simulations.iter().filter(|at| **at >= cutoff).count();
+        let oracle_deviations = events.oracle_deviations.iter().filter(|at| **at >= cutoff).count();
+        let gas_ratio = events.gas_prices.values()
+            .filter_map(|samples| gas_ratio(samples, cutoff))
+            .fold(0.0, f64::max);
+
+        let breaker = |breaker, observed: f64, threshold: f64| BreakerState {
+            breaker,
+            enabled: threshold > 0.0,
+            observed,
+            threshold,
+        };
+        CircuitBreakerStatus {
+            halted: events.halt.is_some(),
+            halt: events.halt.clone(),
+            window_secs: self.config.window.num_seconds(),
+            breakers: vec![
+                breaker(BreakerKind::ThreatScore, threat_score, self.config.max_threat_score),
+                breaker(BreakerKind::FailedSimulations, failed_simulations as f64, self.config.max_failed_simulations as f64),
+                breaker(BreakerKind::GasSpike, gas_ratio, self.config.gas_spike_multiplier),
+                breaker(BreakerKind::OracleDeviation, oracle_deviations as f64, self.config.max_oracle_deviations as f64),
+            ],
+        }
+    }
+}
+
+/// Drop the events older than `cutoff` from the front of a queue
+fn prune<T>(events: &mut VecDeque<T>, cutoff: DateTime<Utc>, at: impl Fn(&T) -> DateTime<Utc>) {
+    while events.front().is_some_and(|event| at(event) < cutoff) {
+        events.pop_front();
+    }
+}
+
+/// Median gas price since `cutoff` over the median before it, `None` without enough samples on both sides
+fn gas_ratio(samples: &VecDeque<(DateTime<Utc>, f64)>, cutoff: DateTime<Utc>) -> Option<f64> {
+    let (baseline, recent): (Vec<_>, Vec<_>) = samples.iter().partition(|(at, _)| *at < cutoff);
+    if baseline.len() < MIN_GAS_SAMPLES || recent.len() < MIN_GAS_SAMPLES {
+        return None;
+    }
+    let baseline = median(baseline.into_iter().map(|&(_, gwei)| gwei).collect())?;
+    let recent = median(recent.into_iter().map(|&(_, gwei)| gwei).collect())?;
+    (baseline > 0.0).then(|| recent / baseline)
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_by(|a, b| a.total_cmp(b));
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[len / 2 - 1] + values[len / 2]) / 2.0),
+        len => Some(values[len / 2]),
+    }
+}
@@ -0,0 +1,184 @@
+// Static analysis of the contract a transaction calls: its bytecode and the calldata sent to it
+use anyhow::Result;
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use super::defi_security::read_proxy;
+use super::input_sanitizer::InputSanitizer;
+use super::reentrancy_guard::ReentrancyGuard;
+use crate::cache::{CacheManager, TimedCache};
+use crate::chains::ChainManager;
+
+/// Bytecode reports are reused this long, code only changes through a proxy upgrade or redeployment
+const CODE_REPORT_TTL: Duration = Duration::from_secs(600);
+const MAX_CACHED_REPORTS: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeRisk {
+    /// Worth knowing, not a threat
+    Low,
+    Medium,
+    High,
+    /// The transaction should not be sent
+    Critical,
+}
+
+impl CodeRisk {
+    /// Contribution of a finding to a transaction's risk score
+    pub fn score(&self) -> f64 {
+        match self {
+            CodeRisk::Low => 0.0,
+            CodeRisk::Medium => 0.2,
+            CodeRisk::High => 0.5,
+            CodeRisk::Critical => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeCheck {
+    SelfDestruct,
+    /// DELEGATECALL to an address neither fixed in the code nor kept in a proxy slot
+    DelegatecallToUnknown,
+    UpgradeableProxy,
+    Callcode,
+    /// Storage written after an external call without a write before it, the shape of a reentrancy bug
+    StateWriteAfterCall,
+    TxOrigin,
+    /// Calldata sent to an address without code
+    NoCode,
+    MaliciousSelector,
+    UnlimitedApproval,
+    /// `setApprovalForAll` granting an operator every token of a collection
+    OperatorApproval,
+    UnusualCalldata,
+    OversizedCalldata,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeFinding {
+    pub check: CodeCheck,
+    pub risk: CodeRisk,
+    /// Contract whose code the finding is about, `None` for calldata findings
+    pub contract: Option<Address>,
+    pub detail: String,
+}
+
+/// Findings in the code of a contract and of the implementation it delegates to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeReport {
+    pub contract: Address,
+    pub has_code: bool,
+    /// Implementation of a proxy, or target of a minimal proxy
+    pub implementation: Option<Address>,
+    pub findings: Vec<CodeFinding>,
+}
+
+/// Static analysis of a transaction's target and calldata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractAnalysis {
+    pub chain_id: u64,
+    pub target: Address,
+    pub has_code: bool,
+    pub implementation: Option<Address>,
+    /// Highest risk among the findings, `low` when there are none
+    pub risk: CodeRisk,
+    pub findings: Vec<CodeFinding>,
+}
+
+/// Runs the bytecode heuristics of the reentrancy guard and the calldata checks of the input sanitizer
+/// against the contracts transactions call
+pub struct ContractAnalyzer {
+    chain_manager: Arc<ChainManager>,
+    reentrancy_guard: ReentrancyGuard,
+    input_sanitizer: InputSanitizer,
+    reports: TimedCache<(u64, Address), CodeReport>,
+}
+
+impl ContractAnalyzer {
+    pub fn new(chain_manager: Arc<ChainManager>, caches: &CacheManager) -> Self {
+        Self {
+            chain_manager,
+            reentrancy_guard: ReentrancyGuard::new(),
+            input_sanitizer: InputSanitizer::new(),
+            reports: caches.timed("contract_code", CODE_REPORT_TTL, MAX_CACHED_REPORTS),
+        }
+    }
+
+    /// Analyze the code of `target` and the calldata a transaction sends it
+    pub async fn analyze(&self, chain_id: u64, target: Address, data: &[u8]) -> Result<ContractAnalysis> {
+        let report = self.code_report(chain_id, target).await?;
+        let mut findings = report.findings;
+        findings.extend(self.input_sanitizer.analyze_call_data(data));
+        if !report.has_code && !data.is_empty() {
+            findings.push(CodeFinding {
+                check: CodeCheck::NoCode,
+                risk: CodeRisk::Medium,
+                contract: Some(target),
+                detail: format!("{:?} has no code on chain {}, the calldata will be ignored", target, chain_id),
+            });
+        }
+        findings.sort_by(|a, b| b.risk.cmp(&a.risk));
+
+        Ok(ContractAnalysis {
+            chain_id,
+            target,
+            has_code: report.has_code,
+            implementation: report.implementation,
+            risk: findings.first().map(|finding| finding.risk).unwrap_or(CodeRisk::Low),
+            findings,
+        })
+    }
+
+    /// Bytecode findings of a contract, reused while recent
+    pub async fn code_report(&self, chain_id: u64, contract: Address) -> Result<CodeReport> {
+        self.reports.get_or_load((chain_id, contract), || self.scan(chain_id, contract)).await
+    }
+
+    async fn scan(&self, chain_id: u64, contract: Address) -> Result<CodeReport> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let provider = Arc::new(chain.provider.clone());
+        let code = provider.get_code(contract, None).await?;
+        if code.is_empty() {
+            return Ok(CodeReport { contract, has_code: false, implementation: None, findings: Vec::new() });
+        }
+        debug!("Analyzing the bytecode of {:?} on chain {}", contract, chain_id);
+
+        let analysis = self.reentrancy_guard.analyze_bytecode(contract, &code);
+        let mut findings = analysis.findings;
+        let mut implementation = analysis.minimal_proxy_target;
+        if implementation.is_none() && analysis.dynamic_delegatecall {
+            match read_proxy(&provider, contract).await {
+                Ok(Some(proxy)) => {
+                    findings.push(CodeFinding {
+                        check: CodeCheck::UpgradeableProxy,
+                        risk: CodeRisk::Low,
+                        contract: Some(contract),
+                        detail: format!("Upgradeable proxy, its implementation {:?} can be replaced", proxy.implementation),
+                    });
+                    implementation = Some(proxy.implementation);
+                }
+                Ok(None) => findings.push(CodeFinding {
+                    check: CodeCheck::DelegatecallToUnknown,
+                    risk: CodeRisk::Medium,
+                    contract: Some(contract),
+                    detail: "Delegates calls to an address that is neither fixed in its code nor kept in a standard proxy slot".to_string(),
+                }),
+                Err(e) => warn!("Failed to read the proxy slots of {:?} on chain {}: {}", contract, chain_id, e),
+            }
+        }
+
+        // The code a proxy runs is its implementation's
+        if let Some(implementation) = implementation {
+            let code = provider.get_code(implementation, None).await?;
+            findings.extend(self.reentrancy_guard.analyze_bytecode(implementation, &code).findings);
+        }
+
+        Ok(CodeReport { contract, has_code: true, implementation, findings })
+    }
+}
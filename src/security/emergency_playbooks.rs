@@ -0,0 +1,328 @@
+// Emergency playbooks turning critical alerts into protective transactions queued for signing
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, TransactionRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use super::approval_scanner::{ApprovalRisk, ApprovalScanner, RevokeScope};
+use super::audit_trail::{AuditEntryType, AuditTrail};
+use super::emergency_response::{EmergencyAlert, EmergencyLevel};
+use crate::defi::closeout::CloseoutAction;
+use crate::defi::collateral_optimizer::LendingMarket;
+use crate::defi::protection::ProtectionPlan;
+use crate::defi::DefiManager;
+use crate::transactions::TransactionTracker;
+
+/// Chains protected unless `emergency_playbook_chain_ids` is set
+const DEFAULT_CHAIN_IDS: [u64; 1] = [1];
+/// Health factor debts are repaid towards unless `emergency_target_health_factor` is set
+const DEFAULT_TARGET_HEALTH_FACTOR: f64 = 1.5;
+/// Runs kept for the API, the oldest are dropped first
+const MAX_RUNS: usize = 200;
+/// Source of the queued transaction records
+const RECORD_SOURCE: &str = "emergency:playbook";
+
+/// Protective action a playbook takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybookAction {
+    /// Reset approvals granted to compromised contracts and risky spenders
+    RevokeApprovals,
+    /// Repay debt to lift a lending market's health factor, or all of it before leaving the market
+    RepayDebt,
+    /// Withdraw every supply from a compromised lending market
+    WithdrawFromProtocol,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookConfig {
+    /// Run the playbooks on critical and emergency alerts
+    pub enabled: bool,
+    /// Build the transactions without queueing them, unless an alert says otherwise
+    pub dry_run: bool,
+    pub target_health_factor: f64,
+    pub chain_ids: Vec<u64>,
+}
+
+impl Default for PlaybookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dry_run: false,
+            target_health_factor: DEFAULT_TARGET_HEALTH_FACTOR,
+            chain_ids: DEFAULT_CHAIN_IDS.to_vec(),
+        }
+    }
+}
+
+impl PlaybookConfig {
+    /// Playbooks from `emergency_playbooks_enabled`, `emergency_playbooks_dry_run`,
+    /// `emergency_target_health_factor` and the comma-separated `emergency_playbook_chain_ids`
+    pub fn from_config(config: &config::Config) -> Self {
+        let defaults = Self::default();
+        let chain_ids: Vec<u64> = config
+            .get_string("emergency_playbook_chain_ids")
+            .map(|chain_ids| chain_ids.split(',').filter_map(|chain_id| chain_id.trim().parse().ok()).collect())
+            .unwrap_or_default();
+        Self {
+            enabled: config.get_bool("emergency_playbooks_enabled").unwrap_or(defaults.enabled),
+            dry_run: config.get_bool("emergency_playbooks_dry_run").unwrap_or(defaults.dry_run),
+            target_health_factor: config
+                .get_float("emergency_target_health_factor")
+                .ok()
+                .filter(|factor| *factor > 1.0)
+                .unwrap_or(defaults.target_health_factor),
+            chain_ids: if chain_ids.is_empty() { defaults.chain_ids } else { chain_ids },
+        }
+    }
+}
+
+/// Transactions one action produced on one chain, to be signed in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookStep {
+    pub action: PlaybookAction,
+    pub chain_id: u64,
+    pub description: String,
+    pub transactions: Vec<TransactionRequest>,
+    /// Tracked transaction records awaiting a signature, empty on a dry run
+    pub record_ids: Vec<String>,
+}
+
+/// What the playbooks prepared for one affected wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookRun {
+    pub id: String,
+    pub alert_id: String,
+    pub alert_title: String,
+    pub wallet: Address,
+    pub dry_run: bool,
+    pub steps: Vec<PlaybookStep>,
+    /// Health factors before and after the repayments, per chain
+    pub protection: Vec<ProtectionPlan>,
+    /// Execution id of the queued transactions per chain
+    pub execution_ids: BTreeMap<u64, String>,
+    pub warnings: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PlaybookRun {
+    pub fn transaction_count(&self) -> usize {
+        self.steps.iter().map(|step| step.transactions.len()).sum()
+    }
+}
+
+/// Builds and queues protective transactions for the wallets critical alerts name
+pub struct EmergencyPlaybooks {
+    config: PlaybookConfig,
+    approvals: Arc<ApprovalScanner>,
+    defi: Arc<DefiManager>,
+    transactions: Arc<TransactionTracker>,
+    audit_trail: Arc<AuditTrail>,
+    runs: RwLock<VecDeque<PlaybookRun>>,
+}
+
+impl EmergencyPlaybooks {
+    pub fn new(
+        config: PlaybookConfig,
+        approvals: Arc<ApprovalScanner>,
+        defi: Arc<DefiManager>,
+        transactions: Arc<TransactionTracker>,
+        audit_trail: Arc<AuditTrail>,
+    ) -> Self {
+        info!(
+            "Emergency playbooks {} on chains {:?}{}",
+            if config.enabled { "enabled" } else { "disabled" },
+            config.chain_ids,
+            if config.dry_run { " in dry-run mode" } else { "" },
+        );
+        Self { config, approvals, defi, transactions, audit_trail, runs: RwLock::new(VecDeque::new()) }
+    }
+
+    pub fn config(&self) -> &PlaybookConfig {
+        &self.config
+    }
+
+    /// Run the playbooks for each affected address of a critical or emergency alert, treating
+    /// affected protocols named `aave` or `compound` as markets to leave and affected contract
+    /// addresses as compromised spenders; `dry_run` overrides the configured mode
+    pub async fn respond(&self, alert: &EmergencyAlert, dry_run: Option<bool>) -> Vec<PlaybookRun> {
+        if !self.config.enabled || !matches!(alert.level, EmergencyLevel::Critical | EmergencyLevel::Emergency) {
+            return Vec::new();
+        }
+        let dry_run = dry_run.unwrap_or(self.config.dry_run);
+
+        let mut exit_markets = Vec::new();
+        let mut compromised = HashSet::new();
+        let mut unknown = Vec::new();
+        for protocol in &alert.affected_protocols {
+            match protocol.trim().to_lowercase().as_str() {
+                "aave" => exit_markets.push(LendingMarket::Aave),
+                "compound" => exit_markets.push(LendingMarket::Compound),
+                other => match other.parse::<Address>() {
+                    Ok(contract) => {
+                        compromised.insert(contract);
+                    }
+                    Err(_) => unknown.push(protocol.clone()),
+                },
+            }
+        }
+
+        let mut wallets = alert.affected_addresses.clone();
+        wallets.sort();
+        wallets.dedup();
+        let mut runs = Vec::with_capacity(wallets.len());
+        for wallet in wallets {
+            let mut run = self.run(alert, wallet, &exit_markets, &compromised, dry_run).await;
+            run.warnings.extend(unknown.iter().map(|protocol| format!("Unknown affected protocol {}, no action taken", protocol)));
+            if let Err(e) = self.record(&run).await {
+                warn!("Failed to audit-log playbook run {}: {}", run.id, e);
+            }
+            runs.push(run);
+        }
+
+        let mut stored = self.runs.write().await;
+        stored.extend(runs.iter().cloned());
+        while stored.len() > MAX_RUNS {
+            stored.pop_front();
+        }
+        runs
+    }
+
+    /// Runs so far, newest first, optionally only those of one alert
+    pub async fn runs(&self, alert_id: Option<&str>) -> Vec<PlaybookRun> {
+        self.runs.read().await.iter()
+            .rev()
+            .filter(|run| alert_id.is_none_or(|alert_id| run.alert_id == alert_id))
+            .cloned()
+            .collect()
+    }
+
+    async fn run(
+        &self,
+        alert: &EmergencyAlert,
+        wallet: Address,
+        exit_markets: &[LendingMarket],
+        compromised: &HashSet<Address>,
+        dry_run: bool,
+    ) -> PlaybookRun {
+        let mut steps = Vec::new();
+        let mut protection = Vec::new();
+        let mut warnings = Vec::new();
+
+        // Approvals first, so a compromised spender cannot drain what the repayments leave behind
+        let report = self.approvals.scan(wallet, Some(self.config.chain_ids.clone()), RevokeScope::None).await;
+        warnings.extend(report.errors.iter()
+            .map(|error| format!("Approval scan on chain {} failed: {}", error.chain_id, error.error)));
+        let mut revokes: BTreeMap<u64, Vec<TransactionRequest>> = BTreeMap::new();
+        for approval in report.approvals.iter()
+            .filter(|approval| compromised.contains(&approval.spender) || approval.risk == ApprovalRisk::High)
+        {
+            revokes.entry(approval.chain_id).or_default().push(approval.revoke.clone());
+        }
+        for (chain_id, transactions) in revokes {
+            steps.push(PlaybookStep {
+                action: PlaybookAction::RevokeApprovals,
+                chain_id,
+                description: format!("Revoke {} compromised or high-risk approvals", transactions.len()),
+                transactions,
+                record_ids: Vec::new(),
+            });
+        }
+
+        for chain_id in &self.config.chain_ids {
+            let plan = match self.defi.plan_protection(*chain_id, wallet, self.config.target_health_factor, exit_markets).await {
+                Ok(plan) => plan,
+                Err(e) => {
+                    warnings.push(format!("Lending positions on chain {} could not be protected: {}", chain_id, e));
+                    continue;
+                }
+            };
+            for step in &plan.steps {
+                let (action, description) = match &step.action {
+                    CloseoutAction::Repay { market, asset, amount } => (
+                        PlaybookAction::RepayDebt,
+                        format!("Repay {} of {:?} on {:?}", amount, asset, market),
+                    ),
+                    CloseoutAction::Withdraw { market, asset, amount } => (
+                        PlaybookAction::WithdrawFromProtocol,
+                        format!("Withdraw {} of {:?} from {:?}", amount, asset, market),
+                    ),
+                    _ => continue,
+                };
+                steps.push(PlaybookStep {
+                    action,
+                    chain_id: *chain_id,
+                    description,
+                    transactions: step.transactions.clone(),
+                    record_ids: Vec::new(),
+                });
+            }
+            warnings.extend(plan.warnings.iter().map(|warning| format!("Chain {}: {}", chain_id, warning)));
+            if !plan.markets.is_empty() {
+                protection.push(plan);
+            }
+        }
+
+        let mut execution_ids = BTreeMap::new();
+        if !dry_run {
+            let chain_ids: BTreeSet<u64> = steps.iter().map(|step| step.chain_id).collect();
+            for chain_id in chain_ids {
+                let transactions: Vec<TransactionRequest> = steps.iter()
+                    .filter(|step| step.chain_id == chain_id)
+                    .flat_map(|step| step.transactions.iter().cloned())
+                    .collect();
+                let records = self.transactions.record_built_all(chain_id, Some(wallet), RECORD_SOURCE, &transactions).await;
+                if let Some(execution_id) = records.first().and_then(|record| record.execution_id.clone()) {
+                    execution_ids.insert(chain_id, execution_id);
+                }
+                let mut records = records.into_iter();
+                for step in steps.iter_mut().filter(|step| step.chain_id == chain_id) {
+                    step.record_ids = records.by_ref().take(step.transactions.len()).map(|record| record.id).collect();
+                }
+            }
+        }
+
+        PlaybookRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            alert_id: alert.id.clone(),
+            alert_title: alert.title.clone(),
+            wallet,
+            dry_run,
+            steps,
+            protection,
+            execution_ids,
+            warnings,
+            created_at: Utc::now(),
+        }
+    }
+
+    async fn record(&self, run: &PlaybookRun) -> Result<()> {
+        let actions: Vec<String> = run.steps.iter()
+            .map(|step| format!("{} on chain {}", step.description, step.chain_id))
+            .collect();
+        let actions = if actions.is_empty() {
+            "no protective transactions needed".to_string()
+        } else {
+            actions.join("; ")
+        };
+        let outcome = if run.dry_run { "dry_run" } else { "queued" };
+        info!(
+            "Playbook for alert {} {} {} transactions for {:?}",
+            run.alert_id,
+            if run.dry_run { "prepared" } else { "queued" },
+            run.transaction_count(),
+            run.wallet,
+        );
+        self.audit_trail.log_security_event(
+            AuditEntryType::EmergencyAction,
+            Some(run.wallet),
+            format!("Playbook for alert {} ({}): {}", run.alert_id, outcome, actions),
+            0.8,
+            vec!["emergency_playbook".to_string(), outcome.to_string()],
+        ).await
+    }
+}
@@ -1,55 +1,128 @@
 use anyhow::Result;
+use ethers::types::U256;
+use ethers::utils::{hex, id};
 use std::collections::HashSet;
 
+use super::approval_scanner::UNLIMITED_ALLOWANCE_BITS;
+use super::contract_analysis::{CodeCheck, CodeFinding, CodeRisk};
+
+/// Functions of wallet drainer contracts, named to pass as routine claims or security fixes and
+/// made payable so the victim sends their Ether along
+const DRAINER_SIGNATURES: [&str; 8] = [
+    "SecurityUpdate()",
+    "Claim()",
+    "ClaimReward()",
+    "ClaimRewards()",
+    "Connect()",
+    "NetworkMerge()",
+    "Verify()",
+    "Airdrop()",
+];
+/// `approve(address,uint256)`
+const APPROVE: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+/// `increaseAllowance(address,uint256)`
+const INCREASE_ALLOWANCE: [u8; 4] = [0x39, 0x50, 0x93, 0x51];
+/// `setApprovalForAll(address,bool)`
+const SET_APPROVAL_FOR_ALL: [u8; 4] = [0xa2, 0x2c, 0xb4, 0x65];
+
 #[derive(Debug)]
 pub struct InputSanitizer {
     max_data_size: usize,
-    blacklisted_opcodes: HashSet<u8>,
-    dangerous_patterns: Vec<Vec<u8>>,
+    malicious_selectors: HashSet<[u8; 4]>,
 }
 
 impl InputSanitizer {
     pub fn new() -> Self {
-        let mut blacklisted_opcodes = HashSet::new();
-        
-        // Add dangerous opcodes
-        blacklisted_opcodes.insert(0xff); // SELFDESTRUCT
-        blacklisted_opcodes.insert(0x32); // ORIGIN (can be dangerous in some contexts)
-        
-        let mut dangerous_patterns = Vec::new();
-        
-        // Add known malicious patterns
-        dangerous_patterns.push(b"selfdestruct".to_vec());
-        dangerous_patterns.push(b"delegatecall".to_vec());
-
         Self {
             max_data_size: 100_000, // 100KB max
-            blacklisted_opcodes,
-            dangerous_patterns,
+            malicious_selectors: DRAINER_SIGNATURES.iter().map(id).collect(),
         }
     }
 
+    /// Reject calldata with a finding of high risk or above
     pub fn validate_call_data(&self, data: &[u8]) -> Result<()> {
-        // Check data size
+        match self.analyze_call_data(data).into_iter().find(|finding| finding.risk >= CodeRisk::High) {
+            Some(finding) => Err(anyhow::anyhow!("{}", finding.detail)),
+            None => Ok(()),
+        }
+    }
+
+    /// Findings in the calldata of a contract call: drainer selectors, unlimited approvals and
+    /// arguments that no ABI encoder would produce
+    pub fn analyze_call_data(&self, data: &[u8]) -> Vec<CodeFinding> {
+        let finding = |check, risk, detail: String| CodeFinding { check, risk, contract: None, detail };
         if data.len() > self.max_data_size {
-            return Err(anyhow::anyhow!("Call data too large"));
+            return vec![finding(
+                CodeCheck::OversizedCalldata,
+                CodeRisk::High,
+                format!("Call data of {} bytes is over the {} byte limit", data.len(), self.max_data_size),
+            )];
+        }
+        if data.is_empty() {
+            return Vec::new();
+        }
+        if data.len() < 4 {
+            return vec![finding(
+                CodeCheck::UnusualCalldata,
+                CodeRisk::Medium,
+                format!("{} bytes of call data are too short for a function selector, only a fallback function can take them", data.len()),
+            )];
         }
 
-        // Check for dangerous opcodes
-        for &byte in data {
-            if self.blacklisted_opcodes.contains(&byte) {
-                return Err(anyhow::anyhow!("Dangerous opcode detected: {:#04x}", byte));
-            }
+        let mut findings = Vec::new();
+        let selector = [data[0], data[1], data[2], data[3]];
+        let args = &data[4..];
+        let word = |index: usize| args.get(index * 32..(index + 1) * 32);
+
+        if self.malicious_selectors.contains(&selector) {
+            findings.push(finding(
+                CodeCheck::MaliciousSelector,
+                CodeRisk::Critical,
+                format!("Selector 0x{} belongs to a known wallet drainer function", hex::encode(selector)),
+            ));
         }
 
-        // Check for dangerous patterns
-        for pattern in &self.dangerous_patterns {
-            if data.windows(pattern.len()).any(|window| window == pattern.as_slice()) {
-                return Err(anyhow::anyhow!("Dangerous pattern detected"));
+        match selector {
+            APPROVE | INCREASE_ALLOWANCE => {
+                if let Some(amount) = word(1).map(U256::from_big_endian) {
+                    if amount >= (U256::one() << UNLIMITED_ALLOWANCE_BITS) - 1 {
+                        findings.push(finding(
+                            CodeCheck::UnlimitedApproval,
+                            CodeRisk::Medium,
+                            "Grants the spender an unlimited allowance over the token".to_string(),
+                        ));
+                    }
+                }
+            }
+            SET_APPROVAL_FOR_ALL if word(1).is_some_and(|approved| approved[31] == 1) => {
+                findings.push(finding(
+                    CodeCheck::OperatorApproval,
+                    CodeRisk::Medium,
+                    "Lets the operator move every token of the collection".to_string(),
+                ));
             }
+            _ => {}
+        }
+        // Address arguments are left-padded with zeros, anything else there is a crafted call
+        if matches!(selector, APPROVE | INCREASE_ALLOWANCE | SET_APPROVAL_FOR_ALL)
+            && word(0).is_some_and(|address| address[..12].iter().any(|&byte| byte != 0))
+        {
+            findings.push(finding(
+                CodeCheck::UnusualCalldata,
+                CodeRisk::Medium,
+                "Address argument has non-zero padding bytes".to_string(),
+            ));
         }
 
-        Ok(())
+        if !args.len().is_multiple_of(32) {
+            findings.push(finding(
+                CodeCheck::UnusualCalldata,
+                CodeRisk::Low,
+                format!("{} argument bytes are not a whole number of 32-byte words", args.len()),
+            ));
+        }
+
+        findings
     }
 
     pub fn validate_message(&self, message: &[u8]) -> Result<()> {
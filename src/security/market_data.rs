@@ -0,0 +1,248 @@
+// Market data sampled for the risk engine: prices from the price feeds and liquidity from
+// Uniswap V2 pairs, with the rolling statistics computed from them
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::analytics::price_feeds::PriceFeedService;
+use crate::dex::DexManager;
+
+const DEFAULT_SAMPLE_INTERVAL_SECS: u64 = 300;
+const DEFAULT_WINDOW_SECS: u64 = 86_400;
+const DEFAULT_MAX_TOKENS: usize = 50;
+/// RiskMetrics decay of the exponentially weighted volatility forecast
+const EWMA_LAMBDA: f64 = 0.94;
+/// Price moves liquidity depth is reported at
+pub const DEPTH_PRICE_IMPACTS: [f64; 3] = [0.01, 0.02, 0.05];
+/// Fewest returns a volatility or correlation is computed from
+const MIN_RETURNS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct MarketDataConfig {
+    pub sample_interval: Duration,
+    /// Span of the samples volatility and correlations are computed over
+    pub window: Duration,
+    /// Tokens sampled from the start, besides those of analyzed transactions
+    pub tokens: Vec<(u64, Address)>,
+    /// Most tokens sampled at once; tokens seen in transactions past it are not tracked
+    pub max_tokens: usize,
+}
+
+impl Default for MarketDataConfig {
+    fn default() -> Self {
+        Self {
+            sample_interval: Duration::from_secs(DEFAULT_SAMPLE_INTERVAL_SECS),
+            window: Duration::from_secs(DEFAULT_WINDOW_SECS),
+            tokens: Vec::new(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+}
+
+impl MarketDataConfig {
+    /// Sampling every `risk_market_sample_interval_secs` (at least 30) over the last
+    /// `risk_volatility_window_secs`, of the comma-separated `chain_id:address` `risk_market_tokens`
+    /// and up to `risk_market_max_tokens` in all
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut market_config = Self::default();
+
+        if let Ok(secs) = config.get_int("risk_market_sample_interval_secs") {
+            market_config.sample_interval = Duration::from_secs(secs.max(30) as u64);
+        }
+        if let Ok(secs) = config.get_int("risk_volatility_window_secs") {
+            market_config.window = Duration::from_secs(secs.max(0) as u64);
+        }
+        // Volatility needs a few returns inside the window
+        market_config.window = market_config.window.max(market_config.sample_interval * (MIN_RETURNS as u32 + 1));
+        if let Ok(tokens) = config.get_string("risk_market_tokens") {
+            market_config.tokens = tokens.split(',').filter_map(|token| parse_token(token.trim())).collect();
+        }
+        if let Ok(max) = config.get_int("risk_market_max_tokens") {
+            market_config.max_tokens = max.max(1) as usize;
+        }
+
+        market_config
+    }
+
+    /// Samples kept per token to cover the window
+    pub fn window_samples(&self) -> usize {
+        (self.window.as_secs() / self.sample_interval.as_secs().max(1)) as usize + 1
+    }
+
+    /// Scale from the volatility of one sample interval to a daily volatility
+    fn daily_scale(&self) -> f64 {
+        (86_400.0 / self.sample_interval.as_secs_f64()).sqrt()
+    }
+}
+
+/// `chain_id:address` of a token
+fn parse_token(token: &str) -> Option<(u64, Address)> {
+    let (chain_id, address) = token.split_once(':')?;
+    Some((chain_id.trim().parse().ok()?, address.trim().parse().ok()?))
+}
+
+/// Where the risk engine samples market data from
+#[derive(Clone)]
+pub struct MarketDataSources {
+    pub price_feeds: Arc<PriceFeedService>,
+    pub dex: Arc<DexManager>,
+    pub config: MarketDataConfig,
+}
+
+/// Liquidity of a token's main Uniswap V2 pair
+#[derive(Debug, Clone, Copy)]
+pub struct PairLiquidity {
+    /// Reserve of the token, raw units
+    pub reserve: U256,
+    /// USD value of both sides of the pair
+    pub pool_value_usd: f64,
+}
+
+impl PairLiquidity {
+    /// USD that can be sold into the pair before its price moves by `price_impact`: the constant
+    /// product moves the price by `(x / (x + dx))^2`, so `dx = x * (1 / sqrt(1 - impact) - 1)`
+    pub fn depth_usd(&self, price_impact: f64) -> f64 {
+        self.pool_value_usd / 2.0 * (1.0 / (1.0 - price_impact).sqrt() - 1.0)
+    }
+}
+
+/// USD a trade can take from the pair before moving its price by a given fraction
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthLevel {
+    pub price_impact: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub amount_usd: f64,
+}
+
+/// The token's Uniswap V2 pair with the chain's wrapped gas token, or with USDC for the wrapped gas
+/// token itself, valued at the quote token's price
+pub async fn pair_liquidity(sources: &MarketDataSources, chain_id: u64, token: Address) -> Result<PairLiquidity> {
+    let assets = sources.dex.assets();
+    let wrapped = assets.wrapped_native(chain_id)
+        .ok_or_else(|| anyhow!("No wrapped gas token on chain {}", chain_id))?;
+    let quote = if wrapped.address == token {
+        assets.assets().iter()
+            .filter(|asset| asset.id == "usdc")
+            .flat_map(|asset| &asset.representations)
+            .find(|representation| representation.chain_id == chain_id)
+            .ok_or_else(|| anyhow!("No USDC on chain {}", chain_id))?
+    } else {
+        wrapped
+    };
+
+    let (reserve, quote_reserve) = sources.dex.uniswap_v2().get_reserves(chain_id, token, quote.address).await?;
+    let quote_price = sources.price_feeds.get_price(chain_id, quote.address).await?.price_usd;
+    let quote_value = u256_to_f64(quote_reserve) / 10f64.powi(quote.decimals as i32) * quote_price;
+    // Both sides of a constant product pair hold the same value
+    Ok(PairLiquidity { reserve, pool_value_usd: 2.0 * quote_value })
+}
+
+/// Latest market data of a token with its rolling statistics
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenMarketSnapshot {
+    pub chain_id: u64,
+    pub token: Address,
+    pub price_usd: f64,
+    /// Daily volatility of log returns over the window, `None` until there are enough samples
+    pub volatility: Option<f64>,
+    /// Exponentially weighted daily volatility, more responsive to recent moves
+    pub volatility_forecast: Option<f64>,
+    /// 95% confidence interval of `volatility`
+    pub volatility_interval: Option<(f64, f64)>,
+    pub samples: usize,
+    /// Reserve of the token in its main Uniswap V2 pair, raw units
+    pub liquidity: Option<U256>,
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub pool_value_usd: Option<f64>,
+    pub depth: Vec<DepthLevel>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Correlation of the log returns of two tokens over the window
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenCorrelation {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub correlation: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketRiskData {
+    pub tokens: Vec<TokenMarketSnapshot>,
+    pub correlations: Vec<TokenCorrelation>,
+}
+
+/// Rolling statistics of a price series
+#[derive(Debug, Clone, Copy)]
+pub struct VolatilityEstimate {
+    pub volatility: f64,
+    pub forecast: f64,
+    pub interval: (f64, f64),
+}
+
+/// Daily volatility of the log returns of `prices`, `None` with fewer than a few returns
+pub fn volatility(prices: &[f64], config: &MarketDataConfig) -> Option<VolatilityEstimate> {
+    let returns = log_returns(prices);
+    if returns.len() < MIN_RETURNS {
+        return None;
+    }
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let ewma = returns.iter().skip(1).fold(returns[0].powi(2), |variance, r| {
+        EWMA_LAMBDA * variance + (1.0 - EWMA_LAMBDA) * r * r
+    });
+
+    let scale = config.daily_scale();
+    let volatility = variance.sqrt() * scale;
+    // Standard error of a sample standard deviation is about sigma / sqrt(2(n - 1))
+    let margin = 1.96 * volatility / (2.0 * (n - 1.0)).sqrt();
+    Some(VolatilityEstimate {
+        volatility,
+        forecast: ewma.sqrt() * scale,
+        interval: ((volatility - margin).max(0.0), volatility + margin),
+    })
+}
+
+/// Pearson correlation of the log returns of two series sampled at the same ticks, over the
+/// ticks both have
+pub fn correlation(a: &VecDeque<(DateTime<Utc>, f64)>, b: &VecDeque<(DateTime<Utc>, f64)>) -> Option<f64> {
+    let b: HashMap<DateTime<Utc>, f64> = b.iter().copied().collect();
+    let (prices_a, prices_b): (Vec<f64>, Vec<f64>) = a.iter()
+        .filter_map(|(at, price)| b.get(at).map(|other| (*price, *other)))
+        .unzip();
+    let (returns_a, returns_b) = (log_returns(&prices_a), log_returns(&prices_b));
+    if returns_a.len() < MIN_RETURNS {
+        return None;
+    }
+
+    let n = returns_a.len() as f64;
+    let (mean_a, mean_b) = (returns_a.iter().sum::<f64>() / n, returns_b.iter().sum::<f64>() / n);
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (ra, rb) in returns_a.iter().zip(&returns_b) {
+        covariance += (ra - mean_a) * (rb - mean_b);
+        variance_a += (ra - mean_a).powi(2);
+        variance_b += (rb - mean_b).powi(2);
+    }
+    // A constant price, such as a stablecoin's, correlates with nothing
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return Some(0.0);
+    }
+    Some((covariance / (variance_a * variance_b).sqrt()).clamp(-1.0, 1.0))
+}
+
+fn log_returns(prices: &[f64]) -> Vec<f64> {
+    prices.windows(2)
+        .filter(|pair| pair[0] > 0.0 && pair[1] > 0.0)
+        .map(|pair| (pair[1] / pair[0]).ln())
+        .collect()
+}
+
+/// Lossy conversion that, unlike `as_u128`, cannot panic on large amounts
+pub fn u256_to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, word| acc * 2f64.powi(64) + *word as f64)
+}
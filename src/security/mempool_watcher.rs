@@ -0,0 +1,155 @@
+// Pending transaction stream feeding MEV detection
+use anyhow::{Result, bail};
+use ethers::{providers::Middleware, types::H256};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::SecurityManager;
+use crate::chains::ChainManager;
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::TransactionTracker;
+
+/// How often the pending transaction filter is polled
+const PENDING_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Pending transactions fetched concurrently per chain
+const FETCH_CONCURRENCY: usize = 16;
+/// Wait before re-subscribing after the node drops or rejects the filter
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// Streams every chain's pending transactions into MEV detection, watching our users'
+/// broadcast swaps for sandwich setups
+pub struct MempoolWatcher {
+    chain_manager: Arc<ChainManager>,
+    security: Arc<SecurityManager>,
+    transactions: Arc<TransactionTracker>,
+    enabled: bool,
+}
+
+impl MempoolWatcher {
+    pub fn new(
+        chain_manager: Arc<ChainManager>,
+        security: Arc<SecurityManager>,
+        transactions: Arc<TransactionTracker>,
+        enabled: bool,
+    ) -> Self {
+        Self {
+            chain_manager,
+            security,
+            transactions,
+            enabled,
+        }
+    }
+
+    /// Watcher enabled by `mempool_monitoring`, off by default since it fetches every pending transaction
+    pub fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        security: Arc<SecurityManager>,
+        transactions: Arc<TransactionTracker>,
+    ) -> Self {
+        let enabled = config.get_bool("mempool_monitoring").unwrap_or(false);
+        Self::new(chain_manager, security, transactions, enabled)
+    }
+
+    /// Stream each chain in the background until shutdown
+    pub fn start(self: Arc<Self>, shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            if !self.enabled {
+                return;
+            }
+            let mut watchers = Vec::new();
+            for chain_id in self.chain_manager.chain_ids().await {
+                let watcher = Arc::clone(&self);
+                let shutdown = shutdown.clone();
+                watchers.push(tokio::spawn(async move { watcher.watch_chain(chain_id, shutdown).await }));
+            }
+            for watcher in watchers {
+                let _ = watcher.await;
+            }
+            info!("Mempool watcher stopped");
+        })
+    }
+
+    async fn watch_chain(&self, chain_id: u64, mut shutdown: ShutdownSignal) {
+        loop {
+            tokio::select! {
+                result = self.stream_chain(chain_id) => match result {
+                    Ok(()) => warn!("Pending transaction stream on chain {} ended", chain_id),
+                    Err(e) => warn!("Pending transaction stream on chain {} failed: {}", chain_id, e),
+                },
+                _ = shutdown.cancelled() => return,
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => {}
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    }
+
+    async fn stream_chain(&self, chain_id: u64) -> Result<()> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        // Subscribed over the chain's WebSocket when it has one, polling a filter over HTTP otherwise
+        let mut session = match &chain.ws {
+            Some(ws) => match ws.session().await {
+                Ok(session) => Some(session),
+                Err(e) => {
+                    warn!("Polling pending transactions on chain {}, WebSocket unavailable: {}", chain_id, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let ws_provider = session.as_ref().map(|session| session.provider.clone());
+        let hashes: Pin<Box<dyn Stream<Item = H256> + Send + '_>> = match &ws_provider {
+            Some(provider) => Box::pin(provider.subscribe_pending_txs().await?),
+            None => Box::pin(chain.provider.watch_pending_transactions().await?.interval(PENDING_POLL_INTERVAL)),
+        };
+        info!("Streaming pending transactions on chain {}", chain_id);
+
+        // Transactions are fetched over HTTP either way
+        let mut pending = hashes
+            .map(|hash| {
+                let provider = &chain.provider;
+                async move { provider.get_transaction(hash).await }
+            })
+            .buffer_unordered(FETCH_CONCURRENCY);
+
+        let lost = async {
+            match session.as_mut() {
+                Some(session) => session.lost().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(lost);
+        loop {
+            let fetched = tokio::select! {
+                fetched = pending.next() => match fetched {
+                    Some(fetched) => fetched,
+                    None => break,
+                },
+                _ = &mut lost => bail!("WebSocket connection lost"),
+            };
+            // Transactions mined or replaced before the fetch are simply gone
+            let Ok(Some(tx)) = fetched else {
+                continue;
+            };
+
+            let ours = self.transactions.get_by_hash(tx.hash).await.is_some();
+            for threat in self.security.ingest_pending_transaction(chain_id, &tx, ours).await {
+                warn!(
+                    "{:?} setup against {:?} by {:?} on chain {}",
+                    threat.threat_type, threat.transaction_hash, threat.attacker_address, chain_id
+                );
+                if let Err(e) = self.security.record_mev_threat(threat).await {
+                    warn!("Failed to record MEV threat: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
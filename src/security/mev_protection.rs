@@ -9,6 +9,13 @@ use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc, Duration};
 
+use crate::dex::aggregator::u256_to_f64;
+
+/// Pending transactions older than this are assumed mined or dropped
+const PENDING_TTL_SECS: i64 = 120;
+/// Pending transactions kept for sandwich detection
+const MAX_PENDING_TRANSACTIONS: usize = 5000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MevType {
     Frontrunning,
@@ -39,6 +46,45 @@ pub struct TransactionPattern {
     pub data: Bytes,
     pub timestamp: DateTime<Utc>,
     pub from_address: Address,
+    pub chain_id: Option<u64>,
+}
+
+/// Swap decoded from router calldata
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SwapIntent {
+    router: Address,
+    token_in: Address,
+    token_out: Address,
+    amount_in: U256,
+}
+
+/// A user's swap that attackers could sandwich
+#[derive(Debug, Clone)]
+struct PendingSwap {
+    chain_id: Option<u64>,
+    from: Option<Address>,
+    gas_price: Option<U256>,
+    swap: SwapIntent,
+    seen_at: DateTime<Utc>,
+}
+
+/// Pool prices observed around one of our executed swaps.
+/// Prices are expressed as output token per input token in raw units.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionPriceSample {
+    pub transaction_hash: H256,
+    pub block_number: u64,
+    pub pool: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// Spot price at the end of the block before execution
+    pub pre_trade_price: f64,
+    /// Spot price at the end of the block after execution
+    pub post_trade_price: f64,
+    /// Price impact the quote predicted, in percent
+    pub expected_price_impact: f64,
+    /// Sender seen trading the same pool both before and after us in the block
+    pub suspected_attacker: Option<Address>,
 }
 
 pub struct MevProtection {
@@ -62,6 +108,10 @@ pub enum ProtectionStrategy {
 #[derive(Debug, Clone)]
 pub struct MempoolMonitor {
     pending_transactions: HashMap<H256, TransactionPattern>,
+    /// Pending swaps of our own users, by hash
+    watched_swaps: HashMap<H256, PendingSwap>,
+    /// (victim, attacker) pairs already reported
+    reported_setups: HashSet<(H256, Address)>,
     suspicious_patterns: Vec<MevThreat>,
     last_block_processed: u64,
 }
@@ -76,6 +126,8 @@ impl MevProtection {
             protection_strategies: Arc::new(RwLock::new(HashMap::new())),
             mempool_monitor: Arc::new(RwLock::new(MempoolMonitor {
                 pending_transactions: HashMap::new(),
+                watched_swaps: HashMap::new(),
+                reported_setups: HashSet::new(),
                 suspicious_patterns: Vec::new(),
                 last_block_processed: 0,
             })),
@@ -168,37 +220,26 @@ impl MevProtection {
         Ok(None)
     }
 
-    /// Detect sandwich attack patterns
+    /// Detect sandwich setups in the mempool targeting a transaction
     async fn detect_sandwich_attack(&self, tx: &TransactionRequest) -> Result<Option<MevThreat>> {
+        let Some(NameOrAddress::Address(router)) = &tx.to else {
+            return Ok(None); // Skip if not a direct address
+        };
+        let data = tx.data.clone().unwrap_or_default();
+        let Some(swap) = decode_swap(*router, &data, tx.value.unwrap_or_default()) else {
+            return Ok(None);
+        };
+
+        let victim = PendingSwap {
+            chain_id: tx.chain_id.map(|chain_id| chain_id.as_u64()),
+            from: tx.from,
+            gas_price: tx.gas_price,
+            swap,
+            seen_at: Utc::now(),
+        };
         let mempool_monitor = self.mempool_monitor.read().await;
-        
-        // Look for sandwich pattern: buy -> victim tx -> sell
-        if let Some(to_addr) = &tx.to {
-            let address = match to_addr {
-                NameOrAddress::Address(addr) => *addr,
-                _ => return Ok(None), // Skip if not a direct address
-            };
-            
-            // Check if this is a DEX transaction
-            if self.is_dex_transaction(address, tx.data.as_ref().unwrap_or(&Bytes::new())).await {
-                // Look for matching buy/sell orders around this transaction
-                for (_, pending_tx) in &mempool_monitor.pending_transactions {
-                    if self.could_be_sandwich_attack(tx, pending_tx).await {
-                        return Ok(Some(MevThreat {
-                            threat_type: MevType::Sandwiching,
-                            confidence: 0.7,
-                            potential_value: tx.value.unwrap_or(U256::zero()),
-                            detected_at: Utc::now(),
-                            transaction_hash: None,
-                            attacker_address: Some(pending_tx.from_address),
-                            block_number: None,
-                        }));
-                    }
-                }
-            }
-        }
-        
-        Ok(None)
+        let known_bots = self.known_mev_bots.read().await;
+        Ok(find_sandwich_setup(&mempool_monitor.pending_transactions, &known_bots, &victim, None))
     }
 
     /// Analyze gas pricing for MEV indicators
@@ -326,8 +367,7 @@ impl MevProtection {
 
     /// Start monitoring mempool for MEV patterns
     async fn start_mempool_monitoring(&self) -> Result<()> {
-        // This would typically connect to a mempool feed
-        // For now, we'll update from recent blocks
+        // Pending transactions arrive through ingest_pending_transaction from the mempool watcher
         let current_block = self.provider.get_block_number().await?;
         let mut monitor = self.mempool_monitor.write().await;
         monitor.last_block_processed = current_block.as_u64();
@@ -344,41 +384,6 @@ impl MevProtection {
         data1[..4] == data2[..4]
     }
 
-    /// Check if transaction is to a DEX
-    async fn is_dex_transaction(&self, to: Address, data: &Bytes) -> bool {
-        // Check for common DEX function selectors
-        if data.len() < 4 {
-            return false;
-        }
-        
-        let selector = &data[..4];
-        // Common DEX selectors (swapExactETHForTokens, swapExactTokensForETH, etc.)
-        matches!(selector, 
-            [0x7f, 0xf3, 0x6a, 0xb5] | // swapExactETHForTokens
-            [0x18, 0xcb, 0xaf, 0xe5] | // swapExactTokensForETH
-            [0x38, 0xed, 0x17, 0x39] | // swapExactTokensForTokens
-            [0x8a, 0x65, 0x7b, 0x9a]   // router02 swap
-        )
-    }
-
-    /// Check if transaction could be part of sandwich attack
-    async fn could_be_sandwich_attack(&self, victim_tx: &TransactionRequest, potential_attacker: &TransactionPattern) -> bool {
-        // Simplified sandwich detection
-        let victim_addr = match &victim_tx.to {
-            Some(NameOrAddress::Address(addr)) => Some(*addr),
-            _ => None,
-        };
-        
-        if let (Some(victim_to), Some(attacker_to)) = (victim_addr, potential_attacker.to_address) {
-            // Same contract target
-            if victim_to == attacker_to {
-                // Check for opposite trade direction
-                return self.is_opposite_trade(&victim_tx.data.as_ref().unwrap_or(&Bytes::new()), &potential_attacker.data).await;
-            }
-        }
-        false
-    }
-
     /// Check if transactions are competing for same opportunity
     async fn is_competing_transaction(&self, tx1: &TransactionRequest, tx2: &TransactionPattern) -> bool {
         // Check if targeting same contract with similar function calls
@@ -395,11 +400,120 @@ impl MevProtection {
         false
     }
 
-    /// Check if two trades are opposite (buy vs sell)
-    async fn is_opposite_trade(&self, data1: &Bytes, data2: &Bytes) -> bool {
-        // This would analyze the function calls to determine trade direction
-        // Simplified implementation
-        false
+    /// Check an executed swap for a sandwich: a worse fill than quoted that
+    /// reverts in the following block once the attacker sells back
+    pub fn detect_sandwich_from_prices(sample: &ExecutionPriceSample) -> Option<MevThreat> {
+        if sample.amount_in.is_zero() || sample.pre_trade_price <= 0.0 {
+            return None;
+        }
+
+        let amount_in = u256_to_f64(sample.amount_in);
+        let amount_out = u256_to_f64(sample.amount_out);
+        let execution_price = amount_out / amount_in;
+
+        // Slippage beyond what our own trade should have caused
+        let slippage = (sample.pre_trade_price - execution_price) / sample.pre_trade_price * 100.0;
+        let excess_slippage = slippage - sample.expected_price_impact;
+        if excess_slippage < 0.25 {
+            return None;
+        }
+
+        // How far the price snapped back towards the pre-trade level
+        let displacement = sample.pre_trade_price - execution_price;
+        let reversion = ((sample.post_trade_price - execution_price) / displacement).clamp(0.0, 1.5);
+        if reversion < 0.5 && sample.suspected_attacker.is_none() {
+            return None;
+        }
+
+        let mut confidence = 0.5 + 0.25 * reversion.min(1.0) + (excess_slippage / 10.0).min(0.1);
+        if sample.suspected_attacker.is_some() {
+            confidence += 0.15;
+        }
+
+        let fair_output = amount_in * sample.pre_trade_price * (1.0 - sample.expected_price_impact / 100.0);
+        let extracted = (fair_output - amount_out).max(0.0);
+
+        Some(MevThreat {
+            threat_type: MevType::Sandwiching,
+            confidence: confidence.min(0.99),
+            potential_value: U256::from(extracted as u128),
+            detected_at: Utc::now(),
+            transaction_hash: Some(sample.transaction_hash),
+            attacker_address: sample.suspected_attacker,
+            block_number: Some(sample.block_number),
+        })
+    }
+
+    /// Record a threat detected outside of pre-trade analysis
+    pub async fn record_threat(&self, threat: MevThreat) {
+        let mut monitor = self.mempool_monitor.write().await;
+        if let Some(attacker) = threat.attacker_address {
+            self.known_mev_bots.write().await.insert(attacker);
+        }
+        monitor.suspicious_patterns.push(threat);
+    }
+
+    /// Add a transaction seen in the mempool. Pending swaps of our users are watched, and
+    /// sandwich setups newly found against any of them are returned once per attacker
+    pub async fn ingest_pending_transaction(&self, chain_id: u64, tx: &Transaction, ours: bool) -> Vec<MevThreat> {
+        let now = Utc::now();
+        let cutoff = now - Duration::seconds(PENDING_TTL_SECS);
+        let mut monitor = self.mempool_monitor.write().await;
+        monitor.pending_transactions.retain(|_, pending| pending.timestamp > cutoff);
+        monitor.watched_swaps.retain(|_, swap| swap.seen_at > cutoff);
+
+        let pattern = TransactionPattern {
+            gas_price: tx.gas_price.unwrap_or_default(),
+            gas_limit: tx.gas,
+            to_address: tx.to,
+            value: tx.value,
+            data: tx.input.clone(),
+            timestamp: now,
+            from_address: tx.from,
+            chain_id: Some(chain_id),
+        };
+
+        if ours {
+            let Some(swap) = tx.to.and_then(|router| decode_swap(router, &tx.input, tx.value)) else {
+                return Vec::new();
+            };
+            monitor.watched_swaps.insert(tx.hash, PendingSwap {
+                chain_id: Some(chain_id),
+                from: Some(tx.from),
+                gas_price: Some(pattern.gas_price),
+                swap,
+                seen_at: now,
+            });
+        } else {
+            if monitor.pending_transactions.len() >= MAX_PENDING_TRANSACTIONS {
+                let oldest = monitor.pending_transactions.iter()
+                    .min_by_key(|(_, pending)| pending.timestamp)
+                    .map(|(hash, _)| *hash);
+                if let Some(oldest) = oldest {
+                    monitor.pending_transactions.remove(&oldest);
+                }
+            }
+            monitor.pending_transactions.insert(tx.hash, pattern);
+        }
+
+        let known_bots = self.known_mev_bots.read().await;
+        let setups: Vec<MevThreat> = monitor.watched_swaps.iter()
+            .filter_map(|(hash, victim)| {
+                find_sandwich_setup(&monitor.pending_transactions, &known_bots, victim, Some(*hash))
+            })
+            .collect();
+
+        setups.into_iter()
+            .filter(|threat| {
+                let key = (threat.transaction_hash.unwrap_or_default(), threat.attacker_address.unwrap_or_default());
+                monitor.reported_setups.insert(key)
+            })
+            .collect()
+    }
+
+    /// Get all recorded MEV threats
+    pub async fn get_recorded_threats(&self) -> Vec<MevThreat> {
+        self.mempool_monitor.read().await.suspicious_patterns.clone()
     }
 
     /// Record transaction pattern for analysis
@@ -417,6 +531,7 @@ impl MevProtection {
             data: tx.data.clone().unwrap_or_default(),
             timestamp: Utc::now(),
             from_address: from,
+            chain_id: tx.chain_id.map(|chain_id| chain_id.as_u64()),
         };
 
         let mut recent_txs = self.recent_transactions.write().await;
@@ -454,3 +569,105 @@ pub struct MevStats {
 }
 
 use std::collections::HashSet;
+
+/// Pending trades on the victim's router and pair that would run ahead of it, sandwiching it
+/// when the same sender also has the opposite trade queued
+fn find_sandwich_setup(
+    pending: &HashMap<H256, TransactionPattern>,
+    known_bots: &HashSet<Address>,
+    victim: &PendingSwap,
+    victim_hash: Option<H256>,
+) -> Option<MevThreat> {
+    let router = victim.swap.router;
+    let legs: Vec<(&TransactionPattern, SwapIntent)> = pending.values()
+        .filter(|pattern| pattern.to_address == Some(router))
+        .filter(|pattern| victim.from != Some(pattern.from_address))
+        .filter(|pattern| {
+            victim.chain_id.is_none_or(|chain_id| pattern.chain_id.is_none_or(|id| id == chain_id))
+        })
+        .filter_map(|pattern| decode_swap(router, &pattern.data, pattern.value).map(|swap| (pattern, swap)))
+        .collect();
+
+    for (front, swap) in &legs {
+        let same_direction = swap.token_in == victim.swap.token_in && swap.token_out == victim.swap.token_out;
+        // Without a gas price of our own every same-direction trade could land first
+        if !same_direction || victim.gas_price.is_some_and(|gas_price| front.gas_price <= gas_price) {
+            continue;
+        }
+
+        let back_run = legs.iter().any(|(back, swap)| {
+            back.from_address == front.from_address
+                && swap.token_in == victim.swap.token_out
+                && swap.token_out == victim.swap.token_in
+        });
+        let known_bot = known_bots.contains(&front.from_address);
+        if !back_run && !known_bot {
+            continue;
+        }
+
+        let confidence = if back_run { 0.9 } else { 0.6 } + if known_bot { 0.05 } else { 0.0 };
+        return Some(MevThreat {
+            threat_type: if back_run { MevType::Sandwiching } else { MevType::Frontrunning },
+            confidence,
+            potential_value: victim.swap.amount_in,
+            detected_at: Utc::now(),
+            transaction_hash: victim_hash,
+            attacker_address: Some(front.from_address),
+            block_number: None,
+        });
+    }
+
+    None
+}
+
+/// Decode Uniswap V2-style router swaps and V3 `exactInputSingle`
+fn decode_swap(router: Address, data: &[u8], value: U256) -> Option<SwapIntent> {
+    use ethers::abi::{decode, ParamType, Token};
+
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, args) = data.split_at(4);
+    let path = || ParamType::Array(Box::new(ParamType::Address));
+    let uint = ParamType::Uint(256);
+    let endpoints = |path: Token| -> Option<(Address, Address)> {
+        let path = path.into_array()?;
+        Some((path.first()?.clone().into_address()?, path.last()?.clone().into_address()?))
+    };
+
+    let (amount_in, (token_in, token_out)) = match selector {
+        // swapExactTokensForTokens, swapExactTokensForETH
+        [0x38, 0xed, 0x17, 0x39] | [0x18, 0xcb, 0xaf, 0xe5] => {
+            let mut tokens = decode(&[uint.clone(), uint.clone(), path(), ParamType::Address, uint], args).ok()?;
+            (tokens[0].clone().into_uint()?, endpoints(tokens.swap_remove(2))?)
+        }
+        // swapTokensForExactTokens, bounded by amountInMax
+        [0x88, 0x03, 0xdb, 0xee] => {
+            let mut tokens = decode(&[uint.clone(), uint.clone(), path(), ParamType::Address, uint], args).ok()?;
+            (tokens[1].clone().into_uint()?, endpoints(tokens.swap_remove(2))?)
+        }
+        // swapExactETHForTokens, the input is the attached value
+        [0x7f, 0xf3, 0x6a, 0xb5] => {
+            let mut tokens = decode(&[uint.clone(), path(), ParamType::Address, uint], args).ok()?;
+            (value, endpoints(tokens.swap_remove(1))?)
+        }
+        // exactInputSingle on SwapRouter, whose params carry a deadline, and on SwapRouter02
+        [0x41, 0x4b, 0xf3, 0x89] | [0x04, 0xe4, 0x5a, 0xaf] => {
+            let with_deadline = selector[0] == 0x41;
+            let mut fields = vec![ParamType::Address, ParamType::Address, ParamType::Uint(24), ParamType::Address];
+            if with_deadline {
+                fields.push(uint.clone());
+            }
+            fields.extend([uint.clone(), uint, ParamType::Uint(160)]);
+
+            let params = decode(&[ParamType::Tuple(fields)], args).ok()?.pop()?.into_tuple()?;
+            let amount_index = if with_deadline { 5 } else { 4 };
+            let token_in = params[0].clone().into_address()?;
+            let token_out = params[1].clone().into_address()?;
+            (params[amount_index].clone().into_uint()?, (token_in, token_out))
+        }
+        _ => return None,
+    };
+
+    Some(SwapIntent { router, token_in, token_out, amount_in })
+}
@@ -6,7 +6,7 @@ use ethers::core::types::{TransactionRequest, Transaction, transaction::eip2718:
 use chrono::{DateTime, Duration, Utc};
 use serde::{Serialize, Deserialize};
 use anyhow::Result;
-use tracing::{info, warn, error};
+use tracing::{info, instrument, warn};
 use ring::digest;
 
 // Import all security modules
@@ -19,6 +19,17 @@ pub mod audit_trail;
 pub mod transaction_validator;
 pub mod reentrancy_guard;
 pub mod input_sanitizer;
+pub mod transaction_limits;
+pub mod mempool_watcher;
+pub mod approval_scanner;
+pub mod sanctions;
+pub mod circuit_breaker;
+pub mod emergency_playbooks;
+pub mod notifications;
+pub mod spending_policy;
+pub mod contract_analysis;
+pub mod market_data;
+pub mod stress_test;
 
 use mev_protection::*;
 use oracle_security::*;
@@ -28,12 +39,32 @@ use emergency_response::*;
 use audit_trail::*;
 
 // Re-export for convenience
-pub use mev_protection::{MevProtection, MevThreat, MevStats};
-pub use oracle_security::{OracleSecurity, OracleSecurityStats};
-pub use defi_security::{DeFiSecurity, DeFiSecurityStats};
+pub use mev_protection::{MevProtection, MevThreat, MevStats, ExecutionPriceSample};
+pub use oracle_security::{
+    OracleSecurity, OracleSecurityStats, PriceDeviation, PriceGuardConfig, PriceVerdict, TradePriceCheck, TradePriceGuard,
+};
+pub use defi_security::{DeFiSecurity, DeFiSecurityStats, TokenRisk, TokenSafetyReport, TokenSafetyScanner};
 pub use risk_engine::{RiskEngine, RiskAssessment};
 pub use emergency_response::{EmergencyResponse, EmergencyAlert, EmergencyStats};
-pub use audit_trail::{AuditTrail, AuditEntry, AuditStats, ComplianceReport};
+pub use audit_trail::{AuditTrail, AuditTrailConfig, AuditEntry, AuditExportFormat, AuditQuery, AuditStats, ChainVerification, ComplianceReport};
+pub use transaction_limits::{TransactionLimits, TransactionLimitEnforcer};
+pub use mempool_watcher::MempoolWatcher;
+pub use approval_scanner::{ApprovalReport, ApprovalScanner, RevokeScope};
+pub use sanctions::{SanctionedCounterparty, SanctionsScreener, ScreeningResult};
+pub use emergency_playbooks::{EmergencyPlaybooks, PlaybookConfig, PlaybookRun};
+pub use circuit_breaker::{CircuitBreakerConfig, CircuitBreakerStatus, CircuitBreakers, TradingHalted};
+pub use spending_policy::{
+    OverrideRequest, PolicyContext, PolicyEvaluation, PolicyOverride, PolicyViolation, SpendingPolicies, SpendingPolicyConfig,
+    SpendingPolicyEngine, SpendingPolicyRefused,
+};
+pub use contract_analysis::{CodeFinding, CodeRisk, ContractAnalysis, ContractAnalyzer};
+pub use market_data::{MarketDataConfig, MarketDataSources, MarketRiskData};
+pub use stress_test::{StressScenario, StressTestResult};
+pub use notifications::{
+    ChannelStatus, NotificationConfig, NotificationKind, NotificationService, NotificationSeverity, SecurityNotification,
+};
+
+use crate::analytics::price_feeds::PriceFeedService;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum SecurityStatus {
@@ -58,6 +89,12 @@ pub enum ThreatType {
     DeFi(String),
     Reentrancy,
     FrontRunning,
+    /// Counterparties on a sanctions list
+    Sanctioned(Vec<Address>),
+    /// Spending policy rules of the wallet or strategy the transaction breaks
+    PolicyViolation(Vec<PolicyViolation>),
+    /// Finding in the bytecode of the called contract or in the calldata sent to it
+    Code(CodeFinding),
     Unknown(String),
 }
 
@@ -124,6 +161,14 @@ pub struct AdvancedSecurityManager {
     risk_engine: Arc<RiskEngine>,
     emergency_response: Arc<EmergencyResponse>,
     audit_trail: Arc<AuditTrail>,
+    sanctions: Arc<SanctionsScreener>,
+    /// Oracle check of the swaps analyzed transactions make, `None` without price feeds
+    price_guard: Option<Arc<TradePriceGuard>>,
+    circuit_breakers: Arc<CircuitBreakers>,
+    notifications: Arc<NotificationService>,
+    spending_policies: Arc<SpendingPolicyEngine>,
+    /// Bytecode and calldata checks of the called contract, `None` without chain access
+    contract_analyzer: Option<Arc<ContractAnalyzer>>,
     
     // State management
     threat_level: Arc<RwLock<ThreatLevel>>,
@@ -131,7 +176,7 @@ pub struct AdvancedSecurityManager {
 }
 
 impl AdvancedSecurityManager {
-    pub async fn new(provider: Arc<Provider<Http>>) -> Result<Self> {
+    pub async fn new(provider: Arc<Provider<Http>>, sanctions: Arc<SanctionsScreener>) -> Result<Self> {
         let config = Arc::new(RwLock::new(SecurityConfig::default()));
         
         // Initialize all security modules
@@ -141,6 +186,9 @@ impl AdvancedSecurityManager {
         let risk_engine = Arc::new(RiskEngine::new(provider.clone()));
         let emergency_response = Arc::new(EmergencyResponse::new(provider.clone()));
         let audit_trail = Arc::new(AuditTrail::new(provider.clone()));
+        let circuit_breakers = Arc::new(CircuitBreakers::new(CircuitBreakerConfig::default(), audit_trail.clone()));
+        let notifications = Arc::new(NotificationService::new(NotificationConfig::default()));
+        let spending_policies = Arc::new(SpendingPolicyEngine::new(SpendingPolicyConfig::default(), None, audit_trail.clone()));
         
         Ok(Self {
             provider,
@@ -151,12 +199,28 @@ impl AdvancedSecurityManager {
             risk_engine,
             emergency_response,
             audit_trail,
+            sanctions,
+            price_guard: None,
+            circuit_breakers,
+            notifications,
+            spending_policies,
+            contract_analyzer: None,
             threat_level: Arc::new(RwLock::new(ThreatLevel::Low)),
             security_metrics: Arc::new(RwLock::new(SecurityMetrics::default())),
         })
     }
 
-    pub async fn new_demo() -> Result<Self> {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new_demo(
+        sanctions: Arc<SanctionsScreener>,
+        audit_config: AuditTrailConfig,
+        price_guard: Arc<TradePriceGuard>,
+        breaker_config: CircuitBreakerConfig,
+        notification_config: NotificationConfig,
+        policy_config: SpendingPolicyConfig,
+        contract_analyzer: Arc<ContractAnalyzer>,
+        market_data: MarketDataSources,
+    ) -> Result<Self> {
         info!("Creating AdvancedSecurityManager in demo mode");
         
         // Create a mock HTTP provider for demo
@@ -166,9 +230,12 @@ impl AdvancedSecurityManager {
         let mev_protection = Arc::new(MevProtection::new(provider.clone()));
         let oracle_security = Arc::new(OracleSecurity::new(provider.clone()));
         let defi_security = Arc::new(DeFiSecurity::new(provider.clone()));
-        let risk_engine = Arc::new(RiskEngine::new(provider.clone()));
+        let risk_engine = Arc::new(RiskEngine::with_market_data(provider.clone(), market_data));
         let emergency_response = Arc::new(EmergencyResponse::new(provider.clone()));
-        let audit_trail = Arc::new(AuditTrail::new(provider.clone()));
+        let audit_trail = Arc::new(AuditTrail::open(provider.clone(), audit_config).await?);
+        let circuit_breakers = Arc::new(CircuitBreakers::new(breaker_config, audit_trail.clone()));
+        let notifications = Arc::new(NotificationService::new(notification_config));
+        let spending_policies = Arc::new(SpendingPolicyEngine::new(policy_config, Some(price_guard.clone()), audit_trail.clone()));
         
         Ok(Self {
             provider,
@@ -179,6 +246,12 @@ impl AdvancedSecurityManager {
             risk_engine,
             emergency_response,
             audit_trail,
+            sanctions,
+            price_guard: Some(price_guard),
+            circuit_breakers,
+            notifications,
+            spending_policies,
+            contract_analyzer: Some(contract_analyzer),
             threat_level: Arc::new(RwLock::new(ThreatLevel::Low)),
             security_metrics: Arc::new(RwLock::new(SecurityMetrics::default())),
         })
@@ -222,14 +295,36 @@ impl AdvancedSecurityManager {
         Ok(())
     }
 
-    /// Analyze transaction for security threats
-    pub async fn analyze_transaction(&self, tx: &TransactionRequest) -> Result<SecurityAnalysisResult> {
+    /// Analyze transaction for security threats, checking it against the spending policies of its
+    /// wallet and of the strategy in `context`
+    pub async fn analyze_transaction(&self, tx: &TransactionRequest, context: &PolicyContext) -> Result<SecurityAnalysisResult> {
         let start_time = Utc::now();
         let mut threats = Vec::new();
         let mut recommendations = Vec::new();
         let mut risk_score = 0.0f64;
 
         let config = self.config.read().await;
+
+        // Sanctions screening of both counterparties, recorded whatever the outcome
+        let to = tx.to.as_ref().and_then(|to| to.as_address().copied());
+        let screening = self.sanctions.screen(&tx.from.into_iter().chain(to).collect::<Vec<_>>()).await;
+        if config.audit_logging_enabled {
+            sanctions::record(&self.audit_trail, &screening, "Transaction analysis").await?;
+        }
+        if !screening.is_clear() {
+            threats.push(ThreatType::Sanctioned(screening.matches.iter().map(|found| found.address).collect()));
+            recommendations.push("Do not transact with sanctioned addresses".to_string());
+        }
+
+        // Spending policies: limits and allowed protocols of the wallet and strategy
+        let policy = match tx.from {
+            Some(wallet) => self.spending_policies.evaluate(wallet, tx, context).await?,
+            None => None,
+        };
+        if let Some(evaluation) = policy.as_ref().filter(|evaluation| !evaluation.allowed) {
+            threats.push(ThreatType::PolicyViolation(evaluation.violations.clone()));
+            recommendations.push("Stay within the spending policy or request an override".to_string());
+        }
         
         // MEV Protection Analysis
         if config.mev_protection_enabled {
@@ -240,10 +335,57 @@ impl AdvancedSecurityManager {
             }
         }
 
-        // Oracle Security Analysis
-        if config.oracle_validation_enabled {
-            // Oracle security analysis would go here
-            // For now, no threats detected
+        // Oracle Security Analysis: the least output a swap accepts against Chainlink and TWAP prices
+        let mut price_check = None;
+        if let (true, Some(guard), Some(chain_id), Some(swap)) = (
+            config.oracle_validation_enabled,
+            &self.price_guard,
+            tx.chain_id,
+            oracle_security::decode_swap(tx),
+        ) {
+            match guard.check_trade(chain_id.as_u64(), swap.token_in, swap.amount_in, swap.token_out, swap.min_amount_out).await {
+                Ok(check) => {
+                    match check.verdict {
+                        PriceVerdict::Block => {
+                            threats.push(ThreatType::Oracle(check.findings.join("; ")));
+                            if check.deviation_percentage.is_some() {
+                                recommendations.push("Do not trade at a price this far from the oracles".to_string());
+                                self.circuit_breakers.record_oracle_deviation().await?;
+                            } else {
+                                recommendations.push("Trade only tokens with a Chainlink feed or TWAP pool".to_string());
+                            }
+                        }
+                        PriceVerdict::Flag => {
+                            threats.push(ThreatType::Oracle(check.findings.join("; ")));
+                            recommendations.push("Review the minimum output against current market prices".to_string());
+                            risk_score += 0.3;
+                        }
+                        PriceVerdict::Pass => {}
+                    }
+                    price_check = Some(check);
+                }
+                Err(e) => warn!("Oracle price check of {:?} failed: {}", tx.to, e),
+            }
+        }
+
+        // Contract analysis: the bytecode of the called contract and the calldata sent to it
+        let mut contract_analysis = None;
+        if let (Some(analyzer), Some(to)) = (&self.contract_analyzer, to) {
+            let chain_id = tx.chain_id.map(|id| id.as_u64()).unwrap_or(1);
+            let data = tx.data.as_deref().unwrap_or_default();
+            match analyzer.analyze(chain_id, to, data).await {
+                Ok(analysis) => {
+                    for finding in analysis.findings.iter().filter(|finding| finding.risk >= CodeRisk::Medium) {
+                        threats.push(ThreatType::Code(finding.clone()));
+                        risk_score += finding.risk.score();
+                    }
+                    if analysis.risk >= CodeRisk::High {
+                        recommendations.push("Review the called contract and the calldata before signing".to_string());
+                    }
+                    contract_analysis = Some(analysis);
+                }
+                Err(e) => warn!("Contract analysis of {:?} on chain {} failed: {}", to, chain_id, e),
+            }
         }
 
         // DeFi Security Analysis
@@ -262,8 +404,11 @@ impl AdvancedSecurityManager {
             recommendations.extend(risk_result.recommended_actions);
         }
 
-        // Normalize risk score to 0-1 range
-        risk_score = risk_score.min(1.0);
+        // Normalize risk score to 0-1 range; a sanctioned counterparty, a blocked price or a critical code
+        // finding is always the maximum
+        let blocked_price = price_check.as_ref().is_some_and(|check| check.verdict == PriceVerdict::Block);
+        let critical_code = contract_analysis.as_ref().is_some_and(|analysis| analysis.risk == CodeRisk::Critical);
+        risk_score = if screening.is_clear() && !blocked_price && !critical_code { risk_score.min(1.0) } else { 1.0 };
 
         // Determine overall security status
         let security_status = match risk_score {
@@ -276,6 +421,24 @@ impl AdvancedSecurityManager {
         // Update threat level if necessary
         self.update_threat_level_if_needed(risk_score).await?;
 
+        // Feed the circuit breakers: the risk of detected threats and the gas price offered
+        if !threats.is_empty() {
+            self.circuit_breakers.record_threat(risk_score).await?;
+            let found: Vec<String> = threats.iter().map(describe_threat).collect();
+            let mut notification = SecurityNotification::new(
+                NotificationKind::Threat,
+                NotificationSeverity::from_risk_score(risk_score),
+                format!("Transaction analysis found {} threats", threats.len()),
+                format!("Risk score {:.2}: {}", risk_score, found.join("; ")),
+            );
+            notification.address = tx.from;
+            notification.chain_id = tx.chain_id.map(|chain_id| chain_id.as_u64());
+            self.notifications.notify(notification);
+        }
+        if let (Some(chain_id), Some(gas_price)) = (tx.chain_id, tx.gas_price) {
+            self.circuit_breakers.record_gas_price(chain_id.as_u64(), gas_price).await?;
+        }
+
         // Log security analysis
         if config.audit_logging_enabled {
             self.audit_trail.log_security_event(
@@ -296,6 +459,9 @@ impl AdvancedSecurityManager {
             metrics.last_updated = Utc::now();
         }).await;
 
+        // Spending is only counted when the transaction is signed
+        let should_proceed = risk_score < config.risk_tolerance && policy.as_ref().is_none_or(|evaluation| evaluation.allowed);
+
         let analysis_time = Utc::now().signed_duration_since(start_time);
 
         Ok(SecurityAnalysisResult {
@@ -312,7 +478,10 @@ impl AdvancedSecurityManager {
             }).collect(),
             recommendations,
             analysis_duration: analysis_time,
-            should_proceed: risk_score < config.risk_tolerance,
+            should_proceed,
+            price_check,
+            policy,
+            contract_analysis,
         })
     }
 
@@ -340,6 +509,7 @@ impl AdvancedSecurityManager {
     /// Handle security emergency
     pub async fn handle_emergency(&self, alert: EmergencyAlert) -> Result<()> {
         self.emergency_response.trigger_alert(alert.clone()).await?;
+        self.notifications.notify(SecurityNotification::from_emergency(&alert));
         
         // Update threat level to critical
         *self.threat_level.write().await = ThreatLevel::Critical;
@@ -431,6 +601,112 @@ impl AdvancedSecurityManager {
         }
     }
 
+    /// Record an MEV threat detected after execution and audit-log it
+    pub async fn record_mev_threat(&self, threat: MevThreat) -> Result<()> {
+        self.audit_trail.log_security_event(
+            AuditEntryType::ThreatDetected,
+            threat.attacker_address,
+            format!("{:?} detected on {:?}", threat.threat_type, threat.transaction_hash),
+            threat.confidence,
+            vec!["post_execution_mev".to_string()],
+        ).await?;
+
+        let mut notification = SecurityNotification::new(
+            NotificationKind::Threat,
+            NotificationSeverity::from_risk_score(threat.confidence),
+            format!("{:?} detected after execution", threat.threat_type),
+            format!("{:?} on {:?} with confidence {:.2}", threat.threat_type, threat.transaction_hash, threat.confidence),
+        );
+        notification.address = threat.attacker_address;
+        self.notifications.notify(notification);
+
+        let confidence = threat.confidence;
+        self.mev_protection.record_threat(threat).await;
+        self.update_security_metrics(|metrics| metrics.threats_detected += 1).await;
+        self.circuit_breakers.record_threat(confidence).await
+    }
+
+    /// Feed a pending transaction from the mempool into MEV detection and the gas spike breaker
+    pub async fn ingest_pending_transaction(&self, chain_id: u64, tx: &Transaction, ours: bool) -> Vec<MevThreat> {
+        if let Some(gas_price) = tx.max_fee_per_gas.or(tx.gas_price) {
+            if let Err(e) = self.circuit_breakers.record_gas_price(chain_id, gas_price).await {
+                warn!("Failed to record gas price on chain {}: {}", chain_id, e);
+            }
+        }
+        if !self.config.read().await.mev_protection_enabled {
+            return Vec::new();
+        }
+        self.mev_protection.ingest_pending_transaction(chain_id, tx, ours).await
+    }
+
+    /// Close the audit trail, called once background tasks were drained
+    pub async fn shutdown(&self) -> Result<()> {
+        if self.config.read().await.audit_logging_enabled {
+            self.audit_trail.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    /// Audit-log an operator action taken through the admin API
+    pub async fn log_admin_action(&self, actor: &str, action: &str, details: String) -> Result<()> {
+        info!("Admin action by {}: {} ({})", actor, action, details);
+        self.audit_trail.log_security_event(
+            AuditEntryType::AdminAction,
+            None,
+            format!("{}: {}", action, details),
+            0.0,
+            vec![format!("actor:{}", actor)],
+        ).await
+    }
+
+    /// Audit trail shared with the basic checks
+    pub fn audit_trail(&self) -> Arc<AuditTrail> {
+        self.audit_trail.clone()
+    }
+
+    /// Breakers halting trading, shared with the API and the order engine
+    pub fn circuit_breakers(&self) -> Arc<CircuitBreakers> {
+        self.circuit_breakers.clone()
+    }
+
+    /// Spending policies, their overrides and the spending counted against them
+    pub fn spending_policies(&self) -> Arc<SpendingPolicyEngine> {
+        self.spending_policies.clone()
+    }
+
+    /// Refuse a transaction `signer` is about to sign that breaks a spending policy, holding its spending
+    /// otherwise until the spending policy engine confirms or releases it
+    pub async fn enforce_spending_policy(&self, signer: Address, tx: &TypedTransaction, context: &PolicyContext) -> Result<()> {
+        let request = TransactionRequest {
+            from: Some(signer),
+            to: tx.to().cloned(),
+            value: tx.value().copied(),
+            data: tx.data().cloned(),
+            chain_id: tx.chain_id(),
+            nonce: tx.nonce().copied(),
+            ..Default::default()
+        };
+        match self.spending_policies.enforce(signer, &request, context).await? {
+            Some(evaluation) if !evaluation.allowed => Err(SpendingPolicyRefused(evaluation.violations).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Notification channels for security events, shared with the position monitor
+    pub fn notifications(&self) -> Arc<NotificationService> {
+        self.notifications.clone()
+    }
+
+    /// Risk engine, whose market data collector runs in the background
+    pub fn risk_engine(&self) -> Arc<RiskEngine> {
+        self.risk_engine.clone()
+    }
+
+    /// Get MEV threats recorded so far
+    pub async fn get_mev_threats(&self) -> Vec<MevThreat> {
+        self.mev_protection.get_recorded_threats().await
+    }
+
     // Helper methods
     async fn update_threat_level_if_needed(&self, risk_score: f64) -> Result<()> {
         let new_level = match risk_score {
@@ -500,6 +776,15 @@ pub struct SecurityAnalysisResult {
     pub recommendations: Vec<String>,
     pub analysis_duration: Duration,
     pub should_proceed: bool,
+    /// Oracle check of the swap the transaction makes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_check: Option<TradePriceCheck>,
+    /// Spending policy check, absent when neither the wallet nor the strategy has a policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyEvaluation>,
+    /// Bytecode and calldata findings for the called contract
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_analysis: Option<ContractAnalysis>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -509,6 +794,7 @@ pub struct SecurityReport {
     pub period_start: DateTime<Utc>,
     pub period_end: DateTime<Utc>,
     pub overall_security_score: f64,
+    #[serde(with = "crate::api::models::sorted_map")]
     pub threat_summary: HashMap<String, usize>,
     pub mev_stats: Option<MevStats>,
     pub oracle_stats: Option<OracleSecurityStats>,
@@ -520,18 +806,22 @@ pub struct SecurityReport {
 }
 
 // Basic security for backward compatibility
-#[derive(Debug)]
 pub struct BasicSecurity {
     blacklisted_addresses: HashSet<Address>,
-    max_transaction_value: U256,
-    max_gas_limit: u64,
+    sanctions: Arc<SanctionsScreener>,
+    audit_trail: Arc<AuditTrail>,
+    limits: TransactionLimitEnforcer,
     validator: transaction_validator::TransactionValidator,
     reentrancy_guard: reentrancy_guard::ReentrancyGuard,
     input_sanitizer: input_sanitizer::InputSanitizer,
 }
 
 impl BasicSecurity {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(
+        price_feeds: Option<Arc<PriceFeedService>>,
+        sanctions: Arc<SanctionsScreener>,
+        audit_trail: Arc<AuditTrail>,
+    ) -> Result<Self> {
         let mut blacklisted_addresses = HashSet::new();
         
         // Add known malicious addresses
@@ -539,8 +829,9 @@ impl BasicSecurity {
         
         Ok(Self {
             blacklisted_addresses,
-            max_transaction_value: U256::from(1000) * U256::exp10(18), // 1000 ETH
-            max_gas_limit: 10_000_000,
+            sanctions,
+            audit_trail,
+            limits: TransactionLimitEnforcer::new(price_feeds),
             validator: transaction_validator::TransactionValidator::new(),
             reentrancy_guard: reentrancy_guard::ReentrancyGuard::new(),
             input_sanitizer: input_sanitizer::InputSanitizer::new(),
@@ -554,17 +845,12 @@ impl BasicSecurity {
                 return Err(anyhow::anyhow!("Transaction to blacklisted address"));
             }
         }
+        let counterparties: Vec<Address> = std::iter::once(tx.from).chain(tx.to).collect();
+        self.sanctions.check(&counterparties, &self.audit_trail, "Transaction").await?;
 
-        // Check transaction value limits
-        if tx.value > self.max_transaction_value {
-            warn!("Transaction value {} exceeds maximum {}", tx.value, self.max_transaction_value);
-            return Err(anyhow::anyhow!("Transaction value too high"));
-        }
-
-        // Check gas limit
-        if tx.gas.as_u64() > self.max_gas_limit {
-            return Err(anyhow::anyhow!("Gas limit too high"));
-        }
+        // Check value and gas against the chain's configured limits
+        let chain_id = tx.chain_id.map(|id| id.as_u64()).unwrap_or(1);
+        self.limits.check(chain_id, tx.to, tx.value, Some(tx.gas), &tx.input).await?;
 
         // Skip additional validations for now to get compilation working
         
@@ -574,12 +860,50 @@ impl BasicSecurity {
         Ok(())
     }
 
+    /// Validate a typed transaction against blacklist and configured limits
+    pub async fn validate_typed_transaction(&self, tx: &TypedTransaction) -> Result<()> {
+        let to = tx.to_addr().copied();
+        if let Some(to) = &to {
+            if self.blacklisted_addresses.contains(to) {
+                return Err(anyhow::anyhow!("Transaction to blacklisted address"));
+            }
+        }
+        let counterparties: Vec<Address> = tx.from().copied().into_iter().chain(to).collect();
+        self.sanctions.check(&counterparties, &self.audit_trail, "Transaction").await?;
+
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(1);
+        let value = tx.value().copied().unwrap_or_default();
+        let data = tx.data().map(|data| data.to_vec()).unwrap_or_default();
+        self.limits.check(chain_id, to, value, tx.gas().copied(), &data).await
+    }
+
+    pub fn limits(&self) -> &TransactionLimitEnforcer {
+        &self.limits
+    }
+
     pub fn calculate_transaction_hash(&self, tx: &Transaction) -> Result<H256> {
         // Use transaction hash or compute from transaction data
         Ok(tx.hash)
     }
 }
 
+/// One-line summary of a threat for notifications
+fn describe_threat(threat: &ThreatType) -> String {
+    match threat {
+        ThreatType::MEV(mev) => format!("{:?} MEV risk ({:.2} confidence)", mev.threat_type, mev.confidence),
+        ThreatType::Oracle(findings) => format!("oracle price deviation: {}", findings),
+        ThreatType::DeFi(description) | ThreatType::Unknown(description) => description.clone(),
+        ThreatType::Reentrancy => "reentrancy".to_string(),
+        ThreatType::FrontRunning => "front-running".to_string(),
+        ThreatType::Sanctioned(addresses) => format!("sanctioned counterparties {:?}", addresses),
+        ThreatType::PolicyViolation(violations) => {
+            let reasons: Vec<&str> = violations.iter().map(|violation| violation.reason.as_str()).collect();
+            format!("spending policy: {}", reasons.join("; "))
+        }
+        ThreatType::Code(finding) => finding.detail.clone(),
+    }
+}
+
 /// Main security manager combining advanced and basic functionality
 pub struct SecurityManager {
     pub advanced: Arc<AdvancedSecurityManager>,
@@ -587,9 +911,9 @@ pub struct SecurityManager {
 }
 
 impl SecurityManager {
-    pub async fn new(provider: Provider<Http>) -> Result<Self> {
-        let advanced = Arc::new(AdvancedSecurityManager::new(Arc::new(provider)).await?);
-        let basic = BasicSecurity::new().await?;
+    pub async fn new(provider: Provider<Http>, sanctions: Arc<SanctionsScreener>) -> Result<Self> {
+        let advanced = Arc::new(AdvancedSecurityManager::new(Arc::new(provider), sanctions.clone()).await?);
+        let basic = BasicSecurity::new(None, sanctions, advanced.audit_trail()).await?;
         
         Ok(Self {
             advanced,
@@ -597,10 +921,33 @@ impl SecurityManager {
         })
     }
 
-    pub async fn new_demo() -> Result<Self> {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new_demo(
+        price_feeds: Arc<PriceFeedService>,
+        sanctions: Arc<SanctionsScreener>,
+        audit_config: AuditTrailConfig,
+        price_guard: Arc<TradePriceGuard>,
+        breaker_config: CircuitBreakerConfig,
+        notification_config: NotificationConfig,
+        policy_config: SpendingPolicyConfig,
+        contract_analyzer: Arc<ContractAnalyzer>,
+        market_data: MarketDataSources,
+    ) -> Result<Self> {
         info!("Creating SecurityManager in demo mode");
-        let basic = BasicSecurity::new().await?;
-        let advanced = Arc::new(AdvancedSecurityManager::new_demo().await?);
+        let advanced = Arc::new(
+            AdvancedSecurityManager::new_demo(
+                sanctions.clone(),
+                audit_config,
+                price_guard,
+                breaker_config,
+                notification_config,
+                policy_config,
+                contract_analyzer,
+                market_data,
+            )
+            .await?,
+        );
+        let basic = BasicSecurity::new(Some(price_feeds), sanctions, advanced.audit_trail()).await?;
         
         Ok(Self {
             advanced,
@@ -609,8 +956,9 @@ impl SecurityManager {
     }
 
     // Delegate advanced functionality
-    pub async fn analyze_transaction(&self, tx: &TransactionRequest) -> Result<SecurityAnalysisResult> {
-        self.advanced.analyze_transaction(tx).await
+    #[instrument(skip_all, fields(chain_id = tx.chain_id.map(|id| id.as_u64()), wallet = ?tx.from))]
+    pub async fn analyze_transaction(&self, tx: &TransactionRequest, context: &PolicyContext) -> Result<SecurityAnalysisResult> {
+        self.advanced.analyze_transaction(tx, context).await
     }
 
     pub async fn apply_protections(&self, tx: TransactionRequest, analysis: &SecurityAnalysisResult) -> Result<TransactionRequest> {
@@ -629,20 +977,48 @@ impl SecurityManager {
         self.advanced.get_security_status().await
     }
 
+    pub async fn record_mev_threat(&self, threat: MevThreat) -> Result<()> {
+        self.advanced.record_mev_threat(threat).await
+    }
+
+    #[instrument(level = "debug", skip_all, fields(chain_id = chain_id, wallet = ?tx.from))]
+    pub async fn ingest_pending_transaction(&self, chain_id: u64, tx: &Transaction, ours: bool) -> Vec<MevThreat> {
+        self.advanced.ingest_pending_transaction(chain_id, tx, ours).await
+    }
+
+    pub async fn log_admin_action(&self, actor: &str, action: &str, details: String) -> Result<()> {
+        self.advanced.log_admin_action(actor, action, details).await
+    }
+
+    pub async fn shutdown(&self) -> Result<()> {
+        self.advanced.shutdown().await
+    }
+
     // Basic functionality delegation
+    #[instrument(skip_all, fields(chain_id = tx.chain_id.map(|id| id.as_u64()), wallet = ?tx.from))]
     pub async fn validate_transaction(&self, tx: &Transaction) -> Result<()> {
         self.basic.validate_transaction(tx).await
     }
 
     // Compatibility method for TypedTransaction
+    #[instrument(skip_all, fields(chain_id = tx.chain_id().map(|id| id.as_u64()), wallet = ?tx.from()))]
     pub async fn validate_typed_transaction(&self, tx: &TypedTransaction) -> Result<()> {
-        // Basic validation for TypedTransaction
-        if let Some(value) = tx.value() {
-            if *value > U256::from(1000) * U256::exp10(18) { // 1000 ETH limit
-                return Err(anyhow::anyhow!("Transaction value too high"));
-            }
-        }
-        Ok(())
+        self.basic.validate_typed_transaction(tx).await
+    }
+
+    /// Validate a transaction `signer` is about to sign and enforce the spending policies of the signer on it
+    #[instrument(skip_all, fields(chain_id = tx.chain_id().map(|id| id.as_u64()), wallet = ?signer))]
+    pub async fn authorize_typed_transaction(&self, signer: Address, tx: &TypedTransaction, context: &PolicyContext) -> Result<()> {
+        self.basic.validate_typed_transaction(tx).await?;
+        self.advanced.enforce_spending_policy(signer, tx, context).await
+    }
+
+    pub async fn get_transaction_limits(&self) -> TransactionLimits {
+        self.basic.limits().get_limits().await
+    }
+
+    pub async fn set_transaction_limits(&self, limits: TransactionLimits) -> Result<()> {
+        self.basic.limits().set_limits(limits).await
     }
 
     pub fn calculate_transaction_hash(&self, tx: &Transaction) -> Result<H256> {
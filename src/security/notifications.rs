@@ -0,0 +1,403 @@
+// Security notifications delivered to webhooks, Slack and Discord with retries and per-channel severity filters
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use super::emergency_response::{EmergencyAlert, EmergencyLevel};
+use crate::shutdown::TaskSet;
+
+/// Attempts per delivery unless `notification_max_attempts` is set
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+/// Wait before the first retry unless `notification_retry_backoff_ms` is set, doubled after every attempt
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest wait between attempts, including a `Retry-After` asked for by the channel
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Discord rejects messages longer than this
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+/// How urgent a notification is, channels only receive those at or above their minimum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl NotificationSeverity {
+    /// Severity of a 0-1 risk score or detection confidence
+    pub fn from_risk_score(score: f64) -> Self {
+        match score {
+            s if s >= 0.8 => NotificationSeverity::Critical,
+            s if s >= 0.5 => NotificationSeverity::Warning,
+            _ => NotificationSeverity::Info,
+        }
+    }
+}
+
+impl fmt::Display for NotificationSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NotificationSeverity::Info => "info",
+            NotificationSeverity::Warning => "warning",
+            NotificationSeverity::Critical => "critical",
+        })
+    }
+}
+
+impl FromStr for NotificationSeverity {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "info" => Ok(NotificationSeverity::Info),
+            "warning" => Ok(NotificationSeverity::Warning),
+            "critical" => Ok(NotificationSeverity::Critical),
+            other => Err(anyhow::anyhow!("Unknown notification severity {}, expected info, warning or critical", other)),
+        }
+    }
+}
+
+impl From<&EmergencyLevel> for NotificationSeverity {
+    fn from(level: &EmergencyLevel) -> Self {
+        match level {
+            EmergencyLevel::Info => NotificationSeverity::Info,
+            EmergencyLevel::Warning => NotificationSeverity::Warning,
+            EmergencyLevel::Critical | EmergencyLevel::Emergency => NotificationSeverity::Critical,
+        }
+    }
+}
+
+/// Event a notification reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    EmergencyAlert,
+    /// A monitored lending position near liquidation
+    LiquidationRisk,
+    /// Threats found analyzing a transaction or detected after execution
+    Threat,
+}
+
+impl fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NotificationKind::EmergencyAlert => "emergency alert",
+            NotificationKind::LiquidationRisk => "liquidation risk",
+            NotificationKind::Threat => "threat",
+        })
+    }
+}
+
+/// A security event as delivered to the channels; webhooks receive it as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityNotification {
+    pub id: String,
+    pub kind: NotificationKind,
+    pub severity: NotificationSeverity,
+    pub title: String,
+    pub message: String,
+    pub address: Option<Address>,
+    pub chain_id: Option<u64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SecurityNotification {
+    pub fn new(kind: NotificationKind, severity: NotificationSeverity, title: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind,
+            severity,
+            title: title.into(),
+            message: message.into(),
+            address: None,
+            chain_id: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn from_emergency(alert: &EmergencyAlert) -> Self {
+        let mut message = alert.description.clone();
+        if !alert.affected_addresses.is_empty() {
+            let addresses: Vec<String> = alert.affected_addresses.iter().map(|address| format!("{:?}", address)).collect();
+            message.push_str(&format!("\nAffected addresses: {}", addresses.join(", ")));
+        }
+        if !alert.affected_protocols.is_empty() {
+            message.push_str(&format!("\nAffected protocols: {}", alert.affected_protocols.join(", ")));
+        }
+        let mut notification = Self::new(NotificationKind::EmergencyAlert, (&alert.level).into(), alert.title.clone(), message);
+        notification.address = alert.affected_addresses.first().copied();
+        notification
+    }
+
+    /// Plain-text rendering for chat channels
+    fn text(&self) -> String {
+        let mut text = format!("[{}] {}: {}\n{}", self.severity, self.kind, self.title, self.message);
+        if let Some(address) = self.address {
+            text.push_str(&format!("\nAddress: {:?}", address));
+        }
+        if let Some(chain_id) = self.chain_id {
+            text.push_str(&format!("\nChain: {}", chain_id));
+        }
+        text
+    }
+}
+
+/// Kind of endpoint a channel posts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelKind {
+    /// Generic webhook receiving the notification as JSON
+    Webhook,
+    /// Slack incoming webhook
+    Slack,
+    /// Discord channel webhook
+    Discord,
+}
+
+impl fmt::Display for ChannelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChannelKind::Webhook => "webhook",
+            ChannelKind::Slack => "slack",
+            ChannelKind::Discord => "discord",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelConfig {
+    pub kind: ChannelKind,
+    /// Kept out of logs and the API, Slack and Discord webhook URLs carry their secret in the path
+    #[serde(skip_serializing)]
+    pub url: String,
+    pub min_severity: NotificationSeverity,
+}
+
+impl ChannelConfig {
+    /// Host of the URL, safe to show
+    fn target(&self) -> String {
+        reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| "invalid url".to_string())
+    }
+
+    fn payload(&self, notification: &SecurityNotification) -> serde_json::Value {
+        match self.kind {
+            ChannelKind::Webhook => json!(notification),
+            ChannelKind::Slack => {
+                let icon = match notification.severity {
+                    NotificationSeverity::Info => ":information_source:",
+                    NotificationSeverity::Warning => ":warning:",
+                    NotificationSeverity::Critical => ":rotating_light:",
+                };
+                json!({ "text": format!("{} {}", icon, notification.text()) })
+            }
+            ChannelKind::Discord => {
+                let content: String = notification.text().chars().take(DISCORD_MAX_CONTENT).collect();
+                // Alert text must not ping @everyone or roles
+                json!({ "content": content, "allowed_mentions": { "parse": [] } })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    pub channels: Vec<ChannelConfig>,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl NotificationConfig {
+    /// Channels from the comma-separated `notification_webhook_urls`, `notification_slack_webhook_urls`
+    /// and `notification_discord_webhook_urls`, each kind filtered by `notification_<kind>_min_severity`
+    /// (default `warning`); retries from `notification_max_attempts` and `notification_retry_backoff_ms`
+    pub fn from_config(config: &config::Config) -> Self {
+        let defaults = Self::default();
+        let mut channels = Vec::new();
+        for kind in [ChannelKind::Webhook, ChannelKind::Slack, ChannelKind::Discord] {
+            let urls_key = match kind {
+                ChannelKind::Webhook => "notification_webhook_urls".to_string(),
+                _ => format!("notification_{}_webhook_urls", kind),
+            };
+            let severity_key = format!("notification_{}_min_severity", kind);
+            let min_severity = match config.get_string(&severity_key) {
+                Ok(value) => value.parse().unwrap_or_else(|e| {
+                    warn!("Ignoring {}: {}", severity_key, e);
+                    NotificationSeverity::Warning
+                }),
+                Err(_) => NotificationSeverity::Warning,
+            };
+            let urls = config.get_string(&urls_key).unwrap_or_default();
+            channels.extend(urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(|url| ChannelConfig { kind, url: url.to_string(), min_severity }));
+        }
+        Self {
+            channels,
+            max_attempts: config
+                .get_int("notification_max_attempts")
+                .map(|attempts| attempts.clamp(1, 10) as u32)
+                .unwrap_or(defaults.max_attempts),
+            initial_backoff: config
+                .get_int("notification_retry_backoff_ms")
+                .map(|millis| Duration::from_millis(millis.max(0) as u64))
+                .unwrap_or(defaults.initial_backoff),
+        }
+    }
+}
+
+/// Deliveries of one channel so far
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelStatus {
+    pub kind: ChannelKind,
+    /// Host the channel posts to
+    pub target: String,
+    pub min_severity: NotificationSeverity,
+    pub delivered: u64,
+    /// Notifications given up on after every attempt failed
+    pub failed: u64,
+    pub retries: u64,
+    pub last_delivered_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Sends security notifications to every channel whose minimum severity they reach
+pub struct NotificationService {
+    config: NotificationConfig,
+    http: reqwest::Client,
+    statuses: Arc<RwLock<Vec<ChannelStatus>>>,
+    deliveries: TaskSet,
+}
+
+impl NotificationService {
+    pub fn new(config: NotificationConfig) -> Self {
+        info!("Initializing NotificationService with {} channels", config.channels.len());
+        let statuses = config.channels.iter()
+            .map(|channel| ChannelStatus {
+                kind: channel.kind,
+                target: channel.target(),
+                min_severity: channel.min_severity,
+                delivered: 0,
+                failed: 0,
+                retries: 0,
+                last_delivered_at: None,
+                last_error: None,
+            })
+            .collect();
+        Self {
+            config,
+            http: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            statuses: Arc::new(RwLock::new(statuses)),
+            deliveries: TaskSet::default(),
+        }
+    }
+
+    pub async fn statuses(&self) -> Vec<ChannelStatus> {
+        self.statuses.read().await.clone()
+    }
+
+    /// Deliveries in flight, for shutdown to wait for
+    pub fn deliveries(&self) -> TaskSet {
+        self.deliveries.clone()
+    }
+
+    /// Deliver a notification in the background, so a slow or failing channel never holds up the caller
+    pub fn notify(&self, notification: SecurityNotification) {
+        for (index, channel) in self.config.channels.iter().enumerate() {
+            if notification.severity < channel.min_severity {
+                continue;
+            }
+            let channel = channel.clone();
+            let http = self.http.clone();
+            let statuses = self.statuses.clone();
+            let payload = channel.payload(&notification);
+            let id = notification.id.clone();
+            let (max_attempts, initial_backoff) = (self.config.max_attempts, self.config.initial_backoff);
+            self.deliveries.spawn(async move {
+                let (result, retries) = deliver(&http, &channel, &payload, max_attempts, initial_backoff).await;
+                let mut statuses = statuses.write().await;
+                let status = &mut statuses[index];
+                status.retries += retries;
+                match result {
+                    Ok(()) => {
+                        debug!("Delivered notification {} to {} channel {}", id, channel.kind, status.target);
+                        status.delivered += 1;
+                        status.last_delivered_at = Some(Utc::now());
+                    }
+                    Err(e) => {
+                        warn!("Giving up on notification {} to {} channel {}: {}", id, channel.kind, status.target, e);
+                        status.failed += 1;
+                        status.last_error = Some(e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Post a payload until it is accepted, backing off exponentially or as long as the channel asks;
+/// client errors other than rate limiting are not retried. Returns the outcome and the retries made
+async fn deliver(
+    http: &reqwest::Client,
+    channel: &ChannelConfig,
+    payload: &serde_json::Value,
+    max_attempts: u32,
+    initial_backoff: Duration,
+) -> (Result<(), String>, u64) {
+    let mut backoff = initial_backoff;
+    let mut retries = 0;
+    for attempt in 1..=max_attempts {
+        let (error, retry_after) = match http.post(&channel.url).json(payload).send().await {
+            Ok(response) if response.status().is_success() => return (Ok(()), retries),
+            Ok(response) => {
+                let status = response.status();
+                if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                    return (Err(format!("rejected with {}", status)), retries);
+                }
+                let retry_after = response.headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<f64>().ok())
+                    .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                    .map(Duration::from_secs_f64);
+                (format!("HTTP {}", status), retry_after)
+            }
+            Err(e) => (e.to_string(), None),
+        };
+        if attempt == max_attempts {
+            return (Err(format!("{} after {} attempts", error, attempt)), retries);
+        }
+        debug!("Attempt {} to {} channel {} failed: {}", attempt, channel.kind, channel.target(), error);
+        tokio::time::sleep(retry_after.unwrap_or(backoff).min(MAX_BACKOFF)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        retries += 1;
+    }
+    (Err("no delivery attempted".to_string()), retries)
+}
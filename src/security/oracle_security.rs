@@ -1,14 +1,19 @@
 use anyhow::{Result, anyhow};
 use ethers::{
+    abi::{self, ParamType, Token},
     prelude::*,
     types::{Address, U256, H256, Bytes},
 };
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc, Duration};
 
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService, PriceSource};
+use crate::chains::tokens::TokenRegistry;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OracleType {
     Chainlink,
@@ -547,3 +552,398 @@ pub struct OracleSecurityStats {
     pub total_price_validations: usize,
     pub anomalies_detected: usize,
 }
+
+/// Thresholds trade prices are checked against
+#[derive(Debug, Clone)]
+pub struct PriceGuardConfig {
+    /// Deviation from the oracle price above which a trade is flagged
+    pub flag_deviation_percentage: f64,
+    /// Deviation from the oracle price above which a trade is refused
+    pub block_deviation_percentage: f64,
+    /// Spread between Chainlink and the DEX TWAP above which a token's reference is flagged
+    pub max_source_deviation_percentage: f64,
+    /// Age after which a Chainlink round no longer counts as a reference
+    pub max_staleness: Duration,
+    /// Refuse trades in tokens without a fresh oracle price instead of flagging them
+    pub refuse_unpriced: bool,
+}
+
+impl Default for PriceGuardConfig {
+    fn default() -> Self {
+        Self {
+            flag_deviation_percentage: 3.0,
+            block_deviation_percentage: 10.0,
+            max_source_deviation_percentage: 2.0,
+            max_staleness: Duration::hours(1),
+            refuse_unpriced: false,
+        }
+    }
+}
+
+impl PriceGuardConfig {
+    /// Thresholds from `oracle_flag_deviation_percentage`, `oracle_block_deviation_percentage`,
+    /// `oracle_max_source_deviation_percentage`, `oracle_max_staleness_secs` and `oracle_refuse_unpriced_trades`
+    pub fn from_config(config: &config::Config) -> Self {
+        let defaults = Self::default();
+        let percentage = |key: &str, default: f64| config.get_float(key).map(|value| value.max(0.0)).unwrap_or(default);
+        Self {
+            flag_deviation_percentage: percentage("oracle_flag_deviation_percentage", defaults.flag_deviation_percentage),
+            block_deviation_percentage: percentage("oracle_block_deviation_percentage", defaults.block_deviation_percentage),
+            max_source_deviation_percentage: percentage(
+                "oracle_max_source_deviation_percentage",
+                defaults.max_source_deviation_percentage,
+            ),
+            max_staleness: config
+                .get_int("oracle_max_staleness_secs")
+                .map(|secs| Duration::seconds(secs.max(1)))
+                .unwrap_or(defaults.max_staleness),
+            refuse_unpriced: config.get_bool("oracle_refuse_unpriced_trades").unwrap_or(defaults.refuse_unpriced),
+        }
+    }
+}
+
+/// Outcome of checking a trade price against the oracles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceVerdict {
+    Pass,
+    /// Within the blocking tolerance but worth reviewing: a deviation above the flag threshold,
+    /// oracles disagreeing, a stale round or a token without a fresh oracle price
+    Flag,
+    /// Beyond the blocking tolerance, or unpriced when `refuse_unpriced` is set
+    Block,
+}
+
+/// One oracle's price of a token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceQuote {
+    pub source: PriceSource,
+    pub price_usd: f64,
+    pub updated_at: DateTime<Utc>,
+    pub age_secs: i64,
+    /// Older than the staleness window, so left out of the reference price
+    pub stale: bool,
+}
+
+/// Oracle prices of one token and the reference taken from the fresh ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenReference {
+    pub token: Address,
+    pub quotes: Vec<ReferenceQuote>,
+    /// Median of the fresh quotes
+    pub reference_price_usd: Option<f64>,
+    /// Spread between the fresh quotes relative to the lowest
+    pub source_deviation_percentage: Option<f64>,
+}
+
+/// Price a trade executes at compared with the price the oracles imply
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradePriceCheck {
+    pub chain_id: u64,
+    pub token_in: TokenReference,
+    pub token_out: TokenReference,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// Whole `token_out` per whole `token_in` the trade pays
+    pub implied_price: f64,
+    /// Whole `token_out` per whole `token_in` at the reference prices
+    pub oracle_price: Option<f64>,
+    /// Signed deviation of the implied price from the oracle price, negative when the trade pays less
+    pub deviation_percentage: Option<f64>,
+    pub verdict: PriceVerdict,
+    pub findings: Vec<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// A trade refused because its price deviates too far from the oracles
+#[derive(Debug, Clone)]
+pub struct PriceDeviation(pub TradePriceCheck);
+
+impl fmt::Display for PriceDeviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.deviation_percentage.is_none() {
+            return write!(
+                f,
+                "Trade of {:?} for {:?} cannot be checked against the oracles: {}",
+                self.0.token_in.token,
+                self.0.token_out.token,
+                self.0.findings.join("; "),
+            );
+        }
+        write!(
+            f,
+            "Trade price {:.6} of {:?} per {:?} deviates from the oracles: {}",
+            self.0.implied_price,
+            self.0.token_out.token,
+            self.0.token_in.token,
+            self.0.findings.join("; "),
+        )
+    }
+}
+
+impl std::error::Error for PriceDeviation {}
+
+/// Swap a router call makes, with the least output it accepts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapCall {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub min_amount_out: U256,
+}
+
+/// Decode the Uniswap V2-style exact-input swaps and Uniswap V3 `exactInputSingle` calls the DEX
+/// managers build, `None` for any other call
+pub fn decode_swap(tx: &TransactionRequest) -> Option<SwapCall> {
+    let data = tx.data.as_ref()?;
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, arguments) = data.split_at(4);
+
+    let v2_path = vec![ParamType::Array(Box::new(ParamType::Address)), ParamType::Address, ParamType::Uint(256)];
+    let path_swap = |leading: Vec<ParamType>, tokens: Vec<Token>| -> Option<(Vec<Address>, Vec<U256>)> {
+        let amounts = tokens[..leading.len()].iter().map(|token| token.clone().into_uint()).collect::<Option<Vec<_>>>()?;
+        let path = tokens.get(leading.len())?.clone().into_array()?
+            .into_iter()
+            .map(Token::into_address)
+            .collect::<Option<Vec<_>>>()?;
+        (path.len() >= 2).then_some((path, amounts))
+    };
+
+    if selector == ethers::utils::id("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)")
+        || selector == ethers::utils::id("swapExactTokensForETH(uint256,uint256,address[],address,uint256)")
+    {
+        let leading = vec![ParamType::Uint(256), ParamType::Uint(256)];
+        let tokens = abi::decode(&[leading.clone(), v2_path].concat(), arguments).ok()?;
+        let (path, amounts) = path_swap(leading, tokens)?;
+        return Some(SwapCall {
+            token_in: path[0],
+            token_out: *path.last()?,
+            amount_in: amounts[0],
+            min_amount_out: amounts[1],
+        });
+    }
+    if selector == ethers::utils::id("swapExactETHForTokens(uint256,address[],address,uint256)") {
+        let leading = vec![ParamType::Uint(256)];
+        let tokens = abi::decode(&[leading.clone(), v2_path].concat(), arguments).ok()?;
+        let (path, amounts) = path_swap(leading, tokens)?;
+        return Some(SwapCall {
+            token_in: path[0],
+            token_out: *path.last()?,
+            amount_in: tx.value.unwrap_or_default(),
+            min_amount_out: amounts[0],
+        });
+    }
+
+    // SwapRouter takes a deadline in the parameters, SwapRouter02 does not
+    let (with_deadline, amount_index) = if selector
+        == ethers::utils::id("exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))")
+    {
+        (true, 5)
+    } else if selector == ethers::utils::id("exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))") {
+        (false, 4)
+    } else {
+        return None;
+    };
+    let mut fields = vec![ParamType::Address, ParamType::Address, ParamType::Uint(24), ParamType::Address];
+    if with_deadline {
+        fields.push(ParamType::Uint(256));
+    }
+    fields.extend([ParamType::Uint(256), ParamType::Uint(256), ParamType::Uint(160)]);
+    let params = abi::decode(&[ParamType::Tuple(fields)], arguments).ok()?.pop()?.into_tuple()?;
+    Some(SwapCall {
+        token_in: params.first()?.clone().into_address()?,
+        token_out: params.get(1)?.clone().into_address()?,
+        amount_in: params.get(amount_index)?.clone().into_uint()?,
+        min_amount_out: params.get(amount_index + 1)?.clone().into_uint()?,
+    })
+}
+
+/// Checks the price a swap or liquidation trades at against Chainlink and DEX TWAP prices
+pub struct TradePriceGuard {
+    price_feeds: Arc<PriceFeedService>,
+    tokens: Arc<TokenRegistry>,
+    config: PriceGuardConfig,
+}
+
+impl TradePriceGuard {
+    pub fn new(price_feeds: Arc<PriceFeedService>, tokens: Arc<TokenRegistry>, config: PriceGuardConfig) -> Self {
+        tracing::info!(
+            "Checking trade prices against oracles: flag above {}%, block above {}%",
+            config.flag_deviation_percentage,
+            config.block_deviation_percentage,
+        );
+        Self { price_feeds, tokens, config }
+    }
+
+    pub fn config(&self) -> &PriceGuardConfig {
+        &self.config
+    }
+
+    /// Compare the price of trading `amount_in` of `token_in` for `amount_out` of `token_out` with the
+    /// oracle prices of both tokens
+    pub async fn check_trade(
+        &self,
+        chain_id: u64,
+        token_in: Address,
+        amount_in: U256,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<TradePriceCheck> {
+        let (reference_in, reference_out, decimals_in, decimals_out) = tokio::join!(
+            self.reference(chain_id, token_in),
+            self.reference(chain_id, token_out),
+            self.decimals(chain_id, token_in),
+            self.decimals(chain_id, token_out),
+        );
+        let units_in = to_units(amount_in, decimals_in?)?;
+        let units_out = to_units(amount_out, decimals_out?)?;
+        if units_in <= 0.0 {
+            return Err(anyhow!("Cannot price a trade of zero {:?}", token_in));
+        }
+        let implied_price = units_out / units_in;
+
+        let mut findings = Vec::new();
+        let mut flagged = false;
+        for reference in [&reference_in, &reference_out] {
+            for quote in reference.quotes.iter().filter(|quote| quote.stale) {
+                flagged = true;
+                findings.push(format!(
+                    "{:?} price of {:?} is stale ({}s old)",
+                    quote.source, reference.token, quote.age_secs,
+                ));
+            }
+            match reference.source_deviation_percentage {
+                Some(spread) if spread > self.config.max_source_deviation_percentage => {
+                    flagged = true;
+                    findings.push(format!(
+                        "Oracles disagree on {:?} by {:.2}% (tolerance {}%)",
+                        reference.token, spread, self.config.max_source_deviation_percentage,
+                    ));
+                }
+                _ => {}
+            }
+            if reference.reference_price_usd.is_none() {
+                findings.push(format!("No fresh Chainlink or TWAP price for {:?}", reference.token));
+            }
+        }
+
+        let oracle_price = reference_in.reference_price_usd
+            .zip(reference_out.reference_price_usd)
+            .filter(|(_, price_out)| *price_out > 0.0)
+            .map(|(price_in, price_out)| price_in / price_out);
+        let deviation_percentage = oracle_price.map(|oracle_price| (implied_price / oracle_price - 1.0) * 100.0);
+
+        let verdict = match deviation_percentage {
+            None if self.config.refuse_unpriced => {
+                findings.push("Trades without an oracle price to check against are refused".to_string());
+                PriceVerdict::Block
+            }
+            None => PriceVerdict::Flag,
+            Some(deviation) if deviation.abs() > self.config.block_deviation_percentage => {
+                findings.push(format!(
+                    "Trade price deviates {:.2}% from the oracle price (limit {}%)",
+                    deviation, self.config.block_deviation_percentage,
+                ));
+                PriceVerdict::Block
+            }
+            Some(deviation) if deviation.abs() > self.config.flag_deviation_percentage => {
+                findings.push(format!(
+                    "Trade price deviates {:.2}% from the oracle price (flagged above {}%)",
+                    deviation, self.config.flag_deviation_percentage,
+                ));
+                PriceVerdict::Flag
+            }
+            Some(_) if flagged => PriceVerdict::Flag,
+            Some(_) => PriceVerdict::Pass,
+        };
+
+        Ok(TradePriceCheck {
+            chain_id,
+            token_in: reference_in,
+            token_out: reference_out,
+            amount_in,
+            amount_out,
+            implied_price,
+            oracle_price,
+            deviation_percentage,
+            verdict,
+            findings,
+            checked_at: Utc::now(),
+        })
+    }
+
+    /// Check a trade, failing with `PriceDeviation` when its price is beyond the blocking tolerance
+    pub async fn validate_trade(
+        &self,
+        chain_id: u64,
+        token_in: Address,
+        amount_in: U256,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<TradePriceCheck> {
+        let check = self.check_trade(chain_id, token_in, amount_in, token_out, amount_out).await?;
+        match check.verdict {
+            PriceVerdict::Block => {
+                tracing::warn!("Refusing trade on chain {}: {}", chain_id, PriceDeviation(check.clone()));
+                return Err(PriceDeviation(check).into());
+            }
+            PriceVerdict::Flag => tracing::warn!("Flagged trade price on chain {}: {}", chain_id, check.findings.join("; ")),
+            PriceVerdict::Pass => {}
+        }
+        Ok(check)
+    }
+
+    /// USD value of `amount` of a token at the median of its fresh oracle prices, `None` without one
+    pub async fn value_usd(&self, chain_id: u64, token: Address, amount: U256) -> Result<Option<f64>> {
+        let (reference, decimals) = tokio::join!(self.reference(chain_id, token), self.decimals(chain_id, token));
+        let units = to_units(amount, decimals?)?;
+        Ok(reference.reference_price_usd.map(|price| units * price))
+    }
+
+    async fn reference(&self, chain_id: u64, token: Address) -> TokenReference {
+        let now = Utc::now();
+        let quotes: Vec<ReferenceQuote> = self.price_feeds
+            .source_prices(chain_id, pricing_address(chain_id, token))
+            .await
+            .into_iter()
+            .map(|price| {
+                let age = now.signed_duration_since(price.updated_at);
+                ReferenceQuote {
+                    source: price.source,
+                    price_usd: price.price_usd,
+                    updated_at: price.updated_at,
+                    age_secs: age.num_seconds().max(0),
+                    stale: age > self.config.max_staleness,
+                }
+            })
+            .collect();
+
+        let mut fresh: Vec<f64> = quotes.iter()
+            .filter(|quote| !quote.stale && quote.price_usd > 0.0)
+            .map(|quote| quote.price_usd)
+            .collect();
+        fresh.sort_by(|a, b| a.total_cmp(b));
+        let reference_price_usd = match fresh.len() {
+            0 => None,
+            len if len % 2 == 0 => Some((fresh[len / 2 - 1] + fresh[len / 2]) / 2.0),
+            len => Some(fresh[len / 2]),
+        };
+        let source_deviation_percentage = (fresh.len() > 1)
+            .then(|| (fresh[fresh.len() - 1] - fresh[0]) / fresh[0] * 100.0);
+
+        TokenReference { token, quotes, reference_price_usd, source_deviation_percentage }
+    }
+
+    async fn decimals(&self, chain_id: u64, token: Address) -> Result<u8> {
+        if token.is_zero() {
+            return Ok(18); // native gas token
+        }
+        Ok(self.tokens.validate(chain_id, token).await?.decimals)
+    }
+}
+
+fn to_units(amount: U256, decimals: u8) -> Result<f64> {
+    Ok(ethers::utils::format_units(amount, decimals as u32)?.parse()?)
+}
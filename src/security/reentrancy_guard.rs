@@ -1,67 +1,125 @@
 use anyhow::Result;
-use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+use ethers::prelude::*;
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use super::contract_analysis::{CodeCheck, CodeFinding, CodeRisk};
+
+const STOP: u8 = 0x00;
+const ORIGIN: u8 = 0x32;
+const CALLDATALOAD: u8 = 0x35;
+const SSTORE: u8 = 0x55;
+const JUMP: u8 = 0x56;
+const JUMPDEST: u8 = 0x5b;
+const PUSH20: u8 = 0x73;
+const CALL: u8 = 0xf1;
+const CALLCODE: u8 = 0xf2;
+const RETURN: u8 = 0xf3;
+const DELEGATECALL: u8 = 0xf4;
+const REVERT: u8 = 0xfd;
+const INVALID: u8 = 0xfe;
+const SELFDESTRUCT: u8 = 0xff;
+
+/// EIP-1167 minimal proxy runtime code around the 20-byte implementation address
+const MINIMAL_PROXY_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const MINIMAL_PROXY_SUFFIX: [u8; 15] = [0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3];
+/// Opcodes looked at on either side of an external call for storage writes
+const CALL_WINDOW: usize = 64;
+/// Opcodes looked back from a DELEGATECALL for where its target came from
+const DELEGATE_LOOKBACK: usize = 32;
+
+/// What the bytecode of one contract shows
+#[derive(Debug, Clone, Default)]
+pub struct BytecodeAnalysis {
+    pub findings: Vec<CodeFinding>,
+    /// Implementation an EIP-1167 minimal proxy forwards every call to
+    pub minimal_proxy_target: Option<Address>,
+    /// DELEGATECALL to an address loaded or computed at run time, as proxies do
+    pub dynamic_delegatecall: bool,
+}
+
+
 #[derive(Debug)]
 pub struct ReentrancyGuard {
     active_transactions: Arc<RwLock<HashSet<H256>>>,
-    suspicious_patterns: Vec<Vec<u8>>,
 }
 
 impl ReentrancyGuard {
     pub fn new() -> Self {
-        let mut suspicious_patterns = Vec::new();
-        
-        // Add known reentrancy patterns (function selectors)
-        suspicious_patterns.push(vec![0xa9, 0x05, 0x9c, 0xbb]); // transfer
-        suspicious_patterns.push(vec![0x23, 0xb8, 0x72, 0xdd]); // transferFrom
-        suspicious_patterns.push(vec![0x2e, 0x1a, 0x7d, 0x4d]); // call
-
         Self {
             active_transactions: Arc::new(RwLock::new(HashSet::new())),
-            suspicious_patterns,
         }
     }
 
-    pub async fn check_transaction(&self, tx: &TypedTransaction) -> Result<()> {
-        // Check for suspicious function calls in transaction data
-        if let Some(data) = tx.data() {
-            if data.len() >= 4 {
-                let function_selector = &data[0..4];
-                
-                for pattern in &self.suspicious_patterns {
-                    if function_selector == pattern.as_slice() {
-                        // Additional checks for potential reentrancy
-                        self.analyze_call_pattern(data)?;
-                    }
-                }
-            }
+    /// Heuristics over the reachable instructions of runtime code: self-destruction, CALLCODE,
+    /// delegation to caller-supplied or run-time addresses, storage written after external calls
+    /// and `tx.origin`
+    pub fn analyze_bytecode(&self, contract: Address, code: &[u8]) -> BytecodeAnalysis {
+        let mut analysis = BytecodeAnalysis::default();
+        if code.len() == MINIMAL_PROXY_PREFIX.len() + 20 + MINIMAL_PROXY_SUFFIX.len()
+            && code.starts_with(&MINIMAL_PROXY_PREFIX)
+            && code.ends_with(&MINIMAL_PROXY_SUFFIX)
+        {
+            analysis.minimal_proxy_target = Some(Address::from_slice(&code[MINIMAL_PROXY_PREFIX.len()..MINIMAL_PROXY_PREFIX.len() + 20]));
+            return analysis;
         }
 
-        Ok(())
-    }
+        let instructions = reachable_instructions(strip_metadata(code));
+        let findings = &mut analysis.findings;
+        let mut finding = |check, risk, detail: String| findings.push(CodeFinding { check, risk, contract: Some(contract), detail });
+        let has = |opcode: u8| instructions.contains(&opcode);
 
-    fn analyze_call_pattern(&self, data: &[u8]) -> Result<()> {
-        // Analyze the call data for reentrancy patterns
-        // This is a simplified check - production would be more sophisticated
-        
-        if data.len() > 100 {
-            // Check for multiple external calls in the same transaction
-            let mut call_count = 0;
-            for window in data.windows(4) {
-                if window == [0x2e, 0x1a, 0x7d, 0x4d] { // call function selector
-                    call_count += 1;
-                }
-            }
-            
-            if call_count > 3 {
-                return Err(anyhow::anyhow!("Potential reentrancy detected: multiple calls"));
+        if has(SELFDESTRUCT) {
+            finding(CodeCheck::SelfDestruct, CodeRisk::High, "Can self-destruct, sending away the Ether it holds".to_string());
+        }
+        if has(CALLCODE) {
+            finding(CodeCheck::Callcode, CodeRisk::Medium, "Uses the deprecated CALLCODE to run foreign code on its storage".to_string());
+        }
+        if has(ORIGIN) {
+            finding(CodeCheck::TxOrigin, CodeRisk::Low, "Reads tx.origin, which a contract the sender calls can act on".to_string());
+        }
+
+        let mut caller_supplied = false;
+        let mut dynamic = false;
+        for (index, _) in instructions.iter().enumerate().filter(|(_, &opcode)| opcode == DELEGATECALL) {
+            // Walk back through the basic block to where the target came from
+            let source = instructions[index.saturating_sub(DELEGATE_LOOKBACK)..index].iter().rev()
+                .take_while(|&&opcode| opcode != JUMPDEST)
+                .find(|&&opcode| matches!(opcode, CALLDATALOAD | PUSH20));
+            match source {
+                Some(&CALLDATALOAD) => caller_supplied = true,
+                Some(_) => {}
+                None => dynamic = true,
             }
         }
+        if caller_supplied {
+            finding(
+                CodeCheck::DelegatecallToUnknown,
+                CodeRisk::High,
+                "Delegates to an address taken from the calldata, letting callers run any code with its storage and balance".to_string(),
+            );
+        }
+        // Caller-supplied targets are already a finding, the rest may be a proxy's
+        analysis.dynamic_delegatecall = dynamic;
 
-        Ok(())
+        let unguarded_calls = instructions.iter().enumerate()
+            .filter(|(_, &opcode)| opcode == CALL)
+            .filter(|&(index, _)| {
+                let before = &instructions[index.saturating_sub(CALL_WINDOW)..index];
+                let after = &instructions[index + 1..(index + 1 + CALL_WINDOW).min(instructions.len())];
+                after.contains(&SSTORE) && !before.contains(&SSTORE)
+            })
+            .count();
+        if unguarded_calls > 0 {
+            finding(
+                CodeCheck::StateWriteAfterCall,
+                CodeRisk::Medium,
+                format!("{} external calls are followed by storage writes with no write before them, open to reentrancy", unguarded_calls),
+            );
+        }
+
+        analysis
     }
 
     pub async fn enter_transaction(&self, tx_hash: H256) -> Result<()> {
@@ -79,3 +137,40 @@ impl ReentrancyGuard {
         Ok(())
     }
 }
+
+/// Runtime code without the CBOR metadata the Solidity and Vyper compilers append, whose length is
+/// in the last two bytes
+fn strip_metadata(code: &[u8]) -> &[u8] {
+    if code.len() < 2 {
+        return code;
+    }
+    let length = u16::from_be_bytes([code[code.len() - 2], code[code.len() - 1]]) as usize;
+    match code.len().checked_sub(length + 2) {
+        Some(start) if (0xa1..=0xa5).contains(&code[start]) => &code[..start],
+        _ => code,
+    }
+}
+
+/// Opcodes that can run, without PUSH data: after a halt or an unconditional jump, bytes up to the
+/// next JUMPDEST are data, not code
+fn reachable_instructions(code: &[u8]) -> Vec<u8> {
+    let mut instructions = Vec::new();
+    let mut reachable = true;
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        // PUSH1..PUSH32 carry 1 to 32 bytes of immediate data to skip
+        let size = if (0x60..=0x7f).contains(&opcode) { (opcode - 0x5f) as usize } else { 0 };
+        if opcode == JUMPDEST {
+            reachable = true;
+        }
+        if reachable {
+            instructions.push(opcode);
+        }
+        if matches!(opcode, STOP | JUMP | RETURN | REVERT | INVALID | SELFDESTRUCT) {
+            reachable = false;
+        }
+        pc += 1 + size;
+    }
+    instructions
+}
@@ -3,11 +3,26 @@ use ethers::{
     prelude::*,
     types::{Address, U256, TransactionRequest, H256},
 };
+use futures::future::join_all;
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
 use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc, Duration};
+use chrono::{DateTime, Utc};
+
+use super::market_data::{
+    self, DepthLevel, MarketDataSources, MarketRiskData, PairLiquidity, TokenCorrelation, TokenMarketSnapshot,
+    DEPTH_PRICE_IMPACTS,
+};
+use super::oracle_security::decode_swap;
+use super::stress_test::{self, StressScenario, StressTestResult};
+use super::transaction_limits::TransactionLimitEnforcer;
+use crate::analytics::price_feeds::pricing_address;
+use crate::chains::assets::AssetRegistry;
+use crate::defi::PositionValuation;
+use crate::shutdown::ShutdownSignal;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RiskAssessment {
@@ -74,13 +89,15 @@ pub struct RiskModel {
     pub last_updated: DateTime<Utc>,
 }
 
+/// One market data sample of a token
 #[derive(Debug, Clone)]
 pub struct MarketData {
-    pub token_price: U256,
-    pub volatility: f64,
-    pub liquidity: U256,
-    pub trading_volume_24h: U256,
-    pub market_cap: U256,
+    pub chain_id: u64,
+    pub price_usd: f64,
+    /// Daily volatility over the window up to this sample, `None` until there are enough samples
+    pub volatility: Option<f64>,
+    /// Main Uniswap V2 pair of the token, `None` when it has none
+    pub liquidity: Option<PairLiquidity>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -102,6 +119,10 @@ pub struct RiskEngine {
     historical_assessments: Arc<RwLock<VecDeque<RiskAssessment>>>,
     risk_calculator: Arc<RwLock<RiskCalculator>>,
     stress_tester: Arc<RwLock<StressTester>>,
+    /// Price feeds and DEX pools market data is sampled from, `None` leaves market risks unassessed
+    sources: Option<MarketDataSources>,
+    /// Tokens sampled, with their chain
+    tracked: Arc<RwLock<HashMap<Address, u64>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -114,41 +135,21 @@ struct RiskCalculator {
 #[derive(Debug, Clone)]
 struct VolatilityModel {
     current_volatility: f64,
-    historical_volatility: Vec<f64>,
-    volatility_forecast: Vec<f64>,
+    volatility_forecast: f64,
     confidence_interval: (f64, f64),
 }
 
 #[derive(Debug, Clone)]
 struct LiquidityModel {
     current_liquidity: U256,
-    liquidity_depth: HashMap<f64, U256>, // Price impact -> Available liquidity
-    slippage_model: Vec<(U256, f64)>, // Trade size -> Expected slippage
+    pool_value_usd: f64,
+    liquidity_depth: Vec<(f64, f64)>, // Price impact -> Available liquidity in USD
 }
 
 #[derive(Debug, Clone)]
 struct StressTester {
+    /// Preset scenarios, by name
     stress_scenarios: Vec<StressScenario>,
-    scenario_results: HashMap<String, StressTestResult>,
-}
-
-#[derive(Debug, Clone)]
-struct StressScenario {
-    pub name: String,
-    pub description: String,
-    pub market_shock: f64, // Price shock percentage
-    pub liquidity_drain: f64, // Liquidity reduction percentage
-    pub correlation_increase: f64, // Correlation increase during stress
-    pub duration: Duration,
-}
-
-#[derive(Debug, Clone)]
-struct StressTestResult {
-    pub scenario_name: String,
-    pub portfolio_loss: f64,
-    pub max_drawdown: f64,
-    pub liquidation_probability: f64,
-    pub recovery_time: Duration,
 }
 
 impl RiskEngine {
@@ -165,17 +166,201 @@ impl RiskEngine {
                 liquidity_models: HashMap::new(),
             })),
             stress_tester: Arc::new(RwLock::new(StressTester {
-                stress_scenarios: Vec::new(),
-                scenario_results: HashMap::new(),
+                stress_scenarios: stress_test::presets(),
             })),
+            sources: None,
+            tracked: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Risk engine sampling market data of the configured tokens and those of analyzed transactions
+    pub fn with_market_data(provider: Arc<Provider<Http>>, sources: MarketDataSources) -> Self {
+        let tracked = sources.config.tokens.iter().map(|&(chain_id, token)| (token, chain_id)).collect();
+        Self {
+            sources: Some(sources),
+            tracked: Arc::new(RwLock::new(tracked)),
+            ..Self::new(provider)
+        }
+    }
+
+    /// Sample market data every interval until shutdown; without sources there is nothing to sample
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let Some(interval) = self.sources.as_ref().map(|sources| sources.config.sample_interval) else {
+                return;
+            };
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.collect_market_data().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Market data collector stopped");
+        })
+    }
+
+    /// Sample the price and pair liquidity of every tracked token, then recompute its volatility
+    /// and the correlations between tokens
+    pub async fn collect_market_data(&self) {
+        let Some(sources) = &self.sources else {
+            return;
+        };
+        let tracked: Vec<(Address, u64)> = self.tracked.read().await.iter().map(|(&token, &chain_id)| (token, chain_id)).collect();
+        let samples = join_all(tracked.iter().map(|&(token, chain_id)| async move {
+            let price = sources.price_feeds.get_price(chain_id, token).await?;
+            let liquidity = match market_data::pair_liquidity(sources, chain_id, token).await {
+                Ok(liquidity) => Some(liquidity),
+                Err(e) => {
+                    debug!("No Uniswap V2 liquidity for {:?} on chain {}: {}", token, chain_id, e);
+                    None
+                }
+            };
+            Ok::<_, anyhow::Error>((price.price_usd, liquidity))
+        }))
+        .await;
+
+        // One timestamp per round, so the series of different tokens line up for correlations
+        let timestamp = Utc::now();
+        let window = sources.config.window_samples();
+        let mut market_data = self.market_data.write().await;
+        let mut calculator = self.risk_calculator.write().await;
+        let mut untracked = Vec::new();
+        for ((token, chain_id), sample) in tracked.into_iter().zip(samples) {
+            let (price_usd, liquidity) = match sample {
+                Ok(sample) => sample,
+                Err(e) => {
+                    debug!("No price for {:?} on chain {}: {}", token, chain_id, e);
+                    // Tokens picked up from transactions are dropped if they never had a price
+                    if !market_data.contains_key(&token) && !sources.config.tokens.contains(&(chain_id, token)) {
+                        untracked.push(token);
+                    }
+                    continue;
+                }
+            };
+
+            let history = market_data.entry(token).or_default();
+            let prices: Vec<f64> = history.iter().map(|data| data.price_usd).chain(std::iter::once(price_usd)).collect();
+            let estimate = market_data::volatility(&prices[prices.len().saturating_sub(window)..], &sources.config);
+            history.push_back(MarketData {
+                chain_id,
+                price_usd,
+                volatility: estimate.map(|estimate| estimate.volatility),
+                liquidity,
+                timestamp,
+            });
+            while history.len() > window {
+                history.pop_front();
+            }
+
+            match estimate {
+                Some(estimate) => {
+                    calculator.volatility_models.insert(token, VolatilityModel {
+                        current_volatility: estimate.volatility,
+                        volatility_forecast: estimate.forecast,
+                        confidence_interval: estimate.interval,
+                    });
+                }
+                None => {
+                    calculator.volatility_models.remove(&token);
+                }
+            }
+            match liquidity {
+                Some(liquidity) => {
+                    calculator.liquidity_models.insert(token, LiquidityModel {
+                        current_liquidity: liquidity.reserve,
+                        pool_value_usd: liquidity.pool_value_usd,
+                        liquidity_depth: DEPTH_PRICE_IMPACTS.iter().map(|&impact| (impact, liquidity.depth_usd(impact))).collect(),
+                    });
+                }
+                None => {
+                    calculator.liquidity_models.remove(&token);
+                }
+            }
+        }
+
+        let series: Vec<(Address, VecDeque<(DateTime<Utc>, f64)>)> = market_data.iter()
+            .map(|(&token, history)| (token, history.iter().map(|data| (data.timestamp, data.price_usd)).collect()))
+            .collect();
+        calculator.correlation_matrix.clear();
+        for (i, (token_a, series_a)) in series.iter().enumerate() {
+            for (token_b, series_b) in series.iter().skip(i + 1) {
+                if let Some(correlation) = market_data::correlation(series_a, series_b) {
+                    calculator.correlation_matrix.insert((*token_a, *token_b), correlation);
+                    calculator.correlation_matrix.insert((*token_b, *token_a), correlation);
+                }
+            }
+        }
+        drop(calculator);
+        drop(market_data);
+
+        if !untracked.is_empty() {
+            let mut tracked = self.tracked.write().await;
+            for token in untracked {
+                tracked.remove(&token);
+            }
+        }
+    }
+
+    /// Latest market data and statistics of the sampled tokens, with their correlations
+    pub async fn market_risk_data(&self) -> MarketRiskData {
+        let market_data = self.market_data.read().await;
+        let calculator = self.risk_calculator.read().await;
+        let mut tokens: Vec<TokenMarketSnapshot> = market_data.iter()
+            .filter_map(|(&token, history)| {
+                let latest = history.back()?;
+                let volatility = calculator.volatility_models.get(&token);
+                let liquidity = calculator.liquidity_models.get(&token);
+                Some(TokenMarketSnapshot {
+                    chain_id: latest.chain_id,
+                    token,
+                    price_usd: latest.price_usd,
+                    volatility: volatility.map(|model| model.current_volatility),
+                    volatility_forecast: volatility.map(|model| model.volatility_forecast),
+                    volatility_interval: volatility.map(|model| model.confidence_interval),
+                    samples: history.len(),
+                    liquidity: liquidity.map(|model| model.current_liquidity),
+                    pool_value_usd: liquidity.map(|model| model.pool_value_usd),
+                    depth: liquidity
+                        .map(|model| {
+                            model.liquidity_depth.iter()
+                                .map(|&(price_impact, amount_usd)| DepthLevel { price_impact, amount_usd })
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    updated_at: latest.timestamp,
+                })
+            })
+            .collect();
+        tokens.sort_by_key(|snapshot| (snapshot.chain_id, snapshot.token));
+
+        let mut correlations: Vec<TokenCorrelation> = calculator.correlation_matrix.iter()
+            .filter(|((token_a, token_b), _)| token_a < token_b)
+            .map(|(&(token_a, token_b), &correlation)| TokenCorrelation { token_a, token_b, correlation })
+            .collect();
+        correlations.sort_by_key(|correlation| (correlation.token_a, correlation.token_b));
+
+        MarketRiskData { tokens, correlations }
+    }
+
+    /// Sample these tokens from now on, up to the configured number of tokens
+    async fn track(&self, tokens: &[(u64, Address)]) {
+        let Some(sources) = &self.sources else {
+            return;
+        };
+        let mut tracked = self.tracked.write().await;
+        for &(chain_id, token) in tokens {
+            if tracked.len() >= sources.config.max_tokens {
+                break;
+            }
+            tracked.entry(token).or_insert(chain_id);
         }
     }
 
     /// Initialize the risk engine with default models
     pub async fn initialize(&self) -> Result<()> {
         self.load_default_risk_models().await?;
-        self.initialize_stress_scenarios().await?;
-        self.start_market_data_collection().await?;
         
         tracing::info!("Risk engine initialized");
         Ok(())
@@ -184,6 +369,7 @@ impl RiskEngine {
     /// Assess risk for a specific transaction
     pub async fn assess_transaction_risk(&self, tx: &TransactionRequest) -> Result<RiskAssessment> {
         let mut risk_factors = Vec::new();
+        self.track(&transaction_tokens(tx)).await;
         
         // Analyze smart contract risks
         if let Some(contract_risk) = self.assess_smart_contract_risk(tx).await? {
@@ -264,17 +450,45 @@ impl RiskEngine {
         })
     }
 
-    /// Perform stress testing
-    pub async fn run_stress_tests(&self, positions: &[PortfolioPosition]) -> Result<Vec<StressTestResult>> {
-        let stress_tester = self.stress_tester.read().await;
-        let mut results = Vec::new();
-        
-        for scenario in &stress_tester.stress_scenarios {
-            let result = self.simulate_stress_scenario(scenario, positions).await?;
-            results.push(result);
-        }
-        
-        Ok(results)
+    /// Preset stress scenarios
+    pub async fn stress_presets(&self) -> Vec<StressScenario> {
+        self.stress_tester.read().await.stress_scenarios.clone()
+    }
+
+    /// Preset stress scenario by name
+    pub async fn stress_preset(&self, name: &str) -> Option<StressScenario> {
+        self.stress_tester.read().await.stress_scenarios.iter()
+            .find(|scenario| scenario.name.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    /// Revalue lending positions under each scenario, pricing the exit from collateral with the
+    /// sampled pair liquidity. The positions' assets are sampled from then on, so exit costs left
+    /// unassessed get filled in by later runs
+    pub async fn run_stress_tests(
+        &self,
+        chain_id: u64,
+        positions: &[PositionValuation],
+        scenarios: &[StressScenario],
+        assets: &AssetRegistry,
+    ) -> Vec<StressTestResult> {
+        let tokens: Vec<(u64, Address)> = positions.iter()
+            .map(|position| (chain_id, pricing_address(chain_id, position.asset)))
+            .collect();
+        self.track(&tokens).await;
+
+        let market_data = self.market_data.read().await;
+        let liquidity: HashMap<Address, PairLiquidity> = positions.iter()
+            .filter_map(|position| {
+                let history = market_data.get(&pricing_address(chain_id, position.asset))?;
+                Some((position.asset, history.back()?.liquidity?))
+            })
+            .collect();
+        drop(market_data);
+
+        scenarios.iter()
+            .map(|scenario| stress_test::simulate(scenario, chain_id, positions, assets, &liquidity))
+            .collect()
     }
 
     /// Assess smart contract risks
@@ -309,77 +523,73 @@ impl RiskEngine {
         Ok(None)
     }
 
-    /// Assess market risks
+    /// Assess market risks from the volatility of the tokens the transaction trades or moves
     async fn assess_market_risk(&self, tx: &TransactionRequest) -> Result<Option<RiskFactor>> {
-        if let Some(to) = &tx.to {
-            let to_address = match to {
-                NameOrAddress::Address(addr) => *addr,
-                NameOrAddress::Name(_) => return Ok(None), // Skip ENS names
-            };
-            
-            let market_data = self.market_data.read().await;
-            
-            if let Some(data_queue) = market_data.get(&to_address) {
-                if let Some(latest_data) = data_queue.back() {
-                    let volatility = latest_data.volatility;
-                    
-                    let severity = match volatility {
-                        v if v < 0.1 => 0.1,
-                        v if v < 0.2 => 0.3,
-                        v if v < 0.4 => 0.5,
-                        v if v < 0.6 => 0.7,
-                        _ => 0.9,
-                    };
-                    
-                    return Ok(Some(RiskFactor {
-                        factor_type: RiskFactorType::PriceVolatility,
-                        severity,
-                        weight: 0.6,
-                        description: format!("Current volatility: {:.2}%", volatility * 100.0),
-                        mitigation: Some("Consider position sizing and stop losses".to_string()),
-                    }));
-                }
-            }
-        }
-        
-        Ok(None)
+        let market_data = self.market_data.read().await;
+        let volatility = transaction_tokens(tx).iter()
+            .filter_map(|(_, token)| market_data.get(token)?.back()?.volatility)
+            .max_by(|a, b| a.total_cmp(b));
+        let Some(volatility) = volatility else {
+            return Ok(None);
+        };
+
+        let severity = match volatility {
+            v if v < 0.1 => 0.1,
+            v if v < 0.2 => 0.3,
+            v if v < 0.4 => 0.5,
+            v if v < 0.6 => 0.7,
+            _ => 0.9,
+        };
+
+        Ok(Some(RiskFactor {
+            factor_type: RiskFactorType::PriceVolatility,
+            severity,
+            weight: 0.6,
+            description: format!("Daily volatility: {:.2}%", volatility * 100.0),
+            mitigation: Some("Consider position sizing and stop losses".to_string()),
+        }))
     }
 
-    /// Assess liquidity risks
+    /// Assess liquidity risks: the share of its token's Uniswap V2 reserve a swap sells, or the
+    /// share of the wrapped gas token's reserve a native transfer moves
     async fn assess_liquidity_risk(&self, tx: &TransactionRequest) -> Result<Option<RiskFactor>> {
-        if let Some(to) = &tx.to {
-            let to_address = match to {
-                NameOrAddress::Address(addr) => *addr,
-                NameOrAddress::Name(_) => return Ok(None), // Skip ENS names
-            };
-            
-            let value = tx.value.unwrap_or(U256::zero());
-            
-            // Get current liquidity
-            let liquidity = self.get_contract_liquidity(to_address).await?;
-            
-            if liquidity > U256::zero() {
-                let impact_ratio = value.as_u128() as f64 / liquidity.as_u128() as f64;
-                
-                let severity = match impact_ratio {
-                    r if r < 0.01 => 0.1,
-                    r if r < 0.05 => 0.3,
-                    r if r < 0.1 => 0.5,
-                    r if r < 0.2 => 0.7,
-                    _ => 0.9,
-                };
-                
-                return Ok(Some(RiskFactor {
-                    factor_type: RiskFactorType::LiquidityRisk,
-                    severity,
-                    weight: 0.7,
-                    description: format!("Transaction impact: {:.2}% of available liquidity", impact_ratio * 100.0),
-                    mitigation: Some("Consider splitting large transactions".to_string()),
-                }));
-            }
+        let chain_id = tx.chain_id.map(|id| id.as_u64()).unwrap_or(1);
+        let (token, amount) = match decode_swap(tx) {
+            Some(swap) => (pricing_address(chain_id, swap.token_in), swap.amount_in),
+            None => (pricing_address(chain_id, Address::zero()), tx.value.unwrap_or_default()),
+        };
+        if amount.is_zero() {
+            return Ok(None);
         }
-        
-        Ok(None)
+
+        let market_data = self.market_data.read().await;
+        let Some(liquidity) = market_data.get(&token).and_then(|history| history.back()?.liquidity) else {
+            return Ok(None);
+        };
+        if liquidity.reserve.is_zero() {
+            return Ok(None);
+        }
+        let impact_ratio = market_data::u256_to_f64(amount) / market_data::u256_to_f64(liquidity.reserve);
+
+        let severity = match impact_ratio {
+            r if r < 0.01 => 0.1,
+            r if r < 0.05 => 0.3,
+            r if r < 0.1 => 0.5,
+            r if r < 0.2 => 0.7,
+            _ => 0.9,
+        };
+
+        Ok(Some(RiskFactor {
+            factor_type: RiskFactorType::LiquidityRisk,
+            severity,
+            weight: 0.7,
+            description: format!(
+                "Transaction impact: {:.2}% of available liquidity (${:.0} in the pair)",
+                impact_ratio * 100.0,
+                liquidity.pool_value_usd,
+            ),
+            mitigation: Some("Consider splitting large transactions".to_string()),
+        }))
     }
 
     /// Assess MEV risks
@@ -659,11 +869,6 @@ impl RiskEngine {
         Ok("audited".to_string())
     }
 
-    async fn get_contract_liquidity(&self, _address: Address) -> Result<U256> {
-        // Would query DEX liquidity
-        Ok(U256::from(1000000)) // Placeholder
-    }
-
     async fn contains_flash_loan_pattern(&self, _data: &ethers::types::Bytes) -> bool {
         // Would analyze call data for flash loan patterns
         false
@@ -674,31 +879,31 @@ impl RiskEngine {
         Ok(0.1)
     }
 
-    async fn simulate_stress_scenario(&self, _scenario: &StressScenario, _positions: &[PortfolioPosition]) -> Result<StressTestResult> {
-        // Would run Monte Carlo simulation
-        Ok(StressTestResult {
-            scenario_name: "test".to_string(),
-            portfolio_loss: 0.1,
-            max_drawdown: 0.15,
-            liquidation_probability: 0.05,
-            recovery_time: Duration::days(7),
-        })
-    }
-
     async fn load_default_risk_models(&self) -> Result<()> {
         // Load risk models from configuration
         Ok(())
     }
 
-    async fn initialize_stress_scenarios(&self) -> Result<()> {
-        // Initialize stress test scenarios
-        Ok(())
-    }
+}
 
-    async fn start_market_data_collection(&self) -> Result<()> {
-        // Start background market data collection
-        Ok(())
+/// Tokens a transaction trades or moves: those of a swap, the token of an ERC-20 transfer and the
+/// wrapped gas token for a native value
+fn transaction_tokens(tx: &TransactionRequest) -> Vec<(u64, Address)> {
+    let chain_id = tx.chain_id.map(|id| id.as_u64()).unwrap_or(1);
+    let mut tokens = Vec::new();
+    if let Some(swap) = decode_swap(tx) {
+        tokens.extend([swap.token_in, swap.token_out]);
+    } else if let (Some(to), Some(data)) = (tx.to.as_ref().and_then(|to| to.as_address()), &tx.data) {
+        if TransactionLimitEnforcer::decode_transfer_amount(data).is_some() {
+            tokens.push(*to);
+        }
+    }
+    if tx.value.is_some_and(|value| !value.is_zero()) {
+        tokens.push(Address::zero());
     }
+    let mut tokens: Vec<(u64, Address)> = tokens.into_iter().map(|token| (chain_id, pricing_address(chain_id, token))).collect();
+    tokens.dedup();
+    tokens
 }
 
 #[derive(Debug, Clone)]
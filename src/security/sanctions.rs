@@ -0,0 +1,257 @@
+// Counterparties screened against published sanctions lists, refreshed in the background
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::audit_trail::{AuditEntryType, AuditTrail};
+use crate::shutdown::ShutdownSignal;
+
+/// Lists loaded unless `sanctions_list_urls` is set, the Ethereum addresses of the OFAC SDN list
+const DEFAULT_LIST_URLS: &str =
+    "https://raw.githubusercontent.com/0xB10C/ofac-sanctioned-digital-currency-addresses/lists/sanctioned_addresses_ETH.txt";
+/// Interval between list reloads unless `sanctions_refresh_interval_secs` is set
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Name under which the addresses of `sanctioned_addresses` are reported
+const CONFIGURED_LIST: &str = "configured";
+
+/// State of one configured sanctions list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SanctionsListStatus {
+    pub url: String,
+    pub addresses: usize,
+    pub loaded_at: Option<DateTime<Utc>>,
+    /// Error of the last load; the addresses of the previous successful load stay in use
+    pub error: Option<String>,
+}
+
+/// Sanctioned counterparty and the lists naming it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SanctionsMatch {
+    pub address: Address,
+    pub lists: Vec<String>,
+}
+
+/// Outcome of screening the counterparties of one operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreeningResult {
+    pub screened: Vec<Address>,
+    pub matches: Vec<SanctionsMatch>,
+    pub screened_at: DateTime<Utc>,
+}
+
+impl ScreeningResult {
+    pub fn is_clear(&self) -> bool {
+        self.matches.is_empty()
+    }
+}
+
+/// An operation refused because a counterparty is sanctioned
+#[derive(Debug, Clone)]
+pub struct SanctionedCounterparty(pub Vec<SanctionsMatch>);
+
+impl fmt::Display for SanctionedCounterparty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let matches: Vec<String> = self.0.iter()
+            .map(|found| format!("{:?} ({})", found.address, found.lists.join(", ")))
+            .collect();
+        write!(f, "Counterparty on a sanctions list: {}", matches.join("; "))
+    }
+}
+
+impl std::error::Error for SanctionedCounterparty {}
+
+/// Addresses of one list as last loaded
+struct LoadedList {
+    status: SanctionsListStatus,
+    addresses: HashSet<Address>,
+}
+
+/// Screens addresses against the configured sanctions lists and addresses
+pub struct SanctionsScreener {
+    list_urls: Vec<String>,
+    refresh_interval: Duration,
+    http: reqwest::Client,
+    configured: HashSet<Address>,
+    lists: RwLock<Vec<LoadedList>>,
+}
+
+impl SanctionsScreener {
+    pub fn new(list_urls: Vec<String>, refresh_interval: Duration, configured: HashSet<Address>) -> Self {
+        info!("Initializing SanctionsScreener with {} lists and {} configured addresses", list_urls.len(), configured.len());
+        Self {
+            list_urls,
+            refresh_interval,
+            http: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            configured,
+            lists: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Screener loading the comma-separated `sanctions_list_urls` (URLs or file paths, empty for none)
+    /// every `sanctions_refresh_interval_secs`, plus the comma-separated `sanctioned_addresses`
+    pub fn from_config(config: &config::Config) -> Self {
+        let list_urls = config
+            .get_string("sanctions_list_urls")
+            .unwrap_or_else(|_| DEFAULT_LIST_URLS.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+        let refresh_interval = config
+            .get_int("sanctions_refresh_interval_secs")
+            .map(|secs| Duration::from_secs(secs.max(60) as u64))
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL);
+        let configured = config
+            .get_string("sanctioned_addresses")
+            .map(|addresses| parse_addresses(&addresses))
+            .unwrap_or_default();
+        Self::new(list_urls, refresh_interval, configured)
+    }
+
+    /// Load the lists now and again every refresh interval until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.refresh_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => self.refresh_lists().await,
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+            info!("Sanctions list refresher stopped");
+        })
+    }
+
+    /// Reload every configured list, keeping the previous addresses of a list that fails to load
+    pub async fn refresh_lists(&self) {
+        if self.list_urls.is_empty() {
+            return;
+        }
+        let results = join_all(self.list_urls.iter().map(|url| self.fetch_list(url))).await;
+
+        let mut lists = self.lists.write().await;
+        for (url, result) in self.list_urls.iter().zip(results) {
+            let index = match lists.iter().position(|list| &list.status.url == url) {
+                Some(index) => index,
+                None => {
+                    lists.push(LoadedList {
+                        status: SanctionsListStatus { url: url.clone(), addresses: 0, loaded_at: None, error: None },
+                        addresses: HashSet::new(),
+                    });
+                    lists.len() - 1
+                }
+            };
+            let list = &mut lists[index];
+            match result {
+                Ok(addresses) => {
+                    info!("Loaded {} sanctioned addresses from {}", addresses.len(), url);
+                    list.status = SanctionsListStatus {
+                        url: url.clone(),
+                        addresses: addresses.len(),
+                        loaded_at: Some(Utc::now()),
+                        error: None,
+                    };
+                    list.addresses = addresses;
+                }
+                Err(e) => {
+                    warn!("Failed to load sanctions list {}: {}", url, e);
+                    list.status.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    async fn fetch_list(&self, url: &str) -> Result<HashSet<Address>> {
+        let body = if url.starts_with("http://") || url.starts_with("https://") {
+            self.http.get(url).send().await?.error_for_status()?.text().await?
+        } else {
+            tokio::fs::read_to_string(url).await?
+        };
+        Ok(parse_addresses(&body))
+    }
+
+    pub async fn list_statuses(&self) -> Vec<SanctionsListStatus> {
+        self.lists.read().await.iter().map(|list| list.status.clone()).collect()
+    }
+
+    /// Lists naming `address`, empty when it is not sanctioned
+    pub async fn lists_naming(&self, address: Address) -> Vec<String> {
+        let mut names: Vec<String> = self.lists.read().await.iter()
+            .filter(|list| list.addresses.contains(&address))
+            .map(|list| list.status.url.clone())
+            .collect();
+        if self.configured.contains(&address) {
+            names.push(CONFIGURED_LIST.to_string());
+        }
+        names
+    }
+
+    /// Screen the distinct non-zero counterparties of an operation
+    pub async fn screen(&self, counterparties: &[Address]) -> ScreeningResult {
+        let screened: Vec<Address> = counterparties.iter()
+            .filter(|address| !address.is_zero())
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let mut matches = Vec::new();
+        for address in &screened {
+            let lists = self.lists_naming(*address).await;
+            if !lists.is_empty() {
+                matches.push(SanctionsMatch { address: *address, lists });
+            }
+        }
+        ScreeningResult { screened, matches, screened_at: Utc::now() }
+    }
+
+    /// Screen the counterparties and record the result, failing with `SanctionedCounterparty` on a match
+    pub async fn check(&self, counterparties: &[Address], audit_trail: &AuditTrail, context: &str) -> Result<ScreeningResult> {
+        let result = self.screen(counterparties).await;
+        record(audit_trail, &result, context).await?;
+        if !result.is_clear() {
+            warn!("Refusing {}: {}", context, SanctionedCounterparty(result.matches.clone()));
+            return Err(SanctionedCounterparty(result.matches).into());
+        }
+        Ok(result)
+    }
+}
+
+/// Audit-log a screening: clear screenings as routine entries, matches with the highest risk
+pub async fn record(audit_trail: &AuditTrail, result: &ScreeningResult, context: &str) -> Result<()> {
+    let screened: Vec<String> = result.screened.iter().map(|address| format!("{:?}", address)).collect();
+    let (description, risk_score, outcome) = if result.is_clear() {
+        (format!("{}: {} clear", context, screened.join(", ")), 0.0, "clear")
+    } else {
+        (format!("{}: {}", context, SanctionedCounterparty(result.matches.clone())), 1.0, "match")
+    };
+    audit_trail.log_security_event(
+        AuditEntryType::SanctionsScreening,
+        result.matches.first().map(|found| found.address).or_else(|| result.screened.first().copied()),
+        description,
+        risk_score,
+        vec!["sanctions_screening".to_string(), outcome.to_string()],
+    ).await
+}
+
+/// Every `0x`-prefixed 20-byte hex address in a text, JSON or CSV document
+fn parse_addresses(text: &str) -> HashSet<Address> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| word.len() == 42 && word.starts_with("0x"))
+        .filter_map(|word| word.parse().ok())
+        .collect()
+}
@@ -0,0 +1,644 @@
+// Spending policies per wallet and strategy: USD limits over rolling windows, a single-transaction
+// maximum and allowed protocols, with operator-approved overrides
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Bytes, NameOrAddress, TransactionRequest, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+use super::audit_trail::{AuditEntryType, AuditTrail};
+use super::oracle_security::{decode_swap, TradePriceGuard};
+use super::transaction_limits::TransactionLimitEnforcer;
+
+/// Lifetime of an approved override unless `spending_override_ttl_secs` is set
+const DEFAULT_OVERRIDE_TTL_SECS: i64 = 3600;
+/// Overrides kept for the API, the oldest decided ones are dropped first
+const MAX_OVERRIDES: usize = 500;
+/// How long an allowed transaction counts towards the limits while it waits to be broadcast
+const HOLD_TTL_SECS: i64 = 900;
+
+/// Limits applied to a wallet, or to each wallet's transactions for a strategy; unset rules do not apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpendingPolicy {
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub max_transaction_usd: Option<f64>,
+    /// Limit over the last 24 hours
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub daily_limit_usd: Option<f64>,
+    /// Limit over the last 7 days
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub weekly_limit_usd: Option<f64>,
+    /// Contracts the transactions may call, token contracts included; `None` allows any
+    #[serde(default)]
+    pub allowed_protocols: Option<BTreeSet<Address>>,
+}
+
+impl SpendingPolicy {
+    fn has_usd_limits(&self) -> bool {
+        self.max_transaction_usd.is_some() || self.daily_limit_usd.is_some() || self.weekly_limit_usd.is_some()
+    }
+}
+
+/// Spending policies of wallets and of strategies
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpendingPolicies {
+    #[serde(default, with = "crate::api::models::sorted_map")]
+    pub wallets: HashMap<Address, SpendingPolicy>,
+    /// Keyed by strategy id, each wallet's spending for the strategy is limited separately
+    #[serde(default, with = "crate::api::models::sorted_map")]
+    pub strategies: HashMap<String, SpendingPolicy>,
+}
+
+impl SpendingPolicies {
+    /// Reject limits that are not positive amounts
+    pub fn validate(&self) -> Result<()> {
+        let scopes = self.wallets.iter().map(|(wallet, policy)| (PolicyScope::Wallet(*wallet), policy))
+            .chain(self.strategies.iter().map(|(id, policy)| (PolicyScope::Strategy(id.clone()), policy)));
+        for (scope, policy) in scopes {
+            for (name, limit) in [
+                ("max_transaction_usd", policy.max_transaction_usd),
+                ("daily_limit_usd", policy.daily_limit_usd),
+                ("weekly_limit_usd", policy.weekly_limit_usd),
+            ] {
+                if limit.is_some_and(|limit| !limit.is_finite() || limit <= 0.0) {
+                    return Err(anyhow!("{} must be positive ({})", name, scope));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wallet or strategy a policy belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyScope {
+    Wallet(Address),
+    Strategy(String),
+}
+
+impl fmt::Display for PolicyScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyScope::Wallet(wallet) => write!(f, "wallet {:?}", wallet),
+            PolicyScope::Strategy(strategy_id) => write!(f, "strategy {}", strategy_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    MaxTransaction,
+    DailyLimit,
+    WeeklyLimit,
+    ProtocolAllowlist,
+    /// The transaction's value has no oracle price, so its USD limits cannot be checked
+    Unpriced,
+}
+
+impl fmt::Display for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PolicyRule::MaxTransaction => "max_transaction",
+            PolicyRule::DailyLimit => "daily_limit",
+            PolicyRule::WeeklyLimit => "weekly_limit",
+            PolicyRule::ProtocolAllowlist => "protocol_allowlist",
+            PolicyRule::Unpriced => "unpriced",
+        })
+    }
+}
+
+/// A rule of a policy the transaction breaks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub scope: PolicyScope,
+    pub rule: PolicyRule,
+    pub reason: String,
+}
+
+/// A transaction refused for the spending policy rules it breaks
+#[derive(Debug, Clone)]
+pub struct SpendingPolicyRefused(pub Vec<PolicyViolation>);
+
+impl fmt::Display for SpendingPolicyRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let violations: Vec<String> = self.0.iter()
+            .map(|violation| format!("{} ({})", violation.reason, violation.rule))
+            .collect();
+        write!(f, "Spending policy refused the transaction: {}", violations.join("; "))
+    }
+}
+
+impl std::error::Error for SpendingPolicyRefused {}
+
+/// Strategy a transaction is made for and the override to apply, both optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicyContext {
+    pub strategy_id: Option<String>,
+    pub override_id: Option<String>,
+}
+
+/// Outcome of checking a transaction against the policies of its wallet and strategy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyEvaluation {
+    pub wallet: Address,
+    pub strategy_id: Option<String>,
+    /// USD value the transaction moves, `None` when it could not be priced
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub value_usd: Option<f64>,
+    /// Spent in the last 24 hours and 7 days before this transaction, by the wallet or for the strategy
+    #[serde(with = "crate::api::models::usd")]
+    pub spent_daily_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub spent_weekly_usd: f64,
+    pub violations: Vec<PolicyViolation>,
+    /// Approved override letting the transaction through despite its violations
+    pub override_id: Option<String>,
+    pub allowed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideStatus {
+    Pending,
+    Approved,
+    Rejected,
+    /// Applied to a transaction, an override covers one
+    Used,
+}
+
+/// Request to let one transaction of a wallet through despite its policies
+#[derive(Debug, Clone, Deserialize)]
+pub struct OverrideRequest {
+    pub wallet: Address,
+    /// Only cover a transaction for this strategy
+    pub strategy_id: Option<String>,
+    /// Largest transaction value covered
+    pub max_value_usd: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyOverride {
+    pub id: String,
+    pub wallet: Address,
+    pub strategy_id: Option<String>,
+    #[serde(with = "crate::api::models::usd")]
+    pub max_value_usd: f64,
+    pub reason: String,
+    pub status: OverrideStatus,
+    pub requested_at: DateTime<Utc>,
+    /// Operator who approved or rejected it
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl PolicyOverride {
+    fn usable(&self, now: DateTime<Utc>) -> bool {
+        self.status == OverrideStatus::Approved && self.expires_at.is_some_and(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendingPolicyConfig {
+    /// How long an approved override can be applied
+    pub override_ttl: Duration,
+}
+
+impl Default for SpendingPolicyConfig {
+    fn default() -> Self {
+        Self { override_ttl: Duration::seconds(DEFAULT_OVERRIDE_TTL_SECS) }
+    }
+}
+
+impl SpendingPolicyConfig {
+    /// Overrides lasting `spending_override_ttl_secs`, at least a minute
+    pub fn from_config(config: &config::Config) -> Self {
+        let defaults = Self::default();
+        Self {
+            override_ttl: config
+                .get_int("spending_override_ttl_secs")
+                .map(|secs| Duration::seconds(secs.max(60)))
+                .unwrap_or(defaults.override_ttl),
+        }
+    }
+}
+
+/// What a transaction calls, to match a broadcast to the spending allowed for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PolicyCall {
+    to: Option<NameOrAddress>,
+    value: U256,
+    data: Bytes,
+}
+
+impl PolicyCall {
+    fn of(to: Option<&NameOrAddress>, value: Option<&U256>, data: Option<&Bytes>) -> Self {
+        Self { to: to.cloned(), value: value.copied().unwrap_or_default(), data: data.cloned().unwrap_or_default() }
+    }
+}
+
+/// Spending counted towards the limits
+struct SpendRecord {
+    wallet: Address,
+    strategy_id: Option<String>,
+    value_usd: f64,
+    at: DateTime<Utc>,
+    call: PolicyCall,
+    /// Chain and nonce it was sent with, a transaction replacing it takes its place
+    slot: Option<(u64, U256)>,
+    /// Until when an allowed transaction not yet broadcast holds its value, `None` once sent
+    held_until: Option<DateTime<Utc>>,
+    /// Override used up by the transaction, given back if it is never sent
+    override_id: Option<String>,
+}
+
+impl SpendRecord {
+    fn counts(&self, now: DateTime<Utc>) -> bool {
+        self.held_until.is_none_or(|until| until > now)
+    }
+}
+
+/// Checks transactions against the spending policies and keeps the spending and overrides they count
+pub struct SpendingPolicyEngine {
+    policies: RwLock<SpendingPolicies>,
+    /// Prices transaction values, `None` leaves every valued transaction unpriced
+    price_guard: Option<Arc<TradePriceGuard>>,
+    audit_trail: Arc<AuditTrail>,
+    override_ttl: Duration,
+    spends: RwLock<VecDeque<SpendRecord>>,
+    overrides: RwLock<VecDeque<PolicyOverride>>,
+    /// Held from evaluation to the hold on its value, so two transactions cannot both fit under the same headroom
+    enforcing: Mutex<()>,
+}
+
+impl SpendingPolicyEngine {
+    pub fn new(config: SpendingPolicyConfig, price_guard: Option<Arc<TradePriceGuard>>, audit_trail: Arc<AuditTrail>) -> Self {
+        Self {
+            policies: RwLock::new(SpendingPolicies::default()),
+            price_guard,
+            audit_trail,
+            override_ttl: config.override_ttl,
+            spends: RwLock::new(VecDeque::new()),
+            overrides: RwLock::new(VecDeque::new()),
+            enforcing: Mutex::new(()),
+        }
+    }
+
+    pub async fn get_policies(&self) -> SpendingPolicies {
+        self.policies.read().await.clone()
+    }
+
+    pub async fn set_policies(&self, policies: SpendingPolicies) -> Result<()> {
+        policies.validate()?;
+        info!("Spending policies set for {} wallets and {} strategies", policies.wallets.len(), policies.strategies.len());
+        *self.policies.write().await = policies;
+        Ok(())
+    }
+
+    /// Check a transaction of `wallet` against the policies of the wallet and strategy, `None` when
+    /// neither has one. Advisory only: nothing is counted and no override is used up, see `enforce`.
+    pub async fn evaluate(&self, wallet: Address, tx: &TransactionRequest, context: &PolicyContext) -> Result<Option<PolicyEvaluation>> {
+        let (wallet_policy, strategy_policy) = {
+            let policies = self.policies.read().await;
+            let strategy_policy = context.strategy_id.as_ref().and_then(|strategy_id| {
+                policies.strategies.get(strategy_id).map(|policy| (PolicyScope::Strategy(strategy_id.clone()), policy.clone()))
+            });
+            (policies.wallets.get(&wallet).map(|policy| (PolicyScope::Wallet(wallet), policy.clone())), strategy_policy)
+        };
+        if wallet_policy.is_none() && strategy_policy.is_none() {
+            return Ok(None);
+        }
+
+        let value_usd = self.value_usd(tx).await;
+        // A transaction reusing a sent nonce replaces that one, whose spending is not counted twice
+        let replacing = tx.nonce.map(|nonce| (chain_id(tx), nonce));
+        let now = Utc::now();
+        let mut violations = Vec::new();
+        let (mut spent_daily_usd, mut spent_weekly_usd) = (0.0, 0.0);
+        for (scope, policy) in wallet_policy.iter().chain(strategy_policy.iter()) {
+            let (daily, weekly) = self.spent(wallet, scope, replacing, now).await;
+            if matches!(scope, PolicyScope::Wallet(_)) || wallet_policy.is_none() {
+                (spent_daily_usd, spent_weekly_usd) = (daily, weekly);
+            }
+            violations.extend(check(scope, policy, tx, value_usd, daily, weekly));
+        }
+
+        let mut override_id = None;
+        if !violations.is_empty() {
+            if let Some(id) = &context.override_id {
+                let overrides = self.overrides.read().await;
+                match overrides.iter().find(|candidate| &candidate.id == id) {
+                    Some(candidate) if !candidate.usable(now) => {
+                        warn!("Override {} is {:?} and cannot be applied", id, candidate.status);
+                    }
+                    Some(candidate) if candidate.wallet != wallet
+                        || candidate.strategy_id.as_ref().is_some_and(|strategy_id| context.strategy_id.as_ref() != Some(strategy_id)) =>
+                    {
+                        warn!("Override {} does not cover transactions of {:?}", id, wallet);
+                    }
+                    Some(candidate) if value_usd.is_none_or(|value| value > candidate.max_value_usd) => {
+                        warn!("Override {} covers at most ${:.2}", id, candidate.max_value_usd);
+                    }
+                    Some(_) => override_id = Some(id.clone()),
+                    None => warn!("Override {} not found", id),
+                }
+            }
+        }
+
+        Ok(Some(PolicyEvaluation {
+            wallet,
+            strategy_id: context.strategy_id.clone(),
+            value_usd,
+            spent_daily_usd,
+            spent_weekly_usd,
+            allowed: violations.is_empty() || override_id.is_some(),
+            violations,
+            override_id,
+        }))
+    }
+
+    /// Check a transaction `wallet` is about to sign: an allowed one holds its value against the limits
+    /// and uses up its override until `confirm` counts it or `release` gives both back, a refused one
+    /// is audit-logged. The wallet is the signer, never the transaction's `from`, which the caller controls.
+    pub async fn enforce(&self, wallet: Address, tx: &TransactionRequest, context: &PolicyContext) -> Result<Option<PolicyEvaluation>> {
+        let _enforcing = self.enforcing.lock().await;
+        let evaluation = self.evaluate(wallet, tx, context).await?;
+        if let Some(evaluation) = &evaluation {
+            self.hold(evaluation, PolicyCall::of(tx.to.as_ref(), tx.value.as_ref(), tx.data.as_ref())).await?;
+        }
+        Ok(evaluation)
+    }
+
+    /// Count the spending held for a transaction of `wallet` that was sent, or signed for the caller to
+    /// send, in the slot of its chain and nonce. Spending of an earlier transaction in that slot, which
+    /// this one replaces, is no longer counted.
+    pub async fn confirm(&self, wallet: Address, tx: &TypedTransaction) {
+        let call = PolicyCall::of(tx.to(), tx.value(), tx.data());
+        let slot = tx.nonce().map(|nonce| (tx.chain_id().map_or(1, |chain_id| chain_id.as_u64()), *nonce));
+        let now = Utc::now();
+        let mut spends = self.spends.write().await;
+        let held = spends.iter().position(|spend| spend.wallet == wallet && spend.held_until.is_some() && spend.call == call);
+        let Some(mut sent) = held.and_then(|index| spends.remove(index)) else {
+            return;
+        };
+        if slot.is_some() {
+            spends.retain(|spend| !(spend.wallet == wallet && spend.held_until.is_none() && spend.slot == slot));
+        }
+        sent.slot = slot;
+        sent.held_until = None;
+        sent.at = now;
+        spends.push_back(sent);
+    }
+
+    /// Drop the spending held for a transaction of `wallet` that was never sent, giving back its override
+    pub async fn release(&self, wallet: Address, tx: &TypedTransaction) {
+        let call = PolicyCall::of(tx.to(), tx.value(), tx.data());
+        let released = {
+            let mut spends = self.spends.write().await;
+            match spends.iter().position(|spend| spend.wallet == wallet && spend.held_until.is_some() && spend.call == call) {
+                Some(index) => spends.remove(index),
+                None => return,
+            }
+        };
+        if let Some(id) = released.and_then(|spend| spend.override_id) {
+            if let Some(unused) = self.overrides.write().await.iter_mut().find(|candidate| candidate.id == id) {
+                unused.status = OverrideStatus::Approved;
+                unused.used_at = None;
+            }
+        }
+    }
+
+    /// Hold an allowed transaction's value against the limits and use up its override; refused
+    /// transactions are audit-logged as violations instead
+    async fn hold(&self, evaluation: &PolicyEvaluation, call: PolicyCall) -> Result<()> {
+        if !evaluation.allowed {
+            let reasons: Vec<&str> = evaluation.violations.iter().map(|violation| violation.reason.as_str()).collect();
+            return self.audit_trail.log_security_event(
+                AuditEntryType::SecurityViolation,
+                Some(evaluation.wallet),
+                format!("Spending policy refused a transaction: {}", reasons.join("; ")),
+                0.7,
+                vec!["spending_policy".to_string(), "refused".to_string()],
+            ).await;
+        }
+
+        let now = Utc::now();
+        let value_usd = evaluation.value_usd.unwrap_or_default();
+        if value_usd > 0.0 || evaluation.override_id.is_some() {
+            let mut spends = self.spends.write().await;
+            spends.retain(|spend| spend.counts(now) && now - spend.at <= Duration::days(7));
+            spends.push_back(SpendRecord {
+                wallet: evaluation.wallet,
+                strategy_id: evaluation.strategy_id.clone(),
+                value_usd,
+                at: now,
+                call,
+                slot: None,
+                held_until: Some(now + Duration::seconds(HOLD_TTL_SECS)),
+                override_id: evaluation.override_id.clone(),
+            });
+        }
+
+        if let Some(id) = &evaluation.override_id {
+            if let Some(used) = self.overrides.write().await.iter_mut().find(|candidate| &candidate.id == id) {
+                used.status = OverrideStatus::Used;
+                used.used_at = Some(now);
+            }
+            let reasons: Vec<&str> = evaluation.violations.iter().map(|violation| violation.reason.as_str()).collect();
+            self.audit_trail.log_security_event(
+                AuditEntryType::SecurityViolation,
+                Some(evaluation.wallet),
+                format!("Override {} let a transaction through: {}", id, reasons.join("; ")),
+                0.5,
+                vec!["spending_policy".to_string(), "override_used".to_string()],
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Ask for an override, pending until an operator decides on it
+    pub async fn request_override(&self, request: OverrideRequest) -> Result<PolicyOverride> {
+        if !request.max_value_usd.is_finite() || request.max_value_usd <= 0.0 {
+            return Err(anyhow!("max_value_usd must be positive"));
+        }
+        if request.reason.trim().is_empty() {
+            return Err(anyhow!("An override needs a reason"));
+        }
+        let requested = PolicyOverride {
+            id: uuid::Uuid::new_v4().to_string(),
+            wallet: request.wallet,
+            strategy_id: request.strategy_id,
+            max_value_usd: request.max_value_usd,
+            reason: request.reason,
+            status: OverrideStatus::Pending,
+            requested_at: Utc::now(),
+            decided_by: None,
+            decided_at: None,
+            expires_at: None,
+            used_at: None,
+        };
+        self.audit_trail.log_security_event(
+            AuditEntryType::UserAction,
+            Some(requested.wallet),
+            format!("Spending override {} requested up to ${:.2}: {}", requested.id, requested.max_value_usd, requested.reason),
+            0.3,
+            vec!["spending_policy".to_string(), "override_requested".to_string()],
+        ).await?;
+
+        let mut overrides = self.overrides.write().await;
+        overrides.push_back(requested.clone());
+        while overrides.len() > MAX_OVERRIDES {
+            match overrides.iter().position(|candidate| candidate.status != OverrideStatus::Pending) {
+                Some(index) => overrides.remove(index),
+                None => overrides.pop_front(),
+            };
+        }
+        Ok(requested)
+    }
+
+    /// Approve or reject a pending override; an approved one can be applied until it expires.
+    /// `None` when there is no such override
+    pub async fn decide_override(&self, id: &str, approve: bool, actor: &str) -> Result<Option<PolicyOverride>> {
+        let mut overrides = self.overrides.write().await;
+        let Some(decided) = overrides.iter_mut().find(|candidate| candidate.id == id) else {
+            return Ok(None);
+        };
+        if decided.status != OverrideStatus::Pending {
+            return Err(anyhow!("Override {} is already {:?}", id, decided.status));
+        }
+        let now = Utc::now();
+        decided.status = if approve { OverrideStatus::Approved } else { OverrideStatus::Rejected };
+        decided.decided_by = Some(actor.to_string());
+        decided.decided_at = Some(now);
+        decided.expires_at = approve.then(|| now + self.override_ttl);
+        Ok(Some(decided.clone()))
+    }
+
+    /// Overrides, newest first, optionally of one wallet
+    pub async fn overrides(&self, wallet: Option<Address>) -> Vec<PolicyOverride> {
+        self.overrides.read().await.iter()
+            .rev()
+            .filter(|candidate| wallet.is_none_or(|wallet| candidate.wallet == wallet))
+            .cloned()
+            .collect()
+    }
+
+    /// Spending of the last 24 hours and 7 days counted against a scope, held spending included and
+    /// that of the transaction sent in the `replacing` slot left out
+    async fn spent(&self, wallet: Address, scope: &PolicyScope, replacing: Option<(u64, U256)>, now: DateTime<Utc>) -> (f64, f64) {
+        let spends = self.spends.read().await;
+        let counted = spends.iter().filter(|spend| {
+            spend.wallet == wallet && spend.counts(now) && (replacing.is_none() || spend.slot != replacing)
+        }).filter(|spend| match scope {
+            PolicyScope::Wallet(_) => true,
+            PolicyScope::Strategy(strategy_id) => spend.strategy_id.as_ref() == Some(strategy_id),
+        });
+        let (mut daily, mut weekly) = (0.0, 0.0);
+        for spend in counted {
+            let age = now - spend.at;
+            if age <= Duration::days(7) {
+                weekly += spend.value_usd;
+            }
+            if age <= Duration::days(1) {
+                daily += spend.value_usd;
+            }
+        }
+        (daily, weekly)
+    }
+
+    /// USD value of the native value sent plus the tokens an ERC-20 transfer or a swap moves,
+    /// `None` if any of it has no price
+    async fn value_usd(&self, tx: &TransactionRequest) -> Option<f64> {
+        let chain_id = chain_id(tx);
+        let mut moved = Vec::new();
+        let value = tx.value.unwrap_or_default();
+        if !value.is_zero() {
+            moved.push((Address::zero(), value));
+        }
+        let to = tx.to.as_ref().and_then(|to| to.as_address().copied());
+        let data = tx.data.as_ref().map(|data| data.to_vec()).unwrap_or_default();
+        if let (Some(token), Some(amount)) = (to, TransactionLimitEnforcer::decode_transfer_amount(&data)) {
+            moved.push((token, amount));
+        } else if let Some(swap) = decode_swap(tx).filter(|_| value.is_zero()) {
+            // Swaps from the native token send it as value
+            moved.push((swap.token_in, swap.amount_in));
+        }
+        if moved.is_empty() {
+            return Some(0.0);
+        }
+
+        let guard = self.price_guard.as_ref()?;
+        let mut total = 0.0;
+        for (token, amount) in moved {
+            match guard.value_usd(chain_id, token, amount).await {
+                Ok(Some(value_usd)) => total += value_usd,
+                Ok(None) => return None,
+                Err(e) => {
+                    warn!("Failed to price {} of {:?} on chain {}: {}", amount, token, chain_id, e);
+                    return None;
+                }
+            }
+        }
+        Some(total)
+    }
+}
+
+/// Chain of a transaction, mainnet unless it names one
+fn chain_id(tx: &TransactionRequest) -> u64 {
+    tx.chain_id.map(|chain_id| chain_id.as_u64()).unwrap_or(1)
+}
+
+/// Rules of one policy a transaction breaks, given what was spent before it
+fn check(
+    scope: &PolicyScope,
+    policy: &SpendingPolicy,
+    tx: &TransactionRequest,
+    value_usd: Option<f64>,
+    spent_daily_usd: f64,
+    spent_weekly_usd: f64,
+) -> Vec<PolicyViolation> {
+    let mut violations = Vec::new();
+    let mut violate = |rule, reason: String| violations.push(PolicyViolation { scope: scope.clone(), rule, reason });
+
+    if let Some(allowed) = &policy.allowed_protocols {
+        match tx.to.as_ref().and_then(|to| to.as_address()) {
+            Some(to) if allowed.contains(to) => {}
+            Some(to) => violate(PolicyRule::ProtocolAllowlist, format!("{:?} is not an allowed protocol of {}", to, scope)),
+            None => violate(PolicyRule::ProtocolAllowlist, format!("{} only allows calls to its protocols", scope)),
+        }
+    }
+
+    match value_usd {
+        Some(value) => {
+            if let Some(limit) = policy.max_transaction_usd.filter(|limit| value > *limit) {
+                violate(PolicyRule::MaxTransaction, format!("${:.2} exceeds the ${:.2} transaction limit of {}", value, limit, scope));
+            }
+            if let Some(limit) = policy.daily_limit_usd.filter(|limit| spent_daily_usd + value > *limit) {
+                violate(PolicyRule::DailyLimit, format!(
+                    "${:.2} on top of ${:.2} spent in the last 24 hours exceeds the ${:.2} daily limit of {}",
+                    value, spent_daily_usd, limit, scope,
+                ));
+            }
+            if let Some(limit) = policy.weekly_limit_usd.filter(|limit| spent_weekly_usd + value > *limit) {
+                violate(PolicyRule::WeeklyLimit, format!(
+                    "${:.2} on top of ${:.2} spent in the last 7 days exceeds the ${:.2} weekly limit of {}",
+                    value, spent_weekly_usd, limit, scope,
+                ));
+            }
+        }
+        None if policy.has_usd_limits() => violate(
+            PolicyRule::Unpriced,
+            format!("The transaction's value has no oracle price to check against the USD limits of {}", scope),
+        ),
+        None => {}
+    }
+    violations
+}
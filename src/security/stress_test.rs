@@ -0,0 +1,274 @@
+// Portfolio stress tests: lending positions revalued under price shocks and drained DEX liquidity
+use anyhow::{Result, bail};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::market_data::PairLiquidity;
+use crate::chains::assets::AssetRegistry;
+use crate::defi::PositionValuation;
+
+/// Share of a liquidatable account's debt a liquidator may repay at once
+const CLOSE_FACTOR: f64 = 0.5;
+/// Canonical assets `market_shock` leaves at their price
+const STABLECOINS: [&str; 3] = ["usdc", "usdt", "dai"];
+
+/// Collateral paid to the liquidator on top of the repaid debt, typical of each protocol
+fn liquidation_penalty(protocol: &str) -> f64 {
+    match protocol {
+        "Aave" => 0.05,
+        "Compound" => 0.08,
+        _ => 0.10,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StressScenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Price changes in percent by asset, named by canonical asset id (`eth`), symbol or address
+    #[serde(default)]
+    pub shocks: BTreeMap<String, f64>,
+    /// Price change in percent of every other asset except stablecoins
+    #[serde(default)]
+    pub market_shock: f64,
+    /// Percent of DEX liquidity withdrawn
+    #[serde(default)]
+    pub liquidity_drain: f64,
+}
+
+impl StressScenario {
+    fn preset(name: &str, description: &str, shocks: &[(&str, f64)], market_shock: f64, liquidity_drain: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            shocks: shocks.iter().map(|(asset, shock)| (asset.to_string(), *shock)).collect(),
+            market_shock,
+            liquidity_drain,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("Scenario name is empty");
+        }
+        let shocks = self.shocks.iter()
+            .map(|(asset, shock)| (asset.as_str(), *shock))
+            .chain(std::iter::once(("market_shock", self.market_shock)));
+        for (asset, shock) in shocks {
+            if !shock.is_finite() || shock < -100.0 {
+                bail!("Shock of {} in scenario {} must be a percentage of at least -100", asset, self.name);
+            }
+        }
+        if !(0.0..100.0).contains(&self.liquidity_drain) {
+            bail!("liquidity_drain of scenario {} must be at least 0 and below 100", self.name);
+        }
+        Ok(())
+    }
+
+    /// Price change of an asset as a multiplier
+    fn price_factor(&self, chain_id: u64, asset: Address, assets: &AssetRegistry) -> f64 {
+        let resolved = assets.resolve(chain_id, asset);
+        let names = |key: &str| {
+            key.parse::<Address>().is_ok_and(|address| address == asset)
+                || resolved.is_some_and(|(canonical, representation)| {
+                    canonical.id.eq_ignore_ascii_case(key) || representation.symbol.eq_ignore_ascii_case(key)
+                })
+        };
+        let shock = match self.shocks.iter().find(|(key, _)| names(key.as_str())) {
+            Some((_, shock)) => *shock,
+            None if resolved.is_some_and(|(canonical, _)| STABLECOINS.contains(&canonical.id.as_str())) => 0.0,
+            None => self.market_shock,
+        };
+        1.0 + shock / 100.0
+    }
+}
+
+/// Scenarios offered by name
+pub fn presets() -> Vec<StressScenario> {
+    vec![
+        StressScenario::preset(
+            "eth_crash",
+            "ETH falls 40% and other volatile assets 25%, with 40% of DEX liquidity withdrawn",
+            &[("eth", -40.0)],
+            -25.0,
+            40.0,
+        ),
+        StressScenario::preset(
+            "market_crash",
+            "Every volatile asset halves and 60% of DEX liquidity is withdrawn, as on 12 March 2020",
+            &[],
+            -50.0,
+            60.0,
+        ),
+        StressScenario::preset(
+            "stablecoin_depeg",
+            "USDC trades at 0.88 and DAI at 0.90, as in March 2023",
+            &[("usdc", -12.0), ("dai", -10.0)],
+            0.0,
+            30.0,
+        ),
+        StressScenario::preset(
+            "liquidity_crunch",
+            "Volatile assets fall 10% while 80% of DEX liquidity is withdrawn",
+            &[],
+            -10.0,
+            80.0,
+        ),
+    ]
+}
+
+/// One lending protocol before and after the shock
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolStress {
+    pub protocol: String,
+    /// `None` without debt
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub health_factor_before: Option<f64>,
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub health_factor_after: Option<f64>,
+    #[serde(with = "crate::api::models::usd")]
+    pub collateral_usd_after: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub debt_usd_after: f64,
+    /// Health factor below 1 after the shock
+    pub liquidated: bool,
+    /// Collateral lost to the liquidator's bonus when half the debt is repaid
+    #[serde(with = "crate::api::models::usd")]
+    pub liquidation_penalty_usd: f64,
+}
+
+/// Position in a protocol that would be liquidated, valued after the shock
+#[derive(Debug, Clone, Serialize)]
+pub struct LiquidatedPosition {
+    pub protocol: String,
+    pub asset: Address,
+    #[serde(with = "crate::api::models::usd")]
+    pub supplied_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub borrowed_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StressTestResult {
+    pub scenario: StressScenario,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_worth_before_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub net_worth_after_usd: f64,
+    /// Net worth lost to the price changes alone
+    #[serde(with = "crate::api::models::usd")]
+    pub price_loss_usd: f64,
+    #[serde(with = "crate::api::models::usd")]
+    pub liquidation_penalty_usd: f64,
+    /// Price loss plus liquidation penalties
+    #[serde(with = "crate::api::models::usd")]
+    pub projected_loss_usd: f64,
+    /// Projected loss against the net worth before the shock, `None` when that is not positive
+    #[serde(with = "crate::api::models::option_ratio")]
+    pub projected_loss_pct: Option<f64>,
+    pub protocols: Vec<ProtocolStress>,
+    pub liquidated_positions: Vec<LiquidatedPosition>,
+    /// Slippage of selling every supplied asset into its drained Uniswap V2 pair
+    #[serde(with = "crate::api::models::usd")]
+    pub exit_cost_usd: f64,
+    /// Supplied assets without sampled pair liquidity, left out of `exit_cost_usd`
+    pub exit_cost_unassessed: Vec<Address>,
+}
+
+/// Revalue the priced positions under a scenario; `liquidity` holds the pair liquidity of assets by
+/// position asset
+pub fn simulate(
+    scenario: &StressScenario,
+    chain_id: u64,
+    positions: &[PositionValuation],
+    assets: &AssetRegistry,
+    liquidity: &HashMap<Address, PairLiquidity>,
+) -> StressTestResult {
+    let priced: Vec<(&PositionValuation, f64)> = positions.iter()
+        .filter(|position| position.price_usd.is_some())
+        .map(|position| (position, scenario.price_factor(chain_id, position.asset, assets)))
+        .collect();
+    let net_worth_before_usd: f64 = priced.iter().map(|(p, _)| p.supplied_usd - p.borrowed_usd).sum();
+    let net_worth_after_usd: f64 = priced.iter().map(|(p, factor)| (p.supplied_usd - p.borrowed_usd) * factor).sum();
+
+    let mut names: Vec<&str> = priced.iter().map(|(p, _)| p.protocol.as_str()).collect();
+    names.sort();
+    names.dedup();
+    let mut protocols = Vec::new();
+    let mut liquidated_positions = Vec::new();
+    for name in names {
+        let held: Vec<&(&PositionValuation, f64)> = priced.iter().filter(|(p, _)| p.protocol == name).collect();
+        let health_factor = |weighted: f64, debt: f64| (debt > 0.0).then(|| weighted / debt);
+        let before = health_factor(
+            held.iter().map(|(p, _)| p.supplied_usd * p.liquidation_threshold).sum(),
+            held.iter().map(|(p, _)| p.borrowed_usd).sum(),
+        );
+        let collateral_usd_after: f64 = held.iter().map(|(p, factor)| p.supplied_usd * factor).sum();
+        let debt_usd_after: f64 = held.iter().map(|(p, factor)| p.borrowed_usd * factor).sum();
+        let after = health_factor(
+            held.iter().map(|(p, factor)| p.supplied_usd * factor * p.liquidation_threshold).sum(),
+            debt_usd_after,
+        );
+
+        let liquidated = after.is_some_and(|hf| hf < 1.0);
+        if liquidated {
+            liquidated_positions.extend(
+                held.iter()
+                    .filter(|(p, _)| p.supplied_usd > 0.0 || p.borrowed_usd > 0.0)
+                    .map(|(p, factor)| LiquidatedPosition {
+                        protocol: name.to_string(),
+                        asset: p.asset,
+                        supplied_usd: p.supplied_usd * factor,
+                        borrowed_usd: p.borrowed_usd * factor,
+                    }),
+            );
+        }
+        protocols.push(ProtocolStress {
+            protocol: name.to_string(),
+            health_factor_before: before,
+            health_factor_after: after,
+            collateral_usd_after,
+            debt_usd_after,
+            liquidated,
+            liquidation_penalty_usd: if liquidated { debt_usd_after * CLOSE_FACTOR * liquidation_penalty(name) } else { 0.0 },
+        });
+    }
+
+    // Selling `v` into a constant product side worth `x` loses v / (x + v) of it to slippage; the
+    // pair's side moves with the price and shrinks with the drain
+    let mut exit_cost_usd = 0.0;
+    let mut exit_cost_unassessed = Vec::new();
+    for (position, factor) in priced.iter().filter(|(p, _)| p.supplied_usd > 0.0) {
+        let value = position.supplied_usd * factor;
+        match liquidity.get(&position.asset) {
+            Some(pair) => {
+                let side = pair.pool_value_usd / 2.0 * factor * (1.0 - scenario.liquidity_drain / 100.0);
+                if side + value > 0.0 {
+                    exit_cost_usd += value * value / (side + value);
+                }
+            }
+            None => exit_cost_unassessed.push(position.asset),
+        }
+    }
+    exit_cost_unassessed.sort();
+    exit_cost_unassessed.dedup();
+
+    let price_loss_usd = net_worth_before_usd - net_worth_after_usd;
+    let liquidation_penalty_usd: f64 = protocols.iter().map(|protocol| protocol.liquidation_penalty_usd).sum();
+    let projected_loss_usd = price_loss_usd + liquidation_penalty_usd;
+    StressTestResult {
+        scenario: scenario.clone(),
+        net_worth_before_usd,
+        net_worth_after_usd,
+        price_loss_usd,
+        liquidation_penalty_usd,
+        projected_loss_usd,
+        projected_loss_pct: (net_worth_before_usd > 0.0).then(|| projected_loss_usd / net_worth_before_usd * 100.0),
+        protocols,
+        liquidated_positions,
+        exit_cost_usd,
+        exit_cost_unassessed,
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::analytics::price_feeds::{pricing_address, PriceFeedService};
+
+/// ERC-20 `transfer(address,uint256)` selector
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+/// ERC-20 `transferFrom(address,address,uint256)` selector
+const TRANSFER_FROM_SELECTOR: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+
+/// USD value limit for transfers of a specific token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenLimit {
+    pub decimals: u8,
+    #[serde(with = "crate::api::models::usd")]
+    pub max_value_usd: f64,
+    /// Raw amount limit used when no price is available; transfers are rejected without one
+    pub fallback_max_amount: Option<U256>,
+}
+
+/// Value and gas limits applied to one chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainLimits {
+    #[serde(with = "crate::api::models::usd")]
+    pub max_value_usd: f64,
+    pub max_gas_limit: u64,
+    /// Native value limit in wei used when the native token price is unavailable
+    pub fallback_max_native_value: U256,
+    #[serde(default, with = "crate::api::models::sorted_map")]
+    pub tokens: HashMap<Address, TokenLimit>,
+}
+
+impl Default for ChainLimits {
+    fn default() -> Self {
+        Self {
+            max_value_usd: 2_500_000.0,
+            max_gas_limit: 10_000_000,
+            fallback_max_native_value: U256::from(1000) * U256::exp10(18), // 1000 ETH
+            tokens: HashMap::new(),
+        }
+    }
+}
+
+/// Transaction limits with per-chain overrides
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransactionLimits {
+    pub default: ChainLimits,
+    #[serde(default, with = "crate::api::models::sorted_map")]
+    pub chains: HashMap<u64, ChainLimits>,
+}
+
+impl TransactionLimits {
+    pub fn for_chain(&self, chain_id: u64) -> &ChainLimits {
+        self.chains.get(&chain_id).unwrap_or(&self.default)
+    }
+
+    /// Reject limits that would block or disable validation entirely
+    pub fn validate(&self) -> Result<()> {
+        for (chain, limits) in std::iter::once((None, &self.default))
+            .chain(self.chains.iter().map(|(id, limits)| (Some(*id), limits)))
+        {
+            if !is_positive_usd(limits.max_value_usd) {
+                return Err(anyhow!("max_value_usd must be positive (chain {:?})", chain));
+            }
+            if limits.max_gas_limit < 21_000 {
+                return Err(anyhow!("max_gas_limit must be at least 21000 (chain {:?})", chain));
+            }
+            if limits.tokens.values().any(|token| !is_positive_usd(token.max_value_usd)) {
+                return Err(anyhow!("token max_value_usd must be positive (chain {:?})", chain));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_positive_usd(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// Enforces configured value and gas limits, pricing values in USD where possible
+pub struct TransactionLimitEnforcer {
+    limits: Arc<RwLock<TransactionLimits>>,
+    price_feeds: Option<Arc<PriceFeedService>>,
+}
+
+impl TransactionLimitEnforcer {
+    pub fn new(price_feeds: Option<Arc<PriceFeedService>>) -> Self {
+        Self {
+            limits: Arc::new(RwLock::new(TransactionLimits::default())),
+            price_feeds,
+        }
+    }
+
+    pub async fn get_limits(&self) -> TransactionLimits {
+        self.limits.read().await.clone()
+    }
+
+    pub async fn set_limits(&self, limits: TransactionLimits) -> Result<()> {
+        limits.validate()?;
+        *self.limits.write().await = limits;
+        Ok(())
+    }
+
+    /// Check a transaction's gas, native value and ERC-20 transfer amount
+    pub async fn check(
+        &self,
+        chain_id: u64,
+        to: Option<Address>,
+        value: U256,
+        gas: Option<U256>,
+        data: &[u8],
+    ) -> Result<()> {
+        let limits = self.limits.read().await.for_chain(chain_id).clone();
+
+        if let Some(gas) = gas {
+            if gas > U256::from(limits.max_gas_limit) {
+                return Err(anyhow!("Gas limit {} exceeds maximum {} on chain {}", gas, limits.max_gas_limit, chain_id));
+            }
+        }
+
+        if !value.is_zero() {
+            self.check_value(chain_id, Address::zero(), value, 18, limits.max_value_usd, Some(limits.fallback_max_native_value)).await?;
+        }
+
+        if let (Some(token), Some(amount)) = (to, Self::decode_transfer_amount(data)) {
+            if let Some(token_limit) = limits.tokens.get(&token) {
+                self.check_value(
+                    chain_id,
+                    token,
+                    amount,
+                    token_limit.decimals,
+                    token_limit.max_value_usd,
+                    token_limit.fallback_max_amount,
+                ).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_value(
+        &self,
+        chain_id: u64,
+        token: Address,
+        amount: U256,
+        decimals: u8,
+        max_value_usd: f64,
+        fallback_max_amount: Option<U256>,
+    ) -> Result<()> {
+        let price = match &self.price_feeds {
+            Some(feeds) => feeds.get_price(chain_id, pricing_address(chain_id, token)).await.ok(),
+            None => None,
+        };
+
+        match price {
+            Some(price) => {
+                let units: f64 = ethers::utils::format_units(amount, decimals as u32)?.parse()?;
+                let value_usd = units * price.price_usd;
+                debug!("Transaction moves {:.2} USD of {:?} on chain {}", value_usd, token, chain_id);
+
+                if value_usd > max_value_usd {
+                    warn!("Transaction value ${:.2} exceeds ${:.2} limit on chain {}", value_usd, max_value_usd, chain_id);
+                    return Err(anyhow!("Transaction value ${:.2} exceeds ${:.2} limit", value_usd, max_value_usd));
+                }
+            }
+            None => match fallback_max_amount {
+                Some(max_amount) if amount <= max_amount => {}
+                Some(max_amount) => {
+                    return Err(anyhow!("Transaction amount {} exceeds fallback limit {}", amount, max_amount));
+                }
+                None => {
+                    return Err(anyhow!("No price available to check transfer of {:?} against its limit", token));
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Extract the amount of an ERC-20 transfer or transferFrom call
+    pub fn decode_transfer_amount(data: &[u8]) -> Option<U256> {
+        if data.len() < 4 {
+            return None;
+        }
+
+        let offset = match [data[0], data[1], data[2], data[3]] {
+            TRANSFER_SELECTOR => 4 + 32,
+            TRANSFER_FROM_SELECTOR => 4 + 64,
+            _ => return None,
+        };
+
+        data.get(offset..offset + 32).map(U256::from_big_endian)
+    }
+}
@@ -0,0 +1,213 @@
+// Layered configuration: defaults, an optional config file, then BLOCKCHAIN_DEMO_ environment variables
+use anyhow::{Result, anyhow};
+use config::{Config, File, Source};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::api::ApiState;
+use crate::monitor::MonitorConfig;
+use crate::shutdown::ShutdownSignal;
+
+const ENV_PREFIX: &str = "BLOCKCHAIN_DEMO";
+/// Environment variable naming the config file, which then has to exist
+const CONFIG_FILE_VARIABLE: &str = "BLOCKCHAIN_DEMO_CONFIG_FILE";
+/// Files looked for in the working directory when no config file is named
+const DEFAULT_CONFIG_FILES: [&str; 4] = ["config.toml", "config.yaml", "config.yml", "config.json"];
+/// How often the config file is checked for changes when `config_reload_interval_secs` is not set
+const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Settings applied while running, anything else in the file takes effect on restart
+const RELOADABLE_PREFIXES: [&str; 2] = ["monitor_", "rate_limit_"];
+/// Monitor settings the position monitor only reads on start
+const RESTART_ONLY_MONITOR_KEYS: [&str; 2] = ["monitor_poll_interval_secs", "monitor_webhook_urls"];
+
+/// Integer settings, recognized by their suffix, which must not be negative
+const INTEGER_SUFFIXES: [&str; 12] = ["_secs", "_ms", "_entries", "_requests", "_size", "_per_minute", "_per_second", "_port", "_hour", "_chain_id", "_block_number", "_blocks"];
+/// Boolean settings besides the `*_enabled` ones
+const FLAGS: [&str; 6] = [
+    "demo_mode",
+    "live_chains",
+    "fork_mode",
+    "mempool_monitoring",
+    "rate_limit_trust_forwarded_for",
+    "oracle_refuse_unpriced_trades",
+];
+
+/// Config file named by `BLOCKCHAIN_DEMO_CONFIG_FILE`, or the first `config.{toml,yaml,yml,json}` found
+pub fn config_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var(CONFIG_FILE_VARIABLE).ok().filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    DEFAULT_CONFIG_FILES.iter().map(PathBuf::from).find(|path| path.is_file())
+}
+
+/// Build and validate the configuration, environment variables override the file
+pub fn load(file: Option<&Path>) -> Result<Config> {
+    let mut builder = Config::builder()
+        .set_default("demo_mode", true)?
+        .set_default("server.host", "0.0.0.0")?
+        .set_default("server.port", 3000)?
+        .set_default("ethereum.rpc_url", "https://mainnet.infura.io/v3/demo")?
+        .set_default("polygon.rpc_url", "https://polygon-rpc.com")?
+        .set_default("arbitrum.rpc_url", "https://arb1.arbitrum.io/rpc")?;
+    if let Some(file) = file {
+        builder = builder.add_source(File::from(file));
+    }
+    let config = builder
+        .add_source(config::Environment::with_prefix(ENV_PREFIX))
+        .build()
+        .map_err(|e| anyhow!("Invalid configuration: {}", e))?;
+
+    validate(&config)?;
+    Ok(config)
+}
+
+/// Check URLs parse, numbers are in range and flags are booleans, reporting every problem at once
+pub fn validate(config: &Config) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for (key, value) in config.collect()? {
+        if key.ends_with("_url") {
+            // RPC URLs may list several endpoints
+            let urls = value.into_string().unwrap_or_default();
+            for url in urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+                if let Err(e) = reqwest::Url::parse(url) {
+                    errors.push(format!("{} is not a valid URL: {}", key, e));
+                }
+            }
+        } else if INTEGER_SUFFIXES.iter().any(|suffix| key.ends_with(suffix)) {
+            match config.get_int(&key) {
+                Ok(number) if number < 0 => errors.push(format!("{} must not be negative", key)),
+                Ok(_) => {}
+                Err(_) => errors.push(format!("{} must be an integer", key)),
+            }
+        } else if (key.ends_with("_enabled") || FLAGS.contains(&key.as_str())) && config.get_bool(&key).is_err() {
+            errors.push(format!("{} must be true or false", key));
+        }
+    }
+
+    let mut check_float = |key: &str, valid: fn(f64) -> bool, range: &str| match config.get_float(key) {
+        Ok(value) if !valid(value) => errors.push(format!("{} must be {}", key, range)),
+        Ok(_) | Err(config::ConfigError::NotFound(_)) => {}
+        Err(_) => errors.push(format!("{} must be a number", key)),
+    };
+    check_float("monitor_health_factor_threshold", |value| value > 0.0, "above 0");
+    check_float("monitor_borrow_ratio_threshold", |value| value > 0.0 && value <= 1.0, "above 0 and at most 1");
+    check_float("monitor_gas_cost_threshold_percentage", |value| (0.0..=100.0).contains(&value), "between 0 and 100");
+    check_float("arbitrage_trade_size_usd", |value| value > 0.0, "above 0");
+    check_float("auto_range_range_factor", |value| value > 0.0, "above 0");
+    check_float("auto_range_edge_threshold_percentage", |value| (0.0..50.0).contains(&value), "at least 0 and below 50");
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.sort();
+    Err(anyhow!("Invalid configuration:\n  {}", errors.join("\n  ")))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Settings that differ between two configurations, sorted
+fn changed_keys(current: &Config, reloaded: &Config) -> Result<Vec<String>> {
+    let current = current.collect()?;
+    let reloaded = reloaded.collect()?;
+    let mut changed: Vec<String> = current
+        .keys()
+        .chain(reloaded.keys())
+        .filter(|key| current.get(*key) != reloaded.get(*key))
+        .cloned()
+        .collect();
+    changed.sort();
+    changed.dedup();
+    Ok(changed)
+}
+
+fn is_reloadable(key: &str) -> bool {
+    RELOADABLE_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
+        && !RESTART_ONLY_MONITOR_KEYS.contains(&key)
+        && key != "rate_limit_enabled"
+        && key != "rate_limit_trust_forwarded_for"
+}
+
+/// Watches the config file and applies monitor thresholds and rate limits when it changes
+pub struct ConfigWatcher {
+    path: PathBuf,
+    interval: Duration,
+    current: Config,
+}
+
+impl ConfigWatcher {
+    /// Watcher of the loaded config file every `config_reload_interval_secs`, `None` without a file or
+    /// when the interval is 0
+    pub fn new(config: &Config, file: Option<PathBuf>) -> Option<Self> {
+        let interval = match config.get_int("config_reload_interval_secs") {
+            Ok(0) => return None,
+            Ok(seconds) => Duration::from_secs(seconds.max(1) as u64),
+            Err(_) => DEFAULT_RELOAD_INTERVAL,
+        };
+        Some(Self { path: file?, interval, current: config.clone() })
+    }
+
+    /// Poll the file in the background until shutdown
+    pub fn start(mut self, state: Arc<ApiState>, mut shutdown: ShutdownSignal) -> JoinHandle<()> {
+        info!("Watching {} for changes every {:?}", self.path.display(), self.interval);
+        tokio::spawn(async move {
+            let mut last_modified = modified(&self.path);
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown.cancelled() => break,
+                }
+                let seen = modified(&self.path);
+                if seen == last_modified {
+                    continue;
+                }
+                last_modified = seen;
+                self.reload(&state).await;
+            }
+        })
+    }
+
+    /// Apply the file's reloadable settings, keeping the current ones when it is invalid
+    async fn reload(&mut self, state: &ApiState) {
+        let reloaded = match load(Some(&self.path)) {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                warn!("Ignoring changes to {}, keeping the current settings: {:#}", self.path.display(), e);
+                return;
+            }
+        };
+        let changed = match changed_keys(&self.current, &reloaded) {
+            Ok(changed) => changed,
+            Err(e) => {
+                warn!("Failed to compare {} with the current settings: {}", self.path.display(), e);
+                return;
+            }
+        };
+        if changed.is_empty() {
+            return;
+        }
+
+        let (reloadable, restart_only): (Vec<String>, Vec<String>) = changed.into_iter().partition(|key| is_reloadable(key));
+        if reloadable.iter().any(|key| key.starts_with("monitor_")) {
+            state.monitor.reload(&MonitorConfig::from_config(&reloaded)).await;
+        }
+        if reloadable.iter().any(|key| key.starts_with("rate_limit_")) {
+            state.rate_limiter.reload(&reloaded).await;
+        }
+        if !reloadable.is_empty() {
+            info!("Reloaded {} from {}", reloadable.join(", "), self.path.display());
+        }
+        if !restart_only.is_empty() {
+            warn!("{} changed in {}, restart to apply", restart_only.join(", "), self.path.display());
+        }
+        self.current = reloaded;
+    }
+}
@@ -0,0 +1,168 @@
+// Graceful shutdown: stop on SIGTERM or SIGINT and let background tasks finish the work in hand
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Time given to in-flight requests and background tasks when `shutdown_drain_timeout_secs` is not set
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Handed to background loops, which stop between iterations once shutdown started
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    /// Wait for shutdown, returns at once when it already started
+    pub async fn cancelled(&mut self) {
+        // A dropped sender means the process is going away as well
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Short-lived tasks spawned on demand, such as notification deliveries and job runs, for shutdown
+/// to wait for
+#[derive(Clone, Default)]
+pub struct TaskSet(Arc<Mutex<JoinSet<()>>>);
+
+impl TaskSet {
+    /// Run `task` in the background, reaping the tasks already finished
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut tasks = self.lock();
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(task);
+    }
+
+    /// Wait until no task is left, including the ones spawned meanwhile; dropping the future aborts
+    /// the tasks it was waiting for
+    pub async fn join_all(&self) {
+        loop {
+            let mut tasks = std::mem::take(&mut *self.lock());
+            if tasks.is_empty() {
+                return;
+            }
+            while let Some(result) = tasks.join_next().await {
+                match result {
+                    Err(e) if e.is_panic() => warn!("Background task panicked: {}", e),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn abort_all(&self) {
+        self.lock().abort_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JoinSet<()>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Background tasks to cancel and wait for when the process stops
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+    drain_timeout: Duration,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+    sets: Vec<(&'static str, TaskSet)>,
+}
+
+impl Shutdown {
+    pub fn new(drain_timeout: Duration) -> Self {
+        Self {
+            sender: watch::Sender::new(false),
+            drain_timeout,
+            tasks: Vec::new(),
+            sets: Vec::new(),
+        }
+    }
+
+    /// Drain timeout from `shutdown_drain_timeout_secs`
+    pub fn from_config(config: &config::Config) -> Self {
+        let drain_timeout = config
+            .get_int("shutdown_drain_timeout_secs")
+            .ok()
+            .and_then(|seconds| u64::try_from(seconds).ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_DRAIN_TIMEOUT);
+        Self::new(drain_timeout)
+    }
+
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal(self.sender.subscribe())
+    }
+
+    /// Wait for `task` on shutdown
+    pub fn track(&mut self, name: &'static str, task: JoinHandle<()>) {
+        self.tasks.push((name, task));
+    }
+
+    /// Wait for the tasks of `set` on shutdown, after the long-running ones so the work they hand off
+    /// is waited for too
+    pub fn track_set(&mut self, name: &'static str, set: TaskSet) {
+        self.sets.push((name, set));
+    }
+
+    /// Cancel every signal and wait for the tracked tasks until the drain timeout, aborting the ones
+    /// still running then
+    pub async fn drain(self) {
+        info!("Draining {} tasks for up to {}s", self.tasks.len() + self.sets.len(), self.drain_timeout.as_secs());
+        self.sender.send_replace(true);
+
+        let deadline = Instant::now() + self.drain_timeout;
+        for (name, mut task) in self.tasks {
+            match tokio::time::timeout_at(deadline, &mut task).await {
+                Ok(Ok(())) => debug!("{} stopped", name),
+                Ok(Err(e)) => warn!("{} failed while stopping: {}", name, e),
+                Err(_) => {
+                    warn!("{} did not stop within the drain timeout, aborting it", name);
+                    task.abort();
+                }
+            }
+        }
+        for (name, set) in self.sets {
+            match tokio::time::timeout_at(deadline, set.join_all()).await {
+                Ok(()) => debug!("{} finished", name),
+                Err(_) => {
+                    warn!("{} did not finish within the drain timeout, aborting them", name);
+                    set.abort_all();
+                }
+            }
+        }
+    }
+}
+
+/// Resolve on SIGINT (Ctrl-C) or, on Unix, SIGTERM
+pub async fn wait_for_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT, shutting down"),
+        _ = terminate => info!("Received SIGTERM, shutting down"),
+    }
+}
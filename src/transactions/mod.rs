@@ -0,0 +1,462 @@
+// Lifecycle tracking of transactions built by the managers and broadcast by users
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, BlockNumber, TransactionRequest, H256, U256},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::analytics::gas_costs::{GasCost, GasCostEstimator};
+use crate::chains::reorg::{ChainReorg, ReorgHandler};
+use crate::chains::tx_broadcaster::{BroadcastStatus, TrackedTransaction};
+use crate::chains::ChainManager;
+
+pub mod settlement;
+
+use settlement::SwapQuote;
+
+/// Store used when `transactions_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/transactions.json";
+/// Records kept before the oldest are dropped
+const MAX_RECORDS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionStatus {
+    /// Built by the API but not yet seen on chain
+    Built,
+    Pending,
+    Confirmed,
+    /// Mined but reverted
+    Failed,
+    Replaced,
+    Dropped,
+}
+
+impl From<BroadcastStatus> for TransactionStatus {
+    fn from(status: BroadcastStatus) -> Self {
+        match status {
+            BroadcastStatus::Pending => TransactionStatus::Pending,
+            BroadcastStatus::Confirmed => TransactionStatus::Confirmed,
+            BroadcastStatus::Failed => TransactionStatus::Failed,
+            BroadcastStatus::Replaced => TransactionStatus::Replaced,
+            BroadcastStatus::Dropped => TransactionStatus::Dropped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub id: String,
+    pub chain_id: u64,
+    pub user: Option<Address>,
+    /// Operation that produced the transaction, e.g. "dex:swap" or "broadcast"
+    pub source: String,
+    /// `None` for transactions first seen when broadcast
+    pub request: Option<TransactionRequest>,
+    pub hash: Option<H256>,
+    pub nonce: Option<U256>,
+    pub status: TransactionStatus,
+    pub block_number: Option<u64>,
+    pub confirmations: u64,
+    pub gas_used: Option<U256>,
+    pub effective_gas_price: Option<U256>,
+    pub replaced_by: Option<H256>,
+    /// Bundle the transaction was built in, `None` for a transaction built on its own
+    #[serde(default)]
+    pub execution_id: Option<String>,
+    /// Swaps the transaction was quoted for, measuring realized slippage once it settled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quoted_swaps: Vec<SwapQuote>,
+    /// Gas a built transaction is expected to use, `None` when it could not be estimated
+    #[serde(default)]
+    pub estimated_gas: Option<U256>,
+    /// Expected gas cost at the fees and native token price when it was built
+    #[serde(default, with = "crate::api::models::option_usd")]
+    pub estimated_cost_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TransactionRecord {
+    fn new(chain_id: u64, user: Option<Address>, source: &str, request: Option<TransactionRequest>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            chain_id,
+            user,
+            source: source.to_string(),
+            request,
+            hash: None,
+            nonce: None,
+            status: TransactionStatus::Built,
+            block_number: None,
+            confirmations: 0,
+            gas_used: None,
+            effective_gas_price: None,
+            replaced_by: None,
+            execution_id: None,
+            quoted_swaps: Vec::new(),
+            estimated_gas: None,
+            estimated_cost_usd: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn with_gas_cost(self, gas_cost: Option<GasCost>) -> Self {
+        Self {
+            estimated_gas: gas_cost.map(|cost| cost.gas_units),
+            estimated_cost_usd: gas_cost.map(|cost| cost.estimated_cost_usd),
+            ..self
+        }
+    }
+
+    /// Whether a broadcast transaction carries out this built request
+    fn matches(&self, chain_id: u64, tx: &TypedTransaction, from: Address) -> bool {
+        let Some(request) = &self.request else {
+            return false;
+        };
+        self.status == TransactionStatus::Built
+            && self.chain_id == chain_id
+            && request.from.is_none_or(|sender| sender == from)
+            && request.to.as_ref() == tx.to()
+            && request.data.as_ref().filter(|data| !data.is_empty()) == tx.data().filter(|data| !data.is_empty())
+    }
+
+    fn apply_broadcast(&mut self, tracked: &TrackedTransaction) {
+        self.hash = Some(tracked.hash);
+        self.nonce = Some(tracked.nonce);
+        self.user = self.user.or(Some(tracked.from));
+        self.status = tracked.status.into();
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Persisted history of every transaction the API built or broadcast
+pub struct TransactionTracker {
+    chain_manager: Arc<ChainManager>,
+    /// Prices the gas of built transactions, `None` leaves them unpriced
+    gas_costs: Option<Arc<GasCostEstimator>>,
+    /// JSON file holding the records, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    records: RwLock<Vec<TransactionRecord>>,
+}
+
+impl TransactionTracker {
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        gas_costs: Option<Arc<GasCostEstimator>>,
+        store_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let records: Vec<TransactionRecord> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if let Some(path) = store_path.as_ref().filter(|_| !records.is_empty()) {
+            info!("Loaded {} tracked transactions from {}", records.len(), path.display());
+        }
+
+        Ok(Self {
+            chain_manager,
+            gas_costs,
+            store_path,
+            records: RwLock::new(records),
+        })
+    }
+
+    /// Tracker persisting to `transactions_store_path`, an empty path keeps history in memory
+    pub async fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        gas_costs: Arc<GasCostEstimator>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("transactions_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(chain_manager, Some(gas_costs), store_path).await
+    }
+
+    /// Record a transaction built for the user to sign
+    pub async fn record_built(
+        &self,
+        chain_id: u64,
+        user: Option<Address>,
+        source: &str,
+        request: &TransactionRequest,
+    ) -> TransactionRecord {
+        self.record_built_with_gas(chain_id, user, source, request, None).await
+    }
+
+    /// Record a built transaction whose gas was estimated when it was quoted, pricing it without a node estimate
+    /// that would revert while an approval it needs is unsent
+    pub async fn record_built_with_gas(
+        &self,
+        chain_id: u64,
+        user: Option<Address>,
+        source: &str,
+        request: &TransactionRequest,
+        gas_estimate: Option<U256>,
+    ) -> TransactionRecord {
+        let record = TransactionRecord::new(chain_id, user, source, Some(request.clone()))
+            .with_gas_cost(self.gas_cost(chain_id, request, gas_estimate).await);
+        let mut records = self.records.write().await;
+        records.push(record.clone());
+        self.persist(&mut records).await;
+        record
+    }
+
+    /// Record the transactions of a bundle, settled together under one execution id
+    pub async fn record_built_all(
+        &self,
+        chain_id: u64,
+        user: Option<Address>,
+        source: &str,
+        requests: &[TransactionRequest],
+    ) -> Vec<TransactionRecord> {
+        let execution_id = uuid::Uuid::new_v4().to_string();
+        let gas_costs = futures::future::join_all(
+            requests.iter().map(|request| self.gas_cost(chain_id, request, None)),
+        ).await;
+        let built: Vec<TransactionRecord> = requests.iter()
+            .zip(gas_costs)
+            .map(|(request, gas_cost)| TransactionRecord {
+                execution_id: Some(execution_id.clone()),
+                ..TransactionRecord::new(chain_id, user, source, Some(request.clone())).with_gas_cost(gas_cost)
+            })
+            .collect();
+
+        let mut records = self.records.write().await;
+        records.extend(built.iter().cloned());
+        self.persist(&mut records).await;
+        built
+    }
+
+    /// Attach the swaps a built transaction was quoted for
+    pub async fn quote_swaps(&self, id: &str, quotes: Vec<SwapQuote>) -> Result<()> {
+        let mut records = self.records.write().await;
+        let record = records.iter_mut()
+            .find(|record| record.id == id)
+            .ok_or_else(|| anyhow!("Unknown transaction record {}", id))?;
+        record.quoted_swaps = quotes;
+        self.persist(&mut records).await;
+        Ok(())
+    }
+
+    /// Sent transactions built with quoted swaps, whose outputs can be measured against the quotes
+    pub async fn quoted(&self) -> Vec<TransactionRecord> {
+        self.records.read().await.iter()
+            .filter(|record| !record.quoted_swaps.is_empty() && record.hash.is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Transactions of an execution, in the order they were built; a record id stands for an
+    /// execution of its own transaction and the ones replacing it
+    pub async fn execution(&self, id: &str) -> Vec<TransactionRecord> {
+        self.records.read().await.iter()
+            .filter(|record| record.id == id || record.execution_id.as_deref() == Some(id))
+            .cloned()
+            .collect()
+    }
+
+    /// Link a broadcast transaction to the built request it carries out, or start a new record
+    pub async fn record_broadcast(&self, tx: &TypedTransaction, tracked: &TrackedTransaction) -> TransactionRecord {
+        let mut records = self.records.write().await;
+        let record = match records.iter_mut().rev().find(|record| record.matches(tracked.chain_id, tx, tracked.from)) {
+            Some(record) => {
+                record.apply_broadcast(tracked);
+                record.clone()
+            }
+            None => {
+                let mut record = TransactionRecord::new(tracked.chain_id, Some(tracked.from), "broadcast", Some(tx.clone().into()));
+                record.apply_broadcast(tracked);
+                records.push(record.clone());
+                record
+            }
+        };
+        self.persist(&mut records).await;
+        record
+    }
+
+    /// Mark `original` replaced and track the replacement under the same source
+    pub async fn record_replacement(&self, original: H256, replacement: &TrackedTransaction) -> Result<TransactionRecord> {
+        let mut records = self.records.write().await;
+        let previous = records.iter_mut()
+            .find(|record| record.hash == Some(original))
+            .ok_or_else(|| anyhow!("Unknown transaction {:?}", original))?;
+        previous.status = TransactionStatus::Replaced;
+        previous.replaced_by = Some(replacement.hash);
+        previous.updated_at = Utc::now();
+
+        let mut record = TransactionRecord::new(previous.chain_id, previous.user, &previous.source, previous.request.clone());
+        record.execution_id = previous.execution_id.clone().or_else(|| Some(previous.id.clone()));
+        record.quoted_swaps = previous.quoted_swaps.clone();
+        record.apply_broadcast(replacement);
+        records.push(record.clone());
+        self.persist(&mut records).await;
+        Ok(record)
+    }
+
+    /// Attach the hash of a built transaction the user broadcast themselves
+    pub async fn attach_hash(&self, id: &str, hash: H256) -> Result<TransactionRecord> {
+        let mut records = self.records.write().await;
+        if records.iter().any(|record| record.hash == Some(hash) && record.id != id) {
+            return Err(anyhow!("Transaction {:?} is already tracked", hash));
+        }
+        let record = records.iter_mut()
+            .find(|record| record.id == id)
+            .ok_or_else(|| anyhow!("Unknown transaction record {}", id))?;
+        if record.status != TransactionStatus::Built {
+            return Err(anyhow!("Transaction record {} was already broadcast", id));
+        }
+
+        record.hash = Some(hash);
+        record.status = TransactionStatus::Pending;
+        record.updated_at = Utc::now();
+        let record = record.clone();
+        self.persist(&mut records).await;
+        Ok(record)
+    }
+
+    pub async fn get(&self, id: &str) -> Option<TransactionRecord> {
+        self.records.read().await.iter().find(|record| record.id == id).cloned()
+    }
+
+    pub async fn get_by_hash(&self, hash: H256) -> Option<TransactionRecord> {
+        self.records.read().await.iter().find(|record| record.hash == Some(hash)).cloned()
+    }
+
+    /// Transactions of a user created within `range` (start inclusive), newest first
+    pub async fn history(
+        &self,
+        user: Address,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+        limit: usize,
+    ) -> Vec<TransactionRecord> {
+        self.records.read().await.iter()
+            .rev()
+            .filter(|record| record.user == Some(user))
+            .filter(|record| range.is_none_or(|(from, to)| record.created_at >= from && record.created_at < to))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Update status, confirmation depth and gas used from the chain
+    pub async fn refresh(&self, hash: H256) -> Result<TransactionRecord> {
+        let mut record = self.get_by_hash(hash).await.ok_or_else(|| anyhow!("Unknown transaction {:?}", hash))?;
+        if !matches!(record.status, TransactionStatus::Pending | TransactionStatus::Confirmed | TransactionStatus::Failed) {
+            return Ok(record);
+        }
+
+        let chain = self.chain_manager.get_provider(record.chain_id).await?;
+        match chain.provider.get_transaction_receipt(hash).await? {
+            Some(receipt) => {
+                let latest = chain.provider.get_block_number().await?.as_u64();
+                record.block_number = receipt.block_number.map(|block| block.as_u64());
+                record.confirmations = record.block_number
+                    .map(|block| latest.saturating_sub(block) + 1)
+                    .unwrap_or(0);
+                record.gas_used = receipt.gas_used;
+                record.effective_gas_price = receipt.effective_gas_price;
+                record.status = if receipt.status.is_some_and(|status| status.as_u64() == 1) {
+                    TransactionStatus::Confirmed
+                } else {
+                    TransactionStatus::Failed
+                };
+            }
+            None => {
+                // A reorg can drop a mined transaction back into the pool
+                record.block_number = None;
+                record.confirmations = 0;
+                record.status = TransactionStatus::Pending;
+                if let (Some(nonce), Some(user)) = (record.nonce, record.user) {
+                    let mined = chain.provider
+                        .get_transaction_count(user, Some(BlockNumber::Latest.into()))
+                        .await?;
+                    if mined > nonce {
+                        record.status = TransactionStatus::Dropped;
+                    }
+                }
+            }
+        }
+        record.updated_at = Utc::now();
+
+        let mut records = self.records.write().await;
+        if let Some(stored) = records.iter_mut().find(|stored| stored.id == record.id) {
+            *stored = record.clone();
+        }
+        self.persist(&mut records).await;
+        Ok(record)
+    }
+
+    /// Trim to the retention limit and write the store, keeping the in-memory state on failure
+    async fn gas_cost(&self, chain_id: u64, request: &TransactionRequest, gas_estimate: Option<U256>) -> Option<GasCost> {
+        self.gas_costs.as_ref()?.transaction_cost(chain_id, request, gas_estimate).await
+    }
+
+    async fn persist(&self, records: &mut Vec<TransactionRecord>) {
+        if records.len() > MAX_RECORDS {
+            let excess = records.len() - MAX_RECORDS;
+            records.drain(..excess);
+        }
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        if let Err(e) = write_store(path, records.as_slice()).await {
+            warn!("Failed to persist transactions to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl ReorgHandler for TransactionTracker {
+    fn name(&self) -> &'static str {
+        "transactions"
+    }
+
+    /// Re-read the receipts of transactions mined in orphaned blocks, which may be pending again,
+    /// mined elsewhere or dropped
+    async fn handle_reorg(&self, reorg: &ChainReorg) -> Result<()> {
+        let orphaned: Vec<H256> = self.records.read().await.iter()
+            .filter(|record| record.chain_id == reorg.chain_id)
+            .filter(|record| record.block_number.is_some_and(|block| block > reorg.fork_block))
+            .filter_map(|record| record.hash)
+            .collect();
+        for hash in orphaned {
+            let record = self.refresh(hash).await?;
+            info!("Transaction {:?} is {:?} after the reorg of chain {}", hash, record.status, reorg.chain_id);
+        }
+        Ok(())
+    }
+}
+
+/// Read a JSON store, `None` when it does not exist yet
+pub(crate) async fn read_store<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    if !tokio::fs::try_exists(path).await? {
+        return Ok(None);
+    }
+    let contents = tokio::fs::read(path).await?;
+    let value = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow!("Invalid store {}: {}", path.display(), e))?;
+    Ok(Some(value))
+}
+
+/// Write through a temporary file so a crash never leaves a truncated store
+pub(crate) async fn write_store<T: Serialize + ?Sized>(path: &Path, records: &T) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temporary = path.with_extension("json.tmp");
+    tokio::fs::write(&temporary, serde_json::to_vec(records)?).await?;
+    tokio::fs::rename(&temporary, path).await?;
+    Ok(())
+}
@@ -0,0 +1,584 @@
+// Settlement reports of executed bundles, read from the receipts once every transaction settled
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, Log, TransactionRequest, H256, I256, U256},
+    utils::{id, keccak256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use super::{read_store, write_store, TransactionRecord, TransactionStatus, TransactionTracker};
+use crate::analytics::price_feeds::pricing_address;
+use crate::chains::{ChainManager, ChainProvider};
+use crate::dex::aggregator::DexType;
+
+/// Store used when `settlements_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/settlements.json";
+/// Uniswap V2 and SushiSwap pairs charge 0.3% of the input
+const V2_FEE_PER_MILLE: u64 = 3;
+/// Uniswap V3 fee tiers are in hundredths of a basis point
+const V3_FEE_DENOMINATOR: u64 = 1_000_000;
+
+/// Swap a transaction was quoted for when it was built
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub expected_output: U256,
+    /// Venue that quoted the swap, `None` for quotes recorded before venues were kept
+    #[serde(default)]
+    pub dex: Option<DexType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeKind {
+    Gas,
+    /// Share of a swap fee paid to liquidity providers
+    Dex,
+    /// Share of a swap fee taken by the DEX protocol
+    Protocol,
+    /// Premium paid on a flash loan
+    FlashLoan,
+}
+
+/// Fees of one kind paid in one token across the execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementFee {
+    pub kind: FeeKind,
+    /// `None` for the native asset
+    pub token: Option<Address>,
+    pub amount: U256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolKind {
+    /// Uniswap V2 and its forks, such as SushiSwap
+    UniswapV2,
+    UniswapV3,
+}
+
+/// Swap through a single pool, from its `Swap` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolSwap {
+    pub pool: Address,
+    pub kind: PoolKind,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub dex_fee: U256,
+    pub protocol_fee: U256,
+}
+
+/// A swap of the bundle and the pools it routed through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettledLeg {
+    pub transaction: H256,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// Output quoted when the transaction was built, `None` for swaps that were not quoted
+    pub expected_output: Option<U256>,
+    /// Shortfall of the output against the quote in basis points, negative when it beat the quote
+    pub realized_slippage_bps: Option<f64>,
+    /// Venue of the quote
+    #[serde(default)]
+    pub dex: Option<DexType>,
+    pub hops: Vec<PoolSwap>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettledTransaction {
+    pub hash: H256,
+    pub status: TransactionStatus,
+    pub block_number: Option<u64>,
+    pub gas_used: U256,
+    /// Gas used at the effective gas price, in the native asset
+    pub gas_fee: U256,
+    pub explorer_url: String,
+}
+
+/// Net change of a wallet's balance of one token across the execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenDelta {
+    pub wallet: Address,
+    /// `None` for the native asset
+    pub token: Option<Address>,
+    pub delta: I256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementReport {
+    pub execution_id: String,
+    pub chain_id: u64,
+    /// Operation that built the bundle, e.g. "defi:closeout"
+    pub source: String,
+    pub user: Option<Address>,
+    /// False when a transaction of the bundle reverted
+    pub succeeded: bool,
+    pub transactions: Vec<SettledTransaction>,
+    /// Balance changes of the wallets that sent or were served by the bundle
+    pub deltas: Vec<TokenDelta>,
+    pub fees: Vec<SettlementFee>,
+    pub legs: Vec<SettledLeg>,
+    pub settled_at: DateTime<Utc>,
+}
+
+/// Tokens and fee settings of a pool swapped through
+#[derive(Debug, Clone, Copy)]
+struct PoolInfo {
+    token0: Address,
+    token1: Address,
+    /// V3 fee tier, in hundredths of a basis point
+    fee: u64,
+    /// V3 protocol fee denominators of the token0 and token1 sides, zero when switched off
+    fee_protocol: (u8, u8),
+}
+
+/// Event signatures read from the receipts
+struct Topics {
+    transfer: H256,
+    deposit: H256,
+    withdrawal: H256,
+    v2_swap: H256,
+    v3_swap: H256,
+    v3_flash: H256,
+    aave_v2_flash_loan: H256,
+    aave_v3_flash_loan: H256,
+    balancer_flash_loan: H256,
+}
+
+impl Topics {
+    fn new() -> Self {
+        let topic = |signature: &str| H256::from(keccak256(signature));
+        Self {
+            transfer: topic("Transfer(address,address,uint256)"),
+            deposit: topic("Deposit(address,uint256)"),
+            withdrawal: topic("Withdrawal(address,uint256)"),
+            v2_swap: topic("Swap(address,uint256,uint256,uint256,uint256,address)"),
+            v3_swap: topic("Swap(address,address,int256,int256,uint160,uint128,int24)"),
+            v3_flash: topic("Flash(address,address,uint256,uint256,uint256,uint256)"),
+            aave_v2_flash_loan: topic("FlashLoan(address,address,address,uint256,uint256,uint16)"),
+            aave_v3_flash_loan: topic("FlashLoan(address,address,address,uint256,uint8,uint256,uint16)"),
+            balancer_flash_loan: topic("FlashLoan(address,address,uint256,uint256)"),
+        }
+    }
+}
+
+/// Report under construction while the receipts are read
+struct SettlementDraft {
+    wallets: BTreeSet<Address>,
+    deltas: BTreeMap<(Address, Option<Address>), I256>,
+    fees: BTreeMap<(FeeKind, Option<Address>), U256>,
+}
+
+impl SettlementDraft {
+    fn credit(&mut self, account: Address, token: Option<Address>, amount: U256) {
+        *self.deltas.entry((account, token)).or_insert_with(I256::zero) += I256::from_raw(amount);
+    }
+
+    fn debit(&mut self, account: Address, token: Option<Address>, amount: U256) {
+        *self.deltas.entry((account, token)).or_insert_with(I256::zero) -= I256::from_raw(amount);
+    }
+
+    fn fee(&mut self, kind: FeeKind, token: Option<Address>, amount: U256) {
+        if !amount.is_zero() {
+            let total = self.fees.entry((kind, token)).or_default();
+            *total = total.saturating_add(amount);
+        }
+    }
+}
+
+/// Builds and keeps the settlement report of each executed bundle
+pub struct SettlementReporter {
+    chain_manager: Arc<ChainManager>,
+    transactions: Arc<TransactionTracker>,
+    /// JSON file holding the reports, `None` keeps them in memory only
+    store_path: Option<PathBuf>,
+    reports: RwLock<HashMap<String, SettlementReport>>,
+}
+
+impl SettlementReporter {
+    pub async fn new(
+        chain_manager: Arc<ChainManager>,
+        transactions: Arc<TransactionTracker>,
+        store_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let reports: Vec<SettlementReport> = match &store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        if !reports.is_empty() {
+            info!("Loaded {} settlement reports", reports.len());
+        }
+
+        Ok(Self {
+            chain_manager,
+            transactions,
+            store_path,
+            reports: RwLock::new(reports.into_iter().map(|report| (report.execution_id.clone(), report)).collect()),
+        })
+    }
+
+    /// Reporter persisting to `settlements_store_path`, an empty path keeps reports in memory
+    pub async fn from_config(
+        config: &config::Config,
+        chain_manager: Arc<ChainManager>,
+        transactions: Arc<TransactionTracker>,
+    ) -> Result<Self> {
+        let path = config
+            .get_string("settlements_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let store_path = (!path.is_empty()).then(|| PathBuf::from(path));
+        Self::new(chain_manager, transactions, store_path).await
+    }
+
+    /// Settlement report of an execution, built the first time it is asked for after every
+    /// transaction settled; `None` while a transaction is still unsent or pending
+    pub async fn report(&self, execution_id: &str) -> Result<Option<SettlementReport>> {
+        if let Some(report) = self.reports.read().await.get(execution_id) {
+            return Ok(Some(report.clone()));
+        }
+
+        let records = self.transactions.execution(execution_id).await;
+        if records.is_empty() {
+            return Err(anyhow!("Unknown execution {}", execution_id));
+        }
+
+        let mut settled = Vec::with_capacity(records.len());
+        for record in records {
+            let record = match (record.status, record.hash) {
+                (TransactionStatus::Pending, Some(hash)) => self.transactions.refresh(hash).await?,
+                _ => record,
+            };
+            match record.status {
+                TransactionStatus::Built | TransactionStatus::Pending => return Ok(None),
+                TransactionStatus::Confirmed | TransactionStatus::Failed => settled.push(record),
+                // Replacements carry on under their own record, dropped transactions changed nothing
+                TransactionStatus::Replaced | TransactionStatus::Dropped => {}
+            }
+        }
+        let first = settled.first()
+            .cloned()
+            .ok_or_else(|| anyhow!("No transaction of execution {} was mined", execution_id))?;
+
+        let report = self.build(execution_id, &first, &settled).await?;
+        info!(
+            "Settled execution {} ({} transactions, {} swaps)",
+            execution_id,
+            report.transactions.len(),
+            report.legs.len()
+        );
+
+        let mut reports = self.reports.write().await;
+        reports.insert(execution_id.to_string(), report.clone());
+        if let Some(path) = &self.store_path {
+            let stored: Vec<&SettlementReport> = reports.values().collect();
+            if let Err(e) = write_store(path, &stored).await {
+                warn!("Failed to persist settlement reports to {}: {}", path.display(), e);
+            }
+        }
+        Ok(Some(report))
+    }
+
+    async fn build(&self, execution_id: &str, first: &TransactionRecord, records: &[TransactionRecord]) -> Result<SettlementReport> {
+        let chain = self.chain_manager.get_provider(first.chain_id).await?;
+        let topics = Topics::new();
+        let wrapped_native = Some(pricing_address(first.chain_id, Address::zero())).filter(|token| !token.is_zero());
+        let mut pools: HashMap<Address, PoolInfo> = HashMap::new();
+        let mut draft = SettlementDraft {
+            wallets: records.iter().filter_map(|record| record.user).collect(),
+            deltas: BTreeMap::new(),
+            fees: BTreeMap::new(),
+        };
+        let mut transactions = Vec::with_capacity(records.len());
+        let mut legs = Vec::new();
+
+        for record in records {
+            let hash = record.hash.ok_or_else(|| anyhow!("Settled record {} has no hash", record.id))?;
+            let receipt = chain.provider.get_transaction_receipt(hash).await?
+                .ok_or_else(|| anyhow!("No receipt for {:?}", hash))?;
+            let transaction = chain.provider.get_transaction(hash).await?
+                .ok_or_else(|| anyhow!("Unknown transaction {:?}", hash))?;
+            let succeeded = receipt.status.is_some_and(|status| status.as_u64() == 1);
+
+            let gas_used = receipt.gas_used.unwrap_or_default();
+            let gas_price = receipt.effective_gas_price.or(transaction.gas_price).unwrap_or_default();
+            let gas_fee = gas_used.saturating_mul(gas_price);
+            draft.wallets.insert(transaction.from);
+            draft.debit(transaction.from, None, gas_fee);
+            draft.fee(FeeKind::Gas, None, gas_fee);
+
+            if succeeded {
+                if let Some(to) = transaction.to.filter(|_| !transaction.value.is_zero()) {
+                    draft.debit(transaction.from, None, transaction.value);
+                    draft.credit(to, None, transaction.value);
+                }
+
+                let mut swaps = Vec::new();
+                for log in &receipt.logs {
+                    match self.apply_log(&chain, &topics, wrapped_native, &mut pools, &mut draft, log).await {
+                        Ok(Some(swap)) => swaps.push(swap),
+                        Ok(None) => {}
+                        Err(e) => warn!("Skipped log {:?} of {:?} in settlement: {}", log.log_index, hash, e),
+                    }
+                }
+                legs.extend(route_legs(hash, &record.quoted_swaps, swaps));
+            }
+
+            transactions.push(SettledTransaction {
+                hash,
+                status: if succeeded { TransactionStatus::Confirmed } else { TransactionStatus::Failed },
+                block_number: receipt.block_number.map(|block| block.as_u64()),
+                gas_used,
+                gas_fee,
+                explorer_url: format!("{}/tx/{:?}", chain.config.block_explorer.trim_end_matches('/'), hash),
+            });
+        }
+
+        let wallets = draft.wallets;
+        Ok(SettlementReport {
+            execution_id: execution_id.to_string(),
+            chain_id: first.chain_id,
+            source: first.source.clone(),
+            user: first.user,
+            succeeded: transactions.iter().all(|transaction| transaction.status == TransactionStatus::Confirmed),
+            transactions,
+            deltas: draft.deltas.into_iter()
+                .filter(|((wallet, _), delta)| wallets.contains(wallet) && !delta.is_zero())
+                .map(|((wallet, token), delta)| TokenDelta { wallet, token, delta })
+                .collect(),
+            fees: draft.fees.into_iter()
+                .map(|((kind, token), amount)| SettlementFee { kind, token, amount })
+                .collect(),
+            legs,
+            settled_at: Utc::now(),
+        })
+    }
+
+    /// Apply a log's balance changes and fees, returning the swap it records if any
+    async fn apply_log(
+        &self,
+        chain: &ChainProvider,
+        topics: &Topics,
+        wrapped_native: Option<Address>,
+        pools: &mut HashMap<Address, PoolInfo>,
+        draft: &mut SettlementDraft,
+        log: &Log,
+    ) -> Result<Option<PoolSwap>> {
+        let Some(topic) = log.topics.first().copied() else {
+            return Ok(None);
+        };
+        let words: Vec<U256> = log.data.chunks_exact(32).map(U256::from_big_endian).collect();
+        let indexed = |index: usize| log.topics.get(index).map(|topic| Address::from(*topic));
+
+        // ERC-721 transfers index the token id and carry no data
+        if topic == topics.transfer && log.topics.len() == 3 && words.len() == 1 {
+            let (Some(from), Some(to)) = (indexed(1), indexed(2)) else {
+                return Ok(None);
+            };
+            draft.debit(from, Some(log.address), words[0]);
+            draft.credit(to, Some(log.address), words[0]);
+        } else if (topic == topics.deposit || topic == topics.withdrawal) && Some(log.address) == wrapped_native {
+            // Wrapping mints and unwrapping burns without a Transfer event
+            let (Some(account), Some(amount)) = (indexed(1), words.first().copied()) else {
+                return Ok(None);
+            };
+            if topic == topics.deposit {
+                draft.credit(account, Some(log.address), amount);
+            } else {
+                draft.debit(account, Some(log.address), amount);
+            }
+        } else if topic == topics.v2_swap && words.len() == 4 {
+            let pool = self.pool(chain, pools, log.address, PoolKind::UniswapV2).await?;
+            let (token_in, token_out, amount_in, amount_out) = if !words[0].is_zero() {
+                (pool.token0, pool.token1, words[0], words[3])
+            } else {
+                (pool.token1, pool.token0, words[1], words[2])
+            };
+            let dex_fee = amount_in * U256::from(V2_FEE_PER_MILLE) / U256::from(1_000);
+            draft.fee(FeeKind::Dex, Some(token_in), dex_fee);
+            return Ok(Some(PoolSwap {
+                pool: log.address,
+                kind: PoolKind::UniswapV2,
+                token_in,
+                token_out,
+                amount_in,
+                amount_out,
+                dex_fee,
+                protocol_fee: U256::zero(),
+            }));
+        } else if topic == topics.v3_swap && words.len() == 5 {
+            let pool = self.pool(chain, pools, log.address, PoolKind::UniswapV3).await?;
+            // Amounts are signed from the pool's side, positive flowing in
+            let (amount0, amount1) = (I256::from_raw(words[0]), I256::from_raw(words[1]));
+            let (token_in, token_out, amount_in, amount_out, fee_protocol) = if amount0.is_positive() {
+                (pool.token0, pool.token1, amount0.into_raw(), amount1.unsigned_abs(), pool.fee_protocol.0)
+            } else {
+                (pool.token1, pool.token0, amount1.into_raw(), amount0.unsigned_abs(), pool.fee_protocol.1)
+            };
+            let swap_fee = amount_in * U256::from(pool.fee) / U256::from(V3_FEE_DENOMINATOR);
+            let protocol_fee = match fee_protocol {
+                0 => U256::zero(),
+                denominator => swap_fee / U256::from(denominator),
+            };
+            let dex_fee = swap_fee - protocol_fee;
+            draft.fee(FeeKind::Dex, Some(token_in), dex_fee);
+            draft.fee(FeeKind::Protocol, Some(token_in), protocol_fee);
+            return Ok(Some(PoolSwap {
+                pool: log.address,
+                kind: PoolKind::UniswapV3,
+                token_in,
+                token_out,
+                amount_in,
+                amount_out,
+                dex_fee,
+                protocol_fee,
+            }));
+        } else if topic == topics.v3_flash && words.len() == 4 {
+            let pool = self.pool(chain, pools, log.address, PoolKind::UniswapV3).await?;
+            draft.fee(FeeKind::FlashLoan, Some(pool.token0), words[2]);
+            draft.fee(FeeKind::FlashLoan, Some(pool.token1), words[3]);
+        } else if topic == topics.aave_v3_flash_loan && words.len() == 4 {
+            draft.fee(FeeKind::FlashLoan, indexed(2), words[3]);
+        } else if topic == topics.aave_v2_flash_loan && words.len() == 3 {
+            draft.fee(FeeKind::FlashLoan, indexed(3), words[1]);
+        } else if topic == topics.balancer_flash_loan && words.len() == 2 {
+            draft.fee(FeeKind::FlashLoan, indexed(2), words[1]);
+        }
+        Ok(None)
+    }
+
+    /// Tokens and fee settings of a pool, read once per report
+    async fn pool(
+        &self,
+        chain: &ChainProvider,
+        pools: &mut HashMap<Address, PoolInfo>,
+        pool: Address,
+        kind: PoolKind,
+    ) -> Result<PoolInfo> {
+        if let Some(info) = pools.get(&pool) {
+            return Ok(*info);
+        }
+
+        let token0 = call_address(chain, pool, "token0()").await?;
+        let token1 = call_address(chain, pool, "token1()").await?;
+        let (fee, fee_protocol) = match kind {
+            PoolKind::UniswapV2 => (0, (0, 0)),
+            PoolKind::UniswapV3 => {
+                let fee = call(chain, pool, "fee()", &[ParamType::Uint(24)]).await?;
+                let slot0 = call(chain, pool, "slot0()", &[
+                    ParamType::Uint(160),
+                    ParamType::Int(24),
+                    ParamType::Uint(16),
+                    ParamType::Uint(16),
+                    ParamType::Uint(16),
+                    ParamType::Uint(8),
+                    ParamType::Bool,
+                ]).await?;
+                let fee = match fee.first() {
+                    Some(Token::Uint(fee)) => fee.low_u64(),
+                    _ => return Err(anyhow!("Invalid fee() response from {:?}", pool)),
+                };
+                // Low four bits apply to token0 and high four bits to token1
+                let fee_protocol = match slot0.get(5) {
+                    Some(Token::Uint(packed)) => (packed.low_u32() as u8 % 16, packed.low_u32() as u8 >> 4),
+                    _ => (0, 0),
+                };
+                (fee, fee_protocol)
+            }
+        };
+
+        let info = PoolInfo { token0, token1, fee, fee_protocol };
+        pools.insert(pool, info);
+        Ok(info)
+    }
+}
+
+/// Group the pool swaps of a transaction into the quoted swaps they route, in order; swaps not
+/// matching a quote become legs of their own
+fn route_legs(transaction: H256, quotes: &[SwapQuote], swaps: Vec<PoolSwap>) -> Vec<SettledLeg> {
+    let mut used = vec![false; swaps.len()];
+    let mut legs = Vec::new();
+
+    for quote in quotes {
+        let Some(start) = (0..swaps.len()).find(|&index| !used[index] && swaps[index].token_in == quote.token_in) else {
+            continue;
+        };
+        let mut route = vec![start];
+        let mut token = swaps[start].token_out;
+        while token != quote.token_out {
+            let Some(next) = (route[route.len() - 1] + 1..swaps.len()).find(|&index| !used[index] && swaps[index].token_in == token) else {
+                break;
+            };
+            token = swaps[next].token_out;
+            route.push(next);
+        }
+        if token != quote.token_out {
+            continue;
+        }
+
+        route.iter().for_each(|&index| used[index] = true);
+        let hops: Vec<PoolSwap> = route.iter().map(|&index| swaps[index].clone()).collect();
+        let amount_out = hops[hops.len() - 1].amount_out;
+        legs.push(SettledLeg {
+            transaction,
+            token_in: quote.token_in,
+            token_out: quote.token_out,
+            amount_in: hops[0].amount_in,
+            amount_out,
+            expected_output: Some(quote.expected_output),
+            realized_slippage_bps: slippage_bps(quote.expected_output, amount_out),
+            dex: quote.dex.clone(),
+            hops,
+        });
+    }
+
+    legs.extend(swaps.into_iter().zip(used).filter(|(_, used)| !used).map(|(swap, _)| SettledLeg {
+        transaction,
+        token_in: swap.token_in,
+        token_out: swap.token_out,
+        amount_in: swap.amount_in,
+        amount_out: swap.amount_out,
+        expected_output: None,
+        realized_slippage_bps: None,
+        dex: None,
+        hops: vec![swap],
+    }));
+    legs
+}
+
+/// Shortfall of `actual` against `expected` in basis points
+fn slippage_bps(expected: U256, actual: U256) -> Option<f64> {
+    if expected.is_zero() {
+        return None;
+    }
+    let expected_f = expected.to_string().parse::<f64>().ok()?;
+    let actual_f = actual.to_string().parse::<f64>().ok()?;
+    Some((expected_f - actual_f) / expected_f * 10_000.0)
+}
+
+async fn call_address(chain: &ChainProvider, contract: Address, function: &str) -> Result<Address> {
+    match call(chain, contract, function, &[ParamType::Address]).await?.pop() {
+        Some(Token::Address(address)) => Ok(address),
+        _ => Err(anyhow!("Invalid {} response from {:?}", function, contract)),
+    }
+}
+
+/// Call a parameterless view function
+async fn call(chain: &ChainProvider, contract: Address, function: &str, outputs: &[ParamType]) -> Result<Vec<Token>> {
+    let tx: TypedTransaction = TransactionRequest::new().to(contract).data(id(function).to_vec()).into();
+    let output = chain.provider.call(&tx, None).await?;
+    abi::decode(outputs, &output).map_err(|e| anyhow!("Invalid {} response from {:?}: {}", function, contract, e))
+}
@@ -0,0 +1,76 @@
+// EIP-712 hashing of typed data as dApps and wallets compute it
+use anyhow::{Result, anyhow};
+use ethers::{
+    types::{H256, transaction::eip712::{hash_struct, Eip712, TypedData}},
+    utils::{hex, keccak256},
+};
+use serde_json::{Map, Value};
+
+/// Type the domain is declared with in the `types` of a request
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// Domain fields in the order of the full domain type
+const DOMAIN_FIELDS: [&str; 5] = ["name", "version", "chainId", "verifyingContract", "salt"];
+
+/// Domain values by field name, as `hash_struct` expects them
+fn domain_values(typed_data: &TypedData) -> Map<String, Value> {
+    let domain = &typed_data.domain;
+    let mut values = Map::new();
+    if let Some(name) = &domain.name {
+        values.insert("name".to_string(), Value::from(name.as_str()));
+    }
+    if let Some(version) = &domain.version {
+        values.insert("version".to_string(), Value::from(version.as_str()));
+    }
+    if let Some(chain_id) = domain.chain_id {
+        values.insert("chainId".to_string(), Value::from(chain_id.to_string()));
+    }
+    if let Some(verifying_contract) = domain.verifying_contract {
+        values.insert("verifyingContract".to_string(), Value::from(format!("{:?}", verifying_contract)));
+    }
+    if let Some(salt) = domain.salt {
+        values.insert("salt".to_string(), Value::from(format!("0x{}", hex::encode(salt))));
+    }
+    values
+}
+
+/// Domain separator, hashed with the `EIP712Domain` type the request declares
+///
+/// Wallets hash the domain by its declared fields and their order, while ethers rebuilds the type
+/// from the fields present, so the two only agree when the declaration is complete and canonical.
+pub fn domain_separator(typed_data: &TypedData) -> Result<H256> {
+    let Some(fields) = typed_data.types.get(DOMAIN_TYPE) else {
+        return Ok(H256::from(typed_data.domain.separator()));
+    };
+
+    let values = domain_values(typed_data);
+    if let Some(undeclared) = values.keys().find(|name| !fields.iter().any(|field| &field.name == *name)) {
+        return Err(anyhow!("Domain field {} is not declared in {}", undeclared, DOMAIN_TYPE));
+    }
+    if let Some(unknown) = fields.iter().find(|field| !DOMAIN_FIELDS.contains(&field.name.as_str())) {
+        return Err(anyhow!("Unknown {} field {}", DOMAIN_TYPE, unknown.name));
+    }
+
+    Ok(H256::from(hash_struct(DOMAIN_TYPE, &Value::Object(values), &typed_data.types)?))
+}
+
+/// Hash of the message, zero for a request signing only the domain
+pub fn struct_hash(typed_data: &TypedData) -> Result<H256> {
+    if typed_data.primary_type == DOMAIN_TYPE {
+        return Ok(H256::zero());
+    }
+    if !typed_data.types.contains_key(&typed_data.primary_type) {
+        return Err(anyhow!("Primary type {} is not declared", typed_data.primary_type));
+    }
+    Ok(H256::from(typed_data.struct_hash()?))
+}
+
+/// Digest the signature is made over, `keccak256(0x1901 ‖ domainSeparator ‖ hashStruct(message))`
+pub fn digest(typed_data: &TypedData) -> Result<H256> {
+    let mut input = vec![0x19, 0x01];
+    input.extend_from_slice(domain_separator(typed_data)?.as_bytes());
+    if typed_data.primary_type != DOMAIN_TYPE {
+        input.extend_from_slice(struct_hash(typed_data)?.as_bytes());
+    }
+    Ok(H256::from(keccak256(input)))
+}
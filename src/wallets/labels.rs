@@ -0,0 +1,150 @@
+// Wallet labels restricting which keys automated and high-risk flows may use
+use anyhow::Result;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::transactions::{read_store, write_store};
+
+/// Store used when `wallet_labels_path` is not configured
+const DEFAULT_LABELS_PATH: &str = "data/wallet_labels.json";
+
+/// Role of a wallet's key, unlabeled wallets sign on request but are never used unattended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletLabel {
+    /// Cold storage or a watched address, never signs through the server
+    #[serde(alias = "cold")]
+    ViewOnly,
+    /// Day-to-day key, may submit orders unattended
+    Operational,
+    /// Key dedicated to strategies, may also take flash loans and run high-risk strategies
+    Strategy,
+}
+
+impl fmt::Display for WalletLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletLabel::ViewOnly => write!(f, "view-only"),
+            WalletLabel::Operational => write!(f, "operational"),
+            WalletLabel::Strategy => write!(f, "strategy"),
+        }
+    }
+}
+
+/// What a wallet is about to be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletUse {
+    Signing,
+    /// Orders signed and broadcast without the owner in the loop
+    AutoExecution,
+    FlashLoan,
+    HighRiskStrategy,
+}
+
+impl fmt::Display for WalletUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletUse::Signing => write!(f, "signing"),
+            WalletUse::AutoExecution => write!(f, "auto-execution"),
+            WalletUse::FlashLoan => write!(f, "flash loans"),
+            WalletUse::HighRiskStrategy => write!(f, "high-risk strategies"),
+        }
+    }
+}
+
+impl WalletUse {
+    /// Whether a wallet with `label`, `None` when unlabeled, may be used this way
+    pub fn allowed_for(self, label: Option<WalletLabel>) -> bool {
+        match self {
+            WalletUse::Signing => label != Some(WalletLabel::ViewOnly),
+            WalletUse::AutoExecution => matches!(label, Some(WalletLabel::Operational | WalletLabel::Strategy)),
+            WalletUse::FlashLoan | WalletUse::HighRiskStrategy => label == Some(WalletLabel::Strategy),
+        }
+    }
+}
+
+/// A wallet was about to be used in a way its label does not allow
+#[derive(Debug, Clone)]
+pub struct WalletUseDenied {
+    pub wallet: Address,
+    pub usage: WalletUse,
+    pub label: Option<WalletLabel>,
+}
+
+impl fmt::Display for WalletUseDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.label {
+            Some(label) => write!(f, "Wallet {:?} is labeled {} and cannot be used for {}", self.wallet, label, self.usage),
+            None => write!(f, "Wallet {:?} has no label allowing {}", self.wallet, self.usage),
+        }
+    }
+}
+
+impl std::error::Error for WalletUseDenied {}
+
+/// Labels by wallet, persisted so a restart never widens what a key may do
+pub struct WalletLabels {
+    path: Option<PathBuf>,
+    labels: RwLock<HashMap<Address, WalletLabel>>,
+}
+
+impl WalletLabels {
+    pub async fn new(path: Option<PathBuf>) -> Result<Self> {
+        let labels: HashMap<Address, WalletLabel> = match &path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        if !labels.is_empty() {
+            info!("Loaded {} wallet labels", labels.len());
+        }
+        Ok(Self {
+            path,
+            labels: RwLock::new(labels),
+        })
+    }
+
+    /// Labels stored at `wallet_labels_path`, empty keeps them in memory
+    pub async fn from_config(config: &config::Config) -> Result<Self> {
+        let path = config
+            .get_string("wallet_labels_path")
+            .unwrap_or_else(|_| DEFAULT_LABELS_PATH.to_string());
+        Self::new((!path.is_empty()).then(|| PathBuf::from(path))).await
+    }
+
+    pub async fn get(&self, wallet: Address) -> Option<WalletLabel> {
+        self.labels.read().await.get(&wallet).copied()
+    }
+
+    /// Label a wallet, or remove its label with `None`
+    pub async fn set(&self, wallet: Address, label: Option<WalletLabel>) -> Result<()> {
+        let mut labels = self.labels.write().await;
+        match label {
+            Some(label) => labels.insert(wallet, label),
+            None => labels.remove(&wallet),
+        };
+        if let Some(path) = &self.path {
+            write_store(path, &*labels).await?;
+        }
+
+        match label {
+            Some(label) => info!("Labeled wallet {:?} {}", wallet, label),
+            None => info!("Removed the label of wallet {:?}", wallet),
+        }
+        Ok(())
+    }
+
+    /// Fail with [`WalletUseDenied`] unless the wallet's label allows `usage`
+    pub async fn require(&self, wallet: Address, usage: WalletUse) -> Result<()> {
+        let label = self.get(wallet).await;
+        if usage.allowed_for(label) {
+            return Ok(());
+        }
+        warn!("Refused to use wallet {:?} for {}", wallet, usage);
+        Err(WalletUseDenied { wallet, usage, label }.into())
+    }
+}
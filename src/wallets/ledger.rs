@@ -1,303 +0,0 @@
-// Ledger hardware wallet integration
-use anyhow::{Result, anyhow};
-use ethers::{
-    prelude::*,
-    types::{Address, Signature, transaction::eip2718::TypedTransaction},
-};
-use serde::{Deserialize, Serialize};
-use tracing::{info, warn, error};
-use std::collections::HashMap;
-
-#[derive(Debug, Clone)]
-pub struct LedgerWallet {
-    device_id: String,
-    derivation_path: String,
-    addresses: HashMap<u32, Address>,
-    current_address_index: u32,
-    is_connected: bool,
-    app_name: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LedgerDevice {
-    pub device_id: String,
-    pub product_name: String,
-    pub firmware_version: String,
-    pub is_bootloader: bool,
-    pub is_genuine: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DerivationPath {
-    pub purpose: u32,    // Usually 44 for BIP44
-    pub coin_type: u32,  // 60 for Ethereum
-    pub account: u32,    // Account index
-    pub change: u32,     // 0 for external addresses
-    pub address_index: u32, // Address index
-}
-
-impl Default for DerivationPath {
-    fn default() -> Self {
-        Self {
-            purpose: 44,
-            coin_type: 60, // Ethereum
-            account: 0,
-            change: 0,
-            address_index: 0,
-        }
-    }
-}
-
-impl DerivationPath {
-    pub fn to_string(&self) -> String {
-        format!("m/{}'/{}'/{}'/{}/{}", 
-            self.purpose, self.coin_type, self.account, self.change, self.address_index)
-    }
-
-    pub fn next_address(&mut self) -> String {
-        self.address_index += 1;
-        self.to_string()
-    }
-}
-
-impl LedgerWallet {
-    pub async fn connect() -> Result<Self> {
-        info!("Connecting to Ledger hardware wallet");
-
-        // In a real implementation, this would:
-        // 1. Use HID/USB libraries to detect Ledger devices
-        // 2. Initialize communication with the device
-        // 3. Verify device authenticity
-        // 4. Check if Ethereum app is running
-
-        warn!("Using mock Ledger connection - implement real HID/USB communication");
-
-        let device_id = format!("ledger_{}", uuid::Uuid::new_v4());
-        let mut addresses = HashMap::new();
-        
-        // Generate mock addresses for different derivation paths
-        for i in 0..10 {
-            addresses.insert(i, Address::random());
-        }
-
-        info!("Mock Ledger device connected: {}", device_id);
-        info!("Generated {} addresses", addresses.len());
-
-        Ok(Self {
-            device_id,
-            derivation_path: DerivationPath::default().to_string(),
-            addresses,
-            current_address_index: 0,
-            is_connected: true,
-            app_name: "Ethereum".to_string(),
-        })
-    }
-
-    pub async fn list_devices() -> Result<Vec<LedgerDevice>> {
-        info!("Scanning for Ledger devices");
-
-        // In a real implementation, scan USB devices for Ledger VID/PID
-        warn!("Mock device enumeration - implement real USB device scanning");
-
-        Ok(vec![
-            LedgerDevice {
-                device_id: "ledger_nano_s_plus".to_string(),
-                product_name: "Nano S Plus".to_string(),
-                firmware_version: "1.1.0".to_string(),
-                is_bootloader: false,
-                is_genuine: true,
-            },
-            LedgerDevice {
-                device_id: "ledger_nano_x".to_string(),
-                product_name: "Nano X".to_string(),
-                firmware_version: "2.2.3".to_string(),
-                is_bootloader: false,
-                is_genuine: true,
-            },
-        ])
-    }
-
-    pub fn get_address(&self) -> Option<Address> {
-        self.addresses.get(&self.current_address_index).copied()
-    }
-
-    pub fn get_address_at_index(&self, index: u32) -> Option<Address> {
-        self.addresses.get(&index).copied()
-    }
-
-    pub async fn get_addresses(&self, start_index: u32, count: u32) -> Result<Vec<(u32, Address)>> {
-        info!("Getting {} addresses starting from index {}", count, start_index);
-
-        // In a real implementation, derive addresses from the device
-        warn!("Mock address derivation - implement real BIP44 derivation");
-
-        let mut result = Vec::new();
-        for i in start_index..start_index + count {
-            if let Some(address) = self.addresses.get(&i) {
-                result.push((i, *address));
-            }
-        }
-
-        Ok(result)
-    }
-
-    pub async fn set_address_index(&mut self, index: u32) -> Result<()> {
-        if !self.addresses.contains_key(&index) {
-            return Err(anyhow!("Address index {} not found", index));
-        }
-
-        self.current_address_index = index;
-        let path = DerivationPath {
-            address_index: index,
-            ..DerivationPath::default()
-        };
-        self.derivation_path = path.to_string();
-
-        info!("Set current address index to {} (path: {})", index, self.derivation_path);
-        Ok(())
-    }
-
-    pub fn is_connected(&self) -> bool {
-        self.is_connected
-    }
-
-    pub fn get_device_id(&self) -> &str {
-        &self.device_id
-    }
-
-    pub async fn verify_device(&self) -> Result<bool> {
-        info!("Verifying Ledger device authenticity");
-
-        // In a real implementation:
-        // 1. Challenge the device with cryptographic verification
-        // 2. Verify Ledger's attestation certificate
-        // 3. Check device genuineness
-
-        warn!("Mock device verification - implement cryptographic verification");
-
-        Ok(true)
-    }
-
-    pub async fn check_app(&self, app_name: &str) -> Result<bool> {
-        info!("Checking if {} app is running on Ledger", app_name);
-
-        // In a real implementation, send APDU command to check current app
-        warn!("Mock app check - implement APDU communication");
-
-        Ok(app_name == "Ethereum")
-    }
-
-    pub async fn install_app(&self, _app_name: &str) -> Result<()> {
-        info!("Installing {} app on Ledger", _app_name);
-
-        // In a real implementation, this would:
-        // 1. Download app from Ledger's app catalog
-        // 2. Verify app signature
-        // 3. Install app on device (requires user confirmation)
-
-        warn!("Mock app installation - implement Ledger Live Manager integration");
-
-        Ok(())
-    }
-
-    pub async fn sign_message(&self, _message: &[u8]) -> Result<Signature> {
-        if !self.is_connected {
-            return Err(anyhow!("Ledger device not connected"));
-        }
-
-        info!("Signing message with Ledger device");
-
-        // In a real implementation:
-        // 1. Send personal_sign APDU command
-        // 2. Display message on device screen
-        // 3. Wait for user confirmation
-        // 4. Return signature from device
-
-        warn!("Mock Ledger message signing - implement APDU signing");
-
-        let mock_signature = Signature {
-            r: U256::from(6),
-            s: U256::from(6),
-            v: 27,
-        };
-
-        Ok(mock_signature)
-    }
-
-    pub async fn sign_transaction(&self, _tx: TypedTransaction) -> Result<Signature> {
-        if !self.is_connected {
-            return Err(anyhow!("Ledger device not connected"));
-        }
-
-        info!("Signing transaction with Ledger device");
-
-        // In a real implementation:
-        // 1. Parse transaction into displayable format
-        // 2. Send transaction signing APDU command
-        // 3. Display transaction details on device
-        // 4. Wait for user confirmation
-        // 5. Return signature from device
-
-        warn!("Mock Ledger transaction signing - implement APDU transaction signing");
-
-        let mock_signature = Signature {
-            r: U256::from(7),
-            s: U256::from(7),
-            v: 28,
-        };
-
-        Ok(mock_signature)
-    }
-
-    pub async fn sign_typed_data(&self, _domain: &str, _types: &str, _data: &str) -> Result<Signature> {
-        if !self.is_connected {
-            return Err(anyhow!("Ledger device not connected"));
-        }
-
-        info!("Signing typed data with Ledger device");
-
-        // In a real implementation:
-        // 1. Parse EIP-712 structured data
-        // 2. Send typed data signing APDU command
-        // 3. Display structured data on device
-        // 4. Wait for user confirmation
-        // 5. Return signature from device
-
-        warn!("Mock Ledger EIP-712 signing - implement structured data signing");
-
-        let mock_signature = Signature {
-            r: U256::from(8),
-            s: U256::from(8),
-            v: 27,
-        };
-
-        Ok(mock_signature)
-    }
-
-    pub async fn disconnect(&mut self) -> Result<()> {
-        info!("Disconnecting from Ledger device: {}", self.device_id);
-
-        // In a real implementation, close HID/USB connection
-        warn!("Mock disconnect - implement real device disconnection");
-
-        self.is_connected = false;
-        Ok(())
-    }
-
-    pub async fn get_device_info(&self) -> Result<LedgerDevice> {
-        if !self.is_connected {
-            return Err(anyhow!("Ledger device not connected"));
-        }
-
-        // In a real implementation, query device for info
-        warn!("Mock device info - implement real device querying");
-
-        Ok(LedgerDevice {
-            device_id: self.device_id.clone(),
-            product_name: "Mock Ledger Device".to_string(),
-            firmware_version: "1.0.0".to_string(),
-            is_bootloader: false,
-            is_genuine: true,
-        })
-    }
-}
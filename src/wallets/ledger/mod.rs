@@ -0,0 +1,379 @@
+// Ledger hardware wallet integration through the Ethereum app
+use anyhow::{Result, anyhow};
+use ethers::{
+    types::{
+        Address, Signature, H256, U256,
+        transaction::{eip2718::TypedTransaction, eip712::TypedData},
+    },
+    utils::hex,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+pub mod transport;
+
+use super::eip712;
+use transport::{HidDevice, LedgerError, Transport, MAX_APDU_DATA};
+
+// Ethereum app instructions, see the app's APDU reference
+const CLA: u8 = 0xe0;
+const INS_GET_PUBLIC_KEY: u8 = 0x02;
+const INS_SIGN_TRANSACTION: u8 = 0x04;
+const INS_GET_APP_CONFIGURATION: u8 = 0x06;
+const INS_SIGN_PERSONAL_MESSAGE: u8 = 0x08;
+const INS_SIGN_EIP712_HASHED: u8 = 0x0c;
+const P1_FIRST_CHUNK: u8 = 0x00;
+const P1_MORE_CHUNKS: u8 = 0x80;
+/// Name and version of the running app, answered by the dashboard as well
+const GET_APP_AND_VERSION: [u8; 5] = [0xb0, 0x01, 0x00, 0x00, 0x00];
+const ETHEREUM_APP: &str = "Ethereum";
+const HARDENED: u32 = 0x8000_0000;
+const MAX_DISCOVERY_COUNT: u32 = 20;
+
+type SharedTransport = Arc<Mutex<Transport>>;
+
+/// Connected Ledger signing with one account of the Ethereum app
+#[derive(Clone)]
+pub struct LedgerWallet {
+    device: LedgerDevice,
+    transport: SharedTransport,
+    derivation_path: DerivationPath,
+    address: Address,
+    is_connected: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerDevice {
+    pub device_id: String,
+    pub product_name: String,
+    /// Version of the Ethereum app, empty until it has been queried
+    pub app_version: String,
+}
+
+/// BIP32 path, hardened components marked with `'`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DerivationPath(Vec<u32>);
+
+impl Default for DerivationPath {
+    /// First Ledger Live account, `m/44'/60'/0'/0/0`
+    fn default() -> Self {
+        DerivationScheme::LedgerLive.path(0)
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let components = path.strip_prefix("m/")
+            .ok_or_else(|| anyhow!("Derivation path {} must start with m/", path))?
+            .split('/')
+            .map(|component| {
+                let (index, hardened) = match component.strip_suffix('\'') {
+                    Some(index) => (index, HARDENED),
+                    None => (component, 0),
+                };
+                let index: u32 = index.parse().map_err(|_| anyhow!("Invalid derivation path component {}", component))?;
+                if index >= HARDENED {
+                    return Err(anyhow!("Derivation path component {} out of range", component));
+                }
+                Ok(index | hardened)
+            })
+            .collect::<Result<Vec<u32>>>()?;
+
+        // The Ethereum app only derives paths of up to 10 levels
+        if components.is_empty() || components.len() > 10 {
+            return Err(anyhow!("Derivation path {} must have 1 to 10 levels", path));
+        }
+        Ok(Self(components))
+    }
+}
+
+impl TryFrom<String> for DerivationPath {
+    type Error = anyhow::Error;
+
+    fn try_from(path: String) -> Result<Self> {
+        path.parse()
+    }
+}
+
+impl From<DerivationPath> for String {
+    fn from(path: DerivationPath) -> Self {
+        path.to_string()
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for component in &self.0 {
+            match component & HARDENED {
+                0 => write!(f, "/{}", component)?,
+                _ => write!(f, "/{}'", component & !HARDENED)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DerivationPath {
+    /// Number of levels followed by each level big-endian, as APDUs carry paths
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.0.len() as u8];
+        for component in &self.0 {
+            bytes.extend_from_slice(&component.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// Layouts wallets number Ethereum accounts with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DerivationScheme {
+    /// `m/44'/60'/{index}'/0/0`
+    #[default]
+    LedgerLive,
+    /// `m/44'/60'/0'/0/{index}`, the BIP44 layout of most software wallets
+    Bip44,
+    /// `m/44'/60'/0'/{index}`, used by MyEtherWallet and older Ledger apps
+    Legacy,
+}
+
+impl DerivationScheme {
+    pub fn path(self, index: u32) -> DerivationPath {
+        let index = index & !HARDENED;
+        DerivationPath(match self {
+            Self::LedgerLive => vec![44 | HARDENED, 60 | HARDENED, index | HARDENED, 0, 0],
+            Self::Bip44 => vec![44 | HARDENED, 60 | HARDENED, HARDENED, 0, index],
+            Self::Legacy => vec![44 | HARDENED, 60 | HARDENED, HARDENED, index],
+        })
+    }
+}
+
+/// Address the device derives at a path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerAccount {
+    pub derivation_path: DerivationPath,
+    pub address: Address,
+}
+
+impl LedgerWallet {
+    /// Connect to the first Ledger found, using the account at `derivation_path`
+    pub async fn connect(derivation_path: DerivationPath) -> Result<Self> {
+        info!("Connecting to Ledger hardware wallet at {}", derivation_path);
+        let (device, transport) = open_first_device().await?;
+
+        let app_version = exchange(&transport, vec![apdu(INS_GET_APP_CONFIGURATION, 0, 0, &[])]).await?;
+        let address = get_address(&transport, &derivation_path).await?;
+        let device = LedgerDevice {
+            app_version: match app_version.get(1..4) {
+                Some([major, minor, patch]) => format!("{}.{}.{}", major, minor, patch),
+                _ => String::new(),
+            },
+            ..device
+        };
+
+        info!("Connected Ledger {} ({:?} at {})", device.product_name, address, derivation_path);
+        Ok(Self {
+            device,
+            transport,
+            derivation_path,
+            address,
+            is_connected: true,
+        })
+    }
+
+    /// Ledger devices attached to this machine
+    pub async fn list_devices() -> Result<Vec<LedgerDevice>> {
+        let devices = tokio::task::spawn_blocking(transport::enumerate).await??;
+        Ok(devices.iter().map(device_info).collect())
+    }
+
+    /// Addresses at `count` consecutive indexes of a scheme, for the user to pick an account
+    pub async fn discover_addresses(scheme: DerivationScheme, start_index: u32, count: u32) -> Result<Vec<LedgerAccount>> {
+        if count == 0 || count > MAX_DISCOVERY_COUNT {
+            return Err(anyhow!("Discover between 1 and {} addresses at a time", MAX_DISCOVERY_COUNT));
+        }
+        let (_, transport) = open_first_device().await?;
+
+        let mut accounts = Vec::with_capacity(count as usize);
+        for index in start_index..start_index.saturating_add(count) {
+            let derivation_path = scheme.path(index);
+            let address = get_address(&transport, &derivation_path).await?;
+            accounts.push(LedgerAccount { derivation_path, address });
+        }
+        Ok(accounts)
+    }
+
+    pub fn get_address(&self) -> Address {
+        self.address
+    }
+
+    pub fn derivation_path(&self) -> &DerivationPath {
+        &self.derivation_path
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.is_connected
+    }
+
+    pub fn get_device_info(&self) -> &LedgerDevice {
+        &self.device
+    }
+
+    /// Name of the app open on the device, `BOLOS` on the dashboard
+    pub async fn running_app(&self) -> Result<String> {
+        running_app(&self.transport).await
+    }
+
+    /// Sign an EIP-191 personal message, confirmed on the device
+    pub async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        self.ensure_connected()?;
+        info!("Signing message with Ledger {:?}", self.address);
+
+        let mut payload = self.derivation_path.to_bytes();
+        payload.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        payload.extend_from_slice(message);
+        let response = exchange(&self.transport, chunked(INS_SIGN_PERSONAL_MESSAGE, &payload)).await?;
+
+        let signature = self.recover_signature(&response, ethers::utils::hash_message(message))?;
+        Ok(signature)
+    }
+
+    /// Sign a legacy, EIP-2930 or EIP-1559 transaction, its details confirmed on the device
+    pub async fn sign_transaction(&self, tx: TypedTransaction) -> Result<Signature> {
+        self.ensure_connected()?;
+        let chain_id = tx.chain_id()
+            .ok_or_else(|| anyhow!("Ledger signing needs the transaction's chain id"))?
+            .as_u64();
+        info!("Signing transaction on chain {} with Ledger {:?}", chain_id, self.address);
+
+        let mut payload = self.derivation_path.to_bytes();
+        payload.extend_from_slice(&tx.rlp());
+        let response = exchange(&self.transport, chunked(INS_SIGN_TRANSACTION, &payload)).await?;
+
+        let mut signature = self.recover_signature(&response, tx.sighash())?;
+        // The device truncates v to a byte, so it is rebuilt in the EIP-155 form local wallets produce
+        signature.v = signature.v - 27 + 35 + chain_id * 2;
+        Ok(signature)
+    }
+
+    /// Sign EIP-712 typed data by its domain separator and message hash
+    pub async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature> {
+        self.ensure_connected()?;
+        info!("Signing typed data with Ledger {:?}", self.address);
+
+        if typed_data.primary_type == "EIP712Domain" {
+            return Err(anyhow!("Ledger cannot sign a domain without a message"));
+        }
+
+        let mut payload = self.derivation_path.to_bytes();
+        payload.extend_from_slice(eip712::domain_separator(typed_data)?.as_bytes());
+        payload.extend_from_slice(eip712::struct_hash(typed_data)?.as_bytes());
+        let response = exchange(&self.transport, vec![apdu(INS_SIGN_EIP712_HASHED, 0, 0, &payload)]).await?;
+
+        self.recover_signature(&response, eip712::digest(typed_data)?)
+    }
+
+    pub async fn disconnect(&mut self) -> Result<()> {
+        info!("Disconnecting from Ledger device: {}", self.device.device_id);
+        self.is_connected = false;
+        Ok(())
+    }
+
+    fn ensure_connected(&self) -> Result<()> {
+        if !self.is_connected {
+            return Err(anyhow!("Ledger device not connected"));
+        }
+        Ok(())
+    }
+
+    /// Signature from a `v ‖ r ‖ s` response with `v` as 27 or 28, checked against the account
+    fn recover_signature(&self, response: &[u8], hash: H256) -> Result<Signature> {
+        if response.len() < 65 {
+            return Err(anyhow!("Truncated Ledger signature"));
+        }
+        let r = U256::from_big_endian(&response[1..33]);
+        let s = U256::from_big_endian(&response[33..65]);
+
+        [27, 28].into_iter()
+            .map(|v| Signature { r, s, v })
+            .find(|signature| signature.recover(hash).is_ok_and(|signer| signer == self.address))
+            .ok_or_else(|| anyhow!("Ledger signature does not match {:?}, check the derivation path", self.address))
+    }
+}
+
+/// Open the first Ledger, failing clearly unless it is unlocked with the Ethereum app open
+async fn open_first_device() -> Result<(LedgerDevice, SharedTransport)> {
+    let devices = tokio::task::spawn_blocking(transport::enumerate).await??;
+    let device = devices.into_iter().next().ok_or(LedgerError::DeviceNotFound)?;
+    let info = device_info(&device);
+    let transport = Arc::new(Mutex::new(Transport::open(&device)?));
+
+    match running_app(&transport).await?.as_str() {
+        ETHEREUM_APP => Ok((info, transport)),
+        other => Err(LedgerError::AppNotOpen(Some(other.to_string())).into()),
+    }
+}
+
+fn device_info(device: &HidDevice) -> LedgerDevice {
+    LedgerDevice {
+        device_id: match device.serial.as_str() {
+            "" => device.path.display().to_string(),
+            serial => serial.to_string(),
+        },
+        product_name: device.product_name.clone(),
+        app_version: String::new(),
+    }
+}
+
+async fn running_app(transport: &SharedTransport) -> Result<String> {
+    let response = exchange(transport, vec![GET_APP_AND_VERSION.to_vec()]).await?;
+    // Format byte, then the length-prefixed name
+    let length = *response.get(1).ok_or_else(|| anyhow!("Truncated Ledger app info"))? as usize;
+    let name = response.get(2..2 + length).ok_or_else(|| anyhow!("Truncated Ledger app info"))?;
+    Ok(String::from_utf8_lossy(name).into_owned())
+}
+
+async fn get_address(transport: &SharedTransport, derivation_path: &DerivationPath) -> Result<Address> {
+    let response = exchange(transport, vec![apdu(INS_GET_PUBLIC_KEY, 0, 0, &derivation_path.to_bytes())]).await?;
+    // Public key and hex address, each length-prefixed
+    let key_length = *response.first().ok_or_else(|| anyhow!("Truncated Ledger address"))? as usize;
+    let address_length = *response.get(1 + key_length).ok_or_else(|| anyhow!("Truncated Ledger address"))? as usize;
+    let address = response.get(2 + key_length..2 + key_length + address_length)
+        .ok_or_else(|| anyhow!("Truncated Ledger address"))?;
+    Ok(Address::from_slice(&hex::decode(address)?))
+}
+
+fn apdu(ins: u8, p1: u8, p2: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![CLA, ins, p1, p2, data.len() as u8];
+    apdu.extend_from_slice(data);
+    apdu
+}
+
+/// APDUs carrying a payload longer than one APDU's data field
+fn chunked(ins: u8, payload: &[u8]) -> Vec<Vec<u8>> {
+    payload.chunks(MAX_APDU_DATA)
+        .enumerate()
+        .map(|(i, chunk)| apdu(ins, if i == 0 { P1_FIRST_CHUNK } else { P1_MORE_CHUNKS }, 0, chunk))
+        .collect()
+}
+
+/// Send APDUs in order without other requests in between, returning the last response
+async fn exchange(transport: &SharedTransport, apdus: Vec<Vec<u8>>) -> Result<Vec<u8>> {
+    let transport = Arc::clone(transport);
+    tokio::task::spawn_blocking(move || {
+        let mut transport = transport.lock().map_err(|_| anyhow!("Ledger transport poisoned"))?;
+        let mut response = Vec::new();
+        for apdu in &apdus {
+            response = transport.exchange(apdu)?;
+        }
+        Ok(response)
+    })
+    .await?
+}
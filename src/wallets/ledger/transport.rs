@@ -0,0 +1,214 @@
+// HID transport to Ledger devices through Linux hidraw nodes
+use anyhow::Result;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const LEDGER_VENDOR_ID: u32 = 0x2c97;
+const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+/// HID report size of every Ledger model
+const PACKET_SIZE: usize = 64;
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+/// Largest APDU data field, longer payloads are split by the caller
+pub const MAX_APDU_DATA: usize = 255;
+
+const SW_OK: u16 = 0x9000;
+
+/// Device states a user has to act on, surfaced as they are instead of a bare status word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerError {
+    DeviceNotFound,
+    DeviceLocked,
+    /// The Ethereum app is not open, with the app that is when known
+    AppNotOpen(Option<String>),
+    /// The user rejected the request on the device
+    Rejected,
+    /// The app refused the data, contract data needs blind signing enabled
+    InvalidData,
+    Unsupported(String),
+    Status(u16),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeviceNotFound => write!(f, "No Ledger device found, connect and unlock it"),
+            Self::DeviceLocked => write!(f, "Ledger device is locked, enter the PIN to unlock it"),
+            Self::AppNotOpen(Some(app)) => write!(f, "Open the Ethereum app on the Ledger ({} is open)", app),
+            Self::AppNotOpen(None) => write!(f, "Open the Ethereum app on the Ledger"),
+            Self::Rejected => write!(f, "Request rejected on the Ledger device"),
+            Self::InvalidData => write!(
+                f,
+                "Ledger Ethereum app refused the data, enable blind signing in its settings for contract calls",
+            ),
+            Self::Unsupported(reason) => write!(f, "Ledger not supported: {}", reason),
+            Self::Status(status) => write!(f, "Ledger returned status {:#06x}", status),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl LedgerError {
+    fn from_status(status: u16) -> Self {
+        match status {
+            0x6985 => Self::Rejected,
+            0x5515 | 0x6982 => Self::DeviceLocked,
+            // Class or instruction unknown to whatever app is running, the dashboard included
+            0x6511 | 0x6d00 | 0x6e00 | 0x6e01 => Self::AppNotOpen(None),
+            0x6a80 => Self::InvalidData,
+            _ => Self::Status(status),
+        }
+    }
+}
+
+/// Generic HID interface of a connected Ledger
+#[derive(Debug, Clone)]
+pub struct HidDevice {
+    pub path: PathBuf,
+    pub product_id: u16,
+    pub product_name: String,
+    pub serial: String,
+}
+
+/// Ledger devices attached over USB, only their APDU interface
+pub fn enumerate() -> Result<Vec<HidDevice>> {
+    let root = Path::new(SYSFS_HIDRAW);
+    if !root.exists() {
+        return Err(LedgerError::Unsupported("HID access needs Linux hidraw".to_string()).into());
+    }
+
+    let mut devices = Vec::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let Ok(uevent) = std::fs::read_to_string(entry.path().join("device/uevent")) else {
+            continue;
+        };
+        if let Some(device) = parse_uevent(&uevent, &entry.file_name().to_string_lossy()) {
+            devices.push(device);
+        }
+    }
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(devices)
+}
+
+/// Device from a hidraw node's uevent, `None` for other vendors and the FIDO interface
+fn parse_uevent(uevent: &str, node: &str) -> Option<HidDevice> {
+    let field = |name: &str| {
+        uevent.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+
+    // HID_ID=<bus>:<vendor>:<product>, all hex
+    let id = field("HID_ID")?;
+    let mut parts = id.split(':').skip(1);
+    let vendor = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let product = u32::from_str_radix(parts.next()?, 16).ok()?;
+    if vendor != LEDGER_VENDOR_ID || !field("HID_PHYS")?.ends_with("input0") {
+        return None;
+    }
+
+    Some(HidDevice {
+        path: Path::new("/dev").join(node),
+        product_id: product as u16,
+        product_name: field("HID_NAME").unwrap_or_else(|| model_name(product as u16).to_string()),
+        serial: field("HID_UNIQ").unwrap_or_default(),
+    })
+}
+
+/// Model from the high byte of the product id
+pub fn model_name(product_id: u16) -> &'static str {
+    match product_id >> 8 {
+        0x10 => "Nano S",
+        0x40 => "Nano X",
+        0x50 => "Nano S Plus",
+        0x60 => "Stax",
+        0x70 => "Flex",
+        _ => "Ledger",
+    }
+}
+
+/// Open hidraw node exchanging APDUs with the device, one at a time
+pub struct Transport {
+    file: File,
+}
+
+impl Transport {
+    pub fn open(device: &HidDevice) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&device.path).map_err(|e| {
+            anyhow::anyhow!(
+                "Cannot open Ledger at {} ({}), check the udev rules grant access to it",
+                device.path.display(),
+                e,
+            )
+        })?;
+        Ok(Self { file })
+    }
+
+    /// Send an APDU and return the response data, failing on any status but 0x9000.
+    /// Blocks until the device answers, which waits on the user for confirmations.
+    pub fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>> {
+        self.write_apdu(apdu)?;
+        let mut response = self.read_response()?;
+        if response.len() < 2 {
+            return Err(anyhow::anyhow!("Truncated Ledger response"));
+        }
+        let status = u16::from_be_bytes([response[response.len() - 2], response[response.len() - 1]]);
+        response.truncate(response.len() - 2);
+        if status != SW_OK {
+            return Err(LedgerError::from_status(status).into());
+        }
+        Ok(response)
+    }
+
+    fn write_apdu(&mut self, apdu: &[u8]) -> Result<()> {
+        let mut framed = (apdu.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(apdu);
+
+        for (sequence, chunk) in framed.chunks(PACKET_SIZE - 5).enumerate() {
+            // Leading report number, Ledger devices do not use numbered reports
+            let mut report = [0u8; PACKET_SIZE + 1];
+            report[1..3].copy_from_slice(&CHANNEL.to_be_bytes());
+            report[3] = TAG_APDU;
+            report[4..6].copy_from_slice(&(sequence as u16).to_be_bytes());
+            report[6..6 + chunk.len()].copy_from_slice(chunk);
+            self.file.write_all(&report)?;
+        }
+        Ok(())
+    }
+
+    fn read_response(&mut self) -> Result<Vec<u8>> {
+        let mut response = Vec::new();
+        let mut expected = None;
+        let mut sequence: u16 = 0;
+
+        loop {
+            let mut packet = [0u8; PACKET_SIZE];
+            let read = self.file.read(&mut packet)?;
+            if read < 5
+                || u16::from_be_bytes([packet[0], packet[1]]) != CHANNEL
+                || packet[2] != TAG_APDU
+                || u16::from_be_bytes([packet[3], packet[4]]) != sequence
+            {
+                return Err(anyhow::anyhow!("Unexpected Ledger HID packet"));
+            }
+
+            let data = match expected {
+                None => {
+                    expected = Some(u16::from_be_bytes([packet[5], packet[6]]) as usize);
+                    &packet[7..read]
+                }
+                Some(_) => &packet[5..read],
+            };
+            let length = expected.unwrap_or_default();
+            response.extend_from_slice(&data[..data.len().min(length - response.len())]);
+            if response.len() >= length {
+                return Ok(response);
+            }
+            sequence = sequence.wrapping_add(1);
+        }
+    }
+}
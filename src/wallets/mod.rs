@@ -1,20 +1,31 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use ethers::{
     prelude::*,
     signers::{LocalWallet, Signer, Wallet, coins_bip39::English},
-    types::{Address, Signature, H256, transaction::eip2718::TypedTransaction},
+    types::{Address, Signature, H256, transaction::{eip2718::TypedTransaction, eip712::TypedData}},
+    utils::hash_message,
 };
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
 use tracing::{info, warn};
 
 pub mod metamask;
 pub mod walletconnect;
 pub mod ledger;
 pub mod multisig;
+pub mod smart_account;
+pub mod labels;
+pub mod eip712;
 
-use crate::security::SecurityManager;
+use crate::api::models::ArchiveFilter;
+use crate::chains::{tx_broadcaster::TxBroadcaster, ChainManager};
+use crate::security::sanctions::DEFAULT_REFRESH_INTERVAL;
+use crate::security::{PolicyContext, SanctionsScreener, SecurityManager};
+use crate::shutdown::ShutdownSignal;
+use crate::transactions::TransactionTracker;
 
 #[derive(Debug, Clone)]
 pub enum WalletType {
@@ -23,6 +34,7 @@ pub enum WalletType {
     Ledger,
     LocalWallet,
     MultiSig,
+    SmartAccount,
 }
 
 #[derive(Debug, Clone)]
@@ -32,12 +44,20 @@ pub struct WalletInfo {
     pub chain_id: u64,
     pub is_connected: bool,
     pub balance: Option<U256>,
+    pub disconnected_at: Option<DateTime<Utc>>,
 }
 
 pub struct WalletManager {
     wallets: Arc<RwLock<HashMap<Address, WalletProvider>>>,
+    /// Wallets disconnected by their users, kept for history
+    disconnected: Arc<RwLock<HashMap<Address, WalletInfo>>>,
     security: Arc<SecurityManager>,
     multisig_manager: multisig::MultiSigManager,
+    smart_accounts: smart_account::SmartAccountManager,
+    /// Roles restricting what each wallet may be used for
+    labels: labels::WalletLabels,
+    /// WalletConnect client, `None` without a project id
+    walletconnect: Option<Arc<walletconnect::WalletConnectClient>>,
 }
 
 pub enum WalletProvider {
@@ -46,58 +66,181 @@ pub enum WalletProvider {
     Ledger(ledger::LedgerWallet),
     Local(LocalWallet),
     MultiSig(multisig::MultiSigWallet),
+    SmartAccount(smart_account::SmartAccountWallet),
 }
 
 impl WalletManager {
-    pub async fn new(_config: Option<&crate::app_config::Config>) -> Result<Self> {
+    pub async fn new(config: Option<&crate::app_config::Config>) -> Result<Self> {
         // Create a default provider for security manager
         let provider_url = "https://eth-mainnet.g.alchemy.com/v2/demo";
         let provider = Provider::<Http>::try_from(provider_url)?;
-        let security = Arc::new(SecurityManager::new(provider).await?);
-        let multisig_manager = multisig::MultiSigManager::new().await?;
+        // Without sanctions lists, the configured blacklist is screened
+        let sanctioned = config
+            .map(|config| config.security.blacklisted_addresses.iter().filter_map(|address| address.parse().ok()).collect())
+            .unwrap_or_default();
+        let sanctions = Arc::new(SanctionsScreener::new(Vec::new(), DEFAULT_REFRESH_INTERVAL, sanctioned));
+        let security = Arc::new(SecurityManager::new(provider, sanctions).await?);
+
+        let walletconnect = walletconnect::WalletConnectConfig {
+            project_id: config.and_then(|config| config.wallets.walletconnect_project_id.clone()),
+            ..Default::default()
+        };
+
+        let chain_manager = Arc::new(ChainManager::new_demo().await?);
+        let transactions = Arc::new(TransactionTracker::new(chain_manager.clone(), None, None).await?);
+        let broadcaster = Arc::new(TxBroadcaster::new(
+            chain_manager.clone(),
+            transactions,
+            Some(security.advanced.spending_policies()),
+        ));
+        let multisig_manager = multisig::MultiSigManager::new(
+            chain_manager.clone(),
+            broadcaster,
+            multisig::SafeConfig::default(),
+        ).await?;
+        let smart_accounts = smart_account::SmartAccountManager::new(
+            chain_manager,
+            smart_account::SmartAccountConfig::default(),
+        ).await?;
+        let labels = labels::WalletLabels::new(None).await?;
+        Self::with_security(security, walletconnect, multisig_manager, smart_accounts, labels).await
+    }
+
+    /// Create a wallet manager that validates through a shared security manager
+    pub async fn with_security(
+        security: Arc<SecurityManager>,
+        walletconnect: walletconnect::WalletConnectConfig,
+        multisig_manager: multisig::MultiSigManager,
+        smart_accounts: smart_account::SmartAccountManager,
+        labels: labels::WalletLabels,
+    ) -> Result<Self> {
+        let walletconnect = match walletconnect.project_id {
+            Some(_) => Some(Arc::new(walletconnect::WalletConnectClient::new(walletconnect)?)),
+            None => None,
+        };
+        // Safes and smart accounts persisted by the last run are wallets again
+        let mut wallets: HashMap<Address, WalletProvider> = multisig_manager.wallets().await
+            .into_iter()
+            .map(|safe| (safe.address, WalletProvider::MultiSig(safe)))
+            .collect();
+        wallets.extend(smart_accounts.wallets().await
+            .into_iter()
+            .map(|account| (account.address, WalletProvider::SmartAccount(account))));
 
         info!("Initialized WalletManager");
 
         Ok(Self {
-            wallets: Arc::new(RwLock::new(HashMap::new())),
+            wallets: Arc::new(RwLock::new(wallets)),
+            disconnected: Arc::new(RwLock::new(HashMap::new())),
             security,
             multisig_manager,
+            smart_accounts,
+            labels,
+            walletconnect,
         })
     }
 
+    /// Connect WalletConnect to its relay, restoring saved sessions, and keep the wallets
+    /// registered in step with the sessions wallets settle and delete, until shutdown
+    pub fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> Option<JoinHandle<()>> {
+        let client = self.walletconnect.clone()?;
+        let mut events = client.subscribe();
+        Some(tokio::spawn(async move {
+            if let Err(e) = Arc::clone(&client).start().await {
+                warn!("Failed to start WalletConnect: {}", e);
+            }
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    _ = shutdown.cancelled() => break,
+                };
+                match event {
+                    Ok(walletconnect::SessionEvent::Settled(session)) => {
+                        for wallet in walletconnect::WalletConnectProvider::for_session(&client, &session) {
+                            let address = wallet.get_address();
+                            self.store_wallet(address, WalletProvider::WalletConnect(wallet)).await;
+                            info!("Connected WalletConnect wallet: {:?}", address);
+                        }
+                    }
+                    Ok(walletconnect::SessionEvent::Deleted(topic)) => {
+                        let addresses: Vec<Address> = self.wallets.read().await
+                            .iter()
+                            .filter(|(_, wallet)| {
+                                matches!(wallet, WalletProvider::WalletConnect(w) if w.get_session_id() == topic)
+                            })
+                            .map(|(address, _)| *address)
+                            .collect();
+                        for address in addresses {
+                            if let Err(e) = self.disconnect_wallet(address).await {
+                                warn!("Failed to disconnect WalletConnect wallet {:?}: {}", address, e);
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Missed {} WalletConnect session events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }))
+    }
+
+    /// Register a connected wallet, bringing it back from the archive if it was disconnected
+    async fn store_wallet(&self, address: Address, wallet: WalletProvider) {
+        self.wallets.write().await.insert(address, wallet);
+        self.disconnected.write().await.remove(&address);
+    }
+
+    /// Labels deciding which wallets automated, flash loan and high-risk flows may use
+    pub fn labels(&self) -> &labels::WalletLabels {
+        &self.labels
+    }
+
     pub async fn connect_metamask(&self, chain_id: u64) -> Result<Address> {
         let wallet = metamask::MetaMaskWallet::connect(chain_id).await?;
         let address = wallet.get_address();
         
-        let mut wallets = self.wallets.write().await;
-        wallets.insert(address, WalletProvider::MetaMask(wallet));
+        self.store_wallet(address, WalletProvider::MetaMask(wallet)).await;
         
         info!("Connected MetaMask wallet: {}", address);
         Ok(address)
     }
 
-    pub async fn connect_walletconnect(&self, project_id: &str) -> Result<Address> {
-        let wallet = walletconnect::WalletConnectProvider::connect(project_id).await?;
-        let address = wallet.get_address();
-        
-        let mut wallets = self.wallets.write().await;
-        wallets.insert(address, WalletProvider::WalletConnect(wallet));
-        
-        info!("Connected WalletConnect wallet: {}", address);
-        Ok(address)
+    /// Start a WalletConnect pairing, its wallets are connected once the user approves it
+    pub async fn pair_walletconnect(&self, chain_ids: &[u64]) -> Result<walletconnect::WalletConnectPairing> {
+        self.walletconnect_client()?.pair(chain_ids).await
+    }
+
+    pub async fn walletconnect_pairing(&self, topic: &str) -> Result<Option<walletconnect::WalletConnectPairing>> {
+        Ok(self.walletconnect_client()?.pairing(topic).await)
+    }
+
+    fn walletconnect_client(&self) -> Result<&Arc<walletconnect::WalletConnectClient>> {
+        self.walletconnect.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("WalletConnect is not configured, set walletconnect_project_id"))
     }
 
-    pub async fn connect_ledger(&self, _derivation_path: &str) -> Result<Address> {
-        let wallet = ledger::LedgerWallet::connect().await?;
-        let address = wallet.get_address().unwrap_or_default();
+    /// Connect the Ledger account at `derivation_path`, e.g. `m/44'/60'/0'/0/0`
+    pub async fn connect_ledger(&self, derivation_path: &str) -> Result<Address> {
+        let wallet = ledger::LedgerWallet::connect(derivation_path.parse()?).await?;
+        let address = wallet.get_address();
         
-        let mut wallets = self.wallets.write().await;
-        wallets.insert(address, WalletProvider::Ledger(wallet));
+        self.store_wallet(address, WalletProvider::Ledger(wallet)).await;
         
         info!("Connected Ledger wallet: {:?}", address);
         Ok(address)
     }
 
+    /// Addresses of the attached Ledger along a derivation scheme, to choose the account to connect
+    pub async fn discover_ledger_addresses(
+        &self,
+        scheme: ledger::DerivationScheme,
+        start_index: u32,
+        count: u32,
+    ) -> Result<Vec<ledger::LedgerAccount>> {
+        ledger::LedgerWallet::discover_addresses(scheme, start_index, count).await
+    }
+
     pub async fn create_local_wallet(&self, private_key: Option<String>) -> Result<Address> {
         let wallet = if let Some(pk) = private_key {
             pk.parse::<LocalWallet>()?
@@ -107,33 +250,191 @@ impl WalletManager {
 
         let address = wallet.address();
         
-        let mut wallets = self.wallets.write().await;
-        wallets.insert(address, WalletProvider::Local(wallet));
+        self.store_wallet(address, WalletProvider::Local(wallet)).await;
         
         info!("Created local wallet: {}", address);
         Ok(address)
     }
 
+    /// Deploy a Safe through the proxy factory, paid for by the local wallet `deployer`
     pub async fn create_multisig_wallet(
         &self,
         owners: Vec<Address>,
         threshold: u8,
         chain_id: u64,
-    ) -> Result<Address> {
-        let multisig_wallet = self.multisig_manager
-            .create_multisig_wallet(owners, threshold, chain_id)
+        deployer: Address,
+        salt_nonce: Option<U256>,
+        context: &PolicyContext,
+    ) -> Result<multisig::MultiSigWallet> {
+        // A fresh salt per deployment, so the same owners can deploy several Safes
+        let salt_nonce = salt_nonce.unwrap_or_else(|| U256::from(Utc::now().timestamp_millis()));
+        let deployment = self.multisig_manager
+            .prepare_deployment(owners, threshold, chain_id, salt_nonce)
             .await?;
-        
+        let signer = self.local_signer(deployer, &deployment.transaction, context).await?;
+        let multisig_wallet = self.multisig_manager.deploy(deployment, &signer).await?;
+
         let address = multisig_wallet.get_address();
-        
-        let mut wallets = self.wallets.write().await;
-        wallets.insert(address, WalletProvider::MultiSig(multisig_wallet));
-        
+        self.store_wallet(address, WalletProvider::MultiSig(multisig_wallet.clone())).await;
+
         info!("Created MultiSig wallet: {} with threshold {}", address, threshold);
-        Ok(address)
+        Ok(multisig_wallet)
+    }
+
+    /// Register a Safe deployed elsewhere, e.g. in the Safe app
+    pub async fn import_multisig_wallet(&self, chain_id: u64, address: Address) -> Result<multisig::MultiSigWallet> {
+        let multisig_wallet = self.multisig_manager.import_safe(chain_id, address).await?;
+        self.store_wallet(address, WalletProvider::MultiSig(multisig_wallet.clone())).await;
+        Ok(multisig_wallet)
+    }
+
+    /// Safe transactions, proposals and the signatures collected for them
+    pub fn multisig(&self) -> &multisig::MultiSigManager {
+        &self.multisig_manager
+    }
+
+    /// Queue a Safe transaction, signed right away when `proposer` is an owner connected here
+    pub async fn propose_safe_transaction(
+        &self,
+        safe: Address,
+        call: multisig::SafeCall,
+        proposer: Option<Address>,
+    ) -> Result<multisig::PendingTransaction> {
+        let pending = self.multisig_manager.propose_transaction(safe, call).await?;
+        match proposer {
+            Some(owner) => self.confirm_safe_transaction(pending.safe_tx_hash, owner).await,
+            None => Ok(pending),
+        }
+    }
+
+    /// Sign a Safe transaction as `owner`: local wallets sign the safeTxHash itself, other
+    /// wallets sign it as a message, which the Safe accepts as an `eth_sign` signature
+    pub async fn confirm_safe_transaction(&self, safe_tx_hash: H256, owner: Address) -> Result<multisig::PendingTransaction> {
+        let local = match self.wallets.read().await.get(&owner) {
+            Some(WalletProvider::Local(wallet)) => Some(wallet.clone()),
+            Some(WalletProvider::MultiSig(_) | WalletProvider::SmartAccount(_)) => {
+                return Err(anyhow::anyhow!("Contract wallet {} cannot sign for a Safe", owner));
+            }
+            Some(_) => None,
+            None => return Err(anyhow::anyhow!("Wallet not found: {}", owner)),
+        };
+
+        let signature = match local {
+            Some(wallet) => {
+                self.labels.require(owner, labels::WalletUse::Signing).await?;
+                wallet.sign_hash(safe_tx_hash)?.to_vec()
+            }
+            None => {
+                let mut signature = self.sign_message(owner, safe_tx_hash.as_bytes()).await?.to_vec();
+                // The Safe marks eth_sign signatures with v + 4
+                signature[64] = match signature[64] {
+                    v @ (0 | 1) => v + 31,
+                    v => v + 4,
+                };
+                signature
+            }
+        };
+        self.multisig_manager.add_signature(safe_tx_hash, signature.into()).await
+    }
+
+    /// Execute a Safe transaction that reached its threshold, paying gas from the local wallet `executor`
+    pub async fn execute_safe_transaction(
+        &self,
+        safe_tx_hash: H256,
+        executor: Address,
+        context: &PolicyContext,
+    ) -> Result<multisig::PendingTransaction> {
+        let (chain_id, tx) = self.multisig_manager.prepare_execution(safe_tx_hash).await?;
+        let signer = self.local_signer(executor, &tx, context).await?;
+        self.multisig_manager.execute(safe_tx_hash, chain_id, tx, &signer).await
+    }
+
+    /// Register the ERC-4337 smart account of `owner`, a connected wallet that signs its user operations
+    pub async fn create_smart_account(
+        &self,
+        owner: Address,
+        chain_id: u64,
+        salt: Option<U256>,
+    ) -> Result<smart_account::SmartAccountWallet> {
+        match self.wallets.read().await.get(&owner) {
+            Some(WalletProvider::Local(_) | WalletProvider::Ledger(_) | WalletProvider::WalletConnect(_)) => {}
+            Some(_) => return Err(anyhow::anyhow!("Wallet {} cannot own a smart account", owner)),
+            None => return Err(anyhow::anyhow!("Wallet not found: {}", owner)),
+        }
+
+        let account = self.smart_accounts.create_account(owner, chain_id, salt.unwrap_or_default()).await?;
+        self.store_wallet(account.address, WalletProvider::SmartAccount(account.clone())).await;
+        Ok(account)
+    }
+
+    /// Smart accounts and the user operations submitted from them
+    pub fn smart_accounts(&self) -> &smart_account::SmartAccountManager {
+        &self.smart_accounts
+    }
+
+    /// Make calls from a smart account through the bundler, gas paid by the paymaster when
+    /// `sponsor` is set; the owner signs the user operation
+    pub async fn execute_user_operation(
+        &self,
+        account: Address,
+        calls: Vec<smart_account::SmartAccountCall>,
+        sponsor: bool,
+        context: &PolicyContext,
+    ) -> Result<smart_account::SubmittedUserOperation> {
+        self.labels.require(account, labels::WalletUse::Signing).await?;
+        let smart_account = self.smart_accounts.get_account(account).await?;
+        let spending_policies = self.security.advanced.spending_policies();
+        let mut authorized = Vec::with_capacity(calls.len());
+        for call in &calls {
+            let tx: TypedTransaction = TransactionRequest::new()
+                .from(account)
+                .to(call.to)
+                .value(call.value)
+                .data(call.data.clone())
+                .chain_id(smart_account.chain_id)
+                .into();
+            if let Err(e) = self.security.authorize_typed_transaction(account, &tx, context).await {
+                for held in &authorized {
+                    spending_policies.release(account, held).await;
+                }
+                return Err(e);
+            }
+            authorized.push(tx);
+        }
+        let submitted = self.submit_user_operation(account, smart_account, &calls, sponsor).await;
+
+        // Spending held for the calls counts once the bundler accepted the operation
+        for tx in &authorized {
+            match &submitted {
+                Ok(_) => spending_policies.confirm(account, tx).await,
+                Err(_) => spending_policies.release(account, tx).await,
+            }
+        }
+        submitted
+    }
+
+    async fn submit_user_operation(
+        &self,
+        account: Address,
+        smart_account: smart_account::SmartAccountWallet,
+        calls: &[smart_account::SmartAccountCall],
+        sponsor: bool,
+    ) -> Result<smart_account::SubmittedUserOperation> {
+
+        let mut user_operation = self.smart_accounts.build_user_operation(account, calls, sponsor).await?;
+        let user_op_hash = user_operation.hash(smart_account::entry_point(), smart_account.chain_id);
+        // SimpleAccount recovers its owner from a personal signature of the hash
+        let signature = self.sign_message(smart_account.owner, user_op_hash.as_bytes()).await?;
+        if signature.recover(hash_message(user_op_hash))? != smart_account.owner {
+            return Err(anyhow::anyhow!("Signature of {} does not recover to the smart account owner", smart_account.owner));
+        }
+        user_operation.signature = signature.to_vec().into();
+
+        self.smart_accounts.submit(smart_account.chain_id, user_operation).await
     }
 
     pub async fn sign_message(&self, address: Address, message: &[u8]) -> Result<Signature> {
+        self.labels.require(address, labels::WalletUse::Signing).await?;
         let wallets = self.wallets.read().await;
         let wallet = wallets
             .get(&address)
@@ -148,28 +449,38 @@ impl WalletManager {
             WalletProvider::MetaMask(w) => w.sign_message(message).await,
             WalletProvider::WalletConnect(w) => w.sign_message(message).await,
             WalletProvider::Ledger(w) => w.sign_message(message).await,
-            WalletProvider::Local(_w) => {
-                // For demo purposes, return a mock signature
-                // In production, you'd properly sign the message hash
-                Ok(Signature {
-                    r: U256::from(1),
-                    s: U256::from(1),
-                    v: 27,
-                })
+            WalletProvider::Local(w) => Ok(w.sign_message(message).await?),
+            WalletProvider::MultiSig(_) => {
+                Err(anyhow::anyhow!("Safe {} signs through its owners, propose a transaction instead", address))
+            }
+            WalletProvider::SmartAccount(_) => {
+                Err(anyhow::anyhow!("Smart account {} signs through its owner, submit a user operation instead", address))
             }
-            WalletProvider::MultiSig(w) => w.sign_message(message).await,
         }
     }
 
-    pub async fn sign_transaction(&self, address: Address, tx: TypedTransaction) -> Result<Signature> {
+    /// Sign a transaction after security validation, counting it against the spending policies
+    pub async fn sign_transaction(&self, address: Address, tx: TypedTransaction, context: &PolicyContext) -> Result<Signature> {
+        self.labels.require(address, labels::WalletUse::Signing).await?;
         let wallets = self.wallets.read().await;
         let wallet = wallets
             .get(&address)
             .ok_or_else(|| anyhow::anyhow!("Wallet not found: {}", address))?;
 
-        // Security validation
-        self.security.validate_typed_transaction(&tx).await?;
+        // Security validation and spending policies
+        self.security.authorize_typed_transaction(address, &tx, context).await?;
 
+        // The signature leaves the server, so its spending counts once signed
+        let spending_policies = self.security.advanced.spending_policies();
+        let signed = self.sign_with(address, wallet, tx.clone()).await;
+        match &signed {
+            Ok(_) => spending_policies.confirm(address, &tx).await,
+            Err(_) => spending_policies.release(address, &tx).await,
+        }
+        signed
+    }
+
+    async fn sign_with(&self, address: Address, wallet: &WalletProvider, tx: TypedTransaction) -> Result<Signature> {
         match wallet {
             WalletProvider::MetaMask(w) => w.sign_transaction(tx).await,
             WalletProvider::WalletConnect(w) => w.sign_transaction(tx).await,
@@ -183,43 +494,130 @@ impl WalletManager {
                     v: 27,
                 })
             }
-            WalletProvider::MultiSig(_w) => {
-                // MultiSig transactions require multiple signatures
-                // Return a mock signature for demo
-                Ok(Signature {
-                    r: U256::from(1),
-                    s: U256::from(1),
-                    v: 27,
-                })
+            WalletProvider::MultiSig(_) => {
+                Err(anyhow::anyhow!("Safe {} signs through its owners, propose a transaction instead", address))
+            }
+            WalletProvider::SmartAccount(_) => {
+                Err(anyhow::anyhow!("Smart account {} signs through its owner, submit a user operation instead", address))
             }
         }
     }
 
-    pub async fn get_wallet_info(&self, address: Address) -> Result<WalletInfo> {
+    /// Sign EIP-712 typed data, such as a permit, Safe transaction or off-chain order, with a connected wallet
+    ///
+    /// The signature is checked to recover to the wallet over the digest the verifying contract
+    /// computes, so a wallet hashing the request differently is caught before the signature is used.
+    pub async fn sign_typed_data(&self, address: Address, typed_data: &TypedData) -> Result<Signature> {
+        self.labels.require(address, labels::WalletUse::Signing).await?;
+        let digest = eip712::digest(typed_data)?;
         let wallets = self.wallets.read().await;
         let wallet = wallets
             .get(&address)
             .ok_or_else(|| anyhow::anyhow!("Wallet not found: {}", address))?;
 
+        let signature = match wallet {
+            WalletProvider::Local(w) => w.sign_hash(digest)?,
+            WalletProvider::WalletConnect(w) => w.sign_typed_data(typed_data).await?,
+            WalletProvider::Ledger(w) => w.sign_typed_data(typed_data).await?,
+            WalletProvider::MetaMask(_) | WalletProvider::MultiSig(_) | WalletProvider::SmartAccount(_) => {
+                return Err(anyhow::anyhow!("Wallet {} cannot sign typed data on the server", address));
+            }
+        };
+
+        if signature.recover(digest)? != address {
+            return Err(anyhow::anyhow!("Typed data signature of {} does not recover to the wallet", address));
+        }
+        Ok(signature)
+    }
+
+    /// Info of a connected wallet, or the archived record of a disconnected one
+    pub async fn get_wallet_info(&self, address: Address) -> Result<WalletInfo> {
+        let wallets = self.wallets.read().await;
+        let Some(wallet) = wallets.get(&address) else {
+            return self.disconnected.read().await
+                .get(&address)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Wallet not found: {}", address));
+        };
+
         let wallet_type = match wallet {
             WalletProvider::MetaMask(_) => WalletType::MetaMask,
             WalletProvider::WalletConnect(_) => WalletType::WalletConnect,
             WalletProvider::Ledger(_) => WalletType::Ledger,
             WalletProvider::Local(_) => WalletType::LocalWallet,
             WalletProvider::MultiSig(_) => WalletType::MultiSig,
+            WalletProvider::SmartAccount(_) => WalletType::SmartAccount,
         };
 
-        // In production, would fetch actual balance and chain info
+        // WalletConnect wallets report their session's chain and are connected while the session is live
+        // and the wallet answers a ping, Safes and smart accounts report the chain they live on, the
+        // others are assumed live on mainnet
+        let (chain_id, is_connected) = match wallet {
+            WalletProvider::WalletConnect(w) => {
+                let answers = w.is_connected().await && w.ping_session().await.unwrap_or(false);
+                (w.get_chain_id(), answers)
+            }
+            WalletProvider::MultiSig(w) => (w.chain_id, true),
+            WalletProvider::SmartAccount(w) => (w.chain_id, true),
+            _ => (1, true),
+        };
+
+        // In production, would fetch actual balance
         Ok(WalletInfo {
             address,
             wallet_type,
-            chain_id: 1, // Default to mainnet, should be fetched from wallet
-            is_connected: true,
+            chain_id,
+            is_connected,
             balance: None, // Would be fetched from chain
+            disconnected_at: None,
         })
     }
 
+    /// Chain a wallet signs on and the chains it can switch to, `None` for wallets signing on any chain
+    pub async fn wallet_chains(&self, address: Address) -> Result<(u64, Option<Vec<u64>>)> {
+        let wallets = self.wallets.read().await;
+        match wallets.get(&address) {
+            Some(WalletProvider::WalletConnect(w)) => Ok((w.get_chain_id(), Some(w.get_supported_chains().await?))),
+            Some(WalletProvider::MetaMask(w)) => Ok((w.get_chain_id(), None)),
+            Some(WalletProvider::MultiSig(w)) => Ok((w.chain_id, Some(vec![w.chain_id]))),
+            Some(WalletProvider::SmartAccount(w)) => Ok((w.chain_id, Some(vec![w.chain_id]))),
+            Some(WalletProvider::Ledger(_) | WalletProvider::Local(_)) => Ok((1, None)),
+            None => Err(anyhow::anyhow!("Wallet not found: {}", address)),
+        }
+    }
+
+    /// Have a WalletConnect or MetaMask wallet sign on another chain; a WalletConnect wallet only
+    /// switches to chains its session approved
+    pub async fn switch_wallet_chain(&self, address: Address, chain_id: u64) -> Result<()> {
+        let mut wallets = self.wallets.write().await;
+        match wallets.get_mut(&address) {
+            Some(WalletProvider::WalletConnect(w)) => w.switch_chain(chain_id).await,
+            Some(WalletProvider::MetaMask(w)) => w.switch_chain(chain_id).await,
+            Some(_) => Err(anyhow::anyhow!("Wallet {} does not switch chains", address)),
+            None => Err(anyhow::anyhow!("Wallet not found: {}", address)),
+        }
+    }
+
+    /// Signer of a local wallet after security validation of the transaction it will send, whose
+    /// spending is held until the broadcaster sends it
+    pub async fn local_signer(&self, address: Address, tx: &TypedTransaction, context: &PolicyContext) -> Result<LocalWallet> {
+        self.labels.require(address, labels::WalletUse::Signing).await?;
+        let wallets = self.wallets.read().await;
+        let wallet = match wallets.get(&address) {
+            Some(WalletProvider::Local(wallet)) => wallet.clone(),
+            Some(_) => return Err(anyhow::anyhow!("Wallet {} cannot sign on the server", address)),
+            None => return Err(anyhow::anyhow!("Wallet not found: {}", address)),
+        };
+
+        self.security.authorize_typed_transaction(address, tx, context).await?;
+        Ok(wallet)
+    }
+
+    /// Disconnect a wallet, archiving its info so it stays queryable
     pub async fn disconnect_wallet(&self, address: Address) -> Result<()> {
+        let Ok(info) = self.get_wallet_info(address).await else {
+            return Ok(());
+        };
         let mut wallets = self.wallets.write().await;
         
         if let Some(wallet) = wallets.remove(&address) {
@@ -229,22 +627,34 @@ impl WalletManager {
                 WalletProvider::Ledger(mut w) => w.disconnect().await?,
                 WalletProvider::Local(_) => {} // Nothing to disconnect
                 WalletProvider::MultiSig(_) => {} // Nothing to disconnect
+                WalletProvider::SmartAccount(_) => {} // Nothing to disconnect
             }
             info!("Disconnected wallet: {}", address);
+            self.disconnected.write().await.insert(address, WalletInfo {
+                is_connected: false,
+                disconnected_at: Some(Utc::now()),
+                ..info
+            });
         }
 
         Ok(())
     }
 
-    pub async fn list_wallets(&self) -> Vec<WalletInfo> {
-        let wallets = self.wallets.read().await;
+    /// Connected wallets, disconnected ones as well or instead depending on `filter`
+    pub async fn list_wallets(&self, filter: ArchiveFilter) -> Vec<WalletInfo> {
         let mut wallet_infos = Vec::new();
 
-        for (address, _wallet) in wallets.iter() {
-            if let Ok(info) = self.get_wallet_info(*address).await {
-                wallet_infos.push(info);
+        if filter.includes(false) {
+            let addresses: Vec<Address> = self.wallets.read().await.keys().copied().collect();
+            for address in addresses {
+                if let Ok(info) = self.get_wallet_info(address).await {
+                    wallet_infos.push(info);
+                }
             }
         }
+        if filter.includes(true) {
+            wallet_infos.extend(self.disconnected.read().await.values().cloned());
+        }
 
         wallet_infos
     }
@@ -253,6 +663,7 @@ impl WalletManager {
         &self,
         address: Address,
         transactions: Vec<TypedTransaction>,
+        context: &PolicyContext,
     ) -> Result<Vec<Signature>> {
         let mut signatures = Vec::new();
 
@@ -263,7 +674,7 @@ impl WalletManager {
 
         // Sign all transactions
         for tx in transactions {
-            let signature = self.sign_transaction(address, tx).await?;
+            let signature = self.sign_transaction(address, tx, context).await?;
             signatures.push(signature);
         }
 
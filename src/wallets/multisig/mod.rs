@@ -0,0 +1,698 @@
+// Safe (formerly Gnosis Safe) multisig wallets: deployment, proposals, owner signatures and execution
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    signers::LocalWallet,
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Bytes, Signature, TransactionRequest, H256, U256,
+    },
+    utils::{get_create2_address_from_hash, hash_message, id, keccak256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, instrument, warn};
+
+use crate::chains::{tx_broadcaster::TxBroadcaster, ChainManager};
+use crate::transactions::{read_store, write_store};
+
+pub mod service;
+
+use service::{ServiceTransaction, TransactionService};
+
+// Safe v1.4.1 deployments, at the same addresses on every chain
+const PROXY_FACTORY: &str = "0x4e1DCf7AD4e460CfD30791CCC4F9c8a4f820ec67";
+const SAFE_SINGLETON: &str = "0x41675C099F32341bf84BFc5382aF534df5C7461a";
+/// Singleton emitting an event for every execution, which indexers on L2s rely on
+const SAFE_L2_SINGLETON: &str = "0x29fcB43b46531BcA003ddC8FCB67FFE91900C762";
+const FALLBACK_HANDLER: &str = "0xfd0732Dc9E303f09fCEf3a7388Ad10A83459Ec99";
+
+/// Store used when `safes_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/safes.json";
+const DOMAIN_SEPARATOR_TYPE: &str = "EIP712Domain(uint256 chainId,address verifyingContract)";
+const SAFE_TX_TYPE: &str = "SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,\
+uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,uint256 nonce)";
+
+fn address(value: &str) -> Address {
+    value.parse().expect("valid Safe deployment address")
+}
+
+/// Safe singleton for a chain, the event-emitting L2 one everywhere but Ethereum mainnet
+fn singleton(chain_id: u64) -> Address {
+    match chain_id {
+        1 => address(SAFE_SINGLETON),
+        _ => address(SAFE_L2_SINGLETON),
+    }
+}
+
+/// Persistence and Transaction Service settings of the Safe integration
+#[derive(Debug, Clone)]
+pub struct SafeConfig {
+    /// JSON file holding known Safes and their pending transactions, `None` keeps them in memory only
+    pub store_path: Option<PathBuf>,
+    /// Share proposals and confirmations through the Safe Transaction Service
+    pub use_transaction_service: bool,
+    pub transaction_service_api_key: Option<String>,
+}
+
+impl Default for SafeConfig {
+    fn default() -> Self {
+        Self {
+            store_path: None,
+            use_transaction_service: true,
+            transaction_service_api_key: None,
+        }
+    }
+}
+
+impl SafeConfig {
+    /// Settings from `safes_store_path`, `safe_transaction_service_enabled` and
+    /// `safe_transaction_service_api_key`
+    pub fn from_config(config: &config::Config) -> Self {
+        let path = config
+            .get_string("safes_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        Self {
+            store_path: (!path.is_empty()).then(|| PathBuf::from(path)),
+            use_transaction_service: config.get_bool("safe_transaction_service_enabled").unwrap_or(true),
+            transaction_service_api_key: config
+                .get_string("safe_transaction_service_api_key")
+                .ok()
+                .filter(|key| !key.is_empty()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    #[default]
+    Call = 0,
+    DelegateCall = 1,
+}
+
+impl TryFrom<u8> for Operation {
+    type Error = anyhow::Error;
+
+    fn try_from(operation: u8) -> Result<Self> {
+        match operation {
+            0 => Ok(Self::Call),
+            1 => Ok(Self::DelegateCall),
+            _ => Err(anyhow!("Invalid Safe operation {}", operation)),
+        }
+    }
+}
+
+/// Call a Safe is asked to make
+#[derive(Debug, Clone, Deserialize)]
+pub struct SafeCall {
+    pub to: Address,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub value: U256,
+    #[serde(default)]
+    pub data: Bytes,
+    #[serde(default)]
+    pub operation: Operation,
+}
+
+/// Parameters of `execTransaction`, refunds left unused
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafeTransaction {
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+    pub operation: Operation,
+    pub safe_tx_gas: U256,
+    pub base_gas: U256,
+    pub gas_price: U256,
+    pub gas_token: Address,
+    pub refund_receiver: Address,
+    pub nonce: U256,
+}
+
+impl SafeTransaction {
+    fn new(call: SafeCall, nonce: U256) -> Self {
+        Self {
+            to: call.to,
+            value: call.value,
+            data: call.data,
+            operation: call.operation,
+            safe_tx_gas: U256::zero(),
+            base_gas: U256::zero(),
+            gas_price: U256::zero(),
+            gas_token: Address::zero(),
+            refund_receiver: Address::zero(),
+            nonce,
+        }
+    }
+
+    /// EIP-712 hash the owners sign, binding the transaction to one Safe on one chain
+    pub fn safe_tx_hash(&self, chain_id: u64, safe: Address) -> H256 {
+        let domain_separator = keccak256(abi::encode(&[
+            Token::FixedBytes(keccak256(DOMAIN_SEPARATOR_TYPE).to_vec()),
+            Token::Uint(U256::from(chain_id)),
+            Token::Address(safe),
+        ]));
+        let struct_hash = keccak256(abi::encode(&[
+            Token::FixedBytes(keccak256(SAFE_TX_TYPE).to_vec()),
+            Token::Address(self.to),
+            Token::Uint(self.value),
+            Token::FixedBytes(keccak256(&self.data).to_vec()),
+            Token::Uint(U256::from(self.operation as u8)),
+            Token::Uint(self.safe_tx_gas),
+            Token::Uint(self.base_gas),
+            Token::Uint(self.gas_price),
+            Token::Address(self.gas_token),
+            Token::Address(self.refund_receiver),
+            Token::Uint(self.nonce),
+        ]));
+
+        let mut digest = vec![0x19, 0x01];
+        digest.extend_from_slice(&domain_separator);
+        digest.extend_from_slice(&struct_hash);
+        H256::from(keccak256(digest))
+    }
+}
+
+/// Safe with the owners and threshold it was deployed or last imported with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiSigWallet {
+    pub address: Address,
+    pub owners: Vec<Address>,
+    pub threshold: u8,
+    pub chain_id: u64,
+    /// Factory call that deployed the Safe, `None` for Safes deployed elsewhere
+    pub deployment_tx: Option<H256>,
+}
+
+impl MultiSigWallet {
+    pub fn get_address(&self) -> Address {
+        self.address
+    }
+
+    pub fn is_owner(&self, address: Address) -> bool {
+        self.owners.contains(&address)
+    }
+}
+
+/// Safe transaction collecting owner signatures until it can be executed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub safe_tx_hash: H256,
+    pub safe: Address,
+    pub chain_id: u64,
+    pub transaction: SafeTransaction,
+    /// Owner signatures in the Safe's encoding, 65 bytes each
+    pub signatures: BTreeMap<Address, Bytes>,
+    pub confirmations_required: u8,
+    pub created_at: DateTime<Utc>,
+    /// `execTransaction` call once the transaction was executed
+    pub executed_tx: Option<H256>,
+    /// Whether the Transaction Service has the proposal
+    #[serde(default)]
+    pub shared: bool,
+}
+
+impl PendingTransaction {
+    pub fn is_ready(&self) -> bool {
+        self.executed_tx.is_none() && self.signatures.len() >= self.confirmations_required as usize
+    }
+
+    /// Signatures concatenated by ascending owner, the order `checkSignatures` requires
+    fn encoded_signatures(&self) -> Vec<u8> {
+        self.signatures.values().flat_map(|signature| signature.to_vec()).collect()
+    }
+}
+
+/// Safe deployment, its address known before the factory call is mined
+pub struct SafeDeployment {
+    pub wallet: MultiSigWallet,
+    pub transaction: TypedTransaction,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SafeStore {
+    safes: Vec<MultiSigWallet>,
+    transactions: Vec<PendingTransaction>,
+}
+
+pub struct MultiSigManager {
+    chain_manager: Arc<ChainManager>,
+    broadcaster: Arc<TxBroadcaster>,
+    /// Transaction Service client, `None` when sharing through it is disabled
+    service: Option<TransactionService>,
+    store_path: Option<PathBuf>,
+    multisig_wallets: RwLock<HashMap<Address, MultiSigWallet>>,
+    transactions: RwLock<HashMap<H256, PendingTransaction>>,
+}
+
+impl MultiSigManager {
+    /// Manager restoring the Safes and pending transactions persisted by the last run
+    pub async fn new(chain_manager: Arc<ChainManager>, broadcaster: Arc<TxBroadcaster>, config: SafeConfig) -> Result<Self> {
+        let service = match config.use_transaction_service {
+            true => Some(TransactionService::new(config.transaction_service_api_key)?),
+            false => None,
+        };
+        let store: SafeStore = match &config.store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => SafeStore::default(),
+        };
+        if !store.safes.is_empty() {
+            info!("Restored {} Safes with {} pending transactions", store.safes.len(), store.transactions.len());
+        }
+
+        Ok(Self {
+            chain_manager,
+            broadcaster,
+            service,
+            store_path: config.store_path,
+            multisig_wallets: RwLock::new(store.safes.into_iter().map(|safe| (safe.address, safe)).collect()),
+            transactions: RwLock::new(
+                store.transactions.into_iter().map(|tx| (tx.safe_tx_hash, tx)).collect(),
+            ),
+        })
+    }
+
+    /// Safes known to the manager, to register them as wallets on start
+    pub async fn wallets(&self) -> Vec<MultiSigWallet> {
+        self.multisig_wallets.read().await.values().cloned().collect()
+    }
+
+    /// Factory call deploying a Safe with `owners`, at an address that only depends on the
+    /// owners, threshold and `salt_nonce`
+    pub async fn prepare_deployment(
+        &self,
+        owners: Vec<Address>,
+        threshold: u8,
+        chain_id: u64,
+        salt_nonce: U256,
+    ) -> Result<SafeDeployment> {
+        if threshold == 0 || threshold as usize > owners.len() {
+            return Err(anyhow!("Invalid threshold: {} for {} owners", threshold, owners.len()));
+        }
+        let mut sorted = owners.clone();
+        sorted.sort();
+        sorted.dedup();
+        if sorted.len() != owners.len() || sorted.contains(&Address::zero()) {
+            return Err(anyhow!("Safe owners must be distinct, non-zero addresses"));
+        }
+
+        let mut initializer = id("setup(address[],uint256,address,bytes,address,address,uint256,address)").to_vec();
+        initializer.extend(abi::encode(&[
+            Token::Array(owners.iter().map(|owner| Token::Address(*owner)).collect()),
+            Token::Uint(U256::from(threshold)),
+            Token::Address(Address::zero()),
+            Token::Bytes(Vec::new()),
+            Token::Address(address(FALLBACK_HANDLER)),
+            Token::Address(Address::zero()),
+            Token::Uint(U256::zero()),
+            Token::Address(Address::zero()),
+        ]));
+
+        // CREATE2 address of the proxy, salted with the initializer as the factory does
+        let factory = address(PROXY_FACTORY);
+        let creation_code = match self.call(chain_id, factory, "proxyCreationCode()", &[ParamType::Bytes]).await?.pop() {
+            Some(Token::Bytes(code)) => code,
+            _ => return Err(anyhow!("Safe proxy factory returned no creation code on chain {}", chain_id)),
+        };
+        let mut salt = keccak256(&initializer).to_vec();
+        salt.extend(abi::encode(&[Token::Uint(salt_nonce)]));
+        let mut init_code = creation_code;
+        init_code.extend(abi::encode(&[Token::Address(singleton(chain_id))]));
+        let safe = get_create2_address_from_hash(factory, keccak256(salt), keccak256(init_code));
+
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        if !chain.provider.get_code(safe, None).await?.is_empty() {
+            return Err(anyhow!("Safe {:?} is already deployed, import it or use another salt nonce", safe));
+        }
+
+        let mut data = id("createProxyWithNonce(address,bytes,uint256)").to_vec();
+        data.extend(abi::encode(&[
+            Token::Address(singleton(chain_id)),
+            Token::Bytes(initializer),
+            Token::Uint(salt_nonce),
+        ]));
+
+        Ok(SafeDeployment {
+            wallet: MultiSigWallet {
+                address: safe,
+                owners,
+                threshold,
+                chain_id,
+                deployment_tx: None,
+            },
+            transaction: TransactionRequest::new().to(factory).data(data).chain_id(chain_id).into(),
+        })
+    }
+
+    /// Broadcast a prepared deployment and register the Safe
+    #[instrument(skip_all, fields(chain_id = deployment.wallet.chain_id, wallet = ?deployment.wallet.address))]
+    pub async fn deploy(&self, deployment: SafeDeployment, signer: &LocalWallet) -> Result<MultiSigWallet> {
+        let SafeDeployment { mut wallet, transaction } = deployment;
+        let tracked = self.broadcaster.send_with_signer(wallet.chain_id, transaction, signer).await?;
+        wallet.deployment_tx = Some(tracked.hash);
+
+        info!(
+            "Deploying Safe {:?} with {} owners and threshold {} in {:?}",
+            wallet.address,
+            wallet.owners.len(),
+            wallet.threshold,
+            tracked.hash,
+        );
+        self.multisig_wallets.write().await.insert(wallet.address, wallet.clone());
+        self.persist().await;
+        Ok(wallet)
+    }
+
+    /// Register a Safe deployed elsewhere, reading its owners and threshold from the chain
+    pub async fn import_safe(&self, chain_id: u64, safe: Address) -> Result<MultiSigWallet> {
+        let (owners, threshold) = self.read_owners(chain_id, safe).await?;
+        let wallet = MultiSigWallet {
+            address: safe,
+            owners,
+            threshold,
+            chain_id,
+            deployment_tx: self.multisig_wallets.read().await.get(&safe).and_then(|known| known.deployment_tx),
+        };
+
+        info!("Imported Safe {:?} with {} owners and threshold {}", safe, wallet.owners.len(), threshold);
+        self.multisig_wallets.write().await.insert(safe, wallet.clone());
+        self.persist().await;
+        Ok(wallet)
+    }
+
+    pub async fn get_wallet(&self, address: Address) -> Result<MultiSigWallet> {
+        let wallets = self.multisig_wallets.read().await;
+        wallets
+            .get(&address)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("MultiSig wallet not found: {}", address))
+    }
+
+    /// Queue a call at the Safe's next free nonce, for owners to sign
+    #[instrument(skip_all, fields(wallet = ?safe))]
+    pub async fn propose_transaction(&self, safe: Address, call: SafeCall) -> Result<PendingTransaction> {
+        let wallet = self.get_wallet(safe).await?;
+        let on_chain_nonce = self.read_nonce(wallet.chain_id, safe).await?;
+
+        let mut transactions = self.transactions.write().await;
+        // Queue after the transactions already waiting for signatures
+        let nonce = transactions.values()
+            .filter(|pending| pending.safe == safe && pending.executed_tx.is_none())
+            .map(|pending| pending.transaction.nonce + 1)
+            .fold(on_chain_nonce, U256::max);
+        let transaction = SafeTransaction::new(call, nonce);
+        let safe_tx_hash = transaction.safe_tx_hash(wallet.chain_id, safe);
+
+        let pending = PendingTransaction {
+            safe_tx_hash,
+            safe,
+            chain_id: wallet.chain_id,
+            transaction,
+            signatures: BTreeMap::new(),
+            confirmations_required: wallet.threshold,
+            created_at: Utc::now(),
+            executed_tx: None,
+            shared: false,
+        };
+        transactions.insert(safe_tx_hash, pending.clone());
+        drop(transactions);
+
+        info!("Proposed Safe transaction {:?} with nonce {} for {:?}", safe_tx_hash, nonce, safe);
+        self.persist().await;
+        Ok(pending)
+    }
+
+    pub async fn get_transaction(&self, safe_tx_hash: H256) -> Result<PendingTransaction> {
+        self.transactions.read().await
+            .get(&safe_tx_hash)
+            .cloned()
+            .ok_or_else(|| anyhow!("Safe transaction not found: {:?}", safe_tx_hash))
+    }
+
+    /// Add an owner's signature over the safeTxHash, as a raw ECDSA signature (v 27/28) or an
+    /// `eth_sign` one (v 31/32), and share it through the Transaction Service
+    pub async fn add_signature(&self, safe_tx_hash: H256, signature: Bytes) -> Result<PendingTransaction> {
+        let pending = self.get_transaction(safe_tx_hash).await?;
+        if pending.executed_tx.is_some() {
+            return Err(anyhow!("Safe transaction {:?} was already executed", safe_tx_hash));
+        }
+        let owner = recover_owner(safe_tx_hash, &signature)?;
+        let wallet = self.get_wallet(pending.safe).await?;
+        if !wallet.is_owner(owner) {
+            return Err(anyhow!("Signer {:?} is not an owner of Safe {:?}", owner, pending.safe));
+        }
+
+        let pending = {
+            let mut transactions = self.transactions.write().await;
+            let pending = transactions.get_mut(&safe_tx_hash)
+                .ok_or_else(|| anyhow!("Safe transaction not found: {:?}", safe_tx_hash))?;
+            pending.signatures.insert(owner, signature.clone());
+            pending.clone()
+        };
+        info!(
+            "Signed Safe transaction {:?} by owner {:?} ({}/{})",
+            safe_tx_hash,
+            owner,
+            pending.signatures.len(),
+            pending.confirmations_required,
+        );
+
+        let pending = self.share(pending, owner, &signature).await;
+        self.persist().await;
+        Ok(pending)
+    }
+
+    /// Propose the transaction to the Transaction Service with its first signature, confirm it with
+    /// the others; the signature stays collected here when the service is unreachable
+    async fn share(&self, pending: PendingTransaction, owner: Address, signature: &Bytes) -> PendingTransaction {
+        let Some(service) = self.service.as_ref().filter(|service| service.supports(pending.chain_id)) else {
+            return pending;
+        };
+        let result = match pending.shared {
+            true => service.confirm(pending.chain_id, pending.safe_tx_hash, signature).await,
+            false => service.propose(
+                pending.chain_id, pending.safe, &pending.transaction, pending.safe_tx_hash, owner, signature,
+            ).await,
+        };
+        if let Err(e) = result {
+            warn!("Failed to share Safe transaction {:?} with the Transaction Service: {}", pending.safe_tx_hash, e);
+            return pending;
+        }
+
+        let mut transactions = self.transactions.write().await;
+        match transactions.get_mut(&pending.safe_tx_hash) {
+            Some(stored) => {
+                stored.shared = true;
+                stored.clone()
+            }
+            None => pending,
+        }
+    }
+
+    /// Transactions of a Safe still waiting for execution, merged with the proposals and
+    /// confirmations other owners made through the Transaction Service
+    pub async fn pending_transactions(&self, safe: Address) -> Result<Vec<PendingTransaction>> {
+        let wallet = self.get_wallet(safe).await?;
+        let nonce = self.read_nonce(wallet.chain_id, safe).await?;
+
+        let queued = match self.service.as_ref().filter(|service| service.supports(wallet.chain_id)) {
+            Some(service) => service.queued(wallet.chain_id, safe, nonce).await.unwrap_or_else(|e| {
+                warn!("Failed to sync Safe {:?} with the Transaction Service: {}", safe, e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+
+        let mut transactions = self.transactions.write().await;
+        // Transactions below the Safe's nonce were executed or replaced and can never execute
+        transactions.retain(|_, pending| {
+            pending.safe != safe || pending.executed_tx.is_some() || pending.transaction.nonce >= nonce
+        });
+        for queued in queued {
+            if let Err(e) = merge(&mut transactions, &wallet, queued) {
+                warn!("Skipping Safe transaction from the Transaction Service: {}", e);
+            }
+        }
+
+        let mut pending: Vec<PendingTransaction> = transactions.values()
+            .filter(|pending| pending.safe == safe && pending.executed_tx.is_none())
+            .cloned()
+            .collect();
+        drop(transactions);
+        pending.sort_by_key(|pending| (pending.transaction.nonce, pending.created_at));
+
+        self.persist().await;
+        Ok(pending)
+    }
+
+    /// `execTransaction` call carrying the owner signatures, once the threshold is reached
+    pub async fn prepare_execution(&self, safe_tx_hash: H256) -> Result<(u64, TypedTransaction)> {
+        let pending = self.get_transaction(safe_tx_hash).await?;
+        if pending.executed_tx.is_some() {
+            return Err(anyhow!("Safe transaction {:?} was already executed", safe_tx_hash));
+        }
+        if !pending.is_ready() {
+            return Err(anyhow!(
+                "Not enough signatures: {}/{}",
+                pending.signatures.len(),
+                pending.confirmations_required,
+            ));
+        }
+
+        let tx = &pending.transaction;
+        let mut data = id(
+            "execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)",
+        ).to_vec();
+        data.extend(abi::encode(&[
+            Token::Address(tx.to),
+            Token::Uint(tx.value),
+            Token::Bytes(tx.data.to_vec()),
+            Token::Uint(U256::from(tx.operation as u8)),
+            Token::Uint(tx.safe_tx_gas),
+            Token::Uint(tx.base_gas),
+            Token::Uint(tx.gas_price),
+            Token::Address(tx.gas_token),
+            Token::Address(tx.refund_receiver),
+            Token::Bytes(pending.encoded_signatures()),
+        ]));
+
+        Ok((
+            pending.chain_id,
+            TransactionRequest::new().to(pending.safe).data(data).chain_id(pending.chain_id).into(),
+        ))
+    }
+
+    /// Broadcast a prepared execution; any account can execute, the owners' signatures authorize it
+    #[instrument(skip_all, fields(chain_id = chain_id))]
+    pub async fn execute(
+        &self,
+        safe_tx_hash: H256,
+        chain_id: u64,
+        transaction: TypedTransaction,
+        signer: &LocalWallet,
+    ) -> Result<PendingTransaction> {
+        let tracked = self.broadcaster.send_with_signer(chain_id, transaction, signer).await?;
+
+        let pending = {
+            let mut transactions = self.transactions.write().await;
+            let pending = transactions.get_mut(&safe_tx_hash)
+                .ok_or_else(|| anyhow!("Safe transaction not found: {:?}", safe_tx_hash))?;
+            pending.executed_tx = Some(tracked.hash);
+            pending.clone()
+        };
+
+        info!("Executed Safe transaction {:?} of {:?} in {:?}", safe_tx_hash, pending.safe, tracked.hash);
+        self.persist().await;
+        Ok(pending)
+    }
+
+    async fn read_owners(&self, chain_id: u64, safe: Address) -> Result<(Vec<Address>, u8)> {
+        let owners = match self.call(chain_id, safe, "getOwners()", &[ParamType::Array(Box::new(ParamType::Address))])
+            .await?
+            .pop()
+        {
+            Some(Token::Array(owners)) => owners.into_iter().filter_map(Token::into_address).collect(),
+            _ => return Err(anyhow!("{:?} is not a Safe", safe)),
+        };
+        let threshold = self.read_uint(chain_id, safe, "getThreshold()").await?;
+        Ok((owners, u8::try_from(threshold.low_u64()).unwrap_or(u8::MAX)))
+    }
+
+    async fn read_nonce(&self, chain_id: u64, safe: Address) -> Result<U256> {
+        self.read_uint(chain_id, safe, "nonce()").await
+    }
+
+    async fn read_uint(&self, chain_id: u64, contract: Address, function: &str) -> Result<U256> {
+        match self.call(chain_id, contract, function, &[ParamType::Uint(256)]).await?.pop() {
+            Some(Token::Uint(value)) => Ok(value),
+            _ => Err(anyhow!("Invalid {} response from {:?}", function, contract)),
+        }
+    }
+
+    /// Call a parameterless view function
+    async fn call(&self, chain_id: u64, contract: Address, function: &str, outputs: &[ParamType]) -> Result<Vec<Token>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let tx: TypedTransaction = TransactionRequest::new().to(contract).data(id(function).to_vec()).into();
+        let output = chain.provider.call(&tx, None).await?;
+        abi::decode(outputs, &output).map_err(|e| anyhow!("Invalid {} response from {:?}: {}", function, contract, e))
+    }
+
+    /// Save Safes and transactions, failures are logged since they stay usable in memory
+    async fn persist(&self) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let store = SafeStore {
+            safes: self.multisig_wallets.read().await.values().cloned().collect(),
+            transactions: self.transactions.read().await.values().cloned().collect(),
+        };
+        if let Err(e) = write_store(path, &store).await {
+            warn!("Failed to persist Safes to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Owner behind a signature over `safe_tx_hash`, as `checkSignatures` recovers it
+fn recover_owner(safe_tx_hash: H256, signature: &Bytes) -> Result<Address> {
+    let (digest, v) = match signature.get(64) {
+        Some(v @ (27 | 28)) => (safe_tx_hash, *v),
+        Some(v @ (31 | 32)) => (hash_message(safe_tx_hash), v - 4),
+        Some(_) => return Err(anyhow!("Only ECDSA and eth_sign owner signatures are supported")),
+        None => return Err(anyhow!("Owner signatures are 65 bytes")),
+    };
+    if signature.len() != 65 {
+        return Err(anyhow!("Owner signatures are 65 bytes"));
+    }
+    let signature = Signature {
+        r: U256::from_big_endian(&signature[..32]),
+        s: U256::from_big_endian(&signature[32..64]),
+        v: v as u64,
+    };
+    Ok(signature.recover(digest)?)
+}
+
+/// Fold a transaction listed by the Transaction Service into the local ones, keeping the
+/// confirmations that verify against its hash
+fn merge(
+    transactions: &mut HashMap<H256, PendingTransaction>,
+    wallet: &MultiSigWallet,
+    queued: ServiceTransaction,
+) -> Result<()> {
+    let transaction = queued.transaction()?;
+    if transaction.safe_tx_hash(wallet.chain_id, wallet.address) != queued.safe_tx_hash {
+        return Err(anyhow!("safeTxHash {:?} does not match the transaction", queued.safe_tx_hash));
+    }
+
+    let pending = transactions.entry(queued.safe_tx_hash).or_insert_with(|| PendingTransaction {
+        safe_tx_hash: queued.safe_tx_hash,
+        safe: wallet.address,
+        chain_id: wallet.chain_id,
+        transaction,
+        signatures: BTreeMap::new(),
+        confirmations_required: wallet.threshold,
+        created_at: Utc::now(),
+        executed_tx: None,
+        shared: true,
+    });
+    pending.shared = true;
+    if queued.is_executed {
+        pending.executed_tx = pending.executed_tx.or(queued.transaction_hash);
+    }
+    for confirmation in queued.confirmations {
+        let Some(signature) = confirmation.signature else {
+            continue;
+        };
+        match recover_owner(queued.safe_tx_hash, &signature) {
+            Ok(owner) if owner == confirmation.owner && wallet.is_owner(owner) => {
+                pending.signatures.insert(owner, signature);
+            }
+            _ => warn!("Ignoring invalid confirmation by {:?} of {:?}", confirmation.owner, queued.safe_tx_hash),
+        }
+    }
+    Ok(())
+}
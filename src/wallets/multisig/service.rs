@@ -0,0 +1,175 @@
+// Safe Transaction Service client, sharing proposals and owner confirmations with the Safe apps
+use anyhow::{Result, anyhow};
+use ethers::{
+    types::{Address, Bytes, H256, U256},
+    utils::{hex, to_checksum},
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tracing::debug;
+
+use super::{Operation, SafeTransaction};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Shown as the origin of proposals in the Safe apps
+const PROPOSAL_ORIGIN: &str = "blockchain-demo";
+
+/// Transaction Service of each chain Safe hosts one for
+fn service_url(chain_id: u64) -> Option<&'static str> {
+    match chain_id {
+        1 => Some("https://safe-transaction-mainnet.safe.global"),
+        10 => Some("https://safe-transaction-optimism.safe.global"),
+        56 => Some("https://safe-transaction-bsc.safe.global"),
+        137 => Some("https://safe-transaction-polygon.safe.global"),
+        8453 => Some("https://safe-transaction-base.safe.global"),
+        42161 => Some("https://safe-transaction-arbitrum.safe.global"),
+        43114 => Some("https://safe-transaction-avalanche.safe.global"),
+        11155111 => Some("https://safe-transaction-sepolia.safe.global"),
+        _ => None,
+    }
+}
+
+/// Multisig transaction as the service lists it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceTransaction {
+    pub safe_tx_hash: H256,
+    pub to: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub value: U256,
+    pub data: Option<Bytes>,
+    pub operation: u8,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub safe_tx_gas: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub base_gas: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub gas_price: U256,
+    pub gas_token: Address,
+    pub refund_receiver: Address,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub nonce: U256,
+    #[serde(default)]
+    pub is_executed: bool,
+    pub transaction_hash: Option<H256>,
+    #[serde(default)]
+    pub confirmations: Vec<ServiceConfirmation>,
+}
+
+impl ServiceTransaction {
+    pub fn transaction(&self) -> Result<SafeTransaction> {
+        Ok(SafeTransaction {
+            to: self.to,
+            value: self.value,
+            data: self.data.clone().unwrap_or_default(),
+            operation: Operation::try_from(self.operation)?,
+            safe_tx_gas: self.safe_tx_gas,
+            base_gas: self.base_gas,
+            gas_price: self.gas_price,
+            gas_token: self.gas_token,
+            refund_receiver: self.refund_receiver,
+            nonce: self.nonce,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServiceConfirmation {
+    pub owner: Address,
+    pub signature: Option<Bytes>,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    results: Vec<T>,
+}
+
+pub struct TransactionService {
+    http: reqwest::Client,
+    api_key: Option<String>,
+}
+
+impl TransactionService {
+    pub fn new(api_key: Option<String>) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            api_key,
+        })
+    }
+
+    pub fn supports(&self, chain_id: u64) -> bool {
+        service_url(chain_id).is_some()
+    }
+
+    fn url(&self, chain_id: u64, path: &str) -> Result<String> {
+        let base = service_url(chain_id)
+            .ok_or_else(|| anyhow!("No Safe Transaction Service for chain {}", chain_id))?;
+        Ok(format!("{}/api/v1/{}", base, path))
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    /// Propose a transaction with its first owner signature, so the other owners see it in their Safe apps
+    pub async fn propose(
+        &self,
+        chain_id: u64,
+        safe: Address,
+        transaction: &SafeTransaction,
+        safe_tx_hash: H256,
+        sender: Address,
+        signature: &Bytes,
+    ) -> Result<()> {
+        let url = self.url(chain_id, &format!("safes/{}/multisig-transactions/", to_checksum(&safe, None)))?;
+        let body = json!({
+            "safe": to_checksum(&safe, None),
+            "to": to_checksum(&transaction.to, None),
+            "value": transaction.value.to_string(),
+            "data": (!transaction.data.is_empty()).then(|| transaction.data.to_string()),
+            "operation": transaction.operation as u8,
+            "safeTxGas": transaction.safe_tx_gas.to_string(),
+            "baseGas": transaction.base_gas.to_string(),
+            "gasPrice": transaction.gas_price.to_string(),
+            "gasToken": to_checksum(&transaction.gas_token, None),
+            "refundReceiver": to_checksum(&transaction.refund_receiver, None),
+            "nonce": transaction.nonce.to_string(),
+            "contractTransactionHash": format!("{:?}", safe_tx_hash),
+            "sender": to_checksum(&sender, None),
+            "signature": format!("0x{}", hex::encode(signature)),
+            "origin": PROPOSAL_ORIGIN,
+        });
+
+        self.request(self.http.post(url)).json(&body).send().await?.error_for_status()?;
+        debug!("Proposed Safe transaction {:?} to the Transaction Service", safe_tx_hash);
+        Ok(())
+    }
+
+    /// Add an owner's signature to a proposed transaction
+    pub async fn confirm(&self, chain_id: u64, safe_tx_hash: H256, signature: &Bytes) -> Result<()> {
+        let url = self.url(chain_id, &format!("multisig-transactions/{:?}/confirmations/", safe_tx_hash))?;
+        let body = json!({ "signature": format!("0x{}", hex::encode(signature)) });
+
+        self.request(self.http.post(url)).json(&body).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Transactions proposed for a Safe from its current nonce on, including ones proposed in other apps
+    pub async fn queued(&self, chain_id: u64, safe: Address, nonce: U256) -> Result<Vec<ServiceTransaction>> {
+        let url = self.url(chain_id, &format!("safes/{}/multisig-transactions/", to_checksum(&safe, None)))?;
+        let query = [("executed", "false".to_string()), ("nonce__gte", nonce.to_string())];
+
+        let page: Page<ServiceTransaction> = self.request(self.http.get(url))
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(page.results)
+    }
+}
@@ -0,0 +1,154 @@
+// ERC-4337 bundler and ERC-7677 paymaster JSON-RPC clients
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, Bytes, H256, U256};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::debug;
+
+use super::UserOperation;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Gas limits a bundler estimated for a user operation
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasEstimate {
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub pre_verification_gas: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub verification_gas_limit: U256,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub call_gas_limit: U256,
+}
+
+/// Paymaster fields returned by `pm_getPaymasterStubData` and `pm_getPaymasterData`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymasterData {
+    paymaster_and_data: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceiptTransaction {
+    transaction_hash: H256,
+}
+
+/// Outcome of an included user operation
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    pub success: bool,
+    #[serde(deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub actual_gas_cost: U256,
+    pub reason: Option<String>,
+    receipt: ReceiptTransaction,
+}
+
+impl UserOperationReceipt {
+    pub fn transaction_hash(&self) -> H256 {
+        self.receipt.transaction_hash
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+/// Fill `{chain_id}` in a URL template, so one setting covers every chain of a provider
+fn chain_url(template: &str, chain_id: u64) -> String {
+    template.replace("{chain_id}", &chain_id.to_string())
+}
+
+pub struct BundlerClient {
+    http: reqwest::Client,
+    bundler_url: Option<String>,
+    paymaster_url: Option<String>,
+    /// Sent with paymaster requests, e.g. the sponsorship policy to apply
+    paymaster_context: Value,
+}
+
+impl BundlerClient {
+    pub fn new(bundler_url: Option<String>, paymaster_url: Option<String>, paymaster_context: Value) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            bundler_url,
+            paymaster_url,
+            paymaster_context,
+        })
+    }
+
+    pub fn sponsors(&self) -> bool {
+        self.paymaster_url.is_some()
+    }
+
+    async fn rpc<T: DeserializeOwned>(&self, url: &str, method: &str, params: Value) -> Result<Option<T>> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response: RpcResponse = self.http.post(url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.error {
+            return Err(anyhow!("{} failed ({}): {}", method, error.code, error.message));
+        }
+        debug!("{} answered", method);
+        response.result
+            .filter(|result| !result.is_null())
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid {} response: {}", method, e))
+    }
+
+    async fn bundler<T: DeserializeOwned>(&self, chain_id: u64, method: &str, params: Value) -> Result<Option<T>> {
+        let template = self.bundler_url.as_deref()
+            .ok_or_else(|| anyhow!("No bundler is configured, set bundler_url"))?;
+        self.rpc(&chain_url(template, chain_id), method, params).await
+    }
+
+    async fn paymaster(&self, chain_id: u64, method: &str, user_operation: &UserOperation, entry_point: Address) -> Result<Bytes> {
+        let template = self.paymaster_url.as_deref()
+            .ok_or_else(|| anyhow!("No paymaster is configured, set paymaster_url"))?;
+        let params = json!([user_operation, entry_point, format!("{:#x}", chain_id), self.paymaster_context]);
+        let data: PaymasterData = self.rpc(&chain_url(template, chain_id), method, params).await?
+            .ok_or_else(|| anyhow!("{} returned no paymaster data", method))?;
+        Ok(data.paymaster_and_data)
+    }
+
+    pub async fn estimate(&self, chain_id: u64, user_operation: &UserOperation, entry_point: Address) -> Result<GasEstimate> {
+        self.bundler(chain_id, "eth_estimateUserOperationGas", json!([user_operation, entry_point])).await?
+            .ok_or_else(|| anyhow!("Bundler returned no gas estimate"))
+    }
+
+    /// Submit a signed user operation, returning its hash
+    pub async fn send(&self, chain_id: u64, user_operation: &UserOperation, entry_point: Address) -> Result<H256> {
+        self.bundler(chain_id, "eth_sendUserOperation", json!([user_operation, entry_point])).await?
+            .ok_or_else(|| anyhow!("Bundler returned no user operation hash"))
+    }
+
+    /// Receipt of a user operation, `None` until a bundle including it was mined
+    pub async fn receipt(&self, chain_id: u64, user_op_hash: H256) -> Result<Option<UserOperationReceipt>> {
+        self.bundler(chain_id, "eth_getUserOperationReceipt", json!([user_op_hash])).await
+    }
+
+    /// Placeholder paymaster data of the right size, to estimate gas with
+    pub async fn paymaster_stub_data(&self, chain_id: u64, user_operation: &UserOperation, entry_point: Address) -> Result<Bytes> {
+        self.paymaster(chain_id, "pm_getPaymasterStubData", user_operation, entry_point).await
+    }
+
+    /// Paymaster data sponsoring the estimated user operation
+    pub async fn paymaster_data(&self, chain_id: u64, user_operation: &UserOperation, entry_point: Address) -> Result<Bytes> {
+        self.paymaster(chain_id, "pm_getPaymasterData", user_operation, entry_point).await
+    }
+}
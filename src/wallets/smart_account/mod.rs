@@ -0,0 +1,412 @@
+// ERC-4337 smart accounts: counterfactual SimpleAccounts, user operations, bundler submission and paymaster sponsorship
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest, H256, U256},
+    utils::{id, keccak256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, instrument, warn};
+
+use crate::chains::ChainManager;
+use crate::transactions::{read_store, write_store};
+
+pub mod bundler;
+
+use bundler::BundlerClient;
+
+// EntryPoint v0.6 and its SimpleAccountFactory, at the same addresses on every chain
+const ENTRY_POINT: &str = "0x5FF137D4b0FDCD49DcA30c7CF57E578a026d2789";
+const SIMPLE_ACCOUNT_FACTORY: &str = "0x9406Cc6185a346906296840746125a0E44976454";
+
+/// Store used when `smart_accounts_store_path` is not configured
+const DEFAULT_STORE_PATH: &str = "data/smart_accounts.json";
+/// Signature recovering to some address, so bundlers can simulate validation before the owner signs
+const DUMMY_SIGNATURE: &str = "0xfffffffffffffffffffffffffffffff0000000000000000000000000000000007aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa1c";
+
+fn address(value: &str) -> Address {
+    value.parse().expect("valid ERC-4337 deployment address")
+}
+
+pub fn entry_point() -> Address {
+    address(ENTRY_POINT)
+}
+
+/// Bundler, paymaster and persistence settings of smart accounts
+#[derive(Debug, Clone, Default)]
+pub struct SmartAccountConfig {
+    /// JSON file holding accounts and submitted user operations, `None` keeps them in memory only
+    pub store_path: Option<PathBuf>,
+    /// Bundler JSON-RPC endpoint, `{chain_id}` is replaced by the chain of the account
+    pub bundler_url: Option<String>,
+    /// ERC-7677 paymaster endpoint sponsoring gas, `None` makes accounts pay their own
+    pub paymaster_url: Option<String>,
+    /// Sponsorship policy passed to the paymaster
+    pub paymaster_policy_id: Option<String>,
+}
+
+impl SmartAccountConfig {
+    /// Settings from `smart_accounts_store_path`, `bundler_url`, `paymaster_url` and `paymaster_policy_id`
+    pub fn from_config(config: &config::Config) -> Self {
+        let path = config
+            .get_string("smart_accounts_store_path")
+            .unwrap_or_else(|_| DEFAULT_STORE_PATH.to_string());
+        let setting = |key: &str| config.get_string(key).ok().filter(|value| !value.is_empty());
+        Self {
+            store_path: (!path.is_empty()).then(|| PathBuf::from(path)),
+            bundler_url: setting("bundler_url"),
+            paymaster_url: setting("paymaster_url"),
+            paymaster_policy_id: setting("paymaster_policy_id"),
+        }
+    }
+}
+
+/// EntryPoint v0.6 user operation, serialized as bundlers expect it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    /// Factory and call deploying the account with its first operation, empty once deployed
+    pub init_code: Bytes,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    /// Paymaster address and its data, empty when the account pays for gas
+    pub paymaster_and_data: Bytes,
+    pub signature: Bytes,
+}
+
+impl UserOperation {
+    /// Hash the owner signs, binding the operation to the EntryPoint and chain
+    pub fn hash(&self, entry_point: Address, chain_id: u64) -> H256 {
+        let hash_bytes = |bytes: &Bytes| Token::FixedBytes(keccak256(bytes).to_vec());
+        let packed = abi::encode(&[
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            hash_bytes(&self.init_code),
+            hash_bytes(&self.call_data),
+            Token::Uint(self.call_gas_limit),
+            Token::Uint(self.verification_gas_limit),
+            Token::Uint(self.pre_verification_gas),
+            Token::Uint(self.max_fee_per_gas),
+            Token::Uint(self.max_priority_fee_per_gas),
+            hash_bytes(&self.paymaster_and_data),
+        ]);
+        H256::from(keccak256(abi::encode(&[
+            Token::FixedBytes(keccak256(packed).to_vec()),
+            Token::Address(entry_point),
+            Token::Uint(U256::from(chain_id)),
+        ])))
+    }
+
+    pub fn is_sponsored(&self) -> bool {
+        !self.paymaster_and_data.is_empty()
+    }
+}
+
+/// Call a smart account is asked to make
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmartAccountCall {
+    pub to: Address,
+    #[serde(default, deserialize_with = "crate::api::models::u256_lenient::deserialize")]
+    pub value: U256,
+    #[serde(default)]
+    pub data: Bytes,
+}
+
+/// SimpleAccount controlled by one owner wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartAccountWallet {
+    pub address: Address,
+    pub owner: Address,
+    pub chain_id: u64,
+    /// Factory salt the address was derived with
+    pub salt: U256,
+    /// False until the first user operation deployed the account
+    pub deployed: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user operation handed to the bundler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmittedUserOperation {
+    pub user_op_hash: H256,
+    pub sender: Address,
+    pub chain_id: u64,
+    pub user_operation: UserOperation,
+    pub sponsored: bool,
+    pub submitted_at: DateTime<Utc>,
+    /// Bundle transaction that included the operation, `None` while it waits in the mempool
+    pub transaction_hash: Option<H256>,
+    /// Whether the account's call succeeded once included
+    pub success: Option<bool>,
+    /// Gas cost charged to the account or its paymaster, in the native asset
+    pub actual_gas_cost: Option<U256>,
+    pub revert_reason: Option<String>,
+}
+
+/// Everything persisted across restarts
+#[derive(Default, Serialize, Deserialize)]
+struct SmartAccountStore {
+    accounts: Vec<SmartAccountWallet>,
+    operations: Vec<SubmittedUserOperation>,
+}
+
+pub struct SmartAccountManager {
+    chain_manager: Arc<ChainManager>,
+    bundler: BundlerClient,
+    store_path: Option<PathBuf>,
+    accounts: RwLock<HashMap<Address, SmartAccountWallet>>,
+    operations: RwLock<HashMap<H256, SubmittedUserOperation>>,
+}
+
+impl SmartAccountManager {
+    pub async fn new(chain_manager: Arc<ChainManager>, config: SmartAccountConfig) -> Result<Self> {
+        let store: SmartAccountStore = match &config.store_path {
+            Some(path) => read_store(path).await?.unwrap_or_default(),
+            None => SmartAccountStore::default(),
+        };
+        if !store.accounts.is_empty() {
+            info!("Loaded {} smart accounts", store.accounts.len());
+        }
+        let paymaster_context = match &config.paymaster_policy_id {
+            Some(policy_id) => serde_json::json!({ "sponsorshipPolicyId": policy_id }),
+            None => serde_json::json!({}),
+        };
+
+        Ok(Self {
+            chain_manager,
+            bundler: BundlerClient::new(config.bundler_url, config.paymaster_url, paymaster_context)?,
+            store_path: config.store_path,
+            accounts: RwLock::new(store.accounts.into_iter().map(|account| (account.address, account)).collect()),
+            operations: RwLock::new(store.operations.into_iter().map(|operation| (operation.user_op_hash, operation)).collect()),
+        })
+    }
+
+    pub async fn wallets(&self) -> Vec<SmartAccountWallet> {
+        self.accounts.read().await.values().cloned().collect()
+    }
+
+    pub async fn get_account(&self, address: Address) -> Result<SmartAccountWallet> {
+        self.accounts.read().await
+            .get(&address)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown smart account {:?}", address))
+    }
+
+    /// Register the SimpleAccount of `owner` at `salt`; it is deployed by its first user operation
+    #[instrument(skip_all, fields(chain_id = chain_id, wallet = ?owner))]
+    pub async fn create_account(&self, owner: Address, chain_id: u64, salt: U256) -> Result<SmartAccountWallet> {
+        let data = [
+            &id("getAddress(address,uint256)")[..],
+            &abi::encode(&[Token::Address(owner), Token::Uint(salt)]),
+        ].concat();
+        let address = match self.call(chain_id, address(SIMPLE_ACCOUNT_FACTORY), data, &[ParamType::Address]).await?.pop() {
+            Some(Token::Address(address)) => address,
+            _ => return Err(anyhow!("Invalid getAddress response from the account factory")),
+        };
+
+        let account = SmartAccountWallet {
+            address,
+            owner,
+            chain_id,
+            salt,
+            deployed: self.is_deployed(chain_id, address).await?,
+            created_at: Utc::now(),
+        };
+        self.accounts.write().await.insert(address, account.clone());
+        self.persist().await;
+
+        info!("Created smart account {:?} owned by {:?}", address, owner);
+        Ok(account)
+    }
+
+    /// Build and gas-estimate a user operation making `calls`, signed with a placeholder until the owner signs it
+    #[instrument(skip_all, fields(wallet = ?account))]
+    pub async fn build_user_operation(&self, account: Address, calls: &[SmartAccountCall], sponsor: bool) -> Result<UserOperation> {
+        let mut smart_account = self.get_account(account).await?;
+        let chain_id = smart_account.chain_id;
+        if sponsor && !self.bundler.sponsors() {
+            return Err(anyhow!("No paymaster is configured to sponsor gas, set paymaster_url"));
+        }
+
+        if !smart_account.deployed && self.is_deployed(chain_id, account).await? {
+            smart_account.deployed = true;
+            self.accounts.write().await.insert(account, smart_account.clone());
+            self.persist().await;
+        }
+        let init_code = match smart_account.deployed {
+            true => Bytes::new(),
+            false => [
+                address(SIMPLE_ACCOUNT_FACTORY).as_bytes(),
+                &id("createAccount(address,uint256)")[..],
+                &abi::encode(&[Token::Address(smart_account.owner), Token::Uint(smart_account.salt)]),
+            ].concat().into(),
+        };
+
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let (max_fee_per_gas, max_priority_fee_per_gas) = chain.provider.estimate_eip1559_fees(None).await?;
+        let mut user_operation = UserOperation {
+            sender: account,
+            nonce: self.nonce(chain_id, account).await?,
+            init_code,
+            call_data: call_data(calls)?,
+            call_gas_limit: U256::zero(),
+            verification_gas_limit: U256::zero(),
+            pre_verification_gas: U256::zero(),
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            paymaster_and_data: Bytes::new(),
+            signature: DUMMY_SIGNATURE.parse()?,
+        };
+
+        if sponsor {
+            user_operation.paymaster_and_data = self.bundler.paymaster_stub_data(chain_id, &user_operation, entry_point()).await?;
+        }
+        let estimate = self.bundler.estimate(chain_id, &user_operation, entry_point()).await?;
+        user_operation.call_gas_limit = estimate.call_gas_limit;
+        user_operation.verification_gas_limit = estimate.verification_gas_limit;
+        user_operation.pre_verification_gas = estimate.pre_verification_gas;
+        // Sponsorship covers the final gas limits and fees, so it is requested last
+        if sponsor {
+            user_operation.paymaster_and_data = self.bundler.paymaster_data(chain_id, &user_operation, entry_point()).await?;
+        }
+
+        Ok(user_operation)
+    }
+
+    /// Hand a signed user operation to the bundler
+    pub async fn submit(&self, chain_id: u64, user_operation: UserOperation) -> Result<SubmittedUserOperation> {
+        let user_op_hash = self.bundler.send(chain_id, &user_operation, entry_point()).await?;
+        let submitted = SubmittedUserOperation {
+            user_op_hash,
+            sender: user_operation.sender,
+            chain_id,
+            sponsored: user_operation.is_sponsored(),
+            user_operation,
+            submitted_at: Utc::now(),
+            transaction_hash: None,
+            success: None,
+            actual_gas_cost: None,
+            revert_reason: None,
+        };
+        self.operations.write().await.insert(user_op_hash, submitted.clone());
+        self.persist().await;
+
+        info!(
+            "Submitted user operation {:?} from {:?}{}",
+            user_op_hash,
+            submitted.sender,
+            if submitted.sponsored { " with sponsored gas" } else { "" }
+        );
+        Ok(submitted)
+    }
+
+    /// A submitted user operation, refreshed from the bundler until it was included
+    pub async fn get_user_operation(&self, user_op_hash: H256) -> Result<SubmittedUserOperation> {
+        let mut operation = self.operations.read().await
+            .get(&user_op_hash)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown user operation {:?}", user_op_hash))?;
+        if operation.transaction_hash.is_some() {
+            return Ok(operation);
+        }
+
+        let Some(receipt) = self.bundler.receipt(operation.chain_id, user_op_hash).await? else {
+            return Ok(operation);
+        };
+        operation.transaction_hash = Some(receipt.transaction_hash());
+        operation.success = Some(receipt.success);
+        operation.actual_gas_cost = Some(receipt.actual_gas_cost);
+        operation.revert_reason = receipt.reason.clone();
+        if let Some(account) = self.accounts.write().await.get_mut(&operation.sender) {
+            account.deployed = true;
+        }
+        self.operations.write().await.insert(user_op_hash, operation.clone());
+        self.persist().await;
+        Ok(operation)
+    }
+
+    /// User operations submitted from an account, newest first
+    pub async fn user_operations(&self, account: Address) -> Vec<SubmittedUserOperation> {
+        let mut operations: Vec<SubmittedUserOperation> = self.operations.read().await
+            .values()
+            .filter(|operation| operation.sender == account)
+            .cloned()
+            .collect();
+        operations.sort_by_key(|operation| std::cmp::Reverse(operation.submitted_at));
+        operations
+    }
+
+    async fn is_deployed(&self, chain_id: u64, account: Address) -> Result<bool> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        Ok(!chain.provider.get_code(account, None).await?.is_empty())
+    }
+
+    /// Next nonce of the account's default key at the EntryPoint
+    async fn nonce(&self, chain_id: u64, account: Address) -> Result<U256> {
+        let data = [
+            &id("getNonce(address,uint192)")[..],
+            &abi::encode(&[Token::Address(account), Token::Uint(U256::zero())]),
+        ].concat();
+        match self.call(chain_id, entry_point(), data, &[ParamType::Uint(256)]).await?.pop() {
+            Some(Token::Uint(nonce)) => Ok(nonce),
+            _ => Err(anyhow!("Invalid getNonce response from the EntryPoint")),
+        }
+    }
+
+    async fn call(&self, chain_id: u64, contract: Address, data: Vec<u8>, outputs: &[ParamType]) -> Result<Vec<Token>> {
+        let chain = self.chain_manager.get_provider(chain_id).await?;
+        let tx: TypedTransaction = TransactionRequest::new().to(contract).data(data).into();
+        let output = chain.provider.call(&tx, None).await?;
+        abi::decode(outputs, &output).map_err(|e| anyhow!("Invalid response from {:?}: {}", contract, e))
+    }
+
+    /// Save accounts and operations, failures are logged since they stay usable in memory
+    async fn persist(&self) {
+        let Some(path) = &self.store_path else {
+            return;
+        };
+        let store = SmartAccountStore {
+            accounts: self.accounts.read().await.values().cloned().collect(),
+            operations: self.operations.read().await.values().cloned().collect(),
+        };
+        if let Err(e) = write_store(path, &store).await {
+            warn!("Failed to persist smart accounts to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// SimpleAccount `execute` for one call, `executeBatch` for several, which cannot carry value
+fn call_data(calls: &[SmartAccountCall]) -> Result<Bytes> {
+    let data = match calls {
+        [] => return Err(anyhow!("A user operation needs at least one call")),
+        [call] => [
+            &id("execute(address,uint256,bytes)")[..],
+            &abi::encode(&[Token::Address(call.to), Token::Uint(call.value), Token::Bytes(call.data.to_vec())]),
+        ].concat(),
+        calls => {
+            if calls.iter().any(|call| !call.value.is_zero()) {
+                return Err(anyhow!("Batched calls cannot send value, submit calls with value on their own"));
+            }
+            [
+                &id("executeBatch(address[],bytes[])")[..],
+                &abi::encode(&[
+                    Token::Array(calls.iter().map(|call| Token::Address(call.to)).collect()),
+                    Token::Array(calls.iter().map(|call| Token::Bytes(call.data.to_vec())).collect()),
+                ]),
+            ].concat()
+        }
+    };
+    Ok(data.into())
+}
@@ -1,207 +0,0 @@
-// WalletConnect integration for mobile and desktop wallets
-use anyhow::Result;
-use ethers::{
-    prelude::*,
-    types::{Address, Signature, transaction::eip2718::TypedTransaction},
-};
-use serde::{Deserialize, Serialize};
-use tracing::{info, warn};
-
-#[derive(Debug, Clone)]
-pub struct WalletConnectProvider {
-    address: Address,
-    session_id: String,
-    project_id: String,
-    chain_id: u64,
-    is_connected: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WalletConnectSession {
-    pub topic: String,
-    pub accounts: Vec<String>,
-    pub chains: Vec<String>,
-    pub methods: Vec<String>,
-    pub events: Vec<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SessionRequest {
-    pub required_namespaces: std::collections::HashMap<String, Namespace>,
-    pub optional_namespaces: std::collections::HashMap<String, Namespace>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Namespace {
-    pub chains: Vec<String>,
-    pub methods: Vec<String>,
-    pub events: Vec<String>,
-}
-
-impl WalletConnectProvider {
-    pub async fn connect(project_id: &str) -> Result<Self> {
-        info!("Connecting via WalletConnect with project ID: {}", project_id);
-
-        // In a real implementation, this would:
-        // 1. Initialize WalletConnect client
-        // 2. Create session proposal
-        // 3. Display QR code for mobile wallet scanning
-        // 4. Wait for wallet approval
-        // 5. Establish session
-
-        warn!("Using mock WalletConnect connection - implement real WalletConnect v2.0");
-
-        let mock_session_id = format!("session_{}", uuid::Uuid::new_v4());
-        let mock_address = Address::random();
-        
-        info!("Mock WalletConnect session established: {}", mock_session_id);
-        info!("Connected address: {:?}", mock_address);
-
-        Ok(Self {
-            address: mock_address,
-            session_id: mock_session_id,
-            project_id: project_id.to_string(),
-            chain_id: 1, // Default to Ethereum mainnet
-            is_connected: true,
-        })
-    }
-
-    pub fn get_address(&self) -> Address {
-        self.address
-    }
-
-    pub fn get_session_id(&self) -> &str {
-        &self.session_id
-    }
-
-    pub fn get_chain_id(&self) -> u64 {
-        self.chain_id
-    }
-
-    pub fn is_connected(&self) -> bool {
-        self.is_connected
-    }
-
-    pub async fn switch_chain(&mut self, chain_id: u64) -> Result<()> {
-        info!("Switching WalletConnect to chain {}", chain_id);
-
-        // In a real implementation, send wallet_switchEthereumChain request
-        warn!("Mock chain switch - implement real WalletConnect chain switching");
-
-        self.chain_id = chain_id;
-        Ok(())
-    }
-
-    pub async fn request_session(&self, chains: Vec<u64>) -> Result<SessionRequest> {
-        info!("Requesting WalletConnect session for chains: {:?}", chains);
-
-        let mut namespaces = std::collections::HashMap::new();
-        
-        // EVM namespace for Ethereum-compatible chains
-        let evm_chains: Vec<String> = chains.iter()
-            .map(|&id| format!("eip155:{}", id))
-            .collect();
-
-        namespaces.insert("eip155".to_string(), Namespace {
-            chains: evm_chains,
-            methods: vec![
-                "eth_sendTransaction".to_string(),
-                "eth_signTransaction".to_string(),
-                "eth_sign".to_string(),
-                "personal_sign".to_string(),
-                "eth_signTypedData".to_string(),
-                "eth_signTypedData_v4".to_string(),
-            ],
-            events: vec![
-                "chainChanged".to_string(),
-                "accountsChanged".to_string(),
-            ],
-        });
-
-        Ok(SessionRequest {
-            required_namespaces: namespaces,
-            optional_namespaces: std::collections::HashMap::new(),
-        })
-    }
-
-    pub async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
-        info!("Signing message via WalletConnect");
-
-        // In a real implementation:
-        // 1. Send personal_sign request to connected wallet
-        // 2. Wallet displays signing prompt to user
-        // 3. User approves and wallet returns signature
-        // 4. Return signature to dApp
-
-        warn!("Mock WalletConnect message signing - implement real signing");
-
-        let mock_signature = Signature {
-            r: U256::from(3),
-            s: U256::from(3),
-            v: 27,
-        };
-
-        Ok(mock_signature)
-    }
-
-    pub async fn sign_transaction(&self, tx: TypedTransaction) -> Result<Signature> {
-        info!("Signing transaction via WalletConnect");
-
-        // In a real implementation:
-        // 1. Send eth_sendTransaction request
-        // 2. Wallet shows transaction details
-        // 3. User approves transaction
-        // 4. Wallet signs and broadcasts
-
-        warn!("Mock WalletConnect transaction signing - implement real signing");
-
-        let mock_signature = Signature {
-            r: U256::from(4),
-            s: U256::from(4),
-            v: 28,
-        };
-
-        Ok(mock_signature)
-    }
-
-    pub async fn sign_typed_data(&self, domain: &str, types: &str, data: &str) -> Result<Signature> {
-        info!("Signing typed data via WalletConnect");
-
-        // EIP-712 structured data signing
-        warn!("Mock WalletConnect typed data signing - implement EIP-712");
-
-        let mock_signature = Signature {
-            r: U256::from(5),
-            s: U256::from(5),
-            v: 27,
-        };
-
-        Ok(mock_signature)
-    }
-
-    pub async fn ping_session(&self) -> Result<bool> {
-        info!("Pinging WalletConnect session: {}", self.session_id);
-
-        // In a real implementation, ping the session to check if it's still active
-        warn!("Mock session ping - implement real session monitoring");
-
-        Ok(self.is_connected)
-    }
-
-    pub async fn disconnect(&mut self) -> Result<()> {
-        info!("Disconnecting WalletConnect session: {}", self.session_id);
-
-        // In a real implementation, send session disconnect request
-        warn!("Mock disconnect - implement real WalletConnect disconnect");
-
-        self.is_connected = false;
-        Ok(())
-    }
-
-    pub async fn get_supported_chains(&self) -> Result<Vec<u64>> {
-        // Return list of chains supported by the connected wallet
-        warn!("Mock supported chains - implement real chain querying");
-
-        Ok(vec![1, 137, 42161]) // Ethereum, Polygon, Arbitrum
-    }
-}
@@ -0,0 +1,157 @@
+// WalletConnect v2 envelope encryption, key agreement and relay authentication
+use anyhow::{Result, anyhow};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use ethers::utils::hex;
+use ring::{
+    aead, agreement, digest, hkdf,
+    rand::{SecureRandom, SystemRandom},
+    signature::{Ed25519KeyPair, KeyPair},
+};
+
+/// Envelope sealed with the topic's symmetric key, the only type a dapp receives
+const ENVELOPE_TYPE_0: u8 = 0;
+const IV_LENGTH: usize = 12;
+/// Multicodec prefix of an Ed25519 public key in a `did:key`
+const ED25519_MULTICODEC: [u8; 2] = [0xed, 0x01];
+/// Lifetime of a relay authentication token
+const RELAY_JWT_TTL_SECS: i64 = 24 * 60 * 60;
+
+pub type SymKey = [u8; 32];
+
+pub fn random_key() -> Result<SymKey> {
+    let mut key = [0u8; 32];
+    SystemRandom::new().fill(&mut key).map_err(|_| anyhow!("System randomness unavailable"))?;
+    Ok(key)
+}
+
+/// Topic a symmetric key is used on, the SHA-256 of the key
+pub fn topic_for(sym_key: &SymKey) -> String {
+    hex::encode(digest::digest(&digest::SHA256, sym_key))
+}
+
+/// Seal a payload in a type 0 envelope: type byte, IV, then the ChaCha20-Poly1305 ciphertext
+pub fn seal(sym_key: &SymKey, plaintext: &[u8]) -> Result<String> {
+    let key = aead_key(sym_key)?;
+    let mut iv = [0u8; IV_LENGTH];
+    SystemRandom::new().fill(&mut iv).map_err(|_| anyhow!("System randomness unavailable"))?;
+
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(iv), aead::Aad::empty(), &mut sealed)
+        .map_err(|_| anyhow!("WalletConnect payload encryption failed"))?;
+
+    let mut envelope = Vec::with_capacity(1 + IV_LENGTH + sealed.len());
+    envelope.push(ENVELOPE_TYPE_0);
+    envelope.extend_from_slice(&iv);
+    envelope.extend_from_slice(&sealed);
+    Ok(STANDARD.encode(envelope))
+}
+
+pub fn open(sym_key: &SymKey, message: &str) -> Result<Vec<u8>> {
+    let envelope = STANDARD.decode(message)?;
+    match envelope.first() {
+        Some(&ENVELOPE_TYPE_0) => {}
+        Some(envelope_type) => return Err(anyhow!("Unsupported WalletConnect envelope type {}", envelope_type)),
+        None => return Err(anyhow!("Empty WalletConnect envelope")),
+    }
+    if envelope.len() < 1 + IV_LENGTH + aead::CHACHA20_POLY1305.tag_len() {
+        return Err(anyhow!("Truncated WalletConnect envelope"));
+    }
+
+    let mut iv = [0u8; IV_LENGTH];
+    iv.copy_from_slice(&envelope[1..=IV_LENGTH]);
+    let mut sealed = envelope[1 + IV_LENGTH..].to_vec();
+    let plaintext = aead_key(sym_key)?
+        .open_in_place(aead::Nonce::assume_unique_for_key(iv), aead::Aad::empty(), &mut sealed)
+        .map_err(|_| anyhow!("WalletConnect payload decryption failed"))?;
+    Ok(plaintext.to_vec())
+}
+
+fn aead_key(sym_key: &SymKey) -> Result<aead::LessSafeKey> {
+    let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, sym_key)
+        .map_err(|_| anyhow!("Invalid WalletConnect symmetric key"))?;
+    Ok(aead::LessSafeKey::new(key))
+}
+
+/// X25519 key a session proposal is made with, consumed once the wallet answers
+pub struct ProposalKey {
+    private_key: agreement::EphemeralPrivateKey,
+    public_key: String,
+}
+
+impl ProposalKey {
+    pub fn generate() -> Result<Self> {
+        let private_key = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate a WalletConnect proposal key"))?;
+        let public_key = private_key.compute_public_key()
+            .map_err(|_| anyhow!("Failed to derive a WalletConnect proposal key"))?;
+
+        Ok(Self {
+            private_key,
+            public_key: hex::encode(public_key.as_ref()),
+        })
+    }
+
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Symmetric key of the session the wallet approved, HKDF-SHA256 of the shared secret
+    pub fn session_key(self, responder_public_key: &str) -> Result<SymKey> {
+        let responder_public_key = hex::decode(responder_public_key.trim_start_matches("0x"))?;
+        let peer = agreement::UnparsedPublicKey::new(&agreement::X25519, responder_public_key);
+
+        agreement::agree_ephemeral(self.private_key, &peer, |shared_secret| {
+            let mut sym_key = [0u8; 32];
+            hkdf::Salt::new(hkdf::HKDF_SHA256, &[])
+                .extract(shared_secret)
+                .expand(&[], hkdf::HKDF_SHA256)
+                .and_then(|okm| okm.fill(&mut sym_key))
+                .map(|_| sym_key)
+        })
+        .and_then(|sym_key| sym_key)
+        .map_err(|_| anyhow!("Invalid WalletConnect responder public key"))
+    }
+}
+
+/// Ed25519 identity the client authenticates to the relay with
+pub struct ClientIdentity {
+    key_pair: Ed25519KeyPair,
+}
+
+impl ClientIdentity {
+    pub fn generate() -> Result<Self> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate a WalletConnect client key"))?;
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+            .map_err(|_| anyhow!("Invalid WalletConnect client key"))?;
+        Ok(Self { key_pair })
+    }
+
+    /// `did:key` of the client's public key
+    fn did_key(&self) -> String {
+        let mut multicodec = ED25519_MULTICODEC.to_vec();
+        multicodec.extend_from_slice(self.key_pair.public_key().as_ref());
+        format!("did:key:z{}", bs58::encode(multicodec).into_string())
+    }
+
+    /// EdDSA JWT the relay expects in the `auth` query parameter
+    pub fn relay_jwt(&self, audience: &str) -> Result<String> {
+        let issued_at = chrono::Utc::now().timestamp();
+        let header = serde_json::json!({ "alg": "EdDSA", "typ": "JWT" });
+        let claims = serde_json::json!({
+            "iss": self.did_key(),
+            "sub": hex::encode(random_key()?),
+            "aud": audience,
+            "iat": issued_at,
+            "exp": issued_at + RELAY_JWT_TTL_SECS,
+        });
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?),
+        );
+        let signature = self.key_pair.sign(signing_input.as_bytes());
+        Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.as_ref())))
+    }
+}
@@ -0,0 +1,858 @@
+// WalletConnect v2 integration for mobile and desktop wallets
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ethers::{
+    types::{Address, Signature, transaction::{eip2718::TypedTransaction, eip712::TypedData}},
+    utils::{hex, rlp::Rlp},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::transactions::{read_store, write_store};
+
+pub mod crypto;
+pub mod relay;
+
+use crypto::{ProposalKey, SymKey};
+use relay::{RelayClient, RelayMessage};
+
+const DEFAULT_RELAY_URL: &str = "wss://relay.walletconnect.org";
+/// Store used when `walletconnect_sessions_path` is not configured
+const DEFAULT_SESSIONS_PATH: &str = "data/walletconnect_sessions.json";
+/// How long a pairing URI can be scanned before the proposal expires
+const PAIRING_TTL_SECS: u64 = 300;
+/// How long a user has to approve a request in their wallet
+const SESSION_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+const SESSION_PING_TIMEOUT: Duration = Duration::from_secs(30);
+const SESSION_EVENTS_CAPACITY: usize = 256;
+
+// Relay tags of the messages a dapp publishes, see the WalletConnect sign API specs
+const TAG_SESSION_PROPOSE: u32 = 1100;
+const TAG_SESSION_DELETE: u32 = 1112;
+const TAG_SESSION_PING: u32 = 1114;
+const TAG_SESSION_REQUEST: u32 = 1108;
+
+const EIP155_METHODS: [&str; 6] = [
+    "eth_sendTransaction",
+    "eth_signTransaction",
+    "eth_sign",
+    "personal_sign",
+    "eth_signTypedData",
+    "eth_signTypedData_v4",
+];
+const EIP155_EVENTS: [&str; 2] = ["chainChanged", "accountsChanged"];
+/// Chains offered as optional next to the ones a pairing requires
+const OPTIONAL_CHAINS: [u64; 5] = [1, 137, 42161, 56, 43114];
+
+type PendingRequests = Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>;
+
+/// Relay, project and persistence settings of the WalletConnect client
+#[derive(Debug, Clone)]
+pub struct WalletConnectConfig {
+    /// WalletConnect Cloud project id, WalletConnect is disabled without one
+    pub project_id: Option<String>,
+    pub relay_url: String,
+    /// JSON file holding settled sessions, `None` keeps them in memory only
+    pub sessions_path: Option<PathBuf>,
+    /// URL of this app shown to users in their wallet
+    pub app_url: String,
+}
+
+impl Default for WalletConnectConfig {
+    fn default() -> Self {
+        Self {
+            project_id: None,
+            relay_url: DEFAULT_RELAY_URL.to_string(),
+            sessions_path: None,
+            app_url: String::new(),
+        }
+    }
+}
+
+impl WalletConnectConfig {
+    /// Settings from `walletconnect_project_id`, `walletconnect_relay_url`,
+    /// `walletconnect_sessions_path` and `walletconnect_app_url`
+    pub fn from_config(config: &config::Config) -> Self {
+        let path = config
+            .get_string("walletconnect_sessions_path")
+            .unwrap_or_else(|_| DEFAULT_SESSIONS_PATH.to_string());
+        Self {
+            project_id: config.get_string("walletconnect_project_id").ok().filter(|id| !id.is_empty()),
+            relay_url: config
+                .get_string("walletconnect_relay_url")
+                .ok()
+                .filter(|url| !url.is_empty())
+                .unwrap_or_else(|| DEFAULT_RELAY_URL.to_string()),
+            sessions_path: (!path.is_empty()).then(|| PathBuf::from(path)),
+            app_url: config.get_string("walletconnect_app_url").unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRequest {
+    #[serde(rename = "requiredNamespaces")]
+    pub required_namespaces: HashMap<String, Namespace>,
+    #[serde(rename = "optionalNamespaces")]
+    pub optional_namespaces: HashMap<String, Namespace>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Namespace {
+    #[serde(default)]
+    pub chains: Vec<String>,
+    /// Only set by the wallet, proposals leave it out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accounts: Vec<String>,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// Name, description, URL and icons a peer presents itself with
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerMetadata {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub icons: Vec<String>,
+}
+
+/// Session a wallet settled, its accounts as CAIP-10 `eip155:<chain>:<address>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletConnectSession {
+    pub topic: String,
+    pub pairing_topic: Option<String>,
+    pub accounts: Vec<String>,
+    pub chains: Vec<String>,
+    pub methods: Vec<String>,
+    pub events: Vec<String>,
+    pub peer: PeerMetadata,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl WalletConnectSession {
+    fn from_namespaces(topic: &str, pairing_topic: Option<String>, params: &Value) -> Result<Self> {
+        let namespaces: HashMap<String, Namespace> = serde_json::from_value(params["namespaces"].clone())?;
+        let eip155 = namespaces.get("eip155")
+            .ok_or_else(|| anyhow!("WalletConnect session without an eip155 namespace"))?;
+        let peer = serde_json::from_value(params["controller"]["metadata"].clone()).unwrap_or_default();
+
+        let mut session = Self {
+            topic: topic.to_string(),
+            pairing_topic,
+            accounts: Vec::new(),
+            chains: Vec::new(),
+            methods: Vec::new(),
+            events: Vec::new(),
+            peer,
+            expires_at: Utc::now(),
+        };
+        session.apply_namespace(eip155);
+        session.set_expiry(params)?;
+        Ok(session)
+    }
+
+    /// Take over the accounts, chains, methods and events of an updated namespace
+    fn apply_namespace(&mut self, eip155: &Namespace) {
+        self.accounts = eip155.accounts.clone();
+        self.chains = eip155.chains.clone();
+        // Chains may only be listed through the accounts on them
+        for account in &self.accounts {
+            if let Some((chain, _)) = account.rsplit_once(':') {
+                if !self.chains.iter().any(|existing| existing == chain) {
+                    self.chains.push(chain.to_string());
+                }
+            }
+        }
+        self.methods = eip155.methods.clone();
+        self.events = eip155.events.clone();
+    }
+
+    fn set_expiry(&mut self, params: &Value) -> Result<()> {
+        let expiry = params["expiry"].as_i64().ok_or_else(|| anyhow!("WalletConnect session without an expiry"))?;
+        self.expires_at = DateTime::from_timestamp(expiry, 0)
+            .ok_or_else(|| anyhow!("Invalid WalletConnect session expiry {}", expiry))?;
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    pub fn chain_ids(&self) -> Vec<u64> {
+        self.chains.iter()
+            .filter_map(|chain| chain.strip_prefix("eip155:")?.parse().ok())
+            .collect()
+    }
+
+    /// Addresses the wallet exposes, each with the first chain it is on
+    pub fn addresses(&self) -> Vec<(Address, u64)> {
+        let mut addresses: Vec<(Address, u64)> = Vec::new();
+        for account in &self.accounts {
+            let mut parts = account.split(':');
+            let (Some("eip155"), Some(chain), Some(address)) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
+            };
+            let (Ok(chain_id), Ok(address)) = (chain.parse(), address.parse::<Address>()) else {
+                continue;
+            };
+            if !addresses.iter().any(|(existing, _)| *existing == address) {
+                addresses.push((address, chain_id));
+            }
+        }
+        addresses
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairingStatus {
+    /// Waiting for the URI to be scanned and the proposal approved
+    Pending,
+    Approved,
+    Rejected,
+    Expired,
+}
+
+/// Pairing a user scans, approved once the wallet settles a session on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletConnectPairing {
+    pub topic: String,
+    /// `wc:` URI shown as a QR code or deep link
+    pub uri: String,
+    pub chains: Vec<u64>,
+    pub expires_at: DateTime<Utc>,
+    pub status: PairingStatus,
+    pub session_topic: Option<String>,
+    pub accounts: Vec<Address>,
+    /// Reason the wallet gave for rejecting the proposal
+    pub error: Option<String>,
+}
+
+struct PendingPairing {
+    pairing: WalletConnectPairing,
+    proposal_id: u64,
+    /// Consumed when the wallet answers the proposal
+    proposal_key: Option<ProposalKey>,
+}
+
+/// Session as persisted, with the key its topic is encrypted with
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    #[serde(flatten)]
+    session: WalletConnectSession,
+    sym_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    /// A session was settled, restored or had its accounts updated
+    Settled(Box<WalletConnectSession>),
+    /// A session was deleted by either side or expired, by topic
+    Deleted(String),
+}
+
+/// Dapp side of the WalletConnect sign protocol: pairs with wallets, relays their
+/// requests and keeps sessions across restarts
+pub struct WalletConnectClient {
+    config: WalletConnectConfig,
+    relay: Arc<RelayClient>,
+    keys: RwLock<HashMap<String, SymKey>>,
+    pairings: RwLock<HashMap<String, PendingPairing>>,
+    sessions: RwLock<HashMap<String, WalletConnectSession>>,
+    pending: PendingRequests,
+    events: broadcast::Sender<SessionEvent>,
+}
+
+impl WalletConnectClient {
+    pub fn new(config: WalletConnectConfig) -> Result<Self> {
+        let project_id = config.project_id.as_deref()
+            .ok_or_else(|| anyhow!("WalletConnect needs a project id"))?;
+        let relay = Arc::new(RelayClient::new(&config.relay_url, project_id)?);
+        let (events, _) = broadcast::channel(SESSION_EVENTS_CAPACITY);
+
+        Ok(Self {
+            config,
+            relay,
+            keys: RwLock::new(HashMap::new()),
+            pairings: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            events,
+        })
+    }
+
+    /// Settled and deleted sessions, including the ones restored on start
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Connect to the relay and restore the sessions persisted by the last run
+    pub async fn start(self: Arc<Self>) -> Result<()> {
+        let (incoming, mut messages) = mpsc::unbounded_channel();
+        Arc::clone(&self.relay).start(incoming).await?;
+
+        let client = Arc::clone(&self);
+        // Messages are handled in order, a settlement follows the proposal response it depends on
+        tokio::spawn(async move {
+            while let Some(message) = messages.recv().await {
+                client.handle_message(message).await;
+            }
+        });
+
+        self.restore().await
+    }
+
+    async fn restore(&self) -> Result<()> {
+        let Some(path) = &self.config.sessions_path else {
+            return Ok(());
+        };
+        let stored: Vec<StoredSession> = read_store(path).await?.unwrap_or_default();
+        let total = stored.len();
+
+        for StoredSession { session, sym_key } in stored {
+            if session.is_expired() {
+                continue;
+            }
+            let key = match hex::decode(&sym_key).ok().and_then(|key| SymKey::try_from(key).ok()) {
+                Some(key) => key,
+                None => {
+                    warn!("Dropping WalletConnect session {} with an invalid key", session.topic);
+                    continue;
+                }
+            };
+            self.keys.write().await.insert(session.topic.clone(), key);
+            if let Err(e) = self.relay.subscribe(&session.topic).await {
+                // Resubscribed with the other topics once the relay reconnects
+                warn!("Failed to resubscribe WalletConnect session {}: {}", session.topic, e);
+            }
+            self.sessions.write().await.insert(session.topic.clone(), session.clone());
+            let _ = self.events.send(SessionEvent::Settled(Box::new(session)));
+        }
+
+        let restored = self.sessions.read().await.len();
+        if restored < total {
+            self.persist().await;
+        }
+        if restored > 0 {
+            info!("Restored {} WalletConnect sessions from {}", restored, path.display());
+        }
+        Ok(())
+    }
+
+    /// Propose a session on a new pairing, returning the URI the wallet scans
+    pub async fn pair(&self, chain_ids: &[u64]) -> Result<WalletConnectPairing> {
+        if chain_ids.is_empty() {
+            return Err(anyhow!("A WalletConnect pairing needs at least one chain"));
+        }
+        let sym_key = crypto::random_key()?;
+        let topic = hex::encode(crypto::random_key()?);
+        self.keys.write().await.insert(topic.clone(), sym_key);
+        self.relay.subscribe(&topic).await?;
+
+        let proposal_key = ProposalKey::generate()?;
+        let expires_at = Utc::now() + ChronoDuration::seconds(PAIRING_TTL_SECS as i64);
+        let proposal_id = self.relay.next_id();
+        let namespaces = Self::session_request(chain_ids);
+        let proposal = json!({
+            "id": proposal_id,
+            "jsonrpc": "2.0",
+            "method": "wc_sessionPropose",
+            "params": {
+                "requiredNamespaces": namespaces.required_namespaces,
+                "optionalNamespaces": namespaces.optional_namespaces,
+                "relays": [{ "protocol": "irn" }],
+                "proposer": {
+                    "publicKey": proposal_key.public_key(),
+                    "metadata": self.metadata(),
+                },
+                "expiryTimestamp": expires_at.timestamp(),
+            },
+        });
+        let message = crypto::seal(&sym_key, proposal.to_string().as_bytes())?;
+        self.relay.publish(&topic, message, TAG_SESSION_PROPOSE, PAIRING_TTL_SECS, true).await?;
+
+        let pairing = WalletConnectPairing {
+            uri: format!(
+                "wc:{}@2?relay-protocol=irn&symKey={}&expiryTimestamp={}",
+                topic,
+                hex::encode(sym_key),
+                expires_at.timestamp(),
+            ),
+            topic: topic.clone(),
+            chains: chain_ids.to_vec(),
+            expires_at,
+            status: PairingStatus::Pending,
+            session_topic: None,
+            accounts: Vec::new(),
+            error: None,
+        };
+        self.pairings.write().await.insert(topic, PendingPairing {
+            pairing: pairing.clone(),
+            proposal_id,
+            proposal_key: Some(proposal_key),
+        });
+
+        info!("Proposed WalletConnect session on pairing {} for chains {:?}", pairing.topic, chain_ids);
+        Ok(pairing)
+    }
+
+    pub async fn pairing(&self, topic: &str) -> Option<WalletConnectPairing> {
+        let mut pairings = self.pairings.write().await;
+        let pending = pairings.get_mut(topic)?;
+        if pending.pairing.status == PairingStatus::Pending && pending.pairing.expires_at <= Utc::now() {
+            pending.pairing.status = PairingStatus::Expired;
+            pending.proposal_key = None;
+        }
+        Some(pending.pairing.clone())
+    }
+
+    pub async fn session(&self, topic: &str) -> Option<WalletConnectSession> {
+        self.sessions.read().await.get(topic).cloned()
+    }
+
+    /// Namespaces requested from the wallet, the given chains required and the other supported ones optional
+    fn session_request(chain_ids: &[u64]) -> SessionRequest {
+        let namespace = |chains: Vec<u64>| Namespace {
+            chains: chains.iter().map(|id| format!("eip155:{}", id)).collect(),
+            accounts: Vec::new(),
+            methods: EIP155_METHODS.iter().map(|method| method.to_string()).collect(),
+            events: EIP155_EVENTS.iter().map(|event| event.to_string()).collect(),
+        };
+        let optional: Vec<u64> = OPTIONAL_CHAINS.into_iter().filter(|id| !chain_ids.contains(id)).collect();
+
+        let mut optional_namespaces = HashMap::new();
+        if !optional.is_empty() {
+            optional_namespaces.insert("eip155".to_string(), namespace(optional));
+        }
+        SessionRequest {
+            required_namespaces: HashMap::from([("eip155".to_string(), namespace(chain_ids.to_vec()))]),
+            optional_namespaces,
+        }
+    }
+
+    fn metadata(&self) -> PeerMetadata {
+        PeerMetadata {
+            name: "Blockchain Demo".to_string(),
+            description: "Multi-chain DEX and DeFi demo API".to_string(),
+            url: self.config.app_url.clone(),
+            icons: Vec::new(),
+        }
+    }
+
+    /// Relay a JSON-RPC request to the wallet of a session, waiting for the user to answer it
+    pub async fn request(&self, topic: &str, chain_id: u64, method: &str, params: Value) -> Result<Value> {
+        let session = self.session(topic).await
+            .ok_or_else(|| anyhow!("WalletConnect session {} not found", topic))?;
+        if session.is_expired() {
+            self.forget_session(topic).await;
+            return Err(anyhow!("WalletConnect session {} expired", topic));
+        }
+        if !session.chain_ids().contains(&chain_id) {
+            return Err(anyhow!("WalletConnect session {} does not include chain {}", topic, chain_id));
+        }
+
+        let params = json!({
+            "request": { "method": method, "params": params },
+            "chainId": format!("eip155:{}", chain_id),
+        });
+        self.call(topic, "wc_sessionRequest", params, TAG_SESSION_REQUEST, SESSION_REQUEST_TIMEOUT).await
+    }
+
+    /// Whether the wallet of a session still answers
+    pub async fn ping(&self, topic: &str) -> Result<bool> {
+        if self.session(topic).await.is_none() {
+            return Ok(false);
+        }
+        match self.call(topic, "wc_sessionPing", json!({}), TAG_SESSION_PING, SESSION_PING_TIMEOUT).await {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                debug!("WalletConnect session {} did not answer a ping: {}", topic, e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Tell the wallet a session is over and forget it, nothing to do for unknown sessions
+    pub async fn delete_session(&self, topic: &str) -> Result<()> {
+        let Some(key) = self.keys.read().await.get(topic).copied() else {
+            return Ok(());
+        };
+        if self.session(topic).await.is_none() {
+            return Ok(());
+        }
+        let payload = json!({
+            "id": self.relay.next_id(),
+            "jsonrpc": "2.0",
+            "method": "wc_sessionDelete",
+            "params": { "code": 6000, "message": "User disconnected." },
+        });
+        let message = crypto::seal(&key, payload.to_string().as_bytes())?;
+        if let Err(e) = self.relay.publish(topic, message, TAG_SESSION_DELETE, ttl_secs(TAG_SESSION_DELETE), false).await {
+            warn!("Failed to notify the wallet of WalletConnect session {} deletion: {}", topic, e);
+        }
+        self.forget_session(topic).await;
+        Ok(())
+    }
+
+    async fn call(&self, topic: &str, method: &str, params: Value, tag: u32, timeout: Duration) -> Result<Value> {
+        let key = self.keys.read().await.get(topic).copied()
+            .ok_or_else(|| anyhow!("WalletConnect session {} not found", topic))?;
+        let id = self.relay.next_id();
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().await.insert(id, sender);
+
+        let payload = json!({ "id": id, "jsonrpc": "2.0", "method": method, "params": params });
+        let published = match crypto::seal(&key, payload.to_string().as_bytes()) {
+            Ok(message) => self.relay.publish(topic, message, tag, ttl_secs(tag), tag == TAG_SESSION_REQUEST).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = published {
+            self.pending.lock().await.remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(anyhow!("WalletConnect {} was dropped", method)),
+            Err(_) => {
+                self.pending.lock().await.remove(&id);
+                Err(anyhow!("Wallet did not answer {} in time", method))
+            }
+        }
+    }
+
+    async fn handle_message(&self, message: RelayMessage) {
+        let Some(key) = self.keys.read().await.get(&message.topic).copied() else {
+            debug!("WalletConnect message on unknown topic {}", message.topic);
+            return;
+        };
+        let payload: Value = match crypto::open(&key, &message.message)
+            .and_then(|plaintext| Ok(serde_json::from_slice(&plaintext)?))
+        {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Unreadable WalletConnect message on {}: {}", message.topic, e);
+                return;
+            }
+        };
+
+        let result = match payload.get("method").and_then(Value::as_str) {
+            Some(method) => self.handle_request(&message.topic, method, &payload).await,
+            None => self.handle_response(&message.topic, &payload).await,
+        };
+        if let Err(e) = result {
+            warn!("Failed to handle WalletConnect message on {}: {}", message.topic, e);
+        }
+    }
+
+    async fn handle_response(&self, topic: &str, payload: &Value) -> Result<()> {
+        let id = payload["id"].as_u64().ok_or_else(|| anyhow!("WalletConnect response without an id"))?;
+        let proposal = self.pairings.read().await
+            .get(topic)
+            .is_some_and(|pending| pending.proposal_id == id);
+        if proposal {
+            return self.handle_proposal_response(topic, payload).await;
+        }
+
+        let Some(sender) = self.pending.lock().await.remove(&id) else {
+            debug!("Unclaimed WalletConnect response {}", id);
+            return Ok(());
+        };
+        let result = match payload.get("error") {
+            Some(error) => Err(anyhow!(
+                "Wallet rejected the request: {}",
+                error["message"].as_str().unwrap_or("no reason given"),
+            )),
+            None => Ok(payload.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        let _ = sender.send(result);
+        Ok(())
+    }
+
+    /// Derive the session key from the wallet's answer and listen on the session topic
+    async fn handle_proposal_response(&self, pairing_topic: &str, payload: &Value) -> Result<()> {
+        let mut pairings = self.pairings.write().await;
+        let pending = pairings.get_mut(pairing_topic)
+            .ok_or_else(|| anyhow!("WalletConnect pairing {} not found", pairing_topic))?;
+
+        if let Some(error) = payload.get("error") {
+            pending.pairing.status = PairingStatus::Rejected;
+            pending.pairing.error = error["message"].as_str().map(str::to_string);
+            pending.proposal_key = None;
+            drop(pairings);
+            info!("Wallet rejected WalletConnect pairing {}", pairing_topic);
+            return self.close_pairing(pairing_topic).await;
+        }
+
+        let responder = payload["result"]["responderPublicKey"].as_str()
+            .ok_or_else(|| anyhow!("WalletConnect proposal response without a responder key"))?;
+        let proposal_key = pending.proposal_key.take()
+            .ok_or_else(|| anyhow!("WalletConnect pairing {} was already answered", pairing_topic))?;
+        let key = proposal_key.session_key(responder)?;
+        let session_topic = crypto::topic_for(&key);
+        pending.pairing.session_topic = Some(session_topic.clone());
+        drop(pairings);
+
+        self.keys.write().await.insert(session_topic.clone(), key);
+        self.relay.subscribe(&session_topic).await
+    }
+
+    async fn handle_request(&self, topic: &str, method: &str, payload: &Value) -> Result<()> {
+        let id = payload["id"].as_u64().ok_or_else(|| anyhow!("WalletConnect request without an id"))?;
+        let params = &payload["params"];
+
+        match method {
+            "wc_sessionSettle" => self.settle(topic, params).await?,
+            "wc_sessionUpdate" => {
+                let namespaces: HashMap<String, Namespace> = serde_json::from_value(params["namespaces"].clone())?;
+                let eip155 = namespaces.get("eip155")
+                    .ok_or_else(|| anyhow!("WalletConnect update without an eip155 namespace"))?;
+                self.update_session(topic, |session| {
+                    session.apply_namespace(eip155);
+                    Ok(())
+                }).await?;
+            }
+            "wc_sessionExtend" => self.update_session(topic, |session| session.set_expiry(params)).await?,
+            "wc_sessionDelete" => {
+                info!("Wallet deleted WalletConnect session {}: {}", topic, params["message"]);
+                self.forget_session(topic).await;
+            }
+            "wc_sessionEvent" => debug!("WalletConnect session {} event {}", topic, params["event"]["name"]),
+            "wc_sessionPing" => {}
+            _ => {
+                debug!("Ignoring WalletConnect {} on {}", method, topic);
+                return Ok(());
+            }
+        }
+
+        // The session key is gone once deleted; the wallet does not wait for that answer
+        if method != "wc_sessionDelete" {
+            self.respond(topic, id, method).await?;
+        }
+        Ok(())
+    }
+
+    /// Store the session a wallet settled on a pairing's session topic
+    async fn settle(&self, topic: &str, params: &Value) -> Result<()> {
+        let pairing_topic = self.pairings.read().await
+            .iter()
+            .find(|(_, pending)| pending.pairing.session_topic.as_deref() == Some(topic))
+            .map(|(pairing_topic, _)| pairing_topic.clone());
+        let session = WalletConnectSession::from_namespaces(topic, pairing_topic.clone(), params)?;
+
+        if let Some(pairing_topic) = &pairing_topic {
+            if let Some(pending) = self.pairings.write().await.get_mut(pairing_topic) {
+                pending.pairing.status = PairingStatus::Approved;
+                pending.pairing.accounts = session.addresses().into_iter().map(|(address, _)| address).collect();
+            }
+            // The pairing is not reused for other proposals
+            self.close_pairing(pairing_topic).await?;
+        }
+
+        info!("WalletConnect session {} settled with {}", topic, session.peer.name);
+        self.sessions.write().await.insert(topic.to_string(), session.clone());
+        self.persist().await;
+        let _ = self.events.send(SessionEvent::Settled(Box::new(session)));
+        Ok(())
+    }
+
+    async fn update_session<F>(&self, topic: &str, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut WalletConnectSession) -> Result<()>,
+    {
+        let session = {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(topic)
+                .ok_or_else(|| anyhow!("WalletConnect session {} not found", topic))?;
+            update(session)?;
+            session.clone()
+        };
+        self.persist().await;
+        let _ = self.events.send(SessionEvent::Settled(Box::new(session)));
+        Ok(())
+    }
+
+    /// Acknowledge a wallet request with the response tag and TTL of its method
+    async fn respond(&self, topic: &str, id: u64, method: &str) -> Result<()> {
+        let Some(tag) = response_tag(method) else {
+            return Ok(());
+        };
+        let key = self.keys.read().await.get(topic).copied()
+            .ok_or_else(|| anyhow!("WalletConnect topic {} has no key", topic))?;
+        let payload = json!({ "id": id, "jsonrpc": "2.0", "result": true });
+        let message = crypto::seal(&key, payload.to_string().as_bytes())?;
+        self.relay.publish(topic, message, tag, ttl_secs(tag), false).await
+    }
+
+    async fn close_pairing(&self, topic: &str) -> Result<()> {
+        self.keys.write().await.remove(topic);
+        self.relay.unsubscribe(topic).await
+    }
+
+    async fn forget_session(&self, topic: &str) {
+        if self.sessions.write().await.remove(topic).is_none() {
+            return;
+        }
+        self.keys.write().await.remove(topic);
+        if let Err(e) = self.relay.unsubscribe(topic).await {
+            debug!("Failed to unsubscribe WalletConnect session {}: {}", topic, e);
+        }
+        self.persist().await;
+        let _ = self.events.send(SessionEvent::Deleted(topic.to_string()));
+    }
+
+    /// Save settled sessions, failures are logged since the sessions stay usable in memory
+    async fn persist(&self) {
+        let Some(path) = &self.config.sessions_path else {
+            return;
+        };
+        let keys = self.keys.read().await;
+        let stored: Vec<StoredSession> = self.sessions.read().await
+            .values()
+            .filter_map(|session| {
+                let key = keys.get(&session.topic)?;
+                Some(StoredSession { session: session.clone(), sym_key: hex::encode(key) })
+            })
+            .collect();
+        drop(keys);
+
+        if let Err(e) = write_store(path, &stored).await {
+            warn!("Failed to persist WalletConnect sessions to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Tag of the response to a wallet request, `None` for requests without one
+fn response_tag(method: &str) -> Option<u32> {
+    match method {
+        "wc_sessionSettle" => Some(1103),
+        "wc_sessionUpdate" => Some(1105),
+        "wc_sessionExtend" => Some(1107),
+        "wc_sessionEvent" => Some(1111),
+        "wc_sessionPing" => Some(1115),
+        _ => None,
+    }
+}
+
+/// Relay TTL of a message by its tag, as set by the sign API specs
+fn ttl_secs(tag: u32) -> u64 {
+    match tag {
+        1100 | 1101 | 1102 | 1103 | 1108 | 1109 | 1110 | 1111 => 300,
+        1114 | 1115 => 30,
+        _ => 86_400,
+    }
+}
+
+/// One address of a WalletConnect session, signing through the user's wallet
+#[derive(Clone)]
+pub struct WalletConnectProvider {
+    client: Arc<WalletConnectClient>,
+    topic: String,
+    address: Address,
+    chain_id: u64,
+}
+
+impl WalletConnectProvider {
+    /// Providers for each address a session exposes
+    pub fn for_session(client: &Arc<WalletConnectClient>, session: &WalletConnectSession) -> Vec<Self> {
+        session.addresses()
+            .into_iter()
+            .map(|(address, chain_id)| Self {
+                client: Arc::clone(client),
+                topic: session.topic.clone(),
+                address,
+                chain_id,
+            })
+            .collect()
+    }
+
+    pub fn get_address(&self) -> Address {
+        self.address
+    }
+
+    pub fn get_session_id(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn get_chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        self.client.session(&self.topic).await.is_some_and(|session| !session.is_expired())
+    }
+
+    /// Sign on another chain of the session, wallets only sign on chains they approved
+    pub async fn switch_chain(&mut self, chain_id: u64) -> Result<()> {
+        if !self.get_supported_chains().await?.contains(&chain_id) {
+            return Err(anyhow!("WalletConnect session {} does not include chain {}", self.topic, chain_id));
+        }
+        info!("Switching WalletConnect wallet {:?} to chain {}", self.address, chain_id);
+        self.chain_id = chain_id;
+        Ok(())
+    }
+
+    pub async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        info!("Requesting personal_sign from WalletConnect wallet {:?}", self.address);
+        let params = json!([format!("0x{}", hex::encode(message)), self.address]);
+        let signature = self.client.request(&self.topic, self.chain_id, "personal_sign", params).await?;
+        parse_signature(&signature)
+    }
+
+    /// Have the wallet sign a transaction without sending it, taking the signature from the raw transaction
+    pub async fn sign_transaction(&self, tx: TypedTransaction) -> Result<Signature> {
+        info!("Requesting eth_signTransaction from WalletConnect wallet {:?}", self.address);
+        let mut tx = tx;
+        tx.set_from(self.address);
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+        let raw = self.client.request(&self.topic, chain_id, "eth_signTransaction", json!([tx])).await?;
+
+        let raw = raw.as_str().ok_or_else(|| anyhow!("Wallet returned no signed transaction"))?;
+        let bytes = hex::decode(raw.trim_start_matches("0x"))?;
+        let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(&bytes))
+            .map_err(|e| anyhow!("Wallet returned an invalid signed transaction: {}", e))?;
+        Ok(signature)
+    }
+
+    pub async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature> {
+        info!("Requesting eth_signTypedData_v4 from WalletConnect wallet {:?}", self.address);
+        let params = json!([self.address, serde_json::to_string(typed_data)?]);
+        let signature = self.client.request(&self.topic, self.chain_id, "eth_signTypedData_v4", params).await?;
+        parse_signature(&signature)
+    }
+
+    pub async fn ping_session(&self) -> Result<bool> {
+        self.client.ping(&self.topic).await
+    }
+
+    pub async fn disconnect(&mut self) -> Result<()> {
+        info!("Disconnecting WalletConnect session: {}", self.topic);
+        self.client.delete_session(&self.topic).await
+    }
+
+    pub async fn get_supported_chains(&self) -> Result<Vec<u64>> {
+        let session = self.client.session(&self.topic).await
+            .ok_or_else(|| anyhow!("WalletConnect session {} not found", self.topic))?;
+        Ok(session.chain_ids())
+    }
+}
+
+fn parse_signature(value: &Value) -> Result<Signature> {
+    let signature = value.as_str().ok_or_else(|| anyhow!("Wallet returned no signature"))?;
+    signature.trim_start_matches("0x").parse::<Signature>()
+        .map_err(|e| anyhow!("Wallet returned an invalid signature: {}", e))
+}
+
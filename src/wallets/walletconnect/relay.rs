@@ -0,0 +1,237 @@
+// JSON-RPC connection to the WalletConnect relay server
+use anyhow::{Result, anyhow};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+use super::crypto::ClientIdentity;
+
+/// How long the relay has to acknowledge a request
+const RELAY_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Pings keep idle connections from being closed by proxies
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+type PendingRequests = Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>;
+type RelaySocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Encrypted message published on a subscribed topic
+#[derive(Debug, Clone)]
+pub struct RelayMessage {
+    pub topic: String,
+    pub message: String,
+}
+
+/// Relay connection kept open in the background, resubscribing its topics after reconnecting
+pub struct RelayClient {
+    relay_url: String,
+    project_id: String,
+    identity: ClientIdentity,
+    next_id: AtomicU64,
+    outgoing: mpsc::UnboundedSender<String>,
+    outgoing_rx: Mutex<Option<mpsc::UnboundedReceiver<String>>>,
+    pending: PendingRequests,
+    /// Subscription id of each subscribed topic, `None` until the relay confirms it
+    subscriptions: RwLock<HashMap<String, Option<String>>>,
+}
+
+impl RelayClient {
+    pub fn new(relay_url: &str, project_id: &str) -> Result<Self> {
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            relay_url: relay_url.trim_end_matches('/').to_string(),
+            project_id: project_id.to_string(),
+            identity: ClientIdentity::generate()?,
+            next_id: AtomicU64::new(payload_id_seed()),
+            outgoing,
+            outgoing_rx: Mutex::new(Some(outgoing_rx)),
+            pending: Mutex::new(HashMap::new()),
+            subscriptions: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// JSON-RPC id, increasing and unique across restarts like the ones the SDKs issue
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Connect in the background, forwarding messages of subscribed topics to `incoming`
+    pub async fn start(self: std::sync::Arc<Self>, incoming: mpsc::UnboundedSender<RelayMessage>) -> Result<JoinHandle<()>> {
+        let outgoing = self.outgoing_rx.lock().await.take()
+            .ok_or_else(|| anyhow!("WalletConnect relay client already started"))?;
+        Ok(tokio::spawn(async move { self.run(outgoing, incoming).await }))
+    }
+
+    pub async fn subscribe(&self, topic: &str) -> Result<()> {
+        self.subscriptions.write().await.entry(topic.to_string()).or_insert(None);
+        let subscription_id = self.request("irn_subscribe", json!({ "topic": topic })).await?;
+        if let Some(subscription) = self.subscriptions.write().await.get_mut(topic) {
+            *subscription = subscription_id.as_str().map(str::to_string);
+        }
+        Ok(())
+    }
+
+    pub async fn unsubscribe(&self, topic: &str) -> Result<()> {
+        let Some(subscription_id) = self.subscriptions.write().await.remove(topic) else {
+            return Ok(());
+        };
+        if let Some(id) = subscription_id {
+            self.request("irn_unsubscribe", json!({ "topic": topic, "id": id })).await?;
+        }
+        Ok(())
+    }
+
+    /// Publish an encrypted message; `tag` tells the relay which WalletConnect method it carries
+    pub async fn publish(&self, topic: &str, message: String, tag: u32, ttl: u64, prompt: bool) -> Result<()> {
+        self.request("irn_publish", json!({
+            "topic": topic,
+            "message": message,
+            "ttl": ttl,
+            "tag": tag,
+            "prompt": prompt,
+        })).await?;
+        Ok(())
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id();
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().await.insert(id, sender);
+
+        let request = json!({ "id": id, "jsonrpc": "2.0", "method": method, "params": params });
+        if self.outgoing.send(request.to_string()).is_err() {
+            self.pending.lock().await.remove(&id);
+            return Err(anyhow!("WalletConnect relay client is not running"));
+        }
+
+        match tokio::time::timeout(RELAY_REQUEST_TIMEOUT, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(anyhow!("WalletConnect relay connection closed during {}", method)),
+            Err(_) => {
+                self.pending.lock().await.remove(&id);
+                Err(anyhow!("WalletConnect relay did not answer {} in time", method))
+            }
+        }
+    }
+
+    async fn run(&self, mut outgoing: mpsc::UnboundedReceiver<String>, incoming: mpsc::UnboundedSender<RelayMessage>) {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match self.connect().await {
+                Ok(socket) => {
+                    info!("Connected to WalletConnect relay {}", self.relay_url);
+                    delay = Duration::from_secs(1);
+                    if let Err(e) = self.serve(socket, &mut outgoing, &incoming).await {
+                        warn!("WalletConnect relay connection lost: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to connect to WalletConnect relay: {}", e),
+            }
+            if incoming.is_closed() {
+                return;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    async fn connect(&self) -> Result<RelaySocket> {
+        let auth = self.identity.relay_jwt(&self.relay_url)?;
+        let url = format!("{}/?auth={}&projectId={}", self.relay_url, auth, self.project_id);
+        let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+        Ok(socket)
+    }
+
+    async fn serve(
+        &self,
+        socket: RelaySocket,
+        outgoing: &mut mpsc::UnboundedReceiver<String>,
+        incoming: &mpsc::UnboundedSender<RelayMessage>,
+    ) -> Result<()> {
+        let (mut sink, mut stream) = socket.split();
+
+        // The relay forgets subscriptions with the connection; answers to these go unclaimed
+        let topics: Vec<String> = self.subscriptions.read().await.keys().cloned().collect();
+        for topic in topics {
+            let request = json!({ "id": self.next_id(), "jsonrpc": "2.0", "method": "irn_subscribe", "params": { "topic": topic } });
+            sink.send(Message::text(request.to_string())).await?;
+        }
+
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let result = loop {
+            tokio::select! {
+                Some(request) = outgoing.recv() => {
+                    if let Err(e) = sink.send(Message::text(request)).await {
+                        break Err(e.into());
+                    }
+                }
+                frame = stream.next() => match frame {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Some(ack) = self.handle_frame(&text, incoming).await {
+                            if let Err(e) = sink.send(Message::text(ack)).await {
+                                break Err(e.into());
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(frame))) => break Err(anyhow!("closed by the relay: {:?}", frame)),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => break Err(e.into()),
+                    None => break Err(anyhow!("connection ended")),
+                },
+                _ = heartbeat.tick() => {
+                    if let Err(e) = sink.send(Message::Ping(Vec::new().into())).await {
+                        break Err(e.into());
+                    }
+                }
+            }
+        };
+
+        // Requests in flight on the lost connection will never be answered
+        for (_, sender) in self.pending.lock().await.drain() {
+            let _ = sender.send(Err(anyhow!("WalletConnect relay connection lost")));
+        }
+        result
+    }
+
+    /// Resolve a response or forward a subscription message, returning the acknowledgement to send
+    async fn handle_frame(&self, text: &str, incoming: &mpsc::UnboundedSender<RelayMessage>) -> Option<String> {
+        let frame: Value = match serde_json::from_str(text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Invalid frame from WalletConnect relay: {}", e);
+                return None;
+            }
+        };
+        let id = frame.get("id").and_then(Value::as_u64)?;
+
+        if frame.get("method").and_then(Value::as_str) == Some("irn_subscription") {
+            let data = &frame["params"]["data"];
+            if let (Some(topic), Some(message)) = (data["topic"].as_str(), data["message"].as_str()) {
+                let _ = incoming.send(RelayMessage { topic: topic.to_string(), message: message.to_string() });
+            }
+            return Some(json!({ "id": id, "jsonrpc": "2.0", "result": true }).to_string());
+        }
+
+        let Some(sender) = self.pending.lock().await.remove(&id) else {
+            debug!("Unclaimed WalletConnect relay response {}", id);
+            return None;
+        };
+        let result = match frame.get("error") {
+            Some(error) => Err(anyhow!("WalletConnect relay error: {}", error)),
+            None => Ok(frame.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        let _ = sender.send(result);
+        None
+    }
+}
+
+/// First JSON-RPC id of the process, milliseconds since the epoch times a thousand
+fn payload_id_seed() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64 * 1000
+}